@@ -0,0 +1,59 @@
+//! Chain identifiers and address checks the stability pool needs to validate
+//! payout destinations the same way the backend's chain rails do.
+
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+#[derive(
+    CandidType, Deserialize, Serialize, Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash,
+)]
+pub struct ChainId(pub u32);
+
+/// True iff `s` is a well-formed EVM address: a `0x`/`0X` prefix followed by
+/// EXACTLY 40 hex digits (20 bytes), case-insensitive. This is the format the
+/// tx-building helpers (`tx::abi_word_address`/`parse_address`) require; an
+/// address that passes this can never panic those helpers. (Format only — no
+/// EIP-55 checksum; derived addresses are lowercase and RPC responses vary.)
+pub fn is_valid_evm_address(s: &str) -> bool {
+    let hex = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(h) => h,
+        None => return false,
+    };
+    hex.len() == 40 && hex.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Synthetic `collateral_type` / `CollateralConfig` map key for native XRP. XRP has
+/// no IC ledger, so this reserved principal is only an opaque key (15 bytes — not a
+/// valid 10-byte canister id, so it cannot collide with a real canister). The XRP
+/// `CollateralConfig` (registered in P5) is keyed by this; its `custody_kind` is
+/// `NativeXrp` and its `ledger_canister_id` is this same synthetic value.
+pub fn xrp_collateral_principal() -> Principal {
+    Principal::from_slice(b"rumi-xrp-native")
+}
+
+/// XRPL version byte for a classic account address (`r…`).
+pub const XRP_CLASSIC_ADDRESS_VERSION: u8 = 0x00;
+
+/// Decode a classic `r…` address to its 20-byte AccountID, rejecting anything
+/// that is not a valid version-0x00 classic address with a good checksum. This is
+/// the single trust-boundary validator for a user-supplied destination: it
+/// rejects X-addresses (the `X…` format), wrong-version strings, empty input, and
+/// corrupted checksums BEFORE any bytes are signed.
+pub fn account_id_from_classic_address(addr: &str) -> Result<[u8; 20], String> {
+    // `with_check(Some(ver))` verifies both the 4-byte SHA256d checksum and the
+    // leading version byte; the returned vec is `version ‖ 20-byte payload`.
+    let decoded = bs58::decode(addr)
+        .with_alphabet(bs58::Alphabet::RIPPLE)
+        .with_check(Some(XRP_CLASSIC_ADDRESS_VERSION))
+        .into_vec()
+        .map_err(|e| format!("invalid XRPL classic address: {e}"))?;
+    if decoded.len() != 21 {
+        return Err(format!(
+            "XRPL address payload is {} bytes, expected 21",
+            decoded.len()
+        ));
+    }
+    let mut out = [0u8; 20];
+    out.copy_from_slice(&decoded[1..21]);
+    Ok(out)
+}
@@ -0,0 +1,96 @@
+//! The backend's error type. Every canister that calls the backend decodes
+//! this from its replies, so it lives here rather than in the backend.
+
+use candid::{CandidType, Deserialize};
+use icrc_ledger_types::icrc1::transfer::TransferError;
+use icrc_ledger_types::icrc2::transfer_from::TransferFromError;
+use std::fmt;
+
+#[derive(CandidType, Debug, Clone, Deserialize)]
+pub enum ProtocolError {
+    TransferFromError(TransferFromError, u64),
+    TransferError(TransferError),
+    TemporarilyUnavailable(String),
+    AlreadyProcessing,
+    AnonymousCallerNotAllowed,
+    CallerNotOwner,
+    AmountTooLow {
+        minimum_amount: u64,
+    },
+    GenericError(String),
+    /// Wave-8b LIQ-002 band-gate rejection. **Deactivated 2026-05-18 — no
+    /// live code path emits this variant.** Retained in the enum so
+    /// historical on-chain events recorded before the deactivation still
+    /// decode cleanly. See `state::is_within_liquidation_band` and the
+    /// "Layer 2.5 — band gate DEACTIVATION fence" comment in
+    /// `tests/audit_pocs_liq_002_sorted_troves_index.rs` for background.
+    NotLowestCR,
+    /// Phase 1a: the periodic supply-invariant self-check (Timer B) caught
+    /// a `sum(chain_supplies) != total_debt` divergence. Every entry that
+    /// touches debt or chain supply returns this error until an operator
+    /// clears `multi_chain.invariant_halted`.
+    SupplyInvariantHalted,
+    /// Phase 1a: admin-endpoint error for `register_chain`, `disable_chain`,
+    /// `set_chain_config`. Wraps a developer-facing message string. The
+    /// structured `ChainAdminError` enum lives in `chains::config` and is
+    /// stringified here so the Candid surface stays append-only.
+    ChainAdmin(String),
+    /// M2 EVM-native self-serve auth failure (bad signature, recovered signer !=
+    /// owner, nonce replay, expired deadline, recipient != owner, per-owner cap,
+    /// unknown/unregistered chain, custody-derive failure, or an underlying vault
+    /// rejection). Wraps a developer-facing message. Appended AFTER `ChainAdmin`
+    /// so historical on-chain events keep decoding (append-only Candid surface).
+    EvmAuth(String),
+    /// A liquidation's collateral payout at execution time fell below the
+    /// liquidator's `min_collateral_out` bound. Nothing was transferred.
+    MinCollateralOutNotMet {
+        min_collateral_out: u64,
+        collateral_out: u64,
+    },
+}
+
+impl ProtocolError {
+    /// The exact `TemporarilyUnavailable` rejection returned whenever the
+    /// protocol is latched `Mode::ReadOnly` (insolvency: total collateral ratio
+    /// below 100%, or the deficit account over its configured threshold).
+    ///
+    /// Single source of truth so the Candid entry-layer gate
+    /// (`main.rs::validate_mode`) and the shared vault-module redemption gates
+    /// (`vault::redeem_collateral` / `vault::redeem_reserves`) return a
+    /// byte-identical error. Audit RED-101 (regression of RED-003) showed that a
+    /// second entry point (`redeem_icp`) silently bypassed the entry-layer-only
+    /// gate; gating inside the vault module closes any present/future redemption
+    /// surface by construction, and this constructor keeps every layer's message
+    /// in lockstep.
+    pub fn read_only_mode() -> Self {
+        ProtocolError::TemporarilyUnavailable(READ_ONLY_MODE_MESSAGE.to_string())
+    }
+
+    /// The rejection returned while an upgrade is still replaying the event
+    /// log (`Mode::Bootstrapping`).
+    pub fn bootstrapping() -> Self {
+        ProtocolError::TemporarilyUnavailable(
+            "protocol is replaying its event log after an upgrade, please retry shortly"
+                .to_string(),
+        )
+    }
+
+    /// Append ` [trace=<id>]` to the message of a string-carrying error so a
+    /// failed call can be matched to its log lines (`/logs?trace=<id>`).
+    /// Structured variants are returned as is, and so is the read-only
+    /// rejection, which must stay byte-identical across layers.
+    pub fn with_trace(self, trace: impl fmt::Display) -> Self {
+        let tag = |msg: String| format!("{} [trace={}]", msg, trace);
+        match self {
+            Self::TemporarilyUnavailable(msg) if msg != READ_ONLY_MODE_MESSAGE => {
+                Self::TemporarilyUnavailable(tag(msg))
+            }
+            Self::GenericError(msg) => Self::GenericError(tag(msg)),
+            Self::ChainAdmin(msg) => Self::ChainAdmin(tag(msg)),
+            Self::EvmAuth(msg) => Self::EvmAuth(tag(msg)),
+            other => other,
+        }
+    }
+}
+
+const READ_ONLY_MODE_MESSAGE: &str = "protocol temporarly unavailable, please wait for an upgrade or for total collateral ratio to go above 100%";
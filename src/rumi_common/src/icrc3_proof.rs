@@ -0,0 +1,65 @@
+//! The writedown proof the stability pool attaches to a backend writedown
+//! call, and the memo that binds an icUSD burn block to a vault. The backend
+//! verifies the proof against the ledger (`rumi_protocol_backend::icrc3_proof`).
+
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+/// Which ledger the proof is against. Drives both the canister to query and
+/// the expected operation kind.
+///
+/// Derives `Ord`/`PartialOrd`/`Eq`/`PartialEq` so it can serve as a key in
+/// `State::consumed_writedown_proofs` (a `BTreeSet<(SpProofLedger, u64)>`).
+#[derive(
+    CandidType, Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize,
+)]
+pub enum SpProofLedger {
+    /// icUSD ledger — expect a `burn` block (legacy 3pool atomic-burn path).
+    IcusdBurn,
+    /// 3USD / 3pool ledger — expect a transfer to the protocol's reserves
+    /// subaccount (reserves path).
+    ThreePoolTransfer,
+}
+
+/// Typed proof argument the SP passes alongside a writedown call.
+///
+/// `vault_id_memo` MUST equal the vault id the call is operating on; the
+/// verifier rejects mismatches so a proof for vault A cannot be replayed
+/// against vault B.
+#[derive(CandidType, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SpWritedownProof {
+    pub block_index: u64,
+    pub ledger_kind: SpProofLedger,
+    pub vault_id_memo: u64,
+}
+
+/// Memo prefix that binds an ICRC-3 block to a Wave-8c writedown. Combined
+/// with the vault id (8 bytes big-endian) the full memo is 21 bytes — well
+/// under the standard ICRC-1 ledger's 32-byte memo cap.
+pub const WRITEDOWN_MEMO_PREFIX: &[u8] = b"RUMI-LIQ-004:";
+
+/// Build the canonical memo bytes for a SP writedown of `vault_id`.
+pub fn encode_writedown_memo(vault_id: u64) -> Vec<u8> {
+    let mut out = Vec::with_capacity(WRITEDOWN_MEMO_PREFIX.len() + 8);
+    out.extend_from_slice(WRITEDOWN_MEMO_PREFIX);
+    out.extend_from_slice(&vault_id.to_be_bytes());
+    out
+}
+
+/// Reverse of `encode_writedown_memo`. Returns the vault id if `memo` matches
+/// the Wave-8c shape, else `Err` with a description.
+pub fn decode_writedown_memo(memo: &[u8]) -> Result<u64, String> {
+    if memo.len() != WRITEDOWN_MEMO_PREFIX.len() + 8 {
+        return Err(format!(
+            "memo length {} not equal to expected {}",
+            memo.len(),
+            WRITEDOWN_MEMO_PREFIX.len() + 8
+        ));
+    }
+    if !memo.starts_with(WRITEDOWN_MEMO_PREFIX) {
+        return Err("memo prefix does not match RUMI-LIQ-004:".to_string());
+    }
+    let mut id_bytes = [0u8; 8];
+    id_bytes.copy_from_slice(&memo[WRITEDOWN_MEMO_PREFIX.len()..]);
+    Ok(u64::from_be_bytes(id_bytes))
+}
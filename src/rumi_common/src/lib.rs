@@ -0,0 +1,15 @@
+//! Types shared by the Rumi canisters.
+//!
+//! The backend re-exports everything here under its historical paths
+//! (`rumi_protocol_backend::numeric`, `rumi_protocol_backend::ProtocolError`,
+//! `rumi_protocol_backend::vault::VaultArg`, ...), so existing callers keep
+//! compiling; new consumers should depend on this crate directly instead of
+//! on the backend.
+
+pub mod chains;
+pub mod error;
+pub mod icrc3_proof;
+pub mod numeric;
+pub mod types;
+
+pub use error::ProtocolError;
@@ -0,0 +1,218 @@
+//! Candid structs the stability pool and treasury exchange with the backend.
+//! Field order and names are wire format; change them only append-only.
+
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+/// Stable token types accepted for vault repayment (1:1 with icUSD)
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StableTokenType {
+    /// ckUSDT stablecoin
+    CKUSDT,
+    /// ckUSDC stablecoin
+    CKUSDC,
+}
+
+/// Arguments for repaying vault with a stable token
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultArgWithToken {
+    pub vault_id: u64,
+    pub amount: u64,
+    pub token_type: StableTokenType,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct SuccessWithFee {
+    pub block_index: u64,
+    pub fee_amount_paid: u64,
+    /// Total collateral (native units) awarded to the liquidator / stability pool.
+    /// Added so the stability pool can correctly credit depositors with their
+    /// proportional share of the actual collateral received, rather than only
+    /// the liquidator bonus (`fee_amount_paid`).
+    pub collateral_amount_received: Option<u64>,
+    /// SP-101: the icUSD-denominated debt the backend ACTUALLY cleared (in e8s).
+    /// The partial-liquidation paths cap the requested draw to the vault's
+    /// `max_liquidatable_debt`; the stability pool must debit depositors by this
+    /// realized amount, not the (possibly larger) amount it requested, or the
+    /// tracked aggregate drifts above the pool's real balance. `None` on the
+    /// non-liquidation paths (redeem / borrow). Optional for Candid back-compat.
+    pub debt_liquidated_e8s: Option<u64>,
+    /// SP-110 (audit 2026-06-05): on the ckUSDT/ckUSDC liquidation path the
+    /// backend pulls `base + repay-fee surcharge` stable from the caller, but
+    /// `debt_liquidated_e8s` only reflects the base debt. The stability pool
+    /// must debit depositors by the TOTAL stable pulled (this field, in the
+    /// stable token's e6 native units), or the repay-fee surcharge leaves the
+    /// pool un-debited and the tracked aggregate drifts above the real ledger
+    /// balance. `Some` only on the ckStable path; `None` elsewhere. Optional for
+    /// Candid back-compat.
+    pub stable_pulled_e6s: Option<u64>,
+    /// Native-XRP manual liquidation payout claim id for the liquidator reward.
+    /// `None` for non-XRP collateral and non-liquidation SuccessWithFee results.
+    pub xrp_claim_id: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct VaultArg {
+    pub vault_id: u64,
+    pub amount: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug)]
+pub struct CandidVault {
+    pub owner: Principal,
+    pub borrowed_icusd_amount: u64,
+    /// Kept for frontend backward compatibility
+    pub icp_margin_amount: u64,
+    pub vault_id: u64,
+    /// Raw collateral amount (same value as icp_margin_amount for ICP vaults)
+    pub collateral_amount: u64,
+    /// Ledger canister ID of the collateral token
+    pub collateral_type: Principal,
+    /// Accumulated interest portion of the vault's debt (in e8s)
+    pub accrued_interest: u64,
+    /// Lifecycle state; see `VaultStatus`.
+    pub status: VaultStatus,
+}
+
+/// Where a vault is in its lifecycle. The backend validates every change
+/// against its transition graph and gates vault operations on it.
+#[derive(
+    CandidType, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum VaultStatus {
+    /// Open, no restrictions beyond the usual ratio checks.
+    #[default]
+    Active,
+    /// Below its liquidation ratio as of the last vault check.
+    AtRisk,
+    /// Claimed by the liquidation bot; the write-down is pending.
+    Liquidating,
+    /// Collateral is being paid out ahead of a close.
+    Settling,
+    /// Removed by a close or a full liquidation.
+    Closed,
+}
+
+/// What `liquidate_vault_partial` would do to a vault at the current price,
+/// from the backend's `preview_liquidation`. icUSD amounts are e8s;
+/// collateral amounts are in the collateral's native units.
+#[derive(CandidType, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LiquidationPreview {
+    pub vault_id: u64,
+    pub collateral_type: Principal,
+    pub debt_e8s: u64,
+    pub collateral_amount: u64,
+    pub collateral_decimals: u8,
+    pub collateral_price_usd: f64,
+    pub collateral_ratio: f64,
+    pub min_liquidation_ratio: f64,
+    /// Below its minimum ratio and its collateral allows liquidation. When
+    /// false the repay and collateral amounts are zero.
+    pub liquidatable: bool,
+    /// The protocol's partial cap: enough to restore the borrow threshold.
+    pub max_liquidatable_debt_e8s: u64,
+    /// The requested amount after the partial cap, dust round-up and
+    /// liquidation cap.
+    pub repay_e8s: u64,
+    pub liquidation_bonus: f64,
+    /// Collateral taken from the vault: the repay value times the bonus,
+    /// capped at the vault's collateral.
+    pub collateral_seized: u64,
+    /// The protocol's share of the bonus portion.
+    pub protocol_cut: u64,
+    pub collateral_to_liquidator: u64,
+    /// Smallest repay a single liquidation call accepts.
+    pub min_liquidation_amount_e8s: u64,
+    /// Surcharge on top of the repay when it is paid in ckUSDT/ckUSDC.
+    pub ckstable_repay_fee: f64,
+    /// Collateral the collateral's liquidation cap lets one call seize now;
+    /// `None` when it is uncapped.
+    pub seizure_cap: Option<u64>,
+    /// The repay before the liquidation cap clipped it; `None` when the cap
+    /// did not bind.
+    pub uncapped_repay_e8s: Option<u64>,
+}
+
+/// The backend's operating mode, as pushed to the stability pool and
+/// treasury on every mode change. Mirrors the backend's `Mode` variant
+/// names, which is all the Candid wire format needs.
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProtocolMode {
+    ReadOnly,
+    GeneralAvailability,
+    Recovery,
+    /// The backend is replaying its event log after an upgrade.
+    Bootstrapping,
+}
+
+impl ProtocolMode {
+    /// The mode a companion's inheritance policy is matched against.
+    /// Bootstrapping restricts like ReadOnly, so policies saved before it
+    /// existed still hold the backend's users off while it replays.
+    pub fn restricts_as(self) -> ProtocolMode {
+        match self {
+            ProtocolMode::Bootstrapping => ProtocolMode::ReadOnly,
+            mode => mode,
+        }
+    }
+}
+
+/// A treasury withdrawal a backend guardian or the developer co-approves.
+/// The treasury sends the same struct to `consume_treasury_withdrawal_approval`
+/// before paying out a withdrawal above its co-approval threshold; every
+/// field must match the approval.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreasuryWithdrawalRequest {
+    /// The withdrawal's `request_id` on the treasury.
+    pub request_id: u64,
+    pub ledger: Principal,
+    pub to: Principal,
+    pub amount: u64,
+}
+
+pub const MAX_XRP_SP_PAYOUT_ALLOCATIONS: usize = 500;
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct XrpSpAbsorbPreflight {
+    pub vault_id: u64,
+    pub icusd_burn_e8s: u64,
+    pub collateral_received_drops: u64,
+    pub collateral_price_e8s: u64,
+    pub expires_at_ns: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct XrpSpPayoutAllocation {
+    pub claimant: Principal,
+    pub payout_address: String,
+    pub destination_tag: Option<u32>,
+    pub drops: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct XrpSpAbsorbRequest {
+    pub vault_id: u64,
+    pub icusd_burned_e8s: u64,
+    pub proof: crate::icrc3_proof::SpWritedownProof,
+    pub allocations: Vec<XrpSpPayoutAllocation>,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct XrpSpPayoutClaim {
+    pub claimant: Principal,
+    pub claim_id: u64,
+    pub payout_address: String,
+    pub destination_tag: Option<u32>,
+    pub drops: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct XrpSpAbsorbResult {
+    pub success: bool,
+    pub vault_id: u64,
+    pub liquidated_debt_e8s: u64,
+    pub collateral_received_drops: u64,
+    pub payout_claims: Vec<XrpSpPayoutClaim>,
+    pub block_index: u64,
+    pub collateral_price_e8s: u64,
+}
@@ -0,0 +1,467 @@
+//! Cross-canister consistency suite.
+//!
+//! The backend, the stability pool and the treasury each declare the Candid
+//! types of the calls they make to one another, some shared through
+//! `rumi_common` and some mirrored by hand. A mirror that drifts from the
+//! callee's real type still compiles; the call then fails to decode on
+//! mainnet. This suite deploys the three canisters together in PocketIC
+//! and drives every cross-canister call path, encoding arguments and
+//! decoding replies with the *caller's* Rust types, so drift fails CI
+//! instead.
+//!
+//! Requires the canister wasms:
+//! `cargo build --release --target wasm32-unknown-unknown -p rumi_protocol_backend -p stability_pool -p rumi_treasury`.
+
+use std::time::{Duration, SystemTime};
+
+use candid::utils::{ArgumentDecoder, ArgumentEncoder};
+use candid::{decode_args, encode_args, encode_one, CandidType, Deserialize, Nat, Principal};
+use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::icrc1::transfer::{TransferArg, TransferError};
+use icrc_ledger_types::icrc2::approve::{ApproveArgs, ApproveError};
+use pocket_ic::{PocketIc, PocketIcBuilder, UserError, WasmResult};
+
+use rumi_common::types::{CandidVault, VaultArg};
+use rumi_protocol_backend::vault::OpenVaultSuccess;
+use rumi_protocol_backend::{InitArg, ProtocolArg, ProtocolError, SuccessWithFee};
+use stability_pool::types::{StabilityPoolError, StabilityPoolInitArgs, StablecoinConfig};
+
+pub const E8S: u64 = 100_000_000;
+pub const LEDGER_FEE: u64 = 10_000;
+
+/// ICP price the stack starts at, in e8s: $10.00.
+pub const ICP_PRICE_E8S: u64 = 10 * E8S;
+
+// ─── ICRC-1 ledger init mirrors (the ledger is an external wasm) ───
+
+#[derive(CandidType, Deserialize)]
+struct FeatureFlags {
+    icrc2: bool,
+}
+
+#[derive(CandidType, Deserialize)]
+struct ArchiveOptions {
+    num_blocks_to_archive: u64,
+    trigger_threshold: u64,
+    controller_id: Principal,
+    max_transactions_per_response: Option<u64>,
+    max_message_size_bytes: Option<u64>,
+    cycles_for_archive_creation: Option<u64>,
+    node_max_memory_size_bytes: Option<u64>,
+    more_controller_ids: Option<Vec<Principal>>,
+}
+
+#[derive(CandidType, Deserialize)]
+enum MetadataValue {
+    Nat(Nat),
+    Int(candid::Int),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+#[derive(CandidType, Deserialize)]
+struct LedgerInitArgs {
+    minting_account: Account,
+    fee_collector_account: Option<Account>,
+    transfer_fee: Nat,
+    decimals: Option<u8>,
+    max_memo_length: Option<u16>,
+    token_name: String,
+    token_symbol: String,
+    metadata: Vec<(String, MetadataValue)>,
+    initial_balances: Vec<(Account, Nat)>,
+    feature_flags: Option<FeatureFlags>,
+    maximum_number_of_accounts: Option<u64>,
+    accounts_overflow_trim_quantity: Option<u64>,
+    archive_options: ArchiveOptions,
+}
+
+#[derive(CandidType, Deserialize)]
+enum LedgerArg {
+    Init(LedgerInitArgs),
+}
+
+// ─── Treasury init mirror (the crate is cdylib-only) ───
+
+#[derive(CandidType, Deserialize)]
+struct TreasuryInitArgs {
+    controller: Principal,
+    icusd_ledger: Principal,
+    icp_ledger: Principal,
+    ckbtc_ledger: Option<Principal>,
+    ckusdt_ledger: Option<Principal>,
+    ckusdc_ledger: Option<Principal>,
+    withdrawal_destinations: Option<Vec<Principal>>,
+}
+
+#[derive(CandidType, Deserialize, Default)]
+struct MockXrc {
+    rates: Vec<(String, u64)>,
+}
+
+// ─── WASM loaders ───
+
+fn icrc1_ledger_wasm() -> Vec<u8> {
+    include_bytes!("../../ledger/ic-icrc1-ledger.wasm").to_vec()
+}
+
+fn xrc_wasm() -> Vec<u8> {
+    include_bytes!("../../xrc_demo/xrc/xrc.wasm").to_vec()
+}
+
+fn backend_wasm() -> Vec<u8> {
+    include_bytes!("../../../target/wasm32-unknown-unknown/release/rumi_protocol_backend.wasm")
+        .to_vec()
+}
+
+fn stability_pool_wasm() -> Vec<u8> {
+    include_bytes!("../../../target/wasm32-unknown-unknown/release/stability_pool.wasm").to_vec()
+}
+
+fn treasury_wasm() -> Vec<u8> {
+    include_bytes!("../../../target/wasm32-unknown-unknown/release/rumi_treasury.wasm").to_vec()
+}
+
+// ─── The stack ───
+
+/// Backend, stability pool and treasury wired to each other, with ICP and
+/// icUSD ledgers and a mock XRC. `user` holds 10,000 ICP; the pool accepts
+/// icUSD deposits.
+pub struct Stack {
+    pub pic: PocketIc,
+    pub developer: Principal,
+    pub pool_admin: Principal,
+    pub user: Principal,
+    pub icp_ledger: Principal,
+    pub icusd_ledger: Principal,
+    pub xrc: Principal,
+    pub backend: Principal,
+    pub stability_pool: Principal,
+    pub treasury: Principal,
+}
+
+impl Stack {
+    pub fn deploy() -> Self {
+        let pic = PocketIcBuilder::new().with_nns_subnet().build();
+
+        let developer = Principal::self_authenticating(b"consistency_developer");
+        let pool_admin = Principal::self_authenticating(b"consistency_pool_admin");
+        let user = Principal::self_authenticating(b"consistency_user");
+
+        // Every id up front: each canister's init names the others.
+        let backend = pic.create_canister();
+        let stability_pool = pic.create_canister();
+        let treasury = pic.create_canister();
+        for canister in [backend, stability_pool, treasury] {
+            pic.add_cycles(canister, 2_000_000_000_000);
+        }
+        pic.set_controllers(backend, None, vec![Principal::anonymous(), developer])
+            .expect("set backend controllers");
+
+        let icp_ledger = deploy_ledger(
+            &pic,
+            backend,
+            vec![(account(user), Nat::from(10_000 * E8S))],
+            "Internet Computer Protocol",
+            "ICP",
+            developer,
+        );
+        let icusd_ledger = deploy_ledger(&pic, backend, vec![], "icUSD", "icUSD", developer);
+
+        let xrc = pic.create_canister();
+        pic.add_cycles(xrc, 1_000_000_000_000);
+        let rates = MockXrc {
+            rates: vec![("ICP/USD".to_string(), ICP_PRICE_E8S)],
+        };
+        pic.install_canister(xrc, xrc_wasm(), encode_one(rates).unwrap(), None);
+
+        pic.set_time(SystemTime::UNIX_EPOCH + Duration::from_secs(1_711_324_800));
+
+        let init = ProtocolArg::Init(InitArg {
+            xrc_principal: xrc,
+            icusd_ledger_principal: icusd_ledger,
+            icp_ledger_principal: icp_ledger,
+            fee_e8s: LEDGER_FEE,
+            developer_principal: developer,
+            treasury_principal: Some(treasury),
+            stability_pool_principal: Some(stability_pool),
+            ckusdt_ledger_principal: None,
+            ckusdc_ledger_principal: None,
+        });
+        pic.install_canister(backend, backend_wasm(), encode_one(init).unwrap(), None);
+
+        let pool_init = StabilityPoolInitArgs {
+            protocol_canister_id: backend,
+            authorized_admins: vec![pool_admin],
+        };
+        pic.install_canister(
+            stability_pool,
+            stability_pool_wasm(),
+            encode_one(pool_init).unwrap(),
+            None,
+        );
+
+        let treasury_init = TreasuryInitArgs {
+            controller: developer,
+            icusd_ledger,
+            icp_ledger,
+            ckbtc_ledger: None,
+            ckusdt_ledger: None,
+            ckusdc_ledger: None,
+            withdrawal_destinations: None,
+        };
+        pic.install_canister(
+            treasury,
+            treasury_wasm(),
+            encode_one(treasury_init).unwrap(),
+            None,
+        );
+        // `deposit` is controllers-only and the backend calls it.
+        pic.set_controllers(
+            treasury,
+            None,
+            vec![Principal::anonymous(), developer, backend],
+        )
+        .expect("set treasury controllers");
+
+        let stack = Self {
+            pic,
+            developer,
+            pool_admin,
+            user,
+            icp_ledger,
+            icusd_ledger,
+            xrc,
+            backend,
+            stability_pool,
+            treasury,
+        };
+        stack.settle();
+
+        let (registered,): (Result<(), StabilityPoolError>,) = stack.update(
+            stability_pool,
+            pool_admin,
+            "register_stablecoin",
+            (StablecoinConfig {
+                ledger_id: icusd_ledger,
+                symbol: "icUSD".to_string(),
+                decimals: 8,
+                priority: 1,
+                is_active: true,
+                transfer_fee: Some(LEDGER_FEE),
+                is_lp_token: None,
+                underlying_pool: None,
+            },),
+        );
+        registered.expect("register icUSD with the pool");
+        stack
+    }
+
+    /// Let timers and in-flight inter-canister calls run.
+    pub fn settle(&self) {
+        self.pic.advance_time(Duration::from_secs(1));
+        for _ in 0..10 {
+            self.pic.tick();
+        }
+    }
+
+    /// Update call encoding `args` and decoding the reply the way
+    /// `ic_cdk::call` does on the calling canister.
+    pub fn update<R>(
+        &self,
+        canister: Principal,
+        sender: Principal,
+        method: &str,
+        args: impl ArgumentEncoder,
+    ) -> R
+    where
+        R: for<'a> ArgumentDecoder<'a>,
+    {
+        let result = self
+            .pic
+            .update_call(canister, sender, method, encode_args(args).unwrap());
+        decode_reply(method, result)
+    }
+
+    /// Query counterpart of `update`.
+    pub fn query<R>(
+        &self,
+        canister: Principal,
+        sender: Principal,
+        method: &str,
+        args: impl ArgumentEncoder,
+    ) -> R
+    where
+        R: for<'a> ArgumentDecoder<'a>,
+    {
+        let result = self
+            .pic
+            .query_call(canister, sender, method, encode_args(args).unwrap());
+        decode_reply(method, result)
+    }
+
+    pub fn approve(&self, ledger: Principal, owner: Principal, spender: Principal, amount: u64) {
+        let args = ApproveArgs {
+            from_subaccount: None,
+            spender: account(spender),
+            amount: Nat::from(amount),
+            expected_allowance: None,
+            expires_at: None,
+            fee: None,
+            memo: None,
+            created_at_time: None,
+        };
+        let (result,): (Result<Nat, ApproveError>,) =
+            self.update(ledger, owner, "icrc2_approve", (args,));
+        result.expect("icrc2_approve");
+    }
+
+    pub fn transfer(&self, ledger: Principal, from: Principal, to: Principal, amount: u64) {
+        let args = TransferArg {
+            from_subaccount: None,
+            to: account(to),
+            fee: None,
+            created_at_time: None,
+            memo: None,
+            amount: Nat::from(amount),
+        };
+        let (result,): (Result<Nat, TransferError>,) =
+            self.update(ledger, from, "icrc1_transfer", (args,));
+        result.expect("icrc1_transfer");
+    }
+
+    /// Opens an ICP vault for `owner` and borrows `borrow_e8s` icUSD from it.
+    pub fn open_and_borrow(&self, owner: Principal, collateral_e8s: u64, borrow_e8s: u64) -> u64 {
+        self.approve(
+            self.icp_ledger,
+            owner,
+            self.backend,
+            collateral_e8s + LEDGER_FEE,
+        );
+        let (opened,): (Result<OpenVaultSuccess, ProtocolError>,) = self.update(
+            self.backend,
+            owner,
+            "open_vault",
+            (collateral_e8s, None::<Principal>),
+        );
+        let vault_id = opened.expect("open_vault").vault_id;
+        let (borrowed,): (Result<SuccessWithFee, ProtocolError>,) = self.update(
+            self.backend,
+            owner,
+            "borrow_from_vault",
+            (VaultArg {
+                vault_id,
+                amount: borrow_e8s,
+            },),
+        );
+        borrowed.expect("borrow_from_vault");
+        self.settle();
+        vault_id
+    }
+
+    /// Moves the mock XRC's ICP price and waits until the backend has
+    /// fetched it and `vault_id` shows up as liquidatable.
+    pub fn crash_icp_until_liquidatable(&self, vault_id: u64) {
+        self.pic
+            .update_call(
+                self.xrc,
+                self.developer,
+                "set_exchange_rate",
+                encode_args(("ICP".to_string(), "USD".to_string(), ICP_PRICE_E8S / 100)).unwrap(),
+            )
+            .expect("set_exchange_rate");
+        for _ in 0..12 {
+            self.pic.advance_time(Duration::from_secs(310));
+            for _ in 0..6 {
+                self.pic.tick();
+            }
+            if self
+                .liquidatable_vaults()
+                .iter()
+                .any(|v| v.vault_id == vault_id)
+            {
+                return;
+            }
+        }
+        panic!("vault {} never became liquidatable", vault_id);
+    }
+
+    /// `get_liquidatable_vaults`, decoded as the pool decodes it.
+    pub fn liquidatable_vaults(&self) -> Vec<CandidVault> {
+        let (vaults,): (Vec<CandidVault>,) = self.query(
+            self.backend,
+            Principal::anonymous(),
+            "get_liquidatable_vaults",
+            (),
+        );
+        vaults
+    }
+}
+
+pub fn account(owner: Principal) -> Account {
+    Account {
+        owner,
+        subaccount: None,
+    }
+}
+
+fn decode_reply<R>(method: &str, result: Result<WasmResult, UserError>) -> R
+where
+    R: for<'a> ArgumentDecoder<'a>,
+{
+    match result {
+        Ok(WasmResult::Reply(bytes)) => decode_args(&bytes).unwrap_or_else(|e| {
+            panic!(
+                "{}: reply does not decode as {}: {}",
+                method,
+                std::any::type_name::<R>(),
+                e
+            )
+        }),
+        Ok(WasmResult::Reject(message)) => panic!("{}: rejected: {}", method, message),
+        Err(error) => panic!("{}: call failed: {:?}", method, error),
+    }
+}
+
+fn deploy_ledger(
+    pic: &PocketIc,
+    minting_account: Principal,
+    initial_balances: Vec<(Account, Nat)>,
+    name: &str,
+    symbol: &str,
+    controller: Principal,
+) -> Principal {
+    let ledger = pic.create_canister();
+    pic.add_cycles(ledger, 2_000_000_000_000);
+    let init = LedgerInitArgs {
+        minting_account: account(minting_account),
+        fee_collector_account: None,
+        transfer_fee: Nat::from(LEDGER_FEE),
+        decimals: Some(8),
+        max_memo_length: Some(64),
+        token_name: name.to_string(),
+        token_symbol: symbol.to_string(),
+        metadata: vec![],
+        initial_balances,
+        feature_flags: Some(FeatureFlags { icrc2: true }),
+        maximum_number_of_accounts: None,
+        accounts_overflow_trim_quantity: None,
+        archive_options: ArchiveOptions {
+            num_blocks_to_archive: 2000,
+            trigger_threshold: 1000,
+            controller_id: controller,
+            max_transactions_per_response: None,
+            max_message_size_bytes: None,
+            cycles_for_archive_creation: None,
+            node_max_memory_size_bytes: None,
+            more_controller_ids: None,
+        },
+    };
+    pic.install_canister(
+        ledger,
+        icrc1_ledger_wasm(),
+        encode_args((LedgerArg::Init(init),)).unwrap(),
+        None,
+    );
+    ledger
+}
@@ -0,0 +1,66 @@
+//! Backend → stability pool: the liquidatable-vault push, the pool-status
+//! read behind pool priority, the interest notification and the mode push
+//! are sent with the backend's types and the replies decode as the backend
+//! reads them.
+//!
+//! Each call is made with the backend as the sender, as the pool only
+//! accepts them from its protocol canister.
+
+use rumi_integration_tests::{Stack, E8S};
+use rumi_protocol_backend::pool_priority::PoolStatusDepth;
+use rumi_protocol_backend::state::Mode;
+use rumi_protocol_backend::treasury::StabilityPoolInterestNotificationResult;
+use rumi_protocol_backend::LiquidatableVaultInfo;
+
+#[test]
+fn liquidatable_vault_pushes_decode_as_the_pool_reads_them() {
+    let stack = Stack::deploy();
+    let vault = LiquidatableVaultInfo {
+        vault_id: 42,
+        collateral_type: stack.icp_ledger,
+        debt_amount: 100 * E8S,
+        collateral_amount: 50 * E8S,
+        recommended_liquidation_amount: 40 * E8S,
+        collateral_price_e8s: E8S,
+    };
+    // The backend ignores the reply, so any reply is fine; a pool that
+    // cannot decode the push rejects the call.
+    let () = stack.update(
+        stack.stability_pool,
+        stack.backend,
+        "notify_liquidatable_vaults",
+        (vec![vault],),
+    );
+}
+
+#[test]
+fn pool_status_decodes_as_the_backend_reads_it() {
+    let stack = Stack::deploy();
+    let (_depth,): (PoolStatusDepth,) =
+        stack.query(stack.stability_pool, stack.backend, "get_pool_status", ());
+}
+
+#[test]
+fn interest_notifications_decode_as_the_backend_reads_them() {
+    let stack = Stack::deploy();
+    // No icUSD backs this notification, so the pool may refuse it; either
+    // arm must decode.
+    let (_result,): (StabilityPoolInterestNotificationResult,) = stack.update(
+        stack.stability_pool,
+        stack.backend,
+        "receive_interest_revenue_v2",
+        (stack.icusd_ledger, E8S, Some(stack.icp_ledger), 7u64),
+    );
+}
+
+#[test]
+fn mode_pushes_decode_as_the_pool_reads_them() {
+    let stack = Stack::deploy();
+    let (result,): (Result<(), candid::Reserved>,) = stack.update(
+        stack.stability_pool,
+        stack.backend,
+        "set_protocol_mode",
+        (Mode::Recovery,),
+    );
+    assert!(result.is_ok());
+}
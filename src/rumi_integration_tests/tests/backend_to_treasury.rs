@@ -0,0 +1,91 @@
+//! Backend → treasury: fee routing (`mint_borrowing_fee_to_treasury` →
+//! `deposit`), a direct `deposit` with the backend's mirror of the treasury
+//! arguments, and the mode push all decode on both sides.
+//!
+//! Fixture: a 0.01% base borrowing fee; `user` opens a 50 ICP vault at $10
+//! and borrows 100 icUSD.
+
+use candid::{CandidType, Deserialize, Principal};
+
+use rumi_integration_tests::{Stack, E8S};
+use rumi_protocol_backend::state::Mode;
+use rumi_protocol_backend::treasury::{AssetType, DepositArgs, DepositType};
+
+/// The part of the treasury's `DepositRecord` the suite reads; Candid
+/// skips the remaining fields.
+#[derive(CandidType, Deserialize, Debug)]
+struct DepositView {
+    id: u64,
+    deposit_type: DepositType,
+    ledger: Option<Principal>,
+    amount: u64,
+}
+
+fn deposits(stack: &Stack) -> Vec<DepositView> {
+    let (deposits,): (Vec<DepositView>,) = stack.query(
+        stack.treasury,
+        Principal::anonymous(),
+        "get_deposits",
+        (None::<u64>, None::<usize>),
+    );
+    deposits
+}
+
+#[test]
+fn borrowing_fees_are_routed_to_the_treasury() {
+    let stack = Stack::deploy();
+    stack.open_and_borrow(stack.user, 50 * E8S, 100 * E8S);
+    stack.settle();
+
+    let fee = deposits(&stack)
+        .into_iter()
+        .find(|d| matches!(d.deposit_type, DepositType::BorrowingFee))
+        .expect("borrowing fee deposit");
+    assert_eq!(fee.ledger, Some(stack.icusd_ledger));
+    // The fee curve scales the 0.01% base fee by the vault's ratio.
+    assert!(fee.amount > 0 && fee.amount < E8S);
+}
+
+#[test]
+fn deposits_decode_as_the_treasury_reads_them() {
+    let stack = Stack::deploy();
+    let args = DepositArgs {
+        deposit_type: DepositType::LiquidationFee,
+        asset_type: Some(AssetType::ICP),
+        ledger: Some(stack.icp_ledger),
+        amount: 3 * E8S,
+        block_index: 9,
+        memo: Some("consistency".to_string()),
+    };
+    let (id,): (Result<u64, String>,) =
+        stack.update(stack.treasury, stack.backend, "deposit", (args,));
+    let id = id.expect("deposit");
+
+    let recorded = deposits(&stack)
+        .into_iter()
+        .find(|d| d.id == id)
+        .expect("recorded");
+    assert!(matches!(recorded.deposit_type, DepositType::LiquidationFee));
+    assert_eq!(recorded.ledger, Some(stack.icp_ledger));
+    assert_eq!(recorded.amount, 3 * E8S);
+}
+
+#[test]
+fn mode_pushes_decode_as_the_treasury_reads_them() {
+    let stack = Stack::deploy();
+    let (configured,): (Result<(), String>,) = stack.update(
+        stack.treasury,
+        stack.developer,
+        "set_protocol_backend",
+        (Some(stack.backend),),
+    );
+    configured.expect("set_protocol_backend");
+
+    let (result,): (Result<(), candid::Reserved>,) = stack.update(
+        stack.treasury,
+        stack.backend,
+        "set_protocol_mode",
+        (Mode::Recovery,),
+    );
+    assert!(result.is_ok());
+}
@@ -0,0 +1,116 @@
+//! Stability pool → backend: the vault queries the pool reads, the
+//! `stability_pool_liquidate` entry point, and a full pool liquidation
+//! (`execute_liquidation` → `get_liquidatable_vaults` →
+//! `liquidate_vault_partial`) all decode with the pool's types.
+//!
+//! Fixture: `user` opens a 50 ICP vault at $10, borrows 100 icUSD, and ICP
+//! then drops to $0.10.
+
+use candid::Principal;
+
+use rumi_common::types::LiquidationPreview;
+use rumi_integration_tests::{Stack, E8S, LEDGER_FEE};
+use rumi_protocol_backend::{ProtocolError, StabilityPoolLiquidationResult};
+use stability_pool::types::{LiquidationResult, StabilityPoolError};
+
+fn underwater_vault(stack: &Stack) -> u64 {
+    let vault_id = stack.open_and_borrow(stack.user, 50 * E8S, 100 * E8S);
+    stack.crash_icp_until_liquidatable(vault_id);
+    vault_id
+}
+
+#[test]
+fn vault_queries_decode_as_the_pool_reads_them() {
+    let stack = Stack::deploy();
+    let vault_id = underwater_vault(&stack);
+
+    let vault = stack
+        .liquidatable_vaults()
+        .into_iter()
+        .find(|v| v.vault_id == vault_id)
+        .expect("listed");
+    assert_eq!(vault.collateral_type, stack.icp_ledger);
+    assert!(vault.borrowed_icusd_amount >= 100 * E8S);
+
+    let (preview,): (Result<LiquidationPreview, ProtocolError>,) = stack.query(
+        stack.backend,
+        Principal::anonymous(),
+        "preview_liquidation",
+        (vault_id, None::<u64>),
+    );
+    let preview = preview.expect("preview_liquidation");
+    assert_eq!(preview.vault_id, vault_id);
+    assert!(preview.liquidatable);
+    assert!(preview.repay_e8s > 0);
+}
+
+#[test]
+fn stability_pool_liquidate_decodes_as_the_pool_reads_it() {
+    let stack = Stack::deploy();
+    let vault_id = underwater_vault(&stack);
+
+    // The backend pulls the repayment from its caller, the pool.
+    let repay = 50 * E8S;
+    stack.transfer(
+        stack.icusd_ledger,
+        stack.user,
+        stack.stability_pool,
+        repay + 2 * LEDGER_FEE,
+    );
+    stack.approve(
+        stack.icusd_ledger,
+        stack.stability_pool,
+        stack.backend,
+        repay + LEDGER_FEE,
+    );
+
+    let (result,): (Result<StabilityPoolLiquidationResult, ProtocolError>,) = stack.update(
+        stack.backend,
+        stack.stability_pool,
+        "stability_pool_liquidate",
+        (vault_id, repay),
+    );
+    let result = result.expect("stability_pool_liquidate");
+    assert!(result.success);
+    assert_eq!(result.vault_id, vault_id);
+    assert_eq!(result.collateral_type, stack.icp_ledger.to_string());
+    assert!(result.liquidated_debt > 0 && result.liquidated_debt <= repay);
+    assert!(result.collateral_received > 0);
+}
+
+#[test]
+fn pool_liquidations_round_trip_through_the_backend() {
+    let stack = Stack::deploy();
+    let vault_id = underwater_vault(&stack);
+
+    let deposit = 90 * E8S;
+    stack.approve(
+        stack.icusd_ledger,
+        stack.user,
+        stack.stability_pool,
+        deposit + LEDGER_FEE,
+    );
+    let (deposited,): (Result<(), StabilityPoolError>,) = stack.update(
+        stack.stability_pool,
+        stack.user,
+        "deposit",
+        (stack.icusd_ledger, deposit),
+    );
+    deposited.expect("deposit");
+
+    // A decode failure inside the pool surfaces as `InterCanisterCallFailed`
+    // or as an unsuccessful result naming the call.
+    let keeper = Principal::self_authenticating(b"consistency_keeper");
+    let (result,): (Result<LiquidationResult, StabilityPoolError>,) = stack.update(
+        stack.stability_pool,
+        keeper,
+        "execute_liquidation",
+        (vault_id,),
+    );
+    let result = result.expect("execute_liquidation");
+    assert!(result.success, "{:?}", result.error_message);
+    assert_eq!(result.vault_id, vault_id);
+    assert_eq!(result.collateral_type, stack.icp_ledger);
+    assert!(result.collateral_gained > 0);
+    assert!(result.stables_consumed[&stack.icusd_ledger] > 0);
+}
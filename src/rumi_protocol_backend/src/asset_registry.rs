@@ -0,0 +1,176 @@
+//! Registry of the ledgers the protocol works with and their ICRC-1
+//! metadata.
+//!
+//! `get_supported_assets` lists every ledger the backend touches, icUSD,
+//! each ICRC-custodied collateral, the ckUSDT/ckUSDC repayment stables and
+//! the 3USD token, with the name, symbol, decimals, fee and logo each ledger
+//! reports from `icrc1_metadata`, so a frontend renders them without a call
+//! per ledger. The metadata is cached in `State::asset_metadata` and
+//! refreshed every `ASSET_METADATA_REFRESH_INTERVAL_SECS`; a ledger that
+//! fails to answer keeps its previous entry, and one never fetched is listed
+//! with no metadata. Native XRP collateral has no ICRC ledger and is left
+//! out.
+
+use crate::logs::INFO;
+use crate::state::{mutate_state, read_state, State};
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use icrc_ledger_types::icrc::generic_metadata_value::MetadataValue;
+use num_traits::ToPrimitive;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// How often the cached metadata is refreshed (6 hours).
+pub const ASSET_METADATA_REFRESH_INTERVAL_SECS: u64 = 6 * 60 * 60;
+
+/// What the protocol uses a ledger for.
+#[derive(
+    CandidType, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum AssetRole {
+    /// icUSD, the protocol's own stablecoin.
+    Stablecoin,
+    Collateral,
+    /// Accepted to repay debt (ckUSDT, ckUSDC).
+    RepaymentStable,
+    /// The 3pool LP token the protocol holds as reserves.
+    ThreeUsd,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub fee: u64,
+    /// `icrc1:logo`, usually a data URL.
+    pub logo: Option<String>,
+    pub fetched_at_ns: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct SupportedAsset {
+    pub ledger: Principal,
+    pub roles: Vec<AssetRole>,
+    /// `None` until the ledger's metadata has been fetched.
+    pub metadata: Option<AssetMetadata>,
+}
+
+/// Every ledger the protocol works with, with its roles.
+pub fn supported_ledgers(state: &State) -> BTreeMap<Principal, Vec<AssetRole>> {
+    let mut ledgers: BTreeMap<Principal, Vec<AssetRole>> = BTreeMap::new();
+    let mut add = |ledger: Principal, role: AssetRole| {
+        let roles = ledgers.entry(ledger).or_default();
+        if !roles.contains(&role) {
+            roles.push(role);
+        }
+    };
+    add(state.icusd_ledger_principal, AssetRole::Stablecoin);
+    for (ledger, config) in &state.collateral_configs {
+        if !config.is_native_xrp() {
+            add(*ledger, AssetRole::Collateral);
+        }
+    }
+    for ledger in [state.ckusdt_ledger_principal, state.ckusdc_ledger_principal]
+        .into_iter()
+        .flatten()
+    {
+        add(ledger, AssetRole::RepaymentStable);
+    }
+    if let Some(three_pool) = state.three_pool_canister {
+        add(three_pool, AssetRole::ThreeUsd);
+    }
+    ledgers
+}
+
+pub fn supported_assets(state: &State) -> Vec<SupportedAsset> {
+    supported_ledgers(state)
+        .into_iter()
+        .map(|(ledger, roles)| SupportedAsset {
+            ledger,
+            roles,
+            metadata: state.asset_metadata.get(&ledger).cloned(),
+        })
+        .collect()
+}
+
+/// Read the standard fields out of an `icrc1_metadata` response. `None` if
+/// the name, symbol, decimals or fee is missing.
+pub fn parse_metadata(
+    entries: Vec<(String, MetadataValue)>,
+    fetched_at_ns: u64,
+) -> Option<AssetMetadata> {
+    let mut name = None;
+    let mut symbol = None;
+    let mut decimals = None;
+    let mut fee = None;
+    let mut logo = None;
+    for (key, value) in entries {
+        match (key.as_str(), value) {
+            ("icrc1:name", MetadataValue::Text(text)) => name = Some(text),
+            ("icrc1:symbol", MetadataValue::Text(text)) => symbol = Some(text),
+            ("icrc1:decimals", MetadataValue::Nat(n)) => decimals = n.0.to_u8(),
+            ("icrc1:fee", MetadataValue::Nat(n)) => fee = n.0.to_u64(),
+            ("icrc1:logo", MetadataValue::Text(text)) => logo = Some(text),
+            _ => {}
+        }
+    }
+    Some(AssetMetadata {
+        name: name?,
+        symbol: symbol?,
+        decimals: decimals?,
+        fee: fee?,
+        logo,
+        fetched_at_ns,
+    })
+}
+
+/// Fetch every supported ledger's metadata and replace the cache. Entries
+/// for ledgers no longer supported are dropped.
+pub async fn refresh_asset_metadata() {
+    let ledgers: Vec<Principal> = read_state(|s| supported_ledgers(s).into_keys().collect());
+    let mut refreshed = 0;
+    for ledger in &ledgers {
+        let metadata = match ic_cdk::call::<(), (Vec<(String, MetadataValue)>,)>(
+            *ledger,
+            "icrc1_metadata",
+            (),
+        )
+        .await
+        {
+            Ok((entries,)) => parse_metadata(entries, ic_cdk::api::time()),
+            Err((code, msg)) => {
+                log!(
+                    INFO,
+                    "[refresh_asset_metadata] icrc1_metadata from {} failed: {:?} {}",
+                    ledger,
+                    code,
+                    msg
+                );
+                continue;
+            }
+        };
+        match metadata {
+            Some(metadata) => {
+                mutate_state(|s| s.asset_metadata.insert(*ledger, metadata));
+                refreshed += 1;
+            }
+            None => log!(
+                INFO,
+                "[refresh_asset_metadata] {} returned incomplete metadata",
+                ledger
+            ),
+        }
+    }
+    mutate_state(|s| {
+        let supported = supported_ledgers(s);
+        s.asset_metadata
+            .retain(|ledger, _| supported.contains_key(ledger));
+    });
+    log!(
+        INFO,
+        "[refresh_asset_metadata] refreshed {} of {} ledgers",
+        refreshed,
+        ledgers.len()
+    );
+}
@@ -0,0 +1,636 @@
+//! Dutch-auction fallback for liquidations.
+//!
+//! `check_vaults` offers an unhealthy vault to the liquidation bot and then
+//! to the stability pool, once each; after that the vault is only listed for
+//! manual liquidation. When the pool could not absorb it and no liquidator
+//! has stepped in for `grace_period_secs`, the protocol auctions the vault's
+//! collateral itself. The price starts `start_premium_bps` above the oracle
+//! price and drops `decay_bps_per_step` of the start price every
+//! `step_secs` (the canister has no block height, so a step stands in for a
+//! block) until it reaches `floor_bps` of the oracle price. Anyone can fill
+//! part of an auction with `bid_auction`: the bid's icUSD is burned against
+//! the vault's debt and the bidder receives collateral at the current
+//! price.
+//!
+//! An auction covers the debt a partial liquidation would
+//! (`compute_partial_liquidation_cap`, fixed when it starts). It ends once
+//! that is covered, when the vault's collateral runs out (debt left on the
+//! emptied vault is written off as a liquidation deficit), when the vault
+//! recovers or leaves by another path, or after `max_duration_secs`. A vault
+//! still unhealthy after its auction expired is redistributed when
+//! `redistribution_enabled` is set (see `redistribution`), and otherwise
+//! becomes a candidate again.
+//! Other liquidation paths stay open while an auction runs.
+//!
+//! The config, starts, bids and ends are recorded as events. How long each
+//! candidate has waited is routing state, like `bot_pending_vaults`, and is
+//! not replayed.
+
+use crate::guard::{trace_tag, GuardPrincipal, VaultLiquidationGuard};
+use crate::logs::INFO;
+use crate::numeric::{collateral_usd_value, icusd_to_collateral_amount, ICP, ICUSD};
+use crate::state::{mutate_state, read_state, State};
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+const E8S: u64 = 100_000_000;
+
+/// Ended auctions kept for queries; older ones are dropped.
+pub const MAX_ENDED_AUCTIONS: usize = 200;
+
+/// Auctions started by one vault check at most.
+pub const MAX_AUCTION_STARTS_PER_TICK: usize = 10;
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuctionConfig {
+    pub enabled: bool,
+    /// How long a vault must stay liquidatable after the stability pool had
+    /// its shot before it is auctioned.
+    pub grace_period_secs: u64,
+    /// Start price above the oracle price, in bps.
+    pub start_premium_bps: u64,
+    /// Price drop per step, in bps of the start price.
+    pub decay_bps_per_step: u64,
+    pub step_secs: u64,
+    /// Lowest price, in bps of the oracle price at the start.
+    pub floor_bps: u64,
+    /// Auctions still open this long after starting end as expired.
+    pub max_duration_secs: u64,
+}
+
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuctionEndReason {
+    /// The debt target was covered.
+    Filled,
+    /// The vault's collateral was sold out.
+    CollateralExhausted,
+    /// The vault was no longer liquidatable at a vault check.
+    VaultRecovered,
+    /// The vault left by another path (liquidation, repayment, close).
+    VaultClosed,
+    Expired,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuctionEnd {
+    pub reason: AuctionEndReason,
+    pub at: u64,
+}
+
+/// Prices are USD e8s per whole collateral token; collateral amounts are in
+/// the collateral's native units.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Auction {
+    pub id: u64,
+    pub vault_id: u64,
+    pub collateral_type: Principal,
+    pub start_price_e8s: u64,
+    pub floor_price_e8s: u64,
+    pub decay_bps_per_step: u64,
+    pub step_secs: u64,
+    pub started_at: u64,
+    pub expires_at: u64,
+    pub debt_target_e8s: u64,
+    pub debt_covered_e8s: u64,
+    pub collateral_sold: u64,
+    pub bids: u64,
+    pub ended: Option<AuctionEnd>,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct AuctionView {
+    pub auction: Auction,
+    /// What a bid placed now would pay.
+    pub current_price_e8s: u64,
+    pub remaining_debt_e8s: u64,
+    /// The vault's collateral; zero once it has left.
+    pub collateral_available: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct AuctionBidSuccess {
+    pub auction_id: u64,
+    pub icusd_paid_e8s: u64,
+    pub collateral_received: u64,
+    pub price_e8s: u64,
+    pub block_index: u64,
+    /// Set when this bid ended the auction.
+    pub ended: Option<AuctionEndReason>,
+}
+
+/// A bid sized against the auction and the vault. See `plan_bid`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BidPlan {
+    pub auction_id: u64,
+    pub vault_id: u64,
+    pub collateral_type: Principal,
+    pub owner: Principal,
+    pub price_e8s: u64,
+    pub icusd: ICUSD,
+    pub collateral: u64,
+    /// Debt left on the vault once this bid takes its last collateral.
+    pub debt_written_off: ICUSD,
+}
+
+pub fn validate_config(config: &AuctionConfig) -> Result<(), ProtocolError> {
+    let invalid = |msg: &str| Err(ProtocolError::GenericError(msg.to_string()));
+    if config.step_secs == 0 || config.max_duration_secs == 0 {
+        return invalid("step_secs and max_duration_secs must be positive");
+    }
+    if config.decay_bps_per_step == 0 || config.decay_bps_per_step > 10_000 {
+        return invalid("decay_bps_per_step must be in 1..=10000");
+    }
+    if config.start_premium_bps > 10_000 {
+        return invalid("start_premium_bps must be at most 10000");
+    }
+    if config.floor_bps == 0 || config.floor_bps > 10_000 + config.start_premium_bps {
+        return invalid("floor_bps must be positive and not above the start price");
+    }
+    Ok(())
+}
+
+fn bps_of(amount: u64, bps: u64) -> u64 {
+    (amount as u128 * bps as u128 / 10_000) as u64
+}
+
+fn price_decimal(price_e8s: u64) -> Decimal {
+    Decimal::from(price_e8s) / Decimal::from(E8S)
+}
+
+/// The auction's price at `now`: the start price less one decay per whole
+/// step elapsed, never below the floor.
+pub fn price_at(auction: &Auction, now: u64) -> u64 {
+    let step_ns = auction.step_secs.max(1).saturating_mul(NANOS_PER_SEC);
+    let steps = now.saturating_sub(auction.started_at) / step_ns;
+    let decay = bps_of(auction.start_price_e8s, auction.decay_bps_per_step);
+    auction
+        .start_price_e8s
+        .saturating_sub(decay.saturating_mul(steps))
+        .max(auction.floor_price_e8s)
+}
+
+pub fn remaining_debt(auction: &Auction) -> u64 {
+    auction
+        .debt_target_e8s
+        .saturating_sub(auction.debt_covered_e8s)
+}
+
+pub fn view(state: &State, auction: &Auction, now: u64) -> AuctionView {
+    AuctionView {
+        auction: auction.clone(),
+        current_price_e8s: price_at(auction, now),
+        remaining_debt_e8s: remaining_debt(auction),
+        collateral_available: state
+            .vault_id_to_vaults
+            .get(&auction.vault_id)
+            .map_or(0, |v| v.collateral_amount),
+    }
+}
+
+/// The open auction of `vault_id`, if any.
+pub fn active_auction_of(state: &State, vault_id: u64) -> Option<&Auction> {
+    state
+        .auctions
+        .values()
+        .find(|a| a.vault_id == vault_id && a.ended.is_none())
+}
+
+/// The vault's oracle price, or why it cannot be auctioned or bid on.
+pub(crate) fn liquidatable_price(state: &State, vault_id: u64) -> Result<Decimal, String> {
+    let vault = state
+        .vault_id_to_vaults
+        .get(&vault_id)
+        .ok_or_else(|| format!("Vault #{} not found", vault_id))?;
+    if let Some(status) = state.get_collateral_status(&vault.collateral_type) {
+        if !status.allows_liquidation() {
+            return Err("Liquidation is not allowed for this collateral type.".to_string());
+        }
+    }
+    let price = state
+        .get_collateral_price_decimal(&vault.collateral_type)
+        .ok_or_else(|| "No price available for collateral.".to_string())?;
+    let ratio = crate::compute_collateral_ratio(vault, price.into(), state);
+    if ratio >= state.get_min_liquidation_ratio_for(&vault.collateral_type) {
+        return Err(format!("Vault #{} is not liquidatable", vault_id));
+    }
+    Ok(price)
+}
+
+/// The auction `vault_id` would get if it started at `now`.
+pub fn plan_start(
+    state: &State,
+    vault_id: u64,
+    config: &AuctionConfig,
+    now: u64,
+) -> Result<Auction, String> {
+    let price = liquidatable_price(state, vault_id)?;
+    let vault = &state.vault_id_to_vaults[&vault_id];
+    if vault.bot_processing {
+        return Err(format!("Vault #{} is being processed by the bot", vault_id));
+    }
+    let debt_target = state
+        .compute_partial_liquidation_cap(vault, price.into())
+        .min(vault.borrowed_icusd_amount);
+    if debt_target.0 == 0 {
+        return Err(format!("Vault #{} has no debt to auction", vault_id));
+    }
+    let oracle_e8s = (price * Decimal::from(E8S)).to_u64().unwrap_or(0);
+    if oracle_e8s == 0 {
+        return Err("Collateral price is zero".to_string());
+    }
+    Ok(Auction {
+        id: state.next_auction_id,
+        vault_id,
+        collateral_type: vault.collateral_type,
+        start_price_e8s: bps_of(oracle_e8s, 10_000 + config.start_premium_bps),
+        floor_price_e8s: bps_of(oracle_e8s, config.floor_bps).max(1),
+        decay_bps_per_step: config.decay_bps_per_step,
+        step_secs: config.step_secs,
+        started_at: now,
+        expires_at: now.saturating_add(config.max_duration_secs.saturating_mul(NANOS_PER_SEC)),
+        debt_target_e8s: debt_target.to_u64(),
+        debt_covered_e8s: 0,
+        collateral_sold: 0,
+        bids: 0,
+        ended: None,
+    })
+}
+
+/// Size a bid of up to `icusd_amount` on `auction_id` at `now`: capped at
+/// the debt the auction still covers, priced at the auction's current price,
+/// and cut down to the vault's collateral when that runs out.
+pub fn plan_bid(
+    state: &State,
+    auction_id: u64,
+    icusd_amount: u64,
+    now: u64,
+) -> Result<BidPlan, ProtocolError> {
+    let generic = ProtocolError::GenericError;
+    let auction = state
+        .auctions
+        .get(&auction_id)
+        .ok_or_else(|| generic(format!("Auction #{} not found", auction_id)))?;
+    if auction.ended.is_some() || now >= auction.expires_at {
+        return Err(generic(format!("Auction #{} has ended", auction_id)));
+    }
+    liquidatable_price(state, auction.vault_id).map_err(generic)?;
+    let vault = &state.vault_id_to_vaults[&auction.vault_id];
+    let decimals = state
+        .get_collateral_config(&vault.collateral_type)
+        .map_or(8, |c| c.decimals);
+
+    let remaining = remaining_debt(auction).min(vault.borrowed_icusd_amount.to_u64());
+    let mut icusd = icusd_amount.min(remaining);
+    if icusd == 0 {
+        return Err(generic("Nothing left to bid for".to_string()));
+    }
+    let min_amount = state.min_icusd_amount.to_u64();
+    if icusd < min_amount && icusd < remaining {
+        return Err(ProtocolError::AmountTooLow {
+            minimum_amount: min_amount,
+        });
+    }
+
+    let price_e8s = price_at(auction, now);
+    let price = price_decimal(price_e8s);
+    let mut collateral = icusd_to_collateral_amount(ICUSD::new(icusd), price, decimals);
+    let mut debt_written_off = ICUSD::new(0);
+    if collateral >= vault.collateral_amount {
+        collateral = vault.collateral_amount;
+        icusd = collateral_usd_value(collateral, price, decimals)
+            .to_u64()
+            .min(icusd);
+        debt_written_off = vault
+            .borrowed_icusd_amount
+            .saturating_sub(ICUSD::new(icusd));
+    }
+    if collateral == 0 || icusd == 0 {
+        return Err(generic(
+            "The bid is too small to buy any collateral".to_string(),
+        ));
+    }
+    Ok(BidPlan {
+        auction_id,
+        vault_id: auction.vault_id,
+        collateral_type: vault.collateral_type,
+        owner: vault.owner,
+        price_e8s,
+        icusd: ICUSD::new(icusd),
+        collateral,
+        debt_written_off,
+    })
+}
+
+/// Register `auction` and drop its vault from the candidates.
+/// `next_auction_id` only ever moves past the recorded id.
+pub fn apply_start(state: &mut State, auction: Auction) {
+    state.next_auction_id = state.next_auction_id.max(auction.id + 1);
+    state.auction_candidates.remove(&auction.vault_id);
+    state.auctions.insert(auction.id, auction);
+}
+
+/// Repay `icusd` of the vault's debt, take `collateral` off it and write off
+/// `debt_written_off`. Returns the interest share of the repayment. A bid on
+/// a vault that has since gone still counts toward the auction's totals.
+pub fn apply_bid(
+    state: &mut State,
+    auction_id: u64,
+    vault_id: u64,
+    icusd: ICUSD,
+    collateral: u64,
+    debt_written_off: ICUSD,
+) -> ICUSD {
+    if let Some(auction) = state.auctions.get_mut(&auction_id) {
+        auction.debt_covered_e8s = auction.debt_covered_e8s.saturating_add(icusd.to_u64());
+        auction.collateral_sold = auction.collateral_sold.saturating_add(collateral);
+        auction.bids += 1;
+    }
+    if !state.vault_id_to_vaults.contains_key(&vault_id) {
+        return ICUSD::new(0);
+    }
+    let (interest_share, _) = state.repay_to_vault(vault_id, icusd);
+    state.remove_margin_from_vault(vault_id, ICP::new(collateral));
+    if debt_written_off.0 > 0 {
+        let _ = state.repay_to_vault(vault_id, debt_written_off);
+    }
+    state.cleanup_if_drained(vault_id);
+    interest_share
+}
+
+/// Mark `auction_id` ended, keeping only the newest `MAX_ENDED_AUCTIONS`
+/// ended auctions.
+pub fn apply_end(state: &mut State, auction_id: u64, reason: AuctionEndReason, at: u64) {
+    if let Some(auction) = state.auctions.get_mut(&auction_id) {
+        auction.ended = Some(AuctionEnd { reason, at });
+    }
+    let ended: Vec<u64> = state
+        .auctions
+        .values()
+        .filter(|a| a.ended.is_some())
+        .map(|a| a.id)
+        .collect();
+    for id in ended
+        .iter()
+        .take(ended.len().saturating_sub(MAX_ENDED_AUCTIONS))
+    {
+        state.auctions.remove(id);
+    }
+}
+
+/// How `auction_id` ends after a bid, if it does.
+pub fn end_after_bid(state: &State, auction_id: u64) -> Option<AuctionEndReason> {
+    let auction = state.auctions.get(&auction_id)?;
+    let collateral_left = state
+        .vault_id_to_vaults
+        .get(&auction.vault_id)
+        .map_or(0, |v| v.collateral_amount);
+    if collateral_left == 0 {
+        Some(AuctionEndReason::CollateralExhausted)
+    } else if remaining_debt(auction) == 0 {
+        Some(AuctionEndReason::Filled)
+    } else {
+        None
+    }
+}
+
+/// The auctions a vault check with `unhealthy` vaults ends, and why.
+pub fn due_ends(
+    state: &State,
+    unhealthy: &BTreeSet<u64>,
+    now: u64,
+) -> Vec<(u64, AuctionEndReason)> {
+    state
+        .auctions
+        .values()
+        .filter(|a| a.ended.is_none())
+        .filter_map(|a| {
+            let reason = if !state.vault_id_to_vaults.contains_key(&a.vault_id) {
+                AuctionEndReason::VaultClosed
+            } else if !unhealthy.contains(&a.vault_id) {
+                AuctionEndReason::VaultRecovered
+            } else if now >= a.expires_at {
+                AuctionEndReason::Expired
+            } else {
+                return None;
+            };
+            Some((a.id, reason))
+        })
+        .collect()
+}
+
+/// Track how long each vault the stability pool passed on has stayed
+/// unhealthy without an auction, and return those past the grace period,
+/// longest waiting first.
+pub fn update_candidates(state: &mut State, unhealthy: &BTreeSet<u64>, now: u64) -> Vec<u64> {
+    let Some(config) = state.auction_config.clone().filter(|c| c.enabled) else {
+        state.auction_candidates.clear();
+        return Vec::new();
+    };
+    let eligible: BTreeSet<u64> = state
+        .sp_attempted_vaults
+        .iter()
+        .filter(|id| unhealthy.contains(id) && active_auction_of(state, **id).is_none())
+        .copied()
+        .collect();
+    state
+        .auction_candidates
+        .retain(|id, _| eligible.contains(id));
+    for id in &eligible {
+        state.auction_candidates.entry(*id).or_insert(now);
+    }
+    let grace_ns = config.grace_period_secs.saturating_mul(NANOS_PER_SEC);
+    let mut due: Vec<(u64, u64)> = state
+        .auction_candidates
+        .iter()
+        .filter(|(_, since)| now.saturating_sub(**since) >= grace_ns)
+        .map(|(id, since)| (*since, *id))
+        .collect();
+    due.sort();
+    due.into_iter()
+        .take(MAX_AUCTION_STARTS_PER_TICK)
+        .map(|(_, id)| id)
+        .collect()
+}
+
+/// End the auctions a vault check settles and start those that are due.
+/// Called from `check_vaults` with the vaults it found unhealthy.
+pub fn on_vault_check(state: &mut State, unhealthy: &BTreeSet<u64>, now: u64) {
+    for (auction_id, reason) in due_ends(state, unhealthy, now) {
+        let vault_id = state.auctions[&auction_id].vault_id;
+        crate::event::record_auction_ended(state, auction_id, reason, now);
+        if reason == AuctionEndReason::Expired && state.redistribution_enabled {
+            // Skip a vault another operation is on; it stays a candidate.
+            let redistributed = VaultLiquidationGuard::new(vault_id)
+                .and_then(|_guard| crate::event::record_vault_redistributed(state, vault_id, now));
+            match redistributed {
+                Ok(handed_out) => log!(
+                    INFO,
+                    "[auction] vault #{} redistributed after auction #{} expired: {} collateral, {} icUSD e8s",
+                    vault_id,
+                    auction_id,
+                    handed_out.collateral,
+                    handed_out.debt
+                ),
+                Err(e) => log!(
+                    INFO,
+                    "[auction] vault #{} not redistributed: {:?}",
+                    vault_id,
+                    e
+                ),
+            }
+        }
+    }
+    let Some(config) = state.auction_config.clone() else {
+        return;
+    };
+    for vault_id in update_candidates(state, unhealthy, now) {
+        match plan_start(state, vault_id, &config, now) {
+            Ok(auction) => {
+                log!(
+                    INFO,
+                    "[auction] #{} started for vault #{}: {} icUSD e8s from {} down to {}",
+                    auction.id,
+                    vault_id,
+                    auction.debt_target_e8s,
+                    auction.start_price_e8s,
+                    auction.floor_price_e8s
+                );
+                crate::event::record_auction_started(state, auction);
+            }
+            Err(reason) => {
+                log!(
+                    INFO,
+                    "[auction] vault #{} not auctioned: {}",
+                    vault_id,
+                    reason
+                );
+            }
+        }
+    }
+}
+
+/// Fill up to `icusd_amount` of `auction_id` for the caller.
+pub async fn bid(
+    auction_id: u64,
+    icusd_amount: u64,
+    min_collateral_out: Option<u64>,
+) -> Result<AuctionBidSuccess, ProtocolError> {
+    let caller = ic_cdk::caller();
+    let guard_principal = GuardPrincipal::new(caller, &format!("auction_bid_{}", auction_id))?;
+    let vault_id = read_state(|s| s.auctions.get(&auction_id).map(|a| a.vault_id))
+        .ok_or_else(|| ProtocolError::GenericError(format!("Auction #{} not found", auction_id)))?;
+    let _vault_guard = VaultLiquidationGuard::new(vault_id)?;
+
+    let now = ic_cdk::api::time();
+    mutate_state(|s| s.accrue_single_vault(vault_id, now));
+    let plan = match read_state(|s| plan_bid(s, auction_id, icusd_amount, now)) {
+        Ok(plan) => plan,
+        Err(e) => {
+            guard_principal.fail();
+            return Err(e);
+        }
+    };
+    if let Err(e) =
+        crate::vault::check_min_collateral_out(ICP::new(plan.collateral), min_collateral_out)
+    {
+        guard_principal.fail();
+        return Err(e);
+    }
+
+    let block_index = match crate::management::transfer_icusd_from(plan.icusd, caller).await {
+        Ok(block_index) => block_index,
+        Err(e) => {
+            guard_principal.fail();
+            return Err(ProtocolError::TransferFromError(e, plan.icusd.to_u64()));
+        }
+    };
+
+    let (interest_share, ended) = mutate_state(|s| {
+        let vault_before = s.vault_id_to_vaults.get(&vault_id).cloned();
+        let oracle_price = s.get_collateral_price_decimal(&plan.collateral_type);
+        let now = ic_cdk::api::time();
+        let interest_share = crate::event::record_auction_bid(s, &plan, caller, block_index, now);
+        crate::event::record_liquidation_for_breaker(s, plan.icusd.to_u64());
+        if plan.debt_written_off.0 > 0 {
+            crate::event::record_deficit_accrued(
+                s,
+                crate::event::DeficitSource::Liquidation { vault_id },
+                plan.debt_written_off,
+                now,
+            );
+            s.check_deficit_readonly_latch();
+            crate::event::record_mode_transitions(s);
+        }
+        let nonce = s.next_op_nonce();
+        crate::vault::queue_collateral_payout(
+            s,
+            vault_id,
+            plan.owner,
+            caller,
+            ICP::new(plan.collateral),
+            plan.collateral_type,
+            nonce,
+            now,
+        );
+        if let Some(before) = &vault_before {
+            crate::liquidation_receipts::issue(
+                s,
+                before,
+                crate::liquidation_receipts::Settlement {
+                    liquidator: Some(caller),
+                    price: oracle_price.unwrap_or_else(|| price_decimal(plan.price_e8s)),
+                    ..Default::default()
+                },
+                now,
+            );
+        }
+        let ended = end_after_bid(s, auction_id);
+        if let Some(reason) = ended {
+            crate::event::record_auction_ended(s, auction_id, reason, now);
+        }
+        (interest_share, ended)
+    });
+
+    let unminted_interest =
+        crate::treasury::distribute_interest(interest_share, plan.collateral_type).await;
+    if unminted_interest.to_u64() > 0 {
+        mutate_state(|s| {
+            s.restore_pending_interest_for_pool(plan.collateral_type, unminted_interest.to_u64())
+        });
+    }
+    if let Err(e) = crate::vault::try_process_pending_transfers_immediate(vault_id).await {
+        log!(
+            INFO,
+            "[auction] trace={} Immediate payout failed: {}. Retrying via timer",
+            trace_tag(caller),
+            e
+        );
+        crate::vault::schedule_transfer_retry(vault_id, 0);
+    }
+
+    log!(
+        INFO,
+        "[auction] trace={} #{} filled {} icUSD e8s for {} of vault #{} at {}",
+        trace_tag(caller),
+        auction_id,
+        plan.icusd.to_u64(),
+        plan.collateral,
+        vault_id,
+        plan.price_e8s
+    );
+    guard_principal.complete();
+    Ok(AuctionBidSuccess {
+        auction_id,
+        icusd_paid_e8s: plan.icusd.to_u64(),
+        collateral_received: plan.collateral,
+        price_e8s: plan.price_e8s,
+        block_index,
+        ended,
+    })
+}
@@ -0,0 +1,554 @@
+//! Opt-in auto-deleveraging of inactive vaults.
+//!
+//! An owner who expects to be away can let the protocol defend a vault for
+//! them (`set_auto_deleverage`). Once the vault has seen no owner activity
+//! for `inactivity_days` and its collateral ratio falls below the owner's
+//! `trigger_cr_bps` buffer, the protocol sells a slice of its collateral for
+//! icUSD on a whitelisted DEX route and repays debt with the proceeds,
+//! bringing the vault back to `target_cr_bps`.
+//!
+//! Activity is any borrow, repayment (including from collateral), margin
+//! top-up, partial withdrawal or collateral swap on the vault, and any
+//! change to its auto-deleverage config. Passing `None` cancels the opt-in
+//! at any time.
+//!
+//! Sales are bounded per vault: at most `max_weekly_sell_bps` of the
+//! collateral the vault held at the first sale of a week may be sold in that
+//! week. Vaults already below their liquidation ratio are left to
+//! liquidation. Candidates are picked at the end of each `check_vaults` tick,
+//! lowest CR first, at most `MAX_AUTO_DELEVERAGES_PER_TICK` at a time.
+//!
+//! Routes are whitelisted per collateral by the developer
+//! (`set_auto_deleverage_route`) and sell through the same Rumi AMM `swap`
+//! interface as `collateral_swap` (see `dex`). The swap pays its icUSD to the protocol's main
+//! account, which is the icUSD minting account, so the proceeds are burned
+//! on arrival and the debt is reduced by the full `amount_out`. The DEX
+//! minimum is the oracle value of the collateral sold less
+//! `AUTO_DELEVERAGE_SLIPPAGE_BPS`; a rejected swap leaves the vault
+//! untouched, is logged as `AutoDeleverageFailed` and backs the vault off for
+//! `AUTO_DELEVERAGE_RETRY_BACKOFF_NS`.
+
+use crate::dex::{DexSale, DexSwapError};
+use crate::event::Event;
+use crate::guard::VaultLiquidationGuard;
+use crate::logs::INFO;
+use crate::numeric::{ICP, ICUSD};
+use crate::state::{mutate_state, read_state, CollateralType, Mode, State};
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+const DAY_NS: u64 = 24 * 3600 * 1_000_000_000;
+
+/// Length of the window `max_weekly_sell_bps` applies to.
+pub const AUTO_DELEVERAGE_WEEK_NS: u64 = 7 * DAY_NS;
+
+/// Longest inactivity period an owner may choose.
+pub const MAX_INACTIVITY_DAYS: u32 = 365;
+
+/// Largest share of collateral an owner may allow to be sold per week.
+pub const MAX_WEEKLY_SELL_BPS: u64 = 2_500;
+
+/// Highest target collateral ratio an owner may choose (1000%).
+pub const MAX_TARGET_CR_BPS: u64 = 100_000;
+
+/// Worst price, below the oracle's, a deleverage sale accepts.
+pub const AUTO_DELEVERAGE_SLIPPAGE_BPS: u64 = 200;
+
+/// Smallest repayment worth a sale (1 icUSD).
+pub const MIN_AUTO_DELEVERAGE_E8S: u64 = 100_000_000;
+
+/// Vaults deleveraged per `check_vaults` tick.
+pub const MAX_AUTO_DELEVERAGES_PER_TICK: usize = 3;
+
+/// Pause after a failed sale before the vault is tried again (1 hour).
+pub const AUTO_DELEVERAGE_RETRY_BACKOFF_NS: u64 = 3600 * 1_000_000_000;
+
+/// How long the DEX allowance for a sale stays valid.
+pub const AUTO_DELEVERAGE_APPROVAL_TTL_NS: u64 = 5 * 60 * 1_000_000_000;
+
+/// Longest accepted DEX pool id, in bytes.
+pub const MAX_DELEVERAGE_POOL_ID_LEN: usize = 128;
+
+/// An owner's auto-deleverage settings for one vault. Ratios are in basis
+/// points (16_000 = 160%).
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutoDeleverageConfig {
+    /// Days without owner activity before the vault may be deleveraged.
+    pub inactivity_days: u32,
+    /// Deleverage once the vault's CR is below this.
+    pub trigger_cr_bps: u64,
+    /// CR a deleverage brings the vault back to.
+    pub target_cr_bps: u64,
+    /// Most collateral sold per week, in basis points of the collateral
+    /// held at the week's first sale.
+    pub max_weekly_sell_bps: u64,
+}
+
+/// A whitelisted venue for selling `collateral_type` for icUSD.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutoDeleverageRoute {
+    pub collateral_type: CollateralType,
+    /// DEX canister implementing the Rumi AMM `swap` interface.
+    pub dex: Principal,
+    pub pool_id: String,
+}
+
+/// A vault's opt-in and the bookkeeping that bounds its sales.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutoDeleverageEntry {
+    pub config: AutoDeleverageConfig,
+    /// Last owner activity (or config change) on the vault.
+    pub last_activity_ns: u64,
+    /// Start of the current weekly window; 0 before the first sale.
+    pub window_start_ns: u64,
+    /// Collateral that may be sold in the current window.
+    pub window_budget: u64,
+    /// Collateral sold in the current window.
+    pub window_sold: u64,
+    /// No sale is tried before this, after a failed one.
+    pub retry_after_ns: u64,
+}
+
+/// Everything a sale needs, checked up front against current state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeleveragePlan {
+    pub vault_id: u64,
+    pub collateral_type: CollateralType,
+    pub route: AutoDeleverageRoute,
+    /// Collateral handed to the DEX.
+    pub amount_in: u64,
+    /// Allowance granted to the DEX (`amount_in` plus one transfer fee).
+    pub allowance: u64,
+    /// Collateral taken off the vault: `amount_in` plus the approve and
+    /// transfer-from fees.
+    pub collateral_debit: u64,
+    /// Least icUSD the DEX must deliver.
+    pub min_icusd_out: u64,
+    pub cr_before_bps: u64,
+}
+
+pub(crate) fn ratio_to_bps(ratio: crate::numeric::Ratio) -> u64 {
+    (ratio.0 * Decimal::from(10_000u64)).to_u64().unwrap_or(0)
+}
+
+/// The vault's collateral ratio in basis points at the cached price, or
+/// `None` without a price. `u64::MAX` for a vault without debt.
+pub fn vault_cr_bps(state: &State, vault_id: u64) -> Option<u64> {
+    let vault = state.vault_id_to_vaults.get(&vault_id)?;
+    let debt = vault.borrowed_icusd_amount.to_u64();
+    if debt == 0 {
+        return Some(u64::MAX);
+    }
+    let config = state.get_collateral_config(&vault.collateral_type)?;
+    let price = state.get_collateral_price_decimal(&vault.collateral_type)?;
+    let value =
+        crate::numeric::collateral_usd_value(vault.collateral_amount, price, config.decimals);
+    Some(
+        (Decimal::from(value.to_u64()) * Decimal::from(10_000u64) / Decimal::from(debt))
+            .to_u64()
+            .unwrap_or(u64::MAX),
+    )
+}
+
+pub fn validate_config(
+    state: &State,
+    vault_id: u64,
+    config: &AutoDeleverageConfig,
+) -> Result<(), String> {
+    let vault = state
+        .vault_id_to_vaults
+        .get(&vault_id)
+        .ok_or_else(|| format!("Vault #{} not found", vault_id))?;
+    if config.inactivity_days == 0 || config.inactivity_days > MAX_INACTIVITY_DAYS {
+        return Err(format!(
+            "Inactivity must be 1 to {} days",
+            MAX_INACTIVITY_DAYS
+        ));
+    }
+    let liquidation_bps = ratio_to_bps(state.get_liquidation_ratio_for(&vault.collateral_type));
+    if config.trigger_cr_bps <= liquidation_bps {
+        return Err(format!(
+            "Trigger ratio must be above the collateral's liquidation ratio ({} bps)",
+            liquidation_bps
+        ));
+    }
+    if config.target_cr_bps <= config.trigger_cr_bps || config.target_cr_bps > MAX_TARGET_CR_BPS {
+        return Err(format!(
+            "Target ratio must be above the trigger ratio and at most {} bps",
+            MAX_TARGET_CR_BPS
+        ));
+    }
+    if config.max_weekly_sell_bps == 0 || config.max_weekly_sell_bps > MAX_WEEKLY_SELL_BPS {
+        return Err(format!(
+            "Weekly sell limit must be 1 to {} bps",
+            MAX_WEEKLY_SELL_BPS
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_route(state: &State, route: &AutoDeleverageRoute) -> Result<(), String> {
+    match state.get_collateral_config(&route.collateral_type) {
+        Some(config) if config.is_native_xrp() => {
+            return Err(format!(
+                "{} is not an ICRC collateral",
+                route.collateral_type
+            ));
+        }
+        Some(_) => {}
+        None => {
+            return Err(format!(
+                "{} is not a registered collateral",
+                route.collateral_type
+            ))
+        }
+    }
+    if route.dex == Principal::anonymous() {
+        return Err("The anonymous principal cannot be a DEX".to_string());
+    }
+    if route.pool_id.is_empty() || route.pool_id.len() > MAX_DELEVERAGE_POOL_ID_LEN {
+        return Err(format!(
+            "Pool id must be 1 to {} bytes",
+            MAX_DELEVERAGE_POOL_ID_LEN
+        ));
+    }
+    Ok(())
+}
+
+/// Opt in, update (`Some`) or cancel (`None`). A change counts as owner
+/// activity; an update keeps the weekly window's sales, so editing the
+/// config does not reset the limit.
+pub fn apply_set(
+    state: &mut State,
+    vault_id: u64,
+    config: Option<AutoDeleverageConfig>,
+    now_ns: u64,
+) {
+    match config {
+        Some(config) => {
+            let entry = state
+                .auto_deleverage
+                .entry(vault_id)
+                .or_insert(AutoDeleverageEntry {
+                    config: config.clone(),
+                    last_activity_ns: now_ns,
+                    window_start_ns: 0,
+                    window_budget: 0,
+                    window_sold: 0,
+                    retry_after_ns: 0,
+                });
+            entry.config = config;
+            entry.last_activity_ns = now_ns;
+        }
+        None => {
+            state.auto_deleverage.remove(&vault_id);
+        }
+    }
+}
+
+/// Add, replace (`Some`) or remove (`None`) the route for a collateral.
+/// Opted-in vaults use whatever route is set when their next sale is planned.
+pub fn apply_set_route(
+    state: &mut State,
+    collateral_type: CollateralType,
+    route: Option<AutoDeleverageRoute>,
+) {
+    match route {
+        Some(route) => {
+            state.auto_deleverage_routes.insert(collateral_type, route);
+        }
+        None => {
+            state.auto_deleverage_routes.remove(&collateral_type);
+        }
+    }
+}
+
+/// Restart the vault's inactivity clock, if it opted in.
+pub fn note_activity(state: &mut State, vault_id: u64, now_ns: u64) {
+    if let Some(entry) = state.auto_deleverage.get_mut(&vault_id) {
+        entry.last_activity_ns = entry.last_activity_ns.max(now_ns);
+    }
+}
+
+/// The vault an event counts as owner activity on, for replay.
+pub fn activity_vault(event: &Event) -> Option<u64> {
+    match event {
+        Event::BorrowFromVault { vault_id, .. }
+        | Event::RepayToVault { vault_id, .. }
+        | Event::AddMarginToVault { vault_id, .. }
+        | Event::PartialCollateralWithdrawn { vault_id, .. }
+        | Event::VaultCollateralSwapped { vault_id, .. }
+        | Event::VaultRepaidFromCollateral { vault_id, .. } => Some(*vault_id),
+        _ => None,
+    }
+}
+
+/// Collateral still allowed to be sold this week, and whether the window
+/// restarts with the next sale.
+fn weekly_allowance(entry: &AutoDeleverageEntry, collateral: u64, now_ns: u64) -> (u64, bool) {
+    let expired = entry.window_start_ns == 0
+        || now_ns
+            >= entry
+                .window_start_ns
+                .saturating_add(AUTO_DELEVERAGE_WEEK_NS);
+    if expired {
+        let budget =
+            (collateral as u128 * entry.config.max_weekly_sell_bps as u128 / 10_000) as u64;
+        (budget, true)
+    } else {
+        (entry.window_budget.saturating_sub(entry.window_sold), false)
+    }
+}
+
+/// Check a vault against its opt-in and size the sale. Pure: callers accrue
+/// the vault's interest first.
+pub fn plan_deleverage(
+    state: &State,
+    vault_id: u64,
+    now_ns: u64,
+) -> Result<DeleveragePlan, String> {
+    match state.mode {
+        Mode::ReadOnly => return Err("Protocol is read-only".to_string()),
+        Mode::Bootstrapping => return Err("Protocol is bootstrapping".to_string()),
+        Mode::GeneralAvailability | Mode::Recovery => {}
+    }
+    let entry = state
+        .auto_deleverage
+        .get(&vault_id)
+        .ok_or_else(|| format!("Vault #{} has not opted in", vault_id))?;
+    let vault = state
+        .vault_id_to_vaults
+        .get(&vault_id)
+        .ok_or_else(|| format!("Vault #{} not found", vault_id))?;
+    if vault.bot_processing {
+        return Err(format!("Vault #{} is being liquidated", vault_id));
+    }
+    crate::vault_freeze::require_vault_not_frozen(state, vault_id, now_ns)
+        .map_err(|e| format!("{:?}", e))?;
+    if now_ns < entry.retry_after_ns {
+        return Err(format!("Vault #{} is backing off a failed sale", vault_id));
+    }
+    let idle_ns = (entry.config.inactivity_days as u64).saturating_mul(DAY_NS);
+    if now_ns < entry.last_activity_ns.saturating_add(idle_ns) {
+        return Err(format!("Vault #{} is not inactive yet", vault_id));
+    }
+
+    let ct = vault.collateral_type;
+    let route = state
+        .auto_deleverage_routes
+        .get(&ct)
+        .cloned()
+        .ok_or_else(|| format!("No auto-deleverage route for {}", ct))?;
+    let config = state
+        .get_collateral_config(&ct)
+        .ok_or_else(|| "Collateral type not configured.".to_string())?;
+    if !config.status.allows_withdraw() {
+        return Err("Withdrawal is not allowed for this collateral.".to_string());
+    }
+    let price = state
+        .get_collateral_price_decimal(&ct)
+        .ok_or_else(|| "No price available for the collateral.".to_string())?;
+
+    let cr_bps = vault_cr_bps(state, vault_id).unwrap_or(0);
+    if cr_bps >= entry.config.trigger_cr_bps {
+        return Err(format!("Vault #{} is above its trigger ratio", vault_id));
+    }
+    if cr_bps < ratio_to_bps(state.get_min_liquidation_ratio_for(&ct)) {
+        return Err(format!("Vault #{} is liquidatable", vault_id));
+    }
+
+    // Repayment R that brings (V - R) / (D - R) to the target T, grossed up
+    // for the worst accepted slippage and capped at the debt.
+    let debt = Decimal::from(vault.borrowed_icusd_amount.to_u64());
+    let value = Decimal::from(
+        crate::numeric::collateral_usd_value(vault.collateral_amount, price, config.decimals)
+            .to_u64(),
+    );
+    let target = Decimal::from(entry.config.target_cr_bps) / Decimal::from(10_000u64);
+    let repay = (target * debt - value) / (target - Decimal::ONE);
+    let keep = Decimal::from(10_000 - AUTO_DELEVERAGE_SLIPPAGE_BPS) / Decimal::from(10_000u64);
+    let gross = (repay / keep).min(debt).to_u64().unwrap_or(0);
+
+    let fees = config.ledger_fee.saturating_mul(2);
+    let (allowance_left, _) = weekly_allowance(entry, vault.collateral_amount, now_ns);
+    let amount_in =
+        crate::numeric::icusd_to_collateral_amount(ICUSD::new(gross), price, config.decimals)
+            .min(allowance_left.saturating_sub(fees))
+            .min(vault.collateral_amount.saturating_sub(fees));
+    let min_icusd_out = (Decimal::from(
+        crate::numeric::collateral_usd_value(amount_in, price, config.decimals).to_u64(),
+    ) * keep)
+        .to_u64()
+        .unwrap_or(0);
+    if min_icusd_out < MIN_AUTO_DELEVERAGE_E8S {
+        return Err(format!(
+            "Vault #{} has no room for a sale this week",
+            vault_id
+        ));
+    }
+
+    Ok(DeleveragePlan {
+        vault_id,
+        collateral_type: ct,
+        route,
+        amount_in,
+        allowance: amount_in.saturating_add(config.ledger_fee),
+        collateral_debit: amount_in.saturating_add(fees),
+        min_icusd_out,
+        cr_before_bps: cr_bps,
+    })
+}
+
+/// Opted-in vaults a sale is due on, lowest CR first.
+pub fn due_vaults(state: &State, now_ns: u64, limit: usize) -> Vec<u64> {
+    let mut due: Vec<(u64, u64)> = state
+        .auto_deleverage
+        .keys()
+        .filter(|id| plan_deleverage(state, **id, now_ns).is_ok())
+        .filter_map(|id| vault_cr_bps(state, *id).map(|cr| (cr, *id)))
+        .collect();
+    due.sort();
+    due.into_iter().take(limit).map(|(_, id)| id).collect()
+}
+
+/// Take the sold collateral off the vault, repay the proceeds and charge the
+/// weekly window. Returns the interest share of the repayment, which never
+/// exceeds the vault's debt; a no-op for an unknown vault.
+pub fn apply_deleverage(
+    state: &mut State,
+    vault_id: u64,
+    collateral_debit: u64,
+    icusd_repaid: ICUSD,
+    now_ns: u64,
+) -> ICUSD {
+    let Some(vault) = state.vault_id_to_vaults.get(&vault_id) else {
+        return ICUSD::new(0);
+    };
+    let collateral = vault.collateral_amount;
+    let repaid = icusd_repaid.min(vault.borrowed_icusd_amount);
+    if let Some(entry) = state.auto_deleverage.get_mut(&vault_id) {
+        let (budget, restart) = weekly_allowance(entry, collateral, now_ns);
+        if restart {
+            entry.window_start_ns = now_ns;
+            entry.window_budget = budget;
+            entry.window_sold = 0;
+        }
+        entry.window_sold = entry.window_sold.saturating_add(collateral_debit);
+    }
+    state.remove_margin_from_vault(vault_id, ICP::new(collateral_debit));
+    let (interest_share, _) = state.repay_to_vault(vault_id, repaid);
+    interest_share
+}
+
+/// Back the vault off after a failed sale: it is not tried again for
+/// `AUTO_DELEVERAGE_RETRY_BACKOFF_NS`.
+pub fn apply_failure(state: &mut State, vault_id: u64, now_ns: u64) {
+    if let Some(entry) = state.auto_deleverage.get_mut(&vault_id) {
+        entry.retry_after_ns = now_ns.saturating_add(AUTO_DELEVERAGE_RETRY_BACKOFF_NS);
+    }
+}
+
+/// Deleverage the vaults that are due. Spawned at the end of `check_vaults`.
+pub async fn run_auto_deleverage() {
+    let due = read_state(|s| due_vaults(s, ic_cdk::api::time(), MAX_AUTO_DELEVERAGES_PER_TICK));
+    for vault_id in due {
+        if let Err(e) = deleverage_vault(vault_id).await {
+            log!(
+                INFO,
+                "[auto_deleverage] vault #{} not deleveraged: {:?}",
+                vault_id,
+                e
+            );
+        }
+    }
+}
+
+/// Sell a slice of the vault's collateral through its route and repay the
+/// proceeds. Nothing changes unless the DEX delivers at least the planned
+/// minimum.
+async fn deleverage_vault(vault_id: u64) -> Result<(), ProtocolError> {
+    let _vault_op_guard = VaultLiquidationGuard::new(vault_id)?;
+
+    let now = ic_cdk::api::time();
+    mutate_state(|s| s.accrue_single_vault(vault_id, now));
+    let plan =
+        read_state(|s| plan_deleverage(s, vault_id, now)).map_err(ProtocolError::GenericError)?;
+
+    log!(
+        INFO,
+        "[auto_deleverage] vault #{} at {} bps: selling {} of {} for at least {} icUSD e8s on {} ({})",
+        plan.vault_id,
+        plan.cr_before_bps,
+        plan.amount_in,
+        plan.collateral_type,
+        plan.min_icusd_out,
+        plan.route.dex,
+        plan.route.pool_id
+    );
+
+    let sale = DexSale {
+        token_in: plan.collateral_type,
+        dex: plan.route.dex,
+        pool_id: &plan.route.pool_id,
+        amount_in: plan.amount_in,
+        allowance: plan.allowance,
+        min_amount_out: plan.min_icusd_out,
+        approval_expires_at: now.saturating_add(AUTO_DELEVERAGE_APPROVAL_TTL_NS),
+    };
+    let amount_out = match crate::dex::swap(&sale).await {
+        Ok(amount_out) => amount_out,
+        Err(e) => {
+            let (reason, temporary) = match e {
+                DexSwapError::Approve(e) => (format!("Could not approve the DEX: {:?}", e), false),
+                DexSwapError::Rejected { .. } => (
+                    format!(
+                        "The DEX rejected the sale (output below {} or pool unavailable)",
+                        plan.min_icusd_out
+                    ),
+                    false,
+                ),
+                DexSwapError::Unreachable { code, msg, .. } => {
+                    (format!("Could not reach the DEX: {:?} {}", code, msg), true)
+                }
+            };
+            mutate_state(|s| {
+                crate::event::record_auto_deleverage_failed(s, vault_id, reason.clone(), now)
+            });
+            return Err(if temporary {
+                ProtocolError::TemporarilyUnavailable(reason)
+            } else {
+                ProtocolError::GenericError(reason)
+            });
+        }
+    };
+
+    let interest_share = mutate_state(|s| {
+        crate::event::record_vault_auto_deleveraged(
+            s,
+            &plan,
+            ICUSD::new(amount_out),
+            ic_cdk::api::time(),
+        )
+    });
+    // Same routing as a manual repayment; re-queue what could not be minted.
+    let unminted_interest =
+        crate::treasury::distribute_interest(interest_share, plan.collateral_type).await;
+    if unminted_interest.to_u64() > 0 {
+        mutate_state(|s| {
+            s.restore_pending_interest_for_pool(plan.collateral_type, unminted_interest.to_u64())
+        });
+    }
+    log!(
+        INFO,
+        "[auto_deleverage] vault #{} repaid {} icUSD e8s from {} of {}",
+        plan.vault_id,
+        amount_out,
+        plan.amount_in,
+        plan.collateral_type
+    );
+    Ok(())
+}
@@ -0,0 +1,380 @@
+//! Resumable admin batch jobs.
+//!
+//! An admin task that walks every vault or every borrower can outgrow the
+//! instruction limit of a single message as the protocol grows. Such tasks
+//! run as a `BatchJob` instead: `start` registers the job, and each
+//! `continue_batch_job` call works through items until the caller's
+//! instruction budget is spent, then returns the job with its cursor, the
+//! continuation token for the next call. Every call makes progress (at
+//! least one item), so a job always finishes; `cancel` stops it where it
+//! stands.
+//!
+//! - `RecomputeVaultIndexes` re-keys every vault in `vault_cr_index` and
+//!   `liquidation_index` (what `post_upgrade` does in one go).
+//! - `ProtocolSnapshot` adds up collateral, debt and vaults per collateral
+//!   type and, on completion, yields a `ProtocolSnapshot` for the snapshot
+//!   log. Vaults that change while the job runs are counted as the walk
+//!   finds them, so a snapshot taken over several calls is not atomic.
+//! - `SweepPrincipalIndex` drops ids of closed or transferred vaults from
+//!   `principal_to_vault_ids` and the entries left empty.
+//!
+//! One job of each kind runs at a time; the newest `MAX_FINISHED_BATCH_JOBS`
+//! finished jobs are kept. Jobs live in the state snapshot and are not
+//! events: none of them changes what replay derives.
+
+use crate::logs::INFO;
+use crate::state::{CollateralType, State};
+use crate::{CollateralSnapshot, ProtocolError, ProtocolSnapshot};
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// Ceiling on the instructions one call may spend, half the limit of a
+/// single message (40B). Also the budget of a call that asks for none.
+pub const MAX_BATCH_STEP_INSTRUCTIONS: u64 = 20_000_000_000;
+
+/// Finished (completed or cancelled) jobs kept for `get_batch_jobs`.
+pub const MAX_FINISHED_BATCH_JOBS: usize = 20;
+
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchJobKind {
+    RecomputeVaultIndexes,
+    ProtocolSnapshot,
+    SweepPrincipalIndex,
+}
+
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchJobStatus {
+    Running,
+    Completed,
+    Cancelled,
+}
+
+/// Where a job resumes: the next vault id, or the last borrower swept.
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchCursor {
+    Vault(u64),
+    Owner(Principal),
+}
+
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize)]
+pub struct BatchJob {
+    pub id: u64,
+    pub kind: BatchJobKind,
+    pub status: BatchJobStatus,
+    /// `None` before the first item and once the job has finished.
+    pub cursor: Option<BatchCursor>,
+    pub processed: u64,
+    /// Items there were when the job started.
+    pub total: u64,
+    /// Calls that have worked on the job.
+    pub steps: u64,
+    pub started_at: u64,
+    pub updated_at: u64,
+    /// `SweepPrincipalIndex`: stale vault ids and empty entries dropped.
+    pub removed: u64,
+    /// `ProtocolSnapshot`: running totals per collateral type.
+    pub snapshot_totals: Vec<CollateralSnapshot>,
+    /// `ProtocolSnapshot`: the snapshot, once completed.
+    pub snapshot: Option<ProtocolSnapshot>,
+}
+
+impl BatchJob {
+    pub fn is_running(&self) -> bool {
+        self.status == BatchJobStatus::Running
+    }
+}
+
+/// Budget for one call: `max_instructions`, capped at and defaulting to
+/// `MAX_BATCH_STEP_INSTRUCTIONS`.
+pub fn step_budget(max_instructions: Option<u64>) -> u64 {
+    match max_instructions {
+        Some(0) | None => MAX_BATCH_STEP_INSTRUCTIONS,
+        Some(n) => n.min(MAX_BATCH_STEP_INSTRUCTIONS),
+    }
+}
+
+/// Register a job of `kind`. Fails while another one is running.
+pub fn start(state: &mut State, kind: BatchJobKind, now: u64) -> Result<u64, ProtocolError> {
+    if let Some(job) = state
+        .batch_jobs
+        .values()
+        .find(|job| job.kind == kind && job.is_running())
+    {
+        return Err(ProtocolError::GenericError(format!(
+            "A {:?} job is already running (job #{})",
+            kind, job.id
+        )));
+    }
+    let total = match kind {
+        BatchJobKind::RecomputeVaultIndexes | BatchJobKind::ProtocolSnapshot => {
+            state.vault_id_to_vaults.len() as u64
+        }
+        BatchJobKind::SweepPrincipalIndex => state.principal_to_vault_ids.len() as u64,
+    };
+    let id = state.next_batch_job_id;
+    state.next_batch_job_id += 1;
+    state.batch_jobs.insert(
+        id,
+        BatchJob {
+            id,
+            kind,
+            status: BatchJobStatus::Running,
+            cursor: None,
+            processed: 0,
+            total,
+            steps: 0,
+            started_at: now,
+            updated_at: now,
+            removed: 0,
+            snapshot_totals: Vec::new(),
+            snapshot: None,
+        },
+    );
+    log!(INFO, "[batch_jobs] started {:?} job #{}", kind, id);
+    Ok(id)
+}
+
+fn running_job(state: &State, job_id: u64) -> Result<&BatchJob, ProtocolError> {
+    match state.batch_jobs.get(&job_id) {
+        None => Err(ProtocolError::GenericError(format!(
+            "Batch job #{} not found",
+            job_id
+        ))),
+        Some(job) if !job.is_running() => Err(ProtocolError::GenericError(format!(
+            "Batch job #{} is {:?}",
+            job_id, job.status
+        ))),
+        Some(job) => Ok(job),
+    }
+}
+
+/// Work on `job_id` until `out_of_budget` says stop (checked after each
+/// item) or the job runs out of items. Returns the job as it stands.
+pub fn step(
+    state: &mut State,
+    job_id: u64,
+    now: u64,
+    mut out_of_budget: impl FnMut() -> bool,
+) -> Result<BatchJob, ProtocolError> {
+    let mut job = running_job(state, job_id)?.clone();
+    job.steps += 1;
+    let finished = loop {
+        let more = match job.kind {
+            BatchJobKind::RecomputeVaultIndexes => recompute_next(state, &mut job),
+            BatchJobKind::ProtocolSnapshot => snapshot_next(state, &mut job),
+            BatchJobKind::SweepPrincipalIndex => sweep_next(state, &mut job),
+        };
+        if !more {
+            break true;
+        }
+        job.processed += 1;
+        if out_of_budget() {
+            break false;
+        }
+    };
+    if finished {
+        if job.kind == BatchJobKind::ProtocolSnapshot {
+            job.snapshot = Some(finish_snapshot(state, &job, now));
+        }
+        job.status = BatchJobStatus::Completed;
+        job.cursor = None;
+        log!(
+            INFO,
+            "[batch_jobs] {:?} job #{} completed: {} items in {} calls",
+            job.kind,
+            job.id,
+            job.processed,
+            job.steps
+        );
+    }
+    job.updated_at = now;
+    state.batch_jobs.insert(job_id, job.clone());
+    if finished {
+        prune_finished(state);
+    }
+    Ok(job)
+}
+
+/// Stop `job_id` where it stands.
+pub fn cancel(state: &mut State, job_id: u64, now: u64) -> Result<BatchJob, ProtocolError> {
+    running_job(state, job_id)?;
+    let job = state.batch_jobs.get_mut(&job_id).expect("checked above");
+    job.status = BatchJobStatus::Cancelled;
+    job.updated_at = now;
+    let job = job.clone();
+    log!(
+        INFO,
+        "[batch_jobs] {:?} job #{} cancelled after {} of {} items",
+        job.kind,
+        job.id,
+        job.processed,
+        job.total
+    );
+    prune_finished(state);
+    Ok(job)
+}
+
+/// Jobs, newest first.
+pub fn jobs(state: &State) -> Vec<BatchJob> {
+    state.batch_jobs.values().rev().cloned().collect()
+}
+
+fn prune_finished(state: &mut State) {
+    let finished: Vec<u64> = state
+        .batch_jobs
+        .values()
+        .filter(|job| !job.is_running())
+        .map(|job| job.id)
+        .collect();
+    for id in finished
+        .iter()
+        .take(finished.len().saturating_sub(MAX_FINISHED_BATCH_JOBS))
+    {
+        state.batch_jobs.remove(id);
+    }
+}
+
+/// The first vault at or after the cursor, moving the cursor past it.
+fn next_vault(state: &State, job: &mut BatchJob) -> Option<u64> {
+    let from = match job.cursor {
+        Some(BatchCursor::Vault(id)) => id,
+        _ => 0,
+    };
+    let (&id, _) = state.vault_id_to_vaults.range(from..).next()?;
+    job.cursor = Some(BatchCursor::Vault(id.saturating_add(1)));
+    Some(id)
+}
+
+fn recompute_next(state: &mut State, job: &mut BatchJob) -> bool {
+    let Some(vault_id) = next_vault(state, job) else {
+        return false;
+    };
+    state.reindex_vault_cr(vault_id);
+    true
+}
+
+/// Legacy `anonymous` vaults are ICP vaults.
+fn resolve(state: &State, ct: &CollateralType) -> CollateralType {
+    if ct == &Principal::anonymous() {
+        state.icp_ledger_principal
+    } else {
+        *ct
+    }
+}
+
+fn snapshot_next(state: &mut State, job: &mut BatchJob) -> bool {
+    let Some(vault_id) = next_vault(state, job) else {
+        return false;
+    };
+    let vault = &state.vault_id_to_vaults[&vault_id];
+    let ct = resolve(state, &vault.collateral_type);
+    let totals = match job
+        .snapshot_totals
+        .iter_mut()
+        .position(|t| t.collateral_type == ct)
+    {
+        Some(i) => &mut job.snapshot_totals[i],
+        None => {
+            job.snapshot_totals.push(CollateralSnapshot {
+                collateral_type: ct,
+                total_collateral: 0,
+                total_debt: 0,
+                vault_count: 0,
+                price: 0.0,
+            });
+            job.snapshot_totals.last_mut().expect("just pushed")
+        }
+    };
+    totals.total_collateral += vault.collateral_amount;
+    totals.total_debt += vault.borrowed_icusd_amount.to_u64();
+    totals.vault_count += 1;
+    true
+}
+
+/// The snapshot `capture_protocol_snapshot` would take, from the walked
+/// totals plus redistributed amounts not yet booked on vaults.
+fn finish_snapshot(state: &State, job: &BatchJob, now: u64) -> ProtocolSnapshot {
+    let mut snapshot = ProtocolSnapshot {
+        timestamp: now,
+        total_collateral_value_usd: 0,
+        total_debt: 0,
+        total_vault_count: 0,
+        collateral_snapshots: Vec::new(),
+    };
+    for (ct, config) in state.collateral_configs.iter() {
+        let walked = job
+            .snapshot_totals
+            .iter()
+            .find(|t| &t.collateral_type == ct);
+        let (pending_collateral, pending_debt) = state
+            .redistribution_indexes
+            .get(ct)
+            .map_or((0, 0), |index| {
+                (index.pending_collateral, index.pending_debt)
+            });
+        let total_collateral = walked.map_or(0, |t| t.total_collateral) + pending_collateral;
+        let total_debt = walked.map_or(0, |t| t.total_debt) + pending_debt;
+        let vault_count = walked.map_or(0, |t| t.vault_count);
+        let price = config.last_price.unwrap_or(0.0);
+
+        let collateral =
+            Decimal::from(total_collateral) / Decimal::from(10u64.pow(config.decimals as u32));
+        let usd_e8s = (collateral
+            * Decimal::try_from(price).unwrap_or_default()
+            * Decimal::from(100_000_000u64))
+        .to_u64()
+        .unwrap_or(0);
+
+        snapshot.total_collateral_value_usd += usd_e8s;
+        snapshot.total_debt += total_debt;
+        snapshot.total_vault_count += vault_count;
+        snapshot.collateral_snapshots.push(CollateralSnapshot {
+            collateral_type: *ct,
+            total_collateral,
+            total_debt,
+            vault_count,
+            price,
+        });
+    }
+    snapshot
+}
+
+fn sweep_next(state: &mut State, job: &mut BatchJob) -> bool {
+    let next = match job.cursor {
+        Some(BatchCursor::Owner(after)) => state
+            .principal_to_vault_ids
+            .range(after..)
+            .find(|(owner, _)| **owner != after),
+        _ => state.principal_to_vault_ids.iter().next(),
+    };
+    let Some((&owner, ids)) = next else {
+        return false;
+    };
+    let stale: Vec<u64> = ids
+        .iter()
+        .filter(|id| {
+            state
+                .vault_id_to_vaults
+                .get(id)
+                .map_or(true, |vault| vault.owner != owner)
+        })
+        .copied()
+        .collect();
+    let ids = state
+        .principal_to_vault_ids
+        .get_mut(&owner)
+        .expect("found above");
+    for id in &stale {
+        ids.remove(id);
+    }
+    job.removed += stale.len() as u64;
+    if ids.is_empty() {
+        state.principal_to_vault_ids.remove(&owner);
+        job.removed += 1;
+    }
+    job.cursor = Some(BatchCursor::Owner(owner));
+    true
+}
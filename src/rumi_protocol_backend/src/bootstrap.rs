@@ -0,0 +1,153 @@
+//! Resumable event replay for upgrades.
+//!
+//! `post_upgrade` restores the last `State` checkpoint and replays the events
+//! logged after it, or the whole log when there is no checkpoint. A tail too
+//! long for the upgrade's instruction limit would trap the upgrade, so the
+//! replay stops once `POST_UPGRADE_REPLAY_INSTRUCTIONS` are spent: the
+//! partially replayed state is installed in `Mode::Bootstrapping` with a
+//! `ReplayCursor`, and a timer carries on, `REPLAY_TICK_INSTRUCTIONS` per
+//! tick. When the last event is applied the mode the replay arrived at is
+//! restored and the rest of the upgrade runs.
+//!
+//! While bootstrapping, operations are rejected (`Mode::check_available`),
+//! the protocol timers are not armed yet and the event log is sealed
+//! (`storage::seal_event_log`), so no logged change lands on a half-built
+//! state. The snapshot in stable memory is left alone:
+//! `pre_upgrade` and `take_state_checkpoint` skip a bootstrapping state, so
+//! an upgrade in the middle of a replay starts over from the same checkpoint.
+
+use crate::event::{replay_onto, Event, ReplayLogError};
+use crate::state::{Mode, State};
+use crate::storage::{events, seal_event_log};
+use crate::UpgradeArg;
+use candid::{CandidType, Deserialize};
+
+/// Instructions `post_upgrade` may spend replaying before it hands over to
+/// the timer. Leaves most of the upgrade limit (300B) for decoding the
+/// snapshot and the migrations that follow the replay.
+pub const POST_UPGRADE_REPLAY_INSTRUCTIONS: u64 = 100_000_000_000;
+
+/// Instructions a timer tick may spend replaying, half the limit of a
+/// single message (40B).
+pub const REPLAY_TICK_INSTRUCTIONS: u64 = 20_000_000_000;
+
+/// Where a replay that outlived `post_upgrade` stands.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct ReplayCursor {
+    /// Event log index of the next event to apply.
+    pub next_event: u64,
+    /// One past the last event to apply.
+    pub end_event: u64,
+    /// `OpenVault` events applied so far when replaying from `Init`, which
+    /// sets `next_available_vault_id` on completion as `event::replay` does.
+    pub opened_vaults: Option<u64>,
+    /// The mode the applied events have left the protocol in, restored on
+    /// completion.
+    pub resume_mode: Mode,
+    /// Applied on completion. `None` when the upgrade event is itself among
+    /// the replayed events.
+    pub upgrade_args: Option<UpgradeArg>,
+}
+
+/// Start replaying events `[first_event, end_event)` onto a restored
+/// checkpoint, applying `upgrade_args` once they are all in.
+pub fn begin(
+    mut state: State,
+    first_event: u64,
+    end_event: u64,
+    upgrade_args: Option<UpgradeArg>,
+) -> State {
+    state.replay_cursor = Some(ReplayCursor {
+        next_event: first_event,
+        end_event,
+        opened_vaults: None,
+        resume_mode: state.mode,
+        upgrade_args,
+    });
+    state.mode = Mode::Bootstrapping;
+    seal_event_log(true);
+    state
+}
+
+/// Start replaying a log of `end_event` events from its `Init` event, the
+/// first item of `events`.
+pub fn begin_from_init(
+    mut events: impl Iterator<Item = Event>,
+    end_event: u64,
+) -> Result<State, ReplayLogError> {
+    let state = match events.next() {
+        Some(Event::Init(args)) => State::from(args),
+        Some(evt) => {
+            return Err(ReplayLogError::InconsistentLog(format!(
+                "The first event is not Init: {:?}",
+                evt
+            )))
+        }
+        None => return Err(ReplayLogError::EmptyLog),
+    };
+    let mut state = begin(state, 1, end_event, None);
+    if let Some(cursor) = state.replay_cursor.as_mut() {
+        cursor.opened_vaults = Some(0);
+    }
+    Ok(state)
+}
+
+/// Apply the events at the cursor, starting with `events`' first item,
+/// until `out_of_budget` says to stop or the replay completes. At least one
+/// event is applied per call, so every tick makes progress. On completion
+/// the cursor is cleared, the mode restored and the event log unsealed.
+pub fn replay_chunk(
+    mut state: State,
+    events: impl Iterator<Item = Event>,
+    mut out_of_budget: impl FnMut() -> bool,
+) -> State {
+    let Some(mut cursor) = state.replay_cursor.take() else {
+        return state;
+    };
+    state.mode = cursor.resume_mode;
+    let mut applied = 0u64;
+    let mut opened_vaults = 0u64;
+    state = replay_onto(
+        state,
+        cursor.next_event,
+        events
+            .take(cursor.end_event.saturating_sub(cursor.next_event) as usize)
+            .enumerate()
+            .take_while(|(i, _)| *i == 0 || !out_of_budget())
+            .map(|(_, event)| event)
+            .inspect(|event| {
+                applied += 1;
+                if matches!(event, Event::OpenVault { .. }) {
+                    opened_vaults += 1;
+                }
+            }),
+    );
+    cursor.next_event += applied;
+    cursor.resume_mode = state.mode;
+    if let Some(opened) = cursor.opened_vaults.as_mut() {
+        *opened += opened_vaults;
+    }
+    // A log that ends early ends the replay rather than stalling it.
+    if cursor.next_event < cursor.end_event && applied > 0 {
+        state.mode = Mode::Bootstrapping;
+        state.replay_cursor = Some(cursor);
+        return state;
+    }
+    if let Some(opened) = cursor.opened_vaults {
+        state.next_available_vault_id = opened;
+    }
+    seal_event_log(false);
+    if let Some(args) = cursor.upgrade_args {
+        state.upgrade(args);
+    }
+    state
+}
+
+/// `replay_chunk` over the event log.
+pub fn resume(state: State, out_of_budget: impl FnMut() -> bool) -> State {
+    let next_event = state
+        .replay_cursor
+        .as_ref()
+        .map_or(0, |cursor| cursor.next_event);
+    replay_chunk(state, events().skip(next_event as usize), out_of_budget)
+}
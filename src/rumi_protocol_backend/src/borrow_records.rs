@@ -0,0 +1,143 @@
+//! Per-vault borrow origination records.
+//!
+//! One `BorrowRecord` per successful borrow, holding the origination fee,
+//! the fee rate it was charged at, the collateral price the borrow was
+//! checked against and the time, so owners can account for each borrow
+//! without rebuilding it from the event log. A promotional fee rebate paid
+//! with the borrow is folded into the record it belongs to.
+//!
+//! Records are derived from `BorrowFromVault` / `BorrowFeeRebated` as they
+//! are applied. History starts at the upgrade that introduced them: the
+//! state restored on that upgrade has none, and older borrows are not
+//! backfilled. Only a full replay of the event log (a restore without a
+//! state checkpoint) rebuilds earlier ones; events written before the price
+//! was added to `BorrowFromVault` replay with no `collateral_price`, and
+//! ones without a timestamp replay with `timestamp` 0.
+//!
+//! Each vault keeps its last `MAX_BORROW_RECORDS_PER_VAULT` records; older
+//! ones are dropped first. Positions keep counting, so a cursor stays valid
+//! after older records are dropped. Records outlive their vault.
+
+use crate::numeric::ICUSD;
+use crate::state::{CollateralType, State};
+use candid::{CandidType, Deserialize};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// Largest page `get_borrow_records` returns.
+pub const MAX_BORROW_RECORDS_PAGE: usize = 100;
+
+/// Records kept per vault.
+pub const MAX_BORROW_RECORDS_PER_VAULT: usize = 500;
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BorrowRecord {
+    pub vault_id: u64,
+    pub collateral_type: CollateralType,
+    /// Debt taken on (e8s), fee included.
+    pub borrowed_amount: u64,
+    pub fee_amount: u64,
+    /// Part of `fee_amount` refunded by a rebate campaign.
+    #[serde(default)]
+    pub fee_rebated: u64,
+    /// `fee_amount / borrowed_amount`, as a decimal string.
+    pub fee_rate: String,
+    /// USD price of one whole collateral token at origination.
+    pub collateral_price: Option<String>,
+    /// icUSD ledger block of the mint.
+    pub block_index: u64,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct BorrowRecordPage {
+    pub records: Vec<BorrowRecord>,
+    /// Pass back as `cursor` to read the next page; `None` at the end.
+    pub next_cursor: Option<u64>,
+}
+
+/// Effective fee rate of a borrow, normalized (`"0.005"`, not `"0.00500000"`).
+pub fn fee_rate(borrowed_amount: ICUSD, fee_amount: ICUSD) -> String {
+    if borrowed_amount.to_u64() == 0 {
+        return "0".to_string();
+    }
+    (Decimal::from(fee_amount.to_u64()) / Decimal::from(borrowed_amount.to_u64()))
+        .normalize()
+        .to_string()
+}
+
+/// Append the record for a borrow, under the vault's collateral at the time;
+/// a vault unknown to `state` is skipped.
+pub fn push_borrow_record(
+    state: &mut State,
+    vault_id: u64,
+    borrowed_amount: ICUSD,
+    fee_amount: ICUSD,
+    collateral_price: Option<String>,
+    block_index: u64,
+    timestamp: u64,
+) {
+    let Some(collateral_type) = state
+        .vault_id_to_vaults
+        .get(&vault_id)
+        .map(|v| v.collateral_type)
+    else {
+        return;
+    };
+    let records = state.borrow_records.entry(vault_id).or_default();
+    if records.len() >= MAX_BORROW_RECORDS_PER_VAULT {
+        records.remove(0);
+        *state.borrow_records_dropped.entry(vault_id).or_default() += 1;
+    }
+    records.push(BorrowRecord {
+        vault_id,
+        collateral_type,
+        borrowed_amount: borrowed_amount.to_u64(),
+        fee_amount: fee_amount.to_u64(),
+        fee_rebated: 0,
+        fee_rate: fee_rate(borrowed_amount, fee_amount),
+        collateral_price,
+        block_index,
+        timestamp,
+    });
+}
+
+/// Credit a fee rebate to the vault's latest borrow, which is the borrow the
+/// rebate was reserved for.
+pub fn apply_fee_rebate(state: &mut State, vault_id: u64, rebate_e8s: u64) {
+    if let Some(record) = state
+        .borrow_records
+        .get_mut(&vault_id)
+        .and_then(|records| records.last_mut())
+    {
+        record.fee_rebated = record.fee_rebated.saturating_add(rebate_e8s);
+    }
+}
+
+/// Borrow records of `vault_id`, oldest first, starting at position `cursor`
+/// (or the oldest record kept, if that was dropped).
+pub fn borrow_records_page(
+    state: &State,
+    vault_id: u64,
+    cursor: Option<u64>,
+    limit: usize,
+) -> BorrowRecordPage {
+    let records = state
+        .borrow_records
+        .get(&vault_id)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let dropped = state
+        .borrow_records_dropped
+        .get(&vault_id)
+        .copied()
+        .unwrap_or(0);
+    let start = (cursor.unwrap_or(0).saturating_sub(dropped) as usize).min(records.len());
+    let end = start
+        .saturating_add(limit.min(MAX_BORROW_RECORDS_PAGE))
+        .min(records.len());
+    BorrowRecordPage {
+        records: records[start..end].to_vec(),
+        next_cursor: (end < records.len()).then_some(dropped + end as u64),
+    }
+}
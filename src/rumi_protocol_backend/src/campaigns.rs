@@ -0,0 +1,186 @@
+//! Promotional borrowing-fee rebate campaigns.
+//!
+//! A campaign earmarks part of the treasury's borrowing-fee revenue as a
+//! rebate pool. Borrows opened inside the campaign window get
+//! `rebate_bps` of their fee back at borrow time: the rebate is minted to the
+//! borrower together with the loan and withheld from the treasury's fee mint,
+//! so the pool is spent out of fee revenue the treasury would otherwise have
+//! received. Rebates stop once the pool is exhausted, the window ends, or the
+//! campaign is closed (any unspent budget simply stays with the treasury).
+//!
+//! Pool accounting is reserved before the borrow's mint `await` so concurrent
+//! borrows cannot overspend a pool, and released if the mint fails.
+
+use crate::numeric::ICUSD;
+use crate::state::State;
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+/// Rebates are expressed in basis points of the borrowing fee.
+pub const MAX_REBATE_BPS: u64 = 10_000;
+
+/// Upper bound on the campaign name, to keep the event log compact.
+pub const MAX_CAMPAIGN_NAME_LEN: usize = 64;
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RebateCampaign {
+    pub id: u64,
+    pub name: String,
+    /// Share of each borrowing fee rebated to the borrower, in bps.
+    pub rebate_bps: u64,
+    /// Campaign window, `[start_ns, end_ns)`.
+    pub start_ns: u64,
+    pub end_ns: u64,
+    /// Total budget ever allocated to the pool (initial + top-ups).
+    pub funded_e8s: u64,
+    /// Budget still available for rebates.
+    pub remaining_e8s: u64,
+    /// Rebates actually paid out.
+    pub rebated_e8s: u64,
+    pub borrows_rebated: u64,
+    pub closed_at_ns: Option<u64>,
+}
+
+impl RebateCampaign {
+    pub fn is_active_at(&self, now_ns: u64) -> bool {
+        self.closed_at_ns.is_none()
+            && self.start_ns <= now_ns
+            && now_ns < self.end_ns
+            && self.remaining_e8s > 0
+    }
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreateRebateCampaignArg {
+    pub name: String,
+    pub rebate_bps: u64,
+    pub start_ns: u64,
+    pub end_ns: u64,
+    pub pool_e8s: u64,
+}
+
+pub fn validate_create_arg(arg: &CreateRebateCampaignArg, now_ns: u64) -> Result<(), String> {
+    if arg.name.is_empty() || arg.name.len() > MAX_CAMPAIGN_NAME_LEN {
+        return Err(format!(
+            "Campaign name must be 1..={} bytes",
+            MAX_CAMPAIGN_NAME_LEN
+        ));
+    }
+    if arg.rebate_bps == 0 || arg.rebate_bps > MAX_REBATE_BPS {
+        return Err(format!(
+            "rebate_bps must be in 1..={}, got {}",
+            MAX_REBATE_BPS, arg.rebate_bps
+        ));
+    }
+    if arg.end_ns <= arg.start_ns {
+        return Err("Campaign end must be after its start".to_string());
+    }
+    if arg.end_ns <= now_ns {
+        return Err("Campaign window has already ended".to_string());
+    }
+    if arg.pool_e8s == 0 {
+        return Err("Campaign pool must be non-zero".to_string());
+    }
+    Ok(())
+}
+
+/// Insert a new campaign under `id`, fully funded and with nothing rebated
+/// yet. `next_rebate_campaign_id` only ever moves past `id`.
+pub fn apply_create(state: &mut State, id: u64, arg: CreateRebateCampaignArg) {
+    state.rebate_campaigns.insert(
+        id,
+        RebateCampaign {
+            id,
+            name: arg.name,
+            rebate_bps: arg.rebate_bps,
+            start_ns: arg.start_ns,
+            end_ns: arg.end_ns,
+            funded_e8s: arg.pool_e8s,
+            remaining_e8s: arg.pool_e8s,
+            rebated_e8s: 0,
+            borrows_rebated: 0,
+            closed_at_ns: None,
+        },
+    );
+    state.next_rebate_campaign_id = state.next_rebate_campaign_id.max(id + 1);
+}
+
+pub fn apply_fund(state: &mut State, campaign_id: u64, amount_e8s: u64) -> Result<(), String> {
+    let campaign = state
+        .rebate_campaigns
+        .get_mut(&campaign_id)
+        .ok_or_else(|| format!("Unknown rebate campaign {}", campaign_id))?;
+    if campaign.closed_at_ns.is_some() {
+        return Err(format!("Rebate campaign {} is closed", campaign_id));
+    }
+    campaign.funded_e8s = campaign.funded_e8s.saturating_add(amount_e8s);
+    campaign.remaining_e8s = campaign.remaining_e8s.saturating_add(amount_e8s);
+    Ok(())
+}
+
+/// Close a campaign, returning the unspent budget that reverts to the
+/// treasury.
+pub fn apply_close(state: &mut State, campaign_id: u64, now_ns: u64) -> Result<u64, String> {
+    let campaign = state
+        .rebate_campaigns
+        .get_mut(&campaign_id)
+        .ok_or_else(|| format!("Unknown rebate campaign {}", campaign_id))?;
+    if campaign.closed_at_ns.is_some() {
+        return Err(format!("Rebate campaign {} is already closed", campaign_id));
+    }
+    campaign.closed_at_ns = Some(now_ns);
+    Ok(std::mem::take(&mut campaign.remaining_e8s))
+}
+
+/// Rebate owed on `fee` by `campaign`, capped by the remaining pool.
+pub fn rebate_for_fee(campaign: &RebateCampaign, fee: ICUSD) -> u64 {
+    let uncapped =
+        (fee.to_u64() as u128 * campaign.rebate_bps as u128 / MAX_REBATE_BPS as u128) as u64;
+    uncapped.min(campaign.remaining_e8s)
+}
+
+/// Reserve a rebate on `fee` from the oldest active campaign. Returns the
+/// campaign id and rebate amount; `None` when no campaign applies or the
+/// rebate rounds to zero. The reservation is deducted from the pool
+/// immediately and must be either committed with
+/// `event::record_borrow_fee_rebated` or undone with `release_rebate`.
+pub fn reserve_rebate(state: &mut State, fee: ICUSD, now_ns: u64) -> Option<(u64, ICUSD)> {
+    let campaign = state
+        .rebate_campaigns
+        .values_mut()
+        .find(|c| c.is_active_at(now_ns))?;
+    let rebate = rebate_for_fee(campaign, fee);
+    if rebate == 0 {
+        return None;
+    }
+    campaign.remaining_e8s -= rebate;
+    Some((campaign.id, ICUSD::new(rebate)))
+}
+
+/// Undo a reservation whose borrow failed. A campaign closed in the meantime
+/// already handed its budget back, so nothing is restored there.
+pub fn release_rebate(state: &mut State, campaign_id: u64, rebate: ICUSD) {
+    if let Some(campaign) = state.rebate_campaigns.get_mut(&campaign_id) {
+        if campaign.closed_at_ns.is_none() {
+            campaign.remaining_e8s = campaign.remaining_e8s.saturating_add(rebate.to_u64());
+        }
+    }
+}
+
+/// Book a paid rebate against its campaign. `from_reservation` is true on
+/// the live path, where `reserve_rebate` already took it out of the pool;
+/// replay has no reservation step and debits the pool here.
+pub fn apply_rebate_paid(
+    state: &mut State,
+    campaign_id: u64,
+    rebate: ICUSD,
+    from_reservation: bool,
+) {
+    if let Some(campaign) = state.rebate_campaigns.get_mut(&campaign_id) {
+        if !from_reservation {
+            campaign.remaining_e8s = campaign.remaining_e8s.saturating_sub(rebate.to_u64());
+        }
+        campaign.rebated_e8s = campaign.rebated_e8s.saturating_add(rebate.to_u64());
+        campaign.borrows_rebated += 1;
+    }
+}
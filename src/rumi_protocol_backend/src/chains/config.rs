@@ -10,8 +10,7 @@
 use candid::{CandidType, Deserialize};
 use serde::Serialize;
 
-#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
-pub struct ChainId(pub u32);
+pub use rumi_common::chains::ChainId;
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, Eq, PartialEq)]
 pub enum ChainStatus {
@@ -13,8 +13,7 @@
 use ripemd::Ripemd160;
 use sha2::{Digest, Sha256};
 
-/// XRPL version byte for a classic account address (`r…`).
-const CLASSIC_ADDRESS_VERSION: u8 = 0x00;
+use rumi_common::chains::XRP_CLASSIC_ADDRESS_VERSION as CLASSIC_ADDRESS_VERSION;
 
 /// `AccountID = RIPEMD160(SHA256(0xED ‖ pubkey))` — the 20-byte account hash.
 pub fn account_id_from_ed25519_pubkey(pubkey: &[u8; 32]) -> [u8; 20] {
@@ -42,29 +41,9 @@ pub fn classic_address_from_ed25519_pubkey(pubkey: &[u8; 32]) -> String {
     classic_address_from_account_id(&account_id_from_ed25519_pubkey(pubkey))
 }
 
-/// Decode a classic `r…` address to its 20-byte AccountID, rejecting anything
-/// that is not a valid version-0x00 classic address with a good checksum. This is
-/// the single trust-boundary validator for a user-supplied destination: it
-/// rejects X-addresses (the `X…` format), wrong-version strings, empty input, and
-/// corrupted checksums BEFORE any bytes are signed.
-pub fn account_id_from_classic_address(addr: &str) -> Result<[u8; 20], String> {
-    // `with_check(Some(ver))` verifies both the 4-byte SHA256d checksum and the
-    // leading version byte; the returned vec is `version ‖ 20-byte payload`.
-    let decoded = bs58::decode(addr)
-        .with_alphabet(bs58::Alphabet::RIPPLE)
-        .with_check(Some(CLASSIC_ADDRESS_VERSION))
-        .into_vec()
-        .map_err(|e| format!("invalid XRPL classic address: {e}"))?;
-    if decoded.len() != 21 {
-        return Err(format!(
-            "XRPL address payload is {} bytes, expected 21",
-            decoded.len()
-        ));
-    }
-    let mut out = [0u8; 20];
-    out.copy_from_slice(&decoded[1..21]);
-    Ok(out)
-}
+/// Decoding is shared with the stability pool, which validates payout
+/// destinations before an absorb.
+pub use rumi_common::chains::account_id_from_classic_address;
 
 /// Boolean view of `account_id_from_classic_address` for cheap validation.
 pub fn is_valid_classic_address(addr: &str) -> bool {
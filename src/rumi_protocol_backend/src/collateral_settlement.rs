@@ -0,0 +1,475 @@
+//! Emergency shutdown of one collateral, with user settlement.
+//!
+//! The wind-down path for a deprecated or exploited collateral when a
+//! `Sunset` is too slow or no longer safe. `settle_collateral` (developer
+//! only) freezes the collateral and fixes its settlement price at the last
+//! oracle price. Every vault of the collateral then has its debt settled
+//! against its own collateral at that price: the collateral worth the debt
+//! (all of it, for a vault under water) moves into the collateral's
+//! settlement pool and the debt is cleared. What stays in a vault is its
+//! owner's excess, reclaimed with `claim_settled_vault`, which closes the
+//! vault and queues the collateral back as a pending margin transfer.
+//!
+//! The settled debt stays in circulation as icUSD. Its holders redeem it
+//! against the pool with `redeem_settled_collateral`: the icUSD is burned
+//! and paid out pro-rata, `pool / outstanding debt` collateral per icUSD,
+//! through the redemption payout queue. A pool short of the settled debt
+//! (vaults under water at the settlement price) pays every redeemer the
+//! same haircut; it is not booked as a protocol deficit. Neither claim nor
+//! redemption needs a price or an available protocol mode.
+//!
+//! A settled collateral stays `Frozen` for good. Settlement is refused while
+//! any of its vaults has an operation or a bot claim in flight, for
+//! native-XRP collateral, which is not held by the backend's ledger account,
+//! and for ICP outside an `emergency_shutdown`, which settles every
+//! collateral at its own fixed price instead.
+//!
+//! Settlements, claims and redemptions are logged as `CollateralSettled`,
+//! `SettledVaultClaimed` and `SettledCollateralRedeemed` and rebuilt by
+//! replay. Their payouts ride the pending transfer queues, which the
+//! snapshot carries.
+
+use crate::guard::GuardPrincipal;
+use crate::logs::INFO;
+use crate::management::transfer_icusd_from;
+use crate::numeric::{collateral_usd_value, icusd_to_collateral_amount, UsdIcp, ICP, ICUSD};
+use crate::state::{mutate_state, read_state, CollateralStatus, State};
+use crate::{PendingMarginTransfer, ProtocolError};
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// A settled collateral's fixed price, what settlement moved into its pool,
+/// and what has since been claimed and redeemed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollateralSettlement {
+    pub price: UsdIcp,
+    pub settled_at: u64,
+    pub vaults_settled: u64,
+    /// icUSD debt cleared from the vaults, redeemable against the pool.
+    pub debt_settled: u64,
+    /// Collateral moved into the pool for that debt.
+    pub collateral_pooled: u64,
+    /// Debt the pooled collateral was worth less than at the settlement
+    /// price.
+    pub shortfall: u64,
+    pub icusd_redeemed: u64,
+    pub collateral_redeemed: u64,
+    pub vaults_claimed: u64,
+    pub collateral_claimed: u64,
+}
+
+impl CollateralSettlement {
+    pub fn debt_outstanding(&self) -> u64 {
+        self.debt_settled.saturating_sub(self.icusd_redeemed)
+    }
+
+    pub fn pool_remaining(&self) -> u64 {
+        self.collateral_pooled
+            .saturating_sub(self.collateral_redeemed)
+    }
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Deserialize)]
+pub struct CollateralSettlementStatus {
+    pub collateral_type: Principal,
+    pub price: f64,
+    pub settled_at: u64,
+    pub vaults_settled: u64,
+    pub debt_settled: u64,
+    pub collateral_pooled: u64,
+    pub shortfall: u64,
+    pub icusd_redeemed: u64,
+    pub collateral_redeemed: u64,
+    pub vaults_claimed: u64,
+    pub collateral_claimed: u64,
+    pub debt_outstanding: u64,
+    pub pool_remaining: u64,
+    /// Vaults not yet claimed.
+    pub vaults_open: u64,
+}
+
+pub fn is_settled(state: &State, collateral_type: &Principal) -> bool {
+    state.collateral_settlements.contains_key(collateral_type)
+}
+
+fn vaults_of(state: &State, collateral_type: &Principal) -> Vec<u64> {
+    state
+        .vault_id_to_vaults
+        .values()
+        .filter(|vault| vault.collateral_type == *collateral_type)
+        .map(|vault| vault.vault_id)
+        .collect()
+}
+
+/// Why `collateral_type` cannot be settled now, if it cannot.
+pub fn check_settleable(state: &State, collateral_type: &Principal) -> Result<(), ProtocolError> {
+    let config = state
+        .get_collateral_config(collateral_type)
+        .ok_or_else(|| ProtocolError::GenericError("Collateral type not found".to_string()))?;
+    if is_settled(state, collateral_type) {
+        return Err(ProtocolError::GenericError(format!(
+            "Collateral {} is already settled",
+            collateral_type
+        )));
+    }
+    if config.is_native_xrp() {
+        return Err(ProtocolError::GenericError(
+            "Native-XRP collateral cannot be settled".to_string(),
+        ));
+    }
+    if *collateral_type == state.icp_collateral_type()
+        && !crate::emergency_shutdown::is_shut_down(state)
+    {
+        return Err(ProtocolError::GenericError(
+            "The ICP collateral cannot be settled".to_string(),
+        ));
+    }
+    let busy = vaults_of(state, collateral_type)
+        .into_iter()
+        .find(|vault_id| {
+            state.vault_id_to_vaults[vault_id].bot_processing
+                || crate::guard::is_vault_liquidating(*vault_id)
+        });
+    if let Some(vault_id) = busy {
+        return Err(ProtocolError::TemporarilyUnavailable(format!(
+            "Vault #{} has an operation in flight; retry shortly",
+            vault_id
+        )));
+    }
+    Ok(())
+}
+
+/// The settlement price of `collateral_type`: its last oracle price.
+pub fn settlement_price(state: &State, collateral_type: &Principal) -> Option<UsdIcp> {
+    state
+        .get_collateral_config(collateral_type)?
+        .last_price
+        .and_then(Decimal::from_f64)
+        .filter(|price| *price > Decimal::ZERO)
+        .map(UsdIcp::from)
+}
+
+/// Freeze `collateral_type` and settle every one of its vaults at `price`.
+/// Uses only the recorded price, never the oracle's, so every vault of the
+/// collateral settles at the same price.
+pub fn apply_settle(
+    state: &mut State,
+    collateral_type: Principal,
+    price: UsdIcp,
+    now: u64,
+) -> CollateralSettlement {
+    let decimals = match state.collateral_configs.get_mut(&collateral_type) {
+        Some(config) => {
+            config.status = CollateralStatus::Frozen;
+            config.decimals
+        }
+        None => 8,
+    };
+    let mut settlement = CollateralSettlement {
+        price,
+        settled_at: now,
+        vaults_settled: 0,
+        debt_settled: 0,
+        collateral_pooled: 0,
+        shortfall: 0,
+        icusd_redeemed: 0,
+        collateral_redeemed: 0,
+        vaults_claimed: 0,
+        collateral_claimed: 0,
+    };
+    for vault_id in vaults_of(state, &collateral_type) {
+        crate::redistribution::settle(state, vault_id);
+        let vault = &state.vault_id_to_vaults[&vault_id];
+        let debt = vault.borrowed_icusd_amount;
+        let collateral = vault.collateral_amount;
+        let pooled = icusd_to_collateral_amount(debt, price.0, decimals).min(collateral);
+        if debt.to_u64() > 0 {
+            state.repay_to_vault(vault_id, debt);
+        }
+        if pooled > 0 {
+            state.remove_margin_from_vault(vault_id, ICP::new(pooled));
+        }
+        if pooled == collateral {
+            let worth = collateral_usd_value(collateral, price.0, decimals);
+            settlement.shortfall += debt.to_u64().saturating_sub(worth.to_u64());
+        }
+        settlement.vaults_settled += 1;
+        settlement.debt_settled += debt.to_u64();
+        settlement.collateral_pooled += pooled;
+    }
+    state
+        .collateral_settlements
+        .insert(collateral_type, settlement.clone());
+    settlement
+}
+
+/// Close settled `vault_id`, returning the collateral left in it; zero for a
+/// vault already claimed.
+pub fn apply_claim(state: &mut State, vault_id: u64) -> u64 {
+    let Some(vault) = state.vault_id_to_vaults.get(&vault_id).cloned() else {
+        return 0;
+    };
+    if let Some(vault) = state.vault_id_to_vaults.get_mut(&vault_id) {
+        vault.collateral_amount = 0;
+    }
+    state.close_vault(vault_id);
+    if let Some(settlement) = state.collateral_settlements.get_mut(&vault.collateral_type) {
+        settlement.vaults_claimed += 1;
+        settlement.collateral_claimed += vault.collateral_amount;
+    }
+    vault.collateral_amount
+}
+
+/// The collateral `icusd_amount` redeems from `collateral_type`'s pool now:
+/// its pro-rata share of what remains.
+pub fn quote_redemption(
+    state: &State,
+    collateral_type: &Principal,
+    icusd_amount: u64,
+) -> Result<u64, ProtocolError> {
+    let settlement = state
+        .collateral_settlements
+        .get(collateral_type)
+        .ok_or_else(|| {
+            ProtocolError::GenericError(format!("Collateral {} is not settled", collateral_type))
+        })?;
+    let outstanding = settlement.debt_outstanding();
+    if icusd_amount > outstanding {
+        return Err(ProtocolError::GenericError(format!(
+            "Only {} icUSD of settled debt is left to redeem against {}",
+            outstanding, collateral_type
+        )));
+    }
+    Ok((icusd_amount as u128 * settlement.pool_remaining() as u128 / outstanding as u128) as u64)
+}
+
+/// Book a redemption of `icusd_amount` for `collateral_amount` against the
+/// pool. Booked before the burn, so a concurrent quote sees the pool net of
+/// it; `restore_redemption` undoes it if the burn fails.
+pub fn take_redemption(
+    state: &mut State,
+    collateral_type: &Principal,
+    icusd_amount: u64,
+    collateral_amount: u64,
+) {
+    if let Some(settlement) = state.collateral_settlements.get_mut(collateral_type) {
+        settlement.icusd_redeemed += icusd_amount;
+        settlement.collateral_redeemed += collateral_amount;
+    }
+}
+
+/// Undo `take_redemption` after a failed burn.
+pub fn restore_redemption(
+    state: &mut State,
+    collateral_type: &Principal,
+    icusd_amount: u64,
+    collateral_amount: u64,
+) {
+    if let Some(settlement) = state.collateral_settlements.get_mut(collateral_type) {
+        settlement.icusd_redeemed = settlement.icusd_redeemed.saturating_sub(icusd_amount);
+        settlement.collateral_redeemed = settlement
+            .collateral_redeemed
+            .saturating_sub(collateral_amount);
+    }
+}
+
+pub fn status(state: &State) -> Vec<CollateralSettlementStatus> {
+    state
+        .collateral_settlements
+        .iter()
+        .map(|(collateral_type, s)| CollateralSettlementStatus {
+            collateral_type: *collateral_type,
+            price: s.price.to_f64(),
+            settled_at: s.settled_at,
+            vaults_settled: s.vaults_settled,
+            debt_settled: s.debt_settled,
+            collateral_pooled: s.collateral_pooled,
+            shortfall: s.shortfall,
+            icusd_redeemed: s.icusd_redeemed,
+            collateral_redeemed: s.collateral_redeemed,
+            vaults_claimed: s.vaults_claimed,
+            collateral_claimed: s.collateral_claimed,
+            debt_outstanding: s.debt_outstanding(),
+            pool_remaining: s.pool_remaining(),
+            vaults_open: vaults_of(state, collateral_type).len() as u64,
+        })
+        .collect()
+}
+
+/// Settle `collateral_type` at its last price. An emergency shutdown fixes
+/// the prices instead.
+pub fn settle_collateral(
+    state: &mut State,
+    collateral_type: Principal,
+    settled_by: Principal,
+    now: u64,
+) -> Result<CollateralSettlementStatus, ProtocolError> {
+    crate::emergency_shutdown::check_not_shut_down(state)?;
+    check_settleable(state, &collateral_type)?;
+    let price = settlement_price(state, &collateral_type).ok_or_else(|| {
+        ProtocolError::TemporarilyUnavailable(format!("No price to settle {} at", collateral_type))
+    })?;
+    Ok(settle_at(state, collateral_type, price, settled_by, now))
+}
+
+/// Record the settlement of `collateral_type` at `price`, which
+/// `check_settleable` passed.
+pub fn settle_at(
+    state: &mut State,
+    collateral_type: Principal,
+    price: UsdIcp,
+    settled_by: Principal,
+    now: u64,
+) -> CollateralSettlementStatus {
+    let settlement =
+        crate::event::record_collateral_settled(state, collateral_type, price, settled_by, now);
+    log!(
+        INFO,
+        "[settle_collateral] {} settled at {}: {} vaults, {} icUSD of debt for {} collateral ({} short)",
+        collateral_type,
+        price.to_f64(),
+        settlement.vaults_settled,
+        settlement.debt_settled,
+        settlement.collateral_pooled,
+        settlement.shortfall
+    );
+    status(state)
+        .into_iter()
+        .find(|s| s.collateral_type == collateral_type)
+        .expect("just settled")
+}
+
+/// Close the caller's settled vault and queue its remaining collateral back.
+/// Returns the collateral queued.
+pub fn claim_settled_vault(vault_id: u64) -> Result<u64, ProtocolError> {
+    let caller = ic_cdk::api::caller();
+    if crate::guard::is_vault_liquidating(vault_id) {
+        return Err(ProtocolError::TemporarilyUnavailable(format!(
+            "Another operation on vault #{} is in flight; retry shortly",
+            vault_id
+        )));
+    }
+    let now = ic_cdk::api::time();
+    let amount = mutate_state(|s| {
+        let vault = s
+            .vault_id_to_vaults
+            .get(&vault_id)
+            .cloned()
+            .ok_or_else(|| ProtocolError::GenericError("Vault not found".to_string()))?;
+        if vault.owner != caller {
+            return Err(ProtocolError::CallerNotOwner);
+        }
+        if !is_settled(s, &vault.collateral_type) {
+            return Err(ProtocolError::GenericError(format!(
+                "Collateral {} is not settled",
+                vault.collateral_type
+            )));
+        }
+        let amount = crate::event::record_settled_vault_claimed(s, vault_id, now);
+        if amount > 0 {
+            let nonce = s.next_op_nonce_at(now);
+            crate::vault::queue_collateral_payout(
+                s,
+                vault_id,
+                caller,
+                caller,
+                ICP::new(amount),
+                vault.collateral_type,
+                nonce,
+                now,
+            );
+        }
+        Ok(amount)
+    })?;
+    log!(
+        INFO,
+        "[claim_settled_vault] closed settled vault #{} of {}, returning {} collateral",
+        vault_id,
+        caller,
+        amount
+    );
+    if amount > 0 {
+        crate::scheduler::kick_pending_transfers(0);
+    }
+    Ok(amount)
+}
+
+/// Burn `icusd_amount` of the caller's icUSD for its pro-rata share of
+/// `collateral_type`'s settlement pool. Returns the collateral queued.
+pub async fn redeem_settled_collateral(
+    collateral_type: Principal,
+    icusd_amount: u64,
+) -> Result<u64, ProtocolError> {
+    let caller = ic_cdk::api::caller();
+    let _guard_principal = GuardPrincipal::new(caller, "redeem_settled_collateral")?;
+    let min_amount = read_state(|s| s.min_icusd_amount);
+    if ICUSD::new(icusd_amount) < min_amount {
+        return Err(ProtocolError::AmountTooLow {
+            minimum_amount: min_amount.to_u64(),
+        });
+    }
+    let ledger_fee = read_state(|s| {
+        s.get_collateral_config(&collateral_type)
+            .map_or(0, |config| config.ledger_fee)
+    });
+    // Book the redemption before the burn so a concurrent redeemer sees the
+    // smaller pool.
+    let collateral_amount = mutate_state(|s| {
+        let collateral_amount = quote_redemption(s, &collateral_type, icusd_amount)?;
+        if collateral_amount <= ledger_fee {
+            return Err(ProtocolError::GenericError(format!(
+                "{} icUSD redeems {} collateral, which does not cover the ledger fee of {}",
+                icusd_amount, collateral_amount, ledger_fee
+            )));
+        }
+        take_redemption(s, &collateral_type, icusd_amount, collateral_amount);
+        Ok(collateral_amount)
+    })?;
+
+    let icusd_block_index = match transfer_icusd_from(ICUSD::new(icusd_amount), caller).await {
+        Ok(block_index) => block_index,
+        Err(error) => {
+            mutate_state(|s| {
+                restore_redemption(s, &collateral_type, icusd_amount, collateral_amount)
+            });
+            return Err(ProtocolError::TransferFromError(error, icusd_amount));
+        }
+    };
+    mutate_state(|s| {
+        let now = ic_cdk::api::time();
+        crate::event::record_settled_collateral_redeemed(
+            caller,
+            collateral_type,
+            icusd_amount,
+            collateral_amount,
+            icusd_block_index,
+            now,
+        );
+        let op_nonce = s.next_op_nonce_at(now);
+        s.pending_redemption_transfer.insert(
+            icusd_block_index,
+            PendingMarginTransfer {
+                owner: caller,
+                margin: ICP::new(collateral_amount),
+                collateral_type,
+                retry_count: 0,
+                op_nonce,
+                trace_id: crate::guard::current_trace(caller),
+            },
+        );
+    });
+    log!(
+        INFO,
+        "[redeem_settled_collateral] {} burned {} icUSD for {} of settled {} (block {})",
+        caller,
+        icusd_amount,
+        collateral_amount,
+        collateral_type,
+        icusd_block_index
+    );
+    crate::scheduler::kick_pending_transfers(0);
+    Ok(collateral_amount)
+}
@@ -0,0 +1,405 @@
+//! In-place collateral swap for a vault.
+//!
+//! An owner can move a vault from one collateral to another (ICP to ckBTC,
+//! say) without repaying and closing it. The protocol sells the vault's
+//! whole collateral on a whitelisted DEX pool, credits what comes back as
+//! the new collateral and re-keys the vault; the debt is unchanged.
+//!
+//! Routes are whitelisted per `(from, to)` pair by the developer
+//! (`set_collateral_swap_route`). A route names a DEX canister exposing the
+//! Rumi AMM interface, `swap : (pool_id, token_in, amount_in, min_amount_out)`,
+//! which pulls `amount_in` through an ICRC-2 allowance and pays the output
+//! (less the output ledger's fee) back to the caller.
+//!
+//! Atomicity comes from the swap's `min_amount_out`: the protocol raises the
+//! owner's floor to the amount of new collateral the vault needs to meet the
+//! new collateral's minimum ratio (the recovery ratio in Recovery mode) at
+//! the current oracle price. The DEX therefore either delivers enough
+//! collateral for a healthy vault or rejects the trade without moving funds,
+//! in which case the vault keeps its collateral less the ledger fees paid to
+//! grant and revoke the DEX allowance (`VaultCollateralSwapFailed`). The
+//! vault is only rewritten, and `VaultCollateralSwapped` logged, once the
+//! output is in.
+//!
+//! For the duration of the swap the vault holds the per-vault op lock, so it
+//! cannot be liquidated, redeemed against or changed by its owner while its
+//! collateral is in flight.
+
+use crate::dex::{DexSale, DexSwapError};
+use crate::guard::{trace_tag, BorrowReservationGuard, GuardPrincipal, VaultLiquidationGuard};
+use crate::logs::INFO;
+use crate::numeric::{ICP, ICUSD};
+use crate::state::{mutate_state, read_state, CollateralType, Mode, State};
+use crate::vault::require_vault_not_processing;
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use serde::Serialize;
+
+/// How long the DEX allowance for a swap stays valid.
+pub const COLLATERAL_SWAP_APPROVAL_TTL_NS: u64 = 5 * 60 * 1_000_000_000;
+
+/// Longest accepted DEX pool id, in bytes.
+pub const MAX_SWAP_POOL_ID_LEN: usize = 128;
+
+/// A whitelisted venue for swapping `from` collateral into `to` collateral.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollateralSwapRoute {
+    pub from: CollateralType,
+    pub to: CollateralType,
+    /// DEX canister implementing the Rumi AMM `swap` interface.
+    pub dex: Principal,
+    pub pool_id: String,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct SwapVaultCollateralArg {
+    pub vault_id: u64,
+    pub new_collateral_type: Principal,
+    /// Least new collateral (smallest unit, after the ledger fee) the owner
+    /// accepts. The protocol may raise it; see the module docs.
+    pub min_amount_out: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct SwapVaultCollateralSuccess {
+    pub vault_id: u64,
+    pub old_collateral_type: Principal,
+    pub new_collateral_type: Principal,
+    /// Old collateral sold.
+    pub amount_in: u64,
+    /// New collateral credited to the vault.
+    pub amount_out: u64,
+}
+
+/// Everything the swap needs, checked up front against current state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CollateralSwapPlan {
+    pub vault_id: u64,
+    pub from: CollateralType,
+    pub to: CollateralType,
+    pub route: CollateralSwapRoute,
+    pub debt: u64,
+    /// Old collateral handed to the DEX: the vault's collateral less the
+    /// approve and transfer-from fees on the old ledger.
+    pub amount_in: u64,
+    /// Allowance granted to the DEX (`amount_in` plus one transfer fee).
+    pub allowance: u64,
+    /// Net new collateral the DEX must deliver.
+    pub min_amount_out: u64,
+    /// Fee each approval pays on the old ledger.
+    pub from_ledger_fee: u64,
+    /// Fee the DEX's payout pays on the new ledger.
+    pub to_ledger_fee: u64,
+}
+
+pub fn find_route<'a>(
+    state: &'a State,
+    from: &CollateralType,
+    to: &CollateralType,
+) -> Option<&'a CollateralSwapRoute> {
+    state
+        .collateral_swap_routes
+        .iter()
+        .find(|r| &r.from == from && &r.to == to)
+}
+
+pub fn validate_route(state: &State, route: &CollateralSwapRoute) -> Result<(), String> {
+    if route.from == route.to {
+        return Err("A swap route needs two different collaterals".to_string());
+    }
+    for ct in [&route.from, &route.to] {
+        match state.get_collateral_config(ct) {
+            Some(config) if config.is_native_xrp() => {
+                return Err(format!("{} is not an ICRC collateral", ct));
+            }
+            Some(_) => {}
+            None => return Err(format!("{} is not a registered collateral", ct)),
+        }
+    }
+    if route.dex == Principal::anonymous() {
+        return Err("The anonymous principal cannot be a DEX".to_string());
+    }
+    if route.pool_id.is_empty() || route.pool_id.len() > MAX_SWAP_POOL_ID_LEN {
+        return Err(format!(
+            "Pool id must be 1 to {} bytes",
+            MAX_SWAP_POOL_ID_LEN
+        ));
+    }
+    Ok(())
+}
+
+/// Add, replace (`Some`) or remove (`None`) the route for `from -> to`.
+/// There is at most one route per pair.
+pub fn apply_set_route(
+    state: &mut State,
+    from: CollateralType,
+    to: CollateralType,
+    route: Option<CollateralSwapRoute>,
+) {
+    state
+        .collateral_swap_routes
+        .retain(|r| !(r.from == from && r.to == to));
+    if let Some(route) = route {
+        state.collateral_swap_routes.push(route);
+    }
+}
+
+/// Ratio the vault must hold on its new collateral: the borrow threshold,
+/// or the recovery ratio if higher while in Recovery mode.
+fn required_ratio(state: &State, ct: &CollateralType) -> crate::numeric::Ratio {
+    let base = state.get_min_collateral_ratio_for(ct);
+    if state.mode == Mode::Recovery {
+        let recovery = state.get_recovery_cr_for(ct);
+        if recovery > base {
+            return recovery;
+        }
+    }
+    base
+}
+
+/// Check a swap request and work out its amounts. Pure: callers accrue the
+/// vault's interest first.
+pub fn plan_collateral_swap(
+    state: &State,
+    caller: Principal,
+    arg: &SwapVaultCollateralArg,
+    now_ns: u64,
+) -> Result<CollateralSwapPlan, ProtocolError> {
+    let generic = |msg: String| ProtocolError::GenericError(msg);
+    state.mode.check_available()?;
+    let vault = state
+        .vault_id_to_vaults
+        .get(&arg.vault_id)
+        .ok_or_else(|| generic(format!("Vault #{} not found", arg.vault_id)))?;
+    if vault.owner != caller {
+        return Err(ProtocolError::CallerNotOwner);
+    }
+    require_vault_not_processing(vault)?;
+    crate::vault_freeze::require_vault_not_frozen(state, arg.vault_id, now_ns)?;
+
+    let from = vault.collateral_type;
+    let to = arg.new_collateral_type;
+    let route = find_route(state, &from, &to)
+        .cloned()
+        .ok_or_else(|| generic(format!("No whitelisted swap route from {} to {}", from, to)))?;
+
+    let from_config = state
+        .get_collateral_config(&from)
+        .ok_or_else(|| generic("Collateral type not configured.".to_string()))?;
+    let to_config = state
+        .get_collateral_config(&to)
+        .ok_or_else(|| generic("Collateral type not configured.".to_string()))?;
+    if !from_config.status.allows_withdraw() {
+        return Err(generic(
+            "Withdrawal is not allowed for the vault's current collateral.".to_string(),
+        ));
+    }
+    if !to_config.status.allows_open() {
+        return Err(generic(
+            "The new collateral is not accepting vaults.".to_string(),
+        ));
+    }
+    let to_price = state
+        .get_collateral_price_decimal(&to)
+        .ok_or_else(|| generic("No price available for the new collateral.".to_string()))?;
+
+    let debt = vault.borrowed_icusd_amount.to_u64();
+    let to_debt = state.total_debt_for_collateral(&to).to_u64();
+    if to_debt.saturating_add(debt) > to_config.debt_ceiling {
+        return Err(generic(format!(
+            "Moving {} icUSD e8s of debt would exceed the new collateral's debt ceiling",
+            debt
+        )));
+    }
+
+    // Approve, then transfer-from: both are charged to the protocol account.
+    let amount_in = vault
+        .collateral_amount
+        .saturating_sub(from_config.ledger_fee.saturating_mul(2));
+    if amount_in == 0 {
+        return Err(generic("No collateral to swap".to_string()));
+    }
+
+    // Least new collateral that keeps the vault at the required ratio, rounded
+    // up so the truncating conversion cannot leave it a hair short.
+    let required = if debt == 0 {
+        0
+    } else {
+        let value: ICUSD = ICUSD::new(debt) * required_ratio(state, &to);
+        crate::numeric::icusd_to_collateral_amount(value, to_price, to_config.decimals)
+            .saturating_add(1)
+    };
+    let min_amount_out = arg
+        .min_amount_out
+        .max(required)
+        .max(to_config.min_collateral_deposit)
+        .max(1);
+
+    Ok(CollateralSwapPlan {
+        vault_id: arg.vault_id,
+        from,
+        to,
+        route,
+        debt,
+        amount_in,
+        allowance: amount_in.saturating_add(from_config.ledger_fee),
+        min_amount_out,
+        from_ledger_fee: from_config.ledger_fee,
+        to_ledger_fee: to_config.ledger_fee,
+    })
+}
+
+/// Move the vault onto its new collateral, holding exactly `amount_out` of
+/// it; the debt is unchanged. A no-op for an unknown vault.
+pub fn apply_collateral_swap(
+    state: &mut State,
+    vault_id: u64,
+    to: CollateralType,
+    amount_out: u64,
+) {
+    let Some(mut vault) = state.vault_id_to_vaults.get(&vault_id).cloned() else {
+        return;
+    };
+    vault.collateral_type = to;
+    vault.collateral_amount = amount_out;
+    // Re-indexes the vault under its new collateral and re-keys its CR.
+    state.open_vault(vault);
+}
+
+/// Take the ledger fees a failed swap paid for its DEX allowance off the
+/// vault's collateral. A no-op for an unknown vault.
+pub fn apply_collateral_swap_failed(state: &mut State, vault_id: u64, fees: u64) {
+    if state.vault_id_to_vaults.contains_key(&vault_id) {
+        state.remove_margin_from_vault(vault_id, ICP::new(fees));
+    }
+}
+
+/// Sell the vault's collateral through its whitelisted route and re-key the
+/// vault to the collateral bought. Nothing changes unless the DEX delivers
+/// at least the planned minimum.
+pub async fn swap_vault_collateral(
+    arg: SwapVaultCollateralArg,
+) -> Result<SwapVaultCollateralSuccess, ProtocolError> {
+    let caller = ic_cdk::caller();
+    let _guard_principal =
+        GuardPrincipal::new(caller, &format!("swap_vault_collateral_{}", arg.vault_id))?;
+    let _vault_op_guard = VaultLiquidationGuard::new(arg.vault_id)?;
+
+    let now = ic_cdk::api::time();
+    mutate_state(|s| s.accrue_single_vault(arg.vault_id, now));
+    let plan = read_state(|s| plan_collateral_swap(s, caller, &arg, now))?;
+
+    // Hold the new collateral's debt-ceiling headroom against concurrent
+    // borrows while the swap is in flight. Moving debt leaves the global
+    // total unchanged, so the global cap is not re-checked.
+    let to_debt = read_state(|s| s.total_debt_for_collateral(&plan.to).to_u64());
+    let to_ceiling = read_state(|s| {
+        s.get_collateral_config(&plan.to)
+            .map(|c| c.debt_ceiling)
+            .unwrap_or(u64::MAX)
+    });
+    let _reservation =
+        BorrowReservationGuard::try_reserve(plan.to, plan.debt, to_debt, to_ceiling, 0, u64::MAX)
+            .map_err(ProtocolError::GenericError)?;
+
+    log!(
+        INFO,
+        "[swap_vault_collateral] trace={} vault #{}: selling {} of {} for at least {} of {} on {} ({})",
+        trace_tag(caller),
+        plan.vault_id,
+        plan.amount_in,
+        plan.from,
+        plan.min_amount_out,
+        plan.to,
+        plan.route.dex,
+        plan.route.pool_id
+    );
+
+    let sale = DexSale {
+        token_in: plan.from,
+        dex: plan.route.dex,
+        pool_id: &plan.route.pool_id,
+        amount_in: plan.amount_in,
+        allowance: plan.allowance,
+        min_amount_out: plan.min_amount_out,
+        approval_expires_at: now.saturating_add(COLLATERAL_SWAP_APPROVAL_TTL_NS),
+    };
+    let gross_out = match crate::dex::swap(&sale).await {
+        Ok(gross_out) => gross_out,
+        Err(DexSwapError::Approve(e)) => {
+            return Err(ProtocolError::GenericError(format!(
+                "Could not approve the DEX to swap: {:?}",
+                e
+            )));
+        }
+        Err(e) => {
+            // The DEX moved nothing, but the allowance cost ledger fees on
+            // the old collateral; the vault pays them, not the pool.
+            let fees = e.approvals_charged().saturating_mul(plan.from_ledger_fee);
+            if fees > 0 {
+                mutate_state(|s| {
+                    crate::event::record_vault_collateral_swap_failed(
+                        s,
+                        plan.vault_id,
+                        plan.from,
+                        fees,
+                        ic_cdk::api::time(),
+                    )
+                });
+            }
+            return Err(match e {
+                DexSwapError::Unreachable { code, msg, .. } => {
+                    log!(
+                        INFO,
+                        "[swap_vault_collateral] trace={} ERROR: could not reach DEX {} for vault #{}: {:?} {}",
+                        trace_tag(caller),
+                        plan.route.dex,
+                        plan.vault_id,
+                        code,
+                        msg
+                    );
+                    ProtocolError::TemporarilyUnavailable(format!(
+                        "Could not reach the DEX; vault #{} keeps its collateral less {} in ledger fees",
+                        plan.vault_id, fees
+                    ))
+                }
+                _ => ProtocolError::GenericError(format!(
+                    "The DEX rejected the swap (output below {} or pool unavailable); vault #{} keeps its collateral less {} in ledger fees",
+                    plan.min_amount_out, plan.vault_id, fees
+                )),
+            });
+        }
+    };
+    // The DEX pays `amount_out` less the output ledger's fee.
+    let amount_out = gross_out.saturating_sub(plan.to_ledger_fee);
+
+    mutate_state(|s| {
+        crate::event::record_vault_collateral_swapped(
+            s,
+            plan.vault_id,
+            plan.from,
+            plan.to,
+            plan.amount_in,
+            amount_out,
+            plan.route.dex,
+            ic_cdk::api::time(),
+        )
+    });
+    log!(
+        INFO,
+        "[swap_vault_collateral] trace={} vault #{} now holds {} of {}",
+        trace_tag(caller),
+        plan.vault_id,
+        amount_out,
+        plan.to
+    );
+
+    Ok(SwapVaultCollateralSuccess {
+        vault_id: plan.vault_id,
+        old_collateral_type: plan.from,
+        new_collateral_type: plan.to,
+        amount_in: plan.amount_in,
+        amount_out,
+    })
+}
@@ -0,0 +1,128 @@
+//! Selling collateral on a whitelisted DEX.
+//!
+//! Collateral swaps, repayment from collateral and auto-deleverage all sell
+//! through a DEX canister exposing the Rumi AMM interface,
+//! `swap : (pool_id, token_in, amount_in, min_amount_out)`, which pulls
+//! `amount_in` through an ICRC-2 allowance. `swap` grants that allowance,
+//! runs the trade and, if the trade does not go through, revokes the
+//! allowance again, so callers only decide what a failure means for their
+//! vault.
+
+use crate::logs::INFO;
+use crate::state::CollateralType;
+use candid::{CandidType, Deserialize, Nat, Principal};
+use ic_canister_log::log;
+use ic_cdk::api::call::RejectionCode;
+use icrc_ledger_types::icrc2::approve::ApproveError;
+use rust_decimal::prelude::ToPrimitive;
+
+/// Shape of the Rumi AMM `SwapResult`.
+#[derive(CandidType, Clone, Debug, Deserialize)]
+struct DexSwapResult {
+    amount_out: Nat,
+    #[allow(dead_code)]
+    fee: Nat,
+}
+
+/// One sale of `token_in` on a DEX pool.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DexSale<'a> {
+    pub token_in: CollateralType,
+    pub dex: Principal,
+    pub pool_id: &'a str,
+    /// Amount handed to the DEX.
+    pub amount_in: u64,
+    /// Allowance granted to the DEX (`amount_in` plus one transfer fee).
+    pub allowance: u64,
+    /// Least output the DEX must deliver.
+    pub min_amount_out: u64,
+    /// When the allowance lapses if it is never used or revoked.
+    pub approval_expires_at: u64,
+}
+
+/// Why a sale did not go through. In every case the DEX moved no funds.
+/// `revoked`: the allowance was reset after the failure.
+#[derive(Clone, Debug)]
+pub enum DexSwapError {
+    /// The allowance could not be granted.
+    Approve(ApproveError),
+    /// The DEX turned the trade down: output below the minimum or pool
+    /// unavailable.
+    Rejected { revoked: bool },
+    /// The DEX could not be reached.
+    Unreachable {
+        code: RejectionCode,
+        msg: String,
+        revoked: bool,
+    },
+}
+
+impl DexSwapError {
+    /// Approvals the failed sale paid a `token_in` ledger fee for: none if
+    /// the grant failed, otherwise the grant and, if it went through, the
+    /// revoke.
+    pub fn approvals_charged(&self) -> u64 {
+        match self {
+            DexSwapError::Approve(_) => 0,
+            DexSwapError::Rejected { revoked } | DexSwapError::Unreachable { revoked, .. } => {
+                1 + *revoked as u64
+            }
+        }
+    }
+}
+
+/// Approve the DEX and run the sale. Returns the output the DEX reports;
+/// on any failure after the approval the allowance is revoked.
+pub async fn swap(sale: &DexSale<'_>) -> Result<u64, DexSwapError> {
+    crate::management::approve_on_ledger(
+        sale.token_in,
+        sale.dex,
+        sale.allowance,
+        Some(sale.approval_expires_at),
+    )
+    .await
+    .map_err(DexSwapError::Approve)?;
+
+    let result: Result<(Result<DexSwapResult, candid::Reserved>,), _> = ic_cdk::call(
+        sale.dex,
+        "swap",
+        (
+            sale.pool_id.to_string(),
+            sale.token_in,
+            Nat::from(sale.amount_in),
+            Nat::from(sale.min_amount_out),
+        ),
+    )
+    .await;
+
+    match result {
+        Ok((Ok(swap),)) => Ok(swap.amount_out.0.to_u64().unwrap_or(0)),
+        Ok((Err(_),)) => Err(DexSwapError::Rejected {
+            revoked: revoke_allowance(sale).await,
+        }),
+        Err((code, msg)) => Err(DexSwapError::Unreachable {
+            code,
+            msg,
+            revoked: revoke_allowance(sale).await,
+        }),
+    }
+}
+
+/// Best-effort reset of the DEX allowance after a failed sale; returns
+/// whether it went through. The allowance also lapses on its own at
+/// `approval_expires_at`.
+async fn revoke_allowance(sale: &DexSale<'_>) -> bool {
+    match crate::management::approve_on_ledger(sale.token_in, sale.dex, 0, None).await {
+        Ok(_) => true,
+        Err(e) => {
+            log!(
+                INFO,
+                "[dex] could not revoke the allowance of {} on {}: {:?}",
+                sale.dex,
+                sale.token_in,
+                e
+            );
+            false
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Donations to the protocol's surplus buffer.
+//!
+//! Grants, refunded penalties and the like used to arrive as plain
+//! transfers to the backend, where no accounting saw them: collateral sat
+//! untracked until `admin_sweep_to_treasury` moved it on, and nothing
+//! recorded who gave what. `donate` pulls the donation with
+//! `icrc2_transfer_from` and credits the surplus buffer instead of any
+//! vault, logging a donor-tagged `Donation` event.
+//!
+//! Donated collateral stays in the backend's account as a per-ledger buffer
+//! balance, counted as tracked by the sweep. Donated icUSD is burned on
+//! receipt (the backend is the minting account): it first repays the
+//! protocol deficit, and the rest is held as icUSD buffer, backing in excess
+//! of supply, which absorbs later deficit accruals before they reach
+//! `protocol_deficit_icusd` (a `DeficitRepaid` from `FeeSource::SurplusBuffer`).
+//!
+//! Each donation must be at least `MIN_DONATION_FEE_MULTIPLE` ledger fees
+//! (the minimum icUSD amount for icUSD), which keeps the per-donor totals from
+//! filling with dust.
+
+use crate::event::FeeSource;
+use crate::guard::GuardPrincipal;
+use crate::logs::INFO;
+use crate::management;
+use crate::numeric::ICUSD;
+use crate::state::{mutate_state, read_state, Mode, State};
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+
+/// A collateral donation must be worth this many of its ledger's fees.
+pub const MIN_DONATION_FEE_MULTIPLE: u64 = 100;
+
+/// Longest accepted donation memo, in bytes.
+pub const MAX_DONATION_MEMO_LEN: usize = 128;
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct DonateArg {
+    /// icUSD or a registered collateral.
+    pub ledger: Principal,
+    pub amount: u64,
+    pub memo: Option<String>,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct DonationReceipt {
+    pub block_index: u64,
+    /// icUSD of the donation that repaid the deficit; 0 for collateral.
+    pub deficit_repaid: u64,
+    /// Buffer balance of the ledger after the donation.
+    pub buffer_balance: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct SurplusBufferEntry {
+    pub ledger: Principal,
+    pub balance: u64,
+    /// Everything ever donated in this ledger, deficit repayments included.
+    pub total_donated: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct DonationTotal {
+    pub donor: Principal,
+    pub ledger: Principal,
+    pub amount: u64,
+}
+
+/// Smallest donation accepted in `ledger`, or why it cannot be donated.
+pub fn minimum_donation(state: &State, ledger: &Principal) -> Result<u64, ProtocolError> {
+    if *ledger == state.icusd_ledger_principal {
+        return Ok(state.min_icusd_amount.to_u64());
+    }
+    match state.get_collateral_config(ledger) {
+        Some(config) if *ledger != Principal::anonymous() => {
+            Ok(config.ledger_fee.saturating_mul(MIN_DONATION_FEE_MULTIPLE))
+        }
+        _ => Err(ProtocolError::GenericError(format!(
+            "{} is neither icUSD nor a registered collateral",
+            ledger
+        ))),
+    }
+}
+
+pub fn check(state: &State, arg: &DonateArg) -> Result<(), ProtocolError> {
+    if state.mode == Mode::Bootstrapping {
+        return Err(ProtocolError::TemporarilyUnavailable(
+            "The protocol is replaying its event log".to_string(),
+        ));
+    }
+    let minimum_amount = minimum_donation(state, &arg.ledger)?;
+    if arg.amount < minimum_amount {
+        return Err(ProtocolError::AmountTooLow { minimum_amount });
+    }
+    if arg
+        .memo
+        .as_ref()
+        .is_some_and(|m| m.len() > MAX_DONATION_MEMO_LEN)
+    {
+        return Err(ProtocolError::GenericError(format!(
+            "Memo longer than {} bytes",
+            MAX_DONATION_MEMO_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// The part of an icUSD donation of `amount` that goes to the deficit.
+pub fn deficit_share(state: &State, ledger: &Principal, amount: u64) -> u64 {
+    if *ledger != state.icusd_ledger_principal {
+        return 0;
+    }
+    amount.min(state.protocol_deficit_icusd.to_u64())
+}
+
+/// Credit a donation: the donor's total and the ledger's buffer, less what
+/// went to the deficit. The deficit repayment itself is booked separately,
+/// from its own `DeficitRepaid`.
+pub fn apply_donation(
+    state: &mut State,
+    donor: Principal,
+    ledger: Principal,
+    amount: u64,
+    deficit_repaid: u64,
+) {
+    *state.donation_totals.entry((donor, ledger)).or_default() += amount;
+    *state.surplus_buffer.entry(ledger).or_default() += amount - deficit_repaid;
+}
+
+/// icUSD buffer that can absorb the current deficit.
+pub fn absorbable_deficit(state: &State) -> u64 {
+    state
+        .surplus_buffer
+        .get(&state.icusd_ledger_principal)
+        .copied()
+        .unwrap_or_default()
+        .min(state.protocol_deficit_icusd.to_u64())
+}
+
+/// Take `amount` out of the icUSD buffer to repay the deficit. The buffer
+/// never goes below zero.
+pub fn apply_buffer_absorption(state: &mut State, amount: u64) {
+    let ledger = state.icusd_ledger_principal;
+    if let Some(balance) = state.surplus_buffer.get_mut(&ledger) {
+        *balance = balance.saturating_sub(amount);
+    }
+}
+
+pub fn surplus_buffer(state: &State) -> Vec<SurplusBufferEntry> {
+    let mut entries: Vec<SurplusBufferEntry> = state
+        .surplus_buffer
+        .iter()
+        .map(|(ledger, balance)| SurplusBufferEntry {
+            ledger: *ledger,
+            balance: *balance,
+            total_donated: 0,
+        })
+        .collect();
+    for ((_, ledger), amount) in &state.donation_totals {
+        if let Some(entry) = entries.iter_mut().find(|e| e.ledger == *ledger) {
+            entry.total_donated += amount;
+        }
+    }
+    entries
+}
+
+/// Cumulative donations, of `donor` only when given.
+pub fn donation_totals(state: &State, donor: Option<Principal>) -> Vec<DonationTotal> {
+    state
+        .donation_totals
+        .iter()
+        .filter(|((d, _), _)| donor.is_none() || donor == Some(*d))
+        .map(|((donor, ledger), amount)| DonationTotal {
+            donor: *donor,
+            ledger: *ledger,
+            amount: *amount,
+        })
+        .collect()
+}
+
+pub async fn donate(arg: DonateArg) -> Result<DonationReceipt, ProtocolError> {
+    let donor = ic_cdk::caller();
+    let _guard_principal = GuardPrincipal::new(donor, "donate")?;
+    let is_icusd = read_state(|s| {
+        check(s, &arg)?;
+        Ok::<_, ProtocolError>(arg.ledger == s.icusd_ledger_principal)
+    })?;
+
+    let block_index = if is_icusd {
+        management::transfer_icusd_from(ICUSD::new(arg.amount), donor).await
+    } else {
+        management::transfer_collateral_from(arg.amount, donor, arg.ledger).await
+    }
+    .map_err(|e| ProtocolError::TransferFromError(e, arg.amount))?;
+
+    let now = ic_cdk::api::time();
+    let receipt = mutate_state(|s| {
+        // Read after the transfer: the deficit may have moved meanwhile.
+        let deficit_repaid = deficit_share(s, &arg.ledger, arg.amount);
+        if deficit_repaid > 0 {
+            crate::event::record_deficit_repaid(
+                s,
+                ICUSD::new(deficit_repaid),
+                FeeSource::Donation,
+                Some(block_index),
+                now,
+            );
+        }
+        crate::event::record_donation(
+            s,
+            donor,
+            arg.ledger,
+            arg.amount,
+            deficit_repaid,
+            block_index,
+            arg.memo.clone(),
+            now,
+        );
+        DonationReceipt {
+            block_index,
+            deficit_repaid,
+            buffer_balance: s
+                .surplus_buffer
+                .get(&arg.ledger)
+                .copied()
+                .unwrap_or_default(),
+        }
+    });
+    log!(
+        INFO,
+        "[donate] {} donated {} of {} (block {}, {} to the deficit)",
+        donor,
+        arg.amount,
+        arg.ledger,
+        block_index,
+        receipt.deficit_repaid
+    );
+    Ok(receipt)
+}
@@ -0,0 +1,142 @@
+//! Auto-closure of dust vaults.
+//!
+//! Borrowing and partial repayment keep a vault's debt at zero or at least
+//! its collateral's `min_vault_debt`, but rounding in interest, redemption
+//! and liquidation math, and vaults from before the floor, can still leave a
+//! few e8s of debt that nobody will ever repay, keeping the vault open and
+//! its collateral idle. An hourly sweep closes vaults owing at most
+//! `DUST_THRESHOLD`: the debt is written off as a `DustWriteOff` deficit,
+//! which the icUSD surplus buffer absorbs at once when it can (see
+//! `donations`), the collateral is queued back to the owner as a pending
+//! margin transfer, and the vault is removed with a `DustVaultClosed` event.
+//!
+//! A vault is left for a later sweep while its owner has an operation in
+//! flight, while the bot has claimed it, while it is frozen or its status
+//! or collateral disallows closing, and while the protocol is unavailable.
+//! A sweep closes at most `MAX_DUST_CLOSURES_PER_SWEEP` vaults.
+
+use crate::event::{record_deficit_accrued, record_dust_vault_closed, DeficitSource};
+use crate::logs::INFO;
+use crate::numeric::{ICP, ICUSD};
+use crate::state::{mutate_state, State};
+use crate::vault_status::VaultOperation;
+use crate::DUST_THRESHOLD;
+use ic_canister_log::log;
+
+/// How often dust vaults are swept.
+pub const DUST_SWEEP_INTERVAL_SECS: u64 = 3_600;
+
+/// Most vaults a single sweep closes.
+pub const MAX_DUST_CLOSURES_PER_SWEEP: usize = 50;
+
+/// Whether `debt` is dust: owed, but no more than `DUST_THRESHOLD`.
+pub fn is_dust(debt: ICUSD) -> bool {
+    debt > ICUSD::new(0) && debt <= DUST_THRESHOLD
+}
+
+/// Whether the sweep may close `vault_id` now.
+fn closable(state: &State, vault_id: u64, now: u64) -> bool {
+    let Some(vault) = state.vault_id_to_vaults.get(&vault_id) else {
+        return false;
+    };
+    state.mode.check_available().is_ok()
+        && !vault.bot_processing
+        && !state.principal_guards.contains(&vault.owner)
+        && crate::vault_freeze::active_freeze(state, vault_id, now).is_none()
+        && crate::vault_status::require_allows(state, vault_id, VaultOperation::Close).is_ok()
+        && state
+            .get_collateral_status(&vault.collateral_type)
+            .map_or(true, |status| status.allows_close())
+}
+
+/// Dust vaults the sweep may close now, lowest id first.
+pub fn dust_vaults(state: &State, now: u64) -> Vec<u64> {
+    state
+        .vault_id_to_vaults
+        .values()
+        .filter(|vault| is_dust(vault.borrowed_icusd_amount))
+        .map(|vault| vault.vault_id)
+        .filter(|vault_id| closable(state, *vault_id, now))
+        .collect()
+}
+
+/// Clear the vault's debt and collateral and remove it, after booking its
+/// pending redistribution. The write-off itself is booked separately, from
+/// its own `DeficitAccrued`.
+pub fn apply_close(state: &mut State, vault_id: u64) {
+    crate::redistribution::settle(state, vault_id);
+    let Some(vault) = state.vault_id_to_vaults.get(&vault_id) else {
+        return;
+    };
+    let debt = vault.borrowed_icusd_amount;
+    let _ = state.repay_to_vault(vault_id, debt);
+    if let Some(vault) = state.vault_id_to_vaults.get_mut(&vault_id) {
+        vault.collateral_amount = 0;
+    }
+    state.close_vault(vault_id);
+}
+
+/// Write off `vault_id`'s dust debt and close it, queueing its collateral
+/// back to the owner. Returns whether the vault was closed: it must still
+/// be closable dust once its pending redistribution is booked.
+pub fn close_dust_vault(state: &mut State, vault_id: u64, now: u64) -> bool {
+    if !closable(state, vault_id, now) {
+        return false;
+    }
+    crate::redistribution::settle(state, vault_id);
+    let vault = state.vault_id_to_vaults[&vault_id].clone();
+    if !is_dust(vault.borrowed_icusd_amount) {
+        return false;
+    }
+    record_deficit_accrued(
+        state,
+        DeficitSource::DustWriteOff { vault_id },
+        vault.borrowed_icusd_amount,
+        now,
+    );
+    record_dust_vault_closed(state, vault_id, now);
+    if vault.collateral_amount > 0 {
+        let nonce = state.next_op_nonce_at(now);
+        crate::vault::queue_collateral_payout(
+            state,
+            vault_id,
+            vault.owner,
+            vault.owner,
+            ICP::new(vault.collateral_amount),
+            vault.collateral_type,
+            nonce,
+            now,
+        );
+    }
+    state.check_deficit_readonly_latch();
+    log!(
+        INFO,
+        "[dust_vaults] closed vault #{} of {}: wrote off {} icUSD, returning {} collateral",
+        vault_id,
+        vault.owner,
+        vault.borrowed_icusd_amount,
+        vault.collateral_amount
+    );
+    true
+}
+
+/// Close up to `MAX_DUST_CLOSURES_PER_SWEEP` dust vaults. Returns their ids.
+pub fn sweep(state: &mut State, now: u64) -> Vec<u64> {
+    dust_vaults(state, now)
+        .into_iter()
+        .filter(|vault_id| close_dust_vault(state, *vault_id, now))
+        .take(MAX_DUST_CLOSURES_PER_SWEEP)
+        .collect()
+}
+
+pub fn setup_sweep_timer() {
+    crate::scheduler::set_interval(
+        crate::scheduler::ScheduledTask::DustSweep,
+        DUST_SWEEP_INTERVAL_SECS,
+        || async {
+            let now = ic_cdk::api::time();
+            mutate_state(|s| sweep(s, now));
+            Ok(())
+        },
+    );
+}
@@ -0,0 +1,226 @@
+//! Effective per-collateral parameters, resolved server-side.
+//!
+//! What a borrower actually pays, or where a vault is actually liquidated,
+//! depends on a cascade: protocol-wide defaults, the collateral's own
+//! config, admin overrides (Recovery-mode fee and rate overrides, a healthy
+//! CR, rate curve or maintenance fee set for the asset, an XRC source
+//! floor), the current mode, and promotional rebate campaigns.
+//! `get_effective_parameters` resolves that cascade with the same getters
+//! the protocol uses and tags each value with where it came from, so clients
+//! do not have to re-derive it.
+//!
+//! Ratios are returned as `f64` for display; the protocol itself works on the
+//! exact values.
+
+use crate::campaigns::MAX_REBATE_BPS;
+use crate::numeric::Ratio;
+use crate::state::{CollateralType, Mode, State};
+use crate::xrc::price_bounds;
+use candid::{CandidType, Deserialize};
+
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum ParameterSource {
+    /// Protocol-wide default; the collateral does not set it.
+    Default,
+    /// The collateral's own config.
+    Config,
+    /// An admin override that replaces the config value (in the current mode).
+    Override,
+    /// An active promotional campaign.
+    Promo,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Deserialize)]
+pub struct EffectiveRatio {
+    pub value: f64,
+    pub source: ParameterSource,
+    /// How the value was derived, when it is not read as-is.
+    pub note: Option<String>,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Deserialize)]
+pub struct EffectiveAmount {
+    pub value: u64,
+    pub source: ParameterSource,
+    pub note: Option<String>,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Deserialize)]
+pub struct EffectiveRateCurve {
+    /// `(collateral ratio, interest multiplier)` pairs, CR ascending.
+    pub markers: Vec<(f64, f64)>,
+    pub source: ParameterSource,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Deserialize)]
+pub struct EffectiveParameters {
+    pub collateral_type: CollateralType,
+    pub mode: Mode,
+    pub borrowing_fee: EffectiveRatio,
+    /// Share of the borrowing fee rebated to new borrows.
+    pub borrowing_fee_rebate: EffectiveRatio,
+    /// Base APR before the rate curve multiplier.
+    pub interest_rate_apr: EffectiveRatio,
+    pub interest_rate_curve: EffectiveRateCurve,
+    /// Annual fee on the collateral's USD value, charged on every open vault
+    /// whether or not it borrows.
+    pub maintenance_fee_apr: EffectiveRatio,
+    /// Ratio below which a vault is liquidatable in the current mode.
+    pub liquidation_threshold: EffectiveRatio,
+    pub borrow_threshold_ratio: EffectiveRatio,
+    pub recovery_cr: EffectiveRatio,
+    pub healthy_cr: EffectiveRatio,
+    pub liquidation_bonus: EffectiveRatio,
+    pub redemption_fee_floor: EffectiveRatio,
+    pub redemption_fee_ceiling: EffectiveRatio,
+    pub debt_ceiling: EffectiveAmount,
+    pub min_vault_debt: EffectiveAmount,
+    pub min_collateral_deposit: EffectiveAmount,
+    pub min_xrc_sources: EffectiveAmount,
+    /// Price bounds in USD e8s per whole token; see `xrc::PriceBounds`.
+    pub min_price_e8s: EffectiveAmount,
+    pub max_price_e8s: EffectiveAmount,
+    pub computed_at: u64,
+}
+
+fn ratio(value: Ratio, source: ParameterSource, note: Option<String>) -> EffectiveRatio {
+    EffectiveRatio {
+        value: value.to_f64(),
+        source,
+        note,
+    }
+}
+
+fn amount(value: u64, source: ParameterSource, note: Option<String>) -> EffectiveAmount {
+    EffectiveAmount {
+        value,
+        source,
+        note,
+    }
+}
+
+fn in_recovery(state: &State) -> Option<String> {
+    (state.mode == Mode::Recovery).then(|| "Recovery mode".to_string())
+}
+
+/// Resolve every parameter for `collateral_type`, or `None` if it is not a
+/// registered collateral.
+pub fn resolve(
+    state: &State,
+    collateral_type: &CollateralType,
+    now_ns: u64,
+) -> Option<EffectiveParameters> {
+    use ParameterSource::*;
+    let config = state.get_collateral_config(collateral_type)?;
+    let recovery = state.mode == Mode::Recovery;
+
+    let borrowing_fee = match config.recovery_borrowing_fee {
+        Some(fee) if recovery => ratio(fee, Override, in_recovery(state)),
+        _ => ratio(config.borrowing_fee, Config, None),
+    };
+    let borrowing_fee_rebate = match state
+        .rebate_campaigns
+        .values()
+        .find(|c| c.is_active_at(now_ns))
+    {
+        Some(campaign) => EffectiveRatio {
+            value: campaign.rebate_bps as f64 / MAX_REBATE_BPS as f64,
+            source: Promo,
+            note: Some(format!(
+                "Campaign #{} \"{}\" until {}",
+                campaign.id, campaign.name, campaign.end_ns
+            )),
+        },
+        None => ratio(Ratio::from_f64(0.0), Default, None),
+    };
+
+    let interest_rate_apr = match config.recovery_interest_rate_apr {
+        Some(rate) if recovery => ratio(
+            rate,
+            Override,
+            Some("Recovery mode; the rate curve is not applied".to_string()),
+        ),
+        _ => ratio(config.interest_rate_apr, Config, None),
+    };
+    let interest_rate_curve = EffectiveRateCurve {
+        markers: state
+            .resolve_layer1_markers(collateral_type)
+            .into_iter()
+            .map(|(cr, multiplier)| (cr.to_f64(), multiplier.to_f64()))
+            .collect(),
+        source: if config.rate_curve.is_some() {
+            Override
+        } else {
+            Default
+        },
+    };
+
+    let maintenance_fee_apr = match config.maintenance_fee_apr {
+        Some(fee) => ratio(
+            fee,
+            Override,
+            Some("Charged on collateral value, borrowing or not".to_string()),
+        ),
+        None => ratio(Ratio::from_f64(0.0), Default, None),
+    };
+
+    let liquidation_threshold = if recovery {
+        ratio(
+            config.borrow_threshold_ratio,
+            Config,
+            Some("Recovery mode liquidates below the borrow threshold".to_string()),
+        )
+    } else {
+        ratio(config.liquidation_ratio, Config, None)
+    };
+    let recovery_cr = ratio(
+        state.get_recovery_cr_for(collateral_type),
+        Config,
+        Some(format!(
+            "Borrow threshold x global recovery multiplier {}",
+            state.recovery_cr_multiplier.to_f64()
+        )),
+    );
+    let healthy_cr = match config.healthy_cr {
+        Some(healthy) => ratio(healthy, Override, None),
+        None => ratio(
+            state.get_healthy_cr_for(collateral_type),
+            Default,
+            Some("1.5 x borrow threshold".to_string()),
+        ),
+    };
+    let min_xrc_sources = match config.min_xrc_sources {
+        Some(floor) => amount(floor as u64, Override, None),
+        None => amount(state.min_xrc_sources_used as u64, Default, None),
+    };
+    let bounds = price_bounds(state, collateral_type);
+    let bounds_source = if state.price_bounds.contains_key(collateral_type) {
+        Override
+    } else {
+        Default
+    };
+
+    Some(EffectiveParameters {
+        collateral_type: *collateral_type,
+        mode: state.mode,
+        borrowing_fee,
+        borrowing_fee_rebate,
+        interest_rate_apr,
+        interest_rate_curve,
+        maintenance_fee_apr,
+        liquidation_threshold,
+        borrow_threshold_ratio: ratio(config.borrow_threshold_ratio, Config, None),
+        recovery_cr,
+        healthy_cr,
+        liquidation_bonus: ratio(config.liquidation_bonus, Config, None),
+        redemption_fee_floor: ratio(config.redemption_fee_floor, Config, None),
+        redemption_fee_ceiling: ratio(config.redemption_fee_ceiling, Config, None),
+        debt_ceiling: amount(config.debt_ceiling, Config, None),
+        min_vault_debt: amount(config.min_vault_debt.to_u64(), Config, None),
+        min_collateral_deposit: amount(config.min_collateral_deposit, Config, None),
+        min_xrc_sources,
+        min_price_e8s: amount(bounds.min_price_e8s, bounds_source, None),
+        max_price_e8s: amount(bounds.max_price_e8s, bounds_source, None),
+        computed_at: now_ns,
+    })
+}
@@ -0,0 +1,194 @@
+//! Global emergency shutdown.
+//!
+//! The protocol-wide, one-way counterpart of `collateral_settlement`, in two
+//! steps:
+//!
+//! 1. `emergency_shutdown` (developer only) halts the protocol and fixes a
+//!    settlement price, its last oracle price, for every unsettled collateral
+//!    that still has vaults. Every collateral is frozen, so no vault is
+//!    opened, borrowed against, liquidated or redeemed against again, and
+//!    flash mints are refused. It is refused while one of those collaterals
+//!    has no price.
+//! 2. `settle_shutdown_collateral` (anyone) settles one of those collaterals
+//!    at its fixed price, as `settle_collateral` would, ICP included. One
+//!    call per collateral keeps each within the instruction limit and lets a
+//!    collateral with a vault operation in flight be retried on its own. The
+//!    shutdown is settled once every fixed price has been used.
+//!
+//! Owners and holders then exit through `collateral_settlement`:
+//! `claim_settled_vault` returns each owner's excess collateral and
+//! `redeem_settled_collateral` pays icUSD out of each pool at its pro-rata
+//! rate, which neither the order of redemptions nor a later price changes.
+//!
+//! Nothing undoes a shutdown: collateral status and config changes, new
+//! collaterals and per-collateral settlement at a later price are refused
+//! from then on. Native-XRP collateral is frozen but not settled (see
+//! `collateral_settlement`). The liquidity pool, the reserves and the surplus
+//! buffer are left as they are.
+//!
+//! The shutdown is logged as `EmergencyShutdown` and each settlement as
+//! `CollateralSettled`; replay rebuilds both.
+
+use crate::collateral_settlement::{self, CollateralSettlementStatus};
+use crate::logs::INFO;
+use crate::numeric::UsdIcp;
+use crate::state::{CollateralStatus, State};
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmergencyShutdown {
+    pub triggered_at: u64,
+    pub triggered_by: Principal,
+    /// The settlement price fixed for each collateral it settles.
+    pub prices: BTreeMap<Principal, UsdIcp>,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Deserialize)]
+pub struct EmergencyShutdownStatus {
+    pub triggered_at: u64,
+    pub triggered_by: Principal,
+    pub prices: Vec<(Principal, f64)>,
+    /// Collaterals not yet settled at their fixed price.
+    pub pending: Vec<Principal>,
+    pub settled: bool,
+}
+
+pub fn is_shut_down(state: &State) -> bool {
+    state.emergency_shutdown.is_some()
+}
+
+/// Refuse an operation a shutdown has ended for good.
+pub fn check_not_shut_down(state: &State) -> Result<(), ProtocolError> {
+    if is_shut_down(state) {
+        return Err(ProtocolError::GenericError(
+            "The protocol has been shut down".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// The settlement price of every unsettled collateral with vaults, except
+/// native XRP.
+pub fn fix_prices(state: &State) -> Result<Vec<(Principal, UsdIcp)>, ProtocolError> {
+    let mut prices = Vec::new();
+    for (collateral_type, config) in &state.collateral_configs {
+        let has_vaults = state
+            .vault_id_to_vaults
+            .values()
+            .any(|vault| vault.collateral_type == *collateral_type);
+        if !has_vaults
+            || config.is_native_xrp()
+            || collateral_settlement::is_settled(state, collateral_type)
+        {
+            continue;
+        }
+        let price =
+            collateral_settlement::settlement_price(state, collateral_type).ok_or_else(|| {
+                ProtocolError::TemporarilyUnavailable(format!(
+                    "No price to fix for {}",
+                    collateral_type
+                ))
+            })?;
+        prices.push((*collateral_type, price));
+    }
+    Ok(prices)
+}
+
+/// Freeze every collateral and fix `prices`, the only prices settlement
+/// uses from then on.
+pub fn apply_shutdown(
+    state: &mut State,
+    triggered_by: Principal,
+    prices: &[(Principal, UsdIcp)],
+    now: u64,
+) {
+    for config in state.collateral_configs.values_mut() {
+        config.status = CollateralStatus::Frozen;
+    }
+    state.emergency_shutdown = Some(EmergencyShutdown {
+        triggered_at: now,
+        triggered_by,
+        prices: prices.iter().copied().collect(),
+    });
+}
+
+/// The collaterals the shutdown has still to settle.
+pub fn pending(state: &State) -> Vec<Principal> {
+    state
+        .emergency_shutdown
+        .iter()
+        .flat_map(|shutdown| shutdown.prices.keys())
+        .filter(|collateral_type| !collateral_settlement::is_settled(state, collateral_type))
+        .copied()
+        .collect()
+}
+
+pub fn status(state: &State) -> Option<EmergencyShutdownStatus> {
+    let shutdown = state.emergency_shutdown.as_ref()?;
+    let pending = pending(state);
+    Some(EmergencyShutdownStatus {
+        triggered_at: shutdown.triggered_at,
+        triggered_by: shutdown.triggered_by,
+        prices: shutdown
+            .prices
+            .iter()
+            .map(|(collateral_type, price)| (*collateral_type, price.to_f64()))
+            .collect(),
+        settled: pending.is_empty(),
+        pending,
+    })
+}
+
+/// Shut the protocol down, fixing every collateral's settlement price.
+pub fn trigger(
+    state: &mut State,
+    triggered_by: Principal,
+    now: u64,
+) -> Result<EmergencyShutdownStatus, ProtocolError> {
+    check_not_shut_down(state)?;
+    let prices = fix_prices(state)?;
+    crate::event::record_emergency_shutdown(state, triggered_by, prices, now);
+    log!(
+        INFO,
+        "[emergency_shutdown] protocol SHUT DOWN by {}; {} collaterals to settle",
+        triggered_by,
+        pending(state).len()
+    );
+    Ok(status(state).expect("just shut down"))
+}
+
+/// Settle `collateral_type` at the price the shutdown fixed for it.
+pub fn settle(
+    state: &mut State,
+    collateral_type: Principal,
+    settled_by: Principal,
+    now: u64,
+) -> Result<CollateralSettlementStatus, ProtocolError> {
+    let price = state
+        .emergency_shutdown
+        .as_ref()
+        .ok_or_else(|| {
+            ProtocolError::GenericError("The protocol has not been shut down".to_string())
+        })?
+        .prices
+        .get(&collateral_type)
+        .copied();
+    collateral_settlement::check_settleable(state, &collateral_type)?;
+    let price = price.ok_or_else(|| {
+        ProtocolError::GenericError(format!(
+            "The shutdown fixed no price for {}",
+            collateral_type
+        ))
+    })?;
+    Ok(collateral_settlement::settle_at(
+        state,
+        collateral_type,
+        price,
+        settled_by,
+        now,
+    ))
+}
@@ -1,12 +1,15 @@
+use crate::campaigns::CreateRebateCampaignArg;
 use crate::numeric::{Ratio, UsdIcp, ICP, ICUSD};
+use crate::session_keys::{RegisterSessionKeyArg, SessionScope};
 use crate::state::{
-    CollateralConfig, CollateralStatus, CollateralType, PendingMarginTransfer, RateCurveV2, State,
+    CollateralConfig, CollateralStatus, CollateralType, LiquidationRebateMode,
+    ModeTransitionReason, PendingMarginTransfer, RateCurveV2, SecondaryPriceSource, State,
 };
-use crate::storage::record_event;
+use crate::storage::{record_event, record_event_after};
 use crate::vault::Vault;
 use crate::{EventTimeRange, EventTypeFilter, InitArg, Mode, StableTokenType, UpgradeArg};
 use candid::{CandidType, Principal};
-use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
@@ -28,6 +31,20 @@ pub struct VaultRedemption {
 pub enum FeeSource {
     BorrowingFee,
     RedemptionFee,
+    /// An icUSD donation (see `donations`), burned on receipt.
+    Donation,
+    /// The icUSD surplus buffer absorbing a newly accrued deficit.
+    SurplusBuffer,
+    /// The protocol's collateral cut of a liquidation (see `surplus`).
+    LiquidationFee,
+}
+
+/// The ckstable surcharge of a stable-token repayment, in the stable
+/// ledger's units (e6s).
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StableRepayFee {
+    pub ledger: Principal,
+    pub fee_e6s: u64,
 }
 
 /// Wave-9 RED-002: identifies which path accrued a shortfall to
@@ -41,12 +58,16 @@ pub enum FeeSource {
 /// back-compat with the existing event-log shape; for redemption,
 /// `vault_id` on the parent is set to 0 (the cr-walk touches multiple
 /// vaults, no single id applies) and the redeemer principal lives
-/// inside this enum.
+/// inside this enum. Flash-mint deficits likewise use `vault_id = 0` and
+/// name the callback that did not repay (see `flash_mint`). Dust written
+/// off when a vault is auto-closed keeps its `vault_id` (see `dust_vaults`).
 #[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case", tag = "kind")]
 pub enum DeficitSource {
     Liquidation { vault_id: u64 },
     Redemption { redeemer: Principal },
+    FlashMint { callback: Principal },
+    DustWriteOff { vault_id: u64 },
 }
 
 #[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -84,6 +105,11 @@ pub enum Event {
         liquidator: Option<Principal>,
         #[serde(default, skip_serializing_if = "Option::is_none")]
         timestamp: Option<u64>,
+        /// Collateral taken for the debt and bonus, not counting what went
+        /// back to the owner. Counted against the liquidation cap; None on
+        /// events from before it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        collateral_seized: Option<u64>,
     },
 
     #[serde(rename = "partial_liquidate_vault")]
@@ -196,6 +222,10 @@ pub enum Event {
         caller: Option<Principal>,
         #[serde(default, skip_serializing_if = "Option::is_none")]
         timestamp: Option<u64>,
+        /// Collateral USD price the borrow was checked against, for the
+        /// vault's borrow records.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        collateral_price: Option<String>,
     },
 
     #[serde(rename = "repay_to_vault")]
@@ -207,6 +237,10 @@ pub enum Event {
         caller: Option<Principal>,
         #[serde(default, skip_serializing_if = "Option::is_none")]
         timestamp: Option<u64>,
+        /// Surcharge pulled on top of a ckUSDT/ckUSDC repayment. None for
+        /// icUSD repayments and for events recorded before this field.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stable_fee: Option<StableRepayFee>,
     },
 
     #[serde(rename = "add_margin_to_vault")]
@@ -419,6 +453,15 @@ pub enum Event {
     #[serde(rename = "set_reserve_redemption_fee")]
     SetReserveRedemptionFee { fee: String },
 
+    /// Add or replace a reserve redemption stable. See `reserve_stables`.
+    #[serde(rename = "set_reserve_stable")]
+    SetReserveStable {
+        stable: crate::reserve_stables::ReserveStable,
+    },
+
+    #[serde(rename = "remove_reserve_stable")]
+    RemoveReserveStable { ledger: Principal },
+
     #[serde(rename = "reserve_redemption")]
     ReserveRedemption {
         owner: Principal,
@@ -775,6 +818,731 @@ pub enum Event {
         total_debt_e8s: u128,
         timestamp: u64,
     },
+    // Every `State::mode` change, with the trigger that caused it. Emitted
+    // from the `pending_mode_transitions` outbox by `record_mode_transitions`.
+    #[serde(rename = "mode_transition")]
+    ModeTransition {
+        from: Mode,
+        to: Mode,
+        reason: ModeTransitionReason,
+        timestamp: u64,
+    },
+    // Borrowing-fee rebate campaigns (see `campaigns`).
+    #[serde(rename = "rebate_campaign_created")]
+    RebateCampaignCreated {
+        campaign_id: u64,
+        arg: CreateRebateCampaignArg,
+        timestamp: u64,
+    },
+    #[serde(rename = "rebate_campaign_funded")]
+    RebateCampaignFunded {
+        campaign_id: u64,
+        amount_e8s: u64,
+        timestamp: u64,
+    },
+    #[serde(rename = "rebate_campaign_closed")]
+    RebateCampaignClosed {
+        campaign_id: u64,
+        unspent_e8s: u64,
+        timestamp: u64,
+    },
+    #[serde(rename = "borrow_fee_rebated")]
+    BorrowFeeRebated {
+        campaign_id: u64,
+        vault_id: u64,
+        rebate_e8s: u64,
+        timestamp: u64,
+    },
+    // Scoped session keys (see `session_keys`).
+    #[serde(rename = "session_key_registered")]
+    SessionKeyRegistered {
+        owner: Principal,
+        arg: RegisterSessionKeyArg,
+        timestamp: u64,
+    },
+    #[serde(rename = "session_key_revoked")]
+    SessionKeyRevoked {
+        owner: Principal,
+        session_principal: Principal,
+        timestamp: u64,
+    },
+    #[serde(rename = "session_key_used")]
+    SessionKeyUsed {
+        session_principal: Principal,
+        vault_id: u64,
+        scope: SessionScope,
+        amount_e8s: u64,
+        timestamp: u64,
+    },
+    /// A full liquidation's leftover collateral was credited to another of
+    /// the owner's vaults (`LiquidationRebateMode::CollateralTopUp`) instead
+    /// of being paid out.
+    #[serde(rename = "liquidation_rebate_applied")]
+    LiquidationRebateApplied {
+        source_vault_id: u64,
+        target_vault_id: u64,
+        owner: Principal,
+        collateral_amount: u64,
+        timestamp: u64,
+    },
+    #[serde(rename = "set_collateral_liquidation_rebate_mode")]
+    SetCollateralLiquidationRebateMode {
+        collateral_type: CollateralType,
+        mode: LiquidationRebateMode,
+    },
+
+    /// An XRC price diverged from the collateral's secondary source by more
+    /// than its band. The XRC sample was dropped, the previous price kept,
+    /// and the collateral marked `price_disputed`.
+    #[serde(rename = "price_disputed")]
+    PriceDisputed {
+        collateral_type: CollateralType,
+        /// Prices as strings, like `PriceUpdate`.
+        xrc_price: String,
+        secondary_price: String,
+        divergence_bps: u64,
+        timestamp: u64,
+    },
+    /// A price dispute ended: either the sources agree again (`resolved_by`
+    /// is `None`) or the developer cleared it manually.
+    #[serde(rename = "price_dispute_cleared")]
+    PriceDisputeCleared {
+        collateral_type: CollateralType,
+        resolved_by: Option<Principal>,
+        timestamp: u64,
+    },
+    #[serde(rename = "set_collateral_secondary_price_source")]
+    SetCollateralSecondaryPriceSource {
+        collateral_type: CollateralType,
+        source: Option<SecondaryPriceSource>,
+    },
+
+    /// A guardian or the developer froze one vault: withdrawals, borrows and
+    /// closes are rejected until `expires_at_ns` (see `vault_freeze`).
+    #[serde(rename = "vault_frozen")]
+    VaultFrozen {
+        vault_id: u64,
+        frozen_by: Principal,
+        reason: String,
+        expires_at_ns: u64,
+        timestamp: u64,
+    },
+    /// A freeze was lifted before its expiry. Lapsed freezes log nothing.
+    #[serde(rename = "vault_unfrozen")]
+    VaultUnfrozen {
+        vault_id: u64,
+        unfrozen_by: Principal,
+        timestamp: u64,
+    },
+    #[serde(rename = "set_guardian_principals")]
+    SetGuardianPrincipals { principals: Vec<Principal> },
+    #[serde(rename = "set_mode_companion_canisters")]
+    SetModeCompanionCanisters { canisters: Vec<Principal> },
+    /// A price update moved vaults of `collateral_type` into or out of the
+    /// liquidatable set (see `liquidatable_set`). Observability only.
+    #[serde(rename = "liquidatable_set_changed")]
+    LiquidatableSetChanged {
+        collateral_type: CollateralType,
+        added: Vec<u64>,
+        removed: Vec<u64>,
+        timestamp: u64,
+    },
+    /// Whitelist (`Some`) or remove (`None`) the DEX route used to swap a
+    /// vault's collateral from one type to another (see `collateral_swap`).
+    #[serde(rename = "set_collateral_swap_route")]
+    SetCollateralSwapRoute {
+        from_collateral_type: CollateralType,
+        to_collateral_type: CollateralType,
+        route: Option<crate::collateral_swap::CollateralSwapRoute>,
+    },
+    /// A vault's collateral was sold on `dex` and replaced in place by
+    /// `amount_out` of the new collateral. Debt is unchanged.
+    #[serde(rename = "vault_collateral_swapped")]
+    VaultCollateralSwapped {
+        vault_id: u64,
+        from_collateral_type: CollateralType,
+        to_collateral_type: CollateralType,
+        amount_in: u64,
+        amount_out: u64,
+        dex: Principal,
+        timestamp: u64,
+    },
+    /// A collateral swap did not go through. The DEX moved nothing, but
+    /// granting and revoking its allowance cost `fees` of the old
+    /// collateral, which were taken off the vault.
+    #[serde(rename = "vault_collateral_swap_failed")]
+    VaultCollateralSwapFailed {
+        vault_id: u64,
+        collateral_type: CollateralType,
+        fees: u64,
+        timestamp: u64,
+    },
+    /// Dust liquidity `returns` of `caller` were taken off their claimable
+    /// returns and set aside for the treasury.
+    #[serde(rename = "liquidity_dust_swept")]
+    LiquidityDustSwept {
+        caller: Principal,
+        returns: ICP,
+        timestamp: u64,
+    },
+    /// `amount` of swept liquidity dust left for the treasury, ledger fee
+    /// included.
+    #[serde(rename = "liquidity_dust_sent_to_treasury")]
+    LiquidityDustSentToTreasury {
+        amount: ICP,
+        block_index: u64,
+        timestamp: u64,
+    },
+    /// A liquidity position below the minimum was minted back to `caller`,
+    /// after a withdrawal (same `block_index`) or by the residual sweep.
+    #[serde(rename = "liquidity_residual_returned")]
+    LiquidityResidualReturned {
+        caller: Principal,
+        amount: ICUSD,
+        block_index: u64,
+        timestamp: u64,
+    },
+    /// `check_vaults` routed Recovery-mode liquidations: `to_pool` fit the
+    /// stability pool's reported `pool_depth` and are reserved for it until
+    /// `reserved_until_ns`; `overflow` went to the usual cascade. See
+    /// `pool_priority`.
+    #[serde(rename = "recovery_pool_routing")]
+    RecoveryPoolRouting {
+        to_pool: Vec<u64>,
+        overflow: Vec<u64>,
+        pool_depth: Vec<(CollateralType, u64)>,
+        reserved_until_ns: u64,
+        timestamp: u64,
+    },
+    #[serde(rename = "set_recovery_pool_priority")]
+    SetRecoveryPoolPriority { enabled: bool, window_ns: u64 },
+    /// `owner` opted `vault_id` into auto-deleveraging or changed its
+    /// settings (`Some`), or cancelled (`None`). See `auto_deleverage`.
+    #[serde(rename = "set_auto_deleverage")]
+    SetAutoDeleverage {
+        vault_id: u64,
+        owner: Principal,
+        config: Option<crate::auto_deleverage::AutoDeleverageConfig>,
+        timestamp: u64,
+    },
+    /// Whitelist (`Some`) or remove (`None`) the DEX route auto-deleverage
+    /// sales of `collateral_type` go through.
+    #[serde(rename = "set_auto_deleverage_route")]
+    SetAutoDeleverageRoute {
+        collateral_type: CollateralType,
+        route: Option<crate::auto_deleverage::AutoDeleverageRoute>,
+    },
+    /// An inactive vault below its buffer had `collateral_sold` (fees
+    /// included) sold on `dex`, and `icusd_repaid` of debt was repaid.
+    #[serde(rename = "vault_auto_deleveraged")]
+    VaultAutoDeleveraged {
+        vault_id: u64,
+        collateral_type: CollateralType,
+        collateral_sold: u64,
+        icusd_repaid: ICUSD,
+        cr_before_bps: u64,
+        dex: Principal,
+        timestamp: u64,
+    },
+    /// An auto-deleverage sale did not go through; the vault is unchanged
+    /// and backs off before the next try.
+    #[serde(rename = "auto_deleverage_failed")]
+    AutoDeleverageFailed {
+        vault_id: u64,
+        reason: String,
+        timestamp: u64,
+    },
+    #[serde(rename = "set_pending_backpressure")]
+    SetPendingBackpressure {
+        max_pending_collateral_transfers: u64,
+        max_pending_redemption_transfers: u64,
+    },
+    /// A live vault moved between lifecycle statuses. Moves to `Closed` are
+    /// the removal events themselves. See `vault_status`.
+    #[serde(rename = "vault_status_changed")]
+    VaultStatusChanged {
+        vault_id: u64,
+        from: crate::vault_status::VaultStatus,
+        to: crate::vault_status::VaultStatus,
+        timestamp: u64,
+    },
+    #[serde(rename = "set_log_retention")]
+    SetLogRetention {
+        critical_capacity: u64,
+        info_capacity: u64,
+        debug_capacity: u64,
+        trace_xrc_capacity: u64,
+        persisted_critical_capacity: u64,
+    },
+    /// One vault's share of a redemption, recorded right after the
+    /// `RedemptionOnVaults` that applied it, so each vault touched has its
+    /// own entry in the log.
+    #[serde(rename = "partial_redemption")]
+    PartialRedemption {
+        vault_id: u64,
+        collateral_type: CollateralType,
+        icusd_redeemed_e8s: u64,
+        collateral_seized: u64,
+        icusd_block_index: u64,
+        timestamp: u64,
+    },
+    /// Admin set the confidence multiple `k` used to check borrows and
+    /// withdrawals against `price - k·σ`. `None` checks at the mid-price.
+    #[serde(rename = "set_collateral_price_confidence")]
+    SetCollateralPriceConfidence {
+        collateral_type: CollateralType,
+        k: Option<String>,
+    },
+    /// Admin set (`Some`) or removed (`None`) a collateral's annual
+    /// maintenance fee, effective from `timestamp`.
+    #[serde(rename = "set_collateral_maintenance_fee")]
+    SetCollateralMaintenanceFee {
+        collateral_type: CollateralType,
+        maintenance_fee_apr: Option<String>,
+        timestamp: u64,
+    },
+    /// Admin applied several parameter changes at once through
+    /// `admin_apply_parameter_batch`. `changes` are in the order given.
+    #[serde(rename = "apply_parameter_batch")]
+    ApplyParameterBatch {
+        changes: Vec<crate::parameter_batch::BatchedParameter>,
+        timestamp: u64,
+    },
+    /// Admin set (`Some`) or reset to the default (`None`) the band above
+    /// the recovery threshold the TCR must clear to leave Recovery.
+    #[serde(rename = "set_recovery_exit_band")]
+    SetRecoveryExitBand { band: Option<String> },
+    /// `owner` sold `collateral_sold` (fees included) of the vault's
+    /// collateral on `dex` and repaid `icusd_repaid` of its debt with the
+    /// proceeds. See `repay_from_collateral`.
+    #[serde(rename = "vault_repaid_from_collateral")]
+    VaultRepaidFromCollateral {
+        vault_id: u64,
+        owner: Principal,
+        collateral_type: CollateralType,
+        collateral_sold: u64,
+        icusd_repaid: ICUSD,
+        dex: Principal,
+        timestamp: u64,
+    },
+    /// `owner` cancelled the failing payout of the redemption burned at
+    /// `icusd_block_index`: each vault got back its `vault_redemptions`
+    /// share and `icusd_refunded` was minted to `owner`.
+    #[serde(rename = "redemption_cancelled")]
+    RedemptionCancelled {
+        owner: Principal,
+        icusd_block_index: u64,
+        icusd_refunded: ICUSD,
+        vault_redemptions: Vec<VaultRedemption>,
+        timestamp: u64,
+    },
+    /// Admin set (`Some`) or removed (`None`) the per-epoch redemption cap
+    /// of a collateral. Resets the epoch's usage.
+    #[serde(rename = "set_collateral_redemption_cap")]
+    SetCollateralRedemptionCap {
+        collateral_type: CollateralType,
+        config: Option<crate::redemption_caps::RedemptionCapConfig>,
+    },
+    /// The price-deviation breaker rejected a sample that moved
+    /// `deviation_bps` away from the last accepted price within its window.
+    /// The previous price was kept. `recovery`: the breaker is holding the
+    /// protocol in Recovery until the collateral's next accepted price.
+    #[serde(rename = "price_rejected")]
+    PriceRejected {
+        collateral_type: CollateralType,
+        /// Prices as strings, like `PriceUpdate`.
+        rejected_price: String,
+        last_price: String,
+        deviation_bps: u64,
+        recovery: bool,
+        timestamp: u64,
+    },
+    /// Admin set (`Some`) or removed (`None`) the price-deviation breaker.
+    #[serde(rename = "set_price_deviation_breaker")]
+    SetPriceDeviationBreaker {
+        config: Option<crate::xrc::PriceDeviationBreaker>,
+    },
+    /// A sample fell outside the collateral's absolute price bounds and was
+    /// rejected; the previous price was kept.
+    #[serde(rename = "price_out_of_bounds")]
+    PriceOutOfBounds {
+        collateral_type: CollateralType,
+        /// As a string, like `PriceUpdate`.
+        rejected_price: String,
+        min_price_e8s: u64,
+        max_price_e8s: u64,
+        timestamp: u64,
+    },
+    /// The collateral had a price accepted after a run of rejections. Only
+    /// the first `PriceRejected` and `PriceOutOfBounds` of the run are
+    /// recorded; this closes it with the totals.
+    #[serde(rename = "price_rejections_cleared")]
+    PriceRejectionsCleared {
+        collateral_type: CollateralType,
+        rejections: crate::xrc::PriceRejections,
+        timestamp: u64,
+    },
+    /// Admin set (`Some`) or reset to `xrc::DEFAULT_PRICE_BOUNDS` (`None`) a
+    /// collateral's price bounds.
+    #[serde(rename = "set_collateral_price_bounds")]
+    SetCollateralPriceBounds {
+        collateral_type: CollateralType,
+        bounds: Option<crate::xrc::PriceBounds>,
+    },
+    /// A redemption against `collateral_type` left its base rate at
+    /// `base_rate`, decaying from `timestamp`.
+    #[serde(rename = "redemption_base_rate_updated")]
+    RedemptionBaseRateUpdated {
+        collateral_type: CollateralType,
+        /// As a string, like `PriceUpdate`.
+        base_rate: String,
+        timestamp: u64,
+    },
+    /// Admin set (`Some`) or disabled (`None`) flash mints.
+    #[serde(rename = "set_flash_mint_config")]
+    SetFlashMintConfig {
+        config: Option<crate::flash_mint::FlashMintConfig>,
+    },
+    /// `initiator` flash-minted `amount_e8s` icUSD to `callback`.
+    /// `repay_block_index` is `None` when the callback did not repay; it was
+    /// then dropped from the allowlist and `amount_e8s` accrued to the
+    /// deficit by the `DeficitAccrued` recorded next.
+    #[serde(rename = "flash_mint")]
+    FlashMint {
+        initiator: Principal,
+        callback: Principal,
+        amount_e8s: u64,
+        fee_e8s: u64,
+        mint_block_index: u64,
+        repay_block_index: Option<u64>,
+        timestamp: u64,
+    },
+
+    /// Admin set (`Some`) or cleared (`None`) the SLO thresholds of
+    /// `endpoint`, or the default ones when `endpoint` is `None`.
+    #[serde(rename = "set_slo_thresholds")]
+    SetSloThresholds {
+        endpoint: Option<String>,
+        thresholds: Option<crate::slo::SloThresholds>,
+    },
+    /// `endpoint` went past its `kind` objective: a call took `observed`
+    /// ms or instructions, or its window's service-error rate reached
+    /// `observed` bps. Recorded once per endpoint, kind and window.
+    #[serde(rename = "slo_breach")]
+    SloBreach {
+        endpoint: String,
+        kind: crate::slo::SloBreachKind,
+        observed: u64,
+        threshold: u64,
+        timestamp: u64,
+    },
+
+    /// `donor` gave `amount` of `ledger` to the surplus buffer, pulled in
+    /// `block_index`. For icUSD, `deficit_repaid` of it repaid the deficit
+    /// (its own `DeficitRepaid`, recorded just before) and the rest went to
+    /// the buffer.
+    #[serde(rename = "donation")]
+    Donation {
+        donor: Principal,
+        ledger: Principal,
+        amount: u64,
+        deficit_repaid: u64,
+        block_index: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        memo: Option<String>,
+        timestamp: u64,
+    },
+
+    /// `liquidator` joined the liquidator registry, or was renamed if
+    /// already in it. See `liquidators`.
+    #[serde(rename = "register_liquidator")]
+    RegisterLiquidator {
+        liquidator: Principal,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        self_registered: bool,
+        timestamp: u64,
+    },
+    #[serde(rename = "remove_liquidator")]
+    RemoveLiquidator {
+        liquidator: Principal,
+        timestamp: u64,
+    },
+    /// Developer allowed or stopped self-registration of liquidators.
+    #[serde(rename = "set_liquidator_self_registration")]
+    SetLiquidatorSelfRegistration { enabled: bool },
+
+    /// Developer configured ledger fee sponsorship. See `fee_sponsorship`.
+    #[serde(rename = "set_fee_sponsorship_config")]
+    SetFeeSponsorshipConfig {
+        config: crate::fee_sponsorship::FeeSponsorshipConfig,
+    },
+    #[serde(rename = "set_fee_sponsorship_verified")]
+    SetFeeSponsorshipVerified { user: Principal, verified: bool },
+    /// `funder` added `amount` of `ledger` to the sponsorship pool, pulled in
+    /// `block_index`.
+    #[serde(rename = "fund_fee_sponsorship")]
+    FundFeeSponsorship {
+        funder: Principal,
+        ledger: Principal,
+        amount: u64,
+        block_index: u64,
+        timestamp: u64,
+    },
+    /// `amount` of the `ledger` pool went back to the treasury in
+    /// `block_index`; the pool also paid `fee`.
+    #[serde(rename = "return_fee_sponsorship")]
+    ReturnFeeSponsorship {
+        ledger: Principal,
+        amount: u64,
+        fee: u64,
+        block_index: u64,
+        timestamp: u64,
+    },
+    /// The pool refunded `user` the `fee` of an operation on `vault_id`,
+    /// credited to the vault by the `AddMarginToVault` recorded just before.
+    #[serde(rename = "fee_sponsored")]
+    FeeSponsored {
+        user: Principal,
+        vault_id: u64,
+        ledger: Principal,
+        fee: u64,
+        timestamp: u64,
+    },
+    /// `principal`'s guard for `operation_name` was held for `age_ns` and
+    /// cleared by the stuck-guard timer. See `guard_metrics`.
+    #[serde(rename = "guard_auto_cleared")]
+    GuardAutoCleared {
+        principal: Principal,
+        operation_name: String,
+        age_ns: u64,
+        reason: crate::guard_metrics::GuardClearReason,
+        timestamp: u64,
+    },
+    #[serde(rename = "set_auction_config")]
+    SetAuctionConfig {
+        config: crate::auction::AuctionConfig,
+    },
+    /// The protocol started auctioning a vault's collateral. See `auction`.
+    #[serde(rename = "auction_started")]
+    AuctionStarted { auction: crate::auction::Auction },
+    /// `bidder` burned `icusd_amount` against the vault's debt for
+    /// `collateral_amount` at `price_e8s`; `debt_written_off` is debt left on
+    /// the vault the bid emptied.
+    #[serde(rename = "auction_bid")]
+    AuctionBid {
+        auction_id: u64,
+        vault_id: u64,
+        bidder: Principal,
+        icusd_amount: ICUSD,
+        collateral_amount: u64,
+        price_e8s: u64,
+        debt_written_off: ICUSD,
+        icusd_block_index: u64,
+        timestamp: u64,
+    },
+    #[serde(rename = "auction_ended")]
+    AuctionEnded {
+        auction_id: u64,
+        vault_id: u64,
+        reason: crate::auction::AuctionEndReason,
+        timestamp: u64,
+    },
+    #[serde(rename = "set_redistribution_enabled")]
+    SetRedistributionEnabled { enabled: bool },
+    /// The vault's debt and collateral went to the other vaults of its
+    /// type and it was removed. See `redistribution`.
+    #[serde(rename = "vault_redistributed")]
+    VaultRedistributed {
+        vault_id: u64,
+        owner: Principal,
+        collateral_type: Principal,
+        collateral_amount: u64,
+        debt: ICUSD,
+        timestamp: u64,
+    },
+    /// A dust vault was auto-closed: its debt written off (see its
+    /// `DeficitAccrued`) and its collateral queued back to the owner. See
+    /// `dust_vaults`.
+    #[serde(rename = "dust_vault_closed")]
+    DustVaultClosed {
+        vault_id: u64,
+        owner: Principal,
+        collateral_type: Principal,
+        collateral_amount: u64,
+        debt: ICUSD,
+        timestamp: u64,
+    },
+    #[serde(rename = "set_shadow_config")]
+    SetShadowConfig { config: crate::shadow::ShadowConfig },
+    #[serde(rename = "set_liquidation_tip")]
+    SetLiquidationTip { tip_e8s: u64 },
+    /// A guardian or the developer co-approved one treasury withdrawal until
+    /// `expires_at_ns` (see `treasury_approvals`).
+    #[serde(rename = "treasury_withdrawal_approved")]
+    TreasuryWithdrawalApproved {
+        request: crate::treasury_approvals::TreasuryWithdrawalRequest,
+        approved_by: Principal,
+        expires_at_ns: u64,
+        timestamp: u64,
+    },
+    #[serde(rename = "treasury_withdrawal_approval_revoked")]
+    TreasuryWithdrawalApprovalRevoked {
+        request_id: u64,
+        revoked_by: Principal,
+        timestamp: u64,
+    },
+    /// The treasury first used an approval. Retries of the same withdrawal
+    /// log nothing.
+    #[serde(rename = "treasury_withdrawal_approval_consumed")]
+    TreasuryWithdrawalApprovalConsumed { request_id: u64, timestamp: u64 },
+    #[serde(rename = "set_surplus_fee_share")]
+    SetSurplusFeeShare { share: Ratio },
+    /// `amount` of a fee in `ledger` was kept in the surplus buffer (see
+    /// `surplus`).
+    #[serde(rename = "surplus_fee_retained")]
+    SurplusFeeRetained {
+        ledger: Principal,
+        amount: u64,
+        source: FeeSource,
+        timestamp: u64,
+    },
+    /// The icUSD surplus buffer repaid `debt` of a vault about to be
+    /// redistributed and took `collateral_amount` of its collateral.
+    #[serde(rename = "surplus_absorbed_vault")]
+    SurplusAbsorbedVault {
+        vault_id: u64,
+        collateral_type: Principal,
+        collateral_amount: u64,
+        debt: ICUSD,
+        timestamp: u64,
+    },
+    /// `amount` of the icUSD surplus buffer was minted to the treasury.
+    #[serde(rename = "surplus_swept")]
+    SurplusSwept {
+        amount: u64,
+        block_index: u64,
+        timestamp: u64,
+    },
+    /// A borrow's proceeds were paid from the `stable_ledger` reserve instead
+    /// of minted; the debt is its `BorrowFromVault`, with the same
+    /// `block_index`. See `vault::borrow_from_vault_as_stable`.
+    #[serde(rename = "borrow_paid_from_reserves")]
+    BorrowPaidFromReserves {
+        vault_id: u64,
+        owner: Principal,
+        stable_ledger: Principal,
+        /// Reserve fee, in icUSD.
+        fee_amount: ICUSD,
+        stable_amount_sent: u64,
+        fee_stable_amount: u64,
+        block_index: u64,
+        timestamp: u64,
+    },
+    /// `collateral_type` was frozen and its vaults settled at `price`. See
+    /// `collateral_settlement`.
+    #[serde(rename = "collateral_settled")]
+    CollateralSettled {
+        collateral_type: Principal,
+        price: UsdIcp,
+        settled_by: Principal,
+        timestamp: u64,
+    },
+    /// A settled vault was closed and `collateral_amount` queued back to its
+    /// owner.
+    #[serde(rename = "settled_vault_claimed")]
+    SettledVaultClaimed {
+        vault_id: u64,
+        owner: Principal,
+        collateral_type: Principal,
+        collateral_amount: u64,
+        timestamp: u64,
+    },
+    /// `icusd_amount` was burned for `collateral_amount` of a settlement
+    /// pool.
+    #[serde(rename = "settled_collateral_redeemed")]
+    SettledCollateralRedeemed {
+        redeemer: Principal,
+        collateral_type: Principal,
+        icusd_amount: u64,
+        collateral_amount: u64,
+        icusd_block_index: u64,
+        timestamp: u64,
+    },
+    /// The protocol was shut down for good, fixing `prices` for settlement.
+    /// See `emergency_shutdown`.
+    #[serde(rename = "emergency_shutdown")]
+    EmergencyShutdown {
+        triggered_by: Principal,
+        prices: Vec<(Principal, UsdIcp)>,
+        timestamp: u64,
+    },
+    /// Admin set (`Some`) or removed (`None`) the liquidation cap of a
+    /// collateral. Resets the window's usage. See `liquidation_caps`.
+    #[serde(rename = "set_collateral_liquidation_cap")]
+    SetCollateralLiquidationCap {
+        collateral_type: CollateralType,
+        config: Option<crate::liquidation_caps::LiquidationCapConfig>,
+    },
+    /// A liquidation asking to repay `requested` of vault `vault_id` was
+    /// clipped to `repay` so its seizure fit the `seizable` collateral the
+    /// cap allowed.
+    #[serde(rename = "liquidation_cap_bound")]
+    LiquidationCapBound {
+        vault_id: u64,
+        collateral_type: CollateralType,
+        requested: ICUSD,
+        repay: ICUSD,
+        seizable: u64,
+        timestamp: u64,
+    },
+    /// Admin set the delay queued parameter changes wait before they may be
+    /// executed; 0 turns the timelock off. See `timelock`.
+    #[serde(rename = "set_parameter_timelock")]
+    SetParameterTimelock { delay_ns: u64 },
+    /// A call to the timelocked setter `method` was queued as change `id`.
+    #[serde(rename = "parameter_change_proposed")]
+    ParameterChangeProposed {
+        id: u64,
+        method: String,
+        arg: serde_bytes::ByteBuf,
+        proposed_by: Principal,
+        activates_at: u64,
+        timestamp: u64,
+    },
+    #[serde(rename = "parameter_change_cancelled")]
+    ParameterChangeCancelled {
+        id: u64,
+        cancelled_by: Principal,
+        timestamp: u64,
+    },
+    /// Change `id`'s setter succeeded. The setter logs its own event just
+    /// before this one.
+    #[serde(rename = "parameter_change_executed")]
+    ParameterChangeExecuted {
+        id: u64,
+        executed_by: Principal,
+        timestamp: u64,
+    },
+    /// Admin replaced the principals allowed to pause single operations.
+    /// See `operation_pauses`.
+    #[serde(rename = "set_emergency_pausers")]
+    SetEmergencyPausers { principals: Vec<Principal> },
+    /// An emergency pauser or the developer paused (`paused`) or resumed
+    /// `operation`.
+    #[serde(rename = "set_operation_paused")]
+    SetOperationPaused {
+        operation: crate::operation_pauses::PausableOperation,
+        paused: bool,
+        changed_by: Principal,
+        timestamp: u64,
+    },
+    /// The startup self-test found these checks failing. See `selftest`.
+    #[serde(rename = "selftest_failed")]
+    SelftestFailed {
+        failed_checks: Vec<crate::selftest::SelftestCheck>,
+        timestamp: u64,
+    },
 
     // Phase 1b: Monad (and future foreign-chain) audit trail.
     #[serde(rename = "deposit_observed")]
@@ -985,6 +1753,7 @@ impl Event {
             Event::SetReserveRedemptionsEnabled { .. } => false,
             Event::SetIcpswapRoutingEnabled { .. } => false,
             Event::SetReserveRedemptionFee { .. } => false,
+            Event::SetReserveStable { .. } | Event::RemoveReserveStable { .. } => false,
             Event::ReserveRedemption { .. } => false,
             Event::AdminMint { .. } => false,
             Event::SetRecoveryParameters { .. } => false,
@@ -1045,6 +1814,105 @@ impl Event {
             | Event::ChainBadDebtCircuitCleared { .. } => false,
             // Phase 1a Task 11: supply invariant failure is protocol-wide.
             Event::SupplyInvariantSelfCheckFailed { .. } => false,
+            Event::ModeTransition { .. } => false,
+            Event::RebateCampaignCreated { .. }
+            | Event::RebateCampaignFunded { .. }
+            | Event::RebateCampaignClosed { .. } => false,
+            Event::BorrowFeeRebated { vault_id, .. } => vault_id == filter_vault_id,
+            Event::SessionKeyRegistered { .. } | Event::SessionKeyRevoked { .. } => false,
+            Event::SessionKeyUsed { vault_id, .. } => vault_id == filter_vault_id,
+            Event::LiquidationRebateApplied {
+                source_vault_id,
+                target_vault_id,
+                ..
+            } => source_vault_id == filter_vault_id || target_vault_id == filter_vault_id,
+            Event::SetCollateralLiquidationRebateMode { .. } => false,
+            Event::PriceDisputed { .. }
+            | Event::PriceDisputeCleared { .. }
+            | Event::SetCollateralSecondaryPriceSource { .. } => false,
+            Event::PriceRejected { .. } | Event::SetPriceDeviationBreaker { .. } => false,
+            Event::PriceOutOfBounds { .. } | Event::SetCollateralPriceBounds { .. } => false,
+            Event::PriceRejectionsCleared { .. } => false,
+            Event::RedemptionBaseRateUpdated { .. } => false,
+            Event::SetFlashMintConfig { .. } | Event::FlashMint { .. } => false,
+            Event::SetSloThresholds { .. } | Event::SloBreach { .. } => false,
+            Event::Donation { .. } => false,
+            Event::RegisterLiquidator { .. }
+            | Event::RemoveLiquidator { .. }
+            | Event::SetLiquidatorSelfRegistration { .. } => false,
+            Event::SetFeeSponsorshipConfig { .. }
+            | Event::SetFeeSponsorshipVerified { .. }
+            | Event::FundFeeSponsorship { .. }
+            | Event::ReturnFeeSponsorship { .. } => false,
+            Event::FeeSponsored { vault_id, .. } => vault_id == filter_vault_id,
+            Event::GuardAutoCleared { .. } => false,
+            Event::SetAuctionConfig { .. } => false,
+            Event::AuctionStarted { auction } => auction.vault_id == *filter_vault_id,
+            Event::AuctionBid { vault_id, .. } | Event::AuctionEnded { vault_id, .. } => {
+                vault_id == filter_vault_id
+            }
+            Event::SetRedistributionEnabled { .. } => false,
+            Event::SetShadowConfig { .. } => false,
+            Event::SetLiquidationTip { .. } => false,
+            Event::TreasuryWithdrawalApproved { .. }
+            | Event::TreasuryWithdrawalApprovalRevoked { .. }
+            | Event::TreasuryWithdrawalApprovalConsumed { .. } => false,
+            Event::SetSurplusFeeShare { .. }
+            | Event::SurplusFeeRetained { .. }
+            | Event::SurplusSwept { .. } => false,
+            Event::SurplusAbsorbedVault { vault_id, .. } => vault_id == filter_vault_id,
+            Event::BorrowPaidFromReserves { vault_id, .. } => vault_id == filter_vault_id,
+            Event::CollateralSettled { .. }
+            | Event::SettledCollateralRedeemed { .. }
+            | Event::EmergencyShutdown { .. }
+            | Event::SetCollateralLiquidationCap { .. }
+            | Event::SetParameterTimelock { .. }
+            | Event::ParameterChangeProposed { .. }
+            | Event::ParameterChangeCancelled { .. }
+            | Event::ParameterChangeExecuted { .. }
+            | Event::SetEmergencyPausers { .. }
+            | Event::SetOperationPaused { .. }
+            | Event::SelftestFailed { .. } => false,
+            Event::LiquidationCapBound { vault_id, .. } => vault_id == filter_vault_id,
+            Event::SettledVaultClaimed { vault_id, .. } => vault_id == filter_vault_id,
+            Event::VaultRedistributed { vault_id, .. } => vault_id == filter_vault_id,
+            Event::DustVaultClosed { vault_id, .. } => vault_id == filter_vault_id,
+            Event::VaultFrozen { vault_id, .. } | Event::VaultUnfrozen { vault_id, .. } => {
+                vault_id == filter_vault_id
+            }
+            Event::SetGuardianPrincipals { .. } => false,
+            Event::SetModeCompanionCanisters { .. } => false,
+            Event::LiquidatableSetChanged { added, removed, .. } => {
+                added.contains(filter_vault_id) || removed.contains(filter_vault_id)
+            }
+            Event::SetCollateralSwapRoute { .. } => false,
+            Event::VaultCollateralSwapped { vault_id, .. }
+            | Event::VaultCollateralSwapFailed { vault_id, .. } => vault_id == filter_vault_id,
+            Event::LiquidityDustSwept { .. } | Event::LiquidityDustSentToTreasury { .. } => false,
+            Event::LiquidityResidualReturned { .. } => false,
+            Event::RecoveryPoolRouting {
+                to_pool, overflow, ..
+            } => to_pool.contains(filter_vault_id) || overflow.contains(filter_vault_id),
+            Event::SetRecoveryPoolPriority { .. } => false,
+            Event::SetAutoDeleverage { vault_id, .. }
+            | Event::VaultAutoDeleveraged { vault_id, .. }
+            | Event::AutoDeleverageFailed { vault_id, .. }
+            | Event::VaultRepaidFromCollateral { vault_id, .. } => vault_id == filter_vault_id,
+            Event::SetAutoDeleverageRoute { .. } => false,
+            Event::SetPendingBackpressure { .. } => false,
+            Event::SetLogRetention { .. } => false,
+            Event::PartialRedemption { vault_id, .. } => vault_id == filter_vault_id,
+            Event::RedemptionCancelled {
+                vault_redemptions, ..
+            } => vault_redemptions
+                .iter()
+                .any(|vr| &vr.vault_id == filter_vault_id),
+            Event::SetCollateralPriceConfidence { .. } => false,
+            Event::SetCollateralMaintenanceFee { .. } => false,
+            Event::ApplyParameterBatch { .. } => false,
+            Event::SetRecoveryExitBand { .. } => false,
+            Event::SetCollateralRedemptionCap { .. } => false,
+            Event::VaultStatusChanged { vault_id, .. } => vault_id == filter_vault_id,
             // Phase 1b: vault-carrying foreign-chain events surface per-vault history.
             Event::DepositObserved { vault_id, .. }
             | Event::ChainMintSubmitted { vault_id, .. }
@@ -1067,6 +1935,29 @@ impl Event {
         }
     }
 
+    /// Returns true for the chain observability events, which replay as
+    /// no-ops because their effects are applied live by the emitting task.
+    pub fn is_chain_observation(&self) -> bool {
+        matches!(
+            self,
+            Event::DepositObserved { .. }
+                | Event::ChainMintSubmitted { .. }
+                | Event::ChainMintConfirmed { .. }
+                | Event::ChainBurnObserved { .. }
+                | Event::ChainInterestMinted { .. }
+                | Event::WithdrawalSigned { .. }
+                | Event::ChainSettlementFailed { .. }
+                | Event::ChainReorgDetected { .. }
+                | Event::ChainVaultLiquidated { .. }
+                | Event::ChainReserveCredited { .. }
+                | Event::ChainCfxClaimSettled { .. }
+                | Event::ChainPendingBurnSettled { .. }
+                | Event::ChainReserveBurnSettled { .. }
+                | Event::ChainLiquidationDeferred { .. }
+                | Event::ChainHotWalletLow { .. }
+        )
+    }
+
     /// Returns true if this is a noisy periodic event (hidden from explorer).
     pub fn is_accrue_interest(&self) -> bool {
         matches!(
@@ -1082,7 +1973,9 @@ impl Event {
             Event::OpenVault { .. } => EventTypeFilter::OpenVault,
             Event::CloseVault { .. }
             | Event::WithdrawAndCloseVault { .. }
-            | Event::VaultWithdrawnAndClosed { .. } => EventTypeFilter::CloseVault,
+            | Event::VaultWithdrawnAndClosed { .. }
+            | Event::DustVaultClosed { .. }
+            | Event::SettledVaultClaimed { .. } => EventTypeFilter::CloseVault,
             Event::AddMarginToVault { .. }
             | Event::CollateralWithdrawn { .. }
             | Event::PartialCollateralWithdrawn { .. }
@@ -1090,19 +1983,44 @@ impl Event {
             | Event::RedistributeVault { .. }
             | Event::DustForgiven { .. }
             | Event::AdminVaultCorrection { .. }
-            | Event::AdminDebtCorrection { .. } => EventTypeFilter::AdjustVault,
-            Event::BorrowFromVault { .. } => EventTypeFilter::Borrow,
-            Event::RepayToVault { .. } => EventTypeFilter::Repay,
-            Event::LiquidateVault { .. } => EventTypeFilter::Liquidation,
-            Event::PartialLiquidateVault { .. } => EventTypeFilter::PartialLiquidation,
-            Event::RedemptionOnVaults { .. } | Event::RedemptionTransfered { .. } => {
-                EventTypeFilter::Redemption
-            }
+            | Event::AdminDebtCorrection { .. }
+            | Event::VaultCollateralSwapped { .. }
+            | Event::VaultCollateralSwapFailed { .. }
+            | Event::SetAutoDeleverage { .. }
+            | Event::AutoDeleverageFailed { .. }
+            | Event::FeeSponsored { .. } => EventTypeFilter::AdjustVault,
+            Event::BorrowFromVault { .. }
+            | Event::BorrowFeeRebated { .. }
+            | Event::BorrowPaidFromReserves { .. } => EventTypeFilter::Borrow,
+            Event::RepayToVault { .. }
+            | Event::VaultAutoDeleveraged { .. }
+            | Event::VaultRepaidFromCollateral { .. }
+            | Event::SessionKeyUsed {
+                scope: SessionScope::Repay,
+                ..
+            } => EventTypeFilter::Repay,
+            Event::SessionKeyUsed {
+                scope: SessionScope::AddMargin,
+                ..
+            } => EventTypeFilter::AdjustVault,
+            Event::LiquidateVault { .. }
+            | Event::LiquidationRebateApplied { .. }
+            | Event::VaultRedistributed { .. }
+            | Event::SurplusAbsorbedVault { .. } => EventTypeFilter::Liquidation,
+            Event::PartialLiquidateVault { .. }
+            | Event::AuctionBid { .. }
+            | Event::LiquidationCapBound { .. } => EventTypeFilter::PartialLiquidation,
+            Event::RedemptionOnVaults { .. }
+            | Event::RedemptionTransfered { .. }
+            | Event::PartialRedemption { .. }
+            | Event::RedemptionCancelled { .. }
+            | Event::RedemptionBaseRateUpdated { .. }
+            | Event::SettledCollateralRedeemed { .. } => EventTypeFilter::Redemption,
             Event::ReserveRedemption { .. } => EventTypeFilter::ReserveRedemption,
             Event::ProvideLiquidity { .. } => EventTypeFilter::StabilityPoolDeposit,
-            Event::WithdrawLiquidity { .. } | Event::ClaimLiquidityReturns { .. } => {
-                EventTypeFilter::StabilityPoolWithdraw
-            }
+            Event::WithdrawLiquidity { .. }
+            | Event::ClaimLiquidityReturns { .. }
+            | Event::LiquidityDustSwept { .. } => EventTypeFilter::StabilityPoolWithdraw,
             Event::AdminMint { .. } => EventTypeFilter::AdminMint,
             Event::AdminSweepToTreasury { .. } => EventTypeFilter::AdminSweepToTreasury,
             Event::PriceUpdate { .. } => EventTypeFilter::PriceUpdate,
@@ -1160,6 +2078,8 @@ impl Event {
             Event::SetReserveRedemptionsEnabled { .. } => Some("SetReserveRedemptionsEnabled"),
             Event::SetIcpswapRoutingEnabled { .. } => Some("SetIcpswapRoutingEnabled"),
             Event::SetReserveRedemptionFee { .. } => Some("SetReserveRedemptionFee"),
+            Event::SetReserveStable { .. } => Some("SetReserveStable"),
+            Event::RemoveReserveStable { .. } => Some("RemoveReserveStable"),
             Event::SetRecoveryParameters { .. } => Some("SetRecoveryParameters"),
             Event::SetRateCurveMarkers { .. } => Some("SetRateCurveMarkers"),
             Event::SetRecoveryRateCurve { .. } => Some("SetRecoveryRateCurve"),
@@ -1189,6 +2109,14 @@ impl Event {
             }
             Event::SetCollateralMinDeposit { .. } => Some("SetCollateralMinDeposit"),
             Event::SetCollateralDisplayColor { .. } => Some("SetCollateralDisplayColor"),
+            Event::SetCollateralLiquidationRebateMode { .. } => {
+                Some("SetCollateralLiquidationRebateMode")
+            }
+            Event::SetCollateralSecondaryPriceSource { .. } => {
+                Some("SetCollateralSecondaryPriceSource")
+            }
+            Event::SetGuardianPrincipals { .. } => Some("SetGuardianPrincipals"),
+            Event::SetModeCompanionCanisters { .. } => Some("SetModeCompanionCanisters"),
             Event::SetDeficitRepaymentFraction { .. } => Some("SetDeficitRepaymentFraction"),
             Event::SetDeficitReadonlyThresholdE8s { .. } => Some("SetDeficitReadonlyThresholdE8s"),
             // Wave-10 LIQ-008
@@ -1203,8 +2131,73 @@ impl Event {
             // for its breakdown rollup.
             Event::OracleCircuitBreaker { .. } => Some("OracleCircuitBreaker"),
             Event::OracleSourceCountInsufficient { .. } => Some("OracleSourceCountInsufficient"),
+            Event::PriceDisputed { .. } => Some("PriceDisputed"),
+            Event::PriceDisputeCleared { .. } => Some("PriceDisputeCleared"),
+            Event::PriceRejected { .. } => Some("PriceRejected"),
+            Event::PriceOutOfBounds { .. } => Some("PriceOutOfBounds"),
+            Event::PriceRejectionsCleared { .. } => Some("PriceRejectionsCleared"),
+            Event::VaultFrozen { .. } => Some("VaultFrozen"),
+            Event::VaultUnfrozen { .. } => Some("VaultUnfrozen"),
+            Event::LiquidatableSetChanged { .. } => Some("LiquidatableSetChanged"),
+            Event::SetCollateralSwapRoute { .. } => Some("SetCollateralSwapRoute"),
+            Event::RecoveryPoolRouting { .. } => Some("RecoveryPoolRouting"),
+            Event::SetRecoveryPoolPriority { .. } => Some("SetRecoveryPoolPriority"),
+            Event::SetAutoDeleverageRoute { .. } => Some("SetAutoDeleverageRoute"),
+            Event::SetPendingBackpressure { .. } => Some("SetPendingBackpressure"),
+            Event::SetLogRetention { .. } => Some("SetLogRetention"),
+            Event::SetCollateralPriceConfidence { .. } => Some("SetCollateralPriceConfidence"),
+            Event::SetCollateralMaintenanceFee { .. } => Some("SetCollateralMaintenanceFee"),
+            Event::ApplyParameterBatch { .. } => Some("ApplyParameterBatch"),
+            Event::SetRecoveryExitBand { .. } => Some("SetRecoveryExitBand"),
+            Event::SetCollateralRedemptionCap { .. } => Some("SetCollateralRedemptionCap"),
+            Event::SetPriceDeviationBreaker { .. } => Some("SetPriceDeviationBreaker"),
+            Event::SetCollateralPriceBounds { .. } => Some("SetCollateralPriceBounds"),
+            Event::SetFlashMintConfig { .. } => Some("SetFlashMintConfig"),
+            Event::FlashMint { .. } => Some("FlashMint"),
+            Event::SetSloThresholds { .. } => Some("SetSloThresholds"),
+            Event::SloBreach { .. } => Some("SloBreach"),
+            Event::Donation { .. } => Some("Donation"),
+            Event::RegisterLiquidator { .. } => Some("RegisterLiquidator"),
+            Event::RemoveLiquidator { .. } => Some("RemoveLiquidator"),
+            Event::SetLiquidatorSelfRegistration { .. } => Some("SetLiquidatorSelfRegistration"),
+            Event::SetFeeSponsorshipConfig { .. } => Some("SetFeeSponsorshipConfig"),
+            Event::SetFeeSponsorshipVerified { .. } => Some("SetFeeSponsorshipVerified"),
+            Event::FundFeeSponsorship { .. } => Some("FundFeeSponsorship"),
+            Event::ReturnFeeSponsorship { .. } => Some("ReturnFeeSponsorship"),
+            Event::GuardAutoCleared { .. } => Some("GuardAutoCleared"),
+            Event::SetAuctionConfig { .. } => Some("SetAuctionConfig"),
+            Event::AuctionStarted { .. } => Some("AuctionStarted"),
+            Event::AuctionEnded { .. } => Some("AuctionEnded"),
+            Event::SetRedistributionEnabled { .. } => Some("SetRedistributionEnabled"),
+            Event::SetShadowConfig { .. } => Some("SetShadowConfig"),
+            Event::SetLiquidationTip { .. } => Some("SetLiquidationTip"),
+            Event::TreasuryWithdrawalApproved { .. } => Some("TreasuryWithdrawalApproved"),
+            Event::TreasuryWithdrawalApprovalRevoked { .. } => {
+                Some("TreasuryWithdrawalApprovalRevoked")
+            }
+            Event::TreasuryWithdrawalApprovalConsumed { .. } => {
+                Some("TreasuryWithdrawalApprovalConsumed")
+            }
+            Event::SetSurplusFeeShare { .. } => Some("SetSurplusFeeShare"),
+            Event::SurplusFeeRetained { .. } => Some("SurplusFeeRetained"),
+            Event::SurplusAbsorbedVault { .. } => Some("SurplusAbsorbedVault"),
+            Event::SurplusSwept { .. } => Some("SurplusSwept"),
+            Event::CollateralSettled { .. } => Some("CollateralSettled"),
+            Event::EmergencyShutdown { .. } => Some("EmergencyShutdown"),
+            Event::SetCollateralLiquidationCap { .. } => Some("SetCollateralLiquidationCap"),
+            Event::SetParameterTimelock { .. } => Some("SetParameterTimelock"),
+            Event::ParameterChangeProposed { .. } => Some("ParameterChangeProposed"),
+            Event::ParameterChangeCancelled { .. } => Some("ParameterChangeCancelled"),
+            Event::ParameterChangeExecuted { .. } => Some("ParameterChangeExecuted"),
+            Event::SetEmergencyPausers { .. } => Some("SetEmergencyPausers"),
+            Event::SetOperationPaused { .. } => Some("SetOperationPaused"),
+            Event::SelftestFailed { .. } => Some("SelftestFailed"),
             Event::StabilityPoolCallFailed { .. } => Some("StabilityPoolCallFailed"),
             Event::SupplyInvariantSelfCheckFailed { .. } => Some("SupplyInvariantSelfCheckFailed"),
+            Event::ModeTransition { .. } => Some("ModeTransition"),
+            Event::RebateCampaignCreated { .. } => Some("RebateCampaignCreated"),
+            Event::RebateCampaignFunded { .. } => Some("RebateCampaignFunded"),
+            Event::RebateCampaignClosed { .. } => Some("RebateCampaignClosed"),
             // Cross-chain admin/audit events (Phase 1a/1b, dev-gated).
             Event::ChainRegistered { .. } => Some("ChainRegistered"),
             Event::ChainDisabled { .. } => Some("ChainDisabled"),
@@ -1264,6 +2257,7 @@ impl Event {
             Event::SetBreakerWindowDebtCeilingE8s { timestamp, .. } => Some(*timestamp),
             // Wave-11 BOT-001
             Event::BotClaimReconciliationNeeded { timestamp, .. } => Some(*timestamp),
+            Event::AuctionStarted { auction } => Some(auction.started_at),
             // Wave-14a CDP-10 + CDP-01 + CDP-14: surface in time-range queries
             // so operators can audit oracle and SP-call failures by window.
             Event::StabilityPoolCallFailed { timestamp, .. } => Some(*timestamp),
@@ -1272,6 +2266,69 @@ impl Event {
             Event::ChainBadDebtCircuitThresholdSet { timestamp, .. } => Some(*timestamp),
             Event::ChainBadDebtCircuitTripped { timestamp, .. } => Some(*timestamp),
             Event::ChainBadDebtCircuitCleared { timestamp, .. } => Some(*timestamp),
+            Event::ModeTransition { timestamp, .. } => Some(*timestamp),
+            Event::RebateCampaignCreated { timestamp, .. }
+            | Event::RebateCampaignFunded { timestamp, .. }
+            | Event::RebateCampaignClosed { timestamp, .. }
+            | Event::BorrowFeeRebated { timestamp, .. }
+            | Event::SessionKeyRegistered { timestamp, .. }
+            | Event::SessionKeyRevoked { timestamp, .. }
+            | Event::SessionKeyUsed { timestamp, .. }
+            | Event::LiquidationRebateApplied { timestamp, .. }
+            | Event::PriceDisputed { timestamp, .. }
+            | Event::PriceDisputeCleared { timestamp, .. }
+            | Event::PriceRejected { timestamp, .. }
+            | Event::PriceOutOfBounds { timestamp, .. }
+            | Event::PriceRejectionsCleared { timestamp, .. }
+            | Event::RedemptionBaseRateUpdated { timestamp, .. }
+            | Event::FlashMint { timestamp, .. }
+            | Event::SloBreach { timestamp, .. }
+            | Event::Donation { timestamp, .. }
+            | Event::RegisterLiquidator { timestamp, .. }
+            | Event::RemoveLiquidator { timestamp, .. }
+            | Event::FundFeeSponsorship { timestamp, .. }
+            | Event::ReturnFeeSponsorship { timestamp, .. }
+            | Event::FeeSponsored { timestamp, .. }
+            | Event::GuardAutoCleared { timestamp, .. }
+            | Event::AuctionBid { timestamp, .. }
+            | Event::AuctionEnded { timestamp, .. }
+            | Event::VaultRedistributed { timestamp, .. }
+            | Event::DustVaultClosed { timestamp, .. }
+            | Event::TreasuryWithdrawalApproved { timestamp, .. }
+            | Event::TreasuryWithdrawalApprovalRevoked { timestamp, .. }
+            | Event::TreasuryWithdrawalApprovalConsumed { timestamp, .. }
+            | Event::SurplusFeeRetained { timestamp, .. }
+            | Event::SurplusAbsorbedVault { timestamp, .. }
+            | Event::SurplusSwept { timestamp, .. }
+            | Event::BorrowPaidFromReserves { timestamp, .. }
+            | Event::CollateralSettled { timestamp, .. }
+            | Event::SettledVaultClaimed { timestamp, .. }
+            | Event::SettledCollateralRedeemed { timestamp, .. }
+            | Event::EmergencyShutdown { timestamp, .. }
+            | Event::LiquidationCapBound { timestamp, .. }
+            | Event::ParameterChangeProposed { timestamp, .. }
+            | Event::ParameterChangeCancelled { timestamp, .. }
+            | Event::ParameterChangeExecuted { timestamp, .. }
+            | Event::SetOperationPaused { timestamp, .. }
+            | Event::SelftestFailed { timestamp, .. }
+            | Event::SetCollateralMaintenanceFee { timestamp, .. }
+            | Event::ApplyParameterBatch { timestamp, .. }
+            | Event::VaultFrozen { timestamp, .. }
+            | Event::VaultUnfrozen { timestamp, .. }
+            | Event::VaultStatusChanged { timestamp, .. }
+            | Event::PartialRedemption { timestamp, .. }
+            | Event::RedemptionCancelled { timestamp, .. }
+            | Event::LiquidatableSetChanged { timestamp, .. }
+            | Event::VaultCollateralSwapped { timestamp, .. }
+            | Event::VaultCollateralSwapFailed { timestamp, .. }
+            | Event::LiquidityDustSwept { timestamp, .. }
+            | Event::LiquidityDustSentToTreasury { timestamp, .. }
+            | Event::LiquidityResidualReturned { timestamp, .. }
+            | Event::RecoveryPoolRouting { timestamp, .. }
+            | Event::SetAutoDeleverage { timestamp, .. }
+            | Event::VaultAutoDeleveraged { timestamp, .. }
+            | Event::VaultRepaidFromCollateral { timestamp, .. }
+            | Event::AutoDeleverageFailed { timestamp, .. } => Some(*timestamp),
             _ => None,
         }
     }
@@ -1331,8 +2388,63 @@ impl Event {
             | Event::SetCollateralMinXrcSources {
                 collateral_type, ..
             }
+            | Event::SetCollateralSecondaryPriceSource {
+                collateral_type, ..
+            }
+            | Event::PriceDisputed {
+                collateral_type, ..
+            }
+            | Event::PriceDisputeCleared {
+                collateral_type, ..
+            }
+            | Event::PriceRejected {
+                collateral_type, ..
+            }
+            | Event::PriceOutOfBounds {
+                collateral_type, ..
+            }
+            | Event::PriceRejectionsCleared {
+                collateral_type, ..
+            }
+            | Event::SetCollateralPriceBounds {
+                collateral_type, ..
+            }
+            | Event::RedemptionBaseRateUpdated {
+                collateral_type, ..
+            }
             | Event::PriceUpdate {
                 collateral_type, ..
+            }
+            | Event::LiquidatableSetChanged {
+                collateral_type, ..
+            }
+            | Event::PartialRedemption {
+                collateral_type, ..
+            }
+            | Event::SetCollateralPriceConfidence {
+                collateral_type, ..
+            }
+            | Event::SetCollateralMaintenanceFee {
+                collateral_type, ..
+            }
+            | Event::SetCollateralRedemptionCap {
+                collateral_type, ..
+            }
+            | Event::VaultCollateralSwapped {
+                to_collateral_type: collateral_type,
+                ..
+            }
+            | Event::VaultCollateralSwapFailed {
+                collateral_type, ..
+            }
+            | Event::VaultAutoDeleveraged {
+                collateral_type, ..
+            }
+            | Event::VaultRepaidFromCollateral {
+                collateral_type, ..
+            }
+            | Event::SetAutoDeleverageRoute {
+                collateral_type, ..
             } => Some(*collateral_type),
             Event::RedemptionOnVaults {
                 collateral_type, ..
@@ -1341,6 +2453,32 @@ impl Event {
                 stable_token_ledger,
                 ..
             } => Some(*stable_token_ledger),
+            Event::BorrowPaidFromReserves { stable_ledger, .. } => Some(*stable_ledger),
+            Event::AuctionStarted { auction } => Some(auction.collateral_type),
+            Event::VaultRedistributed {
+                collateral_type, ..
+            }
+            | Event::DustVaultClosed {
+                collateral_type, ..
+            }
+            | Event::SurplusAbsorbedVault {
+                collateral_type, ..
+            }
+            | Event::CollateralSettled {
+                collateral_type, ..
+            }
+            | Event::SettledVaultClaimed {
+                collateral_type, ..
+            }
+            | Event::SettledCollateralRedeemed {
+                collateral_type, ..
+            }
+            | Event::SetCollateralLiquidationCap {
+                collateral_type, ..
+            }
+            | Event::LiquidationCapBound {
+                collateral_type, ..
+            } => Some(*collateral_type),
             Event::CloseVault { vault_id, .. }
             | Event::MarginTransfer { vault_id, .. }
             | Event::LiquidateVault { vault_id, .. }
@@ -1355,7 +2493,14 @@ impl Event {
             | Event::WithdrawAndCloseVault { vault_id, .. }
             | Event::DustForgiven { vault_id, .. }
             | Event::AdminVaultCorrection { vault_id, .. }
-            | Event::AdminDebtCorrection { vault_id, .. } => vault_lookup.get(vault_id).copied(),
+            | Event::AdminDebtCorrection { vault_id, .. }
+            | Event::VaultFrozen { vault_id, .. }
+            | Event::VaultUnfrozen { vault_id, .. }
+            | Event::VaultStatusChanged { vault_id, .. }
+            | Event::SetAutoDeleverage { vault_id, .. }
+            | Event::AutoDeleverageFailed { vault_id, .. }
+            | Event::AuctionBid { vault_id, .. }
+            | Event::AuctionEnded { vault_id, .. } => vault_lookup.get(vault_id).copied(),
             _ => None,
         }
     }
@@ -1385,6 +2530,12 @@ impl Event {
             Event::PartialLiquidateVault {
                 liquidator_payment, ..
             } => Some(liquidator_payment.0),
+            Event::AuctionBid { icusd_amount, .. } => Some(icusd_amount.0),
+            Event::VaultRedistributed { debt, .. } => Some(debt.0),
+            Event::DustVaultClosed { debt, .. } => Some(debt.0),
+            Event::SurplusAbsorbedVault { debt, .. } => Some(debt.0),
+            Event::SurplusSwept { amount, .. } => Some(*amount),
+            Event::SettledCollateralRedeemed { icusd_amount, .. } => Some(*icusd_amount),
             Event::OpenVault { vault, .. } => Some(convert(vault.collateral_amount)),
             Event::AddMarginToVault { margin_added, .. } => Some(convert(margin_added.0)),
             Event::CollateralWithdrawn { amount, .. } => Some(convert(amount.0)),
@@ -1392,6 +2543,12 @@ impl Event {
             Event::WithdrawAndCloseVault { amount, .. } => Some(convert(amount.0)),
             Event::VaultWithdrawnAndClosed { amount, .. } => Some(convert(amount.0)),
             Event::ClaimLiquidityReturns { amount, .. } => Some(convert(amount.0)),
+            Event::LiquidityDustSwept { returns, .. } => Some(convert(returns.0)),
+            Event::LiquidityDustSentToTreasury { amount, .. } => Some(convert(amount.0)),
+            Event::LiquidityResidualReturned { amount, .. } => Some(amount.0),
+            Event::VaultAutoDeleveraged { icusd_repaid, .. }
+            | Event::VaultRepaidFromCollateral { icusd_repaid, .. } => Some(icusd_repaid.0),
+            Event::RedemptionCancelled { icusd_refunded, .. } => Some(icusd_refunded.0),
             Event::AdminSweepToTreasury { amount, .. } => Some(*amount),
             _ => None,
         }
@@ -1488,7 +2645,39 @@ impl Event {
             Event::ProvideLiquidity { caller, .. } => caller == p,
             Event::WithdrawLiquidity { caller, .. } => caller == p,
             Event::ClaimLiquidityReturns { caller, .. } => caller == p,
+            Event::LiquidityDustSwept { caller, .. }
+            | Event::LiquidityResidualReturned { caller, .. } => caller == p,
+            Event::SetAutoDeleverage { owner, .. }
+            | Event::VaultRepaidFromCollateral { owner, .. } => owner == p,
+            Event::RedemptionCancelled { owner, .. } => owner == p,
             Event::AdminMint { to, .. } => to == p,
+            Event::Donation { donor, .. } => donor == p,
+            Event::RegisterLiquidator { liquidator, .. }
+            | Event::RemoveLiquidator { liquidator, .. } => liquidator == p,
+            Event::FundFeeSponsorship { funder, .. } => funder == p,
+            Event::SetFeeSponsorshipVerified { user, .. } | Event::FeeSponsored { user, .. } => {
+                user == p
+            }
+            Event::GuardAutoCleared { principal, .. } => principal == p,
+            Event::AuctionBid { bidder, .. } => bidder == p,
+            Event::VaultRedistributed { owner, .. } => owner == p,
+            Event::DustVaultClosed { owner, .. } => owner == p,
+            Event::BorrowPaidFromReserves { owner, .. } => owner == p,
+            Event::CollateralSettled { settled_by, .. } => settled_by == p,
+            Event::SettledVaultClaimed { owner, .. } => owner == p,
+            Event::SettledCollateralRedeemed { redeemer, .. } => redeemer == p,
+            Event::EmergencyShutdown { triggered_by, .. } => triggered_by == p,
+            Event::ParameterChangeProposed { proposed_by, .. } => proposed_by == p,
+            Event::ParameterChangeCancelled { cancelled_by, .. } => cancelled_by == p,
+            Event::ParameterChangeExecuted { executed_by, .. } => executed_by == p,
+            Event::SetOperationPaused { changed_by, .. } => changed_by == p,
+            Event::TreasuryWithdrawalApproved { approved_by, .. } => approved_by == p,
+            Event::TreasuryWithdrawalApprovalRevoked { revoked_by, .. } => revoked_by == p,
+            Event::FlashMint {
+                initiator,
+                callback,
+                ..
+            } => initiator == p || callback == p,
             _ => false,
         }
     }
@@ -1503,7 +2692,7 @@ pub enum ReplayLogError {
 }
 
 pub fn replay(mut events: impl Iterator<Item = Event>) -> Result<State, ReplayLogError> {
-    let mut state = match events.next() {
+    let state = match events.next() {
         Some(Event::Init(args)) => State::from(args),
         Some(evt) => {
             return Err(ReplayLogError::InconsistentLog(format!(
@@ -1513,15 +2702,45 @@ pub fn replay(mut events: impl Iterator<Item = Event>) -> Result<State, ReplayLo
         }
         None => return Err(ReplayLogError::EmptyLog),
     };
-    let mut vault_id = 0;
-    for event in events {
+    let mut opened_vaults = 0;
+    // Init is event 0.
+    let mut state = replay_onto(
+        state,
+        1,
+        events.inspect(|event| {
+            if matches!(event, Event::OpenVault { .. }) {
+                opened_vaults += 1;
+            }
+        }),
+    );
+    state.next_available_vault_id = opened_vaults;
+    Ok(state)
+}
+
+/// Applies `events` to `state`, the first of them being event log index
+/// `first_index`. Used on its own to bring a restored state checkpoint up
+/// to the end of the log (see `storage::state_checkpoint`).
+pub fn replay_onto(
+    mut state: State,
+    first_index: u64,
+    events: impl Iterator<Item = Event>,
+) -> State {
+    for (offset, event) in events.enumerate() {
+        let event_index = first_index + offset as u64;
+        let timestamp = event.timestamp_ns().unwrap_or(0);
+        crate::parameter_journal::journal_event(&mut state, &event, event_index, None, timestamp);
+        if let Some(vault_id) = crate::auto_deleverage::activity_vault(&event) {
+            crate::auto_deleverage::note_activity(&mut state, vault_id, timestamp);
+        }
+        crate::liquidation_caps::note_liquidation(&mut state, &event);
         match event {
             Event::OpenVault {
                 mut vault,
                 block_index: _,
                 ..
             } => {
-                vault_id += 1;
+                state.next_available_vault_id =
+                    state.next_available_vault_id.max(vault.vault_id + 1);
                 // Fix up legacy events that lack collateral_type (serde default = anonymous)
                 if vault.collateral_type == Principal::anonymous() {
                     vault.collateral_type = state.icp_ledger_principal;
@@ -1584,9 +2803,22 @@ pub fn replay(mut events: impl Iterator<Item = Event>) -> Result<State, ReplayLo
             Event::BorrowFromVault {
                 vault_id,
                 borrowed_amount,
+                fee_amount,
+                block_index,
+                timestamp,
+                collateral_price,
                 ..
             } => {
                 // Fee was phantom (never minted) in old events; now routed to treasury in async caller.
+                crate::borrow_records::push_borrow_record(
+                    &mut state,
+                    vault_id,
+                    borrowed_amount,
+                    fee_amount,
+                    collateral_price,
+                    block_index,
+                    timestamp.unwrap_or(0),
+                );
                 state.borrow_from_vault(vault_id, borrowed_amount)
             }
             Event::RedemptionOnVaults {
@@ -1596,8 +2828,8 @@ pub fn replay(mut events: impl Iterator<Item = Event>) -> Result<State, ReplayLo
                 fee_amount,
                 icusd_block_index,
                 collateral_type,
+                timestamp,
                 ref vault_redemptions,
-                ..
             } => {
                 state.provide_liquidity(fee_amount, state.developer_principal);
                 let redeem_ct = collateral_type
@@ -1609,28 +2841,67 @@ pub fn replay(mut events: impl Iterator<Item = Event>) -> Result<State, ReplayLo
                 // cannot reconstruct. The consumed-based margin mirrors the
                 // live payout clamp. Pre-Wave-9 events (no stored outcomes)
                 // keep the legacy re-run + full-claim margin.
-                let margin: ICP = match vault_redemptions {
+                let (consumed, record) = match vault_redemptions {
                     Some(vrs) => {
                         state.apply_vault_redemptions(vrs);
-                        let consumed: u64 = vrs.iter().map(|v| v.icusd_redeemed_e8s).sum();
-                        ICUSD::from(consumed) / current_icp_rate
+                        let consumed =
+                            ICUSD::from(vrs.iter().map(|v| v.icusd_redeemed_e8s).sum::<u64>());
+                        let shortfall = state
+                            .get_collateral_config(&redeem_ct)
+                            .map(|c| {
+                                compute_redemption_shortfall(
+                                    consumed,
+                                    vrs,
+                                    current_icp_rate.0,
+                                    c.decimals,
+                                )
+                            })
+                            .unwrap_or(ICUSD::new(0));
+                        let record = crate::redemption_cancel::PendingRedemptionRecord {
+                            vault_redemptions: vrs.clone(),
+                            shortfall_e8s: shortfall.to_u64(),
+                            redeemed_at_ns: timestamp.unwrap_or(0),
+                        };
+                        (consumed, Some(record))
                     }
                     None => {
                         state.redeem_on_vaults(icusd_amount, current_icp_rate, &redeem_ct);
-                        icusd_amount / current_icp_rate
+                        (icusd_amount, None)
                     }
                 };
+                crate::redemption_caps::note_redeemed(
+                    &mut state,
+                    &redeem_ct,
+                    consumed.to_u64(),
+                    timestamp.unwrap_or(0),
+                );
+                let margin: ICP = consumed / current_icp_rate;
                 if margin.to_u64() > 0 {
                     let nonce = state.next_op_nonce();
                     state
                         .pending_redemption_transfer
-                        .insert(icusd_block_index, PendingMarginTransfer { owner, margin, collateral_type: redeem_ct, retry_count: 0, op_nonce: nonce });
+                        .insert(icusd_block_index, PendingMarginTransfer { owner, margin, collateral_type: redeem_ct, retry_count: 0, op_nonce: nonce, trace_id: None });
+                    if let Some(record) = record {
+                        state.pending_redemption_records.insert(icusd_block_index, record);
+                    }
                 }
             }
             Event::RedemptionTransfered {
                 icusd_block_index, ..
             } => {
                 state.pending_redemption_transfer.remove(&icusd_block_index);
+                state.pending_redemption_records.remove(&icusd_block_index);
+            }
+            Event::RedemptionCancelled {
+                icusd_block_index,
+                ref vault_redemptions,
+                ..
+            } => {
+                crate::redemption_cancel::apply_cancel_pending_redemption(
+                    &mut state,
+                    icusd_block_index,
+                    vault_redemptions,
+                );
             }
             Event::AddMarginToVault {
                 vault_id,
@@ -1842,6 +3113,12 @@ pub fn replay(mut events: impl Iterator<Item = Event>) -> Result<State, ReplayLo
                     state.reserve_redemption_fee = Ratio::from(dec);
                 }
             },
+            Event::SetReserveStable { stable } => {
+                crate::reserve_stables::apply_set(&mut state, stable);
+            },
+            Event::RemoveReserveStable { ledger } => {
+                crate::reserve_stables::apply_remove(&mut state, ledger);
+            },
             Event::ReserveRedemption { .. } => {
                 // Reserve redemptions don't change in-memory state during replay;
                 // the actual token transfers are async and not replayed.
@@ -1998,8 +3275,15 @@ pub fn replay(mut events: impl Iterator<Item = Event>) -> Result<State, ReplayLo
             Event::SetAmm1PoolId { pool_id } => {
                 state.amm1_pool_id = Some(pool_id);
             },
-            Event::PriceUpdate { .. } => {
-                // Price history only; no state mutation needed during replay.
+            Event::PriceUpdate { collateral_type, price, timestamp } => {
+                // An accepted price is cached, releases a price-deviation
+                // hold on its collateral and ends its run of rejections, as
+                // on the live path.
+                if let Ok(price) = price.parse::<Decimal>() {
+                    state.apply_price_update(collateral_type, price, timestamp);
+                }
+                state.price_deviation_holds.remove(&collateral_type);
+                state.price_rejections.remove(&collateral_type);
             },
             Event::SetCollateralLiquidationRatio { collateral_type, liquidation_ratio } => {
                 if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
@@ -2076,11 +3360,14 @@ pub fn replay(mut events: impl Iterator<Item = Event>) -> Result<State, ReplayLo
                 // in the replay, which is deterministic given the event order.
                 let _ = state.check_deficit_readonly_latch();
             },
-            Event::DeficitRepaid { amount, .. } => {
+            Event::DeficitRepaid { amount, source, .. } => {
                 state.protocol_deficit_icusd =
                     state.protocol_deficit_icusd.saturating_sub(amount);
                 state.total_deficit_repaid_icusd =
                     state.total_deficit_repaid_icusd + amount;
+                if source == FeeSource::SurplusBuffer {
+                    crate::donations::apply_buffer_absorption(&mut state, amount.to_u64());
+                }
             },
             Event::SetDeficitRepaymentFraction { fraction, .. } => {
                 state.deficit_repayment_fraction = fraction;
@@ -2137,203 +3424,1840 @@ pub fn replay(mut events: impl Iterator<Item = Event>) -> Result<State, ReplayLo
             // Phase 1a Task 11: informational audit trail; state mutation
             // (invariant_halted + mode flip) happens live in the timer tick.
             Event::SupplyInvariantSelfCheckFailed { .. } => {},
-            // Phase 1b: observability-only events; the actual state mutations
-            // happen in their emitting tasks, not on replay.
-            Event::DepositObserved { .. }
-            | Event::ChainMintSubmitted { .. }
-            | Event::ChainMintConfirmed { .. }
-            | Event::ChainBurnObserved { .. }
-            | Event::ChainInterestMinted { .. }
-            | Event::WithdrawalSigned { .. }
-            | Event::ChainSettlementFailed { .. }
-            | Event::ChainReorgDetected { .. }
-            // Increment 1: chains-liquidation events are observability-only; the
-            // reserve/debt/supply mutations happen live in the bot/SP confirm
-            // paths (Increments 2-4), not on replay.
-            | Event::ChainVaultLiquidated { .. }
-            | Event::ChainReserveCredited { .. }
-            | Event::ChainCfxClaimSettled { .. }
-            | Event::ChainPendingBurnSettled { .. }
-            | Event::ChainReserveBurnSettled { .. }
-            | Event::ChainLiquidationDeferred { .. }
-            | Event::ChainHotWalletLow { .. } => {},
-        }
-    }
-    state.next_available_vault_id = vault_id;
-    Ok(state)
-}
-
-/// Helper: current canister time in nanoseconds.
-fn now() -> u64 {
-    ic_cdk::api::time()
-}
-
-pub fn record_liquidate_vault(
-    state: &mut State,
-    vault_id: u64,
-    mode: Mode,
-    collateral_price: UsdIcp,
-) {
-    record_event(&Event::LiquidateVault {
-        vault_id,
-        mode,
-        icp_rate: collateral_price,
-        liquidator: None,
-        timestamp: Some(now()),
-    });
-    let _ = state.liquidate_vault(vault_id, mode, collateral_price);
-}
-
-pub fn record_redistribute_vault(state: &mut State, vault_id: u64) {
-    record_event(&Event::RedistributeVault {
-        vault_id,
-        timestamp: Some(now()),
-    });
-    state.redistribute_vault(vault_id);
-}
-
-pub fn record_provide_liquidity(
-    state: &mut State,
-    amount: ICUSD,
-    caller: Principal,
-    block_index: u64,
-) {
-    record_event(&Event::ProvideLiquidity {
-        amount,
-        block_index,
-        caller,
-        timestamp: Some(now()),
-    });
-    state.provide_liquidity(amount, caller);
-}
-
-pub fn record_withdraw_liquidity(
-    state: &mut State,
-    amount: ICUSD,
-    caller: Principal,
-    block_index: u64,
-) {
-    record_event(&Event::WithdrawLiquidity {
-        amount,
-        block_index,
-        caller,
-        timestamp: Some(now()),
-    });
-    state.withdraw_liquidity(amount, caller);
-}
-
-pub fn record_claim_liquidity_returns(
-    state: &mut State,
-    amount: ICP,
-    caller: Principal,
-    block_index: u64,
-) {
-    record_event(&Event::ClaimLiquidityReturns {
-        amount,
-        block_index,
-        caller,
-        timestamp: Some(now()),
-    });
-    state.claim_liquidity_returns(amount, caller);
-}
-
-pub fn record_open_vault(state: &mut State, vault: Vault, block_index: u64) {
-    record_event(&Event::OpenVault {
-        vault: vault.clone(),
-        block_index,
-        timestamp: Some(now()),
-    });
-    state.open_vault(vault);
-}
-
-pub fn record_close_vault(state: &mut State, vault_id: u64, block_index: Option<u64>) {
-    record_event(&Event::CloseVault {
-        vault_id,
-        block_index,
-        timestamp: Some(now()),
-    });
-    state.close_vault(vault_id);
-}
-
-pub fn record_margin_transfer(
-    state: &mut State,
-    vault_id: u64,
-    owner: Principal,
-    block_index: u64,
-) {
-    record_event(&Event::MarginTransfer {
-        vault_id,
-        block_index,
-        timestamp: Some(now()),
-    });
-    state.pending_margin_transfers.remove(&(vault_id, owner));
-}
-
-// ─── Wave-8e LIQ-005: deficit-account event recorders ───
-
-/// Record a `DeficitAccrued` event and increment `protocol_deficit_icusd`.
-/// Caller is responsible for invoking `state.check_deficit_readonly_latch()`
-/// afterwards if the latch threshold is configured.
-///
-/// Wave-9 RED-002: takes a `DeficitSource` so liquidation and redemption
-/// deficits are distinguishable in the event log. The legacy `vault_id`
-/// field on the event is preserved for back-compat and continues to be
-/// populated for liquidation; redemption deficits set `vault_id = 0` on
-/// the parent event because the cr-walk touches multiple vaults.
-pub fn record_deficit_accrued(
-    state: &mut State,
-    source: DeficitSource,
-    amount: ICUSD,
-    timestamp: u64,
-) {
-    state.accrue_deficit_shortfall(amount);
-    let vault_id = match source {
-        DeficitSource::Liquidation { vault_id } => vault_id,
-        DeficitSource::Redemption { .. } => 0,
-    };
-    record_event(&Event::DeficitAccrued {
-        vault_id,
-        amount,
-        new_deficit: state.protocol_deficit_icusd,
-        timestamp,
-        source: Some(source),
-    });
-}
-
-/// Record a `DeficitRepaid` event and apply the repayment to state.
-pub fn record_deficit_repaid(
-    state: &mut State,
-    amount: ICUSD,
-    source: FeeSource,
-    anchor_block_index: Option<u64>,
-    timestamp: u64,
-) {
-    state.apply_deficit_repayment(amount);
-    record_event(&Event::DeficitRepaid {
-        amount,
-        source,
-        remaining_deficit: state.protocol_deficit_icusd,
-        anchor_block_index,
-        timestamp,
-    });
-}
-
-/// Admin: tune the per-fee fraction routed to deficit repayment.
+            // The recorded transition is authoritative: admin overrides in
+            // particular are not re-derivable from any other event.
+            Event::ModeTransition { to, reason, .. } => {
+                state.mode = to;
+                if reason == ModeTransitionReason::AdminOverride {
+                    state.manual_mode_override = to == Mode::Recovery;
+                }
+            }
+            Event::RebateCampaignCreated {
+                campaign_id, arg, ..
+            } => crate::campaigns::apply_create(&mut state, campaign_id, arg),
+            Event::RebateCampaignFunded {
+                campaign_id,
+                amount_e8s,
+                ..
+            } => {
+                let _ = crate::campaigns::apply_fund(&mut state, campaign_id, amount_e8s);
+            }
+            Event::RebateCampaignClosed {
+                campaign_id,
+                timestamp,
+                ..
+            } => {
+                let _ = crate::campaigns::apply_close(&mut state, campaign_id, timestamp);
+            }
+            Event::BorrowFeeRebated {
+                campaign_id,
+                vault_id,
+                rebate_e8s,
+                ..
+            } => {
+                crate::borrow_records::apply_fee_rebate(&mut state, vault_id, rebate_e8s);
+                crate::campaigns::apply_rebate_paid(
+                    &mut state,
+                    campaign_id,
+                    ICUSD::new(rebate_e8s),
+                    false,
+                );
+            }
+            Event::SessionKeyRegistered {
+                owner,
+                arg,
+                timestamp,
+            } => crate::session_keys::apply_register(&mut state, owner, arg, timestamp),
+            Event::SessionKeyRevoked {
+                owner,
+                session_principal,
+                ..
+            } => {
+                let _ = crate::session_keys::apply_revoke(&mut state, owner, session_principal);
+            }
+            Event::SessionKeyUsed {
+                session_principal,
+                scope,
+                amount_e8s,
+                ..
+            } => crate::session_keys::apply_session_use(
+                &mut state,
+                session_principal,
+                scope,
+                amount_e8s,
+                false,
+            ),
+            Event::LiquidationRebateApplied {
+                target_vault_id,
+                collateral_amount,
+                ..
+            } => {
+                if state.vault_id_to_vaults.contains_key(&target_vault_id) {
+                    state.add_margin_to_vault(target_vault_id, ICP::from(collateral_amount));
+                }
+            }
+            Event::SetCollateralLiquidationRebateMode {
+                collateral_type,
+                mode,
+            } => {
+                if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
+                    config.liquidation_rebate_mode = mode;
+                }
+            }
+            Event::PriceDisputed {
+                collateral_type, ..
+            } => {
+                if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
+                    config.price_disputed = true;
+                }
+            }
+            Event::PriceDisputeCleared {
+                collateral_type, ..
+            } => {
+                if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
+                    config.price_disputed = false;
+                }
+            }
+            // The mode change, if any, replays from its own `ModeTransition`.
+            Event::PriceRejected {
+                collateral_type,
+                recovery,
+                ..
+            } => {
+                if recovery {
+                    state.price_deviation_holds.insert(collateral_type);
+                }
+                crate::xrc::note_price_rejection(&mut state, event);
+            }
+            Event::SetPriceDeviationBreaker { config } => {
+                crate::xrc::apply_price_deviation_breaker(&mut state, config);
+            }
+            // The old price was kept; only the rejection is counted.
+            Event::PriceOutOfBounds { .. } => {
+                crate::xrc::note_price_rejection(&mut state, event);
+            }
+            // The `PriceUpdate` that follows clears the counts.
+            Event::PriceRejectionsCleared { .. } => {}
+            Event::SetCollateralPriceBounds {
+                collateral_type,
+                bounds,
+            } => {
+                crate::xrc::apply_price_bounds(&mut state, collateral_type, bounds);
+            }
+            Event::SetFlashMintConfig { config } => {
+                crate::flash_mint::apply_set_config(&mut state, config);
+            }
+            Event::SetSloThresholds {
+                endpoint,
+                thresholds,
+            } => {
+                crate::slo::apply_set_thresholds(&mut state, endpoint, thresholds);
+            }
+            // Informational; the stats it came from are not replayed.
+            Event::SloBreach { .. } => {}
+            Event::Donation {
+                donor,
+                ledger,
+                amount,
+                deficit_repaid,
+                ..
+            } => {
+                crate::donations::apply_donation(&mut state, donor, ledger, amount, deficit_repaid);
+            }
+            Event::RegisterLiquidator {
+                liquidator,
+                name,
+                self_registered,
+                timestamp,
+            } => {
+                crate::liquidators::apply_register(
+                    &mut state,
+                    liquidator,
+                    name,
+                    self_registered,
+                    timestamp,
+                );
+            }
+            Event::RemoveLiquidator { liquidator, .. } => {
+                crate::liquidators::apply_remove(&mut state, &liquidator);
+            }
+            Event::SetLiquidatorSelfRegistration { enabled } => {
+                state.liquidator_self_registration = enabled;
+            }
+            Event::SetFeeSponsorshipConfig { config } => {
+                state.fee_sponsorship_config = Some(config);
+            }
+            Event::SetFeeSponsorshipVerified { user, verified } => {
+                if verified {
+                    state.fee_sponsorship_verified.insert(user);
+                } else {
+                    state.fee_sponsorship_verified.remove(&user);
+                }
+            }
+            Event::FundFeeSponsorship { ledger, amount, .. } => {
+                crate::fee_sponsorship::apply_funding(&mut state, ledger, amount);
+            }
+            Event::ReturnFeeSponsorship {
+                ledger,
+                amount,
+                fee,
+                ..
+            } => {
+                // The live path checked the balance before sending.
+                let _ = crate::fee_sponsorship::take_for_return(&mut state, ledger, amount, fee);
+            }
+            Event::FeeSponsored {
+                user,
+                ledger,
+                fee,
+                timestamp,
+                ..
+            } => {
+                crate::fee_sponsorship::apply_sponsored(&mut state, user, ledger, fee, timestamp);
+            }
+            // Guards and their metrics are not replayed.
+            Event::GuardAutoCleared { .. } => {}
+            Event::SetAuctionConfig { config } => {
+                state.auction_config = Some(config);
+            }
+            Event::AuctionStarted { auction } => {
+                crate::auction::apply_start(&mut state, auction);
+            }
+            Event::AuctionBid {
+                auction_id,
+                vault_id,
+                icusd_amount,
+                collateral_amount,
+                debt_written_off,
+                ..
+            } => {
+                crate::auction::apply_bid(
+                    &mut state,
+                    auction_id,
+                    vault_id,
+                    icusd_amount,
+                    collateral_amount,
+                    debt_written_off,
+                );
+            }
+            Event::AuctionEnded {
+                auction_id,
+                reason,
+                timestamp,
+                ..
+            } => {
+                crate::auction::apply_end(&mut state, auction_id, reason, timestamp);
+            }
+            Event::SetRedistributionEnabled { enabled } => {
+                state.redistribution_enabled = enabled;
+            }
+            Event::VaultRedistributed { vault_id, .. } => {
+                // Only recorded after the live apply's checks passed.
+                let _ = crate::redistribution::apply_redistribute(&mut state, vault_id);
+            }
+            Event::DustVaultClosed { vault_id, .. } => {
+                crate::dust_vaults::apply_close(&mut state, vault_id);
+            }
+            Event::SetShadowConfig { config } => {
+                state.shadow_config = config;
+            }
+            Event::SetLiquidationTip { tip_e8s } => {
+                state.liquidation_tip_e8s = tip_e8s;
+            }
+            Event::TreasuryWithdrawalApproved {
+                request,
+                approved_by,
+                expires_at_ns,
+                timestamp,
+            } => crate::treasury_approvals::apply_approve(
+                &mut state,
+                request,
+                approved_by,
+                expires_at_ns,
+                timestamp,
+            ),
+            Event::TreasuryWithdrawalApprovalRevoked {
+                request_id,
+                timestamp,
+                ..
+            } => {
+                let _ = crate::treasury_approvals::apply_revoke(&mut state, request_id, timestamp);
+            }
+            Event::TreasuryWithdrawalApprovalConsumed {
+                request_id,
+                timestamp,
+            } => crate::treasury_approvals::apply_consume(&mut state, request_id, timestamp),
+            Event::SetSurplusFeeShare { share } => {
+                state.surplus_fee_share = share;
+            }
+            // A deficit the fee repaid replays from its own `DeficitRepaid`.
+            Event::SurplusFeeRetained { ledger, amount, .. } => {
+                crate::surplus::apply_fee_retained(&mut state, ledger, amount)
+            }
+            Event::SurplusAbsorbedVault {
+                vault_id,
+                collateral_amount,
+                debt,
+                ..
+            } => {
+                crate::surplus::apply_absorption(&mut state, vault_id, debt, collateral_amount);
+            }
+            // The mint is ledger-side; the buffer was debited before it.
+            Event::SurplusSwept { amount, .. } => {
+                let _ = crate::surplus::take_for_sweep(&mut state, amount);
+            }
+            // The debt replays from its `BorrowFromVault`, the fee routing
+            // from its own `DeficitRepaid`.
+            Event::BorrowPaidFromReserves { .. } => {}
+            Event::CollateralSettled {
+                collateral_type,
+                price,
+                timestamp,
+                ..
+            } => {
+                crate::collateral_settlement::apply_settle(
+                    &mut state,
+                    collateral_type,
+                    price,
+                    timestamp,
+                );
+            }
+            // The payouts are queue entries, carried by the snapshot.
+            Event::SettledVaultClaimed { vault_id, .. } => {
+                crate::collateral_settlement::apply_claim(&mut state, vault_id);
+            }
+            Event::SettledCollateralRedeemed {
+                collateral_type,
+                icusd_amount,
+                collateral_amount,
+                ..
+            } => crate::collateral_settlement::take_redemption(
+                &mut state,
+                &collateral_type,
+                icusd_amount,
+                collateral_amount,
+            ),
+            Event::EmergencyShutdown {
+                triggered_by,
+                prices,
+                timestamp,
+            } => crate::emergency_shutdown::apply_shutdown(
+                &mut state,
+                triggered_by,
+                &prices,
+                timestamp,
+            ),
+            Event::SetCollateralLiquidationCap {
+                collateral_type,
+                config,
+            } => crate::liquidation_caps::apply_set_config(&mut state, collateral_type, config),
+            // The seizure it clipped is counted from the liquidation's own
+            // event.
+            Event::LiquidationCapBound { .. } => {}
+            Event::SetParameterTimelock { delay_ns } => {
+                state.parameter_timelock_ns = delay_ns;
+            }
+            Event::ParameterChangeProposed {
+                id,
+                method,
+                arg,
+                proposed_by,
+                activates_at,
+                timestamp,
+            } => crate::timelock::apply_proposed(
+                &mut state,
+                crate::timelock::PendingParameterChange {
+                    id,
+                    method,
+                    arg,
+                    proposed_by,
+                    proposed_at: timestamp,
+                    activates_at,
+                },
+            ),
+            Event::ParameterChangeCancelled { id, .. } | Event::ParameterChangeExecuted { id, .. } => {
+                crate::timelock::apply_removed(&mut state, id)
+            }
+            Event::SetEmergencyPausers { principals } => {
+                state.emergency_pausers = principals.into_iter().collect();
+            }
+            Event::SetOperationPaused {
+                operation, paused, ..
+            } => crate::operation_pauses::apply_set_paused(&mut state, operation, paused),
+            Event::SelftestFailed { .. } => {}
+            // The mint, burn and fee are ledger-side; a default's deficit is
+            // replayed from its own `DeficitAccrued`.
+            Event::FlashMint {
+                callback,
+                repay_block_index,
+                ..
+            } => {
+                if repay_block_index.is_none() {
+                    crate::flash_mint::apply_default(&mut state, &callback);
+                }
+            }
+            Event::RedemptionBaseRateUpdated {
+                collateral_type,
+                base_rate,
+                timestamp,
+            } => {
+                if let Ok(dec) = base_rate.parse::<Decimal>() {
+                    crate::record_per_collateral_redemption_fee(
+                        &mut state,
+                        &collateral_type,
+                        Ratio::from(dec),
+                        timestamp,
+                    );
+                }
+            }
+            Event::SetCollateralSecondaryPriceSource {
+                collateral_type,
+                source,
+            } => {
+                state.set_secondary_price_source(&collateral_type, source);
+            }
+            Event::VaultFrozen {
+                vault_id,
+                frozen_by,
+                reason,
+                expires_at_ns,
+                timestamp,
+            } => crate::vault_freeze::apply_freeze(
+                &mut state,
+                vault_id,
+                frozen_by,
+                reason,
+                expires_at_ns,
+                timestamp,
+            ),
+            Event::VaultUnfrozen {
+                vault_id,
+                timestamp,
+                ..
+            } => {
+                let _ = crate::vault_freeze::apply_unfreeze(&mut state, vault_id, timestamp);
+            }
+            Event::SetGuardianPrincipals { principals } => {
+                state.guardian_principals = principals.into_iter().collect();
+            }
+            Event::SetModeCompanionCanisters { canisters } => {
+                crate::mode_propagation::apply_set_companions(&mut state, canisters);
+            }
+            // Derived from prices; the set is refreshed live, not replayed.
+            Event::LiquidatableSetChanged { .. } => {}
+            Event::SetCollateralSwapRoute {
+                from_collateral_type,
+                to_collateral_type,
+                route,
+            } => crate::collateral_swap::apply_set_route(
+                &mut state,
+                from_collateral_type,
+                to_collateral_type,
+                route,
+            ),
+            Event::VaultCollateralSwapped {
+                vault_id,
+                to_collateral_type,
+                amount_out,
+                ..
+            } => crate::collateral_swap::apply_collateral_swap(
+                &mut state,
+                vault_id,
+                to_collateral_type,
+                amount_out,
+            ),
+            Event::VaultCollateralSwapFailed { vault_id, fees, .. } => {
+                crate::collateral_swap::apply_collateral_swap_failed(&mut state, vault_id, fees)
+            }
+            Event::LiquidityDustSwept {
+                caller, returns, ..
+            } => crate::liquidity_pool::apply_dust_sweep(&mut state, caller, returns),
+            Event::LiquidityDustSentToTreasury { amount, .. } => {
+                state.liquidity_dust_swept = state.liquidity_dust_swept.saturating_sub(amount);
+            }
+            Event::LiquidityResidualReturned { caller, amount, .. } => {
+                state.withdraw_liquidity(amount, caller);
+            }
+            Event::RecoveryPoolRouting {
+                to_pool,
+                reserved_until_ns,
+                timestamp,
+                ..
+            } => crate::pool_priority::apply_routing(
+                &mut state,
+                &to_pool,
+                reserved_until_ns,
+                timestamp,
+            ),
+            Event::SetRecoveryPoolPriority { enabled, window_ns } => {
+                crate::pool_priority::apply_set_config(
+                    &mut state,
+                    crate::pool_priority::PoolPriorityConfig { enabled, window_ns },
+                )
+            }
+            Event::SetAutoDeleverage {
+                vault_id,
+                config,
+                timestamp,
+                ..
+            } => crate::auto_deleverage::apply_set(&mut state, vault_id, config, timestamp),
+            Event::SetAutoDeleverageRoute {
+                collateral_type,
+                route,
+            } => crate::auto_deleverage::apply_set_route(&mut state, collateral_type, route),
+            Event::VaultAutoDeleveraged {
+                vault_id,
+                collateral_sold,
+                icusd_repaid,
+                timestamp,
+                ..
+            } => {
+                crate::auto_deleverage::apply_deleverage(
+                    &mut state,
+                    vault_id,
+                    collateral_sold,
+                    icusd_repaid,
+                    timestamp,
+                );
+            }
+            Event::AutoDeleverageFailed {
+                vault_id,
+                timestamp,
+                ..
+            } => crate::auto_deleverage::apply_failure(&mut state, vault_id, timestamp),
+            Event::SetPendingBackpressure {
+                max_pending_collateral_transfers,
+                max_pending_redemption_transfers,
+            } => crate::pending_backpressure::apply_set_config(
+                &mut state,
+                crate::pending_backpressure::PendingBackpressureConfig {
+                    max_pending_collateral_transfers,
+                    max_pending_redemption_transfers,
+                },
+            ),
+            Event::VaultStatusChanged { vault_id, to, .. } => {
+                crate::vault_status::apply_transition(&mut state, vault_id, to)
+            }
+            Event::SetLogRetention {
+                critical_capacity,
+                info_capacity,
+                debug_capacity,
+                trace_xrc_capacity,
+                persisted_critical_capacity,
+            } => {
+                state.log_retention = crate::logs::LogRetentionConfig {
+                    critical_capacity,
+                    info_capacity,
+                    debug_capacity,
+                    trace_xrc_capacity,
+                    persisted_critical_capacity,
+                };
+            }
+            // Applied by the `RedemptionOnVaults` recorded just before it.
+            Event::PartialRedemption { .. } => {}
+            Event::SetCollateralPriceConfidence { collateral_type, k } => {
+                let k = k
+                    .as_ref()
+                    .and_then(|s| s.parse::<Decimal>().ok())
+                    .map(Ratio::from);
+                state.set_price_confidence_k(&collateral_type, k);
+            }
+            Event::SetCollateralMaintenanceFee {
+                collateral_type,
+                maintenance_fee_apr,
+                timestamp,
+            } => {
+                let fee = maintenance_fee_apr
+                    .as_ref()
+                    .and_then(|s| s.parse::<Decimal>().ok())
+                    .map(Ratio::from);
+                state.set_maintenance_fee_apr(&collateral_type, fee, timestamp);
+            }
+            Event::ApplyParameterBatch { changes, .. } => {
+                crate::parameter_batch::apply(&mut state, &changes);
+            }
+            Event::SetRecoveryExitBand { band } => {
+                state.recovery_exit_band = band
+                    .as_ref()
+                    .and_then(|s| s.parse::<Decimal>().ok())
+                    .map(Ratio::from);
+            }
+            Event::SetCollateralRedemptionCap {
+                collateral_type,
+                config,
+            } => {
+                crate::redemption_caps::apply_set_config(&mut state, collateral_type, config);
+            }
+            Event::VaultRepaidFromCollateral {
+                vault_id,
+                collateral_sold,
+                icusd_repaid,
+                ..
+            } => {
+                crate::repay_from_collateral::apply_repay_from_collateral(
+                    &mut state,
+                    vault_id,
+                    collateral_sold,
+                    icusd_repaid,
+                );
+            }
+            // Phase 1b: observability-only events; the actual state mutations
+            // happen in their emitting tasks, not on replay.
+            Event::DepositObserved { .. }
+            | Event::ChainMintSubmitted { .. }
+            | Event::ChainMintConfirmed { .. }
+            | Event::ChainBurnObserved { .. }
+            | Event::ChainInterestMinted { .. }
+            | Event::WithdrawalSigned { .. }
+            | Event::ChainSettlementFailed { .. }
+            | Event::ChainReorgDetected { .. }
+            // Increment 1: chains-liquidation events are observability-only; the
+            // reserve/debt/supply mutations happen live in the bot/SP confirm
+            // paths (Increments 2-4), not on replay.
+            | Event::ChainVaultLiquidated { .. }
+            | Event::ChainReserveCredited { .. }
+            | Event::ChainCfxClaimSettled { .. }
+            | Event::ChainPendingBurnSettled { .. }
+            | Event::ChainReserveBurnSettled { .. }
+            | Event::ChainLiquidationDeferred { .. }
+            | Event::ChainHotWalletLow { .. } => {},
+        }
+    }
+    // Transitions re-derived while replaying are already in the log.
+    state.pending_mode_transitions.clear();
+    state.pending_shadow_actions.clear();
+    state
+}
+
+/// Helper: current canister time in nanoseconds.
+fn now() -> u64 {
+    ic_cdk::api::time()
+}
+
+/// Records an admin setter event and journals the change it makes, with the
+/// caller as actor. Call it before applying the change: the journal reads
+/// the old value from `state`. See `parameter_journal`.
+fn record_parameter_event(state: &mut State, event: &Event) {
+    let event_index = crate::storage::count_events();
+    record_event(event);
+    crate::parameter_journal::journal_event(
+        state,
+        event,
+        event_index,
+        Some(crate::timelock::acting_caller()),
+        now(),
+    );
+}
+
+pub fn record_liquidate_vault(
+    state: &mut State,
+    vault_id: u64,
+    mode: Mode,
+    collateral_price: UsdIcp,
+) {
+    record_event(&Event::LiquidateVault {
+        vault_id,
+        mode,
+        icp_rate: collateral_price,
+        liquidator: None,
+        timestamp: Some(now()),
+        collateral_seized: None,
+    });
+    let _ = state.liquidate_vault(vault_id, mode, collateral_price);
+}
+
+pub fn record_redistribute_vault(state: &mut State, vault_id: u64) {
+    record_event(&Event::RedistributeVault {
+        vault_id,
+        timestamp: Some(now()),
+    });
+    state.redistribute_vault(vault_id);
+}
+
+pub fn record_provide_liquidity(
+    state: &mut State,
+    amount: ICUSD,
+    caller: Principal,
+    block_index: u64,
+) {
+    record_event(&Event::ProvideLiquidity {
+        amount,
+        block_index,
+        caller,
+        timestamp: Some(now()),
+    });
+    state.provide_liquidity(amount, caller);
+}
+
+pub fn record_withdraw_liquidity(
+    state: &mut State,
+    amount: ICUSD,
+    caller: Principal,
+    block_index: u64,
+) {
+    record_event(&Event::WithdrawLiquidity {
+        amount,
+        block_index,
+        caller,
+        timestamp: Some(now()),
+    });
+    state.withdraw_liquidity(amount, caller);
+}
+
+pub fn record_claim_liquidity_returns(
+    state: &mut State,
+    amount: ICP,
+    caller: Principal,
+    block_index: u64,
+) {
+    record_event(&Event::ClaimLiquidityReturns {
+        amount,
+        block_index,
+        caller,
+        timestamp: Some(now()),
+    });
+    state.claim_liquidity_returns(amount, caller);
+}
+
+pub fn record_liquidity_dust_swept(state: &mut State, caller: Principal, returns: ICP) {
+    record_event(&Event::LiquidityDustSwept {
+        caller,
+        returns,
+        timestamp: now(),
+    });
+    crate::liquidity_pool::apply_dust_sweep(state, caller, returns);
+}
+
+pub fn record_liquidity_dust_sent_to_treasury(state: &mut State, amount: ICP, block_index: u64) {
+    record_event(&Event::LiquidityDustSentToTreasury {
+        amount,
+        block_index,
+        timestamp: now(),
+    });
+    state.liquidity_dust_swept = state.liquidity_dust_swept.saturating_sub(amount);
+}
+
+pub fn record_liquidity_residual_returned(
+    state: &mut State,
+    caller: Principal,
+    amount: ICUSD,
+    block_index: u64,
+) {
+    record_event(&Event::LiquidityResidualReturned {
+        caller,
+        amount,
+        block_index,
+        timestamp: now(),
+    });
+    state.withdraw_liquidity(amount, caller);
+}
+
+pub fn record_recovery_pool_routing(
+    state: &mut State,
+    routing: crate::pool_priority::RecoveryRouting,
+) {
+    let timestamp = now();
+    crate::pool_priority::apply_routing(
+        state,
+        &routing.to_pool,
+        routing.reserved_until_ns,
+        timestamp,
+    );
+    record_event(&Event::RecoveryPoolRouting {
+        to_pool: routing.to_pool,
+        overflow: routing.overflow,
+        pool_depth: routing.pool_depth,
+        reserved_until_ns: routing.reserved_until_ns,
+        timestamp,
+    });
+}
+
+pub fn record_set_recovery_pool_priority(
+    state: &mut State,
+    config: crate::pool_priority::PoolPriorityConfig,
+) {
+    record_parameter_event(
+        state,
+        &Event::SetRecoveryPoolPriority {
+            enabled: config.enabled,
+            window_ns: config.window_ns,
+        },
+    );
+    crate::pool_priority::apply_set_config(state, config);
+}
+
+pub fn record_set_auto_deleverage(
+    state: &mut State,
+    vault_id: u64,
+    owner: Principal,
+    config: Option<crate::auto_deleverage::AutoDeleverageConfig>,
+    timestamp: u64,
+) {
+    record_event(&Event::SetAutoDeleverage {
+        vault_id,
+        owner,
+        config: config.clone(),
+        timestamp,
+    });
+    crate::auto_deleverage::apply_set(state, vault_id, config, timestamp);
+}
+
+pub fn record_set_auto_deleverage_route(
+    state: &mut State,
+    collateral_type: CollateralType,
+    route: Option<crate::auto_deleverage::AutoDeleverageRoute>,
+) {
+    record_parameter_event(
+        state,
+        &Event::SetAutoDeleverageRoute {
+            collateral_type,
+            route: route.clone(),
+        },
+    );
+    crate::auto_deleverage::apply_set_route(state, collateral_type, route);
+}
+
+/// Record a completed auto-deleverage sale and apply it. Returns the
+/// interest share of the repayment (for treasury routing).
+pub fn record_vault_auto_deleveraged(
+    state: &mut State,
+    plan: &crate::auto_deleverage::DeleveragePlan,
+    icusd_repaid: ICUSD,
+    timestamp: u64,
+) -> ICUSD {
+    record_event(&Event::VaultAutoDeleveraged {
+        vault_id: plan.vault_id,
+        collateral_type: plan.collateral_type,
+        collateral_sold: plan.collateral_debit,
+        icusd_repaid,
+        cr_before_bps: plan.cr_before_bps,
+        dex: plan.route.dex,
+        timestamp,
+    });
+    crate::auto_deleverage::apply_deleverage(
+        state,
+        plan.vault_id,
+        plan.collateral_debit,
+        icusd_repaid,
+        timestamp,
+    )
+}
+
+pub fn record_auto_deleverage_failed(
+    state: &mut State,
+    vault_id: u64,
+    reason: String,
+    timestamp: u64,
+) {
+    record_event(&Event::AutoDeleverageFailed {
+        vault_id,
+        reason,
+        timestamp,
+    });
+    crate::auto_deleverage::apply_failure(state, vault_id, timestamp);
+}
+
+pub fn record_set_pending_backpressure(
+    state: &mut State,
+    config: crate::pending_backpressure::PendingBackpressureConfig,
+) {
+    record_parameter_event(
+        state,
+        &Event::SetPendingBackpressure {
+            max_pending_collateral_transfers: config.max_pending_collateral_transfers,
+            max_pending_redemption_transfers: config.max_pending_redemption_transfers,
+        },
+    );
+    crate::pending_backpressure::apply_set_config(state, config);
+}
+
+pub fn record_vault_status_changed(
+    state: &mut State,
+    vault_id: u64,
+    from: crate::vault_status::VaultStatus,
+    to: crate::vault_status::VaultStatus,
+    timestamp: u64,
+) {
+    record_event(&Event::VaultStatusChanged {
+        vault_id,
+        from,
+        to,
+        timestamp,
+    });
+    crate::vault_status::apply_transition(state, vault_id, to);
+}
+
+pub fn record_set_log_retention(state: &mut State, config: crate::logs::LogRetentionConfig) {
+    record_parameter_event(
+        state,
+        &Event::SetLogRetention {
+            critical_capacity: config.critical_capacity,
+            info_capacity: config.info_capacity,
+            debug_capacity: config.debug_capacity,
+            trace_xrc_capacity: config.trace_xrc_capacity,
+            persisted_critical_capacity: config.persisted_critical_capacity,
+        },
+    );
+    state.log_retention = config;
+}
+
+pub fn record_set_collateral_price_confidence(
+    state: &mut State,
+    collateral_type: CollateralType,
+    k: Option<Ratio>,
+) {
+    record_parameter_event(
+        state,
+        &Event::SetCollateralPriceConfidence {
+            collateral_type,
+            k: k.map(|r| r.0.to_string()),
+        },
+    );
+    state.set_price_confidence_k(&collateral_type, k);
+}
+
+pub fn record_set_collateral_maintenance_fee(
+    state: &mut State,
+    collateral_type: CollateralType,
+    maintenance_fee_apr: Option<Ratio>,
+    now: u64,
+) {
+    record_parameter_event(
+        state,
+        &Event::SetCollateralMaintenanceFee {
+            collateral_type,
+            maintenance_fee_apr: maintenance_fee_apr.map(|r| r.0.to_string()),
+            timestamp: now,
+        },
+    );
+    state.set_maintenance_fee_apr(&collateral_type, maintenance_fee_apr, now);
+}
+
+/// Record a validated parameter batch as one event and apply it.
+pub fn record_apply_parameter_batch(
+    state: &mut State,
+    changes: Vec<crate::parameter_batch::BatchedParameter>,
+    now: u64,
+) {
+    record_parameter_event(
+        state,
+        &Event::ApplyParameterBatch {
+            changes: changes.clone(),
+            timestamp: now,
+        },
+    );
+    crate::parameter_batch::apply(state, &changes);
+}
+
+pub fn record_set_recovery_exit_band(state: &mut State, band: Option<Ratio>) {
+    record_parameter_event(
+        state,
+        &Event::SetRecoveryExitBand {
+            band: band.map(|r| r.0.to_string()),
+        },
+    );
+    state.recovery_exit_band = band;
+}
+
+pub fn record_set_collateral_redemption_cap(
+    state: &mut State,
+    collateral_type: CollateralType,
+    config: Option<crate::redemption_caps::RedemptionCapConfig>,
+) {
+    record_parameter_event(
+        state,
+        &Event::SetCollateralRedemptionCap {
+            collateral_type,
+            config,
+        },
+    );
+    crate::redemption_caps::apply_set_config(state, collateral_type, config);
+}
+
+pub fn record_set_price_deviation_breaker(
+    state: &mut State,
+    config: Option<crate::xrc::PriceDeviationBreaker>,
+) {
+    record_parameter_event(state, &Event::SetPriceDeviationBreaker { config });
+    crate::xrc::apply_price_deviation_breaker(state, config);
+}
+
+pub fn record_set_collateral_price_bounds(
+    state: &mut State,
+    collateral_type: CollateralType,
+    bounds: Option<crate::xrc::PriceBounds>,
+) {
+    record_parameter_event(
+        state,
+        &Event::SetCollateralPriceBounds {
+            collateral_type,
+            bounds,
+        },
+    );
+    crate::xrc::apply_price_bounds(state, collateral_type, bounds);
+}
+
+pub fn record_set_flash_mint_config(
+    state: &mut State,
+    config: Option<crate::flash_mint::FlashMintConfig>,
+) {
+    record_parameter_event(
+        state,
+        &Event::SetFlashMintConfig {
+            config: config.clone(),
+        },
+    );
+    crate::flash_mint::apply_set_config(state, config);
+}
+
+pub fn record_set_slo_thresholds(
+    state: &mut State,
+    endpoint: Option<String>,
+    thresholds: Option<crate::slo::SloThresholds>,
+) {
+    record_parameter_event(
+        state,
+        &Event::SetSloThresholds {
+            endpoint: endpoint.clone(),
+            thresholds,
+        },
+    );
+    crate::slo::apply_set_thresholds(state, endpoint, thresholds);
+}
+
+pub fn record_slo_breach(breach: crate::slo::SloBreach, now: u64) {
+    record_event(&Event::SloBreach {
+        endpoint: breach.endpoint,
+        kind: breach.kind,
+        observed: breach.observed,
+        threshold: breach.threshold,
+        timestamp: now,
+    });
+}
+
+/// Records a donation to the surplus buffer.
+#[allow(clippy::too_many_arguments)]
+pub fn record_donation(
+    state: &mut State,
+    donor: Principal,
+    ledger: Principal,
+    amount: u64,
+    deficit_repaid: u64,
+    block_index: u64,
+    memo: Option<String>,
+    now: u64,
+) {
+    record_event(&Event::Donation {
+        donor,
+        ledger,
+        amount,
+        deficit_repaid,
+        block_index,
+        memo,
+        timestamp: now,
+    });
+    crate::donations::apply_donation(state, donor, ledger, amount, deficit_repaid);
+}
+
+pub fn record_register_liquidator(
+    state: &mut State,
+    liquidator: Principal,
+    name: Option<String>,
+    self_registered: bool,
+    now: u64,
+) {
+    record_event(&Event::RegisterLiquidator {
+        liquidator,
+        name: name.clone(),
+        self_registered,
+        timestamp: now,
+    });
+    crate::liquidators::apply_register(state, liquidator, name, self_registered, now);
+}
+
+pub fn record_remove_liquidator(state: &mut State, liquidator: Principal, now: u64) {
+    record_event(&Event::RemoveLiquidator {
+        liquidator,
+        timestamp: now,
+    });
+    crate::liquidators::apply_remove(state, &liquidator);
+}
+
+pub fn record_set_liquidator_self_registration(state: &mut State, enabled: bool) {
+    record_parameter_event(state, &Event::SetLiquidatorSelfRegistration { enabled });
+    state.liquidator_self_registration = enabled;
+}
+
+pub fn record_set_fee_sponsorship_config(
+    state: &mut State,
+    config: crate::fee_sponsorship::FeeSponsorshipConfig,
+) {
+    record_parameter_event(
+        state,
+        &Event::SetFeeSponsorshipConfig {
+            config: config.clone(),
+        },
+    );
+    state.fee_sponsorship_config = Some(config);
+}
+
+pub fn record_set_fee_sponsorship_verified(state: &mut State, user: Principal, verified: bool) {
+    record_parameter_event(state, &Event::SetFeeSponsorshipVerified { user, verified });
+    if verified {
+        state.fee_sponsorship_verified.insert(user);
+    } else {
+        state.fee_sponsorship_verified.remove(&user);
+    }
+}
+
+pub fn record_fund_fee_sponsorship(
+    state: &mut State,
+    funder: Principal,
+    ledger: Principal,
+    amount: u64,
+    block_index: u64,
+    now: u64,
+) {
+    record_event(&Event::FundFeeSponsorship {
+        funder,
+        ledger,
+        amount,
+        block_index,
+        timestamp: now,
+    });
+    crate::fee_sponsorship::apply_funding(state, ledger, amount);
+}
+
+/// Records a return to the treasury whose amount and fee were already taken
+/// from the pool by `fee_sponsorship::take_for_return`.
+pub fn record_return_fee_sponsorship(
+    ledger: Principal,
+    amount: u64,
+    fee: u64,
+    block_index: u64,
+    now: u64,
+) {
+    record_event(&Event::ReturnFeeSponsorship {
+        ledger,
+        amount,
+        fee,
+        block_index,
+        timestamp: now,
+    });
+}
+
+pub fn record_fee_sponsored(
+    state: &mut State,
+    user: Principal,
+    vault_id: u64,
+    ledger: Principal,
+    fee: u64,
+    now: u64,
+) {
+    record_event(&Event::FeeSponsored {
+        user,
+        vault_id,
+        ledger,
+        fee,
+        timestamp: now,
+    });
+    crate::fee_sponsorship::apply_sponsored(state, user, ledger, fee, now);
+}
+
+pub fn record_guard_auto_cleared(
+    principal: Principal,
+    operation_name: String,
+    age_ns: u64,
+    reason: crate::guard_metrics::GuardClearReason,
+    now: u64,
+) {
+    record_event(&Event::GuardAutoCleared {
+        principal,
+        operation_name,
+        age_ns,
+        reason,
+        timestamp: now,
+    });
+}
+
+pub fn record_set_auction_config(state: &mut State, config: crate::auction::AuctionConfig) {
+    record_parameter_event(
+        state,
+        &Event::SetAuctionConfig {
+            config: config.clone(),
+        },
+    );
+    state.auction_config = Some(config);
+}
+
+pub fn record_auction_started(state: &mut State, auction: crate::auction::Auction) {
+    record_event(&Event::AuctionStarted {
+        auction: auction.clone(),
+    });
+    crate::auction::apply_start(state, auction);
+}
+
+/// Records a bid planned by `auction::plan_bid` and applies it. Returns the
+/// interest share of the debt it repaid.
+pub fn record_auction_bid(
+    state: &mut State,
+    plan: &crate::auction::BidPlan,
+    bidder: Principal,
+    icusd_block_index: u64,
+    now: u64,
+) -> ICUSD {
+    record_event(&Event::AuctionBid {
+        auction_id: plan.auction_id,
+        vault_id: plan.vault_id,
+        bidder,
+        icusd_amount: plan.icusd,
+        collateral_amount: plan.collateral,
+        price_e8s: plan.price_e8s,
+        debt_written_off: plan.debt_written_off,
+        icusd_block_index,
+        timestamp: now,
+    });
+    crate::auction::apply_bid(
+        state,
+        plan.auction_id,
+        plan.vault_id,
+        plan.icusd,
+        plan.collateral,
+        plan.debt_written_off,
+    )
+}
+
+pub fn record_auction_ended(
+    state: &mut State,
+    auction_id: u64,
+    reason: crate::auction::AuctionEndReason,
+    now: u64,
+) {
+    let Some(vault_id) = state.auctions.get(&auction_id).map(|a| a.vault_id) else {
+        return;
+    };
+    record_event(&Event::AuctionEnded {
+        auction_id,
+        vault_id,
+        reason,
+        timestamp: now,
+    });
+    crate::auction::apply_end(state, auction_id, reason, now);
+}
+
+pub fn record_set_surplus_fee_share(state: &mut State, share: Ratio) {
+    record_parameter_event(state, &Event::SetSurplusFeeShare { share });
+    state.surplus_fee_share = share;
+}
+
+/// Records and applies a fee kept in the surplus buffer; a retained icUSD
+/// fee then repays what it can of the deficit.
+pub fn record_surplus_fee_retained(
+    state: &mut State,
+    ledger: Principal,
+    amount: u64,
+    source: FeeSource,
+    now: u64,
+) {
+    record_event(&Event::SurplusFeeRetained {
+        ledger,
+        amount,
+        source,
+        timestamp: now,
+    });
+    crate::surplus::apply_fee_retained(state, ledger, amount);
+    if ledger == state.icusd_ledger_principal {
+        absorb_deficit_from_surplus(state, now);
+    }
+}
+
+/// Records a sweep of the icUSD buffer, which `surplus::take_for_sweep`
+/// already debited.
+pub fn record_surplus_swept(amount: u64, block_index: u64, now: u64) {
+    record_event(&Event::SurplusSwept {
+        amount,
+        block_index,
+        timestamp: now,
+    });
+}
+
+pub fn record_borrow_paid_from_reserves(
+    owner: Principal,
+    vault_id: u64,
+    stable_ledger: Principal,
+    payout: &crate::reserve_stables::ReservePayout,
+    block_index: u64,
+) {
+    record_event(&Event::BorrowPaidFromReserves {
+        vault_id,
+        owner,
+        stable_ledger,
+        fee_amount: payout.fee_icusd,
+        stable_amount_sent: payout.amount_native,
+        fee_stable_amount: payout.fee_native,
+        block_index,
+        timestamp: now(),
+    });
+}
+
+/// Records and applies the settlement of `collateral_type` at `price`.
+pub fn record_collateral_settled(
+    state: &mut State,
+    collateral_type: Principal,
+    price: UsdIcp,
+    settled_by: Principal,
+    now: u64,
+) -> crate::collateral_settlement::CollateralSettlement {
+    record_event(&Event::CollateralSettled {
+        collateral_type,
+        price,
+        settled_by,
+        timestamp: now,
+    });
+    crate::collateral_settlement::apply_settle(state, collateral_type, price, now)
+}
+
+/// Records and applies the claim of settled `vault_id`. Returns the
+/// collateral left in it, for the caller to queue.
+pub fn record_settled_vault_claimed(state: &mut State, vault_id: u64, now: u64) -> u64 {
+    let vault = state.vault_id_to_vaults[&vault_id].clone();
+    record_event(&Event::SettledVaultClaimed {
+        vault_id,
+        owner: vault.owner,
+        collateral_type: vault.collateral_type,
+        collateral_amount: vault.collateral_amount,
+        timestamp: now,
+    });
+    crate::collateral_settlement::apply_claim(state, vault_id)
+}
+
+/// Records a settlement redemption, which
+/// `collateral_settlement::take_redemption` already booked.
+pub fn record_settled_collateral_redeemed(
+    redeemer: Principal,
+    collateral_type: Principal,
+    icusd_amount: u64,
+    collateral_amount: u64,
+    icusd_block_index: u64,
+    now: u64,
+) {
+    record_event(&Event::SettledCollateralRedeemed {
+        redeemer,
+        collateral_type,
+        icusd_amount,
+        collateral_amount,
+        icusd_block_index,
+        timestamp: now,
+    });
+}
+
+/// Records and applies an emergency shutdown at `prices`.
+pub fn record_emergency_shutdown(
+    state: &mut State,
+    triggered_by: Principal,
+    prices: Vec<(Principal, UsdIcp)>,
+    now: u64,
+) {
+    record_event(&Event::EmergencyShutdown {
+        triggered_by,
+        prices: prices.clone(),
+        timestamp: now,
+    });
+    crate::emergency_shutdown::apply_shutdown(state, triggered_by, &prices, now);
+}
+
+pub fn record_set_collateral_liquidation_cap(
+    state: &mut State,
+    collateral_type: CollateralType,
+    config: Option<crate::liquidation_caps::LiquidationCapConfig>,
+) {
+    record_parameter_event(
+        state,
+        &Event::SetCollateralLiquidationCap {
+            collateral_type,
+            config,
+        },
+    );
+    crate::liquidation_caps::apply_set_config(state, collateral_type, config);
+}
+
+pub fn record_liquidation_cap_bound(
+    vault_id: u64,
+    collateral_type: CollateralType,
+    requested: ICUSD,
+    repay: ICUSD,
+    seizable: u64,
+) {
+    record_event(&Event::LiquidationCapBound {
+        vault_id,
+        collateral_type,
+        requested,
+        repay,
+        seizable,
+        timestamp: now(),
+    });
+}
+
+pub fn record_set_parameter_timelock(state: &mut State, delay_ns: u64) {
+    record_parameter_event(state, &Event::SetParameterTimelock { delay_ns });
+    state.parameter_timelock_ns = delay_ns;
+}
+
+pub fn record_parameter_change_proposed(
+    state: &mut State,
+    change: crate::timelock::PendingParameterChange,
+) {
+    record_event(&Event::ParameterChangeProposed {
+        id: change.id,
+        method: change.method.clone(),
+        arg: change.arg.clone(),
+        proposed_by: change.proposed_by,
+        activates_at: change.activates_at,
+        timestamp: change.proposed_at,
+    });
+    crate::timelock::apply_proposed(state, change);
+}
+
+pub fn record_parameter_change_cancelled(
+    state: &mut State,
+    id: u64,
+    cancelled_by: Principal,
+    now: u64,
+) {
+    record_event(&Event::ParameterChangeCancelled {
+        id,
+        cancelled_by,
+        timestamp: now,
+    });
+    crate::timelock::apply_removed(state, id);
+}
+
+pub fn record_parameter_change_executed(
+    state: &mut State,
+    id: u64,
+    executed_by: Principal,
+    now: u64,
+) {
+    record_event(&Event::ParameterChangeExecuted {
+        id,
+        executed_by,
+        timestamp: now,
+    });
+    crate::timelock::apply_removed(state, id);
+}
+
+pub fn record_set_emergency_pausers(state: &mut State, principals: Vec<Principal>) {
+    record_parameter_event(
+        state,
+        &Event::SetEmergencyPausers {
+            principals: principals.clone(),
+        },
+    );
+    state.emergency_pausers = principals.into_iter().collect();
+}
+
+pub fn record_set_operation_paused(
+    state: &mut State,
+    operation: crate::operation_pauses::PausableOperation,
+    paused: bool,
+    changed_by: Principal,
+) {
+    record_parameter_event(
+        state,
+        &Event::SetOperationPaused {
+            operation,
+            paused,
+            changed_by,
+            timestamp: now(),
+        },
+    );
+    crate::operation_pauses::apply_set_paused(state, operation, paused);
+}
+
+pub fn record_set_shadow_config(state: &mut State, config: crate::shadow::ShadowConfig) {
+    record_parameter_event(state, &Event::SetShadowConfig { config });
+    state.shadow_config = config;
+}
+
+pub fn record_set_redistribution_enabled(state: &mut State, enabled: bool) {
+    record_parameter_event(state, &Event::SetRedistributionEnabled { enabled });
+    state.redistribution_enabled = enabled;
+}
+
+/// Records and applies the redistribution of `vault_id`, which
+/// `redistribution::check` passed.
+pub fn record_vault_redistributed(
+    state: &mut State,
+    vault_id: u64,
+    now: u64,
+) -> Result<crate::redistribution::PendingRedistribution, crate::ProtocolError> {
+    crate::redistribution::check(state, vault_id)?;
+    // Book the vault's own pending share first so the event carries what
+    // is handed out.
+    crate::redistribution::settle(state, vault_id);
+    // The surplus buffer covers what it can before the other vaults do.
+    if let Some((debt, collateral_amount)) = crate::surplus::plan_absorption(state, vault_id) {
+        record_event(&Event::SurplusAbsorbedVault {
+            vault_id,
+            collateral_type: state.vault_id_to_vaults[&vault_id].collateral_type,
+            collateral_amount,
+            debt,
+            timestamp: now,
+        });
+        if crate::surplus::apply_absorption(state, vault_id, debt, collateral_amount) {
+            return Ok(crate::redistribution::PendingRedistribution::default());
+        }
+    }
+    let vault = state.vault_id_to_vaults[&vault_id].clone();
+    record_event(&Event::VaultRedistributed {
+        vault_id,
+        owner: vault.owner,
+        collateral_type: vault.collateral_type,
+        collateral_amount: vault.collateral_amount,
+        debt: vault.borrowed_icusd_amount,
+        timestamp: now,
+    });
+    crate::redistribution::apply_redistribute(state, vault_id)
+}
+
+/// Records and applies the auto-closure of dust vault `vault_id`, whose
+/// debt the caller has already written off.
+pub fn record_dust_vault_closed(state: &mut State, vault_id: u64, now: u64) {
+    let vault = state.vault_id_to_vaults[&vault_id].clone();
+    record_event(&Event::DustVaultClosed {
+        vault_id,
+        owner: vault.owner,
+        collateral_type: vault.collateral_type,
+        collateral_amount: vault.collateral_amount,
+        debt: vault.borrowed_icusd_amount,
+        timestamp: now,
+    });
+    crate::dust_vaults::apply_close(state, vault_id);
+}
+
+/// Records a flash mint's outcome; a default (`repay_block_index: None`)
+/// drops `callback` from the allowlist.
+#[allow(clippy::too_many_arguments)]
+pub fn record_flash_mint(
+    state: &mut State,
+    initiator: Principal,
+    callback: Principal,
+    amount_e8s: u64,
+    fee_e8s: u64,
+    mint_block_index: u64,
+    repay_block_index: Option<u64>,
+    now: u64,
+) {
+    record_event(&Event::FlashMint {
+        initiator,
+        callback,
+        amount_e8s,
+        fee_e8s,
+        mint_block_index,
+        repay_block_index,
+        timestamp: now,
+    });
+    if repay_block_index.is_none() {
+        crate::flash_mint::apply_default(state, &callback);
+    }
+}
+
+/// Records the base rate a redemption against `collateral_type` left behind
+/// and stores it, decaying from `now`.
+pub fn record_redemption_base_rate(
+    state: &mut State,
+    collateral_type: CollateralType,
+    base_rate: Ratio,
+    now: u64,
+) {
+    record_event(&Event::RedemptionBaseRateUpdated {
+        collateral_type,
+        base_rate: base_rate.0.to_string(),
+        timestamp: now,
+    });
+    crate::record_per_collateral_redemption_fee(state, &collateral_type, base_rate, now);
+}
+
+pub fn record_open_vault(state: &mut State, vault: Vault, block_index: u64) {
+    record_event(&Event::OpenVault {
+        vault: vault.clone(),
+        block_index,
+        timestamp: Some(now()),
+    });
+    state.open_vault(vault);
+}
+
+pub fn record_close_vault(state: &mut State, vault_id: u64, block_index: Option<u64>) {
+    record_event(&Event::CloseVault {
+        vault_id,
+        block_index,
+        timestamp: Some(now()),
+    });
+    state.close_vault(vault_id);
+}
+
+/// `observed_seq` is the causality token taken when the pending entry was
+/// read, before the payout was awaited.
+pub fn record_margin_transfer(
+    state: &mut State,
+    vault_id: u64,
+    owner: Principal,
+    block_index: u64,
+    observed_seq: u64,
+) {
+    record_event_after(
+        &Event::MarginTransfer {
+            vault_id,
+            block_index,
+            timestamp: Some(now()),
+        },
+        observed_seq,
+    );
+    state.pending_margin_transfers.remove(&(vault_id, owner));
+}
+
+// ─── Wave-8e LIQ-005: deficit-account event recorders ───
+
+/// Record a `DeficitAccrued` event and increment `protocol_deficit_icusd`.
+/// Caller is responsible for invoking `state.check_deficit_readonly_latch()`
+/// afterwards if the latch threshold is configured.
+///
+/// Wave-9 RED-002: takes a `DeficitSource` so liquidation and redemption
+/// deficits are distinguishable in the event log. The legacy `vault_id`
+/// field on the event is preserved for back-compat and continues to be
+/// populated for liquidation; redemption deficits set `vault_id = 0` on
+/// the parent event because the cr-walk touches multiple vaults.
+pub fn record_deficit_accrued(
+    state: &mut State,
+    source: DeficitSource,
+    amount: ICUSD,
+    timestamp: u64,
+) {
+    state.accrue_deficit_shortfall(amount);
+    let vault_id = match source {
+        DeficitSource::Liquidation { vault_id } | DeficitSource::DustWriteOff { vault_id } => {
+            vault_id
+        }
+        DeficitSource::Redemption { .. } | DeficitSource::FlashMint { .. } => 0,
+    };
+    record_event(&Event::DeficitAccrued {
+        vault_id,
+        amount,
+        new_deficit: state.protocol_deficit_icusd,
+        timestamp,
+        source: Some(source),
+    });
+    absorb_deficit_from_surplus(state, timestamp);
+}
+
+/// The icUSD surplus buffer (donations and retained fees, already burned or
+/// never minted) repays what it can of the deficit at once.
+fn absorb_deficit_from_surplus(state: &mut State, timestamp: u64) {
+    let absorbed = crate::donations::absorbable_deficit(state);
+    if absorbed > 0 {
+        crate::donations::apply_buffer_absorption(state, absorbed);
+        record_deficit_repaid(
+            state,
+            ICUSD::new(absorbed),
+            FeeSource::SurplusBuffer,
+            None,
+            timestamp,
+        );
+    }
+}
+
+/// Record a `DeficitRepaid` event and apply the repayment to state.
+pub fn record_deficit_repaid(
+    state: &mut State,
+    amount: ICUSD,
+    source: FeeSource,
+    anchor_block_index: Option<u64>,
+    timestamp: u64,
+) {
+    state.apply_deficit_repayment(amount);
+    record_event(&Event::DeficitRepaid {
+        amount,
+        source,
+        remaining_deficit: state.protocol_deficit_icusd,
+        anchor_block_index,
+        timestamp,
+    });
+}
+
+/// Admin: tune the per-fee fraction routed to deficit repayment.
 pub fn record_set_deficit_repayment_fraction(state: &mut State, fraction: Ratio) {
+    record_parameter_event(
+        state,
+        &Event::SetDeficitRepaymentFraction {
+            fraction,
+            timestamp: now(),
+        },
+    );
     state.deficit_repayment_fraction = fraction;
-    record_event(&Event::SetDeficitRepaymentFraction {
-        fraction,
-        timestamp: now(),
-    });
 }
 
 /// Admin: set the deficit-driven ReadOnly auto-latch threshold (0 disables).
 pub fn record_set_deficit_readonly_threshold_e8s(state: &mut State, threshold_e8s: u64) {
+    record_parameter_event(
+        state,
+        &Event::SetDeficitReadonlyThresholdE8s {
+            threshold_e8s,
+            timestamp: now(),
+        },
+    );
     state.deficit_readonly_threshold_e8s = threshold_e8s;
-    record_event(&Event::SetDeficitReadonlyThresholdE8s {
-        threshold_e8s,
+}
+
+/// Drains `State::pending_mode_transitions` into `ModeTransition` events.
+/// Called after any live path that may have flipped the protocol mode; any
+/// recorded transition schedules a push to the mode companions. Shadowed
+/// transitions noted on the way are stamped here too.
+pub fn record_mode_transitions(state: &mut State) {
+    crate::shadow::flush(state, now());
+    let transitions = std::mem::take(&mut state.pending_mode_transitions);
+    if transitions.is_empty() {
+        return;
+    }
+    for transition in transitions {
+        record_event(&Event::ModeTransition {
+            from: transition.from,
+            to: transition.to,
+            reason: transition.reason,
+            timestamp: now(),
+        });
+    }
+    crate::mode_propagation::schedule_mode_propagation();
+}
+
+/// Create a rebate campaign and return its id. `arg` must already be
+/// validated with `campaigns::validate_create_arg`.
+pub fn record_rebate_campaign_created(state: &mut State, arg: CreateRebateCampaignArg) -> u64 {
+    let campaign_id = state.next_rebate_campaign_id;
+    record_event(&Event::RebateCampaignCreated {
+        campaign_id,
+        arg: arg.clone(),
+        timestamp: now(),
+    });
+    crate::campaigns::apply_create(state, campaign_id, arg);
+    campaign_id
+}
+
+/// Add `amount_e8s` to an open campaign's pool; the event is only recorded
+/// once the funding is accepted.
+pub fn record_rebate_campaign_funded(
+    state: &mut State,
+    campaign_id: u64,
+    amount_e8s: u64,
+) -> Result<(), String> {
+    crate::campaigns::apply_fund(state, campaign_id, amount_e8s)?;
+    record_event(&Event::RebateCampaignFunded {
+        campaign_id,
+        amount_e8s,
+        timestamp: now(),
+    });
+    Ok(())
+}
+
+/// Close a rebate campaign; returns the unspent budget left with the treasury.
+pub fn record_rebate_campaign_closed(state: &mut State, campaign_id: u64) -> Result<u64, String> {
+    let timestamp = now();
+    let unspent_e8s = crate::campaigns::apply_close(state, campaign_id, timestamp)?;
+    record_event(&Event::RebateCampaignClosed {
+        campaign_id,
+        unspent_e8s,
+        timestamp,
+    });
+    Ok(unspent_e8s)
+}
+
+/// Commit a rebate reserved with `campaigns::reserve_rebate` once the borrow
+/// has minted.
+pub fn record_borrow_fee_rebated(
+    state: &mut State,
+    campaign_id: u64,
+    vault_id: u64,
+    rebate: ICUSD,
+) {
+    record_event(&Event::BorrowFeeRebated {
+        campaign_id,
+        vault_id,
+        rebate_e8s: rebate.to_u64(),
         timestamp: now(),
     });
+    crate::borrow_records::apply_fee_rebate(state, vault_id, rebate.to_u64());
+    crate::campaigns::apply_rebate_paid(state, campaign_id, rebate, true);
+}
+
+/// Register a session key for `owner`. `arg` must already be validated with
+/// `session_keys::validate_register_arg`.
+pub fn record_session_key_registered(
+    state: &mut State,
+    owner: Principal,
+    arg: RegisterSessionKeyArg,
+) {
+    let timestamp = now();
+    record_event(&Event::SessionKeyRegistered {
+        owner,
+        arg: arg.clone(),
+        timestamp,
+    });
+    crate::session_keys::apply_register(state, owner, arg, timestamp);
+}
+
+/// Revoke `owner`'s session key `session_principal`. Fails, recording
+/// nothing, if the key is not the owner's.
+pub fn record_session_key_revoked(
+    state: &mut State,
+    owner: Principal,
+    session_principal: Principal,
+) -> Result<(), String> {
+    crate::session_keys::apply_revoke(state, owner, session_principal)?;
+    record_event(&Event::SessionKeyRevoked {
+        owner,
+        session_principal,
+        timestamp: now(),
+    });
+    Ok(())
+}
+
+/// Log a completed vault operation performed through a session key. For
+/// repays the amount was already reserved with
+/// `session_keys::reserve_repay_allowance`.
+pub fn record_session_key_used(
+    state: &mut State,
+    session_principal: Principal,
+    vault_id: u64,
+    scope: SessionScope,
+    amount_e8s: u64,
+) {
+    record_event(&Event::SessionKeyUsed {
+        session_principal,
+        vault_id,
+        scope,
+        amount_e8s,
+        timestamp: now(),
+    });
+    crate::session_keys::apply_session_use(state, session_principal, scope, amount_e8s, true);
+}
+
+/// Credit `collateral_amount` of a liquidated vault's owner rebate to
+/// `target_vault_id`. The collateral is already in custody, so nothing is
+/// transferred.
+pub fn record_liquidation_rebate_applied(
+    state: &mut State,
+    source_vault_id: u64,
+    target_vault_id: u64,
+    owner: Principal,
+    collateral_amount: u64,
+) {
+    record_event(&Event::LiquidationRebateApplied {
+        source_vault_id,
+        target_vault_id,
+        owner,
+        collateral_amount,
+        timestamp: now(),
+    });
+    state.add_margin_to_vault(target_vault_id, ICP::from(collateral_amount));
 }
 
 /// Wave-10 LIQ-008: production wrapper called from each vault.rs liquidation
@@ -2378,20 +5302,26 @@ pub fn record_breaker_cleared(state: &mut State, remaining_total_e8s: u64) {
 
 /// Wave-10 LIQ-008: admin tunes the rolling-window length. 0 disables the breaker.
 pub fn record_set_breaker_window_ns(state: &mut State, window_ns: u64) {
+    record_parameter_event(
+        state,
+        &Event::SetBreakerWindowNs {
+            window_ns,
+            timestamp: now(),
+        },
+    );
     state.breaker_window_ns = window_ns;
-    record_event(&Event::SetBreakerWindowNs {
-        window_ns,
-        timestamp: now(),
-    });
 }
 
 /// Wave-10 LIQ-008: admin tunes the cumulative-debt ceiling. 0 disables tripping.
 pub fn record_set_breaker_window_debt_ceiling_e8s(state: &mut State, ceiling_e8s: u64) {
+    record_parameter_event(
+        state,
+        &Event::SetBreakerWindowDebtCeilingE8s {
+            ceiling_e8s,
+            timestamp: now(),
+        },
+    );
     state.breaker_window_debt_ceiling_e8s = ceiling_e8s;
-    record_event(&Event::SetBreakerWindowDebtCeilingE8s {
-        ceiling_e8s,
-        timestamp: now(),
-    });
 }
 
 /// Wave-11 BOT-001: records that `check_vaults` skipped an auto-cancel of an
@@ -2421,14 +5351,33 @@ pub fn record_borrow_from_vault(
     fee_amount: ICUSD,
     block_index: u64,
 ) {
+    let collateral_price = state
+        .vault_id_to_vaults
+        .get(&vault_id)
+        .and_then(|v| state.get_collateral_config(&v.collateral_type))
+        .and_then(|c| c.last_price)
+        .and_then(Decimal::from_f64)
+        .map(|p| p.normalize().to_string());
+    let timestamp = now();
+    crate::auto_deleverage::note_activity(state, vault_id, timestamp);
     record_event(&Event::BorrowFromVault {
         vault_id,
         block_index,
         fee_amount,
         borrowed_amount,
         caller: Some(ic_cdk::caller()),
-        timestamp: Some(now()),
+        timestamp: Some(timestamp),
+        collateral_price: collateral_price.clone(),
     });
+    crate::borrow_records::push_borrow_record(
+        state,
+        vault_id,
+        borrowed_amount,
+        fee_amount,
+        collateral_price,
+        block_index,
+        timestamp,
+    );
     state.borrow_from_vault(vault_id, borrowed_amount);
     // Fee is now minted to treasury in the async caller — no longer credited to liquidity pool.
 }
@@ -2440,6 +5389,7 @@ pub fn record_repayed_to_vault(
     vault_id: u64,
     repayed_amount: ICUSD,
     block_index: u64,
+    stable_fee: Option<StableRepayFee>,
 ) -> ICUSD {
     record_event(&Event::RepayToVault {
         vault_id,
@@ -2447,7 +5397,9 @@ pub fn record_repayed_to_vault(
         repayed_amount,
         caller: Some(ic_cdk::caller()),
         timestamp: Some(now()),
+        stable_fee,
     });
+    crate::auto_deleverage::note_activity(state, vault_id, now());
     let (interest_share, _) = state.repay_to_vault(vault_id, repayed_amount);
     interest_share
 }
@@ -2465,6 +5417,7 @@ pub fn record_add_margin_to_vault(
         caller: Some(ic_cdk::caller()),
         timestamp: Some(now()),
     });
+    crate::auto_deleverage::note_activity(state, vault_id, now());
     state.add_margin_to_vault(vault_id, margin_added);
 }
 
@@ -2536,6 +5489,16 @@ pub fn record_redemption_on_vaults(
             Some(vault_redemptions.clone())
         },
     });
+    for vr in &vault_redemptions {
+        record_event(&Event::PartialRedemption {
+            vault_id: vr.vault_id,
+            collateral_type: redeem_ct,
+            icusd_redeemed_e8s: vr.icusd_redeemed_e8s,
+            collateral_seized: vr.collateral_seized,
+            icusd_block_index,
+            timestamp: now(),
+        });
+    }
 
     // RED-001 (audit 2026-06-09): the payout is derived from the icUSD the
     // water-fill ACTUALLY retired, never from the requested claim. When the
@@ -2552,12 +5515,13 @@ pub fn record_redemption_on_vaults(
             .map(|v| v.icusd_redeemed_e8s)
             .sum::<u64>(),
     );
+    crate::redemption_caps::note_redeemed(state, &redeem_ct, consumed.to_u64(), now());
 
     // Wave-9 RED-002: route any redemption-side shortfall into the
     // Wave-8e deficit account. The pure helper takes an explicit
     // timestamp so unit tests can exercise the predicate without an
     // `ic_cdk::api::time()` panic — production callers pass `now()`.
-    let _shortfall = accrue_redemption_shortfall_at(
+    let shortfall = accrue_redemption_shortfall_at(
         state,
         owner,
         consumed,
@@ -2578,6 +5542,15 @@ pub fn record_redemption_on_vaults(
                 collateral_type: redeem_ct,
                 retry_count: 0,
                 op_nonce,
+                trace_id: crate::guard::current_trace(owner),
+            },
+        );
+        state.pending_redemption_records.insert(
+            icusd_block_index,
+            crate::redemption_cancel::PendingRedemptionRecord {
+                vault_redemptions,
+                shortfall_e8s: shortfall.to_u64(),
+                redeemed_at_ns: now(),
             },
         );
     }
@@ -2648,21 +5621,58 @@ pub fn accrue_redemption_shortfall_at(
                 shortfall.to_u64()
             );
         }
+        record_mode_transitions(state);
     }
     shortfall
 }
 
+/// `observed_seq` as for `record_margin_transfer`.
 pub fn record_redemption_transfered(
     state: &mut State,
     icusd_block_index: u64,
     icp_block_index: u64,
+    observed_seq: u64,
 ) {
-    record_event(&Event::RedemptionTransfered {
+    record_event_after(
+        &Event::RedemptionTransfered {
+            icusd_block_index,
+            icp_block_index,
+            timestamp: Some(now()),
+        },
+        observed_seq,
+    );
+    state.pending_redemption_transfer.remove(&icusd_block_index);
+    state.pending_redemption_records.remove(&icusd_block_index);
+}
+
+/// Record a cancelled redemption payout and reverse it. Returns the icUSD
+/// to mint back to `owner`.
+pub fn record_redemption_cancelled(
+    state: &mut State,
+    owner: Principal,
+    icusd_block_index: u64,
+    vault_redemptions: Vec<VaultRedemption>,
+    timestamp: u64,
+) -> ICUSD {
+    let icusd_refunded = ICUSD::new(
+        vault_redemptions
+            .iter()
+            .map(|vr| vr.icusd_redeemed_e8s)
+            .sum(),
+    );
+    crate::redemption_cancel::apply_cancel_pending_redemption(
+        state,
         icusd_block_index,
-        icp_block_index,
-        timestamp: Some(now()),
+        &vault_redemptions,
+    );
+    record_event(&Event::RedemptionCancelled {
+        owner,
+        icusd_block_index,
+        icusd_refunded,
+        vault_redemptions,
+        timestamp,
     });
-    state.pending_redemption_transfer.remove(&icusd_block_index);
+    icusd_refunded
 }
 
 pub fn record_collateral_withdrawn(
@@ -2693,6 +5703,7 @@ pub fn record_partial_collateral_withdrawn(
         caller: Some(ic_cdk::caller()),
         timestamp: Some(now()),
     });
+    crate::auto_deleverage::note_activity(state, vault_id, now());
     state.remove_margin_from_vault(vault_id, amount);
 }
 
@@ -2715,24 +5726,33 @@ pub fn record_withdraw_and_close_vault(
 }
 
 pub fn record_set_ckstable_repay_fee(state: &mut State, rate: Ratio) {
-    record_event(&Event::SetCkstableRepayFee {
-        rate: rate.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetCkstableRepayFee {
+            rate: rate.0.to_string(),
+        },
+    );
     state.ckstable_repay_fee = rate;
 }
 
 pub fn record_set_min_icusd_amount(state: &mut State, amount: ICUSD) {
-    record_event(&Event::SetMinIcusdAmount {
-        amount: amount.to_u64().to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetMinIcusdAmount {
+            amount: amount.to_u64().to_string(),
+        },
+    );
     state.min_icusd_amount = amount;
 }
 
 pub fn record_set_global_icusd_mint_cap(state: &mut State, amount: u64) {
-    record_event(&Event::SetGlobalIcusdMintCap {
-        amount: Some(amount.to_string()),
-        cap: None,
-    });
+    record_parameter_event(
+        state,
+        &Event::SetGlobalIcusdMintCap {
+            amount: Some(amount.to_string()),
+            cap: None,
+        },
+    );
     state.global_icusd_mint_cap = amount;
 }
 
@@ -2741,10 +5761,13 @@ pub fn record_set_stable_token_enabled(
     token_type: StableTokenType,
     enabled: bool,
 ) {
-    record_event(&Event::SetStableTokenEnabled {
-        token_type: token_type.clone(),
-        enabled,
-    });
+    record_parameter_event(
+        state,
+        &Event::SetStableTokenEnabled {
+            token_type: token_type.clone(),
+            enabled,
+        },
+    );
     match token_type {
         StableTokenType::CKUSDT => state.ckusdt_enabled = enabled,
         StableTokenType::CKUSDC => state.ckusdc_enabled = enabled,
@@ -2756,10 +5779,13 @@ pub fn record_set_stable_ledger_principal(
     token_type: StableTokenType,
     principal: Principal,
 ) {
-    record_event(&Event::SetStableLedgerPrincipal {
-        token_type: token_type.clone(),
-        principal,
-    });
+    record_parameter_event(
+        state,
+        &Event::SetStableLedgerPrincipal {
+            token_type: token_type.clone(),
+            principal,
+        },
+    );
     match token_type {
         StableTokenType::CKUSDT => state.ckusdt_ledger_principal = Some(principal),
         StableTokenType::CKUSDC => state.ckusdc_ledger_principal = Some(principal),
@@ -2767,25 +5793,28 @@ pub fn record_set_stable_ledger_principal(
 }
 
 pub fn record_set_treasury_principal(state: &mut State, principal: Principal) {
-    record_event(&Event::SetTreasuryPrincipal { principal });
+    record_parameter_event(state, &Event::SetTreasuryPrincipal { principal });
     state.treasury_principal = Some(principal);
 }
 
 pub fn record_set_stability_pool_principal(state: &mut State, principal: Principal) {
-    record_event(&Event::SetStabilityPoolPrincipal { principal });
+    record_parameter_event(state, &Event::SetStabilityPoolPrincipal { principal });
     state.stability_pool_canister = Some(principal);
 }
 
 pub fn record_set_liquidation_bot_principal(state: &mut State, principal: Principal) {
-    record_event(&Event::SetLiquidationBotPrincipal { principal });
+    record_parameter_event(state, &Event::SetLiquidationBotPrincipal { principal });
     state.liquidation_bot_principal = Some(principal);
 }
 
 pub fn record_set_bot_budget(state: &mut State, total_e8s: u64, start_timestamp: u64) {
-    record_event(&Event::SetBotBudget {
-        total_e8s,
-        start_timestamp,
-    });
+    record_parameter_event(
+        state,
+        &Event::SetBotBudget {
+            total_e8s,
+            start_timestamp,
+        },
+    );
     state.bot_budget_total_e8s = total_e8s;
     state.bot_budget_remaining_e8s = total_e8s;
     state.bot_budget_start_timestamp = start_timestamp;
@@ -2795,14 +5824,17 @@ pub fn record_set_bot_allowed_collateral_types(
     state: &mut State,
     collateral_types: Vec<Principal>,
 ) {
-    record_event(&Event::SetBotAllowedCollateralTypes {
-        collateral_types: collateral_types.clone(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetBotAllowedCollateralTypes {
+            collateral_types: collateral_types.clone(),
+        },
+    );
     state.bot_allowed_collateral_types = collateral_types.into_iter().collect();
 }
 
 pub fn record_set_bot_cr_tolerance_bps(state: &mut State, bps: u64) {
-    record_event(&Event::SetBotCrToleranceBps { bps });
+    record_parameter_event(state, &Event::SetBotCrToleranceBps { bps });
     state.bot_cr_tolerance_bps = bps;
 }
 
@@ -2815,109 +5847,156 @@ pub fn record_set_collateral_min_xrc_sources(
     collateral_type: CollateralType,
     min_xrc_sources: Option<u32>,
 ) {
-    record_event(&Event::SetCollateralMinXrcSources {
-        collateral_type,
-        min_xrc_sources,
-    });
+    record_parameter_event(
+        state,
+        &Event::SetCollateralMinXrcSources {
+            collateral_type,
+            min_xrc_sources,
+        },
+    );
     if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
         config.min_xrc_sources = min_xrc_sources;
     }
 }
 
 pub fn record_set_liquidation_bonus(state: &mut State, rate: Ratio) {
-    record_event(&Event::SetLiquidationBonus {
-        rate: rate.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetLiquidationBonus {
+            rate: rate.0.to_string(),
+        },
+    );
     state.liquidation_bonus = rate;
     state.sync_icp_collateral_config();
 }
 
 pub fn record_set_borrowing_fee(state: &mut State, rate: Ratio) {
-    record_event(&Event::SetBorrowingFee {
-        rate: rate.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetBorrowingFee {
+            rate: rate.0.to_string(),
+        },
+    );
     state.fee = rate;
     state.sync_icp_collateral_config();
 }
 
 pub fn record_set_redemption_fee_floor(state: &mut State, rate: Ratio) {
-    record_event(&Event::SetRedemptionFeeFloor {
-        rate: rate.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetRedemptionFeeFloor {
+            rate: rate.0.to_string(),
+        },
+    );
     state.redemption_fee_floor = rate;
     state.sync_icp_collateral_config();
 }
 
 pub fn record_set_redemption_fee_ceiling(state: &mut State, rate: Ratio) {
-    record_event(&Event::SetRedemptionFeeCeiling {
-        rate: rate.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetRedemptionFeeCeiling {
+            rate: rate.0.to_string(),
+        },
+    );
     state.redemption_fee_ceiling = rate;
     state.sync_icp_collateral_config();
 }
 
 pub fn record_set_max_partial_liquidation_ratio(state: &mut State, rate: Ratio) {
-    record_event(&Event::SetMaxPartialLiquidationRatio {
-        rate: rate.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetMaxPartialLiquidationRatio {
+            rate: rate.0.to_string(),
+        },
+    );
     state.max_partial_liquidation_ratio = rate;
 }
 
 pub fn record_set_recovery_target_cr(state: &mut State, rate: Ratio) {
-    record_event(&Event::SetRecoveryTargetCr {
-        rate: rate.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetRecoveryTargetCr {
+            rate: rate.0.to_string(),
+        },
+    );
     state.recovery_target_cr = rate;
     state.sync_icp_collateral_config();
 }
 
 pub fn record_set_recovery_cr_multiplier(state: &mut State, multiplier: Ratio) {
-    record_event(&Event::SetRecoveryCrMultiplier {
-        multiplier: multiplier.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetRecoveryCrMultiplier {
+            multiplier: multiplier.0.to_string(),
+        },
+    );
     state.recovery_cr_multiplier = multiplier;
     state.sync_icp_collateral_config();
 }
 
+pub fn record_set_liquidation_tip(state: &mut State, tip_e8s: u64) {
+    record_parameter_event(state, &Event::SetLiquidationTip { tip_e8s });
+    state.liquidation_tip_e8s = tip_e8s;
+}
+
 pub fn record_set_liquidation_protocol_share(state: &mut State, share: Ratio) {
-    record_event(&Event::SetLiquidationProtocolShare {
-        share: share.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetLiquidationProtocolShare {
+            share: share.0.to_string(),
+        },
+    );
     state.liquidation_protocol_share = share;
 }
 
 pub fn record_set_interest_pool_share(state: &mut State, share: Ratio) {
-    record_event(&Event::SetInterestPoolShare {
-        share: share.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetInterestPoolShare {
+            share: share.0.to_string(),
+        },
+    );
     state.interest_pool_share = share;
 }
 
 pub fn record_set_rmr_floor(state: &mut State, value: Ratio) {
-    record_event(&Event::SetRmrFloor {
-        value: value.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetRmrFloor {
+            value: value.0.to_string(),
+        },
+    );
     state.rmr_floor = value;
 }
 
 pub fn record_set_rmr_ceiling(state: &mut State, value: Ratio) {
-    record_event(&Event::SetRmrCeiling {
-        value: value.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetRmrCeiling {
+            value: value.0.to_string(),
+        },
+    );
     state.rmr_ceiling = value;
 }
 
 pub fn record_set_rmr_floor_cr(state: &mut State, value: Ratio) {
-    record_event(&Event::SetRmrFloorCr {
-        value: value.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetRmrFloorCr {
+            value: value.0.to_string(),
+        },
+    );
     state.rmr_floor_cr = value;
 }
 
 pub fn record_set_rmr_ceiling_cr(state: &mut State, value: Ratio) {
-    record_event(&Event::SetRmrCeilingCr {
-        value: value.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetRmrCeilingCr {
+            value: value.0.to_string(),
+        },
+    );
     state.rmr_ceiling_cr = value;
 }
 
@@ -2960,22 +6039,40 @@ pub fn record_update_collateral_config(
 }
 
 pub fn record_set_reserve_redemptions_enabled(state: &mut State, enabled: bool) {
-    record_event(&Event::SetReserveRedemptionsEnabled { enabled });
+    record_parameter_event(state, &Event::SetReserveRedemptionsEnabled { enabled });
     state.reserve_redemptions_enabled = enabled;
 }
 
 pub fn record_set_icpswap_routing_enabled(state: &mut State, enabled: bool) {
-    record_event(&Event::SetIcpswapRoutingEnabled { enabled });
+    record_parameter_event(state, &Event::SetIcpswapRoutingEnabled { enabled });
     state.icpswap_routing_enabled = enabled;
 }
 
 pub fn record_set_reserve_redemption_fee(state: &mut State, fee: Ratio) {
-    record_event(&Event::SetReserveRedemptionFee {
-        fee: fee.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetReserveRedemptionFee {
+            fee: fee.0.to_string(),
+        },
+    );
     state.reserve_redemption_fee = fee;
 }
 
+pub fn record_set_reserve_stable(state: &mut State, stable: crate::reserve_stables::ReserveStable) {
+    record_parameter_event(
+        state,
+        &Event::SetReserveStable {
+            stable: stable.clone(),
+        },
+    );
+    crate::reserve_stables::apply_set(state, stable);
+}
+
+pub fn record_remove_reserve_stable(state: &mut State, ledger: Principal) -> bool {
+    record_parameter_event(state, &Event::RemoveReserveStable { ledger });
+    crate::reserve_stables::apply_remove(state, ledger)
+}
+
 pub fn record_reserve_redemption(
     owner: Principal,
     icusd_amount: ICUSD,
@@ -3013,11 +6110,14 @@ pub fn record_set_recovery_parameters(
     recovery_borrowing_fee: Option<Ratio>,
     recovery_interest_rate_apr: Option<Ratio>,
 ) {
-    record_event(&Event::SetRecoveryParameters {
-        collateral_type,
-        recovery_borrowing_fee: recovery_borrowing_fee.map(|r| r.0.to_string()),
-        recovery_interest_rate_apr: recovery_interest_rate_apr.map(|r| r.0.to_string()),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetRecoveryParameters {
+            collateral_type,
+            recovery_borrowing_fee: recovery_borrowing_fee.map(|r| r.0.to_string()),
+            recovery_interest_rate_apr: recovery_interest_rate_apr.map(|r| r.0.to_string()),
+        },
+    );
     if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
         config.recovery_borrowing_fee = recovery_borrowing_fee;
         config.recovery_interest_rate_apr = recovery_interest_rate_apr;
@@ -3067,10 +6167,13 @@ pub fn record_set_rate_curve_markers(
         .map(|(cr, mult)| (cr.to_string(), mult.to_string()))
         .collect();
     let markers_json = serde_json::to_string(&serialized).unwrap_or_default();
-    record_event(&Event::SetRateCurveMarkers {
-        collateral_type: collateral_type.map(|ct| ct.to_text()),
-        markers: markers_json,
-    });
+    record_parameter_event(
+        state,
+        &Event::SetRateCurveMarkers {
+            collateral_type: collateral_type.map(|ct| ct.to_text()),
+            markers: markers_json,
+        },
+    );
     let parsed: Vec<RateMarker> = markers
         .iter()
         .map(|(cr, mult)| RateMarker {
@@ -3113,9 +6216,12 @@ pub fn record_set_recovery_rate_curve(
         })
         .collect();
     let markers_json = serde_json::to_string(&serialized).unwrap_or_default();
-    record_event(&Event::SetRecoveryRateCurve {
-        markers: markers_json,
-    });
+    record_parameter_event(
+        state,
+        &Event::SetRecoveryRateCurve {
+            markers: markers_json,
+        },
+    );
     state.recovery_rate_curve = markers
         .iter()
         .map(|(thresh, mult)| RecoveryRateMarker {
@@ -3130,9 +6236,12 @@ pub fn record_set_borrowing_fee_curve(state: &mut State, curve: Option<RateCurve
         Some(c) => serde_json::to_string(&c).unwrap_or_default(),
         None => "null".to_string(),
     };
-    record_event(&Event::SetBorrowingFeeCurve {
-        markers: markers_json,
-    });
+    record_parameter_event(
+        state,
+        &Event::SetBorrowingFeeCurve {
+            markers: markers_json,
+        },
+    );
     state.borrowing_fee_curve = curve;
 }
 
@@ -3141,10 +6250,13 @@ pub fn record_set_healthy_cr(
     collateral_type: CollateralType,
     healthy_cr: Option<Ratio>,
 ) {
-    record_event(&Event::SetHealthyCr {
-        collateral_type: collateral_type.to_text(),
-        healthy_cr: healthy_cr.map(|r| r.0.to_string()),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetHealthyCr {
+            collateral_type: collateral_type.to_text(),
+            healthy_cr: healthy_cr.map(|r| r.0.to_string()),
+        },
+    );
     if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
         config.healthy_cr = healthy_cr;
     }
@@ -3152,24 +6264,27 @@ pub fn record_set_healthy_cr(
 
 pub fn record_set_interest_split(state: &mut State, split: Vec<crate::state::InterestRecipient>) {
     let split_json = serde_json::to_string(&split).unwrap_or_default();
-    record_event(&Event::SetInterestSplit { split: split_json });
+    record_parameter_event(state, &Event::SetInterestSplit { split: split_json });
     state.interest_split = split;
 }
 
 pub fn record_set_three_pool_canister(state: &mut State, canister: Principal) {
-    record_event(&Event::SetThreePoolCanister { canister });
+    record_parameter_event(state, &Event::SetThreePoolCanister { canister });
     state.three_pool_canister = Some(canister);
 }
 
 pub fn record_set_amm1_canister(state: &mut State, canister: Principal) {
-    record_event(&Event::SetAmm1Canister { canister });
+    record_parameter_event(state, &Event::SetAmm1Canister { canister });
     state.amm1_canister = Some(canister);
 }
 
 pub fn record_set_amm1_pool_id(state: &mut State, pool_id: String) {
-    record_event(&Event::SetAmm1PoolId {
-        pool_id: pool_id.clone(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetAmm1PoolId {
+            pool_id: pool_id.clone(),
+        },
+    );
     state.amm1_pool_id = Some(pool_id);
 }
 
@@ -3178,12 +6293,15 @@ pub fn record_set_collateral_borrowing_fee(
     collateral_type: CollateralType,
     borrowing_fee: Ratio,
 ) {
-    record_event(&Event::SetCollateralBorrowingFee {
-        collateral_type,
-        borrowing_fee: Some(borrowing_fee.0.to_string()),
-        rate: None,
-        fee: None,
-    });
+    record_parameter_event(
+        state,
+        &Event::SetCollateralBorrowingFee {
+            collateral_type,
+            borrowing_fee: Some(borrowing_fee.0.to_string()),
+            rate: None,
+            fee: None,
+        },
+    );
     if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
         config.borrowing_fee = borrowing_fee;
     }
@@ -3195,10 +6313,13 @@ pub fn record_set_interest_rate(
     collateral_type: CollateralType,
     interest_rate_apr: Ratio,
 ) {
-    record_event(&Event::SetInterestRate {
-        collateral_type,
-        interest_rate_apr: interest_rate_apr.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetInterestRate {
+            collateral_type,
+            interest_rate_apr: interest_rate_apr.0.to_string(),
+        },
+    );
     if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
         config.interest_rate_apr = interest_rate_apr;
     }
@@ -3209,10 +6330,13 @@ pub fn record_set_collateral_liquidation_ratio(
     collateral_type: CollateralType,
     liquidation_ratio: Ratio,
 ) {
-    record_event(&Event::SetCollateralLiquidationRatio {
-        collateral_type,
-        liquidation_ratio: liquidation_ratio.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetCollateralLiquidationRatio {
+            collateral_type,
+            liquidation_ratio: liquidation_ratio.0.to_string(),
+        },
+    );
     if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
         config.liquidation_ratio = liquidation_ratio;
     }
@@ -3223,10 +6347,13 @@ pub fn record_set_collateral_borrow_threshold(
     collateral_type: CollateralType,
     borrow_threshold_ratio: Ratio,
 ) {
-    record_event(&Event::SetCollateralBorrowThreshold {
-        collateral_type,
-        borrow_threshold_ratio: borrow_threshold_ratio.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetCollateralBorrowThreshold {
+            collateral_type,
+            borrow_threshold_ratio: borrow_threshold_ratio.0.to_string(),
+        },
+    );
     if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
         config.borrow_threshold_ratio = borrow_threshold_ratio;
         // Keep the stored (but largely derived) recovery_target_cr in sync.
@@ -3239,10 +6366,13 @@ pub fn record_set_collateral_liquidation_bonus(
     collateral_type: CollateralType,
     liquidation_bonus: Ratio,
 ) {
-    record_event(&Event::SetCollateralLiquidationBonus {
-        collateral_type,
-        liquidation_bonus: liquidation_bonus.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetCollateralLiquidationBonus {
+            collateral_type,
+            liquidation_bonus: liquidation_bonus.0.to_string(),
+        },
+    );
     if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
         config.liquidation_bonus = liquidation_bonus;
     }
@@ -3253,10 +6383,13 @@ pub fn record_set_collateral_min_vault_debt(
     collateral_type: CollateralType,
     min_vault_debt: u64,
 ) {
-    record_event(&Event::SetCollateralMinVaultDebt {
-        collateral_type,
-        min_vault_debt,
-    });
+    record_parameter_event(
+        state,
+        &Event::SetCollateralMinVaultDebt {
+            collateral_type,
+            min_vault_debt,
+        },
+    );
     if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
         config.min_vault_debt = ICUSD::new(min_vault_debt);
     }
@@ -3267,10 +6400,13 @@ pub fn record_set_collateral_ledger_fee(
     collateral_type: CollateralType,
     ledger_fee: u64,
 ) {
-    record_event(&Event::SetCollateralLedgerFee {
-        collateral_type,
-        ledger_fee,
-    });
+    record_parameter_event(
+        state,
+        &Event::SetCollateralLedgerFee {
+            collateral_type,
+            ledger_fee,
+        },
+    );
     if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
         config.ledger_fee = ledger_fee;
     }
@@ -3281,10 +6417,13 @@ pub fn record_set_collateral_redemption_fee_floor(
     collateral_type: CollateralType,
     redemption_fee_floor: Ratio,
 ) {
-    record_event(&Event::SetCollateralRedemptionFeeFloor {
-        collateral_type,
-        redemption_fee_floor: redemption_fee_floor.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetCollateralRedemptionFeeFloor {
+            collateral_type,
+            redemption_fee_floor: redemption_fee_floor.0.to_string(),
+        },
+    );
     if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
         config.redemption_fee_floor = redemption_fee_floor;
     }
@@ -3295,10 +6434,13 @@ pub fn record_set_collateral_redemption_fee_ceiling(
     collateral_type: CollateralType,
     redemption_fee_ceiling: Ratio,
 ) {
-    record_event(&Event::SetCollateralRedemptionFeeCeiling {
-        collateral_type,
-        redemption_fee_ceiling: redemption_fee_ceiling.0.to_string(),
-    });
+    record_parameter_event(
+        state,
+        &Event::SetCollateralRedemptionFeeCeiling {
+            collateral_type,
+            redemption_fee_ceiling: redemption_fee_ceiling.0.to_string(),
+        },
+    );
     if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
         config.redemption_fee_ceiling = redemption_fee_ceiling;
     }
@@ -3309,10 +6451,13 @@ pub fn record_set_collateral_min_deposit(
     collateral_type: CollateralType,
     min_collateral_deposit: u64,
 ) {
-    record_event(&Event::SetCollateralMinDeposit {
-        collateral_type,
-        min_collateral_deposit,
-    });
+    record_parameter_event(
+        state,
+        &Event::SetCollateralMinDeposit {
+            collateral_type,
+            min_collateral_deposit,
+        },
+    );
     if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
         config.min_collateral_deposit = min_collateral_deposit;
     }
@@ -3323,13 +6468,191 @@ pub fn record_set_collateral_display_color(
     collateral_type: CollateralType,
     display_color: Option<String>,
 ) {
-    record_event(&Event::SetCollateralDisplayColor {
+    record_parameter_event(
+        state,
+        &Event::SetCollateralDisplayColor {
+            collateral_type,
+            display_color: display_color.clone(),
+        },
+    );
+    if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
+        config.display_color = display_color;
+    }
+}
+
+pub fn record_set_collateral_liquidation_rebate_mode(
+    state: &mut State,
+    collateral_type: CollateralType,
+    mode: LiquidationRebateMode,
+) {
+    record_parameter_event(
+        state,
+        &Event::SetCollateralLiquidationRebateMode {
+            collateral_type,
+            mode,
+        },
+    );
+    if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
+        config.liquidation_rebate_mode = mode;
+    }
+}
+
+pub fn record_set_collateral_secondary_price_source(
+    state: &mut State,
+    collateral_type: CollateralType,
+    source: Option<SecondaryPriceSource>,
+) {
+    record_parameter_event(
+        state,
+        &Event::SetCollateralSecondaryPriceSource {
+            collateral_type,
+            source: source.clone(),
+        },
+    );
+    state.set_secondary_price_source(&collateral_type, source);
+}
+
+pub fn record_price_dispute_cleared(
+    state: &mut State,
+    collateral_type: CollateralType,
+    resolved_by: Option<Principal>,
+    timestamp: u64,
+) {
+    record_event(&Event::PriceDisputeCleared {
         collateral_type,
-        display_color: display_color.clone(),
+        resolved_by,
+        timestamp,
     });
     if let Some(config) = state.collateral_configs.get_mut(&collateral_type) {
-        config.display_color = display_color;
+        config.price_disputed = false;
+    }
+}
+
+pub fn record_set_guardian_principals(state: &mut State, principals: Vec<Principal>) {
+    record_parameter_event(
+        state,
+        &Event::SetGuardianPrincipals {
+            principals: principals.clone(),
+        },
+    );
+    state.guardian_principals = principals.into_iter().collect();
+}
+
+pub fn record_set_mode_companion_canisters(state: &mut State, canisters: Vec<Principal>) {
+    record_parameter_event(
+        state,
+        &Event::SetModeCompanionCanisters {
+            canisters: canisters.clone(),
+        },
+    );
+    crate::mode_propagation::apply_set_companions(state, canisters);
+}
+
+/// Freeze `vault_id` until `expires_at_ns` and notify its owner. The request
+/// must already be validated with `vault_freeze::validate_freeze_arg`.
+pub fn record_vault_frozen(
+    state: &mut State,
+    vault_id: u64,
+    frozen_by: Principal,
+    reason: String,
+    expires_at_ns: u64,
+) {
+    let timestamp = now();
+    record_event(&Event::VaultFrozen {
+        vault_id,
+        frozen_by,
+        reason: reason.clone(),
+        expires_at_ns,
+        timestamp,
+    });
+    crate::vault_freeze::apply_freeze(
+        state,
+        vault_id,
+        frozen_by,
+        reason.clone(),
+        expires_at_ns,
+        timestamp,
+    );
+    crate::vault_freeze::notify_owner(
+        state,
+        vault_id,
+        crate::notifications::VaultNotificationKind::Frozen {
+            reason,
+            expires_at_ns,
+        },
+        timestamp,
+    );
+}
+
+pub fn record_vault_unfrozen(
+    state: &mut State,
+    vault_id: u64,
+    unfrozen_by: Principal,
+) -> Result<(), String> {
+    let timestamp = now();
+    crate::vault_freeze::apply_unfreeze(state, vault_id, timestamp)?;
+    record_event(&Event::VaultUnfrozen {
+        vault_id,
+        unfrozen_by,
+        timestamp,
+    });
+    crate::vault_freeze::notify_owner(
+        state,
+        vault_id,
+        crate::notifications::VaultNotificationKind::Unfrozen,
+        timestamp,
+    );
+    Ok(())
+}
+
+/// Co-approve a treasury withdrawal until `expires_at_ns`. The request must
+/// already be validated with `treasury_approvals::validate_approval`.
+pub fn record_treasury_withdrawal_approved(
+    state: &mut State,
+    request: crate::treasury_approvals::TreasuryWithdrawalRequest,
+    approved_by: Principal,
+    expires_at_ns: u64,
+) {
+    let timestamp = now();
+    record_event(&Event::TreasuryWithdrawalApproved {
+        request: request.clone(),
+        approved_by,
+        expires_at_ns,
+        timestamp,
+    });
+    crate::treasury_approvals::apply_approve(state, request, approved_by, expires_at_ns, timestamp);
+}
+
+pub fn record_treasury_withdrawal_approval_revoked(
+    state: &mut State,
+    request_id: u64,
+    revoked_by: Principal,
+) -> Result<(), String> {
+    let timestamp = now();
+    crate::treasury_approvals::apply_revoke(state, request_id, timestamp)?;
+    record_event(&Event::TreasuryWithdrawalApprovalRevoked {
+        request_id,
+        revoked_by,
+        timestamp,
+    });
+    Ok(())
+}
+
+/// Check the treasury's withdrawal against its co-approval, recording the
+/// first use.
+pub fn record_treasury_withdrawal_approval_consumed(
+    state: &mut State,
+    request: &crate::treasury_approvals::TreasuryWithdrawalRequest,
+) -> Result<(), String> {
+    let timestamp = now();
+    if crate::treasury_approvals::check_consume(state, request, timestamp)? {
+        record_event(&Event::TreasuryWithdrawalApprovalConsumed {
+            request_id: request.request_id,
+            timestamp,
+        });
+        crate::treasury_approvals::apply_consume(state, request.request_id, timestamp);
     }
+    Ok(())
 }
 
 pub fn record_accrue_interest(state: &mut State, now_nanos: u64) {
@@ -3339,12 +6662,115 @@ pub fn record_accrue_interest(state: &mut State, now_nanos: u64) {
     state.accrue_all_vault_interest(now_nanos);
 }
 
-pub fn record_price_update(collateral_type: CollateralType, price: Decimal, timestamp: u64) {
+pub fn record_set_collateral_swap_route(
+    state: &mut State,
+    from_collateral_type: CollateralType,
+    to_collateral_type: CollateralType,
+    route: Option<crate::collateral_swap::CollateralSwapRoute>,
+) {
+    record_parameter_event(
+        state,
+        &Event::SetCollateralSwapRoute {
+            from_collateral_type,
+            to_collateral_type,
+            route: route.clone(),
+        },
+    );
+    crate::collateral_swap::apply_set_route(state, from_collateral_type, to_collateral_type, route);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn record_vault_collateral_swapped(
+    state: &mut State,
+    vault_id: u64,
+    from_collateral_type: CollateralType,
+    to_collateral_type: CollateralType,
+    amount_in: u64,
+    amount_out: u64,
+    dex: Principal,
+    timestamp: u64,
+) {
+    record_event(&Event::VaultCollateralSwapped {
+        vault_id,
+        from_collateral_type,
+        to_collateral_type,
+        amount_in,
+        amount_out,
+        dex,
+        timestamp,
+    });
+    crate::auto_deleverage::note_activity(state, vault_id, timestamp);
+    crate::collateral_swap::apply_collateral_swap(state, vault_id, to_collateral_type, amount_out);
+}
+
+/// Record a failed collateral swap and take the allowance fees it cost off
+/// the vault.
+pub fn record_vault_collateral_swap_failed(
+    state: &mut State,
+    vault_id: u64,
+    collateral_type: CollateralType,
+    fees: u64,
+    timestamp: u64,
+) {
+    record_event(&Event::VaultCollateralSwapFailed {
+        vault_id,
+        collateral_type,
+        fees,
+        timestamp,
+    });
+    crate::collateral_swap::apply_collateral_swap_failed(state, vault_id, fees);
+}
+
+/// Record an owner's repayment from collateral and apply it. Returns the
+/// interest share of the repayment (for treasury routing).
+pub fn record_vault_repaid_from_collateral(
+    state: &mut State,
+    plan: &crate::repay_from_collateral::RepayFromCollateralPlan,
+    owner: Principal,
+    icusd_repaid: ICUSD,
+    timestamp: u64,
+) -> ICUSD {
+    record_event(&Event::VaultRepaidFromCollateral {
+        vault_id: plan.vault_id,
+        owner,
+        collateral_type: plan.collateral_type,
+        collateral_sold: plan.collateral_debit,
+        icusd_repaid,
+        dex: plan.route.dex,
+        timestamp,
+    });
+    crate::auto_deleverage::note_activity(state, plan.vault_id, timestamp);
+    crate::repay_from_collateral::apply_repay_from_collateral(
+        state,
+        plan.vault_id,
+        plan.collateral_debit,
+        icusd_repaid,
+    )
+}
+
+/// Log an accepted price and refresh the liquidatable set for its collateral.
+/// A run of rejections before it is closed with `PriceRejectionsCleared`.
+pub fn record_price_update(
+    state: &mut State,
+    collateral_type: CollateralType,
+    price: Decimal,
+    timestamp: u64,
+) {
+    if let Some(rejections) = state.price_rejections.remove(&collateral_type) {
+        record_event(&Event::PriceRejectionsCleared {
+            collateral_type,
+            rejections,
+            timestamp,
+        });
+    }
     record_event(&Event::PriceUpdate {
         collateral_type,
         price: price.to_string(),
         timestamp,
     });
+    state.apply_price_update(collateral_type, price, timestamp);
+    state.price_deviation_holds.remove(&collateral_type);
+    crate::liquidatable_set::on_price_update(state, collateral_type, timestamp);
 }
 
 #[cfg(test)]
@@ -3398,6 +6824,7 @@ mod filter_tests {
             block_index: 0,
             caller: Some(caller),
             timestamp: Some(ts),
+            collateral_price: None,
         }
     }
 
@@ -3408,6 +6835,7 @@ mod filter_tests {
             block_index: 0,
             caller: Some(caller),
             timestamp: Some(ts),
+            stable_fee: None,
         }
     }
 
@@ -3418,6 +6846,7 @@ mod filter_tests {
             icp_rate: UsdIcp::new(Decimal::from(5u32)),
             liquidator: Some(liquidator),
             timestamp: Some(ts),
+            collateral_seized: None,
         }
     }
 
@@ -0,0 +1,132 @@
+//! Ordering guarantees of the event log.
+//!
+//! Every event gets a `CausalityToken` when it is recorded, kept in a side
+//! log index-aligned with the event log (see `storage::record_event_after`):
+//!
+//! - `seq` is the event's position in the log. Live state applies events in
+//!   `seq` order and replay applies them in the same order, so replay is
+//!   deterministic however the flows that recorded them interleaved.
+//! - `observed_seq` is how many events the recording flow had seen when it
+//!   read the state the event was computed from. A flow that records in the
+//!   message it read state in has `observed_seq == seq`. A flow that awaits
+//!   a call in between (a pending-transfer payout, a liquidation pulling the
+//!   liquidator's payment) captures `storage::observe_events()` before the
+//!   call, so its `observed_seq` trails `seq` by the events other flows
+//!   recorded meanwhile.
+//!
+//! Because replay applies events in log order, an event recorded after an
+//! await must carry what was applied at record time (the capped amounts of a
+//! liquidation, ...), not what was planned at observation time.
+//! `CausalityToken::interleaved` names the events that landed in between,
+//! which is where to look when a replayed state and the live one disagree.
+//!
+//! `post_upgrade` checks the tokens of the events it is about to replay with
+//! `check_order` and refuses a log whose tokens do not follow the log
+//! position one by one, or that claim an observation after their own event.
+//! Events recorded before the side log shipped have no token and are not
+//! checked.
+
+use crate::event::ReplayLogError;
+use candid::{CandidType, Deserialize};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::ops::Range;
+
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CausalityToken {
+    /// Position of the event in the log.
+    pub seq: u64,
+    /// Events the recording flow had seen, at most `seq`.
+    pub observed_seq: u64,
+}
+
+impl CausalityToken {
+    /// The token of an event recorded at `seq` by a flow that observed
+    /// `observed_seq` events. An observation past the event is clamped.
+    pub fn new(seq: u64, observed_seq: u64) -> Self {
+        Self {
+            seq,
+            observed_seq: observed_seq.min(seq),
+        }
+    }
+
+    /// Log positions of the events other flows recorded between this
+    /// event's observation and the event itself.
+    pub fn interleaved(&self) -> Range<u64> {
+        self.observed_seq..self.seq
+    }
+
+    /// Events recorded between the observation and the event.
+    pub fn lag(&self) -> u64 {
+        self.seq.saturating_sub(self.observed_seq)
+    }
+}
+
+impl Storable for CausalityToken {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut buf = Vec::with_capacity(16);
+        buf.extend_from_slice(&self.seq.to_le_bytes());
+        buf.extend_from_slice(&self.observed_seq.to_le_bytes());
+        Cow::Owned(buf)
+    }
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&bytes[0..8]);
+        let seq = u64::from_le_bytes(word);
+        word.copy_from_slice(&bytes[8..16]);
+        Self {
+            seq,
+            observed_seq: u64::from_le_bytes(word),
+        }
+    }
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 16,
+        is_fixed_size: true,
+    };
+}
+
+/// What `check_order` found in a run of tokens.
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderSummary {
+    /// Tokens checked.
+    pub checked: u64,
+    /// Events recorded after other flows' events landed on their
+    /// observation.
+    pub interleaved: u64,
+    /// The largest `lag` seen.
+    pub max_lag: u64,
+}
+
+/// Check the tokens of consecutive events starting at log position
+/// `first_index`: each token's `seq` must be its position, so `seq` is
+/// strictly increasing with no gaps, and no event may have observed more
+/// events than precede it.
+pub fn check_order(
+    first_index: u64,
+    tokens: impl IntoIterator<Item = CausalityToken>,
+) -> Result<OrderSummary, ReplayLogError> {
+    let mut summary = OrderSummary::default();
+    for (offset, token) in tokens.into_iter().enumerate() {
+        let index = first_index + offset as u64;
+        if token.seq != index {
+            return Err(ReplayLogError::InconsistentLog(format!(
+                "event {} carries sequence number {}",
+                index, token.seq
+            )));
+        }
+        if token.observed_seq > token.seq {
+            return Err(ReplayLogError::InconsistentLog(format!(
+                "event {} observed {} events, more than precede it",
+                index, token.observed_seq
+            )));
+        }
+        summary.checked += 1;
+        if token.lag() > 0 {
+            summary.interleaved += 1;
+            summary.max_lag = summary.max_lag.max(token.lag());
+        }
+    }
+    Ok(summary)
+}
@@ -0,0 +1,194 @@
+//! Fee invoices: everything a principal paid the protocol in fees over a
+//! period, line by line, with a total per token.
+//!
+//! Borrowing, redemption and stable-repay fees come from the events
+//! involving the principal (`Event::involves_principal`), the same index
+//! `get_events_by_principal` serves. Liquidation penalties are paid by the
+//! vault owner, whom the liquidation events do not name, so they come from
+//! the owner's liquidation receipts (see `liquidation_receipts`): the bonus
+//! the liquidator and the protocol took above the debt repaid. Receipts
+//! live in the state snapshot and keep the newest
+//! `MAX_RECEIPTS_PER_OWNER` per owner.
+//!
+//! Amounts are gross: borrowing-fee rebates (`campaigns`) and sponsored
+//! ledger fees (`fee_sponsorship`) are not netted out. Stable repayments
+//! recorded before their event carried the surcharge have no line.
+
+use crate::event::Event;
+use crate::state::State;
+use crate::EventTimeRange;
+use candid::{CandidType, Deserialize, Principal};
+use std::collections::BTreeMap;
+
+/// Lines kept in an invoice; totals always cover every fee in the period.
+pub const MAX_FEE_INVOICE_LINES: usize = 1_000;
+
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum FeeKind {
+    Borrowing,
+    Redemption,
+    /// The ckUSDT/ckUSDC surcharge of a stable-token repayment.
+    StableRepay,
+    LiquidationPenalty,
+}
+
+/// Where a line comes from.
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum FeeLineSource {
+    /// Event log index.
+    Event(u64),
+    /// Liquidation receipt id.
+    LiquidationReceipt(u64),
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct FeeInvoiceLine {
+    pub timestamp: u64,
+    pub kind: FeeKind,
+    pub source: FeeLineSource,
+    /// `None` for redemptions, which walk several vaults.
+    pub vault_id: Option<u64>,
+    /// Ledger the fee is denominated in.
+    pub token: Principal,
+    /// In the token's native units.
+    pub amount: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct FeeInvoiceTotal {
+    pub token: Principal,
+    pub amount: u64,
+    pub lines: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct FeeInvoice {
+    pub principal: Principal,
+    pub period: EventTimeRange,
+    /// Oldest first. The newest `MAX_FEE_INVOICE_LINES` when truncated.
+    pub lines: Vec<FeeInvoiceLine>,
+    pub lines_truncated: bool,
+    /// One entry per token, by ledger principal.
+    pub totals: Vec<FeeInvoiceTotal>,
+}
+
+/// The fee line `event` is for `principal`, if any. `event_index` is its
+/// position in the log.
+pub fn event_fee_line(
+    state: &State,
+    principal: &Principal,
+    event_index: u64,
+    event: &Event,
+) -> Option<FeeInvoiceLine> {
+    if !event.involves_principal(principal) {
+        return None;
+    }
+    let timestamp = event.timestamp_ns()?;
+    let (kind, vault_id, token, amount) = match event {
+        Event::BorrowFromVault {
+            vault_id,
+            fee_amount,
+            ..
+        } => (
+            FeeKind::Borrowing,
+            Some(*vault_id),
+            state.icusd_ledger_principal,
+            fee_amount.to_u64(),
+        ),
+        Event::RedemptionOnVaults { fee_amount, .. }
+        | Event::ReserveRedemption { fee_amount, .. } => (
+            FeeKind::Redemption,
+            None,
+            state.icusd_ledger_principal,
+            fee_amount.to_u64(),
+        ),
+        Event::RepayToVault {
+            vault_id,
+            stable_fee: Some(fee),
+            ..
+        } => (
+            FeeKind::StableRepay,
+            Some(*vault_id),
+            fee.ledger,
+            fee.fee_e6s,
+        ),
+        _ => return None,
+    };
+    (amount > 0).then_some(FeeInvoiceLine {
+        timestamp,
+        kind,
+        source: FeeLineSource::Event(event_index),
+        vault_id,
+        token,
+        amount,
+    })
+}
+
+/// Liquidation penalties `principal`'s vaults paid within `period`.
+pub fn liquidation_penalty_lines(
+    state: &State,
+    principal: &Principal,
+    period: &EventTimeRange,
+) -> Vec<FeeInvoiceLine> {
+    let Some(receipts) = state.liquidation_receipts.get(principal) else {
+        return Vec::new();
+    };
+    receipts
+        .iter()
+        .filter(|r| r.bonus_paid > 0)
+        .filter(|r| r.created_at_ns >= period.start_ns && r.created_at_ns <= period.end_ns)
+        .map(|r| FeeInvoiceLine {
+            timestamp: r.created_at_ns,
+            kind: FeeKind::LiquidationPenalty,
+            source: FeeLineSource::LiquidationReceipt(r.id),
+            vault_id: Some(r.vault_id),
+            // Pre-multi-collateral vaults carry the anonymous sentinel for ICP.
+            token: if r.collateral_type == Principal::anonymous() {
+                state.icp_ledger_principal
+            } else {
+                r.collateral_type
+            },
+            amount: r.bonus_paid,
+        })
+        .collect()
+}
+
+/// `principal`'s invoice for `period`, from the event log `events` (as
+/// `(event_log_index, event)`) and its liquidation receipts.
+pub fn fee_invoice(
+    state: &State,
+    principal: Principal,
+    period: EventTimeRange,
+    events: impl Iterator<Item = (u64, Event)>,
+) -> FeeInvoice {
+    let in_period = |ts: u64| ts >= period.start_ns && ts <= period.end_ns;
+    let mut lines: Vec<FeeInvoiceLine> = events
+        .filter_map(|(idx, event)| event_fee_line(state, &principal, idx, &event))
+        .filter(|line| in_period(line.timestamp))
+        .collect();
+    lines.extend(liquidation_penalty_lines(state, &principal, &period));
+    lines.sort_by_key(|line| line.timestamp);
+
+    let mut totals: BTreeMap<Principal, FeeInvoiceTotal> = BTreeMap::new();
+    for line in &lines {
+        let total = totals.entry(line.token).or_insert(FeeInvoiceTotal {
+            token: line.token,
+            amount: 0,
+            lines: 0,
+        });
+        total.amount = total.amount.saturating_add(line.amount);
+        total.lines += 1;
+    }
+
+    let lines_truncated = lines.len() > MAX_FEE_INVOICE_LINES;
+    if lines_truncated {
+        lines.drain(..lines.len() - MAX_FEE_INVOICE_LINES);
+    }
+    FeeInvoice {
+        principal,
+        period,
+        lines,
+        lines_truncated,
+        totals: totals.into_values().collect(),
+    }
+}
@@ -0,0 +1,306 @@
+//! Ledger fee sponsorship for small vault operations.
+//!
+//! Topping up a vault with a tiny margin costs the owner a full collateral
+//! ledger fee, which can be a large share of the deposit. The sponsorship
+//! pool refunds that fee: when a verified user adds margin worth at most
+//! `max_operation_value_e8s` to their own vault, the vault is credited the
+//! margin plus the ledger fee the user paid, and the fee is drawn from the
+//! pool's balance in that ledger. The user still needs the fee in their
+//! wallet for the transfer itself.
+//!
+//! The treasury (or the developer) funds the pool per ledger with
+//! `fund_fee_sponsorship`, which pulls the tokens like `donate`. The
+//! developer can send unused balance back to the treasury. Pool balances
+//! stay in the backend's account and count as tracked for
+//! `admin_sweep_to_treasury`.
+//!
+//! Abuse protection: only principals the developer has verified are
+//! sponsored, only for their own vaults, at most
+//! `daily_operations_per_user` times per UTC day, and never beyond the
+//! pool's balance. Config, verification, funding and each sponsored fee are
+//! logged, so the pool balances and daily counts are rebuilt exactly.
+
+use crate::guard::GuardPrincipal;
+use crate::logs::INFO;
+use crate::management;
+use crate::state::{mutate_state, read_state, State};
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use serde::Serialize;
+
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// A funding must be worth this many of its ledger's fees.
+pub const MIN_FUNDING_FEE_MULTIPLE: u64 = 100;
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeSponsorshipConfig {
+    pub enabled: bool,
+    /// Operations worth more than this, in USD e8s, pay their own fee.
+    pub max_operation_value_e8s: u64,
+    /// Sponsored operations per user per UTC day.
+    pub daily_operations_per_user: u32,
+}
+
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SponsorshipPool {
+    /// Available for fees, in ledger units.
+    pub balance: u64,
+    pub total_funded: u64,
+    /// Fees refunded to users.
+    pub total_sponsored: u64,
+    /// Sent back to the treasury, ledger fees included.
+    pub total_returned: u64,
+    pub operations: u64,
+}
+
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SponsorshipUsage {
+    /// UTC day (days since the epoch) `operations` counts.
+    pub day: u64,
+    pub operations: u32,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct FeeSponsorshipPoolEntry {
+    pub ledger: Principal,
+    pub pool: SponsorshipPool,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct FeeSponsorshipStatus {
+    pub config: Option<FeeSponsorshipConfig>,
+    pub pools: Vec<FeeSponsorshipPoolEntry>,
+    pub verified_users: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct FeeSponsorshipAllowance {
+    pub verified: bool,
+    pub used_today: u32,
+    pub remaining_today: u32,
+}
+
+fn day(now: u64) -> u64 {
+    now / NANOS_PER_DAY
+}
+
+fn used_today(state: &State, user: &Principal, now: u64) -> u32 {
+    state
+        .fee_sponsorship_usage
+        .get(user)
+        .filter(|usage| usage.day == day(now))
+        .map_or(0, |usage| usage.operations)
+}
+
+/// Smallest funding accepted in `ledger`, or why it cannot be funded.
+pub fn minimum_funding(state: &State, ledger: &Principal) -> Result<u64, ProtocolError> {
+    match state.get_collateral_config(ledger) {
+        Some(config) if *ledger != Principal::anonymous() => {
+            Ok(config.ledger_fee.saturating_mul(MIN_FUNDING_FEE_MULTIPLE))
+        }
+        _ => Err(ProtocolError::GenericError(format!(
+            "{} is not a registered collateral",
+            ledger
+        ))),
+    }
+}
+
+/// The fee to refund `user` for adding `amount` of margin to a vault of
+/// `owner` in `collateral_type` (held in `ledger`), or `None` when the
+/// operation is not sponsored.
+pub fn sponsored_fee(
+    state: &State,
+    user: Principal,
+    owner: Principal,
+    collateral_type: &Principal,
+    ledger: &Principal,
+    amount: u64,
+    now: u64,
+) -> Option<u64> {
+    let config = state.fee_sponsorship_config.as_ref()?;
+    if !config.enabled || user != owner || !state.fee_sponsorship_verified.contains(&user) {
+        return None;
+    }
+    if used_today(state, &user, now) >= config.daily_operations_per_user {
+        return None;
+    }
+    let collateral = state.get_collateral_config(collateral_type)?;
+    let price = state.get_collateral_price_decimal(collateral_type)?;
+    let value = crate::numeric::collateral_usd_value(amount, price, collateral.decimals);
+    if value.to_u64() > config.max_operation_value_e8s {
+        return None;
+    }
+    let fee = collateral.ledger_fee;
+    let balance = state.fee_sponsorship_pools.get(ledger)?.balance;
+    (fee > 0 && balance >= fee).then_some(fee)
+}
+
+/// Draw a sponsored `fee` from the `ledger` pool and count it against
+/// `user`'s operations for the UTC day of `timestamp`, starting a new count
+/// on a new day.
+pub fn apply_sponsored(
+    state: &mut State,
+    user: Principal,
+    ledger: Principal,
+    fee: u64,
+    timestamp: u64,
+) {
+    let pool = state.fee_sponsorship_pools.entry(ledger).or_default();
+    pool.balance = pool.balance.saturating_sub(fee);
+    pool.total_sponsored = pool.total_sponsored.saturating_add(fee);
+    pool.operations += 1;
+    let usage = state.fee_sponsorship_usage.entry(user).or_default();
+    if usage.day != day(timestamp) {
+        *usage = SponsorshipUsage {
+            day: day(timestamp),
+            operations: 0,
+        };
+    }
+    usage.operations += 1;
+}
+
+/// Add `amount` to the `ledger` pool, creating it on first funding.
+pub fn apply_funding(state: &mut State, ledger: Principal, amount: u64) {
+    let pool = state.fee_sponsorship_pools.entry(ledger).or_default();
+    pool.balance = pool.balance.saturating_add(amount);
+    pool.total_funded = pool.total_funded.saturating_add(amount);
+}
+
+/// Take `amount` plus the transfer `fee` out of the `ledger` pool, ahead of
+/// sending `amount` back to the treasury.
+pub fn take_for_return(
+    state: &mut State,
+    ledger: Principal,
+    amount: u64,
+    fee: u64,
+) -> Result<(), ProtocolError> {
+    let pool = state.fee_sponsorship_pools.entry(ledger).or_default();
+    let needed = amount.saturating_add(fee);
+    if pool.balance < needed {
+        return Err(ProtocolError::GenericError(format!(
+            "The {} sponsorship pool holds {}, {} needed",
+            ledger, pool.balance, needed
+        )));
+    }
+    pool.balance -= needed;
+    pool.total_returned = pool.total_returned.saturating_add(needed);
+    Ok(())
+}
+
+/// Undo `take_for_return` after a failed transfer.
+pub fn restore_return(state: &mut State, ledger: Principal, amount: u64, fee: u64) {
+    let pool = state.fee_sponsorship_pools.entry(ledger).or_default();
+    let taken = amount.saturating_add(fee);
+    pool.balance = pool.balance.saturating_add(taken);
+    pool.total_returned = pool.total_returned.saturating_sub(taken);
+}
+
+pub fn status(state: &State) -> FeeSponsorshipStatus {
+    FeeSponsorshipStatus {
+        config: state.fee_sponsorship_config.clone(),
+        pools: state
+            .fee_sponsorship_pools
+            .iter()
+            .map(|(ledger, pool)| FeeSponsorshipPoolEntry {
+                ledger: *ledger,
+                pool: pool.clone(),
+            })
+            .collect(),
+        verified_users: state.fee_sponsorship_verified.len() as u64,
+    }
+}
+
+pub fn allowance(state: &State, user: &Principal, now: u64) -> FeeSponsorshipAllowance {
+    let used = used_today(state, user, now);
+    let cap = state
+        .fee_sponsorship_config
+        .as_ref()
+        .filter(|c| c.enabled)
+        .map_or(0, |c| c.daily_operations_per_user);
+    FeeSponsorshipAllowance {
+        verified: state.fee_sponsorship_verified.contains(user),
+        used_today: used,
+        remaining_today: cap.saturating_sub(used),
+    }
+}
+
+/// Pull `amount` of `ledger` from the caller into the sponsorship pool.
+pub async fn fund(ledger: Principal, amount: u64) -> Result<u64, ProtocolError> {
+    let funder = ic_cdk::caller();
+    let _guard_principal = GuardPrincipal::new(funder, "fund_fee_sponsorship")?;
+    let minimum_amount = read_state(|s| minimum_funding(s, &ledger))?;
+    if amount < minimum_amount {
+        return Err(ProtocolError::AmountTooLow { minimum_amount });
+    }
+
+    let block_index = management::transfer_collateral_from(amount, funder, ledger)
+        .await
+        .map_err(|e| ProtocolError::TransferFromError(e, amount))?;
+    mutate_state(|s| {
+        crate::event::record_fund_fee_sponsorship(
+            s,
+            funder,
+            ledger,
+            amount,
+            block_index,
+            ic_cdk::api::time(),
+        )
+    });
+    log!(
+        INFO,
+        "[fund_fee_sponsorship] {} added {} of {} (block {})",
+        funder,
+        amount,
+        ledger,
+        block_index
+    );
+    Ok(block_index)
+}
+
+/// Send `amount` of the `ledger` pool back to the treasury. The pool also
+/// pays the transfer fee. The balance is taken before the transfer and put
+/// back if it fails.
+pub async fn return_to_treasury(ledger: Principal, amount: u64) -> Result<u64, ProtocolError> {
+    let (treasury, fee) = read_state(|s| {
+        let treasury = s.treasury_principal.ok_or_else(|| {
+            ProtocolError::GenericError("Treasury principal not configured".to_string())
+        })?;
+        let fee = s
+            .get_collateral_config(&ledger)
+            .map(|c| c.ledger_fee)
+            .ok_or_else(|| {
+                ProtocolError::GenericError(format!("{} is not a registered collateral", ledger))
+            })?;
+        Ok::<_, ProtocolError>((treasury, fee))
+    })?;
+    mutate_state(|s| take_for_return(s, ledger, amount, fee))?;
+
+    match management::transfer_collateral(amount, treasury, ledger).await {
+        Ok(block_index) => {
+            crate::event::record_return_fee_sponsorship(
+                ledger,
+                amount,
+                fee,
+                block_index,
+                ic_cdk::api::time(),
+            );
+            log!(
+                INFO,
+                "[return_fee_sponsorship] returned {} of {} to the treasury (block {})",
+                amount,
+                ledger,
+                block_index
+            );
+            Ok(block_index)
+        }
+        Err(e) => {
+            mutate_state(|s| restore_return(s, ledger, amount, fee));
+            Err(ProtocolError::GenericError(format!(
+                "Transfer failed: {:?}",
+                e
+            )))
+        }
+    }
+}
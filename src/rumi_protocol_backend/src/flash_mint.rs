@@ -0,0 +1,227 @@
+//! Flash mints of icUSD.
+//!
+//! `flash_mint(amount, callback_canister, callback_method)` mints `amount`
+//! icUSD to `callback_canister`, calls `callback_method` on it with a
+//! `FlashMintCallbackArg`, and then pulls `amount + fee` back from it with
+//! `icrc2_transfer_from`. The backend is the icUSD minting account, so the
+//! pull burns the repayment; the fee is then minted to the treasury. The
+//! callback must approve the backend for `repay_e8s` before it returns.
+//!
+//! A ledger mint cannot be rolled back the way an EVM transaction reverts,
+//! so the risk is bounded instead: only callback canisters the developer
+//! allowlists (`set_flash_mint_config`) may receive a flash mint, each mint
+//! is capped at `max_amount_e8s`, and a callback that fails to repay is
+//! dropped from the allowlist with the unrepaid icUSD accrued to the
+//! protocol deficit, since nothing backs it. Anyone may initiate a flash
+//! mint to an allowlisted callback; callbacks check `initiator` themselves.
+
+use crate::event::DeficitSource;
+use crate::guard::GuardPrincipal;
+use crate::logs::INFO;
+use crate::management;
+use crate::numeric::ICUSD;
+use crate::state::{mutate_state, read_state, State};
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use serde::Serialize;
+
+/// Highest fee the developer may set (10%).
+pub const MAX_FLASH_MINT_FEE_BPS: u64 = 1_000;
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlashMintConfig {
+    /// Fee on the minted amount, in basis points, rounded up.
+    pub fee_bps: u64,
+    /// Most icUSD (e8s) a single flash mint may mint.
+    pub max_amount_e8s: u64,
+    /// Canisters that may receive flash mints.
+    pub allowed_callbacks: Vec<Principal>,
+}
+
+/// Sent to the callback method.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct FlashMintCallbackArg {
+    /// Who called `flash_mint`.
+    pub initiator: Principal,
+    pub amount_e8s: u64,
+    pub fee_e8s: u64,
+    /// What the backend pulls back once the callback returns: `amount_e8s`
+    /// plus `fee_e8s`.
+    pub repay_e8s: u64,
+    /// Ledger block of the mint to the callback.
+    pub mint_block_index: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct FlashMintSuccess {
+    pub mint_block_index: u64,
+    pub repay_block_index: u64,
+    pub fee_e8s: u64,
+}
+
+pub fn validate_config(config: &FlashMintConfig) -> Result<(), String> {
+    if config.fee_bps > MAX_FLASH_MINT_FEE_BPS {
+        return Err(format!(
+            "fee_bps must be at most {}",
+            MAX_FLASH_MINT_FEE_BPS
+        ));
+    }
+    if config.max_amount_e8s == 0 {
+        return Err(
+            "max_amount_e8s must be positive; pass None to disable flash mints".to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Install (`Some`) or remove (`None`) the flash-mint config. A new config
+/// replaces the allowlist too, re-admitting callbacks dropped for a default.
+pub fn apply_set_config(state: &mut State, config: Option<FlashMintConfig>) {
+    state.flash_mint = config;
+}
+
+/// Drop `callback` from the allowlist after it failed to repay; only a new
+/// config can let it flash again.
+pub fn apply_default(state: &mut State, callback: &Principal) {
+    if let Some(config) = state.flash_mint.as_mut() {
+        config.allowed_callbacks.retain(|c| c != callback);
+    }
+}
+
+/// The fee on a flash mint of `amount_e8s`, rounded up so a nonzero rate
+/// never charges nothing.
+pub fn fee_e8s(config: &FlashMintConfig, amount_e8s: u64) -> u64 {
+    (amount_e8s as u128 * config.fee_bps as u128).div_ceil(10_000) as u64
+}
+
+/// The fee for flashing `amount_e8s` to `callback`, or why it may not.
+pub fn check(state: &State, callback: &Principal, amount_e8s: u64) -> Result<u64, ProtocolError> {
+    let Some(config) = state.flash_mint.as_ref() else {
+        return Err(ProtocolError::TemporarilyUnavailable(
+            "Flash mints are disabled".to_string(),
+        ));
+    };
+    if !config.allowed_callbacks.contains(callback) {
+        return Err(ProtocolError::GenericError(format!(
+            "{} is not an allowed flash-mint callback",
+            callback
+        )));
+    }
+    if amount_e8s == 0 {
+        return Err(ProtocolError::AmountTooLow { minimum_amount: 1 });
+    }
+    if amount_e8s > config.max_amount_e8s {
+        return Err(ProtocolError::GenericError(format!(
+            "Flash mint of {} icUSD e8s exceeds the {} e8s limit",
+            amount_e8s, config.max_amount_e8s
+        )));
+    }
+    Ok(fee_e8s(config, amount_e8s))
+}
+
+pub async fn flash_mint(
+    amount_e8s: u64,
+    callback_canister: Principal,
+    callback_method: String,
+) -> Result<FlashMintSuccess, ProtocolError> {
+    let initiator = ic_cdk::caller();
+    let _guard_principal = GuardPrincipal::new(initiator, "flash_mint")?;
+    let fee_e8s = read_state(|s| check(s, &callback_canister, amount_e8s))?;
+    let repay_e8s = amount_e8s + fee_e8s;
+
+    let mint_block_index = management::mint_icusd(ICUSD::new(amount_e8s), callback_canister)
+        .await
+        .map_err(ProtocolError::TransferError)?;
+
+    let arg = FlashMintCallbackArg {
+        initiator,
+        amount_e8s,
+        fee_e8s,
+        repay_e8s,
+        mint_block_index,
+    };
+    // A rejected callback still holds the minted icUSD and may have approved
+    // the repayment before failing, so the pull is attempted either way.
+    let callback: Result<(), _> = ic_cdk::call(callback_canister, &callback_method, (arg,)).await;
+    if let Err((code, msg)) = &callback {
+        log!(
+            INFO,
+            "[flash_mint] callback {}.{} rejected: {:?} {}",
+            callback_canister,
+            callback_method,
+            code,
+            msg
+        );
+    }
+
+    let now = ic_cdk::api::time();
+    match management::transfer_icusd_from(ICUSD::new(repay_e8s), callback_canister).await {
+        Ok(repay_block_index) => {
+            mutate_state(|s| {
+                crate::event::record_flash_mint(
+                    s,
+                    initiator,
+                    callback_canister,
+                    amount_e8s,
+                    fee_e8s,
+                    mint_block_index,
+                    Some(repay_block_index),
+                    now,
+                )
+            });
+            crate::treasury::mint_flash_mint_fee_to_treasury(ICUSD::new(fee_e8s)).await;
+            log!(
+                INFO,
+                "[flash_mint] {} flashed {} icUSD e8s through {} (fee {})",
+                initiator,
+                amount_e8s,
+                callback_canister,
+                fee_e8s
+            );
+            Ok(FlashMintSuccess {
+                mint_block_index,
+                repay_block_index,
+                fee_e8s,
+            })
+        }
+        Err(error) => {
+            mutate_state(|s| {
+                crate::event::record_flash_mint(
+                    s,
+                    initiator,
+                    callback_canister,
+                    amount_e8s,
+                    fee_e8s,
+                    mint_block_index,
+                    None,
+                    now,
+                );
+                crate::event::record_deficit_accrued(
+                    s,
+                    DeficitSource::FlashMint {
+                        callback: callback_canister,
+                    },
+                    ICUSD::new(amount_e8s),
+                    now,
+                );
+                if s.check_deficit_readonly_latch() {
+                    log!(
+                        INFO,
+                        "[flash_mint] deficit threshold {} crossed; auto-latched ReadOnly",
+                        s.deficit_readonly_threshold_e8s
+                    );
+                }
+                crate::event::record_mode_transitions(s);
+            });
+            log!(
+                INFO,
+                "[flash_mint] {} did not repay {} icUSD e8s: {:?}; removed from the allowlist",
+                callback_canister,
+                repay_e8s,
+                error
+            );
+            Err(ProtocolError::TransferFromError(error, repay_e8s))
+        }
+    }
+}
@@ -0,0 +1,264 @@
+//! Failed-operation forensics for support.
+//!
+//! When a user reports that an operation failed, `get_operation_forensics`
+//! gathers everything the canister still remembers about them in one reply:
+//! the guard they hold now, their recent guard history (including guards
+//! they were refused), the log lines naming them or one of their traces,
+//! the events involving them, and whatever they still have in the pending
+//! payout queues.
+//!
+//! Guard history and most log lines live on the heap, so a bundle only
+//! reaches back to the last upgrade (persisted critical lines excepted).
+//! Events and pending entries are durable.
+
+use crate::event::Event;
+use crate::guard::{GuardRecord, OperationState};
+use crate::logs::LogEntry;
+use crate::state::State;
+use crate::EventTimeRange;
+use candid::{CandidType, Deserialize, Principal};
+use std::collections::{BTreeSet, VecDeque};
+
+/// Newest matching events kept in a bundle.
+pub const MAX_FORENSIC_EVENTS: usize = 200;
+
+/// Newest matching log lines kept in a bundle.
+pub const MAX_FORENSIC_LOG_LINES: usize = 500;
+
+/// The operation the principal's guard is held for right now.
+#[derive(CandidType, Clone, Debug, PartialEq, Deserialize)]
+pub struct InFlightOperation {
+    pub operation_name: String,
+    pub started_at: u64,
+    pub state: OperationState,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct ForensicLogLine {
+    pub timestamp: u64,
+    /// `Critical`, `Info`, `TraceXrc` or `Debug`.
+    pub priority: String,
+    pub file: String,
+    pub line: u32,
+    pub message: String,
+}
+
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum PendingQueue {
+    /// `pending_margin_transfers`, keyed by vault id.
+    Margin,
+    /// `pending_excess_transfers`, keyed by vault id.
+    Excess,
+    /// `pending_redemption_transfer`, keyed by icUSD block index.
+    Redemption,
+    /// `pending_refunds`, keyed by the burn's icUSD block index.
+    Refund,
+}
+
+/// One entry the principal is still owed from a pending queue.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct PendingEntry {
+    pub queue: PendingQueue,
+    pub key: u64,
+    pub ledger: Principal,
+    pub amount_e8s: u64,
+    pub retry_count: u8,
+    /// Trace of the operation that queued it, if recorded.
+    pub trace: Option<String>,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Deserialize)]
+pub struct OperationForensics {
+    pub principal: Principal,
+    pub time_range: EventTimeRange,
+    pub in_flight: Option<InFlightOperation>,
+    /// Guards started within the range, oldest first.
+    pub guard_history: Vec<GuardRecord>,
+    /// Distinct operation names from `in_flight` and `guard_history`, in
+    /// the order they first appear.
+    pub operation_names: Vec<String>,
+    /// Lines within the range that name the principal or one of its
+    /// traces, oldest first.
+    pub log_lines: Vec<ForensicLogLine>,
+    pub log_lines_truncated: bool,
+    /// Events within the range involving the principal, as
+    /// `(event_log_index, event)`, oldest first. Interest accruals and
+    /// events without a timestamp are left out.
+    pub events: Vec<(u64, Event)>,
+    pub events_truncated: bool,
+    /// Everything still queued for the principal, whenever it was queued.
+    pub pending: Vec<PendingEntry>,
+}
+
+pub fn validate_time_range(range: &EventTimeRange) -> Result<(), String> {
+    if range.start_ns > range.end_ns {
+        return Err("time_range.start_ns must not be after end_ns".to_string());
+    }
+    Ok(())
+}
+
+/// Everything still queued for `principal`.
+pub fn pending_entries(state: &State, principal: Principal) -> Vec<PendingEntry> {
+    // Pre-multi-collateral entries carry the anonymous sentinel for ICP.
+    let ledger = |collateral_type: Principal| {
+        if collateral_type == Principal::anonymous() {
+            state.icp_ledger_principal
+        } else {
+            collateral_type
+        }
+    };
+    let mut entries = Vec::new();
+    for (queue, transfers) in [
+        (PendingQueue::Margin, &state.pending_margin_transfers),
+        (PendingQueue::Excess, &state.pending_excess_transfers),
+    ] {
+        for ((vault_id, _), transfer) in transfers.iter() {
+            if transfer.owner == principal {
+                entries.push(PendingEntry {
+                    queue,
+                    key: *vault_id,
+                    ledger: ledger(transfer.collateral_type),
+                    amount_e8s: transfer.margin.0,
+                    retry_count: transfer.retry_count,
+                    trace: transfer.trace_id.map(|t| t.to_string()),
+                });
+            }
+        }
+    }
+    for (block_index, transfer) in state.pending_redemption_transfer.iter() {
+        if transfer.owner == principal {
+            entries.push(PendingEntry {
+                queue: PendingQueue::Redemption,
+                key: *block_index,
+                ledger: ledger(transfer.collateral_type),
+                amount_e8s: transfer.margin.0,
+                retry_count: transfer.retry_count,
+                trace: transfer.trace_id.map(|t| t.to_string()),
+            });
+        }
+    }
+    for (block_index, refund) in state.pending_refunds.iter() {
+        if refund.user == principal {
+            entries.push(PendingEntry {
+                queue: PendingQueue::Refund,
+                key: *block_index,
+                ledger: state.icusd_ledger_principal,
+                amount_e8s: refund.amount_e8s,
+                retry_count: refund.retry_count,
+                trace: None,
+            });
+        }
+    }
+    entries
+}
+
+/// Keep the newest `cap` items of `items`, oldest first, and whether any
+/// were dropped.
+fn newest<T>(items: impl Iterator<Item = T>, cap: usize) -> (Vec<T>, bool) {
+    let mut kept = VecDeque::with_capacity(cap);
+    let mut truncated = false;
+    for item in items {
+        if kept.len() == cap {
+            kept.pop_front();
+            truncated = true;
+        }
+        kept.push_back(item);
+    }
+    (kept.into(), truncated)
+}
+
+/// Assemble `principal`'s bundle from its guard history, the event log and
+/// the log buffers. `guard_history` is expected to be filtered already
+/// (see `guard::guard_history`); `events` and `logs` are filtered here.
+pub fn operation_forensics(
+    state: &State,
+    principal: Principal,
+    time_range: EventTimeRange,
+    guard_history: Vec<GuardRecord>,
+    events: impl Iterator<Item = (u64, Event)>,
+    mut logs: Vec<LogEntry>,
+) -> OperationForensics {
+    let in_flight = state
+        .principal_guards
+        .contains(&principal)
+        .then(|| InFlightOperation {
+            operation_name: state
+                .operation_names
+                .get(&principal)
+                .cloned()
+                .unwrap_or_default(),
+            started_at: state
+                .principal_guard_timestamps
+                .get(&principal)
+                .copied()
+                .unwrap_or_default(),
+            state: state
+                .operation_states
+                .get(&principal)
+                .copied()
+                .unwrap_or_default(),
+        });
+
+    let mut operation_names: Vec<String> = Vec::new();
+    for name in in_flight
+        .iter()
+        .map(|op| &op.operation_name)
+        .chain(guard_history.iter().map(|r| &r.operation_name))
+    {
+        if !operation_names.contains(name) {
+            operation_names.push(name.clone());
+        }
+    }
+
+    let pending = pending_entries(state, principal);
+
+    let in_range = |ts: u64| ts >= time_range.start_ns && ts <= time_range.end_ns;
+
+    let mut needles: BTreeSet<String> = guard_history
+        .iter()
+        .map(|r| format!("trace={}", r.trace))
+        .chain(
+            pending
+                .iter()
+                .filter_map(|p| p.trace.as_ref().map(|t| format!("trace={}", t))),
+        )
+        .collect();
+    needles.insert(principal.to_string());
+
+    logs.sort_by_key(|entry| (entry.timestamp, entry.counter));
+    let (log_lines, log_lines_truncated) = newest(
+        logs.into_iter()
+            .filter(|entry| in_range(entry.timestamp))
+            .filter(|entry| needles.iter().any(|n| entry.message.contains(n.as_str())))
+            .map(|entry| ForensicLogLine {
+                timestamp: entry.timestamp,
+                priority: format!("{:?}", entry.priority),
+                file: entry.file,
+                line: entry.line,
+                message: entry.message,
+            }),
+        MAX_FORENSIC_LOG_LINES,
+    );
+
+    let (events, events_truncated) = newest(
+        events.filter(|(_, event)| {
+            !event.is_accrue_interest()
+                && event.involves_principal(&principal)
+                && event.timestamp_ns().is_some_and(in_range)
+        }),
+        MAX_FORENSIC_EVENTS,
+    );
+
+    OperationForensics {
+        principal,
+        time_range,
+        in_flight,
+        guard_history,
+        operation_names,
+        log_lines,
+        log_lines_truncated,
+        events,
+        events_truncated,
+        pending,
+    }
+}
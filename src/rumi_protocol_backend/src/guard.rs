@@ -1,8 +1,12 @@
 use crate::state::mutate_state;
-use candid::Principal;
+use candid::{CandidType, Deserialize, Principal};
 use std::marker::PhantomData;
 use ic_cdk::api::time;
 use ic_canister_log::log;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
 
 const MAX_CONCURRENT: usize = 100;
 
@@ -10,7 +14,9 @@ const MAX_CONCURRENT: usize = 100;
 const GUARD_TIMEOUT_NANOS: u64 = 5 * 60 * 1_000_000_000; // 5 minutes in nanoseconds
 
 // Track operation state
-#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+#[derive(
+    CandidType, Debug, Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize,
+)]
 pub enum OperationState {
     #[default]
     InProgress,
@@ -18,13 +24,160 @@ pub enum OperationState {
     Failed,
 }
 
+/// Identifies one user operation across its awaits, retries and the
+/// pending transfers it leaves behind. Logged as `trace=<16 hex digits>`,
+/// which is also what `/logs?trace=` matches and what tagged error messages
+/// carry.
+///
+/// The high bits are the start time in milliseconds and the low 16 bits a
+/// per-canister counter, so ids sort by start time and do not repeat unless
+/// 65,536 operations start within the same millisecond.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub struct TraceId(pub u64);
+
+impl TraceId {
+    pub fn new(now_ns: u64) -> Self {
+        let seq = NEXT_TRACE_SEQ.with(|c| {
+            let seq = c.get();
+            c.set(seq.wrapping_add(1));
+            seq
+        });
+        Self(((now_ns / 1_000_000) << 16) | (seq & 0xffff))
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for TraceId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().trim_start_matches("trace=");
+        if s.is_empty() || s.len() > 16 {
+            return Err("a trace id is 1 to 16 hex digits".to_string());
+        }
+        u64::from_str_radix(s, 16)
+            .map(TraceId)
+            .map_err(|_| "a trace id is 1 to 16 hex digits".to_string())
+    }
+}
+
+thread_local! {
+    static NEXT_TRACE_SEQ: Cell<u64> = const { Cell::new(0) };
+
+    /// Trace of the operation each principal has in flight. Keyed like
+    /// `GuardPrincipal`, which already allows one operation per principal,
+    /// so code that only knows the caller (or the counterparty of a ledger
+    /// transfer) can still tag its log lines. Transient (heap): operations
+    /// never span an upgrade, and scopes are dropped on trap cleanup.
+    static ACTIVE_TRACES: RefCell<HashMap<Principal, TraceId>> = RefCell::new(HashMap::new());
+}
+
+/// Trace of the operation `principal` has in flight, if any.
+pub fn current_trace(principal: Principal) -> Option<TraceId> {
+    ACTIVE_TRACES.with(|t| t.borrow().get(&principal).copied())
+}
+
+/// Log-line tag for `principal`'s operation: `trace=<id>`, or `trace=-`
+/// outside of one.
+pub fn trace_tag(principal: Principal) -> TraceTag {
+    TraceTag(current_trace(principal))
+}
+
+/// Display form of an optional trace id; see `trace_tag`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceTag(pub Option<TraceId>);
+
+impl fmt::Display for TraceTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(trace) => write!(f, "trace={}", trace),
+            None => write!(f, "trace=-"),
+        }
+    }
+}
+
+/// Registers a trace id as `principal`'s current operation for as long as
+/// it is held.
+#[must_use]
+pub struct TraceScope {
+    principal: Principal,
+    trace: TraceId,
+    registered: bool,
+}
+
+impl TraceScope {
+    /// Start a new trace for `principal`. If another of its operations is
+    /// already in flight the new id is not registered (that operation keeps
+    /// its own); it is still returned for tagging this call's result.
+    pub fn begin(principal: Principal, now_ns: u64) -> Self {
+        let trace = TraceId::new(now_ns);
+        let registered = ACTIVE_TRACES.with(|t| match t.borrow_mut().entry(principal) {
+            std::collections::hash_map::Entry::Occupied(_) => false,
+            std::collections::hash_map::Entry::Vacant(e) => {
+                e.insert(trace);
+                true
+            }
+        });
+        Self {
+            principal,
+            trace,
+            registered,
+        }
+    }
+
+    /// Join `principal`'s current trace, or start one if it has none.
+    pub fn join(principal: Principal, now_ns: u64) -> Self {
+        match current_trace(principal) {
+            Some(trace) => Self {
+                principal,
+                trace,
+                registered: false,
+            },
+            None => Self::begin(principal, now_ns),
+        }
+    }
+
+    pub fn trace_id(&self) -> TraceId {
+        self.trace
+    }
+
+    /// Tag an error result with this trace; see `ProtocolError::with_trace`.
+    pub fn tag<T>(
+        &self,
+        result: Result<T, crate::ProtocolError>,
+    ) -> Result<T, crate::ProtocolError> {
+        result.map_err(|e| e.with_trace(self.trace))
+    }
+}
+
+impl Drop for TraceScope {
+    fn drop(&mut self) {
+        if self.registered {
+            ACTIVE_TRACES.with(|t| {
+                t.borrow_mut().remove(&self.principal);
+            });
+        }
+    }
+}
+
 /// Guards a block from executing twice when called by the same user and from being
 /// executed [MAX_CONCURRENT] or more times in parallel.
+///
+/// Each guard carries the operation's trace id: the one the endpoint started
+/// (see `TraceScope`), or a fresh one for callers that did not.
 #[must_use]
 pub struct GuardPrincipal {
     principal: Principal,
-    _created_at: u64,
-    _operation_name: String,
+    created_at: u64,
+    operation_name: String,
+    trace: TraceScope,
     _marker: PhantomData<GuardPrincipal>,
 }
 
@@ -40,6 +193,7 @@ impl GuardPrincipal {
     /// already a pending request for the specified principal or if there
     /// are at least [MAX_CONCURRENT] pending requests.
     pub fn new(principal: Principal, operation_name: &str) -> Result<Self, GuardError> {
+        let trace = TraceScope::join(principal, time());
         mutate_state(|s| {
             let current_time = time();
 
@@ -99,14 +253,32 @@ impl GuardPrincipal {
                     s.operation_names.remove(&principal);
                 } else {
                     log!(crate::INFO,
-                        "[guard] Operation '{}' for principal {} is already in progress ({}s old)",
-                        op_name, principal.to_string(), age_seconds
+                        "[guard] Operation '{}' for principal {} is already in progress ({}s old, {})",
+                        op_name, principal.to_string(), age_seconds, trace_tag(principal)
                     );
+                    record_guard(GuardRecord {
+                        principal,
+                        operation_name: operation_name.to_string(),
+                        trace: trace.trace_id().to_string(),
+                        started_at: current_time,
+                        finished_at: current_time,
+                        outcome: GuardOutcome::AlreadyProcessing,
+                    });
+                    crate::guard_metrics::record_refusal(s, principal);
                     return Err(GuardError::AlreadyProcessing);
                 }
             }
 
             if s.principal_guards.len() >= MAX_CONCURRENT {
+                record_guard(GuardRecord {
+                    principal,
+                    operation_name: operation_name.to_string(),
+                    trace: trace.trace_id().to_string(),
+                    started_at: current_time,
+                    finished_at: current_time,
+                    outcome: GuardOutcome::TooManyConcurrentRequests,
+                });
+                crate::guard_metrics::record_refusal(s, principal);
                 return Err(GuardError::TooManyConcurrentRequests);
             }
 
@@ -117,19 +289,26 @@ impl GuardPrincipal {
             s.operation_names.insert(principal, operation_name.to_string());
 
             log!(crate::INFO,
-                "[guard] Created new guard for principal {} operation '{}'",
-                principal.to_string(), operation_name
+                "[guard] trace={} Created new guard for principal {} operation '{}'",
+                trace.trace_id(),
+                principal.to_string(),
+                operation_name
             );
 
             Ok(Self {
                 principal,
-                _created_at: current_time,
-                _operation_name: operation_name.to_string(),
+                created_at: current_time,
+                operation_name: operation_name.to_string(),
+                trace,
                 _marker: PhantomData,
             })
         })
     }
 
+    pub fn trace_id(&self) -> TraceId {
+        self.trace.trace_id()
+    }
+
     /// Mark this operation as complete
     pub fn complete(self) {
         mutate_state(|s| {
@@ -154,11 +333,165 @@ impl Drop for GuardPrincipal {
         // Always release the guard when the struct goes out of scope.
         // The guard exists to prevent concurrent access during an operation;
         // once the Rust function returns (success or failure), the lock must be freed.
-        mutate_state(|s| {
+        // A guard cleared as stale may since have been taken by a newer
+        // operation of the same principal, which this one must not release.
+        let finished_at = time();
+        let outcome = mutate_state(|s| {
+            crate::loyalty::touch(s, self.principal, finished_at);
+            if s.principal_guard_timestamps.get(&self.principal) != Some(&self.created_at) {
+                return GuardOutcome::Released;
+            }
             s.principal_guards.remove(&self.principal);
             s.principal_guard_timestamps.remove(&self.principal);
-            s.operation_states.remove(&self.principal);
             s.operation_names.remove(&self.principal);
+            let outcome = match s.operation_states.remove(&self.principal) {
+                Some(OperationState::Completed) => GuardOutcome::Completed,
+                Some(OperationState::Failed) => GuardOutcome::Failed,
+                // Released without being marked.
+                Some(OperationState::InProgress) | None => GuardOutcome::Released,
+            };
+            crate::guard_metrics::record_release(
+                s,
+                self.principal,
+                outcome,
+                finished_at.saturating_sub(self.created_at),
+            );
+            outcome
+        });
+        record_guard(GuardRecord {
+            principal: self.principal,
+            operation_name: std::mem::take(&mut self.operation_name),
+            trace: self.trace.trace_id().to_string(),
+            started_at: self.created_at,
+            finished_at,
+            outcome,
+        });
+    }
+}
+
+/// Finished and rejected guards kept for `get_operation_forensics`.
+pub const GUARD_HISTORY_CAPACITY: usize = 1_000;
+
+/// How a `GuardPrincipal` ended.
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum GuardOutcome {
+    Completed,
+    Failed,
+    /// Dropped without `complete` or `fail`: the endpoint returned (either
+    /// way) or trapped.
+    Released,
+    /// Never acquired: the principal already had an operation in flight.
+    AlreadyProcessing,
+    /// Never acquired: `MAX_CONCURRENT` operations were in flight.
+    TooManyConcurrentRequests,
+    /// Held past `guard_metrics::GUARD_HARD_TIMEOUT_NANOS` and cleared by
+    /// `guard_metrics::resolve_stuck_guards`.
+    AutoCleared,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct GuardRecord {
+    pub principal: Principal,
+    pub operation_name: String,
+    /// `TraceId` in its logged hex form.
+    pub trace: String,
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub outcome: GuardOutcome,
+}
+
+thread_local! {
+    /// Newest `GUARD_HISTORY_CAPACITY` guard records, oldest first.
+    /// Transient (heap) like `ACTIVE_TRACES`: support looks at recent
+    /// failures, and an upgrade starts the history over.
+    static GUARD_HISTORY: RefCell<VecDeque<GuardRecord>> = RefCell::new(VecDeque::new());
+}
+
+/// Append `record` to the guard history, dropping the oldest past capacity.
+pub fn record_guard(record: GuardRecord) {
+    GUARD_HISTORY.with(|h| {
+        let mut h = h.borrow_mut();
+        if h.len() == GUARD_HISTORY_CAPACITY {
+            h.pop_front();
+        }
+        h.push_back(record);
+    });
+}
+
+/// `principal`'s guard records that started within `[start_ns, end_ns]`,
+/// oldest first.
+pub fn guard_history(principal: Principal, start_ns: u64, end_ns: u64) -> Vec<GuardRecord> {
+    GUARD_HISTORY.with(|h| {
+        h.borrow()
+            .iter()
+            .filter(|r| r.principal == principal && (start_ns..=end_ns).contains(&r.started_at))
+            .cloned()
+            .collect()
+    })
+}
+
+/// A ledger transfer whose reply has not come back yet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpenLedgerCall {
+    pub ledger: Principal,
+    /// Memo the transfer was sent with; unique per op nonce.
+    pub memo: Vec<u8>,
+    pub amount: u128,
+    pub started_at: u64,
+}
+
+thread_local! {
+    /// Transfers sent through `transfer_idempotent` / `transfer_from_idempotent`
+    /// and still awaiting the ledger, keyed by the user-side account owner.
+    /// Transient (heap): a canister is stopped, and so has no outstanding
+    /// calls, before it is upgraded.
+    static OPEN_LEDGER_CALLS: RefCell<HashMap<Principal, Vec<OpenLedgerCall>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// `principal`'s transfers still awaiting the ledger, oldest first.
+pub fn open_ledger_calls(principal: Principal) -> Vec<OpenLedgerCall> {
+    OPEN_LEDGER_CALLS.with(|c| c.borrow().get(&principal).cloned().unwrap_or_default())
+}
+
+/// Marks a transfer with `counterparty` as open for as long as it is held,
+/// so `guard_metrics::resolve_stuck_guards` does not clear a guard whose
+/// transfer may still land. Dropped on trap cleanup like the other guards.
+#[must_use]
+pub struct LedgerCallGuard {
+    counterparty: Principal,
+    memo: Vec<u8>,
+}
+
+impl LedgerCallGuard {
+    pub fn new(counterparty: Principal, ledger: Principal, memo: Vec<u8>, amount: u128) -> Self {
+        OPEN_LEDGER_CALLS.with(|c| {
+            c.borrow_mut()
+                .entry(counterparty)
+                .or_default()
+                .push(OpenLedgerCall {
+                    ledger,
+                    memo: memo.clone(),
+                    amount,
+                    started_at: time(),
+                })
+        });
+        Self { counterparty, memo }
+    }
+}
+
+impl Drop for LedgerCallGuard {
+    fn drop(&mut self) {
+        OPEN_LEDGER_CALLS.with(|c| {
+            let mut calls = c.borrow_mut();
+            if let Some(open) = calls.get_mut(&self.counterparty) {
+                if let Some(i) = open.iter().position(|call| call.memo == self.memo) {
+                    open.remove(i);
+                }
+                if open.is_empty() {
+                    calls.remove(&self.counterparty);
+                }
+            }
         });
     }
 }
@@ -353,6 +686,11 @@ impl BorrowReservationGuard {
     }
 }
 
+/// icUSD e8s currently reserved by in-flight borrows against `collateral`.
+pub fn reserved_borrows(collateral: &Principal) -> u64 {
+    BORROW_RESERVATIONS.with(|r| r.borrow().get(collateral).copied().unwrap_or(0))
+}
+
 impl Drop for BorrowReservationGuard {
     fn drop(&mut self) {
         BORROW_RESERVATIONS.with(|r| {
@@ -421,7 +759,9 @@ mod vault_liquidation_guard_tests {
         // A smaller second borrow that fits (600+300+100=1000) is allowed.
         let _g2 = BorrowReservationGuard::try_reserve(coll, 100, 600, ceiling, 600, global)
             .expect("second borrow of 100 fits exactly at the ceiling");
+        assert_eq!(reserved_borrows(&coll), 400);
         drop(g1);
+        assert_eq!(reserved_borrows(&coll), 100);
         // After the first releases, headroom frees up again.
         let _g3 = BorrowReservationGuard::try_reserve(coll, 300, 600, ceiling, 600, global)
             .expect("headroom freed after first reservation dropped");
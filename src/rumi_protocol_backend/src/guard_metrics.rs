@@ -0,0 +1,341 @@
+//! Guard age metrics and auto-resolution of stuck guards.
+//!
+//! Every `GuardPrincipal` that is released counts toward its principal's
+//! `GuardMetrics`: how long it was held, in `GUARD_AGE_BUCKET_BOUNDS_SECS`
+//! buckets, and how it ended. Refused requests count too. Metrics live in
+//! the state snapshot rather than the event log, so a full replay starts
+//! them over. Past `MAX_GUARD_METRICS_PRINCIPALS`, new principals are
+//! counted under the anonymous principal.
+//!
+//! A timer clears guards held longer than `GUARD_HARD_TIMEOUT_NANOS`, but
+//! only once no ledger transfer of the operation can still land: either no
+//! transfer with the principal is awaiting its reply, or the ledger already
+//! shows each open transfer among its newest `LEDGER_SCAN_BLOCKS` blocks
+//! (matched by memo and amount). A guard that fails the check is kept and
+//! counted as deferred; ledgers without `icrc3_get_blocks` leave it to
+//! `clear_stuck_operations`. Each clear is a `GuardAutoCleared` event.
+
+use crate::guard::{open_ledger_calls, record_guard, GuardOutcome, GuardRecord, OpenLedgerCall};
+use crate::icrc3_proof::{decode_block, DecodedBlock};
+use crate::logs::INFO;
+use crate::state::{mutate_state, read_state, State};
+use candid::{CandidType, Deserialize, Nat, Principal};
+use ic_canister_log::log;
+use icrc_ledger_types::icrc3::blocks::{GetBlocksRequest, GetBlocksResult};
+use num_traits::ToPrimitive;
+use serde::Serialize;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Upper bounds of the age histogram buckets, in seconds. One more bucket
+/// counts everything longer.
+pub const GUARD_AGE_BUCKET_BOUNDS_SECS: [u64; 6] = [1, 5, 30, 60, 300, 1_800];
+
+/// Guards held longer than this are cleared by `resolve_stuck_guards`.
+pub const GUARD_HARD_TIMEOUT_NANOS: u64 = 30 * 60 * NANOS_PER_SEC;
+
+/// How often `resolve_stuck_guards` runs.
+pub const GUARD_RESOLVE_INTERVAL_SECS: u64 = 300;
+
+/// Most principals with their own `GuardMetrics`.
+pub const MAX_GUARD_METRICS_PRINCIPALS: usize = 10_000;
+
+/// Newest blocks searched for an open transfer.
+pub const LEDGER_SCAN_BLOCKS: u64 = 100;
+
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuardMetrics {
+    /// Released guards per age bucket; see `GUARD_AGE_BUCKET_BOUNDS_SECS`.
+    pub age_histogram: Vec<u64>,
+    pub completed: u64,
+    pub failed: u64,
+    /// Released without being marked completed or failed.
+    pub released: u64,
+    /// Requests refused because a guard was already held, or too many were.
+    pub refused: u64,
+    pub auto_cleared: u64,
+    /// Auto-clears held back because a transfer could still land.
+    pub auto_clear_deferred: u64,
+    pub max_age_ns: u64,
+}
+
+/// Why `resolve_stuck_guards` found a guard safe to clear.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GuardClearReason {
+    /// No transfer with the principal was awaiting the ledger.
+    NoOpenTransfer,
+    /// The open transfers were already on their ledgers; `block_index` is
+    /// the last one found.
+    TransferOnLedger { ledger: Principal, block_index: u64 },
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct StuckGuard {
+    pub principal: Principal,
+    pub operation_name: String,
+    pub started_at: u64,
+}
+
+/// Histogram bucket of a guard held for `age_ns`.
+pub fn age_bucket(age_ns: u64) -> usize {
+    let secs = age_ns / NANOS_PER_SEC;
+    GUARD_AGE_BUCKET_BOUNDS_SECS
+        .iter()
+        .position(|bound| secs < *bound)
+        .unwrap_or(GUARD_AGE_BUCKET_BOUNDS_SECS.len())
+}
+
+fn metrics_mut(state: &mut State, principal: Principal) -> &mut GuardMetrics {
+    let key = if state.guard_metrics.contains_key(&principal)
+        || state.guard_metrics.len() < MAX_GUARD_METRICS_PRINCIPALS
+    {
+        principal
+    } else {
+        Principal::anonymous()
+    };
+    state.guard_metrics.entry(key).or_default()
+}
+
+fn record_age(metrics: &mut GuardMetrics, age_ns: u64) {
+    metrics
+        .age_histogram
+        .resize(GUARD_AGE_BUCKET_BOUNDS_SECS.len() + 1, 0);
+    metrics.age_histogram[age_bucket(age_ns)] += 1;
+    metrics.max_age_ns = metrics.max_age_ns.max(age_ns);
+}
+
+/// Count a guard of `principal` that ended with `outcome` after `age_ns`.
+pub fn record_release(state: &mut State, principal: Principal, outcome: GuardOutcome, age_ns: u64) {
+    let metrics = metrics_mut(state, principal);
+    record_age(metrics, age_ns);
+    match outcome {
+        GuardOutcome::Completed => metrics.completed += 1,
+        GuardOutcome::Failed => metrics.failed += 1,
+        GuardOutcome::AutoCleared => metrics.auto_cleared += 1,
+        GuardOutcome::Released
+        | GuardOutcome::AlreadyProcessing
+        | GuardOutcome::TooManyConcurrentRequests => metrics.released += 1,
+    }
+}
+
+pub fn record_refusal(state: &mut State, principal: Principal) {
+    metrics_mut(state, principal).refused += 1;
+}
+
+pub fn record_deferral(state: &mut State, principal: Principal) {
+    metrics_mut(state, principal).auto_clear_deferred += 1;
+}
+
+/// All principals' metrics added up.
+pub fn totals(state: &State) -> GuardMetrics {
+    let mut total = GuardMetrics {
+        age_histogram: vec![0; GUARD_AGE_BUCKET_BOUNDS_SECS.len() + 1],
+        ..GuardMetrics::default()
+    };
+    for metrics in state.guard_metrics.values() {
+        for (bucket, count) in metrics.age_histogram.iter().enumerate() {
+            total.age_histogram[bucket] += count;
+        }
+        total.completed += metrics.completed;
+        total.failed += metrics.failed;
+        total.released += metrics.released;
+        total.refused += metrics.refused;
+        total.auto_cleared += metrics.auto_cleared;
+        total.auto_clear_deferred += metrics.auto_clear_deferred;
+        total.max_age_ns = total.max_age_ns.max(metrics.max_age_ns);
+    }
+    total
+}
+
+/// Guards held longer than `GUARD_HARD_TIMEOUT_NANOS` at `now`.
+pub fn stuck_guards(state: &State, now: u64) -> Vec<StuckGuard> {
+    state
+        .principal_guards
+        .iter()
+        .filter_map(|principal| {
+            let started_at = state
+                .principal_guard_timestamps
+                .get(principal)
+                .copied()
+                .unwrap_or_default();
+            (now.saturating_sub(started_at) > GUARD_HARD_TIMEOUT_NANOS).then(|| StuckGuard {
+                principal: *principal,
+                operation_name: state
+                    .operation_names
+                    .get(principal)
+                    .cloned()
+                    .unwrap_or_default(),
+                started_at,
+            })
+        })
+        .collect()
+}
+
+/// Clear `guard` and count it, unless it was released or replaced while its
+/// transfers were checked. Returns whether it was cleared.
+pub fn clear_stuck_guard(state: &mut State, guard: &StuckGuard, now: u64) -> bool {
+    if state.principal_guard_timestamps.get(&guard.principal) != Some(&guard.started_at) {
+        return false;
+    }
+    state.principal_guards.remove(&guard.principal);
+    state.principal_guard_timestamps.remove(&guard.principal);
+    state.operation_states.remove(&guard.principal);
+    state.operation_names.remove(&guard.principal);
+    record_release(
+        state,
+        guard.principal,
+        GuardOutcome::AutoCleared,
+        now.saturating_sub(guard.started_at),
+    );
+    true
+}
+
+/// Index of the block in `blocks` that carries `call`, if any.
+pub fn find_transfer(blocks: &[(u64, DecodedBlock)], call: &OpenLedgerCall) -> Option<u64> {
+    blocks
+        .iter()
+        .find(|(_, block)| {
+            block.amount == call.amount && block.memo.as_deref() == Some(call.memo.as_slice())
+        })
+        .map(|(index, _)| *index)
+}
+
+thread_local! {
+    static RESOLVING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Keeps timer ticks from overlapping while ledgers are queried.
+struct ResolveGuard;
+
+impl ResolveGuard {
+    fn new() -> Option<Self> {
+        RESOLVING.with(|r| (!r.replace(true)).then_some(Self))
+    }
+}
+
+impl Drop for ResolveGuard {
+    fn drop(&mut self) {
+        RESOLVING.with(|r| r.set(false));
+    }
+}
+
+pub fn setup_resolve_timer() {
+    crate::scheduler::set_interval(
+        crate::scheduler::ScheduledTask::GuardResolve,
+        GUARD_RESOLVE_INTERVAL_SECS,
+        || async {
+            resolve_stuck_guards().await;
+            Ok(())
+        },
+    );
+}
+
+/// Clear every guard past the hard timeout whose transfers can no longer
+/// land. Returns how many were cleared.
+pub async fn resolve_stuck_guards() -> u64 {
+    let Some(_resolving) = ResolveGuard::new() else {
+        return 0;
+    };
+    let stuck = read_state(|s| stuck_guards(s, ic_cdk::api::time()));
+    let mut cleared = 0;
+    for guard in stuck {
+        let reason = match check_open_transfers(open_ledger_calls(guard.principal)).await {
+            Ok(reason) => reason,
+            Err(why) => {
+                log!(
+                    INFO,
+                    "[resolve_stuck_guards] keeping '{}' of {}: {}",
+                    guard.operation_name,
+                    guard.principal,
+                    why
+                );
+                mutate_state(|s| record_deferral(s, guard.principal));
+                continue;
+            }
+        };
+        let now = ic_cdk::api::time();
+        let age_ns = now.saturating_sub(guard.started_at);
+        if !mutate_state(|s| clear_stuck_guard(s, &guard, now)) {
+            continue;
+        }
+        crate::event::record_guard_auto_cleared(
+            guard.principal,
+            guard.operation_name.clone(),
+            age_ns,
+            reason.clone(),
+            now,
+        );
+        log!(
+            INFO,
+            "[resolve_stuck_guards] cleared '{}' of {} after {}s ({:?})",
+            guard.operation_name,
+            guard.principal,
+            age_ns / NANOS_PER_SEC,
+            reason
+        );
+        record_guard(GuardRecord {
+            principal: guard.principal,
+            operation_name: guard.operation_name,
+            trace: crate::guard::current_trace(guard.principal)
+                .map(|trace| trace.to_string())
+                .unwrap_or_default(),
+            started_at: guard.started_at,
+            finished_at: now,
+            outcome: GuardOutcome::AutoCleared,
+        });
+        cleared += 1;
+    }
+    cleared
+}
+
+/// `Ok` once none of `calls` can still move funds, else why not.
+async fn check_open_transfers(calls: Vec<OpenLedgerCall>) -> Result<GuardClearReason, String> {
+    let mut reason = GuardClearReason::NoOpenTransfer;
+    let mut scanned: BTreeMap<Principal, Vec<(u64, DecodedBlock)>> = BTreeMap::new();
+    for call in calls {
+        if !scanned.contains_key(&call.ledger) {
+            scanned.insert(call.ledger, newest_blocks(call.ledger).await?);
+        }
+        let block_index = find_transfer(&scanned[&call.ledger], &call).ok_or_else(|| {
+            format!(
+                "transfer of {} on {} is not among the newest {} blocks",
+                call.amount, call.ledger, LEDGER_SCAN_BLOCKS
+            )
+        })?;
+        reason = GuardClearReason::TransferOnLedger {
+            ledger: call.ledger,
+            block_index,
+        };
+    }
+    Ok(reason)
+}
+
+/// The newest `LEDGER_SCAN_BLOCKS` blocks of `ledger` that decode.
+async fn newest_blocks(ledger: Principal) -> Result<Vec<(u64, DecodedBlock)>, String> {
+    let tip = get_blocks(ledger, 0, 0).await?.log_length;
+    let tip = tip.0.to_u64().unwrap_or(u64::MAX);
+    let start = tip.saturating_sub(LEDGER_SCAN_BLOCKS);
+    let result = get_blocks(ledger, start, tip - start).await?;
+    Ok(result
+        .blocks
+        .iter()
+        .filter_map(|b| {
+            let index = b.id.0.to_u64()?;
+            decode_block(&b.block).ok().map(|block| (index, block))
+        })
+        .collect())
+}
+
+async fn get_blocks(ledger: Principal, start: u64, length: u64) -> Result<GetBlocksResult, String> {
+    let request = vec![GetBlocksRequest {
+        start: Nat::from(start),
+        length: Nat::from(length),
+    }];
+    let result: Result<(GetBlocksResult,), _> =
+        ic_cdk::call(ledger, "icrc3_get_blocks", (request,)).await;
+    result
+        .map(|(blocks,)| blocks)
+        .map_err(|(code, msg)| format!("icrc3_get_blocks on {} failed: {:?} {}", ledger, code, msg))
+}
@@ -0,0 +1,223 @@
+//! Composite protocol health score for wallets' risk badge.
+//!
+//! `get_health_score` folds five risk signals into one 0–100 number. Each
+//! signal is scored 0–100 on its own and returned alongside the composite,
+//! so a client can show why the badge is what it is:
+//!
+//! - total collateral ratio: 0 at or below 100% (insolvent), 50 at the
+//!   recovery-mode threshold, 100 at twice that threshold;
+//! - price freshness: the oldest price among collaterals that back debt,
+//!   100 within `PRICE_FRESHNESS_THRESHOLD_NANOS`, 0 at ten times that;
+//! - stability coverage: the stability pool's icUSD depth for each
+//!   collateral against the debt of its currently liquidatable vaults, for
+//!   the least covered collateral. The depth is the snapshot
+//!   `pool_priority` caches from the pool; without one younger than
+//!   `MAX_POOL_DEPTH_AGE_NS` nothing counts as covered;
+//! - reserve coverage: protocol 3USD reserves against the bad-debt deficit;
+//! - pending queue: margin, excess, redemption and refund transfers waiting
+//!   for a retry, 0 at `PENDING_QUEUE_ALARM`.
+//!
+//! The score is computed on demand from cached state and never stored.
+
+use crate::pool_priority::MAX_POOL_DEPTH_AGE_NS;
+use crate::state::State;
+use crate::xrc::PRICE_FRESHNESS_THRESHOLD_NANOS;
+use candid::{CandidType, Deserialize, Principal};
+use std::collections::BTreeMap;
+
+/// Component weights, in percent of the composite.
+pub const TCR_WEIGHT: u8 = 35;
+pub const PRICE_FRESHNESS_WEIGHT: u8 = 20;
+pub const STABILITY_COVERAGE_WEIGHT: u8 = 20;
+pub const RESERVE_COVERAGE_WEIGHT: u8 = 15;
+pub const PENDING_QUEUE_WEIGHT: u8 = 10;
+
+/// Pending transfers at which the queue component reaches 0.
+pub const PENDING_QUEUE_ALARM: u64 = 50;
+
+/// Composite score at or above which the badge is `Healthy`.
+pub const HEALTHY_SCORE: u8 = 80;
+/// Composite score at or above which the badge is `Caution`.
+pub const CAUTION_SCORE: u8 = 50;
+
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum HealthBadge {
+    Healthy,
+    Caution,
+    Critical,
+}
+
+/// One signal: its 0–100 `score`, its `weight` in the composite, and the
+/// raw `value` it was scored from (a ratio, seconds, or a count).
+#[derive(CandidType, Clone, Debug, PartialEq, Deserialize)]
+pub struct HealthComponent {
+    pub score: u8,
+    pub weight: u8,
+    pub value: f64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Deserialize)]
+pub struct HealthScore {
+    pub score: u8,
+    pub badge: HealthBadge,
+    /// Total collateral ratio (`Decimal::MAX` without debt).
+    pub total_collateral_ratio: HealthComponent,
+    /// Age in seconds of the oldest price backing debt.
+    pub price_freshness: HealthComponent,
+    /// Stability pool depth / liquidatable debt, for the least covered
+    /// collateral.
+    pub stability_coverage: HealthComponent,
+    /// 3USD reserves / protocol deficit.
+    pub reserve_coverage: HealthComponent,
+    /// Transfers awaiting retry.
+    pub pending_queue: HealthComponent,
+    pub computed_at: u64,
+}
+
+fn clamp_score(score: f64) -> u8 {
+    score.clamp(0.0, 100.0).round() as u8
+}
+
+fn component(score: f64, weight: u8, value: f64) -> HealthComponent {
+    HealthComponent {
+        score: clamp_score(score),
+        weight,
+        value,
+    }
+}
+
+fn tcr_component(state: &State) -> HealthComponent {
+    let tcr = state.total_collateral_ratio.to_f64();
+    let threshold = state.recovery_mode_threshold.to_f64().max(1.0);
+    let score = if tcr <= 1.0 {
+        0.0
+    } else if tcr < threshold {
+        50.0 * (tcr - 1.0) / (threshold - 1.0)
+    } else {
+        50.0 + 50.0 * (tcr - threshold) / threshold
+    };
+    component(score, TCR_WEIGHT, tcr)
+}
+
+fn price_freshness_component(state: &State, now: u64) -> HealthComponent {
+    let oldest_age_ns = state
+        .collateral_configs
+        .iter()
+        .filter(|(ct, _)| state.total_debt_for_collateral(ct).to_u64() > 0)
+        .map(|(_, config)| match config.last_price_timestamp {
+            Some(ts) if config.last_price.is_some() => now.saturating_sub(ts),
+            _ => u64::MAX,
+        })
+        .max()
+        .unwrap_or(0);
+    let limit = PRICE_FRESHNESS_THRESHOLD_NANOS;
+    let score = if oldest_age_ns <= limit {
+        100.0
+    } else {
+        100.0 * (10 * limit).saturating_sub(oldest_age_ns) as f64 / (9 * limit) as f64
+    };
+    component(
+        score,
+        PRICE_FRESHNESS_WEIGHT,
+        (oldest_age_ns / 1_000_000_000) as f64,
+    )
+}
+
+fn coverage_component(available: u64, needed: u64, weight: u8) -> HealthComponent {
+    if needed == 0 {
+        return component(100.0, weight, f64::MAX);
+    }
+    let coverage = available as f64 / needed as f64;
+    component(100.0 * coverage, weight, coverage)
+}
+
+fn stability_coverage_component(state: &State, now: u64) -> HealthComponent {
+    let depth: BTreeMap<Principal, u64> = state
+        .pool_depth
+        .as_ref()
+        .filter(|d| now.saturating_sub(d.updated_at_ns) <= MAX_POOL_DEPTH_AGE_NS)
+        .map(|d| d.eligible_icusd.iter().copied().collect())
+        .unwrap_or_default();
+    let coverage = liquidatable_debt_by_collateral(state)
+        .into_iter()
+        .filter(|(_, debt)| *debt > 0)
+        .map(|(ct, debt)| depth.get(&ct).copied().unwrap_or(0) as f64 / debt as f64)
+        .reduce(f64::min);
+    match coverage {
+        Some(coverage) => component(100.0 * coverage, STABILITY_COVERAGE_WEIGHT, coverage),
+        None => component(100.0, STABILITY_COVERAGE_WEIGHT, f64::MAX),
+    }
+}
+
+fn pending_queue_component(state: &State) -> HealthComponent {
+    let pending = (state.pending_margin_transfers.len()
+        + state.pending_excess_transfers.len()
+        + state.pending_redemption_transfer.len()
+        + state.pending_refunds.len()) as u64;
+    let score =
+        100.0 * PENDING_QUEUE_ALARM.saturating_sub(pending) as f64 / PENDING_QUEUE_ALARM as f64;
+    component(score, PENDING_QUEUE_WEIGHT, pending as f64)
+}
+
+/// icUSD debt of the vaults in the liquidatable set.
+pub fn liquidatable_debt(state: &State) -> u64 {
+    liquidatable_debt_by_collateral(state).values().sum()
+}
+
+/// icUSD debt of the vaults in the liquidatable set, per collateral.
+pub fn liquidatable_debt_by_collateral(state: &State) -> BTreeMap<Principal, u64> {
+    let mut debt = BTreeMap::new();
+    for vault in state
+        .liquidatable_vault_ids
+        .iter()
+        .filter_map(|id| state.vault_id_to_vaults.get(id))
+    {
+        *debt.entry(vault.collateral_type).or_insert(0) += vault.borrowed_icusd_amount.to_u64();
+    }
+    debt
+}
+
+pub fn badge_for(score: u8) -> HealthBadge {
+    if score >= HEALTHY_SCORE {
+        HealthBadge::Healthy
+    } else if score >= CAUTION_SCORE {
+        HealthBadge::Caution
+    } else {
+        HealthBadge::Critical
+    }
+}
+
+pub fn compute_health_score(state: &State, now: u64) -> HealthScore {
+    let total_collateral_ratio = tcr_component(state);
+    let price_freshness = price_freshness_component(state, now);
+    let stability_coverage = stability_coverage_component(state, now);
+    let reserve_coverage = coverage_component(
+        state.protocol_3usd_reserves,
+        state.protocol_deficit_icusd.to_u64(),
+        RESERVE_COVERAGE_WEIGHT,
+    );
+    let pending_queue = pending_queue_component(state);
+
+    let weighted: u32 = [
+        &total_collateral_ratio,
+        &price_freshness,
+        &stability_coverage,
+        &reserve_coverage,
+        &pending_queue,
+    ]
+    .iter()
+    .map(|c| c.score as u32 * c.weight as u32)
+    .sum();
+    let score = ((weighted + 50) / 100) as u8;
+
+    HealthScore {
+        score,
+        badge: badge_for(score),
+        total_collateral_ratio,
+        price_freshness,
+        stability_coverage,
+        reserve_coverage,
+        pending_queue,
+        computed_at: now,
+    }
+}
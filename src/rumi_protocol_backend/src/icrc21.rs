@@ -3,6 +3,12 @@
 
 use candid::{CandidType, Decode, Deserialize, Principal};
 use crate::vault::VaultArg;
+use crate::RepayAllAndCloseArg;
+
+mod locale;
+
+pub use locale::Locale;
+use locale::{render, template, MessageKey};
 
 /// Metadata about the consent message request
 #[derive(CandidType, Deserialize, Clone, Debug)]
@@ -99,10 +105,18 @@ pub enum Icrc21Error {
     ConsentMessageUnavailable(ErrorInfo),
 }
 
-/// Helper to format icUSD amount from e8s
-fn format_icusd_amount(e8s: u64) -> String {
+/// Helper to format icUSD amount from e8s, with the locale's decimal separator
+fn format_icusd_amount(e8s: u64, locale: Locale) -> String {
     let icusd = e8s as f64 / 100_000_000.0;
-    format!("{:.2} icUSD", icusd)
+    format!("{} icUSD", localize_decimal(format!("{:.2}", icusd), locale))
+}
+
+/// Swap the `.` produced by `format!` for the locale's decimal separator.
+fn localize_decimal(number: String, locale: Locale) -> String {
+    match locale.decimal_separator() {
+        '.' => number,
+        sep => number.replace('.', &sep.to_string()),
+    }
 }
 
 /// Human-readable label for a collateral whose symbol is unknown (not yet
@@ -152,15 +166,33 @@ fn resolve_collateral_for_vault(vault_id: u64) -> (String, u8) {
 /// Format a raw token amount (in the token's smallest unit) using the token's
 /// own decimals and symbol. Trailing zeros are trimmed for readability, so e.g.
 /// 400_000 drops of XRP (6 decimals) renders "0.4 XRP" and 4_000_000_000_000_000
-/// wei of ckETH (18 decimals) renders "0.004 ckETH".
-fn format_collateral_amount(raw: u64, decimals: u8, symbol: &str) -> String {
+/// wei of ckETH (18 decimals) renders "0.004 ckETH" ("0,004 ckETH" in Spanish).
+fn format_collateral_amount(raw: u64, decimals: u8, symbol: &str, locale: Locale) -> String {
     let amount = raw as f64 / 10f64.powi(decimals as i32);
     // Show up to 8 fractional digits, then trim trailing zeros (and a bare dot).
     let mut s = format!("{:.8}", amount);
     if s.contains('.') {
         s = s.trim_end_matches('0').trim_end_matches('.').to_string();
     }
-    format!("{} {}", s, symbol)
+    format!("{} {}", localize_decimal(s, locale), symbol)
+}
+
+/// The maintenance-fee disclosure for vaults of `collateral_type` (`None` =
+/// ICP, as in `resolve_collateral_display`), or an empty string when the
+/// collateral charges none.
+fn maintenance_fee_notice(collateral_type: Option<Principal>, symbol: &str, locale: Locale) -> String {
+    let fee = crate::state::read_state(|s| {
+        let ct = collateral_type.unwrap_or_else(|| s.icp_collateral_type());
+        s.get_maintenance_fee_apr_for(&ct).to_f64()
+    });
+    if fee <= 0.0 {
+        return String::new();
+    }
+    let rate = format!("{}%", localize_decimal(format!("{:.2}", fee * 100.0), locale));
+    render(
+        template(locale, MessageKey::MaintenanceFeeNotice),
+        &[("symbol", symbol.to_string()), ("rate", rate)],
+    )
 }
 
 /// Helper to convert bytes to hex string for debugging
@@ -199,6 +231,21 @@ fn try_decode_vault_arg(arg: &[u8], _method_name: &str) -> Result<Option<VaultAr
     }
 }
 
+/// Try to decode RepayAllAndCloseArg - returns None for graceful fallback
+fn try_decode_repay_all_and_close_arg(
+    arg: &[u8],
+    _method_name: &str,
+) -> Result<Option<RepayAllAndCloseArg>, String> {
+    if arg.is_empty() || arg.len() < 6 {
+        return Ok(None);
+    }
+
+    match Decode!(arg, RepayAllAndCloseArg) {
+        Ok(value) => Ok(Some(value)),
+        Err(_) => Ok(None),
+    }
+}
+
 /// Try to decode (principal, u64) for redeem_collateral — the collateral type
 /// being redeemed for, and the icUSD amount in e8s.
 fn try_decode_principal_u64(
@@ -267,7 +314,11 @@ fn try_decode_u64_u64_opt_principal(
 }
 
 /// Generate consent message for a specific method and arguments
-fn generate_consent_message(method: &str, arg: &[u8]) -> Result<String, String> {
+fn generate_consent_message(method: &str, arg: &[u8], locale: Locale) -> Result<String, String> {
+    let msg = |key: MessageKey, params: &[(&str, String)]| -> Result<String, String> {
+        Ok(render(template(locale, key), params))
+    };
+    let icusd = |e8s: u64| format_icusd_amount(e8s, locale);
     match method {
         "open_vault" => {
             // Decode argument: (nat64, opt principal) — collateral amount in the
@@ -275,25 +326,14 @@ fn generate_consent_message(method: &str, arg: &[u8]) -> Result<String, String>
             match try_decode_u64_opt_principal(arg, "open_vault")? {
                 Some((amount, collateral_type)) => {
                     let (symbol, decimals) = resolve_collateral_display(collateral_type);
-                    Ok(format!(
-                        "## Create New Vault\n\n\
-                        You are creating a new vault with **{}** as collateral.\n\n\
-                        This will:\n\
-                        - Lock your {} in the Rumi Protocol\n\
-                        - Create a new vault that you can borrow icUSD against\n\n\
-                        *Minimum collateral ratio: 150%*",
-                        format_collateral_amount(amount, decimals, &symbol),
-                        symbol
-                    ))
+                    let notice = maintenance_fee_notice(collateral_type, &symbol, locale);
+                    msg(MessageKey::OpenVault, &[
+                        ("amount", format_collateral_amount(amount, decimals, &symbol, locale)),
+                        ("symbol", symbol),
+                    ])
+                    .map(|m| m + &notice)
                 }
-                None => Ok(
-                    "## Create New Vault\n\n\
-                    You are creating a new vault in the Rumi Protocol.\n\n\
-                    This will:\n\
-                    - Lock your chosen collateral in the Rumi Protocol\n\
-                    - Create a new vault that you can borrow icUSD against\n\n\
-                    *Minimum collateral ratio: 150%*".to_string()
-                ),
+                None => msg(MessageKey::OpenVaultGeneric, &[]),
             }
         }
 
@@ -303,42 +343,24 @@ fn generate_consent_message(method: &str, arg: &[u8]) -> Result<String, String>
             match try_decode_u64_u64_opt_principal(arg, "open_vault_and_borrow")? {
                 Some((collateral, borrow, collateral_type)) if borrow > 0 => {
                     let (symbol, decimals) = resolve_collateral_display(collateral_type);
-                    Ok(format!(
-                        "## Create Vault & Borrow\n\n\
-                        You are creating a new vault with **{}** as collateral \
-                        and borrowing **{}**.\n\n\
-                        This will:\n\
-                        - Lock your {} in the Rumi Protocol\n\
-                        - Create a new vault\n\
-                        - Borrow icUSD to your wallet\n\n\
-                        *A small borrowing fee will be applied. Minimum collateral ratio: 150%*",
-                        format_collateral_amount(collateral, decimals, &symbol),
-                        format_icusd_amount(borrow),
-                        symbol
-                    ))
+                    let notice = maintenance_fee_notice(collateral_type, &symbol, locale);
+                    msg(MessageKey::OpenVaultAndBorrow, &[
+                        ("amount", format_collateral_amount(collateral, decimals, &symbol, locale)),
+                        ("borrow", icusd(borrow)),
+                        ("symbol", symbol),
+                    ])
+                    .map(|m| m + &notice)
                 }
                 Some((collateral, _, collateral_type)) => {
                     let (symbol, decimals) = resolve_collateral_display(collateral_type);
-                    Ok(format!(
-                        "## Create New Vault\n\n\
-                        You are creating a new vault with **{}** as collateral.\n\n\
-                        This will:\n\
-                        - Lock your {} in the Rumi Protocol\n\
-                        - Create a new vault that you can borrow icUSD against\n\n\
-                        *Minimum collateral ratio: 150%*",
-                        format_collateral_amount(collateral, decimals, &symbol),
-                        symbol
-                    ))
+                    let notice = maintenance_fee_notice(collateral_type, &symbol, locale);
+                    msg(MessageKey::OpenVault, &[
+                        ("amount", format_collateral_amount(collateral, decimals, &symbol, locale)),
+                        ("symbol", symbol),
+                    ])
+                    .map(|m| m + &notice)
                 }
-                None => Ok(
-                    "## Create Vault & Borrow\n\n\
-                    You are creating a new vault and borrowing icUSD.\n\n\
-                    This will:\n\
-                    - Lock your chosen collateral in the Rumi Protocol\n\
-                    - Create a new vault\n\
-                    - Borrow icUSD to your wallet\n\n\
-                    *A small borrowing fee will be applied. Minimum collateral ratio: 150%*".to_string()
-                ),
+                None => msg(MessageKey::OpenVaultAndBorrowGeneric, &[]),
             }
         }
 
@@ -346,106 +368,58 @@ fn generate_consent_message(method: &str, arg: &[u8]) -> Result<String, String>
             match try_decode_vault_arg(arg, "add_margin_to_vault")? {
                 Some(vault_arg) => {
                     let (symbol, decimals) = resolve_collateral_for_vault(vault_arg.vault_id);
-                    Ok(format!(
-                        "## Add Collateral to Vault\n\n\
-                        You are adding **{}** to vault #{}.\n\n\
-                        This will increase your collateral ratio and reduce liquidation risk.",
-                        format_collateral_amount(vault_arg.amount, decimals, &symbol),
-                        vault_arg.vault_id
-                    ))
+                    msg(MessageKey::AddMargin, &[
+                        ("amount", format_collateral_amount(vault_arg.amount, decimals, &symbol, locale)),
+                        ("vault_id", vault_arg.vault_id.to_string()),
+                    ])
                 }
-                None => Ok(
-                    "## Add Collateral to Vault\n\n\
-                    You are adding collateral to your vault.\n\n\
-                    This will increase your collateral ratio and reduce liquidation risk.".to_string()
-                ),
+                None => msg(MessageKey::AddMarginGeneric, &[]),
             }
         }
         
         "borrow_from_vault" => {
             match try_decode_vault_arg(arg, "borrow_from_vault")? {
-                Some(vault_arg) => Ok(format!(
-                    "## Borrow icUSD\n\n\
-                    You are borrowing **{}** from vault #{}.\n\n\
-                    This will:\n\
-                    - Transfer icUSD to your wallet\n\
-                    - Decrease your collateral ratio\n\n\
-                    *A small borrowing fee will be applied.*",
-                    format_icusd_amount(vault_arg.amount),
-                    vault_arg.vault_id
-                )),
-                None => Ok(
-                    "## Borrow icUSD\n\n\
-                    You are borrowing icUSD from your vault.\n\n\
-                    This will:\n\
-                    - Transfer icUSD to your wallet\n\
-                    - Decrease your collateral ratio\n\n\
-                    *A small borrowing fee will be applied.*".to_string()
-                ),
+                Some(vault_arg) => msg(MessageKey::Borrow, &[
+                    ("amount", icusd(vault_arg.amount)),
+                    ("vault_id", vault_arg.vault_id.to_string()),
+                ]),
+                None => msg(MessageKey::BorrowGeneric, &[]),
             }
         }
         
         "repay_to_vault" => {
             match try_decode_vault_arg(arg, "repay_to_vault")? {
-                Some(vault_arg) => Ok(format!(
-                    "## Repay icUSD\n\n\
-                    You are repaying **{}** to vault #{}.\n\n\
-                    This will:\n\
-                    - Burn the icUSD from your balance\n\
-                    - Increase your collateral ratio",
-                    format_icusd_amount(vault_arg.amount),
-                    vault_arg.vault_id
-                )),
-                None => Ok(
-                    "## Repay icUSD\n\n\
-                    You are repaying icUSD to your vault.\n\n\
-                    This will:\n\
-                    - Burn the icUSD from your balance\n\
-                    - Increase your collateral ratio".to_string()
-                ),
+                Some(vault_arg) => msg(MessageKey::Repay, &[
+                    ("amount", icusd(vault_arg.amount)),
+                    ("vault_id", vault_arg.vault_id.to_string()),
+                ]),
+                None => msg(MessageKey::RepayGeneric, &[]),
             }
         }
 
         "repay_and_close_vault" => {
             match try_decode_vault_arg(arg, "repay_and_close_vault")? {
-                Some(vault_arg) => Ok(format!(
-                    "## Repay and Close Vault\n\n\
-                    You are repaying **{}** to vault #{} and closing it.\n\n\
-                    This will:\n\
-                    - Burn the icUSD from your balance\n\
-                    - Return all remaining collateral to your wallet\n\
-                    - Remove the vault from the protocol",
-                    format_icusd_amount(vault_arg.amount),
-                    vault_arg.vault_id
-                )),
-                None => Ok(
-                    "## Repay and Close Vault\n\n\
-                    You are repaying icUSD to your vault and closing it.\n\n\
-                    This will:\n\
-                    - Burn the icUSD from your balance\n\
-                    - Return all remaining collateral to your wallet\n\
-                    - Remove the vault from the protocol".to_string()
-                ),
+                Some(vault_arg) => msg(MessageKey::RepayAndClose, &[
+                    ("amount", icusd(vault_arg.amount)),
+                    ("vault_id", vault_arg.vault_id.to_string()),
+                ]),
+                None => msg(MessageKey::RepayAndCloseGeneric, &[]),
+            }
+        }
+
+        "repay_all_and_close_vault" => {
+            match try_decode_repay_all_and_close_arg(arg, "repay_all_and_close_vault")? {
+                Some(close_arg) => msg(MessageKey::RepayAllAndClose, &[
+                    ("vault_id", close_arg.vault_id.to_string()),
+                ]),
+                None => msg(MessageKey::RepayAndCloseGeneric, &[]),
             }
         }
 
         "close_vault" => {
             match try_decode_u64(arg, "close_vault")? {
-                Some(vault_id) => Ok(format!(
-                    "## Close Vault\n\n\
-                    You are closing vault #{}.\n\n\
-                    **Requirements:**\n\
-                    - All borrowed icUSD must be repaid first\n\n\
-                    Your remaining collateral will be returned to your wallet.",
-                    vault_id
-                )),
-                None => Ok(
-                    "## Close Vault\n\n\
-                    You are closing your vault.\n\n\
-                    **Requirements:**\n\
-                    - All borrowed icUSD must be repaid first\n\n\
-                    Your remaining collateral will be returned to your wallet.".to_string()
-                ),
+                Some(vault_id) => msg(MessageKey::CloseVault, &[("vault_id", vault_id.to_string())]),
+                None => msg(MessageKey::CloseVaultGeneric, &[]),
             }
         }
         
@@ -457,130 +431,56 @@ fn generate_consent_message(method: &str, arg: &[u8]) -> Result<String, String>
             match try_decode_u64(arg, "withdraw_collateral")? {
                 Some(vault_id) => {
                     let (symbol, _decimals) = resolve_collateral_for_vault(vault_id);
-                    Ok(format!(
-                        "## Withdraw Collateral\n\n\
-                        You are withdrawing excess **{}** collateral from vault #{}.\n\n\
-                        Only collateral above the minimum ratio can be withdrawn.",
-                        symbol,
-                        vault_id
-                    ))
+                    msg(MessageKey::WithdrawCollateral, &[
+                        ("symbol", symbol),
+                        ("vault_id", vault_id.to_string()),
+                    ])
                 }
-                None => Ok(
-                    "## Withdraw Collateral\n\n\
-                    You are withdrawing excess collateral from your vault.\n\n\
-                    Only collateral above the minimum ratio can be withdrawn.".to_string()
-                ),
+                None => msg(MessageKey::WithdrawCollateralGeneric, &[]),
             }
         }
         
         "withdraw_and_close_vault" => {
             match try_decode_u64(arg, "withdraw_and_close_vault")? {
-                Some(vault_id) => Ok(format!(
-                    "## Withdraw and Close Vault\n\n\
-                    You are withdrawing all collateral and closing vault #{}.\n\n\
-                    **Requirements:**\n\
-                    - All borrowed icUSD must be repaid first\n\n\
-                    All collateral will be returned to your wallet.",
-                    vault_id
-                )),
-                None => Ok(
-                    "## Withdraw and Close Vault\n\n\
-                    You are withdrawing all collateral and closing your vault.\n\n\
-                    **Requirements:**\n\
-                    - All borrowed icUSD must be repaid first\n\n\
-                    All collateral will be returned to your wallet.".to_string()
-                ),
+                Some(vault_id) => {
+                    msg(MessageKey::WithdrawAndClose, &[("vault_id", vault_id.to_string())])
+                }
+                None => msg(MessageKey::WithdrawAndCloseGeneric, &[]),
             }
         }
         
         "liquidate_vault" => {
             match try_decode_u64(arg, "liquidate_vault")? {
-                Some(vault_id) => Ok(format!(
-                    "## Liquidate Vault\n\n\
-                    You are liquidating vault #{} which is undercollateralized.\n\n\
-                    This will:\n\
-                    - Use icUSD from the stability pool to cover the debt\n\
-                    - Transfer the vault's collateral to liquidators\n\n\
-                    *You will receive a liquidation reward.*",
-                    vault_id
-                )),
-                None => Ok(
-                    "## Liquidate Vault\n\n\
-                    You are liquidating an undercollateralized vault.\n\n\
-                    This will:\n\
-                    - Use icUSD from the stability pool to cover the debt\n\
-                    - Transfer the vault's collateral to liquidators\n\n\
-                    *You will receive a liquidation reward.*".to_string()
-                ),
+                Some(vault_id) => msg(MessageKey::Liquidate, &[("vault_id", vault_id.to_string())]),
+                None => msg(MessageKey::LiquidateGeneric, &[]),
             }
         }
         
         "liquidate_vault_partial" => {
             match try_decode_u64_pair(arg, "liquidate_vault_partial")? {
-                Some((vault_id, amount)) => Ok(format!(
-                    "## Partial Liquidation\n\n\
-                    You are partially liquidating vault #{} for **{}**.\n\n\
-                    This will:\n\
-                    - Repay part of the vault's debt\n\
-                    - Transfer proportional collateral to you at a discount\n\n\
-                    *You will receive the collateral at a discount to market rate.*",
-                    vault_id,
-                    format_icusd_amount(amount)
-                )),
-                None => Ok(
-                    "## Partial Liquidation\n\n\
-                    You are partially liquidating an undercollateralized vault.\n\n\
-                    This will:\n\
-                    - Repay part of the vault's debt\n\
-                    - Transfer proportional collateral to you at a discount\n\n\
-                    *You will receive the collateral at a discount to market rate.*".to_string()
-                ),
+                Some((vault_id, amount)) => msg(MessageKey::PartialLiquidation, &[
+                    ("vault_id", vault_id.to_string()),
+                    ("amount", icusd(amount)),
+                ]),
+                None => msg(MessageKey::PartialLiquidationGeneric, &[]),
             }
         }
         
         "provide_liquidity" => {
             match try_decode_u64(arg, "provide_liquidity")? {
-                Some(amount) => Ok(format!(
-                    "## Provide Liquidity to Stability Pool\n\n\
-                    You are depositing **{}** to the stability pool.\n\n\
-                    Benefits:\n\
-                    - Earn rewards from liquidations\n\
-                    - Support the protocol's stability\n\n\
-                    *You can withdraw your liquidity at any time.*",
-                    format_icusd_amount(amount)
-                )),
-                None => Ok(
-                    "## Provide Liquidity to Stability Pool\n\n\
-                    You are depositing icUSD to the stability pool.\n\n\
-                    Benefits:\n\
-                    - Earn rewards from liquidations\n\
-                    - Support the protocol's stability\n\n\
-                    *You can withdraw your liquidity at any time.*".to_string()
-                ),
+                Some(amount) => msg(MessageKey::ProvideLiquidity, &[("amount", icusd(amount))]),
+                None => msg(MessageKey::ProvideLiquidityGeneric, &[]),
             }
         }
         
         "withdraw_liquidity" => {
             match try_decode_u64(arg, "withdraw_liquidity")? {
-                Some(amount) => Ok(format!(
-                    "## Withdraw from Stability Pool\n\n\
-                    You are withdrawing **{}** from the stability pool.\n\n\
-                    Your icUSD will be returned to your wallet.",
-                    format_icusd_amount(amount)
-                )),
-                None => Ok(
-                    "## Withdraw from Stability Pool\n\n\
-                    You are withdrawing icUSD from the stability pool.\n\n\
-                    Your icUSD will be returned to your wallet.".to_string()
-                ),
+                Some(amount) => msg(MessageKey::WithdrawLiquidity, &[("amount", icusd(amount))]),
+                None => msg(MessageKey::WithdrawLiquidityGeneric, &[]),
             }
         }
         
-        "claim_liquidity_returns" => {
-            Ok("## Claim Liquidation Rewards\n\n\
-                You are claiming your accumulated liquidation rewards.\n\n\
-                This will transfer all earned ICP collateral to your wallet.".to_string())
-        }
+        "claim_liquidity_returns" => msg(MessageKey::ClaimLiquidityReturns, &[]),
         
         "redeem_collateral" => {
             // Argument: (principal, nat64) — the collateral type to receive and
@@ -588,119 +488,52 @@ fn generate_consent_message(method: &str, arg: &[u8]) -> Result<String, String>
             match try_decode_principal_u64(arg, "redeem_collateral")? {
                 Some((collateral_type, amount)) => {
                     let (symbol, _decimals) = resolve_collateral_display(Some(collateral_type));
-                    Ok(format!(
-                        "## Redeem icUSD for {}\n\n\
-                        You are redeeming **{}** for {}.\n\n\
-                        This will:\n\
-                        - Burn your icUSD\n\
-                        - Transfer {} to your wallet at the current oracle rate\n\n\
-                        *A small redemption fee may apply.*",
-                        symbol,
-                        format_icusd_amount(amount),
-                        symbol,
-                        symbol
-                    ))
+                    msg(MessageKey::RedeemCollateral, &[
+                        ("amount", icusd(amount)),
+                        ("symbol", symbol),
+                    ])
                 }
-                None => Ok(
-                    "## Redeem icUSD for Collateral\n\n\
-                    You are redeeming icUSD for collateral.\n\n\
-                    This will:\n\
-                    - Burn your icUSD\n\
-                    - Transfer collateral to your wallet at the current oracle rate\n\n\
-                    *A small redemption fee may apply.*".to_string()
-                ),
+                None => msg(MessageKey::RedeemCollateralGeneric, &[]),
             }
         }
 
         "redeem_icp" => {
             match try_decode_u64(arg, "redeem_icp")? {
-                Some(amount) => Ok(format!(
-                    "## Redeem icUSD for ICP\n\n\
-                    You are redeeming **{}** for ICP.\n\n\
-                    This will:\n\
-                    - Burn your icUSD\n\
-                    - Transfer ICP to your wallet at the current oracle rate\n\n\
-                    *A small redemption fee may apply.*",
-                    format_icusd_amount(amount)
-                )),
-                None => Ok(
-                    "## Redeem icUSD for ICP\n\n\
-                    You are redeeming icUSD for ICP.\n\n\
-                    This will:\n\
-                    - Burn your icUSD\n\
-                    - Transfer ICP to your wallet at the current oracle rate\n\n\
-                    *A small redemption fee may apply.*".to_string()
-                ),
+                Some(amount) => msg(MessageKey::RedeemIcp, &[("amount", icusd(amount))]),
+                None => msg(MessageKey::RedeemIcpGeneric, &[]),
             }
         }
         
         // ─── Push-deposit methods (Oisy wallet integration) ───
         "open_vault_with_deposit" => {
             match try_decode_u64(arg, "open_vault_with_deposit")? {
-                Some(borrow_amount) if borrow_amount > 0 => Ok(format!(
-                    "## Create Vault (Push-Deposit)\n\n\
-                    You are creating a new vault using collateral you deposited to your deposit account.\n\n\
-                    Requested initial borrow: **{}**\n\n\
-                    This will:\n\
-                    - Sweep deposited collateral into the protocol\n\
-                    - Create a new vault\n\
-                    - Borrow the requested icUSD amount\n\n\
-                    *Minimum collateral ratio: 150%*",
-                    format_icusd_amount(borrow_amount)
-                )),
-                _ => Ok(
-                    "## Create Vault (Push-Deposit)\n\n\
-                    You are creating a new vault using collateral you deposited to your deposit account.\n\n\
-                    This will:\n\
-                    - Sweep deposited collateral into the protocol\n\
-                    - Create a new vault that you can borrow icUSD against\n\n\
-                    *Minimum collateral ratio: 150%*".to_string()
-                ),
+                Some(borrow_amount) if borrow_amount > 0 => {
+                    msg(MessageKey::OpenVaultWithDeposit, &[("amount", icusd(borrow_amount))])
+                }
+                _ => msg(MessageKey::OpenVaultWithDepositGeneric, &[]),
             }
         }
 
         "add_margin_with_deposit" => {
             match try_decode_u64(arg, "add_margin_with_deposit")? {
-                Some(vault_id) => Ok(format!(
-                    "## Add Collateral (Push-Deposit)\n\n\
-                    You are adding collateral to vault #{} using funds from your deposit account.\n\n\
-                    This will sweep your deposited collateral and increase your vault's collateral ratio.",
-                    vault_id
-                )),
-                None => Ok(
-                    "## Add Collateral (Push-Deposit)\n\n\
-                    You are adding collateral to your vault using funds from your deposit account.\n\n\
-                    This will increase your collateral ratio and reduce liquidation risk.".to_string()
-                ),
+                Some(vault_id) => {
+                    msg(MessageKey::AddMarginWithDeposit, &[("vault_id", vault_id.to_string())])
+                }
+                None => msg(MessageKey::AddMarginWithDepositGeneric, &[]),
             }
         }
 
-        "get_deposit_account" => {
-            Ok("## Get Deposit Account\n\n\
-                This is a read-only query that returns your deposit account address.\n\
-                No funds will be moved.".to_string())
-        }
+        "get_deposit_account" => msg(MessageKey::GetDepositAccount, &[]),
 
         // Query methods don't need consent messages, but we handle them gracefully
         "get_fees" | "get_liquidity_status" | "get_protocol_status" |
         "get_vaults" | "get_vault_history" | "get_events" |
         "get_redemption_rate" | "get_liquidatable_vaults" | "http_request" => {
-            Ok(format!(
-                "## Query: {}\n\n\
-                This is a read-only query that does not modify any state.",
-                method
-            ))
+            msg(MessageKey::Query, &[("method", method.to_string())])
         }
         
-        _ => {
-            // Unknown method - provide a generic message
-            Ok(format!(
-                "## Rumi Protocol Action\n\n\
-                You are calling the **{}** method on the Rumi Protocol.\n\n\
-                *Please verify this action before approving.*",
-                method
-            ))
-        }
+        // Unknown method - provide a generic message
+        _ => msg(MessageKey::Unknown, &[("method", method.to_string())]),
     }
 }
 
@@ -717,7 +550,18 @@ pub fn icrc21_canister_call_consent_message(
         request.user_preferences.metadata.language
     );
     
-    let message = match generate_consent_message(&request.method, &request.arg) {
+    // Unsupported languages fall back to English; the response reports the
+    // language actually used. A wallet that sends no language gets the
+    // caller's stored preference (see `user_preferences`).
+    let locale = crate::state::read_state(|s| {
+        crate::user_preferences::consent_locale(
+            s,
+            ic_cdk::caller(),
+            &request.user_preferences.metadata.language,
+        )
+    });
+
+    let message = match generate_consent_message(&request.method, &request.arg, locale) {
         Ok(msg) => {
             ic_cdk::println!("[ICRC21] Generated message successfully for method: {}", request.method);
             msg
@@ -750,7 +594,7 @@ pub fn icrc21_canister_call_consent_message(
                     
                     if clean_line.is_empty() {
                         vec![]
-                    } else if clean_line.len() <= chars {
+                    } else if clean_line.chars().count() <= chars {
                         vec![clean_line]
                     } else {
                         // Word wrap
@@ -759,7 +603,7 @@ pub fn icrc21_canister_call_consent_message(
                         for word in clean_line.split_whitespace() {
                             if current_line.is_empty() {
                                 current_line = word.to_string();
-                            } else if current_line.len() + 1 + word.len() <= chars {
+                            } else if current_line.chars().count() + 1 + word.chars().count() <= chars {
                                 current_line.push(' ');
                                 current_line.push_str(word);
                             } else {
@@ -793,7 +637,7 @@ pub fn icrc21_canister_call_consent_message(
 
     Ok(ConsentInfo {
         metadata: ConsentMessageMetadata {
-            language: request.user_preferences.metadata.language,
+            language: locale.language_tag().to_string(),
             utc_offset_minutes: request.user_preferences.metadata.utc_offset_minutes,
         },
         consent_message,
@@ -819,6 +663,10 @@ pub fn icrc28_trusted_origins() -> Icrc28TrustedOriginsResponse {
 /// ICRC-10: Return supported standards
 pub fn icrc10_supported_standards() -> Vec<StandardRecord> {
     vec![
+        StandardRecord {
+            name: "ICRC-3".to_string(),
+            url: "https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-3".to_string(),
+        },
         StandardRecord {
             name: "ICRC-21".to_string(),
             url: "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-21/ICRC-21.md".to_string(),
@@ -849,19 +697,19 @@ mod tests {
     #[test]
     fn format_collateral_amount_respects_decimals_and_symbol() {
         // 8-decimal ICP
-        assert_eq!(format_collateral_amount(400_000, 8, "ICP"), "0.004 ICP");
+        assert_eq!(format_collateral_amount(400_000, 8, "ICP", Locale::En), "0.004 ICP");
         // 6-decimal XRP (drops)
-        assert_eq!(format_collateral_amount(400_000, 6, "XRP"), "0.4 XRP");
+        assert_eq!(format_collateral_amount(400_000, 6, "XRP", Locale::En), "0.4 XRP");
         // 18-decimal ckETH — the fixed /1e8 divisor would have understated this
         // by 10 orders of magnitude and labeled it ICP.
         assert_eq!(
-            format_collateral_amount(4_000_000_000_000_000, 18, "ckETH"),
+            format_collateral_amount(4_000_000_000_000_000, 18, "ckETH", Locale::En),
             "0.004 ckETH"
         );
         // Whole number trims the trailing dot.
-        assert_eq!(format_collateral_amount(500_000_000, 8, "ICP"), "5 ICP");
+        assert_eq!(format_collateral_amount(500_000_000, 8, "ICP", Locale::En), "5 ICP");
         // Zero.
-        assert_eq!(format_collateral_amount(0, 8, "ckXAUT"), "0 ckXAUT");
+        assert_eq!(format_collateral_amount(0, 8, "ckXAUT", Locale::En), "0 ckXAUT");
     }
 
     #[test]
@@ -904,17 +752,108 @@ mod tests {
         );
     }
 
+    #[test]
+    fn decode_repay_all_and_close() {
+        let close_arg = RepayAllAndCloseArg {
+            vault_id: 9,
+            token_type: Some(crate::StableTokenType::CKUSDC),
+        };
+        let arg = Encode!(&close_arg).unwrap();
+        assert_eq!(
+            try_decode_repay_all_and_close_arg(&arg, "repay_all_and_close_vault").unwrap(),
+            Some(close_arg)
+        );
+        let en = generate_consent_message("repay_all_and_close_vault", &arg, Locale::En).unwrap();
+        assert!(en.contains("all outstanding debt of vault #9"));
+    }
+
     // The generic (empty-arg) fallbacks are what Oisy renders while the user is
     // still typing. They must never claim "ICP" for what could be any collateral
     // — that is the exact bug this module fixes.
     #[test]
     fn generic_collateral_messages_never_hardcode_icp() {
         for method in ["open_vault", "open_vault_and_borrow", "add_margin_to_vault"] {
-            let msg = generate_consent_message(method, &[]).unwrap();
-            assert!(
-                !msg.contains("ICP"),
-                "generic {method} consent message must not hardcode ICP: {msg}"
-            );
+            for locale in [Locale::En, Locale::Es] {
+                let msg = generate_consent_message(method, &[], locale).unwrap();
+                assert!(
+                    !msg.contains("ICP"),
+                    "generic {method} consent message must not hardcode ICP: {msg}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn locale_is_selected_from_the_primary_language_subtag() {
+        assert_eq!(Locale::from_language_tag("es"), Locale::Es);
+        assert_eq!(Locale::from_language_tag("ES"), Locale::Es);
+        assert_eq!(Locale::from_language_tag("es-MX"), Locale::Es);
+        assert_eq!(Locale::from_language_tag("es_419"), Locale::Es);
+        assert_eq!(Locale::from_language_tag("en-GB"), Locale::En);
+        // Unsupported or missing languages fall back to English.
+        assert_eq!(Locale::from_language_tag("fr"), Locale::En);
+        assert_eq!(Locale::from_language_tag("esperanto"), Locale::En);
+        assert_eq!(Locale::from_language_tag(""), Locale::En);
+    }
+
+    #[test]
+    fn amounts_use_the_locale_decimal_separator() {
+        assert_eq!(format_icusd_amount(150_000_000, Locale::En), "1.50 icUSD");
+        assert_eq!(format_icusd_amount(150_000_000, Locale::Es), "1,50 icUSD");
+        assert_eq!(
+            format_collateral_amount(4_000_000_000_000_000, 18, "ckETH", Locale::Es),
+            "0,004 ckETH"
+        );
+        assert_eq!(format_collateral_amount(500_000_000, 8, "ICP", Locale::Es), "5 ICP");
+    }
+
+    #[test]
+    fn messages_are_rendered_in_the_requested_locale() {
+        let arg = Encode!(&VaultArg { vault_id: 7, amount: 250_000_000 }).unwrap();
+        let en = generate_consent_message("borrow_from_vault", &arg, Locale::En).unwrap();
+        assert!(en.starts_with("## Borrow icUSD"));
+        assert!(en.contains("You are borrowing **2.50 icUSD** from vault #7."));
+
+        let es = generate_consent_message("borrow_from_vault", &arg, Locale::Es).unwrap();
+        assert!(es.starts_with("## Pedir prestado icUSD"));
+        assert!(es.contains("Está pidiendo prestado **2,50 icUSD** de la bóveda #7."));
+
+        let unknown = generate_consent_message("mystery", &[], Locale::Es).unwrap();
+        assert!(unknown.contains("**mystery**"));
+    }
+
+    #[test]
+    fn every_template_fills_its_placeholders_in_both_locales() {
+        use MessageKey::*;
+        let params = [
+            ("amount", "1".to_string()),
+            ("borrow", "2".to_string()),
+            ("symbol", "ICP".to_string()),
+            ("vault_id", "3".to_string()),
+            ("method", "m".to_string()),
+            ("rate", "0.50%".to_string()),
+        ];
+        let keys = [
+            OpenVault, OpenVaultGeneric, OpenVaultAndBorrow, OpenVaultAndBorrowGeneric,
+            AddMargin, AddMarginGeneric, Borrow, BorrowGeneric, Repay, RepayGeneric,
+            RepayAndClose, RepayAndCloseGeneric, RepayAllAndClose, CloseVault, CloseVaultGeneric,
+            WithdrawCollateral, WithdrawCollateralGeneric, WithdrawAndClose,
+            WithdrawAndCloseGeneric, Liquidate, LiquidateGeneric, PartialLiquidation,
+            PartialLiquidationGeneric, ProvideLiquidity, ProvideLiquidityGeneric,
+            WithdrawLiquidity, WithdrawLiquidityGeneric, ClaimLiquidityReturns,
+            RedeemCollateral, RedeemCollateralGeneric, RedeemIcp, RedeemIcpGeneric,
+            OpenVaultWithDeposit, OpenVaultWithDepositGeneric, AddMarginWithDeposit,
+            AddMarginWithDepositGeneric, GetDepositAccount, Query, Unknown, MaintenanceFeeNotice,
+        ];
+        for key in keys {
+            let en = render(template(Locale::En, key), &params);
+            let es = render(template(Locale::Es, key), &params);
+            assert!(!en.contains('{') && !es.contains('{'), "{key:?} left a placeholder");
+            assert_ne!(en, es, "{key:?} is not translated");
         }
+        // A substituted value is never expanded again.
+        let symbol = ("symbol", "{vault_id}".to_string());
+        assert_eq!(render("{symbol} #{vault_id}", &[symbol, params[3].clone()]), "{vault_id} #3");
+        assert_eq!(render("{missing}", &params), "{missing}");
     }
 }
@@ -0,0 +1,706 @@
+// Localized ICRC-21 consent message templates.
+//
+// Each consent message is a template with `{name}` placeholders, filled in by
+// `render`. Amounts are formatted by the caller (see `Locale::decimal_separator`)
+// before being substituted, so templates only hold words and Markdown.
+
+/// Languages consent messages are available in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Locale {
+    En,
+    Es,
+}
+
+impl Locale {
+    /// Language used when the requested one is not supported.
+    pub const FALLBACK: Locale = Locale::En;
+
+    /// Pick the locale for a BCP-47 language tag. Only the primary language
+    /// subtag is considered and case is ignored, so "es", "ES" and "es-MX"
+    /// all select Spanish. Anything unsupported (or empty) falls back to
+    /// English.
+    pub fn from_language_tag(tag: &str) -> Locale {
+        let primary = tag
+            .trim()
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match primary.as_str() {
+            "en" => Locale::En,
+            "es" => Locale::Es,
+            _ => Self::FALLBACK,
+        }
+    }
+
+    /// BCP-47 tag reported back in `ConsentInfo::metadata`.
+    pub fn language_tag(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Es => "es",
+        }
+    }
+
+    pub fn decimal_separator(self) -> char {
+        match self {
+            Locale::En => '.',
+            Locale::Es => ',',
+        }
+    }
+}
+
+/// One consent message per method, with a `Generic` variant used when the
+/// arguments could not be decoded (e.g. Oisy probing before the user has
+/// entered an amount).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageKey {
+    OpenVault,
+    OpenVaultGeneric,
+    OpenVaultAndBorrow,
+    OpenVaultAndBorrowGeneric,
+    AddMargin,
+    AddMarginGeneric,
+    Borrow,
+    BorrowGeneric,
+    Repay,
+    RepayGeneric,
+    RepayAndClose,
+    RepayAndCloseGeneric,
+    RepayAllAndClose,
+    CloseVault,
+    CloseVaultGeneric,
+    WithdrawCollateral,
+    WithdrawCollateralGeneric,
+    WithdrawAndClose,
+    WithdrawAndCloseGeneric,
+    Liquidate,
+    LiquidateGeneric,
+    PartialLiquidation,
+    PartialLiquidationGeneric,
+    ProvideLiquidity,
+    ProvideLiquidityGeneric,
+    WithdrawLiquidity,
+    WithdrawLiquidityGeneric,
+    ClaimLiquidityReturns,
+    RedeemCollateral,
+    RedeemCollateralGeneric,
+    RedeemIcp,
+    RedeemIcpGeneric,
+    OpenVaultWithDeposit,
+    OpenVaultWithDepositGeneric,
+    AddMarginWithDeposit,
+    AddMarginWithDepositGeneric,
+    GetDepositAccount,
+    Query,
+    Unknown,
+    /// Appended to vault-opening messages when the collateral charges a
+    /// maintenance fee.
+    MaintenanceFeeNotice,
+}
+
+/// Template for `key` in `locale`.
+pub fn template(locale: Locale, key: MessageKey) -> &'static str {
+    match locale {
+        Locale::En => english(key),
+        Locale::Es => spanish(key),
+    }
+}
+
+/// Fill `{name}` placeholders from `params` in a single pass, so a
+/// substituted value (a token symbol, say) is never itself expanded. A
+/// placeholder without a parameter is left as written.
+pub fn render(template: &str, params: &[(&str, String)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let value = after.find('}').and_then(|close| {
+            let name = &after[..close];
+            params
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| (v, close))
+        });
+        match value {
+            Some((v, close)) => {
+                out.push_str(v);
+                rest = &after[close + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn english(key: MessageKey) -> &'static str {
+    match key {
+        MessageKey::OpenVault => {
+            "## Create New Vault\n\n\
+            You are creating a new vault with **{amount}** as collateral.\n\n\
+            This will:\n\
+            - Lock your {symbol} in the Rumi Protocol\n\
+            - Create a new vault that you can borrow icUSD against\n\n\
+            *Minimum collateral ratio: 150%*"
+        }
+        MessageKey::OpenVaultGeneric => {
+            "## Create New Vault\n\n\
+            You are creating a new vault in the Rumi Protocol.\n\n\
+            This will:\n\
+            - Lock your chosen collateral in the Rumi Protocol\n\
+            - Create a new vault that you can borrow icUSD against\n\n\
+            *Minimum collateral ratio: 150%*"
+        }
+        MessageKey::OpenVaultAndBorrow => {
+            "## Create Vault & Borrow\n\n\
+            You are creating a new vault with **{amount}** as collateral \
+            and borrowing **{borrow}**.\n\n\
+            This will:\n\
+            - Lock your {symbol} in the Rumi Protocol\n\
+            - Create a new vault\n\
+            - Borrow icUSD to your wallet\n\n\
+            *A small borrowing fee will be applied. Minimum collateral ratio: 150%*"
+        }
+        MessageKey::OpenVaultAndBorrowGeneric => {
+            "## Create Vault & Borrow\n\n\
+            You are creating a new vault and borrowing icUSD.\n\n\
+            This will:\n\
+            - Lock your chosen collateral in the Rumi Protocol\n\
+            - Create a new vault\n\
+            - Borrow icUSD to your wallet\n\n\
+            *A small borrowing fee will be applied. Minimum collateral ratio: 150%*"
+        }
+        MessageKey::AddMargin => {
+            "## Add Collateral to Vault\n\n\
+            You are adding **{amount}** to vault #{vault_id}.\n\n\
+            This will increase your collateral ratio and reduce liquidation risk."
+        }
+        MessageKey::AddMarginGeneric => {
+            "## Add Collateral to Vault\n\n\
+            You are adding collateral to your vault.\n\n\
+            This will increase your collateral ratio and reduce liquidation risk."
+        }
+        MessageKey::Borrow => {
+            "## Borrow icUSD\n\n\
+            You are borrowing **{amount}** from vault #{vault_id}.\n\n\
+            This will:\n\
+            - Transfer icUSD to your wallet\n\
+            - Decrease your collateral ratio\n\n\
+            *A small borrowing fee will be applied.*"
+        }
+        MessageKey::BorrowGeneric => {
+            "## Borrow icUSD\n\n\
+            You are borrowing icUSD from your vault.\n\n\
+            This will:\n\
+            - Transfer icUSD to your wallet\n\
+            - Decrease your collateral ratio\n\n\
+            *A small borrowing fee will be applied.*"
+        }
+        MessageKey::Repay => {
+            "## Repay icUSD\n\n\
+            You are repaying **{amount}** to vault #{vault_id}.\n\n\
+            This will:\n\
+            - Burn the icUSD from your balance\n\
+            - Increase your collateral ratio"
+        }
+        MessageKey::RepayGeneric => {
+            "## Repay icUSD\n\n\
+            You are repaying icUSD to your vault.\n\n\
+            This will:\n\
+            - Burn the icUSD from your balance\n\
+            - Increase your collateral ratio"
+        }
+        MessageKey::RepayAndClose => {
+            "## Repay and Close Vault\n\n\
+            You are repaying **{amount}** to vault #{vault_id} and closing it.\n\n\
+            This will:\n\
+            - Burn the icUSD from your balance\n\
+            - Return all remaining collateral to your wallet\n\
+            - Remove the vault from the protocol"
+        }
+        MessageKey::RepayAndCloseGeneric => {
+            "## Repay and Close Vault\n\n\
+            You are repaying icUSD to your vault and closing it.\n\n\
+            This will:\n\
+            - Burn the icUSD from your balance\n\
+            - Return all remaining collateral to your wallet\n\
+            - Remove the vault from the protocol"
+        }
+        MessageKey::RepayAllAndClose => {
+            "## Repay and Close Vault\n\n\
+            You are repaying all outstanding debt of vault #{vault_id} and closing it.\n\n\
+            This will:\n\
+            - Take the exact debt, including accrued interest, from your balance\n\
+            - Return all remaining collateral to your wallet\n\
+            - Remove the vault from the protocol"
+        }
+        MessageKey::CloseVault => {
+            "## Close Vault\n\n\
+            You are closing vault #{vault_id}.\n\n\
+            **Requirements:**\n\
+            - All borrowed icUSD must be repaid first\n\n\
+            Your remaining collateral will be returned to your wallet."
+        }
+        MessageKey::CloseVaultGeneric => {
+            "## Close Vault\n\n\
+            You are closing your vault.\n\n\
+            **Requirements:**\n\
+            - All borrowed icUSD must be repaid first\n\n\
+            Your remaining collateral will be returned to your wallet."
+        }
+        MessageKey::WithdrawCollateral => {
+            "## Withdraw Collateral\n\n\
+            You are withdrawing excess **{symbol}** collateral from vault #{vault_id}.\n\n\
+            Only collateral above the minimum ratio can be withdrawn."
+        }
+        MessageKey::WithdrawCollateralGeneric => {
+            "## Withdraw Collateral\n\n\
+            You are withdrawing excess collateral from your vault.\n\n\
+            Only collateral above the minimum ratio can be withdrawn."
+        }
+        MessageKey::WithdrawAndClose => {
+            "## Withdraw and Close Vault\n\n\
+            You are withdrawing all collateral and closing vault #{vault_id}.\n\n\
+            **Requirements:**\n\
+            - All borrowed icUSD must be repaid first\n\n\
+            All collateral will be returned to your wallet."
+        }
+        MessageKey::WithdrawAndCloseGeneric => {
+            "## Withdraw and Close Vault\n\n\
+            You are withdrawing all collateral and closing your vault.\n\n\
+            **Requirements:**\n\
+            - All borrowed icUSD must be repaid first\n\n\
+            All collateral will be returned to your wallet."
+        }
+        MessageKey::Liquidate => {
+            "## Liquidate Vault\n\n\
+            You are liquidating vault #{vault_id} which is undercollateralized.\n\n\
+            This will:\n\
+            - Use icUSD from the stability pool to cover the debt\n\
+            - Transfer the vault's collateral to liquidators\n\n\
+            *You will receive a liquidation reward.*"
+        }
+        MessageKey::LiquidateGeneric => {
+            "## Liquidate Vault\n\n\
+            You are liquidating an undercollateralized vault.\n\n\
+            This will:\n\
+            - Use icUSD from the stability pool to cover the debt\n\
+            - Transfer the vault's collateral to liquidators\n\n\
+            *You will receive a liquidation reward.*"
+        }
+        MessageKey::PartialLiquidation => {
+            "## Partial Liquidation\n\n\
+            You are partially liquidating vault #{vault_id} for **{amount}**.\n\n\
+            This will:\n\
+            - Repay part of the vault's debt\n\
+            - Transfer proportional collateral to you at a discount\n\n\
+            *You will receive the collateral at a discount to market rate.*"
+        }
+        MessageKey::PartialLiquidationGeneric => {
+            "## Partial Liquidation\n\n\
+            You are partially liquidating an undercollateralized vault.\n\n\
+            This will:\n\
+            - Repay part of the vault's debt\n\
+            - Transfer proportional collateral to you at a discount\n\n\
+            *You will receive the collateral at a discount to market rate.*"
+        }
+        MessageKey::ProvideLiquidity => {
+            "## Provide Liquidity to Stability Pool\n\n\
+            You are depositing **{amount}** to the stability pool.\n\n\
+            Benefits:\n\
+            - Earn rewards from liquidations\n\
+            - Support the protocol's stability\n\n\
+            *You can withdraw your liquidity at any time.*"
+        }
+        MessageKey::ProvideLiquidityGeneric => {
+            "## Provide Liquidity to Stability Pool\n\n\
+            You are depositing icUSD to the stability pool.\n\n\
+            Benefits:\n\
+            - Earn rewards from liquidations\n\
+            - Support the protocol's stability\n\n\
+            *You can withdraw your liquidity at any time.*"
+        }
+        MessageKey::WithdrawLiquidity => {
+            "## Withdraw from Stability Pool\n\n\
+            You are withdrawing **{amount}** from the stability pool.\n\n\
+            Your icUSD will be returned to your wallet."
+        }
+        MessageKey::WithdrawLiquidityGeneric => {
+            "## Withdraw from Stability Pool\n\n\
+            You are withdrawing icUSD from the stability pool.\n\n\
+            Your icUSD will be returned to your wallet."
+        }
+        MessageKey::ClaimLiquidityReturns => {
+            "## Claim Liquidation Rewards\n\n\
+            You are claiming your accumulated liquidation rewards.\n\n\
+            This will transfer all earned ICP collateral to your wallet."
+        }
+        MessageKey::RedeemCollateral => {
+            "## Redeem icUSD for {symbol}\n\n\
+            You are redeeming **{amount}** for {symbol}.\n\n\
+            This will:\n\
+            - Burn your icUSD\n\
+            - Transfer {symbol} to your wallet at the current oracle rate\n\n\
+            *A small redemption fee may apply.*"
+        }
+        MessageKey::RedeemCollateralGeneric => {
+            "## Redeem icUSD for Collateral\n\n\
+            You are redeeming icUSD for collateral.\n\n\
+            This will:\n\
+            - Burn your icUSD\n\
+            - Transfer collateral to your wallet at the current oracle rate\n\n\
+            *A small redemption fee may apply.*"
+        }
+        MessageKey::RedeemIcp => {
+            "## Redeem icUSD for ICP\n\n\
+            You are redeeming **{amount}** for ICP.\n\n\
+            This will:\n\
+            - Burn your icUSD\n\
+            - Transfer ICP to your wallet at the current oracle rate\n\n\
+            *A small redemption fee may apply.*"
+        }
+        MessageKey::RedeemIcpGeneric => {
+            "## Redeem icUSD for ICP\n\n\
+            You are redeeming icUSD for ICP.\n\n\
+            This will:\n\
+            - Burn your icUSD\n\
+            - Transfer ICP to your wallet at the current oracle rate\n\n\
+            *A small redemption fee may apply.*"
+        }
+        MessageKey::OpenVaultWithDeposit => {
+            "## Create Vault (Push-Deposit)\n\n\
+            You are creating a new vault using collateral you deposited to your deposit account.\n\n\
+            Requested initial borrow: **{amount}**\n\n\
+            This will:\n\
+            - Sweep deposited collateral into the protocol\n\
+            - Create a new vault\n\
+            - Borrow the requested icUSD amount\n\n\
+            *Minimum collateral ratio: 150%*"
+        }
+        MessageKey::OpenVaultWithDepositGeneric => {
+            "## Create Vault (Push-Deposit)\n\n\
+            You are creating a new vault using collateral you deposited to your deposit account.\n\n\
+            This will:\n\
+            - Sweep deposited collateral into the protocol\n\
+            - Create a new vault that you can borrow icUSD against\n\n\
+            *Minimum collateral ratio: 150%*"
+        }
+        MessageKey::AddMarginWithDeposit => {
+            "## Add Collateral (Push-Deposit)\n\n\
+            You are adding collateral to vault #{vault_id} using funds from your deposit account.\n\n\
+            This will sweep your deposited collateral and increase your vault's collateral ratio."
+        }
+        MessageKey::AddMarginWithDepositGeneric => {
+            "## Add Collateral (Push-Deposit)\n\n\
+            You are adding collateral to your vault using funds from your deposit account.\n\n\
+            This will increase your collateral ratio and reduce liquidation risk."
+        }
+        MessageKey::GetDepositAccount => {
+            "## Get Deposit Account\n\n\
+            This is a read-only query that returns your deposit account address.\n\
+            No funds will be moved."
+        }
+        MessageKey::Query => {
+            "## Query: {method}\n\n\
+            This is a read-only query that does not modify any state."
+        }
+        MessageKey::Unknown => {
+            "## Rumi Protocol Action\n\n\
+            You are calling the **{method}** method on the Rumi Protocol.\n\n\
+            *Please verify this action before approving.*"
+        }
+        MessageKey::MaintenanceFeeNotice => {
+            "\n\n**Maintenance fee:** {symbol} vaults are charged {rate} a year \
+            on the value of their collateral, added to the vault's debt even \
+            if you do not borrow."
+        }
+    }
+}
+
+fn spanish(key: MessageKey) -> &'static str {
+    match key {
+        MessageKey::OpenVault => {
+            "## Crear nueva bóveda\n\n\
+            Está creando una nueva bóveda con **{amount}** como colateral.\n\n\
+            Esto:\n\
+            - Bloqueará su {symbol} en Rumi Protocol\n\
+            - Creará una nueva bóveda contra la que podrá pedir prestado icUSD\n\n\
+            *Ratio de colateral mínimo: 150%*"
+        }
+        MessageKey::OpenVaultGeneric => {
+            "## Crear nueva bóveda\n\n\
+            Está creando una nueva bóveda en Rumi Protocol.\n\n\
+            Esto:\n\
+            - Bloqueará el colateral elegido en Rumi Protocol\n\
+            - Creará una nueva bóveda contra la que podrá pedir prestado icUSD\n\n\
+            *Ratio de colateral mínimo: 150%*"
+        }
+        MessageKey::OpenVaultAndBorrow => {
+            "## Crear bóveda y pedir prestado\n\n\
+            Está creando una nueva bóveda con **{amount}** como colateral \
+            y pidiendo prestado **{borrow}**.\n\n\
+            Esto:\n\
+            - Bloqueará su {symbol} en Rumi Protocol\n\
+            - Creará una nueva bóveda\n\
+            - Enviará el icUSD prestado a su billetera\n\n\
+            *Se aplicará una pequeña comisión de préstamo. Ratio de colateral mínimo: 150%*"
+        }
+        MessageKey::OpenVaultAndBorrowGeneric => {
+            "## Crear bóveda y pedir prestado\n\n\
+            Está creando una nueva bóveda y pidiendo prestado icUSD.\n\n\
+            Esto:\n\
+            - Bloqueará el colateral elegido en Rumi Protocol\n\
+            - Creará una nueva bóveda\n\
+            - Enviará el icUSD prestado a su billetera\n\n\
+            *Se aplicará una pequeña comisión de préstamo. Ratio de colateral mínimo: 150%*"
+        }
+        MessageKey::AddMargin => {
+            "## Añadir colateral a la bóveda\n\n\
+            Está añadiendo **{amount}** a la bóveda #{vault_id}.\n\n\
+            Esto aumentará su ratio de colateral y reducirá el riesgo de liquidación."
+        }
+        MessageKey::AddMarginGeneric => {
+            "## Añadir colateral a la bóveda\n\n\
+            Está añadiendo colateral a su bóveda.\n\n\
+            Esto aumentará su ratio de colateral y reducirá el riesgo de liquidación."
+        }
+        MessageKey::Borrow => {
+            "## Pedir prestado icUSD\n\n\
+            Está pidiendo prestado **{amount}** de la bóveda #{vault_id}.\n\n\
+            Esto:\n\
+            - Transferirá icUSD a su billetera\n\
+            - Reducirá su ratio de colateral\n\n\
+            *Se aplicará una pequeña comisión de préstamo.*"
+        }
+        MessageKey::BorrowGeneric => {
+            "## Pedir prestado icUSD\n\n\
+            Está pidiendo prestado icUSD de su bóveda.\n\n\
+            Esto:\n\
+            - Transferirá icUSD a su billetera\n\
+            - Reducirá su ratio de colateral\n\n\
+            *Se aplicará una pequeña comisión de préstamo.*"
+        }
+        MessageKey::Repay => {
+            "## Devolver icUSD\n\n\
+            Está devolviendo **{amount}** a la bóveda #{vault_id}.\n\n\
+            Esto:\n\
+            - Quemará el icUSD de su saldo\n\
+            - Aumentará su ratio de colateral"
+        }
+        MessageKey::RepayGeneric => {
+            "## Devolver icUSD\n\n\
+            Está devolviendo icUSD a su bóveda.\n\n\
+            Esto:\n\
+            - Quemará el icUSD de su saldo\n\
+            - Aumentará su ratio de colateral"
+        }
+        MessageKey::RepayAndClose => {
+            "## Devolver y cerrar bóveda\n\n\
+            Está devolviendo **{amount}** a la bóveda #{vault_id} y cerrándola.\n\n\
+            Esto:\n\
+            - Quemará el icUSD de su saldo\n\
+            - Devolverá todo el colateral restante a su billetera\n\
+            - Eliminará la bóveda del protocolo"
+        }
+        MessageKey::RepayAndCloseGeneric => {
+            "## Devolver y cerrar bóveda\n\n\
+            Está devolviendo icUSD a su bóveda y cerrándola.\n\n\
+            Esto:\n\
+            - Quemará el icUSD de su saldo\n\
+            - Devolverá todo el colateral restante a su billetera\n\
+            - Eliminará la bóveda del protocolo"
+        }
+        MessageKey::RepayAllAndClose => {
+            "## Devolver y cerrar bóveda\n\n\
+            Está devolviendo toda la deuda pendiente de la bóveda #{vault_id} y cerrándola.\n\n\
+            Esto:\n\
+            - Tomará de su saldo la deuda exacta, con los intereses acumulados\n\
+            - Devolverá todo el colateral restante a su billetera\n\
+            - Eliminará la bóveda del protocolo"
+        }
+        MessageKey::CloseVault => {
+            "## Cerrar bóveda\n\n\
+            Está cerrando la bóveda #{vault_id}.\n\n\
+            **Requisitos:**\n\
+            - Todo el icUSD prestado debe devolverse antes\n\n\
+            El colateral restante se devolverá a su billetera."
+        }
+        MessageKey::CloseVaultGeneric => {
+            "## Cerrar bóveda\n\n\
+            Está cerrando su bóveda.\n\n\
+            **Requisitos:**\n\
+            - Todo el icUSD prestado debe devolverse antes\n\n\
+            El colateral restante se devolverá a su billetera."
+        }
+        MessageKey::WithdrawCollateral => {
+            "## Retirar colateral\n\n\
+            Está retirando el colateral **{symbol}** excedente de la bóveda #{vault_id}.\n\n\
+            Solo se puede retirar el colateral por encima del ratio mínimo."
+        }
+        MessageKey::WithdrawCollateralGeneric => {
+            "## Retirar colateral\n\n\
+            Está retirando el colateral excedente de su bóveda.\n\n\
+            Solo se puede retirar el colateral por encima del ratio mínimo."
+        }
+        MessageKey::WithdrawAndClose => {
+            "## Retirar y cerrar bóveda\n\n\
+            Está retirando todo el colateral y cerrando la bóveda #{vault_id}.\n\n\
+            **Requisitos:**\n\
+            - Todo el icUSD prestado debe devolverse antes\n\n\
+            Todo el colateral se devolverá a su billetera."
+        }
+        MessageKey::WithdrawAndCloseGeneric => {
+            "## Retirar y cerrar bóveda\n\n\
+            Está retirando todo el colateral y cerrando su bóveda.\n\n\
+            **Requisitos:**\n\
+            - Todo el icUSD prestado debe devolverse antes\n\n\
+            Todo el colateral se devolverá a su billetera."
+        }
+        MessageKey::Liquidate => {
+            "## Liquidar bóveda\n\n\
+            Está liquidando la bóveda #{vault_id}, que no tiene colateral suficiente.\n\n\
+            Esto:\n\
+            - Usará icUSD del fondo de estabilidad para cubrir la deuda\n\
+            - Transferirá el colateral de la bóveda a los liquidadores\n\n\
+            *Recibirá una recompensa por la liquidación.*"
+        }
+        MessageKey::LiquidateGeneric => {
+            "## Liquidar bóveda\n\n\
+            Está liquidando una bóveda que no tiene colateral suficiente.\n\n\
+            Esto:\n\
+            - Usará icUSD del fondo de estabilidad para cubrir la deuda\n\
+            - Transferirá el colateral de la bóveda a los liquidadores\n\n\
+            *Recibirá una recompensa por la liquidación.*"
+        }
+        MessageKey::PartialLiquidation => {
+            "## Liquidación parcial\n\n\
+            Está liquidando parcialmente la bóveda #{vault_id} por **{amount}**.\n\n\
+            Esto:\n\
+            - Pagará parte de la deuda de la bóveda\n\
+            - Le transferirá el colateral proporcional con descuento\n\n\
+            *Recibirá el colateral con descuento sobre el precio de mercado.*"
+        }
+        MessageKey::PartialLiquidationGeneric => {
+            "## Liquidación parcial\n\n\
+            Está liquidando parcialmente una bóveda que no tiene colateral suficiente.\n\n\
+            Esto:\n\
+            - Pagará parte de la deuda de la bóveda\n\
+            - Le transferirá el colateral proporcional con descuento\n\n\
+            *Recibirá el colateral con descuento sobre el precio de mercado.*"
+        }
+        MessageKey::ProvideLiquidity => {
+            "## Aportar liquidez al fondo de estabilidad\n\n\
+            Está depositando **{amount}** en el fondo de estabilidad.\n\n\
+            Beneficios:\n\
+            - Gane recompensas de las liquidaciones\n\
+            - Contribuya a la estabilidad del protocolo\n\n\
+            *Puede retirar su liquidez en cualquier momento.*"
+        }
+        MessageKey::ProvideLiquidityGeneric => {
+            "## Aportar liquidez al fondo de estabilidad\n\n\
+            Está depositando icUSD en el fondo de estabilidad.\n\n\
+            Beneficios:\n\
+            - Gane recompensas de las liquidaciones\n\
+            - Contribuya a la estabilidad del protocolo\n\n\
+            *Puede retirar su liquidez en cualquier momento.*"
+        }
+        MessageKey::WithdrawLiquidity => {
+            "## Retirar del fondo de estabilidad\n\n\
+            Está retirando **{amount}** del fondo de estabilidad.\n\n\
+            Su icUSD se devolverá a su billetera."
+        }
+        MessageKey::WithdrawLiquidityGeneric => {
+            "## Retirar del fondo de estabilidad\n\n\
+            Está retirando icUSD del fondo de estabilidad.\n\n\
+            Su icUSD se devolverá a su billetera."
+        }
+        MessageKey::ClaimLiquidityReturns => {
+            "## Reclamar recompensas de liquidación\n\n\
+            Está reclamando sus recompensas de liquidación acumuladas.\n\n\
+            Esto transferirá todo el colateral ICP ganado a su billetera."
+        }
+        MessageKey::RedeemCollateral => {
+            "## Canjear icUSD por {symbol}\n\n\
+            Está canjeando **{amount}** por {symbol}.\n\n\
+            Esto:\n\
+            - Quemará su icUSD\n\
+            - Transferirá {symbol} a su billetera al precio actual del oráculo\n\n\
+            *Puede aplicarse una pequeña comisión de canje.*"
+        }
+        MessageKey::RedeemCollateralGeneric => {
+            "## Canjear icUSD por colateral\n\n\
+            Está canjeando icUSD por colateral.\n\n\
+            Esto:\n\
+            - Quemará su icUSD\n\
+            - Transferirá colateral a su billetera al precio actual del oráculo\n\n\
+            *Puede aplicarse una pequeña comisión de canje.*"
+        }
+        MessageKey::RedeemIcp => {
+            "## Canjear icUSD por ICP\n\n\
+            Está canjeando **{amount}** por ICP.\n\n\
+            Esto:\n\
+            - Quemará su icUSD\n\
+            - Transferirá ICP a su billetera al precio actual del oráculo\n\n\
+            *Puede aplicarse una pequeña comisión de canje.*"
+        }
+        MessageKey::RedeemIcpGeneric => {
+            "## Canjear icUSD por ICP\n\n\
+            Está canjeando icUSD por ICP.\n\n\
+            Esto:\n\
+            - Quemará su icUSD\n\
+            - Transferirá ICP a su billetera al precio actual del oráculo\n\n\
+            *Puede aplicarse una pequeña comisión de canje.*"
+        }
+        MessageKey::OpenVaultWithDeposit => {
+            "## Crear bóveda (depósito previo)\n\n\
+            Está creando una nueva bóveda con el colateral que depositó en su cuenta de depósito.\n\n\
+            Préstamo inicial solicitado: **{amount}**\n\n\
+            Esto:\n\
+            - Transferirá el colateral depositado al protocolo\n\
+            - Creará una nueva bóveda\n\
+            - Pedirá prestada la cantidad de icUSD solicitada\n\n\
+            *Ratio de colateral mínimo: 150%*"
+        }
+        MessageKey::OpenVaultWithDepositGeneric => {
+            "## Crear bóveda (depósito previo)\n\n\
+            Está creando una nueva bóveda con el colateral que depositó en su cuenta de depósito.\n\n\
+            Esto:\n\
+            - Transferirá el colateral depositado al protocolo\n\
+            - Creará una nueva bóveda contra la que podrá pedir prestado icUSD\n\n\
+            *Ratio de colateral mínimo: 150%*"
+        }
+        MessageKey::AddMarginWithDeposit => {
+            "## Añadir colateral (depósito previo)\n\n\
+            Está añadiendo colateral a la bóveda #{vault_id} con fondos de su cuenta de depósito.\n\n\
+            Esto transferirá el colateral depositado y aumentará el ratio de colateral de su bóveda."
+        }
+        MessageKey::AddMarginWithDepositGeneric => {
+            "## Añadir colateral (depósito previo)\n\n\
+            Está añadiendo colateral a su bóveda con fondos de su cuenta de depósito.\n\n\
+            Esto aumentará su ratio de colateral y reducirá el riesgo de liquidación."
+        }
+        MessageKey::GetDepositAccount => {
+            "## Obtener cuenta de depósito\n\n\
+            Esta es una consulta de solo lectura que devuelve la dirección de su cuenta de depósito.\n\
+            No se moverán fondos."
+        }
+        MessageKey::Query => {
+            "## Consulta: {method}\n\n\
+            Esta es una consulta de solo lectura que no modifica ningún estado."
+        }
+        MessageKey::Unknown => {
+            "## Acción de Rumi Protocol\n\n\
+            Está llamando al método **{method}** de Rumi Protocol.\n\n\
+            *Verifique esta acción antes de aprobarla.*"
+        }
+        MessageKey::MaintenanceFeeNotice => {
+            "\n\n**Comisión de mantenimiento:** las bóvedas de {symbol} pagan \
+            {rate} al año sobre el valor de su colateral, que se suma a la deuda \
+            de la bóveda aunque no pida prestado."
+        }
+    }
+}
@@ -0,0 +1,396 @@
+//! ICRC-3 log of the icUSD ledger operations the protocol initiates.
+//!
+//! Every icUSD mint, burn and transfer the backend makes goes through
+//! `management::transfer_idempotent` / `transfer_from_idempotent`, and each
+//! one that lands is appended here as an ICRC-3 block, so explorers and
+//! indexers can follow protocol activity through `icrc3_get_blocks` instead
+//! of decoding the event log. Blocks are kept in stable memory apart from
+//! `State` and chained by `phash` like a ledger's; the tip is certified, so
+//! `icrc3_get_tip_certificate` lets a client verify what it fetched.
+//!
+//! The block types are the ledger's own: `1mint` for payouts from the
+//! protocol's main account (the icUSD minting account), `1burn` for amounts
+//! moved into it, `1xfer` for other transfers from a protocol subaccount and
+//! `2xfer` for other pulls under an allowance, with the protocol as spender.
+//! Each block also carries `ledger_block`, the index of the icUSD ledger
+//! block it mirrors. A retry deduplicated by the ledger resolves to the same
+//! ledger block and is logged once. Approvals are not logged.
+
+use crate::state::read_state;
+use candid::{CandidType, Deserialize, Nat, Principal};
+use icrc_ledger_types::icrc::generic_value::{ICRC3Map, ICRC3Value};
+use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::icrc3::blocks::{
+    BlockWithId, GetBlocksRequest, GetBlocksResult, ICRC3DataCertificate, SupportedBlockType,
+};
+use num_traits::ToPrimitive;
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+
+/// Most blocks one `icrc3_get_blocks` call returns across all its ranges.
+pub const MAX_GET_BLOCKS_RESPONSE: u64 = 2_000;
+
+const ICRC3_URL: &str = "https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-3";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProtocolBlockKind {
+    Mint,
+    Burn,
+    Transfer,
+    TransferFrom,
+}
+
+impl ProtocolBlockKind {
+    pub fn btype(self) -> &'static str {
+        match self {
+            ProtocolBlockKind::Mint => "1mint",
+            ProtocolBlockKind::Burn => "1burn",
+            ProtocolBlockKind::Transfer => "1xfer",
+            ProtocolBlockKind::TransferFrom => "2xfer",
+        }
+    }
+}
+
+/// An icUSD ledger operation as the protocol submitted it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LedgerTransfer {
+    pub from: Account,
+    pub to: Account,
+    /// Set for `icrc2_transfer_from`, where the protocol spends an allowance.
+    pub spender: Option<Account>,
+    pub amount: u128,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+/// A stored block. The hash is kept with it, so the next block's `phash`
+/// and the certified tip need no re-encoding of the chain.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolBlock {
+    pub kind: ProtocolBlockKind,
+    pub from: Option<Account>,
+    pub to: Option<Account>,
+    pub spender: Option<Account>,
+    pub amount: u128,
+    pub memo: Option<ByteBuf>,
+    pub created_at_time: Option<u64>,
+    pub ledger_block_index: u64,
+    pub timestamp: u64,
+    pub phash: Option<[u8; 32]>,
+    pub hash: [u8; 32],
+}
+
+#[derive(CandidType, Clone, Debug, Deserialize)]
+pub struct GetArchivesArgs {
+    pub from: Option<Principal>,
+}
+
+#[derive(CandidType, Clone, Debug, Deserialize)]
+pub struct ArchiveInfo {
+    pub canister_id: Principal,
+    pub start: Nat,
+    pub end: Nat,
+}
+
+/// What a transfer is from the ledger's point of view, given that the
+/// protocol's main account is the icUSD minting account.
+pub fn classify(protocol: Principal, transfer: &LedgerTransfer) -> ProtocolBlockKind {
+    let minting_account = Account {
+        owner: protocol,
+        subaccount: None,
+    };
+    if transfer.spender.is_none() && transfer.from == minting_account {
+        ProtocolBlockKind::Mint
+    } else if transfer.to == minting_account {
+        ProtocolBlockKind::Burn
+    } else if transfer.spender.is_some() {
+        ProtocolBlockKind::TransferFrom
+    } else {
+        ProtocolBlockKind::Transfer
+    }
+}
+
+/// The ICRC-3 value of `block`, which its hash is taken over.
+pub fn encode_block(block: &ProtocolBlock) -> ICRC3Value {
+    let mut tx = ICRC3Map::new();
+    if block.kind != ProtocolBlockKind::Mint {
+        if let Some(from) = block.from {
+            tx.insert("from".to_string(), account_value(from));
+        }
+    }
+    if block.kind != ProtocolBlockKind::Burn {
+        if let Some(to) = block.to {
+            tx.insert("to".to_string(), account_value(to));
+        }
+    }
+    if let Some(spender) = block.spender {
+        tx.insert("spender".to_string(), account_value(spender));
+    }
+    tx.insert("amt".to_string(), ICRC3Value::Nat(Nat::from(block.amount)));
+    if let Some(memo) = &block.memo {
+        tx.insert("memo".to_string(), ICRC3Value::Blob(memo.clone()));
+    }
+    if let Some(ts) = block.created_at_time {
+        tx.insert("ts".to_string(), ICRC3Value::Nat(Nat::from(ts)));
+    }
+
+    let mut map = ICRC3Map::new();
+    if let Some(phash) = block.phash {
+        map.insert(
+            "phash".to_string(),
+            ICRC3Value::Blob(ByteBuf::from(phash.to_vec())),
+        );
+    }
+    map.insert(
+        "btype".to_string(),
+        ICRC3Value::Text(block.kind.btype().to_string()),
+    );
+    map.insert(
+        "ts".to_string(),
+        ICRC3Value::Nat(Nat::from(block.timestamp)),
+    );
+    map.insert(
+        "ledger_block".to_string(),
+        ICRC3Value::Nat(Nat::from(block.ledger_block_index)),
+    );
+    map.insert("tx".to_string(), ICRC3Value::Map(tx));
+    ICRC3Value::Map(map)
+}
+
+/// Log `transfer`, which landed at `ledger_block_index` on the icUSD
+/// ledger, chained to the current tip. Returns the new block's index, or
+/// `None` if that ledger block is already logged.
+pub fn append(
+    protocol: Principal,
+    transfer: &LedgerTransfer,
+    ledger_block_index: u64,
+    now_ns: u64,
+) -> Option<u64> {
+    let mut block = ProtocolBlock {
+        kind: classify(protocol, transfer),
+        from: Some(transfer.from),
+        to: Some(transfer.to),
+        spender: transfer.spender,
+        amount: transfer.amount,
+        memo: transfer.memo.clone().map(ByteBuf::from),
+        created_at_time: transfer.created_at_time,
+        ledger_block_index,
+        timestamp: now_ns,
+        phash: tip().map(|(_, hash)| hash),
+        hash: [0; 32],
+    };
+    block.hash = hash_value(&encode_block(&block));
+    crate::storage::append_protocol_block(&block)
+}
+
+/// Index and hash of the last block.
+pub fn tip() -> Option<(u64, [u8; 32])> {
+    let index = crate::storage::count_protocol_blocks().checked_sub(1)?;
+    crate::storage::protocol_block(index).map(|block| (index, block.hash))
+}
+
+/// Log a landed icUSD operation and certify the new tip. Operations on
+/// other ledgers are ignored.
+pub fn record_ledger_transfer(
+    ledger: Principal,
+    transfer: LedgerTransfer,
+    ledger_block_index: u64,
+) {
+    if ledger != read_state(|s| s.icusd_ledger_principal) {
+        return;
+    }
+    if append(
+        ic_cdk::id(),
+        &transfer,
+        ledger_block_index,
+        ic_cdk::api::time(),
+    )
+    .is_some()
+    {
+        certify_tip();
+    }
+}
+
+pub fn get_blocks(requests: Vec<GetBlocksRequest>) -> GetBlocksResult {
+    let log_length = crate::storage::count_protocol_blocks();
+    let mut blocks = Vec::new();
+    let mut remaining = MAX_GET_BLOCKS_RESPONSE;
+    for request in requests {
+        let start = request.start.0.to_u64().unwrap_or(u64::MAX);
+        let length = request.length.0.to_u64().unwrap_or(u64::MAX).min(remaining);
+        let end = start.saturating_add(length).min(log_length);
+        for id in start..end {
+            if let Some(block) = crate::storage::protocol_block(id) {
+                blocks.push(BlockWithId {
+                    id: Nat::from(id),
+                    block: encode_block(&block),
+                });
+            }
+        }
+        remaining -= end.saturating_sub(start);
+        if remaining == 0 {
+            break;
+        }
+    }
+    GetBlocksResult {
+        log_length: Nat::from(log_length),
+        blocks,
+        archived_blocks: vec![],
+    }
+}
+
+pub fn supported_block_types() -> Vec<SupportedBlockType> {
+    ["1mint", "1burn", "1xfer", "2xfer"]
+        .into_iter()
+        .map(|block_type| SupportedBlockType {
+            block_type: block_type.to_string(),
+            url: ICRC3_URL.to_string(),
+        })
+        .collect()
+}
+
+/// Set the canister's certified data to the tip. Certified data does not
+/// survive an upgrade, so `post_upgrade` calls this too.
+pub fn certify_tip() {
+    if let Some((index, hash)) = tip() {
+        ic_cdk::api::set_certified_data(&tip_tree(index, &hash).digest());
+    }
+}
+
+/// The certificate for the tip. Only available in a query.
+pub fn tip_certificate() -> Option<ICRC3DataCertificate> {
+    let certificate = ic_cdk::api::data_certificate()?;
+    let (index, hash) = tip()?;
+    let mut hash_tree = Vec::new();
+    ciborium::ser::into_writer(&tip_tree(index, &hash).to_cbor(), &mut hash_tree)
+        .expect("failed to encode hash tree");
+    Some(ICRC3DataCertificate {
+        certificate: ByteBuf::from(certificate),
+        hash_tree: ByteBuf::from(hash_tree),
+    })
+}
+
+/// The representation-independent hash of an ICRC-3 value.
+pub fn hash_value(value: &ICRC3Value) -> [u8; 32] {
+    match value {
+        ICRC3Value::Blob(bytes) => Sha256::digest(bytes).into(),
+        ICRC3Value::Text(text) => Sha256::digest(text.as_bytes()).into(),
+        ICRC3Value::Nat(nat) => {
+            let mut buf = Vec::new();
+            nat.encode(&mut buf).expect("failed to encode nat");
+            Sha256::digest(&buf).into()
+        }
+        ICRC3Value::Int(int) => {
+            let mut buf = Vec::new();
+            int.encode(&mut buf).expect("failed to encode int");
+            Sha256::digest(&buf).into()
+        }
+        ICRC3Value::Array(values) => {
+            let mut hasher = Sha256::new();
+            for value in values {
+                hasher.update(hash_value(value));
+            }
+            hasher.finalize().into()
+        }
+        ICRC3Value::Map(map) => {
+            let mut pairs: Vec<([u8; 32], [u8; 32])> = map
+                .iter()
+                .map(|(key, value)| (Sha256::digest(key.as_bytes()).into(), hash_value(value)))
+                .collect();
+            pairs.sort_unstable();
+            let mut hasher = Sha256::new();
+            for (key, value) in pairs {
+                hasher.update(key);
+                hasher.update(value);
+            }
+            hasher.finalize().into()
+        }
+    }
+}
+
+fn account_value(account: Account) -> ICRC3Value {
+    let mut parts = vec![ICRC3Value::Blob(ByteBuf::from(
+        account.owner.as_slice().to_vec(),
+    ))];
+    if let Some(subaccount) = account.subaccount {
+        parts.push(ICRC3Value::Blob(ByteBuf::from(subaccount.to_vec())));
+    }
+    ICRC3Value::Array(parts)
+}
+
+// ── Certified tip ──────────────────────────────────────────────────────────
+//
+// The two-leaf hash tree ICRC-3 certifies: `last_block_hash` and
+// `last_block_index` (LEB128), in the IC hash tree format.
+
+enum HashTree {
+    Fork(Box<HashTree>, Box<HashTree>),
+    Labeled(&'static [u8], Box<HashTree>),
+    Leaf(Vec<u8>),
+}
+
+fn tip_tree(index: u64, hash: &[u8; 32]) -> HashTree {
+    let mut index_leb = Vec::new();
+    Nat::from(index)
+        .encode(&mut index_leb)
+        .expect("failed to encode block index");
+    HashTree::Fork(
+        Box::new(HashTree::Labeled(
+            b"last_block_hash",
+            Box::new(HashTree::Leaf(hash.to_vec())),
+        )),
+        Box::new(HashTree::Labeled(
+            b"last_block_index",
+            Box::new(HashTree::Leaf(index_leb)),
+        )),
+    )
+}
+
+impl HashTree {
+    fn digest(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        match self {
+            HashTree::Fork(left, right) => {
+                hasher.update(domain_separator("ic-hashtree-fork"));
+                hasher.update(left.digest());
+                hasher.update(right.digest());
+            }
+            HashTree::Labeled(label, subtree) => {
+                hasher.update(domain_separator("ic-hashtree-labeled"));
+                hasher.update(label);
+                hasher.update(subtree.digest());
+            }
+            HashTree::Leaf(data) => {
+                hasher.update(domain_separator("ic-hashtree-leaf"));
+                hasher.update(data);
+            }
+        }
+        hasher.finalize().into()
+    }
+
+    fn to_cbor(&self) -> ciborium::value::Value {
+        use ciborium::value::Value;
+        match self {
+            HashTree::Fork(left, right) => Value::Array(vec![
+                Value::Integer(1.into()),
+                left.to_cbor(),
+                right.to_cbor(),
+            ]),
+            HashTree::Labeled(label, subtree) => Value::Array(vec![
+                Value::Integer(2.into()),
+                Value::Bytes(label.to_vec()),
+                subtree.to_cbor(),
+            ]),
+            HashTree::Leaf(data) => {
+                Value::Array(vec![Value::Integer(3.into()), Value::Bytes(data.clone())])
+            }
+        }
+    }
+}
+
+fn domain_separator(name: &str) -> Vec<u8> {
+    let mut buf = vec![name.len() as u8];
+    buf.extend_from_slice(name.as_bytes());
+    buf
+}
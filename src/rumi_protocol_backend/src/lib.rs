@@ -1,10 +1,9 @@
 use crate::state::PendingMarginTransfer;
 use icrc_ledger_types::icrc1::transfer::TransferError;
-use icrc_ledger_types::icrc2::transfer_from::TransferFromError;
 use serde::Serialize;
 
-use crate::guard::GuardError;
-use crate::logs::{DEBUG, INFO};
+use crate::guard::{GuardError, TraceTag};
+use crate::logs::{CRITICAL, DEBUG, INFO};
 use crate::numeric::{Ratio, UsdIcp, ICP, ICUSD};
 use crate::state::{mutate_state, read_state, Mode};
 use crate::vault::Vault;
@@ -15,29 +14,95 @@ use rust_decimal::prelude::FromPrimitive;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
 
-/// Maximum number of retries for a pending transfer before it is abandoned.
-/// At 5-second intervals, 60 retries = 5 minutes of attempts.
-const MAX_PENDING_RETRIES: u8 = 60;
+use crate::transfer_retry::{PendingTransferKey, RetryOutcome, MAX_PENDING_RETRIES};
 
+pub mod asset_registry;
+pub mod auction;
+pub mod auto_deleverage;
+pub mod batch_jobs;
+pub mod bootstrap;
+pub mod borrow_records;
+pub mod campaigns;
 pub mod chains;
+pub mod collateral_settlement;
+pub mod collateral_swap;
 pub mod dashboard;
+pub mod dex;
+pub mod donations;
+pub mod dust_vaults;
+pub mod effective_parameters;
+pub mod emergency_shutdown;
 pub mod event;
+pub mod event_ordering;
+pub mod fee_invoice;
+pub mod fee_sponsorship;
+pub mod flash_mint;
+pub mod forensics;
 pub mod guard;
+pub mod guard_metrics;
+pub mod health_score;
 pub mod icrc21;
+pub mod icrc3_log;
 pub mod icrc3_proof;
+pub mod liquidatable_set;
+pub mod liquidation_caps;
+pub mod liquidation_index;
+pub mod liquidation_receipts;
+pub mod liquidators;
 pub mod liquidity_pool;
 pub mod logs;
+pub mod loyalty;
 pub mod management;
-pub mod numeric;
+pub mod math_vectors;
+pub mod mode;
+pub mod mode_propagation;
+pub mod notifications;
+pub mod operation_ids;
+pub mod operation_pauses;
+pub mod ops_panel;
+pub mod parameter_batch;
+pub mod parameter_journal;
+pub mod pending_backpressure;
+pub mod pool_priority;
+pub mod public_stats;
+pub mod redemption_cancel;
+pub mod redemption_caps;
+pub mod redistribution;
+pub mod repay_from_collateral;
+pub mod reserve_stables;
+pub mod scheduler;
+pub mod self_liquidation;
+pub mod selftest;
+pub mod session_keys;
+pub mod shadow;
+pub mod slo;
 pub mod state;
 pub mod storage;
+pub mod surplus;
+pub mod timelock;
+pub mod transfer_retry;
 pub mod treasury;
+pub mod treasury_approvals;
+pub mod user_preferences;
 pub mod vault;
+pub mod vault_freeze;
+pub mod vault_status;
+pub mod vault_store;
 pub mod xrc;
 
 #[cfg(any(test, feature = "test_endpoints"))]
 pub mod test_helpers;
 
+// Shared with the stability pool and treasury through `rumi_common`;
+// re-exported here so the historical paths keep working.
+pub use rumi_common::numeric;
+pub use rumi_common::types::{
+    LiquidationPreview, StableTokenType, SuccessWithFee, VaultArgWithToken, XrpSpAbsorbPreflight,
+    XrpSpAbsorbRequest, XrpSpAbsorbResult, XrpSpPayoutAllocation, XrpSpPayoutClaim,
+    MAX_XRP_SP_PAYOUT_ALLOCATIONS,
+};
+pub use rumi_common::ProtocolError;
+
 #[cfg(test)]
 mod tests;
 
@@ -100,21 +165,15 @@ pub const DEFAULT_BOT_CR_TOLERANCE_BPS: u64 = 200;
 /// "near-threshold liquidation" than "race-window absorption".
 pub const MAX_BOT_CR_TOLERANCE_BPS: u64 = 500;
 
-/// Stable token types accepted for vault repayment (1:1 with icUSD)
-#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub enum StableTokenType {
-    /// ckUSDT stablecoin
-    CKUSDT,
-    /// ckUSDC stablecoin
-    CKUSDC,
-}
+/// Highest liquidation tip an admin may set: 100 icUSD.
+pub const MAX_LIQUIDATION_TIP_E8S: u64 = 10_000_000_000;
 
-/// Arguments for repaying vault with a stable token
+/// Arguments for `repay_all_and_close_vault`. The debt is repaid in icUSD
+/// unless a stable token is given.
 #[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub struct VaultArgWithToken {
+pub struct RepayAllAndCloseArg {
     pub vault_id: u64,
-    pub amount: u64,
-    pub token_type: StableTokenType,
+    pub token_type: Option<StableTokenType>,
 }
 
 #[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -260,34 +319,48 @@ pub struct Fees {
     pub redemption_fee: f64,
 }
 
-#[derive(CandidType, Deserialize, Debug)]
-pub struct SuccessWithFee {
-    pub block_index: u64,
-    pub fee_amount_paid: u64,
-    /// Total collateral (native units) awarded to the liquidator / stability pool.
-    /// Added so the stability pool can correctly credit depositors with their
-    /// proportional share of the actual collateral received, rather than only
-    /// the liquidator bonus (`fee_amount_paid`).
-    pub collateral_amount_received: Option<u64>,
-    /// SP-101: the icUSD-denominated debt the backend ACTUALLY cleared (in e8s).
-    /// The partial-liquidation paths cap the requested draw to the vault's
-    /// `max_liquidatable_debt`; the stability pool must debit depositors by this
-    /// realized amount, not the (possibly larger) amount it requested, or the
-    /// tracked aggregate drifts above the pool's real balance. `None` on the
-    /// non-liquidation paths (redeem / borrow). Optional for Candid back-compat.
-    pub debt_liquidated_e8s: Option<u64>,
-    /// SP-110 (audit 2026-06-05): on the ckUSDT/ckUSDC liquidation path the
-    /// backend pulls `base + repay-fee surcharge` stable from the caller, but
-    /// `debt_liquidated_e8s` only reflects the base debt. The stability pool
-    /// must debit depositors by the TOTAL stable pulled (this field, in the
-    /// stable token's e6 native units), or the repay-fee surcharge leaves the
-    /// pool un-debited and the tracked aggregate drifts above the real ledger
-    /// balance. `Some` only on the ckStable path; `None` elsewhere. Optional for
-    /// Candid back-compat.
-    pub stable_pulled_e6s: Option<u64>,
-    /// Native-XRP manual liquidation payout claim id for the liquidator reward.
-    /// `None` for non-XRP collateral and non-liquidation SuccessWithFee results.
-    pub xrp_claim_id: Option<u64>,
+/// Which bound set the repay amount in a `liquidate_to_target` quote.
+#[derive(CandidType, Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub enum LiquidationTargetLimit {
+    /// The exact amount needed to reach the target CR.
+    Target,
+    /// `max_partial_liquidation_ratio` of the vault's debt.
+    MaxPartialRatio,
+    /// The protocol's own partial-liquidation cap (restore to borrow threshold).
+    ProtocolCap,
+    /// The liquidator's `max_icusd` budget.
+    MaxIcusd,
+    /// A residual below `min_vault_debt` was rounded up to the full debt.
+    DustRoundUp,
+}
+
+/// Server-side math behind a `liquidate_to_target` call. Amounts are icUSD
+/// e8s; ratios are plain multiples (1.5 = 150%).
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+pub struct LiquidationTargetQuote {
+    pub vault_id: u64,
+    pub debt_before_e8s: u64,
+    pub collateral_value_before_e8s: u64,
+    pub cr_before: f64,
+    pub target_cr: f64,
+    pub liquidation_bonus: f64,
+    /// `(debt * target - collateral_value) / (target - bonus)`, uncapped.
+    pub required_repay_e8s: u64,
+    pub max_partial_repay_e8s: u64,
+    pub protocol_cap_e8s: u64,
+    pub max_icusd_e8s: u64,
+    /// The amount actually sent to partial liquidation.
+    pub repay_e8s: u64,
+    pub limited_by: LiquidationTargetLimit,
+    pub projected_debt_after_e8s: u64,
+    pub projected_cr_after: f64,
+    pub target_reached: bool,
+}
+
+#[derive(CandidType, Debug, Deserialize)]
+pub struct LiquidateToTargetResult {
+    pub quote: LiquidationTargetQuote,
+    pub liquidation: SuccessWithFee,
 }
 
 /// Result from stability pool liquidation (both standard and debt-already-burned paths).
@@ -303,53 +376,6 @@ pub struct StabilityPoolLiquidationResult {
     pub collateral_price_e8s: u64,
 }
 
-pub const MAX_XRP_SP_PAYOUT_ALLOCATIONS: usize = 500;
-
-#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub struct XrpSpAbsorbPreflight {
-    pub vault_id: u64,
-    pub icusd_burn_e8s: u64,
-    pub collateral_received_drops: u64,
-    pub collateral_price_e8s: u64,
-    pub expires_at_ns: u64,
-}
-
-#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub struct XrpSpPayoutAllocation {
-    pub claimant: Principal,
-    pub payout_address: String,
-    pub destination_tag: Option<u32>,
-    pub drops: u64,
-}
-
-#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub struct XrpSpAbsorbRequest {
-    pub vault_id: u64,
-    pub icusd_burned_e8s: u64,
-    pub proof: crate::icrc3_proof::SpWritedownProof,
-    pub allocations: Vec<XrpSpPayoutAllocation>,
-}
-
-#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub struct XrpSpPayoutClaim {
-    pub claimant: Principal,
-    pub claim_id: u64,
-    pub payout_address: String,
-    pub destination_tag: Option<u32>,
-    pub drops: u64,
-}
-
-#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub struct XrpSpAbsorbResult {
-    pub success: bool,
-    pub vault_id: u64,
-    pub liquidated_debt_e8s: u64,
-    pub collateral_received_drops: u64,
-    pub payout_claims: Vec<XrpSpPayoutClaim>,
-    pub block_index: u64,
-    pub collateral_price_e8s: u64,
-}
-
 /// Coarse classification of an `Event` for the explorer's type facet.
 /// Each variant maps to one or more concrete `Event` cases via
 /// `Event::type_filter()`. Adding a new `Event` variant requires extending
@@ -628,6 +654,17 @@ pub struct CollateralTotals {
     pub price: f64, // Last USD price
 }
 
+/// A collateral's debt against its ceiling. `headroom` is what can still be
+/// borrowed against it, after in-flight borrows.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CollateralUtilization {
+    pub collateral_type: Principal,
+    pub total_debt: u64,        // icUSD e8s
+    pub in_flight_borrows: u64, // icUSD e8s reserved by borrows still minting
+    pub debt_ceiling: u64,      // icUSD e8s; u64::MAX = uncapped
+    pub headroom: u64,          // icUSD e8s
+}
+
 /// Per-collateral data captured in each hourly protocol snapshot.
 #[derive(CandidType, Deserialize, Serialize, Debug, Clone)]
 pub struct CollateralSnapshot {
@@ -687,43 +724,6 @@ pub struct AddCollateralArg {
     pub redemption_tier: Option<u8>,
 }
 
-#[derive(CandidType, Debug, Clone, Deserialize)]
-pub enum ProtocolError {
-    TransferFromError(TransferFromError, u64),
-    TransferError(TransferError),
-    TemporarilyUnavailable(String),
-    AlreadyProcessing,
-    AnonymousCallerNotAllowed,
-    CallerNotOwner,
-    AmountTooLow {
-        minimum_amount: u64,
-    },
-    GenericError(String),
-    /// Wave-8b LIQ-002 band-gate rejection. **Deactivated 2026-05-18 — no
-    /// live code path emits this variant.** Retained in the enum so
-    /// historical on-chain events recorded before the deactivation still
-    /// decode cleanly. See `state::is_within_liquidation_band` and the
-    /// "Layer 2.5 — band gate DEACTIVATION fence" comment in
-    /// `tests/audit_pocs_liq_002_sorted_troves_index.rs` for background.
-    NotLowestCR,
-    /// Phase 1a: the periodic supply-invariant self-check (Timer B) caught
-    /// a `sum(chain_supplies) != total_debt` divergence. Every entry that
-    /// touches debt or chain supply returns this error until an operator
-    /// clears `multi_chain.invariant_halted`.
-    SupplyInvariantHalted,
-    /// Phase 1a: admin-endpoint error for `register_chain`, `disable_chain`,
-    /// `set_chain_config`. Wraps a developer-facing message string. The
-    /// structured `ChainAdminError` enum lives in `chains::config` and is
-    /// stringified here so the Candid surface stays append-only.
-    ChainAdmin(String),
-    /// M2 EVM-native self-serve auth failure (bad signature, recovered signer !=
-    /// owner, nonce replay, expired deadline, recipient != owner, per-owner cap,
-    /// unknown/unregistered chain, custody-derive failure, or an underlying vault
-    /// rejection). Wraps a developer-facing message. Appended AFTER `ChainAdmin`
-    /// so historical on-chain events keep decoding (append-only Candid surface).
-    EvmAuth(String),
-}
-
 impl From<GuardError> for ProtocolError {
     fn from(e: GuardError) -> Self {
         match e {
@@ -738,27 +738,6 @@ impl From<GuardError> for ProtocolError {
     }
 }
 
-impl ProtocolError {
-    /// The exact `TemporarilyUnavailable` rejection returned whenever the
-    /// protocol is latched `Mode::ReadOnly` (insolvency: total collateral ratio
-    /// below 100%, or the deficit account over its configured threshold).
-    ///
-    /// Single source of truth so the Candid entry-layer gate
-    /// (`main.rs::validate_mode`) and the shared vault-module redemption gates
-    /// (`vault::redeem_collateral` / `vault::redeem_reserves`) return a
-    /// byte-identical error. Audit RED-101 (regression of RED-003) showed that a
-    /// second entry point (`redeem_icp`) silently bypassed the entry-layer-only
-    /// gate; gating inside the vault module closes any present/future redemption
-    /// surface by construction, and this constructor keeps every layer's message
-    /// in lockstep.
-    pub fn read_only_mode() -> Self {
-        ProtocolError::TemporarilyUnavailable(
-            "protocol temporarly unavailable, please wait for an upgrade or for total collateral ratio to go above 100%"
-                .to_string(),
-        )
-    }
-}
-
 /// Candid-compatible struct matching the stability pool's and bot's `LiquidatableVaultInfo`.
 /// Defined inline to avoid a crate dependency between backend and pool/bot.
 #[derive(CandidType, Clone, Debug, Deserialize)]
@@ -771,6 +750,23 @@ pub struct LiquidatableVaultInfo {
     pub collateral_price_e8s: u64,
 }
 
+/// Interest position of one vault, returned by `get_accrued_interest`.
+/// `accrued_interest_e8s` is already part of `borrowed_icusd_amount`;
+/// `pending_interest_e8s` has been earned since `last_accrual_time` and is
+/// booked on the next accrual tick or vault operation.
+#[derive(CandidType, Clone, Debug, PartialEq, Deserialize)]
+pub struct AccruedInterest {
+    pub vault_id: u64,
+    /// Accrued since the last treasury harvest.
+    pub accrued_interest_e8s: u64,
+    pub pending_interest_e8s: u64,
+    /// Debt including pending interest.
+    pub current_debt_e8s: u64,
+    pub last_accrual_time: u64,
+    /// Current dynamic APR for the vault.
+    pub interest_rate_apr: f64,
+}
+
 /// Wave-14a CDP-10: post-spawn handler for the stability_pool
 /// `notify_liquidatable_vaults` call.
 ///
@@ -876,11 +872,11 @@ pub fn prune_recovered_routing_state(
 pub fn record_per_collateral_redemption_fee(
     state: &mut state::State,
     collateral_type: &candid::Principal,
-    base_fee: numeric::Ratio,
+    base_rate: numeric::Ratio,
     now_ns: u64,
 ) {
     if let Some(config) = state.collateral_configs.get_mut(collateral_type) {
-        config.current_base_rate = base_fee;
+        config.current_base_rate = base_rate;
         config.last_redemption_time = now_ns;
     }
 }
@@ -992,6 +988,12 @@ pub async fn check_vaults() {
             if let Some(vault) = s.vault_id_to_vaults.get_mut(vault_id) {
                 vault.bot_processing = false;
             }
+            crate::vault_status::transition_or_log(
+                s,
+                *vault_id,
+                crate::vault_status::VaultStatus::Active,
+                now,
+            );
             s.bot_budget_remaining_e8s += claim.debt_amount;
             s.bot_claims.remove(vault_id);
         });
@@ -1037,21 +1039,41 @@ pub async fn check_vaults() {
     // band-only ticks but caught by the next full sweep. Tunable via
     // `set_check_vaults_alert_band_bps` and
     // `set_check_vaults_full_sweep_every_n_ticks`.
+    //
+    // The unhealthy list now comes from `liquidation_index`, which is exact
+    // at the current price and sorted worst-CR first without a scan. The
+    // band-only `vault_cr_index` walk is no longer needed; the full sweep
+    // still runs on its cadence as a cross-check, and anything it finds
+    // that the index missed is logged and dispatched too.
     let do_full_sweep = mutate_state(|s| s.advance_check_vaults_tick());
-    let scan = read_state(|s| s.scan_unhealthy_vaults(dummy_rate, do_full_sweep));
+    let mut unhealthy_vaults = read_state(liquidation_index::unhealthy_vaults);
+    if do_full_sweep {
+        let scan = read_state(|s| s.scan_unhealthy_vaults(dummy_rate, true));
+        log!(
+            INFO,
+            "[check_vaults] full-sweep tick: visited {} vault(s), found {} unhealthy",
+            scan.vaults_visited,
+            scan.unhealthy_vaults.len(),
+        );
+        let indexed: std::collections::BTreeSet<u64> =
+            unhealthy_vaults.iter().map(|v| v.vault_id).collect();
+        for vault in scan.unhealthy_vaults {
+            if indexed.contains(&vault.vault_id) {
+                continue;
+            }
+            log!(
+                INFO,
+                "[check_vaults] vault #{} is unhealthy but missing from the liquidation index",
+                vault.vault_id
+            );
+            unhealthy_vaults.push(vault);
+        }
+    }
     log!(
         INFO,
-        "[check_vaults] {} tick: visited {} vault(s), threshold_key={}, found {} unhealthy",
-        if scan.was_full_sweep {
-            "full-sweep"
-        } else {
-            "band-only"
-        },
-        scan.vaults_visited,
-        scan.threshold_key,
-        scan.unhealthy_vaults.len(),
+        "[check_vaults] {} unhealthy vault(s) from the liquidation index",
+        unhealthy_vaults.len(),
     );
-    let unhealthy_vaults = scan.unhealthy_vaults;
 
     // 2026-05-18 follow-up: prune routing state unconditionally on every
     // tick — even quiet ones — so a vault that was SP-attempted during a
@@ -1067,6 +1089,11 @@ pub async fn check_vaults() {
         unhealthy_vaults.iter().map(|v| v.vault_id).collect();
     mutate_state(|s| {
         prune_recovered_routing_state(s, &scan_unhealthy_ids, now, bot_timeout_ns);
+        // Same set drives the lifecycle: unhealthy vaults become `AtRisk`,
+        // recovered ones go back to `Active`.
+        crate::vault_status::sync_at_risk(s, &scan_unhealthy_ids);
+        // Settle auctions and start those for vaults the pool passed on.
+        crate::auction::on_vault_check(s, &scan_unhealthy_ids, now);
     });
 
     // Log unhealthy vaults but don't liquidate them
@@ -1123,6 +1150,7 @@ pub async fn check_vaults() {
         // 1. Bot gets first shot at vaults with bot-eligible collateral
         // 2. Stability pool handles: non-bot-eligible immediately + bot-eligible after timeout
         // 3. Manual liquidation is always available as last resort (via get_liquidatable_vaults)
+        // 4. Vaults the pool passed on are auctioned after a grace period (see `auction`)
         //
         // `now` and `bot_timeout_ns` were established above the `if` block
         // for the unconditional `prune_recovered_routing_state` call;
@@ -1136,6 +1164,40 @@ pub async fn check_vaults() {
             )
         });
 
+        // Recovery-mode pool priority: vaults the pool's depth covers skip
+        // the bot and are reserved for the pool; the rest fall through to the
+        // cascade below. See `pool_priority`.
+        let routing = read_state(|s| pool_priority::plan_routing(s, &vault_notifications, now));
+        let shadowed =
+            read_state(|s| shadow::is_shadowed(s, shadow::ShadowComponent::RecoveryRouting));
+        let pool_first: std::collections::BTreeSet<u64> = match routing {
+            Some(routing) if shadowed => {
+                mutate_state(|s| {
+                    shadow::note(
+                        s,
+                        shadow::ShadowAction::ReserveForPool {
+                            to_pool: routing.to_pool,
+                            overflow: routing.overflow,
+                        },
+                    );
+                    shadow::flush(s, now);
+                });
+                std::collections::BTreeSet::new()
+            }
+            Some(routing) => {
+                log!(
+                    INFO,
+                    "[check_vaults] Recovery routing: {} vault(s) reserved for the stability pool, {} beyond its depth",
+                    routing.to_pool.len(),
+                    routing.overflow.len()
+                );
+                let ids = routing.to_pool.iter().copied().collect();
+                mutate_state(|s| event::record_recovery_pool_routing(s, routing));
+                ids
+            }
+            None => std::collections::BTreeSet::new(),
+        };
+
         let mut for_bot: Vec<LiquidatableVaultInfo> = Vec::new();
         let mut for_pool: Vec<LiquidatableVaultInfo> = Vec::new();
 
@@ -1151,6 +1213,11 @@ pub async fn check_vaults() {
                 continue;
             }
 
+            if pool_first.contains(&vault_info.vault_id) {
+                for_pool.push(vault_info.clone());
+                continue;
+            }
+
             if !bot_eligible {
                 // Not bot-eligible → stability pool (one shot)
                 for_pool.push(vault_info.clone());
@@ -1265,6 +1332,17 @@ pub async fn check_vaults() {
         );
     }
 
+    // Refresh the pool depth the next tick's Recovery routing and the
+    // health score use.
+    if let Some(pool) = read_state(|s| s.stability_pool_canister) {
+        ic_cdk::spawn(pool_priority::refresh_pool_depth(pool));
+    }
+
+    // Sell down opted-in inactive vaults that slipped below their buffer.
+    if read_state(|s| !s.auto_deleverage.is_empty()) {
+        ic_cdk::spawn(auto_deleverage::run_auto_deleverage());
+    }
+
     // No longer calling record_liquidate_vault to trigger automatic liquidations
 }
 
@@ -1320,10 +1398,16 @@ pub async fn process_pending_transfer() {
     //   * Success: `record_margin_transfer` writes a MarginTransfer event AND
     //     removes the entry by `(vault_id, owner)` (event.rs).
     //   * Skipped (margin <= fee): `drop_pending` drops the entry inline.
-    //   * Abandon (>= MAX_PENDING_RETRIES): `drop_pending` drops the entry inline.
+    //   * Failure: `transfer_retry::record_failure` backs the entry off, and
+    //     moves it to `failed_transfers` after MAX_PENDING_RETRIES failures.
     //   * BadFee: refresh fee cache, do NOT drop. The next tick retries.
-    // Excess and redemption loops follow the same contract; redemption uses
-    // its own event recorder with the same removal semantics.
+    // Every loop skips entries still backing off. Excess and redemption loops
+    // follow the same contract; redemption uses its own event recorder with
+    // the same removal semantics.
+    let now = ic_cdk::api::time();
+    mutate_state(crate::transfer_retry::prune_backoff);
+    // Every payout below is awaited, so the entries are as of this read.
+    let observed_seq = crate::storage::observe_events();
     let pending_transfers = read_state(|s| {
         // Log for visibility
         if !s.pending_margin_transfers.is_empty() {
@@ -1336,6 +1420,13 @@ pub async fn process_pending_transfer() {
 
         s.pending_margin_transfers
             .iter()
+            .filter(|((vault_id, owner), _)| {
+                let key = PendingTransferKey::Margin {
+                    vault_id: *vault_id,
+                    owner: *owner,
+                };
+                crate::transfer_retry::is_due(s, &key, now)
+            })
             .map(|(key, margin_transfer)| (*key, *margin_transfer))
             .collect::<Vec<((u64, candid::Principal), PendingMarginTransfer)>>()
     });
@@ -1353,7 +1444,8 @@ pub async fn process_pending_transfer() {
         if transfer.margin <= transfer_fee {
             log!(
                 INFO,
-                "[transfering_margins] Skipping vault {} owner {} - margin {} <= fee {}, removing",
+                "[transfering_margins] trace={} Skipping vault {} owner {} - margin {} <= fee {}, removing",
+                TraceTag(transfer.trace_id),
                 vault_id,
                 transfer.owner,
                 transfer.margin,
@@ -1373,20 +1465,28 @@ pub async fn process_pending_transfer() {
             Ok(block_index) => {
                 log!(
                     INFO,
-                    "[transfering_margins] successfully transferred: {} to {} via ledger {}",
+                    "[transfering_margins] trace={} successfully transferred: {} to {} via ledger {}",
+                    TraceTag(transfer.trace_id),
                     transfer.margin,
                     transfer.owner,
                     ledger
                 );
                 mutate_state(|s| {
-                    crate::event::record_margin_transfer(s, vault_id, transfer.owner, block_index)
+                    crate::event::record_margin_transfer(
+                        s,
+                        vault_id,
+                        transfer.owner,
+                        block_index,
+                        observed_seq,
+                    )
                 });
             }
             Err(error) => {
                 // Improved error logging with more details
                 log!(
                     INFO,
-                    "[transfering_margins] failed to transfer margin: {}, to principal: {}, via ledger: {}, with error: {}",
+                    "[transfering_margins] trace={} failed to transfer margin: {}, to principal: {}, via ledger: {}, with error: {}",
+                    TraceTag(transfer.trace_id),
                     transfer.margin,
                     transfer.owner,
                     ledger,
@@ -1397,7 +1497,8 @@ pub async fn process_pending_transfer() {
                 if let TransferError::BadFee { expected_fee } = error {
                     log!(
                         INFO,
-                        "[transfering_margins] Updating transfer fee to: {:?}",
+                        "[transfering_margins] trace={} Updating transfer fee to: {:?}",
+                        TraceTag(transfer.trace_id),
                         expected_fee
                     );
                     mutate_state(|s| {
@@ -1424,25 +1525,31 @@ pub async fn process_pending_transfer() {
 
                     // After updating the fee, we should retry this transfer next time
                 } else {
-                    // Increment retry count; abandon after MAX_PENDING_RETRIES
-                    let retries = mutate_state(|s| {
-                        if let Some(t) = s.pending_margin_transfers.get_mut(&key) {
-                            t.retry_count = t.retry_count.saturating_add(1);
-                            t.retry_count
-                        } else {
-                            0
+                    let retry_key = PendingTransferKey::Margin {
+                        vault_id,
+                        owner: key.1,
+                    };
+                    match mutate_state(|s| {
+                        crate::transfer_retry::record_failure(s, retry_key, error.to_string(), now)
+                    }) {
+                        RetryOutcome::DeadLettered { attempts } => {
+                            log!(CRITICAL,
+                                "[transfering_margins] trace={} CRITICAL: moving margin transfer for vault {} \
+                                 to failed_transfers after {} attempts. Owner: {}, amount: {}. Use resolve_failed_transfer to requeue it.",
+                                TraceTag(transfer.trace_id),
+                                vault_id, attempts, transfer.owner, transfer.margin
+                            );
                         }
-                    });
-                    if retries >= MAX_PENDING_RETRIES {
-                        log!(INFO,
-                            "[transfering_margins] CRITICAL: abandoning margin transfer for vault {} \
-                             after {} retries. Owner: {}, amount: {}. Use recover_pending_transfer to retry manually.",
-                            vault_id, retries, transfer.owner, transfer.margin
-                        );
-                        mutate_state(|s| drop_pending(&mut s.pending_margin_transfers, &key));
-                    } else {
-                        log!(INFO, "[transfering_margins] Will retry transfer for vault {} owner {} (attempt {}/{})",
-                            vault_id, transfer.owner, retries, MAX_PENDING_RETRIES);
+                        RetryOutcome::Retry {
+                            attempts,
+                            next_attempt_ns,
+                        } => {
+                            log!(INFO, "[transfering_margins] trace={} Will retry transfer for vault {} owner {} (attempt {}/{}) in {}s",
+                                TraceTag(transfer.trace_id),
+                                vault_id, transfer.owner, attempts, MAX_PENDING_RETRIES,
+                                next_attempt_ns.saturating_sub(now) / 1_000_000_000);
+                        }
+                        RetryOutcome::Gone => {}
                     }
                 }
             }
@@ -1453,6 +1560,13 @@ pub async fn process_pending_transfer() {
     let pending_excess = read_state(|s| {
         s.pending_excess_transfers
             .iter()
+            .filter(|((vault_id, owner), _)| {
+                let key = PendingTransferKey::Excess {
+                    vault_id: *vault_id,
+                    owner: *owner,
+                };
+                crate::transfer_retry::is_due(s, &key, now)
+            })
             .map(|(key, transfer)| (*key, *transfer))
             .collect::<Vec<((u64, candid::Principal), PendingMarginTransfer)>>()
     });
@@ -1470,7 +1584,8 @@ pub async fn process_pending_transfer() {
         if transfer.margin <= transfer_fee {
             log!(
                 INFO,
-                "[transfering_excess] Skipping vault {} owner {} - margin {} <= fee {}, removing",
+                "[transfering_excess] trace={} Skipping vault {} owner {} - margin {} <= fee {}, removing",
+                TraceTag(transfer.trace_id),
                 vault_id,
                 transfer.owner,
                 transfer.margin,
@@ -1490,7 +1605,8 @@ pub async fn process_pending_transfer() {
             Ok(_block_index) => {
                 log!(
                     INFO,
-                    "[transfering_excess] successfully transferred excess collateral: {} to {} via ledger {}",
+                    "[transfering_excess] trace={} successfully transferred excess collateral: {} to {} via ledger {}",
+                    TraceTag(transfer.trace_id),
                     transfer.margin,
                     transfer.owner,
                     ledger
@@ -1500,7 +1616,8 @@ pub async fn process_pending_transfer() {
             Err(error) => {
                 log!(
                     INFO,
-                    "[transfering_excess] failed to transfer excess collateral: {}, to principal: {}, via ledger: {}, with error: {}",
+                    "[transfering_excess] trace={} failed to transfer excess collateral: {}, to principal: {}, via ledger: {}, with error: {}",
+                    TraceTag(transfer.trace_id),
                     transfer.margin,
                     transfer.owner,
                     ledger,
@@ -1509,7 +1626,8 @@ pub async fn process_pending_transfer() {
                 if let TransferError::BadFee { expected_fee } = error {
                     log!(
                         INFO,
-                        "[transfering_excess] Updating transfer fee to: {:?}",
+                        "[transfering_excess] trace={} Updating transfer fee to: {:?}",
+                        TraceTag(transfer.trace_id),
                         expected_fee
                     );
                     mutate_state(|s| {
@@ -1534,21 +1652,20 @@ pub async fn process_pending_transfer() {
                     });
                     // Don't increment retry counter on BadFee — refresh fee, retry next tick.
                 } else {
-                    let retries = mutate_state(|s| {
-                        if let Some(t) = s.pending_excess_transfers.get_mut(&key) {
-                            t.retry_count = t.retry_count.saturating_add(1);
-                            t.retry_count
-                        } else {
-                            0
-                        }
+                    let retry_key = PendingTransferKey::Excess {
+                        vault_id,
+                        owner: key.1,
+                    };
+                    let outcome = mutate_state(|s| {
+                        crate::transfer_retry::record_failure(s, retry_key, error.to_string(), now)
                     });
-                    if retries >= MAX_PENDING_RETRIES {
-                        log!(INFO,
-                            "[transfering_excess] CRITICAL: abandoning excess transfer for vault {} \
-                             after {} retries. Owner: {}, amount: {}. Use recover_pending_transfer to retry manually.",
-                            vault_id, retries, transfer.owner, transfer.margin
+                    if let RetryOutcome::DeadLettered { attempts } = outcome {
+                        log!(CRITICAL,
+                            "[transfering_excess] trace={} CRITICAL: moving excess transfer for vault {} \
+                             to failed_transfers after {} attempts. Owner: {}, amount: {}. Use resolve_failed_transfer to requeue it.",
+                            TraceTag(transfer.trace_id),
+                            vault_id, attempts, transfer.owner, transfer.margin
                         );
-                        mutate_state(|s| drop_pending(&mut s.pending_excess_transfers, &key));
                     }
                 }
             }
@@ -1556,9 +1673,16 @@ pub async fn process_pending_transfer() {
     }
 
     // Similar improved logic for redemption transfers
+    let observed_seq = crate::storage::observe_events();
     let pending_redemptions = read_state(|s| {
         s.pending_redemption_transfer
             .iter()
+            .filter(|(icusd_block_index, _)| {
+                let key = PendingTransferKey::Redemption {
+                    icusd_block_index: **icusd_block_index,
+                };
+                crate::transfer_retry::is_due(s, &key, now)
+            })
             .map(|(icusd_block_index, margin_transfer)| (*icusd_block_index, *margin_transfer))
             .collect::<Vec<(u64, PendingMarginTransfer)>>()
     });
@@ -1575,12 +1699,16 @@ pub async fn process_pending_transfer() {
         if pending_transfer.margin <= transfer_fee {
             log!(
                 INFO,
-                "[transfering_redemptions] Skipping redemption {} - margin {} <= fee {}, removing",
+                "[transfering_redemptions] trace={} Skipping redemption {} - margin {} <= fee {}, removing",
+                TraceTag(pending_transfer.trace_id),
                 icusd_block_index,
                 pending_transfer.margin,
                 transfer_fee
             );
-            mutate_state(|s| drop_pending(&mut s.pending_redemption_transfer, &icusd_block_index));
+            mutate_state(|s| {
+                drop_pending(&mut s.pending_redemption_transfer, &icusd_block_index);
+                s.pending_redemption_records.remove(&icusd_block_index);
+            });
             continue;
         }
         match crate::management::transfer_collateral_with_nonce(
@@ -1594,19 +1722,26 @@ pub async fn process_pending_transfer() {
             Ok(block_index) => {
                 log!(
                     INFO,
-                    "[transfering_redemptions] successfully transferred: {} to {} via ledger {}",
+                    "[transfering_redemptions] trace={} successfully transferred: {} to {} via ledger {}",
+                    TraceTag(pending_transfer.trace_id),
                     pending_transfer.margin,
                     pending_transfer.owner,
                     ledger
                 );
                 mutate_state(|s| {
-                    crate::event::record_redemption_transfered(s, icusd_block_index, block_index)
+                    crate::event::record_redemption_transfered(
+                        s,
+                        icusd_block_index,
+                        block_index,
+                        observed_seq,
+                    )
                 });
             }
             Err(error) => {
                 log!(
                     INFO,
-                    "[transfering_redemptions] failed to transfer margin: {}, to principal: {}, via ledger: {}, with error: {}",
+                    "[transfering_redemptions] trace={} failed to transfer margin: {}, to principal: {}, via ledger: {}, with error: {}",
+                    TraceTag(pending_transfer.trace_id),
                     pending_transfer.margin,
                     pending_transfer.owner,
                     ledger,
@@ -1615,7 +1750,8 @@ pub async fn process_pending_transfer() {
                 if let TransferError::BadFee { expected_fee } = error {
                     log!(
                         INFO,
-                        "[transfering_redemptions] Updating transfer fee to: {:?}",
+                        "[transfering_redemptions] trace={} Updating transfer fee to: {:?}",
+                        TraceTag(pending_transfer.trace_id),
                         expected_fee
                     );
                     mutate_state(|s| {
@@ -1641,23 +1777,17 @@ pub async fn process_pending_transfer() {
                     });
                     // Don't increment retry counter on BadFee — refresh fee, retry next tick.
                 } else {
-                    let retries = mutate_state(|s| {
-                        if let Some(t) = s.pending_redemption_transfer.get_mut(&icusd_block_index) {
-                            t.retry_count = t.retry_count.saturating_add(1);
-                            t.retry_count
-                        } else {
-                            0
-                        }
+                    let retry_key = PendingTransferKey::Redemption { icusd_block_index };
+                    let outcome = mutate_state(|s| {
+                        crate::transfer_retry::record_failure(s, retry_key, error.to_string(), now)
                     });
-                    if retries >= MAX_PENDING_RETRIES {
-                        log!(INFO,
-                            "[transfering_redemptions] CRITICAL: abandoning redemption transfer {} \
-                             after {} retries. Owner: {}, amount: {}. Use recover_pending_transfer to retry manually.",
-                            icusd_block_index, retries, pending_transfer.owner, pending_transfer.margin
+                    if let RetryOutcome::DeadLettered { attempts } = outcome {
+                        log!(CRITICAL,
+                            "[transfering_redemptions] trace={} CRITICAL: moving redemption transfer {} \
+                             to failed_transfers after {} attempts. Owner: {}, amount: {}. Use resolve_failed_transfer to requeue or refund it.",
+                            TraceTag(pending_transfer.trace_id),
+                            icusd_block_index, attempts, pending_transfer.owner, pending_transfer.margin
                         );
-                        mutate_state(|s| {
-                            drop_pending(&mut s.pending_redemption_transfer, &icusd_block_index)
-                        });
                     }
                 }
             }
@@ -1671,6 +1801,12 @@ pub async fn process_pending_transfer() {
     let pending_refunds = read_state(|s| {
         s.pending_refunds
             .iter()
+            .filter(|(k, _)| {
+                let key = PendingTransferKey::Refund {
+                    icusd_block_index: **k,
+                };
+                crate::transfer_retry::is_due(s, &key, now)
+            })
             .map(|(k, v)| (*k, *v))
             .collect::<Vec<(u64, crate::state::PendingRefund)>>()
     });
@@ -1707,27 +1843,20 @@ pub async fn process_pending_transfer() {
                         crate::management::set_cached_fee(icusd_ledger, expected_fee_u64);
                     }
                 } else {
-                    let retries = mutate_state(|s| {
-                        if let Some(r) = s.pending_refunds.get_mut(&icusd_block_index) {
-                            r.retry_count = r.retry_count.saturating_add(1);
-                            r.retry_count
-                        } else {
-                            0
-                        }
+                    let retry_key = PendingTransferKey::Refund { icusd_block_index };
+                    let outcome = mutate_state(|s| {
+                        crate::transfer_retry::record_failure(s, retry_key, error.to_string(), now)
                     });
-                    if retries >= MAX_PENDING_RETRIES {
+                    if let RetryOutcome::DeadLettered { attempts } = outcome {
                         log!(
-                            INFO,
-                            "[refunding] CRITICAL: abandoning icUSD refund for {} (burn block {}) \
-                             after {} retries. Amount: {}. Manual reconciliation required.",
+                            CRITICAL,
+                            "[refunding] CRITICAL: moving icUSD refund for {} (burn block {}) \
+                             to failed_transfers after {} attempts. Amount: {}. Use resolve_failed_transfer to requeue it.",
                             refund.user,
                             icusd_block_index,
-                            retries,
+                            attempts,
                             refund.amount_e8s
                         );
-                        mutate_state(|s| {
-                            s.pending_refunds.remove(&icusd_block_index);
-                        });
                     }
                 }
             }
@@ -1743,6 +1872,10 @@ pub async fn process_pending_transfer() {
     let pending_3usd_refunds = read_state(|s| {
         s.pending_3usd_refunds
             .iter()
+            .filter(|(k, _)| {
+                let key = PendingTransferKey::ThreeUsdRefund { op_nonce: **k };
+                crate::transfer_retry::is_due(s, &key, now)
+            })
             .map(|(k, v)| (*k, *v))
             .collect::<Vec<(u128, crate::state::PendingThreeUsdRefund)>>()
     });
@@ -1784,50 +1917,43 @@ pub async fn process_pending_transfer() {
                         crate::management::set_cached_fee(refund.ledger, expected_fee_u64);
                     }
                 } else {
-                    let retries = mutate_state(|s| {
-                        if let Some(r) = s.pending_3usd_refunds.get_mut(&nonce_key) {
-                            r.retry_count = r.retry_count.saturating_add(1);
-                            r.retry_count
-                        } else {
-                            0
-                        }
+                    let retry_key = PendingTransferKey::ThreeUsdRefund {
+                        op_nonce: nonce_key,
+                    };
+                    let outcome = mutate_state(|s| {
+                        crate::transfer_retry::record_failure(
+                            s,
+                            retry_key,
+                            format!("{:?}", error),
+                            now,
+                        )
                     });
-                    if retries >= MAX_PENDING_RETRIES {
+                    if let RetryOutcome::DeadLettered { attempts } = outcome {
                         log!(
-                            INFO,
-                            "[refunding] CRITICAL: abandoning 3USD reserve refund for SP {} (vault {}) \
-                             after {} retries. Amount: {}. Manual reconciliation required.",
+                            CRITICAL,
+                            "[refunding] CRITICAL: moving 3USD reserve refund for SP {} (vault {}) \
+                             to failed_transfers after {} attempts. Amount: {}. Use resolve_failed_transfer to requeue it.",
                             refund.stability_pool,
                             refund.vault_id,
-                            retries,
+                            attempts,
                             refund.amount_e8s
                         );
-                        mutate_state(|s| {
-                            s.pending_3usd_refunds.remove(&nonce_key);
-                        });
                     }
                 }
             }
         }
     }
 
-    // Schedule another run if needed, but with better timing
-    if read_state(|s| {
-        !s.pending_margin_transfers.is_empty()
-            || !s.pending_excess_transfers.is_empty()
-            || !s.pending_redemption_transfer.is_empty()
-            || !s.pending_refunds.is_empty()
-            || !s.pending_3usd_refunds.is_empty()
-    }) {
-        // Schedule another check in 5 seconds
-        log!(
-            INFO,
-            "[process_pending_transfer] Scheduling another transfer attempt in 5 seconds"
-        );
-        ic_cdk_timers::set_timer(std::time::Duration::from_secs(5), || {
-            ic_cdk::spawn(crate::process_pending_transfer())
-        });
-    } else {
-        log!(INFO, "[process_pending_transfer] No more pending transfers");
+    // Schedule another run for when the earliest entry is due
+    match read_state(|s| crate::transfer_retry::next_run_delay_secs(s, ic_cdk::api::time())) {
+        Some(delay_secs) => {
+            log!(
+                INFO,
+                "[process_pending_transfer] Scheduling another transfer attempt in {} seconds",
+                delay_secs
+            );
+            crate::scheduler::kick_pending_transfers(delay_secs);
+        }
+        None => log!(INFO, "[process_pending_transfer] No more pending transfers"),
     }
 }
@@ -0,0 +1,190 @@
+//! Liquidatable-vault set and liquidation proximity index, refreshed on
+//! every accepted price.
+//!
+//! `check_vaults` runs on its own timer and only dispatches; nothing kept
+//! an up-to-date picture of which vaults are liquidatable between its ticks.
+//! Every accepted price update (`event::record_price_update`) now re-scores
+//! the vaults of the repriced collateral:
+//!
+//! - `State::liquidatable_vault_ids` holds vaults whose CR is below their
+//!   collateral's minimum liquidation ratio (the `get_liquidatable_vaults`
+//!   rule: an unpriced vault is never liquidatable).
+//! - `State::near_liquidation_vaults` maps vaults within
+//!   `check_vaults_alert_band_bps` above that ratio to their headroom in bps.
+//!
+//! Membership changes are logged as one `LiquidatableSetChanged` event per
+//! refresh, so subscribers polling the event log see them within one price
+//! cycle. Both sets are derived from prices and are not rebuilt by replay.
+//!
+//! A refresh visits at most `LIQUIDATABLE_REFRESH_MAX_VISITS` vaults. A
+//! collateral with more vaults is finished by continuation timers, resuming
+//! after the last vault visited; see `State::liquidatable_refresh_cursors`.
+
+use crate::compute_collateral_ratio;
+use crate::event::Event;
+use crate::numeric::UsdIcp;
+use crate::state::{CollateralType, State};
+use candid::{CandidType, Deserialize};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// Most vaults re-scored by a single refresh.
+pub const LIQUIDATABLE_REFRESH_MAX_VISITS: usize = 2_000;
+
+/// Outcome of one refresh.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LiquidatableDelta {
+    pub added: Vec<u64>,
+    pub removed: Vec<u64>,
+    pub vaults_visited: u64,
+    /// The budget ran out before the collateral's last vault.
+    pub truncated: bool,
+}
+
+impl LiquidatableDelta {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LiquidatableRefreshStats {
+    pub refreshes: u64,
+    /// Refreshes that ran out of budget and were continued later.
+    pub truncated_refreshes: u64,
+    pub last_refresh_ns: u64,
+    pub last_vaults_visited: u64,
+    pub total_added: u64,
+    pub total_removed: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct LiquidatableSetView {
+    pub liquidatable_vault_ids: Vec<u64>,
+    /// `(vault_id, headroom_bps)`, closest to liquidation first.
+    pub near_liquidation: Vec<(u64, u64)>,
+    pub stats: LiquidatableRefreshStats,
+}
+
+/// Re-score up to `max_visits` vaults of `collateral_type`, resuming after
+/// the collateral's cursor, and update both sets. Also drops members whose
+/// vault no longer exists.
+pub fn refresh_liquidatable_set(
+    state: &mut State,
+    collateral_type: &CollateralType,
+    max_visits: usize,
+    now_ns: u64,
+) -> LiquidatableDelta {
+    let mut delta = LiquidatableDelta::default();
+
+    let gone: Vec<u64> = state
+        .liquidatable_vault_ids
+        .iter()
+        .filter(|id| !state.vault_id_to_vaults.contains_key(id))
+        .copied()
+        .collect();
+    for id in gone {
+        state.liquidatable_vault_ids.remove(&id);
+        delta.removed.push(id);
+    }
+    let vault_ids = &state.vault_id_to_vaults;
+    state
+        .near_liquidation_vaults
+        .retain(|id, _| vault_ids.contains_key(id));
+
+    let start = state
+        .liquidatable_refresh_cursors
+        .get(collateral_type)
+        .copied()
+        .unwrap_or(0);
+    let ids: Vec<u64> = state
+        .collateral_to_vault_ids
+        .get(collateral_type)
+        .map(|ids| ids.range(start..).copied().take(max_visits + 1).collect())
+        .unwrap_or_default();
+    delta.truncated = ids.len() > max_visits;
+
+    let min_ratio = state.get_min_liquidation_ratio_for(collateral_type).0;
+    let band = Decimal::from(state.check_vaults_alert_band_bps) / Decimal::from(10_000u64);
+    let rate = state.last_icp_rate.unwrap_or(UsdIcp::from(Decimal::ZERO));
+    for vault_id in ids.iter().take(max_visits) {
+        let Some(vault) = state.vault_id_to_vaults.get(vault_id) else {
+            continue;
+        };
+        delta.vaults_visited += 1;
+        let ratio = compute_collateral_ratio(vault, rate, state).0;
+        let priced = ratio != Decimal::ZERO;
+        let liquidatable = priced && ratio < min_ratio;
+        let headroom_bps = (priced && !liquidatable && ratio < min_ratio + band)
+            .then(|| ((ratio - min_ratio) * Decimal::from(10_000u64)).to_u64())
+            .flatten();
+
+        if liquidatable {
+            if state.liquidatable_vault_ids.insert(*vault_id) {
+                delta.added.push(*vault_id);
+            }
+        } else if state.liquidatable_vault_ids.remove(vault_id) {
+            delta.removed.push(*vault_id);
+        }
+        match headroom_bps {
+            Some(bps) => state.near_liquidation_vaults.insert(*vault_id, bps),
+            None => state.near_liquidation_vaults.remove(vault_id),
+        };
+    }
+
+    if delta.truncated {
+        state
+            .liquidatable_refresh_cursors
+            .insert(*collateral_type, ids[max_visits]);
+    } else {
+        state.liquidatable_refresh_cursors.remove(collateral_type);
+    }
+
+    let stats = &mut state.liquidatable_refresh_stats;
+    stats.refreshes += 1;
+    stats.truncated_refreshes += delta.truncated as u64;
+    stats.last_refresh_ns = now_ns;
+    stats.last_vaults_visited = delta.vaults_visited;
+    stats.total_added += delta.added.len() as u64;
+    stats.total_removed += delta.removed.len() as u64;
+    delta
+}
+
+pub fn liquidatable_set_view(state: &State) -> LiquidatableSetView {
+    let mut near_liquidation: Vec<(u64, u64)> = state
+        .near_liquidation_vaults
+        .iter()
+        .map(|(id, bps)| (*id, *bps))
+        .collect();
+    near_liquidation.sort_by_key(|(id, bps)| (*bps, *id));
+    LiquidatableSetView {
+        liquidatable_vault_ids: state.liquidatable_vault_ids.iter().copied().collect(),
+        near_liquidation,
+        stats: state.liquidatable_refresh_stats.clone(),
+    }
+}
+
+/// Live path: refresh after a price update for `collateral_type`, log the
+/// delta, and schedule a continuation if the refresh ran out of budget.
+pub fn on_price_update(state: &mut State, collateral_type: CollateralType, now_ns: u64) {
+    let delta = refresh_liquidatable_set(
+        state,
+        &collateral_type,
+        LIQUIDATABLE_REFRESH_MAX_VISITS,
+        now_ns,
+    );
+    if !delta.is_empty() {
+        crate::storage::record_event(&Event::LiquidatableSetChanged {
+            collateral_type,
+            added: delta.added,
+            removed: delta.removed,
+            timestamp: now_ns,
+        });
+    }
+    if delta.truncated {
+        ic_cdk_timers::set_timer(std::time::Duration::ZERO, move || {
+            crate::state::mutate_state(|s| on_price_update(s, collateral_type, ic_cdk::api::time()))
+        });
+    }
+}
@@ -0,0 +1,272 @@
+//! Per-collateral caps on the collateral liquidations seize.
+//!
+//! A crash in a thin collateral can make most of its vaults liquidatable at
+//! once, and liquidators then dump everything they seized into the same
+//! shallow market. A collateral with a cap configured
+//! (`set_collateral_liquidation_cap`) only gives up so much at a time: at
+//! most `max_seized_per_call` in one liquidation, and at most
+//! `max_seized_per_window` across all liquidations in a window of
+//! `window_ns`. Both are in the collateral's native units; either can be 0
+//! to leave it off, and the tighter one applies.
+//!
+//! Windows are aligned to multiples of `window_ns` since the Unix epoch, so
+//! every caller sees the same boundaries. A partial liquidation asking for
+//! more is clipped to what fits, stopping short of leaving dust debt, and
+//! logs `LiquidationCapBound`; the rest of the demand comes in later calls
+//! or windows. A full `liquidate_vault` that would seize more is refused in
+//! favour of a partial one. The stability-pool write-down and the bot
+//! liquidation paths arrive with their icUSD already committed, so they are
+//! counted against the window but never clipped; auctions pace themselves
+//! and are not counted.
+//!
+//! The cap is checked before the liquidator's icUSD is pulled, so
+//! liquidations of different vaults racing across that `await` can overrun
+//! a window by one call each. Usage is rebuilt on replay from the
+//! liquidation events themselves.
+
+use crate::event::Event;
+use crate::numeric::ICUSD;
+use crate::state::{CollateralType, State};
+use crate::vault::Vault;
+use candid::{CandidType, Deserialize};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// Shortest window the developer may set (1 second).
+pub const MIN_LIQUIDATION_WINDOW_NS: u64 = 1_000_000_000;
+
+/// Longest window the developer may set (1 day).
+pub const MAX_LIQUIDATION_WINDOW_NS: u64 = 24 * 3600 * 1_000_000_000;
+
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LiquidationCapConfig {
+    pub window_ns: u64,
+    /// Most collateral one liquidation seizes. 0 = no per-call cap.
+    pub max_seized_per_call: u64,
+    /// Most collateral all liquidations seize per window. 0 = no window cap.
+    pub max_seized_per_window: u64,
+}
+
+/// What the current window has seized. Kept only for capped collaterals.
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LiquidationWindowUsage {
+    pub window_start_ns: u64,
+    pub seized: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct LiquidationCapacity {
+    pub collateral_type: CollateralType,
+    /// `None` when the collateral is uncapped.
+    pub config: Option<LiquidationCapConfig>,
+    pub window_start_ns: u64,
+    pub window_end_ns: u64,
+    pub seized: u64,
+    /// What one liquidation may seize now: the per-call cap or what the
+    /// window has left, whichever is lower. `None` when uncapped.
+    pub seizable: Option<u64>,
+}
+
+pub fn validate_config(config: &LiquidationCapConfig) -> Result<(), String> {
+    if config.window_ns < MIN_LIQUIDATION_WINDOW_NS || config.window_ns > MAX_LIQUIDATION_WINDOW_NS
+    {
+        return Err(format!(
+            "window_ns must be between {} and {}",
+            MIN_LIQUIDATION_WINDOW_NS, MAX_LIQUIDATION_WINDOW_NS
+        ));
+    }
+    if config.max_seized_per_call == 0 && config.max_seized_per_window == 0 {
+        return Err(
+            "Set max_seized_per_call or max_seized_per_window, or pass None to remove the cap"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Install (`Some`) or remove (`None`) a collateral's cap. A changed cap
+/// starts from a fresh window count.
+pub fn apply_set_config(
+    state: &mut State,
+    collateral_type: CollateralType,
+    config: Option<LiquidationCapConfig>,
+) {
+    state.liquidation_window_usage.remove(&collateral_type);
+    match config {
+        Some(config) => {
+            state.liquidation_caps.insert(collateral_type, config);
+        }
+        None => {
+            state.liquidation_caps.remove(&collateral_type);
+        }
+    }
+}
+
+fn window_start(config: &LiquidationCapConfig, now_ns: u64) -> u64 {
+    now_ns - now_ns % config.window_ns.max(1)
+}
+
+/// What the window `now_ns` falls in has seized; a new window starts empty.
+fn current_seized(
+    state: &State,
+    collateral_type: &CollateralType,
+    config: &LiquidationCapConfig,
+    now_ns: u64,
+) -> u64 {
+    match state.liquidation_window_usage.get(collateral_type) {
+        Some(usage) if usage.window_start_ns == window_start(config, now_ns) => usage.seized,
+        _ => 0,
+    }
+}
+
+pub fn capacity(
+    state: &State,
+    collateral_type: &CollateralType,
+    now_ns: u64,
+) -> LiquidationCapacity {
+    let Some(config) = state.liquidation_caps.get(collateral_type) else {
+        return LiquidationCapacity {
+            collateral_type: *collateral_type,
+            config: None,
+            window_start_ns: 0,
+            window_end_ns: 0,
+            seized: 0,
+            seizable: None,
+        };
+    };
+    let start = window_start(config, now_ns);
+    let seized = current_seized(state, collateral_type, config, now_ns);
+    let per_call = if config.max_seized_per_call > 0 {
+        config.max_seized_per_call
+    } else {
+        u64::MAX
+    };
+    let window_left = if config.max_seized_per_window > 0 {
+        config.max_seized_per_window.saturating_sub(seized)
+    } else {
+        u64::MAX
+    };
+    LiquidationCapacity {
+        collateral_type: *collateral_type,
+        config: Some(*config),
+        window_start_ns: start,
+        window_end_ns: start.saturating_add(config.window_ns),
+        seized,
+        seizable: Some(per_call.min(window_left)),
+    }
+}
+
+/// What one liquidation of `collateral_type` may seize now, or `None` when
+/// it is uncapped.
+pub fn seizable(state: &State, collateral_type: &CollateralType, now_ns: u64) -> Option<u64> {
+    capacity(state, collateral_type, now_ns).seizable
+}
+
+/// Clip a partial liquidation's `repay` of `vault`, already past the dust
+/// round-up, so the collateral it seizes at `price` fits what the
+/// collateral may give up now. A clipped repay also leaves at least
+/// `min_vault_debt` behind, so it needs no round-up of its own. Returns the
+/// repay and, when the cap bound, what was seizable; refuses a cap with
+/// nothing left.
+pub fn cap_repay(
+    state: &State,
+    vault: &Vault,
+    repay: ICUSD,
+    price: Decimal,
+    decimals: u8,
+    now_ns: u64,
+) -> Result<(ICUSD, Option<u64>), String> {
+    let capacity = capacity(state, &vault.collateral_type, now_ns);
+    let Some(seizable) = capacity.seizable else {
+        return Ok((repay, None));
+    };
+    let (seized, _, _) = state.partial_liquidation_split(vault, repay, price, decimals);
+    if seized.to_u64() <= seizable {
+        return Ok((repay, None));
+    }
+    let bonus = state.get_liquidation_bonus_for(&vault.collateral_type);
+    let collateral_raw = (Decimal::from(seizable) / bonus.0.max(Decimal::ONE)).to_u64().unwrap_or(0);
+    let min_vault_debt = state
+        .get_collateral_config(&vault.collateral_type)
+        .map(|c| c.min_vault_debt)
+        .unwrap_or(ICUSD::new(0));
+    let clipped = crate::numeric::collateral_usd_value(collateral_raw, price, decimals)
+        .min(vault.borrowed_icusd_amount.saturating_sub(min_vault_debt))
+        .min(repay);
+    if clipped.0 == 0 {
+        return Err(format!(
+            "Liquidation cap reached for {}: {} may be seized until {} (ns). \
+             Retry in a later window.",
+            vault.collateral_type, seizable, capacity.window_end_ns
+        ));
+    }
+    Ok((clipped, Some(seizable)))
+}
+
+/// Reject a full liquidation of `vault_id` seizing `seized` that the cap
+/// cannot fit.
+pub fn check_seizure(
+    state: &State,
+    vault_id: u64,
+    collateral_type: &CollateralType,
+    seized: u64,
+    now_ns: u64,
+) -> Result<(), String> {
+    let capacity = capacity(state, collateral_type, now_ns);
+    match capacity.seizable {
+        Some(seizable) if seized > seizable => Err(format!(
+            "Liquidating vault #{} would seize {} of {}, more than the {} its liquidation cap \
+             allows now. Use liquidate_vault_partial or retry after {} (ns).",
+            vault_id, seized, collateral_type, seizable, capacity.window_end_ns
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Count `seized` collateral just taken from a `collateral_type` vault. A
+/// no-op for uncapped collaterals.
+pub fn note_seized(state: &mut State, collateral_type: &CollateralType, seized: u64, now_ns: u64) {
+    let Some(config) = state.liquidation_caps.get(collateral_type).copied() else {
+        return;
+    };
+    let usage = LiquidationWindowUsage {
+        window_start_ns: window_start(&config, now_ns),
+        seized: current_seized(state, collateral_type, &config, now_ns).saturating_add(seized),
+    };
+    state
+        .liquidation_window_usage
+        .insert(*collateral_type, usage);
+}
+
+/// Count the collateral a liquidation event seized. Called right after the
+/// event is recorded, while its vault still exists: the vault gives the
+/// collateral type the seizure counts against.
+pub fn note_liquidation(state: &mut State, event: &Event) {
+    let (vault_id, seized) = match event {
+        Event::PartialLiquidateVault {
+            vault_id,
+            icp_to_liquidator,
+            protocol_fee_collateral,
+            ..
+        } => (
+            *vault_id,
+            icp_to_liquidator.to_u64() + protocol_fee_collateral.unwrap_or(0),
+        ),
+        Event::LiquidateVault {
+            vault_id,
+            collateral_seized: Some(seized),
+            ..
+        } => (*vault_id, *seized),
+        _ => return,
+    };
+    let Some(collateral_type) = state
+        .vault_id_to_vaults
+        .get(&vault_id)
+        .map(|vault| vault.collateral_type)
+    else {
+        return;
+    };
+    let now_ns = event.timestamp_ns().unwrap_or(0);
+    note_seized(state, &collateral_type, seized, now_ns);
+}
@@ -0,0 +1,180 @@
+//! Per-collateral liquidation index, kept up to date on every vault change.
+//!
+//! `vault_cr_index` keys vaults by their CR at the cached price of their
+//! last re-key, so its keys go stale with every price tick and passive
+//! accrual, and callers that need the current picture re-check every vault.
+//! This index keys each vault by its debt per unit of collateral instead
+//! (`debt_e8s * 1e18 / collateral`), which does not depend on the price:
+//! within one collateral type a higher key is a lower CR at any price. A
+//! vault is liquidatable once its key passes a threshold derived from the
+//! collateral's last price and minimum liquidation ratio, so the
+//! liquidatable vaults of a type are one range of the index, read in
+//! O(log n) plus the vaults returned, worst first.
+//!
+//! Price changes and mode changes only move the threshold. Vault changes
+//! re-key through `State::reindex_vault_cr` / `unindex_vault_cr`, and
+//! interest accrual re-keys here directly (O(log n) thanks to the
+//! `liquidation_index_keys` reverse map). Like `vault_cr_index` the index
+//! is not persisted and is rebuilt in `post_upgrade`.
+//!
+//! Each candidate is confirmed with `compute_collateral_ratio` before it
+//! is returned; the walk starts `THRESHOLD_SLACK_BPS` below the threshold
+//! so rounding in the USD valuation cannot hide a vault at the boundary.
+
+use crate::compute_collateral_ratio;
+use crate::numeric::UsdIcp;
+use crate::state::{CollateralType, State};
+use crate::vault::Vault;
+use candid::Principal;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+
+/// Fixed-point scale of the keys.
+const SCALE: u128 = 1_000_000_000_000_000_000;
+
+/// How far below the threshold (in bps of it) the walk starts.
+pub const THRESHOLD_SLACK_BPS: u128 = 1;
+
+/// `debt_e8s` per unit of `collateral`, scaled by 1e18. Zero-debt vaults
+/// key at 0 and vaults with debt but no collateral at `u128::MAX`.
+pub fn key(debt_e8s: u64, collateral: u64) -> u128 {
+    if debt_e8s == 0 {
+        return 0;
+    }
+    if collateral == 0 {
+        return u128::MAX;
+    }
+    (debt_e8s as u128) * SCALE / collateral as u128
+}
+
+/// Legacy `anonymous` vaults are ICP vaults.
+fn resolve(state: &State, ct: &CollateralType) -> CollateralType {
+    if ct == &Principal::anonymous() {
+        state.icp_ledger_principal
+    } else {
+        *ct
+    }
+}
+
+/// Insert or move `vault_id` at its current debt and collateral. Drops it
+/// if the vault no longer exists.
+pub fn reindex(state: &mut State, vault_id: u64) {
+    unindex(state, vault_id);
+    let Some(vault) = state.vault_id_to_vaults.get(&vault_id) else {
+        return;
+    };
+    let ct = resolve(state, &vault.collateral_type);
+    let key = key(
+        vault.borrowed_icusd_amount.to_u64(),
+        vault.collateral_amount,
+    );
+    state
+        .liquidation_index
+        .entry(ct)
+        .or_default()
+        .entry(key)
+        .or_default()
+        .insert(vault_id);
+    state.liquidation_index_keys.insert(vault_id, (ct, key));
+}
+
+/// Drop `vault_id` from the index. Idempotent.
+pub fn unindex(state: &mut State, vault_id: u64) {
+    let Some((ct, key)) = state.liquidation_index_keys.remove(&vault_id) else {
+        return;
+    };
+    let Some(index) = state.liquidation_index.get_mut(&ct) else {
+        return;
+    };
+    if let Some(bucket) = index.get_mut(&key) {
+        bucket.remove(&vault_id);
+        if bucket.is_empty() {
+            index.remove(&key);
+        }
+    }
+    if index.is_empty() {
+        state.liquidation_index.remove(&ct);
+    }
+}
+
+/// The key above which a vault of `ct` is liquidatable at its last price,
+/// already lowered by `THRESHOLD_SLACK_BPS`. `None` when `ct` has no price:
+/// an unpriced vault is never liquidatable.
+pub fn threshold_key(state: &State, ct: &CollateralType) -> Option<u128> {
+    let config = state.get_collateral_config(ct)?;
+    let price = config
+        .last_price
+        .and_then(Decimal::from_f64)
+        .filter(|p| *p > Decimal::ZERO)?;
+    let ratio = state.get_min_liquidation_ratio_for(ct).0;
+    if ratio <= Decimal::ZERO {
+        return None;
+    }
+    // CR < ratio  <=>  debt / collateral > price * 1e8 / (10^decimals * ratio),
+    // and the key carries another 1e18.
+    let exponent = 26 - config.decimals as i32;
+    let power = 10u128
+        .checked_pow(exponent.unsigned_abs())
+        .and_then(Decimal::from_u128);
+    let exact = power
+        .and_then(|power| {
+            if exponent >= 0 {
+                (price / ratio).checked_mul(power)
+            } else {
+                (price / ratio).checked_div(power)
+            }
+        })
+        .and_then(|v| v.to_u128());
+    // Too large to represent: walk the whole type and let the CR check decide.
+    let exact = exact.unwrap_or(0);
+    Some(exact.saturating_sub(exact * THRESHOLD_SLACK_BPS / 10_000))
+}
+
+/// Liquidatable vaults of `ct` at its last price, worst first.
+pub fn liquidatable_for<'a>(state: &'a State, ct: &CollateralType) -> Vec<&'a Vault> {
+    let ct = resolve(state, ct);
+    let (Some(threshold), Some(index)) =
+        (threshold_key(state, &ct), state.liquidation_index.get(&ct))
+    else {
+        return Vec::new();
+    };
+    let min_ratio = state.get_min_liquidation_ratio_for(&ct);
+    let rate = UsdIcp::from(Decimal::ZERO);
+    index
+        .range(threshold..)
+        .rev()
+        .flat_map(|(_, bucket)| bucket)
+        .filter_map(|id| state.vault_id_to_vaults.get(id))
+        .filter(|vault| compute_collateral_ratio(vault, rate, state) < min_ratio)
+        .collect()
+}
+
+/// Liquidatable vaults of every collateral type, lowest CR first.
+pub fn liquidatable(state: &State) -> Vec<&Vault> {
+    let rate = UsdIcp::from(Decimal::ZERO);
+    let mut vaults: Vec<(Decimal, &Vault)> = state
+        .liquidation_index
+        .keys()
+        .flat_map(|ct| liquidatable_for(state, ct))
+        .map(|vault| (compute_collateral_ratio(vault, rate, state).0, vault))
+        .collect();
+    vaults.sort_by_key(|(cr, vault)| (*cr, vault.vault_id));
+    vaults.into_iter().map(|(_, vault)| vault).collect()
+}
+
+/// What `check_vaults` dispatches: `liquidatable` minus vaults already
+/// claimed by the bot and native-XRP vaults, which are only liquidated
+/// manually (same rules as `State::scan_unhealthy_vaults`).
+pub fn unhealthy_vaults(state: &State) -> Vec<Vault> {
+    liquidatable(state)
+        .into_iter()
+        .filter(|vault| !vault.bot_processing)
+        .filter(|vault| {
+            !state
+                .get_collateral_config(&vault.collateral_type)
+                .map(|c| c.is_native_xrp())
+                .unwrap_or(false)
+        })
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,169 @@
+//! Liquidation receipts: a post-mortem of each liquidation, addressed to the
+//! owner of the liquidated vault.
+//!
+//! Every vault liquidation path calls `issue` once its state change is
+//! applied, passing the vault as it stood just before. The receipt records
+//! the price the liquidation used, the vault's CR at that price, the debt
+//! repaid, the collateral seized and how much of it was bonus, and what was
+//! left for the owner. Issuing a receipt also queues a `Liquidated`
+//! notification carrying the receipt id (see `notifications`), unless the
+//! owner opted out in their `user_preferences`. Chain vaults (`chains`) get
+//! no receipts yet; `ChainVaultLiquidated` is their record.
+//!
+//! Owners read their receipts with `get_my_liquidation_receipts`. Like
+//! notifications, receipts live in the state snapshot only and are not
+//! rebuilt by event replay.
+
+use crate::notifications::{
+    classify_vault, push_notification, VaultNotification, VaultNotificationKind, VaultRiskLevel,
+};
+use crate::numeric::{collateral_usd_value, icusd_to_collateral_amount, ICUSD};
+use crate::state::{CollateralType, State};
+use crate::vault::Vault;
+use candid::{CandidType, Deserialize, Principal};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// Oldest receipts are dropped beyond this many per owner.
+pub const MAX_RECEIPTS_PER_OWNER: usize = 50;
+
+/// `parameter` of the notification queued with each receipt.
+pub const LIQUIDATION_NOTIFICATION_PARAMETER: &str = "liquidation";
+
+/// Collateral amounts are in the collateral's native units.
+#[derive(CandidType, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LiquidationReceipt {
+    pub id: u64,
+    pub vault_id: u64,
+    pub collateral_type: CollateralType,
+    /// `None` when the protocol liquidated the vault itself.
+    pub liquidator: Option<Principal>,
+    /// USD price of one whole collateral token used by the liquidation.
+    pub price_usd: f64,
+    /// The vault's CR at `price_usd` just before the liquidation.
+    pub collateral_ratio_at_trigger: f64,
+    /// The liquidation ratio the vault fell below, as in effect at the time.
+    pub liquidation_ratio: f64,
+    pub debt_repaid_e8s: u64,
+    /// Everything taken from the vault, bonus and protocol fee included.
+    pub collateral_seized: u64,
+    /// The part of `collateral_seized` above the value of `debt_repaid_e8s`
+    /// at `price_usd`. Zero when the vault was underwater.
+    pub bonus_paid: u64,
+    /// The protocol's cut of `bonus_paid`.
+    pub protocol_fee: u64,
+    /// Collateral left over after a full liquidation and returned to the
+    /// owner.
+    pub residual_returned: u64,
+    /// Left-over collateral credited to another of the owner's vaults instead
+    /// of being returned (`LiquidationRebateMode::CollateralTopUp`).
+    pub residual_rebated: u64,
+    /// What is left in the vault; both zero when it was closed.
+    pub debt_remaining_e8s: u64,
+    pub collateral_remaining: u64,
+    pub created_at_ns: u64,
+}
+
+/// How a liquidation path settled, beyond what the vault's before/after
+/// amounts show.
+#[derive(Clone, Debug, Default)]
+pub struct Settlement {
+    pub liquidator: Option<Principal>,
+    /// USD per whole collateral token.
+    pub price: Decimal,
+    pub protocol_fee: u64,
+    pub residual_returned: u64,
+    pub residual_rebated: u64,
+}
+
+/// Build the receipt for a liquidation of `before`, comparing it with the
+/// vault as it is now (absent if the liquidation closed it).
+pub fn build_receipt(
+    state: &State,
+    before: &Vault,
+    settlement: &Settlement,
+    now_ns: u64,
+) -> LiquidationReceipt {
+    let ct = before.collateral_type;
+    let decimals = state
+        .get_collateral_config(&ct)
+        .map(|c| c.decimals)
+        .unwrap_or(8);
+    let (debt_remaining, collateral_remaining) = state
+        .vault_id_to_vaults
+        .get(&before.vault_id)
+        .map(|v| (v.borrowed_icusd_amount, v.collateral_amount))
+        .unwrap_or((ICUSD::new(0), 0));
+
+    let debt_repaid = before.borrowed_icusd_amount.saturating_sub(debt_remaining);
+    let collateral_seized = before
+        .collateral_amount
+        .saturating_sub(collateral_remaining)
+        .saturating_sub(settlement.residual_returned)
+        .saturating_sub(settlement.residual_rebated);
+    let debt_in_collateral = icusd_to_collateral_amount(debt_repaid, settlement.price, decimals);
+    let collateral_ratio_at_trigger = if before.borrowed_icusd_amount.0 == 0 {
+        f64::MAX
+    } else {
+        (collateral_usd_value(before.collateral_amount, settlement.price, decimals)
+            / before.borrowed_icusd_amount)
+            .to_f64()
+    };
+
+    LiquidationReceipt {
+        id: 0,
+        vault_id: before.vault_id,
+        collateral_type: ct,
+        liquidator: settlement.liquidator,
+        price_usd: settlement.price.to_f64().unwrap_or(0.0),
+        collateral_ratio_at_trigger,
+        liquidation_ratio: state.get_min_liquidation_ratio_for(&ct).to_f64(),
+        debt_repaid_e8s: debt_repaid.to_u64(),
+        collateral_seized,
+        bonus_paid: collateral_seized.saturating_sub(debt_in_collateral),
+        protocol_fee: settlement.protocol_fee,
+        residual_returned: settlement.residual_returned,
+        residual_rebated: settlement.residual_rebated,
+        debt_remaining_e8s: debt_remaining.to_u64(),
+        collateral_remaining,
+        created_at_ns: now_ns,
+    }
+}
+
+/// Store the receipt for a liquidation of `before` under its owner and
+/// queue the matching notification if the owner wants it. Call after the liquidation's state
+/// change is applied. Returns the receipt id.
+pub fn issue(state: &mut State, before: &Vault, settlement: Settlement, now_ns: u64) -> u64 {
+    let mut receipt = build_receipt(state, before, &settlement, now_ns);
+    let id = state.next_liquidation_receipt_id;
+    state.next_liquidation_receipt_id += 1;
+    receipt.id = id;
+
+    let new_level = state
+        .vault_id_to_vaults
+        .get(&before.vault_id)
+        .cloned()
+        .and_then(|v| classify_vault(state, &v))
+        .unwrap_or(VaultRiskLevel::Liquidatable);
+    let notification = VaultNotification {
+        id: 0,
+        vault_id: before.vault_id,
+        collateral_type: before.collateral_type,
+        parameter: LIQUIDATION_NOTIFICATION_PARAMETER.to_string(),
+        previous_level: VaultRiskLevel::Liquidatable,
+        new_level,
+        collateral_ratio: receipt.collateral_ratio_at_trigger,
+        created_at_ns: now_ns,
+        kind: VaultNotificationKind::Liquidated { receipt_id: id },
+    };
+
+    let queue = state.liquidation_receipts.entry(before.owner).or_default();
+    queue.push(receipt);
+    if queue.len() > MAX_RECEIPTS_PER_OWNER {
+        let excess = queue.len() - MAX_RECEIPTS_PER_OWNER;
+        queue.drain(..excess);
+    }
+    push_notification(state, before.owner, notification);
+    id
+}
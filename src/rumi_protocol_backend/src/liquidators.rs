@@ -0,0 +1,158 @@
+//! Registry of external liquidators and how they perform.
+//!
+//! The developer whitelists liquidators with `register_liquidator_admin`; when
+//! self-registration is switched on, anyone can add themselves with
+//! `register_liquidator`. Registration, removal and the switch are logged as
+//! events.
+//!
+//! For a registered caller, each call of the external liquidation endpoints
+//! (`liquidate_vault`, `liquidate_vault_partial`, `liquidate_to_target` and
+//! their `_with_stable` twins) counts toward its `LiquidatorStats`: a
+//! success adds the icUSD debt it cleared to the volume, whatever it was
+//! paid in, and any error reply is a failure. Stats live in the state
+//! snapshot rather than the event log, so a full replay starts them over.
+//! Unregistered callers are not tracked, which keeps the stats bounded by
+//! `MAX_LIQUIDATORS`.
+
+use crate::state::State;
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+/// Most liquidators the registry holds.
+pub const MAX_LIQUIDATORS: usize = 500;
+
+/// Longest accepted liquidator name, in bytes.
+pub const MAX_LIQUIDATOR_NAME_LEN: usize = 64;
+
+/// Largest page `get_liquidator_leaderboard` returns.
+pub const MAX_LEADERBOARD_LEN: usize = 100;
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LiquidatorRegistration {
+    pub name: Option<String>,
+    pub registered_at: u64,
+    /// Added by the liquidator itself rather than the developer.
+    pub self_registered: bool,
+}
+
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LiquidatorStats {
+    pub liquidations: u64,
+    /// icUSD debt cleared, in e8s.
+    pub volume_e8s: u64,
+    pub failures: u64,
+    pub last_liquidation_at: Option<u64>,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct LiquidatorEntry {
+    pub liquidator: Principal,
+    pub registration: LiquidatorRegistration,
+    pub stats: LiquidatorStats,
+}
+
+pub fn validate_name(name: &Option<String>) -> Result<(), ProtocolError> {
+    match name {
+        Some(name) if name.is_empty() || name.len() > MAX_LIQUIDATOR_NAME_LEN => {
+            Err(ProtocolError::GenericError(format!(
+                "Liquidator name must be 1 to {} bytes",
+                MAX_LIQUIDATOR_NAME_LEN
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Whether `liquidator` may be added: a renaming always can, a new entry
+/// needs room.
+pub fn check_register(state: &State, liquidator: &Principal) -> Result<(), ProtocolError> {
+    if *liquidator == Principal::anonymous() {
+        return Err(ProtocolError::AnonymousCallerNotAllowed);
+    }
+    if !state.liquidators.contains_key(liquidator) && state.liquidators.len() >= MAX_LIQUIDATORS {
+        return Err(ProtocolError::GenericError(format!(
+            "The liquidator registry is full ({} entries)",
+            MAX_LIQUIDATORS
+        )));
+    }
+    Ok(())
+}
+
+/// Add or rename `liquidator`; a re-registration keeps the original time
+/// and stats.
+pub fn apply_register(
+    state: &mut State,
+    liquidator: Principal,
+    name: Option<String>,
+    self_registered: bool,
+    timestamp: u64,
+) {
+    state
+        .liquidators
+        .entry(liquidator)
+        .and_modify(|r| r.name = name.clone())
+        .or_insert(LiquidatorRegistration {
+            name,
+            registered_at: timestamp,
+            self_registered,
+        });
+}
+
+/// Drop `liquidator` and its stats; registering again starts from zero.
+pub fn apply_remove(state: &mut State, liquidator: &Principal) {
+    state.liquidators.remove(liquidator);
+    state.liquidator_stats.remove(liquidator);
+}
+
+/// Count a liquidation call by `liquidator`: `Some(cleared_e8s)` for a
+/// success, `None` for a failure. Calls by unregistered principals are
+/// ignored.
+pub fn record_outcome(
+    state: &mut State,
+    liquidator: Principal,
+    cleared_e8s: Option<u64>,
+    now: u64,
+) {
+    if !state.liquidators.contains_key(&liquidator) {
+        return;
+    }
+    let stats = state.liquidator_stats.entry(liquidator).or_default();
+    match cleared_e8s {
+        Some(cleared) => {
+            stats.liquidations += 1;
+            stats.volume_e8s = stats.volume_e8s.saturating_add(cleared);
+            stats.last_liquidation_at = Some(now);
+        }
+        None => stats.failures += 1,
+    }
+}
+
+pub fn entry(state: &State, liquidator: &Principal) -> Option<LiquidatorEntry> {
+    state
+        .liquidators
+        .get(liquidator)
+        .map(|registration| LiquidatorEntry {
+            liquidator: *liquidator,
+            registration: registration.clone(),
+            stats: state
+                .liquidator_stats
+                .get(liquidator)
+                .cloned()
+                .unwrap_or_default(),
+        })
+}
+
+/// Registered liquidators by volume, then liquidation count, highest first.
+pub fn leaderboard(state: &State, limit: usize) -> Vec<LiquidatorEntry> {
+    let mut entries: Vec<LiquidatorEntry> = state
+        .liquidators
+        .keys()
+        .filter_map(|liquidator| entry(state, liquidator))
+        .collect();
+    entries.sort_by(|a, b| {
+        (b.stats.volume_e8s, b.stats.liquidations).cmp(&(a.stats.volume_e8s, a.stats.liquidations))
+    });
+    entries.truncate(limit.min(MAX_LEADERBOARD_LEN));
+    entries
+}
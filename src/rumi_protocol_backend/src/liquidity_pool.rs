@@ -1,24 +1,187 @@
 use crate::event::{
     record_claim_liquidity_returns, record_provide_liquidity, record_withdraw_liquidity,
 };
+use crate::event::{
+    record_liquidity_dust_sent_to_treasury, record_liquidity_dust_swept,
+    record_liquidity_residual_returned,
+};
 use crate::guard::GuardPrincipal;
 use crate::logs::INFO;
 use crate::management::{mint_icusd, transfer_icusd_from, transfer_icp};
+use crate::state::State;
 use crate::{mutate_state, read_state, ProtocolError, ICP, MIN_LIQUIDITY_AMOUNT, ICUSD};
+use candid::{CandidType, Deserialize, Principal};
 use ic_canister_log::log;
 use icrc_ledger_types::icrc1::transfer::TransferError;
 
-pub async fn provide_liquidity(amount: u64) -> Result<u64, ProtocolError> {
-    let caller = ic_cdk::api::caller();
-    let _guard_principal = GuardPrincipal::new(caller, "provide_liquidity")?;
+/// Address-book limit: a new provider is refused once this many principals
+/// hold a position. Existing providers can always top up.
+pub const MAX_LIQUIDITY_PROVIDERS: usize = 10_000;
 
-    let amount: ICUSD = amount.into();
+/// ICP returns below this are not worth a ledger transfer (0.001 ICP, ten
+/// ledger fees); they are swept into `State::liquidity_dust_swept` and sent
+/// to the treasury in one transfer instead.
+pub const LIQUIDITY_RETURN_DUST: ICP = ICP::new(100_000);
+
+/// Residual positions returned per `sweep_liquidity_residuals` call. Each
+/// one is a mint, so the sweep is bounded like any other fan-out.
+pub const MAX_RESIDUAL_SWEEP: usize = 50;
 
+/// Outcome of one `sweep_liquidity_residuals` call.
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LiquiditySweepResult {
+    pub dust_swept: u64,
+    pub dust_to_treasury: u64,
+    pub residuals_returned: u64,
+    pub residual_amount: u64,
+    pub residuals_remaining: u64,
+}
+
+/// Deposit checks. The minimum applies to every deposit, top-ups included,
+/// so a position only ever grows in steps of at least `MIN_LIQUIDITY_AMOUNT`.
+pub fn check_provide(state: &State, caller: Principal, amount: ICUSD) -> Result<(), ProtocolError> {
     if amount < MIN_LIQUIDITY_AMOUNT {
         return Err(ProtocolError::AmountTooLow {
             minimum_amount: MIN_LIQUIDITY_AMOUNT.to_u64(),
         });
     }
+    if !state.liquidity_pool.contains_key(&caller)
+        && state.liquidity_pool.len() >= MAX_LIQUIDITY_PROVIDERS
+    {
+        return Err(ProtocolError::GenericError(format!(
+            "the liquidity pool is full ({MAX_LIQUIDITY_PROVIDERS} providers)"
+        )));
+    }
+    Ok(())
+}
+
+/// Split a withdrawal into what is minted and the residual that goes with
+/// it. A withdrawal must be at least `MIN_LIQUIDITY_AMOUNT` unless it takes
+/// the whole position; one that would leave less than the minimum behind
+/// takes the remainder too. Returns `(minted, residual)`.
+pub fn plan_withdrawal(
+    state: &State,
+    caller: Principal,
+    amount: ICUSD,
+) -> Result<(ICUSD, ICUSD), ProtocolError> {
+    let provided = state.liquidity_pool.get(&caller).cloned().ok_or_else(|| {
+        ProtocolError::GenericError("You have no provided liquidity to withdraw".to_string())
+    })?;
+    if amount > provided {
+        return Err(ProtocolError::GenericError(format!(
+            "cannot withdraw: {amount}, provided: {provided}"
+        )));
+    }
+    if amount < MIN_LIQUIDITY_AMOUNT && amount != provided {
+        return Err(ProtocolError::AmountTooLow {
+            minimum_amount: MIN_LIQUIDITY_AMOUNT.to_u64(),
+        });
+    }
+    let rest = provided - amount;
+    let residual = if rest < MIN_LIQUIDITY_AMOUNT {
+        rest
+    } else {
+        ICUSD::new(0)
+    };
+    Ok((amount + residual, residual))
+}
+
+/// Dust returns of `caller` to sweep. `None` without a position or dust to
+/// sweep.
+pub fn dust_sweep(state: &State, caller: Principal) -> Option<ICP> {
+    if !state.liquidity_pool.contains_key(&caller) {
+        return None;
+    }
+    let returns = state.get_liquidity_returns_of(caller);
+    (returns > ICP::new(0) && returns < LIQUIDITY_RETURN_DUST).then_some(returns)
+}
+
+/// Move dust `returns` of `caller` into the treasury-bound balance. The ICP
+/// stays ICP: crediting it as icUSD principal would let a later withdrawal
+/// mint icUSD nothing backs.
+pub fn apply_dust_sweep(state: &mut State, caller: Principal, returns: ICP) {
+    state.claim_liquidity_returns(returns, caller);
+    state.liquidity_dust_swept += returns;
+}
+
+/// Positions below `MIN_LIQUIDITY_AMOUNT`, smallest first, at most `limit`.
+pub fn residual_positions(state: &State, limit: usize) -> Vec<(Principal, ICUSD)> {
+    let mut residuals: Vec<(Principal, ICUSD)> = state
+        .liquidity_pool
+        .iter()
+        .filter(|(_, amount)| **amount < MIN_LIQUIDITY_AMOUNT)
+        .map(|(p, amount)| (*p, *amount))
+        .collect();
+    residuals.sort_by_key(|(p, amount)| (*amount, *p));
+    residuals.truncate(limit);
+    residuals
+}
+
+fn sweep_dust_returns(caller: Principal) -> bool {
+    mutate_state(|s| match dust_sweep(s, caller) {
+        Some(returns) => {
+            log!(
+                INFO,
+                "[sweep_dust_returns] {caller} swept {returns} returns"
+            );
+            record_liquidity_dust_swept(s, caller, returns);
+            true
+        }
+        None => false,
+    })
+}
+
+/// Send the swept dust to the treasury once it covers more than the ledger
+/// fee. Returns the amount that left, fee included.
+async fn send_dust_to_treasury() -> u64 {
+    let (swept, fee, treasury) = read_state(|s| {
+        (
+            s.liquidity_dust_swept,
+            s.icp_ledger_fee,
+            s.treasury_principal,
+        )
+    });
+    let Some(treasury) = treasury else {
+        return 0;
+    };
+    if swept <= fee {
+        return 0;
+    }
+    let ledger = read_state(|s| s.icp_ledger_principal);
+    match transfer_icp(swept - fee, treasury).await {
+        Ok(block_index) => {
+            log!(
+                INFO,
+                "[sweep_liquidity_residuals] sent {swept} of dust to the treasury",
+            );
+            mutate_state(|s| record_liquidity_dust_sent_to_treasury(s, swept, block_index));
+            let _ = crate::treasury::notify_treasury_deposit(
+                treasury,
+                crate::treasury::DepositType::SurplusSweep,
+                ledger,
+                (swept - fee).to_u64(),
+                block_index,
+            )
+            .await;
+            swept.to_u64()
+        }
+        Err(error) => {
+            log!(
+                INFO,
+                "[sweep_liquidity_residuals] failed to send {swept} of dust to the treasury: {error:?}",
+            );
+            0
+        }
+    }
+}
+
+pub async fn provide_liquidity(amount: u64) -> Result<u64, ProtocolError> {
+    let caller = ic_cdk::api::caller();
+    let _guard_principal = GuardPrincipal::new(caller, "provide_liquidity")?;
+
+    let amount: ICUSD = amount.into();
+
+    read_state(|s| check_provide(s, caller, amount))?;
 
     match transfer_icusd_from(amount, caller).await {
         Ok(block_index) => {
@@ -26,6 +189,7 @@ pub async fn provide_liquidity(amount: u64) -> Result<u64, ProtocolError> {
             mutate_state(|s| {
                 record_provide_liquidity(s, amount, caller, block_index);
             });
+            sweep_dust_returns(caller);
             Ok(block_index)
         }
         Err(transfer_from_error) => Err(ProtocolError::TransferFromError(
@@ -41,30 +205,21 @@ pub async fn withdraw_liquidity(amount: u64) -> Result<u64, ProtocolError> {
 
     let amount: ICUSD = amount.into();
 
-    if amount < MIN_LIQUIDITY_AMOUNT {
-        return Err(ProtocolError::AmountTooLow {
-            minimum_amount: MIN_LIQUIDITY_AMOUNT.to_u64(),
-        });
-    }
+    sweep_dust_returns(caller);
+    let (minted, residual) = read_state(|s| plan_withdrawal(s, caller, amount))?;
 
-    let provided_liquidity = read_state(|s| {
-        s.liquidity_pool
-            .get(&caller)
-            .cloned()
-    }).ok_or_else(|| ProtocolError::GenericError(
-        "You have no provided liquidity to withdraw".to_string()
-    ))?;
-    if amount > provided_liquidity {
-        return Err(ProtocolError::GenericError(format!(
-            "cannot withdraw: {amount}, provided: {provided_liquidity}"
-        )));
-    }
-
-    match mint_icusd(amount, caller).await {
+    match mint_icusd(minted, caller).await {
         Ok(block_index) => {
             log!(INFO, "[withdraw_liquidity] {caller} withdrew {amount}",);
             mutate_state(|s| {
                 record_withdraw_liquidity(s, amount, caller, block_index);
+                if residual > ICUSD::new(0) {
+                    log!(
+                        INFO,
+                        "[withdraw_liquidity] {caller} residual {residual} returned",
+                    );
+                    record_liquidity_residual_returned(s, caller, residual, block_index);
+                }
             });
             Ok(block_index)
         }
@@ -107,3 +262,62 @@ pub async fn claim_liquidity_returns() -> Result<u64, ProtocolError> {
         }
     }
 }
+
+/// Developer sweep: send dust returns to the treasury, then mint positions
+/// below `MIN_LIQUIDITY_AMOUNT` back to their owners (at most
+/// `MAX_RESIDUAL_SWEEP` per call). Owners busy with another operation are
+/// skipped and picked up by the next sweep.
+pub async fn sweep_liquidity_residuals() -> Result<LiquiditySweepResult, ProtocolError> {
+    let caller = ic_cdk::caller();
+    if !read_state(|s| s.developer_principal == caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer can sweep liquidity residuals.".to_string(),
+        ));
+    }
+    let _guard_principal = GuardPrincipal::new(caller, "sweep_liquidity_residuals")?;
+
+    let mut result = LiquiditySweepResult::default();
+    let providers: Vec<Principal> = read_state(|s| {
+        s.liquidity_returns
+            .keys()
+            .filter(|p| s.liquidity_pool.contains_key(*p))
+            .copied()
+            .collect()
+    });
+    for provider in providers {
+        if sweep_dust_returns(provider) {
+            result.dust_swept += 1;
+        }
+    }
+    result.dust_to_treasury = send_dust_to_treasury().await;
+
+    for (owner, _) in read_state(|s| residual_positions(s, MAX_RESIDUAL_SWEEP)) {
+        let Ok(_owner_guard) = GuardPrincipal::new(owner, "return_liquidity_residual") else {
+            continue;
+        };
+        // Re-read under the owner's guard: a deposit may have lifted it.
+        let residual = match read_state(|s| s.liquidity_pool.get(&owner).cloned()) {
+            Some(amount) if amount < MIN_LIQUIDITY_AMOUNT => amount,
+            _ => continue,
+        };
+        match mint_icusd(residual, owner).await {
+            Ok(block_index) => {
+                log!(
+                    INFO,
+                    "[sweep_liquidity_residuals] returned {residual} to {owner}",
+                );
+                mutate_state(|s| {
+                    record_liquidity_residual_returned(s, owner, residual, block_index);
+                });
+                result.residuals_returned += 1;
+                result.residual_amount += residual.to_u64();
+            }
+            Err(error) => log!(
+                INFO,
+                "[sweep_liquidity_residuals] failed to return {residual} to {owner}: {error:?}",
+            ),
+        }
+    }
+    result.residuals_remaining = read_state(|s| residual_positions(s, usize::MAX).len()) as u64;
+    Ok(result)
+}
@@ -1,7 +1,23 @@
-use ic_canister_log::{declare_log_buffer, export as export_logs, GlobalBuffer, Sink};
+use candid::CandidType;
+use ic_canister_log::{declare_log_buffer, export as export_logs, GlobalBuffer, LogBuffer, Sink};
 use serde::Deserialize;
+use std::cell::Cell;
 use std::str::FromStr;
 
+/// Capacity of each heap buffer until `apply_retention` resizes it.
+pub const DEFAULT_LOG_BUFFER_CAPACITY: u64 = 1000;
+
+/// Critical entries kept in stable memory across upgrades.
+pub const DEFAULT_PERSISTED_CRITICAL_CAPACITY: u64 = 2000;
+
+/// Bounds on every configurable capacity.
+pub const MIN_LOG_BUFFER_CAPACITY: u64 = 10;
+pub const MAX_LOG_BUFFER_CAPACITY: u64 = 20_000;
+
+// Failures that need an operator: abandoned transfers, failed refunds.
+// Also persisted to stable memory, see `CRITICAL`.
+declare_log_buffer!(name = CRITICAL_BUF, capacity = 1000);
+
 // High-priority messages.
 declare_log_buffer!(name = INFO_BUF, capacity = 1000);
 
@@ -11,10 +27,20 @@ declare_log_buffer!(name = DEBUG_BUF, capacity = 1000);
 // Trace of XRC responses.
 declare_log_buffer!(name = TRACE_XRC_BUF, capacity = 1000);
 
+pub const CRITICAL: CriticalSink = CriticalSink;
 pub const INFO: PrintProxySink = PrintProxySink("INFO", &INFO_BUF);
 pub const DEBUG: PrintProxySink = PrintProxySink("DEBUG", &DEBUG_BUF);
 pub const TRACE_XRC: PrintProxySink = PrintProxySink("TRACE_XRC", &TRACE_XRC_BUF);
 
+thread_local! {
+    static PERSISTED_CRITICAL_CAPACITY: Cell<u64> =
+        const { Cell::new(DEFAULT_PERSISTED_CRITICAL_CAPACITY) };
+
+    /// When the running wasm instance started (init or post_upgrade). Heap
+    /// only, so it resets on every upgrade.
+    static INSTANCE_STARTED_AT: Cell<u64> = const { Cell::new(0) };
+}
+
 pub struct PrintProxySink(&'static str, &'static GlobalBuffer);
 
 impl Sink for PrintProxySink {
@@ -24,8 +50,31 @@ impl Sink for PrintProxySink {
     }
 }
 
+/// Like `PrintProxySink`, but also appends the entry to the bounded
+/// stable-memory ring so it survives upgrades.
+pub struct CriticalSink;
+
+impl Sink for CriticalSink {
+    fn append(&self, entry: ic_canister_log::LogEntry) {
+        ic_cdk::println!("CRITICAL {}:{} {}", entry.file, entry.line, entry.message);
+        crate::storage::record_critical_log(
+            &LogEntry {
+                timestamp: entry.timestamp,
+                priority: Priority::Critical,
+                file: entry.file.to_string(),
+                line: entry.line,
+                message: entry.message.clone(),
+                counter: entry.counter,
+            },
+            PERSISTED_CRITICAL_CAPACITY.with(|c| c.get()),
+        );
+        (&CRITICAL_BUF).append(entry)
+    }
+}
+
 #[derive(Clone, serde::Serialize, Deserialize, Debug, Copy)]
 pub enum Priority {
+    Critical,
     Info,
     TraceXrc,
     Debug,
@@ -36,6 +85,7 @@ impl FromStr for Priority {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
+            "critical" => Ok(Priority::Critical),
             "info" => Ok(Priority::Info),
             "trace_xrc" => Ok(Priority::TraceXrc),
             "debug" => Ok(Priority::Debug),
@@ -44,6 +94,97 @@ impl FromStr for Priority {
     }
 }
 
+/// Per-priority log buffer sizes, set with `set_log_retention` and replayed
+/// from `SetLogRetention`.
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, Deserialize)]
+pub struct LogRetentionConfig {
+    pub critical_capacity: u64,
+    pub info_capacity: u64,
+    pub debug_capacity: u64,
+    pub trace_xrc_capacity: u64,
+    /// Critical entries kept in stable memory; the oldest are dropped first.
+    pub persisted_critical_capacity: u64,
+}
+
+impl Default for LogRetentionConfig {
+    fn default() -> Self {
+        Self {
+            critical_capacity: DEFAULT_LOG_BUFFER_CAPACITY,
+            info_capacity: DEFAULT_LOG_BUFFER_CAPACITY,
+            debug_capacity: DEFAULT_LOG_BUFFER_CAPACITY,
+            trace_xrc_capacity: DEFAULT_LOG_BUFFER_CAPACITY,
+            persisted_critical_capacity: DEFAULT_PERSISTED_CRITICAL_CAPACITY,
+        }
+    }
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct LogRetentionStatus {
+    pub config: LogRetentionConfig,
+    pub persisted_critical_entries: u64,
+    /// Start of the running wasm instance; `/logs?persisted=true` returns the
+    /// critical entries recorded before it.
+    pub instance_started_at: u64,
+}
+
+pub fn validate_retention(config: &LogRetentionConfig) -> Result<(), String> {
+    for (name, value) in [
+        ("critical_capacity", config.critical_capacity),
+        ("info_capacity", config.info_capacity),
+        ("debug_capacity", config.debug_capacity),
+        ("trace_xrc_capacity", config.trace_xrc_capacity),
+        (
+            "persisted_critical_capacity",
+            config.persisted_critical_capacity,
+        ),
+    ] {
+        if !(MIN_LOG_BUFFER_CAPACITY..=MAX_LOG_BUFFER_CAPACITY).contains(&value) {
+            return Err(format!(
+                "{} must be between {} and {}",
+                name, MIN_LOG_BUFFER_CAPACITY, MAX_LOG_BUFFER_CAPACITY
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Resize `buffer` to `capacity`, keeping its newest entries.
+fn resize_buffer(buffer: &'static GlobalBuffer, capacity: u64) {
+    let entries = export_logs(buffer);
+    let mut resized = LogBuffer::with_capacity(capacity as usize);
+    for entry in entries {
+        resized.append(entry);
+    }
+    buffer.with(|b| *b.borrow_mut() = resized);
+}
+
+/// Apply `config` to the heap buffers and the persisted ring. Called by the
+/// setter and on init/upgrade, since buffer sizes live on the heap.
+pub fn apply_retention(config: &LogRetentionConfig) {
+    resize_buffer(&CRITICAL_BUF, config.critical_capacity);
+    resize_buffer(&INFO_BUF, config.info_capacity);
+    resize_buffer(&DEBUG_BUF, config.debug_capacity);
+    resize_buffer(&TRACE_XRC_BUF, config.trace_xrc_capacity);
+    PERSISTED_CRITICAL_CAPACITY.with(|c| c.set(config.persisted_critical_capacity));
+    crate::storage::trim_critical_logs(config.persisted_critical_capacity);
+}
+
+pub fn mark_instance_start(now_ns: u64) {
+    INSTANCE_STARTED_AT.with(|c| c.set(now_ns));
+}
+
+pub fn instance_started_at() -> u64 {
+    INSTANCE_STARTED_AT.with(|c| c.get())
+}
+
+pub fn retention_status(config: &LogRetentionConfig) -> LogRetentionStatus {
+    LogRetentionStatus {
+        config: *config,
+        persisted_critical_entries: crate::storage::count_critical_logs(),
+        instance_started_at: instance_started_at(),
+    }
+}
+
 #[derive(Clone, serde::Serialize, Deserialize, Debug)]
 pub struct LogEntry {
     pub timestamp: u64,
@@ -62,6 +203,7 @@ pub struct Log {
 impl Log {
     pub fn push_logs(&mut self, priority: Priority) {
         let logs = match priority {
+            Priority::Critical => export_logs(&CRITICAL_BUF),
             Priority::Info => export_logs(&INFO_BUF),
             Priority::TraceXrc => export_logs(&TRACE_XRC_BUF),
             Priority::Debug => export_logs(&DEBUG_BUF),
@@ -79,8 +221,24 @@ impl Log {
     }
 
     pub fn push_all(&mut self) {
+        self.push_logs(Priority::Critical);
         self.push_logs(Priority::Info);
         self.push_logs(Priority::TraceXrc);
         self.push_logs(Priority::Debug);
     }
+
+    /// Critical entries from stable memory recorded before `before_ns`.
+    pub fn push_persisted_critical(&mut self, before_ns: u64) {
+        self.entries.extend(
+            crate::storage::critical_logs()
+                .into_iter()
+                .filter(|entry| entry.timestamp < before_ns),
+        );
+    }
+
+    /// Keep only the lines tagged with `trace` (`trace=<id>` in the message).
+    pub fn retain_trace(&mut self, trace: crate::guard::TraceId) {
+        let tag = format!("trace={}", trace);
+        self.entries.retain(|entry| entry.message.contains(&tag));
+    }
 }
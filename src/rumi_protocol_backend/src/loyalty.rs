@@ -0,0 +1,213 @@
+//! Time-weighted borrower metrics, kept so a future token distribution can
+//! be computed on-chain.
+//!
+//! Each borrower's `LoyaltyMetrics` holds their debt and collateral value
+//! as of the last update and the running sums of those amounts times the
+//! nanoseconds they were held. An update adds the time since the previous
+//! one at the old amounts, then reads the current amounts from the vaults.
+//! Updates run when the borrower's operation releases its guard and, for
+//! every borrower, on an hourly checkpoint, which also catches changes the
+//! borrower did not make (liquidations, redemptions, interest). Between
+//! updates the last amounts are assumed to hold.
+//!
+//! Collateral is counted at its USD value (e8s) at the last known price
+//! when updated, so vaults of different collateral types add up. Metrics
+//! live in the state snapshot rather than the event log, like
+//! `guard_metrics`, and start when a principal is first seen with a vault.
+
+use crate::numeric::collateral_usd_value;
+use crate::state::{mutate_state, State};
+use candid::{CandidType, Deserialize, Principal};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// How often every borrower is updated.
+pub const LOYALTY_CHECKPOINT_INTERVAL_SECS: u64 = 3_600;
+
+/// Most entries per `get_loyalty_metrics_page` call.
+pub const MAX_LOYALTY_PAGE: usize = 500;
+
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoyaltyMetrics {
+    pub first_seen_ns: u64,
+    pub last_update_ns: u64,
+    /// Amounts as of `last_update_ns`.
+    pub debt_e8s: u64,
+    pub collateral_usd_e8s: u64,
+    /// Sums of amount × nanoseconds held since `first_seen_ns`.
+    pub debt_time_e8s_ns: u128,
+    pub collateral_time_usd_e8s_ns: u128,
+}
+
+/// A principal's metrics projected to `as_of_ns`.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct LoyaltyView {
+    pub principal: Principal,
+    pub first_seen_ns: u64,
+    pub as_of_ns: u64,
+    pub debt_e8s: u64,
+    pub collateral_usd_e8s: u64,
+    pub debt_time_e8s_ns: u128,
+    pub collateral_time_usd_e8s_ns: u128,
+    /// Time-weighted averages since `first_seen_ns`.
+    pub avg_debt_e8s: u64,
+    pub avg_collateral_usd_e8s: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct LoyaltyPage {
+    pub entries: Vec<LoyaltyView>,
+    /// Pass as `start_after` for the next page; `None` on the last one.
+    pub next: Option<Principal>,
+    pub total: u64,
+}
+
+/// `principal`'s current debt and collateral value across their vaults.
+pub fn current_amounts(state: &State, principal: &Principal) -> (u64, u64) {
+    let Some(vault_ids) = state.principal_to_vault_ids.get(principal) else {
+        return (0, 0);
+    };
+    let mut debt = 0u64;
+    let mut collateral_usd = 0u64;
+    for vault in vault_ids
+        .iter()
+        .filter_map(|id| state.vault_id_to_vaults.get(id))
+    {
+        debt = debt.saturating_add(vault.borrowed_icusd_amount.to_u64());
+        let Some(config) = state.collateral_configs.get(&vault.collateral_type) else {
+            continue;
+        };
+        let price = config
+            .last_price
+            .and_then(Decimal::from_f64)
+            .unwrap_or(Decimal::ZERO);
+        collateral_usd = collateral_usd.saturating_add(
+            collateral_usd_value(vault.collateral_amount, price, config.decimals).to_u64(),
+        );
+    }
+    (debt, collateral_usd)
+}
+
+fn held(amount: u64, from_ns: u64, to_ns: u64) -> u128 {
+    (amount as u128).saturating_mul(to_ns.saturating_sub(from_ns) as u128)
+}
+
+/// Add the time since `principal`'s last update at the old amounts and
+/// take the current ones. Principals never seen with a vault are skipped.
+pub fn touch(state: &mut State, principal: Principal, now: u64) {
+    let (debt, collateral_usd) = current_amounts(state, &principal);
+    if !state.loyalty_metrics.contains_key(&principal)
+        && !state.principal_to_vault_ids.contains_key(&principal)
+    {
+        return;
+    }
+    let metrics = state
+        .loyalty_metrics
+        .entry(principal)
+        .or_insert(LoyaltyMetrics {
+            first_seen_ns: now,
+            last_update_ns: now,
+            ..Default::default()
+        });
+    let debt_held = held(metrics.debt_e8s, metrics.last_update_ns, now);
+    let collateral_held = held(metrics.collateral_usd_e8s, metrics.last_update_ns, now);
+    metrics.debt_time_e8s_ns = metrics.debt_time_e8s_ns.saturating_add(debt_held);
+    metrics.collateral_time_usd_e8s_ns = metrics
+        .collateral_time_usd_e8s_ns
+        .saturating_add(collateral_held);
+    metrics.last_update_ns = metrics.last_update_ns.max(now);
+    metrics.debt_e8s = debt;
+    metrics.collateral_usd_e8s = collateral_usd;
+}
+
+/// Update every borrower, and everyone tracked who has since left.
+pub fn checkpoint(state: &mut State, now: u64) {
+    let mut principals: Vec<Principal> = state.principal_to_vault_ids.keys().copied().collect();
+    principals.extend(
+        state
+            .loyalty_metrics
+            .keys()
+            .filter(|p| !state.principal_to_vault_ids.contains_key(p)),
+    );
+    for principal in principals {
+        touch(state, principal, now);
+    }
+}
+
+fn project(principal: Principal, metrics: &LoyaltyMetrics, now: u64) -> LoyaltyView {
+    let as_of_ns = now.max(metrics.last_update_ns);
+    let debt_held = held(metrics.debt_e8s, metrics.last_update_ns, as_of_ns);
+    let collateral_held = held(metrics.collateral_usd_e8s, metrics.last_update_ns, as_of_ns);
+    let debt_time = metrics.debt_time_e8s_ns.saturating_add(debt_held);
+    let collateral_time = metrics
+        .collateral_time_usd_e8s_ns
+        .saturating_add(collateral_held);
+    let elapsed = as_of_ns.saturating_sub(metrics.first_seen_ns) as u128;
+    let average = |time: u128, current: u64| {
+        if elapsed == 0 {
+            current
+        } else {
+            u64::try_from(time / elapsed).unwrap_or(u64::MAX)
+        }
+    };
+    LoyaltyView {
+        principal,
+        first_seen_ns: metrics.first_seen_ns,
+        as_of_ns,
+        debt_e8s: metrics.debt_e8s,
+        collateral_usd_e8s: metrics.collateral_usd_e8s,
+        debt_time_e8s_ns: debt_time,
+        collateral_time_usd_e8s_ns: collateral_time,
+        avg_debt_e8s: average(debt_time, metrics.debt_e8s),
+        avg_collateral_usd_e8s: average(collateral_time, metrics.collateral_usd_e8s),
+    }
+}
+
+/// `principal`'s metrics as of `now`, assuming their last amounts held
+/// since the last update.
+pub fn view(state: &State, principal: &Principal, now: u64) -> Option<LoyaltyView> {
+    state
+        .loyalty_metrics
+        .get(principal)
+        .map(|metrics| project(*principal, metrics, now))
+}
+
+/// Up to `limit` (1 to `MAX_LOYALTY_PAGE`) principals' metrics after
+/// `start_after`, in principal order.
+pub fn page(state: &State, start_after: Option<Principal>, limit: usize, now: u64) -> LoyaltyPage {
+    let limit = limit.clamp(1, MAX_LOYALTY_PAGE);
+    let range = match start_after {
+        Some(after) => state
+            .loyalty_metrics
+            .range((std::ops::Bound::Excluded(after), std::ops::Bound::Unbounded)),
+        None => state.loyalty_metrics.range(..),
+    };
+    let mut rest = range.peekable();
+    let entries: Vec<LoyaltyView> = rest
+        .by_ref()
+        .take(limit)
+        .map(|(principal, metrics)| project(*principal, metrics, now))
+        .collect();
+    let next = match (rest.peek(), entries.last()) {
+        (Some(_), Some(last)) => Some(last.principal),
+        _ => None,
+    };
+    LoyaltyPage {
+        entries,
+        next,
+        total: state.loyalty_metrics.len() as u64,
+    }
+}
+
+pub fn setup_checkpoint_timer() {
+    crate::scheduler::set_interval(
+        crate::scheduler::ScheduledTask::LoyaltyCheckpoint,
+        LOYALTY_CHECKPOINT_INTERVAL_SECS,
+        || async {
+            let now = ic_cdk::api::time();
+            mutate_state(|s| checkpoint(s, now));
+            Ok(())
+        },
+    );
+}
@@ -3,25 +3,32 @@ use candid::{CandidType, Deserialize};
 use ic_canister_log::log;
 use ic_canisters_http_types::{HttpRequest, HttpResponse, HttpResponseBuilder};
 use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
+use rumi_protocol_backend::collateral_settlement::CollateralSettlementStatus;
+use rumi_protocol_backend::emergency_shutdown::EmergencyShutdownStatus;
 use rumi_protocol_backend::event;
 use rumi_protocol_backend::logs::DEBUG;
 use rumi_protocol_backend::management;
 use rumi_protocol_backend::state::mutate_state;
 use rumi_protocol_backend::storage::events;
 use rumi_protocol_backend::treasury;
+use rumi_protocol_backend::vault_status::{candid_vault, transition_or_log, VaultStatus};
 use rumi_protocol_backend::LiquidityStatus;
 use rumi_protocol_backend::{
     event::Event,
     logs::INFO,
     numeric::{Ratio, UsdIcp, ICP, ICUSD},
-    state::{read_state, replace_state, Mode, RateCurveV2, State},
+    operation_ids::{self, OperationRequest, RecordedOutcome},
+    operation_pauses::PausableOperation,
+    pending_backpressure::PayoutQueue,
+    scheduler::{self, ScheduledTask},
+    state::{read_state, replace_state, Mode, ModeTransitionReason, RateCurveV2, State},
     vault::{CandidVault, OpenVaultSuccess, VaultArg},
-    CollateralInterestInfo, CollateralSnapshot, CollateralTotals, EventTypeFilter,
+    AccruedInterest, CollateralInterestInfo, CollateralSnapshot, CollateralTotals, EventTypeFilter,
     EventsByPrincipalPagedResponse, Fees, ForwardFilteredEventsResponse, GetEventsArg,
     GetEventsFilteredResponse, GetSnapshotsArg, InterestSplitArg, PerCollateralRateCurve,
-    ProtocolArg, ProtocolError, ProtocolSnapshot, ProtocolStatus, ReserveBalance,
-    ReserveRedemptionResult, StabilityPoolLiquidationResult, StableTokenType, SuccessWithFee,
-    SupplyAudit, SupplyAuditEntry, VaultArgWithToken, VaultHistoryPagedResponse,
+    ProtocolArg, ProtocolError, ProtocolSnapshot, ProtocolStatus, RepayAllAndCloseArg,
+    ReserveBalance, ReserveRedemptionResult, StabilityPoolLiquidationResult, StableTokenType,
+    SuccessWithFee, SupplyAudit, SupplyAuditEntry, VaultArgWithToken, VaultHistoryPagedResponse,
     VaultsPageResponse, XrpSpAbsorbPreflight, XrpSpAbsorbRequest, XrpSpAbsorbResult,
     MAX_EVENTS_BY_PRINCIPAL_LEGACY, MAX_EVENTS_BY_PRINCIPAL_OUTPUT, MAX_EVENTS_BY_PRINCIPAL_SCAN,
     MAX_VAULTS_LEGACY_PAGE, MAX_VAULTS_PAGE_LIMIT, MAX_VAULT_HISTORY,
@@ -77,6 +84,91 @@ fn check_postcondition<T>(t: T) -> T {
     t
 }
 
+/// Runs a user operation under a fresh trace id, so its log lines (and any
+/// pending transfers it queues) carry `trace=<id>`, and tags an error result
+/// with the same id. See `guard::TraceScope`.
+async fn traced<T>(
+    op: impl std::future::Future<Output = Result<T, ProtocolError>>,
+) -> Result<T, ProtocolError> {
+    let scope =
+        rumi_protocol_backend::guard::TraceScope::begin(ic_cdk::caller(), ic_cdk::api::time());
+    scope.tag(op.await)
+}
+
+/// Runs a vault operation at most once per caller-supplied `operation_id`: a
+/// retry of an operation that succeeded gets its result back instead of
+/// running it again. Without an id the operation just runs. See
+/// `operation_ids`.
+async fn deduplicated<T: RecordedOutcome>(
+    operation_id: Option<Vec<u8>>,
+    request: OperationRequest,
+    op: impl std::future::Future<Output = Result<T, ProtocolError>>,
+) -> Result<T, ProtocolError> {
+    let Some(bytes) = operation_id else {
+        return op.await;
+    };
+    let id = operation_ids::parse_operation_id(&bytes)?;
+    let caller = ic_cdk::caller();
+    let claimed =
+        mutate_state(|s| operation_ids::claim(s, caller, id, request, ic_cdk::api::time()))?;
+    if let Some(outcome) = claimed {
+        return T::from_outcome(&outcome).ok_or_else(|| {
+            ProtocolError::GenericError(
+                "The operation id was already used for a different operation".to_string(),
+            )
+        });
+    }
+    let result = op.await;
+    mutate_state(|s| match &result {
+        Ok(value) => operation_ids::complete(s, caller, id, value.outcome()),
+        Err(_) => operation_ids::release(s, caller, id),
+    });
+    result
+}
+
+/// Runs a user endpoint's body, validation included, as one call of
+/// `endpoint` for its SLO stats. See `slo::EndpointCall`.
+async fn slo_tracked<T>(
+    endpoint: &'static str,
+    body: impl std::future::Future<Output = Result<T, ProtocolError>>,
+) -> Result<T, ProtocolError> {
+    let call = rumi_protocol_backend::slo::EndpointCall::start(endpoint);
+    let result = body.await;
+    call.finish(&result);
+    result
+}
+
+/// Count a liquidation call toward the caller's stats when it is a
+/// registered liquidator. `cleared` is the debt the backend reports it
+/// cleared, if it does; otherwise the drop in the vault's debt is used.
+/// See `liquidators`.
+async fn liquidator_tracked<T>(
+    vault_id: u64,
+    cleared: fn(&T) -> Option<u64>,
+    body: impl std::future::Future<Output = Result<T, ProtocolError>>,
+) -> Result<T, ProtocolError> {
+    let caller = ic_cdk::caller();
+    let vault_debt = || {
+        read_state(|s| {
+            s.vault_id_to_vaults
+                .get(&vault_id)
+                .map_or(0, |v| v.borrowed_icusd_amount.to_u64())
+        })
+    };
+    let debt_before = vault_debt();
+    let result = body.await;
+    let outcome = match &result {
+        Ok(success) => {
+            Some(cleared(success).unwrap_or_else(|| debt_before.saturating_sub(vault_debt())))
+        }
+        Err(_) => None,
+    };
+    mutate_state(|s| {
+        rumi_protocol_backend::liquidators::record_outcome(s, caller, outcome, ic_cdk::api::time())
+    });
+    result
+}
+
 /// Validates caller identity and ensures a fresh price is available.
 /// If the cached ICP price is older than the freshness threshold, triggers
 /// an on-demand XRC fetch before proceeding. This allows the background
@@ -95,6 +187,13 @@ fn check_postcondition<T>(t: T) -> T {
 /// but yields once to the executor; in either case, treat the call as a
 /// suspension boundary.
 async fn validate_call() -> Result<(), ProtocolError> {
+    validate_caller()?;
+    rumi_protocol_backend::xrc::ensure_fresh_price().await
+}
+
+/// `validate_call` without the price refresh, for operations that need no
+/// price (see `collateral_settlement`).
+fn validate_caller() -> Result<(), ProtocolError> {
     if ic_cdk::caller() == Principal::anonymous() {
         return Err(ProtocolError::AnonymousCallerNotAllowed);
     }
@@ -104,18 +203,23 @@ async fn validate_call() -> Result<(), ProtocolError> {
             "Protocol is frozen. All operations are suspended pending admin review.".to_string(),
         ));
     }
-    rumi_protocol_backend::xrc::ensure_fresh_price().await
+    Ok(())
 }
 
 fn validate_mode() -> Result<(), ProtocolError> {
-    match read_state(|s| s.mode) {
-        // Shared constructor keeps this entry-layer gate byte-identical to the
-        // vault-module gates in vault::redeem_collateral / redeem_reserves
-        // (audit RED-101).
-        Mode::ReadOnly => Err(ProtocolError::read_only_mode()),
-        Mode::GeneralAvailability => Ok(()),
-        Mode::Recovery => Ok(()),
-    }
+    // Shared constructor keeps this entry-layer gate byte-identical to the
+    // vault-module gates in vault::redeem_collateral / redeem_reserves
+    // (audit RED-101).
+    read_state(|s| s.mode).check_available()
+}
+
+/// Reject an operation that would queue another outbound transfer while the
+/// matching pending queue is at its threshold (ledger outage backpressure).
+/// Repayments and margin additions never call this.
+fn validate_pending_room(
+    queue: rumi_protocol_backend::pending_backpressure::PayoutQueue,
+) -> Result<(), ProtocolError> {
+    mutate_state(|s| rumi_protocol_backend::pending_backpressure::check_room(s, queue))
 }
 
 /// Validates price freshness for liquidation operations.
@@ -128,14 +232,21 @@ fn validate_price_for_liquidation() -> Result<(), ProtocolError> {
 /// because ReadOnly auto-latches on TCR < 100% and liquidations should remain open
 /// in that state (they reduce bad debt). `liquidation_frozen` is the explicit
 /// admin switch to halt liquidations during a confirmed oracle/dependency outage
-/// where liquidating against the cached price would be unsafe.
+/// where liquidating against the cached price would be unsafe. An emergency
+/// pauser's `Liquidation` pause halts the same paths.
 fn validate_liquidation_not_frozen() -> Result<(), ProtocolError> {
     if read_state(|s| s.liquidation_frozen) {
         return Err(ProtocolError::TemporarilyUnavailable(
             "Liquidations are currently frozen by admin.".to_string(),
         ));
     }
-    Ok(())
+    validate_operation_not_paused(PausableOperation::Liquidation)
+}
+
+/// Reject `operation` while it is paused. Checked alongside `validate_mode`,
+/// not instead of it. See `operation_pauses`.
+fn validate_operation_not_paused(operation: PausableOperation) -> Result<(), ProtocolError> {
+    read_state(|s| rumi_protocol_backend::operation_pauses::check_not_paused(s, operation))
 }
 
 /// Wave-5 LIQ-006: refresh the cached price for a vault's collateral type before
@@ -206,67 +317,32 @@ fn inspect_message() {
     }
 }
 
-// Wave-14b CDP-12 follow-up: track the active TimerId for each of the
-// three CDP-12 timers so the developer-gated setters can clear-and-re-
-// register in place. Transient (not persisted): every upgrade re-runs
-// `setup_timers` which assigns fresh ids.
-thread_local! {
-    static XRC_FETCH_TIMER_ID: std::cell::Cell<Option<ic_cdk_timers::TimerId>> =
-        const { std::cell::Cell::new(None) };
-    static INTEREST_TREASURY_TIMER_ID: std::cell::Cell<Option<ic_cdk_timers::TimerId>> =
-        const { std::cell::Cell::new(None) };
-    static VAULT_CHECK_TIMER_ID: std::cell::Cell<Option<ic_cdk_timers::TimerId>> =
-        const { std::cell::Cell::new(None) };
-    // Phase 1b Task 15: Monad async loops. Same transient (not-persisted)
-    // lifecycle — re-assigned fresh ids by `setup_timers` on every upgrade.
-    static SETTLEMENT_TIMER_ID: std::cell::Cell<Option<ic_cdk_timers::TimerId>> =
-        const { std::cell::Cell::new(None) };
-    static OBSERVER_TIMER_ID: std::cell::Cell<Option<ic_cdk_timers::TimerId>> =
-        const { std::cell::Cell::new(None) };
-    // Task 12: foreign-chain interest harvest. Same transient lifecycle.
-    static CHAIN_INTEREST_TIMER_ID: std::cell::Cell<Option<ic_cdk_timers::TimerId>> =
-        const { std::cell::Cell::new(None) };
-}
-
+// Wave-14b CDP-12 follow-up: the developer-gated setters call these to
+// re-register the affected timer in place; `scheduler::set_interval`
+// replaces the task's previous timer. Timers are transient: every upgrade
+// re-runs `setup_timers`.
 fn register_xrc_fetch_timer() {
     let secs = read_state(|s| s.xrc_fetch_interval_secs);
-    XRC_FETCH_TIMER_ID.with(|cell| {
-        if let Some(old) = cell.get() {
-            ic_cdk_timers::clear_timer(old);
-        }
-        let new_id =
-            ic_cdk_timers::set_timer_interval(std::time::Duration::from_secs(secs), || {
-                ic_cdk::spawn(rumi_protocol_backend::xrc::fetch_icp_rate())
-            });
-        cell.set(Some(new_id));
-    });
+    scheduler::set_interval(
+        ScheduledTask::IcpPriceFetch,
+        secs,
+        rumi_protocol_backend::xrc::scheduled_icp_rate_fetch,
+    );
 }
 
 fn register_interest_treasury_timer() {
     let secs = read_state(|s| s.interest_treasury_tick_interval_secs);
-    INTEREST_TREASURY_TIMER_ID.with(|cell| {
-        if let Some(old) = cell.get() {
-            ic_cdk_timers::clear_timer(old);
-        }
-        let new_id =
-            ic_cdk_timers::set_timer_interval(std::time::Duration::from_secs(secs), || {
-                ic_cdk::spawn(rumi_protocol_backend::xrc::interest_and_treasury_tick())
-            });
-        cell.set(Some(new_id));
+    scheduler::set_interval(ScheduledTask::InterestTreasury, secs, || async {
+        rumi_protocol_backend::xrc::interest_and_treasury_tick().await;
+        Ok(())
     });
 }
 
 fn register_vault_check_timer() {
     let secs = read_state(|s| s.vault_check_tick_interval_secs);
-    VAULT_CHECK_TIMER_ID.with(|cell| {
-        if let Some(old) = cell.get() {
-            ic_cdk_timers::clear_timer(old);
-        }
-        let new_id =
-            ic_cdk_timers::set_timer_interval(std::time::Duration::from_secs(secs), || {
-                ic_cdk::spawn(rumi_protocol_backend::xrc::vault_check_tick())
-            });
-        cell.set(Some(new_id));
+    scheduler::set_interval(ScheduledTask::VaultCheck, secs, || async {
+        rumi_protocol_backend::xrc::vault_check_tick().await;
+        Ok(())
     });
 }
 
@@ -353,15 +429,9 @@ async fn run_all_settlements() {
 fn register_settlement_timer() {
     let secs = read_state(|s| s.settlement_tick_interval_secs);
     let secs = if secs == 0 { 30 } else { secs.max(1) };
-    SETTLEMENT_TIMER_ID.with(|cell| {
-        if let Some(old) = cell.get() {
-            ic_cdk_timers::clear_timer(old);
-        }
-        let new_id =
-            ic_cdk_timers::set_timer_interval(std::time::Duration::from_secs(secs), || {
-                ic_cdk::spawn(run_all_settlements())
-            });
-        cell.set(Some(new_id));
+    scheduler::set_interval(ScheduledTask::ChainSettlement, secs, || async {
+        run_all_settlements().await;
+        Ok(())
     });
 }
 
@@ -372,7 +442,7 @@ fn register_settlement_timer() {
 /// here), so Solana is skipped. No-op in ReadOnly mode. No state borrow is held
 /// across the treasury-address derive `.await`.
 async fn run_all_chain_interest_harvests() {
-    if read_state(|s| s.mode == Mode::ReadOnly) {
+    if !read_state(|s| s.mode.is_available()) {
         return;
     }
     let (chains, _solana_enabled) = registered_chains_and_solana_flag();
@@ -445,15 +515,9 @@ async fn harvest_one_chain_interest(
 fn register_chain_interest_timer() {
     let secs = read_state(|s| s.chain_interest_tick_interval_secs);
     let secs = if secs == 0 { 31_536_000 } else { secs };
-    CHAIN_INTEREST_TIMER_ID.with(|cell| {
-        if let Some(old) = cell.get() {
-            ic_cdk_timers::clear_timer(old);
-        }
-        let new_id =
-            ic_cdk_timers::set_timer_interval(std::time::Duration::from_secs(secs), || {
-                ic_cdk::spawn(run_all_chain_interest_harvests())
-            });
-        cell.set(Some(new_id));
+    scheduler::set_interval(ScheduledTask::ChainInterest, secs, || async {
+        run_all_chain_interest_harvests().await;
+        Ok(())
     });
 }
 
@@ -462,27 +526,38 @@ fn register_chain_interest_timer() {
 fn register_observer_timer() {
     let secs = read_state(|s| s.observer_tick_interval_secs);
     let secs = if secs == 0 { 30 } else { secs.max(1) };
-    OBSERVER_TIMER_ID.with(|cell| {
-        if let Some(old) = cell.get() {
-            ic_cdk_timers::clear_timer(old);
-        }
-        let new_id =
-            ic_cdk_timers::set_timer_interval(std::time::Duration::from_secs(secs), || {
-                ic_cdk::spawn(run_all_observers())
-            });
-        cell.set(Some(new_id));
+    scheduler::set_interval(ScheduledTask::ChainObserver, secs, || async {
+        run_all_observers().await;
+        Ok(())
     });
 }
 
+/// Run the startup self-test once the upgrade's timers are in place. See
+/// `selftest`.
+fn schedule_selftest() {
+    ic_cdk_timers::set_timer(
+        std::time::Duration::from_secs(rumi_protocol_backend::selftest::SELFTEST_DELAY_SECS),
+        || {
+            ic_cdk::spawn(async {
+                if let Err(e) = rumi_protocol_backend::selftest::run().await {
+                    log!(INFO, "[selftest] not run: {:?}", e);
+                }
+            })
+        },
+    );
+}
+
 fn setup_timers() {
     // ── Immediate price fetch (fire on the very next execution round) ───────
     // Prices are ephemeral and not stored as events, so after an upgrade
     // the collateral configs have stale or missing prices.  An immediate
     // fetch ensures CRs are correct within seconds instead of waiting
     // up to 5 minutes for the first interval tick.
-    ic_cdk_timers::set_timer(std::time::Duration::ZERO, || {
-        ic_cdk::spawn(rumi_protocol_backend::xrc::fetch_icp_rate())
-    });
+    scheduler::schedule_once(
+        ScheduledTask::IcpPriceFetch,
+        0,
+        rumi_protocol_backend::xrc::scheduled_icp_rate_fetch,
+    );
     let non_icp_collaterals_immediate: Vec<candid::Principal> = read_state(|s| {
         let icp = s.icp_collateral_type();
         s.collateral_configs
@@ -492,9 +567,14 @@ fn setup_timers() {
             .collect()
     });
     for ledger_id in non_icp_collaterals_immediate {
-        ic_cdk_timers::set_timer(std::time::Duration::ZERO, move || {
-            rumi_protocol_backend::xrc::spawn_collateral_price_fetch_if_needed(ledger_id)
-        });
+        scheduler::schedule_once(
+            ScheduledTask::CollateralPriceFetch(ledger_id),
+            0,
+            move || async move {
+                rumi_protocol_backend::xrc::spawn_collateral_price_fetch_if_needed(ledger_id);
+                Ok(())
+            },
+        );
     }
 
     // ── Wave-14b CDP-12: three independent timers ────────────────────────
@@ -508,6 +588,20 @@ fn setup_timers() {
     register_interest_treasury_timer();
     register_vault_check_timer();
 
+    // Acknowledgements are not persisted, so re-send the current mode to
+    // every companion after install or upgrade.
+    rumi_protocol_backend::mode_propagation::schedule_mode_propagation();
+
+    // Timers do not survive upgrades: resume an unfinished event log
+    // migration.
+    if rumi_protocol_backend::storage::event_log_status().layout
+        == rumi_protocol_backend::storage::EventLogLayout::Migrating
+    {
+        schedule_event_log_migration(rumi_protocol_backend::storage::DEFAULT_EVENT_MIGRATION_BATCH);
+    }
+    // A migration finished before the original log was truncated on switch.
+    rumi_protocol_backend::storage::truncate_retired_event_log();
+
     // Price timers for all non-ICP collateral types (timers don't survive upgrades,
     // so we re-register them here for any collateral added via add_collateral_token).
     // Wave-9d DOS-011: register through `xrc::register_collateral_price_timer` so
@@ -537,11 +631,45 @@ fn setup_timers() {
 
     // ── Hourly protocol snapshot ────────────────────────────────────────────
     // First snapshot fires after 5 seconds (let prices load first).
-    ic_cdk_timers::set_timer(std::time::Duration::from_secs(5), || {
+    scheduler::schedule_once(ScheduledTask::ProtocolSnapshot, 5, || async {
         capture_protocol_snapshot();
+        Ok(())
     });
-    ic_cdk_timers::set_timer_interval(std::time::Duration::from_secs(3600), || {
+    scheduler::set_interval(ScheduledTask::ProtocolSnapshot, 3600, || async {
         capture_protocol_snapshot();
+        Ok(())
+    });
+
+    // ── Supported asset metadata ────────────────────────────────────────────
+    // The cache survives upgrades; refresh shortly after install or upgrade
+    // too so a fresh install does not wait out the first interval.
+    let refresh_asset_metadata = || async {
+        rumi_protocol_backend::asset_registry::refresh_asset_metadata().await;
+        Ok(())
+    };
+    scheduler::schedule_once(
+        ScheduledTask::AssetMetadataRefresh,
+        10,
+        refresh_asset_metadata,
+    );
+    scheduler::set_interval(
+        ScheduledTask::AssetMetadataRefresh,
+        rumi_protocol_backend::asset_registry::ASSET_METADATA_REFRESH_INTERVAL_SECS,
+        refresh_asset_metadata,
+    );
+
+    // ── State checkpoint every 6 hours ──────────────────────────────────────
+    // Bounds the replay of an upgrade that has to skip pre_upgrade to the
+    // events logged since (see `storage::state_checkpoint`).
+    scheduler::set_interval(ScheduledTask::StateCheckpoint, 6 * 3600, || async {
+        let checkpoint = mutate_state(rumi_protocol_backend::storage::save_state_to_stable);
+        log!(
+            INFO,
+            "[state_checkpoint] checkpoint at event {} ({} bytes)",
+            checkpoint.events_covered,
+            checkpoint.size_bytes
+        );
+        Ok(())
     });
 
     // ── Phase 1b Task 15: Monad async loops (Timer D + inbound observer) ─────
@@ -561,6 +689,16 @@ fn setup_timers() {
     // `set_chain_interest_tick_interval_secs`. No-op when no EVM chain is
     // registered, so it is safe to register on staging before any chain exists.
     register_chain_interest_timer();
+
+    // Clears guards stuck past the hard timeout once no transfer of theirs
+    // can still land.
+    rumi_protocol_backend::guard_metrics::setup_resolve_timer();
+
+    // Brings every borrower's time-weighted metrics up to date.
+    rumi_protocol_backend::loyalty::setup_checkpoint_timer();
+
+    // Closes vaults left owing only dust.
+    rumi_protocol_backend::dust_vaults::setup_sweep_timer();
 }
 
 /// M2 anti-spam backstop: hourly GC of stale `AwaitingDeposit` chain vaults
@@ -569,7 +707,7 @@ fn setup_timers() {
 /// Pruning an `AwaitingDeposit` vault is supply-invariant-safe (no confirmed
 /// debt / enqueued mint). Re-registered every upgrade via `setup_timers`.
 fn register_chain_vault_gc_timer() {
-    ic_cdk_timers::set_timer_interval(std::time::Duration::from_secs(3600), || {
+    scheduler::set_interval(ScheduledTask::ChainVaultGc, 3600, || async {
         let now = ic_cdk::api::time();
         let pruned = mutate_state(|s| {
             rumi_protocol_backend::chains::vault::prune_stale_awaiting_deposit(
@@ -585,6 +723,7 @@ fn register_chain_vault_gc_timer() {
                 pruned
             );
         }
+        Ok(())
     });
 }
 
@@ -660,6 +799,7 @@ fn init(arg: ProtocolArg) {
             );
             rumi_protocol_backend::storage::record_event(&Event::Init(init_arg.clone()));
             replace_state(State::from(init_arg));
+            rumi_protocol_backend::logs::mark_instance_start(ic_cdk::api::time());
         }
         ProtocolArg::Upgrade(_) => ic_cdk::trap("expected Init got Upgrade"),
     }
@@ -670,7 +810,17 @@ fn init(arg: ProtocolArg) {
 fn pre_upgrade() {
     use rumi_protocol_backend::storage::save_state_to_stable;
 
-    read_state(|state| {
+    // A bootstrapping state is only partly replayed; keep the snapshot it
+    // is being rebuilt from so the next upgrade starts over from it.
+    if read_state(|state| state.replay_cursor.is_some()) {
+        log!(
+            INFO,
+            "[pre_upgrade]: event replay still running, keeping the existing snapshot"
+        );
+        return;
+    }
+
+    mutate_state(|state| {
         save_state_to_stable(state);
     });
 
@@ -679,10 +829,16 @@ fn pre_upgrade() {
 
 #[post_upgrade]
 fn post_upgrade(arg: ProtocolArg) {
-    use rumi_protocol_backend::event::replay;
-    use rumi_protocol_backend::storage::{count_events, events, record_event};
+    use rumi_protocol_backend::bootstrap;
+    use rumi_protocol_backend::storage::{
+        count_events, events, record_event, replay_range, state_checkpoint,
+    };
 
     let start = ic_cdk::api::instruction_counter();
+    // Critical logs persisted before this point belong to the previous wasm.
+    rumi_protocol_backend::logs::mark_instance_start(ic_cdk::api::time());
+    // The log as the restored snapshot may have seen it, before this upgrade.
+    let log_len = count_events();
 
     // Extract and record the upgrade event
     let upgrade_args = match arg {
@@ -695,17 +851,43 @@ fn post_upgrade(arg: ProtocolArg) {
     };
 
     // Try to restore from stable memory (fast path, no drift)
-    let mut state = match rumi_protocol_backend::storage::load_state_from_stable() {
-        Some(mut state) => {
-            log!(
-                INFO,
-                "[upgrade]: restored state from stable memory (skipped event replay of {} events)",
-                count_events()
-            );
-            // Apply upgrade args to the restored state (the snapshot was taken
-            // before this upgrade event, so we must apply it explicitly)
-            state.upgrade(upgrade_args);
-            state
+    let state = match rumi_protocol_backend::storage::load_state_from_stable() {
+        Some(state) => {
+            // A checkpoint taken before the last events (pre_upgrade was
+            // skipped): replay just those.
+            let tail = replay_range(state_checkpoint().as_ref(), log_len);
+            if tail.is_empty() {
+                log!(
+                    INFO,
+                    "[upgrade]: restored state from stable memory (skipped event replay of {} events)",
+                    log_len
+                );
+            } else {
+                // Chain events replay as no-ops, so their effects on
+                // `multi_chain` would be lost (see `load_state_from_stable`).
+                if events()
+                    .skip(tail.start as usize)
+                    .take((tail.end - tail.start) as usize)
+                    .any(|event| event.is_chain_observation())
+                {
+                    ic_cdk::trap(&format!(
+                        "[upgrade] ABORT: the State checkpoint covers {} of {} events and chain \
+                         events follow it, which replay cannot apply. Upgrade with pre_upgrade \
+                         or take a fresh checkpoint first.",
+                        tail.start, log_len
+                    ));
+                }
+                log!(
+                    INFO,
+                    "[upgrade]: restored state checkpoint at event {}, replaying {} later events",
+                    tail.start,
+                    tail.end - tail.start
+                );
+                assert_replay_order(tail.clone());
+            }
+            // The snapshot was taken before this upgrade event, so the
+            // upgrade args are applied explicitly once the tail is in.
+            bootstrap::begin(state, tail.start, tail.end, Some(upgrade_args))
         }
         None => {
             // Fallback: replay events (first upgrade after this change, or recovery)
@@ -714,7 +896,8 @@ fn post_upgrade(arg: ProtocolArg) {
                 "[upgrade]: no stable state found, replaying {} events",
                 count_events()
             );
-            replay(events()).unwrap_or_else(|e| {
+            assert_replay_order(0..count_events());
+            bootstrap::begin_from_init(events(), count_events()).unwrap_or_else(|e| {
                 ic_cdk::trap(&format!(
                     "[upgrade]: failed to replay the event log: {:?}",
                     e
@@ -722,6 +905,76 @@ fn post_upgrade(arg: ProtocolArg) {
             })
         }
     };
+    let state = bootstrap::resume(state, || {
+        ic_cdk::api::instruction_counter() > bootstrap::POST_UPGRADE_REPLAY_INSTRUCTIONS
+    });
+    if let Some(cursor) = &state.replay_cursor {
+        log!(
+            INFO,
+            "[upgrade]: replayed up to event {} of {} within the instruction budget, \
+             bootstrapping the rest from a timer",
+            cursor.next_event,
+            cursor.end_event
+        );
+        replace_state(state);
+        schedule_replay_resume();
+        return;
+    }
+    finish_upgrade(state, start);
+}
+
+/// Trap the upgrade unless the causality tokens of the events about to be
+/// replayed follow the log order (see `event_ordering`).
+fn assert_replay_order(range: std::ops::Range<u64>) {
+    match rumi_protocol_backend::storage::check_event_order(range.clone()) {
+        Ok(summary) => log!(
+            INFO,
+            "[upgrade]: event order checked for events {}..{}: {} tokens, {} recorded after an interleaving (max lag {})",
+            range.start,
+            range.end,
+            summary.checked,
+            summary.interleaved,
+            summary.max_lag
+        ),
+        Err(e) => ic_cdk::trap(&format!(
+            "[upgrade] ABORT: the events to replay are out of order: {:?}",
+            e
+        )),
+    }
+}
+
+/// Continue a replay `post_upgrade` could not finish, one instruction
+/// budget per timer tick, then finish the upgrade (see `bootstrap`).
+fn schedule_replay_resume() {
+    ic_cdk_timers::set_timer(std::time::Duration::ZERO, || {
+        use rumi_protocol_backend::bootstrap;
+        let start = ic_cdk::api::instruction_counter();
+        let state = bootstrap::resume(rumi_protocol_backend::state::take_state(), || {
+            ic_cdk::api::instruction_counter() > bootstrap::REPLAY_TICK_INSTRUCTIONS
+        });
+        match &state.replay_cursor {
+            Some(cursor) => {
+                log!(
+                    INFO,
+                    "[bootstrap]: replayed up to event {} of {}",
+                    cursor.next_event,
+                    cursor.end_event
+                );
+                replace_state(state);
+                schedule_replay_resume();
+            }
+            None => {
+                log!(INFO, "[bootstrap]: event replay complete");
+                finish_upgrade(state, start);
+            }
+        }
+    });
+}
+
+/// The rest of an upgrade once the event replay is complete: migrations,
+/// index rebuilds and the timers. Runs at the end of `post_upgrade`, or from
+/// the replay timer if the replay outlived it.
+fn finish_upgrade(mut state: State, start: u64) {
     let xrp_guardrail_migration =
         rumi_protocol_backend::state::enforce_xrp_launch_guardrails(&mut state);
     if let Some(previous) = xrp_guardrail_migration.previous_status {
@@ -736,6 +989,8 @@ fn post_upgrade(arg: ProtocolArg) {
     validate_collateral_state(&state);
 
     replace_state(state);
+    read_state(|s| rumi_protocol_backend::logs::apply_retention(&s.log_retention));
+    mutate_state(rumi_protocol_backend::event::record_mode_transitions);
 
     // Migration: set last_accrual_time for any existing vaults that have it at 0.
     // This avoids a massive retroactive accrual on first tick.
@@ -874,7 +1129,8 @@ fn post_upgrade(arg: ProtocolArg) {
     // of the on-disk snapshot to avoid a state-format migration), so it is
     // empty after `replace_state(state)`. Walking the surviving vaults and
     // re-keying each one converges the index to the post-upgrade CR
-    // distribution. O(N log N) one-shot. Empty for fresh installs.
+    // distribution. O(N log N) one-shot. Empty for fresh installs. The same
+    // walk rebuilds `liquidation_index`.
     let reindexed = mutate_state(|s| {
         let vault_ids: Vec<u64> = s.vault_id_to_vaults.keys().copied().collect();
         let count = vault_ids.len();
@@ -947,7 +1203,11 @@ fn post_upgrade(arg: ProtocolArg) {
         chain_vaults
     );
 
+    // Certified data is cleared by an upgrade.
+    rumi_protocol_backend::icrc3_log::certify_tip();
+
     setup_timers();
+    schedule_selftest();
 }
 
 /// Validates that the State has consistent collateral configuration after replay.
@@ -1303,7 +1563,7 @@ fn set_chain_config(
     chain_id: rumi_protocol_backend::chains::config::ChainId,
     update: rumi_protocol_backend::chains::config::UpdateChainConfigArg,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_chain_config")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::ChainAdmin("not developer".into()));
@@ -2048,7 +2308,7 @@ fn set_chain_contract(
     chain: rumi_protocol_backend::chains::config::ChainId,
     address: String,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_chain_contract")?;
     if read_state(|s| s.developer_principal != caller) {
         return Err(ProtocolError::ChainAdmin("not developer".into()));
     }
@@ -2189,7 +2449,7 @@ async fn set_chain_liquidation_config(
     chain: rumi_protocol_backend::chains::config::ChainId,
     config: rumi_protocol_backend::chains::liquidation_config::ChainLiquidationConfigV1,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_chain_liquidation_config")?;
     if read_state(|s| s.developer_principal != caller) {
         return Err(ProtocolError::ChainAdmin("not developer".into()));
     }
@@ -2255,7 +2515,7 @@ fn set_chain_debt_config(
     chain: rumi_protocol_backend::chains::config::ChainId,
     config: rumi_protocol_backend::chains::collateral_config::ChainDebtConfigV1,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_chain_debt_config")?;
     if read_state(|s| s.developer_principal != caller) {
         return Err(ProtocolError::ChainAdmin("not developer".into()));
     }
@@ -2343,7 +2603,7 @@ fn set_chain_bad_debt_circuit_threshold(
     chain: rumi_protocol_backend::chains::config::ChainId,
     threshold_e8s: Option<u128>,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_chain_bad_debt_circuit_threshold")?;
     if read_state(|s| s.developer_principal != caller) {
         return Err(ProtocolError::ChainAdmin("not developer".into()));
     }
@@ -2836,7 +3096,7 @@ fn set_price_pusher_principal(
     principal: Option<Principal>,
     allowed: Vec<(u32, String)>,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_price_pusher_principal")?;
     if read_state(|s| s.developer_principal != caller) {
         return Err(ProtocolError::ChainAdmin("not developer".into()));
     }
@@ -2882,7 +3142,7 @@ fn get_price_pusher_allowed() -> Vec<(u32, String)> {
 #[candid_method(update)]
 #[update]
 fn set_evm_rpc_principal(principal: candid::Principal) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_evm_rpc_principal")?;
     if read_state(|s| s.developer_principal != caller) {
         return Err(ProtocolError::ChainAdmin("not developer".into()));
     }
@@ -4120,6 +4380,208 @@ fn get_event_count() -> u64 {
     rumi_protocol_backend::storage::count_events()
 }
 
+/// Encoding and migration progress of the event log.
+#[candid_method(query)]
+#[query]
+fn get_event_log_status() -> rumi_protocol_backend::storage::EventLogStatus {
+    rumi_protocol_backend::storage::event_log_status()
+}
+
+/// Start re-encoding the event log into the compact log, `batch_size`
+/// events per timer tick (developer only). Replay and `get_events` keep
+/// working throughout; the compact log goes live once it has caught up.
+#[candid_method(update)]
+#[update]
+async fn start_event_log_migration(
+    batch_size: Option<u64>,
+) -> Result<rumi_protocol_backend::storage::EventLogStatus, ProtocolError> {
+    use rumi_protocol_backend::storage::{
+        start_event_log_migration, EventLogLayout, DEFAULT_EVENT_MIGRATION_BATCH,
+        MAX_EVENT_MIGRATION_BATCH,
+    };
+    let caller = ic_cdk::caller();
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can start the event log migration".to_string(),
+        ));
+    }
+    let batch_size = batch_size.unwrap_or(DEFAULT_EVENT_MIGRATION_BATCH);
+    if batch_size == 0 || batch_size > MAX_EVENT_MIGRATION_BATCH {
+        return Err(ProtocolError::GenericError(format!(
+            "batch_size must be between 1 and {}",
+            MAX_EVENT_MIGRATION_BATCH
+        )));
+    }
+    let status = rumi_protocol_backend::storage::event_log_status();
+    if status.layout != EventLogLayout::Legacy {
+        return Err(ProtocolError::GenericError(format!(
+            "Event log migration already {:?}",
+            status.layout
+        )));
+    }
+    start_event_log_migration();
+    schedule_event_log_migration(batch_size);
+    log!(
+        INFO,
+        "[start_event_log_migration] migrating {} events, batch={}",
+        status.total_events,
+        batch_size
+    );
+    Ok(rumi_protocol_backend::storage::event_log_status())
+}
+
+/// The `State` checkpoint an upgrade restores from, and how many events it
+/// would replay on top of it.
+#[candid_method(query)]
+#[query]
+fn get_state_checkpoint_status() -> rumi_protocol_backend::storage::StateCheckpointStatus {
+    rumi_protocol_backend::storage::state_checkpoint_status()
+}
+
+/// Vault counts on the heap and in the stable vault store, and what the last
+/// checkpoint's sync wrote.
+#[candid_method(query)]
+#[query]
+fn get_vault_store_status() -> rumi_protocol_backend::vault_store::VaultStoreStatus {
+    read_state(rumi_protocol_backend::storage::vault_store_status)
+}
+
+/// How far an upgrade's event replay has got while the protocol is
+/// bootstrapping; `None` once it is complete.
+#[candid_method(query)]
+#[query]
+fn get_replay_status() -> Option<rumi_protocol_backend::bootstrap::ReplayCursor> {
+    read_state(|s| s.replay_cursor.clone())
+}
+
+/// Write a `State` checkpoint now (developer only), e.g. right before an
+/// upgrade that will have to skip `pre_upgrade`.
+#[candid_method(update)]
+#[update]
+fn take_state_checkpoint() -> Result<rumi_protocol_backend::storage::StateCheckpoint, ProtocolError>
+{
+    let caller = ic_cdk::caller();
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can take a state checkpoint".to_string(),
+        ));
+    }
+    if read_state(|s| s.replay_cursor.is_some()) {
+        return Err(ProtocolError::bootstrapping());
+    }
+    let checkpoint = mutate_state(rumi_protocol_backend::storage::save_state_to_stable);
+    log!(
+        INFO,
+        "[take_state_checkpoint] checkpoint at event {} ({} bytes)",
+        checkpoint.events_covered,
+        checkpoint.size_bytes
+    );
+    Ok(checkpoint)
+}
+
+/// Work on `job_id` for at most `max_instructions` (see
+/// `batch_jobs::step_budget`), recording the snapshot a completed
+/// `ProtocolSnapshot` job yields.
+fn run_batch_job(
+    job_id: u64,
+    max_instructions: Option<u64>,
+) -> Result<rumi_protocol_backend::batch_jobs::BatchJob, ProtocolError> {
+    use rumi_protocol_backend::batch_jobs;
+    if read_state(|s| s.replay_cursor.is_some()) {
+        return Err(ProtocolError::bootstrapping());
+    }
+    let budget = batch_jobs::step_budget(max_instructions);
+    let job = mutate_state(|s| {
+        batch_jobs::step(s, job_id, ic_cdk::api::time(), || {
+            ic_cdk::api::instruction_counter() > budget
+        })
+    })?;
+    if let Some(snapshot) = &job.snapshot {
+        rumi_protocol_backend::storage::record_snapshot(snapshot);
+    }
+    Ok(job)
+}
+
+fn check_batch_job_caller() -> Result<(), ProtocolError> {
+    let caller = ic_cdk::caller();
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can run batch jobs".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Start an admin batch job and run its first call (developer only). A
+/// job still running when the budget is spent is carried on with
+/// `continue_batch_job`.
+#[candid_method(update)]
+#[update]
+fn start_batch_job(
+    kind: rumi_protocol_backend::batch_jobs::BatchJobKind,
+    max_instructions: Option<u64>,
+) -> Result<rumi_protocol_backend::batch_jobs::BatchJob, ProtocolError> {
+    check_batch_job_caller()?;
+    if read_state(|s| s.replay_cursor.is_some()) {
+        return Err(ProtocolError::bootstrapping());
+    }
+    let job_id =
+        mutate_state(|s| rumi_protocol_backend::batch_jobs::start(s, kind, ic_cdk::api::time()))?;
+    run_batch_job(job_id, max_instructions)
+}
+
+/// Carry on a running batch job from its cursor (developer only).
+#[candid_method(update)]
+#[update]
+fn continue_batch_job(
+    job_id: u64,
+    max_instructions: Option<u64>,
+) -> Result<rumi_protocol_backend::batch_jobs::BatchJob, ProtocolError> {
+    check_batch_job_caller()?;
+    run_batch_job(job_id, max_instructions)
+}
+
+/// Stop a running batch job where it stands (developer only).
+#[candid_method(update)]
+#[update]
+fn cancel_batch_job(
+    job_id: u64,
+) -> Result<rumi_protocol_backend::batch_jobs::BatchJob, ProtocolError> {
+    check_batch_job_caller()?;
+    mutate_state(|s| rumi_protocol_backend::batch_jobs::cancel(s, job_id, ic_cdk::api::time()))
+}
+
+#[candid_method(query)]
+#[query]
+fn get_batch_job(job_id: u64) -> Option<rumi_protocol_backend::batch_jobs::BatchJob> {
+    read_state(|s| s.batch_jobs.get(&job_id).cloned())
+}
+
+/// Running and recently finished batch jobs, newest first.
+#[candid_method(query)]
+#[query]
+fn get_batch_jobs() -> Vec<rumi_protocol_backend::batch_jobs::BatchJob> {
+    read_state(rumi_protocol_backend::batch_jobs::jobs)
+}
+
+fn schedule_event_log_migration(batch_size: u64) {
+    ic_cdk_timers::set_timer(std::time::Duration::from_secs(1), move || {
+        use rumi_protocol_backend::storage::{migrate_events_batch, EventLogLayout};
+        let status = migrate_events_batch(batch_size);
+        if status.layout == EventLogLayout::Migrating {
+            schedule_event_log_migration(batch_size);
+        } else {
+            log!(
+                INFO,
+                "[event_log_migration] compact log live with {} events",
+                status.total_events
+            );
+        }
+    });
+}
+
 /// Recording-time timestamp for `length` consecutive events starting at
 /// `start`. Slots past the end of the side log come back as `0`; the
 /// frontend uses these to fill in a real time on admin/upgrade rows whose
@@ -4141,6 +4603,23 @@ fn get_event_timestamps(start: u64, length: u64) -> Vec<u64> {
     rumi_protocol_backend::storage::get_event_timestamps(start, length.min(MAX))
 }
 
+/// Causality tokens of `length` consecutive events starting at `start`
+/// (see `event_ordering`), `None` for events recorded before tokens
+/// shipped. A token whose `observed_seq` trails its `seq` marks an event
+/// recorded after other flows' events landed on the state it read.
+#[candid_method(query)]
+#[query]
+fn get_event_causality(
+    start: u64,
+    length: u64,
+) -> Vec<Option<rumi_protocol_backend::event_ordering::CausalityToken>> {
+    if ic_cdk::api::data_certificate().is_none() {
+        ic_cdk::trap("update call rejected");
+    }
+    const MAX: u64 = 10_000;
+    rumi_protocol_backend::storage::get_event_causality(start, length.min(MAX))
+}
+
 /// Server-side filtered event query, paginated newest-first.
 /// `start` is the page number (0-indexed) into the *filtered* result set;
 /// `length` is page size (capped at `MAX_PAGE_SIZE`).
@@ -4451,13 +4930,92 @@ fn get_events_by_principal_paged(
     }
 }
 
+/// Everything support needs to triage a user's failed operation in one
+/// reply: the guard they hold now, their guard history and operation names,
+/// the log lines naming them or one of their traces, the events involving
+/// them and their pending payouts, all within `time_range` except the
+/// pending entries (developer or controllers only). Guard history and most
+/// log lines reach back only to the last upgrade; see `forensics`.
 #[candid_method(query)]
 #[query]
-fn get_protocol_snapshots(args: GetSnapshotsArg) -> Vec<ProtocolSnapshot> {
+fn get_operation_forensics(
+    principal: Principal,
+    time_range: rumi_protocol_backend::EventTimeRange,
+) -> Result<rumi_protocol_backend::forensics::OperationForensics, ProtocolError> {
     if ic_cdk::api::data_certificate().is_none() {
         ic_cdk::trap("update call rejected");
     }
-    const MAX_SNAPSHOTS_PER_QUERY: usize = 2000;
+    let caller = ic_cdk::caller();
+    if read_state(|s| s.developer_principal != caller) && !ic_cdk::api::is_controller(&caller) {
+        return Err(ProtocolError::CallerNotOwner);
+    }
+    rumi_protocol_backend::forensics::validate_time_range(&time_range)
+        .map_err(ProtocolError::GenericError)?;
+
+    let guard_history = rumi_protocol_backend::guard::guard_history(
+        principal,
+        time_range.start_ns,
+        time_range.end_ns,
+    );
+    let mut logs = rumi_protocol_backend::logs::Log::default();
+    logs.push_all();
+    logs.push_persisted_critical(rumi_protocol_backend::logs::instance_started_at());
+    read_state(|s| {
+        rumi_protocol_backend::forensics::operation_forensics(
+            s,
+            principal,
+            time_range,
+            guard_history,
+            events().enumerate().map(|(idx, event)| (idx as u64, event)),
+            logs.entries,
+        )
+    })
+}
+
+/// Guard age histogram and outcome counts of `principal`, or of all
+/// principals added up. See `guard_metrics`.
+#[candid_method(query)]
+#[query]
+fn get_guard_metrics(
+    principal: Option<Principal>,
+) -> rumi_protocol_backend::guard_metrics::GuardMetrics {
+    read_state(|s| match principal {
+        Some(principal) => s.guard_metrics.get(&principal).cloned().unwrap_or_default(),
+        None => rumi_protocol_backend::guard_metrics::totals(s),
+    })
+}
+
+/// Time-weighted debt and collateral of `principal` (default: the caller)
+/// as of now. See `loyalty`.
+#[candid_method(query)]
+#[query]
+fn get_loyalty_metrics(
+    principal: Option<Principal>,
+) -> Option<rumi_protocol_backend::loyalty::LoyaltyView> {
+    let principal = principal.unwrap_or_else(ic_cdk::caller);
+    let now = ic_cdk::api::time();
+    read_state(|s| rumi_protocol_backend::loyalty::view(s, &principal, now))
+}
+
+/// Every tracked principal's metrics, in pages of up to
+/// `MAX_LOYALTY_PAGE`, for export.
+#[candid_method(query)]
+#[query]
+fn get_loyalty_metrics_page(
+    start_after: Option<Principal>,
+    limit: u64,
+) -> rumi_protocol_backend::loyalty::LoyaltyPage {
+    let now = ic_cdk::api::time();
+    read_state(|s| rumi_protocol_backend::loyalty::page(s, start_after, limit as usize, now))
+}
+
+#[candid_method(query)]
+#[query]
+fn get_protocol_snapshots(args: GetSnapshotsArg) -> Vec<ProtocolSnapshot> {
+    if ic_cdk::api::data_certificate().is_none() {
+        ic_cdk::trap("update call rejected");
+    }
+    const MAX_SNAPSHOTS_PER_QUERY: usize = 2000;
 
     rumi_protocol_backend::storage::snapshots()
         .skip(args.start as usize)
@@ -4510,8 +5068,8 @@ fn get_vaults(target: Option<Principal>) -> Vec<CandidVault> {
             Some(vault_ids) => vault_ids
                 .iter()
                 .map(|id| {
-                    let vault = s.vault_id_to_vaults.get(id).cloned().unwrap();
-                    CandidVault::from(vault)
+                    let vault = s.vault_id_to_vaults.get(id).unwrap();
+                    candid_vault(s, vault)
                 })
                 .collect(),
             None => vec![],
@@ -4520,8 +5078,7 @@ fn get_vaults(target: Option<Principal>) -> Vec<CandidVault> {
             s.vault_id_to_vaults
                 .values()
                 .take(MAX_VAULTS_LEGACY_PAGE)
-                .cloned()
-                .map(CandidVault::from)
+                .map(|vault| candid_vault(s, vault))
                 .collect::<Vec<CandidVault>>()
         }),
     }
@@ -4545,7 +5102,7 @@ fn get_vaults_page(start_id: u64, limit: u64) -> VaultsPageResponse {
         let mut iter = s.vault_id_to_vaults.range(start_id..);
         let mut vaults = Vec::with_capacity(limit);
         for (_, vault) in iter.by_ref().take(limit) {
-            vaults.push(CandidVault::from(vault.clone()));
+            vaults.push(candid_vault(s, vault));
         }
         let next_start_id = iter.next().map(|(id, _)| *id);
         VaultsPageResponse {
@@ -4568,14 +5125,19 @@ fn get_vault_count() -> u64 {
 #[candid_method(update)]
 #[update]
 async fn redeem_icp(icusd_amount: u64) -> Result<SuccessWithFee, ProtocolError> {
-    validate_call().await?;
-    // Wave-9 RED-003 / RED-101: gate the ICP redemption path on protocol mode,
-    // matching redeem_collateral. This endpoint was the RED-003 fix's blind spot
-    // (it reaches the same collateral-seizing path via vault::redeem_icp ->
-    // vault::redeem_collateral). Defense in depth alongside the shared
-    // vault-module gate now in vault::redeem_collateral.
-    validate_mode()?;
-    check_postcondition(rumi_protocol_backend::vault::redeem_icp(icusd_amount).await)
+    slo_tracked("redeem_icp", async move {
+        validate_call().await?;
+        // Wave-9 RED-003 / RED-101: gate the ICP redemption path on protocol mode,
+        // matching redeem_collateral. This endpoint was the RED-003 fix's blind spot
+        // (it reaches the same collateral-seizing path via vault::redeem_icp ->
+        // vault::redeem_collateral). Defense in depth alongside the shared
+        // vault-module gate now in vault::redeem_collateral.
+        validate_mode()?;
+        validate_operation_not_paused(PausableOperation::Redemption)?;
+        validate_pending_room(PayoutQueue::Redemption)?;
+        check_postcondition(traced(rumi_protocol_backend::vault::redeem_icp(icusd_amount)).await)
+    })
+    .await
 }
 
 /// Generic collateral redemption: burn icUSD and receive any collateral type.
@@ -4586,24 +5148,56 @@ async fn redeem_collateral(
     collateral_type: Principal,
     icusd_amount: u64,
 ) -> Result<SuccessWithFee, ProtocolError> {
-    validate_call().await?;
-    // Wave-9 RED-003: gate redemption on protocol mode. ReadOnly auto-latches
-    // when total collateral ratio drops below 100% (Wave-1) or when the
-    // deficit account crosses the configured threshold (Wave-8e LIQ-005);
-    // both are insolvency signals where further redemption would deepen the
-    // bad-debt position by extracting collateral from a protocol that
-    // already owes more than it holds.
-    validate_mode()?;
-    // Wave-5 RED-001: validate_call only refreshes ICP. For non-ICP collaterals
-    // (BOB, EXE, ckBTC, ckETH, ckXAUT, nICP) the redeemer would otherwise pay
-    // out at whatever last_price is cached, which could be hours stale if the
-    // background timer for that asset has been failing. ensure_fresh_price_for
-    // delegates to ensure_fresh_price for ICP (already handled), so this is
-    // safe to call unconditionally.
-    rumi_protocol_backend::xrc::ensure_fresh_price_for(&collateral_type).await?;
-    check_postcondition(
-        rumi_protocol_backend::vault::redeem_collateral(collateral_type, icusd_amount).await,
-    )
+    slo_tracked("redeem_collateral", async move {
+        validate_call().await?;
+        // Wave-9 RED-003: gate redemption on protocol mode. ReadOnly auto-latches
+        // when total collateral ratio drops below 100% (Wave-1) or when the
+        // deficit account crosses the configured threshold (Wave-8e LIQ-005);
+        // both are insolvency signals where further redemption would deepen the
+        // bad-debt position by extracting collateral from a protocol that
+        // already owes more than it holds.
+        validate_mode()?;
+        validate_operation_not_paused(PausableOperation::Redemption)?;
+        validate_pending_room(PayoutQueue::Redemption)?;
+        // Wave-5 RED-001: validate_call only refreshes ICP. For non-ICP collaterals
+        // (BOB, EXE, ckBTC, ckETH, ckXAUT, nICP) the redeemer would otherwise pay
+        // out at whatever last_price is cached, which could be hours stale if the
+        // background timer for that asset has been failing. ensure_fresh_price_for
+        // delegates to ensure_fresh_price for ICP (already handled), so this is
+        // safe to call unconditionally.
+        rumi_protocol_backend::xrc::ensure_fresh_price_for(&collateral_type).await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::redeem_collateral(
+                collateral_type,
+                icusd_amount,
+            ))
+            .await,
+        )
+    })
+    .await
+}
+
+/// Cancel the caller's redemption whose collateral payout keeps failing:
+/// the collateral goes back to the redeemed vaults and the icUSD they were
+/// credited with is minted back, less the redemption fee.
+#[candid_method(update)]
+#[update]
+async fn cancel_pending_redemption(
+    icusd_block_index: u64,
+) -> Result<rumi_protocol_backend::redemption_cancel::RedemptionCancelSuccess, ProtocolError> {
+    slo_tracked("cancel_pending_redemption", async move {
+        validate_call().await?;
+        validate_mode()?;
+        check_postcondition(
+            traced(
+                rumi_protocol_backend::redemption_cancel::cancel_pending_redemption(
+                    icusd_block_index,
+                ),
+            )
+            .await,
+        )
+    })
+    .await
 }
 
 #[candid_method(query)]
@@ -4621,11 +5215,26 @@ fn get_redemption_rate() -> f64 {
 async fn open_vault(
     collateral_amount: u64,
     collateral_type: Option<Principal>,
+    operation_id: Option<Vec<u8>>,
 ) -> Result<OpenVaultSuccess, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(
-        rumi_protocol_backend::vault::open_vault(collateral_amount, collateral_type).await,
-    )
+    let request = OperationRequest::OpenVault {
+        collateral_amount,
+        collateral_type,
+    };
+    slo_tracked("open_vault", async move {
+        deduplicated(operation_id, request, async move {
+            validate_call().await?;
+            check_postcondition(
+                traced(rumi_protocol_backend::vault::open_vault(
+                    collateral_amount,
+                    collateral_type,
+                ))
+                .await,
+            )
+        })
+        .await
+    })
+    .await
 }
 
 /// Compound open vault + borrow in a single canister call.
@@ -4637,50 +5246,119 @@ async fn open_vault_and_borrow(
     borrow_amount: u64,
     collateral_type: Option<Principal>,
 ) -> Result<OpenVaultSuccess, ProtocolError> {
-    validate_call().await?;
-    validate_mode()?;
-    // ORACLE-001: refresh the (possibly non-ICP) collateral price before minting.
-    validate_freshness_for_collateral(collateral_type).await?;
-    check_postcondition(
-        rumi_protocol_backend::vault::open_vault_and_borrow(
-            collateral_amount,
-            borrow_amount,
-            collateral_type,
+    slo_tracked("open_vault_and_borrow", async move {
+        validate_call().await?;
+        validate_mode()?;
+        if borrow_amount > 0 {
+            validate_operation_not_paused(PausableOperation::Borrow)?;
+        }
+        // ORACLE-001: refresh the (possibly non-ICP) collateral price before minting.
+        validate_freshness_for_collateral(collateral_type).await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::open_vault_and_borrow(
+                collateral_amount,
+                borrow_amount,
+                collateral_type,
+            ))
+            .await,
         )
-        .await,
-    )
+    })
+    .await
 }
 
 #[candid_method(update)]
 #[update]
-async fn borrow_from_vault(arg: VaultArg) -> Result<SuccessWithFee, ProtocolError> {
-    validate_call().await?;
-    validate_mode()?;
-    // ORACLE-001: refresh this vault's collateral price before minting more debt.
-    validate_freshness_for_vault(arg.vault_id).await?;
-    check_postcondition(rumi_protocol_backend::vault::borrow_from_vault(arg).await)
+async fn borrow_from_vault(
+    arg: VaultArg,
+    operation_id: Option<Vec<u8>>,
+) -> Result<SuccessWithFee, ProtocolError> {
+    let request = OperationRequest::Borrow {
+        vault_id: arg.vault_id,
+        amount: arg.amount,
+    };
+    slo_tracked("borrow_from_vault", async move {
+        deduplicated(operation_id, request, async move {
+            validate_call().await?;
+            validate_mode()?;
+            validate_operation_not_paused(PausableOperation::Borrow)?;
+            // ORACLE-001: refresh this vault's collateral price before minting more debt.
+            validate_freshness_for_vault(arg.vault_id).await?;
+            check_postcondition(traced(rumi_protocol_backend::vault::borrow_from_vault(arg)).await)
+        })
+        .await
+    })
+    .await
 }
 
+/// Borrow from a vault and receive ckUSDT or ckUSDC from the reserves, 1:1
+/// less the reserve redemption fee
 #[candid_method(update)]
 #[update]
-async fn repay_to_vault(arg: VaultArg) -> Result<u64, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::vault::repay_to_vault(arg).await)
+async fn borrow_from_vault_as_stable(
+    arg: VaultArg,
+    token_type: StableTokenType,
+) -> Result<SuccessWithFee, ProtocolError> {
+    slo_tracked("borrow_from_vault_as_stable", async move {
+        validate_call().await?;
+        validate_mode()?;
+        validate_operation_not_paused(PausableOperation::Borrow)?;
+        validate_operation_not_paused(PausableOperation::ReserveRedemption)?;
+        // ORACLE-001: refresh this vault's collateral price before minting more debt.
+        validate_freshness_for_vault(arg.vault_id).await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::borrow_from_vault_as_stable(
+                arg, token_type,
+            ))
+            .await,
+        )
+    })
+    .await
+}
+
+#[candid_method(update)]
+#[update]
+async fn repay_to_vault(
+    arg: VaultArg,
+    operation_id: Option<Vec<u8>>,
+) -> Result<u64, ProtocolError> {
+    let request = OperationRequest::Repay {
+        vault_id: arg.vault_id,
+        amount: arg.amount,
+    };
+    slo_tracked("repay_to_vault", async move {
+        deduplicated(operation_id, request, async move {
+            validate_call().await?;
+            check_postcondition(traced(rumi_protocol_backend::vault::repay_to_vault(arg)).await)
+        })
+        .await
+    })
+    .await
 }
 
 /// Repay vault debt using ckUSDT or ckUSDC (1:1 with icUSD)
 #[candid_method(update)]
 #[update]
 async fn repay_to_vault_with_stable(arg: VaultArgWithToken) -> Result<u64, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::vault::repay_to_vault_with_stable(arg).await)
+    slo_tracked("repay_to_vault_with_stable", async move {
+        validate_call().await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::repay_to_vault_with_stable(
+                arg,
+            ))
+            .await,
+        )
+    })
+    .await
 }
 
 #[candid_method(update)]
 #[update]
 async fn add_margin_to_vault(arg: VaultArg) -> Result<u64, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::vault::add_margin_to_vault(arg).await)
+    slo_tracked("add_margin_to_vault", async move {
+        validate_call().await?;
+        check_postcondition(traced(rumi_protocol_backend::vault::add_margin_to_vault(arg)).await)
+    })
+    .await
 }
 
 // ─── Push-deposit endpoints (Oisy wallet integration) ───
@@ -4704,13 +5382,23 @@ async fn open_vault_with_deposit(
     borrow_amount: u64,
     collateral_type: Option<Principal>,
 ) -> Result<OpenVaultSuccess, ProtocolError> {
-    validate_call().await?;
-    validate_mode()?;
-    // ORACLE-001: refresh the (possibly non-ICP) collateral price before minting.
-    validate_freshness_for_collateral(collateral_type).await?;
-    check_postcondition(
-        rumi_protocol_backend::vault::open_vault_with_deposit(borrow_amount, collateral_type).await,
-    )
+    slo_tracked("open_vault_with_deposit", async move {
+        validate_call().await?;
+        validate_mode()?;
+        if borrow_amount > 0 {
+            validate_operation_not_paused(PausableOperation::Borrow)?;
+        }
+        // ORACLE-001: refresh the (possibly non-ICP) collateral price before minting.
+        validate_freshness_for_collateral(collateral_type).await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::open_vault_with_deposit(
+                borrow_amount,
+                collateral_type,
+            ))
+            .await,
+        )
+    })
+    .await
 }
 
 /// Add margin to a vault using funds already deposited to the caller's deposit account.
@@ -4718,25 +5406,50 @@ async fn open_vault_with_deposit(
 #[candid_method(update)]
 #[update]
 async fn add_margin_with_deposit(vault_id: u64) -> Result<u64, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::vault::add_margin_with_deposit(vault_id).await)
+    slo_tracked("add_margin_with_deposit", async move {
+        validate_call().await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::add_margin_with_deposit(
+                vault_id,
+            ))
+            .await,
+        )
+    })
+    .await
 }
 
 #[candid_method(update)]
 #[update]
-async fn close_vault(vault_id: u64) -> Result<Option<u64>, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::vault::close_vault(vault_id).await)
+async fn close_vault(
+    vault_id: u64,
+    operation_id: Option<Vec<u8>>,
+) -> Result<Option<u64>, ProtocolError> {
+    let request = OperationRequest::Close { vault_id };
+    slo_tracked("close_vault", async move {
+        deduplicated(operation_id, request, async move {
+            validate_call().await?;
+            validate_pending_room(PayoutQueue::Collateral)?;
+            check_postcondition(traced(rumi_protocol_backend::vault::close_vault(vault_id)).await)
+        })
+        .await
+    })
+    .await
 }
 
 // Add the new withdraw collateral endpoint
 #[candid_method(update)]
 #[update]
 async fn withdraw_collateral(vault_id: u64) -> Result<u64, ProtocolError> {
-    validate_call().await?;
-    // ORACLE-001: refresh this vault's collateral price before releasing collateral.
-    validate_freshness_for_vault(vault_id).await?;
-    check_postcondition(rumi_protocol_backend::vault::withdraw_collateral(vault_id).await)
+    slo_tracked("withdraw_collateral", async move {
+        validate_call().await?;
+        validate_pending_room(PayoutQueue::Collateral)?;
+        // ORACLE-001: refresh this vault's collateral price before releasing collateral.
+        validate_freshness_for_vault(vault_id).await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::withdraw_collateral(vault_id)).await,
+        )
+    })
+    .await
 }
 
 #[candid_method(update)]
@@ -4744,19 +5457,109 @@ async fn withdraw_collateral(vault_id: u64) -> Result<u64, ProtocolError> {
 async fn withdraw_partial_collateral(
     arg: rumi_protocol_backend::vault::VaultArg,
 ) -> Result<u64, ProtocolError> {
-    validate_call().await?;
-    // ORACLE-001: refresh this vault's collateral price before releasing collateral.
-    validate_freshness_for_vault(arg.vault_id).await?;
-    check_postcondition(
-        rumi_protocol_backend::vault::withdraw_partial_collateral(arg.vault_id, arg.amount).await,
-    )
+    slo_tracked("withdraw_partial_collateral", async move {
+        validate_call().await?;
+        validate_pending_room(PayoutQueue::Collateral)?;
+        // ORACLE-001: refresh this vault's collateral price before releasing collateral.
+        validate_freshness_for_vault(arg.vault_id).await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::withdraw_partial_collateral(
+                arg.vault_id,
+                arg.amount,
+            ))
+            .await,
+        )
+    })
+    .await
+}
+
+/// Swap a vault's collateral in place through a whitelisted DEX route. The
+/// vault is unchanged unless the DEX delivers enough new collateral to keep
+/// it above the new collateral's minimum ratio.
+#[candid_method(update)]
+#[update]
+async fn swap_vault_collateral(
+    arg: rumi_protocol_backend::collateral_swap::SwapVaultCollateralArg,
+) -> Result<rumi_protocol_backend::collateral_swap::SwapVaultCollateralSuccess, ProtocolError> {
+    slo_tracked("swap_vault_collateral", async move {
+        validate_call().await?;
+        validate_mode()?;
+        // ORACLE-001: both prices feed the minimum the DEX must deliver.
+        validate_freshness_for_vault(arg.vault_id).await?;
+        validate_freshness_for_collateral(Some(arg.new_collateral_type)).await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::collateral_swap::swap_vault_collateral(arg)).await,
+        )
+    })
+    .await
+}
+
+/// Sell `collateral_amount` of a vault's collateral through its whitelisted
+/// DEX route and repay the debt with the proceeds. The vault is unchanged
+/// unless the DEX delivers at least `min_debt_repaid` and enough to keep the
+/// vault's collateral ratio from falling.
+#[candid_method(update)]
+#[update]
+async fn repay_from_collateral(
+    vault_id: u64,
+    collateral_amount: u64,
+    min_debt_repaid: u64,
+) -> Result<rumi_protocol_backend::repay_from_collateral::RepayFromCollateralSuccess, ProtocolError>
+{
+    slo_tracked("repay_from_collateral", async move {
+        validate_call().await?;
+        validate_mode()?;
+        // ORACLE-001: the price caps the sale against the debt.
+        validate_freshness_for_vault(vault_id).await?;
+        check_postcondition(
+            traced(
+                rumi_protocol_backend::repay_from_collateral::repay_from_collateral(
+                    vault_id,
+                    collateral_amount,
+                    min_debt_repaid,
+                ),
+            )
+            .await,
+        )
+    })
+    .await
+}
+
+/// Close a vault below its minimum collateral ratio by selling enough of its
+/// collateral through its whitelisted DEX route to repay the whole debt,
+/// returning the rest of the collateral to the owner.
+#[candid_method(update)]
+#[update]
+async fn self_liquidate_vault(
+    vault_id: u64,
+) -> Result<rumi_protocol_backend::self_liquidation::SelfLiquidationSuccess, ProtocolError> {
+    slo_tracked("self_liquidate_vault", async move {
+        validate_call().await?;
+        validate_mode()?;
+        validate_pending_room(PayoutQueue::Collateral)?;
+        // ORACLE-001: the price decides eligibility and sizes the sale.
+        validate_freshness_for_vault(vault_id).await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::self_liquidation::self_liquidate_vault(vault_id)).await,
+        )
+    })
+    .await
 }
 
 #[candid_method(update)]
 #[update]
 async fn withdraw_and_close_vault(vault_id: u64) -> Result<Option<u64>, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::vault::withdraw_and_close_vault(vault_id).await)
+    slo_tracked("withdraw_and_close_vault", async move {
+        validate_call().await?;
+        validate_pending_room(PayoutQueue::Collateral)?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::withdraw_and_close_vault(
+                vault_id,
+            ))
+            .await,
+        )
+    })
+    .await
 }
 
 /// Compound repay + withdraw + close in a single canister call.
@@ -4766,85 +5569,465 @@ async fn withdraw_and_close_vault(vault_id: u64) -> Result<Option<u64>, Protocol
 async fn repay_and_close_vault(
     arg: VaultArg,
 ) -> Result<rumi_protocol_backend::vault::RepayAndCloseSuccess, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::vault::repay_and_close_vault(arg).await)
+    slo_tracked("repay_and_close_vault", async move {
+        validate_call().await?;
+        validate_pending_room(PayoutQueue::Collateral)?;
+        check_postcondition(traced(rumi_protocol_backend::vault::repay_and_close_vault(arg)).await)
+    })
+    .await
 }
 
-// Add the new liquidate vault endpoint
+/// Repay a vault's exact outstanding debt (icUSD or an enabled stable token)
+/// and close it, queueing the collateral return.
 #[candid_method(update)]
 #[update]
-async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolError> {
-    validate_call().await?;
-    validate_liquidation_not_frozen()?;
-    validate_price_for_liquidation()?;
-    validate_freshness_for_vault(vault_id).await?;
-    check_postcondition(rumi_protocol_backend::vault::liquidate_vault(vault_id).await)
+async fn repay_all_and_close_vault(
+    arg: RepayAllAndCloseArg,
+) -> Result<rumi_protocol_backend::vault::RepayAllAndCloseSuccess, ProtocolError> {
+    slo_tracked("repay_all_and_close_vault", async move {
+        validate_call().await?;
+        validate_pending_room(PayoutQueue::Collateral)?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::repay_all_and_close_vault(arg)).await,
+        )
+    })
+    .await
 }
 
-// Add the new partial repay vault endpoint
+// Add the new liquidate vault endpoint.
+// `min_collateral_out` (optional, native collateral units): abort if the
+// payout computed at execution time would be smaller.
 #[candid_method(update)]
 #[update]
-async fn partial_repay_to_vault(arg: VaultArg) -> Result<u64, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::vault::partial_repay_to_vault(arg).await)
+async fn liquidate_vault(
+    vault_id: u64,
+    min_collateral_out: Option<u64>,
+) -> Result<SuccessWithFee, ProtocolError> {
+    slo_tracked(
+        "liquidate_vault",
+        liquidator_tracked(
+            vault_id,
+            |r: &SuccessWithFee| r.debt_liquidated_e8s,
+            async move {
+                validate_call().await?;
+                validate_liquidation_not_frozen()?;
+                validate_price_for_liquidation()?;
+                validate_freshness_for_vault(vault_id).await?;
+                check_postcondition(
+                    traced(rumi_protocol_backend::vault::liquidate_vault(
+                        vault_id,
+                        min_collateral_out,
+                    ))
+                    .await,
+                )
+            },
+        ),
+    )
+    .await
 }
 
-// Partial liquidation with icUSD
+/// `liquidate_vault` paid in ckUSDT or ckUSDC (1:1 with icUSD, plus the
+/// repay-fee surcharge).
 #[candid_method(update)]
 #[update]
-async fn liquidate_vault_partial(arg: VaultArg) -> Result<SuccessWithFee, ProtocolError> {
-    validate_call().await?;
-    validate_liquidation_not_frozen()?;
-    validate_price_for_liquidation()?;
-    validate_freshness_for_vault(arg.vault_id).await?;
-    check_postcondition(
-        rumi_protocol_backend::vault::liquidate_vault_partial(arg.vault_id, arg.amount).await,
+async fn liquidate_vault_with_stable(
+    vault_id: u64,
+    token_type: StableTokenType,
+    min_collateral_out: Option<u64>,
+) -> Result<SuccessWithFee, ProtocolError> {
+    slo_tracked(
+        "liquidate_vault_with_stable",
+        liquidator_tracked(
+            vault_id,
+            |r: &SuccessWithFee| r.debt_liquidated_e8s,
+            async move {
+                validate_call().await?;
+                validate_liquidation_not_frozen()?;
+                validate_price_for_liquidation()?;
+                validate_freshness_for_vault(vault_id).await?;
+                check_postcondition(
+                    traced(rumi_protocol_backend::vault::liquidate_vault_with_stable(
+                        vault_id,
+                        token_type,
+                        min_collateral_out,
+                    ))
+                    .await,
+                )
+            },
+        ),
     )
+    .await
 }
 
-/// Liquidate a vault using ckUSDT or ckUSDC (1:1 with icUSD)
+// Add the new partial repay vault endpoint
+#[candid_method(update)]
 #[update]
+async fn partial_repay_to_vault(arg: VaultArg) -> Result<u64, ProtocolError> {
+    slo_tracked("partial_repay_to_vault", async move {
+        validate_call().await?;
+        check_postcondition(traced(rumi_protocol_backend::vault::partial_repay_to_vault(arg)).await)
+    })
+    .await
+}
+
+// Partial liquidation with icUSD
 #[candid_method(update)]
-async fn liquidate_vault_partial_with_stable(
-    arg: VaultArgWithToken,
+#[update]
+async fn liquidate_vault_partial(
+    arg: VaultArg,
+    min_collateral_out: Option<u64>,
 ) -> Result<SuccessWithFee, ProtocolError> {
-    validate_call().await?;
-    validate_liquidation_not_frozen()?;
-    validate_price_for_liquidation()?;
-    validate_freshness_for_vault(arg.vault_id).await?;
-    check_postcondition(
-        rumi_protocol_backend::vault::liquidate_vault_partial_with_stable(
+    slo_tracked(
+        "liquidate_vault_partial",
+        liquidator_tracked(
             arg.vault_id,
-            arg.amount,
-            arg.token_type,
-        )
-        .await,
+            |r: &SuccessWithFee| r.debt_liquidated_e8s,
+            async move {
+                validate_call().await?;
+                validate_liquidation_not_frozen()?;
+                validate_price_for_liquidation()?;
+                validate_freshness_for_vault(arg.vault_id).await?;
+                check_postcondition(
+                    traced(rumi_protocol_backend::vault::liquidate_vault_partial(
+                        arg.vault_id,
+                        arg.amount,
+                        min_collateral_out,
+                    ))
+                    .await,
+                )
+            },
+        ),
     )
+    .await
 }
 
-// Stability Pool Integration - allows stability pool to execute liquidations
-#[update]
+/// Fill up to `icusd_amount` e8s of a protocol liquidation auction at its
+/// current price. See `auction`.
 #[candid_method(update)]
-async fn stability_pool_liquidate(
-    vault_id: u64,
-    max_debt_to_liquidate: u64,
-) -> Result<StabilityPoolLiquidationResult, ProtocolError> {
-    validate_call().await?;
-    validate_liquidation_not_frozen()?;
-    validate_price_for_liquidation()?;
-    validate_freshness_for_vault(vault_id).await?;
-    // P5: native-XRP collateral is liquidated MANUALLY (claim-based) only; automated
-    // stability-pool / bot liquidation cannot settle an XrpClaim (would strand the
-    // seized XRP and burn SP depositors), so reject native-XRP here.
-    if rumi_protocol_backend::vault::vault_is_native_xrp(vault_id) {
-        return Err(ProtocolError::GenericError(
-            "Native-XRP collateral is liquidated manually (claim-based), not via the stability pool or bot".to_string(),
-        ));
-    }
-    let caller = ic_cdk::api::caller();
-
-    // Authorization: only the registered stability pool canister can call this
-    let is_stability_pool =
+#[update]
+async fn bid_auction(
+    auction_id: u64,
+    icusd_amount: u64,
+    min_collateral_out: Option<u64>,
+) -> Result<rumi_protocol_backend::auction::AuctionBidSuccess, ProtocolError> {
+    let vault_id = read_state(|s| s.auctions.get(&auction_id).map(|a| a.vault_id))
+        .ok_or_else(|| ProtocolError::GenericError(format!("Auction #{} not found", auction_id)))?;
+    slo_tracked(
+        "bid_auction",
+        liquidator_tracked(
+            vault_id,
+            |r: &rumi_protocol_backend::auction::AuctionBidSuccess| Some(r.icusd_paid_e8s),
+            async move {
+                validate_call().await?;
+                validate_liquidation_not_frozen()?;
+                validate_price_for_liquidation()?;
+                validate_freshness_for_vault(vault_id).await?;
+                check_postcondition(
+                    traced(rumi_protocol_backend::auction::bid(
+                        auction_id,
+                        icusd_amount,
+                        min_collateral_out,
+                    ))
+                    .await,
+                )
+            },
+        ),
+    )
+    .await
+}
+
+#[candid_method(query)]
+#[query]
+fn get_auction(auction_id: u64) -> Option<rumi_protocol_backend::auction::AuctionView> {
+    let now = ic_cdk::api::time();
+    read_state(|s| {
+        s.auctions
+            .get(&auction_id)
+            .map(|a| rumi_protocol_backend::auction::view(s, a, now))
+    })
+}
+
+#[candid_method(query)]
+#[query]
+fn get_active_auctions() -> Vec<rumi_protocol_backend::auction::AuctionView> {
+    let now = ic_cdk::api::time();
+    read_state(|s| {
+        s.auctions
+            .values()
+            .filter(|a| a.ended.is_none())
+            .map(|a| rumi_protocol_backend::auction::view(s, a, now))
+            .collect()
+    })
+}
+
+#[candid_method(query)]
+#[query]
+fn get_auction_config() -> Option<rumi_protocol_backend::auction::AuctionConfig> {
+    read_state(|s| s.auction_config.clone())
+}
+
+/// Configure the liquidation auction fallback (developer only).
+#[candid_method(update)]
+#[update]
+fn set_auction_config(
+    config: rumi_protocol_backend::auction::AuctionConfig,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_auction_config")?;
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can configure auctions".to_string(),
+        ));
+    }
+    rumi_protocol_backend::auction::validate_config(&config)?;
+    log!(INFO, "[set_auction_config] {:?}", config);
+    mutate_state(|s| rumi_protocol_backend::event::record_set_auction_config(s, config));
+    Ok(())
+}
+
+/// What redistributions have handed `vault_id` that is not yet booked on it.
+#[candid_method(query)]
+#[query]
+fn get_pending_redistribution(
+    vault_id: u64,
+) -> rumi_protocol_backend::redistribution::PendingRedistribution {
+    read_state(|s| rumi_protocol_backend::redistribution::pending(s, vault_id))
+}
+
+/// Redistribute vaults whose auction expired unhealthy (developer only).
+#[candid_method(update)]
+#[update]
+fn set_redistribution_enabled(enabled: bool) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_redistribution_enabled")?;
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can toggle redistribution".to_string(),
+        ));
+    }
+    log!(INFO, "[set_redistribution_enabled] {}", enabled);
+    mutate_state(|s| rumi_protocol_backend::event::record_set_redistribution_enabled(s, enabled));
+    Ok(())
+}
+
+/// Hand a liquidatable vault's debt and collateral to the other vaults of
+/// its collateral type and remove it (developer only). For vaults no
+/// liquidation path absorbed.
+#[candid_method(update)]
+#[update]
+fn redistribute_vault(
+    vault_id: u64,
+) -> Result<rumi_protocol_backend::redistribution::PendingRedistribution, ProtocolError> {
+    let caller = ic_cdk::caller();
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can redistribute vaults".to_string(),
+        ));
+    }
+    let _guard = rumi_protocol_backend::guard::VaultLiquidationGuard::new(vault_id)?;
+    let now = ic_cdk::api::time();
+    let handed_out = mutate_state(|s| {
+        s.accrue_single_vault(vault_id, now);
+        rumi_protocol_backend::event::record_vault_redistributed(s, vault_id, now)
+    })?;
+    log!(
+        INFO,
+        "[redistribute_vault] vault #{}: {} collateral, {} icUSD e8s",
+        vault_id,
+        handed_out.collateral,
+        handed_out.debt
+    );
+    Ok(handed_out)
+}
+
+/// Put the mode machine, circuit breakers or recovery routing in shadow
+/// mode (developer only). See `shadow`.
+#[candid_method(update)]
+#[update]
+fn set_shadow_config(
+    config: rumi_protocol_backend::shadow::ShadowConfig,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_shadow_config")?;
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set the shadow config".to_string(),
+        ));
+    }
+    log!(INFO, "[set_shadow_config] {:?}", config);
+    mutate_state(|s| rumi_protocol_backend::event::record_set_shadow_config(s, config));
+    Ok(())
+}
+
+#[candid_method(query)]
+#[query]
+fn get_shadow_config() -> rumi_protocol_backend::shadow::ShadowConfig {
+    read_state(|s| s.shadow_config)
+}
+
+/// What the shadowed logic would have done, newest first.
+#[candid_method(query)]
+#[query]
+fn get_shadow_decisions(
+    component: Option<rumi_protocol_backend::shadow::ShadowComponent>,
+    limit: u64,
+) -> Vec<rumi_protocol_backend::shadow::ShadowDecision> {
+    read_state(|s| rumi_protocol_backend::shadow::decisions(s, component, limit as usize))
+}
+
+/// Partial liquidation sized server-side to bring the vault to `target_cr`
+/// (e.g. 1.5 = 150%), spending at most `max_icusd` e8s.
+#[candid_method(update)]
+#[update]
+async fn liquidate_to_target(
+    vault_id: u64,
+    target_cr: f64,
+    max_icusd: u64,
+    min_collateral_out: Option<u64>,
+) -> Result<rumi_protocol_backend::LiquidateToTargetResult, ProtocolError> {
+    slo_tracked(
+        "liquidate_to_target",
+        liquidator_tracked(
+            vault_id,
+            |r: &rumi_protocol_backend::LiquidateToTargetResult| r.liquidation.debt_liquidated_e8s,
+            async move {
+                rumi_protocol_backend::validate_f64_inclusive("target_cr", target_cr, 1.0, 10.0)
+                    .map_err(ProtocolError::GenericError)?;
+                let target_cr = Ratio::from(Decimal::from_f64(target_cr).ok_or_else(|| {
+                    ProtocolError::GenericError("target_cr is not representable".to_string())
+                })?);
+                validate_call().await?;
+                validate_liquidation_not_frozen()?;
+                validate_price_for_liquidation()?;
+                validate_freshness_for_vault(vault_id).await?;
+                check_postcondition(
+                    traced(rumi_protocol_backend::vault::liquidate_to_target(
+                        vault_id,
+                        target_cr,
+                        ICUSD::from(max_icusd),
+                        min_collateral_out,
+                    ))
+                    .await,
+                )
+            },
+        ),
+    )
+    .await
+}
+
+/// `liquidate_to_target` paid in ckUSDT or ckUSDC. `max_amount` caps the
+/// icUSD-equivalent debt repaid, in e8s, before the surcharge.
+#[candid_method(update)]
+#[update]
+async fn liquidate_to_target_with_stable(
+    vault_id: u64,
+    target_cr: f64,
+    max_amount: u64,
+    token_type: StableTokenType,
+    min_collateral_out: Option<u64>,
+) -> Result<rumi_protocol_backend::LiquidateToTargetResult, ProtocolError> {
+    slo_tracked(
+        "liquidate_to_target_with_stable",
+        liquidator_tracked(
+            vault_id,
+            |r: &rumi_protocol_backend::LiquidateToTargetResult| r.liquidation.debt_liquidated_e8s,
+            async move {
+                rumi_protocol_backend::validate_f64_inclusive("target_cr", target_cr, 1.0, 10.0)
+                    .map_err(ProtocolError::GenericError)?;
+                let target_cr = Ratio::from(Decimal::from_f64(target_cr).ok_or_else(|| {
+                    ProtocolError::GenericError("target_cr is not representable".to_string())
+                })?);
+                validate_call().await?;
+                validate_liquidation_not_frozen()?;
+                validate_price_for_liquidation()?;
+                validate_freshness_for_vault(vault_id).await?;
+                check_postcondition(
+                    traced(
+                        rumi_protocol_backend::vault::liquidate_to_target_with_stable(
+                            vault_id,
+                            target_cr,
+                            ICUSD::from(max_amount),
+                            token_type,
+                            min_collateral_out,
+                        ),
+                    )
+                    .await,
+                )
+            },
+        ),
+    )
+    .await
+}
+
+/// Preview `liquidate_vault_partial(vault_id, icusd_amount)` at the cached
+/// price without executing it: the capped repay, the collateral it would
+/// seize and the protocol's cut. `icusd_amount` defaults to the full debt.
+#[candid_method(query)]
+#[query]
+fn preview_liquidation(
+    vault_id: u64,
+    icusd_amount: Option<u64>,
+) -> Result<rumi_protocol_backend::LiquidationPreview, ProtocolError> {
+    read_state(|s| {
+        s.preview_liquidation(vault_id, icusd_amount.map(ICUSD::from), ic_cdk::api::time())
+    })
+    .map_err(ProtocolError::GenericError)
+}
+
+/// Liquidate a vault using ckUSDT or ckUSDC (1:1 with icUSD)
+#[update]
+#[candid_method(update)]
+async fn liquidate_vault_partial_with_stable(
+    arg: VaultArgWithToken,
+    min_collateral_out: Option<u64>,
+) -> Result<SuccessWithFee, ProtocolError> {
+    slo_tracked(
+        "liquidate_vault_partial_with_stable",
+        liquidator_tracked(
+            arg.vault_id,
+            |r: &SuccessWithFee| r.debt_liquidated_e8s,
+            async move {
+                validate_call().await?;
+                validate_liquidation_not_frozen()?;
+                validate_price_for_liquidation()?;
+                validate_freshness_for_vault(arg.vault_id).await?;
+                check_postcondition(
+                    traced(
+                        rumi_protocol_backend::vault::liquidate_vault_partial_with_stable(
+                            arg.vault_id,
+                            arg.amount,
+                            arg.token_type,
+                            min_collateral_out,
+                        ),
+                    )
+                    .await,
+                )
+            },
+        ),
+    )
+    .await
+}
+
+// Stability Pool Integration - allows stability pool to execute liquidations
+#[update]
+#[candid_method(update)]
+async fn stability_pool_liquidate(
+    vault_id: u64,
+    max_debt_to_liquidate: u64,
+) -> Result<StabilityPoolLiquidationResult, ProtocolError> {
+    validate_call().await?;
+    validate_liquidation_not_frozen()?;
+    validate_price_for_liquidation()?;
+    validate_freshness_for_vault(vault_id).await?;
+    // P5: native-XRP collateral is liquidated MANUALLY (claim-based) only; automated
+    // stability-pool / bot liquidation cannot settle an XrpClaim (would strand the
+    // seized XRP and burn SP depositors), so reject native-XRP here.
+    if rumi_protocol_backend::vault::vault_is_native_xrp(vault_id) {
+        return Err(ProtocolError::GenericError(
+            "Native-XRP collateral is liquidated manually (claim-based), not via the stability pool or bot".to_string(),
+        ));
+    }
+    let caller = ic_cdk::api::caller();
+
+    // Authorization: only the registered stability pool canister can call this
+    let is_stability_pool =
         read_state(|s| s.stability_pool_canister.map_or(false, |sp| sp == caller));
     if !is_stability_pool {
         return Err(ProtocolError::GenericError(
@@ -4906,9 +6089,12 @@ async fn stability_pool_liquidate(
     }
 
     // Execute the liquidation using existing logic
-    let result =
-        rumi_protocol_backend::vault::liquidate_vault_partial(vault_id, liquidatable_debt.to_u64())
-            .await?;
+    let result = rumi_protocol_backend::vault::liquidate_vault_partial(
+        vault_id,
+        liquidatable_debt.to_u64(),
+        None,
+    )
+    .await?;
 
     // Return structured result for stability pool
     Ok(StabilityPoolLiquidationResult {
@@ -5580,7 +6766,7 @@ async fn refund_3usd_to_stability_pool(
     vault_id: u64,
 ) {
     use ic_canister_log::log;
-    use rumi_protocol_backend::logs::INFO;
+    use rumi_protocol_backend::logs::{CRITICAL, INFO};
 
     // Mint the dedup nonce up front so a stranded refund is enqueued under the
     // SAME nonce it (may have) attempted the transfer with; a retry then
@@ -5606,7 +6792,7 @@ async fn refund_3usd_to_stability_pool(
         // nothing recoverable to queue. This is unreachable while the 3USD fee
         // is 0 and only ever concerns amounts of at most one fee.
         log!(
-            INFO,
+            CRITICAL,
             "[stability_pool_liquidate_with_reserves] CRITICAL: refund of {} 3USD for vault {} \
              to SP {} aborted (amount does not cover ledger fee {}). Dust stranded in reserves.",
             amount_e8s,
@@ -5714,12 +6900,24 @@ fn get_stability_pool_config() -> StabilityPoolConfig {
 // Add the new partial liquidate vault endpoint
 #[candid_method(update)]
 #[update]
-async fn partial_liquidate_vault(arg: VaultArg) -> Result<SuccessWithFee, ProtocolError> {
-    validate_call().await?;
-    validate_liquidation_not_frozen()?;
-    validate_price_for_liquidation()?;
-    validate_freshness_for_vault(arg.vault_id).await?;
-    check_postcondition(rumi_protocol_backend::vault::partial_liquidate_vault(arg).await)
+async fn partial_liquidate_vault(
+    arg: VaultArg,
+    min_collateral_out: Option<u64>,
+) -> Result<SuccessWithFee, ProtocolError> {
+    slo_tracked("partial_liquidate_vault", async move {
+        validate_call().await?;
+        validate_liquidation_not_frozen()?;
+        validate_price_for_liquidation()?;
+        validate_freshness_for_vault(arg.vault_id).await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::partial_liquidate_vault(
+                arg,
+                min_collateral_out,
+            ))
+            .await,
+        )
+    })
+    .await
 }
 
 /// Legacy entry point used by the layout's at-risk banner and the
@@ -5734,23 +6932,22 @@ async fn partial_liquidate_vault(arg: VaultArg) -> Result<SuccessWithFee, Protoc
 fn get_liquidatable_vaults() -> Vec<CandidVault> {
     // Wave 9a (DOS-004) shares `MAX_VAULTS_LEGACY_PAGE` with the other
     // vault enumeration legacy entry points; the cap is the same.
+    //
+    // Read from `liquidation_index`, lowest CR first, instead of scanning
+    // every vault.
     read_state(|s| {
         // Dummy rate for compute_collateral_ratio parameter (it uses per-collateral price internally)
         let dummy_rate = s.last_icp_rate.unwrap_or(UsdIcp::from(dec!(0.0)));
 
-        s.vault_id_to_vaults
-            .values()
+        rumi_protocol_backend::liquidation_index::liquidatable(s)
+            .into_iter()
             .filter(|vault| {
-                let ratio = rumi_protocol_backend::compute_collateral_ratio(vault, dummy_rate, s);
                 // Zero ratio means no price available — don't mark as liquidatable
-                if ratio == Ratio::from(Decimal::ZERO) {
-                    return false;
-                }
-                ratio < s.get_min_liquidation_ratio_for(&vault.collateral_type)
+                rumi_protocol_backend::compute_collateral_ratio(vault, dummy_rate, s)
+                    != Ratio::from(Decimal::ZERO)
             })
             .take(MAX_VAULTS_LEGACY_PAGE)
-            .cloned()
-            .map(CandidVault::from)
+            .map(|vault| candid_vault(s, vault))
             .collect::<Vec<CandidVault>>()
     })
 }
@@ -5783,7 +6980,7 @@ fn get_liquidatable_vaults_page(start_id: u64, limit: u64) -> VaultsPageResponse
                     next_start_id = Some(*id);
                     break;
                 }
-                vaults.push(CandidVault::from(vault.clone()));
+                vaults.push(candid_vault(s, vault));
             }
         }
         VaultsPageResponse {
@@ -5807,32 +7004,77 @@ fn get_all_vaults() -> Vec<CandidVault> {
         s.vault_id_to_vaults
             .values()
             .take(MAX_VAULTS_LEGACY_PAGE)
-            .cloned()
-            .map(CandidVault::from)
+            .map(|vault| candid_vault(s, vault))
             .collect::<Vec<CandidVault>>()
     })
 }
 
+/// Lifecycle status of a vault; `None` for an id that was never issued.
+#[candid_method(query)]
+#[query]
+fn get_vault_status(vault_id: u64) -> Option<VaultStatus> {
+    read_state(|s| {
+        (vault_id < s.next_available_vault_id)
+            .then(|| rumi_protocol_backend::vault_status::status_of(s, vault_id))
+    })
+}
+
 // Liquidity related operations
 #[candid_method(update)]
 #[update]
 async fn provide_liquidity(amount: u64) -> Result<u64, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::liquidity_pool::provide_liquidity(amount).await)
+    slo_tracked("provide_liquidity", async move {
+        validate_call().await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::liquidity_pool::provide_liquidity(
+                amount,
+            ))
+            .await,
+        )
+    })
+    .await
 }
 
 #[candid_method(update)]
 #[update]
 async fn withdraw_liquidity(amount: u64) -> Result<u64, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::liquidity_pool::withdraw_liquidity(amount).await)
+    slo_tracked("withdraw_liquidity", async move {
+        validate_call().await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::liquidity_pool::withdraw_liquidity(
+                amount,
+            ))
+            .await,
+        )
+    })
+    .await
 }
 
 #[candid_method(update)]
 #[update]
 async fn claim_liquidity_returns() -> Result<u64, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::liquidity_pool::claim_liquidity_returns().await)
+    slo_tracked("claim_liquidity_returns", async move {
+        validate_call().await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::liquidity_pool::claim_liquidity_returns()).await,
+        )
+    })
+    .await
+}
+
+/// Developer-gated: fold dust returns into positions and return positions
+/// below the minimum to their owners.
+#[candid_method(update)]
+#[update]
+async fn sweep_liquidity_residuals(
+) -> Result<rumi_protocol_backend::liquidity_pool::LiquiditySweepResult, ProtocolError> {
+    slo_tracked("sweep_liquidity_residuals", async move {
+        validate_call().await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::liquidity_pool::sweep_liquidity_residuals()).await,
+        )
+    })
+    .await
 }
 
 /// Transform function for HTTPS outcalls (CoinGecko price fetches).
@@ -5936,7 +7178,7 @@ fn xrp_has_key_bound_state() -> bool {
 #[candid_method(update)]
 #[update]
 fn set_xrp_schnorr_key_name(name: String) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_xrp_schnorr_key_name")?;
     if !read_state(|s| s.developer_principal == caller) {
         return Err(ProtocolError::GenericError(
             "Only developer can set XRP Schnorr key".to_string(),
@@ -6010,8 +7252,11 @@ async fn xrp_balance(address: String) -> Result<u64, ProtocolError> {
 /// Errors until native-XRP collateral is registered (P5).
 #[update]
 async fn open_xrp_vault() -> Result<rumi_protocol_backend::vault::XrpVaultOpenInfo, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::vault::open_xrp_vault().await)
+    slo_tracked("open_xrp_vault", async move {
+        validate_call().await?;
+        check_postcondition(traced(rumi_protocol_backend::vault::open_xrp_vault()).await)
+    })
+    .await
 }
 
 /// P3 (native-XRP collateral): verify the deposit to a vault's custody address and
@@ -6019,8 +7264,13 @@ async fn open_xrp_vault() -> Result<rumi_protocol_backend::vault::XrpVaultOpenIn
 /// idempotent. Borrow icUSD afterwards via the normal `borrow_from_vault`.
 #[update]
 async fn confirm_xrp_deposit(vault_id: u64) -> Result<u64, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::vault::confirm_xrp_deposit(vault_id).await)
+    slo_tracked("confirm_xrp_deposit", async move {
+        validate_call().await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::confirm_xrp_deposit(vault_id)).await,
+        )
+    })
+    .await
 }
 
 /// P4 (native-XRP collateral): settle an XRP collateral claim by signing +
@@ -6028,8 +7278,17 @@ async fn confirm_xrp_deposit(vault_id: u64) -> Result<u64, ProtocolError> {
 /// (claimant bears the fee). Claimant-only. Returns the local tx hash.
 #[update]
 async fn settle_xrp_claim(claim_id: u64, destination: String) -> Result<String, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::vault::settle_xrp_claim(claim_id, destination).await)
+    slo_tracked("settle_xrp_claim", async move {
+        validate_call().await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::settle_xrp_claim(
+                claim_id,
+                destination,
+            ))
+            .await,
+        )
+    })
+    .await
 }
 
 /// XRP-007: settle an XRP collateral claim to a destination that requires an XRPL
@@ -6041,31 +7300,50 @@ async fn settle_xrp_claim_with_tag(
     destination: String,
     destination_tag: u32,
 ) -> Result<String, ProtocolError> {
-    validate_call().await?;
-    check_postcondition(
-        rumi_protocol_backend::vault::settle_xrp_claim_with_tag(
-            claim_id,
-            destination,
-            Some(destination_tag),
+    slo_tracked("settle_xrp_claim_with_tag", async move {
+        validate_call().await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::settle_xrp_claim_with_tag(
+                claim_id,
+                destination,
+                Some(destination_tag),
+            ))
+            .await,
         )
-        .await,
-    )
+    })
+    .await
 }
 
 /// XRP-006: owner cleanup for an abandoned native-XRP open. The vault layer
 /// verifies live XRPL state and removes the pending entry only if it is unfunded.
 #[update]
 async fn cancel_xrp_pending_open(vault_id: u64) -> Result<(), ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::vault::cancel_xrp_pending_open(vault_id).await)
+    slo_tracked("cancel_xrp_pending_open", async move {
+        validate_call().await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::cancel_xrp_pending_open(
+                vault_id,
+            ))
+            .await,
+        )
+    })
+    .await
 }
 
 /// XRP-006: developer cleanup for abandoned native-XRP opens. This is also
 /// unfunded-only; funded custody addresses remain confirmable by their owners.
 #[update]
 async fn sweep_xrp_pending_open(vault_id: u64) -> Result<(), ProtocolError> {
-    validate_call().await?;
-    check_postcondition(rumi_protocol_backend::vault::sweep_xrp_pending_open(vault_id).await)
+    slo_tracked("sweep_xrp_pending_open", async move {
+        validate_call().await?;
+        check_postcondition(
+            traced(rumi_protocol_backend::vault::sweep_xrp_pending_open(
+                vault_id,
+            ))
+            .await,
+        )
+    })
+    .await
 }
 
 /// P5 (native-XRP collateral): register XRP as a collateral (developer-gated). XRP
@@ -6083,6 +7361,7 @@ async fn register_xrp_collateral() -> Result<(), ProtocolError> {
             "Only the developer can register XRP collateral".to_string(),
         ));
     }
+    read_state(rumi_protocol_backend::emergency_shutdown::check_not_shut_down)?;
     xrp_require_production_schnorr_key()?;
     let xrp_ct = rumi_protocol_backend::state::xrp_collateral_principal();
     if read_state(|s| s.collateral_configs.contains_key(&xrp_ct)) {
@@ -6290,11 +7569,24 @@ fn get_my_xrp_claims() -> Vec<(u64, rumi_protocol_backend::state::XrpClaim)> {
 }
 
 #[query]
-fn http_request(req: HttpRequest) -> HttpResponse {
-    use ic_metrics_encoder::MetricsEncoder;
+fn http_request(req: HttpRequest) -> rumi_protocol_backend::ops_panel::HttpResponse {
     if ic_cdk::api::data_certificate().is_none() {
         ic_cdk::trap("update call rejected");
     }
+    match rumi_protocol_backend::ops_panel::http_query(&req) {
+        Some(response) => response,
+        None => serve_http_request(req).into(),
+    }
+}
+
+/// The operator panel actions `http_request` upgraded. See `ops_panel`.
+#[update]
+async fn http_request_update(req: HttpRequest) -> rumi_protocol_backend::ops_panel::HttpResponse {
+    rumi_protocol_backend::ops_panel::http_update(req).await
+}
+
+fn serve_http_request(req: HttpRequest) -> HttpResponse {
+    use ic_metrics_encoder::MetricsEncoder;
 
     if req.path() == "/metrics" {
         let mut writer = MetricsEncoder::new(vec![], ic_cdk::api::time() as i64 / 1_000_000);
@@ -6361,15 +7653,107 @@ fn http_request(req: HttpRequest) -> HttpResponse {
                     "Pending redemption transfers count.",
                 )?;
 
+                let transfers =
+                    rumi_protocol_backend::transfer_retry::metrics(s, ic_cdk::api::time());
+
                 w.encode_gauge(
-                    "rumi_icp_rate",
-                    s.last_icp_rate.unwrap_or(UsdIcp::from(dec!(0))).to_f64(),
-                    "ICP rate.",
+                    "rumi_pending_transfers_queued",
+                    transfers.queued as f64,
+                    "Entries across the pending transfer and refund queues.",
                 )?;
 
-                let total_icp_dec = Decimal::from_u64(s.total_icp_margin_amount().0)
-                    .expect("failed to construct decimal from u64")
-                    / dec!(100_000_000);
+                w.encode_gauge(
+                    "rumi_pending_transfers_backing_off",
+                    transfers.backing_off as f64,
+                    "Queued transfers waiting out a retry backoff.",
+                )?;
+
+                w.encode_gauge(
+                    "rumi_pending_transfer_oldest_age_seconds",
+                    transfers.oldest_age_ns.unwrap_or(0) as f64 / 1e9,
+                    "Age of the oldest queued transfer, in seconds.",
+                )?;
+
+                w.encode_gauge(
+                    "rumi_failed_transfers_count",
+                    transfers.failed as f64,
+                    "Transfers dead-lettered after exhausting their retries.",
+                )?;
+
+                let backpressure = rumi_protocol_backend::pending_backpressure::status(s);
+
+                w.encode_gauge(
+                    "rumi_pending_collateral_transfer_threshold",
+                    backpressure.config.max_pending_collateral_transfers as f64,
+                    "Pending collateral payouts at which closes and withdrawals pause (0 = off).",
+                )?;
+
+                w.encode_gauge(
+                    "rumi_pending_redemption_transfer_threshold",
+                    backpressure.config.max_pending_redemption_transfers as f64,
+                    "Pending redemption payouts at which redemptions are rejected (0 = off).",
+                )?;
+
+                w.encode_gauge(
+                    "rumi_pending_collateral_backpressure_active",
+                    backpressure.collateral_blocked as u8 as f64,
+                    "1 while closes and withdrawals are paused by pending-queue backpressure.",
+                )?;
+
+                w.encode_gauge(
+                    "rumi_pending_redemption_backpressure_active",
+                    backpressure.redemption_blocked as u8 as f64,
+                    "1 while redemptions are rejected for pending-queue backpressure.",
+                )?;
+
+                w.encode_counter(
+                    "rumi_pending_collateral_backpressure_rejections",
+                    backpressure.stats.collateral_rejections as f64,
+                    "Closes and withdrawals rejected for pending-queue backpressure.",
+                )?;
+
+                w.encode_counter(
+                    "rumi_pending_redemption_backpressure_rejections",
+                    backpressure.stats.redemption_rejections as f64,
+                    "Redemptions rejected for pending-queue backpressure.",
+                )?;
+
+                let slo_stats = rumi_protocol_backend::slo::endpoint_stats();
+                for (name, help, value) in rumi_protocol_backend::slo::METRICS {
+                    let mut metric = w.gauge_vec(name, help)?;
+                    for (endpoint, stats) in &slo_stats {
+                        metric = metric
+                            .value(&[("endpoint", endpoint.as_str())], value(stats) as f64)?;
+                    }
+                }
+
+                w.encode_gauge(
+                    "rumi_liquidatable_vault_count",
+                    s.liquidatable_vault_ids.len() as f64,
+                    "Vaults below their minimum liquidation ratio at the last refresh.",
+                )?;
+
+                w.encode_gauge(
+                    "rumi_near_liquidation_vault_count",
+                    s.near_liquidation_vaults.len() as f64,
+                    "Vaults within the alert band of their minimum liquidation ratio.",
+                )?;
+
+                w.encode_gauge(
+                    "rumi_liquidatable_set_refreshes",
+                    s.liquidatable_refresh_stats.refreshes as f64,
+                    "Liquidatable-set refreshes triggered by price updates.",
+                )?;
+
+                w.encode_gauge(
+                    "rumi_icp_rate",
+                    s.last_icp_rate.unwrap_or(UsdIcp::from(dec!(0))).to_f64(),
+                    "ICP rate.",
+                )?;
+
+                let total_icp_dec = Decimal::from_u64(s.total_icp_margin_amount().0)
+                    .expect("failed to construct decimal from u64")
+                    / dec!(100_000_000);
 
                 w.encode_gauge(
                     "icp_total_ICP_margin",
@@ -6429,23 +7813,52 @@ fn http_request(req: HttpRequest) -> HttpResponse {
             None => 0,
         };
 
+        let trace = match req.raw_query_param("trace") {
+            Some(arg) => match rumi_protocol_backend::guard::TraceId::from_str(arg) {
+                Ok(trace) => Some(trace),
+                Err(_) => {
+                    return HttpResponseBuilder::bad_request()
+                        .with_body_and_content_length("failed to parse the 'trace' parameter")
+                        .build()
+                }
+            },
+            None => None,
+        };
+
+        let persisted = match req.raw_query_param("persisted") {
+            Some(arg) => match bool::from_str(arg) {
+                Ok(value) => value,
+                Err(_) => {
+                    return HttpResponseBuilder::bad_request()
+                        .with_body_and_content_length("failed to parse the 'persisted' parameter")
+                        .build()
+                }
+            },
+            None => false,
+        };
+
         let mut entries: Log = Default::default();
 
-        match req.raw_query_param("priority") {
-            Some(priority_str) => match Priority::from_str(priority_str) {
-                Ok(priority) => match priority {
-                    Priority::Info => entries.push_logs(Priority::Info),
-                    Priority::TraceXrc => entries.push_logs(Priority::TraceXrc),
-                    Priority::Debug => entries.push_logs(Priority::Debug),
+        if persisted {
+            // Critical entries from stable memory recorded before the running
+            // wasm started, i.e. before the last upgrade.
+            entries.push_persisted_critical(rumi_protocol_backend::logs::instance_started_at());
+        } else {
+            match req.raw_query_param("priority") {
+                Some(priority_str) => match Priority::from_str(priority_str) {
+                    Ok(priority) => entries.push_logs(priority),
+                    Err(_) => entries.push_all(),
                 },
-                Err(_) => entries.push_all(),
-            },
-            None => entries.push_all(),
+                None => entries.push_all(),
+            }
         }
 
         entries
             .entries
             .retain(|entry| entry.timestamp >= max_skip_timestamp);
+        if let Some(trace) = trace {
+            entries.retain_trace(trace);
+        }
         let mut entries_bytes: Vec<u8> = serde_json::to_string(&entries)
             .unwrap_or_default()
             .into_bytes();
@@ -6458,6 +7871,13 @@ fn http_request(req: HttpRequest) -> HttpResponse {
             .header("Content-Type", "application/json; charset=utf-8")
             .with_body_and_content_length(entries_bytes)
             .build()
+    } else if cfg!(feature = "test_endpoints") && req.path() == "/math_vectors" {
+        HttpResponseBuilder::ok()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .with_body_and_content_length(
+                rumi_protocol_backend::math_vectors::math_test_vectors_json().into_bytes(),
+            )
+            .build()
     } else if req.path() == "/dashboard" {
         use rumi_protocol_backend::dashboard::build_dashboard;
 
@@ -6525,6 +7945,7 @@ async fn recover_pending_transfer(vault_id: u64) -> Result<bool, ProtocolError>
         // memo) with process_pending_transfer's timer retry. transfer_idempotent
         // converts the ledger's Duplicate response to Ok, so a concurrent timer
         // retry and this manual recovery can never double-pay the owner.
+        let observed_seq = rumi_protocol_backend::storage::observe_events();
         let result = management::transfer_collateral_with_nonce(
             (transfer.margin - transfer_fee).to_u64(),
             transfer.owner,
@@ -6537,7 +7958,13 @@ async fn recover_pending_transfer(vault_id: u64) -> Result<bool, ProtocolError>
             Ok(block_index) => {
                 mutate_state(|s| match source {
                     "margin" => {
-                        event::record_margin_transfer(s, vault_id, caller, block_index);
+                        event::record_margin_transfer(
+                            s,
+                            vault_id,
+                            caller,
+                            block_index,
+                            observed_seq,
+                        );
                     }
                     _ => {
                         s.pending_excess_transfers.remove(&key);
@@ -6564,11 +7991,63 @@ async fn recover_pending_transfer(vault_id: u64) -> Result<bool, ProtocolError>
     }
 }
 
+/// Pending transfers dead-lettered after `MAX_PENDING_RETRIES` failed
+/// attempts. Developer-gated read (returns empty for non-developers).
+#[candid_method(query)]
+#[query]
+fn get_failed_transfers() -> Vec<rumi_protocol_backend::transfer_retry::FailedTransferView> {
+    let caller = ic_cdk::caller();
+    read_state(|s| {
+        if s.developer_principal != caller {
+            return Vec::new();
+        }
+        rumi_protocol_backend::transfer_retry::failed_transfers(s)
+    })
+}
+
+/// Depth of the pending-transfer queues, how many entries are backing off,
+/// and the age of the oldest one.
+#[candid_method(query)]
+#[query]
+fn get_pending_transfer_metrics() -> rumi_protocol_backend::transfer_retry::PendingTransferMetrics {
+    read_state(|s| rumi_protocol_backend::transfer_retry::metrics(s, ic_cdk::api::time()))
+}
+
+/// Requeue a dead-lettered transfer, or refund the redeemer of a
+/// dead-lettered redemption payout. Developer-gated. See `transfer_retry`.
+#[candid_method(update)]
+#[update]
+fn resolve_failed_transfer(
+    key: rumi_protocol_backend::transfer_retry::PendingTransferKey,
+    action: rumi_protocol_backend::transfer_retry::FailedTransferAction,
+) -> Result<(), ProtocolError> {
+    use rumi_protocol_backend::transfer_retry::{self, FailedTransferAction};
+    let caller = ic_cdk::caller();
+    read_state(|s| transfer_retry::check_resolver(s, caller))?;
+    match action {
+        FailedTransferAction::Requeue => {
+            mutate_state(|s| transfer_retry::requeue(s, key))?;
+            log!(INFO, "[resolve_failed_transfer] requeued {:?}", key);
+        }
+        FailedTransferAction::Refund => {
+            let refunded = mutate_state(|s| transfer_retry::refund(s, key, ic_cdk::api::time()))?;
+            log!(
+                INFO,
+                "[resolve_failed_transfer] reversed {:?}, refunding {} icUSD",
+                key,
+                refunded
+            );
+        }
+    }
+    scheduler::kick_pending_transfers(0);
+    Ok(())
+}
+
 // Add treasury configuration endpoint (developer only)
 #[candid_method(update)]
 #[update]
 async fn set_treasury_principal(treasury_principal: Principal) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_treasury_principal")?;
 
     // Only developer can set treasury principal
     let is_developer = read_state(|s| s.developer_principal == caller);
@@ -6602,7 +8081,7 @@ fn get_treasury_principal() -> Option<Principal> {
 async fn set_stability_pool_principal(
     stability_pool_principal: Principal,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_stability_pool_principal")?;
 
     // Only developer can set stability pool principal
     let is_developer = read_state(|s| s.developer_principal == caller);
@@ -6660,7 +8139,7 @@ async fn set_liquidation_bot_config(
     bot_principal: Principal,
     monthly_budget_e8s: u64,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_liquidation_bot_config")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -6717,7 +8196,7 @@ async fn reset_bot_budget(new_budget_e8s: u64) -> Result<(), ProtocolError> {
 async fn set_bot_allowed_collateral_types(
     collateral_types: Vec<Principal>,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_bot_allowed_collateral_types")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -6751,7 +8230,7 @@ fn get_bot_allowed_collateral_types() -> Vec<Principal> {
 #[candid_method(update)]
 #[update]
 async fn set_bot_cr_tolerance_bps(bps: u64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_bot_cr_tolerance_bps")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -6817,6 +8296,7 @@ async fn bot_claim_liquidation(vault_id: u64) -> Result<BotLiquidationResult, Pr
             "Caller is not the registered liquidation bot canister".to_string(),
         ));
     }
+    rumi_protocol_backend::vault::reject_if_reserved_for_pool(vault_id, caller)?;
 
     // BK-001/002: hold the per-vault liquidation lock across the claim so the
     // bot's collateral-seizing claim cannot interleave with an in-flight manual
@@ -6966,6 +8446,7 @@ async fn bot_claim_liquidation(vault_id: u64) -> Result<BotLiquidationResult, Pr
         if let Some(vault) = s.vault_id_to_vaults.get_mut(&vault_id) {
             vault.bot_processing = true;
         }
+        transition_or_log(s, vault_id, VaultStatus::Liquidating, now);
         s.bot_claims.insert(
             vault_id,
             rumi_protocol_backend::state::BotClaim {
@@ -7019,6 +8500,7 @@ async fn bot_confirm_liquidation(vault_id: u64) -> Result<(), ProtocolError> {
     })?;
 
     mutate_state(|s| {
+        let vault_before = s.vault_id_to_vaults.get(&vault_id).cloned();
         if let Some(vault) = s.vault_id_to_vaults.get_mut(&vault_id) {
             // AR-B-001 (audit 2026-06-09): saturate the debt write-down. A
             // non-saturating `-=` traps if anything reduced the vault's debt
@@ -7035,6 +8517,7 @@ async fn bot_confirm_liquidation(vault_id: u64) -> Result<(), ProtocolError> {
                 .saturating_sub(claim.collateral_amount);
             vault.bot_processing = false;
         }
+        transition_or_log(s, vault_id, VaultStatus::Active, ic_cdk::api::time());
 
         let event = rumi_protocol_backend::event::Event::PartialLiquidateVault {
             vault_id,
@@ -7049,6 +8532,7 @@ async fn bot_confirm_liquidation(vault_id: u64) -> Result<(), ProtocolError> {
             three_usd_reserves_e8s: None,
         };
         rumi_protocol_backend::storage::record_event(&event);
+        rumi_protocol_backend::liquidation_caps::note_liquidation(s, &event);
 
         s.bot_total_debt_covered_e8s += claim.debt_amount;
         s.bot_claims.remove(&vault_id);
@@ -7063,6 +8547,20 @@ async fn bot_confirm_liquidation(vault_id: u64) -> Result<(), ProtocolError> {
                 vault_id
             );
         }
+
+        // The owner's post-mortem; see `liquidation_receipts`.
+        if let Some(before) = &vault_before {
+            rumi_protocol_backend::liquidation_receipts::issue(
+                s,
+                before,
+                rumi_protocol_backend::liquidation_receipts::Settlement {
+                    liquidator: Some(caller),
+                    price: Decimal::from(claim.collateral_price_e8s) / dec!(100_000_000),
+                    ..Default::default()
+                },
+                ic_cdk::api::time(),
+            );
+        }
     });
 
     log!(
@@ -7161,6 +8659,7 @@ async fn bot_cancel_liquidation(vault_id: u64) -> Result<(), ProtocolError> {
         if let Some(vault) = s.vault_id_to_vaults.get_mut(&vault_id) {
             vault.bot_processing = false;
         }
+        transition_or_log(s, vault_id, VaultStatus::Active, ic_cdk::api::time());
         // Restore budget since this liquidation didn't go through
         s.bot_budget_remaining_e8s += claim.debt_amount;
         s.bot_claims.remove(&vault_id);
@@ -7280,6 +8779,7 @@ async fn dev_force_bot_liquidate(vault_id: u64) -> Result<BotLiquidationResult,
         if let Some(vault) = s.vault_id_to_vaults.get_mut(&vault_id) {
             vault.bot_processing = true;
         }
+        transition_or_log(s, vault_id, VaultStatus::Liquidating, now);
         s.bot_claims.insert(
             vault_id,
             rumi_protocol_backend::state::BotClaim {
@@ -7420,6 +8920,7 @@ async fn dev_force_partial_bot_liquidate(
         if let Some(vault) = s.vault_id_to_vaults.get_mut(&vault_id) {
             vault.bot_processing = true;
         }
+        transition_or_log(s, vault_id, VaultStatus::Liquidating, now);
         s.bot_claims.insert(
             vault_id,
             rumi_protocol_backend::state::BotClaim {
@@ -7572,6 +9073,17 @@ async fn dev_set_collateral_price(
     ))
 }
 
+/// Canonical collateral-math test vectors as JSON, for the frontend's drift
+/// tests (see `math_vectors`). Also served at `/math_vectors`. Computed on
+/// fixture states, so open to any caller.
+/// Compiled out of the mainnet wasm via `cfg(feature = "test_endpoints")`.
+#[cfg(feature = "test_endpoints")]
+#[candid_method(query)]
+#[query]
+fn dev_get_math_test_vectors() -> String {
+    rumi_protocol_backend::math_vectors::math_test_vectors_json()
+}
+
 #[candid_method(query)]
 #[query]
 fn get_bot_stats() -> BotStatsResponse {
@@ -7640,6 +9152,7 @@ fn admin_resolve_stuck_claim(
             }
             vault.bot_processing = false;
         }
+        transition_or_log(s, vault_id, VaultStatus::Active, ic_cdk::api::time());
         if !apply_debt_reduction {
             s.bot_budget_remaining_e8s += claim.debt_amount;
         }
@@ -7665,7 +9178,7 @@ fn admin_resolve_stuck_claim(
 #[candid_method(update)]
 #[update]
 async fn set_ckstable_repay_fee(new_rate: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_ckstable_repay_fee")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -7704,7 +9217,7 @@ fn get_ckstable_repay_fee() -> f64 {
 #[candid_method(update)]
 #[update]
 async fn set_min_icusd_amount(new_amount_e8s: u64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_min_icusd_amount")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -7740,7 +9253,7 @@ fn get_min_icusd_amount() -> u64 {
 #[candid_method(update)]
 #[update]
 async fn set_global_icusd_mint_cap(amount_e8s: u64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_global_icusd_mint_cap")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -7818,7 +9331,7 @@ async fn set_stable_ledger_principal(
     token_type: StableTokenType,
     principal: Principal,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_stable_ledger_principal")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -7846,7 +9359,7 @@ async fn set_stable_ledger_principal(
 #[candid_method(update)]
 #[update]
 async fn set_liquidation_bonus(new_rate: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_liquidation_bonus")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -7885,7 +9398,7 @@ fn get_liquidation_bonus() -> f64 {
 #[candid_method(update)]
 #[update]
 fn set_redemption_tier(ledger_canister_id: Principal, tier: u8) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_redemption_tier")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -7935,7 +9448,7 @@ fn get_redemption_tier(ledger_canister_id: Principal) -> Result<u8, ProtocolErro
 #[candid_method(update)]
 #[update]
 async fn set_borrowing_fee(new_rate: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_borrowing_fee")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -7974,7 +9487,7 @@ fn get_borrowing_fee() -> f64 {
 #[candid_method(update)]
 #[update]
 async fn set_redemption_fee_floor(new_rate: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_redemption_fee_floor")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -8013,7 +9526,7 @@ fn get_redemption_fee_floor() -> f64 {
 #[candid_method(update)]
 #[update]
 async fn set_redemption_fee_ceiling(new_rate: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_redemption_fee_ceiling")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -8047,6 +9560,18 @@ fn get_redemption_fee_ceiling() -> f64 {
     read_state(|s| s.redemption_fee_ceiling.to_f64())
 }
 
+/// A collateral's redemption base rate, decayed to now. Redemptions raise it
+/// by half the fraction of the collateral's debt they redeem; it halves
+/// every 12 hours without them.
+#[candid_method(query)]
+#[query]
+fn get_base_rate(collateral_type: Principal) -> f64 {
+    read_state(|s| {
+        s.get_base_rate_at(&collateral_type, ic_cdk::api::time())
+            .to_f64()
+    })
+}
+
 // ── Reserve redemption admin functions ──────────────────────────────
 
 /// Enable or disable reserve redemptions (developer only)
@@ -8115,7 +9640,7 @@ fn get_icpswap_routing_enabled() -> bool {
 #[candid_method(update)]
 #[update]
 async fn set_reserve_redemption_fee(new_rate: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_reserve_redemption_fee")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -8149,6 +9674,58 @@ fn get_reserve_redemption_fee() -> f64 {
     read_state(|s| s.reserve_redemption_fee.to_f64())
 }
 
+/// Add a stable token reserve redemptions pay from, or replace its
+/// settings (developer only). See `reserve_stables`.
+#[candid_method(update)]
+#[update]
+fn set_reserve_stable(
+    stable: rumi_protocol_backend::reserve_stables::ReserveStable,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_reserve_stable")?;
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only developer can configure reserve stables".to_string(),
+        ));
+    }
+    read_state(|s| rumi_protocol_backend::reserve_stables::validate(s, &stable))?;
+    log!(INFO, "[set_reserve_stable] {:?}", stable);
+    mutate_state(|s| rumi_protocol_backend::event::record_set_reserve_stable(s, stable));
+    Ok(())
+}
+
+/// Stop paying reserve redemptions in `ledger` (developer only). Its
+/// balance stays in the reserves.
+#[candid_method(update)]
+#[update]
+fn remove_reserve_stable(ledger: Principal) -> Result<(), ProtocolError> {
+    let caller = ic_cdk::caller();
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only developer can configure reserve stables".to_string(),
+        ));
+    }
+    let listed = read_state(|s| {
+        rumi_protocol_backend::reserve_stables::configured(s)
+            .iter()
+            .any(|stable| stable.ledger == ledger)
+    });
+    if !listed {
+        return Err(ProtocolError::GenericError(
+            "Ledger is not a reserve stable".to_string(),
+        ));
+    }
+    log!(INFO, "[remove_reserve_stable] {}", ledger);
+    mutate_state(|s| rumi_protocol_backend::event::record_remove_reserve_stable(s, ledger));
+    Ok(())
+}
+
+/// Reserve redemption stables, enabled or not, in priority order.
+#[candid_method(query)]
+#[query]
+fn get_reserve_stables() -> Vec<rumi_protocol_backend::reserve_stables::ReserveStable> {
+    read_state(rumi_protocol_backend::reserve_stables::configured)
+}
+
 // ── Admin safety functions (controller-only) ──────────────────────────────────
 
 fn require_controller() -> Result<(), ProtocolError> {
@@ -8166,14 +9743,15 @@ fn require_controller() -> Result<(), ProtocolError> {
 fn enter_recovery_mode() -> Result<(), ProtocolError> {
     require_controller()?;
     mutate_state(|s| {
-        s.mode = Mode::Recovery;
+        s.transition_mode(s.mode, Mode::Recovery, ModeTransitionReason::AdminOverride)?;
         s.manual_mode_override = true;
+        rumi_protocol_backend::event::record_mode_transitions(s);
         log!(
             INFO,
             "[admin] entered Recovery mode (manual override active)"
         );
-    });
-    Ok(())
+        Ok(())
+    })
 }
 
 /// Exit Recovery mode and re-enable automatic mode transitions based on
@@ -8183,14 +9761,19 @@ fn enter_recovery_mode() -> Result<(), ProtocolError> {
 fn exit_recovery_mode() -> Result<(), ProtocolError> {
     require_controller()?;
     mutate_state(|s| {
-        s.mode = Mode::GeneralAvailability;
+        s.transition_mode(
+            s.mode,
+            Mode::GeneralAvailability,
+            ModeTransitionReason::AdminOverride,
+        )?;
         s.manual_mode_override = false;
+        rumi_protocol_backend::event::record_mode_transitions(s);
         log!(
             INFO,
             "[admin] exited Recovery mode, automatic mode management restored"
         );
-    });
-    Ok(())
+        Ok(())
+    })
 }
 
 /// Emergency kill switch — halts ALL state-changing operations.
@@ -8234,6 +9817,27 @@ fn set_liquidation_frozen(frozen: bool) -> Result<(), ProtocolError> {
     Ok(())
 }
 
+/// Set the SHA-256 of the operator panel token, or `None` to turn the panel
+/// off (controllers only). See `ops_panel`.
+#[candid_method(update)]
+#[update]
+fn set_ops_panel_token_hash(hash: Option<Vec<u8>>) -> Result<(), ProtocolError> {
+    require_controller()?;
+    let hash = match hash {
+        Some(bytes) => Some(<[u8; 32]>::try_from(bytes.as_slice()).map_err(|_| {
+            ProtocolError::GenericError("The token hash must be 32 bytes".to_string())
+        })?),
+        None => None,
+    };
+    mutate_state(|s| s.ops_panel_token_hash = hash);
+    log!(
+        INFO,
+        "[admin] operator panel {}",
+        if hash.is_some() { "token set" } else { "off" }
+    );
+    Ok(())
+}
+
 /// Wave-5 LIQ-007: read the liquidation kill switch state.
 #[candid_method(query)]
 #[query]
@@ -8329,6 +9933,7 @@ async fn redeem_reserves(
     // redeem_collateral on its spillover branch, so the same ReadOnly gate
     // applies. See main.rs::redeem_collateral for the rationale.
     validate_mode()?;
+    validate_operation_not_paused(PausableOperation::ReserveRedemption)?;
     rumi_protocol_backend::vault::redeem_reserves(amount, preferred_token).await
 }
 
@@ -8338,25 +9943,18 @@ async fn redeem_reserves(
 fn get_reserve_balances() -> Vec<ReserveBalance> {
     // Note: This returns cached/approximate balances.
     // Actual balances require async inter-canister calls via the update version.
-    // For now we return the configured ledgers; actual balances fetched by frontend directly.
-    let mut balances = Vec::new();
+    // For now we return the enabled reserve stables, in priority order; actual
+    // balances fetched by frontend directly.
     read_state(|s| {
-        if let Some(ledger) = s.ckusdt_ledger_principal {
-            balances.push(ReserveBalance {
-                ledger,
+        rumi_protocol_backend::reserve_stables::enabled(s)
+            .into_iter()
+            .map(|stable| ReserveBalance {
+                ledger: stable.ledger,
                 balance: 0, // frontend queries ledger directly for live balance
-                symbol: "ckUSDT".to_string(),
-            });
-        }
-        if let Some(ledger) = s.ckusdc_ledger_principal {
-            balances.push(ReserveBalance {
-                ledger,
-                balance: 0,
-                symbol: "ckUSDC".to_string(),
-            });
-        }
-    });
-    balances
+                symbol: stable.symbol,
+            })
+            .collect()
+    })
 }
 
 /// Admin: mint icUSD to a recipient (developer only).
@@ -8434,7 +10032,7 @@ async fn admin_mint_icusd(
 #[candid_method(update)]
 #[update]
 async fn set_recovery_cr_multiplier(new_multiplier: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_recovery_cr_multiplier")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -8470,12 +10068,56 @@ fn get_recovery_cr_multiplier() -> f64 {
     read_state(|s| s.recovery_cr_multiplier.to_f64())
 }
 
+/// Set the Recovery exit band (developer only): the protocol leaves Recovery
+/// only once the TCR is this far above the recovery threshold. `None`
+/// restores the default of 0.05; `0` exits as soon as the threshold is met.
+/// Range 0.0–0.5.
+#[candid_method(update)]
+#[update]
+async fn set_recovery_exit_band(band: Option<f64>) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_recovery_exit_band")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set the Recovery exit band".to_string(),
+        ));
+    }
+    let ratio = match band {
+        Some(band) => {
+            rumi_protocol_backend::validate_f64_inclusive(
+                "recovery_exit_band",
+                band,
+                0.0,
+                rumi_protocol_backend::mode::MAX_RECOVERY_EXIT_BAND,
+            )
+            .map_err(ProtocolError::GenericError)?;
+            let band = Decimal::try_from(band).map_err(|_| {
+                ProtocolError::GenericError("Invalid Recovery exit band value".to_string())
+            })?;
+            Some(Ratio::from(band))
+        }
+        None => None,
+    };
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_recovery_exit_band(s, ratio);
+    });
+    log!(INFO, "[set_recovery_exit_band] Band set to: {:?}", band);
+    Ok(())
+}
+
+/// Get the Recovery exit band in effect.
+#[candid_method(query)]
+#[query]
+fn get_recovery_exit_band() -> f64 {
+    read_state(|s| s.get_recovery_exit_band().to_f64())
+}
+
 /// Set the global liquidation protocol share (fraction of liquidator's bonus profit).
 /// Default: 0.03 (3%). Range: 0.0–1.0.
 #[candid_method(update)]
 #[update]
 async fn set_liquidation_protocol_share(new_share: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_liquidation_protocol_share")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -8510,13 +10152,54 @@ fn get_liquidation_protocol_share() -> f64 {
     read_state(|s| s.liquidation_protocol_share.to_f64())
 }
 
+/// Set the flat tip (icUSD e8s) paid to whoever completes a
+/// `liquidate_vault`, funded from the liquidation penalty. 0 disables; at
+/// most `MAX_LIQUIDATION_TIP_E8S`.
+#[candid_method(update)]
+#[update]
+fn set_liquidation_tip(tip_e8s: u64) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_liquidation_tip")?;
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set the liquidation tip".to_string(),
+        ));
+    }
+    if tip_e8s > rumi_protocol_backend::MAX_LIQUIDATION_TIP_E8S {
+        return Err(ProtocolError::GenericError(format!(
+            "Liquidation tip must be at most {} e8s",
+            rumi_protocol_backend::MAX_LIQUIDATION_TIP_E8S
+        )));
+    }
+    mutate_state(|s| rumi_protocol_backend::event::record_set_liquidation_tip(s, tip_e8s));
+    log!(INFO, "[set_liquidation_tip] Tip set to {} e8s", tip_e8s);
+    Ok(())
+}
+
+#[candid_method(query)]
+#[query]
+fn get_liquidation_tip() -> u64 {
+    read_state(|s| s.liquidation_tip_e8s)
+}
+
+/// What `liquidate_vault(vault_id)` would pay its caller at the current
+/// price, tip included.
+#[candid_method(query)]
+#[query]
+fn get_liquidation_reward_preview(
+    vault_id: u64,
+) -> Result<rumi_protocol_backend::vault::LiquidationRewardPreview, ProtocolError> {
+    read_state(|s| {
+        rumi_protocol_backend::vault::liquidation_reward_preview(s, vault_id, ic_cdk::api::time())
+    })
+}
+
 /// Wave-8e LIQ-005: tune the per-fee fraction routed to deficit repayment.
 /// Default 0.5; bounded [0.0, 1.0]. 0.0 disables repayment; 1.0 routes the
 /// entire fee until the deficit is cleared.
 #[candid_method(update)]
 #[update]
 async fn set_deficit_repayment_fraction(new_fraction: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_deficit_repayment_fraction")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -8551,7 +10234,7 @@ async fn set_deficit_repayment_fraction(new_fraction: f64) -> Result<(), Protoco
 #[candid_method(update)]
 #[update]
 async fn set_deficit_readonly_threshold_e8s(new_threshold: u64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_deficit_readonly_threshold_e8s")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -8580,7 +10263,7 @@ async fn set_deficit_readonly_threshold_e8s(new_threshold: u64) -> Result<(), Pr
 #[candid_method(update)]
 #[update]
 async fn set_breaker_window_ns(new_window_ns: u64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_breaker_window_ns")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -8610,7 +10293,7 @@ async fn set_breaker_window_ns(new_window_ns: u64) -> Result<(), ProtocolError>
 #[candid_method(update)]
 #[update]
 async fn set_breaker_window_debt_ceiling_e8s(new_ceiling: u64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_breaker_window_debt_ceiling_e8s")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -8633,41 +10316,335 @@ async fn set_breaker_window_debt_ceiling_e8s(new_ceiling: u64) -> Result<(), Pro
     Ok(())
 }
 
-/// Wave-9c DOS-005: tune the alert-band width (in bps) used by
-/// `check_vaults` to bound the sorted-troves walk on band-only ticks.
-/// Default 1000 bps (10% headroom above the worst per-collateral
-/// `min_liquidation_ratio`). 0 disables the band (only vaults strictly
-/// below the worst floor are visited). Wider values trade cycle savings
-/// for safety margin against cross-collateral CR-key drift.
+/// Open a borrowing-fee rebate campaign funded from treasury fee revenue.
+/// Borrows inside `[start_ns, end_ns)` get `rebate_bps` of their fee back
+/// until `pool_e8s` is spent. Returns the campaign id. Developer-only.
 #[candid_method(update)]
 #[update]
-async fn set_check_vaults_alert_band_bps(new_band_bps: u64) -> Result<(), ProtocolError> {
+fn create_rebate_campaign(
+    arg: rumi_protocol_backend::campaigns::CreateRebateCampaignArg,
+) -> Result<u64, ProtocolError> {
     let caller = ic_cdk::caller();
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
-            "Only the developer principal can set check_vaults alert band".to_string(),
+            "Only the developer principal can create rebate campaigns".to_string(),
         ));
     }
-    mutate_state(|s| s.set_check_vaults_alert_band_bps(new_band_bps));
+    rumi_protocol_backend::campaigns::validate_create_arg(&arg, ic_cdk::api::time())
+        .map_err(ProtocolError::GenericError)?;
+    let name = arg.name.clone();
+    let campaign_id = mutate_state(|s| {
+        rumi_protocol_backend::event::record_rebate_campaign_created(s, arg)
+    });
     log!(
         INFO,
-        "[set_check_vaults_alert_band_bps] Alert band set to: {} bps ({:.2}% headroom)",
-        new_band_bps,
-        (new_band_bps as f64) / 100.0
+        "[create_rebate_campaign] Created campaign {} ({})",
+        campaign_id,
+        name
     );
-    Ok(())
+    Ok(campaign_id)
 }
 
-/// Wave-14a CDP-14: tune the XRC source-count floor. Default is 3 (set
-/// via `xrc::MIN_XRC_SOURCES`); 0 disables the gate entirely. Used by ops
-/// to slacken the requirement if XRC's aggregation degrades industry-
-/// wide, or to tighten it when more depth is available.
+/// Top up an open rebate campaign's pool. Developer-only.
 #[candid_method(update)]
 #[update]
-async fn set_min_xrc_sources_used(value: u32) -> Result<(), ProtocolError> {
+fn fund_rebate_campaign(campaign_id: u64, amount_e8s: u64) -> Result<(), ProtocolError> {
     let caller = ic_cdk::caller();
     let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can fund rebate campaigns".to_string(),
+        ));
+    }
+    if amount_e8s == 0 {
+        return Err(ProtocolError::GenericError(
+            "Funding amount must be non-zero".to_string(),
+        ));
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_rebate_campaign_funded(s, campaign_id, amount_e8s)
+    })
+    .map_err(ProtocolError::GenericError)?;
+    log!(
+        INFO,
+        "[fund_rebate_campaign] Added {} e8s to campaign {}",
+        amount_e8s,
+        campaign_id
+    );
+    Ok(())
+}
+
+/// Close a rebate campaign early. The unspent pool stays with the treasury;
+/// returns its size in e8s. Developer-only.
+#[candid_method(update)]
+#[update]
+fn close_rebate_campaign(campaign_id: u64) -> Result<u64, ProtocolError> {
+    let caller = ic_cdk::caller();
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can close rebate campaigns".to_string(),
+        ));
+    }
+    let unspent_e8s = mutate_state(|s| {
+        rumi_protocol_backend::event::record_rebate_campaign_closed(s, campaign_id)
+    })
+    .map_err(ProtocolError::GenericError)?;
+    log!(
+        INFO,
+        "[close_rebate_campaign] Closed campaign {} ({} e8s unspent)",
+        campaign_id,
+        unspent_e8s
+    );
+    Ok(unspent_e8s)
+}
+
+#[candid_method(query)]
+#[query]
+fn get_rebate_campaigns() -> Vec<rumi_protocol_backend::campaigns::RebateCampaign> {
+    read_state(|s| s.rebate_campaigns.values().cloned().collect())
+}
+
+/// Register (or replace) a session key that may repay and/or add margin on
+/// every vault the caller owns until `expires_at_ns`, optionally capped at
+/// `repay_limit_e8s` of icUSD repaid in total.
+#[candid_method(update)]
+#[update]
+fn register_session_key(
+    arg: rumi_protocol_backend::session_keys::RegisterSessionKeyArg,
+) -> Result<(), ProtocolError> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err(ProtocolError::AnonymousCallerNotAllowed);
+    }
+    read_state(|s| {
+        rumi_protocol_backend::session_keys::validate_register_arg(
+            s,
+            caller,
+            &arg,
+            ic_cdk::api::time(),
+        )
+    })
+    .map_err(ProtocolError::GenericError)?;
+    let session_principal = arg.session_principal;
+    let expires_at_ns = arg.expires_at_ns;
+    mutate_state(|s| rumi_protocol_backend::event::record_session_key_registered(s, caller, arg));
+    log!(
+        INFO,
+        "[register_session_key] {} registered session key {} (expires {})",
+        caller,
+        session_principal,
+        expires_at_ns
+    );
+    Ok(())
+}
+
+/// Revoke one of the caller's session keys with immediate effect.
+#[candid_method(update)]
+#[update]
+fn revoke_session_key(session_principal: Principal) -> Result<(), ProtocolError> {
+    let caller = ic_cdk::caller();
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_session_key_revoked(s, caller, session_principal)
+    })
+    .map_err(ProtocolError::GenericError)?;
+    log!(
+        INFO,
+        "[revoke_session_key] {} revoked session key {}",
+        caller,
+        session_principal
+    );
+    Ok(())
+}
+
+/// Session keys registered by the caller, including expired ones that have
+/// not been pruned yet.
+#[candid_method(query)]
+#[query]
+fn get_my_session_keys() -> Vec<rumi_protocol_backend::session_keys::SessionKey> {
+    let caller = ic_cdk::caller();
+    read_state(|s| {
+        s.session_keys
+            .values()
+            .filter(|k| k.owner == caller)
+            .cloned()
+            .collect()
+    })
+}
+
+/// Notifications for the caller's vaults, newest first. Entries are queued
+/// when a parameter change moves one of the caller's vaults to a different
+/// risk level, when a guardian freezes or unfreezes one, and when one is
+/// liquidated.
+#[candid_method(query)]
+#[query]
+fn get_my_notifications() -> Vec<rumi_protocol_backend::notifications::VaultNotification> {
+    let caller = ic_cdk::caller();
+    read_state(|s| {
+        s.vault_notifications
+            .get(&caller)
+            .map(|q| q.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    })
+}
+
+/// Drop the caller's notifications with `id <= up_to_id`. Returns how many
+/// were removed.
+#[candid_method(update)]
+#[update]
+fn dismiss_my_notifications(up_to_id: u64) -> u64 {
+    let caller = ic_cdk::caller();
+    mutate_state(|s| {
+        rumi_protocol_backend::notifications::dismiss_notifications(s, caller, up_to_id) as u64
+    })
+}
+
+/// The caller's language and notification preferences, the defaults if
+/// it stored none. See `user_preferences`.
+#[candid_method(query)]
+#[query]
+fn get_my_preferences() -> rumi_protocol_backend::user_preferences::UserPreferences {
+    let caller = ic_cdk::caller();
+    read_state(|s| rumi_protocol_backend::user_preferences::preferences(s, caller))
+}
+
+/// Replace the caller's preferences. Storing the defaults clears them.
+#[candid_method(update)]
+#[update]
+fn set_my_preferences(
+    preferences: rumi_protocol_backend::user_preferences::UserPreferences,
+) -> Result<(), ProtocolError> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err(ProtocolError::AnonymousCallerNotAllowed);
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::user_preferences::set_preferences(s, caller, preferences)
+    })
+    .map_err(ProtocolError::GenericError)
+}
+
+/// Fees `principal` paid in `period`: borrowing, redemption, stable-repay
+/// and liquidation penalties, with a total per token. For the principal
+/// itself, the developer or controllers. See `fee_invoice`.
+#[candid_method(query)]
+#[query]
+fn get_fee_invoice(
+    principal: Principal,
+    period: rumi_protocol_backend::EventTimeRange,
+) -> Result<rumi_protocol_backend::fee_invoice::FeeInvoice, ProtocolError> {
+    if ic_cdk::api::data_certificate().is_none() {
+        ic_cdk::trap("update call rejected");
+    }
+    let caller = ic_cdk::caller();
+    if caller != principal
+        && read_state(|s| s.developer_principal != caller)
+        && !ic_cdk::api::is_controller(&caller)
+    {
+        return Err(ProtocolError::CallerNotOwner);
+    }
+    if period.start_ns > period.end_ns {
+        return Err(ProtocolError::GenericError(
+            "period.start_ns must not be after end_ns".to_string(),
+        ));
+    }
+    read_state(|s| {
+        Ok(rumi_protocol_backend::fee_invoice::fee_invoice(
+            s,
+            principal,
+            period,
+            events().enumerate().map(|(idx, event)| (idx as u64, event)),
+        ))
+    })
+}
+
+/// Post-mortems of liquidations of the caller's vaults, newest first. Each
+/// is also announced by a `Liquidated` notification carrying its id, unless
+/// the caller opted out of them.
+#[candid_method(query)]
+#[query]
+fn get_my_liquidation_receipts(
+) -> Vec<rumi_protocol_backend::liquidation_receipts::LiquidationReceipt> {
+    let caller = ic_cdk::caller();
+    read_state(|s| {
+        s.liquidation_receipts
+            .get(&caller)
+            .map(|q| q.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    })
+}
+
+/// Before/after history of one admin parameter, oldest first. `name` is the
+/// setter event tag without `set_` (e.g. `collateral_liquidation_ratio`);
+/// pass the returned `next_cursor` back to read the next page.
+#[candid_method(query)]
+#[query]
+fn get_parameter_history(
+    name: String,
+    cursor: Option<u64>,
+) -> rumi_protocol_backend::parameter_journal::ParameterHistoryPage {
+    use rumi_protocol_backend::parameter_journal::{parameter_history, MAX_PARAMETER_HISTORY_PAGE};
+    read_state(|s| parameter_history(s, &name, cursor, MAX_PARAMETER_HISTORY_PAGE))
+}
+
+/// Origination records (fee, fee rate, collateral price, time) of the latest
+/// borrows against `vault_id`, oldest first; pass the returned `next_cursor`
+/// back to read the next page. See `borrow_records` for what is kept.
+#[candid_method(query)]
+#[query]
+fn get_borrow_records(
+    vault_id: u64,
+    cursor: Option<u64>,
+) -> rumi_protocol_backend::borrow_records::BorrowRecordPage {
+    use rumi_protocol_backend::borrow_records::{borrow_records_page, MAX_BORROW_RECORDS_PAGE};
+    read_state(|s| borrow_records_page(s, vault_id, cursor, MAX_BORROW_RECORDS_PAGE))
+}
+
+/// Look up a session key by its principal, so a bot can check its own scopes.
+#[candid_method(query)]
+#[query]
+fn get_session_key(
+    session_principal: Principal,
+) -> Option<rumi_protocol_backend::session_keys::SessionKey> {
+    read_state(|s| s.session_keys.get(&session_principal).cloned())
+}
+
+/// Wave-9c DOS-005: tune the alert-band width (in bps) used by
+/// `check_vaults` to bound the sorted-troves walk on band-only ticks.
+/// Default 1000 bps (10% headroom above the worst per-collateral
+/// `min_liquidation_ratio`). 0 disables the band (only vaults strictly
+/// below the worst floor are visited). Wider values trade cycle savings
+/// for safety margin against cross-collateral CR-key drift.
+#[candid_method(update)]
+#[update]
+async fn set_check_vaults_alert_band_bps(new_band_bps: u64) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_check_vaults_alert_band_bps")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set check_vaults alert band".to_string(),
+        ));
+    }
+    mutate_state(|s| s.set_check_vaults_alert_band_bps(new_band_bps));
+    log!(
+        INFO,
+        "[set_check_vaults_alert_band_bps] Alert band set to: {} bps ({:.2}% headroom)",
+        new_band_bps,
+        (new_band_bps as f64) / 100.0
+    );
+    Ok(())
+}
+
+/// Wave-14a CDP-14: tune the XRC source-count floor. Default is 3 (set
+/// via `xrc::MIN_XRC_SOURCES`); 0 disables the gate entirely. Used by ops
+/// to slacken the requirement if XRC's aggregation degrades industry-
+/// wide, or to tighten it when more depth is available.
+#[candid_method(update)]
+#[update]
+async fn set_min_xrc_sources_used(value: u32) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_min_xrc_sources_used")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
             "Only the developer principal can set the XRC source-count floor".to_string(),
@@ -8700,7 +10677,7 @@ async fn set_min_xrc_sources_used(value: u32) -> Result<(), ProtocolError> {
 #[candid_method(update)]
 #[update]
 async fn set_xrc_fetch_interval_secs(secs: u64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_xrc_fetch_interval_secs")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -8733,7 +10710,7 @@ async fn set_xrc_fetch_interval_secs(secs: u64) -> Result<(), ProtocolError> {
 #[candid_method(update)]
 #[update]
 async fn set_interest_treasury_tick_interval_secs(secs: u64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_interest_treasury_tick_interval_secs")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -8767,7 +10744,7 @@ async fn set_interest_treasury_tick_interval_secs(secs: u64) -> Result<(), Proto
 #[candid_method(update)]
 #[update]
 async fn set_vault_check_tick_interval_secs(secs: u64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_vault_check_tick_interval_secs")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -8811,7 +10788,7 @@ async fn set_collateral_price_fetch_interval_secs(
     collateral: Principal,
     secs: u64,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_collateral_price_fetch_interval_secs")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -8891,7 +10868,7 @@ fn get_collateral_price_fetch_intervals() -> Vec<(Principal, u64)> {
 #[candid_method(update)]
 #[update]
 async fn set_settlement_tick_interval_secs(secs: u64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_settlement_tick_interval_secs")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -8919,7 +10896,7 @@ async fn set_settlement_tick_interval_secs(secs: u64) -> Result<(), ProtocolErro
 #[candid_method(update)]
 #[update]
 async fn set_chain_interest_tick_interval_secs(secs: u64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_chain_interest_tick_interval_secs")?;
     if read_state(|s| s.developer_principal != caller) {
         return Err(ProtocolError::GenericError(
             "Only the developer principal can set the chain interest tick interval".to_string(),
@@ -8946,7 +10923,7 @@ async fn set_chain_interest_tick_interval_secs(secs: u64) -> Result<(), Protocol
 #[candid_method(update)]
 #[update]
 async fn set_chain_interest_min_realize_e8s(e8s: u128) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_chain_interest_min_realize_e8s")?;
     if read_state(|s| s.developer_principal != caller) {
         return Err(ProtocolError::GenericError(
             "Only the developer principal can set the chain interest dust floor".to_string(),
@@ -8987,7 +10964,7 @@ fn validate_ecdsa_key_change(name: &str, has_chain_vaults: bool) -> Result<(), P
 #[candid_method(update)]
 #[update]
 fn set_chains_ecdsa_key_name(name: String) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_chains_ecdsa_key_name")?;
     if read_state(|s| s.developer_principal != caller) {
         return Err(ProtocolError::ChainAdmin("not developer".into()));
     }
@@ -9079,7 +11056,7 @@ async fn get_chain_reserve_address(
 #[candid_method(update)]
 #[update]
 async fn set_observer_tick_interval_secs(secs: u64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_observer_tick_interval_secs")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -9262,7 +11239,7 @@ async fn solana_bootstrap_nonce(blockhash: Option<String>) -> Result<(), Protoco
 #[candid_method(update)]
 #[update]
 async fn set_sol_rpc_principal(p: Principal) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_sol_rpc_principal")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -9306,7 +11283,7 @@ async fn set_solana_workers_enabled(enabled: bool) -> Result<(), ProtocolError>
 #[candid_method(update)]
 #[update]
 async fn set_check_vaults_full_sweep_every_n_ticks(new_n: u64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_check_vaults_full_sweep_every_n_ticks")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -9359,7 +11336,7 @@ async fn clear_liquidation_breaker() -> Result<(), ProtocolError> {
 #[candid_method(update)]
 #[update]
 async fn set_interest_pool_share(new_share: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_interest_pool_share")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -9402,7 +11379,7 @@ fn get_interest_pool_share() -> f64 {
 #[candid_method(update)]
 #[update]
 async fn set_interest_split(recipients: Vec<InterestSplitArg>) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_interest_split")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -9508,7 +11485,7 @@ fn get_interest_split() -> Vec<InterestSplitArg> {
 #[candid_method(update)]
 #[update]
 async fn set_interest_flush_threshold(threshold_e8s: u64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_interest_flush_threshold")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -9536,7 +11513,7 @@ async fn set_interest_flush_threshold(threshold_e8s: u64) -> Result<(), Protocol
 #[candid_method(update)]
 #[update]
 async fn set_three_pool_canister(canister_id: Principal) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_three_pool_canister")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -9565,7 +11542,7 @@ fn get_three_pool_canister() -> Option<Principal> {
 #[candid_method(update)]
 #[update]
 async fn set_amm1_canister(canister: Principal) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_amm1_canister")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -9597,7 +11574,7 @@ fn get_amm1_canister() -> Option<Principal> {
 #[candid_method(update)]
 #[update]
 async fn set_amm1_pool_id(pool_id: String) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_amm1_pool_id")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -9686,7 +11663,7 @@ fn get_rmr_ceiling_cr() -> f64 {
 #[candid_method(update)]
 #[update]
 async fn set_rmr_floor(value: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_rmr_floor")?;
     let (is_dev, ceiling) =
         read_state(|s| (s.developer_principal == caller, s.rmr_ceiling.to_f64()));
     if !is_dev {
@@ -9725,7 +11702,7 @@ async fn set_rmr_floor(value: f64) -> Result<(), ProtocolError> {
 #[candid_method(update)]
 #[update]
 async fn set_rmr_ceiling(value: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_rmr_ceiling")?;
     let (is_dev, floor) = read_state(|s| (s.developer_principal == caller, s.rmr_floor.to_f64()));
     if !is_dev {
         return Err(ProtocolError::GenericError(
@@ -9763,7 +11740,7 @@ async fn set_rmr_ceiling(value: f64) -> Result<(), ProtocolError> {
 #[candid_method(update)]
 #[update]
 async fn set_rmr_floor_cr(value: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_rmr_floor_cr")?;
     let (is_dev, ceiling_cr) =
         read_state(|s| (s.developer_principal == caller, s.rmr_ceiling_cr.to_f64()));
     if !is_dev {
@@ -9802,7 +11779,7 @@ async fn set_rmr_floor_cr(value: f64) -> Result<(), ProtocolError> {
 #[candid_method(update)]
 #[update]
 async fn set_rmr_ceiling_cr(value: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_rmr_ceiling_cr")?;
     let (is_dev, floor_cr) =
         read_state(|s| (s.developer_principal == caller, s.rmr_floor_cr.to_f64()));
     if !is_dev {
@@ -9896,6 +11873,28 @@ fn get_treasury_stats() -> TreasuryStats {
     })
 }
 
+/// Anonymized aggregates over the vault set: size buckets, debt percentiles,
+/// liquidation frequency and average collateral ratio. Served from the
+/// snapshot refreshed on the vault-check tick; computed inline only before
+/// the first tick after an install or upgrade.
+#[candid_method(query)]
+#[query]
+fn get_public_stats() -> rumi_protocol_backend::public_stats::PublicStats {
+    read_state(|s| {
+        s.public_stats_snapshot
+            .clone()
+            .unwrap_or_else(|| rumi_protocol_backend::public_stats::compute(s, ic_cdk::api::time()))
+    })
+}
+
+/// Every ledger the protocol works with (icUSD, collaterals, repayment
+/// stables, 3USD) with its cached ICRC-1 metadata, refreshed every few hours.
+#[candid_method(query)]
+#[query]
+fn get_supported_assets() -> Vec<rumi_protocol_backend::asset_registry::SupportedAsset> {
+    read_state(rumi_protocol_backend::asset_registry::supported_assets)
+}
+
 /// Number of minted stability-pool interest payments awaiting acknowledgement
 /// from the stability pool. These entries are retried by the periodic treasury
 /// tick and are deliberately exposed so production release checks can verify
@@ -9906,6 +11905,14 @@ fn get_pending_stability_pool_interest_notification_count() -> u64 {
     read_state(|s| s.pending_stability_pool_interest_notifications.len() as u64)
 }
 
+/// Number of treasury deposit notifications awaiting a retry. The funds are
+/// at the treasury; these are deposits its books do not show yet.
+#[candid_method(query)]
+#[query]
+fn get_pending_treasury_deposit_count() -> u64 {
+    read_state(|s| s.pending_treasury_deposits.len() as u64)
+}
+
 /// Get the effective recovery target CR (threshold × multiplier)
 #[candid_method(query)]
 #[query]
@@ -9918,7 +11925,7 @@ fn get_recovery_target_cr() -> f64 {
 #[candid_method(update)]
 #[update]
 async fn set_recovery_target_cr(new_rate: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_recovery_target_cr")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -9964,7 +11971,7 @@ async fn set_recovery_parameters(
     recovery_borrowing_fee: Option<f64>,
     recovery_interest_rate_apr: Option<f64>,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_recovery_parameters")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -10029,7 +12036,7 @@ async fn set_interest_rate(
     collateral_type: Principal,
     interest_rate_apr: f64,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_interest_rate")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -10068,7 +12075,7 @@ async fn set_collateral_borrowing_fee(
     collateral_type: Principal,
     borrowing_fee: f64,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_collateral_borrowing_fee")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -10108,7 +12115,7 @@ async fn set_rate_curve_markers(
     collateral_type: Option<Principal>,
     markers: Vec<(f64, f64)>,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_rate_curve_markers")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -10170,7 +12177,7 @@ async fn set_rate_curve_markers(
 #[candid_method(update)]
 #[update]
 async fn set_recovery_rate_curve(markers: Vec<(String, f64)>) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_recovery_rate_curve")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -10217,7 +12224,7 @@ async fn set_recovery_rate_curve(markers: Vec<(String, f64)>) -> Result<(), Prot
 #[candid_method(update)]
 #[update]
 async fn set_borrowing_fee_curve(curve_json: Option<String>) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_borrowing_fee_curve")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -10254,7 +12261,7 @@ async fn set_healthy_cr(
     collateral_type: Principal,
     healthy_cr: Option<f64>,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_healthy_cr")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -10293,18 +12300,49 @@ async fn set_healthy_cr(
         .transpose()
         .map_err(|_| ProtocolError::GenericError("Invalid healthy_cr value".to_string()))?
         .map(Ratio::from);
-    mutate_state(|s| {
-        rumi_protocol_backend::event::record_set_healthy_cr(s, collateral_type, ratio);
+    let ((), notified) = mutate_state(|s| {
+        rumi_protocol_backend::notifications::with_risk_notifications(
+            s,
+            collateral_type,
+            "healthy_cr",
+            ic_cdk::api::time(),
+            |s| rumi_protocol_backend::event::record_set_healthy_cr(s, collateral_type, ratio),
+        )
     });
     log!(
         INFO,
-        "[set_healthy_cr] collateral={}, healthy_cr={:?}",
+        "[set_healthy_cr] collateral={}, healthy_cr={:?}, {} risk notifications queued",
         collateral_type,
-        healthy_cr
+        healthy_cr,
+        notified
     );
     Ok(())
 }
 
+/// Query: interest accrued on a vault, including interest earned since the
+/// last accrual tick that is not yet booked into its debt.
+#[candid_method(query)]
+#[query]
+fn get_accrued_interest(vault_id: u64) -> Result<AccruedInterest, ProtocolError> {
+    let now = ic_cdk::api::time();
+    let interest_rate_apr = get_vault_interest_rate(vault_id)?;
+    read_state(|s| {
+        let vault = s
+            .vault_id_to_vaults
+            .get(&vault_id)
+            .ok_or_else(|| ProtocolError::GenericError(format!("Vault {} not found", vault_id)))?;
+        let pending = s.pending_interest(vault_id, now);
+        Ok(AccruedInterest {
+            vault_id,
+            accrued_interest_e8s: vault.accrued_interest.to_u64(),
+            pending_interest_e8s: pending.to_u64(),
+            current_debt_e8s: (vault.borrowed_icusd_amount + pending).to_u64(),
+            last_accrual_time: vault.last_accrual_time,
+            interest_rate_apr,
+        })
+    })
+}
+
 /// Query: get the current dynamic interest rate for a specific vault.
 #[candid_method(query)]
 #[query]
@@ -10431,6 +12469,10 @@ async fn add_collateral_token(
             "Only developer can add collateral types".to_string(),
         ));
     }
+    read_state(rumi_protocol_backend::emergency_shutdown::check_not_shut_down)?;
+
+    rumi_protocol_backend::state::validate_price_source(&arg.price_source)
+        .map_err(ProtocolError::GenericError)?;
 
     // Check it doesn't already exist
     let already_exists = read_state(|s| s.collateral_configs.contains_key(&arg.ledger_canister_id));
@@ -10483,7 +12525,7 @@ async fn add_collateral_token(
             }
         };
 
-    use rumi_protocol_backend::state::{CollateralConfig, CollateralStatus};
+    use rumi_protocol_backend::state::{CollateralConfig, CollateralStatus, LiquidationRebateMode};
 
     let config = CollateralConfig {
         ledger_canister_id: arg.ledger_canister_id,
@@ -10523,6 +12565,12 @@ async fn add_collateral_token(
         // separate path once its deposit flow is wired (spec P5); not settable here.
         custody_kind: None,
         symbol: symbol_opt.clone(),
+        liquidation_rebate_mode: LiquidationRebateMode::CollateralPayout,
+        secondary_price_source: None,
+        price_disputed: false,
+        last_price_std_dev: None,
+        price_confidence_k: None,
+        maintenance_fee_apr: None,
     };
 
     mutate_state(|s| {
@@ -10703,6 +12751,12 @@ async fn set_collateral_status(
             "Collateral type not found".to_string(),
         ));
     }
+    if read_state(|s| s.collateral_settlements.contains_key(&collateral_type)) {
+        return Err(ProtocolError::GenericError(
+            "A settled collateral stays frozen".to_string(),
+        ));
+    }
+    read_state(rumi_protocol_backend::emergency_shutdown::check_not_shut_down)?;
     if collateral_type == rumi_protocol_backend::state::xrp_collateral_principal()
         && !matches!(
             status,
@@ -10742,7 +12796,7 @@ async fn set_collateral_min_xrc_sources(
     collateral_type: Principal,
     min_xrc_sources: Option<u32>,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_collateral_min_xrc_sources")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -10788,7 +12842,7 @@ async fn set_collateral_debt_ceiling(
     collateral_type: Principal,
     debt_ceiling: u64,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_collateral_debt_ceiling")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -10822,7 +12876,7 @@ async fn set_collateral_debt_ceiling(
 #[candid_method(update)]
 #[update]
 async fn set_lst_haircut(collateral_type: Principal, haircut: f64) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_lst_haircut")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -10867,7 +12921,7 @@ async fn set_collateral_liquidation_ratio(
     collateral_type: Principal,
     liquidation_ratio: f64,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_collateral_liquidation_ratio")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -10903,18 +12957,27 @@ async fn set_collateral_liquidation_ratio(
         Ratio::from(Decimal::try_from(liquidation_ratio).map_err(|_| {
             ProtocolError::GenericError("Invalid liquidation_ratio value".to_string())
         })?);
-    mutate_state(|s| {
-        rumi_protocol_backend::event::record_set_collateral_liquidation_ratio(
+    let ((), notified) = mutate_state(|s| {
+        rumi_protocol_backend::notifications::with_risk_notifications(
             s,
             collateral_type,
-            ratio,
-        );
+            "liquidation_ratio",
+            ic_cdk::api::time(),
+            |s| {
+                rumi_protocol_backend::event::record_set_collateral_liquidation_ratio(
+                    s,
+                    collateral_type,
+                    ratio,
+                )
+            },
+        )
     });
     log!(
         INFO,
-        "[set_collateral_liquidation_ratio] collateral={}, liquidation_ratio={}",
+        "[set_collateral_liquidation_ratio] collateral={}, liquidation_ratio={}, {} risk notifications queued",
         collateral_type,
-        liquidation_ratio
+        liquidation_ratio,
+        notified
     );
     Ok(())
 }
@@ -10928,7 +12991,7 @@ async fn set_collateral_borrow_threshold(
     collateral_type: Principal,
     borrow_threshold_ratio: f64,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_collateral_borrow_threshold")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -10978,18 +13041,84 @@ async fn set_collateral_borrow_threshold(
     let ratio = Ratio::from(Decimal::try_from(borrow_threshold_ratio).map_err(|_| {
         ProtocolError::GenericError("Invalid borrow_threshold_ratio value".to_string())
     })?);
-    mutate_state(|s| {
-        rumi_protocol_backend::event::record_set_collateral_borrow_threshold(
+    let ((), notified) = mutate_state(|s| {
+        rumi_protocol_backend::notifications::with_risk_notifications(
             s,
             collateral_type,
-            ratio,
-        );
+            "borrow_threshold_ratio",
+            ic_cdk::api::time(),
+            |s| {
+                rumi_protocol_backend::event::record_set_collateral_borrow_threshold(
+                    s,
+                    collateral_type,
+                    ratio,
+                )
+            },
+        )
     });
     log!(
         INFO,
-        "[set_collateral_borrow_threshold] collateral={}, borrow_threshold_ratio={}",
+        "[set_collateral_borrow_threshold] collateral={}, borrow_threshold_ratio={}, {} risk notifications queued",
         collateral_type,
-        borrow_threshold_ratio
+        borrow_threshold_ratio,
+        notified
+    );
+    Ok(())
+}
+
+/// Apply several parameter changes as one transaction (developer only).
+/// Each change is checked against its single setter's bounds and the batch
+/// against the cross-parameter constraints (liquidation_ratio <
+/// borrow_threshold_ratio < healthy_cr, redemption fee floor ≤ ceiling) as
+/// it would leave them; a rejected batch changes nothing. Recorded as one
+/// `ApplyParameterBatch` event. See `parameter_batch`.
+#[candid_method(update)]
+#[update]
+async fn admin_apply_parameter_batch(
+    updates: Vec<rumi_protocol_backend::parameter_batch::ParameterUpdate>,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("admin_apply_parameter_batch")?;
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can apply parameter batches".to_string(),
+        ));
+    }
+    let now = ic_cdk::api::time();
+    let (changes, notified) = mutate_state(|s| {
+        let batch = rumi_protocol_backend::parameter_batch::validate(s, &updates)?;
+        let changes = batch.len();
+        let collaterals: std::collections::BTreeSet<Principal> =
+            batch.iter().filter_map(|c| c.collateral_type()).collect();
+        let before: Vec<_> = collaterals
+            .into_iter()
+            .map(|ct| {
+                (
+                    ct,
+                    rumi_protocol_backend::notifications::risk_snapshot(s, ct),
+                )
+            })
+            .collect();
+        rumi_protocol_backend::event::record_apply_parameter_batch(s, batch, now);
+        let notified: usize = before
+            .iter()
+            .map(|(ct, before)| {
+                rumi_protocol_backend::notifications::notify_risk_changes(
+                    s,
+                    *ct,
+                    before,
+                    "parameter_batch",
+                    now,
+                )
+            })
+            .sum();
+        Ok::<_, String>((changes, notified))
+    })
+    .map_err(ProtocolError::GenericError)?;
+    log!(
+        INFO,
+        "[admin_apply_parameter_batch] applied {} changes, {} risk notifications queued",
+        changes,
+        notified
     );
     Ok(())
 }
@@ -11002,7 +13131,7 @@ async fn set_collateral_liquidation_bonus(
     collateral_type: Principal,
     liquidation_bonus: f64,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_collateral_liquidation_bonus")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -11045,7 +13174,7 @@ async fn set_collateral_min_vault_debt(
     collateral_type: Principal,
     min_vault_debt: u64,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_collateral_min_vault_debt")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -11083,7 +13212,7 @@ async fn set_collateral_ledger_fee(
     collateral_type: Principal,
     ledger_fee: u64,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_collateral_ledger_fee")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -11120,7 +13249,7 @@ async fn set_collateral_redemption_fee_floor(
     collateral_type: Principal,
     redemption_fee_floor: f64,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_collateral_redemption_fee_floor")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -11180,7 +13309,7 @@ async fn set_collateral_redemption_fee_ceiling(
     collateral_type: Principal,
     redemption_fee_ceiling: f64,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_collateral_redemption_fee_ceiling")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -11240,7 +13369,7 @@ async fn set_collateral_min_deposit(
     collateral_type: Principal,
     min_collateral_deposit: u64,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("set_collateral_min_deposit")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -11253,66 +13382,1563 @@ async fn set_collateral_min_deposit(
             "Unknown collateral type".to_string(),
         ));
     }
-    mutate_state(|s| {
-        rumi_protocol_backend::event::record_set_collateral_min_deposit(
-            s,
-            collateral_type,
-            min_collateral_deposit,
-        );
-    });
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_collateral_min_deposit(
+            s,
+            collateral_type,
+            min_collateral_deposit,
+        );
+    });
+    log!(
+        INFO,
+        "[set_collateral_min_deposit] collateral={}, min_collateral_deposit={}",
+        collateral_type,
+        min_collateral_deposit
+    );
+    Ok(())
+}
+
+/// Set the display color (hex) for a collateral type, used by frontend (developer only).
+/// Pass None to clear.
+#[candid_method(update)]
+#[update]
+async fn set_collateral_display_color(
+    collateral_type: Principal,
+    display_color: Option<String>,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_collateral_display_color")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set display color".to_string(),
+        ));
+    }
+    if let Some(ref c) = display_color {
+        if !c.starts_with('#') || (c.len() != 4 && c.len() != 7 && c.len() != 9) {
+            return Err(ProtocolError::GenericError(
+                "display_color must be a hex color like #RGB, #RRGGBB, or #RRGGBBAA".to_string(),
+            ));
+        }
+    }
+    let exists = read_state(|s| s.collateral_configs.contains_key(&collateral_type));
+    if !exists {
+        return Err(ProtocolError::GenericError(
+            "Unknown collateral type".to_string(),
+        ));
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_collateral_display_color(
+            s,
+            collateral_type,
+            display_color.clone(),
+        );
+    });
+    log!(
+        INFO,
+        "[set_collateral_display_color] collateral={}, display_color={:?}",
+        collateral_type,
+        display_color
+    );
+    Ok(())
+}
+
+/// Choose whether a full liquidation returns the owner's leftover collateral
+/// as a transfer or credits it as collateral to the owner's riskiest other
+/// vault of the same collateral (developer only). Native-XRP collateral only
+/// supports `CollateralPayout`.
+#[candid_method(update)]
+#[update]
+async fn set_collateral_liquidation_rebate_mode(
+    collateral_type: Principal,
+    mode: rumi_protocol_backend::state::LiquidationRebateMode,
+) -> Result<(), ProtocolError> {
+    use rumi_protocol_backend::state::LiquidationRebateMode;
+
+    let caller = timelock_caller("set_collateral_liquidation_rebate_mode")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set the liquidation rebate mode".to_string(),
+        ));
+    }
+    let is_native_xrp = match read_state(|s| {
+        s.get_collateral_config(&collateral_type)
+            .map(|c| c.is_native_xrp())
+    }) {
+        Some(is_native_xrp) => is_native_xrp,
+        None => {
+            return Err(ProtocolError::GenericError(
+                "Unknown collateral type".to_string(),
+            ))
+        }
+    };
+    if is_native_xrp && mode == LiquidationRebateMode::CollateralTopUp {
+        return Err(ProtocolError::GenericError(
+            "Native-XRP collateral only supports CollateralPayout".to_string(),
+        ));
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_collateral_liquidation_rebate_mode(
+            s,
+            collateral_type,
+            mode,
+        );
+    });
+    log!(
+        INFO,
+        "[set_collateral_liquidation_rebate_mode] collateral={}, mode={:?}",
+        collateral_type,
+        mode
+    );
+    Ok(())
+}
+
+/// Configure (or remove with `None`) the oracle that XRC prices for a
+/// collateral are cross-checked against before being applied (developer
+/// only). `max_divergence_bps` must be in 1..=10_000. Removing the source
+/// also lifts an open price dispute.
+#[candid_method(update)]
+#[update]
+async fn set_collateral_secondary_price_source(
+    collateral_type: Principal,
+    source: Option<rumi_protocol_backend::state::SecondaryPriceSource>,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_collateral_secondary_price_source")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set the secondary price source".to_string(),
+        ));
+    }
+    if read_state(|s| s.get_collateral_config(&collateral_type).is_none()) {
+        return Err(ProtocolError::GenericError(
+            "Unknown collateral type".to_string(),
+        ));
+    }
+    if let Some(source) = &source {
+        if source.max_divergence_bps == 0 || source.max_divergence_bps > 10_000 {
+            return Err(ProtocolError::GenericError(
+                "max_divergence_bps must be between 1 and 10000".to_string(),
+            ));
+        }
+        if source.method.is_empty() {
+            return Err(ProtocolError::GenericError(
+                "Secondary price source method must not be empty".to_string(),
+            ));
+        }
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_collateral_secondary_price_source(
+            s,
+            collateral_type,
+            source.clone(),
+        );
+    });
+    log!(
+        INFO,
+        "[set_collateral_secondary_price_source] collateral={}, source={:?}",
+        collateral_type,
+        source
+    );
+    Ok(())
+}
+
+/// Set how many XRC standard deviations borrow and withdrawal checks take
+/// off a collateral's price (developer only). `None` or `0` checks at the
+/// mid-price; liquidations always use the mid-price. Range 0.0–5.0.
+#[candid_method(update)]
+#[update]
+async fn set_collateral_price_confidence(
+    collateral_type: Principal,
+    k: Option<f64>,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_collateral_price_confidence")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set the price confidence".to_string(),
+        ));
+    }
+    if read_state(|s| s.get_collateral_config(&collateral_type).is_none()) {
+        return Err(ProtocolError::GenericError(
+            "Unknown collateral type".to_string(),
+        ));
+    }
+    let ratio = match k {
+        Some(k) => {
+            rumi_protocol_backend::validate_f64_inclusive(
+                "k",
+                k,
+                0.0,
+                rumi_protocol_backend::state::MAX_PRICE_CONFIDENCE_K,
+            )
+            .map_err(ProtocolError::GenericError)?;
+            let k = Decimal::try_from(k)
+                .map_err(|_| ProtocolError::GenericError("Invalid k value".to_string()))?;
+            (!k.is_zero()).then(|| Ratio::from(k))
+        }
+        None => None,
+    };
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_collateral_price_confidence(
+            s,
+            collateral_type,
+            ratio,
+        );
+    });
+    log!(
+        INFO,
+        "[set_collateral_price_confidence] collateral={}, k={:?}",
+        collateral_type,
+        k
+    );
+    Ok(())
+}
+
+/// Set a collateral's annual maintenance fee (developer only): a charge on
+/// the USD value of every open vault's collateral, borrowing or not, added
+/// to the vault's debt by interest accrual. Applies from now on; `None` or
+/// `0` removes it. Range 0.0–0.05.
+#[candid_method(update)]
+#[update]
+async fn set_collateral_maintenance_fee(
+    collateral_type: Principal,
+    maintenance_fee_apr: Option<f64>,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_collateral_maintenance_fee")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set the maintenance fee".to_string(),
+        ));
+    }
+    if read_state(|s| s.get_collateral_config(&collateral_type).is_none()) {
+        return Err(ProtocolError::GenericError(
+            "Unknown collateral type".to_string(),
+        ));
+    }
+    let ratio = match maintenance_fee_apr {
+        Some(fee) => {
+            rumi_protocol_backend::validate_f64_inclusive(
+                "maintenance_fee_apr",
+                fee,
+                0.0,
+                rumi_protocol_backend::state::MAX_MAINTENANCE_FEE_APR,
+            )
+            .map_err(ProtocolError::GenericError)?;
+            let fee = Decimal::try_from(fee).map_err(|_| {
+                ProtocolError::GenericError("Invalid maintenance fee value".to_string())
+            })?;
+            (!fee.is_zero()).then(|| Ratio::from(fee))
+        }
+        None => None,
+    };
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_collateral_maintenance_fee(
+            s,
+            collateral_type,
+            ratio,
+            ic_cdk::api::time(),
+        );
+    });
+    log!(
+        INFO,
+        "[set_collateral_maintenance_fee] collateral={}, maintenance_fee_apr={:?}",
+        collateral_type,
+        maintenance_fee_apr
+    );
+    Ok(())
+}
+
+/// Cap how much of a collateral's debt can be redeemed per epoch (developer
+/// only). `None` removes the cap. Setting or removing a cap resets the
+/// current epoch's usage.
+#[candid_method(update)]
+#[update]
+async fn set_collateral_redemption_cap(
+    collateral_type: Principal,
+    config: Option<rumi_protocol_backend::redemption_caps::RedemptionCapConfig>,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_collateral_redemption_cap")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set redemption caps".to_string(),
+        ));
+    }
+    if read_state(|s| s.get_collateral_config(&collateral_type).is_none()) {
+        return Err(ProtocolError::GenericError(
+            "Unknown collateral type".to_string(),
+        ));
+    }
+    if let Some(config) = &config {
+        rumi_protocol_backend::redemption_caps::validate_config(config)
+            .map_err(ProtocolError::GenericError)?;
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_collateral_redemption_cap(
+            s,
+            collateral_type,
+            config,
+        );
+    });
+    log!(
+        INFO,
+        "[set_collateral_redemption_cap] collateral={}, config={:?}",
+        collateral_type,
+        config
+    );
+    Ok(())
+}
+
+/// Cap how much collateral liquidations seize per call and per window
+/// (developer only). `None` removes the cap. Setting or removing a cap
+/// resets the current window's usage.
+#[candid_method(update)]
+#[update]
+async fn set_collateral_liquidation_cap(
+    collateral_type: Principal,
+    config: Option<rumi_protocol_backend::liquidation_caps::LiquidationCapConfig>,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_collateral_liquidation_cap")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set liquidation caps".to_string(),
+        ));
+    }
+    if read_state(|s| s.get_collateral_config(&collateral_type).is_none()) {
+        return Err(ProtocolError::GenericError(
+            "Unknown collateral type".to_string(),
+        ));
+    }
+    if let Some(config) = &config {
+        rumi_protocol_backend::liquidation_caps::validate_config(config)
+            .map_err(ProtocolError::GenericError)?;
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_collateral_liquidation_cap(
+            s,
+            collateral_type,
+            config,
+        );
+    });
+    log!(
+        INFO,
+        "[set_collateral_liquidation_cap] collateral={}, config={:?}",
+        collateral_type,
+        config
+    );
+    Ok(())
+}
+
+/// Configure the price-deviation breaker (developer only): reject a fresh
+/// price that moves more than `max_deviation_bps` from the last accepted
+/// one within `window_ns`, optionally holding the protocol in Recovery.
+/// `None` turns it off and releases any hold.
+#[candid_method(update)]
+#[update]
+async fn set_price_deviation_breaker(
+    config: Option<rumi_protocol_backend::xrc::PriceDeviationBreaker>,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_price_deviation_breaker")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set the price deviation breaker".to_string(),
+        ));
+    }
+    if let Some(config) = &config {
+        rumi_protocol_backend::xrc::validate_price_deviation_breaker(config)
+            .map_err(ProtocolError::GenericError)?;
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_price_deviation_breaker(s, config);
+    });
+    log!(INFO, "[set_price_deviation_breaker] config={:?}", config);
+    Ok(())
+}
+
+#[candid_method(query)]
+#[query]
+fn get_price_deviation_breaker() -> rumi_protocol_backend::xrc::PriceDeviationBreakerStatus {
+    use rumi_protocol_backend::xrc::PriceDeviationBreakerStatus;
+    read_state(|s| PriceDeviationBreakerStatus {
+        config: s.price_deviation_breaker,
+        holding_recovery: s.price_deviation_holds.iter().copied().collect(),
+    })
+}
+
+/// Samples rejected per collateral since its last accepted price. Only the
+/// first rejection of each kind in a run is on the event log.
+#[candid_method(query)]
+#[query]
+fn get_price_rejections() -> Vec<(Principal, rumi_protocol_backend::xrc::PriceRejections)> {
+    read_state(|s| {
+        s.price_rejections
+            .iter()
+            .map(|(collateral_type, rejections)| (*collateral_type, *rejections))
+            .collect()
+    })
+}
+
+/// Set a collateral's absolute price bounds in USD e8s per whole token
+/// (developer only). A fetched price outside them is rejected, the old
+/// price kept and a `PriceOutOfBounds` event recorded. `None` goes back to
+/// `xrc::DEFAULT_PRICE_BOUNDS`.
+#[candid_method(update)]
+#[update]
+async fn set_collateral_price_bounds(
+    collateral_type: Principal,
+    bounds: Option<rumi_protocol_backend::xrc::PriceBounds>,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_collateral_price_bounds")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set price bounds".to_string(),
+        ));
+    }
+    if read_state(|s| s.get_collateral_config(&collateral_type).is_none()) {
+        return Err(ProtocolError::GenericError(
+            "Unknown collateral type".to_string(),
+        ));
+    }
+    if let Some(bounds) = &bounds {
+        rumi_protocol_backend::xrc::validate_price_bounds(bounds)
+            .map_err(ProtocolError::GenericError)?;
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_collateral_price_bounds(
+            s,
+            collateral_type,
+            bounds,
+        );
+    });
+    log!(
+        INFO,
+        "[set_collateral_price_bounds] collateral={}, bounds={:?}",
+        collateral_type,
+        bounds
+    );
+    Ok(())
+}
+
+/// Price bounds in force for a collateral, defaults included.
+#[candid_method(query)]
+#[query]
+fn get_collateral_price_bounds(
+    collateral_type: Principal,
+) -> rumi_protocol_backend::xrc::PriceBounds {
+    read_state(|s| rumi_protocol_backend::xrc::price_bounds(s, &collateral_type))
+}
+
+/// Mint `amount` icUSD to an allowlisted `callback_canister`, call its
+/// `callback_method`, and pull back `amount` plus the fee, which it must
+/// approve before returning. See `flash_mint`.
+#[candid_method(update)]
+#[update]
+async fn flash_mint(
+    amount: u64,
+    callback_canister: Principal,
+    callback_method: String,
+) -> Result<rumi_protocol_backend::flash_mint::FlashMintSuccess, ProtocolError> {
+    slo_tracked("flash_mint", async move {
+        validate_call().await?;
+        validate_mode()?;
+        read_state(rumi_protocol_backend::emergency_shutdown::check_not_shut_down)?;
+        check_postcondition(
+            traced(rumi_protocol_backend::flash_mint::flash_mint(
+                amount,
+                callback_canister,
+                callback_method,
+            ))
+            .await,
+        )
+    })
+    .await
+}
+
+/// Enable flash mints with a fee, per-mint cap and callback allowlist, or
+/// disable them with `None` (developer only).
+#[candid_method(update)]
+#[update]
+async fn set_flash_mint_config(
+    config: Option<rumi_protocol_backend::flash_mint::FlashMintConfig>,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_flash_mint_config")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can configure flash mints".to_string(),
+        ));
+    }
+    if let Some(config) = &config {
+        rumi_protocol_backend::flash_mint::validate_config(config)
+            .map_err(ProtocolError::GenericError)?;
+    }
+    log!(INFO, "[set_flash_mint_config] config={:?}", config);
+    mutate_state(|s| rumi_protocol_backend::event::record_set_flash_mint_config(s, config));
+    Ok(())
+}
+
+#[candid_method(query)]
+#[query]
+fn get_flash_mint_config() -> Option<rumi_protocol_backend::flash_mint::FlashMintConfig> {
+    read_state(|s| s.flash_mint.clone())
+}
+
+/// Donate icUSD or a registered collateral to the surplus buffer, pulled
+/// with `icrc2_transfer_from` (approve the backend first). Donated icUSD
+/// repays the protocol deficit first. See `donations`.
+#[candid_method(update)]
+#[update]
+async fn donate(
+    arg: rumi_protocol_backend::donations::DonateArg,
+) -> Result<rumi_protocol_backend::donations::DonationReceipt, ProtocolError> {
+    slo_tracked("donate", async move {
+        if ic_cdk::caller() == Principal::anonymous() {
+            return Err(ProtocolError::AnonymousCallerNotAllowed);
+        }
+        if read_state(|s| s.frozen) {
+            return Err(ProtocolError::TemporarilyUnavailable(
+                "Protocol is frozen. All operations are suspended pending admin review."
+                    .to_string(),
+            ));
+        }
+        traced(rumi_protocol_backend::donations::donate(arg)).await
+    })
+    .await
+}
+
+/// Per ledger, the surplus buffer balance and everything donated in it.
+#[candid_method(query)]
+#[query]
+fn get_surplus_buffer() -> Vec<rumi_protocol_backend::donations::SurplusBufferEntry> {
+    read_state(rumi_protocol_backend::donations::surplus_buffer)
+}
+
+/// Cumulative donations per donor and ledger, of `donor` only when given.
+#[candid_method(query)]
+#[query]
+fn get_donations(donor: Option<Principal>) -> Vec<rumi_protocol_backend::donations::DonationTotal> {
+    read_state(|s| rumi_protocol_backend::donations::donation_totals(s, donor))
+}
+
+/// The surplus buffer's fee share, balances and lifetime flows. See
+/// `surplus`.
+#[candid_method(query)]
+#[query]
+fn get_surplus_status() -> rumi_protocol_backend::surplus::SurplusStatus {
+    read_state(rumi_protocol_backend::surplus::status)
+}
+
+/// Set the share of borrowing, vault redemption and liquidation fees kept
+/// in the surplus buffer, in [0.0, 1.0] (developer only).
+#[candid_method(update)]
+#[update]
+fn set_surplus_fee_share(share: f64) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_surplus_fee_share")?;
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set the surplus fee share".to_string(),
+        ));
+    }
+    let share = rumi_protocol_backend::surplus::fee_share_from(share)
+        .map_err(ProtocolError::GenericError)?;
+    mutate_state(|s| rumi_protocol_backend::event::record_set_surplus_fee_share(s, share));
+    log!(
+        INFO,
+        "[set_surplus_fee_share] share set to {}",
+        share.to_f64()
+    );
+    Ok(())
+}
+
+/// Mint `amount` of the icUSD surplus buffer to the treasury (developer
+/// only). Returns the mint's block index.
+#[candid_method(update)]
+#[update]
+async fn sweep_surplus_to_treasury(amount: u64) -> Result<u64, ProtocolError> {
+    let caller = ic_cdk::caller();
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can sweep the surplus buffer".to_string(),
+        ));
+    }
+    rumi_protocol_backend::surplus::sweep_to_treasury(amount).await
+}
+
+/// Settled collaterals: their price, pool and what is left to claim and
+/// redeem. See `collateral_settlement`.
+#[candid_method(query)]
+#[query]
+fn get_collateral_settlements() -> Vec<CollateralSettlementStatus> {
+    read_state(rumi_protocol_backend::collateral_settlement::status)
+}
+
+/// Freeze `collateral_type` for good and settle its vaults at its last price
+/// (developer only). See `collateral_settlement`.
+#[candid_method(update)]
+#[update]
+fn settle_collateral(
+    collateral_type: Principal,
+) -> Result<CollateralSettlementStatus, ProtocolError> {
+    let caller = ic_cdk::caller();
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can settle a collateral".to_string(),
+        ));
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::collateral_settlement::settle_collateral(
+            s,
+            collateral_type,
+            caller,
+            ic_cdk::api::time(),
+        )
+    })
+}
+
+/// Close the caller's vault of a settled collateral and queue the collateral
+/// left after settlement back to them. Returns the amount queued.
+#[candid_method(update)]
+#[update]
+async fn claim_settled_vault(vault_id: u64) -> Result<u64, ProtocolError> {
+    slo_tracked("claim_settled_vault", async move {
+        validate_caller()?;
+        validate_pending_room(PayoutQueue::Collateral)?;
+        rumi_protocol_backend::collateral_settlement::claim_settled_vault(vault_id)
+    })
+    .await
+}
+
+/// Burn icUSD for its pro-rata share of a settled collateral's pool.
+/// Returns the collateral queued, before the ledger fee.
+#[candid_method(update)]
+#[update]
+async fn redeem_settled_collateral(
+    collateral_type: Principal,
+    icusd_amount: u64,
+) -> Result<u64, ProtocolError> {
+    slo_tracked("redeem_settled_collateral", async move {
+        validate_caller()?;
+        validate_pending_room(PayoutQueue::Redemption)?;
+        check_postcondition(
+            traced(
+                rumi_protocol_backend::collateral_settlement::redeem_settled_collateral(
+                    collateral_type,
+                    icusd_amount,
+                ),
+            )
+            .await,
+        )
+    })
+    .await
+}
+
+/// The emergency shutdown, if the protocol has been shut down. See
+/// `emergency_shutdown`.
+#[candid_method(query)]
+#[query]
+fn get_emergency_shutdown() -> Option<EmergencyShutdownStatus> {
+    read_state(rumi_protocol_backend::emergency_shutdown::status)
+}
+
+/// Shut the protocol down for good: freeze every collateral and fix the
+/// prices they settle at (developer only). See `emergency_shutdown`.
+#[candid_method(update)]
+#[update]
+fn emergency_shutdown() -> Result<EmergencyShutdownStatus, ProtocolError> {
+    let caller = ic_cdk::caller();
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can shut the protocol down".to_string(),
+        ));
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::emergency_shutdown::trigger(s, caller, ic_cdk::api::time())
+    })
+}
+
+/// Settle one collateral at the price the emergency shutdown fixed for it.
+/// Open to anyone once the protocol is shut down.
+#[candid_method(update)]
+#[update]
+fn settle_shutdown_collateral(
+    collateral_type: Principal,
+) -> Result<CollateralSettlementStatus, ProtocolError> {
+    validate_caller()?;
+    let caller = ic_cdk::caller();
+    mutate_state(|s| {
+        rumi_protocol_backend::emergency_shutdown::settle(
+            s,
+            collateral_type,
+            caller,
+            ic_cdk::api::time(),
+        )
+    })
+}
+
+/// Join the liquidator registry, or rename an existing entry, while
+/// self-registration is on. See `liquidators`.
+#[candid_method(update)]
+#[update]
+fn register_liquidator(name: Option<String>) -> Result<(), ProtocolError> {
+    let caller = ic_cdk::caller();
+    rumi_protocol_backend::liquidators::validate_name(&name)?;
+    mutate_state(|s| {
+        if !s.liquidator_self_registration && !s.liquidators.contains_key(&caller) {
+            return Err(ProtocolError::GenericError(
+                "Liquidator self-registration is closed".to_string(),
+            ));
+        }
+        rumi_protocol_backend::liquidators::check_register(s, &caller)?;
+        rumi_protocol_backend::event::record_register_liquidator(
+            s,
+            caller,
+            name,
+            true,
+            ic_cdk::api::time(),
+        );
+        Ok(())
+    })?;
+    log!(INFO, "[register_liquidator] {} registered", caller);
+    Ok(())
+}
+
+/// Add `liquidator` to the registry, or rename it (developer only).
+#[candid_method(update)]
+#[update]
+fn register_liquidator_admin(
+    liquidator: Principal,
+    name: Option<String>,
+) -> Result<(), ProtocolError> {
+    let caller = ic_cdk::caller();
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can register liquidators".to_string(),
+        ));
+    }
+    rumi_protocol_backend::liquidators::validate_name(&name)?;
+    mutate_state(|s| {
+        rumi_protocol_backend::liquidators::check_register(s, &liquidator)?;
+        rumi_protocol_backend::event::record_register_liquidator(
+            s,
+            liquidator,
+            name,
+            false,
+            ic_cdk::api::time(),
+        );
+        Ok::<_, ProtocolError>(())
+    })?;
+    log!(
+        INFO,
+        "[register_liquidator_admin] {} registered by {}",
+        liquidator,
+        caller
+    );
+    Ok(())
+}
+
+/// Remove `liquidator` and its stats from the registry. The developer can
+/// remove anyone, a liquidator itself.
+#[candid_method(update)]
+#[update]
+fn remove_liquidator(liquidator: Principal) -> Result<(), ProtocolError> {
+    let caller = ic_cdk::caller();
+    if caller != liquidator && read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::CallerNotOwner);
+    }
+    if !read_state(|s| s.liquidators.contains_key(&liquidator)) {
+        return Err(ProtocolError::GenericError(format!(
+            "{} is not a registered liquidator",
+            liquidator
+        )));
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_remove_liquidator(s, liquidator, ic_cdk::api::time())
+    });
+    log!(
+        INFO,
+        "[remove_liquidator] {} removed by {}",
+        liquidator,
+        caller
+    );
+    Ok(())
+}
+
+/// Open or close liquidator self-registration (developer only).
+#[candid_method(update)]
+#[update]
+fn set_liquidator_self_registration(enabled: bool) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_liquidator_self_registration")?;
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set liquidator self-registration".to_string(),
+        ));
+    }
+    log!(
+        INFO,
+        "[set_liquidator_self_registration] enabled={}",
+        enabled
+    );
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_liquidator_self_registration(s, enabled)
+    });
+    Ok(())
+}
+
+#[candid_method(query)]
+#[query]
+fn get_liquidator(
+    liquidator: Principal,
+) -> Option<rumi_protocol_backend::liquidators::LiquidatorEntry> {
+    read_state(|s| rumi_protocol_backend::liquidators::entry(s, &liquidator))
+}
+
+/// Registered liquidators ranked by the debt they cleared, at most
+/// `limit` (default and cap `MAX_LEADERBOARD_LEN`).
+#[candid_method(query)]
+#[query]
+fn get_liquidator_leaderboard(
+    limit: Option<u64>,
+) -> Vec<rumi_protocol_backend::liquidators::LiquidatorEntry> {
+    use rumi_protocol_backend::liquidators::{leaderboard, MAX_LEADERBOARD_LEN};
+    let limit = limit.map_or(MAX_LEADERBOARD_LEN, |l| l as usize);
+    read_state(|s| leaderboard(s, limit))
+}
+
+/// Configure ledger fee sponsorship of small operations (developer only).
+/// See `fee_sponsorship`.
+#[candid_method(update)]
+#[update]
+fn set_fee_sponsorship_config(
+    config: rumi_protocol_backend::fee_sponsorship::FeeSponsorshipConfig,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_fee_sponsorship_config")?;
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can configure fee sponsorship".to_string(),
+        ));
+    }
+    log!(INFO, "[set_fee_sponsorship_config] {:?}", config);
+    mutate_state(|s| rumi_protocol_backend::event::record_set_fee_sponsorship_config(s, config));
+    Ok(())
+}
+
+/// Mark `user` as eligible, or no longer eligible, for fee sponsorship
+/// (developer only).
+#[candid_method(update)]
+#[update]
+fn set_fee_sponsorship_verified(user: Principal, verified: bool) -> Result<(), ProtocolError> {
+    let caller = ic_cdk::caller();
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can verify users for fee sponsorship".to_string(),
+        ));
+    }
+    if user == Principal::anonymous() {
+        return Err(ProtocolError::AnonymousCallerNotAllowed);
+    }
+    log!(
+        INFO,
+        "[set_fee_sponsorship_verified] {} verified={}",
+        user,
+        verified
+    );
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_fee_sponsorship_verified(s, user, verified)
+    });
+    Ok(())
+}
+
+/// Add `amount` of a collateral ledger to the fee sponsorship pool, pulled
+/// with `icrc2_transfer_from` (treasury or developer only).
+#[candid_method(update)]
+#[update]
+async fn fund_fee_sponsorship(ledger: Principal, amount: u64) -> Result<u64, ProtocolError> {
+    let caller = ic_cdk::caller();
+    if read_state(|s| s.developer_principal != caller && s.treasury_principal != Some(caller)) {
+        return Err(ProtocolError::GenericError(
+            "Only the treasury or the developer can fund fee sponsorship".to_string(),
+        ));
+    }
+    rumi_protocol_backend::fee_sponsorship::fund(ledger, amount).await
+}
+
+/// Send unused sponsorship funds back to the treasury (developer only).
+#[candid_method(update)]
+#[update]
+async fn return_fee_sponsorship(ledger: Principal, amount: u64) -> Result<u64, ProtocolError> {
+    let caller = ic_cdk::caller();
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can return sponsorship funds".to_string(),
+        ));
+    }
+    rumi_protocol_backend::fee_sponsorship::return_to_treasury(ledger, amount).await
+}
+
+#[candid_method(query)]
+#[query]
+fn get_fee_sponsorship() -> rumi_protocol_backend::fee_sponsorship::FeeSponsorshipStatus {
+    read_state(rumi_protocol_backend::fee_sponsorship::status)
+}
+
+/// Whether `user` (default: the caller) is verified and how many sponsored
+/// operations it has left today.
+#[candid_method(query)]
+#[query]
+fn get_fee_sponsorship_allowance(
+    user: Option<Principal>,
+) -> rumi_protocol_backend::fee_sponsorship::FeeSponsorshipAllowance {
+    let user = user.unwrap_or_else(ic_cdk::caller);
+    read_state(|s| rumi_protocol_backend::fee_sponsorship::allowance(s, &user, ic_cdk::api::time()))
+}
+
+/// Manually end a collateral's price dispute (developer only). The next XRC
+/// sample is applied through the usual sanity band; if it still diverges
+/// from the secondary source the dispute reopens.
+#[candid_method(update)]
+#[update]
+async fn resolve_collateral_price_dispute(collateral_type: Principal) -> Result<(), ProtocolError> {
+    let caller = ic_cdk::caller();
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can resolve a price dispute".to_string(),
+        ));
+    }
+    match read_state(|s| {
+        s.get_collateral_config(&collateral_type)
+            .map(|c| c.price_disputed)
+    }) {
+        Some(true) => {}
+        Some(false) => {
+            return Err(ProtocolError::GenericError(
+                "Collateral price is not disputed".to_string(),
+            ))
+        }
+        None => {
+            return Err(ProtocolError::GenericError(
+                "Unknown collateral type".to_string(),
+            ))
+        }
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_price_dispute_cleared(
+            s,
+            collateral_type,
+            Some(caller),
+            ic_cdk::api::time(),
+        );
+    });
+    log!(
+        INFO,
+        "[resolve_collateral_price_dispute] collateral={}",
+        collateral_type
+    );
+    Ok(())
+}
+
+/// Replace the set of guardians allowed to freeze single vaults (developer
+/// only). The developer can always freeze, guardian or not.
+#[candid_method(update)]
+#[update]
+async fn set_guardian_principals(principals: Vec<Principal>) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_guardian_principals")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set guardian principals".to_string(),
+        ));
+    }
+    if principals.contains(&Principal::anonymous()) {
+        return Err(ProtocolError::GenericError(
+            "The anonymous principal cannot be a guardian".to_string(),
+        ));
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_guardian_principals(s, principals.clone());
+    });
+    log!(INFO, "[set_guardian_principals] guardians={:?}", principals);
+    Ok(())
+}
+
+/// Freeze one vault for `duration_secs` (at most 7 days): withdrawals,
+/// borrows and closes are rejected until it expires or is lifted, while
+/// repayments and margin top-ups still go through. Guardians and the
+/// developer only. Re-freezing a frozen vault replaces its expiry. Returns
+/// the expiry in nanoseconds.
+#[candid_method(update)]
+#[update]
+async fn freeze_vault(
+    arg: rumi_protocol_backend::vault_freeze::FreezeVaultArg,
+) -> Result<u64, ProtocolError> {
+    use rumi_protocol_backend::vault_freeze::{is_freeze_authority, validate_freeze_arg};
+    let caller = ic_cdk::caller();
+    if !read_state(|s| is_freeze_authority(s, caller)) {
+        return Err(ProtocolError::GenericError(
+            "Only a guardian or the developer principal can freeze a vault".to_string(),
+        ));
+    }
+    let expires_at_ns = read_state(|s| validate_freeze_arg(s, &arg, ic_cdk::api::time()))
+        .map_err(ProtocolError::GenericError)?;
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_vault_frozen(
+            s,
+            arg.vault_id,
+            caller,
+            arg.reason.clone(),
+            expires_at_ns,
+        );
+    });
+    log!(
+        INFO,
+        "[freeze_vault] {} froze vault #{} until {} ({})",
+        caller,
+        arg.vault_id,
+        expires_at_ns,
+        arg.reason
+    );
+    Ok(expires_at_ns)
+}
+
+/// Lift a vault freeze before it expires (guardians and the developer only).
+#[candid_method(update)]
+#[update]
+async fn unfreeze_vault(vault_id: u64) -> Result<(), ProtocolError> {
+    let caller = ic_cdk::caller();
+    if !read_state(|s| rumi_protocol_backend::vault_freeze::is_freeze_authority(s, caller)) {
+        return Err(ProtocolError::GenericError(
+            "Only a guardian or the developer principal can unfreeze a vault".to_string(),
+        ));
+    }
+    mutate_state(|s| rumi_protocol_backend::event::record_vault_unfrozen(s, vault_id, caller))
+        .map_err(ProtocolError::GenericError)?;
+    log!(
+        INFO,
+        "[unfreeze_vault] {} unfroze vault #{}",
+        caller,
+        vault_id
+    );
+    Ok(())
+}
+
+/// The live freeze on `vault_id`, if any.
+#[candid_method(query)]
+#[query]
+fn get_vault_freeze(vault_id: u64) -> Option<rumi_protocol_backend::vault_freeze::VaultFreeze> {
+    read_state(|s| {
+        rumi_protocol_backend::vault_freeze::active_freeze(s, vault_id, ic_cdk::api::time())
+            .cloned()
+    })
+}
+
+/// Co-approve one treasury withdrawal above the treasury's co-approval
+/// threshold for `ttl_secs` (at most 24 hours). Guardians and the developer
+/// only. Returns the expiry in nanoseconds.
+#[candid_method(update)]
+#[update]
+async fn approve_treasury_withdrawal(
+    request: rumi_protocol_backend::treasury_approvals::TreasuryWithdrawalRequest,
+    ttl_secs: u64,
+) -> Result<u64, ProtocolError> {
+    let caller = ic_cdk::caller();
+    if !read_state(|s| rumi_protocol_backend::vault_freeze::is_freeze_authority(s, caller)) {
+        return Err(ProtocolError::GenericError(
+            "Only a guardian or the developer principal can approve treasury withdrawals"
+                .to_string(),
+        ));
+    }
+    let expires_at_ns = read_state(|s| {
+        rumi_protocol_backend::treasury_approvals::validate_approval(
+            s,
+            &request,
+            ttl_secs,
+            ic_cdk::api::time(),
+        )
+    })
+    .map_err(ProtocolError::GenericError)?;
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_treasury_withdrawal_approved(
+            s,
+            request.clone(),
+            caller,
+            expires_at_ns,
+        )
+    });
+    log!(
+        INFO,
+        "[approve_treasury_withdrawal] {} approved request {}: {} of {} to {} until {}",
+        caller,
+        request.request_id,
+        request.amount,
+        request.ledger,
+        request.to,
+        expires_at_ns
+    );
+    Ok(expires_at_ns)
+}
+
+/// Withdraw an unused co-approval (guardians and the developer only).
+#[candid_method(update)]
+#[update]
+async fn revoke_treasury_withdrawal_approval(request_id: u64) -> Result<(), ProtocolError> {
+    let caller = ic_cdk::caller();
+    if !read_state(|s| rumi_protocol_backend::vault_freeze::is_freeze_authority(s, caller)) {
+        return Err(ProtocolError::GenericError(
+            "Only a guardian or the developer principal can revoke treasury approvals".to_string(),
+        ));
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_treasury_withdrawal_approval_revoked(
+            s, request_id, caller,
+        )
+    })
+    .map_err(ProtocolError::GenericError)?;
+    log!(
+        INFO,
+        "[revoke_treasury_withdrawal_approval] {} revoked request {}",
+        caller,
+        request_id
+    );
+    Ok(())
+}
+
+/// Treasury check, just before it transfers, that a withdrawal matches a
+/// live co-approval (treasury only). Retries of the same withdrawal pass
+/// until the approval expires.
+#[candid_method(update)]
+#[update]
+async fn consume_treasury_withdrawal_approval(
+    request: rumi_protocol_backend::treasury_approvals::TreasuryWithdrawalRequest,
+) -> Result<(), ProtocolError> {
+    let caller = ic_cdk::caller();
+    if read_state(|s| s.treasury_principal != Some(caller)) {
+        return Err(ProtocolError::GenericError(
+            "Only the treasury can use treasury withdrawal approvals".to_string(),
+        ));
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_treasury_withdrawal_approval_consumed(s, &request)
+    })
+    .map_err(ProtocolError::GenericError)?;
+    log!(
+        INFO,
+        "[consume_treasury_withdrawal_approval] treasury used request {}",
+        request.request_id
+    );
+    Ok(())
+}
+
+/// Co-approvals still live, lowest `request_id` first.
+#[candid_method(query)]
+#[query]
+fn get_treasury_withdrawal_approvals(
+) -> Vec<rumi_protocol_backend::treasury_approvals::TreasuryWithdrawalApproval> {
+    read_state(|s| rumi_protocol_backend::treasury_approvals::approvals(s, ic_cdk::api::time()))
+}
+
+/// Turn the Recovery-mode stability-pool priority on or off and set how long
+/// a routed vault stays reserved for the pool (developer only).
+#[candid_method(update)]
+#[update]
+async fn set_recovery_pool_priority(
+    config: rumi_protocol_backend::pool_priority::PoolPriorityConfig,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_recovery_pool_priority")?;
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set the recovery pool priority".to_string(),
+        ));
+    }
+    rumi_protocol_backend::pool_priority::validate_config(&config)
+        .map_err(ProtocolError::GenericError)?;
+    log!(
+        INFO,
+        "[set_recovery_pool_priority] enabled={}, window={}s",
+        config.enabled,
+        config.window_ns / 1_000_000_000
+    );
+    mutate_state(|s| rumi_protocol_backend::event::record_set_recovery_pool_priority(s, config));
+    Ok(())
+}
+
+/// Recovery-mode stability-pool priority: configuration, the last pool depth
+/// and the live reservations.
+#[candid_method(query)]
+#[query]
+fn get_recovery_pool_priority() -> rumi_protocol_backend::pool_priority::RecoveryPoolPriorityStatus
+{
+    read_state(|s| rumi_protocol_backend::pool_priority::status(s, ic_cdk::api::time()))
+}
+
+/// Set the pending-transfer thresholds past which closes, withdrawals and
+/// redemptions are rejected (developer only). 0 turns a check off.
+#[candid_method(update)]
+#[update]
+async fn set_pending_backpressure(
+    config: rumi_protocol_backend::pending_backpressure::PendingBackpressureConfig,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_pending_backpressure")?;
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set the pending-transfer backpressure".to_string(),
+        ));
+    }
+    rumi_protocol_backend::pending_backpressure::validate_config(&config)
+        .map_err(ProtocolError::GenericError)?;
+    log!(
+        INFO,
+        "[set_pending_backpressure] collateral={}, redemption={}",
+        config.max_pending_collateral_transfers,
+        config.max_pending_redemption_transfers
+    );
+    mutate_state(|s| rumi_protocol_backend::event::record_set_pending_backpressure(s, config));
+    Ok(())
+}
+
+/// Pending-queue backpressure: thresholds, current queue sizes, whether each
+/// check is rejecting, and rejection counts.
+#[candid_method(query)]
+#[query]
+fn get_pending_backpressure(
+) -> rumi_protocol_backend::pending_backpressure::PendingBackpressureStatus {
+    read_state(rumi_protocol_backend::pending_backpressure::status)
+}
+
+/// Set (`Some`) or clear (`None`) the SLO thresholds of `endpoint`, or the
+/// default thresholds when `endpoint` is `None` (developer only).
+#[candid_method(update)]
+#[update]
+async fn set_slo_thresholds(
+    endpoint: Option<String>,
+    thresholds: Option<rumi_protocol_backend::slo::SloThresholds>,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_slo_thresholds")?;
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set SLO thresholds".to_string(),
+        ));
+    }
+    if endpoint
+        .as_ref()
+        .is_some_and(|e| e.is_empty() || e.len() > 64)
+    {
+        return Err(ProtocolError::GenericError(
+            "Endpoint name must be 1 to 64 characters".to_string(),
+        ));
+    }
+    if let Some(thresholds) = &thresholds {
+        rumi_protocol_backend::slo::validate_thresholds(thresholds)
+            .map_err(ProtocolError::GenericError)?;
+    }
+    log!(
+        INFO,
+        "[set_slo_thresholds] endpoint={:?}, thresholds={:?}",
+        endpoint,
+        thresholds
+    );
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_slo_thresholds(s, endpoint, thresholds)
+    });
+    Ok(())
+}
+
+/// Per-endpoint SLO thresholds and stats since the last upgrade.
+#[candid_method(query)]
+#[query]
+fn get_slo_status() -> Vec<rumi_protocol_backend::slo::EndpointSlo> {
+    read_state(rumi_protocol_backend::slo::status)
+}
+
+/// The last startup self-test, or `None` before the first one since the
+/// upgrade has finished.
+#[candid_method(query)]
+#[query]
+fn get_selftest_report() -> Option<rumi_protocol_backend::selftest::SelftestReport> {
+    rumi_protocol_backend::selftest::last_report()
+}
+
+/// Every background task registered since the last upgrade: its cadence,
+/// last and next run, and run and error counters.
+#[candid_method(query)]
+#[query]
+fn get_scheduler_status() -> Vec<rumi_protocol_backend::scheduler::TaskStatus> {
+    scheduler::status()
+}
+
+/// Run the startup self-test now and return its report (developer only).
+/// Failures are logged and recorded as a `SelftestFailed` event like the
+/// post-upgrade run.
+#[candid_method(update)]
+#[update]
+async fn run_selftest() -> Result<rumi_protocol_backend::selftest::SelftestReport, ProtocolError> {
+    let caller = ic_cdk::caller();
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can run the self-test".to_string(),
+        ));
+    }
+    rumi_protocol_backend::selftest::run().await
+}
+
+/// Resize the per-priority log buffers and the persisted critical-log ring
+/// (developer only). Shrinking drops the oldest entries.
+#[candid_method(update)]
+#[update]
+async fn set_log_retention(
+    config: rumi_protocol_backend::logs::LogRetentionConfig,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_log_retention")?;
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set the log retention".to_string(),
+        ));
+    }
+    rumi_protocol_backend::logs::validate_retention(&config)
+        .map_err(ProtocolError::GenericError)?;
+    log!(
+        INFO,
+        "[set_log_retention] critical={}, info={}, debug={}, trace_xrc={}, persisted_critical={}",
+        config.critical_capacity,
+        config.info_capacity,
+        config.debug_capacity,
+        config.trace_xrc_capacity,
+        config.persisted_critical_capacity
+    );
+    mutate_state(|s| rumi_protocol_backend::event::record_set_log_retention(s, config));
+    rumi_protocol_backend::logs::apply_retention(&config);
+    Ok(())
+}
+
+/// Log buffer sizes and how many critical entries are persisted.
+#[candid_method(query)]
+#[query]
+fn get_log_retention() -> rumi_protocol_backend::logs::LogRetentionStatus {
+    read_state(|s| rumi_protocol_backend::logs::retention_status(&s.log_retention))
+}
+
+/// Principals currently allowed to freeze vaults, besides the developer.
+#[candid_method(query)]
+#[query]
+fn get_guardian_principals() -> Vec<Principal> {
+    read_state(|s| s.guardian_principals.iter().copied().collect())
+}
+
+/// Replace the canisters (stability pool, treasury) that are told about every
+/// protocol mode change (developer only). Each must accept
+/// `set_protocol_mode` from this canister. The current mode is pushed to the
+/// new set straight away.
+#[candid_method(update)]
+#[update]
+async fn set_mode_companion_canisters(canisters: Vec<Principal>) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_mode_companion_canisters")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set mode companion canisters".to_string(),
+        ));
+    }
+    rumi_protocol_backend::mode_propagation::validate_companions(&canisters)
+        .map_err(ProtocolError::GenericError)?;
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_mode_companion_canisters(s, canisters.clone());
+    });
+    rumi_protocol_backend::mode_propagation::schedule_mode_propagation();
+    log!(
+        INFO,
+        "[set_mode_companion_canisters] companions={:?}",
+        canisters
+    );
+    Ok(())
+}
+
+/// Whitelist (`Some`) or remove (`None`) the DEX route for swapping vault
+/// collateral from `from_collateral_type` to `to_collateral_type`.
+#[candid_method(update)]
+#[update]
+async fn set_collateral_swap_route(
+    from_collateral_type: Principal,
+    to_collateral_type: Principal,
+    route: Option<rumi_protocol_backend::collateral_swap::CollateralSwapRoute>,
+) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_collateral_swap_route")?;
+    if !read_state(|s| s.developer_principal == caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set collateral swap routes".to_string(),
+        ));
+    }
+    if let Some(route) = &route {
+        if route.from != from_collateral_type || route.to != to_collateral_type {
+            return Err(ProtocolError::GenericError(
+                "The route must connect the given collateral types".to_string(),
+            ));
+        }
+        read_state(|s| rumi_protocol_backend::collateral_swap::validate_route(s, route))
+            .map_err(ProtocolError::GenericError)?;
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_collateral_swap_route(
+            s,
+            from_collateral_type,
+            to_collateral_type,
+            route.clone(),
+        )
+    });
+    log!(
+        INFO,
+        "[set_collateral_swap_route] {} -> {}: {:?}",
+        from_collateral_type,
+        to_collateral_type,
+        route
+    );
+    Ok(())
+}
+
+#[candid_method(query)]
+#[query]
+fn get_collateral_swap_routes() -> Vec<rumi_protocol_backend::collateral_swap::CollateralSwapRoute>
+{
+    read_state(|s| s.collateral_swap_routes.clone())
+}
+
+/// Opt a vault into auto-deleveraging or change its settings (`Some`), or
+/// cancel (`None`). Owner only; the change counts as activity on the vault.
+#[candid_method(update)]
+#[update]
+async fn set_auto_deleverage(
+    vault_id: u64,
+    config: Option<rumi_protocol_backend::auto_deleverage::AutoDeleverageConfig>,
+) -> Result<(), ProtocolError> {
+    validate_call().await?;
+    let caller = ic_cdk::caller();
+    let owner = read_state(|s| s.vault_id_to_vaults.get(&vault_id).map(|v| v.owner))
+        .ok_or_else(|| ProtocolError::GenericError(format!("Vault #{} not found", vault_id)))?;
+    if owner != caller {
+        return Err(ProtocolError::CallerNotOwner);
+    }
+    if let Some(config) = &config {
+        read_state(|s| {
+            rumi_protocol_backend::auto_deleverage::validate_config(s, vault_id, config)
+        })
+        .map_err(ProtocolError::GenericError)?;
+    }
     log!(
         INFO,
-        "[set_collateral_min_deposit] collateral={}, min_collateral_deposit={}",
-        collateral_type,
-        min_collateral_deposit
+        "[set_auto_deleverage] vault #{}: {:?}",
+        vault_id,
+        config
     );
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_auto_deleverage(
+            s,
+            vault_id,
+            caller,
+            config,
+            ic_cdk::api::time(),
+        )
+    });
     Ok(())
 }
 
-/// Set the display color (hex) for a collateral type, used by frontend (developer only).
-/// Pass None to clear.
+/// A vault's auto-deleverage opt-in and weekly sale bookkeeping, if any.
+#[candid_method(query)]
+#[query]
+fn get_auto_deleverage(
+    vault_id: u64,
+) -> Option<rumi_protocol_backend::auto_deleverage::AutoDeleverageEntry> {
+    read_state(|s| s.auto_deleverage.get(&vault_id).cloned())
+}
+
+/// Whitelist (`Some`) or remove (`None`) the DEX route auto-deleverage sales
+/// of `collateral_type` go through (developer only).
 #[candid_method(update)]
 #[update]
-async fn set_collateral_display_color(
+async fn set_auto_deleverage_route(
     collateral_type: Principal,
-    display_color: Option<String>,
+    route: Option<rumi_protocol_backend::auto_deleverage::AutoDeleverageRoute>,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
-    let is_developer = read_state(|s| s.developer_principal == caller);
-    if !is_developer {
+    let caller = timelock_caller("set_auto_deleverage_route")?;
+    if !read_state(|s| s.developer_principal == caller) {
         return Err(ProtocolError::GenericError(
-            "Only the developer principal can set display color".to_string(),
+            "Only the developer principal can set auto-deleverage routes".to_string(),
         ));
     }
-    if let Some(ref c) = display_color {
-        if !c.starts_with('#') || (c.len() != 4 && c.len() != 7 && c.len() != 9) {
+    if let Some(route) = &route {
+        if route.collateral_type != collateral_type {
             return Err(ProtocolError::GenericError(
-                "display_color must be a hex color like #RGB, #RRGGBB, or #RRGGBBAA".to_string(),
+                "The route must sell the given collateral type".to_string(),
             ));
         }
-    }
-    let exists = read_state(|s| s.collateral_configs.contains_key(&collateral_type));
-    if !exists {
-        return Err(ProtocolError::GenericError(
-            "Unknown collateral type".to_string(),
-        ));
+        read_state(|s| rumi_protocol_backend::auto_deleverage::validate_route(s, route))
+            .map_err(ProtocolError::GenericError)?;
     }
     mutate_state(|s| {
-        rumi_protocol_backend::event::record_set_collateral_display_color(
+        rumi_protocol_backend::event::record_set_auto_deleverage_route(
             s,
             collateral_type,
-            display_color.clone(),
-        );
+            route.clone(),
+        )
     });
     log!(
         INFO,
-        "[set_collateral_display_color] collateral={}, display_color={:?}",
+        "[set_auto_deleverage_route] {}: {:?}",
         collateral_type,
-        display_color
+        route
     );
     Ok(())
 }
 
+#[candid_method(query)]
+#[query]
+fn get_auto_deleverage_routes() -> Vec<rumi_protocol_backend::auto_deleverage::AutoDeleverageRoute>
+{
+    read_state(|s| s.auto_deleverage_routes.values().cloned().collect())
+}
+
+/// Vaults currently liquidatable or near liquidation, as of the last
+/// price-triggered refresh.
+#[candid_method(query)]
+#[query]
+fn get_liquidatable_set() -> rumi_protocol_backend::liquidatable_set::LiquidatableSetView {
+    read_state(rumi_protocol_backend::liquidatable_set::liquidatable_set_view)
+}
+
+/// Parameters that actually apply to `collateral_type` right now, after
+/// defaults, config, overrides, mode and campaigns, each with its source.
+#[candid_method(query)]
+#[query]
+fn get_effective_parameters(
+    collateral_type: Principal,
+) -> Option<rumi_protocol_backend::effective_parameters::EffectiveParameters> {
+    read_state(|s| {
+        rumi_protocol_backend::effective_parameters::resolve(
+            s,
+            &collateral_type,
+            ic_cdk::api::time(),
+        )
+    })
+}
+
+/// Composite 0–100 protocol health score with its component breakdown.
+#[candid_method(query)]
+#[query]
+fn get_health_score() -> rumi_protocol_backend::health_score::HealthScore {
+    let now = ic_cdk::api::time();
+    read_state(|s| rumi_protocol_backend::health_score::compute_health_score(s, now))
+}
+
+/// Registered mode companions and whether each has accepted the current mode.
+#[candid_method(query)]
+#[query]
+fn get_mode_propagation_status() -> Vec<rumi_protocol_backend::mode_propagation::ModeCompanionStatus>
+{
+    read_state(rumi_protocol_backend::mode_propagation::mode_propagation_status)
+}
+
 #[candid_method(query)]
 #[query]
 fn get_collateral_config(
@@ -11374,6 +15000,292 @@ fn get_collateral_totals() -> Vec<CollateralTotals> {
     })
 }
 
+/// Current debt, ceiling and remaining borrow headroom of a collateral.
+/// Headroom also counts borrows still in flight. `None` for an unknown
+/// collateral.
+#[candid_method(query)]
+#[query]
+fn get_collateral_utilization(
+    collateral_type: Principal,
+) -> Option<rumi_protocol_backend::CollateralUtilization> {
+    let in_flight = rumi_protocol_backend::guard::reserved_borrows(&collateral_type);
+    read_state(|s| s.collateral_utilization(&collateral_type, in_flight))
+}
+
+/// What a collateral's redemption cap allows this epoch and how much of it
+/// is left.
+#[candid_method(query)]
+#[query]
+fn get_redemption_capacity(
+    collateral_type: Principal,
+) -> rumi_protocol_backend::redemption_caps::RedemptionCapacity {
+    read_state(|s| {
+        rumi_protocol_backend::redemption_caps::capacity(s, &collateral_type, ic_cdk::api::time())
+    })
+}
+
+/// Redemption capacity of every capped collateral.
+#[candid_method(query)]
+#[query]
+fn get_redemption_capacities() -> Vec<rumi_protocol_backend::redemption_caps::RedemptionCapacity> {
+    let now = ic_cdk::api::time();
+    read_state(|s| {
+        s.redemption_caps
+            .keys()
+            .map(|ct| rumi_protocol_backend::redemption_caps::capacity(s, ct, now))
+            .collect()
+    })
+}
+
+/// What a collateral's liquidation cap lets one call seize now and what its
+/// window has seized so far.
+#[candid_method(query)]
+#[query]
+fn get_liquidation_capacity(
+    collateral_type: Principal,
+) -> rumi_protocol_backend::liquidation_caps::LiquidationCapacity {
+    read_state(|s| {
+        rumi_protocol_backend::liquidation_caps::capacity(s, &collateral_type, ic_cdk::api::time())
+    })
+}
+
+/// Liquidation capacity of every capped collateral.
+#[candid_method(query)]
+#[query]
+fn get_liquidation_capacities() -> Vec<rumi_protocol_backend::liquidation_caps::LiquidationCapacity>
+{
+    let now = ic_cdk::api::time();
+    read_state(|s| {
+        s.liquidation_caps
+            .keys()
+            .map(|ct| rumi_protocol_backend::liquidation_caps::capacity(s, ct, now))
+            .collect()
+    })
+}
+
+/// The caller a timelocked setter acts for. While the timelock is on a
+/// direct call is refused; the canister's own call executing a queued
+/// change acts for its proposer. See `timelock`.
+fn timelock_caller(method: &str) -> Result<Principal, ProtocolError> {
+    let caller = ic_cdk::caller();
+    if caller == ic_cdk::id() {
+        if let Some(proposer) = rumi_protocol_backend::timelock::executing_as(method) {
+            return Ok(proposer);
+        }
+    }
+    read_state(|s| rumi_protocol_backend::timelock::check_direct_call(s, method))?;
+    Ok(caller)
+}
+
+/// Set the delay queued parameter changes wait before they may be executed;
+/// 0 turns the timelock off (developer only). Instant while the timelock is
+/// off, itself timelocked while it is on.
+#[candid_method(update)]
+#[update]
+fn set_parameter_timelock(delay_ns: u64) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_parameter_timelock")?;
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set the parameter timelock".to_string(),
+        ));
+    }
+    rumi_protocol_backend::timelock::validate_delay(delay_ns)?;
+    mutate_state(|s| rumi_protocol_backend::event::record_set_parameter_timelock(s, delay_ns));
+    log!(INFO, "[set_parameter_timelock] delay_ns={}", delay_ns);
+    Ok(())
+}
+
+/// The delay queued parameter changes wait; 0 when the timelock is off.
+#[candid_method(query)]
+#[query]
+fn get_parameter_timelock() -> u64 {
+    read_state(|s| s.parameter_timelock_ns)
+}
+
+/// Queue a call to the timelocked setter `method` with its Candid-encoded
+/// `arg` (developer only). Returns the change's id.
+#[candid_method(update)]
+#[update]
+fn propose_parameter_change(
+    method: String,
+    arg: serde_bytes::ByteBuf,
+) -> Result<u64, ProtocolError> {
+    let caller = ic_cdk::caller();
+    if read_state(|s| s.developer_principal != caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can propose parameter changes".to_string(),
+        ));
+    }
+    let change = read_state(|s| {
+        rumi_protocol_backend::timelock::propose(s, method, arg, caller, ic_cdk::api::time())
+    })?;
+    let id = change.id;
+    log!(
+        INFO,
+        "[propose_parameter_change] #{} {} activates at {}",
+        id,
+        change.method,
+        change.activates_at
+    );
+    mutate_state(|s| rumi_protocol_backend::event::record_parameter_change_proposed(s, change));
+    Ok(id)
+}
+
+/// Drop a queued parameter change (developer or guardian).
+#[candid_method(update)]
+#[update]
+fn cancel_pending_change(id: u64) -> Result<(), ProtocolError> {
+    let caller = ic_cdk::caller();
+    mutate_state(|s| {
+        rumi_protocol_backend::timelock::check_cancellable(s, id, caller)?;
+        rumi_protocol_backend::event::record_parameter_change_cancelled(
+            s,
+            id,
+            caller,
+            ic_cdk::api::time(),
+        );
+        Ok::<_, ProtocolError>(())
+    })?;
+    log!(
+        INFO,
+        "[cancel_pending_change] #{} cancelled by {}",
+        id,
+        caller
+    );
+    Ok(())
+}
+
+/// Apply a queued parameter change once it has activated. Open to anyone:
+/// the canister calls the setter on itself for the proposer. A setter that
+/// fails leaves the change queued.
+#[candid_method(update)]
+#[update]
+async fn execute_parameter_change(id: u64) -> Result<(), ProtocolError> {
+    validate_caller()?;
+    let caller = ic_cdk::caller();
+    let change = read_state(|s| {
+        rumi_protocol_backend::timelock::check_executable(s, id, ic_cdk::api::time())
+    })?;
+    rumi_protocol_backend::timelock::begin_execution(&change)?;
+    let reply =
+        ic_cdk::api::call::call_raw(ic_cdk::id(), &change.method, change.arg.to_vec(), 0).await;
+    rumi_protocol_backend::timelock::end_execution();
+    let result = match reply {
+        Ok(bytes) => candid::decode_one::<Result<(), ProtocolError>>(&bytes).map_err(|e| {
+            ProtocolError::GenericError(format!("Undecodable reply from {}: {}", change.method, e))
+        })?,
+        Err((code, msg)) => Err(ProtocolError::GenericError(format!(
+            "{} rejected: {:?} {}",
+            change.method, code, msg
+        ))),
+    };
+    if let Err(e) = result {
+        log!(
+            INFO,
+            "[execute_parameter_change] #{} {} failed: {:?}",
+            id,
+            change.method,
+            e
+        );
+        return Err(e);
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_parameter_change_executed(
+            s,
+            id,
+            caller,
+            ic_cdk::api::time(),
+        )
+    });
+    log!(
+        INFO,
+        "[execute_parameter_change] #{} {} executed",
+        id,
+        change.method
+    );
+    Ok(())
+}
+
+/// Every queued parameter change, oldest first.
+#[candid_method(query)]
+#[query]
+fn get_pending_parameter_changes() -> Vec<rumi_protocol_backend::timelock::PendingParameterChange> {
+    read_state(|s| s.pending_parameter_changes.values().cloned().collect())
+}
+
+/// Replace the principals allowed to pause single operations (developer
+/// only). The developer can always pause, pauser or not.
+#[candid_method(update)]
+#[update]
+async fn set_emergency_pausers(principals: Vec<Principal>) -> Result<(), ProtocolError> {
+    let caller = timelock_caller("set_emergency_pausers")?;
+    let is_developer = read_state(|s| s.developer_principal == caller);
+    if !is_developer {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal can set emergency pausers".to_string(),
+        ));
+    }
+    if principals.contains(&Principal::anonymous()) {
+        return Err(ProtocolError::GenericError(
+            "The anonymous principal cannot be an emergency pauser".to_string(),
+        ));
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_emergency_pausers(s, principals.clone());
+    });
+    log!(INFO, "[set_emergency_pausers] pausers={:?}", principals);
+    Ok(())
+}
+
+/// Principals currently allowed to pause operations, besides the developer.
+#[candid_method(query)]
+#[query]
+fn get_emergency_pausers() -> Vec<Principal> {
+    read_state(|s| s.emergency_pausers.iter().copied().collect())
+}
+
+/// Pause one operation (borrows, redemptions, liquidations or reserve
+/// redemptions) until it is unpaused, leaving the others and every
+/// repayment path running. Emergency pausers and the developer only.
+#[candid_method(update)]
+#[update]
+async fn pause_operation(operation: PausableOperation) -> Result<(), ProtocolError> {
+    set_operation_paused(operation, true)
+}
+
+/// Resume a paused operation. Emergency pausers and the developer only.
+#[candid_method(update)]
+#[update]
+async fn unpause_operation(operation: PausableOperation) -> Result<(), ProtocolError> {
+    set_operation_paused(operation, false)
+}
+
+fn set_operation_paused(operation: PausableOperation, paused: bool) -> Result<(), ProtocolError> {
+    let caller = ic_cdk::caller();
+    read_state(|s| rumi_protocol_backend::operation_pauses::check_pause_authority(s, caller))?;
+    if read_state(|s| rumi_protocol_backend::operation_pauses::is_paused(s, operation)) == paused {
+        return Ok(());
+    }
+    mutate_state(|s| {
+        rumi_protocol_backend::event::record_set_operation_paused(s, operation, paused, caller);
+    });
+    log!(
+        INFO,
+        "[set_operation_paused] {} set {:?} paused={}",
+        caller,
+        operation,
+        paused
+    );
+    Ok(())
+}
+
+/// Operations currently paused.
+#[candid_method(query)]
+#[query]
+fn get_paused_operations() -> Vec<PausableOperation> {
+    read_state(|s| s.paused_operations.iter().copied().collect())
+}
+
 /// Update any per-collateral parameter (developer only).
 /// Replaces the entire CollateralConfig for the given collateral type.
 /// Use `get_collateral_config` to fetch the current config, modify fields, then pass back.
@@ -11383,7 +15295,7 @@ async fn update_collateral_config(
     collateral_type: Principal,
     config: rumi_protocol_backend::state::CollateralConfig,
 ) -> Result<(), ProtocolError> {
-    let caller = ic_cdk::caller();
+    let caller = timelock_caller("update_collateral_config")?;
     let is_developer = read_state(|s| s.developer_principal == caller);
     if !is_developer {
         return Err(ProtocolError::GenericError(
@@ -11404,6 +15316,9 @@ async fn update_collateral_config(
             "ledger_canister_id in config must match collateral_type".to_string(),
         ));
     }
+    read_state(rumi_protocol_backend::emergency_shutdown::check_not_shut_down)?;
+    rumi_protocol_backend::state::validate_price_source(&config.price_source)
+        .map_err(ProtocolError::GenericError)?;
     let configured_xrp_key = read_state(|s| s.xrp_schnorr_key_name.clone());
     rumi_protocol_backend::state::validate_xrp_launch_config_update(
         collateral_type,
@@ -11487,8 +15402,9 @@ async fn admin_correct_vault_collateral(
 /// Sweep untracked ICP surplus from the backend to treasury.
 ///
 /// Auto-calculates the surplus: actual ICP balance minus the sum of all
-/// ICP vault collateral, pending margin/excess/redemption transfers, and
-/// pending treasury collateral. Only the surplus can be swept — it is
+/// ICP vault collateral, pending margin/excess/redemption transfers,
+/// pending treasury collateral, donated ICP in the surplus buffer and the ICP
+/// fee sponsorship pool. Only the surplus can be swept — it is
 /// physically impossible to touch tracked collateral with this function.
 #[update]
 async fn admin_sweep_to_treasury(reason: String) -> Result<u64, ProtocolError> {
@@ -11561,6 +15477,16 @@ async fn admin_sweep_to_treasury(reason: String) -> Result<u64, ProtocolError> {
             }
         }
 
+        // Donated ICP held in the surplus buffer
+        if let Some(buffer) = s.surplus_buffer.get(&s.icp_ledger_principal) {
+            total = total.saturating_add(*buffer);
+        }
+
+        // ICP held for fee sponsorship
+        if let Some(pool) = s.fee_sponsorship_pools.get(&s.icp_ledger_principal) {
+            total = total.saturating_add(pool.balance);
+        }
+
         total
     });
 
@@ -11597,7 +15523,7 @@ async fn admin_sweep_to_treasury(reason: String) -> Result<u64, ProtocolError> {
     let _ = treasury::notify_treasury_deposit(
         treasury,
         treasury::DepositType::LiquidationFee, // closest category for recovered funds
-        treasury::AssetType::ICP,
+        icp_ledger,
         surplus,
         block_index,
     )
@@ -11695,6 +15621,31 @@ fn icrc10_supported_standards() -> Vec<rumi_protocol_backend::icrc21::StandardRe
     rumi_protocol_backend::icrc21::icrc10_supported_standards()
 }
 
+// ICRC-3 log of protocol-initiated icUSD operations (delegates to icrc3_log)
+#[query]
+fn icrc3_get_blocks(
+    requests: Vec<icrc_ledger_types::icrc3::blocks::GetBlocksRequest>,
+) -> icrc_ledger_types::icrc3::blocks::GetBlocksResult {
+    rumi_protocol_backend::icrc3_log::get_blocks(requests)
+}
+
+#[query]
+fn icrc3_get_archives(
+    _args: rumi_protocol_backend::icrc3_log::GetArchivesArgs,
+) -> Vec<rumi_protocol_backend::icrc3_log::ArchiveInfo> {
+    vec![]
+}
+
+#[query]
+fn icrc3_get_tip_certificate() -> Option<icrc_ledger_types::icrc3::blocks::ICRC3DataCertificate> {
+    rumi_protocol_backend::icrc3_log::tip_certificate()
+}
+
+#[query]
+fn icrc3_supported_block_types() -> Vec<icrc_ledger_types::icrc3::blocks::SupportedBlockType> {
+    rumi_protocol_backend::icrc3_log::supported_block_types()
+}
+
 // Validates the forward, id-cursored, type-filtered scan that backs
 // `get_events_forward_filtered` (the rumi_points ingestion endpoint): window
 // bounding, global-index tagging, type filtering, and the resume cursor.
@@ -13,6 +13,7 @@ use sha2::{Sha256, Digest};
 use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::fmt;
+use crate::guard::trace_tag;
 use crate::log;
 use crate::DEBUG;
 
@@ -91,7 +92,8 @@ pub async fn get_or_refresh_fee(ledger: Principal) -> Result<u64, String> {
     refresh_fee_cache(ledger).await
 }
 
-/// Idempotent ICRC-1 transfer.
+/// Idempotent ICRC-1 transfer. icUSD transfers that land are logged to
+/// `icrc3_log`.
 ///
 /// `op_nonce` MUST be stable across retries of the same logical operation
 /// (mint via `crate::state::next_op_nonce` once, persist alongside the
@@ -116,7 +118,22 @@ pub async fn transfer_idempotent(
 ) -> Result<u64, TransferError> {
     let created_at_time = nonce_to_created_at_time(op_nonce);
     let memo = memo.unwrap_or_else(|| nonce_to_memo(op_nonce));
+    let counterparty = to.owner;
 
+    let logged = crate::icrc3_log::LedgerTransfer {
+        from: Account {
+            owner: ic_cdk::id(),
+            subaccount: from_subaccount,
+        },
+        to,
+        spender: None,
+        amount,
+        memo: Some(memo.0.to_vec()),
+        created_at_time: Some(created_at_time),
+    };
+
+    let _open_call =
+        crate::guard::LedgerCallGuard::new(counterparty, ledger, memo.0.to_vec(), amount);
     let client = ICRC1Client {
         runtime: CdkRuntime,
         ledger_canister_id: ledger,
@@ -132,7 +149,11 @@ pub async fn transfer_idempotent(
         })
         .await;
 
-    handle_transfer_outcome(ledger, outer)
+    let result = handle_transfer_outcome(ledger, counterparty, outer);
+    if let Ok(block) = result {
+        crate::icrc3_log::record_ledger_transfer(ledger, logged, block);
+    }
+    result
 }
 
 /// Idempotent ICRC-2 transfer_from. Same semantics as `transfer_idempotent`
@@ -147,7 +168,22 @@ pub async fn transfer_from_idempotent(
 ) -> Result<u64, TransferFromError> {
     let created_at_time = nonce_to_created_at_time(op_nonce);
     let memo = memo.unwrap_or_else(|| nonce_to_memo(op_nonce));
+    let counterparty = from.owner;
+
+    let logged = crate::icrc3_log::LedgerTransfer {
+        from,
+        to,
+        spender: Some(Account {
+            owner: ic_cdk::id(),
+            subaccount: None,
+        }),
+        amount,
+        memo: Some(memo.0.to_vec()),
+        created_at_time: Some(created_at_time),
+    };
 
+    let _open_call =
+        crate::guard::LedgerCallGuard::new(counterparty, ledger, memo.0.to_vec(), amount);
     let client = ICRC1Client {
         runtime: CdkRuntime,
         ledger_canister_id: ledger,
@@ -164,11 +200,18 @@ pub async fn transfer_from_idempotent(
         })
         .await;
 
-    handle_transfer_from_outcome(ledger, outer)
+    let result = handle_transfer_from_outcome(ledger, counterparty, outer);
+    if let Ok(block) = result {
+        crate::icrc3_log::record_ledger_transfer(ledger, logged, block);
+    }
+    result
 }
 
+/// `counterparty` is the account owner on the user side of the transfer; its
+/// in-flight operation's trace (if any) tags the log lines.
 fn handle_transfer_outcome(
     ledger: Principal,
+    counterparty: Principal,
     outer: Result<Result<Nat, TransferError>, (i32, String)>,
 ) -> Result<u64, TransferError> {
     match outer {
@@ -176,30 +219,43 @@ fn handle_transfer_outcome(
         Ok(Err(TransferError::Duplicate { duplicate_of })) => {
             let block = duplicate_of.0.to_u64().unwrap_or(0);
             log!(DEBUG,
-                "[transfer_idempotent] ledger {} reported Duplicate; treating as success (block {})",
-                ledger, block
+                "[transfer_idempotent] {} ledger {} reported Duplicate; treating as success (block {})",
+                trace_tag(counterparty), ledger, block
             );
             Ok(block)
         }
         Ok(Err(TransferError::BadFee { expected_fee })) => {
             let fee = expected_fee.0.to_u64().unwrap_or(0);
             log!(DEBUG,
-                "[transfer_idempotent] ledger {} returned BadFee (expected {}), refreshing cache",
-                ledger, fee
+                "[transfer_idempotent] {} ledger {} returned BadFee (expected {}), refreshing cache",
+                trace_tag(counterparty), ledger, fee
             );
             set_cached_fee(ledger, fee);
             Err(TransferError::BadFee { expected_fee })
         }
-        Ok(Err(other)) => Err(other),
-        Err((code, msg)) => Err(TransferError::GenericError {
-            error_code: Nat::from(code.max(0) as u64),
-            message: msg,
-        }),
+        Ok(Err(other)) => {
+            log!(DEBUG,
+                "[transfer_idempotent] {} ledger {} rejected transfer: {:?}",
+                trace_tag(counterparty), ledger, other
+            );
+            Err(other)
+        }
+        Err((code, msg)) => {
+            log!(DEBUG,
+                "[transfer_idempotent] {} ledger {} unreachable: {:?} {}",
+                trace_tag(counterparty), ledger, code, msg
+            );
+            Err(TransferError::GenericError {
+                error_code: Nat::from(code.max(0) as u64),
+                message: msg,
+            })
+        }
     }
 }
 
 fn handle_transfer_from_outcome(
     ledger: Principal,
+    counterparty: Principal,
     outer: Result<Result<Nat, TransferFromError>, (i32, String)>,
 ) -> Result<u64, TransferFromError> {
     match outer {
@@ -207,25 +263,37 @@ fn handle_transfer_from_outcome(
         Ok(Err(TransferFromError::Duplicate { duplicate_of })) => {
             let block = duplicate_of.0.to_u64().unwrap_or(0);
             log!(DEBUG,
-                "[transfer_from_idempotent] ledger {} reported Duplicate; treating as success (block {})",
-                ledger, block
+                "[transfer_from_idempotent] {} ledger {} reported Duplicate; treating as success (block {})",
+                trace_tag(counterparty), ledger, block
             );
             Ok(block)
         }
         Ok(Err(TransferFromError::BadFee { expected_fee })) => {
             let fee = expected_fee.0.to_u64().unwrap_or(0);
             log!(DEBUG,
-                "[transfer_from_idempotent] ledger {} returned BadFee (expected {}), refreshing cache",
-                ledger, fee
+                "[transfer_from_idempotent] {} ledger {} returned BadFee (expected {}), refreshing cache",
+                trace_tag(counterparty), ledger, fee
             );
             set_cached_fee(ledger, fee);
             Err(TransferFromError::BadFee { expected_fee })
         }
-        Ok(Err(other)) => Err(other),
-        Err((code, msg)) => Err(TransferFromError::GenericError {
-            error_code: Nat::from(code.max(0) as u64),
-            message: msg,
-        }),
+        Ok(Err(other)) => {
+            log!(DEBUG,
+                "[transfer_from_idempotent] {} ledger {} rejected transfer: {:?}",
+                trace_tag(counterparty), ledger, other
+            );
+            Err(other)
+        }
+        Err((code, msg)) => {
+            log!(DEBUG,
+                "[transfer_from_idempotent] {} ledger {} unreachable: {:?} {}",
+                trace_tag(counterparty), ledger, code, msg
+            );
+            Err(TransferFromError::GenericError {
+                error_code: Nat::from(code.max(0) as u64),
+                message: msg,
+            })
+        }
     }
 }
 
@@ -446,10 +514,61 @@ pub fn compute_lst_wrapped_price(
     Some(adjusted)
 }
 
+/// Reply expected from a `SecondaryPriceSource` method. As with
+/// `LstCanisterInfo`, extra fields in the source's reply are ignored.
+#[derive(candid::CandidType, serde::Deserialize)]
+struct SecondaryPriceQuote {
+    /// USD price of one whole token, scaled by 1e8.
+    price_e8s: u64,
+}
+
+/// Ask a collateral's secondary source for its USD price. `None` when the
+/// call fails or the source quotes zero; the cross-check then decides what
+/// an unavailable source means.
+pub async fn fetch_secondary_price(
+    collateral_type: Principal,
+    source: &crate::state::SecondaryPriceSource,
+) -> Option<f64> {
+    fetch_canister_price(collateral_type, source.canister_id, &source.method).await
+}
+
+/// Call `method(collateral_type)` on an oracle canister answering with a
+/// `SecondaryPriceQuote`. `None` when the call fails or it quotes zero.
+async fn fetch_canister_price(
+    collateral_type: Principal,
+    canister_id: Principal,
+    method: &str,
+) -> Option<f64> {
+    use crate::logs::TRACE_XRC;
+    use ic_canister_log::log;
+
+    let result: Result<(SecondaryPriceQuote,), _> =
+        ic_cdk::call(canister_id, method, (collateral_type,)).await;
+    match result {
+        Ok((quote,)) if quote.price_e8s > 0 => Some(quote.price_e8s as f64 / crate::E8S as f64),
+        Ok(_) => {
+            log!(
+                TRACE_XRC,
+                "[fetch_canister_price] {} quoted zero for {}",
+                canister_id, collateral_type
+            );
+            None
+        }
+        Err((code, msg)) => {
+            log!(
+                TRACE_XRC,
+                "[fetch_canister_price] {} error for {}: {:?} {}",
+                canister_id, collateral_type, code, msg
+            );
+            None
+        }
+    }
+}
+
 /// Generic price fetch for any collateral type using its PriceSource config.
 /// Routes to XRC, CoinGecko HTTPS outcall, or LstWrapped depending on config.
 pub async fn fetch_collateral_price(collateral_type: Principal) {
-    use crate::state::{mutate_state, PriceSource, XrcAssetClass};
+    use crate::state::{mutate_state, PriceSource};
     use ic_canister_log::log;
     use crate::logs::TRACE_XRC;
     use rust_decimal::prelude::FromPrimitive;
@@ -570,8 +689,9 @@ pub async fn fetch_collateral_price(collateral_type: Principal) {
                 return;
             }
         };
-        let accepted =
-            mutate_state(|s| s.check_price_sanity_band(&collateral_type, final_rate_f64));
+        let accepted = crate::xrc::check_price_bounds(collateral_type, final_rate_f64)
+            && crate::xrc::check_price_deviation(collateral_type, final_rate_f64, ts_nanos)
+            && mutate_state(|s| s.check_price_sanity_band(&collateral_type, final_rate_f64));
         if !accepted {
             log!(
                 TRACE_XRC,
@@ -585,7 +705,7 @@ pub async fn fetch_collateral_price(collateral_type: Principal) {
             if let Some(config) = s.collateral_configs.get_mut(&collateral_type) {
                 config.last_price = Some(final_rate_f64);
                 config.last_price_timestamp = Some(ts_nanos);
-                crate::event::record_price_update(collateral_type, final_rate, ts_nanos);
+                crate::event::record_price_update(s, collateral_type, final_rate, ts_nanos);
             }
         });
         return;
@@ -615,7 +735,9 @@ pub async fn fetch_collateral_price(collateral_type: Principal) {
                 }
                 // Wave-5 LIQ-007: gate every accepted price through the sanity band
                 // (rejects single outliers, accepts after N consecutive confirmations).
-                let accepted = mutate_state(|s| s.check_price_sanity_band(&collateral_type, price));
+                let accepted = crate::xrc::check_price_bounds(collateral_type, price)
+                    && crate::xrc::check_price_deviation(collateral_type, price, ts_nanos)
+                    && mutate_state(|s| s.check_price_sanity_band(&collateral_type, price));
                 if !accepted {
                     log!(
                         TRACE_XRC,
@@ -629,7 +751,7 @@ pub async fn fetch_collateral_price(collateral_type: Principal) {
                         config.last_price = Some(price);
                         config.last_price_timestamp = Some(ts_nanos);
                         if let Some(price_dec) = rust_decimal::Decimal::from_f64(price) {
-                            crate::event::record_price_update(collateral_type, price_dec, ts_nanos);
+                            crate::event::record_price_update(s, collateral_type, price_dec, ts_nanos);
                         }
                     }
                 });
@@ -641,29 +763,197 @@ pub async fn fetch_collateral_price(collateral_type: Principal) {
         return;
     }
 
+    // Median variant: every feed is fetched concurrently and the middle
+    // quote published. The secondary-source cross-check is not applied:
+    // the feeds already check each other.
+    if let PriceSource::Median { ref feeds, min_feeds } = price_source {
+        let quotes: Vec<f64> = futures::future::join_all(
+            feeds.iter().map(|feed| fetch_price_feed(collateral_type, feed)),
+        )
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
+        let Some(price) = crate::xrc::median_price(&quotes, min_feeds) else {
+            log!(
+                TRACE_XRC,
+                "[fetch_collateral_price] Median {}: only {} of {} feeds answered (need {})",
+                collateral_type, quotes.len(), feeds.len(), min_feeds
+            );
+            return;
+        };
+        let ts_nanos = ic_cdk::api::time();
+        log!(
+            TRACE_XRC,
+            "[fetch_collateral_price] Median {} price: {} from {:?} at {}",
+            collateral_type, price, quotes, ts_nanos
+        );
+        let should_update = read_state(|s| {
+            s.get_collateral_config(&collateral_type)
+                .map(|c| match c.last_price_timestamp {
+                    Some(last_ts) => last_ts < ts_nanos,
+                    None => true,
+                })
+                .unwrap_or(false)
+        });
+        if !should_update {
+            return;
+        }
+        let accepted = crate::xrc::check_price_bounds(collateral_type, price)
+            && crate::xrc::check_price_deviation(collateral_type, price, ts_nanos)
+            && mutate_state(|s| s.check_price_sanity_band(&collateral_type, price));
+        if !accepted {
+            log!(
+                TRACE_XRC,
+                "[fetch_collateral_price] rejecting outlier median price {} for {}; awaiting confirmation",
+                price, collateral_type
+            );
+            return;
+        }
+        mutate_state(|s| {
+            if let Some(config) = s.collateral_configs.get_mut(&collateral_type) {
+                config.last_price = Some(price);
+                config.last_price_timestamp = Some(ts_nanos);
+                // The spread between feeds is not an XRC standard deviation.
+                config.last_price_std_dev = None;
+                if let Some(price_dec) = rust_decimal::Decimal::from_f64(price) {
+                    crate::event::record_price_update(s, collateral_type, price_dec, ts_nanos);
+                }
+            }
+        });
+        return;
+    }
+
     // XRC-based path (only the `Xrc` variant reaches here now —
     // `LstWrapped` is handled above without an XRC call, and `CoinGecko`
-    // is handled in its own branch).
-    const XRC_CALL_COST_CYCLES: u64 = 1_000_000_000;
-    const XRC_MARGIN_SEC: u64 = 60;
-
+    // and `Median` are handled in their own branches).
     let (base_asset, base_asset_class, quote_asset, quote_asset_class) = match &price_source {
         PriceSource::Xrc { base_asset, base_asset_class, quote_asset, quote_asset_class } => {
             (base_asset.clone(), base_asset_class.clone(), quote_asset.clone(), quote_asset_class.clone())
         }
         PriceSource::LstWrapped { .. } => unreachable!(), // handled above
         PriceSource::CoinGecko { .. } => unreachable!(), // handled above
+        PriceSource::Median { .. } => unreachable!(), // handled above
     };
 
+    let Some((rate, ts_nanos, std_dev)) = fetch_xrc_pair_rate(
+        collateral_type,
+        &base_asset,
+        &base_asset_class,
+        &quote_asset,
+        &quote_asset_class,
+    )
+    .await
+    else {
+        return;
+    };
+
+    // Only the plain `Xrc` variant reaches here — `LstWrapped` is handled
+    // above without re-fetching from XRC, and `CoinGecko` has its own
+    // early-return branch.
+    let final_rate = rate;
+
+    let should_update = read_state(|s| {
+        s.get_collateral_config(&collateral_type)
+            .map(|c| match c.last_price_timestamp {
+                Some(last_ts) => last_ts < ts_nanos,
+                None => true,
+            })
+            .unwrap_or(false)
+    });
+    if !should_update {
+        return;
+    }
+
+    // Wave-5 LIQ-007: gate every accepted price through the sanity band.
+    let final_rate_f64 = match final_rate.to_f64() {
+        Some(v) if v.is_finite() && v > 0.0 => v,
+        _ => {
+            log!(
+                TRACE_XRC,
+                "[fetch_collateral_price] {}: dropping non-positive/non-finite final rate {}",
+                base_asset, final_rate
+            );
+            return;
+        }
+    };
+    if !crate::xrc::cross_check_xrc_price(collateral_type, final_rate_f64).await {
+        return;
+    }
+    let accepted = crate::xrc::check_price_bounds(collateral_type, final_rate_f64)
+        && crate::xrc::check_price_deviation(collateral_type, final_rate_f64, ts_nanos)
+        && mutate_state(|s| s.check_price_sanity_band(&collateral_type, final_rate_f64));
+    if !accepted {
+        log!(
+            TRACE_XRC,
+            "[fetch_collateral_price] rejecting outlier {} rate {} for {}; awaiting confirmation",
+            base_asset, final_rate_f64, collateral_type
+        );
+        return;
+    }
+
+    mutate_state(|s| {
+        if let Some(config) = s.collateral_configs.get_mut(&collateral_type) {
+            config.last_price = Some(final_rate_f64);
+            config.last_price_timestamp = Some(ts_nanos);
+            config.last_price_std_dev = Some(std_dev);
+            crate::event::record_price_update(s, collateral_type, final_rate, ts_nanos);
+        }
+    });
+}
+
+/// One feed of a `PriceSource::Median`, as a USD price per whole token.
+async fn fetch_price_feed(collateral_type: Principal, feed: &crate::state::PriceFeed) -> Option<f64> {
+    use crate::state::PriceFeed;
+
+    match feed {
+        PriceFeed::Xrc { base_asset, base_asset_class, quote_asset, quote_asset_class } => {
+            fetch_xrc_pair_rate(
+                collateral_type,
+                base_asset,
+                base_asset_class,
+                quote_asset,
+                quote_asset_class,
+            )
+            .await
+            .and_then(|(rate, _, _)| rate.to_f64())
+        }
+        PriceFeed::Canister { canister_id, method } => {
+            fetch_canister_price(collateral_type, *canister_id, method).await
+        }
+        PriceFeed::CoinGecko { coin_id, vs_currency } => {
+            fetch_coingecko_price(coin_id, vs_currency).await
+        }
+    }
+}
+
+/// Fetch one XRC pair for a non-ICP collateral, gated by the collateral's
+/// source floor. Returns the rate, its timestamp (ns) and the XRC standard
+/// deviation.
+async fn fetch_xrc_pair_rate(
+    collateral_type: Principal,
+    base_asset: &str,
+    base_asset_class: &crate::state::XrcAssetClass,
+    quote_asset: &str,
+    quote_asset_class: &crate::state::XrcAssetClass,
+) -> Option<(rust_decimal::Decimal, u64, f64)> {
+    use crate::state::XrcAssetClass;
+    use ic_canister_log::log;
+    use crate::logs::TRACE_XRC;
+    use rust_decimal::prelude::FromPrimitive;
+
+    const XRC_CALL_COST_CYCLES: u64 = 1_000_000_000;
+    const XRC_MARGIN_SEC: u64 = 60;
+
     let base = Asset {
-        symbol: base_asset.clone(),
+        symbol: base_asset.to_string(),
         class: match base_asset_class {
             XrcAssetClass::Cryptocurrency => AssetClass::Cryptocurrency,
             XrcAssetClass::FiatCurrency => AssetClass::FiatCurrency,
         },
     };
     let quote = Asset {
-        symbol: quote_asset.clone(),
+        symbol: quote_asset.to_string(),
         class: match quote_asset_class {
             XrcAssetClass::Cryptocurrency => AssetClass::Cryptocurrency,
             XrcAssetClass::FiatCurrency => AssetClass::FiatCurrency,
@@ -688,7 +978,7 @@ pub async fn fetch_collateral_price(collateral_type: Principal) {
     )
     .await;
 
-    let underlying_rate = match res_xrc {
+    match res_xrc {
         Ok((GetExchangeRateResult::Ok(exchange_rate_result),)) => {
             // Wave-14a CDP-14: source-floor gate for non-ICP collaterals.
             // Same rationale as the ICP path: a thin aggregation is cheaper
@@ -736,7 +1026,16 @@ pub async fn fetch_collateral_price(collateral_type: Principal) {
                     base_asset, rate, exchange_rate_result.timestamp
                 );
 
-                Some((rate, exchange_rate_result.timestamp * 1_000_000_000))
+                let std_dev = crate::xrc::xrc_std_dev(
+                    exchange_rate_result.metadata.standard_deviation,
+                    exchange_rate_result.metadata.decimals,
+                );
+
+                Some((
+                    rate,
+                    exchange_rate_result.timestamp * 1_000_000_000,
+                    std_dev,
+                ))
             }
         }
         Ok((GetExchangeRateResult::Err(error),)) => {
@@ -747,56 +1046,7 @@ pub async fn fetch_collateral_price(collateral_type: Principal) {
             log!(TRACE_XRC, "[fetch_collateral_price] Call error for {}: {:?} {}", base_asset, code, msg);
             None
         }
-    };
-
-    let Some((rate, ts_nanos)) = underlying_rate else { return };
-
-    // Only the plain `Xrc` variant reaches here — `LstWrapped` is handled
-    // above without re-fetching from XRC, and `CoinGecko` has its own
-    // early-return branch.
-    let final_rate = rate;
-
-    let should_update = read_state(|s| {
-        s.get_collateral_config(&collateral_type)
-            .map(|c| match c.last_price_timestamp {
-                Some(last_ts) => last_ts < ts_nanos,
-                None => true,
-            })
-            .unwrap_or(false)
-    });
-    if !should_update {
-        return;
     }
-
-    // Wave-5 LIQ-007: gate every accepted price through the sanity band.
-    let final_rate_f64 = match final_rate.to_f64() {
-        Some(v) if v.is_finite() && v > 0.0 => v,
-        _ => {
-            log!(
-                TRACE_XRC,
-                "[fetch_collateral_price] {}: dropping non-positive/non-finite final rate {}",
-                base_asset, final_rate
-            );
-            return;
-        }
-    };
-    let accepted = mutate_state(|s| s.check_price_sanity_band(&collateral_type, final_rate_f64));
-    if !accepted {
-        log!(
-            TRACE_XRC,
-            "[fetch_collateral_price] rejecting outlier {} rate {} for {}; awaiting confirmation",
-            base_asset, final_rate_f64, collateral_type
-        );
-        return;
-    }
-
-    mutate_state(|s| {
-        if let Some(config) = s.collateral_configs.get_mut(&collateral_type) {
-            config.last_price = Some(final_rate_f64);
-            config.last_price_timestamp = Some(ts_nanos);
-            crate::event::record_price_update(collateral_type, final_rate, ts_nanos);
-        }
-    });
 }
 
 /// Fetch a token price from the CoinGecko simple/price API via HTTPS outcall.
@@ -1164,7 +1414,20 @@ pub async fn sweep_deposit(
 /// treats `ApproveError::Duplicate { duplicate_of }` as success (the approve
 /// already landed at that block — same effective allowance).
 pub async fn approve_icusd(spender: Principal, amount: u64) -> Result<u64, ApproveError> {
-    let (ledger, op_nonce) = crate::state::mutate_state(|s| (s.icusd_ledger_principal, s.next_op_nonce()));
+    let ledger = crate::state::read_state(|s| s.icusd_ledger_principal);
+    approve_on_ledger(ledger, spender, amount, None).await
+}
+
+/// Approve a spender to transfer tokens on `ledger` from the protocol
+/// canister, optionally until `expires_at` (ns). In-place collateral swaps use
+/// it to let the DEX pull the vault's collateral.
+pub async fn approve_on_ledger(
+    ledger: Principal,
+    spender: Principal,
+    amount: u64,
+    expires_at: Option<u64>,
+) -> Result<u64, ApproveError> {
+    let op_nonce = crate::state::mutate_state(|s| s.next_op_nonce());
     let created_at_time = nonce_to_created_at_time(op_nonce);
     let memo = nonce_to_memo(op_nonce);
 
@@ -1176,7 +1439,7 @@ pub async fn approve_icusd(spender: Principal, amount: u64) -> Result<u64, Appro
             spender: Account { owner: spender, subaccount: None },
             amount: Nat::from(amount),
             expected_allowance: None,
-            expires_at: None,
+            expires_at,
             fee: None,
             created_at_time: Some(created_at_time),
             memo: Some(memo),
@@ -1186,7 +1449,7 @@ pub async fn approve_icusd(spender: Principal, amount: u64) -> Result<u64, Appro
         Ok((Ok(block_index),)) => Ok(block_index.0.to_u64().unwrap_or(0)),
         Ok((Err(ApproveError::Duplicate { duplicate_of }),)) => {
             log!(DEBUG,
-                "[approve_icusd] ledger {} reported Duplicate; treating as success (block {})",
+                "[approve_on_ledger] ledger {} reported Duplicate; treating as success (block {})",
                 ledger, duplicate_of
             );
             Ok(duplicate_of.0.to_u64().unwrap_or(0))
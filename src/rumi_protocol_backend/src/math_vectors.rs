@@ -0,0 +1,406 @@
+//! Canonical test vectors for the collateral math the frontend mirrors.
+//!
+//! The frontend re-implements collateral ratios, borrowing and redemption
+//! fees, liquidation seizure and the redemption water-fill to preview
+//! operations before they are sent. `math_test_vectors` runs the canister's
+//! own functions over a fixed set of inputs and returns each input next to
+//! the output it produced, so the TypeScript side can replay the same
+//! inputs and fail its tests the moment the two implementations drift.
+//!
+//! The vectors are computed on synthetic fixture states, never on live
+//! state, so the artifact only changes when the math does. Every number is
+//! serialized as a decimal string: amounts can exceed 2^53, and the ratios
+//! must compare exactly rather than as JS floats. Bump
+//! `MATH_TEST_VECTORS_VERSION` whenever a case is added or changed.
+//!
+//! Exposed by the `dev_get_math_test_vectors` query and the
+//! `/math_vectors` HTTP path, both compiled only with `test_endpoints`.
+
+use crate::numeric::{Ratio, UsdIcp, ICUSD};
+use crate::state::{compute_redemption_fee, CollateralType, State};
+use crate::vault::{clamp_borrow_fee, Vault};
+use crate::InitArg;
+use candid::Principal;
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::Serialize;
+
+pub const MATH_TEST_VECTORS_VERSION: u32 = 2;
+
+/// Fixture vaults are numbered from here. The redemption water-fill skips
+/// vaults the liquidation guard holds, and live vault ids never come near
+/// this range, so a live liquidation cannot change a fixture's split.
+const FIXTURE_VAULT_ID_BASE: u64 = u64::MAX - 1_000;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MathTestVectors {
+    pub version: u32,
+    pub collateral_ratio: Vec<CollateralRatioVector>,
+    pub borrowing_fee: Vec<BorrowingFeeVector>,
+    pub redemption_fee: Vec<RedemptionFeeVector>,
+    pub liquidation: Vec<LiquidationVector>,
+    pub redemption_split: Vec<RedemptionSplitVector>,
+}
+
+/// `compute_collateral_ratio` of a single vault.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CollateralRatioVector {
+    pub collateral_amount: String,
+    pub decimals: u8,
+    pub price_usd: String,
+    pub debt_e8s: String,
+    pub collateral_ratio: String,
+}
+
+/// A borrow's fee with no borrowing-fee curve: `amount × fee_rate`, clamped
+/// so at least 1 e8s is minted.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BorrowingFeeVector {
+    pub amount_e8s: String,
+    pub fee_rate: String,
+    pub fee_e8s: String,
+}
+
+/// `compute_redemption_fee`: the base rate, halving every 12 hours, plus
+/// half the redeemed share of the debt, clamped to the floor and ceiling.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RedemptionFeeVector {
+    pub elapsed_hours: String,
+    pub redeemed_e8s: String,
+    pub total_debt_e8s: String,
+    pub base_rate: String,
+    pub fee_floor: String,
+    pub fee_ceiling: String,
+    pub fee_rate: String,
+}
+
+/// `partial_liquidation_split`: the collateral a liquidation repaying
+/// `repay_e8s` takes and how it divides between protocol and liquidator.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LiquidationVector {
+    pub collateral_amount: String,
+    pub decimals: u8,
+    pub price_usd: String,
+    pub debt_e8s: String,
+    pub repay_e8s: String,
+    pub liquidation_bonus: String,
+    pub protocol_share: String,
+    pub collateral_seized: String,
+    pub protocol_cut: String,
+    pub collateral_to_liquidator: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct VaultInput {
+    pub vault_id: String,
+    pub collateral_amount: String,
+    pub debt_e8s: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct VaultRedemptionOutput {
+    pub vault_id: String,
+    pub icusd_redeemed_e8s: String,
+    pub collateral_seized: String,
+}
+
+/// `redeem_on_vaults`: how a redemption of `redeemed_e8s` is spread over
+/// `vaults`, lowest collateral ratio first.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RedemptionSplitVector {
+    pub decimals: u8,
+    pub price_usd: String,
+    pub vaults: Vec<VaultInput>,
+    pub redeemed_e8s: String,
+    pub redemptions: Vec<VaultRedemptionOutput>,
+}
+
+fn collateral() -> CollateralType {
+    Principal::from_slice(&[10])
+}
+
+/// A state with one collateral at `price_usd` and `decimals`.
+fn fixture(decimals: u8, price_usd: f64) -> State {
+    let mut state = State::from(InitArg {
+        xrc_principal: Principal::anonymous(),
+        icusd_ledger_principal: Principal::anonymous(),
+        icp_ledger_principal: collateral(),
+        fee_e8s: 0,
+        developer_principal: Principal::anonymous(),
+        treasury_principal: None,
+        stability_pool_principal: None,
+        ckusdt_ledger_principal: None,
+        ckusdc_ledger_principal: None,
+    });
+    let config = state
+        .collateral_configs
+        .get_mut(&collateral())
+        .expect("fixture collateral");
+    config.decimals = decimals;
+    config.last_price = Some(price_usd);
+    state
+}
+
+fn vault(index: u64, collateral_amount: u64, debt_e8s: u64) -> Vault {
+    Vault {
+        owner: Principal::anonymous(),
+        vault_id: FIXTURE_VAULT_ID_BASE + index,
+        collateral_amount,
+        borrowed_icusd_amount: ICUSD::new(debt_e8s),
+        collateral_type: collateral(),
+        last_accrual_time: 0,
+        accrued_interest: ICUSD::new(0),
+        bot_processing: false,
+    }
+}
+
+fn price_decimal(price_usd: f64) -> Decimal {
+    Decimal::from_f64(price_usd).unwrap_or(Decimal::ZERO)
+}
+
+fn collateral_ratio_vector(
+    collateral_amount: u64,
+    decimals: u8,
+    price_usd: f64,
+    debt_e8s: u64,
+) -> CollateralRatioVector {
+    let state = fixture(decimals, price_usd);
+    let vault = vault(0, collateral_amount, debt_e8s);
+    let ratio =
+        crate::compute_collateral_ratio(&vault, UsdIcp::from(price_decimal(price_usd)), &state);
+    CollateralRatioVector {
+        collateral_amount: collateral_amount.to_string(),
+        decimals,
+        price_usd: price_usd.to_string(),
+        debt_e8s: debt_e8s.to_string(),
+        collateral_ratio: ratio.0.to_string(),
+    }
+}
+
+fn borrowing_fee_vector(amount_e8s: u64, fee_rate: Decimal) -> BorrowingFeeVector {
+    let amount = ICUSD::new(amount_e8s);
+    let fee = clamp_borrow_fee(amount, amount * Ratio::from(fee_rate));
+    BorrowingFeeVector {
+        amount_e8s: amount_e8s.to_string(),
+        fee_rate: fee_rate.to_string(),
+        fee_e8s: fee.to_u64().to_string(),
+    }
+}
+
+fn redemption_fee_vector(
+    elapsed_hours: u64,
+    redeemed_e8s: u64,
+    total_debt_e8s: u64,
+    base_rate: Decimal,
+    fee_floor: Decimal,
+    fee_ceiling: Decimal,
+) -> RedemptionFeeVector {
+    let fee = compute_redemption_fee(
+        elapsed_hours * 3_600 * 1_000_000_000,
+        ICUSD::new(redeemed_e8s),
+        ICUSD::new(total_debt_e8s),
+        Ratio::from(base_rate),
+        Ratio::from(fee_floor),
+        Ratio::from(fee_ceiling),
+    );
+    RedemptionFeeVector {
+        elapsed_hours: elapsed_hours.to_string(),
+        redeemed_e8s: redeemed_e8s.to_string(),
+        total_debt_e8s: total_debt_e8s.to_string(),
+        base_rate: base_rate.to_string(),
+        fee_floor: fee_floor.to_string(),
+        fee_ceiling: fee_ceiling.to_string(),
+        fee_rate: fee.0.to_string(),
+    }
+}
+
+fn liquidation_vector(
+    collateral_amount: u64,
+    decimals: u8,
+    price_usd: f64,
+    debt_e8s: u64,
+    repay_e8s: u64,
+    liquidation_bonus: Decimal,
+    protocol_share: Decimal,
+) -> LiquidationVector {
+    let mut state = fixture(decimals, price_usd);
+    if let Some(config) = state.collateral_configs.get_mut(&collateral()) {
+        config.liquidation_bonus = Ratio::from(liquidation_bonus);
+    }
+    state.liquidation_protocol_share = Ratio::from(protocol_share);
+    let vault = vault(0, collateral_amount, debt_e8s);
+    let (seized, protocol_cut, to_liquidator) = state.partial_liquidation_split(
+        &vault,
+        ICUSD::new(repay_e8s),
+        price_decimal(price_usd),
+        decimals,
+    );
+    LiquidationVector {
+        collateral_amount: collateral_amount.to_string(),
+        decimals,
+        price_usd: price_usd.to_string(),
+        debt_e8s: debt_e8s.to_string(),
+        repay_e8s: repay_e8s.to_string(),
+        liquidation_bonus: liquidation_bonus.to_string(),
+        protocol_share: protocol_share.to_string(),
+        collateral_seized: seized.to_u64().to_string(),
+        protocol_cut: protocol_cut.to_string(),
+        collateral_to_liquidator: to_liquidator.to_u64().to_string(),
+    }
+}
+
+/// `vaults` are `(collateral_amount, debt_e8s)` pairs.
+fn redemption_split_vector(
+    decimals: u8,
+    price_usd: f64,
+    vaults: &[(u64, u64)],
+    redeemed_e8s: u64,
+) -> RedemptionSplitVector {
+    let mut state = fixture(decimals, price_usd);
+    let mut inputs = Vec::with_capacity(vaults.len());
+    for (index, &(collateral_amount, debt_e8s)) in vaults.iter().enumerate() {
+        let vault = vault(index as u64, collateral_amount, debt_e8s);
+        inputs.push(VaultInput {
+            vault_id: vault.vault_id.to_string(),
+            collateral_amount: collateral_amount.to_string(),
+            debt_e8s: debt_e8s.to_string(),
+        });
+        state.open_vault(vault);
+    }
+    let redemptions = state.redeem_on_vaults(
+        ICUSD::new(redeemed_e8s),
+        UsdIcp::from(price_decimal(price_usd)),
+        &collateral(),
+    );
+    RedemptionSplitVector {
+        decimals,
+        price_usd: price_usd.to_string(),
+        vaults: inputs,
+        redeemed_e8s: redeemed_e8s.to_string(),
+        redemptions: redemptions
+            .iter()
+            .map(|r| VaultRedemptionOutput {
+                vault_id: r.vault_id.to_string(),
+                icusd_redeemed_e8s: r.icusd_redeemed_e8s.to_string(),
+                collateral_seized: r.collateral_seized.to_string(),
+            })
+            .collect(),
+    }
+}
+
+pub fn math_test_vectors() -> MathTestVectors {
+    const E8S: u64 = 100_000_000;
+    const E18: u64 = 1_000_000_000_000_000_000;
+    MathTestVectors {
+        version: MATH_TEST_VECTORS_VERSION,
+        collateral_ratio: vec![
+            collateral_ratio_vector(10 * E8S, 8, 10.0, 50 * E8S),
+            collateral_ratio_vector(123_456_789, 8, 7.123, 31 * E8S + 7),
+            collateral_ratio_vector(2_500_000_000, 6, 1.0, 1_000 * E8S),
+            collateral_ratio_vector(5 * E18, 18, 3_412.57, 9_000 * E8S),
+            collateral_ratio_vector(E8S, 8, 10.0, 1),
+            collateral_ratio_vector(E8S, 8, 10.0, 0),
+        ],
+        borrowing_fee: vec![
+            borrowing_fee_vector(100 * E8S, dec!(0.005)),
+            borrowing_fee_vector(123_456_789, dec!(0.0075)),
+            borrowing_fee_vector(199, dec!(0.005)),
+            borrowing_fee_vector(1, dec!(0.05)),
+            borrowing_fee_vector(10, dec!(1)),
+        ],
+        redemption_fee: vec![
+            redemption_fee_vector(
+                0,
+                1_000 * E8S,
+                100_000 * E8S,
+                dec!(0),
+                dec!(0.005),
+                dec!(0.05),
+            ),
+            redemption_fee_vector(0, 10 * E8S, 100_000 * E8S, dec!(0), dec!(0.005), dec!(0.05)),
+            redemption_fee_vector(
+                12,
+                500 * E8S,
+                100_000 * E8S,
+                dec!(0.02),
+                dec!(0.005),
+                dec!(0.05),
+            ),
+            redemption_fee_vector(
+                0,
+                20_000 * E8S,
+                100_000 * E8S,
+                dec!(0.01),
+                dec!(0.005),
+                dec!(0.05),
+            ),
+            redemption_fee_vector(
+                1_000,
+                E8S,
+                100_000 * E8S,
+                dec!(0.04),
+                dec!(0.003),
+                dec!(0.05),
+            ),
+            redemption_fee_vector(0, E8S, 0, dec!(0.01), dec!(0.005), dec!(0.05)),
+        ],
+        liquidation: vec![
+            liquidation_vector(10 * E8S, 8, 6.0, 50 * E8S, 25 * E8S, dec!(1.15), dec!(0.03)),
+            liquidation_vector(10 * E8S, 8, 5.0, 50 * E8S, 50 * E8S, dec!(1.15), dec!(0.03)),
+            liquidation_vector(10 * E8S, 8, 4.5, 50 * E8S, 50 * E8S, dec!(1.1), dec!(0.5)),
+            liquidation_vector(
+                1_200_000_000,
+                6,
+                1.0,
+                1_100 * E8S,
+                333 * E8S + 3,
+                dec!(1.05),
+                dec!(0.1),
+            ),
+            liquidation_vector(
+                2 * E18,
+                18,
+                3_000.0,
+                5_000 * E8S,
+                1_000 * E8S,
+                dec!(1.1),
+                dec!(0),
+            ),
+        ],
+        redemption_split: vec![
+            redemption_split_vector(8, 10.0, &[(15 * E8S, 100 * E8S)], 40 * E8S),
+            redemption_split_vector(
+                8,
+                10.0,
+                &[
+                    (15 * E8S, 100 * E8S),
+                    (20 * E8S, 100 * E8S),
+                    (30 * E8S, 100 * E8S),
+                ],
+                150 * E8S,
+            ),
+            redemption_split_vector(
+                8,
+                7.5,
+                &[
+                    (20 * E8S, 100 * E8S),
+                    (20 * E8S, 100 * E8S),
+                    (40 * E8S, 50 * E8S),
+                ],
+                123 * E8S + 45,
+            ),
+            redemption_split_vector(
+                6,
+                1.0,
+                &[(150_000_000, 100 * E8S), (300_000_000, 100 * E8S)],
+                500 * E8S,
+            ),
+        ],
+    }
+}
+
+/// `math_test_vectors` as the JSON artifact the frontend tests load.
+pub fn math_test_vectors_json() -> String {
+    serde_json::to_string_pretty(&math_test_vectors()).unwrap_or_default()
+}
@@ -0,0 +1,277 @@
+//! The protocol mode state machine.
+//!
+//! ```text
+//!   GeneralAvailability --(TCR < X, deviation hold)--> Recovery
+//!   Recovery            --(TCR >= X + band)----------> GeneralAvailability
+//!   any                 --(safety trigger)-----------> ReadOnly
+//!   ReadOnly            --(readiness checks pass)----> Recovery | GeneralAvailability
+//! ```
+//!
+//! `X` is the debt-weighted recovery threshold and `band` the Recovery exit
+//! band (`State::recovery_exit_band`, `DEFAULT_RECOVERY_EXIT_BAND` unless
+//! set), so a TCR hovering around `X` does not flip the mode on every tick.
+//!
+//! Every change goes through `State::transition_mode`, which checks the edge
+//! against `is_legal_mode_transition` and buffers it for a `ModeTransition`
+//! event carrying its `ModeTransitionReason`. Automatic moves toward a less
+//! restrictive mode additionally need a fresh price for every collateral
+//! that backs debt (`stale_price_blocking_relaxation`); moves toward a more
+//! restrictive mode never wait for prices.
+
+use crate::numeric::Ratio;
+use crate::state::{CollateralType, State};
+use crate::{ProtocolError, MINIMUM_COLLATERAL_RATIO, RECOVERY_COLLATERAL_RATIO};
+use rust_decimal_macros::dec;
+use serde::Serialize;
+use std::fmt;
+
+/// Default Recovery exit band: the protocol leaves Recovery once the TCR is
+/// 5 points above the recovery threshold.
+pub const DEFAULT_RECOVERY_EXIT_BAND: Ratio = Ratio::new(dec!(0.05));
+
+/// Widest Recovery exit band an admin can set.
+pub const MAX_RECOVERY_EXIT_BAND: f64 = 0.5;
+
+/// Oldest a collateral price may be for an automatic transition toward a
+/// less restrictive mode (10 minutes, the hard ceiling of
+/// `xrc::ensure_fresh_price_for`).
+pub const MODE_RELAXATION_MAX_PRICE_AGE_NANOS: u64 = 10 * 60 * 1_000_000_000;
+
+/// Controls which operations the protocol can perform.
+#[derive(candid::CandidType, Clone, Debug, PartialEq, Eq, serde::Deserialize, Serialize, Copy)]
+pub enum Mode {
+    /// Protocol's state is read-only.
+    ReadOnly,
+    /// No restrictions on the protocol interactions.
+    GeneralAvailability,
+    /// The protocols tries to get back to a total
+    /// collateral ratio above 150%
+    Recovery,
+    /// An upgrade is still replaying the event log (see `bootstrap`); every
+    /// operation is rejected until it completes. Never entered or left
+    /// through `State::transition_mode`.
+    Bootstrapping,
+}
+
+impl Mode {
+    pub fn is_available(&self) -> bool {
+        match self {
+            Mode::ReadOnly => false,
+            Mode::GeneralAvailability => true,
+            Mode::Recovery => true,
+            Mode::Bootstrapping => false,
+        }
+    }
+
+    /// The rejection for an operation attempted in this mode, if any.
+    pub fn check_available(&self) -> Result<(), ProtocolError> {
+        match self {
+            Mode::ReadOnly => Err(ProtocolError::read_only_mode()),
+            Mode::Bootstrapping => Err(ProtocolError::bootstrapping()),
+            Mode::GeneralAvailability | Mode::Recovery => Ok(()),
+        }
+    }
+
+    pub fn get_minimum_liquidation_collateral_ratio(&self) -> Ratio {
+        match self {
+            Mode::ReadOnly => MINIMUM_COLLATERAL_RATIO,
+            Mode::GeneralAvailability => MINIMUM_COLLATERAL_RATIO,
+            Mode::Recovery => RECOVERY_COLLATERAL_RATIO,
+            Mode::Bootstrapping => MINIMUM_COLLATERAL_RATIO,
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::ReadOnly => write!(f, "Read-only"),
+            Mode::GeneralAvailability => write!(f, "General availability"),
+            Mode::Recovery => write!(f, "Recovery"),
+            Mode::Bootstrapping => write!(f, "Bootstrapping"),
+        }
+    }
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Self::GeneralAvailability
+    }
+}
+
+/// Why the protocol mode changed. Carried on every `Event::ModeTransition`
+/// so the explorer can attribute each flip to its trigger, and checked by
+/// `State::transition_mode` against the legal transition graph.
+#[derive(candid::CandidType, Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModeTransitionReason {
+    /// Periodic re-evaluation of the total collateral ratio against the
+    /// debt-weighted recovery threshold.
+    CollateralRatio,
+    /// Total collateral ratio fell below 100%.
+    Insolvency,
+    /// Wave-14a CDP-01: consecutive XRC failures tripped the oracle breaker.
+    OracleCircuitBreaker,
+    /// A fresh XRC price cleared an oracle-triggered ReadOnly.
+    OracleRecovered,
+    /// A confirmed sub-$0.01 ICP price.
+    PriceFloor,
+    /// The price-deviation breaker rejected a sample under `enter_recovery`.
+    PriceDeviation,
+    /// Wave-8e LIQ-005: the deficit account reached
+    /// `deficit_readonly_threshold_e8s`.
+    DeficitThreshold,
+    /// Phase 1a: the multi-chain supply self-check found a divergence.
+    SupplyInvariantHalt,
+    /// A controller called `enter_recovery_mode` / `exit_recovery_mode`.
+    AdminOverride,
+    /// `UpgradeArg::mode` supplied by the controller at upgrade time.
+    Upgrade,
+}
+
+/// A mode change applied by `State::transition_mode`, buffered in
+/// `State::pending_mode_transitions` until the live path records it.
+#[derive(candid::CandidType, Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, Serialize)]
+pub struct ModeTransition {
+    pub from: Mode,
+    pub to: Mode,
+    pub reason: ModeTransitionReason,
+}
+
+/// Rejection returned by `State::transition_mode`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ModeTransitionError {
+    /// The caller's view of the current mode is stale: `from` did not match
+    /// `State::mode` at the time of the call.
+    UnexpectedCurrentMode { expected: Mode, actual: Mode },
+    /// `reason` is not a legal trigger for the `from -> to` edge.
+    IllegalTransition {
+        from: Mode,
+        to: Mode,
+        reason: ModeTransitionReason,
+    },
+    /// Leaving ReadOnly is legal for this reason, but a readiness check
+    /// (solvency, supply invariant, deficit latch) has not passed yet.
+    ExitChecksFailed(String),
+}
+
+impl fmt::Display for ModeTransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModeTransitionError::UnexpectedCurrentMode { expected, actual } => write!(
+                f,
+                "expected current mode {} but protocol is in {}",
+                expected, actual
+            ),
+            ModeTransitionError::IllegalTransition { from, to, reason } => write!(
+                f,
+                "illegal mode transition {} -> {} for reason {:?}",
+                from, to, reason
+            ),
+            ModeTransitionError::ExitChecksFailed(msg) => {
+                write!(f, "cannot leave Read-only: {}", msg)
+            }
+        }
+    }
+}
+
+impl From<ModeTransitionError> for ProtocolError {
+    fn from(e: ModeTransitionError) -> Self {
+        ProtocolError::GenericError(e.to_string())
+    }
+}
+
+/// The legal transition graph, independent of readiness checks.
+///
+/// * GeneralAvailability <-> Recovery: TCR re-evaluation, admin, upgrade;
+///   the price-deviation breaker can only enter Recovery.
+/// * any -> ReadOnly: any safety trigger, admin, upgrade (never the
+///   "things got better" reasons).
+/// * ReadOnly -> GeneralAvailability / Recovery: TCR re-evaluation, oracle
+///   recovery, admin, upgrade — and, except for upgrade, only once
+///   `State::readonly_exit_blocker` clears.
+/// * to or from Bootstrapping: never; the upgrade replay sets and clears it
+///   directly (see `bootstrap`).
+pub fn is_legal_mode_transition(from: Mode, to: Mode, reason: ModeTransitionReason) -> bool {
+    use ModeTransitionReason as R;
+    match (from, to) {
+        (a, b) if a == b => true,
+        (Mode::Bootstrapping, _) | (_, Mode::Bootstrapping) => false,
+        (_, Mode::ReadOnly) => !matches!(
+            reason,
+            R::CollateralRatio | R::OracleRecovered | R::PriceDeviation
+        ),
+        (Mode::ReadOnly, _) => matches!(
+            reason,
+            R::CollateralRatio | R::OracleRecovered | R::AdminOverride | R::Upgrade
+        ),
+        (Mode::GeneralAvailability, Mode::Recovery) => matches!(
+            reason,
+            R::CollateralRatio | R::PriceDeviation | R::AdminOverride | R::Upgrade
+        ),
+        (Mode::Recovery, Mode::GeneralAvailability) => {
+            matches!(reason, R::CollateralRatio | R::AdminOverride | R::Upgrade)
+        }
+        _ => false,
+    }
+}
+
+impl Mode {
+    /// How much the mode restricts: GeneralAvailability < Recovery < ReadOnly
+    /// < Bootstrapping.
+    pub fn restrictiveness(&self) -> u8 {
+        match self {
+            Mode::GeneralAvailability => 0,
+            Mode::Recovery => 1,
+            Mode::ReadOnly => 2,
+            Mode::Bootstrapping => 3,
+        }
+    }
+}
+
+/// Whether `from -> to` moves to a less restrictive mode.
+pub fn is_relaxation(from: Mode, to: Mode) -> bool {
+    to.restrictiveness() < from.restrictiveness()
+}
+
+/// The mode a solvent protocol (TCR >= 100%) should be in, by collateral
+/// ratio. Entering Recovery happens below `recovery_threshold`; leaving it,
+/// or leaving ReadOnly for GeneralAvailability, only at
+/// `recovery_threshold + exit_band`. A price held back by the deviation
+/// breaker keeps the protocol in Recovery whatever the ratio says.
+pub fn collateral_ratio_target(
+    current: Mode,
+    total_collateral_ratio: Ratio,
+    recovery_threshold: Ratio,
+    exit_band: Ratio,
+    deviation_hold: bool,
+) -> Mode {
+    let threshold = match current {
+        Mode::GeneralAvailability => recovery_threshold,
+        Mode::Recovery | Mode::ReadOnly | Mode::Bootstrapping => recovery_threshold + exit_band,
+    };
+    if deviation_hold || total_collateral_ratio < threshold {
+        Mode::Recovery
+    } else {
+        Mode::GeneralAvailability
+    }
+}
+
+/// The first collateral backing debt whose price is missing or older than
+/// `MODE_RELAXATION_MAX_PRICE_AGE_NANOS` at `now_ns`. An automatic
+/// transition toward a less restrictive mode waits while there is one.
+pub fn stale_price_blocking_relaxation(state: &State, now_ns: u64) -> Option<CollateralType> {
+    state
+        .collateral_configs
+        .iter()
+        .filter(|(ct, _)| state.total_debt_for_collateral(ct).to_u64() > 0)
+        .find(
+            |(_, config)| match (config.last_price, config.last_price_timestamp) {
+                (Some(_), Some(ts)) => {
+                    now_ns.saturating_sub(ts) > MODE_RELAXATION_MAX_PRICE_AGE_NANOS
+                }
+                _ => true,
+            },
+        )
+        .map(|(ct, _)| *ct)
+}
@@ -0,0 +1,176 @@
+//! Propagation of the protocol mode to companion canisters.
+//!
+//! The stability pool and the treasury run independently of the backend, so
+//! on their own they keep taking deposits, liquidating and paying out while
+//! the backend sits in ReadOnly or Recovery. Every mode change is therefore
+//! pushed to each registered companion through its
+//! `set_protocol_mode : (Mode) -> (variant { Ok; Err : .. })` endpoint; what
+//! a companion restricts in which mode is its own inheritance policy.
+//!
+//! Delivery is tracked per companion in `State::mode_companion_acks`, which
+//! is not persisted: after an upgrade the current mode is re-sent to all.
+//! A transport failure is retried after `MODE_PROPAGATION_RETRY_SECS`; a
+//! companion that answers `Err` (usually because it does not recognise this
+//! canister yet) is logged and left unacknowledged until the next mode
+//! change, upgrade or companion registration.
+
+use crate::logs::INFO;
+use crate::state::{mutate_state, read_state, Mode, State};
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use std::cell::Cell;
+use std::time::Duration;
+
+/// Delay before retrying a companion that could not be reached.
+pub const MODE_PROPAGATION_RETRY_SECS: u64 = 60;
+
+/// Most companions that can be registered.
+pub const MAX_MODE_COMPANIONS: usize = 8;
+
+thread_local! {
+    /// Set while a propagation pass is running, so overlapping mode changes
+    /// fold into the running pass instead of sending concurrently.
+    static PROPAGATING: Cell<bool> = const { Cell::new(false) };
+}
+
+struct PropagationGuard;
+
+impl PropagationGuard {
+    fn new() -> Option<Self> {
+        if PROPAGATING.with(|p| p.replace(true)) {
+            None
+        } else {
+            Some(Self)
+        }
+    }
+}
+
+impl Drop for PropagationGuard {
+    fn drop(&mut self) {
+        PROPAGATING.with(|p| p.set(false));
+    }
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct ModeCompanionStatus {
+    pub canister: Principal,
+    /// Last mode it accepted since the last upgrade.
+    pub acknowledged_mode: Option<Mode>,
+    /// Whether it has accepted the current mode.
+    pub in_sync: bool,
+}
+
+pub fn mode_propagation_status(state: &State) -> Vec<ModeCompanionStatus> {
+    state
+        .mode_companion_canisters
+        .iter()
+        .map(|c| {
+            let acknowledged_mode = state.mode_companion_acks.get(c).copied();
+            ModeCompanionStatus {
+                canister: *c,
+                acknowledged_mode,
+                in_sync: acknowledged_mode == Some(state.mode),
+            }
+        })
+        .collect()
+}
+
+/// Companions that have not acknowledged the current mode.
+pub fn companions_to_notify(state: &State) -> Vec<Principal> {
+    state
+        .mode_companion_canisters
+        .iter()
+        .filter(|c| state.mode_companion_acks.get(c) != Some(&state.mode))
+        .copied()
+        .collect()
+}
+
+/// Note that `companion` accepted `mode`. Ignored for a companion that was
+/// deregistered while the call was in flight.
+pub fn record_ack(state: &mut State, companion: Principal, mode: Mode) {
+    if state.mode_companion_canisters.contains(&companion) {
+        state.mode_companion_acks.insert(companion, mode);
+    }
+}
+
+/// Replace the registered companions, dropping acknowledgements of the ones
+/// removed, so every acknowledgement kept belongs to a registered companion.
+pub fn apply_set_companions(state: &mut State, canisters: Vec<Principal>) {
+    state.mode_companion_canisters = canisters.into_iter().collect();
+    let companions = &state.mode_companion_canisters;
+    state
+        .mode_companion_acks
+        .retain(|c, _| companions.contains(c));
+}
+
+pub fn validate_companions(canisters: &[Principal]) -> Result<(), String> {
+    if canisters.len() > MAX_MODE_COMPANIONS {
+        return Err(format!(
+            "At most {} mode companions can be registered",
+            MAX_MODE_COMPANIONS
+        ));
+    }
+    if canisters.contains(&Principal::anonymous()) {
+        return Err("The anonymous principal cannot be a mode companion".to_string());
+    }
+    Ok(())
+}
+
+/// Run a propagation pass on the next execution round.
+pub fn schedule_mode_propagation() {
+    ic_cdk_timers::set_timer(Duration::ZERO, || ic_cdk::spawn(propagate_mode()));
+}
+
+/// Send the current mode to every companion that has not acknowledged it,
+/// until all have or a transport failure schedules a retry.
+pub async fn propagate_mode() {
+    let Some(_guard) = PropagationGuard::new() else {
+        return;
+    };
+    loop {
+        let (mode, targets) = read_state(|s| (s.mode, companions_to_notify(s)));
+        if targets.is_empty() {
+            return;
+        }
+        let mut progressed = false;
+        let mut retry = false;
+        for companion in targets {
+            let result: Result<(Result<(), candid::Reserved>,), _> =
+                ic_cdk::call(companion, "set_protocol_mode", (mode,)).await;
+            match result {
+                Ok((Ok(()),)) => {
+                    mutate_state(|s| record_ack(s, companion, mode));
+                    progressed = true;
+                }
+                Ok((Err(_),)) => {
+                    log!(
+                        INFO,
+                        "[propagate_mode] {} rejected mode {:?}; check that it is configured to accept this canister",
+                        companion,
+                        mode
+                    );
+                }
+                Err((code, msg)) => {
+                    log!(
+                        INFO,
+                        "[propagate_mode] ERROR: could not reach {}: {:?} {}",
+                        companion,
+                        code,
+                        msg
+                    );
+                    retry = true;
+                }
+            }
+        }
+        if retry {
+            ic_cdk_timers::set_timer(Duration::from_secs(MODE_PROPAGATION_RETRY_SECS), || {
+                ic_cdk::spawn(propagate_mode())
+            });
+            return;
+        }
+        // Only rejections left: wait for the next trigger rather than spin.
+        if !progressed {
+            return;
+        }
+    }
+}
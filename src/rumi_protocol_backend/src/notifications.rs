@@ -0,0 +1,209 @@
+//! Per-owner notifications for vaults whose risk classification moved after
+//! a parameter change, that a guardian froze or unfroze (see
+//! `vault_freeze`), or that were liquidated (see `liquidation_receipts`).
+//!
+//! Admin setters that move a classification boundary (liquidation ratio,
+//! borrow threshold, healthy CR) snapshot the risk level of every vault of
+//! the affected collateral before the change, re-classify after it, and
+//! queue one `VaultNotification` for the owner of each vault whose level
+//! changed. Fee and interest-rate changes never move a vault between levels
+//! on their own, so they produce no entries. Owners can opt out of each kind
+//! of entry, or raise the level risk changes must reach, through their
+//! `user_preferences`.
+//!
+//! Owners read their queue with `get_my_notifications` and drop entries with
+//! `dismiss_my_notifications`. The queue lives in the state snapshot only; it
+//! is operational data and is not rebuilt by event replay. There is no push
+//! channel yet: frontends and bots poll.
+
+use crate::compute_collateral_ratio;
+use crate::numeric::UsdIcp;
+use crate::state::{CollateralType, State};
+use crate::vault::Vault;
+use candid::{CandidType, Deserialize, Principal};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Oldest entries are dropped beyond this many per owner.
+pub const MAX_NOTIFICATIONS_PER_OWNER: usize = 50;
+
+/// Risk bands, least to most severe.
+#[derive(
+    CandidType, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum VaultRiskLevel {
+    /// At or above the collateral's healthy CR.
+    Healthy,
+    /// Below healthy CR but at or above the borrow threshold.
+    Caution,
+    /// Below the borrow threshold but not yet liquidatable.
+    AtRisk,
+    /// Below the mode-dependent minimum liquidation ratio.
+    Liquidatable,
+}
+
+/// What a notification is about. Entries queued before freezes existed
+/// deserialize as `RiskChange`.
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VaultNotificationKind {
+    /// A parameter change moved the vault to `new_level`.
+    #[default]
+    RiskChange,
+    /// Withdrawals, borrows and closes are blocked until `expires_at_ns`.
+    Frozen {
+        reason: String,
+        expires_at_ns: u64,
+    },
+    Unfrozen,
+    /// The vault was liquidated; `get_my_liquidation_receipts` has the
+    /// details under `receipt_id`.
+    Liquidated {
+        receipt_id: u64,
+    },
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VaultNotification {
+    pub id: u64,
+    pub vault_id: u64,
+    pub collateral_type: CollateralType,
+    /// Name of the parameter whose change triggered this entry.
+    pub parameter: String,
+    pub previous_level: VaultRiskLevel,
+    pub new_level: VaultRiskLevel,
+    pub collateral_ratio: f64,
+    pub created_at_ns: u64,
+    #[serde(default)]
+    pub kind: VaultNotificationKind,
+}
+
+/// Classify a vault against its collateral's current thresholds. `None`
+/// when the collateral has no price, since the CR is then unknown.
+pub fn classify_vault(state: &State, vault: &Vault) -> Option<VaultRiskLevel> {
+    state.get_price_for(&vault.collateral_type)?;
+    let cr = compute_collateral_ratio(vault, UsdIcp::from(Decimal::ZERO), state);
+    let ct = &vault.collateral_type;
+    Some(if cr < state.get_min_liquidation_ratio_for(ct) {
+        VaultRiskLevel::Liquidatable
+    } else if cr < state.get_min_collateral_ratio_for(ct) {
+        VaultRiskLevel::AtRisk
+    } else if cr < state.get_healthy_cr_for(ct) {
+        VaultRiskLevel::Caution
+    } else {
+        VaultRiskLevel::Healthy
+    })
+}
+
+/// Risk level of every indebted vault of `collateral_type`.
+pub fn risk_snapshot(
+    state: &State,
+    collateral_type: CollateralType,
+) -> BTreeMap<u64, VaultRiskLevel> {
+    state
+        .vault_id_to_vaults
+        .values()
+        .filter(|v| v.collateral_type == collateral_type && v.borrowed_icusd_amount.to_u64() > 0)
+        .filter_map(|v| classify_vault(state, v).map(|level| (v.vault_id, level)))
+        .collect()
+}
+
+/// Apply a parameter change to `collateral_type` through `change` and
+/// notify the owner of every vault whose risk level moved. Returns the
+/// closure's result and the number of notifications queued.
+pub fn with_risk_notifications<R>(
+    state: &mut State,
+    collateral_type: CollateralType,
+    parameter: &str,
+    now_ns: u64,
+    change: impl FnOnce(&mut State) -> R,
+) -> (R, usize) {
+    let before = risk_snapshot(state, collateral_type);
+    let result = change(state);
+    let queued = notify_risk_changes(state, collateral_type, &before, parameter, now_ns);
+    (result, queued)
+}
+
+/// Compare the current classification against `before` and queue a
+/// notification for each vault whose level differs, as its owner's
+/// preferences allow. Returns how many were queued.
+pub fn notify_risk_changes(
+    state: &mut State,
+    collateral_type: CollateralType,
+    before: &BTreeMap<u64, VaultRiskLevel>,
+    parameter: &str,
+    now_ns: u64,
+) -> usize {
+    let changed: Vec<(Principal, u64, VaultRiskLevel, VaultRiskLevel, f64)> = state
+        .vault_id_to_vaults
+        .values()
+        .filter(|v| v.collateral_type == collateral_type)
+        .filter_map(|v| {
+            let previous = *before.get(&v.vault_id)?;
+            let new = classify_vault(state, v)?;
+            if previous == new {
+                return None;
+            }
+            let cr = compute_collateral_ratio(v, UsdIcp::from(Decimal::ZERO), state);
+            Some((v.owner, v.vault_id, previous, new, cr.to_f64()))
+        })
+        .collect();
+
+    let mut queued = 0;
+    for (owner, vault_id, previous_level, new_level, collateral_ratio) in changed {
+        let notification = VaultNotification {
+            id: 0,
+            vault_id,
+            collateral_type,
+            parameter: parameter.to_string(),
+            previous_level,
+            new_level,
+            collateral_ratio,
+            created_at_ns: now_ns,
+            kind: VaultNotificationKind::RiskChange,
+        };
+        if push_notification(state, owner, notification).is_some() {
+            queued += 1;
+        }
+    }
+    queued
+}
+
+/// Queue `notification` for `owner` under the next id, dropping the oldest
+/// entries beyond `MAX_NOTIFICATIONS_PER_OWNER`. Returns the assigned id,
+/// or `None` when the owner's preferences leave it out (see
+/// `user_preferences`).
+pub fn push_notification(
+    state: &mut State,
+    owner: Principal,
+    mut notification: VaultNotification,
+) -> Option<u64> {
+    if !crate::user_preferences::preferences(state, owner).wants(&notification) {
+        return None;
+    }
+    let id = state.next_notification_id;
+    state.next_notification_id += 1;
+    notification.id = id;
+    let queue = state.vault_notifications.entry(owner).or_default();
+    queue.push(notification);
+    if queue.len() > MAX_NOTIFICATIONS_PER_OWNER {
+        let excess = queue.len() - MAX_NOTIFICATIONS_PER_OWNER;
+        queue.drain(..excess);
+    }
+    Some(id)
+}
+
+/// Drop `owner`'s notifications with `id <= up_to_id`; returns how many
+/// were removed.
+pub fn dismiss_notifications(state: &mut State, owner: Principal, up_to_id: u64) -> usize {
+    let Some(queue) = state.vault_notifications.get_mut(&owner) else {
+        return 0;
+    };
+    let before = queue.len();
+    queue.retain(|n| n.id > up_to_id);
+    let removed = before - queue.len();
+    if queue.is_empty() {
+        state.vault_notifications.remove(&owner);
+    }
+    removed
+}
@@ -0,0 +1,288 @@
+//! Client-supplied operation ids, so a retried vault operation applies once.
+//!
+//! A client whose `open_vault`, `borrow_from_vault`, `repay_to_vault` or
+//! `close_vault` call timed out cannot tell whether it went through, and a
+//! blind retry may borrow twice. Each of these endpoints takes an optional
+//! 32-byte `operation_id`; a client that sends the same id with every retry
+//! of one operation gets it applied at most once:
+//!
+//! - The first call claims the id before doing anything else. Ids are
+//!   scoped to the caller, so they only need to be unique per principal.
+//! - A retry of an operation that succeeded gets the original result back
+//!   without running again.
+//! - A retry while the operation is still running is refused with
+//!   `TemporarilyUnavailable`; the client retries later.
+//! - A failed operation applies nothing and releases its id, so its retry
+//!   runs again.
+//! - An id sent with different arguments, or for another operation, is
+//!   refused.
+//!
+//! Ids are remembered for `OPERATION_ID_TTL_NANOS` after they are claimed,
+//! at most `MAX_OPERATION_IDS` of them, oldest evicted first. A claim left
+//! running by a call that trapped can be taken over after
+//! `GUARD_HARD_TIMEOUT_NANOS`, when the operation's guard would be cleared
+//! too. Like guards, operation ids live in the state snapshot only and are
+//! not rebuilt by event replay.
+
+use crate::guard_metrics::GUARD_HARD_TIMEOUT_NANOS;
+use crate::state::State;
+use crate::vault::OpenVaultSuccess;
+use crate::{ProtocolError, SuccessWithFee};
+use candid::Principal;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// How long a claimed id is remembered.
+pub const OPERATION_ID_TTL_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Operation ids remembered at most, across principals.
+pub const MAX_OPERATION_IDS: usize = 100_000;
+
+pub type OperationId = [u8; 32];
+
+/// The operation an id was claimed for, with its arguments.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OperationRequest {
+    OpenVault {
+        collateral_amount: u64,
+        collateral_type: Option<Principal>,
+    },
+    Borrow {
+        vault_id: u64,
+        amount: u64,
+    },
+    Repay {
+        vault_id: u64,
+        amount: u64,
+    },
+    Close {
+        vault_id: u64,
+    },
+}
+
+/// What a successful operation returned.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OperationOutcome {
+    OpenVault {
+        vault_id: u64,
+        block_index: u64,
+    },
+    Borrow {
+        block_index: u64,
+        fee_amount_paid: u64,
+    },
+    Repay {
+        block_index: u64,
+    },
+    Close {
+        block_index: Option<u64>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationRecord {
+    pub request: OperationRequest,
+    pub claimed_at_ns: u64,
+    /// `None` while the operation runs.
+    pub outcome: Option<OperationOutcome>,
+}
+
+/// The ids claimed per principal, and the order they were claimed in.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationIds {
+    records: BTreeMap<(Principal, OperationId), OperationRecord>,
+    /// `(claimed_at_ns, principal, id)` of every record, oldest first.
+    claims: BTreeSet<(u64, Principal, OperationId)>,
+}
+
+impl OperationIds {
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn get(&self, principal: Principal, id: &OperationId) -> Option<&OperationRecord> {
+        self.records.get(&(principal, *id))
+    }
+
+    fn insert(&mut self, principal: Principal, id: OperationId, record: OperationRecord) {
+        self.claims.insert((record.claimed_at_ns, principal, id));
+        if let Some(old) = self.records.insert((principal, id), record) {
+            self.claims.remove(&(old.claimed_at_ns, principal, id));
+        }
+    }
+
+    fn remove(&mut self, principal: Principal, id: &OperationId) {
+        if let Some(old) = self.records.remove(&(principal, *id)) {
+            self.claims.remove(&(old.claimed_at_ns, principal, *id));
+        }
+    }
+
+    /// Forget the oldest claim. Returns `false` when there is none.
+    fn evict_oldest(&mut self) -> bool {
+        match self.claims.pop_first() {
+            Some((_, principal, id)) => {
+                self.records.remove(&(principal, id));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A success value of one of the deduplicated operations.
+pub trait RecordedOutcome: Sized {
+    fn outcome(&self) -> OperationOutcome;
+    /// The value `outcome` was recorded from, `None` for another
+    /// operation's outcome.
+    fn from_outcome(outcome: &OperationOutcome) -> Option<Self>;
+}
+
+impl RecordedOutcome for OpenVaultSuccess {
+    fn outcome(&self) -> OperationOutcome {
+        OperationOutcome::OpenVault {
+            vault_id: self.vault_id,
+            block_index: self.block_index,
+        }
+    }
+    fn from_outcome(outcome: &OperationOutcome) -> Option<Self> {
+        match *outcome {
+            OperationOutcome::OpenVault {
+                vault_id,
+                block_index,
+            } => Some(Self {
+                vault_id,
+                block_index,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl RecordedOutcome for SuccessWithFee {
+    fn outcome(&self) -> OperationOutcome {
+        OperationOutcome::Borrow {
+            block_index: self.block_index,
+            fee_amount_paid: self.fee_amount_paid,
+        }
+    }
+    fn from_outcome(outcome: &OperationOutcome) -> Option<Self> {
+        match *outcome {
+            OperationOutcome::Borrow {
+                block_index,
+                fee_amount_paid,
+            } => Some(Self {
+                block_index,
+                fee_amount_paid,
+                collateral_amount_received: None,
+                debt_liquidated_e8s: None,
+                stable_pulled_e6s: None,
+                xrp_claim_id: None,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// `repay_to_vault`'s block index.
+impl RecordedOutcome for u64 {
+    fn outcome(&self) -> OperationOutcome {
+        OperationOutcome::Repay { block_index: *self }
+    }
+    fn from_outcome(outcome: &OperationOutcome) -> Option<Self> {
+        match *outcome {
+            OperationOutcome::Repay { block_index } => Some(block_index),
+            _ => None,
+        }
+    }
+}
+
+/// `close_vault`'s block index.
+impl RecordedOutcome for Option<u64> {
+    fn outcome(&self) -> OperationOutcome {
+        OperationOutcome::Close { block_index: *self }
+    }
+    fn from_outcome(outcome: &OperationOutcome) -> Option<Self> {
+        match *outcome {
+            OperationOutcome::Close { block_index } => Some(block_index),
+            _ => None,
+        }
+    }
+}
+
+/// Parse an `operation_id` argument.
+pub fn parse_operation_id(bytes: &[u8]) -> Result<OperationId, ProtocolError> {
+    OperationId::try_from(bytes)
+        .map_err(|_| ProtocolError::GenericError("The operation id must be 32 bytes".to_string()))
+}
+
+/// Forget the ids claimed more than `OPERATION_ID_TTL_NANOS` before `now_ns`.
+pub fn prune(state: &mut State, now_ns: u64) {
+    let ids = &mut state.operation_ids;
+    while ids.claims.first().is_some_and(|(claimed_at_ns, _, _)| {
+        now_ns.saturating_sub(*claimed_at_ns) >= OPERATION_ID_TTL_NANOS
+    }) {
+        ids.evict_oldest();
+    }
+}
+
+/// Claim `id` for `principal`'s `request` at `now_ns`. Returns the recorded
+/// outcome when the operation already succeeded, `None` when the caller is
+/// to run it.
+pub fn claim(
+    state: &mut State,
+    principal: Principal,
+    id: OperationId,
+    request: OperationRequest,
+    now_ns: u64,
+) -> Result<Option<OperationOutcome>, ProtocolError> {
+    prune(state, now_ns);
+    let ids = &mut state.operation_ids;
+    if let Some(record) = ids.records.get(&(principal, id)) {
+        if record.request != request {
+            return Err(ProtocolError::GenericError(
+                "The operation id was already used for a different operation".to_string(),
+            ));
+        }
+        if let Some(outcome) = &record.outcome {
+            return Ok(Some(outcome.clone()));
+        }
+        if now_ns.saturating_sub(record.claimed_at_ns) < GUARD_HARD_TIMEOUT_NANOS {
+            return Err(ProtocolError::TemporarilyUnavailable(
+                "The operation with this id is still in progress".to_string(),
+            ));
+        }
+    } else {
+        while ids.len() >= MAX_OPERATION_IDS && ids.evict_oldest() {}
+    }
+    ids.insert(
+        principal,
+        id,
+        OperationRecord {
+            request,
+            claimed_at_ns: now_ns,
+            outcome: None,
+        },
+    );
+    Ok(None)
+}
+
+/// Record the outcome of the operation that claimed `id`.
+pub fn complete(
+    state: &mut State,
+    principal: Principal,
+    id: OperationId,
+    outcome: OperationOutcome,
+) {
+    if let Some(record) = state.operation_ids.records.get_mut(&(principal, id)) {
+        record.outcome = Some(outcome);
+    }
+}
+
+/// Release `id` after its operation failed, so a retry runs it again.
+pub fn release(state: &mut State, principal: Principal, id: OperationId) {
+    state.operation_ids.remove(principal, &id);
+}
@@ -0,0 +1,525 @@
+//! Operator panel over the HTTP gateway, for incident response without dfx.
+//!
+//! `GET /dashboard/ops` serves a page that asks for the operator token and
+//! drives two routes with it in the `x-rumi-ops-token` header:
+//!
+//! - `GET /dashboard/ops/status`: the kill switches, each collateral's price
+//!   and its age, the pending transfer queues, and the guards held past the
+//!   hard timeout.
+//! - `POST /dashboard/ops/<action>`: one `OpsAction`, a thin wrapper over
+//!   the admin endpoint or timer with the same effect.
+//!
+//! The actions wrapping `pause_operation`, `unpause_operation` and
+//! `resolve_failed_transfer` also check the caller of
+//! `http_request_update` as those endpoints do (`check_caller`): the token
+//! alone does not make an emergency pauser or the developer. They need a
+//! request signed by one, which the page's anonymous calls are not.
+//!
+//! The gateway's query call cannot change state, so `http_request` answers
+//! an authorized POST with `upgrade = true` and the gateway repeats it as
+//! `http_request_update`. Both check the token.
+//!
+//! The gateway calls anonymously, so the token is the only credential. A
+//! controller sets its SHA-256 with `set_ops_panel_token_hash`; controllers
+//! only, since the actions include the controller-only kill switches. With
+//! no hash set, the default, the token routes answer 404. The hash lives in
+//! the snapshot, not the event log: a full replay turns the panel off until
+//! it is set again.
+
+use crate::guard_metrics::{self, GUARD_HARD_TIMEOUT_NANOS};
+use crate::logs::INFO;
+use crate::operation_pauses::PausableOperation;
+use crate::state::{mutate_state, read_state, State};
+use crate::transfer_retry::PendingTransferKey;
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use ic_canisters_http_types::HttpRequest;
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+
+pub const TOKEN_HEADER: &str = "x-rumi-ops-token";
+
+const PREFIX: &str = "/dashboard/ops";
+
+/// `ic_canisters_http_types::HttpResponse` with the gateway's `upgrade`
+/// flag, which asks it to repeat the request as an update call.
+#[derive(CandidType, Clone, Debug, Deserialize)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: ByteBuf,
+    pub upgrade: Option<bool>,
+}
+
+impl From<ic_canisters_http_types::HttpResponse> for HttpResponse {
+    fn from(response: ic_canisters_http_types::HttpResponse) -> Self {
+        Self {
+            status_code: response.status_code,
+            headers: response.headers,
+            body: response.body,
+            upgrade: None,
+        }
+    }
+}
+
+fn respond(status_code: u16, content_type: &str, body: Vec<u8>) -> HttpResponse {
+    HttpResponse {
+        status_code,
+        headers: vec![
+            ("Content-Type".to_string(), content_type.to_string()),
+            ("Content-Length".to_string(), body.len().to_string()),
+            ("Cache-Control".to_string(), "no-store".to_string()),
+        ],
+        body: ByteBuf::from(body),
+        upgrade: None,
+    }
+}
+
+fn respond_text(status_code: u16, text: &str) -> HttpResponse {
+    respond(
+        status_code,
+        "text/plain; charset=utf-8",
+        text.as_bytes().to_vec(),
+    )
+}
+
+fn respond_json<T: Serialize>(value: &T) -> HttpResponse {
+    match serde_json::to_vec(value) {
+        Ok(body) => respond(200, "application/json; charset=utf-8", body),
+        Err(err) => respond_text(500, &format!("failed to encode: {}", err)),
+    }
+}
+
+pub fn token_hash(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenCheck {
+    /// No hash is set: the panel is off.
+    Disabled,
+    Missing,
+    Wrong,
+    Valid,
+}
+
+/// Check the token in `headers` against the hash in `state`. Digests are
+/// compared, so the comparison leaks nothing usable about the token.
+pub fn check_token(state: &State, headers: &[(String, String)]) -> TokenCheck {
+    let Some(expected) = state.ops_panel_token_hash else {
+        return TokenCheck::Disabled;
+    };
+    let token = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(TOKEN_HEADER))
+        .map(|(_, value)| value.trim());
+    match token {
+        None | Some("") => TokenCheck::Missing,
+        Some(token) if token_hash(token) == expected => TokenCheck::Valid,
+        Some(_) => TokenCheck::Wrong,
+    }
+}
+
+fn deny(check: TokenCheck) -> Option<HttpResponse> {
+    match check {
+        TokenCheck::Valid => None,
+        TokenCheck::Disabled => Some(respond_text(404, "not found")),
+        TokenCheck::Missing => Some(respond_text(401, "operator token required")),
+        TokenCheck::Wrong => Some(respond_text(403, "wrong operator token")),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpsAction {
+    /// Fetch every collateral's price now, as its timer would.
+    RefreshPrices,
+    /// Run the pending transfer queues now instead of at the next tick,
+    /// cutting every backoff short. Transfers dead-lettered after
+    /// `MAX_PENDING_RETRIES` have left the queues; `resolve_failed_transfer`
+    /// puts them back.
+    RetryPendingTransfers,
+    /// `guard_metrics::resolve_stuck_guards` now.
+    ResolveStuckGuards,
+    /// `freeze_protocol` / `unfreeze_protocol`.
+    SetFrozen(bool),
+    /// `set_liquidation_frozen`.
+    SetLiquidationFrozen(bool),
+    /// `set_sp_writedown_disabled`.
+    SetSpWritedownDisabled(bool),
+    /// `pause_operation`.
+    PauseOperation(PausableOperation),
+    /// `unpause_operation`.
+    UnpauseOperation(PausableOperation),
+    /// `resolve_failed_transfer` with `Requeue`.
+    RequeueFailedTransfer(PendingTransferKey),
+}
+
+impl OpsAction {
+    /// The action at `/dashboard/ops/<name>`, with `value`: `true` or
+    /// `false` for the switches, the operation's name (`Borrow`, ...) for
+    /// the pauses, and the transfer key for a requeue, as
+    /// `margin:<vault id>:<owner>`, `excess:<vault id>:<owner>`,
+    /// `redemption:<block>`, `refund:<block>` or `three_usd_refund:<nonce>`.
+    pub fn parse(name: &str, value: Option<&str>) -> Option<Self> {
+        let switch = match value {
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            _ => None,
+        };
+        match name {
+            "refresh_prices" => Some(Self::RefreshPrices),
+            "retry_pending_transfers" => Some(Self::RetryPendingTransfers),
+            "resolve_stuck_guards" => Some(Self::ResolveStuckGuards),
+            "set_frozen" => switch.map(Self::SetFrozen),
+            "set_liquidation_frozen" => switch.map(Self::SetLiquidationFrozen),
+            "set_sp_writedown_disabled" => switch.map(Self::SetSpWritedownDisabled),
+            "pause_operation" => value.and_then(parse_operation).map(Self::PauseOperation),
+            "unpause_operation" => value.and_then(parse_operation).map(Self::UnpauseOperation),
+            "requeue_failed_transfer" => value
+                .and_then(parse_transfer_key)
+                .map(Self::RequeueFailedTransfer),
+            _ => None,
+        }
+    }
+}
+
+fn parse_operation(name: &str) -> Option<PausableOperation> {
+    match name {
+        "Borrow" => Some(PausableOperation::Borrow),
+        "Redemption" => Some(PausableOperation::Redemption),
+        "Liquidation" => Some(PausableOperation::Liquidation),
+        "ReserveRedemption" => Some(PausableOperation::ReserveRedemption),
+        _ => None,
+    }
+}
+
+fn parse_transfer_key(value: &str) -> Option<PendingTransferKey> {
+    let mut parts = value.split(':');
+    let kind = parts.next()?;
+    let id = parts.next()?;
+    let owner = parts.next().map(Principal::from_text);
+    if parts.next().is_some() {
+        return None;
+    }
+    let key = match (kind, owner) {
+        ("margin", Some(Ok(owner))) => PendingTransferKey::Margin {
+            vault_id: id.parse().ok()?,
+            owner,
+        },
+        ("excess", Some(Ok(owner))) => PendingTransferKey::Excess {
+            vault_id: id.parse().ok()?,
+            owner,
+        },
+        ("redemption", None) => PendingTransferKey::Redemption {
+            icusd_block_index: id.parse().ok()?,
+        },
+        ("refund", None) => PendingTransferKey::Refund {
+            icusd_block_index: id.parse().ok()?,
+        },
+        ("three_usd_refund", None) => PendingTransferKey::ThreeUsdRefund {
+            op_nonce: id.parse().ok()?,
+        },
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// Refuse `caller` for `action` where the endpoint it wraps would. Actions
+/// wrapping controller-only switches and timers rely on the token alone.
+pub fn check_caller(
+    state: &State,
+    caller: Principal,
+    action: OpsAction,
+) -> Result<(), ProtocolError> {
+    match action {
+        OpsAction::PauseOperation(_) | OpsAction::UnpauseOperation(_) => {
+            crate::operation_pauses::check_pause_authority(state, caller)
+        }
+        OpsAction::RequeueFailedTransfer(_) => crate::transfer_retry::check_resolver(state, caller),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OpsPrice {
+    pub collateral_type: String,
+    pub symbol: Option<String>,
+    pub status: String,
+    pub price: Option<f64>,
+    /// Seconds since the price was fetched.
+    pub age_secs: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct OpsStuckGuard {
+    pub principal: String,
+    pub operation_name: String,
+    pub held_secs: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OpsStatus {
+    pub mode: String,
+    pub frozen: bool,
+    pub liquidation_frozen: bool,
+    pub sp_writedown_disabled: bool,
+    pub prices: Vec<OpsPrice>,
+    pub pending_margin_transfers: u64,
+    pub pending_excess_transfers: u64,
+    pub pending_redemption_transfers: u64,
+    /// Guards held past `GUARD_HARD_TIMEOUT_NANOS`.
+    pub stuck_guards: Vec<OpsStuckGuard>,
+}
+
+pub fn status(state: &State, now: u64) -> OpsStatus {
+    const NANOS_PER_SEC: u64 = 1_000_000_000;
+    OpsStatus {
+        mode: format!("{:?}", state.mode),
+        frozen: state.frozen,
+        liquidation_frozen: state.liquidation_frozen,
+        sp_writedown_disabled: state.sp_writedown_disabled,
+        prices: state
+            .collateral_configs
+            .iter()
+            .map(|(collateral_type, config)| OpsPrice {
+                collateral_type: collateral_type.to_text(),
+                symbol: config.symbol.clone(),
+                status: format!("{:?}", config.status),
+                price: config.last_price,
+                age_secs: config
+                    .last_price_timestamp
+                    .map(|at| now.saturating_sub(at) / NANOS_PER_SEC),
+            })
+            .collect(),
+        pending_margin_transfers: state.pending_margin_transfers.len() as u64,
+        pending_excess_transfers: state.pending_excess_transfers.len() as u64,
+        pending_redemption_transfers: state.pending_redemption_transfer.len() as u64,
+        stuck_guards: guard_metrics::stuck_guards(state, now)
+            .into_iter()
+            .map(|guard| OpsStuckGuard {
+                principal: guard.principal.to_text(),
+                operation_name: guard.operation_name,
+                held_secs: now.saturating_sub(guard.started_at) / NANOS_PER_SEC,
+            })
+            .collect(),
+    }
+}
+
+/// The action a `/dashboard/ops/<action>` request names, or why it names
+/// none.
+fn requested_action(req: &HttpRequest) -> Result<OpsAction, HttpResponse> {
+    if req.method != "POST" {
+        return Err(respond_text(405, "actions are POST requests"));
+    }
+    let name = req
+        .path()
+        .strip_prefix(PREFIX)
+        .and_then(|rest| rest.strip_prefix('/'))
+        .unwrap_or_default();
+    OpsAction::parse(name, req.raw_query_param("value"))
+        .ok_or_else(|| respond_text(400, &format!("unknown action '{}'", name)))
+}
+
+/// Answer a `/dashboard/ops` request in `http_request`, or `None` for any
+/// other path.
+pub fn http_query(req: &HttpRequest) -> Option<HttpResponse> {
+    let path = req.path();
+    if path != PREFIX && !path.starts_with("/dashboard/ops/") {
+        return None;
+    }
+    if path == PREFIX {
+        return Some(respond(
+            200,
+            "text/html; charset=utf-8",
+            PANEL_HTML.as_bytes().to_vec(),
+        ));
+    }
+    if let Some(denied) = deny(read_state(|s| check_token(s, &req.headers))) {
+        return Some(denied);
+    }
+    if path == "/dashboard/ops/status" {
+        return Some(read_state(|s| {
+            respond_json(&status(s, ic_cdk::api::time()))
+        }));
+    }
+    Some(match requested_action(req) {
+        Ok(_) => HttpResponse {
+            upgrade: Some(true),
+            ..respond_text(200, "")
+        },
+        Err(response) => response,
+    })
+}
+
+#[derive(Serialize)]
+struct ActionResult {
+    action: String,
+    result: String,
+}
+
+/// Run the `/dashboard/ops/<action>` request the gateway upgraded.
+pub async fn http_update(req: HttpRequest) -> HttpResponse {
+    if let Some(denied) = deny(read_state(|s| check_token(s, &req.headers))) {
+        return denied;
+    }
+    let action = match requested_action(&req) {
+        Ok(action) => action,
+        Err(response) => return response,
+    };
+    let caller = ic_cdk::caller();
+    if let Err(err) = read_state(|s| check_caller(s, caller, action)) {
+        return respond_text(403, &format!("{:?}", err));
+    }
+    let result = run(action, caller).await;
+    log!(INFO, "[ops_panel] {:?}: {}", action, result);
+    respond_json(&ActionResult {
+        action: format!("{:?}", action),
+        result,
+    })
+}
+
+async fn run(action: OpsAction, caller: Principal) -> String {
+    match action {
+        OpsAction::RefreshPrices => {
+            let icp = read_state(|s| s.icp_collateral_type());
+            ic_cdk::spawn(crate::xrc::fetch_icp_rate());
+            let others: Vec<_> = read_state(|s| {
+                s.collateral_configs
+                    .keys()
+                    .filter(|collateral_type| **collateral_type != icp)
+                    .copied()
+                    .collect()
+            });
+            for collateral_type in &others {
+                crate::xrc::spawn_collateral_price_fetch_if_needed(*collateral_type);
+            }
+            format!("price fetches started for {} collaterals", others.len() + 1)
+        }
+        OpsAction::RetryPendingTransfers => {
+            let queued = mutate_state(|s| {
+                s.pending_transfer_backoff.clear();
+                crate::transfer_retry::metrics(s, ic_cdk::api::time()).queued
+            });
+            ic_cdk::spawn(crate::process_pending_transfer());
+            format!("processing {} pending transfers", queued)
+        }
+        OpsAction::ResolveStuckGuards => {
+            let cleared = guard_metrics::resolve_stuck_guards().await;
+            format!(
+                "cleared {} guards held past {}s",
+                cleared,
+                GUARD_HARD_TIMEOUT_NANOS / 1_000_000_000
+            )
+        }
+        OpsAction::SetFrozen(frozen) => {
+            mutate_state(|s| s.frozen = frozen);
+            format!("frozen = {}", frozen)
+        }
+        OpsAction::SetLiquidationFrozen(frozen) => {
+            mutate_state(|s| s.liquidation_frozen = frozen);
+            format!("liquidation_frozen = {}", frozen)
+        }
+        OpsAction::SetSpWritedownDisabled(disabled) => {
+            mutate_state(|s| s.sp_writedown_disabled = disabled);
+            format!("sp_writedown_disabled = {}", disabled)
+        }
+        OpsAction::PauseOperation(operation) => set_operation_paused(operation, true, caller),
+        OpsAction::UnpauseOperation(operation) => set_operation_paused(operation, false, caller),
+        OpsAction::RequeueFailedTransfer(key) => {
+            match mutate_state(|s| crate::transfer_retry::requeue(s, key)) {
+                Ok(()) => {
+                    crate::scheduler::kick_pending_transfers(0);
+                    format!("requeued {:?}", key)
+                }
+                Err(err) => format!("{:?} not requeued: {:?}", key, err),
+            }
+        }
+    }
+}
+
+/// What `pause_operation` / `unpause_operation` do once the caller passed.
+fn set_operation_paused(operation: PausableOperation, paused: bool, caller: Principal) -> String {
+    if read_state(|s| crate::operation_pauses::is_paused(s, operation)) != paused {
+        mutate_state(|s| crate::event::record_set_operation_paused(s, operation, paused, caller));
+    }
+    format!("{:?} paused = {}", operation, paused)
+}
+
+const PANEL_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<title>Rumi Protocol Operations</title>
+<style>
+  body { font-family: sans-serif; max-width: 960px; margin: 0 auto; }
+  table { border: thin solid; width: 100%; text-align: left; }
+  tbody tr:nth-child(odd) { background-color: #eeeeee; }
+  h3 { font-variant: small-caps; margin: 30px 0 5px; }
+  button { margin: 2px; }
+  #out { white-space: pre-wrap; }
+</style>
+</head>
+<body>
+<h3>Operations</h3>
+<div>
+  <button onclick="act('refresh_prices')">Refresh prices</button>
+  <button onclick="act('retry_pending_transfers')">Retry pending transfers</button>
+  <button onclick="act('resolve_stuck_guards')">Resolve stuck guards</button>
+</div>
+<div id="switches"></div>
+<div id="out"></div>
+<h3>Prices</h3>
+<table><thead><tr><th>Collateral</th><th>Symbol</th><th>Status</th><th>Price</th><th>Age (s)</th></tr></thead><tbody id="prices"></tbody></table>
+<h3>Pending Transfers</h3>
+<div id="pending"></div>
+<h3>Stuck Guards</h3>
+<table><thead><tr><th>Principal</th><th>Operation</th><th>Held (s)</th></tr></thead><tbody id="guards"></tbody></table>
+<script>
+function token() {
+  var t = sessionStorage.getItem("ops-token");
+  if (!t) { t = prompt("Operator token"); sessionStorage.setItem("ops-token", t || ""); }
+  return t;
+}
+function call(method, path) {
+  return fetch("/dashboard/ops/" + path, { method: method, headers: { "x-rumi-ops-token": token() } })
+    .then(function (r) {
+      if (r.status === 401 || r.status === 403) { sessionStorage.removeItem("ops-token"); }
+      return r.ok ? r.json() : r.text().then(function (t) { throw new Error(r.status + " " + t); });
+    });
+}
+function cell(row, text) { var td = document.createElement("td"); td.textContent = text; row.appendChild(td); }
+function rows(id, items, fields) {
+  var body = document.getElementById(id);
+  body.innerHTML = "";
+  items.forEach(function (item) {
+    var row = document.createElement("tr");
+    fields.forEach(function (f) { cell(row, item[f] === null ? "-" : item[f]); });
+    body.appendChild(row);
+  });
+}
+function toggle(name, on) {
+  return "<button onclick=\"act('" + name + "?value=" + !on + "')\">" + name.replace("set_", "") + ": " + on + "</button>";
+}
+function load() {
+  call("GET", "status").then(function (s) {
+    document.getElementById("switches").innerHTML = "Mode: " + s.mode + " " +
+      toggle("set_frozen", s.frozen) + toggle("set_liquidation_frozen", s.liquidation_frozen) +
+      toggle("set_sp_writedown_disabled", s.sp_writedown_disabled);
+    rows("prices", s.prices, ["collateral_type", "symbol", "status", "price", "age_secs"]);
+    document.getElementById("pending").textContent = "margin " + s.pending_margin_transfers +
+      ", excess " + s.pending_excess_transfers + ", redemption " + s.pending_redemption_transfers;
+    rows("guards", s.stuck_guards, ["principal", "operation_name", "held_secs"]);
+  }).catch(function (e) { document.getElementById("out").textContent = e.message; });
+}
+function act(path) {
+  call("POST", path).then(function (r) {
+    document.getElementById("out").textContent = r.action + ": " + r.result;
+    load();
+  }).catch(function (e) { document.getElementById("out").textContent = e.message; });
+}
+load();
+</script>
+</body>
+</html>
+"#;
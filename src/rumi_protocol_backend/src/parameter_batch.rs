@@ -0,0 +1,457 @@
+//! Bulk admin parameter updates.
+//!
+//! `admin_apply_parameter_batch` takes several setter-style changes and
+//! applies all of them or none. Each change is checked against the bounds of
+//! the single setter it stands in for, then the batch as a whole is checked
+//! against the cross-parameter constraints those setters enforce one at a
+//! time (`liquidation_ratio < borrow_threshold_ratio < healthy_cr`, redemption
+//! fee floor ≤ ceiling), using the values the batch would leave behind. A
+//! batch can therefore move a collateral's ratios together where a sequence
+//! of single setters would have to find a valid order to step through.
+//!
+//! The batch is recorded as one `ApplyParameterBatch` event. The parameter
+//! journal still gets one entry per change, named after the single-setter
+//! event the change is equivalent to, all pointing at the batch event.
+
+use crate::event::Event;
+use crate::numeric::Ratio;
+use crate::state::State;
+use crate::validate_f64_inclusive;
+use candid::{CandidType, Deserialize, Principal};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Most changes one batch may carry.
+pub const MAX_PARAMETER_BATCH: usize = 32;
+
+/// One change in an `admin_apply_parameter_batch` call. Values use the units
+/// and bounds of the matching single setter.
+#[derive(CandidType, Clone, Debug, PartialEq, Deserialize)]
+pub enum ParameterUpdate {
+    /// `set_collateral_liquidation_ratio`.
+    CollateralLiquidationRatio {
+        collateral_type: Principal,
+        value: f64,
+    },
+    /// `set_collateral_borrow_threshold`.
+    CollateralBorrowThreshold {
+        collateral_type: Principal,
+        value: f64,
+    },
+    /// `set_collateral_liquidation_bonus`.
+    CollateralLiquidationBonus {
+        collateral_type: Principal,
+        value: f64,
+    },
+    /// `set_collateral_borrowing_fee`.
+    CollateralBorrowingFee {
+        collateral_type: Principal,
+        value: f64,
+    },
+    /// `set_healthy_cr`; `None` clears it.
+    HealthyCr {
+        collateral_type: Principal,
+        value: Option<f64>,
+    },
+    /// `set_redemption_fee_floor`.
+    RedemptionFeeFloor { value: f64 },
+    /// `set_redemption_fee_ceiling`.
+    RedemptionFeeCeiling { value: f64 },
+}
+
+/// A validated `ParameterUpdate` as the event log stores it, with values as
+/// decimal strings like the single-setter events.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchedParameter {
+    CollateralLiquidationRatio {
+        collateral_type: Principal,
+        value: String,
+    },
+    CollateralBorrowThreshold {
+        collateral_type: Principal,
+        value: String,
+    },
+    CollateralLiquidationBonus {
+        collateral_type: Principal,
+        value: String,
+    },
+    CollateralBorrowingFee {
+        collateral_type: Principal,
+        value: String,
+    },
+    HealthyCr {
+        collateral_type: Principal,
+        value: Option<String>,
+    },
+    RedemptionFeeFloor {
+        value: String,
+    },
+    RedemptionFeeCeiling {
+        value: String,
+    },
+}
+
+impl BatchedParameter {
+    /// Collateral the change applies to; `None` for protocol-wide parameters.
+    pub fn collateral_type(&self) -> Option<Principal> {
+        match self {
+            BatchedParameter::CollateralLiquidationRatio {
+                collateral_type, ..
+            }
+            | BatchedParameter::CollateralBorrowThreshold {
+                collateral_type, ..
+            }
+            | BatchedParameter::CollateralLiquidationBonus {
+                collateral_type, ..
+            }
+            | BatchedParameter::CollateralBorrowingFee {
+                collateral_type, ..
+            }
+            | BatchedParameter::HealthyCr {
+                collateral_type, ..
+            } => Some(*collateral_type),
+            BatchedParameter::RedemptionFeeFloor { .. }
+            | BatchedParameter::RedemptionFeeCeiling { .. } => None,
+        }
+    }
+
+    /// The single-setter event this change is equivalent to.
+    pub fn setter_event(&self) -> Event {
+        match self.clone() {
+            BatchedParameter::CollateralLiquidationRatio {
+                collateral_type,
+                value,
+            } => Event::SetCollateralLiquidationRatio {
+                collateral_type,
+                liquidation_ratio: value,
+            },
+            BatchedParameter::CollateralBorrowThreshold {
+                collateral_type,
+                value,
+            } => Event::SetCollateralBorrowThreshold {
+                collateral_type,
+                borrow_threshold_ratio: value,
+            },
+            BatchedParameter::CollateralLiquidationBonus {
+                collateral_type,
+                value,
+            } => Event::SetCollateralLiquidationBonus {
+                collateral_type,
+                liquidation_bonus: value,
+            },
+            BatchedParameter::CollateralBorrowingFee {
+                collateral_type,
+                value,
+            } => Event::SetCollateralBorrowingFee {
+                collateral_type,
+                borrowing_fee: Some(value),
+                rate: None,
+                fee: None,
+            },
+            BatchedParameter::HealthyCr {
+                collateral_type,
+                value,
+            } => Event::SetHealthyCr {
+                collateral_type: collateral_type.to_text(),
+                healthy_cr: value,
+            },
+            BatchedParameter::RedemptionFeeFloor { value } => {
+                Event::SetRedemptionFeeFloor { rate: value }
+            }
+            BatchedParameter::RedemptionFeeCeiling { value } => {
+                Event::SetRedemptionFeeCeiling { rate: value }
+            }
+        }
+    }
+}
+
+fn ratio(value: &str) -> Option<Ratio> {
+    value.parse::<Decimal>().ok().map(Ratio::from)
+}
+
+fn decimal(name: &str, value: f64) -> Result<String, String> {
+    Decimal::try_from(value)
+        .map(|d| d.to_string())
+        .map_err(|_| format!("Invalid {} value", name))
+}
+
+fn known_collateral(state: &State, collateral_type: &Principal) -> Result<(), String> {
+    if state.collateral_configs.contains_key(collateral_type) {
+        Ok(())
+    } else {
+        Err(format!("Unknown collateral type {}", collateral_type))
+    }
+}
+
+fn validate_cr(name: &str, value: f64) -> Result<(), String> {
+    if !value.is_finite() || value <= 1.0 || value > 5.0 {
+        return Err(format!(
+            "{} ({}) must be a finite number > 1.0 and ≤ 5.0",
+            name, value
+        ));
+    }
+    Ok(())
+}
+
+/// Check one change against its single setter's bounds.
+fn validate_update(state: &State, update: &ParameterUpdate) -> Result<BatchedParameter, String> {
+    Ok(match *update {
+        ParameterUpdate::CollateralLiquidationRatio {
+            collateral_type,
+            value,
+        } => {
+            known_collateral(state, &collateral_type)?;
+            validate_cr("liquidation_ratio", value)?;
+            BatchedParameter::CollateralLiquidationRatio {
+                collateral_type,
+                value: decimal("liquidation_ratio", value)?,
+            }
+        }
+        ParameterUpdate::CollateralBorrowThreshold {
+            collateral_type,
+            value,
+        } => {
+            known_collateral(state, &collateral_type)?;
+            validate_cr("borrow_threshold_ratio", value)?;
+            BatchedParameter::CollateralBorrowThreshold {
+                collateral_type,
+                value: decimal("borrow_threshold_ratio", value)?,
+            }
+        }
+        ParameterUpdate::CollateralLiquidationBonus {
+            collateral_type,
+            value,
+        } => {
+            known_collateral(state, &collateral_type)?;
+            validate_f64_inclusive("liquidation_bonus", value, 1.0, 1.5)?;
+            BatchedParameter::CollateralLiquidationBonus {
+                collateral_type,
+                value: decimal("liquidation_bonus", value)?,
+            }
+        }
+        ParameterUpdate::CollateralBorrowingFee {
+            collateral_type,
+            value,
+        } => {
+            known_collateral(state, &collateral_type)?;
+            validate_f64_inclusive("borrowing_fee", value, 0.0, 0.10)?;
+            BatchedParameter::CollateralBorrowingFee {
+                collateral_type,
+                value: decimal("borrowing_fee", value)?,
+            }
+        }
+        ParameterUpdate::HealthyCr {
+            collateral_type,
+            value,
+        } => {
+            known_collateral(state, &collateral_type)?;
+            if let Some(cr) = value {
+                if !cr.is_finite() {
+                    return Err(format!("healthy_cr ({}) must be a finite number", cr));
+                }
+            }
+            BatchedParameter::HealthyCr {
+                collateral_type,
+                value: value.map(|cr| decimal("healthy_cr", cr)).transpose()?,
+            }
+        }
+        ParameterUpdate::RedemptionFeeFloor { value } => {
+            validate_f64_inclusive("redemption_fee_floor", value, 0.0, 0.10)?;
+            BatchedParameter::RedemptionFeeFloor {
+                value: decimal("redemption_fee_floor", value)?,
+            }
+        }
+        ParameterUpdate::RedemptionFeeCeiling { value } => {
+            validate_f64_inclusive("redemption_fee_ceiling", value, 0.0, 0.50)?;
+            BatchedParameter::RedemptionFeeCeiling {
+                value: decimal("redemption_fee_ceiling", value)?,
+            }
+        }
+    })
+}
+
+/// (liquidation_ratio, borrow_threshold_ratio, healthy_cr) of a collateral
+/// as a batch leaves them, seeded from its current config.
+type ProjectedRatios = (f64, f64, Option<f64>);
+
+fn projected_ratios<'a>(
+    state: &State,
+    ratios: &'a mut BTreeMap<Principal, ProjectedRatios>,
+    collateral_type: Principal,
+) -> &'a mut ProjectedRatios {
+    ratios.entry(collateral_type).or_insert_with(|| {
+        let config = &state.collateral_configs[&collateral_type];
+        (
+            config.liquidation_ratio.to_f64(),
+            config.borrow_threshold_ratio.to_f64(),
+            config.healthy_cr.map(|r| r.to_f64()),
+        )
+    })
+}
+
+/// Check the values `updates` would leave behind against the constraints
+/// between parameters. Only collaterals and parameters the batch touches are
+/// checked, so an unrelated batch is not blocked by existing state.
+fn check_constraints(state: &State, updates: &[ParameterUpdate]) -> Result<(), String> {
+    let mut ratios = BTreeMap::new();
+    let mut floor = None;
+    let mut ceiling = None;
+    for update in updates {
+        match *update {
+            ParameterUpdate::CollateralLiquidationRatio {
+                collateral_type,
+                value,
+            } => projected_ratios(state, &mut ratios, collateral_type).0 = value,
+            ParameterUpdate::CollateralBorrowThreshold {
+                collateral_type,
+                value,
+            } => projected_ratios(state, &mut ratios, collateral_type).1 = value,
+            ParameterUpdate::HealthyCr {
+                collateral_type,
+                value,
+            } => projected_ratios(state, &mut ratios, collateral_type).2 = value,
+            ParameterUpdate::RedemptionFeeFloor { value } => floor = Some(value),
+            ParameterUpdate::RedemptionFeeCeiling { value } => ceiling = Some(value),
+            ParameterUpdate::CollateralLiquidationBonus { .. }
+            | ParameterUpdate::CollateralBorrowingFee { .. } => {}
+        }
+    }
+
+    for (collateral_type, (liquidation_ratio, borrow_threshold, healthy_cr)) in &ratios {
+        if liquidation_ratio >= borrow_threshold {
+            return Err(format!(
+                "collateral {}: liquidation_ratio ({}) must be strictly less than borrow_threshold_ratio ({})",
+                collateral_type, liquidation_ratio, borrow_threshold
+            ));
+        }
+        if let Some(healthy_cr) = healthy_cr {
+            if borrow_threshold >= healthy_cr {
+                return Err(format!(
+                    "collateral {}: borrow_threshold_ratio ({}) must be strictly less than healthy_cr ({})",
+                    collateral_type, borrow_threshold, healthy_cr
+                ));
+            }
+        }
+    }
+
+    if floor.is_some() || ceiling.is_some() {
+        let floor = floor.unwrap_or_else(|| state.redemption_fee_floor.to_f64());
+        let ceiling = ceiling.unwrap_or_else(|| state.redemption_fee_ceiling.to_f64());
+        if floor > ceiling {
+            return Err(format!(
+                "redemption_fee_floor ({}) must not exceed redemption_fee_ceiling ({})",
+                floor, ceiling
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Validate a whole batch. Returns the changes to record, in the order
+/// given, or the first reason the batch is rejected; nothing is applied
+/// either way.
+pub fn validate(
+    state: &State,
+    updates: &[ParameterUpdate],
+) -> Result<Vec<BatchedParameter>, String> {
+    if updates.is_empty() {
+        return Err("Parameter batch is empty".to_string());
+    }
+    if updates.len() > MAX_PARAMETER_BATCH {
+        return Err(format!(
+            "Parameter batch has {} changes, at most {} are allowed",
+            updates.len(),
+            MAX_PARAMETER_BATCH
+        ));
+    }
+    let mut batch: Vec<BatchedParameter> = Vec::with_capacity(updates.len());
+    for update in updates {
+        let change = validate_update(state, update)?;
+        let duplicate = batch.iter().any(|c| {
+            std::mem::discriminant(c) == std::mem::discriminant(&change)
+                && c.collateral_type() == change.collateral_type()
+        });
+        if duplicate {
+            return Err(format!("Parameter batch sets {:?} more than once", update));
+        }
+        batch.push(change);
+    }
+    check_constraints(state, updates)?;
+    Ok(batch)
+}
+
+/// Write a batch `validate` accepted to state, in order. A change whose
+/// collateral is gone is skipped rather than trapping.
+pub fn apply(state: &mut State, batch: &[BatchedParameter]) {
+    for change in batch {
+        match change {
+            BatchedParameter::CollateralLiquidationRatio {
+                collateral_type,
+                value,
+            } => {
+                if let (Some(config), Some(value)) = (
+                    state.collateral_configs.get_mut(collateral_type),
+                    ratio(value),
+                ) {
+                    config.liquidation_ratio = value;
+                }
+            }
+            BatchedParameter::CollateralBorrowThreshold {
+                collateral_type,
+                value,
+            } => {
+                let multiplier = state.recovery_cr_multiplier;
+                if let (Some(config), Some(value)) = (
+                    state.collateral_configs.get_mut(collateral_type),
+                    ratio(value),
+                ) {
+                    config.borrow_threshold_ratio = value;
+                    config.recovery_target_cr = value * multiplier;
+                }
+            }
+            BatchedParameter::CollateralLiquidationBonus {
+                collateral_type,
+                value,
+            } => {
+                if let (Some(config), Some(value)) = (
+                    state.collateral_configs.get_mut(collateral_type),
+                    ratio(value),
+                ) {
+                    config.liquidation_bonus = value;
+                }
+            }
+            BatchedParameter::CollateralBorrowingFee {
+                collateral_type,
+                value,
+            } => {
+                if let (Some(config), Some(value)) = (
+                    state.collateral_configs.get_mut(collateral_type),
+                    ratio(value),
+                ) {
+                    config.borrowing_fee = value;
+                }
+            }
+            BatchedParameter::HealthyCr {
+                collateral_type,
+                value,
+            } => {
+                if let Some(config) = state.collateral_configs.get_mut(collateral_type) {
+                    config.healthy_cr = value.as_deref().and_then(ratio);
+                }
+            }
+            BatchedParameter::RedemptionFeeFloor { value } => {
+                if let Some(value) = ratio(value) {
+                    state.redemption_fee_floor = value;
+                    state.sync_icp_collateral_config();
+                }
+            }
+            BatchedParameter::RedemptionFeeCeiling { value } => {
+                if let Some(value) = ratio(value) {
+                    state.redemption_fee_ceiling = value;
+                    state.sync_icp_collateral_config();
+                }
+            }
+        }
+    }
+}
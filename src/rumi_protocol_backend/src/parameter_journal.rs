@@ -0,0 +1,543 @@
+//! Parameter-change journal: a before/after view of admin setter events.
+//!
+//! Every `Set*` event already lands in the event log, but reading a diff out
+//! of it means decoding the whole log and remembering the previous value of
+//! each parameter by hand. The journal keeps one `ParameterChange` per setter
+//! event with the value it replaced, who made the change, when, and the
+//! event's index in the log so the raw event can still be fetched.
+//!
+//! Parameters are named after the event tag without its `set_` prefix
+//! (`SetCollateralLiquidationRatio` -> `collateral_liquidation_ratio`).
+//! Per-collateral, per-token and per-operation setters carry that key in
+//! `scope`, and old values are tracked per (parameter, scope). Values are
+//! the event payload rendered as text, so they read exactly as the event log
+//! stores them.
+//!
+//! Old values are read from state before the setter applies, by rendering
+//! the setter event as it would read for the current value, so the first
+//! change of a parameter set only by `InitArg` has one too. The setters whose
+//! payload state does not keep as sent (rate-curve markers, reserve stables,
+//! operation pauses) fall back to the previous journal entry.
+//!
+//! The journal is rebuilt by event replay, but replayed entries have no
+//! actor (events do not record the caller) and only the timestamp the event
+//! itself carries. It keeps the last `MAX_PARAMETER_JOURNAL` entries; ids
+//! keep counting, so a cursor stays valid after older entries are dropped.
+//!
+//! An `ApplyParameterBatch` event is journaled as one entry per change, each
+//! named after the single-setter event it stands in for and carrying the
+//! batch event's index.
+
+use crate::event::Event;
+use crate::state::State;
+use crate::StableTokenType;
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Largest page `get_parameter_history` returns.
+pub const MAX_PARAMETER_HISTORY_PAGE: usize = 100;
+
+/// Entries the journal keeps; the oldest are dropped first.
+pub const MAX_PARAMETER_JOURNAL: usize = 10_000;
+
+/// Payload fields that locate a setter rather than describe its value.
+const SCOPE_FIELDS: [&str; 4] = ["collateral_type", "token_type", "endpoint", "operation"];
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParameterChange {
+    pub id: u64,
+    pub parameter: String,
+    /// Collateral (principal text), stable token, endpoint or operation the
+    /// change applies to; `None` for protocol-wide parameters.
+    pub scope: Option<String>,
+    pub old_value: Option<String>,
+    pub new_value: String,
+    /// Caller of the setter; `None` for entries rebuilt by replay.
+    pub actor: Option<Principal>,
+    /// 0 when rebuilt from an event that carries no timestamp.
+    pub timestamp: u64,
+    pub event_index: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct ParameterHistoryPage {
+    pub entries: Vec<ParameterChange>,
+    /// Pass back as `cursor` to read the next page; `None` at the end.
+    pub next_cursor: Option<u64>,
+}
+
+/// Split a setter event into (parameter, scope, new value). `None` for any
+/// event that is not an admin `Set*` event.
+pub fn parameter_change(event: &Event) -> Option<(String, Option<String>, String)> {
+    if !event.admin_label()?.starts_with("Set") {
+        return None;
+    }
+    let Value::Object(tagged) = serde_json::to_value(event).ok()? else {
+        return None;
+    };
+    let (tag, payload) = tagged.into_iter().next()?;
+    let parameter = tag.strip_prefix("set_").unwrap_or(&tag).to_string();
+    let Value::Object(mut fields) = payload else {
+        return Some((parameter, None, render(&payload)));
+    };
+
+    let scope = SCOPE_FIELDS
+        .iter()
+        .find_map(|key| fields.remove(*key))
+        .filter(|v| !v.is_null())
+        .map(|v| render(&v));
+    fields.remove("timestamp");
+    // Legacy events carry superseded optional fields next to the live one.
+    fields.retain(|_, v| !v.is_null());
+
+    let new_value = match fields.len() {
+        0 => "null".to_string(),
+        1 => render(fields.values().next().unwrap()),
+        _ => Value::Object(fields).to_string(),
+    };
+    Some((parameter, scope, new_value))
+}
+
+fn render(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Append a journal entry for `event` if it is a setter event, or one per
+/// change of a parameter batch. `state` must not have applied the change
+/// yet: the old value is read from it.
+pub fn journal_event(
+    state: &mut State,
+    event: &Event,
+    event_index: u64,
+    actor: Option<Principal>,
+    timestamp: u64,
+) {
+    if let Event::ApplyParameterBatch { changes, .. } = event {
+        for change in changes {
+            journal_event(state, &change.setter_event(), event_index, actor, timestamp);
+        }
+        return;
+    }
+    let Some((parameter, scope, new_value)) = parameter_change(event) else {
+        return;
+    };
+    let old_value = current_setting(state, event)
+        .and_then(|current| parameter_change(&current))
+        .map(|(_, _, value)| value)
+        .or_else(|| {
+            state
+                .parameter_journal
+                .iter()
+                .rev()
+                .find(|c| c.parameter == parameter && c.scope == scope)
+                .map(|c| c.new_value.clone())
+        });
+    let id = state.parameter_journal.back().map_or(0, |c| c.id + 1);
+    if state.parameter_journal.len() >= MAX_PARAMETER_JOURNAL {
+        state.parameter_journal.pop_front();
+    }
+    state.parameter_journal.push_back(ParameterChange {
+        id,
+        parameter,
+        scope,
+        old_value,
+        new_value,
+        actor,
+        timestamp,
+        event_index,
+    });
+}
+
+/// `event` as it would read for the value `state` holds now, or `None` when
+/// the parameter is unset or state does not keep it as the event carries it.
+fn current_setting(state: &State, event: &Event) -> Option<Event> {
+    let ratio = |r: crate::numeric::Ratio| r.0.to_string();
+    let config = |collateral_type: &Principal| state.collateral_configs.get(collateral_type);
+    let current = match event {
+        Event::SetCkstableRepayFee { .. } => Event::SetCkstableRepayFee {
+            rate: ratio(state.ckstable_repay_fee),
+        },
+        Event::SetMinIcusdAmount { .. } => Event::SetMinIcusdAmount {
+            amount: state.min_icusd_amount.to_u64().to_string(),
+        },
+        Event::SetGlobalIcusdMintCap { .. } => Event::SetGlobalIcusdMintCap {
+            amount: Some(state.global_icusd_mint_cap.to_string()),
+            cap: None,
+        },
+        Event::SetStableTokenEnabled { token_type, .. } => Event::SetStableTokenEnabled {
+            token_type: token_type.clone(),
+            enabled: match token_type {
+                StableTokenType::CKUSDT => state.ckusdt_enabled,
+                StableTokenType::CKUSDC => state.ckusdc_enabled,
+            },
+        },
+        Event::SetStableLedgerPrincipal { token_type, .. } => Event::SetStableLedgerPrincipal {
+            token_type: token_type.clone(),
+            principal: match token_type {
+                StableTokenType::CKUSDT => state.ckusdt_ledger_principal?,
+                StableTokenType::CKUSDC => state.ckusdc_ledger_principal?,
+            },
+        },
+        Event::SetTreasuryPrincipal { .. } => Event::SetTreasuryPrincipal {
+            principal: state.treasury_principal?,
+        },
+        Event::SetStabilityPoolPrincipal { .. } => Event::SetStabilityPoolPrincipal {
+            principal: state.stability_pool_canister?,
+        },
+        Event::SetLiquidationBotPrincipal { .. } => Event::SetLiquidationBotPrincipal {
+            principal: state.liquidation_bot_principal?,
+        },
+        Event::SetBotBudget { .. } => Event::SetBotBudget {
+            total_e8s: state.bot_budget_total_e8s,
+            start_timestamp: state.bot_budget_start_timestamp,
+        },
+        Event::SetBotAllowedCollateralTypes { .. } => Event::SetBotAllowedCollateralTypes {
+            collateral_types: state.bot_allowed_collateral_types.iter().copied().collect(),
+        },
+        Event::SetBotCrToleranceBps { .. } => Event::SetBotCrToleranceBps {
+            bps: state.bot_cr_tolerance_bps,
+        },
+        Event::SetCollateralMinXrcSources {
+            collateral_type, ..
+        } => Event::SetCollateralMinXrcSources {
+            collateral_type: *collateral_type,
+            min_xrc_sources: config(collateral_type)?.min_xrc_sources,
+        },
+        Event::SetLiquidationBonus { .. } => Event::SetLiquidationBonus {
+            rate: ratio(state.liquidation_bonus),
+        },
+        Event::SetBorrowingFee { .. } => Event::SetBorrowingFee {
+            rate: ratio(state.fee),
+        },
+        Event::SetRedemptionFeeFloor { .. } => Event::SetRedemptionFeeFloor {
+            rate: ratio(state.redemption_fee_floor),
+        },
+        Event::SetRedemptionFeeCeiling { .. } => Event::SetRedemptionFeeCeiling {
+            rate: ratio(state.redemption_fee_ceiling),
+        },
+        Event::SetMaxPartialLiquidationRatio { .. } => Event::SetMaxPartialLiquidationRatio {
+            rate: ratio(state.max_partial_liquidation_ratio),
+        },
+        Event::SetRecoveryTargetCr { .. } => Event::SetRecoveryTargetCr {
+            rate: ratio(state.recovery_target_cr),
+        },
+        Event::SetRecoveryCrMultiplier { .. } => Event::SetRecoveryCrMultiplier {
+            multiplier: ratio(state.recovery_cr_multiplier),
+        },
+        Event::SetLiquidationProtocolShare { .. } => Event::SetLiquidationProtocolShare {
+            share: ratio(state.liquidation_protocol_share),
+        },
+        Event::SetReserveRedemptionsEnabled { .. } => Event::SetReserveRedemptionsEnabled {
+            enabled: state.reserve_redemptions_enabled,
+        },
+        Event::SetIcpswapRoutingEnabled { .. } => Event::SetIcpswapRoutingEnabled {
+            enabled: state.icpswap_routing_enabled,
+        },
+        Event::SetReserveRedemptionFee { .. } => Event::SetReserveRedemptionFee {
+            fee: ratio(state.reserve_redemption_fee),
+        },
+        Event::SetRecoveryParameters {
+            collateral_type, ..
+        } => {
+            let config = config(collateral_type)?;
+            Event::SetRecoveryParameters {
+                collateral_type: *collateral_type,
+                recovery_borrowing_fee: config.recovery_borrowing_fee.map(ratio),
+                recovery_interest_rate_apr: config.recovery_interest_rate_apr.map(ratio),
+            }
+        }
+        Event::SetHealthyCr {
+            collateral_type, ..
+        } => Event::SetHealthyCr {
+            collateral_type: collateral_type.clone(),
+            healthy_cr: config(&Principal::from_text(collateral_type).ok()?)?
+                .healthy_cr
+                .map(ratio),
+        },
+        Event::SetCollateralBorrowingFee {
+            collateral_type, ..
+        } => Event::SetCollateralBorrowingFee {
+            collateral_type: *collateral_type,
+            borrowing_fee: Some(ratio(config(collateral_type)?.borrowing_fee)),
+            rate: None,
+            fee: None,
+        },
+        Event::SetInterestRate {
+            collateral_type, ..
+        } => Event::SetInterestRate {
+            collateral_type: *collateral_type,
+            interest_rate_apr: ratio(config(collateral_type)?.interest_rate_apr),
+        },
+        Event::SetInterestPoolShare { .. } => Event::SetInterestPoolShare {
+            share: ratio(state.interest_pool_share),
+        },
+        Event::SetRmrFloor { .. } => Event::SetRmrFloor {
+            value: ratio(state.rmr_floor),
+        },
+        Event::SetRmrCeiling { .. } => Event::SetRmrCeiling {
+            value: ratio(state.rmr_ceiling),
+        },
+        Event::SetRmrFloorCr { .. } => Event::SetRmrFloorCr {
+            value: ratio(state.rmr_floor_cr),
+        },
+        Event::SetRmrCeilingCr { .. } => Event::SetRmrCeilingCr {
+            value: ratio(state.rmr_ceiling_cr),
+        },
+        Event::SetBorrowingFeeCurve { .. } => Event::SetBorrowingFeeCurve {
+            markers: match &state.borrowing_fee_curve {
+                Some(curve) => serde_json::to_string(curve).ok()?,
+                None => "null".to_string(),
+            },
+        },
+        Event::SetInterestSplit { .. } => Event::SetInterestSplit {
+            split: serde_json::to_string(&state.interest_split).ok()?,
+        },
+        Event::SetThreePoolCanister { .. } => Event::SetThreePoolCanister {
+            canister: state.three_pool_canister?,
+        },
+        Event::SetAmm1Canister { .. } => Event::SetAmm1Canister {
+            canister: state.amm1_canister?,
+        },
+        Event::SetAmm1PoolId { .. } => Event::SetAmm1PoolId {
+            pool_id: state.amm1_pool_id.clone()?,
+        },
+        Event::SetCollateralLiquidationRatio {
+            collateral_type, ..
+        } => Event::SetCollateralLiquidationRatio {
+            collateral_type: *collateral_type,
+            liquidation_ratio: ratio(config(collateral_type)?.liquidation_ratio),
+        },
+        Event::SetCollateralBorrowThreshold {
+            collateral_type, ..
+        } => Event::SetCollateralBorrowThreshold {
+            collateral_type: *collateral_type,
+            borrow_threshold_ratio: ratio(config(collateral_type)?.borrow_threshold_ratio),
+        },
+        Event::SetCollateralLiquidationBonus {
+            collateral_type, ..
+        } => Event::SetCollateralLiquidationBonus {
+            collateral_type: *collateral_type,
+            liquidation_bonus: ratio(config(collateral_type)?.liquidation_bonus),
+        },
+        Event::SetCollateralMinVaultDebt {
+            collateral_type, ..
+        } => Event::SetCollateralMinVaultDebt {
+            collateral_type: *collateral_type,
+            min_vault_debt: config(collateral_type)?.min_vault_debt.to_u64(),
+        },
+        Event::SetCollateralLedgerFee {
+            collateral_type, ..
+        } => Event::SetCollateralLedgerFee {
+            collateral_type: *collateral_type,
+            ledger_fee: config(collateral_type)?.ledger_fee,
+        },
+        Event::SetCollateralRedemptionFeeFloor {
+            collateral_type, ..
+        } => Event::SetCollateralRedemptionFeeFloor {
+            collateral_type: *collateral_type,
+            redemption_fee_floor: ratio(config(collateral_type)?.redemption_fee_floor),
+        },
+        Event::SetCollateralRedemptionFeeCeiling {
+            collateral_type, ..
+        } => Event::SetCollateralRedemptionFeeCeiling {
+            collateral_type: *collateral_type,
+            redemption_fee_ceiling: ratio(config(collateral_type)?.redemption_fee_ceiling),
+        },
+        Event::SetCollateralMinDeposit {
+            collateral_type, ..
+        } => Event::SetCollateralMinDeposit {
+            collateral_type: *collateral_type,
+            min_collateral_deposit: config(collateral_type)?.min_collateral_deposit,
+        },
+        Event::SetCollateralDisplayColor {
+            collateral_type, ..
+        } => Event::SetCollateralDisplayColor {
+            collateral_type: *collateral_type,
+            display_color: config(collateral_type)?.display_color.clone(),
+        },
+        Event::SetDeficitRepaymentFraction { .. } => Event::SetDeficitRepaymentFraction {
+            fraction: state.deficit_repayment_fraction,
+            timestamp: 0,
+        },
+        Event::SetDeficitReadonlyThresholdE8s { .. } => Event::SetDeficitReadonlyThresholdE8s {
+            threshold_e8s: state.deficit_readonly_threshold_e8s,
+            timestamp: 0,
+        },
+        Event::SetBreakerWindowNs { .. } => Event::SetBreakerWindowNs {
+            window_ns: state.breaker_window_ns,
+            timestamp: 0,
+        },
+        Event::SetBreakerWindowDebtCeilingE8s { .. } => Event::SetBreakerWindowDebtCeilingE8s {
+            ceiling_e8s: state.breaker_window_debt_ceiling_e8s,
+            timestamp: 0,
+        },
+        Event::SetCollateralLiquidationRebateMode {
+            collateral_type, ..
+        } => Event::SetCollateralLiquidationRebateMode {
+            collateral_type: *collateral_type,
+            mode: config(collateral_type)?.liquidation_rebate_mode,
+        },
+        Event::SetCollateralSecondaryPriceSource {
+            collateral_type, ..
+        } => Event::SetCollateralSecondaryPriceSource {
+            collateral_type: *collateral_type,
+            source: config(collateral_type)?.secondary_price_source.clone(),
+        },
+        Event::SetGuardianPrincipals { .. } => Event::SetGuardianPrincipals {
+            principals: state.guardian_principals.iter().copied().collect(),
+        },
+        Event::SetModeCompanionCanisters { .. } => Event::SetModeCompanionCanisters {
+            canisters: state.mode_companion_canisters.iter().copied().collect(),
+        },
+        Event::SetCollateralSwapRoute {
+            from_collateral_type,
+            to_collateral_type,
+            ..
+        } => Event::SetCollateralSwapRoute {
+            from_collateral_type: *from_collateral_type,
+            to_collateral_type: *to_collateral_type,
+            route: state
+                .collateral_swap_routes
+                .iter()
+                .find(|r| r.from == *from_collateral_type && r.to == *to_collateral_type)
+                .cloned(),
+        },
+        Event::SetRecoveryPoolPriority { .. } => Event::SetRecoveryPoolPriority {
+            enabled: state.pool_priority.enabled,
+            window_ns: state.pool_priority.window_ns,
+        },
+        Event::SetAutoDeleverageRoute {
+            collateral_type, ..
+        } => Event::SetAutoDeleverageRoute {
+            collateral_type: *collateral_type,
+            route: state.auto_deleverage_routes.get(collateral_type).cloned(),
+        },
+        Event::SetPendingBackpressure { .. } => Event::SetPendingBackpressure {
+            max_pending_collateral_transfers: state
+                .pending_backpressure
+                .max_pending_collateral_transfers,
+            max_pending_redemption_transfers: state
+                .pending_backpressure
+                .max_pending_redemption_transfers,
+        },
+        Event::SetLogRetention { .. } => {
+            let retention = state.log_retention;
+            Event::SetLogRetention {
+                critical_capacity: retention.critical_capacity,
+                info_capacity: retention.info_capacity,
+                debug_capacity: retention.debug_capacity,
+                trace_xrc_capacity: retention.trace_xrc_capacity,
+                persisted_critical_capacity: retention.persisted_critical_capacity,
+            }
+        }
+        Event::SetCollateralPriceConfidence {
+            collateral_type, ..
+        } => Event::SetCollateralPriceConfidence {
+            collateral_type: *collateral_type,
+            k: config(collateral_type)?.price_confidence_k.map(ratio),
+        },
+        Event::SetCollateralMaintenanceFee {
+            collateral_type, ..
+        } => Event::SetCollateralMaintenanceFee {
+            collateral_type: *collateral_type,
+            maintenance_fee_apr: config(collateral_type)?.maintenance_fee_apr.map(ratio),
+            timestamp: 0,
+        },
+        Event::SetRecoveryExitBand { .. } => Event::SetRecoveryExitBand {
+            band: state.recovery_exit_band.map(ratio),
+        },
+        Event::SetCollateralRedemptionCap {
+            collateral_type, ..
+        } => Event::SetCollateralRedemptionCap {
+            collateral_type: *collateral_type,
+            config: state.redemption_caps.get(collateral_type).copied(),
+        },
+        Event::SetPriceDeviationBreaker { .. } => Event::SetPriceDeviationBreaker {
+            config: state.price_deviation_breaker,
+        },
+        Event::SetCollateralPriceBounds {
+            collateral_type, ..
+        } => Event::SetCollateralPriceBounds {
+            collateral_type: *collateral_type,
+            bounds: state.price_bounds.get(collateral_type).copied(),
+        },
+        Event::SetFlashMintConfig { .. } => Event::SetFlashMintConfig {
+            config: state.flash_mint.clone(),
+        },
+        Event::SetSloThresholds { endpoint, .. } => Event::SetSloThresholds {
+            endpoint: endpoint.clone(),
+            thresholds: match endpoint {
+                Some(endpoint) => state.slo_config.endpoints.get(endpoint).copied(),
+                None => state.slo_config.default,
+            },
+        },
+        Event::SetLiquidatorSelfRegistration { .. } => Event::SetLiquidatorSelfRegistration {
+            enabled: state.liquidator_self_registration,
+        },
+        Event::SetFeeSponsorshipConfig { .. } => Event::SetFeeSponsorshipConfig {
+            config: state.fee_sponsorship_config.clone()?,
+        },
+        Event::SetFeeSponsorshipVerified { user, .. } => Event::SetFeeSponsorshipVerified {
+            user: *user,
+            verified: state.fee_sponsorship_verified.contains(user),
+        },
+        Event::SetAuctionConfig { .. } => Event::SetAuctionConfig {
+            config: state.auction_config.clone()?,
+        },
+        Event::SetRedistributionEnabled { .. } => Event::SetRedistributionEnabled {
+            enabled: state.redistribution_enabled,
+        },
+        Event::SetShadowConfig { .. } => Event::SetShadowConfig {
+            config: state.shadow_config,
+        },
+        Event::SetLiquidationTip { .. } => Event::SetLiquidationTip {
+            tip_e8s: state.liquidation_tip_e8s,
+        },
+        Event::SetSurplusFeeShare { .. } => Event::SetSurplusFeeShare {
+            share: state.surplus_fee_share,
+        },
+        Event::SetCollateralLiquidationCap {
+            collateral_type, ..
+        } => Event::SetCollateralLiquidationCap {
+            collateral_type: *collateral_type,
+            config: state.liquidation_caps.get(collateral_type).copied(),
+        },
+        Event::SetParameterTimelock { .. } => Event::SetParameterTimelock {
+            delay_ns: state.parameter_timelock_ns,
+        },
+        Event::SetEmergencyPausers { .. } => Event::SetEmergencyPausers {
+            principals: state.emergency_pausers.iter().copied().collect(),
+        },
+        _ => return None,
+    };
+    Some(current)
+}
+
+/// Changes to `parameter`, oldest first, starting at journal id `cursor`.
+pub fn parameter_history(
+    state: &State,
+    parameter: &str,
+    cursor: Option<u64>,
+    limit: usize,
+) -> ParameterHistoryPage {
+    let start = cursor.unwrap_or(0);
+    let limit = limit.min(MAX_PARAMETER_HISTORY_PAGE);
+    let mut matching = state
+        .parameter_journal
+        .iter()
+        .skip_while(|c| c.id < start)
+        .filter(|c| c.parameter == parameter);
+    let entries: Vec<ParameterChange> = matching.by_ref().take(limit).cloned().collect();
+    let next_cursor = match (entries.last(), matching.next()) {
+        (Some(last), Some(_)) => Some(last.id + 1),
+        _ => None,
+    };
+    ParameterHistoryPage {
+        entries,
+        next_cursor,
+    }
+}
@@ -0,0 +1,148 @@
+//! Backpressure on the pending outbound-transfer queues.
+//!
+//! Closes, collateral withdrawals and redemptions pay out through queues
+//! (`pending_margin_transfers`, `pending_excess_transfers`,
+//! `pending_redemption_transfer`) that the timer drains. During a ledger
+//! outage those queues only grow, and every new entry is another transfer
+//! to retry once the ledger is back. When a queue is at its threshold, new
+//! operations that would add to it are rejected with `TemporarilyUnavailable`
+//! until it drains. Repayments and margin additions do not queue payouts and
+//! are never held back.
+//!
+//! A threshold of 0 turns the check off for that queue. Thresholds are
+//! replayed from `SetPendingBackpressure`; the rejection counters are
+//! metrics only.
+
+use crate::state::State;
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+/// Collateral payouts (margin + excess) queued before closes and withdrawals
+/// are held back.
+pub const DEFAULT_MAX_PENDING_COLLATERAL_TRANSFERS: u64 = 500;
+
+/// Redemption payouts queued before redemptions are held back.
+pub const DEFAULT_MAX_PENDING_REDEMPTION_TRANSFERS: u64 = 200;
+
+/// Highest threshold the developer may set for either queue.
+pub const MAX_PENDING_TRANSFER_THRESHOLD: u64 = 100_000;
+
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingBackpressureConfig {
+    /// Pending margin + excess transfers at which closes and withdrawals are
+    /// rejected. 0 disables the check.
+    pub max_pending_collateral_transfers: u64,
+    /// Pending redemption transfers at which redemptions are rejected.
+    /// 0 disables the check.
+    pub max_pending_redemption_transfers: u64,
+}
+
+impl Default for PendingBackpressureConfig {
+    fn default() -> Self {
+        Self {
+            max_pending_collateral_transfers: DEFAULT_MAX_PENDING_COLLATERAL_TRANSFERS,
+            max_pending_redemption_transfers: DEFAULT_MAX_PENDING_REDEMPTION_TRANSFERS,
+        }
+    }
+}
+
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingBackpressureStats {
+    pub collateral_rejections: u64,
+    pub redemption_rejections: u64,
+}
+
+/// Which payout queue an operation adds to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayoutQueue {
+    /// Closes and collateral withdrawals.
+    Collateral,
+    Redemption,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct PendingBackpressureStatus {
+    pub config: PendingBackpressureConfig,
+    pub pending_collateral_transfers: u64,
+    pub pending_redemption_transfers: u64,
+    /// Closes and withdrawals are currently rejected.
+    pub collateral_blocked: bool,
+    /// Redemptions are currently rejected.
+    pub redemption_blocked: bool,
+    pub stats: PendingBackpressureStats,
+}
+
+pub fn validate_config(config: &PendingBackpressureConfig) -> Result<(), String> {
+    if config.max_pending_collateral_transfers > MAX_PENDING_TRANSFER_THRESHOLD
+        || config.max_pending_redemption_transfers > MAX_PENDING_TRANSFER_THRESHOLD
+    {
+        return Err(format!(
+            "Pending transfer thresholds must be at most {}",
+            MAX_PENDING_TRANSFER_THRESHOLD
+        ));
+    }
+    Ok(())
+}
+
+pub fn apply_set_config(state: &mut State, config: PendingBackpressureConfig) {
+    state.pending_backpressure = config;
+}
+
+pub fn queue_len(state: &State, queue: PayoutQueue) -> u64 {
+    match queue {
+        PayoutQueue::Collateral => {
+            (state.pending_margin_transfers.len() + state.pending_excess_transfers.len()) as u64
+        }
+        PayoutQueue::Redemption => state.pending_redemption_transfer.len() as u64,
+    }
+}
+
+fn threshold(state: &State, queue: PayoutQueue) -> u64 {
+    match queue {
+        PayoutQueue::Collateral => state.pending_backpressure.max_pending_collateral_transfers,
+        PayoutQueue::Redemption => state.pending_backpressure.max_pending_redemption_transfers,
+    }
+}
+
+pub fn is_blocked(state: &State, queue: PayoutQueue) -> bool {
+    let max = threshold(state, queue);
+    max > 0 && queue_len(state, queue) >= max
+}
+
+/// Reject an operation that would add to `queue` while the queue is at its
+/// threshold, counting the rejection.
+pub fn check_room(state: &mut State, queue: PayoutQueue) -> Result<(), ProtocolError> {
+    if !is_blocked(state, queue) {
+        return Ok(());
+    }
+    let (what, pending) = match queue {
+        PayoutQueue::Collateral => {
+            state.pending_backpressure_stats.collateral_rejections += 1;
+            ("Closes and collateral withdrawals", "collateral payouts")
+        }
+        PayoutQueue::Redemption => {
+            state.pending_backpressure_stats.redemption_rejections += 1;
+            ("Redemptions", "redemption payouts")
+        }
+    };
+    Err(ProtocolError::TemporarilyUnavailable(format!(
+        "{} are paused: {} {} are waiting on the ledger (limit {}). \
+         Repayments and margin additions are still accepted; please retry later.",
+        what,
+        queue_len(state, queue),
+        pending,
+        threshold(state, queue)
+    )))
+}
+
+pub fn status(state: &State) -> PendingBackpressureStatus {
+    PendingBackpressureStatus {
+        config: state.pending_backpressure,
+        pending_collateral_transfers: queue_len(state, PayoutQueue::Collateral),
+        pending_redemption_transfers: queue_len(state, PayoutQueue::Redemption),
+        collateral_blocked: is_blocked(state, PayoutQueue::Collateral),
+        redemption_blocked: is_blocked(state, PayoutQueue::Redemption),
+        stats: state.pending_backpressure_stats.clone(),
+    }
+}
@@ -0,0 +1,232 @@
+//! Stability-pool priority for Recovery-mode liquidations.
+//!
+//! In Recovery the stability pool and external liquidators race for the
+//! same vaults, and every vault an outside liquidator takes is a bonus the
+//! pool's depositors do not earn. With the rule enabled, `check_vaults`
+//! sends liquidatable vaults to the pool first, skipping the bot, for as far
+//! as the pool's reported icUSD depth for their collateral covers their
+//! recommended liquidation amount. Those vaults are reserved for the pool
+//! for `window_ns`: while a reservation is live and the pool has not yet
+//! had its attempt, manual liquidations and bot claims of the vault are
+//! rejected. Vaults beyond the depth go through the usual cascade and stay
+//! open to external liquidators.
+//!
+//! The depth comes from the pool's `get_pool_status`, refreshed at the end
+//! of each `check_vaults` tick while a pool is registered (the health score
+//! reads it too), so a tick routes on the previous tick's figure. Without a snapshot younger than
+//! `MAX_POOL_DEPTH_AGE_NS` nothing is reserved. Reservations only bind in
+//! Recovery.
+//!
+//! Routing decisions are logged as `RecoveryPoolRouting` and rebuilt by
+//! replay; the depth snapshot is cached pool data and is not.
+
+use crate::logs::INFO;
+use crate::state::{Mode, State};
+use crate::{mutate_state, LiquidatableVaultInfo, ProtocolError};
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Reservation window used until the developer sets one (5 minutes).
+pub const DEFAULT_POOL_PRIORITY_WINDOW_NS: u64 = 5 * 60 * 1_000_000_000;
+
+/// Longest reservation window the developer may set (1 hour).
+pub const MAX_POOL_PRIORITY_WINDOW_NS: u64 = 3600 * 1_000_000_000;
+
+/// Oldest depth snapshot routing will act on (two `check_vaults` ticks).
+pub const MAX_POOL_DEPTH_AGE_NS: u64 = 10 * 60 * 1_000_000_000;
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolPriorityConfig {
+    pub enabled: bool,
+    /// How long a routed vault stays reserved for the pool.
+    pub window_ns: u64,
+}
+
+impl Default for PoolPriorityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_ns: DEFAULT_POOL_PRIORITY_WINDOW_NS,
+        }
+    }
+}
+
+/// icUSD the pool reported it can spend per collateral type.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolDepthSnapshot {
+    pub eligible_icusd: Vec<(Principal, u64)>,
+    pub updated_at_ns: u64,
+}
+
+/// One routing decision of `check_vaults`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecoveryRouting {
+    /// Vaults the pool's depth covers, reserved until `reserved_until_ns`.
+    pub to_pool: Vec<u64>,
+    /// Vaults beyond the depth, left to the usual cascade.
+    pub overflow: Vec<u64>,
+    /// The depth the decision was made against.
+    pub pool_depth: Vec<(Principal, u64)>,
+    pub reserved_until_ns: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct RecoveryPoolPriorityStatus {
+    pub config: PoolPriorityConfig,
+    /// Enabled and the protocol is in Recovery.
+    pub active: bool,
+    pub pool_depth: Option<PoolDepthSnapshot>,
+    /// Live reservations as (vault id, reserved until).
+    pub reservations: Vec<(u64, u64)>,
+}
+
+/// The subset of the pool's `StabilityPoolStatus` read for the depth.
+#[derive(CandidType, Deserialize)]
+pub struct PoolStatusDepth {
+    eligible_icusd_per_collateral: Vec<(Principal, u64)>,
+}
+
+pub fn is_active(state: &State) -> bool {
+    state.pool_priority.enabled && state.mode == Mode::Recovery
+}
+
+pub fn validate_config(config: &PoolPriorityConfig) -> Result<(), String> {
+    if config.window_ns == 0 || config.window_ns > MAX_POOL_PRIORITY_WINDOW_NS {
+        return Err(format!(
+            "Reservation window must be non-zero and at most {} minutes",
+            MAX_POOL_PRIORITY_WINDOW_NS / (60 * 1_000_000_000)
+        ));
+    }
+    Ok(())
+}
+
+/// Expiry of the reservation on `vault_id`, while it is live at `now_ns`
+/// and the pool has not yet been notified of the vault.
+pub fn live_reservation(state: &State, vault_id: u64, now_ns: u64) -> Option<u64> {
+    state
+        .pool_reservations
+        .get(&vault_id)
+        .copied()
+        .filter(|until| *until > now_ns && !state.sp_attempted_vaults.contains(&vault_id))
+}
+
+/// Rejects a liquidation of a reserved vault by anyone but the pool.
+pub fn require_not_reserved(
+    state: &State,
+    vault_id: u64,
+    caller: Principal,
+    now_ns: u64,
+) -> Result<(), ProtocolError> {
+    if !is_active(state) || state.stability_pool_canister == Some(caller) {
+        return Ok(());
+    }
+    match live_reservation(state, vault_id, now_ns) {
+        Some(until) => Err(ProtocolError::GenericError(format!(
+            "Vault #{} is reserved for the stability pool until {} during Recovery mode",
+            vault_id, until
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Split `candidates` (worst CR first) between the pool and the usual
+/// cascade. `None` when the rule is inactive, no pool is registered, the
+/// depth snapshot is missing or stale, or there is nothing to route.
+pub fn plan_routing(
+    state: &State,
+    candidates: &[LiquidatableVaultInfo],
+    now_ns: u64,
+) -> Option<RecoveryRouting> {
+    if !is_active(state) || state.stability_pool_canister.is_none() {
+        return None;
+    }
+    let depth = state
+        .pool_depth
+        .as_ref()
+        .filter(|d| now_ns.saturating_sub(d.updated_at_ns) <= MAX_POOL_DEPTH_AGE_NS)?;
+    let mut remaining: BTreeMap<Principal, u64> = depth.eligible_icusd.iter().copied().collect();
+
+    let mut to_pool = Vec::new();
+    let mut overflow = Vec::new();
+    for vault in candidates {
+        if state.sp_attempted_vaults.contains(&vault.vault_id) {
+            continue;
+        }
+        let needed = if vault.recommended_liquidation_amount > 0 {
+            vault.recommended_liquidation_amount
+        } else {
+            vault.debt_amount
+        };
+        match remaining.get_mut(&vault.collateral_type) {
+            Some(left) if *left >= needed => {
+                *left -= needed;
+                to_pool.push(vault.vault_id);
+            }
+            _ => overflow.push(vault.vault_id),
+        }
+    }
+    if to_pool.is_empty() && overflow.is_empty() {
+        return None;
+    }
+    Some(RecoveryRouting {
+        to_pool,
+        overflow,
+        pool_depth: depth.eligible_icusd.clone(),
+        reserved_until_ns: now_ns.saturating_add(state.pool_priority.window_ns),
+    })
+}
+
+/// Reserve `to_pool` and drop lapsed reservations. A vault already reserved
+/// keeps its original expiry, so routing it again cannot extend the pool's
+/// hold on it.
+pub fn apply_routing(state: &mut State, to_pool: &[u64], reserved_until_ns: u64, now_ns: u64) {
+    state.pool_reservations.retain(|_, until| *until > now_ns);
+    for vault_id in to_pool {
+        state
+            .pool_reservations
+            .entry(*vault_id)
+            .or_insert(reserved_until_ns);
+    }
+}
+
+pub fn apply_set_config(state: &mut State, config: PoolPriorityConfig) {
+    if !config.enabled {
+        state.pool_reservations.clear();
+    }
+    state.pool_priority = config;
+}
+
+pub fn status(state: &State, now_ns: u64) -> RecoveryPoolPriorityStatus {
+    RecoveryPoolPriorityStatus {
+        config: state.pool_priority.clone(),
+        active: is_active(state),
+        pool_depth: state.pool_depth.clone(),
+        reservations: state
+            .pool_reservations
+            .keys()
+            .filter_map(|id| live_reservation(state, *id, now_ns).map(|until| (*id, until)))
+            .collect(),
+    }
+}
+
+/// Read the pool's per-collateral depth and cache it.
+pub async fn refresh_pool_depth(pool: Principal) {
+    let result: Result<(PoolStatusDepth,), _> = ic_cdk::call(pool, "get_pool_status", ()).await;
+    match result {
+        Ok((status,)) => mutate_state(|s| {
+            s.pool_depth = Some(PoolDepthSnapshot {
+                eligible_icusd: status.eligible_icusd_per_collateral,
+                updated_at_ns: ic_cdk::api::time(),
+            });
+        }),
+        Err((code, msg)) => log!(
+            INFO,
+            "[pool_priority] get_pool_status on {} failed: {:?} {}",
+            pool,
+            code,
+            msg
+        ),
+    }
+}
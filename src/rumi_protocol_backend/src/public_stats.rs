@@ -0,0 +1,180 @@
+//! Anonymized protocol statistics for researchers and dashboards.
+//!
+//! `get_public_stats` serves aggregates over the vault set so callers do not
+//! have to page through every vault: how many vaults fall in each size
+//! bucket, percentiles of vault debt, how often vaults are liquidated and
+//! the average collateral ratio. Nothing in it identifies a vault or an
+//! owner, and debt percentiles are withheld while fewer than
+//! `MIN_VAULTS_FOR_PERCENTILES` vaults borrow, since with a handful of
+//! vaults they would be individual positions.
+//!
+//! The snapshot is recomputed with the other cached aggregates on the
+//! vault-check tick (`State::refresh_aggregate_snapshots`), so it is up to
+//! one tick old. Liquidation counts come from the liquidation receipts and
+//! leave out chain vaults, which get no receipts.
+
+use crate::numeric::collateral_usd_value;
+use crate::state::State;
+use candid::{CandidType, Deserialize};
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// Upper bounds, in whole USD of collateral, of every size bucket but the
+/// last, which is open-ended.
+pub const VAULT_SIZE_BUCKET_BOUNDS_USD: [u64; 4] = [100, 1_000, 10_000, 100_000];
+
+/// Fewest borrowing vaults for which debt percentiles are published.
+pub const MIN_VAULTS_FOR_PERCENTILES: usize = 10;
+
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultSizeBucket {
+    /// Collateral value range in whole USD: `min_usd <= value < max_usd`.
+    pub min_usd: u64,
+    /// `None` for the open-ended top bucket.
+    pub max_usd: Option<u64>,
+    pub vault_count: u64,
+}
+
+/// Nearest-rank percentiles of the debt of vaults that borrow, in icUSD e8s.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebtPercentiles {
+    pub p10: u64,
+    pub p25: u64,
+    pub p50: u64,
+    pub p75: u64,
+    pub p90: u64,
+}
+
+/// Vault liquidations (full or partial) in the trailing windows.
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LiquidationFrequency {
+    pub last_24h: u64,
+    pub last_7d: u64,
+    pub last_30d: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PublicStats {
+    pub computed_at_ns: u64,
+    pub vault_count: u64,
+    /// Vaults with debt.
+    pub borrowing_vault_count: u64,
+    pub total_debt_e8s: u64,
+    /// USD value of all vault collateral at the cached prices, in e8s.
+    pub total_collateral_value_e8s: u64,
+    /// Vaults by collateral value. Vaults whose collateral has no price are
+    /// left out.
+    pub vault_size_buckets: Vec<VaultSizeBucket>,
+    /// `None` while fewer than `MIN_VAULTS_FOR_PERCENTILES` vaults borrow.
+    pub debt_percentiles: Option<DebtPercentiles>,
+    pub liquidations: LiquidationFrequency,
+    /// Mean collateral ratio of the borrowing vaults with a price.
+    pub average_collateral_ratio: Option<f64>,
+    /// Their collateral value over their debt: the debt-weighted average.
+    pub debt_weighted_collateral_ratio: Option<f64>,
+}
+
+/// Compute the statistics from the current vault set at `now_ns`.
+pub fn compute(state: &State, now_ns: u64) -> PublicStats {
+    let mut vault_size_buckets: Vec<VaultSizeBucket> = (0..=VAULT_SIZE_BUCKET_BOUNDS_USD.len())
+        .map(|i| VaultSizeBucket {
+            min_usd: if i == 0 {
+                0
+            } else {
+                VAULT_SIZE_BUCKET_BOUNDS_USD[i - 1]
+            },
+            max_usd: VAULT_SIZE_BUCKET_BOUNDS_USD.get(i).copied(),
+            vault_count: 0,
+        })
+        .collect();
+    let mut debts = Vec::new();
+    let mut total_debt_e8s: u64 = 0;
+    let mut total_collateral_value_e8s: u64 = 0;
+    let mut ratio_sum = Decimal::ZERO;
+    let mut priced_borrowers: u64 = 0;
+    let mut priced_debt = Decimal::ZERO;
+    let mut priced_value = Decimal::ZERO;
+
+    for vault in state.vault_id_to_vaults.values() {
+        let debt = vault.borrowed_icusd_amount.to_u64();
+        total_debt_e8s = total_debt_e8s.saturating_add(debt);
+        if debt > 0 {
+            debts.push(debt);
+        }
+        let Some(config) = state.get_collateral_config(&vault.collateral_type) else {
+            continue;
+        };
+        let Some(price) = config.last_price.and_then(Decimal::from_f64) else {
+            continue;
+        };
+        let value = collateral_usd_value(vault.collateral_amount, price, config.decimals).to_u64();
+        total_collateral_value_e8s = total_collateral_value_e8s.saturating_add(value);
+
+        let whole_usd = value / 100_000_000;
+        let bucket = VAULT_SIZE_BUCKET_BOUNDS_USD
+            .iter()
+            .position(|bound| whole_usd < *bound)
+            .unwrap_or(VAULT_SIZE_BUCKET_BOUNDS_USD.len());
+        vault_size_buckets[bucket].vault_count += 1;
+
+        if debt > 0 {
+            ratio_sum += Decimal::from(value) / Decimal::from(debt);
+            priced_borrowers += 1;
+            priced_debt += Decimal::from(debt);
+            priced_value += Decimal::from(value);
+        }
+    }
+
+    debts.sort_unstable();
+    let debt_percentiles = (debts.len() >= MIN_VAULTS_FOR_PERCENTILES).then(|| DebtPercentiles {
+        p10: nearest_rank(&debts, 10),
+        p25: nearest_rank(&debts, 25),
+        p50: nearest_rank(&debts, 50),
+        p75: nearest_rank(&debts, 75),
+        p90: nearest_rank(&debts, 90),
+    });
+
+    PublicStats {
+        computed_at_ns: now_ns,
+        vault_count: state.vault_id_to_vaults.len() as u64,
+        borrowing_vault_count: debts.len() as u64,
+        total_debt_e8s,
+        total_collateral_value_e8s,
+        vault_size_buckets,
+        debt_percentiles,
+        liquidations: liquidation_frequency(state, now_ns),
+        average_collateral_ratio: (priced_borrowers > 0)
+            .then(|| (ratio_sum / Decimal::from(priced_borrowers)).to_f64())
+            .flatten(),
+        debt_weighted_collateral_ratio: (priced_borrowers > 0)
+            .then(|| (priced_value / priced_debt).to_f64())
+            .flatten(),
+    }
+}
+
+/// The value at `percentile` of an ascending, non-empty slice: the
+/// smallest value with at least that share of values at or below it.
+fn nearest_rank(sorted: &[u64], percentile: usize) -> u64 {
+    let rank = (percentile * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+fn liquidation_frequency(state: &State, now_ns: u64) -> LiquidationFrequency {
+    let mut frequency = LiquidationFrequency::default();
+    for receipt in state.liquidation_receipts.values().flatten() {
+        let age = now_ns.saturating_sub(receipt.created_at_ns);
+        if age < NANOS_PER_DAY {
+            frequency.last_24h += 1;
+        }
+        if age < 7 * NANOS_PER_DAY {
+            frequency.last_7d += 1;
+        }
+        if age < 30 * NANOS_PER_DAY {
+            frequency.last_30d += 1;
+        }
+    }
+    frequency
+}
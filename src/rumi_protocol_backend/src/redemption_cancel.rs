@@ -0,0 +1,253 @@
+//! Cancelling a redemption whose collateral payout keeps failing.
+//!
+//! A redemption burns the redeemer's icUSD and moves collateral out of the
+//! redeemed vaults into `pending_redemption_transfer`, which the payout
+//! timer retries. If the redeemer's account cannot receive the collateral
+//! the payout fails on every attempt until it is dead-lettered (see
+//! `transfer_retry`), and the redeemer has paid for nothing. `cancel_pending_redemption` lets them back out
+//! instead: the collateral and debt go back to the vaults they came from,
+//! and the icUSD the vaults were credited with is minted back to the
+//! redeemer. The redemption fee and the redemption-margin haircut are not
+//! refunded, so the re-mint matches the debt restored and supply is
+//! unchanged by the round trip.
+//!
+//! Reversal needs the per-vault breakdown, which is kept next to each
+//! pending payout (`State::pending_redemption_records`); payouts queued
+//! before it was kept cannot be cancelled. A cancellation is allowed once
+//! the payout has failed `MIN_FAILED_PAYOUTS_BEFORE_CANCEL` times, and
+//! only within `REDEMPTION_CANCEL_WINDOW_NANOS` of the redemption, while
+//! the vaults' positions are still close to what the redemption left. It
+//! is refused when a redeemed vault has since been closed or is busy, and
+//! when the redemption accrued a deficit, which a reversal would not undo.
+
+use crate::event::VaultRedemption;
+use crate::guard::{trace_tag, GuardPrincipal, VaultLiquidationGuard};
+use crate::logs::INFO;
+use crate::numeric::ICUSD;
+use crate::state::{mutate_state, read_state, State};
+use crate::vault::require_vault_not_processing;
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use serde::Serialize;
+
+/// Failed payout attempts before the redeemer may cancel.
+pub const MIN_FAILED_PAYOUTS_BEFORE_CANCEL: u8 = 3;
+
+/// How long after the redemption it may be cancelled (24 hours).
+pub const REDEMPTION_CANCEL_WINDOW_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// What a pending redemption payout took from each vault.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingRedemptionRecord {
+    pub vault_redemptions: Vec<VaultRedemption>,
+    /// Deficit the redemption accrued against underwater vaults.
+    pub shortfall_e8s: u64,
+    pub redeemed_at_ns: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct RedemptionCancelSuccess {
+    /// icUSD minted back to the redeemer.
+    pub icusd_refunded: u64,
+    /// Collateral returned to the redeemed vaults.
+    pub collateral_returned: u64,
+    /// `None` when the mint failed and was queued for retry.
+    pub refund_block_index: Option<u64>,
+}
+
+/// Check a cancellation of the payout queued by the redemption burned at
+/// `icusd_block_index`. Returns the per-vault breakdown to reverse.
+pub fn check_cancel_pending_redemption(
+    state: &State,
+    caller: Principal,
+    icusd_block_index: u64,
+    now_ns: u64,
+) -> Result<Vec<VaultRedemption>, ProtocolError> {
+    let generic = |msg: String| ProtocolError::GenericError(msg);
+    state.mode.check_available()?;
+    let pending = state
+        .pending_redemption_transfer
+        .get(&icusd_block_index)
+        .ok_or_else(|| {
+            generic(format!(
+                "No pending redemption payout for block {}",
+                icusd_block_index
+            ))
+        })?;
+    if pending.owner != caller {
+        return Err(ProtocolError::CallerNotOwner);
+    }
+    let record = state
+        .pending_redemption_records
+        .get(&icusd_block_index)
+        .ok_or_else(|| {
+            generic(format!(
+                "Redemption {} predates cancellable payouts and cannot be reversed",
+                icusd_block_index
+            ))
+        })?;
+    // The payout timer may be mid-transfer on this entry; a cancellation
+    // then could pay the redeemer twice.
+    if state.is_timer_running {
+        return Err(ProtocolError::TemporarilyUnavailable(
+            "Pending payouts are being processed; retry shortly".to_string(),
+        ));
+    }
+    if pending.retry_count < MIN_FAILED_PAYOUTS_BEFORE_CANCEL {
+        return Err(generic(format!(
+            "The payout has failed {} times; it can be cancelled after {}",
+            pending.retry_count, MIN_FAILED_PAYOUTS_BEFORE_CANCEL
+        )));
+    }
+    if now_ns.saturating_sub(record.redeemed_at_ns) > REDEMPTION_CANCEL_WINDOW_NANOS {
+        return Err(generic(format!(
+            "Redemption {} is past its cancellation window",
+            icusd_block_index
+        )));
+    }
+    if record.shortfall_e8s > 0 {
+        return Err(generic(format!(
+            "Redemption {} accrued a deficit and cannot be reversed",
+            icusd_block_index
+        )));
+    }
+    // Its unconsumed-claim refund shares the key in `pending_refunds`.
+    if state.pending_refunds.contains_key(&icusd_block_index) {
+        return Err(ProtocolError::TemporarilyUnavailable(format!(
+            "A refund for redemption {} is still queued; retry once it lands",
+            icusd_block_index
+        )));
+    }
+    for vr in &record.vault_redemptions {
+        let vault = state
+            .vault_id_to_vaults
+            .get(&vr.vault_id)
+            .filter(|v| v.collateral_type == pending.collateral_type)
+            .ok_or_else(|| {
+                generic(format!(
+                    "Vault #{} has closed since the redemption; it cannot be reversed",
+                    vr.vault_id
+                ))
+            })?;
+        require_vault_not_processing(vault)?;
+    }
+    Ok(record.vault_redemptions.clone())
+}
+
+/// Give each vault back what the redemption took and drop the payout.
+/// Vaults closed since are skipped (live cancellations refuse them).
+pub fn apply_cancel_pending_redemption(
+    state: &mut State,
+    icusd_block_index: u64,
+    vault_redemptions: &[VaultRedemption],
+) {
+    for vr in vault_redemptions {
+        if state.vault_id_to_vaults.contains_key(&vr.vault_id) {
+            state.add_margin_to_vault(vr.vault_id, vr.collateral_seized.into());
+            state.borrow_from_vault(vr.vault_id, ICUSD::new(vr.icusd_redeemed_e8s));
+        }
+    }
+    state.pending_redemption_transfer.remove(&icusd_block_index);
+    state.pending_redemption_records.remove(&icusd_block_index);
+}
+
+/// Cancel the caller's failing redemption payout: return the collateral to
+/// the redeemed vaults and mint back the icUSD they were credited with.
+pub async fn cancel_pending_redemption(
+    icusd_block_index: u64,
+) -> Result<RedemptionCancelSuccess, ProtocolError> {
+    let caller = ic_cdk::caller();
+    let guard_principal = GuardPrincipal::new(
+        caller,
+        &format!("cancel_pending_redemption_{}", icusd_block_index),
+    )?;
+    let vault_ids: Vec<u64> = read_state(|s| {
+        s.pending_redemption_records
+            .get(&icusd_block_index)
+            .map(|r| r.vault_redemptions.iter().map(|vr| vr.vault_id).collect())
+            .unwrap_or_default()
+    });
+    let mut _vault_op_guards = Vec::with_capacity(vault_ids.len());
+    for vault_id in vault_ids {
+        match VaultLiquidationGuard::new(vault_id) {
+            Ok(g) => _vault_op_guards.push(g),
+            Err(e) => {
+                guard_principal.fail();
+                return Err(e);
+            }
+        }
+    }
+
+    let now = ic_cdk::api::time();
+    let (refund, collateral_returned, refund_nonce) = match mutate_state(|s| {
+        let vault_redemptions = check_cancel_pending_redemption(s, caller, icusd_block_index, now)?;
+        let refund = crate::event::record_redemption_cancelled(
+            s,
+            caller,
+            icusd_block_index,
+            vault_redemptions.clone(),
+            now,
+        );
+        let collateral_returned: u64 = vault_redemptions
+            .iter()
+            .map(|vr| vr.collateral_seized)
+            .sum();
+        Ok::<_, ProtocolError>((refund, collateral_returned, s.next_op_nonce()))
+    }) {
+        Ok(reversal) => reversal,
+        Err(e) => {
+            guard_principal.fail();
+            return Err(e);
+        }
+    };
+
+    // Same saga as the unconsumed-claim refund in `redeem_collateral`: mint
+    // inline, and on failure queue a durable refund under the burn block.
+    let refund_block_index = match crate::management::transfer_icusd_with_nonce(
+        refund,
+        caller,
+        refund_nonce,
+    )
+    .await
+    {
+        Ok(block_index) => Some(block_index),
+        Err(error) => {
+            log!(
+                    INFO,
+                    "[cancel_pending_redemption] trace={} Refund of {} icUSD to {} failed: {:?}. Enqueueing durable refund (block {}).",
+                    trace_tag(caller),
+                    refund.to_u64(),
+                    caller,
+                    error,
+                    icusd_block_index
+                );
+            mutate_state(|s| {
+                s.pending_refunds.insert(
+                    icusd_block_index,
+                    crate::state::PendingRefund {
+                        user: caller,
+                        amount_e8s: refund.to_u64(),
+                        retry_count: 0,
+                        op_nonce: refund_nonce,
+                    },
+                );
+            });
+            None
+        }
+    };
+    guard_principal.complete();
+    log!(
+        INFO,
+        "[cancel_pending_redemption] trace={} redemption {} cancelled: {} collateral returned to vaults, {} icUSD refunded",
+        trace_tag(caller),
+        icusd_block_index,
+        collateral_returned,
+        refund.to_u64()
+    );
+    Ok(RedemptionCancelSuccess {
+        icusd_refunded: refund.to_u64(),
+        collateral_returned,
+        refund_block_index,
+    })
+}
@@ -0,0 +1,219 @@
+//! Per-collateral redemption capacity per epoch.
+//!
+//! Redemptions seize the lowest-CR vaults of the priority collateral first,
+//! so one large redemption against a small collateral can strip most of its
+//! vaults in a single call. A collateral with a cap configured
+//! (`set_collateral_redemption_cap`) only lets so much of its debt be
+//! redeemed per epoch: at most `max_redeemed_e8s`, and at most
+//! `max_debt_bps` of the debt it carried when the epoch's first redemption
+//! landed. Either limit can be 0 to leave it off; the tighter one applies.
+//!
+//! Epochs are aligned to multiples of `epoch_ns` since the Unix epoch, so
+//! every caller sees the same boundaries. `redeem_collateral` rejects a
+//! claim the remaining capacity cannot fit, then clips it again after the
+//! icUSD pull (when concurrent redemptions may have used the epoch) and
+//! refunds the rest; reserve-redemption spillover is clipped to it likewise. Usage is rebuilt on replay from the
+//! redemption events themselves.
+
+use crate::state::{CollateralType, State};
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+/// Shortest epoch the developer may set (1 hour).
+pub const MIN_REDEMPTION_EPOCH_NS: u64 = 3600 * 1_000_000_000;
+
+/// Longest epoch the developer may set (30 days).
+pub const MAX_REDEMPTION_EPOCH_NS: u64 = 30 * 24 * 3600 * 1_000_000_000;
+
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedemptionCapConfig {
+    pub epoch_ns: u64,
+    /// Most icUSD debt (e8s) redeemed per epoch. 0 = no absolute cap.
+    pub max_redeemed_e8s: u64,
+    /// Most debt redeemed per epoch, in basis points of the collateral's
+    /// debt at the epoch's first redemption. 0 = no relative cap.
+    pub max_debt_bps: u64,
+}
+
+/// What the current epoch has used. Kept only for capped collaterals.
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedemptionEpochUsage {
+    pub epoch_start_ns: u64,
+    pub debt_at_start_e8s: u64,
+    pub redeemed_e8s: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct RedemptionCapacity {
+    pub collateral_type: CollateralType,
+    /// `None` when the collateral is uncapped.
+    pub config: Option<RedemptionCapConfig>,
+    pub epoch_start_ns: u64,
+    pub epoch_end_ns: u64,
+    pub redeemed_e8s: u64,
+    /// This epoch's limit; `None` when uncapped.
+    pub limit_e8s: Option<u64>,
+    /// Debt that can still be redeemed this epoch; `None` when uncapped.
+    pub remaining_e8s: Option<u64>,
+}
+
+pub fn validate_config(config: &RedemptionCapConfig) -> Result<(), String> {
+    if config.epoch_ns < MIN_REDEMPTION_EPOCH_NS || config.epoch_ns > MAX_REDEMPTION_EPOCH_NS {
+        return Err(format!(
+            "epoch_ns must be between {} and {}",
+            MIN_REDEMPTION_EPOCH_NS, MAX_REDEMPTION_EPOCH_NS
+        ));
+    }
+    if config.max_debt_bps > 10_000 {
+        return Err("max_debt_bps must be at most 10000".to_string());
+    }
+    if config.max_redeemed_e8s == 0 && config.max_debt_bps == 0 {
+        return Err(
+            "Set max_redeemed_e8s or max_debt_bps, or pass None to remove the cap".to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Install (`Some`) or remove (`None`) a collateral's cap. A changed cap
+/// starts from a fresh epoch count.
+pub fn apply_set_config(
+    state: &mut State,
+    collateral_type: CollateralType,
+    config: Option<RedemptionCapConfig>,
+) {
+    state.redemption_epoch_usage.remove(&collateral_type);
+    match config {
+        Some(config) => {
+            state.redemption_caps.insert(collateral_type, config);
+        }
+        None => {
+            state.redemption_caps.remove(&collateral_type);
+        }
+    }
+}
+
+fn epoch_start(config: &RedemptionCapConfig, now_ns: u64) -> u64 {
+    now_ns - now_ns % config.epoch_ns.max(1)
+}
+
+/// The usage of the epoch `now_ns` falls in; a new epoch starts empty with
+/// the collateral's current debt as its base.
+fn current_usage(
+    state: &State,
+    collateral_type: &CollateralType,
+    config: &RedemptionCapConfig,
+    now_ns: u64,
+) -> RedemptionEpochUsage {
+    let start = epoch_start(config, now_ns);
+    match state.redemption_epoch_usage.get(collateral_type) {
+        Some(usage) if usage.epoch_start_ns == start => *usage,
+        _ => RedemptionEpochUsage {
+            epoch_start_ns: start,
+            debt_at_start_e8s: state.total_debt_for_collateral(collateral_type).to_u64(),
+            redeemed_e8s: 0,
+        },
+    }
+}
+
+fn limit(config: &RedemptionCapConfig, usage: &RedemptionEpochUsage) -> u64 {
+    let absolute = if config.max_redeemed_e8s > 0 {
+        config.max_redeemed_e8s
+    } else {
+        u64::MAX
+    };
+    let relative = if config.max_debt_bps > 0 {
+        (usage.debt_at_start_e8s as u128 * config.max_debt_bps as u128 / 10_000) as u64
+    } else {
+        u64::MAX
+    };
+    absolute.min(relative)
+}
+
+pub fn capacity(
+    state: &State,
+    collateral_type: &CollateralType,
+    now_ns: u64,
+) -> RedemptionCapacity {
+    let Some(config) = state.redemption_caps.get(collateral_type) else {
+        return RedemptionCapacity {
+            collateral_type: *collateral_type,
+            config: None,
+            epoch_start_ns: 0,
+            epoch_end_ns: 0,
+            redeemed_e8s: 0,
+            limit_e8s: None,
+            remaining_e8s: None,
+        };
+    };
+    let usage = current_usage(state, collateral_type, config, now_ns);
+    let limit = limit(config, &usage);
+    RedemptionCapacity {
+        collateral_type: *collateral_type,
+        config: Some(*config),
+        epoch_start_ns: usage.epoch_start_ns,
+        epoch_end_ns: usage.epoch_start_ns.saturating_add(config.epoch_ns),
+        redeemed_e8s: usage.redeemed_e8s,
+        limit_e8s: Some(limit),
+        remaining_e8s: Some(limit.saturating_sub(usage.redeemed_e8s)),
+    }
+}
+
+/// Debt that can still be redeemed against `collateral_type` this epoch, or
+/// `u64::MAX` when it is uncapped.
+pub fn remaining(state: &State, collateral_type: &CollateralType, now_ns: u64) -> u64 {
+    capacity(state, collateral_type, now_ns)
+        .remaining_e8s
+        .unwrap_or(u64::MAX)
+}
+
+/// Reject a redemption of `amount_e8s` of debt the epoch cannot fit.
+pub fn check_capacity(
+    state: &State,
+    collateral_type: &CollateralType,
+    amount_e8s: u64,
+    now_ns: u64,
+) -> Result<(), ProtocolError> {
+    let capacity = capacity(state, collateral_type, now_ns);
+    match capacity.remaining_e8s {
+        Some(remaining) if amount_e8s > remaining => Err(ProtocolError::GenericError(format!(
+            "Redemption of {} icUSD e8s exceeds what {} has left this epoch ({} of {}). \
+             Reduce the amount or retry after {} (ns).",
+            amount_e8s,
+            collateral_type,
+            remaining,
+            capacity.limit_e8s.unwrap_or(0),
+            capacity.epoch_end_ns
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Count `redeemed_e8s` of debt just redeemed against `collateral_type`.
+/// Called after the redemption applied, so the epoch base adds it back. A
+/// no-op for uncapped collaterals.
+pub fn note_redeemed(
+    state: &mut State,
+    collateral_type: &CollateralType,
+    redeemed_e8s: u64,
+    now_ns: u64,
+) {
+    let Some(config) = state.redemption_caps.get(collateral_type).copied() else {
+        return;
+    };
+    let start = epoch_start(&config, now_ns);
+    let mut usage = match state.redemption_epoch_usage.get(collateral_type) {
+        Some(usage) if usage.epoch_start_ns == start => *usage,
+        _ => RedemptionEpochUsage {
+            epoch_start_ns: start,
+            debt_at_start_e8s: state
+                .total_debt_for_collateral(collateral_type)
+                .to_u64()
+                .saturating_add(redeemed_e8s),
+            redeemed_e8s: 0,
+        },
+    };
+    usage.redeemed_e8s = usage.redeemed_e8s.saturating_add(redeemed_e8s);
+    state.redemption_epoch_usage.insert(*collateral_type, usage);
+}
@@ -0,0 +1,310 @@
+//! Redistribution of vaults no liquidation absorbed.
+//!
+//! When neither the stability pool nor an auction cleared an unhealthy
+//! vault, its whole debt and collateral can be handed to the remaining
+//! vaults of the same collateral type, each taking a share proportional to
+//! its stake (its collateral when last settled). Touching every vault per
+//! redistribution would not scale, so the split is tracked the Liquity way:
+//! each collateral type keeps running totals of collateral and debt handed
+//! out per unit of stake, and each vault a snapshot of those totals. A
+//! vault's pending share is its stake times the growth since its snapshot,
+//! computed and booked in O(1).
+//!
+//! Pending shares are booked like interest: on the vault's next accrual,
+//! which runs at the start of the owner's operations and for every vault on
+//! each price tick. Until then vault reads show the booked amounts, while
+//! the debt and collateral totals per type already include what is pending.
+//! The share of a vault removed before booking it is redistributed again.
+//!
+//! Nothing is tracked for a collateral type until its first redistribution,
+//! which registers its vaults once. Redistribution runs when an auction
+//! expires on a still-unhealthy vault and `redistribution_enabled` is set,
+//! or when the developer calls `redistribute_vault`. Both are recorded as
+//! `VaultRedistributed`. The older `Event::RedistributeVault` spread a vault
+//! across all vaults eagerly and is kept only for replaying old logs.
+
+use crate::state::State;
+use crate::vault::Vault;
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use serde::Serialize;
+
+/// Fixed-point scale of the per-stake totals.
+const SCALE: u128 = 1_000_000_000_000_000_000;
+
+/// Running totals for one collateral type.
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedistributionIndex {
+    /// Collateral handed out per unit of stake, scaled by 1e18.
+    pub l_collateral: u128,
+    /// icUSD debt (e8s) handed out per unit of stake, scaled by 1e18.
+    pub l_debt: u128,
+    pub total_stakes: u128,
+    /// Remainders of the last divisions, carried into the next one.
+    pub error_collateral: u128,
+    pub error_debt: u128,
+    /// Handed out but not yet booked on any vault.
+    pub pending_collateral: u64,
+    pub pending_debt: u64,
+}
+
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedistributionSnapshot {
+    pub stake: u64,
+    pub l_collateral: u128,
+    pub l_debt: u128,
+}
+
+#[derive(CandidType, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingRedistribution {
+    pub collateral: u64,
+    pub debt: u64,
+}
+
+fn share(stake: u64, growth: u128) -> u64 {
+    let share = (stake as u128).saturating_mul(growth) / SCALE;
+    u64::try_from(share).unwrap_or(u64::MAX)
+}
+
+/// `vault_id`'s share not yet booked on it.
+pub fn pending(state: &State, vault_id: u64) -> PendingRedistribution {
+    let (Some(vault), Some(snapshot)) = (
+        state.vault_id_to_vaults.get(&vault_id),
+        state.redistribution_snapshots.get(&vault_id),
+    ) else {
+        return PendingRedistribution::default();
+    };
+    let Some(index) = state.redistribution_indexes.get(&vault.collateral_type) else {
+        return PendingRedistribution::default();
+    };
+    PendingRedistribution {
+        collateral: share(
+            snapshot.stake,
+            index.l_collateral.saturating_sub(snapshot.l_collateral),
+        ),
+        debt: share(snapshot.stake, index.l_debt.saturating_sub(snapshot.l_debt)),
+    }
+}
+
+/// Start tracking `ct`, registering its vaults. Runs once per type.
+fn ensure_index(state: &mut State, ct: Principal) {
+    if state.redistribution_indexes.contains_key(&ct) {
+        return;
+    }
+    let mut index = RedistributionIndex::default();
+    let vault_ids: Vec<u64> = state
+        .collateral_to_vault_ids
+        .get(&ct)
+        .map(|ids| ids.iter().copied().collect())
+        .unwrap_or_default();
+    for vault_id in vault_ids {
+        if let Some(vault) = state.vault_id_to_vaults.get(&vault_id) {
+            index.total_stakes += vault.collateral_amount as u128;
+            state.redistribution_snapshots.insert(
+                vault_id,
+                RedistributionSnapshot {
+                    stake: vault.collateral_amount,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+    state.redistribution_indexes.insert(ct, index);
+}
+
+/// Register a newly opened vault if its type is tracked.
+pub fn register(state: &mut State, vault: &Vault) {
+    let Some(index) = state.redistribution_indexes.get_mut(&vault.collateral_type) else {
+        return;
+    };
+    if let Some(old) = state.redistribution_snapshots.get(&vault.vault_id) {
+        index.total_stakes = index.total_stakes.saturating_sub(old.stake as u128);
+    }
+    index.total_stakes += vault.collateral_amount as u128;
+    state.redistribution_snapshots.insert(
+        vault.vault_id,
+        RedistributionSnapshot {
+            stake: vault.collateral_amount,
+            l_collateral: index.l_collateral,
+            l_debt: index.l_debt,
+        },
+    );
+}
+
+/// Hand `collateral` and `debt` out across the stakes of `index`, carrying
+/// the rounding remainders. The caller checks `total_stakes > 0`.
+fn spread(index: &mut RedistributionIndex, collateral: u64, debt: u64) {
+    let total = index.total_stakes;
+    let numerator = (collateral as u128)
+        .saturating_mul(SCALE)
+        .saturating_add(index.error_collateral);
+    let per_stake = numerator / total;
+    index.error_collateral = numerator - per_stake * total;
+    index.l_collateral = index.l_collateral.saturating_add(per_stake);
+
+    let numerator = (debt as u128)
+        .saturating_mul(SCALE)
+        .saturating_add(index.error_debt);
+    let per_stake = numerator / total;
+    index.error_debt = numerator - per_stake * total;
+    index.l_debt = index.l_debt.saturating_add(per_stake);
+
+    index.pending_collateral = index.pending_collateral.saturating_add(collateral);
+    index.pending_debt = index.pending_debt.saturating_add(debt);
+}
+
+/// Book `vault_id`'s pending share on it and restake it at its collateral.
+/// Returns what was booked.
+pub fn settle(state: &mut State, vault_id: u64) -> PendingRedistribution {
+    let Some(ct) = state
+        .vault_id_to_vaults
+        .get(&vault_id)
+        .map(|v| v.collateral_type)
+    else {
+        return PendingRedistribution::default();
+    };
+    if !state.redistribution_indexes.contains_key(&ct) {
+        return PendingRedistribution::default();
+    }
+    let booked = pending(state, vault_id);
+    let vault = state
+        .vault_id_to_vaults
+        .get_mut(&vault_id)
+        .expect("vault checked above");
+    vault.collateral_amount = vault.collateral_amount.saturating_add(booked.collateral);
+    vault.borrowed_icusd_amount += crate::numeric::ICUSD::new(booked.debt);
+    let stake = vault.collateral_amount;
+
+    let index = state
+        .redistribution_indexes
+        .get_mut(&ct)
+        .expect("index checked above");
+    index.pending_collateral = index.pending_collateral.saturating_sub(booked.collateral);
+    index.pending_debt = index.pending_debt.saturating_sub(booked.debt);
+    let old_stake = state
+        .redistribution_snapshots
+        .get(&vault_id)
+        .map_or(0, |s| s.stake);
+    index.total_stakes = index
+        .total_stakes
+        .saturating_sub(old_stake as u128)
+        .saturating_add(stake as u128);
+    state.redistribution_snapshots.insert(
+        vault_id,
+        RedistributionSnapshot {
+            stake,
+            l_collateral: index.l_collateral,
+            l_debt: index.l_debt,
+        },
+    );
+    if booked != PendingRedistribution::default() {
+        state.reindex_vault_cr(vault_id);
+    }
+    booked
+}
+
+/// Drop a removed vault's stake and hand its unbooked share to the
+/// remaining vaults of its type.
+pub fn forget(state: &mut State, vault: &Vault) {
+    let Some(snapshot) = state.redistribution_snapshots.remove(&vault.vault_id) else {
+        return;
+    };
+    let Some(index) = state.redistribution_indexes.get_mut(&vault.collateral_type) else {
+        return;
+    };
+    let collateral = share(
+        snapshot.stake,
+        index.l_collateral.saturating_sub(snapshot.l_collateral),
+    );
+    let debt = share(snapshot.stake, index.l_debt.saturating_sub(snapshot.l_debt));
+    index.total_stakes = index.total_stakes.saturating_sub(snapshot.stake as u128);
+    if collateral == 0 && debt == 0 {
+        return;
+    }
+    index.pending_collateral = index.pending_collateral.saturating_sub(collateral);
+    index.pending_debt = index.pending_debt.saturating_sub(debt);
+    if index.total_stakes > 0 {
+        spread(index, collateral, debt);
+    } else {
+        log!(
+            crate::INFO,
+            "[redistribution] vault #{} left with {} collateral and {} icUSD e8s unbooked and no vault to take them",
+            vault.vault_id,
+            collateral,
+            debt
+        );
+    }
+}
+
+/// Checks before redistributing `vault_id`: it must be liquidatable and
+/// not the only vault of its type with collateral.
+pub fn check(state: &State, vault_id: u64) -> Result<(), ProtocolError> {
+    crate::auction::liquidatable_price(state, vault_id).map_err(ProtocolError::GenericError)?;
+    let vault = &state.vault_id_to_vaults[&vault_id];
+    let others = state
+        .collateral_to_vault_ids
+        .get(&vault.collateral_type)
+        .into_iter()
+        .flatten()
+        .filter(|&&id| id != vault_id)
+        .filter_map(|id| state.vault_id_to_vaults.get(id))
+        .any(|v| v.collateral_amount > 0);
+    if !others {
+        return Err(ProtocolError::GenericError(format!(
+            "No other vault of vault #{}'s collateral type to take its debt",
+            vault_id
+        )));
+    }
+    Ok(())
+}
+
+/// Redistribute `vault_id` and remove it. Returns the collateral and debt
+/// handed out. The vault's own pending share is booked first, so it is
+/// never handed back to itself.
+pub fn apply_redistribute(
+    state: &mut State,
+    vault_id: u64,
+) -> Result<PendingRedistribution, ProtocolError> {
+    let ct = state
+        .vault_id_to_vaults
+        .get(&vault_id)
+        .map(|v| v.collateral_type)
+        .ok_or_else(|| ProtocolError::GenericError(format!("Vault #{} not found", vault_id)))?;
+    ensure_index(state, ct);
+    settle(state, vault_id);
+    let vault = state.vault_id_to_vaults[&vault_id].clone();
+    let own_stake = state
+        .redistribution_snapshots
+        .get(&vault_id)
+        .map_or(0, |s| s.stake as u128);
+    let index = state
+        .redistribution_indexes
+        .get_mut(&ct)
+        .expect("index ensured above");
+    if index.total_stakes.saturating_sub(own_stake) == 0 {
+        return Err(ProtocolError::GenericError(format!(
+            "No other vault of vault #{}'s collateral type to take its debt",
+            vault_id
+        )));
+    }
+    // Unstake first so the vault takes no part of its own debt.
+    index.total_stakes -= own_stake;
+    state.redistribution_snapshots.remove(&vault_id);
+    let handed_out = PendingRedistribution {
+        collateral: vault.collateral_amount,
+        debt: vault.borrowed_icusd_amount.to_u64(),
+    };
+    let index = state
+        .redistribution_indexes
+        .get_mut(&ct)
+        .expect("index ensured above");
+    spread(index, handed_out.collateral, handed_out.debt);
+    state.remove_vault_and_unindex(vault_id);
+    Ok(handed_out)
+}
+
+/// The index of `ct`, if it was ever redistributed into.
+pub fn index_of(state: &State, ct: &Principal) -> Option<RedistributionIndex> {
+    state.redistribution_indexes.get(ct).cloned()
+}
@@ -0,0 +1,315 @@
+//! Owner-initiated deleverage: repay a vault's debt with its own collateral.
+//!
+//! `repay_from_collateral` sells `collateral_amount` of the vault's
+//! collateral for icUSD and repays the debt with the proceeds in one call.
+//! The sale goes through the collateral's whitelisted auto-deleverage route
+//! (`set_auto_deleverage_route`), a DEX exposing the Rumi AMM `swap`
+//! interface. As with auto-deleverage, the swap pays its icUSD to the
+//! protocol's main account, which is the icUSD minting account, so the
+//! proceeds are burned on arrival and repay the debt in full.
+//!
+//! Atomicity comes from the swap's `min_amount_out`. The owner names the
+//! least debt they accept having repaid (`min_debt_repaid`); the protocol
+//! raises it to the vault's debt share of the collateral sold, so a sale can
+//! never leave the vault at a lower collateral ratio than before. The DEX
+//! either delivers that much or rejects the trade without moving funds, in
+//! which case the vault is left exactly as it was.
+//!
+//! A sale is capped at the oracle value of the debt, so the proceeds can
+//! only overshoot it by the DEX beating the oracle. Any such surplus is
+//! minted back to the owner.
+
+use crate::auto_deleverage::AutoDeleverageRoute;
+use crate::dex::{DexSale, DexSwapError};
+use crate::guard::{trace_tag, GuardPrincipal, VaultLiquidationGuard};
+use crate::logs::INFO;
+use crate::numeric::{ICP, ICUSD};
+use crate::state::{mutate_state, read_state, CollateralType, State};
+use crate::vault::require_vault_not_processing;
+use crate::vault_status::VaultOperation;
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+
+/// How long the DEX allowance for a sale stays valid.
+pub const REPAY_FROM_COLLATERAL_APPROVAL_TTL_NS: u64 = 5 * 60 * 1_000_000_000;
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct RepayFromCollateralSuccess {
+    pub vault_id: u64,
+    /// Collateral taken off the vault, ledger fees included.
+    pub collateral_sold: u64,
+    /// Debt repaid.
+    pub icusd_repaid: u64,
+    /// Proceeds above the debt, minted back to the owner.
+    pub surplus_returned: u64,
+}
+
+/// Everything the sale needs, checked up front against current state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepayFromCollateralPlan {
+    pub vault_id: u64,
+    pub collateral_type: CollateralType,
+    pub route: AutoDeleverageRoute,
+    pub debt: u64,
+    /// Collateral handed to the DEX.
+    pub amount_in: u64,
+    /// Allowance granted to the DEX (`amount_in` plus one transfer fee).
+    pub allowance: u64,
+    /// Collateral taken off the vault: `amount_in` plus the approve and
+    /// transfer-from fees.
+    pub collateral_debit: u64,
+    /// Least icUSD the DEX must deliver.
+    pub min_icusd_out: u64,
+}
+
+/// Check a request and work out its amounts. Pure: callers accrue the
+/// vault's interest first.
+pub fn plan_repay_from_collateral(
+    state: &State,
+    caller: Principal,
+    vault_id: u64,
+    collateral_amount: u64,
+    min_debt_repaid: u64,
+    now_ns: u64,
+) -> Result<RepayFromCollateralPlan, ProtocolError> {
+    let generic = |msg: String| ProtocolError::GenericError(msg);
+    state.mode.check_available()?;
+    let vault = state
+        .vault_id_to_vaults
+        .get(&vault_id)
+        .ok_or_else(|| generic(format!("Vault #{} not found", vault_id)))?;
+    if vault.owner != caller {
+        return Err(ProtocolError::CallerNotOwner);
+    }
+    require_vault_not_processing(vault)?;
+    crate::vault_freeze::require_vault_not_frozen(state, vault_id, now_ns)?;
+    crate::vault_status::require_allows(state, vault_id, VaultOperation::Repay)?;
+    crate::vault_status::require_allows(state, vault_id, VaultOperation::Withdraw)?;
+
+    let ct = vault.collateral_type;
+    let route = state
+        .auto_deleverage_routes
+        .get(&ct)
+        .cloned()
+        .ok_or_else(|| generic(format!("No whitelisted route to sell {} for icUSD", ct)))?;
+    let config = state
+        .get_collateral_config(&ct)
+        .ok_or_else(|| generic("Collateral type not configured.".to_string()))?;
+    if !config.status.allows_repay() || !config.status.allows_withdraw() {
+        return Err(generic(
+            "Repaying from collateral is not allowed for this collateral type.".to_string(),
+        ));
+    }
+    let price = state
+        .get_collateral_price_decimal(&ct)
+        .ok_or_else(|| generic("No price available for the collateral.".to_string()))?;
+
+    let debt = vault.borrowed_icusd_amount.to_u64();
+    if debt == 0 {
+        return Err(generic(format!("Vault #{} has no debt to repay", vault_id)));
+    }
+    if collateral_amount == 0 {
+        return Err(generic("Nothing to sell".to_string()));
+    }
+    // Approve, then transfer-from: both are charged to the protocol account.
+    let collateral_debit = collateral_amount.saturating_add(config.ledger_fee.saturating_mul(2));
+    if collateral_debit > vault.collateral_amount {
+        return Err(generic(format!(
+            "Vault #{} holds {} collateral; selling {} needs {} with ledger fees",
+            vault_id, vault.collateral_amount, collateral_amount, collateral_debit
+        )));
+    }
+    let value =
+        crate::numeric::collateral_usd_value(collateral_amount, price, config.decimals).to_u64();
+    if value > debt {
+        return Err(generic(format!(
+            "{} collateral is worth {} icUSD e8s, more than the vault's {} of debt",
+            collateral_amount, value, debt
+        )));
+    }
+    if min_debt_repaid > debt {
+        return Err(generic(format!(
+            "min_debt_repaid {} is above the vault's debt of {}",
+            min_debt_repaid, debt
+        )));
+    }
+
+    // The debt share of the collateral taken off, rounded up: repaying at
+    // least this keeps the vault's collateral ratio where it was.
+    let proportional = ((debt as u128 * collateral_debit as u128)
+        .div_ceil(vault.collateral_amount as u128)) as u64;
+    let min_icusd_out = min_debt_repaid.max(proportional).max(1);
+
+    Ok(RepayFromCollateralPlan {
+        vault_id,
+        collateral_type: ct,
+        route,
+        debt,
+        amount_in: collateral_amount,
+        allowance: collateral_amount.saturating_add(config.ledger_fee),
+        collateral_debit,
+        min_icusd_out,
+    })
+}
+
+/// Take the sold collateral off the vault and repay the proceeds. Returns
+/// the interest share of the repayment. Proceeds above the vault's debt
+/// repay nothing; a no-op for an unknown vault.
+pub fn apply_repay_from_collateral(
+    state: &mut State,
+    vault_id: u64,
+    collateral_debit: u64,
+    icusd_repaid: ICUSD,
+) -> ICUSD {
+    let Some(vault) = state.vault_id_to_vaults.get(&vault_id) else {
+        return ICUSD::new(0);
+    };
+    let repaid = icusd_repaid.min(vault.borrowed_icusd_amount);
+    state.remove_margin_from_vault(vault_id, ICP::new(collateral_debit));
+    let (interest_share, _) = state.repay_to_vault(vault_id, repaid);
+    interest_share
+}
+
+/// Sell part of the vault's collateral through its whitelisted route and
+/// repay the proceeds. Nothing changes unless the DEX delivers at least the
+/// planned minimum.
+pub async fn repay_from_collateral(
+    vault_id: u64,
+    collateral_amount: u64,
+    min_debt_repaid: u64,
+) -> Result<RepayFromCollateralSuccess, ProtocolError> {
+    let caller = ic_cdk::caller();
+    let _guard_principal =
+        GuardPrincipal::new(caller, &format!("repay_from_collateral_{}", vault_id))?;
+    let _vault_op_guard = VaultLiquidationGuard::new(vault_id)?;
+
+    let now = ic_cdk::api::time();
+    mutate_state(|s| s.accrue_single_vault(vault_id, now));
+    let plan = read_state(|s| {
+        plan_repay_from_collateral(s, caller, vault_id, collateral_amount, min_debt_repaid, now)
+    })?;
+
+    log!(
+        INFO,
+        "[repay_from_collateral] trace={} vault #{}: selling {} of {} to repay at least {} of {} icUSD e8s on {} ({})",
+        trace_tag(caller),
+        plan.vault_id,
+        plan.amount_in,
+        plan.collateral_type,
+        plan.min_icusd_out,
+        plan.debt,
+        plan.route.dex,
+        plan.route.pool_id
+    );
+
+    let amount_out = sell(&plan, caller, now).await?;
+    let (icusd_repaid, surplus_returned) = settle(&plan, caller, amount_out).await;
+    log!(
+        INFO,
+        "[repay_from_collateral] trace={} vault #{} repaid {} icUSD e8s from {} of {}",
+        trace_tag(caller),
+        plan.vault_id,
+        icusd_repaid,
+        plan.amount_in,
+        plan.collateral_type
+    );
+
+    Ok(RepayFromCollateralSuccess {
+        vault_id: plan.vault_id,
+        collateral_sold: plan.collateral_debit,
+        icusd_repaid,
+        surplus_returned,
+    })
+}
+
+/// Run the planned sale on the route's DEX. Returns the icUSD it
+/// delivered; on any failure the vault is unchanged.
+pub(crate) async fn sell(
+    plan: &RepayFromCollateralPlan,
+    caller: Principal,
+    now: u64,
+) -> Result<u64, ProtocolError> {
+    let sale = DexSale {
+        token_in: plan.collateral_type,
+        dex: plan.route.dex,
+        pool_id: &plan.route.pool_id,
+        amount_in: plan.amount_in,
+        allowance: plan.allowance,
+        min_amount_out: plan.min_icusd_out,
+        approval_expires_at: now.saturating_add(REPAY_FROM_COLLATERAL_APPROVAL_TTL_NS),
+    };
+    match crate::dex::swap(&sale).await {
+        Ok(amount_out) => Ok(amount_out),
+        Err(DexSwapError::Approve(e)) => Err(ProtocolError::GenericError(format!(
+            "Could not approve the DEX to sell: {:?}",
+            e
+        ))),
+        Err(DexSwapError::Rejected { .. }) => Err(ProtocolError::GenericError(format!(
+            "The DEX rejected the sale (output below {} or pool unavailable); vault #{} is unchanged",
+            plan.min_icusd_out, plan.vault_id
+        ))),
+        Err(DexSwapError::Unreachable { code, msg, .. }) => {
+            log!(
+                INFO,
+                "[repay_from_collateral] trace={} ERROR: could not reach DEX {} for vault #{}: {:?} {}",
+                trace_tag(caller),
+                plan.route.dex,
+                plan.vault_id,
+                code,
+                msg
+            );
+            Err(ProtocolError::TemporarilyUnavailable(format!(
+                "Could not reach the DEX; vault #{} is unchanged",
+                plan.vault_id
+            )))
+        }
+    }
+}
+
+/// Record the sale and repay the vault with its proceeds, route the
+/// interest share and mint any surplus back to the owner. Returns the debt
+/// repaid and the surplus actually returned.
+pub(crate) async fn settle(
+    plan: &RepayFromCollateralPlan,
+    caller: Principal,
+    amount_out: u64,
+) -> (u64, u64) {
+    let icusd_repaid = amount_out.min(plan.debt);
+    let surplus = amount_out - icusd_repaid;
+
+    let interest_share = mutate_state(|s| {
+        crate::event::record_vault_repaid_from_collateral(
+            s,
+            plan,
+            caller,
+            ICUSD::new(icusd_repaid),
+            ic_cdk::api::time(),
+        )
+    });
+    // Same routing as a manual repayment; re-queue what could not be minted.
+    let unminted_interest =
+        crate::treasury::distribute_interest(interest_share, plan.collateral_type).await;
+    if unminted_interest.to_u64() > 0 {
+        mutate_state(|s| {
+            s.restore_pending_interest_for_pool(plan.collateral_type, unminted_interest.to_u64())
+        });
+    }
+
+    let mut surplus_returned = 0;
+    if surplus > 0 {
+        match crate::management::mint_icusd(ICUSD::new(surplus), caller).await {
+            Ok(_) => surplus_returned = surplus,
+            Err(e) => log!(
+                INFO,
+                "[repay_from_collateral] trace={} ERROR: could not return {} icUSD e8s of surplus to {} for vault #{}: {:?}",
+                trace_tag(caller),
+                surplus,
+                caller,
+                plan.vault_id,
+                e
+            ),
+        }
+    }
+    (icusd_repaid, surplus_returned)
+}
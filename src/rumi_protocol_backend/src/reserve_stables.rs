@@ -0,0 +1,205 @@
+//! Stable tokens accepted for reserve redemption.
+//!
+//! `redeem_reserves` pays redeemers from the protocol's stable-token
+//! reserves. Which ledgers it pays from is a list the developer maintains:
+//! each entry has a priority (lowest first), an enabled flag and optionally
+//! its own fee in place of the global `reserve_redemption_fee`. Without a
+//! preferred token, a redemption takes the first enabled entry whose
+//! reserve covers it in full, or else the one holding the most, and the
+//! rest spills over to vaults as before.
+//!
+//! Until the list is first edited, it is ckUSDT then ckUSDC, from the
+//! ledgers set at init. The first edit writes those two entries into the
+//! list, so later changes to `ckusdt_ledger_principal` and
+//! `ckusdc_ledger_principal` (which stable repayments use) no longer move
+//! them. Each edit is logged as an event.
+//!
+//! `borrow_from_vault_as_stable` pays a borrow's proceeds from the same
+//! reserves, as if the icUSD were minted and redeemed at once: 1:1, less
+//! the reserve fee.
+
+use crate::numeric::{Ratio, ICUSD};
+use crate::state::State;
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// Highest per-token fee, matching `set_reserve_redemption_fee`.
+pub const MAX_RESERVE_STABLE_FEE_BPS: u64 = 1_000;
+
+/// Entries the list may hold.
+pub const MAX_RESERVE_STABLES: usize = 10;
+
+/// Amounts of the legacy ckUSDT/ckUSDC entries are in e6s.
+const LEGACY_DECIMALS: u8 = 6;
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReserveStable {
+    pub ledger: Principal,
+    pub symbol: String,
+    pub decimals: u8,
+    pub enabled: bool,
+    /// Lower goes first; ties go by ledger principal.
+    pub priority: u32,
+    /// Overrides the global reserve redemption fee when set.
+    pub fee_bps: Option<u64>,
+}
+
+fn legacy(state: &State) -> Vec<ReserveStable> {
+    [
+        (state.ckusdt_ledger_principal, "ckUSDT"),
+        (state.ckusdc_ledger_principal, "ckUSDC"),
+    ]
+    .into_iter()
+    .enumerate()
+    .filter_map(|(priority, (ledger, symbol))| {
+        ledger.map(|ledger| ReserveStable {
+            ledger,
+            symbol: symbol.to_string(),
+            decimals: LEGACY_DECIMALS,
+            enabled: true,
+            priority: priority as u32,
+            fee_bps: None,
+        })
+    })
+    .collect()
+}
+
+/// Every configured reserve stable, enabled or not, in priority order.
+pub fn configured(state: &State) -> Vec<ReserveStable> {
+    let mut stables: Vec<ReserveStable> = if state.reserve_stables.is_empty() {
+        legacy(state)
+    } else {
+        state.reserve_stables.values().cloned().collect()
+    };
+    stables.sort_by_key(|s| (s.priority, s.ledger));
+    stables
+}
+
+/// The enabled reserve stables, in priority order.
+pub fn enabled(state: &State) -> Vec<ReserveStable> {
+    configured(state)
+        .into_iter()
+        .filter(|s| s.enabled)
+        .collect()
+}
+
+pub fn validate(state: &State, stable: &ReserveStable) -> Result<(), ProtocolError> {
+    let invalid = |msg: &str| Err(ProtocolError::GenericError(msg.to_string()));
+    if stable.ledger == Principal::anonymous() {
+        return invalid("ledger must not be anonymous");
+    }
+    if stable.ledger == state.icusd_ledger_principal {
+        return invalid("icUSD cannot back its own redemptions");
+    }
+    if stable.symbol.is_empty() || stable.symbol.len() > 16 {
+        return invalid("symbol must be 1 to 16 bytes");
+    }
+    // Balances and transfers are u64; more decimals would overflow them.
+    if stable.decimals > 8 {
+        return invalid("decimals must be at most 8");
+    }
+    if stable
+        .fee_bps
+        .is_some_and(|bps| bps > MAX_RESERVE_STABLE_FEE_BPS)
+    {
+        return invalid("fee_bps must be at most 1000 (10%)");
+    }
+    let listed = configured(state).iter().any(|s| s.ledger == stable.ledger);
+    if !listed && configured(state).len() >= MAX_RESERVE_STABLES {
+        return invalid("too many reserve stables");
+    }
+    Ok(())
+}
+
+/// Write the legacy entries into the list if it was never edited.
+fn materialize(state: &mut State) {
+    if state.reserve_stables.is_empty() {
+        for stable in legacy(state) {
+            state.reserve_stables.insert(stable.ledger, stable);
+        }
+    }
+}
+
+/// Add or replace `stable`. The configured legacy stables become entries
+/// first, so the first edit does not drop them.
+pub fn apply_set(state: &mut State, stable: ReserveStable) {
+    materialize(state);
+    state.reserve_stables.insert(stable.ledger, stable);
+}
+
+/// Drop `ledger` from the list, legacy stables included. Returns whether
+/// it was listed.
+pub fn apply_remove(state: &mut State, ledger: Principal) -> bool {
+    materialize(state);
+    state.reserve_stables.remove(&ledger).is_some()
+}
+
+/// Convert icUSD e8s to `decimals` (at most 8) units, rounding down.
+pub fn e8s_to_native(e8s: u64, decimals: u8) -> u64 {
+    e8s / 10u64.pow(8u32.saturating_sub(decimals as u32))
+}
+
+pub fn native_to_e8s(amount: u64, decimals: u8) -> u64 {
+    amount.saturating_mul(10u64.pow(8u32.saturating_sub(decimals as u32)))
+}
+
+/// The fee `stable` charges: its own, else the global `global_fee`.
+pub fn fee_ratio(stable: &ReserveStable, global_fee: Ratio) -> Ratio {
+    match stable.fee_bps {
+        Some(bps) => Ratio::from(Decimal::from(bps) / Decimal::from(10_000u64)),
+        None => global_fee,
+    }
+}
+
+/// A borrow's proceeds paid from a reserve instead of minted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReservePayout {
+    /// The reserve fee on the proceeds.
+    pub fee_icusd: ICUSD,
+    /// Paid to the borrower, in the stable's units.
+    pub amount_native: u64,
+    /// Paid to the treasury, in the stable's units.
+    pub fee_native: u64,
+}
+
+impl ReservePayout {
+    /// Reserve balance the payout needs: both amounts and a ledger fee for
+    /// each transfer.
+    pub fn needed(&self, ledger_fee: u64) -> u64 {
+        let transfers = if self.fee_native > 0 { 2 } else { 1 };
+        self.amount_native
+            .saturating_add(self.fee_native)
+            .saturating_add(ledger_fee.saturating_mul(transfers))
+    }
+}
+
+/// Pay `proceeds` of icUSD 1:1 in `stable`, less its fee.
+pub fn borrow_payout(proceeds: ICUSD, stable: &ReserveStable, global_fee: Ratio) -> ReservePayout {
+    let fee_icusd = proceeds * fee_ratio(stable, global_fee);
+    ReservePayout {
+        fee_icusd,
+        amount_native: e8s_to_native((proceeds - fee_icusd).to_u64(), stable.decimals),
+        fee_native: e8s_to_native(fee_icusd.to_u64(), stable.decimals),
+    }
+}
+
+/// The stable a redemption needing `needed_e8s` of reserve (fees included)
+/// pays from: the first, in `candidates` order, whose balance covers it,
+/// else the one whose balance is worth the most. `balances` holds each
+/// candidate's balance in its own units.
+pub fn pick<'a>(
+    candidates: &'a [ReserveStable],
+    balances: &[u64],
+    needed_e8s: u64,
+) -> Option<&'a ReserveStable> {
+    let worth = |i: usize| native_to_e8s(balances[i], candidates[i].decimals);
+    (0..candidates.len())
+        .find(|&i| worth(i) >= needed_e8s)
+        .or_else(|| {
+            // Earliest wins ties.
+            (0..candidates.len()).rev().max_by_key(|&i| worth(i))
+        })
+        .map(|i| &candidates[i])
+}
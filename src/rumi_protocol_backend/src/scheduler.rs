@@ -0,0 +1,323 @@
+//! Background work scheduler.
+//!
+//! Every recurring timer the canister runs is registered here under a
+//! `ScheduledTask`, and so are the kicks that process pending transfers. The
+//! work itself stays with the modules that own it (`xrc`, `treasury`, the
+//! chain workers, ...); the scheduler owns the timer ids and keeps, per
+//! task:
+//!
+//! - the interval and when the task runs next,
+//! - when it last started and last finished, and how many runs are in
+//!   flight,
+//! - how many runs it has made and how many of them failed, with the last
+//!   error.
+//!
+//! A run fails when its job returns `Err`, or when it traps after its first
+//! await (the dropped run is recorded as `trapped`). A trap before the first
+//! await rolls the run's bookkeeping back with everything else, so it is not
+//! counted. `get_scheduler_status` returns the whole table.
+//!
+//! `set_interval` clears the timer it replaces, so the setters that re-tune
+//! a cadence, and `setup_timers` after an upgrade, never leave a duplicate
+//! timer behind. Timers and the table are
+//! heap-only: an upgrade drops both, and `setup_timers` registers every task
+//! again with fresh counters.
+//!
+//! Not covered: one-shot continuations that are part of a single operation
+//! (replay resume, the event log migration, the post-upgrade self-test,
+//! mode propagation retries, per-vault liquidation transfer retries and the
+//! liquidatable set's truncated-update follow-up).
+
+use crate::state::State;
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk_timers::TimerId;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Duration;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Recorded as the error of a run that was dropped before it finished.
+pub const TRAPPED: &str = "trapped";
+
+#[derive(
+    CandidType, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum ScheduledTask {
+    /// `xrc::fetch_icp_rate`.
+    IcpPriceFetch,
+    /// The background price fetch of one non-ICP collateral.
+    CollateralPriceFetch(Principal),
+    /// `xrc::interest_and_treasury_tick`.
+    InterestTreasury,
+    /// `xrc::vault_check_tick`.
+    VaultCheck,
+    /// `process_pending_transfer`, run when something queues a transfer.
+    PendingTransfers,
+    ChainSettlement,
+    ChainObserver,
+    ChainInterest,
+    /// Pruning unfunded chain vaults.
+    ChainVaultGc,
+    ProtocolSnapshot,
+    AssetMetadataRefresh,
+    StateCheckpoint,
+    GuardResolve,
+    LoyaltyCheckpoint,
+    DustSweep,
+}
+
+/// The interval tasks `setup_timers` registers, besides one
+/// `CollateralPriceFetch` per non-ICP collateral.
+pub const FIXED_INTERVAL_TASKS: [ScheduledTask; 13] = [
+    ScheduledTask::IcpPriceFetch,
+    ScheduledTask::InterestTreasury,
+    ScheduledTask::VaultCheck,
+    ScheduledTask::ChainSettlement,
+    ScheduledTask::ChainObserver,
+    ScheduledTask::ChainInterest,
+    ScheduledTask::ChainVaultGc,
+    ScheduledTask::ProtocolSnapshot,
+    ScheduledTask::AssetMetadataRefresh,
+    ScheduledTask::StateCheckpoint,
+    ScheduledTask::GuardResolve,
+    ScheduledTask::LoyaltyCheckpoint,
+    ScheduledTask::DustSweep,
+];
+
+/// Every interval task that should be registered for `state`.
+pub fn interval_tasks(state: &State) -> Vec<ScheduledTask> {
+    let icp = state.icp_collateral_type();
+    let collaterals = state
+        .collateral_configs
+        .keys()
+        .filter(|ct| **ct != icp)
+        .map(|ct| ScheduledTask::CollateralPriceFetch(*ct));
+    FIXED_INTERVAL_TASKS
+        .into_iter()
+        .chain(collaterals)
+        .collect()
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskStatus {
+    pub task: ScheduledTask,
+    /// `None` for a task that only runs when something schedules it.
+    pub interval_secs: Option<u64>,
+    /// The earliest scheduled run, `None` when nothing is scheduled.
+    pub next_run_ns: Option<u64>,
+    pub last_run_ns: Option<u64>,
+    pub last_finished_ns: Option<u64>,
+    pub in_flight: u32,
+    pub runs: u64,
+    pub errors: u64,
+    pub last_error: Option<String>,
+    /// When the last failed run started.
+    pub last_error_ns: Option<u64>,
+}
+
+#[derive(Default)]
+struct TaskEntry {
+    interval: Option<(TimerId, u64)>,
+    next_interval_run_ns: Option<u64>,
+    /// Due times of scheduled one-shot runs.
+    pending_runs_ns: Vec<u64>,
+    last_run_ns: Option<u64>,
+    last_finished_ns: Option<u64>,
+    in_flight: u32,
+    runs: u64,
+    errors: u64,
+    last_error: Option<String>,
+    last_error_ns: Option<u64>,
+}
+
+impl TaskEntry {
+    fn status(&self, task: ScheduledTask) -> TaskStatus {
+        let next_run_ns = self
+            .pending_runs_ns
+            .iter()
+            .copied()
+            .chain(self.next_interval_run_ns)
+            .min();
+        TaskStatus {
+            task,
+            interval_secs: self.interval.map(|(_, secs)| secs),
+            next_run_ns,
+            last_run_ns: self.last_run_ns,
+            last_finished_ns: self.last_finished_ns,
+            in_flight: self.in_flight,
+            runs: self.runs,
+            errors: self.errors,
+            last_error: self.last_error.clone(),
+            last_error_ns: self.last_error_ns,
+        }
+    }
+}
+
+thread_local! {
+    static TASKS: RefCell<BTreeMap<ScheduledTask, TaskEntry>> =
+        RefCell::new(BTreeMap::new());
+}
+
+fn with_entry<R>(task: ScheduledTask, f: impl FnOnce(&mut TaskEntry) -> R) -> R {
+    TASKS.with(|tasks| f(tasks.borrow_mut().entry(task).or_default()))
+}
+
+/// Every task that has been scheduled since the last upgrade.
+pub fn status() -> Vec<TaskStatus> {
+    TASKS.with(|tasks| {
+        tasks
+            .borrow()
+            .iter()
+            .map(|(task, entry)| entry.status(*task))
+            .collect()
+    })
+}
+
+pub fn task_status(task: ScheduledTask) -> Option<TaskStatus> {
+    TASKS.with(|tasks| tasks.borrow().get(&task).map(|entry| entry.status(task)))
+}
+
+/// Whether `task` has an interval timer registered.
+pub fn is_registered(task: ScheduledTask) -> bool {
+    TASKS.with(|tasks| {
+        tasks
+            .borrow()
+            .get(&task)
+            .is_some_and(|entry| entry.interval.is_some())
+    })
+}
+
+/// Record `timer` as the interval timer of `task`, returning the timer it
+/// replaces.
+pub fn note_registered(
+    task: ScheduledTask,
+    timer: TimerId,
+    interval_secs: u64,
+    now_ns: u64,
+) -> Option<TimerId> {
+    with_entry(task, |entry| {
+        entry.next_interval_run_ns = Some(now_ns + interval_secs * NANOS_PER_SEC);
+        entry
+            .interval
+            .replace((timer, interval_secs))
+            .map(|(old, _)| old)
+    })
+}
+
+/// Record a one-shot run of `task` due at `due_ns`.
+pub fn note_scheduled(task: ScheduledTask, due_ns: u64) {
+    with_entry(task, |entry| entry.pending_runs_ns.push(due_ns));
+}
+
+/// A run in flight. Finish it with its outcome; dropping it unfinished
+/// records a trap.
+#[must_use]
+pub struct TaskRun {
+    task: ScheduledTask,
+    started_ns: u64,
+    finished: bool,
+}
+
+/// Start a run of `task` at `now_ns`: a tick of its interval timer, or the
+/// one-shot run due at `due_ns`.
+pub fn begin_run(task: ScheduledTask, now_ns: u64, due_ns: Option<u64>) -> TaskRun {
+    with_entry(task, |entry| {
+        match due_ns {
+            Some(due_ns) => {
+                if let Some(i) = entry.pending_runs_ns.iter().position(|d| *d == due_ns) {
+                    entry.pending_runs_ns.swap_remove(i);
+                }
+            }
+            None => {
+                if let Some((_, secs)) = entry.interval {
+                    entry.next_interval_run_ns = Some(now_ns + secs * NANOS_PER_SEC);
+                }
+            }
+        }
+        entry.last_run_ns = Some(now_ns);
+        entry.in_flight += 1;
+        entry.runs += 1;
+    });
+    TaskRun {
+        task,
+        started_ns: now_ns,
+        finished: false,
+    }
+}
+
+impl TaskRun {
+    pub fn finish(mut self, now_ns: u64, outcome: Result<(), String>) {
+        self.finished = true;
+        self.record(Some(now_ns), outcome);
+    }
+
+    fn record(&self, finished_ns: Option<u64>, outcome: Result<(), String>) {
+        with_entry(self.task, |entry| {
+            entry.in_flight = entry.in_flight.saturating_sub(1);
+            if finished_ns.is_some() {
+                entry.last_finished_ns = finished_ns;
+            }
+            if let Err(error) = outcome {
+                entry.errors += 1;
+                entry.last_error = Some(error);
+                entry.last_error_ns = Some(self.started_ns);
+            }
+        });
+    }
+}
+
+impl Drop for TaskRun {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.record(None, Err(TRAPPED.to_string()));
+        }
+    }
+}
+
+fn spawn_run<Fut>(run: TaskRun, job: Fut)
+where
+    Fut: Future<Output = Result<(), String>> + 'static,
+{
+    ic_cdk::spawn(async move {
+        let outcome = job.await;
+        run.finish(ic_cdk::api::time(), outcome);
+    });
+}
+
+/// Run `job` every `interval_secs`, replacing any timer `task` already has.
+pub fn set_interval<F, Fut>(task: ScheduledTask, interval_secs: u64, job: F)
+where
+    F: Fn() -> Fut + 'static,
+    Fut: Future<Output = Result<(), String>> + 'static,
+{
+    let timer = ic_cdk_timers::set_timer_interval(Duration::from_secs(interval_secs), move || {
+        spawn_run(begin_run(task, ic_cdk::api::time(), None), job())
+    });
+    if let Some(old) = note_registered(task, timer, interval_secs, ic_cdk::api::time()) {
+        ic_cdk_timers::clear_timer(old);
+    }
+}
+
+/// Run `job` once, `delay_secs` from now, as a run of `task`.
+pub fn schedule_once<F, Fut>(task: ScheduledTask, delay_secs: u64, job: F)
+where
+    F: FnOnce() -> Fut + 'static,
+    Fut: Future<Output = Result<(), String>> + 'static,
+{
+    let due_ns = ic_cdk::api::time() + delay_secs * NANOS_PER_SEC;
+    note_scheduled(task, due_ns);
+    ic_cdk_timers::set_timer(Duration::from_secs(delay_secs), move || {
+        spawn_run(begin_run(task, ic_cdk::api::time(), Some(due_ns)), job())
+    });
+}
+
+/// Process pending transfers `delay_secs` from now.
+pub fn kick_pending_transfers(delay_secs: u64) {
+    schedule_once(ScheduledTask::PendingTransfers, delay_secs, || async {
+        crate::process_pending_transfer().await;
+        Ok(())
+    });
+}
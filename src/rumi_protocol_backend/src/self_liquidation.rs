@@ -0,0 +1,245 @@
+//! Owner-initiated exit from an undercollateralized vault.
+//!
+//! Once a vault's collateral ratio falls below its collateral's minimum the
+//! owner can no longer withdraw, and short of finding icUSD to repay, the
+//! only way out is a liquidation that costs them the liquidation bonus.
+//! `self_liquidate_vault` closes such a vault instead: it sells just enough
+//! collateral to cover the debt, repays it, and returns the rest.
+//!
+//! The sale is a `repay_from_collateral` sale sized by the protocol rather
+//! than the owner. It goes through the collateral's whitelisted route, sells
+//! the debt's oracle worth plus `SELF_LIQUIDATION_SLIPPAGE_BPS`, and demands
+//! the full debt back from the DEX, so it either clears the debt or leaves
+//! the vault untouched. The sale and the close are recorded as the usual
+//! `vault_repaid_from_collateral` and withdraw-and-close events.
+//!
+//! A vault whose collateral is worth less than its debt at the oracle price
+//! cannot cover it by selling and is left to liquidation.
+
+use crate::guard::{trace_tag, GuardPrincipal, VaultLiquidationGuard};
+use crate::logs::INFO;
+use crate::numeric::ICUSD;
+use crate::repay_from_collateral::RepayFromCollateralPlan;
+use crate::state::{mutate_state, read_state, State};
+use crate::vault::require_vault_not_processing;
+use crate::vault_status::VaultOperation;
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use rust_decimal::Decimal;
+
+/// Collateral sold above the debt's oracle worth, to absorb DEX price
+/// impact and fees (1%). Whatever it fetches above the debt is minted back
+/// to the owner.
+pub const SELF_LIQUIDATION_SLIPPAGE_BPS: u64 = 100;
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct SelfLiquidationSuccess {
+    pub vault_id: u64,
+    /// Collateral taken off the vault for the sale, ledger fees included.
+    pub collateral_sold: u64,
+    /// Debt repaid.
+    pub icusd_repaid: u64,
+    /// Proceeds above the debt, minted back to the owner.
+    pub surplus_returned: u64,
+    /// Collateral left after the sale, sent back to the owner.
+    pub collateral_returned: u64,
+    pub collateral_return_block_index: Option<u64>,
+}
+
+/// Check a request and size the sale. Pure: callers accrue the vault's
+/// interest first.
+pub fn plan_self_liquidation(
+    state: &State,
+    caller: Principal,
+    vault_id: u64,
+    now_ns: u64,
+) -> Result<RepayFromCollateralPlan, ProtocolError> {
+    let generic = |msg: String| ProtocolError::GenericError(msg);
+    state.mode.check_available()?;
+    let vault = state
+        .vault_id_to_vaults
+        .get(&vault_id)
+        .ok_or_else(|| generic(format!("Vault #{} not found", vault_id)))?;
+    if vault.owner != caller {
+        return Err(ProtocolError::CallerNotOwner);
+    }
+    require_vault_not_processing(vault)?;
+    crate::vault_freeze::require_vault_not_frozen(state, vault_id, now_ns)?;
+    for op in [
+        VaultOperation::Repay,
+        VaultOperation::Withdraw,
+        VaultOperation::Close,
+    ] {
+        crate::vault_status::require_allows(state, vault_id, op)?;
+    }
+
+    let ct = vault.collateral_type;
+    let route = state
+        .auto_deleverage_routes
+        .get(&ct)
+        .cloned()
+        .ok_or_else(|| generic(format!("No whitelisted route to sell {} for icUSD", ct)))?;
+    let config = state
+        .get_collateral_config(&ct)
+        .ok_or_else(|| generic("Collateral type not configured.".to_string()))?;
+    if !config.status.allows_repay()
+        || !config.status.allows_withdraw()
+        || !config.status.allows_close()
+    {
+        return Err(generic(
+            "Self-liquidation is not allowed for this collateral type.".to_string(),
+        ));
+    }
+    if config.is_native_xrp() {
+        return Err(generic(
+            "Native XRP vaults cannot be self-liquidated.".to_string(),
+        ));
+    }
+    let price = state
+        .get_collateral_price_decimal(&ct)
+        .ok_or_else(|| generic("No price available for the collateral.".to_string()))?;
+
+    let debt = vault.borrowed_icusd_amount.to_u64();
+    if debt == 0 {
+        return Err(generic(format!(
+            "Vault #{} has no debt; close it with withdraw_and_close_vault",
+            vault_id
+        )));
+    }
+    let value =
+        crate::numeric::collateral_usd_value(vault.collateral_amount, price, config.decimals)
+            .to_u64();
+    let min_ratio = state.get_min_collateral_ratio_for(&ct);
+    if Decimal::from(value) >= Decimal::from(debt) * min_ratio.0 {
+        return Err(generic(format!(
+            "Vault #{} is above its minimum collateral ratio of {}; repay and close it instead",
+            vault_id, min_ratio
+        )));
+    }
+
+    // Approve, then transfer-from: both are charged to the protocol account.
+    let fees = config.ledger_fee.saturating_mul(2);
+    let sellable = vault.collateral_amount.saturating_sub(fees);
+    let sellable_value =
+        crate::numeric::collateral_usd_value(sellable, price, config.decimals).to_u64();
+    if sellable_value < debt {
+        return Err(generic(format!(
+            "Vault #{} is underwater: its collateral is worth {} icUSD e8s against {} of debt, so it is left to liquidation",
+            vault_id, sellable_value, debt
+        )));
+    }
+    let with_slippage =
+        (debt as u128 * (10_000 + SELF_LIQUIDATION_SLIPPAGE_BPS) as u128).div_ceil(10_000) as u64;
+    // One unit over the rounded-down conversion so the sale is never short
+    // of the debt's worth.
+    let amount_in = crate::numeric::icusd_to_collateral_amount(
+        ICUSD::new(with_slippage),
+        price,
+        config.decimals,
+    )
+    .saturating_add(1)
+    .min(sellable);
+
+    Ok(RepayFromCollateralPlan {
+        vault_id,
+        collateral_type: ct,
+        route,
+        debt,
+        amount_in,
+        allowance: amount_in.saturating_add(config.ledger_fee),
+        collateral_debit: amount_in.saturating_add(fees),
+        min_icusd_out: debt,
+    })
+}
+
+/// Sell enough of the vault's collateral to repay all of its debt, then
+/// close it and return what is left. Nothing changes unless the DEX
+/// delivers the whole debt.
+pub async fn self_liquidate_vault(vault_id: u64) -> Result<SelfLiquidationSuccess, ProtocolError> {
+    let caller = ic_cdk::caller();
+    let guard_principal =
+        GuardPrincipal::new(caller, &format!("self_liquidate_vault_{}", vault_id))?;
+    let _vault_op_guard = match VaultLiquidationGuard::new(vault_id) {
+        Ok(g) => g,
+        Err(e) => {
+            guard_principal.fail();
+            return Err(e);
+        }
+    };
+
+    let now = ic_cdk::api::time();
+    mutate_state(|s| s.accrue_single_vault(vault_id, now));
+    let plan = match read_state(|s| plan_self_liquidation(s, caller, vault_id, now)) {
+        Ok(plan) => plan,
+        Err(e) => {
+            guard_principal.fail();
+            return Err(e);
+        }
+    };
+
+    log!(
+        INFO,
+        "[self_liquidate_vault] trace={} vault #{}: selling {} of {} to repay {} icUSD e8s on {} ({})",
+        trace_tag(caller),
+        plan.vault_id,
+        plan.amount_in,
+        plan.collateral_type,
+        plan.debt,
+        plan.route.dex,
+        plan.route.pool_id
+    );
+
+    let amount_out = match crate::repay_from_collateral::sell(&plan, caller, now).await {
+        Ok(amount_out) => amount_out,
+        Err(e) => {
+            guard_principal.fail();
+            return Err(e);
+        }
+    };
+    let (icusd_repaid, surplus_returned) =
+        crate::repay_from_collateral::settle(&plan, caller, amount_out).await;
+
+    // The debt is cleared; what remains of the collateral goes back through
+    // the ordinary withdraw-and-close path.
+    let collateral_returned = read_state(|s| {
+        s.vault_id_to_vaults
+            .get(&vault_id)
+            .map(|v| v.collateral_amount)
+            .unwrap_or(0)
+    });
+    match crate::vault::withdraw_and_close_vault_internal(caller, vault_id).await {
+        Ok(collateral_return_block_index) => {
+            guard_principal.complete();
+            log!(
+                INFO,
+                "[self_liquidate_vault] trace={} vault #{} closed: repaid {} icUSD e8s from {} of {}, returned {}",
+                trace_tag(caller),
+                plan.vault_id,
+                icusd_repaid,
+                plan.amount_in,
+                plan.collateral_type,
+                collateral_returned
+            );
+            Ok(SelfLiquidationSuccess {
+                vault_id: plan.vault_id,
+                collateral_sold: plan.collateral_debit,
+                icusd_repaid,
+                surplus_returned,
+                collateral_returned,
+                collateral_return_block_index,
+            })
+        }
+        Err(e) => {
+            guard_principal.fail();
+            log!(
+                INFO,
+                "[self_liquidate_vault] trace={} Debt of vault #{} repaid but withdraw/close failed: {:?}. Vault is recoverable via withdraw_and_close_vault.",
+                trace_tag(caller),
+                vault_id,
+                e
+            );
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Startup self-test after an upgrade.
+//!
+//! A deployment can come up with a wrong ledger id in its upgrade args, an
+//! XRC canister that rejects its calls, a collateral whose price source no
+//! longer answers, or timers that failed to register, and nothing would
+//! notice until the first user call or price tick went wrong. Shortly after
+//! every upgrade (`SELFTEST_DELAY_SECS`, once the event replay is done) the
+//! canister checks, in order:
+//!
+//! - `Ledger`: an `icrc1_balance_of` query on the icUSD ledger, every
+//!   collateral ledger and every enabled reserve stable ledger.
+//! - `Xrc`: an ICP/USD rate from the exchange rate canister.
+//! - `CollateralPrice`: a fresh price for every collateral that is still
+//!   priced, fetched on demand as a price-sensitive operation would.
+//! - `Invariants`: `State::check_invariants` and the chain supply invariant.
+//! - `Timer`: every interval task `setup_timers` registers with the
+//!   `scheduler`, each collateral's price timer included.
+//!
+//! The result is kept as the `SelftestReport` that `get_selftest_report`
+//! returns. Any failed check also logs a CRITICAL line and records a
+//! `SelftestFailed` event naming the failures, so operators watching the
+//! log or the events see a broken deployment within minutes. The developer
+//! can re-run the test at any time with `run_selftest`.
+//!
+//! The report is heap-only and starts over on upgrade; the event log keeps
+//! the failures.
+
+use crate::event::Event;
+use crate::logs::{CRITICAL, INFO};
+use crate::scheduler::ScheduledTask;
+use crate::state::{read_state, State};
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+
+/// How long after an upgrade the self-test runs, so the immediate price
+/// fetches `setup_timers` schedules have had their turn.
+pub const SELFTEST_DELAY_SECS: u64 = 30;
+
+#[derive(
+    CandidType, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum SelftestCheckKind {
+    Ledger,
+    Xrc,
+    CollateralPrice,
+    Invariants,
+    Timer,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelftestCheck {
+    pub kind: SelftestCheckKind,
+    /// What was checked, e.g. `icUSD ledger` or `vault_check timer`.
+    pub name: String,
+    /// The ledger or collateral checked, where there is one.
+    pub target: Option<Principal>,
+    /// `None` when the check passed, else why it failed.
+    pub error: Option<String>,
+}
+
+impl SelftestCheck {
+    pub fn new(
+        kind: SelftestCheckKind,
+        name: impl Into<String>,
+        target: Option<Principal>,
+        outcome: Result<(), String>,
+    ) -> Self {
+        Self {
+            kind,
+            name: name.into(),
+            target,
+            error: outcome.err(),
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct SelftestReport {
+    pub started_at_ns: u64,
+    pub finished_at_ns: u64,
+    pub passed: bool,
+    pub checks: Vec<SelftestCheck>,
+}
+
+thread_local! {
+    static LAST_REPORT: RefCell<Option<SelftestReport>> = RefCell::new(None);
+    static RUNNING: Cell<bool> = Cell::new(false);
+}
+
+/// The most recent self-test, or `None` before the first one finishes.
+pub fn last_report() -> Option<SelftestReport> {
+    LAST_REPORT.with(|report| report.borrow().clone())
+}
+
+/// Every ledger the protocol holds funds on, labelled, each once.
+pub fn ledgers_to_check(state: &State) -> Vec<(String, Principal)> {
+    let mut ledgers = vec![("icUSD ledger".to_string(), state.icusd_ledger_principal)];
+    for collateral_type in state.collateral_configs.keys() {
+        ledgers.push(("collateral ledger".to_string(), *collateral_type));
+    }
+    for stable in crate::reserve_stables::enabled(state) {
+        ledgers.push((format!("{} ledger", stable.symbol), stable.ledger));
+    }
+    let mut seen = std::collections::BTreeSet::new();
+    ledgers.retain(|(_, ledger)| seen.insert(*ledger));
+    ledgers
+}
+
+/// The collaterals whose price is still consumed, ICP included.
+pub fn collaterals_to_price(state: &State) -> Vec<Principal> {
+    let icp = state.icp_collateral_type();
+    state
+        .collateral_configs
+        .keys()
+        .filter(|ct| **ct == icp || crate::xrc::should_fetch_collateral_price(state, ct))
+        .copied()
+        .collect()
+}
+
+pub fn invariant_checks(state: &State) -> Vec<SelftestCheck> {
+    let chain_debt_e8s = state.multi_chain.total_chain_vault_debt_e8s();
+    vec![
+        SelftestCheck::new(
+            SelftestCheckKind::Invariants,
+            "state invariants",
+            None,
+            state.check_invariants(),
+        ),
+        SelftestCheck::new(
+            SelftestCheckKind::Invariants,
+            "chain supply invariant",
+            None,
+            crate::chains::supply::check_invariant(&state.multi_chain, chain_debt_e8s)
+                .map_err(|e| format!("{:?}", e)),
+        ),
+    ]
+}
+
+pub fn timer_checks(state: &State) -> Vec<SelftestCheck> {
+    crate::scheduler::interval_tasks(state)
+        .into_iter()
+        .map(|task| {
+            let (name, target) = match task {
+                ScheduledTask::CollateralPriceFetch(ct) => {
+                    ("collateral price timer".to_string(), Some(ct))
+                }
+                task => (format!("{:?} timer", task), None),
+            };
+            let outcome = if crate::scheduler::is_registered(task) {
+                Ok(())
+            } else {
+                Err("not registered".to_string())
+            };
+            SelftestCheck::new(SelftestCheckKind::Timer, name, target, outcome)
+        })
+        .collect()
+}
+
+pub fn build_report(
+    started_at_ns: u64,
+    finished_at_ns: u64,
+    checks: Vec<SelftestCheck>,
+) -> SelftestReport {
+    SelftestReport {
+        started_at_ns,
+        finished_at_ns,
+        passed: checks.iter().all(SelftestCheck::passed),
+        checks,
+    }
+}
+
+async fn ledger_check(name: String, ledger: Principal) -> SelftestCheck {
+    let outcome = crate::management::get_token_balance(ledger)
+        .await
+        .map(|_| ());
+    SelftestCheck::new(SelftestCheckKind::Ledger, name, Some(ledger), outcome)
+}
+
+async fn xrc_check() -> SelftestCheck {
+    let outcome = match crate::management::fetch_icp_price().await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(format!("XRC rejected ICP/USD: {:?}", e)),
+        Err(e) => Err(e),
+    };
+    let xrc = read_state(|s| s.xrc_principal);
+    SelftestCheck::new(SelftestCheckKind::Xrc, "ICP/USD rate", Some(xrc), outcome)
+}
+
+async fn price_check(collateral_type: Principal) -> SelftestCheck {
+    let outcome = crate::xrc::ensure_fresh_price_for(&collateral_type)
+        .await
+        .map_err(|e| format!("{:?}", e));
+    SelftestCheck::new(
+        SelftestCheckKind::CollateralPrice,
+        "collateral price",
+        Some(collateral_type),
+        outcome,
+    )
+}
+
+/// Marks a run in flight; cleared on drop, a trapped callback included.
+struct RunGuard;
+
+impl RunGuard {
+    fn new() -> Result<Self, ProtocolError> {
+        if RUNNING.with(|running| running.replace(true)) {
+            return Err(ProtocolError::AlreadyProcessing);
+        }
+        Ok(RunGuard)
+    }
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        RUNNING.with(|running| running.set(false));
+    }
+}
+
+/// Run every check and keep the report. Refuses to start while another run
+/// is in flight.
+pub async fn run() -> Result<SelftestReport, ProtocolError> {
+    let _guard = RunGuard::new()?;
+    let started_at_ns = ic_cdk::api::time();
+    let mut checks = Vec::new();
+    for (name, ledger) in read_state(ledgers_to_check) {
+        checks.push(ledger_check(name, ledger).await);
+    }
+    checks.push(xrc_check().await);
+    for collateral_type in read_state(collaterals_to_price) {
+        checks.push(price_check(collateral_type).await);
+    }
+    checks.extend(read_state(invariant_checks));
+    checks.extend(read_state(timer_checks));
+    let report = build_report(started_at_ns, ic_cdk::api::time(), checks);
+
+    let failed: Vec<SelftestCheck> = report
+        .checks
+        .iter()
+        .filter(|check| !check.passed())
+        .cloned()
+        .collect();
+    if failed.is_empty() {
+        log!(INFO, "[selftest] all {} checks passed", report.checks.len());
+    } else {
+        for check in &failed {
+            log!(
+                CRITICAL,
+                "[selftest] {:?} check failed: {} {:?}: {}",
+                check.kind,
+                check.name,
+                check.target,
+                check.error.as_deref().unwrap_or_default()
+            );
+        }
+        crate::storage::record_event(&Event::SelftestFailed {
+            failed_checks: failed,
+            timestamp: report.finished_at_ns,
+        });
+    }
+    LAST_REPORT.with(|last| *last.borrow_mut() = Some(report.clone()));
+    Ok(report)
+}
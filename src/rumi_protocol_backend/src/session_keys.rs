@@ -0,0 +1,244 @@
+//! Scoped session keys for vault management.
+//!
+//! A vault owner can register a second principal (a hot wallet or a bot) as
+//! a session key that may act on all of the owner's vaults, but only for the
+//! scopes it was granted and only until it expires. Scopes are limited to
+//! risk-reducing operations: repaying debt and adding collateral. Borrowing,
+//! withdrawing and closing always require the owner key.
+//!
+//! A session key pays from its own balances (the icUSD for a repay and the
+//! collateral for a margin top-up are pulled from the session principal), so
+//! the owner key never has to sign anything after registration. An optional
+//! `repay_limit_e8s` caps the total icUSD the key may repay over its lifetime.
+//!
+//! The limit is reserved before the ledger `await` so concurrent repays
+//! cannot overrun it, and released if the transfer fails.
+
+use crate::state::State;
+use crate::vault::Vault;
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+/// Longest lifetime a session key may be registered for (30 days).
+pub const MAX_SESSION_KEY_DURATION_NS: u64 = 30 * 24 * 3600 * 1_000_000_000;
+
+/// Upper bound on live session keys per owner.
+pub const MAX_SESSION_KEYS_PER_OWNER: usize = 10;
+
+#[derive(
+    CandidType, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum SessionScope {
+    /// `repay_to_vault`, `partial_repay_to_vault`, `repay_to_vault_with_stable`.
+    Repay,
+    /// `add_margin_to_vault`.
+    AddMargin,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionKey {
+    pub session_principal: Principal,
+    pub owner: Principal,
+    pub scopes: Vec<SessionScope>,
+    pub created_at_ns: u64,
+    pub expires_at_ns: u64,
+    /// Lifetime cap on icUSD repaid through this key; `None` = uncapped.
+    pub repay_limit_e8s: Option<u64>,
+    /// icUSD repaid (or reserved by an in-flight repay) through this key.
+    pub repaid_e8s: u64,
+}
+
+impl SessionKey {
+    pub fn is_expired_at(&self, now_ns: u64) -> bool {
+        now_ns >= self.expires_at_ns
+    }
+
+    pub fn remaining_repay_e8s(&self) -> Option<u64> {
+        self.repay_limit_e8s
+            .map(|limit| limit.saturating_sub(self.repaid_e8s))
+    }
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisterSessionKeyArg {
+    pub session_principal: Principal,
+    pub scopes: Vec<SessionScope>,
+    pub expires_at_ns: u64,
+    pub repay_limit_e8s: Option<u64>,
+}
+
+pub fn validate_register_arg(
+    state: &State,
+    owner: Principal,
+    arg: &RegisterSessionKeyArg,
+    now_ns: u64,
+) -> Result<(), String> {
+    if arg.session_principal == owner {
+        return Err("A session key must differ from the owner principal".to_string());
+    }
+    if arg.session_principal == Principal::anonymous() {
+        return Err("The anonymous principal cannot be a session key".to_string());
+    }
+    if arg.scopes.is_empty() {
+        return Err("A session key needs at least one scope".to_string());
+    }
+    if arg.expires_at_ns <= now_ns {
+        return Err("Session key expiry must be in the future".to_string());
+    }
+    if arg.expires_at_ns - now_ns > MAX_SESSION_KEY_DURATION_NS {
+        return Err(format!(
+            "Session keys can last at most {} days",
+            MAX_SESSION_KEY_DURATION_NS / (24 * 3600 * 1_000_000_000)
+        ));
+    }
+    if arg.repay_limit_e8s == Some(0) {
+        return Err("repay_limit_e8s must be non-zero; omit it for no limit".to_string());
+    }
+    if let Some(existing) = state.session_keys.get(&arg.session_principal) {
+        if existing.owner != owner && !existing.is_expired_at(now_ns) {
+            return Err("This principal is already a session key for another owner".to_string());
+        }
+    }
+    let live_keys = state
+        .session_keys
+        .values()
+        .filter(|k| {
+            k.owner == owner
+                && k.session_principal != arg.session_principal
+                && !k.is_expired_at(now_ns)
+        })
+        .count();
+    if live_keys >= MAX_SESSION_KEYS_PER_OWNER {
+        return Err(format!(
+            "At most {} live session keys per owner",
+            MAX_SESSION_KEYS_PER_OWNER
+        ));
+    }
+    Ok(())
+}
+
+/// Register (or replace) a session key and prune the owner's expired keys.
+/// Replacing a key starts its repay allowance over.
+pub fn apply_register(
+    state: &mut State,
+    owner: Principal,
+    arg: RegisterSessionKeyArg,
+    now_ns: u64,
+) {
+    state
+        .session_keys
+        .retain(|_, k| !(k.owner == owner && k.is_expired_at(now_ns)));
+    let mut scopes = arg.scopes;
+    scopes.sort();
+    scopes.dedup();
+    state.session_keys.insert(
+        arg.session_principal,
+        SessionKey {
+            session_principal: arg.session_principal,
+            owner,
+            scopes,
+            created_at_ns: now_ns,
+            expires_at_ns: arg.expires_at_ns,
+            repay_limit_e8s: arg.repay_limit_e8s,
+            repaid_e8s: 0,
+        },
+    );
+}
+
+pub fn apply_revoke(
+    state: &mut State,
+    owner: Principal,
+    session_principal: Principal,
+) -> Result<(), String> {
+    match state.session_keys.get(&session_principal) {
+        Some(key) if key.owner == owner => {
+            state.session_keys.remove(&session_principal);
+            Ok(())
+        }
+        _ => Err(format!(
+            "No session key {} for this owner",
+            session_principal
+        )),
+    }
+}
+
+/// Check that `caller` may perform a `scope` operation on `vault`. The owner
+/// always may; anyone else needs a live session key from the owner carrying
+/// `scope`. Returns the session principal when access comes from a key.
+pub fn authorize_vault_op(
+    state: &State,
+    caller: Principal,
+    vault: &Vault,
+    scope: SessionScope,
+    now_ns: u64,
+) -> Result<Option<Principal>, ProtocolError> {
+    if caller == vault.owner {
+        return Ok(None);
+    }
+    let key = match state.session_keys.get(&caller) {
+        Some(key) if key.owner == vault.owner => key,
+        _ => return Err(ProtocolError::CallerNotOwner),
+    };
+    if key.is_expired_at(now_ns) {
+        return Err(ProtocolError::GenericError(
+            "Session key has expired".to_string(),
+        ));
+    }
+    if !key.scopes.contains(&scope) {
+        return Err(ProtocolError::GenericError(format!(
+            "Session key is not authorized for {:?}",
+            scope
+        )));
+    }
+    Ok(Some(caller))
+}
+
+/// Reserve `amount_e8s` of a session key's repay limit. Must be followed by
+/// either `event::record_session_key_used` or `release_repay_allowance`.
+pub fn reserve_repay_allowance(
+    state: &mut State,
+    session_principal: Principal,
+    amount_e8s: u64,
+) -> Result<(), ProtocolError> {
+    let key = state
+        .session_keys
+        .get_mut(&session_principal)
+        .ok_or(ProtocolError::CallerNotOwner)?;
+    if let Some(remaining) = key.remaining_repay_e8s() {
+        if amount_e8s > remaining {
+            return Err(ProtocolError::GenericError(format!(
+                "Session key repay limit exceeded: {} e8s requested, {} e8s left",
+                amount_e8s, remaining
+            )));
+        }
+    }
+    key.repaid_e8s = key.repaid_e8s.saturating_add(amount_e8s);
+    Ok(())
+}
+
+/// Undo a reservation whose repay failed. A key revoked in the meantime is
+/// simply gone.
+pub fn release_repay_allowance(state: &mut State, session_principal: Principal, amount_e8s: u64) {
+    if let Some(key) = state.session_keys.get_mut(&session_principal) {
+        key.repaid_e8s = key.repaid_e8s.saturating_sub(amount_e8s);
+    }
+}
+
+/// Book a completed session-key operation. `from_reservation` is true on the
+/// live path, where `reserve_repay_allowance` already counted the repay;
+/// replay has no reservation step and counts it here.
+pub fn apply_session_use(
+    state: &mut State,
+    session_principal: Principal,
+    scope: SessionScope,
+    amount_e8s: u64,
+    from_reservation: bool,
+) {
+    if scope != SessionScope::Repay || from_reservation {
+        return;
+    }
+    if let Some(key) = state.session_keys.get_mut(&session_principal) {
+        key.repaid_e8s = key.repaid_e8s.saturating_add(amount_e8s);
+    }
+}
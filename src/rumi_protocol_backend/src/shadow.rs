@@ -0,0 +1,178 @@
+//! Shadow mode for the protocol's safety logic.
+//!
+//! Each part of `ShadowConfig` puts one piece of safety logic in shadow
+//! mode: it still runs on live traffic and decides, but instead of acting
+//! it records what it would have done in `shadow_decisions`, read with
+//! `get_shadow_decisions`. This lets new thresholds and triggers be checked
+//! against mainnet traffic before they are enforced.
+//!
+//! - `mode_machine`: automatic mode transitions (collateral ratio,
+//!   insolvency, deficit threshold, supply invariant, oracle recovery).
+//! - `circuit_breakers`: the oracle, price-floor and price-deviation
+//!   breakers (the sample a deviation would reject is applied) and the
+//!   mass-liquidation breaker.
+//! - `recovery_routing`: Recovery-mode reservation of vaults for the
+//!   stability pool (`pool_priority`).
+//!
+//! Admin overrides and upgrades are never shadowed. The config is an event
+//! and replays, since replay re-derives some transitions; the decisions
+//! live in the state snapshot only, newest `MAX_SHADOW_DECISIONS` kept.
+
+use crate::logs::INFO;
+use crate::mode::{Mode, ModeTransitionReason};
+use crate::state::State;
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use serde::Serialize;
+
+/// Decisions kept for `get_shadow_decisions`.
+pub const MAX_SHADOW_DECISIONS: usize = 500;
+
+#[derive(CandidType, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShadowConfig {
+    pub mode_machine: bool,
+    pub circuit_breakers: bool,
+    pub recovery_routing: bool,
+}
+
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShadowComponent {
+    ModeMachine,
+    CircuitBreakers,
+    RecoveryRouting,
+}
+
+/// What the shadowed logic would have done.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShadowAction {
+    ModeTransition {
+        from: Mode,
+        to: Mode,
+        reason: ModeTransitionReason,
+    },
+    TripLiquidationBreaker {
+        windowed_total_e8s: u64,
+        ceiling_e8s: u64,
+    },
+    RejectPrice {
+        collateral_type: Principal,
+        price: String,
+        last_price: String,
+        deviation_bps: u64,
+    },
+    ReserveForPool {
+        to_pool: Vec<u64>,
+        overflow: Vec<u64>,
+    },
+}
+
+impl ShadowAction {
+    pub fn component(&self) -> ShadowComponent {
+        match self {
+            ShadowAction::ModeTransition { reason, .. } => {
+                reason_component(*reason).unwrap_or(ShadowComponent::ModeMachine)
+            }
+            ShadowAction::TripLiquidationBreaker { .. } | ShadowAction::RejectPrice { .. } => {
+                ShadowComponent::CircuitBreakers
+            }
+            ShadowAction::ReserveForPool { .. } => ShadowComponent::RecoveryRouting,
+        }
+    }
+
+    /// Whether `self` repeats `previous`. A breaker trip repeats while it
+    /// would have stayed latched.
+    fn repeats(&self, previous: &ShadowAction) -> bool {
+        match (self, previous) {
+            (
+                ShadowAction::TripLiquidationBreaker { .. },
+                ShadowAction::TripLiquidationBreaker { .. },
+            ) => true,
+            (ShadowAction::RejectPrice { .. }, _) => false,
+            _ => self == previous,
+        }
+    }
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShadowDecision {
+    pub component: ShadowComponent,
+    pub action: ShadowAction,
+    pub timestamp: u64,
+}
+
+/// The component a mode transition for `reason` belongs to; `None` for
+/// triggers that are never shadowed.
+fn reason_component(reason: ModeTransitionReason) -> Option<ShadowComponent> {
+    match reason {
+        ModeTransitionReason::CollateralRatio
+        | ModeTransitionReason::Insolvency
+        | ModeTransitionReason::DeficitThreshold
+        | ModeTransitionReason::SupplyInvariantHalt
+        | ModeTransitionReason::OracleRecovered => Some(ShadowComponent::ModeMachine),
+        ModeTransitionReason::OracleCircuitBreaker
+        | ModeTransitionReason::PriceFloor
+        | ModeTransitionReason::PriceDeviation => Some(ShadowComponent::CircuitBreakers),
+        ModeTransitionReason::AdminOverride | ModeTransitionReason::Upgrade => None,
+    }
+}
+
+pub fn is_shadowed(state: &State, component: ShadowComponent) -> bool {
+    let config = state.shadow_config;
+    match component {
+        ShadowComponent::ModeMachine => config.mode_machine,
+        ShadowComponent::CircuitBreakers => config.circuit_breakers,
+        ShadowComponent::RecoveryRouting => config.recovery_routing,
+    }
+}
+
+/// Whether a mode transition for `reason` is shadowed.
+pub fn shadows_transition(state: &State, reason: ModeTransitionReason) -> bool {
+    reason_component(reason).is_some_and(|component| is_shadowed(state, component))
+}
+
+/// Buffer `action` until the next `flush` stamps it, unless it repeats the
+/// newest decision of its component.
+pub fn note(state: &mut State, action: ShadowAction) {
+    let component = action.component();
+    let newest = state
+        .pending_shadow_actions
+        .iter()
+        .rev()
+        .chain(state.shadow_decisions.iter().rev().map(|d| &d.action))
+        .find(|a| a.component() == component);
+    if newest.is_some_and(|newest| action.repeats(newest)) {
+        return;
+    }
+    state.pending_shadow_actions.push(action);
+}
+
+/// Move the buffered actions into `shadow_decisions` at `now`.
+pub fn flush(state: &mut State, now: u64) {
+    for action in std::mem::take(&mut state.pending_shadow_actions) {
+        log!(INFO, "[shadow] would have acted: {:?}", action);
+        state.shadow_decisions.push_back(ShadowDecision {
+            component: action.component(),
+            action,
+            timestamp: now,
+        });
+        while state.shadow_decisions.len() > MAX_SHADOW_DECISIONS {
+            state.shadow_decisions.pop_front();
+        }
+    }
+}
+
+/// Up to `limit` decisions, newest first, optionally of one component.
+pub fn decisions(
+    state: &State,
+    component: Option<ShadowComponent>,
+    limit: usize,
+) -> Vec<ShadowDecision> {
+    state
+        .shadow_decisions
+        .iter()
+        .rev()
+        .filter(|d| component.map_or(true, |c| d.component == c))
+        .take(limit)
+        .cloned()
+        .collect()
+}
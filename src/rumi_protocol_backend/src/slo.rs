@@ -0,0 +1,339 @@
+//! Per-endpoint service-level objectives.
+//!
+//! Every user-facing update endpoint runs under an `EndpointCall`, which
+//! counts the call and its outcome and measures it: instructions across the
+//! whole call context, awaits included (`performance_counter(1)`), and wall
+//! time from the request to its reply. A failure is a service error when the
+//! protocol or one of its dependencies caused it (a ledger transfer, an XRC
+//! price fetch, a paused queue) rather than the request itself.
+//!
+//! Each endpoint is judged against its `SloThresholds`: the developer's
+//! override for it, else the configured default, else
+//! `DEFAULT_SLO_THRESHOLDS`. A call over the latency or instruction bound,
+//! or a window of `SLO_WINDOW_NANOS` whose service-error rate passes the
+//! bound, is a breach. Breaches are counted, logged and recorded as
+//! `SloBreach` events, at most once per endpoint, kind and window so a
+//! degraded ledger does not flood the event log.
+//!
+//! Stats are heap-only metrics and start over on upgrade; the thresholds
+//! replay from `SetSloThresholds`.
+
+use crate::logs::INFO;
+use crate::state::{read_state, State};
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize};
+use ic_canister_log::log;
+use icrc_ledger_types::icrc2::transfer_from::TransferFromError;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+/// Length of the window a service-error rate is judged over (1 hour).
+pub const SLO_WINDOW_NANOS: u64 = 60 * 60 * 1_000_000_000;
+
+/// Applied to an endpoint with no override when no default is configured.
+pub const DEFAULT_SLO_THRESHOLDS: SloThresholds = SloThresholds {
+    max_latency_ms: 30_000,
+    max_instructions: 20_000_000_000,
+    max_error_rate_bps: 500,
+    min_calls: 20,
+};
+
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SloThresholds {
+    /// Wall time from request to reply. 0 disables the check.
+    pub max_latency_ms: u64,
+    /// Instructions across the call's awaits. 0 disables the check.
+    pub max_instructions: u64,
+    /// Service errors per 10,000 calls in a window. 0 disables the check.
+    pub max_error_rate_bps: u64,
+    /// Calls a window needs before its error rate is judged.
+    pub min_calls: u64,
+}
+
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SloConfig {
+    /// Replaces `DEFAULT_SLO_THRESHOLDS` for endpoints without an override.
+    pub default: Option<SloThresholds>,
+    pub endpoints: BTreeMap<String, SloThresholds>,
+}
+
+#[derive(
+    CandidType, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum SloBreachKind {
+    Latency,
+    Instructions,
+    ErrorRate,
+}
+
+/// What one call cost and how it ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CallOutcome {
+    pub latency_ns: u64,
+    pub instructions: u64,
+    /// `None` on success; `Some(true)` for a service error.
+    pub error: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SloBreach {
+    pub endpoint: String,
+    pub kind: SloBreachKind,
+    /// Milliseconds, instructions or basis points, as the threshold.
+    pub observed: u64,
+    pub threshold: u64,
+}
+
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct EndpointStats {
+    pub calls: u64,
+    pub errors: u64,
+    /// Errors counted against the error-rate objective.
+    pub service_errors: u64,
+    pub total_instructions: u64,
+    pub max_instructions: u64,
+    pub total_latency_ns: u64,
+    pub max_latency_ns: u64,
+    pub breaches: u64,
+    pub window_start_ns: u64,
+    pub window_calls: u64,
+    pub window_service_errors: u64,
+    /// Breach kinds already recorded as events this window.
+    pub window_reported: Vec<SloBreachKind>,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct EndpointSlo {
+    pub endpoint: String,
+    pub thresholds: SloThresholds,
+    pub stats: EndpointStats,
+}
+
+/// Per-endpoint series exported on `/metrics`: name, help and value.
+#[allow(clippy::type_complexity)]
+pub const METRICS: [(&str, &str, fn(&EndpointStats) -> u64); 6] = [
+    ("rumi_endpoint_calls", "Calls per tracked endpoint.", |s| {
+        s.calls
+    }),
+    (
+        "rumi_endpoint_errors",
+        "Error replies per tracked endpoint.",
+        |s| s.errors,
+    ),
+    (
+        "rumi_endpoint_service_errors",
+        "Errors counted against the endpoint's error-rate objective.",
+        |s| s.service_errors,
+    ),
+    (
+        "rumi_endpoint_slo_breaches",
+        "SLO breaches per tracked endpoint.",
+        |s| s.breaches,
+    ),
+    (
+        "rumi_endpoint_max_latency_ms",
+        "Slowest call per tracked endpoint, request to reply.",
+        |s| s.max_latency_ns / 1_000_000,
+    ),
+    (
+        "rumi_endpoint_max_instructions",
+        "Most instructions a call per tracked endpoint took.",
+        |s| s.max_instructions,
+    ),
+];
+
+thread_local! {
+    static ENDPOINT_STATS: RefCell<BTreeMap<String, EndpointStats>> =
+        RefCell::new(BTreeMap::new());
+}
+
+pub fn validate_thresholds(thresholds: &SloThresholds) -> Result<(), String> {
+    if thresholds.max_error_rate_bps > 10_000 {
+        return Err("max_error_rate_bps must be at most 10000".to_string());
+    }
+    Ok(())
+}
+
+/// Set (`Some`) or clear (`None`) the thresholds of `endpoint`, or the
+/// default when `endpoint` is `None`.
+pub fn apply_set_thresholds(
+    state: &mut State,
+    endpoint: Option<String>,
+    thresholds: Option<SloThresholds>,
+) {
+    match (endpoint, thresholds) {
+        (None, thresholds) => state.slo_config.default = thresholds,
+        (Some(endpoint), Some(thresholds)) => {
+            state.slo_config.endpoints.insert(endpoint, thresholds);
+        }
+        (Some(endpoint), None) => {
+            state.slo_config.endpoints.remove(&endpoint);
+        }
+    }
+}
+
+pub fn thresholds_for(state: &State, endpoint: &str) -> SloThresholds {
+    state
+        .slo_config
+        .endpoints
+        .get(endpoint)
+        .copied()
+        .or(state.slo_config.default)
+        .unwrap_or(DEFAULT_SLO_THRESHOLDS)
+}
+
+/// Whether `error` counts against the error-rate objective: the protocol,
+/// a ledger or the XRC failed, as opposed to the request being refused.
+pub fn is_service_error(error: &ProtocolError) -> bool {
+    match error {
+        ProtocolError::TemporarilyUnavailable(_)
+        | ProtocolError::TransferError(_)
+        | ProtocolError::SupplyInvariantHalted => true,
+        ProtocolError::TransferFromError(error, _) => matches!(
+            error,
+            TransferFromError::TemporarilyUnavailable | TransferFromError::GenericError { .. }
+        ),
+        _ => false,
+    }
+}
+
+/// Count `outcome` against `endpoint` and return the breaches to report:
+/// those not yet reported in the endpoint's current window.
+pub fn record_call(
+    thresholds: &SloThresholds,
+    endpoint: &str,
+    outcome: CallOutcome,
+    now_ns: u64,
+) -> Vec<SloBreach> {
+    ENDPOINT_STATS.with(|stats| {
+        let mut stats = stats.borrow_mut();
+        let stats = stats.entry(endpoint.to_string()).or_default();
+        if now_ns.saturating_sub(stats.window_start_ns) >= SLO_WINDOW_NANOS {
+            stats.window_start_ns = now_ns;
+            stats.window_calls = 0;
+            stats.window_service_errors = 0;
+            stats.window_reported.clear();
+        }
+
+        stats.calls += 1;
+        stats.window_calls += 1;
+        if let Some(service) = outcome.error {
+            stats.errors += 1;
+            if service {
+                stats.service_errors += 1;
+                stats.window_service_errors += 1;
+            }
+        }
+        stats.total_instructions = stats
+            .total_instructions
+            .saturating_add(outcome.instructions);
+        stats.max_instructions = stats.max_instructions.max(outcome.instructions);
+        stats.total_latency_ns = stats.total_latency_ns.saturating_add(outcome.latency_ns);
+        stats.max_latency_ns = stats.max_latency_ns.max(outcome.latency_ns);
+
+        let latency_ms = outcome.latency_ns / 1_000_000;
+        let error_rate_bps = stats.window_service_errors * 10_000 / stats.window_calls;
+        let checks = [
+            (
+                SloBreachKind::Latency,
+                latency_ms,
+                thresholds.max_latency_ms,
+                true,
+            ),
+            (
+                SloBreachKind::Instructions,
+                outcome.instructions,
+                thresholds.max_instructions,
+                true,
+            ),
+            (
+                SloBreachKind::ErrorRate,
+                error_rate_bps,
+                thresholds.max_error_rate_bps,
+                stats.window_calls >= thresholds.min_calls,
+            ),
+        ];
+        let mut breaches = vec![];
+        for (kind, observed, threshold, judged) in checks {
+            if threshold == 0 || !judged || observed <= threshold {
+                continue;
+            }
+            stats.breaches += 1;
+            if stats.window_reported.contains(&kind) {
+                continue;
+            }
+            stats.window_reported.push(kind);
+            breaches.push(SloBreach {
+                endpoint: endpoint.to_string(),
+                kind,
+                observed,
+                threshold,
+            });
+        }
+        breaches
+    })
+}
+
+pub fn endpoint_stats() -> BTreeMap<String, EndpointStats> {
+    ENDPOINT_STATS.with(|stats| stats.borrow().clone())
+}
+
+/// Every endpoint with an override or a recorded call, with its thresholds
+/// and stats.
+pub fn status(state: &State) -> Vec<EndpointSlo> {
+    let mut stats = endpoint_stats();
+    for endpoint in state.slo_config.endpoints.keys() {
+        stats.entry(endpoint.clone()).or_default();
+    }
+    stats
+        .into_iter()
+        .map(|(endpoint, stats)| EndpointSlo {
+            thresholds: thresholds_for(state, &endpoint),
+            endpoint,
+            stats,
+        })
+        .collect()
+}
+
+/// One call of a tracked endpoint, from request to reply.
+#[must_use]
+pub struct EndpointCall {
+    endpoint: &'static str,
+    started_at: u64,
+}
+
+impl EndpointCall {
+    pub fn start(endpoint: &'static str) -> Self {
+        Self {
+            endpoint,
+            started_at: ic_cdk::api::time(),
+        }
+    }
+
+    /// Record the call's outcome and report any new breach.
+    pub fn finish<T>(self, result: &Result<T, ProtocolError>) {
+        let now = ic_cdk::api::time();
+        let outcome = CallOutcome {
+            latency_ns: now.saturating_sub(self.started_at),
+            instructions: ic_cdk::api::performance_counter(1),
+            error: result.as_ref().err().map(is_service_error),
+        };
+        let thresholds = read_state(|s| thresholds_for(s, self.endpoint));
+        for breach in record_call(&thresholds, self.endpoint, outcome, now) {
+            log!(
+                INFO,
+                "[slo] {} breached its {:?} objective: {} over {}",
+                breach.endpoint,
+                breach.kind,
+                breach.observed,
+                breach.threshold
+            );
+            // Calls rejected while the event log is sealed (bootstrapping)
+            // are counted but not recorded.
+            if !crate::storage::is_event_log_sealed() {
+                crate::event::record_slo_breach(breach, now);
+            }
+        }
+    }
+}
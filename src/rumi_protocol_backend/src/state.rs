@@ -14,9 +14,12 @@ use serde::Serialize;
 use std::cell::RefCell;
 use std::collections::btree_map::Entry::{Occupied, Vacant};
 use std::collections::{BTreeMap, BTreeSet};
-use std::fmt;
 use std::ops::Bound;
 
+pub use crate::mode::{
+    is_legal_mode_transition, Mode, ModeTransition, ModeTransitionError, ModeTransitionReason,
+};
+
 // Like assert_eq, but returns an error instead of panicking.
 macro_rules! ensure_eq {
     ($lhs:expr, $rhs:expr, $msg:expr $(, $args:expr)* $(,)*) => {
@@ -58,6 +61,12 @@ pub const DEFAULT_INTEREST_POOL_SHARE: Ratio = Ratio::new(dec!(0.75)); // 75% to
 /// headroom for future high-risk-tier configurations while preventing the
 /// `amount - fee` underflow that would trap every borrow.
 pub const MAX_BORROWING_FEE_MULTIPLIER: Ratio = Ratio::new(dec!(20));
+/// Upper bound on a collateral's price confidence multiple `k`. Five
+/// standard deviations already discounts well past any spread XRC reports
+/// for a liquid asset.
+pub const MAX_PRICE_CONFIDENCE_K: f64 = 5.0;
+/// Upper bound on a collateral's annual maintenance fee (5%).
+pub const MAX_MAINTENANCE_FEE_APR: f64 = 0.05;
 
 /// Where a share of interest revenue is routed.
 #[derive(candid::CandidType, Clone, Debug, PartialEq, Eq, serde::Deserialize, Serialize)]
@@ -162,6 +171,12 @@ pub const DUST_DEBT_THRESHOLD: u64 = 50_000; // 0.0005 icUSD — debt below this
 pub const MAX_SP_CHAIN_ABSORB_RESULTS_BY_PROOF: usize = 10_000;
 pub const MAX_SP_XRP_ABSORB_RESULTS_BY_PROOF: usize = 10_000;
 
+/// Failed treasury deposit notifications held for retry.
+pub const MAX_PENDING_TREASURY_DEPOSITS: usize = 1_000;
+/// Retries of one treasury deposit notification before it is given up.
+/// The treasury tick runs every few minutes, so this spans several hours.
+pub const MAX_TREASURY_DEPOSIT_ATTEMPTS: u32 = 100;
+
 #[derive(candid::CandidType, Clone, Debug, PartialEq, Eq, serde::Deserialize, Serialize)]
 pub struct StoredChainSpAbsorbResult {
     pub caller: Principal,
@@ -407,6 +422,81 @@ pub enum PriceSource {
         /// Conservative discount applied to redemption value (e.g., 0.15 = 15%)
         haircut: f64,
     },
+    /// Median of several independent feeds, all fetched on every refresh.
+    /// One stale or manipulated feed cannot move the published price on its
+    /// own; it has to be joined by enough others to shift the middle.
+    Median {
+        feeds: Vec<PriceFeed>,
+        /// Fewest feeds that must answer for a price to be published.
+        min_feeds: u32,
+    },
+}
+
+/// Most feeds a `PriceSource::Median` may list. Every feed is a call (XRC
+/// feeds cost 1B cycles each) on every refresh of the collateral.
+pub const MAX_MEDIAN_PRICE_FEEDS: usize = 7;
+
+/// One input of a `PriceSource::Median`.
+#[derive(candid::CandidType, Clone, Debug, PartialEq, Eq, serde::Deserialize, Serialize)]
+pub enum PriceFeed {
+    /// An XRC asset pair, subject to the collateral's XRC source floor.
+    Xrc {
+        base_asset: String,
+        #[serde(default)]
+        base_asset_class: XrcAssetClass,
+        quote_asset: String,
+        #[serde(default = "default_fiat")]
+        quote_asset_class: XrcAssetClass,
+    },
+    /// A DEX TWAP or pyth-style oracle canister that answers
+    /// `method(collateral_type)` with a `SecondaryPriceQuote`.
+    Canister {
+        canister_id: Principal,
+        method: String,
+    },
+    /// CoinGecko HTTPS outcall.
+    CoinGecko {
+        coin_id: String,
+        vs_currency: String,
+    },
+}
+
+/// Reject a price source the fetch path cannot serve. Only `Median` has
+/// anything to check today.
+pub fn validate_price_source(source: &PriceSource) -> Result<(), String> {
+    let PriceSource::Median { feeds, min_feeds } = source else {
+        return Ok(());
+    };
+    if feeds.is_empty() || feeds.len() > MAX_MEDIAN_PRICE_FEEDS {
+        return Err(format!(
+            "A median price source needs between 1 and {} feeds",
+            MAX_MEDIAN_PRICE_FEEDS
+        ));
+    }
+    if *min_feeds == 0 || *min_feeds as usize > feeds.len() {
+        return Err(format!(
+            "min_feeds must be between 1 and the number of feeds ({})",
+            feeds.len()
+        ));
+    }
+    for feed in feeds {
+        let empty = match feed {
+            PriceFeed::Xrc {
+                base_asset,
+                quote_asset,
+                ..
+            } => base_asset.is_empty() || quote_asset.is_empty(),
+            PriceFeed::Canister { method, .. } => method.is_empty(),
+            PriceFeed::CoinGecko {
+                coin_id,
+                vs_currency,
+            } => coin_id.is_empty() || vs_currency.is_empty(),
+        };
+        if empty {
+            return Err(format!("Incomplete price feed: {:?}", feed));
+        }
+    }
+    Ok(())
 }
 
 impl PartialEq for PriceSource {
@@ -464,6 +554,16 @@ impl PartialEq for PriceSource {
                     vs_currency: v2,
                 },
             ) => c1 == c2 && v1 == v2,
+            (
+                PriceSource::Median {
+                    feeds: f1,
+                    min_feeds: m1,
+                },
+                PriceSource::Median {
+                    feeds: f2,
+                    min_feeds: m2,
+                },
+            ) => f1 == f2 && m1 == m2,
             _ => false,
         }
     }
@@ -685,6 +785,37 @@ pub struct CollateralConfig {
     /// snapshot missing this field decodes cleanly to `None`.
     #[serde(default)]
     pub symbol: Option<String>,
+    /// What a full liquidation does with the collateral left over for the
+    /// vault owner. Absent in legacy snapshots, which decode to the original
+    /// `CollateralPayout`.
+    #[serde(default)]
+    pub liquidation_rebate_mode: LiquidationRebateMode,
+    /// Oracle every XRC price for this collateral is cross-checked against
+    /// before it is applied. `None` (the legacy default) applies XRC prices
+    /// unchecked.
+    #[serde(default)]
+    pub secondary_price_source: Option<SecondaryPriceSource>,
+    /// Set when XRC and the secondary source diverged beyond the configured
+    /// band. While set, `last_price` is frozen at the last undisputed value;
+    /// cleared once the two sources agree again or by the developer.
+    #[serde(default)]
+    pub price_disputed: bool,
+    /// XRC standard deviation across sources of the last applied price, in
+    /// USD per whole token. `None` until a price arrives from XRC (and for
+    /// non-XRC price sources, which report no spread).
+    #[serde(default)]
+    pub last_price_std_dev: Option<f64>,
+    /// Standard deviations subtracted from the mid-price for borrow and
+    /// withdrawal checks (`borrow_check_price`). Liquidations keep using the
+    /// mid-price. `None` checks borrows at the mid-price too.
+    #[serde(default)]
+    pub price_confidence_k: Option<Ratio>,
+    /// Annual fee on the USD value of an open vault's collateral (e.g.
+    /// 0.005 = 0.5%), charged whether or not the vault borrows. Interest
+    /// accrual adds it to the vault's debt alongside interest. `None` charges
+    /// nothing.
+    #[serde(default)]
+    pub maintenance_fee_apr: Option<Ratio>,
 }
 
 /// How a collateral's underlying asset is custodied. `IcrcLedger` (the legacy /
@@ -696,6 +827,34 @@ pub enum CustodyKind {
     NativeXrp,
 }
 
+/// Where the owner's leftover collateral from a full liquidation goes. See
+/// `CollateralConfig::liquidation_rebate_mode`.
+#[derive(
+    candid::CandidType, Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, Serialize,
+)]
+pub enum LiquidationRebateMode {
+    /// Transfer it back to the owner.
+    #[default]
+    CollateralPayout,
+    /// Credit it as collateral to the owner's lowest-CR other vault of the
+    /// same collateral that still carries debt. The collateral never leaves
+    /// custody and no debt is forgiven, so icUSD supply still matches vault
+    /// debt. Paid out as usual when the owner has no such vault.
+    CollateralTopUp,
+}
+
+/// Secondary price oracle for `CollateralConfig::secondary_price_source`: a
+/// DEX TWAP or another oracle canister that answers `method(collateral_type)`
+/// with a `SecondaryPriceQuote`.
+#[derive(candid::CandidType, Clone, Debug, PartialEq, Eq, serde::Deserialize, Serialize)]
+pub struct SecondaryPriceSource {
+    pub canister_id: Principal,
+    pub method: String,
+    /// Largest tolerated divergence between the XRC and secondary prices, in
+    /// basis points of the secondary price.
+    pub max_divergence_bps: u64,
+}
+
 /// P3: a native-XRP vault awaiting its on-chain deposit. Created by
 /// `open_xrp_vault` (no collateral credited, no icUSD minted) and removed by
 /// `confirm_xrp_deposit` once the deposit to `custody_address` is verified and a
@@ -718,14 +877,7 @@ pub struct XrpPendingDeposit {
     pub reserve_base_drops: u64,
 }
 
-/// Synthetic `collateral_type` / `CollateralConfig` map key for native XRP. XRP has
-/// no IC ledger, so this reserved principal is only an opaque key (15 bytes — not a
-/// valid 10-byte canister id, so it cannot collide with a real canister). The XRP
-/// `CollateralConfig` (registered in P5) is keyed by this; its `custody_kind` is
-/// `NativeXrp` and its `ledger_canister_id` is this same synthetic value.
-pub fn xrp_collateral_principal() -> Principal {
-    Principal::from_slice(b"rumi-xrp-native")
-}
+pub use rumi_common::chains::xrp_collateral_principal;
 
 /// P5: the `CollateralConfig` for native-XRP collateral. Parameters (Rob's):
 /// 150% borrow threshold / 133% liquidation / 12% liquidation penalty / 2,500 icUSD
@@ -779,6 +931,12 @@ pub fn xrp_collateral_config(
         min_xrc_sources: None,
         custody_kind: Some(CustodyKind::NativeXrp),
         symbol: Some("XRP".to_string()),
+        liquidation_rebate_mode: LiquidationRebateMode::CollateralPayout,
+        secondary_price_source: None,
+        price_disputed: false,
+        last_price_std_dev: None,
+        price_confidence_k: None,
+        maintenance_fee_apr: None,
     }
 }
 
@@ -911,6 +1069,20 @@ impl CollateralConfig {
     pub fn is_native_xrp(&self) -> bool {
         self.custody() == CustodyKind::NativeXrp
     }
+
+    /// Price for borrow and withdrawal checks: the conservative bound
+    /// `last_price - k·σ` when a confidence multiple is configured and XRC
+    /// reported a spread, floored at zero; otherwise the mid-price.
+    pub fn borrow_check_price(&self) -> Option<f64> {
+        let mid = self.last_price?;
+        let k = self.price_confidence_k.and_then(|k| k.0.to_f64());
+        match (k, self.last_price_std_dev) {
+            (Some(k), Some(sigma)) if k > 0.0 && sigma.is_finite() && sigma > 0.0 => {
+                Some((mid - k * sigma).max(0.0))
+            }
+            _ => Some(mid),
+        }
+    }
 }
 
 fn default_redemption_tier() -> u8 {
@@ -948,57 +1120,18 @@ impl PartialEq for CollateralConfig {
             && self.min_xrc_sources == other.min_xrc_sources
             && self.custody_kind == other.custody_kind
             && self.symbol == other.symbol
+            && self.liquidation_rebate_mode == other.liquidation_rebate_mode
+            && self.secondary_price_source == other.secondary_price_source
+            && self.price_disputed == other.price_disputed
+            && self.last_price_std_dev.map(f64::to_bits)
+                == other.last_price_std_dev.map(f64::to_bits)
+            && self.price_confidence_k == other.price_confidence_k
+            && self.maintenance_fee_apr == other.maintenance_fee_apr
     }
 }
 
 impl Eq for CollateralConfig {}
 
-/// Controls which operations the protocol can perform.
-#[derive(candid::CandidType, Clone, Debug, PartialEq, Eq, serde::Deserialize, Serialize, Copy)]
-pub enum Mode {
-    /// Protocol's state is read-only.
-    ReadOnly,
-    /// No restrictions on the protocol interactions.
-    GeneralAvailability,
-    /// The protocols tries to get back to a total
-    /// collateral ratio above 150%
-    Recovery,
-}
-
-impl Mode {
-    pub fn is_available(&self) -> bool {
-        match self {
-            Mode::ReadOnly => false,
-            Mode::GeneralAvailability => true,
-            Mode::Recovery => true,
-        }
-    }
-
-    pub fn get_minimum_liquidation_collateral_ratio(&self) -> Ratio {
-        match self {
-            Mode::ReadOnly => MINIMUM_COLLATERAL_RATIO,
-            Mode::GeneralAvailability => MINIMUM_COLLATERAL_RATIO,
-            Mode::Recovery => RECOVERY_COLLATERAL_RATIO,
-        }
-    }
-}
-
-impl fmt::Display for Mode {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Mode::ReadOnly => write!(f, "Read-only"),
-            Mode::GeneralAvailability => write!(f, "General availability"),
-            Mode::Recovery => write!(f, "Recovery"),
-        }
-    }
-}
-
-impl Default for Mode {
-    fn default() -> Self {
-        Self::GeneralAvailability
-    }
-}
-
 #[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, Serialize, Copy)]
 pub struct PendingMarginTransfer {
     pub owner: Principal,
@@ -1018,6 +1151,11 @@ pub struct PendingMarginTransfer {
     /// without dedup, matching prior behaviour, no regression).
     #[serde(default)]
     pub op_nonce: u128,
+    /// Trace of the operation that queued this transfer, carried into the
+    /// retry log lines. `None` for entries rebuilt by replay or written
+    /// before traces existed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<crate::guard::TraceId>,
 }
 
 /// Wave-4 ICC-007: durable refund record for `redeem_reserves` failures.
@@ -1051,6 +1189,19 @@ pub struct PendingStabilityPoolInterestNotification {
     pub source_mint_block: u64,
 }
 
+/// A treasury `deposit` notification whose call failed. The funds are
+/// already at the treasury, so only the bookkeeping call is retried; the
+/// treasury returns the recorded deposit if an earlier attempt landed.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, Serialize)]
+pub struct PendingTreasuryDeposit {
+    pub treasury: Principal,
+    pub deposit_type: crate::treasury::DepositType,
+    pub ledger: Principal,
+    pub amount: u64,
+    pub block_index: u64,
+    pub attempts: u32,
+}
+
 /// Durable refund record for a stranded 3USD reserve refund
 /// (`stability_pool_liquidate_with_reserves`).
 ///
@@ -1159,6 +1310,12 @@ pub struct State {
     /// keyed by the burn icUSD block index. Empty for pre-Wave-4 snapshots.
     #[serde(default)]
     pub pending_refunds: BTreeMap<u64, PendingRefund>,
+    /// Per-vault breakdown of each pending redemption payout, keyed like
+    /// `pending_redemption_transfer`, so a failing payout can be reversed.
+    /// See `redemption_cancel`.
+    #[serde(default)]
+    pub pending_redemption_records:
+        BTreeMap<u64, crate::redemption_cancel::PendingRedemptionRecord>,
     /// Durable retry queue for stranded 3USD reserve refunds
     /// (`stability_pool_liquidate_with_reserves`), keyed by `op_nonce`. A failed
     /// refund back to the stability pool would otherwise leave the SP's live 3USD
@@ -1361,6 +1518,11 @@ pub struct State {
     /// Updated alongside total_collateral_ratio on each price tick.
     pub recovery_mode_threshold: Ratio,
 
+    /// How far above `recovery_mode_threshold` the TCR must climb before the
+    /// protocol leaves Recovery. `None` uses `mode::DEFAULT_RECOVERY_EXIT_BAND`.
+    #[serde(default)]
+    pub recovery_exit_band: Option<Ratio>,
+
     // Reserve redemptions
     pub reserve_redemptions_enabled: bool,
     pub reserve_redemption_fee: Ratio,
@@ -1552,6 +1714,14 @@ pub struct State {
     #[serde(default, skip_serializing)]
     pub vault_cr_index: BTreeMap<u64, BTreeSet<u64>>,
 
+    /// `vault_cr_index` split by collateral type (legacy `anonymous` vaults
+    /// under the ICP ledger), so redemptions walk one collateral's vaults in
+    /// CR order instead of scanning every vault. Maintained by the same
+    /// `reindex_vault_cr` / `unindex_vault_cr` calls and, like it, rebuilt in
+    /// `post_upgrade` rather than persisted.
+    #[serde(default, skip_serializing)]
+    pub vault_cr_index_by_collateral: BTreeMap<CollateralType, BTreeMap<u64, BTreeSet<u64>>>,
+
     /// Wave-8b LIQ-002: tolerance band (in absolute CR units) above the
     /// worst-CR vault inside which liquidations are accepted. e.g., 0.01 means
     /// any vault within 0.01 CR (= 100 bps) of the lowest CR may be
@@ -1703,6 +1873,196 @@ pub struct State {
     #[serde(default)]
     pub treasury_stats_snapshot: Option<(u64, TreasuryStatsSnapshot)>,
 
+    /// Cached `get_public_stats` result; see `public_stats`.
+    #[serde(default)]
+    pub public_stats_snapshot: Option<crate::public_stats::PublicStats>,
+
+    /// ICRC-1 metadata of every supported ledger, by ledger; see
+    /// `asset_registry`.
+    #[serde(default)]
+    pub asset_metadata: BTreeMap<Principal, crate::asset_registry::AssetMetadata>,
+
+    /// Where an upgrade's event replay stands while it runs past
+    /// `post_upgrade` (`Mode::Bootstrapping`); `None` otherwise. See
+    /// `bootstrap`. Never serialized: a snapshot is not written while it is
+    /// set.
+    #[serde(skip)]
+    pub replay_cursor: Option<crate::bootstrap::ReplayCursor>,
+
+    /// Per-endpoint SLO thresholds set by the developer. See `slo`.
+    #[serde(default)]
+    pub slo_config: crate::slo::SloConfig,
+
+    /// Donated funds held as the surplus buffer, per ledger. See
+    /// `donations`.
+    #[serde(default)]
+    pub surplus_buffer: BTreeMap<Principal, u64>,
+    /// Cumulative donations per (donor, ledger).
+    #[serde(default)]
+    pub donation_totals: BTreeMap<(Principal, Principal), u64>,
+
+    /// Registered external liquidators. See `liquidators`.
+    #[serde(default)]
+    pub liquidators: BTreeMap<Principal, crate::liquidators::LiquidatorRegistration>,
+    /// Performance of registered liquidators; not in the event log.
+    #[serde(default)]
+    pub liquidator_stats: BTreeMap<Principal, crate::liquidators::LiquidatorStats>,
+    /// Whether anyone can join the liquidator registry.
+    #[serde(default)]
+    pub liquidator_self_registration: bool,
+
+    /// Ledger fee sponsorship for small operations; `None` until configured.
+    /// See `fee_sponsorship`.
+    #[serde(default)]
+    pub fee_sponsorship_config: Option<crate::fee_sponsorship::FeeSponsorshipConfig>,
+    /// Principals eligible for sponsorship.
+    #[serde(default)]
+    pub fee_sponsorship_verified: BTreeSet<Principal>,
+    /// Sponsorship pool per collateral ledger.
+    #[serde(default)]
+    pub fee_sponsorship_pools: BTreeMap<Principal, crate::fee_sponsorship::SponsorshipPool>,
+    #[serde(default)]
+    pub fee_sponsorship_usage: BTreeMap<Principal, crate::fee_sponsorship::SponsorshipUsage>,
+
+    /// Guard age histogram and outcome counts per principal. Snapshot-only,
+    /// like `liquidator_stats`. See `guard_metrics`.
+    #[serde(default)]
+    pub guard_metrics: BTreeMap<Principal, crate::guard_metrics::GuardMetrics>,
+
+    /// Dutch-auction fallback for liquidations. See `auction`.
+    #[serde(default)]
+    pub auction_config: Option<crate::auction::AuctionConfig>,
+    /// Open auctions and the newest `MAX_ENDED_AUCTIONS` ended ones, by id.
+    #[serde(default)]
+    pub auctions: BTreeMap<u64, crate::auction::Auction>,
+    #[serde(default)]
+    pub next_auction_id: u64,
+    /// vault_id -> when it became an auction candidate (ns). Routing state,
+    /// not replayed.
+    #[serde(default)]
+    pub auction_candidates: BTreeMap<u64, u64>,
+
+    /// Stable tokens reserve redemptions pay from, by ledger. Empty until
+    /// first edited; see `reserve_stables`.
+    #[serde(default)]
+    pub reserve_stables: BTreeMap<Principal, crate::reserve_stables::ReserveStable>,
+
+    /// Redistribute vaults whose auction expired unhealthy. See
+    /// `redistribution`.
+    #[serde(default)]
+    pub redistribution_enabled: bool,
+    /// Per collateral type, the totals handed out by redistributions.
+    #[serde(default)]
+    pub redistribution_indexes: BTreeMap<Principal, crate::redistribution::RedistributionIndex>,
+    /// vault_id -> its stake and the totals it last settled at.
+    #[serde(default)]
+    pub redistribution_snapshots: BTreeMap<u64, crate::redistribution::RedistributionSnapshot>,
+
+    /// Time-weighted debt and collateral per borrower. Snapshot-only, like
+    /// `guard_metrics`. See `loyalty`.
+    #[serde(default)]
+    pub loyalty_metrics: BTreeMap<Principal, crate::loyalty::LoyaltyMetrics>,
+
+    /// Safety logic that decides without acting. See `shadow`.
+    #[serde(default)]
+    pub shadow_config: crate::shadow::ShadowConfig,
+    /// What shadowed logic would have done, oldest first. Snapshot-only.
+    #[serde(default)]
+    pub shadow_decisions: std::collections::VecDeque<crate::shadow::ShadowDecision>,
+    /// Shadow actions not yet stamped by `shadow::flush`. Never persisted,
+    /// and cleared at the end of `event::replay` like
+    /// `pending_mode_transitions`.
+    #[serde(default, skip_serializing)]
+    pub pending_shadow_actions: Vec<crate::shadow::ShadowAction>,
+
+    /// Vaults of each collateral type by debt per unit of collateral, and
+    /// each vault's entry. Not persisted; rebuilt in `post_upgrade`. See
+    /// `liquidation_index`.
+    #[serde(default, skip_serializing)]
+    pub liquidation_index: BTreeMap<CollateralType, BTreeMap<u128, BTreeSet<u64>>>,
+    #[serde(default, skip_serializing)]
+    pub liquidation_index_keys: BTreeMap<u64, (CollateralType, u128)>,
+
+    /// Flat tip (icUSD e8s, paid in collateral) for whoever completes a
+    /// `liquidate_vault`. 0 disables. See `vault::plan_liquidation`.
+    #[serde(default)]
+    pub liquidation_tip_e8s: u64,
+
+    /// Resumable admin batch jobs by id, running and recently finished.
+    /// Snapshot-only. See `batch_jobs`.
+    #[serde(default)]
+    pub batch_jobs: BTreeMap<u64, crate::batch_jobs::BatchJob>,
+    #[serde(default)]
+    pub next_batch_job_id: u64,
+
+    /// Guardian and developer co-approvals of treasury withdrawals, keyed by
+    /// the treasury's `request_id`. See `treasury_approvals`.
+    #[serde(default)]
+    pub treasury_withdrawal_approvals:
+        BTreeMap<u64, crate::treasury_approvals::TreasuryWithdrawalApproval>,
+
+    /// Share of borrowing, vault redemption and liquidation fees kept in the
+    /// surplus buffer. Bounded to [0, 1]; 0 (the default) keeps nothing. See
+    /// `surplus`.
+    #[serde(default)]
+    pub surplus_fee_share: Ratio,
+
+    /// Lifetime fee retentions, absorptions and sweeps of the surplus buffer.
+    #[serde(default)]
+    pub surplus_totals: crate::surplus::SurplusTotals,
+
+    /// Set only inside a snapshot written by `storage::save_state_to_stable`:
+    /// its vault maps were left out and are restored from the stable vault
+    /// store. Always false in the live state. See `vault_store`.
+    #[serde(default)]
+    pub vaults_in_stable_store: bool,
+
+    /// Likewise for the pending margin, excess and redemption transfer
+    /// queues.
+    #[serde(default)]
+    pub pending_transfers_in_stable_store: bool,
+
+    /// Settled collaterals, by ledger. See `collateral_settlement`.
+    #[serde(default)]
+    pub collateral_settlements:
+        BTreeMap<Principal, crate::collateral_settlement::CollateralSettlement>,
+
+    /// Set for good by an emergency shutdown. See `emergency_shutdown`.
+    #[serde(default)]
+    pub emergency_shutdown: Option<crate::emergency_shutdown::EmergencyShutdown>,
+
+    /// SHA-256 of the operator panel token; `None` turns the panel off. Set
+    /// directly, not logged. See `ops_panel`.
+    #[serde(default)]
+    pub ops_panel_token_hash: Option<[u8; 32]>,
+
+    /// Per-collateral caps on liquidation seizures. See `liquidation_caps`.
+    #[serde(default)]
+    pub liquidation_caps: BTreeMap<CollateralType, crate::liquidation_caps::LiquidationCapConfig>,
+    /// Current-window seizures of each capped collateral. Rebuilt on replay
+    /// from the liquidation events.
+    #[serde(default)]
+    pub liquidation_window_usage:
+        BTreeMap<CollateralType, crate::liquidation_caps::LiquidationWindowUsage>,
+
+    /// Delay before a queued parameter change may be executed; 0 leaves the
+    /// setters instant. See `timelock`.
+    #[serde(default)]
+    pub parameter_timelock_ns: u64,
+    /// Queued parameter changes by id. Rebuilt on replay.
+    #[serde(default)]
+    pub pending_parameter_changes: BTreeMap<u64, crate::timelock::PendingParameterChange>,
+    #[serde(default)]
+    pub next_parameter_change_id: u64,
+
+    /// Principals allowed to pause single operations, alongside the
+    /// developer. See `operation_pauses`.
+    #[serde(default)]
+    pub emergency_pausers: BTreeSet<Principal>,
+    /// Operations currently paused. See `operation_pauses`.
+    #[serde(default)]
+    pub paused_operations: BTreeSet<crate::operation_pauses::PausableOperation>,
+
     // ─── Wave-9c DOS-005: shard `check_vaults` to the at-risk band ───
     //
     // `check_vaults` runs every 5-minute XRC tick. Pre-Wave-9c it walked
@@ -1810,6 +2170,208 @@ pub struct State {
     /// snapshot that lacks this key decodes with the field defaulting to 0.
     #[serde(default)]
     pub chain_vault_id_counter: u64,
+
+    /// Mode transitions applied by `transition_mode` that have not been
+    /// written to the event log yet. Drained by
+    /// `event::record_mode_transitions` on the live paths. Never persisted,
+    /// and cleared at the end of `event::replay` so transitions re-derived
+    /// during replay are not recorded a second time.
+    #[serde(default, skip_serializing)]
+    pub pending_mode_transitions: Vec<ModeTransition>,
+
+    /// Borrowing-fee rebate campaigns, keyed by id. See `campaigns`.
+    #[serde(default)]
+    pub rebate_campaigns: BTreeMap<u64, crate::campaigns::RebateCampaign>,
+
+    #[serde(default)]
+    pub next_rebate_campaign_id: u64,
+
+    /// Scoped session keys, keyed by session principal. See `session_keys`.
+    #[serde(default)]
+    pub session_keys: BTreeMap<Principal, crate::session_keys::SessionKey>,
+
+    /// Risk-change notifications queued per vault owner. See `notifications`.
+    #[serde(default)]
+    pub vault_notifications: BTreeMap<Principal, Vec<crate::notifications::VaultNotification>>,
+
+    #[serde(default)]
+    pub next_notification_id: u64,
+
+    /// Post-mortems of liquidations, per vault owner. See
+    /// `liquidation_receipts`.
+    #[serde(default)]
+    pub liquidation_receipts:
+        BTreeMap<Principal, Vec<crate::liquidation_receipts::LiquidationReceipt>>,
+
+    #[serde(default)]
+    pub next_liquidation_receipt_id: u64,
+
+    /// Language and notification preferences, per principal. See
+    /// `user_preferences`.
+    #[serde(default)]
+    pub user_preferences: BTreeMap<Principal, crate::user_preferences::UserPreferences>,
+
+    /// When each pending transfer that failed may next be attempted. See
+    /// `transfer_retry`.
+    #[serde(default)]
+    pub pending_transfer_backoff: BTreeMap<crate::transfer_retry::PendingTransferKey, u64>,
+
+    /// Pending transfers that ran out of attempts, until the developer
+    /// requeues or refunds them.
+    #[serde(default)]
+    pub failed_transfers:
+        BTreeMap<crate::transfer_retry::PendingTransferKey, crate::transfer_retry::FailedTransfer>,
+
+    /// Client-supplied ids of recent vault operations and their outcomes.
+    /// See `operation_ids`.
+    #[serde(default)]
+    pub operation_ids: crate::operation_ids::OperationIds,
+
+    /// Before/after journal of admin setter events, oldest first, capped at
+    /// `MAX_PARAMETER_JOURNAL` entries. See `parameter_journal`.
+    #[serde(default)]
+    pub parameter_journal: std::collections::VecDeque<crate::parameter_journal::ParameterChange>,
+
+    /// Origination records of the latest borrows, keyed by vault id, oldest
+    /// first, capped at `MAX_BORROW_RECORDS_PER_VAULT` per vault. See
+    /// `borrow_records`.
+    #[serde(default)]
+    pub borrow_records: BTreeMap<u64, Vec<crate::borrow_records::BorrowRecord>>,
+    /// Records dropped per vault to stay under the cap.
+    #[serde(default)]
+    pub borrow_records_dropped: BTreeMap<u64, u64>,
+
+    /// Principals allowed to freeze single vaults, alongside the developer.
+    /// See `vault_freeze`.
+    #[serde(default)]
+    pub guardian_principals: BTreeSet<Principal>,
+
+    /// Guardian freezes keyed by vault id. Lapsed entries are ignored and
+    /// pruned on the next freeze.
+    #[serde(default)]
+    pub vault_freezes: BTreeMap<u64, crate::vault_freeze::VaultFreeze>,
+
+    /// Canisters (stability pool, treasury) told about every mode change via
+    /// their `set_protocol_mode` endpoint. See `mode_propagation`.
+    #[serde(default)]
+    pub mode_companion_canisters: BTreeSet<Principal>,
+
+    /// Last mode each companion acknowledged. Never persisted: after an
+    /// upgrade the map starts empty, so the first propagation re-sends the
+    /// current mode to every companion.
+    #[serde(default, skip_serializing)]
+    pub mode_companion_acks: BTreeMap<Principal, Mode>,
+
+    /// Vaults below their collateral's minimum liquidation ratio at the last
+    /// refresh. See `liquidatable_set`.
+    #[serde(default)]
+    pub liquidatable_vault_ids: BTreeSet<u64>,
+
+    /// Vaults within `check_vaults_alert_band_bps` of liquidation, mapped to
+    /// their headroom above the minimum liquidation ratio in bps.
+    #[serde(default)]
+    pub near_liquidation_vaults: BTreeMap<u64, u64>,
+
+    /// First vault id not yet visited by a refresh that ran out of budget,
+    /// per collateral. Not persisted: a refresh cut short by an upgrade
+    /// restarts from the first vault on the next price update.
+    #[serde(default, skip_serializing)]
+    pub liquidatable_refresh_cursors: BTreeMap<CollateralType, u64>,
+
+    #[serde(default)]
+    pub liquidatable_refresh_stats: crate::liquidatable_set::LiquidatableRefreshStats,
+
+    /// Whitelisted DEX routes for in-place collateral swaps, at most one per
+    /// `(from, to)` pair. See `collateral_swap`.
+    #[serde(default)]
+    pub collateral_swap_routes: Vec<crate::collateral_swap::CollateralSwapRoute>,
+
+    /// Recovery-mode stability-pool priority. See `pool_priority`.
+    #[serde(default)]
+    pub pool_priority: crate::pool_priority::PoolPriorityConfig,
+
+    /// Last per-collateral depth read from the stability pool. Cached, not
+    /// replayed.
+    #[serde(default)]
+    pub pool_depth: Option<crate::pool_priority::PoolDepthSnapshot>,
+
+    /// Vaults reserved for the stability pool, with the reservation expiry.
+    #[serde(default)]
+    pub pool_reservations: BTreeMap<u64, u64>,
+
+    /// Owner opt-ins to auto-deleveraging, keyed by vault id (see
+    /// `auto_deleverage`).
+    #[serde(default)]
+    pub auto_deleverage: BTreeMap<u64, crate::auto_deleverage::AutoDeleverageEntry>,
+
+    /// Whitelisted DEX routes for auto-deleverage sales, per collateral.
+    #[serde(default)]
+    pub auto_deleverage_routes:
+        BTreeMap<CollateralType, crate::auto_deleverage::AutoDeleverageRoute>,
+
+    /// Pending-transfer thresholds past which closes, withdrawals and
+    /// redemptions are rejected. See `pending_backpressure`.
+    #[serde(default)]
+    pub pending_backpressure: crate::pending_backpressure::PendingBackpressureConfig,
+
+    /// Operations rejected by the pending-transfer backpressure. Metrics
+    /// only, not replayed.
+    #[serde(default)]
+    pub pending_backpressure_stats: crate::pending_backpressure::PendingBackpressureStats,
+
+    /// Per-priority log buffer sizes and the persisted critical-log bound.
+    /// Applied to the heap buffers on init and upgrade. See `logs::apply_retention`.
+    #[serde(default)]
+    pub log_retention: crate::logs::LogRetentionConfig,
+
+    /// Lifecycle status of live vaults that are not `Active`. See
+    /// `vault_status`.
+    #[serde(default)]
+    pub vault_statuses: BTreeMap<u64, crate::vault_status::VaultStatus>,
+
+    /// Per-collateral redemption caps per epoch. See `redemption_caps`.
+    #[serde(default)]
+    pub redemption_caps: BTreeMap<CollateralType, crate::redemption_caps::RedemptionCapConfig>,
+    /// Current-epoch redemption usage of each capped collateral. Rebuilt on
+    /// replay from the redemption events.
+    #[serde(default)]
+    pub redemption_epoch_usage:
+        BTreeMap<CollateralType, crate::redemption_caps::RedemptionEpochUsage>,
+
+    /// Price-deviation breaker config. `None` leaves it off. See
+    /// `xrc::check_price_deviation_at`.
+    #[serde(default)]
+    pub price_deviation_breaker: Option<crate::xrc::PriceDeviationBreaker>,
+    /// Collaterals whose last sample the breaker rejected under
+    /// `enter_recovery`; while any remain the protocol stays in Recovery.
+    /// Cleared by the collateral's next accepted price.
+    #[serde(default)]
+    pub price_deviation_holds: BTreeSet<CollateralType>,
+
+    /// Per-collateral absolute price bounds; collaterals without an entry
+    /// use `xrc::DEFAULT_PRICE_BOUNDS`. See `xrc::check_price_bounds_at`.
+    #[serde(default)]
+    pub price_bounds: BTreeMap<CollateralType, crate::xrc::PriceBounds>,
+    /// Rejected samples per collateral since its last accepted price. See
+    /// `xrc::note_price_rejection`.
+    #[serde(default)]
+    pub price_rejections: BTreeMap<CollateralType, crate::xrc::PriceRejections>,
+
+    /// Flash-mint fee, cap and callback allowlist; `None` disables flash
+    /// mints. See `flash_mint`.
+    #[serde(default)]
+    pub flash_mint: Option<crate::flash_mint::FlashMintConfig>,
+
+    /// Dust liquidity returns taken off providers by the dust sweep and not
+    /// yet sent to the treasury. The ICP is already in the backend's account;
+    /// it is never credited back as icUSD.
+    #[serde(default)]
+    pub liquidity_dust_swept: ICP,
+
+    /// Treasury deposit notifications that failed, keyed by (ledger, block
+    /// index) and retried by the treasury tick.
+    #[serde(default)]
+    pub pending_treasury_deposits: BTreeMap<(Principal, u64), PendingTreasuryDeposit>,
 }
 
 fn default_check_vaults_alert_band_bps() -> u64 {
@@ -1923,6 +2485,7 @@ impl Default for State {
             pending_excess_transfers: BTreeMap::new(),
             pending_redemption_transfer: BTreeMap::new(),
             pending_refunds: BTreeMap::new(),
+            pending_redemption_records: BTreeMap::new(),
             pending_3usd_refunds: BTreeMap::new(),
             mode: Mode::default(),
             consecutive_xrc_failures: 0,
@@ -1986,6 +2549,7 @@ impl Default for State {
             recovery_target_cr: DEFAULT_RECOVERY_TARGET_CR,
             recovery_cr_multiplier: DEFAULT_RECOVERY_CR_MULTIPLIER,
             recovery_mode_threshold: RECOVERY_COLLATERAL_RATIO,
+            recovery_exit_band: None,
             reserve_redemptions_enabled: false,
             reserve_redemption_fee: DEFAULT_RESERVE_REDEMPTION_FEE,
             icpswap_routing_enabled: false,
@@ -2030,6 +2594,7 @@ impl Default for State {
             pending_outlier_prices: BTreeMap::new(),
             liquidation_frozen: false,
             vault_cr_index: BTreeMap::new(),
+            vault_cr_index_by_collateral: BTreeMap::new(),
             liquidation_ordering_tolerance: DEFAULT_LIQUIDATION_ORDERING_TOLERANCE,
             sp_writedown_disabled: false,
             consumed_writedown_proofs: BTreeSet::new(),
@@ -2049,6 +2614,52 @@ impl Default for State {
             // Wave-9b DOS-006/-007
             protocol_status_snapshot: None,
             treasury_stats_snapshot: None,
+            public_stats_snapshot: None,
+            asset_metadata: BTreeMap::new(),
+            replay_cursor: None,
+            slo_config: Default::default(),
+            surplus_buffer: BTreeMap::new(),
+            donation_totals: BTreeMap::new(),
+            liquidators: BTreeMap::new(),
+            liquidator_stats: BTreeMap::new(),
+            liquidator_self_registration: false,
+            fee_sponsorship_config: None,
+            fee_sponsorship_verified: BTreeSet::new(),
+            fee_sponsorship_pools: BTreeMap::new(),
+            fee_sponsorship_usage: BTreeMap::new(),
+            guard_metrics: BTreeMap::new(),
+            auction_config: None,
+            auctions: BTreeMap::new(),
+            next_auction_id: 0,
+            auction_candidates: BTreeMap::new(),
+            reserve_stables: BTreeMap::new(),
+            redistribution_enabled: false,
+            redistribution_indexes: BTreeMap::new(),
+            redistribution_snapshots: BTreeMap::new(),
+            loyalty_metrics: BTreeMap::new(),
+            shadow_config: Default::default(),
+            shadow_decisions: Default::default(),
+            pending_shadow_actions: Vec::new(),
+            liquidation_index: BTreeMap::new(),
+            liquidation_index_keys: BTreeMap::new(),
+            liquidation_tip_e8s: 0,
+            batch_jobs: BTreeMap::new(),
+            next_batch_job_id: 0,
+            treasury_withdrawal_approvals: BTreeMap::new(),
+            surplus_fee_share: Ratio::default(),
+            surplus_totals: Default::default(),
+            vaults_in_stable_store: false,
+            pending_transfers_in_stable_store: false,
+            collateral_settlements: BTreeMap::new(),
+            emergency_shutdown: None,
+            ops_panel_token_hash: None,
+            liquidation_caps: BTreeMap::new(),
+            liquidation_window_usage: BTreeMap::new(),
+            parameter_timelock_ns: 0,
+            pending_parameter_changes: BTreeMap::new(),
+            next_parameter_change_id: 0,
+            emergency_pausers: BTreeSet::new(),
+            paused_operations: BTreeSet::new(),
             // Wave-9c DOS-005
             check_vaults_alert_band_bps: default_check_vaults_alert_band_bps(),
             check_vaults_full_sweep_every_n_ticks: default_check_vaults_full_sweep_every_n_ticks(),
@@ -2062,6 +2673,48 @@ impl Default for State {
             sol_rpc_principal_override: None,
             solana_workers_enabled: false,
             chain_vault_id_counter: 0,
+            pending_mode_transitions: Vec::new(),
+            rebate_campaigns: BTreeMap::new(),
+            next_rebate_campaign_id: 0,
+            session_keys: BTreeMap::new(),
+            vault_notifications: BTreeMap::new(),
+            next_notification_id: 0,
+            liquidation_receipts: BTreeMap::new(),
+            next_liquidation_receipt_id: 0,
+            user_preferences: BTreeMap::new(),
+            pending_transfer_backoff: BTreeMap::new(),
+            failed_transfers: BTreeMap::new(),
+            operation_ids: Default::default(),
+            parameter_journal: std::collections::VecDeque::new(),
+            borrow_records: BTreeMap::new(),
+            borrow_records_dropped: BTreeMap::new(),
+            guardian_principals: BTreeSet::new(),
+            vault_freezes: BTreeMap::new(),
+            mode_companion_canisters: BTreeSet::new(),
+            mode_companion_acks: BTreeMap::new(),
+            liquidatable_vault_ids: BTreeSet::new(),
+            near_liquidation_vaults: BTreeMap::new(),
+            liquidatable_refresh_cursors: BTreeMap::new(),
+            liquidatable_refresh_stats: Default::default(),
+            collateral_swap_routes: Vec::new(),
+            pool_priority: crate::pool_priority::PoolPriorityConfig::default(),
+            pool_depth: None,
+            pool_reservations: BTreeMap::new(),
+            auto_deleverage: BTreeMap::new(),
+            auto_deleverage_routes: BTreeMap::new(),
+            pending_backpressure: crate::pending_backpressure::PendingBackpressureConfig::default(),
+            pending_backpressure_stats: Default::default(),
+            log_retention: crate::logs::LogRetentionConfig::default(),
+            vault_statuses: BTreeMap::new(),
+            redemption_caps: BTreeMap::new(),
+            redemption_epoch_usage: BTreeMap::new(),
+            price_deviation_breaker: None,
+            price_deviation_holds: BTreeSet::new(),
+            price_bounds: BTreeMap::new(),
+            price_rejections: BTreeMap::new(),
+            flash_mint: None,
+            liquidity_dust_swept: ICP::new(0),
+            pending_treasury_deposits: BTreeMap::new(),
         }
     }
 }
@@ -2079,6 +2732,7 @@ impl From<InitArg> for State {
             principal_to_vault_ids: BTreeMap::new(),
             pending_redemption_transfer: BTreeMap::new(),
             pending_refunds: BTreeMap::new(),
+            pending_redemption_records: BTreeMap::new(),
             pending_3usd_refunds: BTreeMap::new(),
             vault_id_to_vaults: BTreeMap::new(),
             xrc_principal: args.xrc_principal,
@@ -2142,6 +2796,7 @@ impl From<InitArg> for State {
             recovery_target_cr: DEFAULT_RECOVERY_TARGET_CR,
             recovery_cr_multiplier: DEFAULT_RECOVERY_CR_MULTIPLIER,
             recovery_mode_threshold: RECOVERY_COLLATERAL_RATIO,
+            recovery_exit_band: None,
 
             // Reserve redemptions
             reserve_redemptions_enabled: false,
@@ -2193,6 +2848,12 @@ impl From<InitArg> for State {
                         min_xrc_sources: None, // inherit global floor for ICP
                         custody_kind: None,    // ICRC (ICP ledger) — legacy default
                         symbol: Some("ICP".to_string()),
+                        liquidation_rebate_mode: LiquidationRebateMode::CollateralPayout,
+                        secondary_price_source: None,
+                        price_disputed: false,
+                        last_price_std_dev: None,
+                        price_confidence_k: None,
+                        maintenance_fee_apr: None,
                     },
                 );
                 configs
@@ -2306,6 +2967,7 @@ impl From<InitArg> for State {
             pending_outlier_prices: BTreeMap::new(),
             liquidation_frozen: false,
             vault_cr_index: BTreeMap::new(),
+            vault_cr_index_by_collateral: BTreeMap::new(),
             liquidation_ordering_tolerance: DEFAULT_LIQUIDATION_ORDERING_TOLERANCE,
             sp_writedown_disabled: false,
             consumed_writedown_proofs: BTreeSet::new(),
@@ -2325,6 +2987,52 @@ impl From<InitArg> for State {
             // Wave-9b DOS-006/-007
             protocol_status_snapshot: None,
             treasury_stats_snapshot: None,
+            public_stats_snapshot: None,
+            asset_metadata: BTreeMap::new(),
+            replay_cursor: None,
+            slo_config: Default::default(),
+            surplus_buffer: BTreeMap::new(),
+            donation_totals: BTreeMap::new(),
+            liquidators: BTreeMap::new(),
+            liquidator_stats: BTreeMap::new(),
+            liquidator_self_registration: false,
+            fee_sponsorship_config: None,
+            fee_sponsorship_verified: BTreeSet::new(),
+            fee_sponsorship_pools: BTreeMap::new(),
+            fee_sponsorship_usage: BTreeMap::new(),
+            guard_metrics: BTreeMap::new(),
+            auction_config: None,
+            auctions: BTreeMap::new(),
+            next_auction_id: 0,
+            auction_candidates: BTreeMap::new(),
+            reserve_stables: BTreeMap::new(),
+            redistribution_enabled: false,
+            redistribution_indexes: BTreeMap::new(),
+            redistribution_snapshots: BTreeMap::new(),
+            loyalty_metrics: BTreeMap::new(),
+            shadow_config: Default::default(),
+            shadow_decisions: Default::default(),
+            pending_shadow_actions: Vec::new(),
+            liquidation_index: BTreeMap::new(),
+            liquidation_index_keys: BTreeMap::new(),
+            liquidation_tip_e8s: 0,
+            batch_jobs: BTreeMap::new(),
+            next_batch_job_id: 0,
+            treasury_withdrawal_approvals: BTreeMap::new(),
+            surplus_fee_share: Ratio::default(),
+            surplus_totals: Default::default(),
+            vaults_in_stable_store: false,
+            pending_transfers_in_stable_store: false,
+            collateral_settlements: BTreeMap::new(),
+            emergency_shutdown: None,
+            ops_panel_token_hash: None,
+            liquidation_caps: BTreeMap::new(),
+            liquidation_window_usage: BTreeMap::new(),
+            parameter_timelock_ns: 0,
+            pending_parameter_changes: BTreeMap::new(),
+            next_parameter_change_id: 0,
+            emergency_pausers: BTreeSet::new(),
+            paused_operations: BTreeSet::new(),
             // Wave-9c DOS-005
             check_vaults_alert_band_bps: default_check_vaults_alert_band_bps(),
             check_vaults_full_sweep_every_n_ticks: default_check_vaults_full_sweep_every_n_ticks(),
@@ -2338,6 +3046,48 @@ impl From<InitArg> for State {
             sol_rpc_principal_override: None,
             solana_workers_enabled: false,
             chain_vault_id_counter: 0,
+            pending_mode_transitions: Vec::new(),
+            rebate_campaigns: BTreeMap::new(),
+            next_rebate_campaign_id: 0,
+            session_keys: BTreeMap::new(),
+            vault_notifications: BTreeMap::new(),
+            next_notification_id: 0,
+            liquidation_receipts: BTreeMap::new(),
+            next_liquidation_receipt_id: 0,
+            user_preferences: BTreeMap::new(),
+            pending_transfer_backoff: BTreeMap::new(),
+            failed_transfers: BTreeMap::new(),
+            operation_ids: Default::default(),
+            parameter_journal: std::collections::VecDeque::new(),
+            borrow_records: BTreeMap::new(),
+            borrow_records_dropped: BTreeMap::new(),
+            guardian_principals: BTreeSet::new(),
+            vault_freezes: BTreeMap::new(),
+            mode_companion_canisters: BTreeSet::new(),
+            mode_companion_acks: BTreeMap::new(),
+            liquidatable_vault_ids: BTreeSet::new(),
+            near_liquidation_vaults: BTreeMap::new(),
+            liquidatable_refresh_cursors: BTreeMap::new(),
+            liquidatable_refresh_stats: Default::default(),
+            collateral_swap_routes: Vec::new(),
+            pool_priority: crate::pool_priority::PoolPriorityConfig::default(),
+            pool_depth: None,
+            pool_reservations: BTreeMap::new(),
+            auto_deleverage: BTreeMap::new(),
+            auto_deleverage_routes: BTreeMap::new(),
+            pending_backpressure: crate::pending_backpressure::PendingBackpressureConfig::default(),
+            pending_backpressure_stats: Default::default(),
+            log_retention: crate::logs::LogRetentionConfig::default(),
+            vault_statuses: BTreeMap::new(),
+            redemption_caps: BTreeMap::new(),
+            redemption_epoch_usage: BTreeMap::new(),
+            price_deviation_breaker: None,
+            price_deviation_holds: BTreeSet::new(),
+            price_bounds: BTreeMap::new(),
+            price_rejections: BTreeMap::new(),
+            flash_mint: None,
+            liquidity_dust_swept: ICP::new(0),
+            pending_treasury_deposits: BTreeMap::new(),
         }
     }
 }
@@ -2566,6 +3316,65 @@ impl State {
         }
     }
 
+    /// Store the XRC spread that came with a just-applied price.
+    pub fn set_price_std_dev(&mut self, collateral_type: &Principal, std_dev: Option<f64>) {
+        if let Some(config) = self.collateral_configs.get_mut(collateral_type) {
+            config.last_price_std_dev = std_dev;
+        }
+    }
+
+    pub fn set_price_confidence_k(&mut self, collateral_type: &Principal, k: Option<Ratio>) {
+        if let Some(config) = self.collateral_configs.get_mut(collateral_type) {
+            config.price_confidence_k = k;
+        }
+    }
+
+    /// Set `collateral_type`'s maintenance fee from `now_nanos` on. Its
+    /// vaults are accrued to `now_nanos` under the old fee first, and the
+    /// accrual clock of vaults without debt (which stands still while no fee
+    /// applies) restarts, so a new fee is never charged for earlier time.
+    pub fn set_maintenance_fee_apr(
+        &mut self,
+        collateral_type: &Principal,
+        fee: Option<Ratio>,
+        now_nanos: u64,
+    ) {
+        let vault_ids: Vec<u64> = self
+            .vault_id_to_vaults
+            .values()
+            .filter(|v| &v.collateral_type == collateral_type)
+            .map(|v| v.vault_id)
+            .collect();
+        for vault_id in &vault_ids {
+            self.accrue_single_vault(*vault_id, now_nanos);
+        }
+        for vault_id in &vault_ids {
+            if let Some(vault) = self.vault_id_to_vaults.get_mut(vault_id) {
+                if vault.borrowed_icusd_amount.0 == 0 {
+                    vault.last_accrual_time = vault.last_accrual_time.max(now_nanos);
+                }
+            }
+        }
+        if let Some(config) = self.collateral_configs.get_mut(collateral_type) {
+            config.maintenance_fee_apr = fee;
+        }
+    }
+
+    /// Install or remove a collateral's secondary price source. Removing it
+    /// also lifts any open dispute, since nothing could clear it afterwards.
+    pub fn set_secondary_price_source(
+        &mut self,
+        collateral_type: &Principal,
+        source: Option<SecondaryPriceSource>,
+    ) {
+        if let Some(config) = self.collateral_configs.get_mut(collateral_type) {
+            if source.is_none() {
+                config.price_disputed = false;
+            }
+            config.secondary_price_source = source;
+        }
+    }
+
     /// Mint a fresh idempotency nonce for an ICRC transfer (audit Wave-3).
     ///
     /// Layout: upper 64 bits = current IC time (nanoseconds), lower 64 bits =
@@ -2577,9 +3386,13 @@ impl State {
     /// `PendingMarginTransfer`) and pass it back into the helper on retries —
     /// that is what makes the transfer idempotent at the ledger.
     pub fn next_op_nonce(&mut self) -> u128 {
+        self.next_op_nonce_at(ic_cdk::api::time())
+    }
+
+    /// `next_op_nonce` with the time given, for callers that already have it.
+    pub fn next_op_nonce_at(&mut self, now: u64) -> u128 {
         let counter = self.op_nonce_counter;
         self.op_nonce_counter = self.op_nonce_counter.wrapping_add(1);
-        let now = ic_cdk::api::time();
         ((now as u128) << 64) | (counter as u128)
     }
 
@@ -2601,15 +3414,119 @@ impl State {
 
     pub fn upgrade(&mut self, args: UpgradeArg) {
         if let Some(mode) = args.mode {
-            self.mode = mode;
+            let from = self.mode;
+            // Upgrade is legal on every edge but those into and out of
+            // Bootstrapping and skips the ReadOnly exit checks, so this only
+            // fails for an upgrade asking for Bootstrapping, which is ignored.
+            let _ = self.transition_mode(from, mode, ModeTransitionReason::Upgrade);
+        }
+    }
+
+    /// Single entry point for every protocol mode change.
+    ///
+    /// `from` is the mode the caller believes is current; a mismatch is
+    /// rejected so a decision computed against a stale mode never lands.
+    /// The `from -> to` edge must be legal for `reason`
+    /// (`is_legal_mode_transition`), and leaving ReadOnly additionally
+    /// requires `readonly_exit_blocker` to clear unless the trigger is an
+    /// upgrade. Returns `Ok(true)` when the mode changed and `Ok(false)` for
+    /// a same-mode no-op or a transition `shadow` only notes.
+    ///
+    /// Applied transitions are buffered in `pending_mode_transitions`; the
+    /// live caller drains them into the event log with
+    /// `event::record_mode_transitions`. Pure-state otherwise, so it is safe
+    /// to call from `replay`.
+    pub fn transition_mode(
+        &mut self,
+        from: Mode,
+        to: Mode,
+        reason: ModeTransitionReason,
+    ) -> Result<bool, ModeTransitionError> {
+        if self.mode != from {
+            return Err(ModeTransitionError::UnexpectedCurrentMode {
+                expected: from,
+                actual: self.mode,
+            });
+        }
+        if from == to {
+            return Ok(false);
         }
+        if !is_legal_mode_transition(from, to, reason) {
+            return Err(ModeTransitionError::IllegalTransition { from, to, reason });
+        }
+        if from == Mode::ReadOnly && reason != ModeTransitionReason::Upgrade {
+            if let Some(blocker) = self.readonly_exit_blocker(reason) {
+                return Err(ModeTransitionError::ExitChecksFailed(blocker));
+            }
+        }
+        if crate::shadow::shadows_transition(self, reason) {
+            crate::shadow::note(
+                self,
+                crate::shadow::ShadowAction::ModeTransition { from, to, reason },
+            );
+            return Ok(false);
+        }
+        self.mode = to;
+        self.pending_mode_transitions
+            .push(ModeTransition { from, to, reason });
+        log!(
+            crate::DEBUG,
+            "[transition_mode] {} -> {} ({:?})",
+            from,
+            to,
+            reason
+        );
+        Ok(true)
+    }
+
+    /// Readiness checks for leaving ReadOnly. Returns a human-readable
+    /// description of the first failing check, or `None` when the protocol
+    /// may resume.
+    ///
+    /// The deficit latch is waived for `AdminOverride`: clearing it via
+    /// `exit_recovery_mode` is the documented operator path once the deficit
+    /// has been reviewed.
+    pub fn readonly_exit_blocker(&self, reason: ModeTransitionReason) -> Option<String> {
+        if self.total_collateral_ratio < Ratio::from(dec!(1.0)) {
+            return Some(format!(
+                "total collateral ratio {} is below 100%",
+                self.total_collateral_ratio.to_f64()
+            ));
+        }
+        if self.multi_chain.invariant_halted {
+            return Some("multi-chain supply invariant is halted".to_string());
+        }
+        if reason != ModeTransitionReason::AdminOverride
+            && self.deficit_readonly_threshold_e8s > 0
+            && self.protocol_deficit_icusd.0 >= self.deficit_readonly_threshold_e8s
+        {
+            return Some(format!(
+                "protocol deficit {} e8s is at or above the ReadOnly threshold {} e8s",
+                self.protocol_deficit_icusd.0, self.deficit_readonly_threshold_e8s
+            ));
+        }
+        None
+    }
+
+    /// Move to ReadOnly for a safety `reason`, whatever the current mode.
+    /// Entering ReadOnly is legal from every mode for every safety trigger,
+    /// so this only returns `false` when the protocol was already ReadOnly.
+    pub fn enter_read_only(&mut self, reason: ModeTransitionReason) -> bool {
+        let from = self.mode;
+        self.transition_mode(from, Mode::ReadOnly, reason)
+            .unwrap_or(false)
     }
 
     pub fn total_borrowed_icusd_amount(&self) -> ICUSD {
         self.vault_id_to_vaults
             .values()
             .map(|vault| vault.borrowed_icusd_amount)
-            .sum()
+            .sum::<ICUSD>()
+            + self
+                .redistribution_indexes
+                .values()
+                .map(|index| ICUSD::new(index.pending_debt))
+                .sum::<ICUSD>()
     }
 
     /// Deprecated: use `total_collateral_for(&icp_ledger)` for ICP specifically,
@@ -2729,10 +3646,8 @@ impl State {
 
     pub fn get_redemption_fee(&self, redeemed_amount: ICUSD) -> Ratio {
         let current_time = ic_cdk::api::time();
-        let last_redemption_time = self.last_redemption_time;
-        let elapsed_hours = (current_time - last_redemption_time) / 1_000_000_000 / 3600;
         compute_redemption_fee(
-            elapsed_hours,
+            current_time.saturating_sub(self.last_redemption_time),
             redeemed_amount,
             self.total_borrowed_icusd_amount(),
             self.current_base_rate,
@@ -2895,6 +3810,24 @@ impl State {
         }
     }
 
+    /// Cache `price` as the accepted price of `collateral_type`, exactly as
+    /// its `PriceUpdate` event records it. Replay goes through here as well,
+    /// so price-dependent accrual such as the maintenance fee books the same
+    /// debt on both paths.
+    pub fn apply_price_update(
+        &mut self,
+        collateral_type: CollateralType,
+        price: Decimal,
+        timestamp_nanos: u64,
+    ) {
+        if collateral_type == self.icp_collateral_type() {
+            self.set_icp_rate(crate::numeric::UsdIcp::from(price), Some(timestamp_nanos));
+        } else if let Some(config) = self.collateral_configs.get_mut(&collateral_type) {
+            config.last_price = price.to_f64();
+            config.last_price_timestamp = Some(timestamp_nanos);
+        }
+    }
+
     /// Get borrowing fee for a specific collateral type
     pub fn get_borrowing_fee_for(&self, ct: &CollateralType) -> Ratio {
         let config = self.collateral_configs.get(ct);
@@ -3223,6 +4156,89 @@ impl State {
         layer1_rate
     }
 
+    /// Annual maintenance fee of `ct`; zero when it has none.
+    pub fn get_maintenance_fee_apr_for(&self, ct: &CollateralType) -> Ratio {
+        self.get_collateral_config(ct)
+            .and_then(|c| c.maintenance_fee_apr)
+            .unwrap_or(Ratio::from(Decimal::ZERO))
+    }
+
+    /// Whether `vault` accrues even without debt: its collateral charges a
+    /// maintenance fee and it holds collateral.
+    fn accrues_maintenance_fee(&self, vault: &Vault) -> bool {
+        vault.collateral_amount > 0
+            && !self
+                .get_maintenance_fee_apr_for(&vault.collateral_type)
+                .0
+                .is_zero()
+    }
+
+    /// Maintenance fee `vault` owes for `elapsed` nanoseconds, in icUSD e8s
+    /// and unrounded, at the collateral's current price. Zero while the
+    /// collateral has no price. The price is the last `PriceUpdate`, which
+    /// replay restores (`apply_price_update`), so replayed debt matches.
+    fn maintenance_fee_due(&self, vault: &Vault, elapsed: u64) -> Decimal {
+        let fee_apr = self.get_maintenance_fee_apr_for(&vault.collateral_type);
+        if fee_apr.0.is_zero() {
+            return Decimal::ZERO;
+        }
+        let (Some(price), Some(config)) = (
+            self.get_collateral_price_decimal(&vault.collateral_type),
+            self.get_collateral_config(&vault.collateral_type),
+        ) else {
+            return Decimal::ZERO;
+        };
+        let value =
+            crate::numeric::collateral_usd_value(vault.collateral_amount, price, config.decimals);
+        Decimal::from(value.0) * fee_apr.0 * Decimal::from(elapsed)
+            / Decimal::from(crate::numeric::NANOS_PER_YEAR)
+    }
+
+    /// Rate, elapsed time and maintenance fee (see `maintenance_fee_due`)
+    /// `accrue_single_vault` would apply to `vault_id` at `now_nanos`, or
+    /// `None` when there is nothing to accrue.
+    fn accrual_rate_and_elapsed(
+        &self,
+        vault_id: u64,
+        now_nanos: u64,
+    ) -> Option<(Ratio, u64, Decimal)> {
+        match self.vault_id_to_vaults.get(&vault_id) {
+            Some(vault)
+                if (vault.borrowed_icusd_amount.0 > 0 || self.accrues_maintenance_fee(vault))
+                    && vault.last_accrual_time < now_nanos =>
+            {
+                let dummy_rate = self
+                    .last_icp_rate
+                    .unwrap_or(UsdIcp::from(rust_decimal_macros::dec!(1.0)));
+                let cr = crate::compute_collateral_ratio(vault, dummy_rate, self);
+                let rate = self.get_dynamic_interest_rate_for(&vault.collateral_type, cr);
+                let elapsed = now_nanos.saturating_sub(vault.last_accrual_time);
+                Some((rate, elapsed, self.maintenance_fee_due(vault, elapsed)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Interest `vault_id` has earned since its last accrual that is not yet
+    /// booked into its debt: exactly what `accrue_single_vault(vault_id,
+    /// now_nanos)` would add, rounding included.
+    pub fn pending_interest(&self, vault_id: u64, now_nanos: u64) -> ICUSD {
+        let (Some((rate, elapsed, fee)), Some(vault)) = (
+            self.accrual_rate_and_elapsed(vault_id, now_nanos),
+            self.vault_id_to_vaults.get(&vault_id),
+        ) else {
+            return ICUSD::new(0);
+        };
+        let debt = Decimal::from(vault.borrowed_icusd_amount.0);
+        let factor = Decimal::ONE
+            + rate.0 * Decimal::from(elapsed) / Decimal::from(crate::numeric::NANOS_PER_YEAR);
+        (debt * factor + fee)
+            .ceil()
+            .to_u64()
+            .map(|new_debt| ICUSD::new(new_debt.saturating_sub(vault.borrowed_icusd_amount.0)))
+            .unwrap_or(ICUSD::new(0))
+    }
+
     /// Accrue interest on a single vault up to `now_nanos`.
     /// Two-phase for borrow checker: compute rate (immutable), then apply (mutable).
     /// SAFETY (Wave-8b LIQ-002): interest accrual changes a vault's debt and
@@ -3232,27 +4248,14 @@ impl State {
     /// so the index converges to the post-accrual CR on the next user action.
     /// Re-keying inside passive accrual would be O(N log N) per timer tick for
     /// zero ordering benefit at the band tolerance scale (default 1% CR).
+    /// `liquidation_index` has no such tolerance and is re-keyed, at
+    /// O(log n) per vault.
     pub fn accrue_single_vault(&mut self, vault_id: u64, now_nanos: u64) {
+        crate::redistribution::settle(self, vault_id);
         // Phase 1: compute rate (immutable borrow of self)
-        let rate_and_elapsed = {
-            let s: &State = &*self;
-            match s.vault_id_to_vaults.get(&vault_id) {
-                Some(vault)
-                    if vault.borrowed_icusd_amount.0 > 0 && vault.last_accrual_time < now_nanos =>
-                {
-                    let dummy_rate = s
-                        .last_icp_rate
-                        .unwrap_or(UsdIcp::from(rust_decimal_macros::dec!(1.0)));
-                    let cr = crate::compute_collateral_ratio(vault, dummy_rate, s);
-                    let rate = s.get_dynamic_interest_rate_for(&vault.collateral_type, cr);
-                    let elapsed = now_nanos.saturating_sub(vault.last_accrual_time);
-                    Some((rate, elapsed))
-                }
-                _ => None,
-            }
-        };
+        let rate_and_elapsed = self.accrual_rate_and_elapsed(vault_id, now_nanos);
         // Phase 2: apply (mutable borrow)
-        if let Some((rate, elapsed)) = rate_and_elapsed {
+        if let Some((rate, elapsed, fee)) = rate_and_elapsed {
             if elapsed == 0 {
                 return;
             }
@@ -3265,8 +4268,9 @@ impl State {
                 // (protocol favor, CDP convention). INT-102: on a Decimal->u64
                 // overflow (unreachable at real debt scales) do NOT advance the
                 // accrual clock, so the interest is retried instead of being
-                // silently dropped while the clock moves on.
-                match (debt * factor).ceil().to_u64() {
+                // silently dropped while the clock moves on. The maintenance
+                // fee is booked as interest.
+                match (debt * factor + fee).ceil().to_u64() {
                     Some(new_debt) => {
                         let interest_delta = new_debt.saturating_sub(vault.borrowed_icusd_amount.0);
                         vault.accrued_interest += ICUSD::from(interest_delta);
@@ -3282,6 +4286,7 @@ impl State {
                     }
                 }
             }
+            crate::liquidation_index::reindex(self, vault_id);
         }
     }
 
@@ -3289,28 +4294,36 @@ impl State {
     /// Two-phase: collect (vault_id, rate, elapsed) immutably, then apply mutably.
     ///
     /// SAFETY (Wave-8b LIQ-002): same contract as `accrue_single_vault` —
-    /// passive accrual does not re-key the CR index. See that function's
-    /// SAFETY block for the rationale.
+    /// passive accrual does not re-key the CR index, only
+    /// `liquidation_index`. See that function's SAFETY block for the
+    /// rationale.
     pub fn accrue_all_vault_interest(&mut self, now_nanos: u64) {
+        let tracked: Vec<u64> = self.redistribution_snapshots.keys().copied().collect();
+        for vault_id in tracked {
+            crate::redistribution::settle(self, vault_id);
+        }
         // Phase 1: compute rates for all vaults (immutable)
-        let accruals: Vec<(u64, Ratio, u64)> = {
+        let accruals: Vec<(u64, Ratio, u64, Decimal)> = {
             let s: &State = &*self;
             let dummy_rate = s
                 .last_icp_rate
                 .unwrap_or(UsdIcp::from(rust_decimal_macros::dec!(1.0)));
             s.vault_id_to_vaults
                 .iter()
-                .filter(|(_, v)| v.borrowed_icusd_amount.0 > 0 && v.last_accrual_time < now_nanos)
+                .filter(|(_, v)| {
+                    (v.borrowed_icusd_amount.0 > 0 || s.accrues_maintenance_fee(v))
+                        && v.last_accrual_time < now_nanos
+                })
                 .map(|(id, vault)| {
                     let cr = crate::compute_collateral_ratio(vault, dummy_rate, s);
                     let rate = s.get_dynamic_interest_rate_for(&vault.collateral_type, cr);
                     let elapsed = now_nanos.saturating_sub(vault.last_accrual_time);
-                    (*id, rate, elapsed)
+                    (*id, rate, elapsed, s.maintenance_fee_due(vault, elapsed))
                 })
                 .collect()
         };
         // Phase 2: apply accruals (mutable)
-        for (vault_id, rate, elapsed) in accruals {
+        for (vault_id, rate, elapsed, fee) in accruals {
             if elapsed == 0 {
                 continue;
             }
@@ -3321,7 +4334,7 @@ impl State {
                         / Decimal::from(crate::numeric::NANOS_PER_YEAR);
                 // DBT-001 / INT-102: round UP, defer on overflow (see
                 // accrue_single_vault).
-                match (debt * factor).ceil().to_u64() {
+                match (debt * factor + fee).ceil().to_u64() {
                     Some(new_debt) => {
                         let interest_delta = new_debt.saturating_sub(vault.borrowed_icusd_amount.0);
                         vault.accrued_interest += ICUSD::from(interest_delta);
@@ -3337,6 +4350,7 @@ impl State {
                     }
                 }
             }
+            crate::liquidation_index::reindex(self, vault_id);
         }
     }
 
@@ -3419,6 +4433,43 @@ impl State {
         self.pending_treasury_interest = ICUSD::new(combined);
     }
 
+    /// Queue a treasury deposit whose notification failed. When the queue is
+    /// full the deposit is dropped and `false` returned; the funds stay at
+    /// the treasury, unbooked until an operator records them.
+    pub fn queue_treasury_deposit(&mut self, deposit: PendingTreasuryDeposit) -> bool {
+        let key = (deposit.ledger, deposit.block_index);
+        if !self.pending_treasury_deposits.contains_key(&key)
+            && self.pending_treasury_deposits.len() >= MAX_PENDING_TREASURY_DEPOSITS
+        {
+            return false;
+        }
+        self.pending_treasury_deposits.insert(key, deposit);
+        true
+    }
+
+    /// Settle one retry of the queued deposit for `block_index` on `ledger`.
+    /// A delivered deposit leaves the queue, as does one that has failed
+    /// `MAX_TREASURY_DEPOSIT_ATTEMPTS` times; that one is returned so the
+    /// caller can report it.
+    pub fn settle_treasury_deposit_retry(
+        &mut self,
+        ledger: Principal,
+        block_index: u64,
+        delivered: bool,
+    ) -> Option<PendingTreasuryDeposit> {
+        let key = (ledger, block_index);
+        if delivered {
+            self.pending_treasury_deposits.remove(&key);
+            return None;
+        }
+        let deposit = self.pending_treasury_deposits.get_mut(&key)?;
+        deposit.attempts = deposit.attempts.saturating_add(1);
+        if deposit.attempts >= MAX_TREASURY_DEPOSIT_ATTEMPTS {
+            return self.pending_treasury_deposits.remove(&key);
+        }
+        None
+    }
+
     /// Compute the debt-weighted average interest rate across all vaults.
     /// Returns 0 if no vaults have outstanding debt.
     pub fn weighted_average_interest_rate(&self) -> Ratio {
@@ -3528,6 +4579,15 @@ impl State {
             .and_then(|p| Decimal::from_f64(p))
     }
 
+    /// `get_collateral_price_decimal` for borrow and withdrawal checks: the
+    /// collateral's conservative bound when it has a confidence multiple set.
+    /// See `CollateralConfig::borrow_check_price`.
+    pub fn get_borrow_check_price_decimal(&self, ct: &CollateralType) -> Option<Decimal> {
+        self.get_collateral_config(ct)
+            .and_then(|c| c.borrow_check_price())
+            .and_then(Decimal::from_f64)
+    }
+
     /// Compute the effective recovery target CR: dynamic threshold × proportional multiplier.
     /// This is the CR that partial-liquidated vaults are restored to during Recovery Mode.
     pub fn get_recovery_target_cr_for(&self, _ct: &CollateralType) -> Ratio {
@@ -3552,45 +4612,99 @@ impl State {
 
     /// Get the redemption fee for a specific collateral type
     pub fn get_redemption_fee_for(&self, ct: &CollateralType, redeemed_amount: ICUSD) -> Ratio {
-        if let Some(config) = self.collateral_configs.get(ct) {
-            let current_time = ic_cdk::api::time();
-            let elapsed_hours = (current_time - config.last_redemption_time) / 1_000_000_000 / 3600;
-            let total_borrowed = self.total_debt_for_collateral(ct);
-            compute_redemption_fee(
-                elapsed_hours,
-                redeemed_amount,
-                total_borrowed,
-                config.current_base_rate,
-                config.redemption_fee_floor,
-                config.redemption_fee_ceiling,
-            )
+        if self.collateral_configs.contains_key(ct) {
+            self.redemption_fee_at(ct, redeemed_amount, ic_cdk::api::time())
+                .0
         } else {
             self.get_redemption_fee(redeemed_amount)
         }
     }
 
+    /// `ct`'s redemption base rate decayed to `now`; the legacy global rate
+    /// for an unknown collateral.
+    pub fn get_base_rate_at(&self, ct: &CollateralType, now: u64) -> Ratio {
+        let (base_rate, last_redemption_time) = match self.collateral_configs.get(ct) {
+            Some(config) => (config.current_base_rate, config.last_redemption_time),
+            None => (self.current_base_rate, self.last_redemption_time),
+        };
+        decay_base_rate(base_rate, now.saturating_sub(last_redemption_time))
+    }
+
+    /// The fee for redeeming `redeemed_amount` against `ct` at `now`, and
+    /// the base rate the redemption leaves behind.
+    pub fn redemption_fee_at(
+        &self,
+        ct: &CollateralType,
+        redeemed_amount: ICUSD,
+        now: u64,
+    ) -> (Ratio, Ratio) {
+        let (floor, ceiling) = match self.collateral_configs.get(ct) {
+            Some(config) => (config.redemption_fee_floor, config.redemption_fee_ceiling),
+            None => (self.redemption_fee_floor, self.redemption_fee_ceiling),
+        };
+        let total_debt = self.total_debt_for_collateral(ct);
+        let base_rate =
+            base_rate_after_redemption(self.get_base_rate_at(ct, now), redeemed_amount, total_debt);
+        if total_debt == 0 {
+            return (Ratio::from(Decimal::ZERO), base_rate);
+        }
+        (base_rate.max(floor).min(ceiling), base_rate)
+    }
+
+    /// `ct`'s debt against its ceiling, counting `in_flight` icUSD e8s of
+    /// borrows that have reserved headroom but not yet recorded their debt.
+    /// `None` for an unknown collateral.
+    pub fn collateral_utilization(
+        &self,
+        ct: &CollateralType,
+        in_flight: u64,
+    ) -> Option<crate::CollateralUtilization> {
+        let config = self.get_collateral_config(ct)?;
+        let total_debt = self.total_debt_for_collateral(ct).to_u64();
+        Some(crate::CollateralUtilization {
+            collateral_type: *ct,
+            total_debt,
+            in_flight_borrows: in_flight,
+            debt_ceiling: config.debt_ceiling,
+            headroom: config
+                .debt_ceiling
+                .saturating_sub(total_debt.saturating_add(in_flight)),
+        })
+    }
+
     /// Total borrowed icUSD for a specific collateral type
     pub fn total_debt_for_collateral(&self, ct: &CollateralType) -> ICUSD {
-        match self.collateral_to_vault_ids.get(ct) {
+        let booked = match self.collateral_to_vault_ids.get(ct) {
             Some(vault_ids) => vault_ids
                 .iter()
                 .filter_map(|id| self.vault_id_to_vaults.get(id))
                 .map(|v| v.borrowed_icusd_amount)
                 .sum(),
             None => ICUSD::new(0),
-        }
+        };
+        // Redistributed debt not yet booked on its vaults.
+        let pending = self
+            .redistribution_indexes
+            .get(ct)
+            .map_or(0, |index| index.pending_debt);
+        booked + ICUSD::new(pending)
     }
 
     /// Total raw collateral amount for a specific collateral type
     pub fn total_collateral_for(&self, ct: &CollateralType) -> u64 {
-        match self.collateral_to_vault_ids.get(ct) {
+        let booked: u64 = match self.collateral_to_vault_ids.get(ct) {
             Some(vault_ids) => vault_ids
                 .iter()
                 .filter_map(|id| self.vault_id_to_vaults.get(id))
                 .map(|v| v.collateral_amount)
                 .sum(),
             None => 0,
-        }
+        };
+        let pending = self
+            .redistribution_indexes
+            .get(ct)
+            .map_or(0, |index| index.pending_collateral);
+        booked + pending
     }
 
     /// Total USD value of collateral for a specific collateral type (normalized by decimals).
@@ -3677,6 +4791,9 @@ impl State {
         }
         self.unindex_vault_by_collateral(&vault.collateral_type, vault_id);
         self.unindex_vault_cr(vault_id);
+        // Removal is the move to `Closed`, which is derived, not stored.
+        self.vault_statuses.remove(&vault_id);
+        crate::redistribution::forget(self, &vault);
         Some(vault)
     }
 
@@ -3770,6 +4887,7 @@ impl State {
     /// Insert or move a vault's entry in `vault_cr_index`. Idempotent: any
     /// prior entry for `vault_id` is removed first. Reads the vault's current
     /// CR via `compute_collateral_ratio` and the cached collateral price.
+    /// Also re-keys the vault in `liquidation_index`.
     ///
     /// **Call after every mutation that changes the vault's debt or
     /// collateral.** Single mutator pattern: every call site must mutate
@@ -3817,15 +4935,28 @@ impl State {
             .entry(key)
             .or_insert_with(BTreeSet::new)
             .insert(vault_id);
+        let ct = if vault.collateral_type == Principal::anonymous() {
+            self.icp_ledger_principal
+        } else {
+            vault.collateral_type
+        };
+        self.vault_cr_index_by_collateral
+            .entry(ct)
+            .or_default()
+            .entry(key)
+            .or_default()
+            .insert(vault_id);
+        crate::liquidation_index::reindex(self, vault_id);
     }
 
-    /// Drop a vault from `vault_cr_index`. Idempotent — safe to call on a
-    /// vault that was never indexed.
+    /// Drop a vault from `vault_cr_index` and `liquidation_index`.
+    /// Idempotent — safe to call on a vault that was never indexed.
     ///
     /// Call from `close_vault` and from any cleanup that removes the vault
     /// entirely from `vault_id_to_vaults` (e.g., the full-liquidation branch
     /// of `state::liquidate_vault`).
     pub fn unindex_vault_cr(&mut self, vault_id: u64) {
+        crate::liquidation_index::unindex(self, vault_id);
         // The reverse lookup (vault_id → key) would speed this up but doubles
         // bookkeeping. Linear over the buckets in CR order is acceptable: at
         // current TVL the index is small, and the BTreeMap iteration short-
@@ -3842,6 +4973,51 @@ impl State {
         if let Some(k) = empty_key {
             self.vault_cr_index.remove(&k);
         }
+
+        // The vault may already be gone from `vault_id_to_vaults`, so its
+        // collateral type is unknown here; there are only a handful.
+        let mut empty_ct: Option<CollateralType> = None;
+        for (ct, index) in self.vault_cr_index_by_collateral.iter_mut() {
+            let mut found: Option<(u64, bool)> = None;
+            for (key, bucket) in index.iter_mut() {
+                if bucket.remove(&vault_id) {
+                    found = Some((*key, bucket.is_empty()));
+                    break;
+                }
+            }
+            if let Some((key, bucket_empty)) = found {
+                if bucket_empty {
+                    index.remove(&key);
+                }
+                if index.is_empty() {
+                    empty_ct = Some(*ct);
+                }
+                break;
+            }
+        }
+        if let Some(ct) = empty_ct {
+            self.vault_cr_index_by_collateral.remove(&ct);
+        }
+    }
+
+    /// Live vaults of `collateral_type` in ascending CR-key order, from
+    /// `vault_cr_index_by_collateral`. Keys are as of each vault's last
+    /// re-key (interest accrual does not re-key), so callers that need exact
+    /// ordering re-sort by current CR.
+    pub fn vaults_by_cr_for<'a>(
+        &'a self,
+        collateral_type: &CollateralType,
+    ) -> impl Iterator<Item = &'a Vault> + 'a {
+        let ct = if collateral_type == &Principal::anonymous() {
+            self.icp_ledger_principal
+        } else {
+            *collateral_type
+        };
+        self.vault_cr_index_by_collateral
+            .get(&ct)
+            .into_iter()
+            .flat_map(|index| index.values().flatten())
+            .filter_map(move |id| self.vault_id_to_vaults.get(id))
     }
 
     /// Returns true if `vault_id` is within `liquidation_ordering_tolerance`
@@ -4062,7 +5238,16 @@ impl State {
         }
     }
 
-    pub fn update_total_collateral_ratio_and_mode(&mut self, rate: UsdIcp) {
+    pub fn get_recovery_exit_band(&self) -> Ratio {
+        self.recovery_exit_band
+            .unwrap_or(crate::mode::DEFAULT_RECOVERY_EXIT_BAND)
+    }
+
+    /// Re-evaluate the total collateral ratio and move the mode it implies
+    /// (`mode::collateral_ratio_target`). A move toward a less restrictive
+    /// mode waits until every collateral backing debt has a price fresh at
+    /// `now_ns`.
+    pub fn update_total_collateral_ratio_and_mode(&mut self, rate: UsdIcp, now_ns: u64) {
         let previous_mode = self.mode;
         let new_total_collateral_ratio = self.compute_total_collateral_ratio(rate);
         self.total_collateral_ratio = new_total_collateral_ratio;
@@ -4085,8 +5270,9 @@ impl State {
         // If an admin has manually set the mode, don't override it automatically.
         // Exception: if collateral ratio drops below 100%, always go ReadOnly for safety.
         if self.manual_mode_override {
-            if new_total_collateral_ratio < Ratio::from(dec!(1.0)) {
-                self.mode = Mode::ReadOnly;
+            if new_total_collateral_ratio < Ratio::from(dec!(1.0))
+                && self.enter_read_only(ModeTransitionReason::Insolvency)
+            {
                 log!(
                     crate::DEBUG,
                     "[update_mode] manual override active but ratio < 100%, forcing ReadOnly"
@@ -4101,19 +5287,44 @@ impl State {
         // Exception: TCR < 100% still forces ReadOnly for safety.
         if self.mode_triggered_by_oracle {
             if new_total_collateral_ratio < Ratio::from(dec!(1.0)) {
-                self.mode = Mode::ReadOnly;
+                self.enter_read_only(ModeTransitionReason::Insolvency);
             }
             return;
         }
 
-        if new_total_collateral_ratio < dynamic_threshold {
-            self.mode = Mode::Recovery;
-        } else {
-            self.mode = Mode::GeneralAvailability;
-        }
-
         if new_total_collateral_ratio < Ratio::from(dec!(1.0)) {
-            self.mode = Mode::ReadOnly;
+            self.enter_read_only(ModeTransitionReason::Insolvency);
+        } else {
+            let target = crate::mode::collateral_ratio_target(
+                previous_mode,
+                new_total_collateral_ratio,
+                dynamic_threshold,
+                self.get_recovery_exit_band(),
+                !self.price_deviation_holds.is_empty(),
+            );
+            if crate::mode::is_relaxation(previous_mode, target) {
+                if let Some(ct) = crate::mode::stale_price_blocking_relaxation(self, now_ns) {
+                    log!(
+                        crate::DEBUG,
+                        "[update_mode] staying in {} instead of {}: no fresh price for {}",
+                        previous_mode,
+                        target,
+                        ct
+                    );
+                    return;
+                }
+            }
+            if let Err(e) =
+                self.transition_mode(previous_mode, target, ModeTransitionReason::CollateralRatio)
+            {
+                log!(
+                    crate::DEBUG,
+                    "[update_mode] staying in {} instead of {}: {}",
+                    previous_mode,
+                    target,
+                    e
+                );
+            }
         }
 
         if previous_mode != self.mode {
@@ -4166,6 +5377,7 @@ impl State {
         self.index_vault_by_collateral(collateral_type, vault_id);
         // Wave-8b LIQ-002: insert into the sorted-troves CR index.
         self.reindex_vault_cr(vault_id);
+        crate::redistribution::register(self, &vault);
     }
 
     pub fn close_vault(&mut self, vault_id: u64) {
@@ -4407,6 +5619,261 @@ impl State {
         repay_amount.min(vault.borrowed_icusd_amount)
     }
 
+    /// The vault a full liquidation's leftover collateral is credited to
+    /// under `LiquidationRebateMode::CollateralTopUp`: `owner`'s lowest-CR
+    /// other vault of `collateral_type` that still carries debt. `None` when
+    /// there is none and the leftover is paid out instead.
+    pub fn liquidation_rebate_target(
+        &self,
+        owner: Principal,
+        collateral_type: CollateralType,
+        source_vault_id: u64,
+    ) -> Option<u64> {
+        self.principal_to_vault_ids
+            .get(&owner)
+            .into_iter()
+            .flatten()
+            .filter(|id| **id != source_vault_id)
+            .filter_map(|id| self.vault_id_to_vaults.get(id))
+            .filter(|v| v.collateral_type == collateral_type && v.borrowed_icusd_amount.0 > 0)
+            .map(|v| {
+                (
+                    crate::compute_collateral_ratio(v, UsdIcp::from(Decimal::ZERO), self),
+                    v.vault_id,
+                )
+            })
+            .min()
+            .map(|(_, vault_id)| vault_id)
+    }
+
+    /// Collateral a partial liquidation repaying `repay` takes from `vault`,
+    /// as `(total_seized, protocol_cut, to_liquidator)`. The seizure is the
+    /// repay value times the liquidation bonus, capped at the vault's
+    /// collateral; the protocol keeps its share of the bonus portion.
+    pub fn partial_liquidation_split(
+        &self,
+        vault: &Vault,
+        repay: ICUSD,
+        price: Decimal,
+        decimals: u8,
+    ) -> (ICP, u64, ICP) {
+        let liq_bonus = self.get_liquidation_bonus_for(&vault.collateral_type);
+        let protocol_share = self.get_liquidation_protocol_share();
+        let collateral_raw = crate::numeric::icusd_to_collateral_amount(repay, price, decimals);
+        let collateral_with_bonus = ICP::from(collateral_raw) * liq_bonus;
+        let total_to_seize = collateral_with_bonus.min(ICP::from(vault.collateral_amount));
+
+        let bonus_portion = total_to_seize.to_u64().saturating_sub(collateral_raw);
+        let protocol_cut = (Decimal::from(bonus_portion) * protocol_share.0)
+            .to_u64()
+            .unwrap_or(0);
+        let to_liquidator = ICP::from(total_to_seize.to_u64() - protocol_cut);
+        (total_to_seize, protocol_cut, to_liquidator)
+    }
+
+    /// What `liquidate_vault_partial` would do to `vault_id` if asked to repay
+    /// `icusd_amount` (default: the full debt) at the cached price at
+    /// `now`: the same liquidatability check, partial cap, dust round-up,
+    /// liquidation cap and collateral split, without executing anything.
+    pub fn preview_liquidation(
+        &self,
+        vault_id: u64,
+        icusd_amount: Option<ICUSD>,
+        now: u64,
+    ) -> Result<crate::LiquidationPreview, String> {
+        let vault = self
+            .vault_id_to_vaults
+            .get(&vault_id)
+            .ok_or_else(|| format!("Vault #{} not found", vault_id))?;
+        let ct = &vault.collateral_type;
+        let price = self.get_collateral_price_decimal(ct).ok_or_else(|| {
+            "No price available for collateral. Price feed may be down.".to_string()
+        })?;
+        let config = self
+            .get_collateral_config(ct)
+            .ok_or_else(|| "Collateral type not configured.".to_string())?;
+        let ratio = crate::compute_collateral_ratio(vault, UsdIcp::from(price), self);
+        let min_liq_ratio = self.get_min_liquidation_ratio_for(ct);
+        let allowed = self
+            .get_collateral_status(ct)
+            .map_or(true, |status| status.allows_liquidation());
+        let liquidatable = allowed && ratio < min_liq_ratio;
+
+        let seizure_cap = crate::liquidation_caps::seizable(self, ct, now);
+        let (max_liquidatable, repay, uncapped_repay) = if liquidatable {
+            let max_liquidatable = self.compute_partial_liquidation_cap(vault, UsdIcp::from(price));
+            let capped = icusd_amount
+                .unwrap_or(vault.borrowed_icusd_amount)
+                .min(max_liquidatable)
+                .min(vault.borrowed_icusd_amount);
+            let rounded =
+                crate::vault::round_up_partial_liq_dust(vault, capped, config.min_vault_debt);
+            // A cap with nothing left previews a zero repay.
+            let (repay, bound) = crate::liquidation_caps::cap_repay(
+                self,
+                vault,
+                rounded,
+                price,
+                config.decimals,
+                now,
+            )
+            .unwrap_or((ICUSD::new(0), seizure_cap));
+            (max_liquidatable, repay, bound.map(|_| rounded))
+        } else {
+            (ICUSD::new(0), ICUSD::new(0), None)
+        };
+        let (seized, protocol_cut, to_liquidator) = if repay.0 > 0 {
+            self.partial_liquidation_split(vault, repay, price, config.decimals)
+        } else {
+            (ICP::new(0), 0, ICP::new(0))
+        };
+
+        Ok(crate::LiquidationPreview {
+            vault_id,
+            collateral_type: *ct,
+            debt_e8s: vault.borrowed_icusd_amount.to_u64(),
+            collateral_amount: vault.collateral_amount,
+            collateral_decimals: config.decimals,
+            collateral_price_usd: price.to_f64().unwrap_or(0.0),
+            collateral_ratio: ratio.to_f64(),
+            min_liquidation_ratio: min_liq_ratio.to_f64(),
+            liquidatable,
+            max_liquidatable_debt_e8s: max_liquidatable.to_u64(),
+            repay_e8s: repay.to_u64(),
+            liquidation_bonus: self.get_liquidation_bonus_for(ct).to_f64(),
+            collateral_seized: seized.to_u64(),
+            protocol_cut,
+            collateral_to_liquidator: to_liquidator.to_u64(),
+            min_liquidation_amount_e8s: self.min_icusd_amount.to_u64(),
+            ckstable_repay_fee: self.ckstable_repay_fee.to_f64(),
+            seizure_cap,
+            uncapped_repay_e8s: uncapped_repay.map(|repay| repay.to_u64()),
+        })
+    }
+
+    /// Quote a partial liquidation that brings `vault` to `target_cr`.
+    ///
+    /// Repaying `R` icUSD seizes `R * bonus` of collateral value, so the
+    /// post-liquidation CR is `(CV - R * bonus) / (D - R)`; solving for the
+    /// target gives `R = (D * target - CV) / (target - bonus)`. The result is
+    /// then capped by `max_partial_liquidation_ratio` of the debt, the
+    /// protocol's own partial cap, and the liquidator's `max_icusd`, and a
+    /// residual below `min_vault_debt` is rounded up to the full debt exactly
+    /// as `liquidate_vault_partial` would (rejected if that breaks `max_icusd`).
+    pub fn quote_liquidation_to_target(
+        &self,
+        vault: &Vault,
+        target_cr: Ratio,
+        max_icusd: ICUSD,
+    ) -> Result<crate::LiquidationTargetQuote, String> {
+        use crate::LiquidationTargetLimit;
+
+        let ct = &vault.collateral_type;
+        let config = self
+            .get_collateral_config(ct)
+            .ok_or_else(|| "Collateral type not configured.".to_string())?;
+        let price = config
+            .last_price
+            .and_then(Decimal::from_f64)
+            .ok_or_else(|| {
+                "No price available for collateral. Price feed may be down.".to_string()
+            })?;
+        let collateral_value =
+            crate::numeric::collateral_usd_value(vault.collateral_amount, price, config.decimals);
+        let debt = vault.borrowed_icusd_amount;
+        if debt.0 == 0 {
+            return Err(format!("Vault #{} has no debt", vault.vault_id));
+        }
+        let cr_before = Decimal::from(collateral_value.to_u64()) / Decimal::from(debt.to_u64());
+        if target_cr.0 <= cr_before {
+            return Err(format!(
+                "Target CR {} must be above the vault's current CR {}",
+                target_cr.to_f64(),
+                cr_before.to_f64().unwrap_or(0.0)
+            ));
+        }
+        let liq_bonus = self.get_liquidation_bonus_for(ct);
+        if target_cr <= liq_bonus {
+            return Err(format!(
+                "Target CR {} is unreachable: it must exceed the liquidation bonus {}",
+                target_cr.to_f64(),
+                liq_bonus.to_f64()
+            ));
+        }
+
+        // Round up so the quoted amount actually reaches the target.
+        let required = ICUSD::new(
+            ((Decimal::from(debt.to_u64()) * target_cr.0
+                - Decimal::from(collateral_value.to_u64()))
+                / (target_cr.0 - liq_bonus.0))
+                .ceil()
+                .to_u64()
+                .unwrap_or(u64::MAX),
+        );
+        let max_partial = debt * self.max_partial_liquidation_ratio;
+        let protocol_cap = self.compute_partial_liquidation_cap(vault, UsdIcp::from(price));
+
+        let mut repay = required.min(debt);
+        let mut limited_by = LiquidationTargetLimit::Target;
+        for (bound, limit) in [
+            (max_partial, LiquidationTargetLimit::MaxPartialRatio),
+            (protocol_cap, LiquidationTargetLimit::ProtocolCap),
+            (max_icusd, LiquidationTargetLimit::MaxIcusd),
+        ] {
+            if bound < repay {
+                repay = bound;
+                limited_by = limit;
+            }
+        }
+        let min_vault_debt = config.min_vault_debt;
+        let rounded = crate::vault::round_up_partial_liq_dust(vault, repay, min_vault_debt);
+        if rounded != repay {
+            if rounded > max_icusd {
+                return Err(format!(
+                    "Repaying {} e8s would leave {} e8s, below the minimum vault debt; \
+                     raise max_icusd to the full debt of {} e8s",
+                    repay.to_u64(),
+                    debt.saturating_sub(repay).to_u64(),
+                    debt.to_u64()
+                ));
+            }
+            repay = rounded;
+            limited_by = LiquidationTargetLimit::DustRoundUp;
+        }
+        if repay.0 == 0 {
+            return Err("Cannot liquidate zero amount".to_string());
+        }
+
+        let seized_value = (repay * liq_bonus).min(collateral_value);
+        let debt_after = debt.saturating_sub(repay);
+        let cr_after = if debt_after.0 == 0 {
+            Ratio::from(Decimal::MAX)
+        } else {
+            Ratio::from(
+                Decimal::from(collateral_value.saturating_sub(seized_value).to_u64())
+                    / Decimal::from(debt_after.to_u64()),
+            )
+        };
+
+        Ok(crate::LiquidationTargetQuote {
+            vault_id: vault.vault_id,
+            debt_before_e8s: debt.to_u64(),
+            collateral_value_before_e8s: collateral_value.to_u64(),
+            cr_before: cr_before.to_f64().unwrap_or(0.0),
+            target_cr: target_cr.to_f64(),
+            liquidation_bonus: liq_bonus.to_f64(),
+            required_repay_e8s: required.to_u64(),
+            max_partial_repay_e8s: max_partial.to_u64(),
+            protocol_cap_e8s: protocol_cap.to_u64(),
+            max_icusd_e8s: max_icusd.to_u64(),
+            repay_e8s: repay.to_u64(),
+            limited_by,
+            projected_debt_after_e8s: debt_after.to_u64(),
+            projected_cr_after: cr_after.to_f64(),
+            target_reached: cr_after >= target_cr,
+        })
+    }
+
     // ─── Wave-8e LIQ-005: deficit-account helpers ───
 
     /// Increment `protocol_deficit_icusd` by `shortfall` and return the
@@ -4460,8 +5927,9 @@ impl State {
         if self.protocol_deficit_icusd.0 < self.deficit_readonly_threshold_e8s {
             return false;
         }
-        self.mode = Mode::ReadOnly;
-        true
+        // False when the transition is only shadowed.
+        self.enter_read_only(ModeTransitionReason::DeficitThreshold);
+        self.mode == Mode::ReadOnly
     }
 
     /// Wave-9b DOS-006: compute the heavy aggregates that
@@ -4542,7 +6010,7 @@ impl State {
         }
     }
 
-    /// Wave-9b DOS-006/-007: refresh both aggregate snapshots to the
+    /// Wave-9b DOS-006/-007: refresh the aggregate snapshots to the
     /// current state at `now_ns`. Called from the existing 5-minute
     /// XRC tick after `check_vaults` (which already iterates every
     /// vault) so the cache stays warm without a new timer.
@@ -4551,6 +6019,7 @@ impl State {
         let treasury = self.compute_treasury_stats_snapshot();
         self.protocol_status_snapshot = Some((now_ns, proto));
         self.treasury_stats_snapshot = Some((now_ns, treasury));
+        self.public_stats_snapshot = Some(crate::public_stats::compute(self, now_ns));
     }
 
     /// Phase 1b Task 6: returns the EVM RPC canister principal override, if set.
@@ -4743,9 +6212,12 @@ impl State {
             *collateral_type
         };
 
-        // Collect eligible vaults sorted by CR ascending
+        // Collect eligible vaults sorted by CR ascending. The per-collateral
+        // CR index limits the walk to this collateral's vaults and yields
+        // them nearly sorted; the exact sort below absorbs keys that drifted
+        // with accrued interest since their last re-key.
         let mut vault_entries: Vec<(Decimal, VaultId)> = Vec::new();
-        for vault in self.vault_id_to_vaults.values() {
+        for vault in self.vaults_by_cr_for(&resolved_ct) {
             if vault.borrowed_icusd_amount == 0 {
                 continue; // skip zero-debt vaults
             }
@@ -4762,14 +6234,6 @@ impl State {
             if vault.bot_processing || crate::guard::is_vault_liquidating(vault.vault_id) {
                 continue;
             }
-            let vault_ct = if vault.collateral_type == Principal::anonymous() {
-                self.icp_ledger_principal
-            } else {
-                vault.collateral_type
-            };
-            if vault_ct != resolved_ct {
-                continue;
-            }
             let cr = crate::compute_collateral_ratio(vault, collateral_price, self);
             vault_entries.push((cr.0, vault.vault_id));
         }
@@ -4976,21 +6440,13 @@ impl State {
             *collateral_type
         };
         let mut total = ICUSD::new(0);
-        for vault in self.vault_id_to_vaults.values() {
+        for vault in self.vaults_by_cr_for(&resolved_ct) {
             if vault.borrowed_icusd_amount == 0 {
                 continue;
             }
             if vault.bot_processing || crate::guard::is_vault_liquidating(vault.vault_id) {
                 continue;
             }
-            let vault_ct = if vault.collateral_type == Principal::anonymous() {
-                self.icp_ledger_principal
-            } else {
-                vault.collateral_type
-            };
-            if vault_ct != resolved_ct {
-                continue;
-            }
             total += vault.borrowed_icusd_amount;
         }
         total
@@ -5129,9 +6585,11 @@ impl State {
 
         for vault_ids in self.principal_to_vault_ids.values() {
             for vault_id in vault_ids {
-                if self.vault_id_to_vaults.get(vault_id).is_none() {
-                    panic!("Not all vault ids are in the id -> Vault map.")
-                }
+                ensure!(
+                    self.vault_id_to_vaults.contains_key(vault_id),
+                    "Not all vault ids are in the id -> Vault map: {} is missing",
+                    vault_id
+                );
             }
         }
 
@@ -5195,7 +6653,8 @@ impl State {
 ///
 /// No-ops (returns `false`) if the window is disabled (`window_ns == 0`),
 /// the recorded debt is zero, the ceiling is disabled (`ceiling == 0`), or
-/// the latch is already tripped.
+/// the latch is already tripped. With circuit breakers in shadow mode a
+/// trip is only noted (see `shadow`).
 pub fn record_recent_liquidation(state: &mut State, debt_e8s: u64, now_ns: u64) -> bool {
     if debt_e8s == 0 || state.breaker_window_ns == 0 {
         return false;
@@ -5209,6 +6668,17 @@ pub fn record_recent_liquidation(state: &mut State, debt_e8s: u64, now_ns: u64)
     }
     let total = state.windowed_liquidation_total(now_ns);
     if total >= state.breaker_window_debt_ceiling_e8s {
+        if crate::shadow::is_shadowed(state, crate::shadow::ShadowComponent::CircuitBreakers) {
+            crate::shadow::note(
+                state,
+                crate::shadow::ShadowAction::TripLiquidationBreaker {
+                    windowed_total_e8s: total,
+                    ceiling_e8s: state.breaker_window_debt_ceiling_e8s,
+                },
+            );
+            crate::shadow::flush(state, now_ns);
+            return false;
+        }
         state.liquidation_breaker_tripped = true;
         return true;
     }
@@ -5265,8 +6735,56 @@ pub(crate) fn distribute_across_vaults(
     result
 }
 
+/// A redemption base rate halves every 12 hours without redemptions.
+pub const REDEMPTION_BASE_RATE_HALF_LIFE_NS: u64 = 12 * 3_600 * 1_000_000_000;
+
+/// Share of the redeemed fraction of the debt a redemption adds to the base
+/// rate.
+pub const REDEMPTION_BASE_RATE_BETA: Ratio = Ratio::new(dec!(0.5));
+
+/// 0.5^(1/720): one minute of the half-life.
+const BASE_RATE_MINUTE_DECAY: Decimal = dec!(0.999037758833783);
+
+/// `base_rate` after `elapsed_ns` without redemptions. Decays once per whole
+/// minute, so a rate read twice within a minute does not move.
+pub fn decay_base_rate(base_rate: Ratio, elapsed_ns: u64) -> Ratio {
+    let half_lives = elapsed_ns / REDEMPTION_BASE_RATE_HALF_LIFE_NS;
+    if half_lives >= 64 {
+        return Ratio::from(Decimal::ZERO);
+    }
+    let mut minutes = elapsed_ns % REDEMPTION_BASE_RATE_HALF_LIFE_NS / 60_000_000_000;
+    let mut factor = Decimal::ONE;
+    let mut step = BASE_RATE_MINUTE_DECAY;
+    while minutes > 0 {
+        if minutes & 1 == 1 {
+            factor *= step;
+        }
+        step *= step;
+        minutes >>= 1;
+    }
+    Ratio::from(base_rate.0 * factor / Decimal::from(1u64 << half_lives))
+}
+
+/// The base rate after redeeming `redeemed_amount` of
+/// `total_borrowed_icusd_amount`: the decayed rate plus `BETA` times the
+/// redeemed fraction, capped at 100%.
+pub fn base_rate_after_redemption(
+    decayed_base_rate: Ratio,
+    redeemed_amount: ICUSD,
+    total_borrowed_icusd_amount: ICUSD,
+) -> Ratio {
+    if total_borrowed_icusd_amount == 0 {
+        return decayed_base_rate;
+    }
+    (decayed_base_rate + redeemed_amount / total_borrowed_icusd_amount * REDEMPTION_BASE_RATE_BETA)
+        .min(Ratio::from(Decimal::ONE))
+}
+
+/// The fee for a redemption `elapsed_ns` after the one that left
+/// `current_base_rate`: the base rate the redemption leaves behind, clamped
+/// to the floor and ceiling.
 pub fn compute_redemption_fee(
-    elapsed_hours: u64,
+    elapsed_ns: u64,
     redeemed_amount: ICUSD,
     total_borrowed_icusd_amount: ICUSD,
     current_base_rate: Ratio,
@@ -5276,18 +6794,13 @@ pub fn compute_redemption_fee(
     if total_borrowed_icusd_amount == 0 {
         return Ratio::from(Decimal::ZERO);
     }
-    const REEDEMED_PROPORTION: Ratio = Ratio::new(dec!(0.5)); // 0.5
-    const DECAY_FACTOR: Ratio = Ratio::new(dec!(0.94));
-
-    log!(
-        crate::INFO,
-        "current_base_rate: {current_base_rate}, elapsed_hours: {elapsed_hours}"
-    );
-
-    let rate = current_base_rate * DECAY_FACTOR.pow(elapsed_hours);
-    let total_rate = rate + redeemed_amount / total_borrowed_icusd_amount * REEDEMED_PROPORTION;
-    debug_assert!(total_rate < Ratio::from(dec!(1.0)));
-    total_rate.max(fee_floor).min(fee_ceiling)
+    base_rate_after_redemption(
+        decay_base_rate(current_base_rate, elapsed_ns),
+        redeemed_amount,
+        total_borrowed_icusd_amount,
+    )
+    .max(fee_floor)
+    .min(fee_ceiling)
 }
 
 pub fn mutate_state<F, R>(f: F) -> R
@@ -5314,6 +6827,11 @@ pub fn replace_state(state: State) {
     });
 }
 
+/// Takes the current state out, to be put back with `replace_state`.
+pub fn take_state() -> State {
+    __STATE.with(|s| s.borrow_mut().take().expect("State not initialized!"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -6173,6 +7691,41 @@ mod tests {
         assert_eq!(vault_after.borrowed_icusd_amount.0, 500_000_000);
     }
 
+    #[test]
+    fn test_pending_interest_matches_accrual() {
+        let mut state = accrual_test_state();
+        let icp = state.icp_ledger_principal;
+        state.vault_id_to_vaults.insert(
+            1,
+            Vault {
+                owner: Principal::anonymous(),
+                vault_id: 1,
+                collateral_amount: 150_000_000,
+                borrowed_icusd_amount: ICUSD::new(500_000_000),
+                collateral_type: icp,
+                last_accrual_time: 0,
+                accrued_interest: ICUSD::new(0),
+                bot_processing: false,
+            },
+        );
+
+        let half_year = crate::numeric::NANOS_PER_YEAR / 2;
+        let pending = state.pending_interest(1, half_year);
+        assert!(pending.0 > 0);
+        // Reading does not book anything.
+        assert_eq!(
+            state.vault_id_to_vaults[&1].borrowed_icusd_amount.0,
+            500_000_000
+        );
+
+        state.accrue_single_vault(1, half_year);
+        let vault = &state.vault_id_to_vaults[&1];
+        assert_eq!(vault.accrued_interest, pending);
+        assert_eq!(vault.borrowed_icusd_amount.0, 500_000_000 + pending.0);
+        assert_eq!(state.pending_interest(1, half_year), ICUSD::new(0));
+        assert_eq!(state.pending_interest(2, half_year), ICUSD::new(0));
+    }
+
     #[test]
     fn test_accrue_all_vault_interest_multiple_vaults() {
         let mut state = accrual_test_state();
@@ -6724,6 +8277,72 @@ mod tests {
         );
     }
 
+    #[test]
+    fn redemption_walks_the_per_collateral_cr_index() {
+        let mut state = test_state();
+        let icp_ct = state.icp_collateral_type();
+        let other_ct = Principal::from_slice(&[7]);
+        state
+            .collateral_configs
+            .get_mut(&icp_ct)
+            .unwrap()
+            .last_price = Some(5.0);
+        // CR 5.0, CR 1.25, and a low-CR vault on another collateral.
+        state.open_vault(audit_vault(1, icp_ct, 1_000_000_000, 1_000_000_000));
+        state.open_vault(audit_vault(2, icp_ct, 500_000_000, 2_000_000_000));
+        state.open_vault(audit_vault(3, other_ct, 100_000_000, 2_000_000_000));
+
+        let indexed = |state: &State, ct: &Principal| -> BTreeSet<u64> {
+            state.vaults_by_cr_for(ct).map(|v| v.vault_id).collect()
+        };
+        assert_eq!(indexed(&state, &icp_ct), BTreeSet::from([1, 2]));
+        assert_eq!(indexed(&state, &other_ct), BTreeSet::from([3]));
+
+        let price = UsdIcp::from(rust_decimal_macros::dec!(5.0));
+        let results = state.redeem_on_vaults(ICUSD::new(100_000_000), price, &icp_ct);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].vault_id, 2, "lowest-CR vault is redeemed first");
+        assert_eq!(results[0].icusd_redeemed_e8s, 100_000_000);
+        assert_eq!(
+            state.vault_id_to_vaults[&3].borrowed_icusd_amount,
+            ICUSD::new(2_000_000_000)
+        );
+
+        state.remove_vault_and_unindex(2);
+        assert_eq!(indexed(&state, &icp_ct), BTreeSet::from([1]));
+        state.remove_vault_and_unindex(3);
+        assert!(!state.vault_cr_index_by_collateral.contains_key(&other_ct));
+    }
+
+    #[test]
+    fn collateral_utilization_reports_headroom_after_in_flight_borrows() {
+        let mut state = test_state();
+        let icp_ct = state.icp_collateral_type();
+        state
+            .collateral_configs
+            .get_mut(&icp_ct)
+            .unwrap()
+            .debt_ceiling = 1_000;
+        state.open_vault(audit_vault(1, icp_ct, 500_000_000, 300));
+        state.open_vault(audit_vault(2, icp_ct, 500_000_000, 200));
+
+        let utilization = state.collateral_utilization(&icp_ct, 100).unwrap();
+        assert_eq!(utilization.total_debt, 500);
+        assert_eq!(utilization.in_flight_borrows, 100);
+        assert_eq!(utilization.debt_ceiling, 1_000);
+        assert_eq!(utilization.headroom, 400);
+
+        // Interest can push debt past the ceiling; headroom bottoms out at 0.
+        state.open_vault(audit_vault(3, icp_ct, 500_000_000, 700));
+        assert_eq!(
+            state.collateral_utilization(&icp_ct, 0).unwrap().headroom,
+            0
+        );
+        assert!(state
+            .collateral_utilization(&Principal::from_slice(&[7]), 0)
+            .is_none());
+    }
+
     #[test]
     fn red001_total_redeemable_debt_excludes_locked_and_bot_vaults() {
         let mut state = test_state();
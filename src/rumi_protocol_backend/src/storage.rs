@@ -1,10 +1,14 @@
 use crate::event::Event;
+use candid::{CandidType, Deserialize};
+use ciborium::value::Value;
 use ic_stable_structures::{
     log::{Log as StableLog, NoSuchEntry},
     memory_manager::{MemoryId, MemoryManager, VirtualMemory},
-    DefaultMemoryImpl, Memory,
+    DefaultMemoryImpl, Memory, StableBTreeMap,
 };
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ops::Range;
 
 const LOG_INDEX_MEMORY_ID: MemoryId = MemoryId::new(0);
 const LOG_DATA_MEMORY_ID: MemoryId = MemoryId::new(1);
@@ -23,28 +27,99 @@ const STATE_MEMORY_ID: MemoryId = MemoryId::new(4);
 // which matches today's behaviour.
 const EVENT_TS_INDEX_MEMORY_ID: MemoryId = MemoryId::new(5);
 const EVENT_TS_DATA_MEMORY_ID: MemoryId = MemoryId::new(6);
+// Compact event encoding (see "Event Encoding" below): the append-only table
+// of interned CBOR map keys, the compact log historical events are migrated
+// into, and one word recording which of the two logs is live.
+const EVENT_KEYS_INDEX_MEMORY_ID: MemoryId = MemoryId::new(7);
+const EVENT_KEYS_DATA_MEMORY_ID: MemoryId = MemoryId::new(8);
+const COMPACT_LOG_INDEX_MEMORY_ID: MemoryId = MemoryId::new(9);
+const COMPACT_LOG_DATA_MEMORY_ID: MemoryId = MemoryId::new(10);
+const EVENT_LOG_LAYOUT_MEMORY_ID: MemoryId = MemoryId::new(11);
+// Bounded ring of CBOR-encoded critical log entries, keyed by sequence
+// number, so they outlive the heap log buffers across upgrades.
+const CRITICAL_LOG_MEMORY_ID: MemoryId = MemoryId::new(12);
+// Which events the `State` snapshot in `STATE_MEMORY_ID` already covers; see
+// "State Checkpoints" below.
+const STATE_CHECKPOINT_MEMORY_ID: MemoryId = MemoryId::new(13);
+// ICRC-3 log of protocol-initiated icUSD ledger operations (see
+// `icrc3_log`), plus the icUSD ledger block each one was recorded for so a
+// deduplicated retry is not logged twice.
+const PROTOCOL_BLOCKS_INDEX_MEMORY_ID: MemoryId = MemoryId::new(14);
+const PROTOCOL_BLOCKS_DATA_MEMORY_ID: MemoryId = MemoryId::new(15);
+const PROTOCOL_BLOCK_BY_LEDGER_BLOCK_MEMORY_ID: MemoryId = MemoryId::new(16);
+// The vaults by id and their `(owner, vault id)` index, brought up to date
+// at each checkpoint (see `vault_store`).
+const VAULTS_MEMORY_ID: MemoryId = MemoryId::new(17);
+const VAULT_OWNERS_MEMORY_ID: MemoryId = MemoryId::new(18);
+// Index-aligned causality tokens, one per event recorded since it shipped
+// (see `event_ordering`).
+const EVENT_TOKENS_INDEX_MEMORY_ID: MemoryId = MemoryId::new(19);
+const EVENT_TOKENS_DATA_MEMORY_ID: MemoryId = MemoryId::new(20);
+// The pending margin, excess and redemption transfer queues, brought up to
+// date at each checkpoint alongside the vaults.
+const PENDING_MARGIN_MEMORY_ID: MemoryId = MemoryId::new(21);
+const PENDING_EXCESS_MEMORY_ID: MemoryId = MemoryId::new(22);
+const PENDING_REDEMPTION_MEMORY_ID: MemoryId = MemoryId::new(23);
 
 type VMem = VirtualMemory<DefaultMemoryImpl>;
 type EventLog = StableLog<Vec<u8>, VMem, VMem>;
 type SnapshotLog = StableLog<Vec<u8>, VMem, VMem>;
 type TimestampLog = StableLog<u64, VMem, VMem>;
+type TokenLog = StableLog<crate::event_ordering::CausalityToken, VMem, VMem>;
+type KeyLog = StableLog<String, VMem, VMem>;
+type CriticalLogRing = StableBTreeMap<u64, Vec<u8>, VMem>;
+type ProtocolBlockLog = StableLog<Vec<u8>, VMem, VMem>;
+type LedgerBlockIndex = StableBTreeMap<u64, u64, VMem>;
+type VaultStore = crate::vault_store::StableVaultStore<VMem>;
+type PendingTransferStore = crate::vault_store::StablePendingTransfers<VMem>;
 
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
         MemoryManager::init(DefaultMemoryImpl::default())
     );
 
-    /// The log of the state modifications.
-    static EVENTS: RefCell<EventLog> = MEMORY_MANAGER
+    /// The log of the state modifications: the original log until a
+    /// migration has switched over to the compact one.
+    static EVENTS: RefCell<EventLog> = RefCell::new(
+        match read_event_log_layout() {
+            EventLogLayout::Compact => init_compact_log(),
+            EventLogLayout::Legacy | EventLogLayout::Migrating => MEMORY_MANAGER
+                .with(|m|
+                      StableLog::init(
+                          m.borrow().get(LOG_INDEX_MEMORY_ID),
+                          m.borrow().get(LOG_DATA_MEMORY_ID)
+                      ).expect("failed to initialize stable log")
+                ),
+        }
+    );
+
+    /// Migration target while `EventLogLayout::Migrating`; moved into
+    /// `EVENTS` once it has caught up.
+    static COMPACT_EVENTS: RefCell<Option<EventLog>> = RefCell::new(
+        (read_event_log_layout() == EventLogLayout::Migrating).then(init_compact_log)
+    );
+
+    /// Interned CBOR map keys, position = id. Append-only.
+    static EVENT_KEYS: RefCell<KeyLog> = MEMORY_MANAGER
         .with(|m|
               RefCell::new(
                   StableLog::init(
-                      m.borrow().get(LOG_INDEX_MEMORY_ID),
-                      m.borrow().get(LOG_DATA_MEMORY_ID)
-                  ).expect("failed to initialize stable log")
+                      m.borrow().get(EVENT_KEYS_INDEX_MEMORY_ID),
+                      m.borrow().get(EVENT_KEYS_DATA_MEMORY_ID)
+                  ).expect("failed to initialize event key table")
               )
         );
 
+    /// Heap copy of `EVENT_KEYS`, loaded on first use.
+    static KEY_TABLE: RefCell<KeyTable> = RefCell::new(EVENT_KEYS.with(|keys| {
+        let keys = keys.borrow();
+        let mut table = KeyTable::default();
+        for key in keys.iter() {
+            table.push(key);
+        }
+        table
+    }));
+
     /// Hourly protocol snapshots for historical charts.
     static SNAPSHOTS: RefCell<SnapshotLog> = MEMORY_MANAGER
         .with(|m|
@@ -69,6 +144,61 @@ thread_local! {
                   ).expect("failed to initialize event timestamp log")
               )
         );
+
+    /// Index-aligned causality tokens: position N here is the token of
+    /// `EVENTS[N - offset]`, the offset being the events recorded before this
+    /// log shipped.
+    static EVENT_TOKENS: RefCell<TokenLog> = MEMORY_MANAGER
+        .with(|m|
+              RefCell::new(
+                  StableLog::init(
+                      m.borrow().get(EVENT_TOKENS_INDEX_MEMORY_ID),
+                      m.borrow().get(EVENT_TOKENS_DATA_MEMORY_ID)
+                  ).expect("failed to initialize event causality log")
+              )
+        );
+
+    /// Critical log entries, oldest first. See `record_critical_log`.
+    static CRITICAL_LOGS: RefCell<CriticalLogRing> = MEMORY_MANAGER
+        .with(|m| RefCell::new(StableBTreeMap::init(m.borrow().get(CRITICAL_LOG_MEMORY_ID))));
+
+    /// ICRC-3 blocks for protocol-initiated icUSD operations, oldest first.
+    static PROTOCOL_BLOCKS: RefCell<ProtocolBlockLog> = MEMORY_MANAGER
+        .with(|m|
+              RefCell::new(
+                  StableLog::init(
+                      m.borrow().get(PROTOCOL_BLOCKS_INDEX_MEMORY_ID),
+                      m.borrow().get(PROTOCOL_BLOCKS_DATA_MEMORY_ID)
+                  ).expect("failed to initialize protocol block log")
+              )
+        );
+
+    /// icUSD ledger block index -> position in `PROTOCOL_BLOCKS`.
+    static PROTOCOL_BLOCK_BY_LEDGER_BLOCK: RefCell<LedgerBlockIndex> = MEMORY_MANAGER
+        .with(|m| RefCell::new(StableBTreeMap::init(m.borrow().get(PROTOCOL_BLOCK_BY_LEDGER_BLOCK_MEMORY_ID))));
+
+    /// Stable copy of the vault maps as of the last checkpoint.
+    static VAULTS: RefCell<VaultStore> = MEMORY_MANAGER.with(|m| {
+        let m = m.borrow();
+        RefCell::new(VaultStore::init(m.get(VAULTS_MEMORY_ID), m.get(VAULT_OWNERS_MEMORY_ID)))
+    });
+
+    /// Stable copy of the pending transfer queues as of the last checkpoint.
+    static PENDING_TRANSFERS: RefCell<PendingTransferStore> = MEMORY_MANAGER.with(|m| {
+        let m = m.borrow();
+        RefCell::new(PendingTransferStore::init(
+            m.get(PENDING_MARGIN_MEMORY_ID),
+            m.get(PENDING_EXCESS_MEMORY_ID),
+            m.get(PENDING_REDEMPTION_MEMORY_ID),
+        ))
+    });
+
+    /// What the last checkpoint's vault sync did since this wasm started.
+    static LAST_VAULT_SYNC: Cell<Option<crate::vault_store::VaultStoreSync>> = const { Cell::new(None) };
+
+    /// Set while an upgrade's replay runs past `post_upgrade`; see
+    /// `seal_event_log`.
+    static EVENT_LOG_SEALED: Cell<bool> = const { Cell::new(false) };
 }
 
 pub struct EventIterator {
@@ -99,18 +229,366 @@ impl Iterator for EventIterator {
     }
 }
 
-/// Encodes an event into a byte array.
-fn encode_event(event: &Event) -> Vec<u8> {
+// ── Event Encoding ─────────────────────────────────────────────────────────
+//
+// Two encodings live side by side in the event log, told apart by the first
+// byte:
+//
+// - Legacy (v0): plain CBOR of `Event`. Every field and variant name is
+//   spelled out as a text map key in every entry, which is most of the bytes.
+//   Its first byte is always a CBOR map or text-string header (>= 0x60).
+// - v1: `EVENT_ENCODING_V1` followed by the same CBOR tree with each text map
+//   key replaced by `tag(KEY_REF_TAG, id)`, `id` being the key's position in
+//   the append-only `EVENT_KEYS` table. Keys are interned on first write, so
+//   new fields and variants need no schema bump, and since decoding rebuilds
+//   the exact v0 tree, serde defaults and renames apply to both versions.
+//
+// New events are written as v1. Historical v0 entries stay readable forever;
+// `migrate_events_batch` re-encodes them into a separate compact log which
+// becomes the live log once it has caught up. The original log is then
+// truncated, but the `MemoryManager` of ic-stable-structures 0.6 never hands
+// a virtual memory's buckets back, so its pages stay reserved at the size it
+// had reached. Nothing appends to it after the switch, so that is the whole
+// cost; `EventLogStatus::retired_log_bytes` reports it.
+
+/// Version byte of the compact encoding.
+pub const EVENT_ENCODING_V1: u8 = 1;
+
+/// CBOR tag marking an interned map key (the stringref tag number).
+const KEY_REF_TAG: u64 = 25;
+
+/// Interned event map keys, both directions.
+#[derive(Default)]
+pub struct KeyTable {
+    keys: Vec<String>,
+    ids: HashMap<String, u64>,
+}
+
+impl KeyTable {
+    fn push(&mut self, key: String) {
+        self.ids.insert(key.clone(), self.keys.len() as u64);
+        self.keys.push(key);
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Id of `key`, adding it if new. Returns `true` with a new key, which
+    /// the caller must persist.
+    pub fn intern(&mut self, key: &str) -> (u64, bool) {
+        if let Some(id) = self.ids.get(key) {
+            return (*id, false);
+        }
+        self.push(key.to_string());
+        (self.keys.len() as u64 - 1, true)
+    }
+
+    pub fn key(&self, id: u64) -> Option<&str> {
+        self.keys.get(id as usize).map(String::as_str)
+    }
+}
+
+fn intern_keys(value: Value, table: &mut KeyTable, new_keys: &mut Vec<String>) -> Value {
+    match value {
+        Value::Map(entries) => Value::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| {
+                    let k = match k {
+                        Value::Text(key) => {
+                            let (id, is_new) = table.intern(&key);
+                            if is_new {
+                                new_keys.push(key);
+                            }
+                            Value::Tag(KEY_REF_TAG, Box::new(Value::Integer(id.into())))
+                        }
+                        other => intern_keys(other, table, new_keys),
+                    };
+                    (k, intern_keys(v, table, new_keys))
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|v| intern_keys(v, table, new_keys))
+                .collect(),
+        ),
+        Value::Tag(tag, inner) => Value::Tag(tag, Box::new(intern_keys(*inner, table, new_keys))),
+        other => other,
+    }
+}
+
+fn resolve_keys(value: Value, table: &KeyTable) -> Result<Value, String> {
+    Ok(match value {
+        Value::Map(entries) => Value::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| {
+                    let k = match k {
+                        Value::Tag(KEY_REF_TAG, id) => {
+                            let id = match *id {
+                                Value::Integer(id) => u64::try_from(id).ok(),
+                                _ => None,
+                            }
+                            .ok_or("malformed interned key")?;
+                            Value::Text(
+                                table
+                                    .key(id)
+                                    .ok_or_else(|| format!("unknown interned key {id}"))?
+                                    .to_string(),
+                            )
+                        }
+                        other => resolve_keys(other, table)?,
+                    };
+                    Ok((k, resolve_keys(v, table)?))
+                })
+                .collect::<Result<_, String>>()?,
+        ),
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|v| resolve_keys(v, table))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Tag(tag, inner) => Value::Tag(tag, Box::new(resolve_keys(*inner, table)?)),
+        other => other,
+    })
+}
+
+/// Encodes an event in the legacy (v0) format.
+pub fn encode_event_v0(event: &Event) -> Vec<u8> {
     let mut buf = Vec::new();
     ciborium::ser::into_writer(event, &mut buf).expect("failed to encode a minter event");
     buf
 }
 
+/// Encodes an event in the v1 format, interning its keys into `table`.
+/// Returns the bytes and the keys that were new to `table`.
+pub fn encode_event_v1(event: &Event, table: &mut KeyTable) -> (Vec<u8>, Vec<String>) {
+    let value = Value::serialized(event).expect("failed to encode a minter event");
+    let mut new_keys = Vec::new();
+    let value = intern_keys(value, table, &mut new_keys);
+    let mut buf = vec![EVENT_ENCODING_V1];
+    ciborium::ser::into_writer(&value, &mut buf).expect("failed to encode a minter event");
+    (buf, new_keys)
+}
+
+/// Decodes an event in any supported encoding.
+pub fn decode_event_with(buf: &[u8], table: &KeyTable) -> Result<Event, String> {
+    match buf.first() {
+        Some(&EVENT_ENCODING_V1) => {
+            let value: Value = ciborium::de::from_reader(&buf[1..])
+                .map_err(|e| format!("v1 decode failed: {e:?}"))?;
+            resolve_keys(value, table)?
+                .deserialized()
+                .map_err(|e| format!("v1 decode failed: {e:?}"))
+        }
+        _ => ciborium::de::from_reader(buf).map_err(|e| format!("v0 decode failed: {e:?}")),
+    }
+}
+
+/// Encodes an event in the current format, persisting any new keys.
+fn encode_event(event: &Event) -> Vec<u8> {
+    let (bytes, new_keys) = KEY_TABLE.with(|t| encode_event_v1(event, &mut t.borrow_mut()));
+    if !new_keys.is_empty() {
+        EVENT_KEYS.with(|keys| {
+            let keys = keys.borrow();
+            for key in &new_keys {
+                keys.append(key)
+                    .expect("failed to append to the event key table");
+            }
+        });
+    }
+    bytes
+}
+
 /// # Panics
 ///
 /// This function panics if the event decoding fails.
 fn decode_event(buf: &[u8]) -> Event {
-    ciborium::de::from_reader(buf).expect("failed to decode a minter event")
+    KEY_TABLE
+        .with(|t| decode_event_with(buf, &t.borrow()))
+        .unwrap_or_else(|e| panic!("failed to decode a minter event: {e}"))
+}
+
+// ── Event Log Migration ────────────────────────────────────────────────────
+
+/// Which event log is live.
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum EventLogLayout {
+    /// The original log, holding v0 entries followed by v1 ones.
+    Legacy,
+    /// The original log is live while it is copied, re-encoded as v1, into
+    /// the compact log.
+    Migrating,
+    /// The compact log is live; every entry is v1.
+    Compact,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct EventLogStatus {
+    pub layout: EventLogLayout,
+    /// Version byte new events are written with.
+    pub encoding_version: u8,
+    pub total_events: u64,
+    /// Events already copied into the compact log.
+    pub migrated_events: u64,
+    pub interned_keys: u64,
+    /// Stable memory still reserved by the original log once the compact
+    /// log is live; zero before.
+    pub retired_log_bytes: u64,
+}
+
+fn init_compact_log() -> EventLog {
+    MEMORY_MANAGER.with(|m| {
+        StableLog::init(
+            m.borrow().get(COMPACT_LOG_INDEX_MEMORY_ID),
+            m.borrow().get(COMPACT_LOG_DATA_MEMORY_ID),
+        )
+        .expect("failed to initialize compact event log")
+    })
+}
+
+/// Drop every entry of the original log once the compact log is live, so its
+/// pages hold nothing that could be read back as events. No-op before the
+/// switch or once truncated.
+pub fn truncate_retired_event_log() {
+    if read_event_log_layout() != EventLogLayout::Compact {
+        return;
+    }
+    MEMORY_MANAGER.with(|m| {
+        let m = m.borrow();
+        let (index, data) = (m.get(LOG_INDEX_MEMORY_ID), m.get(LOG_DATA_MEMORY_ID));
+        let retired: Result<EventLog, _> = StableLog::init(index.clone(), data.clone());
+        if retired.map_or(true, |log| log.len() > 0) {
+            StableLog::<Vec<u8>, VMem, VMem>::new(index, data);
+        }
+    });
+}
+
+fn retired_log_bytes() -> u64 {
+    if read_event_log_layout() != EventLogLayout::Compact {
+        return 0;
+    }
+    MEMORY_MANAGER.with(|m| {
+        let m = m.borrow();
+        (m.get(LOG_INDEX_MEMORY_ID).size() + m.get(LOG_DATA_MEMORY_ID).size())
+            * ic_stable_structures::WASM_PAGE_SIZE
+    })
+}
+
+fn read_event_log_layout() -> EventLogLayout {
+    MEMORY_MANAGER.with(|m| {
+        let mem = m.borrow().get(EVENT_LOG_LAYOUT_MEMORY_ID);
+        if mem.size() == 0 {
+            return EventLogLayout::Legacy;
+        }
+        let mut word = [0u8; 8];
+        mem.read(0, &mut word);
+        match u64::from_le_bytes(word) {
+            1 => EventLogLayout::Migrating,
+            2 => EventLogLayout::Compact,
+            _ => EventLogLayout::Legacy,
+        }
+    })
+}
+
+fn write_event_log_layout(layout: EventLogLayout) {
+    let word: u64 = match layout {
+        EventLogLayout::Legacy => 0,
+        EventLogLayout::Migrating => 1,
+        EventLogLayout::Compact => 2,
+    };
+    MEMORY_MANAGER.with(|m| {
+        let mem = m.borrow().get(EVENT_LOG_LAYOUT_MEMORY_ID);
+        if mem.size() == 0 {
+            assert!(mem.grow(1) != -1, "failed to grow event log layout memory");
+        }
+        mem.write(0, &word.to_le_bytes());
+    });
+}
+
+pub fn event_log_status() -> EventLogStatus {
+    let layout = read_event_log_layout();
+    let total_events = count_events();
+    EventLogStatus {
+        layout,
+        encoding_version: EVENT_ENCODING_V1,
+        total_events,
+        migrated_events: match layout {
+            EventLogLayout::Legacy => 0,
+            EventLogLayout::Migrating => {
+                COMPACT_EVENTS.with(|c| c.borrow().as_ref().map(|log| log.len()).unwrap_or(0))
+            }
+            EventLogLayout::Compact => total_events,
+        },
+        interned_keys: KEY_TABLE.with(|t| t.borrow().len() as u64),
+        retired_log_bytes: retired_log_bytes(),
+    }
+}
+
+/// Events re-encoded per migration batch when none is given.
+pub const DEFAULT_EVENT_MIGRATION_BATCH: u64 = 1_000;
+
+/// Largest accepted migration batch, keeping each batch well inside the
+/// per-message instruction limit.
+pub const MAX_EVENT_MIGRATION_BATCH: u64 = 10_000;
+
+/// Start copying the event log into the compact log. No-op unless the
+/// layout is still `Legacy`.
+pub fn start_event_log_migration() {
+    if read_event_log_layout() != EventLogLayout::Legacy {
+        return;
+    }
+    COMPACT_EVENTS.with(|c| *c.borrow_mut() = Some(init_compact_log()));
+    write_event_log_layout(EventLogLayout::Migrating);
+}
+
+/// Copy up to `max_events` more events into the compact log, re-encoded as
+/// v1. Events recorded meanwhile land in the original log and are picked up
+/// by later batches. The batch that catches up switches the live log in the
+/// same message, so no event can fall between the two, and truncates the
+/// original one. Returns the status after the batch.
+pub fn migrate_events_batch(max_events: u64) -> EventLogStatus {
+    if read_event_log_layout() == EventLogLayout::Migrating {
+        let caught_up = COMPACT_EVENTS.with(|c| {
+            let compact = c.borrow();
+            let compact = compact
+                .as_ref()
+                .expect("compact event log must be open while migrating");
+            let total = count_events();
+            let end = compact.len().saturating_add(max_events).min(total);
+            let mut buf = Vec::new();
+            for index in compact.len()..end {
+                EVENTS.with(|events| {
+                    events
+                        .borrow()
+                        .read_entry(index, &mut buf)
+                        .expect("event index below the event count")
+                });
+                let bytes = encode_event(&decode_event(&buf));
+                compact
+                    .append(&bytes)
+                    .expect("failed to append to the compact event log");
+            }
+            compact.len() == total
+        });
+        if caught_up {
+            let compact = COMPACT_EVENTS
+                .with(|c| c.borrow_mut().take())
+                .expect("compact event log must be open while migrating");
+            EVENTS.with(|events| *events.borrow_mut() = compact);
+            write_event_log_layout(EventLogLayout::Compact);
+            truncate_retired_event_log();
+        }
+    }
+    event_log_status()
 }
 
 /// Returns an iterator over all minter events.
@@ -131,9 +609,29 @@ pub fn count_events() -> u64 {
 /// time even for variants whose payload has no `timestamp` field (Upgrade,
 /// every set_*, admin_*). The two logs always grow in lock-step from this
 /// point forward — index N in EVENTS aligns with index N in EVENT_TIMESTAMPS.
+///
+/// The event is taken to be computed from the state as of now; a flow that
+/// read its state before an await uses `record_event_after` instead.
 pub fn record_event(event: &Event) {
+    record_event_after(event, count_events());
+}
+
+/// The causality token of the events recorded from now on: capture it when
+/// reading the state an event will be computed from, before awaiting, and
+/// pass it to `record_event_after`.
+pub fn observe_events() -> u64 {
+    count_events()
+}
+
+/// Records a new minter event computed from the state as it was when
+/// `observed_seq` events had been recorded (see `event_ordering`).
+pub fn record_event_after(event: &Event, observed_seq: u64) {
+    if is_event_log_sealed() {
+        ic_cdk::trap("the event log is sealed until the upgrade replay completes");
+    }
     let bytes = encode_event(event);
     let now = ic_cdk::api::time();
+    let token = crate::event_ordering::CausalityToken::new(count_events(), observed_seq);
     EVENTS.with(|events| {
         events
             .borrow()
@@ -145,6 +643,24 @@ pub fn record_event(event: &Event) {
             .append(&now)
             .expect("failed to append to the event timestamp log");
     });
+    EVENT_TOKENS.with(|tokens| {
+        tokens
+            .borrow()
+            .append(&token)
+            .expect("failed to append to the event causality log");
+    });
+}
+
+/// Seal or unseal the event log. While an upgrade's replay is still
+/// applying earlier events (see `bootstrap`), an event appended now would
+/// land on a half-built state, so `record_event` traps instead, rolling
+/// back whatever the message changed.
+pub fn seal_event_log(sealed: bool) {
+    EVENT_LOG_SEALED.with(|s| s.set(sealed));
+}
+
+pub fn is_event_log_sealed() -> bool {
+    EVENT_LOG_SEALED.with(|s| s.get())
 }
 
 /// Returns the recording-time timestamp for the event at the given **event-log
@@ -193,19 +709,103 @@ pub fn get_event_timestamps(start: u64, length: u64) -> Vec<u64> {
     })
 }
 
+/// Causality tokens of up to `length` consecutive events starting at
+/// `start`, `None` for events recorded before the side log shipped. Stops at
+/// the end of the log.
+pub fn get_event_causality(
+    start: u64,
+    length: u64,
+) -> Vec<Option<crate::event_ordering::CausalityToken>> {
+    let events_len = count_events();
+    EVENT_TOKENS.with(|tokens| {
+        let log = tokens.borrow();
+        let offset = events_len.saturating_sub(log.len());
+        (start..start.saturating_add(length).min(events_len))
+            .map(|idx| {
+                if idx < offset {
+                    None
+                } else {
+                    log.get(idx - offset)
+                }
+            })
+            .collect()
+    })
+}
+
+/// Check the causality tokens of the events in `range` with
+/// `event_ordering::check_order`. Events without a token are skipped.
+pub fn check_event_order(
+    range: Range<u64>,
+) -> Result<crate::event_ordering::OrderSummary, crate::event::ReplayLogError> {
+    let events_len = count_events();
+    EVENT_TOKENS.with(|tokens| {
+        let log = tokens.borrow();
+        let offset = events_len.saturating_sub(log.len());
+        let start = range.start.max(offset);
+        let end = range.end.min(events_len);
+        crate::event_ordering::check_order(
+            start,
+            (start..end.max(start)).map(|idx| {
+                log.get(idx - offset)
+                    .expect("event index below the event count")
+            }),
+        )
+    })
+}
+
 // ── State Serialization (pre/post upgrade) ────────────────────────────────
+//
+// The snapshot in `STATE_MEMORY_ID` holds `State` without its vault maps and
+// pending transfer queues; those live in the stable maps of `vault_store`
+// (memories 17/18 and 21–23) and are restored from there. The flags
+// `vaults_in_stable_store` and `pending_transfers_in_stable_store` mark a
+// snapshot written that way.
+//
+// Downgrades: a wasm from before the vault store ignores both flags and
+// would come up with no vaults and no pending transfers. Do not install a
+// wasm older than the vault store over a canister that has taken a
+// checkpoint with it. A snapshot from an older wasm still loads here:
+// without the flags, its maps are read from the snapshot itself.
 
 const WASM_PAGE_SIZE: u64 = 65_536; // 64 KiB
 
-/// Serializes the full State to stable memory (called in pre_upgrade).
-/// Format: 8-byte little-endian length prefix, then CBOR-encoded state.
-pub fn save_state_to_stable(state: &crate::state::State) {
+/// Writes a checkpoint of `state`: called by `pre_upgrade`, the checkpoint
+/// timer and `take_state_checkpoint`. Returns it as recorded, covering every
+/// event logged so far.
+///
+/// The vault maps and pending transfer queues are first synced to their
+/// stable maps (see `vault_store`) and left out of the snapshot, then put
+/// back into `state`, which stays the copy every endpoint works on. The rest
+/// goes to `STATE_MEMORY_ID` as an 8-byte little-endian length prefix
+/// followed by the CBOR-encoded `State`.
+pub fn save_state_to_stable(state: &mut crate::state::State) -> StateCheckpoint {
+    let start = ic_cdk::api::instruction_counter();
+    let mut sync = VAULTS.with(|v| crate::vault_store::sync(&mut v.borrow_mut(), state));
+    PENDING_TRANSFERS
+        .with(|p| crate::vault_store::sync_pending(&mut p.borrow_mut(), state, &mut sync));
+    sync.instructions = ic_cdk::api::instruction_counter().saturating_sub(start);
+    sync.taken_at = ic_cdk::api::time();
+    LAST_VAULT_SYNC.with(|last| last.set(Some(sync)));
+
+    let vaults = std::mem::take(&mut state.vault_id_to_vaults);
+    let owners = std::mem::take(&mut state.principal_to_vault_ids);
+    let margin = std::mem::take(&mut state.pending_margin_transfers);
+    let excess = std::mem::take(&mut state.pending_excess_transfers);
+    let redemption = std::mem::take(&mut state.pending_redemption_transfer);
+    state.vaults_in_stable_store = true;
+    state.pending_transfers_in_stable_store = true;
     let bytes = {
         let mut buf = Vec::new();
-        ciborium::ser::into_writer(state, &mut buf)
-            .expect("failed to serialize State to CBOR");
+        ciborium::ser::into_writer(&*state, &mut buf).expect("failed to serialize State to CBOR");
         buf
     };
+    state.vault_id_to_vaults = vaults;
+    state.principal_to_vault_ids = owners;
+    state.pending_margin_transfers = margin;
+    state.pending_excess_transfers = excess;
+    state.pending_redemption_transfer = redemption;
+    state.vaults_in_stable_store = false;
+    state.pending_transfers_in_stable_store = false;
 
     MEMORY_MANAGER.with(|m| {
         let mem = m.borrow().get(STATE_MEMORY_ID);
@@ -220,6 +820,14 @@ pub fn save_state_to_stable(state: &crate::state::State) {
         mem.write(0, &len_bytes);
         mem.write(8, &bytes);
     });
+
+    let checkpoint = StateCheckpoint {
+        events_covered: count_events(),
+        taken_at: ic_cdk::api::time(),
+        size_bytes: bytes.len() as u64,
+    };
+    write_state_checkpoint(&checkpoint);
+    checkpoint
 }
 
 /// Attempts to restore State from stable memory.
@@ -242,7 +850,7 @@ pub fn save_state_to_stable(state: &crate::state::State) {
 /// operator can fix the schema and retry — a bricked-pending-fix upgrade is
 /// strictly safer than a silent balance wipe.
 pub fn load_state_from_stable() -> Option<crate::state::State> {
-    MEMORY_MANAGER.with(|m| {
+    let mut state = MEMORY_MANAGER.with(|m| {
         let mem = m.borrow().get(STATE_MEMORY_ID);
         if mem.size() == 0 {
             return None; // No state memory allocated yet (genuine first upgrade).
@@ -269,7 +877,27 @@ pub fn load_state_from_stable() -> Option<crate::state::State> {
             Ok(state) => Some(state),
             Err(e) => ic_cdk::trap(&corrupt_snapshot_trap_msg(&e)),
         }
-    })
+    })?;
+    // The vault maps of a snapshot that left them out.
+    if state.vaults_in_stable_store {
+        VAULTS.with(|v| {
+            let store = v.borrow();
+            if let Err(e) = crate::vault_store::check_invariants(&store) {
+                ic_cdk::trap(&corrupt_snapshot_trap_msg(&format!("vault store: {e}")));
+            }
+            crate::vault_store::restore_into(&mut state, &store);
+        });
+        state.vaults_in_stable_store = false;
+    }
+    // Likewise the pending transfer queues. A snapshot from before they
+    // moved still carries them itself.
+    if state.pending_transfers_in_stable_store {
+        PENDING_TRANSFERS.with(|p| {
+            crate::vault_store::restore_pending_into(&mut state, &p.borrow());
+        });
+        state.pending_transfers_in_stable_store = false;
+    }
+    Some(state)
 }
 
 /// Pure ciborium decode of a `State` snapshot body (the bytes AFTER the 8-byte
@@ -293,6 +921,154 @@ fn corrupt_snapshot_trap_msg(detail: &str) -> String {
     )
 }
 
+// ── State Checkpoints ──────────────────────────────────────────────────────
+//
+// The `State` snapshot is written by `pre_upgrade` and, so that an upgrade
+// which has to skip `pre_upgrade` does not fall back to replaying from
+// genesis, periodically by a timer. `STATE_CHECKPOINT_MEMORY_ID` records how
+// many events each snapshot covers; `post_upgrade` restores the snapshot and
+// replays only the events logged after it.
+//
+// Snapshots written by a wasm that predates checkpoints leave this memory
+// stale or empty. The recorded `size_bytes` must match the snapshot's length
+// prefix, otherwise the checkpoint is ignored and the snapshot is taken as
+// covering the whole log, as before.
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct StateCheckpoint {
+    /// Length of the event log when the snapshot was taken.
+    pub events_covered: u64,
+    pub taken_at: u64,
+    /// Size of the CBOR-encoded `State`.
+    pub size_bytes: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct StateCheckpointStatus {
+    pub checkpoint: Option<StateCheckpoint>,
+    pub total_events: u64,
+    /// Events logged after the checkpoint, which an upgrade skipping
+    /// `pre_upgrade` would replay.
+    pub events_since: u64,
+}
+
+fn write_state_checkpoint(checkpoint: &StateCheckpoint) {
+    MEMORY_MANAGER.with(|m| {
+        let mem = m.borrow().get(STATE_CHECKPOINT_MEMORY_ID);
+        if mem.size() == 0 {
+            assert!(mem.grow(1) != -1, "failed to grow state checkpoint memory");
+        }
+        mem.write(0, &checkpoint.events_covered.to_le_bytes());
+        mem.write(8, &checkpoint.taken_at.to_le_bytes());
+        mem.write(16, &checkpoint.size_bytes.to_le_bytes());
+    });
+}
+
+/// The checkpoint the `State` snapshot in stable memory was taken at, if it
+/// was recorded by this wasm's `save_state_to_stable`.
+pub fn state_checkpoint() -> Option<StateCheckpoint> {
+    MEMORY_MANAGER.with(|m| {
+        let mm = m.borrow();
+        let meta = mm.get(STATE_CHECKPOINT_MEMORY_ID);
+        let snapshot = mm.get(STATE_MEMORY_ID);
+        if meta.size() == 0 || snapshot.size() == 0 {
+            return None;
+        }
+        let read_word = |mem: &VMem, offset: u64| {
+            let mut word = [0u8; 8];
+            mem.read(offset, &mut word);
+            u64::from_le_bytes(word)
+        };
+        let checkpoint = StateCheckpoint {
+            events_covered: read_word(&meta, 0),
+            taken_at: read_word(&meta, 8),
+            size_bytes: read_word(&meta, 16),
+        };
+        // A snapshot written without updating the checkpoint (older wasm).
+        if checkpoint.size_bytes == 0 || checkpoint.size_bytes != read_word(&snapshot, 0) {
+            return None;
+        }
+        Some(checkpoint)
+    })
+}
+
+/// Event log indices still to replay onto a snapshot restored at
+/// `checkpoint` when the log holds `log_len` events. Without a checkpoint
+/// the snapshot is taken as covering the whole log.
+pub fn replay_range(checkpoint: Option<&StateCheckpoint>, log_len: u64) -> Range<u64> {
+    let start = checkpoint.map_or(log_len, |c| c.events_covered.min(log_len));
+    start..log_len
+}
+
+pub fn state_checkpoint_status() -> StateCheckpointStatus {
+    let checkpoint = state_checkpoint();
+    let total_events = count_events();
+    let tail = replay_range(checkpoint.as_ref(), total_events);
+    StateCheckpointStatus {
+        checkpoint,
+        total_events,
+        events_since: tail.end - tail.start,
+    }
+}
+
+pub fn vault_store_status(state: &crate::state::State) -> crate::vault_store::VaultStoreStatus {
+    use crate::vault_store::VaultStore as _;
+    VAULTS.with(|v| {
+        let store = v.borrow();
+        crate::vault_store::VaultStoreStatus {
+            heap_vaults: state.vault_count(),
+            stable_vaults: store.vault_count(),
+            stable_owner_entries: store.owner_entry_count(),
+            heap_pending_transfers: crate::vault_store::pending_transfer_count(state),
+            stable_pending_transfers: PENDING_TRANSFERS.with(|p| p.borrow().len()),
+            last_sync: LAST_VAULT_SYNC.with(|last| last.get()),
+        }
+    })
+}
+
+// ── Persisted Critical Logs ────────────────────────────────────────────────
+
+/// Append `entry` to the critical log ring, dropping the oldest entries
+/// beyond `capacity`.
+pub fn record_critical_log(entry: &crate::logs::LogEntry, capacity: u64) {
+    let mut buf = Vec::new();
+    ciborium::ser::into_writer(entry, &mut buf).expect("failed to encode log entry");
+    CRITICAL_LOGS.with(|ring| {
+        let mut ring = ring.borrow_mut();
+        let next = ring.last_key_value().map(|(seq, _)| seq + 1).unwrap_or(0);
+        ring.insert(next, buf);
+    });
+    trim_critical_logs(capacity);
+}
+
+/// Drop the oldest critical log entries until at most `capacity` remain.
+pub fn trim_critical_logs(capacity: u64) {
+    CRITICAL_LOGS.with(|ring| {
+        let mut ring = ring.borrow_mut();
+        while ring.len() > capacity {
+            match ring.first_key_value() {
+                Some((seq, _)) => ring.remove(&seq),
+                None => break,
+            };
+        }
+    });
+}
+
+/// Every persisted critical log entry, oldest first. Entries that fail to
+/// decode are skipped.
+pub fn critical_logs() -> Vec<crate::logs::LogEntry> {
+    CRITICAL_LOGS.with(|ring| {
+        ring.borrow()
+            .iter()
+            .filter_map(|(_, bytes)| ciborium::de::from_reader(bytes.as_slice()).ok())
+            .collect()
+    })
+}
+
+pub fn count_critical_logs() -> u64 {
+    CRITICAL_LOGS.with(|ring| ring.borrow().len())
+}
+
 // ── Protocol Snapshots ─────────────────────────────────────────────────────
 
 fn encode_snapshot(snapshot: &crate::ProtocolSnapshot) -> Vec<u8> {
@@ -350,6 +1126,43 @@ impl Iterator for SnapshotIterator {
     }
 }
 
+// ── Protocol ICRC-3 Blocks ─────────────────────────────────────────────────
+
+/// Append `block` unless one was already recorded for its icUSD ledger
+/// block. Returns its position, or `None` for a duplicate.
+pub fn append_protocol_block(block: &crate::icrc3_log::ProtocolBlock) -> Option<u64> {
+    if protocol_block_for_ledger_block(block.ledger_block_index).is_some() {
+        return None;
+    }
+    let mut buf = Vec::new();
+    ciborium::ser::into_writer(block, &mut buf).expect("failed to encode protocol block");
+    let index = PROTOCOL_BLOCKS.with(|log| {
+        log.borrow()
+            .append(&buf)
+            .expect("failed to append protocol block")
+    });
+    PROTOCOL_BLOCK_BY_LEDGER_BLOCK.with(|map| {
+        map.borrow_mut().insert(block.ledger_block_index, index);
+    });
+    Some(index)
+}
+
+pub fn protocol_block(index: u64) -> Option<crate::icrc3_log::ProtocolBlock> {
+    PROTOCOL_BLOCKS.with(|log| {
+        log.borrow().get(index).map(|bytes| {
+            ciborium::de::from_reader(bytes.as_slice()).expect("failed to decode protocol block")
+        })
+    })
+}
+
+pub fn count_protocol_blocks() -> u64 {
+    PROTOCOL_BLOCKS.with(|log| log.borrow().len())
+}
+
+pub fn protocol_block_for_ledger_block(ledger_block_index: u64) -> Option<u64> {
+    PROTOCOL_BLOCK_BY_LEDGER_BLOCK.with(|map| map.borrow().get(&ledger_block_index))
+}
+
 #[cfg(test)]
 mod state_snapshot_tests {
     //! Guards for the upgrade snapshot decode path. `load_state_from_stable`
@@ -446,3 +1259,101 @@ mod state_snapshot_tests {
         );
     }
 }
+
+#[cfg(test)]
+mod event_encoding_tests {
+    //! The compact (v1) event encoding round-trips, is smaller than the
+    //! legacy one, leaves legacy entries readable, and the log migration
+    //! switches to the compact log only once it has caught up.
+    use super::*;
+    use crate::numeric::ICUSD;
+    use candid::Principal;
+
+    fn borrow(vault_id: u64) -> Event {
+        Event::BorrowFromVault {
+            vault_id,
+            borrowed_amount: ICUSD::new(100_000_000),
+            fee_amount: ICUSD::new(500_000),
+            block_index: 7,
+            caller: Some(Principal::from_slice(&[1])),
+            timestamp: Some(1_000),
+            collateral_price: Some("10.25".to_string()),
+        }
+    }
+
+    #[test]
+    fn v1_round_trips_and_is_smaller() {
+        let mut table = KeyTable::default();
+        let event = borrow(1);
+        let (v1, new_keys) = encode_event_v1(&event, &mut table);
+        assert_eq!(v1[0], EVENT_ENCODING_V1);
+        assert!(new_keys.contains(&"borrow_from_vault".to_string()));
+        assert_eq!(decode_event_with(&v1, &table), Ok(event.clone()));
+        assert!(v1.len() * 2 < encode_event_v0(&event).len());
+
+        // Keys are interned once.
+        let (again, new_keys) = encode_event_v1(&borrow(2), &mut table);
+        assert!(new_keys.is_empty());
+        assert_eq!(decode_event_with(&again, &table), Ok(borrow(2)));
+    }
+
+    #[test]
+    fn legacy_entries_still_decode() {
+        let event = Event::SetGuardianPrincipals {
+            principals: vec![Principal::from_slice(&[7])],
+        };
+        let v0 = encode_event_v0(&event);
+        assert_ne!(v0[0], EVENT_ENCODING_V1);
+        assert_eq!(decode_event_with(&v0, &KeyTable::default()), Ok(event));
+    }
+
+    #[test]
+    fn unknown_interned_keys_are_rejected() {
+        let mut table = KeyTable::default();
+        let (v1, _) = encode_event_v1(&borrow(1), &mut table);
+        assert!(decode_event_with(&v1, &KeyTable::default()).is_err());
+    }
+
+    #[test]
+    fn migration_switches_once_caught_up() {
+        let legacy: Vec<Event> = (1..=3).map(borrow).collect();
+        EVENTS.with(|events| {
+            for event in &legacy {
+                events.borrow().append(&encode_event_v0(event)).unwrap();
+            }
+        });
+        assert_eq!(event_log_status().layout, EventLogLayout::Legacy);
+        // A batch before the migration starts does nothing.
+        assert_eq!(migrate_events_batch(10).migrated_events, 0);
+
+        start_event_log_migration();
+        let status = migrate_events_batch(2);
+        assert_eq!(status.layout, EventLogLayout::Migrating);
+        assert_eq!(status.migrated_events, 2);
+
+        // Recorded mid-migration: lands in the original log, copied later.
+        EVENTS.with(|events| {
+            events.borrow().append(&encode_event(&borrow(4))).unwrap();
+        });
+        let status = migrate_events_batch(2);
+        assert_eq!(status.layout, EventLogLayout::Compact);
+        assert_eq!((status.total_events, status.migrated_events), (4, 4));
+
+        let mut expected = legacy;
+        expected.push(borrow(4));
+        assert_eq!(events().collect::<Vec<_>>(), expected);
+        EVENTS.with(|events| {
+            let mut buf = Vec::new();
+            events.borrow().read_entry(0, &mut buf).unwrap();
+            assert_eq!(buf[0], EVENT_ENCODING_V1);
+        });
+
+        // The original log is emptied; its pages stay reserved.
+        let retired: EventLog = MEMORY_MANAGER.with(|m| {
+            let m = m.borrow();
+            StableLog::init(m.get(LOG_INDEX_MEMORY_ID), m.get(LOG_DATA_MEMORY_ID)).unwrap()
+        });
+        assert_eq!(retired.len(), 0);
+        assert!(status.retired_log_bytes > 0);
+    }
+}
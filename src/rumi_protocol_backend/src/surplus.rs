@@ -0,0 +1,238 @@
+//! Protocol surplus buffer: fee retention, bad-debt absorption and sweeps.
+//!
+//! The surplus buffer (`State::surplus_buffer`, first filled by
+//! `donations`) is the protocol's insurance fund. Besides donations it now
+//! keeps `surplus_fee_share` of the fees the protocol earns:
+//!
+//! - borrowing fees: the retained slice is simply not minted to the
+//!   treasury, so it stays as icUSD backing in excess of supply;
+//! - vault redemption fees: already protocol equity once the redeemer's
+//!   icUSD is burned, so the slice is only booked to the icUSD buffer;
+//! - liquidation fees: the protocol's cut is collateral, so the slice stays
+//!   in the backend's account as that ledger's buffer balance.
+//!
+//! Fees are retained after deficit repayment takes its fraction, and a
+//! retained icUSD slice repays whatever deficit is left straight away.
+//!
+//! The icUSD buffer absorbs bad debt twice over: a newly accrued deficit
+//! is covered from it at once (see `event::record_deficit_accrued`), and
+//! before a vault is redistributed the buffer repays as much of its debt as
+//! it holds, taking a proportional share of the vault's collateral into the
+//! collateral's buffer balance. Only what the buffer cannot cover reaches
+//! the other vaults; a vault the buffer covers in full is removed without
+//! touching them.
+//!
+//! The developer can sweep icUSD buffer to the treasury, which mints it.
+//! Retentions, absorptions and sweeps are logged as `SurplusFeeRetained`,
+//! `SurplusAbsorbedVault` and `SurplusSwept` and rebuilt by replay.
+
+use crate::event::FeeSource;
+use crate::logs::INFO;
+use crate::management;
+use crate::numeric::{Ratio, ICP, ICUSD};
+use crate::state::{mutate_state, read_state, State};
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// Lifetime flows through the buffer, apart from donations.
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SurplusTotals {
+    /// Fees retained, per ledger.
+    pub fees_retained: Vec<(Principal, u64)>,
+    /// Vault debt repaid from the icUSD buffer ahead of redistribution.
+    pub debt_absorbed: u64,
+    /// Collateral taken in for that debt, per ledger.
+    pub collateral_absorbed: Vec<(Principal, u64)>,
+    /// icUSD swept to the treasury.
+    pub swept: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct SurplusStatus {
+    /// Share of each borrowing, vault redemption and liquidation fee kept.
+    pub fee_share: f64,
+    /// icUSD buffer, available to absorb bad debt and to sweep.
+    pub icusd_balance: u64,
+    /// Every ledger's buffer balance, donations included.
+    pub buffer: Vec<crate::donations::SurplusBufferEntry>,
+    pub protocol_deficit: u64,
+    pub totals: SurplusTotals,
+}
+
+fn add_to(entries: &mut Vec<(Principal, u64)>, ledger: Principal, amount: u64) {
+    match entries.iter_mut().find(|(l, _)| *l == ledger) {
+        Some((_, total)) => *total = total.saturating_add(amount),
+        None => entries.push((ledger, amount)),
+    }
+}
+
+/// The part of a fee of `amount` the buffer keeps.
+pub fn retained_share(state: &State, amount: u64) -> u64 {
+    if amount == 0 || state.surplus_fee_share.0.is_zero() {
+        return 0;
+    }
+    (Decimal::from(amount) * state.surplus_fee_share.0)
+        .floor()
+        .to_u64()
+        .unwrap_or(0)
+        .min(amount)
+}
+
+/// Credit a retained fee to `ledger`'s buffer. A deficit it repays is booked
+/// separately, from its own `DeficitRepaid`.
+pub fn apply_fee_retained(state: &mut State, ledger: Principal, amount: u64) {
+    *state.surplus_buffer.entry(ledger).or_default() += amount;
+    add_to(&mut state.surplus_totals.fees_retained, ledger, amount);
+}
+
+/// Keep the buffer's share of a fee of `amount` in `ledger`, earned from
+/// `source`. Returns the part retained; the caller passes on the rest.
+pub fn retain_fee(
+    state: &mut State,
+    ledger: Principal,
+    amount: u64,
+    source: FeeSource,
+    now: u64,
+) -> u64 {
+    let retained = retained_share(state, amount);
+    if retained > 0 {
+        crate::event::record_surplus_fee_retained(state, ledger, retained, source, now);
+    }
+    retained
+}
+
+/// The debt and collateral the icUSD buffer would take off `vault_id`
+/// before redistributing it, `None` if the buffer is empty or the vault
+/// owes nothing.
+pub fn plan_absorption(state: &State, vault_id: u64) -> Option<(ICUSD, u64)> {
+    let vault = state.vault_id_to_vaults.get(&vault_id)?;
+    let balance = state
+        .surplus_buffer
+        .get(&state.icusd_ledger_principal)
+        .copied()
+        .unwrap_or_default();
+    let debt = vault.borrowed_icusd_amount.to_u64().min(balance);
+    if debt == 0 {
+        return None;
+    }
+    let collateral = if debt == vault.borrowed_icusd_amount.to_u64() {
+        vault.collateral_amount
+    } else {
+        (vault.collateral_amount as u128 * debt as u128 / vault.borrowed_icusd_amount.0 as u128)
+            as u64
+    };
+    Some((ICUSD::new(debt), collateral))
+}
+
+/// Repay `debt` of `vault_id` from the icUSD buffer and move `collateral`
+/// from the vault to its ledger's buffer. Returns whether the vault was
+/// emptied and removed. The vault's pending redistribution share is booked
+/// first, so the absorption sees its full debt and collateral.
+pub fn apply_absorption(state: &mut State, vault_id: u64, debt: ICUSD, collateral: u64) -> bool {
+    let Some(collateral_type) = state
+        .vault_id_to_vaults
+        .get(&vault_id)
+        .map(|v| v.collateral_type)
+    else {
+        return false;
+    };
+    crate::redistribution::settle(state, vault_id);
+    state.repay_to_vault(vault_id, debt);
+    state.remove_margin_from_vault(vault_id, ICP::new(collateral));
+    crate::donations::apply_buffer_absorption(state, debt.to_u64());
+    *state.surplus_buffer.entry(collateral_type).or_default() += collateral;
+    let totals = &mut state.surplus_totals;
+    totals.debt_absorbed = totals.debt_absorbed.saturating_add(debt.to_u64());
+    add_to(&mut totals.collateral_absorbed, collateral_type, collateral);
+    state.cleanup_if_drained(vault_id)
+}
+
+/// Take `amount` out of the icUSD buffer ahead of a sweep.
+pub fn take_for_sweep(state: &mut State, amount: u64) -> Result<(), ProtocolError> {
+    let ledger = state.icusd_ledger_principal;
+    let balance = state.surplus_buffer.entry(ledger).or_default();
+    if *balance < amount {
+        return Err(ProtocolError::GenericError(format!(
+            "The icUSD surplus buffer holds {}, {} requested",
+            balance, amount
+        )));
+    }
+    *balance -= amount;
+    state.surplus_totals.swept = state.surplus_totals.swept.saturating_add(amount);
+    Ok(())
+}
+
+/// Undo `take_for_sweep` after a failed mint.
+pub fn restore_sweep(state: &mut State, amount: u64) {
+    let ledger = state.icusd_ledger_principal;
+    let balance = state.surplus_buffer.entry(ledger).or_default();
+    *balance = balance.saturating_add(amount);
+    state.surplus_totals.swept = state.surplus_totals.swept.saturating_sub(amount);
+}
+
+pub fn status(state: &State) -> SurplusStatus {
+    SurplusStatus {
+        fee_share: state.surplus_fee_share.to_f64(),
+        icusd_balance: state
+            .surplus_buffer
+            .get(&state.icusd_ledger_principal)
+            .copied()
+            .unwrap_or_default(),
+        buffer: crate::donations::surplus_buffer(state),
+        protocol_deficit: state.protocol_deficit_icusd.to_u64(),
+        totals: state.surplus_totals.clone(),
+    }
+}
+
+/// Validate a new `surplus_fee_share`.
+pub fn fee_share_from(share: f64) -> Result<Ratio, String> {
+    if !share.is_finite() || !(0.0..=1.0).contains(&share) {
+        return Err(format!(
+            "surplus fee share must be in [0.0, 1.0]; got {}",
+            share
+        ));
+    }
+    Decimal::try_from(share)
+        .map(Ratio::from)
+        .map_err(|_| "Invalid fee share".to_string())
+}
+
+/// Mint `amount` of the icUSD buffer to the treasury.
+pub async fn sweep_to_treasury(amount: u64) -> Result<u64, ProtocolError> {
+    if amount == 0 {
+        return Err(ProtocolError::GenericError("Nothing to sweep".to_string()));
+    }
+    let treasury = read_state(|s| s.treasury_principal).ok_or_else(|| {
+        ProtocolError::GenericError("Treasury principal not configured".to_string())
+    })?;
+    mutate_state(|s| take_for_sweep(s, amount))?;
+
+    match management::mint_icusd(ICUSD::new(amount), treasury).await {
+        Ok(block_index) => {
+            crate::event::record_surplus_swept(amount, block_index, ic_cdk::api::time());
+            log!(
+                INFO,
+                "[sweep_surplus_to_treasury] minted {} icUSD of surplus to the treasury (block {})",
+                amount,
+                block_index
+            );
+            let _ = crate::treasury::notify_treasury_deposit(
+                treasury,
+                crate::treasury::DepositType::SurplusSweep,
+                read_state(|s| s.icusd_ledger_principal),
+                amount,
+                block_index,
+            )
+            .await;
+            Ok(block_index)
+        }
+        Err(e) => {
+            mutate_state(|s| restore_sweep(s, amount));
+            Err(ProtocolError::GenericError(format!("Mint failed: {:?}", e)))
+        }
+    }
+}
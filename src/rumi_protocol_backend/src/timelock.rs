@@ -0,0 +1,318 @@
+//! Timelock on parameter changes.
+//!
+//! With a delay set (`set_parameter_timelock`), the developer's parameter
+//! setters no longer take effect when called. The developer queues the call
+//! instead with `propose_parameter_change`, naming the setter and passing
+//! its Candid-encoded arguments. Until the change activates the developer or
+//! a guardian can `cancel_pending_change` it; from `activates_at` on anyone
+//! may `execute_parameter_change`, which has the canister call the setter on
+//! itself on the proposer's behalf. `get_pending_parameter_changes` lists
+//! the queue, so every change is public for the whole delay before it
+//! lands.
+//!
+//! Only the setters in `TIMELOCKED_METHODS` are gated. Kill switches
+//! (collateral status, stable-token and reserve-redemption toggles, worker
+//! switches), chain cursors, price pushes, per-user flags and the
+//! controller-only switches stay instant so an incident can still be
+//! handled at once. Turning the timelock on is instant; changing the delay
+//! or turning it off again goes through the timelock itself.
+//!
+//! A change whose setter fails stays queued to be retried or cancelled.
+//! Proposals, cancellations and executions are logged
+//! (`ParameterChangeProposed`, `ParameterChangeCancelled`,
+//! `ParameterChangeExecuted`) and replay rebuilds the queue; the executed
+//! setter logs its own event as a direct call would.
+
+use crate::state::State;
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+use std::cell::RefCell;
+
+/// Longest delay the developer may set (30 days).
+pub const MAX_PARAMETER_TIMELOCK_NS: u64 = 30 * 24 * 3600 * 1_000_000_000;
+
+/// Most changes that may be queued at once.
+pub const MAX_PENDING_PARAMETER_CHANGES: usize = 100;
+
+/// The endpoints that only take effect through the timelock while it is on.
+pub const TIMELOCKED_METHODS: &[&str] = &[
+    "set_parameter_timelock",
+    "update_collateral_config",
+    "set_chain_config",
+    "set_chain_contract",
+    "set_chain_liquidation_config",
+    "set_chain_debt_config",
+    "set_chain_bad_debt_circuit_threshold",
+    "set_price_pusher_principal",
+    "set_evm_rpc_principal",
+    "set_auction_config",
+    "set_redistribution_enabled",
+    "set_shadow_config",
+    "set_xrp_schnorr_key_name",
+    "set_treasury_principal",
+    "set_stability_pool_principal",
+    "set_liquidation_bot_config",
+    "set_bot_allowed_collateral_types",
+    "set_bot_cr_tolerance_bps",
+    "set_ckstable_repay_fee",
+    "set_min_icusd_amount",
+    "set_global_icusd_mint_cap",
+    "set_stable_ledger_principal",
+    "set_liquidation_bonus",
+    "set_redemption_tier",
+    "set_borrowing_fee",
+    "set_redemption_fee_floor",
+    "set_redemption_fee_ceiling",
+    "set_reserve_redemption_fee",
+    "set_reserve_stable",
+    "set_recovery_cr_multiplier",
+    "set_recovery_exit_band",
+    "set_liquidation_protocol_share",
+    "set_liquidation_tip",
+    "set_deficit_repayment_fraction",
+    "set_deficit_readonly_threshold_e8s",
+    "set_breaker_window_ns",
+    "set_breaker_window_debt_ceiling_e8s",
+    "set_check_vaults_alert_band_bps",
+    "set_min_xrc_sources_used",
+    "set_xrc_fetch_interval_secs",
+    "set_interest_treasury_tick_interval_secs",
+    "set_vault_check_tick_interval_secs",
+    "set_collateral_price_fetch_interval_secs",
+    "set_settlement_tick_interval_secs",
+    "set_chain_interest_tick_interval_secs",
+    "set_chain_interest_min_realize_e8s",
+    "set_chains_ecdsa_key_name",
+    "set_observer_tick_interval_secs",
+    "set_sol_rpc_principal",
+    "set_check_vaults_full_sweep_every_n_ticks",
+    "set_interest_pool_share",
+    "set_interest_split",
+    "set_interest_flush_threshold",
+    "set_three_pool_canister",
+    "set_amm1_canister",
+    "set_amm1_pool_id",
+    "set_rmr_floor",
+    "set_rmr_ceiling",
+    "set_rmr_floor_cr",
+    "set_rmr_ceiling_cr",
+    "set_recovery_target_cr",
+    "set_recovery_parameters",
+    "set_interest_rate",
+    "set_collateral_borrowing_fee",
+    "set_rate_curve_markers",
+    "set_recovery_rate_curve",
+    "set_borrowing_fee_curve",
+    "set_healthy_cr",
+    "set_collateral_min_xrc_sources",
+    "set_collateral_debt_ceiling",
+    "set_lst_haircut",
+    "set_collateral_liquidation_ratio",
+    "set_collateral_borrow_threshold",
+    "admin_apply_parameter_batch",
+    "set_collateral_liquidation_bonus",
+    "set_collateral_min_vault_debt",
+    "set_collateral_ledger_fee",
+    "set_collateral_redemption_fee_floor",
+    "set_collateral_redemption_fee_ceiling",
+    "set_collateral_min_deposit",
+    "set_collateral_display_color",
+    "set_collateral_liquidation_rebate_mode",
+    "set_collateral_secondary_price_source",
+    "set_collateral_price_confidence",
+    "set_collateral_maintenance_fee",
+    "set_collateral_redemption_cap",
+    "set_collateral_liquidation_cap",
+    "set_price_deviation_breaker",
+    "set_collateral_price_bounds",
+    "set_flash_mint_config",
+    "set_surplus_fee_share",
+    "set_liquidator_self_registration",
+    "set_fee_sponsorship_config",
+    "set_guardian_principals",
+    "set_emergency_pausers",
+    "set_recovery_pool_priority",
+    "set_pending_backpressure",
+    "set_slo_thresholds",
+    "set_log_retention",
+    "set_mode_companion_canisters",
+    "set_collateral_swap_route",
+    "set_auto_deleverage_route",
+];
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingParameterChange {
+    pub id: u64,
+    /// The setter to call, e.g. `set_borrowing_fee`.
+    pub method: String,
+    /// The setter's Candid-encoded arguments.
+    pub arg: ByteBuf,
+    pub proposed_by: Principal,
+    pub proposed_at: u64,
+    /// Earliest time the change may be executed.
+    pub activates_at: u64,
+}
+
+thread_local! {
+    /// The change `execute_parameter_change` is calling while its
+    /// self-call is in flight.
+    static EXECUTING: RefCell<Option<PendingParameterChange>> = RefCell::new(None);
+}
+
+pub fn is_timelocked(method: &str) -> bool {
+    TIMELOCKED_METHODS.contains(&method)
+}
+
+pub fn is_enabled(state: &State) -> bool {
+    state.parameter_timelock_ns > 0
+}
+
+/// Refuse a direct call to a timelocked setter while the timelock is on.
+pub fn check_direct_call(state: &State, method: &str) -> Result<(), ProtocolError> {
+    if is_enabled(state) && is_timelocked(method) {
+        return Err(ProtocolError::GenericError(format!(
+            "{} is timelocked: queue it with propose_parameter_change",
+            method
+        )));
+    }
+    Ok(())
+}
+
+pub fn validate_delay(delay_ns: u64) -> Result<(), ProtocolError> {
+    if delay_ns > MAX_PARAMETER_TIMELOCK_NS {
+        return Err(ProtocolError::GenericError(format!(
+            "The timelock delay must be at most {} ns",
+            MAX_PARAMETER_TIMELOCK_NS
+        )));
+    }
+    Ok(())
+}
+
+/// Build the change `proposed_by` asks for, activating one delay from now.
+pub fn propose(
+    state: &State,
+    method: String,
+    arg: ByteBuf,
+    proposed_by: Principal,
+    now: u64,
+) -> Result<PendingParameterChange, ProtocolError> {
+    if !is_enabled(state) {
+        return Err(ProtocolError::GenericError(
+            "The parameter timelock is off: call the setter directly".to_string(),
+        ));
+    }
+    if !is_timelocked(&method) {
+        return Err(ProtocolError::GenericError(format!(
+            "{} is not a timelocked setter",
+            method
+        )));
+    }
+    if state.pending_parameter_changes.len() >= MAX_PENDING_PARAMETER_CHANGES {
+        return Err(ProtocolError::GenericError(format!(
+            "At most {} parameter changes may be pending",
+            MAX_PENDING_PARAMETER_CHANGES
+        )));
+    }
+    Ok(PendingParameterChange {
+        id: state.next_parameter_change_id,
+        method,
+        arg,
+        proposed_by,
+        proposed_at: now,
+        activates_at: now.saturating_add(state.parameter_timelock_ns),
+    })
+}
+
+/// Queue `change`. `next_parameter_change_id` only ever moves past its id.
+pub fn apply_proposed(state: &mut State, change: PendingParameterChange) {
+    state.next_parameter_change_id = state.next_parameter_change_id.max(change.id + 1);
+    state.pending_parameter_changes.insert(change.id, change);
+}
+
+/// Drop change `id` from the queue, once cancelled or executed. Its id is
+/// never reused.
+pub fn apply_removed(state: &mut State, id: u64) {
+    state.pending_parameter_changes.remove(&id);
+}
+
+fn pending(state: &State, id: u64) -> Result<&PendingParameterChange, ProtocolError> {
+    state
+        .pending_parameter_changes
+        .get(&id)
+        .ok_or_else(|| ProtocolError::GenericError(format!("No pending parameter change #{}", id)))
+}
+
+pub fn check_cancellable(state: &State, id: u64, caller: Principal) -> Result<(), ProtocolError> {
+    if caller != state.developer_principal && !state.guardian_principals.contains(&caller) {
+        return Err(ProtocolError::GenericError(
+            "Only the developer principal or a guardian can cancel a parameter change".to_string(),
+        ));
+    }
+    if is_executing(id) {
+        return Err(ProtocolError::AlreadyProcessing);
+    }
+    pending(state, id).map(|_| ())
+}
+
+/// The change `id`, if it has activated.
+pub fn check_executable(
+    state: &State,
+    id: u64,
+    now: u64,
+) -> Result<PendingParameterChange, ProtocolError> {
+    let change = pending(state, id)?;
+    if now < change.activates_at {
+        return Err(ProtocolError::GenericError(format!(
+            "Parameter change #{} activates at {} (ns)",
+            id, change.activates_at
+        )));
+    }
+    Ok(change.clone())
+}
+
+/// Mark `change` as executing until `end_execution`. One change executes
+/// at a time.
+pub fn begin_execution(change: &PendingParameterChange) -> Result<(), ProtocolError> {
+    EXECUTING.with(|executing| {
+        let mut executing = executing.borrow_mut();
+        if executing.is_some() {
+            return Err(ProtocolError::AlreadyProcessing);
+        }
+        *executing = Some(change.clone());
+        Ok(())
+    })
+}
+
+pub fn is_executing(id: u64) -> bool {
+    EXECUTING.with(|executing| executing.borrow().as_ref().map(|change| change.id) == Some(id))
+}
+
+pub fn end_execution() {
+    EXECUTING.with(|executing| *executing.borrow_mut() = None);
+}
+
+/// The proposer a self-call to `method` acts for, if that is the change
+/// being executed.
+pub fn executing_as(method: &str) -> Option<Principal> {
+    EXECUTING.with(|executing| match &*executing.borrow() {
+        Some(change) if change.method == method => Some(change.proposed_by),
+        _ => None,
+    })
+}
+
+/// Who the current call acts for: the proposer when it is the canister's
+/// own call executing a change, the caller otherwise.
+pub fn acting_caller() -> Principal {
+    let caller = ic_cdk::caller();
+    if caller != ic_cdk::id() {
+        return caller;
+    }
+    EXECUTING.with(|executing| {
+        executing
+            .borrow()
+            .as_ref()
+            .map_or(caller, |change| change.proposed_by)
+    })
+}
@@ -0,0 +1,562 @@
+//! Retry policy for the pending-transfer queues, and the dead-letter map for
+//! transfers that keep failing.
+//!
+//! `process_pending_transfer` drains five queues: liquidation margin and
+//! excess collateral payouts, redemption payouts, icUSD refunds and 3USD
+//! reserve refunds. A failed attempt bumps the entry's `retry_count` and
+//! backs it off: `BASE_BACKOFF_SECS` after the first failure, doubling with
+//! each further one up to `MAX_BACKOFF_SECS`. A `BadFee` answer only
+//! refreshes the fee and is retried on the next run without counting.
+//! Entries still backing off are skipped, and the next run is scheduled for
+//! when the earliest of them is due.
+//!
+//! After `MAX_PENDING_RETRIES` failed attempts the entry moves to
+//! `State::failed_transfers` instead of being retried forever. The last retry
+//! lands about ten hours after the first attempt, inside the ledgers' 24 hour
+//! deduplication window, so an attempt whose reply was lost is still
+//! recognised. The developer inspects the map with `get_failed_transfers` and
+//! resolves an entry with `resolve_failed_transfer`:
+//!
+//! - `Requeue` puts it back in its queue with a fresh attempt budget. It
+//!   keeps its op nonce, so the ledger still deduplicates it against an
+//!   earlier attempt that landed; past the ledger's window the transfer is
+//!   refused as too old and fails again.
+//! - `Refund` reverses a redemption payout the way `redemption_cancel` does:
+//!   the collateral and debt go back to the vaults and the redeemer's icUSD
+//!   is refunded through `pending_refunds`. Only redemption payouts can be
+//!   refunded, within the same window and under the same conditions as the
+//!   redeemer's own cancellation.
+//!
+//! The backoff schedule and the dead-letter map live in the state snapshot
+//! only, like the queues themselves.
+
+use crate::event::VaultRedemption;
+use crate::numeric::ICUSD;
+use crate::redemption_cancel::REDEMPTION_CANCEL_WINDOW_NANOS;
+use crate::state::{PendingMarginTransfer, PendingRefund, PendingThreeUsdRefund, State};
+use crate::vault::require_vault_not_processing;
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Failed attempts before an entry is dead-lettered.
+pub const MAX_PENDING_RETRIES: u8 = 20;
+
+/// Wait after the first failed attempt, and the cadence of the queue runs.
+pub const BASE_BACKOFF_SECS: u64 = 5;
+
+/// Longest wait between two attempts.
+pub const MAX_BACKOFF_SECS: u64 = 3_600;
+
+/// An entry of one of the pending-transfer queues, by its key there.
+#[derive(
+    CandidType, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum PendingTransferKey {
+    /// `pending_margin_transfers`.
+    Margin { vault_id: u64, owner: Principal },
+    /// `pending_excess_transfers`.
+    Excess { vault_id: u64, owner: Principal },
+    /// `pending_redemption_transfer`.
+    Redemption { icusd_block_index: u64 },
+    /// `pending_refunds`.
+    Refund { icusd_block_index: u64 },
+    /// `pending_3usd_refunds`.
+    ThreeUsdRefund { op_nonce: u128 },
+}
+
+/// A queue entry as it was when it was dead-lettered.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QueuedTransfer {
+    /// Margin, excess and redemption payouts.
+    Collateral(PendingMarginTransfer),
+    Refund(PendingRefund),
+    ThreeUsdRefund(PendingThreeUsdRefund),
+}
+
+impl QueuedTransfer {
+    fn op_nonce(&self) -> u128 {
+        match self {
+            Self::Collateral(t) => t.op_nonce,
+            Self::Refund(r) => r.op_nonce,
+            Self::ThreeUsdRefund(r) => r.op_nonce,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailedTransfer {
+    pub entry: QueuedTransfer,
+    /// Failed attempts before it was dead-lettered.
+    pub attempts: u8,
+    pub last_error: String,
+    pub failed_at_ns: u64,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct FailedTransferView {
+    pub key: PendingTransferKey,
+    pub recipient: Principal,
+    /// The ledger the transfer is made on.
+    pub ledger: Principal,
+    /// Amount owed, in the ledger's units, before its fee.
+    pub amount: u64,
+    pub attempts: u8,
+    pub last_error: String,
+    pub failed_at_ns: u64,
+    /// When the transfer was first queued, from its op nonce. `None` for
+    /// entries queued before op nonces existed.
+    pub queued_at_ns: Option<u64>,
+}
+
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum FailedTransferAction {
+    Requeue,
+    Refund,
+}
+
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct PendingTransferMetrics {
+    /// Entries across the five queues.
+    pub queued: u64,
+    /// Queued entries waiting out a backoff.
+    pub backing_off: u64,
+    /// Age of the oldest queued entry with an op nonce.
+    pub oldest_age_ns: Option<u64>,
+    /// Dead-lettered entries.
+    pub failed: u64,
+}
+
+/// What `record_failure` did with an entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryOutcome {
+    /// Retried once `next_attempt_ns` has passed.
+    Retry { attempts: u8, next_attempt_ns: u64 },
+    /// Moved to `failed_transfers`.
+    DeadLettered { attempts: u8 },
+    /// No longer queued, e.g. settled meanwhile.
+    Gone,
+}
+
+/// Wait before the attempt that follows the `failures`-th failed one.
+pub fn backoff_secs(failures: u8) -> u64 {
+    match failures {
+        0 => 0,
+        n => BASE_BACKOFF_SECS
+            .checked_shl(u32::from(n - 1))
+            .unwrap_or(u64::MAX)
+            .min(MAX_BACKOFF_SECS),
+    }
+}
+
+/// When a transfer carrying `op_nonce` was queued: the nonce's upper half is
+/// the time it was minted at (see `State::next_op_nonce`).
+pub fn queued_at_ns(op_nonce: u128) -> Option<u64> {
+    (op_nonce != 0).then_some((op_nonce >> 64) as u64)
+}
+
+/// Whether `key` may be attempted at `now_ns`.
+pub fn is_due(state: &State, key: &PendingTransferKey, now_ns: u64) -> bool {
+    !state
+        .pending_transfer_backoff
+        .get(key)
+        .is_some_and(|due| *due > now_ns)
+}
+
+fn retry_count_mut<'a>(state: &'a mut State, key: &PendingTransferKey) -> Option<&'a mut u8> {
+    match *key {
+        PendingTransferKey::Margin { vault_id, owner } => state
+            .pending_margin_transfers
+            .get_mut(&(vault_id, owner))
+            .map(|t| &mut t.retry_count),
+        PendingTransferKey::Excess { vault_id, owner } => state
+            .pending_excess_transfers
+            .get_mut(&(vault_id, owner))
+            .map(|t| &mut t.retry_count),
+        PendingTransferKey::Redemption { icusd_block_index } => state
+            .pending_redemption_transfer
+            .get_mut(&icusd_block_index)
+            .map(|t| &mut t.retry_count),
+        PendingTransferKey::Refund { icusd_block_index } => state
+            .pending_refunds
+            .get_mut(&icusd_block_index)
+            .map(|r| &mut r.retry_count),
+        PendingTransferKey::ThreeUsdRefund { op_nonce } => state
+            .pending_3usd_refunds
+            .get_mut(&op_nonce)
+            .map(|r| &mut r.retry_count),
+    }
+}
+
+fn is_queued(state: &State, key: &PendingTransferKey) -> bool {
+    match *key {
+        PendingTransferKey::Margin { vault_id, owner } => state
+            .pending_margin_transfers
+            .contains_key(&(vault_id, owner)),
+        PendingTransferKey::Excess { vault_id, owner } => state
+            .pending_excess_transfers
+            .contains_key(&(vault_id, owner)),
+        PendingTransferKey::Redemption { icusd_block_index } => state
+            .pending_redemption_transfer
+            .contains_key(&icusd_block_index),
+        PendingTransferKey::Refund { icusd_block_index } => {
+            state.pending_refunds.contains_key(&icusd_block_index)
+        }
+        PendingTransferKey::ThreeUsdRefund { op_nonce } => {
+            state.pending_3usd_refunds.contains_key(&op_nonce)
+        }
+    }
+}
+
+fn take_queued(state: &mut State, key: &PendingTransferKey) -> Option<QueuedTransfer> {
+    match *key {
+        PendingTransferKey::Margin { vault_id, owner } => state
+            .pending_margin_transfers
+            .remove(&(vault_id, owner))
+            .map(QueuedTransfer::Collateral),
+        PendingTransferKey::Excess { vault_id, owner } => state
+            .pending_excess_transfers
+            .remove(&(vault_id, owner))
+            .map(QueuedTransfer::Collateral),
+        // The redemption's record stays, so the payout can still be
+        // reversed once it is requeued or refunded.
+        PendingTransferKey::Redemption { icusd_block_index } => state
+            .pending_redemption_transfer
+            .remove(&icusd_block_index)
+            .map(QueuedTransfer::Collateral),
+        PendingTransferKey::Refund { icusd_block_index } => state
+            .pending_refunds
+            .remove(&icusd_block_index)
+            .map(QueuedTransfer::Refund),
+        PendingTransferKey::ThreeUsdRefund { op_nonce } => state
+            .pending_3usd_refunds
+            .remove(&op_nonce)
+            .map(QueuedTransfer::ThreeUsdRefund),
+    }
+}
+
+fn put_queued(state: &mut State, key: &PendingTransferKey, entry: QueuedTransfer) {
+    match (*key, entry) {
+        (PendingTransferKey::Margin { vault_id, owner }, QueuedTransfer::Collateral(t)) => {
+            state.pending_margin_transfers.insert((vault_id, owner), t);
+        }
+        (PendingTransferKey::Excess { vault_id, owner }, QueuedTransfer::Collateral(t)) => {
+            state.pending_excess_transfers.insert((vault_id, owner), t);
+        }
+        (PendingTransferKey::Redemption { icusd_block_index }, QueuedTransfer::Collateral(t)) => {
+            state
+                .pending_redemption_transfer
+                .insert(icusd_block_index, t);
+        }
+        (PendingTransferKey::Refund { icusd_block_index }, QueuedTransfer::Refund(r)) => {
+            state.pending_refunds.insert(icusd_block_index, r);
+        }
+        (PendingTransferKey::ThreeUsdRefund { op_nonce }, QueuedTransfer::ThreeUsdRefund(r)) => {
+            state.pending_3usd_refunds.insert(op_nonce, r);
+        }
+        (key, entry) => panic!("{:?} cannot be queued under {:?}", entry, key),
+    }
+}
+
+/// Count a failed attempt of `key` at `now_ns`: back it off, or dead-letter
+/// it once it has failed `MAX_PENDING_RETRIES` times.
+pub fn record_failure(
+    state: &mut State,
+    key: PendingTransferKey,
+    error: String,
+    now_ns: u64,
+) -> RetryOutcome {
+    let Some(retry_count) = retry_count_mut(state, &key) else {
+        state.pending_transfer_backoff.remove(&key);
+        return RetryOutcome::Gone;
+    };
+    *retry_count = retry_count.saturating_add(1);
+    let attempts = *retry_count;
+    if attempts < MAX_PENDING_RETRIES {
+        let next_attempt_ns = now_ns.saturating_add(backoff_secs(attempts) * NANOS_PER_SEC);
+        state.pending_transfer_backoff.insert(key, next_attempt_ns);
+        return RetryOutcome::Retry {
+            attempts,
+            next_attempt_ns,
+        };
+    }
+    state.pending_transfer_backoff.remove(&key);
+    if let Some(entry) = take_queued(state, &key) {
+        state.failed_transfers.insert(
+            key,
+            FailedTransfer {
+                entry,
+                attempts,
+                last_error: error,
+                failed_at_ns: now_ns,
+            },
+        );
+    }
+    RetryOutcome::DeadLettered { attempts }
+}
+
+/// Drop the backoff of entries that have left their queue.
+pub fn prune_backoff(state: &mut State) {
+    let stale: Vec<PendingTransferKey> = state
+        .pending_transfer_backoff
+        .keys()
+        .filter(|key| !is_queued(state, key))
+        .copied()
+        .collect();
+    for key in stale {
+        state.pending_transfer_backoff.remove(&key);
+    }
+}
+
+fn queued_nonces(state: &State) -> Vec<(PendingTransferKey, u128)> {
+    let collateral = |key: PendingTransferKey, t: &PendingMarginTransfer| (key, t.op_nonce);
+    state
+        .pending_margin_transfers
+        .iter()
+        .map(|((vault_id, owner), t)| {
+            collateral(
+                PendingTransferKey::Margin {
+                    vault_id: *vault_id,
+                    owner: *owner,
+                },
+                t,
+            )
+        })
+        .chain(
+            state
+                .pending_excess_transfers
+                .iter()
+                .map(|((vault_id, owner), t)| {
+                    collateral(
+                        PendingTransferKey::Excess {
+                            vault_id: *vault_id,
+                            owner: *owner,
+                        },
+                        t,
+                    )
+                }),
+        )
+        .chain(state.pending_redemption_transfer.iter().map(|(block, t)| {
+            collateral(
+                PendingTransferKey::Redemption {
+                    icusd_block_index: *block,
+                },
+                t,
+            )
+        }))
+        .chain(state.pending_refunds.iter().map(|(block, r)| {
+            (
+                PendingTransferKey::Refund {
+                    icusd_block_index: *block,
+                },
+                r.op_nonce,
+            )
+        }))
+        .chain(state.pending_3usd_refunds.iter().map(|(nonce, r)| {
+            (
+                PendingTransferKey::ThreeUsdRefund { op_nonce: *nonce },
+                r.op_nonce,
+            )
+        }))
+        .collect()
+}
+
+/// Seconds until the next queue run, `None` when every queue is empty.
+/// Never sooner than `BASE_BACKOFF_SECS`.
+pub fn next_run_delay_secs(state: &State, now_ns: u64) -> Option<u64> {
+    let next_due_ns = queued_nonces(state)
+        .iter()
+        .map(|(key, _)| {
+            state
+                .pending_transfer_backoff
+                .get(key)
+                .copied()
+                .unwrap_or(now_ns)
+        })
+        .min()?;
+    let wait_secs = next_due_ns.saturating_sub(now_ns).div_ceil(NANOS_PER_SEC);
+    Some(wait_secs.max(BASE_BACKOFF_SECS))
+}
+
+pub fn metrics(state: &State, now_ns: u64) -> PendingTransferMetrics {
+    let queued = queued_nonces(state);
+    PendingTransferMetrics {
+        queued: queued.len() as u64,
+        backing_off: queued
+            .iter()
+            .filter(|(key, _)| !is_due(state, key, now_ns))
+            .count() as u64,
+        oldest_age_ns: queued
+            .iter()
+            .filter_map(|(_, nonce)| queued_at_ns(*nonce))
+            .min()
+            .map(|queued_at| now_ns.saturating_sub(queued_at)),
+        failed: state.failed_transfers.len() as u64,
+    }
+}
+
+pub fn failed_transfers(state: &State) -> Vec<FailedTransferView> {
+    state
+        .failed_transfers
+        .iter()
+        .map(|(key, failed)| {
+            let (recipient, ledger, amount) = match &failed.entry {
+                QueuedTransfer::Collateral(t) => {
+                    let ledger = if t.collateral_type == Principal::anonymous() {
+                        state.icp_ledger_principal
+                    } else {
+                        t.collateral_type
+                    };
+                    (t.owner, ledger, t.margin.to_u64())
+                }
+                QueuedTransfer::Refund(r) => (r.user, state.icusd_ledger_principal, r.amount_e8s),
+                QueuedTransfer::ThreeUsdRefund(r) => (r.stability_pool, r.ledger, r.amount_e8s),
+            };
+            FailedTransferView {
+                key: *key,
+                recipient,
+                ledger,
+                amount,
+                attempts: failed.attempts,
+                last_error: failed.last_error.clone(),
+                failed_at_ns: failed.failed_at_ns,
+                queued_at_ns: queued_at_ns(failed.entry.op_nonce()),
+            }
+        })
+        .collect()
+}
+
+fn not_failed(key: &PendingTransferKey) -> ProtocolError {
+    ProtocolError::GenericError(format!("{:?} is not a failed transfer", key))
+}
+
+/// Returns an error unless `caller` may resolve failed transfers: the
+/// developer only.
+pub fn check_resolver(state: &State, caller: Principal) -> Result<(), ProtocolError> {
+    if caller == state.developer_principal {
+        return Ok(());
+    }
+    Err(ProtocolError::GenericError(
+        "Only the developer can resolve failed transfers".to_string(),
+    ))
+}
+
+/// Put a dead-lettered entry back in its queue with a fresh attempt budget.
+pub fn requeue(state: &mut State, key: PendingTransferKey) -> Result<(), ProtocolError> {
+    if !state.failed_transfers.contains_key(&key) {
+        return Err(not_failed(&key));
+    }
+    if is_queued(state, &key) {
+        return Err(ProtocolError::GenericError(format!(
+            "{:?} is already queued again",
+            key
+        )));
+    }
+    let mut entry = state
+        .failed_transfers
+        .remove(&key)
+        .expect("checked above")
+        .entry;
+    match &mut entry {
+        QueuedTransfer::Collateral(t) => t.retry_count = 0,
+        QueuedTransfer::Refund(r) => r.retry_count = 0,
+        QueuedTransfer::ThreeUsdRefund(r) => r.retry_count = 0,
+    }
+    put_queued(state, &key, entry);
+    Ok(())
+}
+
+/// Check a refund of the dead-lettered redemption payout at `key`. Returns
+/// the redeemer and the per-vault breakdown to reverse.
+pub fn check_refund(
+    state: &State,
+    key: &PendingTransferKey,
+    now_ns: u64,
+) -> Result<(Principal, Vec<VaultRedemption>), ProtocolError> {
+    let generic = |msg: String| ProtocolError::GenericError(msg);
+    let PendingTransferKey::Redemption { icusd_block_index } = *key else {
+        return Err(generic(format!(
+            "Only redemption payouts can be refunded; requeue {:?} instead",
+            key
+        )));
+    };
+    let payout = match state.failed_transfers.get(key).map(|f| &f.entry) {
+        Some(QueuedTransfer::Collateral(t)) => t,
+        _ => return Err(not_failed(key)),
+    };
+    let record = state
+        .pending_redemption_records
+        .get(&icusd_block_index)
+        .ok_or_else(|| {
+            generic(format!(
+                "Redemption {} predates cancellable payouts and cannot be reversed",
+                icusd_block_index
+            ))
+        })?;
+    if now_ns.saturating_sub(record.redeemed_at_ns) > REDEMPTION_CANCEL_WINDOW_NANOS {
+        return Err(generic(format!(
+            "Redemption {} is past its cancellation window",
+            icusd_block_index
+        )));
+    }
+    if record.shortfall_e8s > 0 {
+        return Err(generic(format!(
+            "Redemption {} accrued a deficit and cannot be reversed",
+            icusd_block_index
+        )));
+    }
+    if state.pending_refunds.contains_key(&icusd_block_index) {
+        return Err(ProtocolError::TemporarilyUnavailable(format!(
+            "A refund for redemption {} is still queued; retry once it lands",
+            icusd_block_index
+        )));
+    }
+    for vr in &record.vault_redemptions {
+        let vault = state
+            .vault_id_to_vaults
+            .get(&vr.vault_id)
+            .filter(|v| v.collateral_type == payout.collateral_type)
+            .ok_or_else(|| {
+                generic(format!(
+                    "Vault #{} has closed since the redemption; it cannot be reversed",
+                    vr.vault_id
+                ))
+            })?;
+        require_vault_not_processing(vault)?;
+    }
+    Ok((payout.owner, record.vault_redemptions.clone()))
+}
+
+/// Reverse the dead-lettered redemption payout at `key` and queue the
+/// redeemer's icUSD refund. Returns the icUSD refunded.
+pub fn refund(
+    state: &mut State,
+    key: PendingTransferKey,
+    now_ns: u64,
+) -> Result<ICUSD, ProtocolError> {
+    let (redeemer, vault_redemptions) = check_refund(state, &key, now_ns)?;
+    let PendingTransferKey::Redemption { icusd_block_index } = key else {
+        unreachable!("check_refund only accepts redemption payouts");
+    };
+    state.failed_transfers.remove(&key);
+    let refunded = crate::event::record_redemption_cancelled(
+        state,
+        redeemer,
+        icusd_block_index,
+        vault_redemptions,
+        now_ns,
+    );
+    let op_nonce = state.next_op_nonce_at(now_ns);
+    state.pending_refunds.insert(
+        icusd_block_index,
+        PendingRefund {
+            user: redeemer,
+            amount_e8s: refunded.to_u64(),
+            retry_count: 0,
+            op_nonce,
+        },
+    );
+    Ok(refunded)
+}
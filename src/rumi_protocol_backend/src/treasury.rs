@@ -4,13 +4,14 @@
 //! `treasury.deposit()` for categorized bookkeeping.
 //!
 //! All treasury operations are **non-critical**: failures are logged but
-//! never block user-facing operations (borrow, repay, liquidation).
+//! never block user-facing operations (borrow, repay, liquidation). A
+//! `deposit()` call that fails is queued and retried by the treasury tick.
 
 use candid::{CandidType, Deserialize, Principal};
 use ic_canister_log::log;
 use serde::Serialize;
 
-use crate::logs::INFO;
+use crate::logs::{CRITICAL, INFO};
 use crate::management;
 use crate::numeric::ICUSD;
 use crate::state::read_state;
@@ -20,12 +21,14 @@ use crate::state::read_state;
 // ---------------------------------------------------------------------------
 
 /// Mirrors `rumi_treasury::types::DepositType`.
-#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum DepositType {
     BorrowingFee,
     RedemptionFee,
     LiquidationFee,
     InterestRevenue,
+    FlashMintFee,
+    SurplusSweep,
 }
 
 /// Mirrors `rumi_treasury::types::AssetType`.
@@ -42,16 +45,19 @@ pub enum AssetType {
 /// dependency on its cdylib crate. `IDLValue` accepts every typed error arm;
 /// only `Ok` acknowledges a post-mint receipt.
 #[derive(CandidType, Deserialize)]
-enum StabilityPoolInterestNotificationResult {
+pub enum StabilityPoolInterestNotificationResult {
     Ok,
     Err(candid::IDLValue),
 }
 
-/// Mirrors `rumi_treasury::types::DepositArgs`.
+/// Mirrors `rumi_treasury::types::DepositArgs`. The treasury keys assets by
+/// ledger and only reads `asset_type` when `ledger` is absent, so a
+/// treasury predating its asset registry must be upgraded first.
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct DepositArgs {
     pub deposit_type: DepositType,
-    pub asset_type: AssetType,
+    pub asset_type: Option<AssetType>,
+    pub ledger: Option<Principal>,
     pub amount: u64,
     pub block_index: u64,
     pub memo: Option<String>,
@@ -121,30 +127,25 @@ pub fn plan_fee_routing(
 }
 
 // ---------------------------------------------------------------------------
-// Helper: map collateral ledger principal → AssetType
+// Helper: map ledger principal → legacy AssetType
 // ---------------------------------------------------------------------------
 
-/// Map a collateral ledger principal to the treasury's AssetType enum.
-/// Uses known ckStable ledger principals from state config, plus ICP ledger.
-pub fn collateral_to_asset_type(ct: &Principal) -> AssetType {
+/// The treasury's legacy AssetType for `ledger`, if it has one. Any other
+/// ledger (ckBTC, ckETH, ...) is identified to the treasury by principal
+/// alone.
+pub fn legacy_asset_type(ledger: &Principal) -> Option<AssetType> {
     read_state(|s| {
-        if *ct == s.icp_ledger_principal {
-            return AssetType::ICP;
-        }
-        if let Some(ckusdt) = s.ckusdt_ledger_principal {
-            if *ct == ckusdt {
-                return AssetType::CKUSDT;
-            }
-        }
-        if let Some(ckusdc) = s.ckusdc_ledger_principal {
-            if *ct == ckusdc {
-                return AssetType::CKUSDC;
-            }
+        if *ledger == s.icusd_ledger_principal {
+            Some(AssetType::ICUSD)
+        } else if *ledger == s.icp_ledger_principal {
+            Some(AssetType::ICP)
+        } else if s.ckusdt_ledger_principal == Some(*ledger) {
+            Some(AssetType::CKUSDT)
+        } else if s.ckusdc_ledger_principal == Some(*ledger) {
+            Some(AssetType::CKUSDC)
+        } else {
+            None
         }
-        // For any other collateral (ckBTC, ckETH, etc.), default to ICP
-        // since treasury AssetType only has ICP/CKBTC/CKUSDT/CKUSDC.
-        // TODO: Expand AssetType when new collaterals are added.
-        AssetType::ICP
     })
 }
 
@@ -153,17 +154,84 @@ pub fn collateral_to_asset_type(ct: &Principal) -> AssetType {
 // ---------------------------------------------------------------------------
 
 /// Notify the treasury canister about a deposit (for bookkeeping).
-/// Non-critical: failures are logged but don't affect protocol operation.
+/// Non-critical: a failed notification is queued for
+/// `flush_pending_treasury_deposits` and doesn't affect protocol operation.
 pub async fn notify_treasury_deposit(
     treasury: Principal,
     deposit_type: DepositType,
-    asset_type: AssetType,
+    ledger: Principal,
+    amount: u64,
+    block_index: u64,
+) -> Result<u64, String> {
+    let result =
+        call_treasury_deposit(treasury, deposit_type.clone(), ledger, amount, block_index).await;
+    if result.is_err() {
+        let queued = crate::state::mutate_state(|s| {
+            s.queue_treasury_deposit(crate::state::PendingTreasuryDeposit {
+                treasury,
+                deposit_type,
+                ledger,
+                amount,
+                block_index,
+                attempts: 0,
+            })
+        });
+        if !queued {
+            log!(
+                CRITICAL,
+                "[treasury] retry queue full; deposit of {} from block {} on {} left unbooked",
+                amount,
+                block_index,
+                ledger
+            );
+        }
+    }
+    result
+}
+
+/// Retry queued deposit notifications. The treasury returns the recorded
+/// deposit for a block it already booked, so a retry whose earlier attempt
+/// landed is harmless.
+pub async fn flush_pending_treasury_deposits() {
+    let pending: Vec<crate::state::PendingTreasuryDeposit> =
+        read_state(|s| s.pending_treasury_deposits.values().cloned().collect());
+    for deposit in pending {
+        let delivered = call_treasury_deposit(
+            deposit.treasury,
+            deposit.deposit_type.clone(),
+            deposit.ledger,
+            deposit.amount,
+            deposit.block_index,
+        )
+        .await
+        .is_ok();
+        let given_up = crate::state::mutate_state(|s| {
+            s.settle_treasury_deposit_retry(deposit.ledger, deposit.block_index, delivered)
+        });
+        if let Some(deposit) = given_up {
+            log!(
+                CRITICAL,
+                "[treasury] gave up booking deposit of {} from block {} on {} after {} attempts",
+                deposit.amount,
+                deposit.block_index,
+                deposit.ledger,
+                deposit.attempts
+            );
+        }
+    }
+}
+
+async fn call_treasury_deposit(
+    treasury: Principal,
+    deposit_type: DepositType,
+    ledger: Principal,
     amount: u64,
     block_index: u64,
 ) -> Result<u64, String> {
     let args = DepositArgs {
         deposit_type,
-        asset_type,
+        asset_type: legacy_asset_type(&ledger),
+        ledger: Some(ledger),
         amount,
         block_index,
         memo: None,
@@ -227,7 +295,7 @@ pub async fn mint_interest_to_treasury(interest_share: ICUSD) -> Result<(), ICUS
             let _ = notify_treasury_deposit(
                 tp,
                 DepositType::InterestRevenue,
-                AssetType::ICUSD,
+                read_state(|s| s.icusd_ledger_principal),
                 interest_share.to_u64(),
                 block_index,
             )
@@ -510,14 +578,10 @@ pub async fn distribute_stablecoin_interest(
                         {
                             Ok(block_index) => {
                                 log!(INFO, "[treasury] Transferred {} {:?} interest to treasury (block {})", treasury_e6s, token_type, block_index);
-                                let asset_type = match token_type {
-                                    crate::StableTokenType::CKUSDT => AssetType::CKUSDT,
-                                    crate::StableTokenType::CKUSDC => AssetType::CKUSDC,
-                                };
                                 let _ = notify_treasury_deposit(
                                     tp,
                                     DepositType::InterestRevenue,
-                                    asset_type,
+                                    ledger,
                                     treasury_e6s,
                                     block_index,
                                 )
@@ -824,18 +888,29 @@ enum AmmDonateError {
 /// repayment first via `plan_fee_routing`. The "repayment" is supply-
 /// conserving — we mint `to_remainder` instead of the full `fee`, so the
 /// skipped `to_repay` mint is the foregone-revenue that pays down the
-/// deficit. No separate ledger op is required.
+/// deficit. No separate ledger op is required. The surplus buffer's share
+/// of the remainder is likewise left unminted (see `surplus`).
 pub async fn mint_borrowing_fee_to_treasury(fee: ICUSD) {
     if fee.0 == 0 {
         return;
     }
     let outcome = crate::state::mutate_state(|s| {
-        plan_fee_routing(s, fee, crate::event::FeeSource::BorrowingFee)
+        let mut outcome = plan_fee_routing(s, fee, crate::event::FeeSource::BorrowingFee);
+        let icusd_ledger = s.icusd_ledger_principal;
+        let retained = crate::surplus::retain_fee(
+            s,
+            icusd_ledger,
+            outcome.to_remainder.to_u64(),
+            crate::event::FeeSource::BorrowingFee,
+            ic_cdk::api::time(),
+        );
+        outcome.to_remainder -= ICUSD::new(retained);
+        outcome
     });
     if outcome.to_remainder.0 == 0 {
         log!(
             INFO,
-            "[treasury] Borrowing fee {} fully routed to deficit repayment (no treasury mint)",
+            "[treasury] Borrowing fee {} fully routed to deficit repayment and the surplus buffer (no treasury mint)",
             fee.to_u64()
         );
         return;
@@ -863,7 +938,7 @@ pub async fn mint_borrowing_fee_to_treasury(fee: ICUSD) {
                 let _ = notify_treasury_deposit(
                     tp,
                     DepositType::BorrowingFee,
-                    AssetType::ICUSD,
+                    read_state(|s| s.icusd_ledger_principal),
                     outcome.to_remainder.to_u64(),
                     block_index,
                 )
@@ -878,12 +953,57 @@ pub async fn mint_borrowing_fee_to_treasury(fee: ICUSD) {
     }
 }
 
+/// Mint a repaid flash mint's fee to treasury and record the deposit. The
+/// repayment burned the fee along with the principal, so this restores it
+/// as treasury revenue.
+pub async fn mint_flash_mint_fee_to_treasury(fee: ICUSD) {
+    if fee.0 == 0 {
+        return;
+    }
+    let Some(tp) = read_state(|s| s.treasury_principal) else {
+        return;
+    };
+    match management::mint_icusd(fee, tp).await {
+        Ok(block_index) => {
+            log!(
+                INFO,
+                "[treasury] Minted {} icUSD flash-mint fee (block {})",
+                fee.to_u64(),
+                block_index
+            );
+            let _ = notify_treasury_deposit(
+                tp,
+                DepositType::FlashMintFee,
+                read_state(|s| s.icusd_ledger_principal),
+                fee.to_u64(),
+                block_index,
+            )
+            .await;
+        }
+        Err(e) => log!(
+            INFO,
+            "[treasury] WARNING: flash-mint fee mint failed: {:?}",
+            e
+        ),
+    }
+}
+
 /// Transfer collateral (liquidation fee) to treasury and record the deposit.
-pub async fn send_liquidation_fee_to_treasury(
-    amount: u64,
-    collateral_ledger: Principal,
-    asset_type: AssetType,
-) {
+/// The surplus buffer's share stays in this canister's account.
+pub async fn send_liquidation_fee_to_treasury(amount: u64, collateral_ledger: Principal) {
+    if amount == 0 {
+        return;
+    }
+    let retained = crate::state::mutate_state(|s| {
+        crate::surplus::retain_fee(
+            s,
+            collateral_ledger,
+            amount,
+            crate::event::FeeSource::LiquidationFee,
+            ic_cdk::api::time(),
+        )
+    });
+    let amount = amount - retained;
     if amount == 0 {
         return;
     }
@@ -900,7 +1020,7 @@ pub async fn send_liquidation_fee_to_treasury(
                 let _ = notify_treasury_deposit(
                     tp,
                     DepositType::LiquidationFee,
-                    asset_type,
+                    collateral_ledger,
                     amount,
                     block_index,
                 )
@@ -958,7 +1078,7 @@ pub async fn flush_pending_interest() {
                 s.restore_pending_interest_for_pool(collateral_type, unminted.to_u64());
             });
             log!(
-                INFO,
+                CRITICAL,
                 "[treasury] CRITICAL: re-queued {} icUSD for collateral {} after partial mint failure (snapshot {})",
                 unminted.to_u64(),
                 collateral_type,
@@ -1057,7 +1177,7 @@ pub async fn drain_pending_treasury_interest() {
             let _ = notify_treasury_deposit(
                 tp,
                 DepositType::InterestRevenue,
-                AssetType::ICUSD,
+                read_state(|s| s.icusd_ledger_principal),
                 snapshot.to_u64(),
                 block_index,
             )
@@ -1066,7 +1186,7 @@ pub async fn drain_pending_treasury_interest() {
         Err(e) => {
             crate::state::mutate_state(|s| s.restore_pending_treasury_interest(snapshot));
             log!(
-                INFO,
+                CRITICAL,
                 "[treasury] CRITICAL: pending interest drain failed, re-queued {} icUSD: {:?}",
                 snapshot.to_u64(),
                 e
@@ -1086,7 +1206,6 @@ pub async fn drain_pending_treasury_collateral() {
     if let Some(tp) = treasury {
         let mut drained = Vec::new();
         for (amount, ledger) in &pending {
-            let asset_type = collateral_to_asset_type(ledger);
             match management::transfer_collateral(*amount, tp, *ledger).await {
                 Ok(block_index) => {
                     log!(
@@ -1099,7 +1218,7 @@ pub async fn drain_pending_treasury_collateral() {
                     let _ = notify_treasury_deposit(
                         tp,
                         DepositType::LiquidationFee,
-                        asset_type,
+                        *ledger,
                         *amount,
                         block_index,
                     )
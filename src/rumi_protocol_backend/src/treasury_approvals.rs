@@ -0,0 +1,165 @@
+//! Co-approval of large treasury withdrawals.
+//!
+//! The treasury pays out a withdrawal above its per-ledger co-approval
+//! threshold only once this canister has agreed to it, so treasury spending
+//! answers to the same authority as the protocol: a guardian or the
+//! developer (see `vault_freeze::is_freeze_authority`) registers an approval
+//! for one exact withdrawal (`request_id`, ledger, recipient, amount), and
+//! the treasury consumes it by inter-canister call just before transferring.
+//!
+//! An approval lasts at most `MAX_TREASURY_APPROVAL_TTL_NS`, the ledgers'
+//! deduplication window. The treasury retries a withdrawal with the same
+//! `request_id` and `created_at_time`, so a consumed approval keeps matching
+//! until it expires: a retry of a transfer that did land is deduplicated by
+//! the ledger, and none can be paid after the window closes. A live approval
+//! that was not consumed can be revoked. Lapsed approvals are pruned on the
+//! next approval.
+//!
+//! Approvals, revocations and first consumptions are logged as events and
+//! rebuilt by replay.
+
+use crate::state::State;
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+pub use rumi_common::types::TreasuryWithdrawalRequest;
+
+/// Longest an approval may stay usable (24 hours, the ICRC-1 ledgers'
+/// deduplication window).
+pub const MAX_TREASURY_APPROVAL_TTL_NS: u64 = 24 * 3600 * 1_000_000_000;
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreasuryWithdrawalApproval {
+    pub request: TreasuryWithdrawalRequest,
+    pub approved_by: Principal,
+    pub approved_at_ns: u64,
+    pub expires_at_ns: u64,
+    /// When the treasury first consumed it, `None` while unused.
+    pub consumed_at_ns: Option<u64>,
+}
+
+impl TreasuryWithdrawalApproval {
+    pub fn is_expired_at(&self, now_ns: u64) -> bool {
+        now_ns >= self.expires_at_ns
+    }
+}
+
+/// Check an approval request and return its expiry.
+pub fn validate_approval(
+    state: &State,
+    request: &TreasuryWithdrawalRequest,
+    ttl_secs: u64,
+    now_ns: u64,
+) -> Result<u64, String> {
+    if request.amount == 0 {
+        return Err("An approval needs a non-zero amount".to_string());
+    }
+    if ttl_secs == 0 {
+        return Err("An approval needs a non-zero lifetime".to_string());
+    }
+    let ttl_ns = ttl_secs.saturating_mul(1_000_000_000);
+    if ttl_ns > MAX_TREASURY_APPROVAL_TTL_NS {
+        return Err(format!(
+            "An approval can last at most {} hours",
+            MAX_TREASURY_APPROVAL_TTL_NS / (3600 * 1_000_000_000)
+        ));
+    }
+    if let Some(existing) = live_approval(state, request.request_id, now_ns) {
+        return Err(format!(
+            "Treasury request {} is already approved until {}",
+            request.request_id, existing.expires_at_ns
+        ));
+    }
+    Ok(now_ns.saturating_add(ttl_ns))
+}
+
+/// The approval of `request_id`, if it is live at `now_ns`.
+pub fn live_approval(
+    state: &State,
+    request_id: u64,
+    now_ns: u64,
+) -> Option<&TreasuryWithdrawalApproval> {
+    state
+        .treasury_withdrawal_approvals
+        .get(&request_id)
+        .filter(|a| !a.is_expired_at(now_ns))
+}
+
+/// Record an approval and prune lapsed ones. Approving a request again
+/// replaces its approval, unconsumed.
+pub fn apply_approve(
+    state: &mut State,
+    request: TreasuryWithdrawalRequest,
+    approved_by: Principal,
+    expires_at_ns: u64,
+    now_ns: u64,
+) {
+    state
+        .treasury_withdrawal_approvals
+        .retain(|_, a| !a.is_expired_at(now_ns));
+    state.treasury_withdrawal_approvals.insert(
+        request.request_id,
+        TreasuryWithdrawalApproval {
+            request,
+            approved_by,
+            approved_at_ns: now_ns,
+            expires_at_ns,
+            consumed_at_ns: None,
+        },
+    );
+}
+
+pub fn apply_revoke(state: &mut State, request_id: u64, now_ns: u64) -> Result<(), String> {
+    match live_approval(state, request_id, now_ns) {
+        None => Err(format!("Treasury request {} is not approved", request_id)),
+        Some(a) if a.consumed_at_ns.is_some() => Err(format!(
+            "Treasury request {} was already used and cannot be revoked",
+            request_id
+        )),
+        Some(_) => {
+            state.treasury_withdrawal_approvals.remove(&request_id);
+            Ok(())
+        }
+    }
+}
+
+/// Check the treasury's withdrawal against its approval. Returns whether this
+/// is the first consumption, which the caller must record.
+pub fn check_consume(
+    state: &State,
+    request: &TreasuryWithdrawalRequest,
+    now_ns: u64,
+) -> Result<bool, String> {
+    let approval = live_approval(state, request.request_id, now_ns).ok_or_else(|| {
+        format!(
+            "Treasury request {} has no live co-approval",
+            request.request_id
+        )
+    })?;
+    if approval.request != *request {
+        return Err(format!(
+            "Treasury request {} does not match its co-approval ({} of {} to {})",
+            request.request_id,
+            approval.request.amount,
+            approval.request.ledger,
+            approval.request.to
+        ));
+    }
+    Ok(approval.consumed_at_ns.is_none())
+}
+
+pub fn apply_consume(state: &mut State, request_id: u64, now_ns: u64) {
+    if let Some(approval) = state.treasury_withdrawal_approvals.get_mut(&request_id) {
+        approval.consumed_at_ns.get_or_insert(now_ns);
+    }
+}
+
+/// Approvals still live at `now_ns`, lowest `request_id` first.
+pub fn approvals(state: &State, now_ns: u64) -> Vec<TreasuryWithdrawalApproval> {
+    state
+        .treasury_withdrawal_approvals
+        .values()
+        .filter(|a| !a.is_expired_at(now_ns))
+        .cloned()
+        .collect()
+}
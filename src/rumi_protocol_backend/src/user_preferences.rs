@@ -0,0 +1,128 @@
+//! Per-principal preferences shared by the user-facing features.
+//!
+//! A principal stores its `UserPreferences` with `set_my_preferences` and
+//! reads them back with `get_my_preferences`. They are consumed by:
+//!
+//! - consent messages (`icrc21`): a wallet that sends no language gets the
+//!   caller's stored `language`.
+//! - notifications (`notifications`): `push_notification` drops entries the
+//!   owner opted out of, and risk changes that stay below `alert_threshold`.
+//! - liquidation receipts (`liquidation_receipts`): the receipt is always
+//!   kept; its `Liquidated` notification honours `notify_liquidations`.
+//!
+//! The defaults reproduce the behaviour from before preferences existed, and
+//! storing them removes the principal's entry, so only principals that
+//! changed something take space. Like notifications, preferences live in the
+//! state snapshot only and are not rebuilt by event replay.
+
+use crate::icrc21::Locale;
+use crate::notifications::{VaultNotification, VaultNotificationKind, VaultRiskLevel};
+use crate::state::State;
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+/// Longest language tag accepted. Enough for any BCP-47 tag in practice.
+pub const MAX_LANGUAGE_TAG_LEN: usize = 35;
+
+/// Principals with non-default preferences stored at most.
+pub const MAX_PREFERENCE_ENTRIES: usize = 100_000;
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserPreferences {
+    /// BCP-47 language tag, e.g. `es` or `es-MX`.
+    pub language: Option<String>,
+    pub notify_risk_changes: bool,
+    pub notify_freezes: bool,
+    pub notify_liquidations: bool,
+    /// Risk changes are only notified when the vault moves into or out of
+    /// this level or a worse one. `Healthy` notifies every change.
+    pub alert_threshold: VaultRiskLevel,
+}
+
+impl Default for UserPreferences {
+    fn default() -> Self {
+        Self {
+            language: None,
+            notify_risk_changes: true,
+            notify_freezes: true,
+            notify_liquidations: true,
+            alert_threshold: VaultRiskLevel::Healthy,
+        }
+    }
+}
+
+impl UserPreferences {
+    /// Whether the owner wants `notification` queued.
+    pub fn wants(&self, notification: &VaultNotification) -> bool {
+        match notification.kind {
+            VaultNotificationKind::RiskChange => {
+                self.notify_risk_changes
+                    && notification.previous_level.max(notification.new_level)
+                        >= self.alert_threshold
+            }
+            VaultNotificationKind::Frozen { .. } | VaultNotificationKind::Unfrozen => {
+                self.notify_freezes
+            }
+            VaultNotificationKind::Liquidated { .. } => self.notify_liquidations,
+        }
+    }
+}
+
+fn validate_language(tag: &str) -> Result<(), String> {
+    if tag.is_empty() || tag.len() > MAX_LANGUAGE_TAG_LEN {
+        return Err(format!(
+            "language must be 1 to {} characters",
+            MAX_LANGUAGE_TAG_LEN
+        ));
+    }
+    if !tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err("language may only hold letters, digits and '-'".to_string());
+    }
+    Ok(())
+}
+
+/// `principal`'s preferences, the defaults when it stored none.
+pub fn preferences(state: &State, principal: Principal) -> UserPreferences {
+    state
+        .user_preferences
+        .get(&principal)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Store `preferences` for `principal`. Storing the defaults removes the
+/// entry.
+pub fn set_preferences(
+    state: &mut State,
+    principal: Principal,
+    preferences: UserPreferences,
+) -> Result<(), String> {
+    if let Some(tag) = &preferences.language {
+        validate_language(tag)?;
+    }
+    if preferences == UserPreferences::default() {
+        state.user_preferences.remove(&principal);
+        return Ok(());
+    }
+    if !state.user_preferences.contains_key(&principal)
+        && state.user_preferences.len() >= MAX_PREFERENCE_ENTRIES
+    {
+        return Err("preferences store is full".to_string());
+    }
+    state.user_preferences.insert(principal, preferences);
+    Ok(())
+}
+
+/// The consent message locale for `principal`: `requested_tag` when the
+/// wallet sent one, else the stored language, else the fallback.
+pub fn consent_locale(state: &State, principal: Principal, requested_tag: &str) -> Locale {
+    if !requested_tag.trim().is_empty() {
+        return Locale::from_language_tag(requested_tag);
+    }
+    state
+        .user_preferences
+        .get(&principal)
+        .and_then(|p| p.language.as_deref())
+        .map(Locale::from_language_tag)
+        .unwrap_or(Locale::FALLBACK)
+}
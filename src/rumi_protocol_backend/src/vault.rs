@@ -1,16 +1,18 @@
 use crate::event::{
     record_add_margin_to_vault, record_borrow_from_vault, record_open_vault,
-    record_redemption_on_vaults, record_repayed_to_vault,
+    record_redemption_on_vaults, record_repayed_to_vault, record_session_key_used,
 };
-use crate::guard::{GuardPrincipal, VaultLiquidationGuard};
-use crate::logs::INFO;
+use crate::guard::{trace_tag, GuardPrincipal, TraceTag, VaultLiquidationGuard};
+use crate::logs::{CRITICAL, INFO};
 use crate::management;
 use crate::management::{
     mint_icusd, transfer_collateral, transfer_collateral_from, transfer_icusd_from,
     transfer_stable_from,
 };
 use crate::numeric::{Ratio, UsdIcp, ICP, ICUSD};
+use crate::session_keys::SessionScope;
 use crate::state::Mode;
+use crate::vault_status::{VaultOperation, VaultStatus};
 use crate::GuardError;
 use crate::PendingMarginTransfer;
 use crate::DEBUG;
@@ -30,6 +32,10 @@ use std::panic::{catch_unwind, AssertUnwindSafe};
 
 use crate::compute_collateral_ratio;
 
+/// Defined in `rumi_common` so the stability pool can build them without the
+/// backend; re-exported to keep the `vault::` paths.
+pub use rumi_common::types::{CandidVault, VaultArg};
+
 /// INT-003 defense in depth: clamp a raw borrow fee so `amount - fee >= 1 e8s`.
 /// The validation cap on borrowing-fee curve multipliers (see
 /// `state::MAX_BORROWING_FEE_MULTIPLIER`) is the primary fence; this clamp
@@ -42,6 +48,20 @@ pub fn clamp_borrow_fee(amount: ICUSD, raw_fee: ICUSD) -> ICUSD {
     raw_fee.min(amount.saturating_sub(ICUSD::new(1)))
 }
 
+/// The collateral's `min_vault_debt` if `debt` would fall strictly between
+/// zero and it, `None` if `debt` is allowed.
+pub fn below_min_vault_debt(
+    state: &crate::state::State,
+    vault: &Vault,
+    debt: ICUSD,
+) -> Option<ICUSD> {
+    let min_vault_debt = state
+        .get_collateral_config(&vault.collateral_type)
+        .map(|c| c.min_vault_debt)
+        .unwrap_or(ICUSD::new(0));
+    (debt > ICUSD::new(0) && debt < min_vault_debt).then_some(min_vault_debt)
+}
+
 /// Checks that a partial repayment won't leave the vault with dust debt below `min_vault_debt`.
 /// Returns Ok(()) if remaining debt is zero or >= min_vault_debt, Err otherwise.
 fn check_min_vault_debt_after_repay(
@@ -49,19 +69,31 @@ fn check_min_vault_debt_after_repay(
     repay_amount: ICUSD,
 ) -> Result<(), ProtocolError> {
     let remaining_debt = vault.borrowed_icusd_amount - repay_amount;
-    if remaining_debt > ICUSD::new(0) {
-        let min_vault_debt = read_state(|s| {
-            s.get_collateral_config(&vault.collateral_type)
-                .map(|c| c.min_vault_debt)
-                .unwrap_or(ICUSD::new(0))
-        });
-        if remaining_debt < min_vault_debt {
-            return Err(ProtocolError::GenericError(format!(
-                "Partial repayment would leave {} icUSD debt, below the minimum of {}. \
-                 Repay the full amount or leave at least {} icUSD.",
-                remaining_debt, min_vault_debt, min_vault_debt
-            )));
-        }
+    if let Some(min_vault_debt) = read_state(|s| below_min_vault_debt(s, vault, remaining_debt)) {
+        return Err(ProtocolError::GenericError(format!(
+            "Partial repayment would leave {} icUSD debt, below the minimum of {}. \
+             Repay the full amount or leave at least {} icUSD.",
+            remaining_debt, min_vault_debt, min_vault_debt
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that a borrow leaves the vault owing at least `min_vault_debt`.
+fn check_min_vault_debt_after_borrow(
+    vault: &Vault,
+    borrow_amount: ICUSD,
+) -> Result<(), ProtocolError> {
+    let debt = vault.borrowed_icusd_amount + borrow_amount;
+    if let Some(min_vault_debt) = read_state(|s| below_min_vault_debt(s, vault, debt)) {
+        return Err(ProtocolError::GenericError(format!(
+            "Borrowing {} icUSD would leave the vault owing {} icUSD, below the minimum \
+             of {}. Borrow at least {} icUSD.",
+            borrow_amount,
+            debt,
+            min_vault_debt,
+            min_vault_debt - vault.borrowed_icusd_amount
+        )));
     }
     Ok(())
 }
@@ -92,18 +124,30 @@ pub fn round_up_partial_liq_dust(
     }
 }
 
+/// Liquidator slippage bound: reject when the collateral payout computed from
+/// the execution-time price is below the caller's `min_collateral_out`
+/// (native collateral units, net of the protocol's cut). Checked before the
+/// liquidator's funds are pulled, so a rejected call leaves no trace; the
+/// per-vault lock keeps the payout from shrinking across that `await`.
+pub fn check_min_collateral_out(
+    collateral_out: ICP,
+    min_collateral_out: Option<u64>,
+) -> Result<(), ProtocolError> {
+    match min_collateral_out {
+        Some(min) if collateral_out.to_u64() < min => Err(ProtocolError::MinCollateralOutNotMet {
+            min_collateral_out: min,
+            collateral_out: collateral_out.to_u64(),
+        }),
+        _ => Ok(()),
+    }
+}
+
 #[derive(CandidType, Clone, Debug, Deserialize, PartialEq, Eq)]
 pub struct OpenVaultSuccess {
     pub vault_id: u64,
     pub block_index: u64,
 }
 
-#[derive(CandidType, Deserialize)]
-pub struct VaultArg {
-    pub vault_id: u64,
-    pub amount: u64,
-}
-
 /// Returns `Principal::anonymous()` as sentinel for old events missing `collateral_type`.
 /// The replay handler replaces this with the actual ICP ledger principal.
 pub(crate) fn default_collateral_type() -> Principal {
@@ -182,26 +226,34 @@ pub fn require_vault_not_processing(vault: &Vault) -> Result<(), ProtocolError>
 /// the bot's swap settles), a manual / stability-pool liquidation here would
 /// seize the same collateral a second time. Mirrors the lock every user op
 /// already honors. Absent vault => Ok (a later check surfaces "not found").
+/// Also applies the lifecycle gate, so a vault mid-close cannot be liquidated.
 pub fn reject_if_bot_processing(vault_id: u64) -> Result<(), ProtocolError> {
     match read_state(|s| s.vault_id_to_vaults.get(&vault_id).cloned()) {
-        Some(vault) => require_vault_not_processing(&vault),
-        None => Ok(()),
+        Some(vault) => require_vault_not_processing(&vault)?,
+        None => return Ok(()),
     }
+    reject_if_status_disallows(vault_id, VaultOperation::Liquidate)
 }
 
-#[derive(CandidType, Serialize, Deserialize, Debug)]
-pub struct CandidVault {
-    pub owner: Principal,
-    pub borrowed_icusd_amount: u64,
-    /// Kept for frontend backward compatibility
-    pub icp_margin_amount: u64,
-    pub vault_id: u64,
-    /// Raw collateral amount (same value as icp_margin_amount for ICP vaults)
-    pub collateral_amount: u64,
-    /// Ledger canister ID of the collateral token
-    pub collateral_type: Principal,
-    /// Accumulated interest portion of the vault's debt (in e8s)
-    pub accrued_interest: u64,
+/// Lifecycle gate for vault operations. See `vault_status`.
+pub fn reject_if_status_disallows(vault_id: u64, op: VaultOperation) -> Result<(), ProtocolError> {
+    read_state(|s| crate::vault_status::require_allows(s, vault_id, op))
+}
+
+/// Recovery-mode pool priority gate for manual liquidations and bot claims:
+/// a vault reserved for the stability pool is off limits to everyone else
+/// until the pool has had its attempt. See `pool_priority`.
+pub fn reject_if_reserved_for_pool(vault_id: u64, caller: Principal) -> Result<(), ProtocolError> {
+    read_state(|s| {
+        crate::pool_priority::require_not_reserved(s, vault_id, caller, ic_cdk::api::time())
+    })
+}
+
+/// Guardian freeze gate for the owner operations that move value out of a
+/// vault: withdrawals, borrows and closes. Repay and add-margin never call
+/// it. See `vault_freeze`.
+pub fn reject_if_vault_frozen(vault_id: u64, now_ns: u64) -> Result<(), ProtocolError> {
+    read_state(|s| crate::vault_freeze::require_vault_not_frozen(s, vault_id, now_ns))
 }
 
 impl From<Vault> for CandidVault {
@@ -214,6 +266,13 @@ impl From<Vault> for CandidVault {
             collateral_amount: vault.collateral_amount,
             collateral_type: vault.collateral_type,
             accrued_interest: vault.accrued_interest.to_u64(),
+            // Without the state at hand only the bot claim is visible;
+            // `vault_status::candid_vault` fills in the stored status.
+            status: if vault.bot_processing {
+                VaultStatus::Liquidating
+            } else {
+                VaultStatus::Active
+            },
         }
     }
 }
@@ -232,9 +291,7 @@ pub async fn redeem_reserves(
     // calls record_redemption_on_vaults DIRECTLY (it does not route through
     // redeem_collateral), so the redeem_collateral gate does not cover it. Gate
     // at this entry point so the reserve path is covered by construction as well.
-    if read_state(|s| s.mode) == Mode::ReadOnly {
-        return Err(ProtocolError::read_only_mode());
-    }
+    read_state(|s| s.mode).check_available()?;
 
     let icusd_amount: ICUSD = icusd_amount_raw.into();
 
@@ -245,12 +302,11 @@ pub async fn redeem_reserves(
     }
 
     // Check reserve redemptions are enabled
-    let (enabled, reserve_fee_ratio, ckusdt_ledger, ckusdc_ledger, treasury) = read_state(|s| {
+    let (enabled, global_fee_ratio, stables, treasury) = read_state(|s| {
         (
             s.reserve_redemptions_enabled,
             s.reserve_redemption_fee,
-            s.ckusdt_ledger_principal,
-            s.ckusdc_ledger_principal,
+            crate::reserve_stables::enabled(s),
             s.treasury_principal,
         )
     });
@@ -261,24 +317,62 @@ pub async fn redeem_reserves(
         ));
     }
 
-    // Determine which ledger to use
-    let stable_ledger = if let Some(pref) = preferred_token {
-        // Validate it's one of our known stable ledgers
-        if Some(pref) == ckusdt_ledger || Some(pref) == ckusdc_ledger {
-            pref
-        } else {
-            return Err(ProtocolError::GenericError(
-                "Preferred token is not a supported reserve token.".to_string(),
-            ));
+    // Determine which ledger to use: the preferred one, or the configured
+    // priority list (see `reserve_stables`).
+    let candidates: Vec<crate::reserve_stables::ReserveStable> = match preferred_token {
+        Some(pref) => match stables.into_iter().find(|s| s.ledger == pref) {
+            Some(stable) => vec![stable],
+            None => {
+                return Err(ProtocolError::GenericError(
+                    "Preferred token is not a supported reserve token.".to_string(),
+                ));
+            }
+        },
+        None => stables,
+    };
+    if candidates.is_empty() {
+        return Err(ProtocolError::GenericError(
+            "No reserve token ledgers configured.".to_string(),
+        ));
+    }
+
+    // Check reserve balances before pulling icUSD
+    let mut balances = Vec::with_capacity(candidates.len());
+    for candidate in &candidates {
+        match management::get_token_balance(candidate.ledger).await {
+            Ok(balance) => balances.push(balance),
+            // A single token has nowhere else to go.
+            Err(e) if candidates.len() == 1 => {
+                return Err(ProtocolError::TemporarilyUnavailable(format!(
+                    "Cannot query reserve balance: {}",
+                    e
+                )));
+            }
+            Err(e) => {
+                log!(
+                    crate::INFO,
+                    "[redeem_reserves] trace={} Cannot query {} reserve balance: {}. Skipping it.",
+                    trace_tag(caller),
+                    candidate.symbol,
+                    e
+                );
+                balances.push(0);
+            }
         }
-    } else {
-        // Default: try ckUSDT first, then ckUSDC
-        ckusdt_ledger.or(ckusdc_ledger).ok_or_else(|| {
+    }
+    let stable = crate::reserve_stables::pick(&candidates, &balances, icusd_amount.to_u64())
+        .ok_or_else(|| {
             ProtocolError::GenericError("No reserve token ledgers configured.".to_string())
-        })?
-    };
+        })?;
+    let stable_ledger = stable.ledger;
+    let decimals = stable.decimals;
+    let reserve_balance = candidates
+        .iter()
+        .position(|c| c.ledger == stable_ledger)
+        .map_or(0, |i| balances[i]);
 
-    // Calculate fee (flat rate)
+    // Calculate fee (flat rate, per token if it has its own)
+    let reserve_fee_ratio = crate::reserve_stables::fee_ratio(stable, global_fee_ratio);
     let fee_icusd = icusd_amount * reserve_fee_ratio;
     let net_icusd = icusd_amount - fee_icusd;
 
@@ -286,46 +380,41 @@ pub async fn redeem_reserves(
     let rmr = read_state(|s| s.get_redemption_margin_ratio());
     let effective_icusd = net_icusd * rmr;
 
-    // Convert e8s (icUSD) to e6s (ckStable): divide by 100
-    let net_e6s = effective_icusd.to_u64() / 100;
-    let fee_e6s = fee_icusd.to_u64() / 100;
+    // Convert e8s (icUSD) to the stable's units (e6s for ckUSDT/ckUSDC)
+    let net_native = crate::reserve_stables::e8s_to_native(effective_icusd.to_u64(), decimals);
+    let fee_native = crate::reserve_stables::e8s_to_native(fee_icusd.to_u64(), decimals);
 
-    if net_e6s == 0 {
+    if net_native == 0 {
         return Err(ProtocolError::GenericError(
             "Redemption amount too small after fee.".to_string(),
         ));
     }
 
-    // Check reserve balance before pulling icUSD
-    let reserve_balance = management::get_token_balance(stable_ledger)
-        .await
-        .map_err(|e| {
-            ProtocolError::TemporarilyUnavailable(format!("Cannot query reserve balance: {}", e))
-        })?;
-
     // Determine how much can come from reserves vs vault spillover.
     // Each ICRC-1 transfer also costs a ledger fee (deducted from sender balance).
     // Query the actual fee from the ledger rather than hardcoding.
     let ledger_fee = management::get_ledger_fee(stable_ledger)
         .await
-        .unwrap_or(10_000); // fallback to 10_000 e6s (0.01 USD) if query fails
-    let fee_budget = if fee_e6s > 0 {
+        // fallback to 0.01 USD if query fails
+        .unwrap_or_else(|_| crate::reserve_stables::e8s_to_native(1_000_000, decimals));
+    let fee_budget = if fee_native > 0 {
         ledger_fee * 2
     } else {
         ledger_fee
     };
-    let total_needed_e6s = net_e6s + fee_e6s + fee_budget;
-    let available_for_user = if reserve_balance >= total_needed_e6s {
-        net_e6s
-    } else if reserve_balance > fee_e6s + fee_budget {
+    let total_needed = net_native + fee_native + fee_budget;
+    let available_for_user = if reserve_balance >= total_needed {
+        net_native
+    } else if reserve_balance > fee_native + fee_budget {
         // Partial: reserve can cover some but not all
-        reserve_balance - fee_e6s - fee_budget
+        reserve_balance - fee_native - fee_budget
     } else {
         0
     };
 
-    let spillover_e6s = net_e6s - available_for_user;
-    let spillover_e8s = spillover_e6s * 100; // convert back to icUSD e8s
+    let spillover_native = net_native - available_for_user;
+    // convert back to icUSD e8s
+    let spillover_e8s = crate::reserve_stables::native_to_e8s(spillover_native, decimals);
 
     // Pull icUSD from caller (effectively burns it)
     let icusd_block_index = transfer_icusd_from(icusd_amount, caller)
@@ -347,7 +436,8 @@ pub async fn redeem_reserves(
         {
             log!(
                 crate::INFO,
-                "[redeem_reserves] ckStable transfer failed for {}: {:?}. Refunding {} icUSD.",
+                "[redeem_reserves] trace={} ckStable transfer failed for {}: {:?}. Refunding {} icUSD.",
+                trace_tag(caller),
                 caller,
                 transfer_err,
                 icusd_amount.to_u64()
@@ -357,7 +447,8 @@ pub async fn redeem_reserves(
                 Ok(refund_block) => {
                     log!(
                         crate::INFO,
-                        "[redeem_reserves] Refunded {} icUSD to {} (block {})",
+                        "[redeem_reserves] trace={} Refunded {} icUSD to {} (block {})",
+                        trace_tag(caller),
                         icusd_amount.to_u64(),
                         caller,
                         refund_block
@@ -365,9 +456,10 @@ pub async fn redeem_reserves(
                 }
                 Err(refund_err) => {
                     log!(crate::INFO,
-                        "[redeem_reserves] ckStable transfer failed AND inline icUSD refund failed for {}! \
+                        "[redeem_reserves] trace={} ckStable transfer failed AND inline icUSD refund failed for {}! \
                          Amount: {} icUSD, ckStable error: {:?}, refund error: {:?}. \
                          Enqueueing durable refund (block {}).",
+                        trace_tag(caller),
                         caller, icusd_amount.to_u64(), transfer_err, refund_err, icusd_block_index
                     );
                     mutate_state(|s| {
@@ -381,9 +473,7 @@ pub async fn redeem_reserves(
                             },
                         );
                     });
-                    ic_cdk_timers::set_timer(std::time::Duration::from_secs(2), || {
-                        ic_cdk::spawn(crate::process_pending_transfer())
-                    });
+                    crate::scheduler::kick_pending_transfers(2);
                 }
             }
             return Err(ProtocolError::GenericError(format!(
@@ -394,14 +484,15 @@ pub async fn redeem_reserves(
     }
 
     // Transfer fee to treasury (if configured), otherwise fee stays in reserves
-    if fee_e6s > 0 {
+    if fee_native > 0 {
         if let Some(treasury_principal) = treasury {
             if let Err(e) =
-                management::transfer_collateral(fee_e6s, treasury_principal, stable_ledger).await
+                management::transfer_collateral(fee_native, treasury_principal, stable_ledger).await
             {
                 log!(crate::INFO,
-                    "[redeem_reserves] WARNING: treasury fee transfer failed ({} e6s to {}): {:?}. Fee stays in reserves.",
-                    fee_e6s, treasury_principal, e
+                    "[redeem_reserves] trace={} WARNING: treasury fee transfer failed ({} to {}): {:?}. Fee stays in reserves.",
+                    trace_tag(caller),
+                    fee_native, treasury_principal, e
                 );
             }
         }
@@ -414,7 +505,7 @@ pub async fn redeem_reserves(
         fee_icusd,
         stable_ledger,
         available_for_user,
-        fee_e6s,
+        fee_native,
         icusd_block_index,
     );
 
@@ -461,18 +552,24 @@ pub async fn redeem_reserves(
             // for full rationale). The spillover redeems against `best_ct`,
             // so the base rate is read from and written back to that
             // collateral's config alone.
-            let base_fee = s.get_redemption_fee_for(&best_ct, spillover_icusd);
-            crate::record_per_collateral_redemption_fee(s, &best_ct, base_fee, ic_cdk::api::time());
+            let now = ic_cdk::api::time();
+            let (base_fee, base_rate) = s.redemption_fee_at(&best_ct, spillover_icusd, now);
+            crate::event::record_redemption_base_rate(s, best_ct, base_rate, now);
             let vault_fee = spillover_icusd * base_fee;
 
             // Note: RMR was already applied when computing spillover_e8s (line 160).
             // Do NOT apply it again here — that would double-discount.
             let effective_spillover = spillover_icusd - vault_fee;
+            // A capped `best_ct` only takes what is left of this epoch's cap;
+            // the rest is refunded with the unconsumed remainder below.
+            let capped_spillover = effective_spillover.min(ICUSD::from(
+                crate::redemption_caps::remaining(s, &best_ct, ic_cdk::api::time()),
+            ));
 
             let outcome = record_redemption_on_vaults(
                 s,
                 caller,
-                effective_spillover,
+                capped_spillover,
                 vault_fee,
                 current_price,
                 icusd_block_index,
@@ -481,11 +578,19 @@ pub async fn redeem_reserves(
 
             // Wave-8e LIQ-005: route the spillover-portion fee through
             // deficit repayment. icUSD already burned via `transfer_icusd_from`.
-            let _routing = crate::treasury::plan_fee_routing(
+            let routing = crate::treasury::plan_fee_routing(
                 s,
                 vault_fee,
                 crate::event::FeeSource::RedemptionFee,
             );
+            let icusd_ledger = s.icusd_ledger_principal;
+            crate::surplus::retain_fee(
+                s,
+                icusd_ledger,
+                routing.to_remainder.to_u64(),
+                crate::event::FeeSource::RedemptionFee,
+                now,
+            );
 
             // RED-001: unconsumed spillover is refunded (RMR already applied
             // upstream, so the unconsumed effective amount IS the raw refund;
@@ -506,7 +611,8 @@ pub async fn redeem_reserves(
                 Ok(refund_block) => {
                     log!(
                         crate::INFO,
-                        "[redeem_reserves] Refunded {} unconsumed spillover icUSD to {} (block {})",
+                        "[redeem_reserves] trace={} Refunded {} unconsumed spillover icUSD to {} (block {})",
+                        trace_tag(caller),
                         refund_e8s,
                         caller,
                         refund_block
@@ -514,7 +620,8 @@ pub async fn redeem_reserves(
                 }
                 Err(refund_err) => {
                     log!(crate::INFO,
-                        "[redeem_reserves] Unconsumed-spillover refund of {} icUSD to {} failed: {:?}. Enqueueing durable refund (block {}).",
+                        "[redeem_reserves] trace={} Unconsumed-spillover refund of {} icUSD to {} failed: {:?}. Enqueueing durable refund (block {}).",
+                        trace_tag(caller),
                         refund_e8s, caller, refund_err, icusd_block_index
                     );
                     mutate_state(|s| {
@@ -531,13 +638,12 @@ pub async fn redeem_reserves(
                 }
             }
         }
-        ic_cdk_timers::set_timer(std::time::Duration::from_secs(0), || {
-            ic_cdk::spawn(crate::process_pending_transfer())
-        });
+        crate::scheduler::kick_pending_transfers(0);
     }
 
-    log!(INFO, "[redeem_reserves] {} redeemed {} icUSD: {} e6s from reserves, {} e8s vault spillover, fee {} e6s",
-        caller, icusd_amount.to_u64(), available_for_user, spillover_e8s, fee_e6s);
+    log!(INFO, "[redeem_reserves] trace={} {} redeemed {} icUSD: {} from reserves, {} e8s vault spillover, fee {}",
+        trace_tag(caller),
+        caller, icusd_amount.to_u64(), available_for_user, spillover_e8s, fee_native);
 
     Ok(crate::ReserveRedemptionResult {
         icusd_block_index,
@@ -574,9 +680,7 @@ pub async fn redeem_collateral(
     // (redeem_icp, redeem_collateral) is covered by construction. The Wave-9
     // fix lived only in main.rs::validate_mode and the redeem_icp endpoint
     // bypassed it. Same error as that gate via the shared constructor.
-    if read_state(|s| s.mode) == Mode::ReadOnly {
-        return Err(ProtocolError::read_only_mode());
-    }
+    read_state(|s| s.mode).check_available()?;
 
     let icusd_amount: ICUSD = _icusd_amount.into();
 
@@ -658,6 +762,15 @@ pub async fn redeem_collateral(
             total_redeemable.to_u64()
         )));
     }
+    // A capped collateral only takes what is left of this epoch's cap.
+    read_state(|s| {
+        crate::redemption_caps::check_capacity(
+            s,
+            &redeem_ct,
+            estimated_effective.to_u64(),
+            ic_cdk::api::time(),
+        )
+    })?;
 
     match transfer_icusd_from(icusd_amount, caller).await {
         Ok(block_index) => {
@@ -668,23 +781,27 @@ pub async fn redeem_collateral(
                 // redemption against one collateral no longer corrupts the
                 // base rate used to price redemptions against any other.
                 // RED-002: keyed on the seized collateral, not the caller's.
-                let base_fee = s.get_redemption_fee_for(&redeem_ct, icusd_amount);
-                crate::record_per_collateral_redemption_fee(
-                    s,
-                    &redeem_ct,
-                    base_fee,
-                    ic_cdk::api::time(),
-                );
+                // The fee is the post-redemption base rate clamped to the
+                // floor and ceiling; the unclamped rate is what decays.
+                let now = ic_cdk::api::time();
+                let (base_fee, base_rate) = s.redemption_fee_at(&redeem_ct, icusd_amount, now);
+                crate::event::record_redemption_base_rate(s, redeem_ct, base_rate, now);
                 let fee_amount = icusd_amount * base_fee;
 
                 // Apply dynamic Redemption Margin Ratio: redeemers get RMR × face value
                 let rmr = s.get_redemption_margin_ratio();
                 let effective_icusd = (icusd_amount - fee_amount) * rmr;
+                // The capacity check ran before the icUSD pull, so concurrent
+                // redemptions may have used the epoch since. Only redeem what
+                // is left; the unconsumed refund below returns the rest.
+                let capped_icusd = effective_icusd.min(ICUSD::from(
+                    crate::redemption_caps::remaining(s, &redeem_ct, now),
+                ));
 
                 let outcome = record_redemption_on_vaults(
                     s,
                     caller,
-                    effective_icusd,
+                    capped_icusd,
                     fee_amount,
                     current_collateral_price,
                     block_index,
@@ -712,11 +829,19 @@ pub async fn redeem_collateral(
                 // (the protocol's main account is the icUSD minting
                 // account), so the supply side is already correct — this
                 // is a pure state mutation that decrements the deficit.
-                let _routing = crate::treasury::plan_fee_routing(
+                let routing = crate::treasury::plan_fee_routing(
                     s,
                     fee_amount,
                     crate::event::FeeSource::RedemptionFee,
                 );
+                let icusd_ledger = s.icusd_ledger_principal;
+                crate::surplus::retain_fee(
+                    s,
+                    icusd_ledger,
+                    routing.to_remainder.to_u64(),
+                    crate::event::FeeSource::RedemptionFee,
+                    now,
+                );
 
                 (fee_amount, outcome, refund_e8s)
             });
@@ -737,7 +862,8 @@ pub async fn redeem_collateral(
                     Ok(refund_block) => {
                         log!(
                             INFO,
-                            "[redeem_collateral] Refunded {} unconsumed icUSD to {} (block {})",
+                            "[redeem_collateral] trace={} Refunded {} unconsumed icUSD to {} (block {})",
+                            trace_tag(caller),
                             refund_e8s,
                             caller,
                             refund_block
@@ -745,7 +871,8 @@ pub async fn redeem_collateral(
                     }
                     Err(refund_err) => {
                         log!(INFO,
-                            "[redeem_collateral] Unconsumed-claim refund of {} icUSD to {} failed: {:?}. Enqueueing durable refund (block {}).",
+                            "[redeem_collateral] trace={} Unconsumed-claim refund of {} icUSD to {} failed: {:?}. Enqueueing durable refund (block {}).",
+                            trace_tag(caller),
                             refund_e8s, caller, refund_err, block_index
                         );
                         mutate_state(|s| {
@@ -763,9 +890,7 @@ pub async fn redeem_collateral(
                 }
             }
 
-            ic_cdk_timers::set_timer(std::time::Duration::from_secs(0), || {
-                ic_cdk::spawn(crate::process_pending_transfer())
-            });
+            crate::scheduler::kick_pending_transfers(0);
             Ok(SuccessWithFee {
                 block_index,
                 fee_amount_paid: fee_amount.to_u64(),
@@ -793,7 +918,8 @@ pub async fn open_vault(
         Err(GuardError::AlreadyProcessing) => {
             log!(
                 INFO,
-                "[open_vault] Principal {:?} already has an ongoing operation",
+                "[open_vault] trace={} Principal {:?} already has an ongoing operation",
+                trace_tag(caller),
                 caller
             );
             return Err(ProtocolError::AlreadyProcessing);
@@ -801,7 +927,8 @@ pub async fn open_vault(
         Err(GuardError::StaleOperation) => {
             log!(
                 INFO,
-                "[open_vault] Principal {:?} has a stale operation that's being cleaned up",
+                "[open_vault] trace={} Principal {:?} has a stale operation that's being cleaned up",
+                trace_tag(caller),
                 caller
             );
             return Err(ProtocolError::TemporarilyUnavailable(
@@ -885,7 +1012,11 @@ pub async fn open_vault(
 
             match vault_result {
                 Ok(vault_id) => {
-                    log!(INFO, "[open_vault] opened vault with id: {vault_id}");
+                    log!(
+                        INFO,
+                        "[open_vault] trace={} opened vault with id: {vault_id}",
+                        trace_tag(caller)
+                    );
                     guard_principal.complete();
                     Ok(OpenVaultSuccess {
                         vault_id,
@@ -894,9 +1025,10 @@ pub async fn open_vault(
                 }
                 Err(panic_info) => {
                     // State mutation failed -- refund collateral to caller
-                    log!(INFO,
-                        "[open_vault] CRITICAL: vault record creation panicked after collateral transfer \
+                    log!(CRITICAL,
+                        "[open_vault] trace={} CRITICAL: vault record creation panicked after collateral transfer \
                          (block {}). Attempting refund of {} to {}. Panic: {:?}",
+                        trace_tag(caller),
                         block_index, collateral_amount_raw, caller, panic_info
                     );
 
@@ -917,16 +1049,18 @@ pub async fn open_vault(
                             Ok(refund_block) => {
                                 log!(
                                     INFO,
-                                    "[open_vault] Refunded {} collateral to {} (block {})",
+                                    "[open_vault] trace={} Refunded {} collateral to {} (block {})",
+                                    trace_tag(caller),
                                     collateral_amount_raw - ledger_fee,
                                     caller,
                                     refund_block
                                 );
                             }
                             Err(refund_err) => {
-                                log!(INFO,
-                                    "[open_vault] CRITICAL: collateral refund ALSO failed for {}! \
+                                log!(CRITICAL,
+                                    "[open_vault] trace={} CRITICAL: collateral refund ALSO failed for {}! \
                                      Amount: {}, ledger: {}. Error: {:?}. Manual intervention required.",
+                                    trace_tag(caller),
                                     caller, collateral_amount_raw, config_ledger, refund_err
                                 );
                             }
@@ -1289,7 +1423,7 @@ pub(crate) fn record_xrp_claim(
 /// `custody_owner` is the SOURCE vault's owner (its threshold key controls the
 /// custody address), captured while the vault is in hand — safe even when
 /// cleanup_if_drained removes the vault immediately after.
-fn queue_collateral_payout(
+pub(crate) fn queue_collateral_payout(
     s: &mut crate::state::State,
     vault_id: u64,
     custody_owner: Principal,
@@ -1321,6 +1455,7 @@ fn queue_collateral_payout(
                 collateral_type,
                 retry_count: 0,
                 op_nonce,
+                trace_id: crate::guard::current_trace(recipient),
             },
         );
         None
@@ -2609,7 +2744,8 @@ pub async fn settle_xrp_claim_with_tag(
     if let Err(e) = ensure_xrp_claim_aggregate_solvency(&acct, reserve, unresolved_claim_drops) {
         log!(
             INFO,
-            "[settle_xrp_claim] aggregate solvency rejected claim #{} from {}: {:?}",
+            "[settle_xrp_claim] trace={} aggregate solvency rejected claim #{} from {}: {:?}",
+            trace_tag(caller),
             claim_id,
             source_address,
             e
@@ -3355,7 +3491,8 @@ pub async fn open_vault_and_borrow(
         Err(GuardError::AlreadyProcessing) => {
             log!(
                 INFO,
-                "[open_vault_and_borrow] Principal {:?} already has an ongoing operation",
+                "[open_vault_and_borrow] trace={} Principal {:?} already has an ongoing operation",
+                trace_tag(caller),
                 caller
             );
             return Err(ProtocolError::AlreadyProcessing);
@@ -3363,7 +3500,8 @@ pub async fn open_vault_and_borrow(
         Err(GuardError::StaleOperation) => {
             log!(
                 INFO,
-                "[open_vault_and_borrow] Principal {:?} has a stale operation being cleaned up",
+                "[open_vault_and_borrow] trace={} Principal {:?} has a stale operation being cleaned up",
+                trace_tag(caller),
                 caller
             );
             return Err(ProtocolError::TemporarilyUnavailable(
@@ -3419,6 +3557,27 @@ pub async fn open_vault_and_borrow(
         });
     }
 
+    // Refuse a borrow the debt ceiling cannot fit before pulling collateral,
+    // rather than leaving the caller with a vault they did not ask for. The
+    // borrow itself still reserves the headroom (BK-003).
+    if borrow_amount_raw > 0 {
+        let reserved = crate::guard::reserved_borrows(&collateral_type);
+        if let Some(utilization) =
+            read_state(|s| s.collateral_utilization(&collateral_type, reserved))
+        {
+            if borrow_amount_raw > utilization.headroom {
+                guard_principal.fail();
+                return Err(ProtocolError::GenericError(format!(
+                    "Borrow of {} would exceed the collateral's debt ceiling ({} of {} used, {} in flight)",
+                    borrow_amount_raw,
+                    utilization.total_debt,
+                    utilization.debt_ceiling,
+                    utilization.in_flight_borrows
+                )));
+            }
+        }
+    }
+
     // Pull collateral via ICRC-2 transfer_from (caller must have approved first)
     let block_index =
         match transfer_collateral_from(collateral_amount_raw, caller, config_ledger).await {
@@ -3463,7 +3622,8 @@ pub async fn open_vault_and_borrow(
 
     log!(
         INFO,
-        "[open_vault_and_borrow] opened vault {vault_id}, now borrowing {borrow_amount_raw}"
+        "[open_vault_and_borrow] trace={} opened vault {vault_id}, now borrowing {borrow_amount_raw}",
+        trace_tag(caller)
     );
 
     // Borrow icUSD — reuse internal fn to avoid guard conflict
@@ -3491,7 +3651,8 @@ pub async fn open_vault_and_borrow(
             Ok(borrow_result) => {
                 log!(
                     INFO,
-                    "[open_vault_and_borrow] vault {} borrow of {} succeeded (fee: {})",
+                    "[open_vault_and_borrow] trace={} vault {} borrow of {} succeeded (fee: {})",
+                    trace_tag(caller),
                     vault_id,
                     borrow_amount_raw,
                     borrow_result.fee_amount_paid
@@ -3514,6 +3675,42 @@ pub async fn open_vault_and_borrow(
     })
 }
 
+/// A borrow that passed its checks, holding its share of the debt caps and
+/// its rebate until the proceeds are paid and the debt recorded.
+struct PreparedBorrow {
+    vault_id: u64,
+    amount: ICUSD,
+    fee: ICUSD,
+    /// `fee` less the rebate: what the treasury is minted.
+    net_fee: ICUSD,
+    rebate: Option<(u64, ICUSD)>,
+    _reservation: crate::guard::BorrowReservationGuard,
+}
+
+impl PreparedBorrow {
+    /// What the borrower receives.
+    fn proceeds(&self) -> ICUSD {
+        self.amount - self.net_fee
+    }
+
+    /// Record the debt once the proceeds were paid in `block_index`.
+    fn record(&self, block_index: u64) {
+        mutate_state(|s| {
+            record_borrow_from_vault(s, self.vault_id, self.amount, self.fee, block_index);
+            if let Some((campaign_id, rebate)) = self.rebate {
+                crate::event::record_borrow_fee_rebated(s, campaign_id, self.vault_id, rebate);
+            }
+        });
+    }
+
+    /// Give the rebate back after the payout failed.
+    fn release(&self) {
+        if let Some((campaign_id, rebate)) = self.rebate {
+            mutate_state(|s| crate::campaigns::release_rebate(s, campaign_id, rebate));
+        }
+    }
+}
+
 /// Internal borrow logic without guard management.
 /// Called by both `borrow_from_vault` (which acquires its own guard) and
 /// `open_vault_with_deposit` (which already holds a guard for the same principal).
@@ -3521,6 +3718,33 @@ async fn borrow_from_vault_internal(
     caller: Principal,
     arg: VaultArg,
 ) -> Result<SuccessWithFee, ProtocolError> {
+    let borrow = prepare_borrow(caller, &arg)?;
+
+    match mint_icusd(borrow.proceeds(), caller).await {
+        Ok(block_index) => {
+            borrow.record(block_index);
+
+            // Mint the borrowing fee to treasury (fire-and-forget)
+            crate::treasury::mint_borrowing_fee_to_treasury(borrow.net_fee).await;
+
+            Ok(SuccessWithFee {
+                block_index,
+                fee_amount_paid: borrow.net_fee.to_u64(),
+                collateral_amount_received: None,
+                debt_liquidated_e8s: None, // SP-101
+                stable_pulled_e6s: None,   // SP-110
+                xrp_claim_id: None,
+            })
+        }
+        Err(mint_error) => {
+            borrow.release();
+            Err(ProtocolError::TransferError(mint_error))
+        }
+    }
+}
+
+/// The checks, fee and reservations of a borrow, before anything is paid.
+fn prepare_borrow(caller: Principal, arg: &VaultArg) -> Result<PreparedBorrow, ProtocolError> {
     let amount: ICUSD = arg.amount.into();
 
     if amount < read_state(|s| s.min_icusd_amount) {
@@ -3532,13 +3756,14 @@ async fn borrow_from_vault_internal(
     // Accrue interest on this vault before borrowing so CR check uses up-to-date debt.
     let now = ic_cdk::api::time();
     reject_active_xrp_sp_absorb_preflight(arg.vault_id, now)?;
+    reject_if_vault_frozen(arg.vault_id, now)?;
     mutate_state(|s| s.accrue_single_vault(arg.vault_id, now));
 
     let (vault, collateral_price, config_decimals, is_native_xrp) =
         read_state(|s| match s.vault_id_to_vaults.get(&arg.vault_id) {
             Some(vault) => {
                 let price = s
-                    .get_collateral_price_decimal(&vault.collateral_type)
+                    .get_borrow_check_price_decimal(&vault.collateral_type)
                     .ok_or("No price available for collateral. Price feed may be down.")?;
                 let config = s
                     .get_collateral_config(&vault.collateral_type)
@@ -3555,6 +3780,7 @@ async fn borrow_from_vault_internal(
         .map_err(|msg: &str| ProtocolError::GenericError(msg.to_string()))?;
 
     require_vault_not_processing(&vault)?;
+    reject_if_status_disallows(vault.vault_id, VaultOperation::Borrow)?;
 
     // Check collateral status allows borrowing
     let collateral_status = read_state(|s| s.get_collateral_status(&vault.collateral_type));
@@ -3572,15 +3798,17 @@ async fn borrow_from_vault_internal(
     if caller != vault.owner {
         return Err(ProtocolError::CallerNotOwner);
     }
+    check_min_vault_debt_after_borrow(&vault, amount)?;
 
     // Check debt ceiling + global mint cap AND reserve the headroom atomically.
     //
     // BK-003 (audit 2026-06-05): these caps are checked here but the debt is not
-    // recorded until after the `mint_icusd().await` below. Two borrows from
+    // recorded until the proceeds are paid (the `mint_icusd().await`, or a
+    // reserve transfer for `borrow_from_vault_as_stable`). Two borrows from
     // DIFFERENT owners both pass this check against the same committed aggregate,
     // both mint, and jointly exceed the cap (the per-caller GuardPrincipal does
     // not serialize distinct owners against the aggregate). The reservation guard
-    // counts every in-flight borrow in the check and is held across the mint, so
+    // counts every in-flight borrow in the check and is held across the payout, so
     // a concurrent borrow sees this one's reserved amount. Released on Drop
     // (return or continuation-trap via ic-cdk cleanup).
     let current_debt = read_state(|s| s.total_debt_for_collateral(&vault.collateral_type));
@@ -3591,7 +3819,7 @@ async fn borrow_from_vault_internal(
     });
     let (global_cap, total_borrowed) =
         read_state(|s| (s.global_icusd_mint_cap, s.total_borrowed_icusd_amount()));
-    let _borrow_reservation = crate::guard::BorrowReservationGuard::try_reserve(
+    let borrow_reservation = crate::guard::BorrowReservationGuard::try_reserve(
         vault.collateral_type,
         amount.to_u64(),
         current_debt.to_u64(),
@@ -3650,26 +3878,20 @@ async fn borrow_from_vault_internal(
         clamp_borrow_fee(amount, raw_fee)
     });
 
-    match mint_icusd(amount - fee, caller).await {
-        Ok(block_index) => {
-            mutate_state(|s| {
-                record_borrow_from_vault(s, arg.vault_id, amount, fee, block_index);
-            });
-
-            // Mint the borrowing fee to treasury (fire-and-forget)
-            crate::treasury::mint_borrowing_fee_to_treasury(fee).await;
-
-            Ok(SuccessWithFee {
-                block_index,
-                fee_amount_paid: fee.to_u64(),
-                collateral_amount_received: None,
-                debt_liquidated_e8s: None, // SP-101
-                stable_pulled_e6s: None,   // SP-110
-                xrp_claim_id: None,
-            })
-        }
-        Err(mint_error) => Err(ProtocolError::TransferError(mint_error)),
-    }
+    // Promotional rebate: reserved before the mint so concurrent borrows
+    // cannot overspend a campaign pool; the treasury's fee mint shrinks by
+    // the same amount. See `campaigns`.
+    let rebate = mutate_state(|s| crate::campaigns::reserve_rebate(s, fee, now));
+    let net_fee = fee - rebate.map(|(_, r)| r).unwrap_or(ICUSD::new(0));
+
+    Ok(PreparedBorrow {
+        vault_id: arg.vault_id,
+        amount,
+        fee,
+        net_fee,
+        rebate,
+        _reservation: borrow_reservation,
+    })
 }
 
 pub async fn borrow_from_vault(arg: VaultArg) -> Result<SuccessWithFee, ProtocolError> {
@@ -3680,7 +3902,8 @@ pub async fn borrow_from_vault(arg: VaultArg) -> Result<SuccessWithFee, Protocol
             Err(GuardError::AlreadyProcessing) => {
                 log!(
                     INFO,
-                    "[borrow_from_vault] Principal {:?} already has an ongoing operation",
+                    "[borrow_from_vault] trace={} Principal {:?} already has an ongoing operation",
+                    trace_tag(caller),
                     caller
                 );
                 return Err(ProtocolError::AlreadyProcessing);
@@ -3712,6 +3935,181 @@ pub async fn borrow_from_vault(arg: VaultArg) -> Result<SuccessWithFee, Protocol
     }
 }
 
+/// Borrow from a vault and receive `token_type` instead of icUSD.
+///
+/// The proceeds (`amount` less the borrowing fee) are paid 1:1 from the
+/// protocol's reserve of that stable, less its reserve redemption fee, as if
+/// the icUSD were minted and redeemed through `redeem_reserves` at once; no
+/// icUSD is minted for them. The debt is the same as `borrow_from_vault`'s.
+/// If the reserve cannot cover the payout, nothing is borrowed.
+///
+/// `block_index` is the stable ledger's, and `fee_amount_paid` counts both
+/// fees in icUSD e8s.
+pub async fn borrow_from_vault_as_stable(
+    arg: VaultArg,
+    token_type: StableTokenType,
+) -> Result<SuccessWithFee, ProtocolError> {
+    let caller = ic_cdk::api::caller();
+    // Same key as `borrow_from_vault`: one borrow per vault at a time.
+    let guard_principal = GuardPrincipal::new(caller, &format!("borrow_vault_{}", arg.vault_id))?;
+    // AR-B-003: per-vault op lock; see guard.rs::VaultLiquidationGuard.
+    let _vault_op_guard = match VaultLiquidationGuard::new(arg.vault_id) {
+        Ok(g) => g,
+        Err(e) => {
+            guard_principal.fail();
+            return Err(e);
+        }
+    };
+
+    match borrow_as_stable_internal(caller, arg, token_type).await {
+        Ok(result) => {
+            guard_principal.complete();
+            Ok(result)
+        }
+        Err(e) => {
+            guard_principal.fail();
+            Err(e)
+        }
+    }
+}
+
+async fn borrow_as_stable_internal(
+    caller: Principal,
+    arg: VaultArg,
+    token_type: StableTokenType,
+) -> Result<SuccessWithFee, ProtocolError> {
+    let (enabled, global_fee_ratio, stable, treasury) = read_state(|s| {
+        let ledger = match token_type {
+            StableTokenType::CKUSDT => s.ckusdt_ledger_principal,
+            StableTokenType::CKUSDC => s.ckusdc_ledger_principal,
+        };
+        (
+            s.reserve_redemptions_enabled,
+            s.reserve_redemption_fee,
+            crate::reserve_stables::enabled(s)
+                .into_iter()
+                .find(|stable| Some(stable.ledger) == ledger),
+            s.treasury_principal,
+        )
+    });
+    if !enabled {
+        return Err(ProtocolError::GenericError(
+            "Reserve redemptions are currently disabled.".to_string(),
+        ));
+    }
+    let stable = stable.ok_or_else(|| {
+        ProtocolError::GenericError(format!("{:?} is not an enabled reserve token.", token_type))
+    })?;
+
+    // Reserves pay 1:1, so a depegged stable must not be paid out.
+    crate::xrc::ensure_stable_not_depegged(&token_type).await?;
+
+    let borrow = prepare_borrow(caller, &arg)?;
+    let payout =
+        crate::reserve_stables::borrow_payout(borrow.proceeds(), &stable, global_fee_ratio);
+    if payout.amount_native == 0 {
+        borrow.release();
+        return Err(ProtocolError::GenericError(
+            "Borrow amount too small after the reserve fee.".to_string(),
+        ));
+    }
+
+    // Check the reserve before any debt is recorded.
+    let reserve_balance = match management::get_token_balance(stable.ledger).await {
+        Ok(balance) => balance,
+        Err(e) => {
+            borrow.release();
+            return Err(ProtocolError::TemporarilyUnavailable(format!(
+                "Cannot query reserve balance: {}",
+                e
+            )));
+        }
+    };
+    let ledger_fee = management::get_ledger_fee(stable.ledger)
+        .await
+        // fallback to 0.01 USD if query fails
+        .unwrap_or_else(|_| crate::reserve_stables::e8s_to_native(1_000_000, stable.decimals));
+    let needed = payout.needed(ledger_fee);
+    if reserve_balance < needed {
+        borrow.release();
+        return Err(ProtocolError::GenericError(format!(
+            "The {} reserve holds {} but this borrow needs {}. Borrow icUSD with borrow_from_vault instead.",
+            stable.symbol, reserve_balance, needed
+        )));
+    }
+
+    let block_index =
+        match management::transfer_collateral(payout.amount_native, caller, stable.ledger).await {
+            Ok(block_index) => block_index,
+            Err(transfer_error) => {
+                borrow.release();
+                return Err(ProtocolError::TransferError(transfer_error));
+            }
+        };
+    borrow.record(block_index);
+    crate::event::record_borrow_paid_from_reserves(
+        caller,
+        arg.vault_id,
+        stable.ledger,
+        &payout,
+        block_index,
+    );
+    // The reserve fee is protocol equity, as on `redeem_reserves`.
+    if payout.fee_icusd.0 > 0 {
+        mutate_state(|s| {
+            let _routing = crate::treasury::plan_fee_routing(
+                s,
+                payout.fee_icusd,
+                crate::event::FeeSource::RedemptionFee,
+            );
+        });
+    }
+    log!(
+        INFO,
+        "[borrow_from_vault_as_stable] trace={} vault {} paid {} {} from reserves (block {})",
+        trace_tag(caller),
+        arg.vault_id,
+        payout.amount_native,
+        stable.symbol,
+        block_index
+    );
+
+    // Transfer the reserve fee to treasury (if configured), otherwise it
+    // stays in reserves.
+    if payout.fee_native > 0 {
+        if let Some(treasury_principal) = treasury {
+            if let Err(e) = management::transfer_collateral(
+                payout.fee_native,
+                treasury_principal,
+                stable.ledger,
+            )
+            .await
+            {
+                log!(
+                    INFO,
+                    "[borrow_from_vault_as_stable] trace={} WARNING: treasury fee transfer failed ({} to {}): {:?}. Fee stays in reserves.",
+                    trace_tag(caller),
+                    payout.fee_native,
+                    treasury_principal,
+                    e
+                );
+            }
+        }
+    }
+
+    // Mint the borrowing fee to treasury (fire-and-forget)
+    crate::treasury::mint_borrowing_fee_to_treasury(borrow.net_fee).await;
+
+    Ok(SuccessWithFee {
+        block_index,
+        fee_amount_paid: (borrow.net_fee + payout.fee_icusd).to_u64(),
+        collateral_amount_received: None,
+        debt_liquidated_e8s: None, // SP-101
+        stable_pulled_e6s: None,   // SP-110
+        xrp_claim_id: None,
+    })
+}
+
 /// Internal repay logic without guard management.
 ///
 /// Called by both `repay_to_vault` (which acquires its own `repay_vault_{id}`
@@ -3746,6 +4144,7 @@ async fn repay_to_vault_internal(
         .ok_or_else(|| ProtocolError::GenericError("Vault not found".to_string()))?;
 
     require_vault_not_processing(&vault)?;
+    reject_if_status_disallows(vault.vault_id, VaultOperation::Repay)?;
 
     // Check collateral status allows repayment
     let collateral_status = read_state(|s| s.get_collateral_status(&vault.collateral_type));
@@ -3757,10 +4156,19 @@ async fn repay_to_vault_internal(
         }
     }
 
-    if caller != vault.owner {
-        return Err(ProtocolError::CallerNotOwner);
-    }
-
+    // Closing stays owner-only; plain repays also accept a session key
+    // holding the Repay scope.
+    let session = if is_full_close {
+        if caller != vault.owner {
+            return Err(ProtocolError::CallerNotOwner);
+        }
+        None
+    } else {
+        read_state(|s| {
+            crate::session_keys::authorize_vault_op(s, caller, &vault, SessionScope::Repay, now)
+        })?
+    };
+
     if !is_full_close && amount < read_state(|s| s.min_icusd_amount) {
         return Err(ProtocolError::AmountTooLow {
             minimum_amount: read_state(|s| s.min_icusd_amount).to_u64(),
@@ -3784,10 +4192,26 @@ async fn repay_to_vault_internal(
 
     check_min_vault_debt_after_repay(&vault, amount)?;
 
+    if let Some(session_principal) = session {
+        mutate_state(|s| {
+            crate::session_keys::reserve_repay_allowance(s, session_principal, amount.to_u64())
+        })?;
+    }
+
     match transfer_icusd_from(amount, caller).await {
         Ok(block_index) => {
-            let interest_share =
-                mutate_state(|s| record_repayed_to_vault(s, arg.vault_id, amount, block_index));
+            let interest_share = mutate_state(|s| {
+                if let Some(session_principal) = session {
+                    record_session_key_used(
+                        s,
+                        session_principal,
+                        arg.vault_id,
+                        SessionScope::Repay,
+                        amount.to_u64(),
+                    );
+                }
+                record_repayed_to_vault(s, arg.vault_id, amount, block_index, None)
+            });
             // IC-B-002 (audit 2026-06-09): re-queue any unminted interest share so the
             // next flush retries it instead of silently dropping treasury revenue.
             let unminted_interest =
@@ -3802,10 +4226,21 @@ async fn repay_to_vault_internal(
             }
             Ok(block_index)
         }
-        Err(transfer_from_error) => Err(ProtocolError::TransferFromError(
-            transfer_from_error,
-            amount.to_u64(),
-        )),
+        Err(transfer_from_error) => {
+            if let Some(session_principal) = session {
+                mutate_state(|s| {
+                    crate::session_keys::release_repay_allowance(
+                        s,
+                        session_principal,
+                        amount.to_u64(),
+                    )
+                });
+            }
+            Err(ProtocolError::TransferFromError(
+                transfer_from_error,
+                amount.to_u64(),
+            ))
+        }
     }
 }
 
@@ -3891,6 +4326,10 @@ pub async fn repay_to_vault_with_stable(arg: VaultArgWithToken) -> Result<u64, P
         guard_principal.fail();
         return Err(e);
     }
+    if let Err(e) = reject_if_status_disallows(vault.vault_id, VaultOperation::Repay) {
+        guard_principal.fail();
+        return Err(e);
+    }
 
     // Check collateral status allows repayment
     let collateral_status = read_state(|s| s.get_collateral_status(&vault.collateral_type));
@@ -3903,10 +4342,15 @@ pub async fn repay_to_vault_with_stable(arg: VaultArgWithToken) -> Result<u64, P
         }
     }
 
-    if caller != vault.owner {
-        guard_principal.fail();
-        return Err(ProtocolError::CallerNotOwner);
-    }
+    let session = match read_state(|s| {
+        crate::session_keys::authorize_vault_op(s, caller, &vault, SessionScope::Repay, now)
+    }) {
+        Ok(session) => session,
+        Err(e) => {
+            guard_principal.fail();
+            return Err(e);
+        }
+    };
 
     if amount < read_state(|s| s.min_icusd_amount) {
         guard_principal.fail();
@@ -3943,11 +4387,31 @@ pub async fn repay_to_vault_with_stable(arg: VaultArgWithToken) -> Result<u64, P
         .unwrap_or(0);
     let total_pull_e6s = base_stable_e6s + fee_e6s;
 
+    if let Some(session_principal) = session {
+        if let Err(e) = mutate_state(|s| {
+            crate::session_keys::reserve_repay_allowance(s, session_principal, amount.to_u64())
+        }) {
+            guard_principal.fail();
+            return Err(e);
+        }
+    }
+
     // Transfer the stable token from user (in 6-decimal units)
     match transfer_stable_from(arg.token_type.clone(), total_pull_e6s, caller).await {
         Ok(block_index) => {
-            let interest_share =
-                mutate_state(|s| record_repayed_to_vault(s, arg.vault_id, amount, block_index));
+            let interest_share = mutate_state(|s| {
+                if let Some(session_principal) = session {
+                    record_session_key_used(
+                        s,
+                        session_principal,
+                        arg.vault_id,
+                        SessionScope::Repay,
+                        amount.to_u64(),
+                    );
+                }
+                let stable_fee = stable_repay_fee(s, &arg.token_type, fee_e6s);
+                record_repayed_to_vault(s, arg.vault_id, amount, block_index, stable_fee)
+            });
 
             // Route interest via N-way split (stablecoin-denominated)
             if interest_share.to_u64() > 0 {
@@ -3960,45 +4424,21 @@ pub async fn repay_to_vault_with_stable(arg: VaultArgWithToken) -> Result<u64, P
             }
 
             // Route fee surcharge to treasury as stablecoins
-            if fee_e6s > 0 {
-                let (treasury, stable_ledger) = read_state(|s| {
-                    let ledger = match arg.token_type {
-                        StableTokenType::CKUSDT => s.ckusdt_ledger_principal,
-                        StableTokenType::CKUSDC => s.ckusdc_ledger_principal,
-                    };
-                    (s.treasury_principal, ledger)
-                });
-                if let (Some(treasury_principal), Some(stable_ledger)) = (treasury, stable_ledger) {
-                    match management::transfer_collateral(
-                        fee_e6s,
-                        treasury_principal,
-                        stable_ledger,
-                    )
-                    .await
-                    {
-                        Ok(block) => {
-                            log!(
-                                INFO,
-                                "[repay_with_stable] Transferred {} e6s fee to treasury (block {})",
-                                fee_e6s,
-                                block
-                            );
-                        }
-                        Err(e) => {
-                            // Non-critical: fee stays in reserves if transfer fails
-                            log!(INFO,
-                                "[repay_with_stable] Fee transfer to treasury failed: {:?}. Fee remains in reserves.",
-                                e
-                            );
-                        }
-                    }
-                }
-            }
+            send_stable_repay_fee_to_treasury(&arg.token_type, fee_e6s, caller).await;
 
             guard_principal.complete();
             Ok(block_index)
         }
         Err(transfer_from_error) => {
+            if let Some(session_principal) = session {
+                mutate_state(|s| {
+                    crate::session_keys::release_repay_allowance(
+                        s,
+                        session_principal,
+                        amount.to_u64(),
+                    )
+                });
+            }
             guard_principal.fail();
             Err(ProtocolError::TransferFromError(
                 transfer_from_error,
@@ -4008,6 +4448,60 @@ pub async fn repay_to_vault_with_stable(arg: VaultArgWithToken) -> Result<u64, P
     }
 }
 
+/// The surcharge recorded on a stable-token repayment's event, or `None`
+/// when there was none.
+fn stable_repay_fee(
+    state: &crate::state::State,
+    token_type: &StableTokenType,
+    fee_e6s: u64,
+) -> Option<crate::event::StableRepayFee> {
+    let ledger = match token_type {
+        StableTokenType::CKUSDT => state.ckusdt_ledger_principal,
+        StableTokenType::CKUSDC => state.ckusdc_ledger_principal,
+    }?;
+    (fee_e6s > 0).then_some(crate::event::StableRepayFee { ledger, fee_e6s })
+}
+
+/// Send a ckstable fee surcharge, of a repayment or a liquidation, to
+/// treasury. Non-critical: on failure (or without a configured treasury) the
+/// fee stays in reserves.
+async fn send_stable_repay_fee_to_treasury(
+    token_type: &StableTokenType,
+    fee_e6s: u64,
+    caller: Principal,
+) {
+    if fee_e6s == 0 {
+        return;
+    }
+    let (treasury, stable_ledger) = read_state(|s| {
+        let ledger = match token_type {
+            StableTokenType::CKUSDT => s.ckusdt_ledger_principal,
+            StableTokenType::CKUSDC => s.ckusdc_ledger_principal,
+        };
+        (s.treasury_principal, ledger)
+    });
+    if let (Some(treasury_principal), Some(stable_ledger)) = (treasury, stable_ledger) {
+        match management::transfer_collateral(fee_e6s, treasury_principal, stable_ledger).await {
+            Ok(block) => {
+                log!(
+                    INFO,
+                    "[stable_fee] trace={} Transferred {} e6s fee to treasury (block {})",
+                    trace_tag(caller),
+                    fee_e6s,
+                    block
+                );
+            }
+            Err(e) => {
+                log!(INFO,
+                    "[stable_fee] trace={} Fee transfer to treasury failed: {:?}. Fee remains in reserves.",
+                    trace_tag(caller),
+                    e
+                );
+            }
+        }
+    }
+}
+
 pub async fn add_margin_to_vault(arg: VaultArg) -> Result<u64, ProtocolError> {
     let caller = ic_cdk::api::caller();
     let guard_principal =
@@ -4064,6 +4558,10 @@ pub async fn add_margin_to_vault(arg: VaultArg) -> Result<u64, ProtocolError> {
         guard_principal.fail();
         return Err(e);
     }
+    if let Err(e) = reject_if_status_disallows(vault.vault_id, VaultOperation::AddMargin) {
+        guard_principal.fail();
+        return Err(e);
+    }
 
     if min_deposit > 0 && amount < ICP::new(min_deposit) {
         guard_principal.fail();
@@ -4083,14 +4581,52 @@ pub async fn add_margin_to_vault(arg: VaultArg) -> Result<u64, ProtocolError> {
         }
     }
 
-    if caller != vault.owner {
-        guard_principal.fail();
-        return Err(ProtocolError::CallerNotOwner);
-    }
+    let session = match read_state(|s| {
+        crate::session_keys::authorize_vault_op(s, caller, &vault, SessionScope::AddMargin, now)
+    }) {
+        Ok(session) => session,
+        Err(e) => {
+            guard_principal.fail();
+            return Err(e);
+        }
+    };
 
     match transfer_collateral_from(arg.amount, caller, config_ledger).await {
         Ok(block_index) => {
-            mutate_state(|s| record_add_margin_to_vault(s, arg.vault_id, amount, block_index));
+            mutate_state(|s| {
+                if let Some(session_principal) = session {
+                    record_session_key_used(
+                        s,
+                        session_principal,
+                        arg.vault_id,
+                        SessionScope::AddMargin,
+                        amount.to_u64(),
+                    );
+                }
+                // A small top-up by a verified owner also gets back the
+                // ledger fee it paid. See `fee_sponsorship`.
+                let sponsored_fee = crate::fee_sponsorship::sponsored_fee(
+                    s,
+                    caller,
+                    vault.owner,
+                    &vault.collateral_type,
+                    &config_ledger,
+                    amount.to_u64(),
+                    ic_cdk::api::time(),
+                );
+                let credited = amount + ICP::new(sponsored_fee.unwrap_or(0));
+                record_add_margin_to_vault(s, arg.vault_id, credited, block_index);
+                if let Some(fee) = sponsored_fee {
+                    crate::event::record_fee_sponsored(
+                        s,
+                        caller,
+                        arg.vault_id,
+                        config_ledger,
+                        fee,
+                        ic_cdk::api::time(),
+                    );
+                }
+            });
             guard_principal.complete();
             Ok(block_index)
         }
@@ -4128,7 +4664,8 @@ pub async fn open_vault_with_deposit(
         Err(GuardError::AlreadyProcessing) => {
             log!(
                 INFO,
-                "[open_vault_with_deposit] Principal {:?} already has an ongoing operation",
+                "[open_vault_with_deposit] trace={} Principal {:?} already has an ongoing operation",
+                trace_tag(caller),
                 caller
             );
             return Err(ProtocolError::AlreadyProcessing);
@@ -4216,7 +4753,8 @@ pub async fn open_vault_with_deposit(
         vault_id
     });
 
-    log!(INFO, "[open_vault_with_deposit] opened vault {} for {} with {} collateral via push-deposit (sweep block {})",
+    log!(INFO, "[open_vault_with_deposit] trace={} opened vault {} for {} with {} collateral via push-deposit (sweep block {})",
+        trace_tag(caller),
         vault_id, caller, collateral_amount, sweep_block_index);
 
     // If the caller also requested an initial borrow, do it now.
@@ -4237,7 +4775,8 @@ pub async fn open_vault_with_deposit(
             Ok(borrow_result) => {
                 log!(
                     INFO,
-                    "[open_vault_with_deposit] vault {} initial borrow of {} succeeded (fee: {})",
+                    "[open_vault_with_deposit] trace={} vault {} initial borrow of {} succeeded (fee: {})",
+                    trace_tag(caller),
                     vault_id,
                     borrow_amount_raw,
                     borrow_result.fee_amount_paid
@@ -4315,6 +4854,10 @@ pub async fn add_margin_with_deposit(vault_id: u64) -> Result<u64, ProtocolError
         guard_principal.fail();
         return Err(e);
     }
+    if let Err(e) = reject_if_status_disallows(vault.vault_id, VaultOperation::AddMargin) {
+        guard_principal.fail();
+        return Err(e);
+    }
 
     // Check collateral status
     let collateral_status = read_state(|s| s.get_collateral_status(&vault.collateral_type));
@@ -4359,7 +4902,8 @@ pub async fn add_margin_with_deposit(vault_id: u64) -> Result<u64, ProtocolError
 
     mutate_state(|s| record_add_margin_to_vault(s, vault_id, margin_added, sweep_block_index));
 
-    log!(INFO, "[add_margin_with_deposit] added {} collateral to vault {} via push-deposit (sweep block {})",
+    log!(INFO, "[add_margin_with_deposit] trace={} added {} collateral to vault {} via push-deposit (sweep block {})",
+        trace_tag(caller),
         collateral_amount, vault_id, sweep_block_index);
 
     guard_principal.complete();
@@ -4391,6 +4935,7 @@ pub async fn close_vault(vault_id: u64) -> Result<Option<u64>, ProtocolError> {
     // AR-B-003: per-vault op lock; see guard.rs::VaultLiquidationGuard.
     let _vault_op_guard = VaultLiquidationGuard::new(vault_id)?;
     reject_active_xrp_sp_absorb_preflight(vault_id, ic_cdk::api::time())?;
+    reject_if_vault_frozen(vault_id, ic_cdk::api::time())?;
 
     // Check rate limits first
     mutate_state(|s| s.check_close_vault_rate_limit(caller))?;
@@ -4409,7 +4954,8 @@ pub async fn close_vault(vault_id: u64) -> Result<Option<u64>, ProtocolError> {
         mutate_state(|s| s.complete_close_vault_request());
         log!(
             INFO,
-            "[close_vault] Vault #{} not found for principal {}",
+            "[close_vault] trace={} Vault #{} not found for principal {}",
+            trace_tag(caller),
             vault_id,
             caller
         );
@@ -4428,6 +4974,7 @@ pub async fn close_vault(vault_id: u64) -> Result<Option<u64>, ProtocolError> {
     })?;
 
     require_vault_not_processing(&vault)?;
+    reject_if_status_disallows(vault.vault_id, VaultOperation::Close)?;
 
     // Check collateral status allows closing
     let collateral_status = read_state(|s| s.get_collateral_status(&vault.collateral_type));
@@ -4445,7 +4992,8 @@ pub async fn close_vault(vault_id: u64) -> Result<Option<u64>, ProtocolError> {
         mutate_state(|s| s.complete_close_vault_request());
         log!(
             INFO,
-            "[close_vault] Principal {} is not the owner of vault #{}",
+            "[close_vault] trace={} Principal {} is not the owner of vault #{}",
+            trace_tag(caller),
             caller,
             vault_id
         );
@@ -4456,7 +5004,8 @@ pub async fn close_vault(vault_id: u64) -> Result<Option<u64>, ProtocolError> {
     if vault.borrowed_icusd_amount <= DUST_THRESHOLD {
         log!(
             INFO,
-            "[close_vault] Forgiving dust debt of {} icUSD for vault #{}",
+            "[close_vault] trace={} Forgiving dust debt of {} icUSD for vault #{}",
+            trace_tag(caller),
             vault.borrowed_icusd_amount,
             vault_id
         );
@@ -4477,7 +5026,8 @@ pub async fn close_vault(vault_id: u64) -> Result<Option<u64>, ProtocolError> {
         mutate_state(|s| s.complete_close_vault_request());
         log!(
             INFO,
-            "[close_vault] Cannot close vault #{} with outstanding debt: {}",
+            "[close_vault] trace={} Cannot close vault #{} with outstanding debt: {}",
+            trace_tag(caller),
             vault_id,
             vault.borrowed_icusd_amount
         );
@@ -4491,7 +5041,8 @@ pub async fn close_vault(vault_id: u64) -> Result<Option<u64>, ProtocolError> {
         mutate_state(|s| s.complete_close_vault_request());
         log!(
             INFO,
-            "[close_vault] Cannot close vault #{} with remaining collateral: {}",
+            "[close_vault] trace={} Cannot close vault #{} with remaining collateral: {}",
+            trace_tag(caller),
             vault_id,
             vault.collateral_amount
         );
@@ -4511,7 +5062,8 @@ pub async fn close_vault(vault_id: u64) -> Result<Option<u64>, ProtocolError> {
         mutate_state(|s| s.complete_close_vault_request());
         log!(
             INFO,
-            "[close_vault] Keeping native-XRP vault #{} open because the XRPL reserve remains locked",
+            "[close_vault] trace={} Keeping native-XRP vault #{} open because the XRPL reserve remains locked",
+            trace_tag(caller),
             vault_id
         );
         return Err(ProtocolError::GenericError(
@@ -4536,7 +5088,8 @@ pub async fn close_vault(vault_id: u64) -> Result<Option<u64>, ProtocolError> {
 
             log!(
                 INFO,
-                "[close_vault] Successfully closed vault #{} for principal {}",
+                "[close_vault] trace={} Successfully closed vault #{} for principal {}",
+                trace_tag(caller),
                 vault_id,
                 caller
             );
@@ -4544,7 +5097,8 @@ pub async fn close_vault(vault_id: u64) -> Result<Option<u64>, ProtocolError> {
             // Log that we tried to close a vault that was already gone
             log!(
                 INFO,
-                "[close_vault] Attempted to close vault #{} that was already removed",
+                "[close_vault] trace={} Attempted to close vault #{} that was already removed",
+                trace_tag(caller),
                 vault_id
             );
             s.complete_close_vault_request();
@@ -4562,10 +5116,12 @@ pub async fn withdraw_collateral(vault_id: u64) -> Result<u64, ProtocolError> {
     // AR-B-003: per-vault op lock; see guard.rs::VaultLiquidationGuard.
     let _vault_op_guard = VaultLiquidationGuard::new(vault_id)?;
     reject_active_xrp_sp_absorb_preflight(vault_id, ic_cdk::api::time())?;
+    reject_if_vault_frozen(vault_id, ic_cdk::api::time())?;
 
     log!(
         INFO,
-        "[withdraw_collateral] Request to withdraw collateral from vault #{} by principal {}",
+        "[withdraw_collateral] trace={} Request to withdraw collateral from vault #{} by principal {}",
+        trace_tag(caller),
         vault_id,
         caller
     );
@@ -4580,6 +5136,7 @@ pub async fn withdraw_collateral(vault_id: u64) -> Result<u64, ProtocolError> {
     })?;
 
     require_vault_not_processing(&vault)?;
+    reject_if_status_disallows(vault.vault_id, VaultOperation::Withdraw)?;
 
     // Check collateral status allows withdrawal
     let collateral_status = read_state(|s| s.get_collateral_status(&vault.collateral_type));
@@ -4594,7 +5151,8 @@ pub async fn withdraw_collateral(vault_id: u64) -> Result<u64, ProtocolError> {
     if caller != vault.owner {
         log!(
             INFO,
-            "[withdraw_collateral] Caller {} is not the owner of vault #{}",
+            "[withdraw_collateral] trace={} Caller {} is not the owner of vault #{}",
+            trace_tag(caller),
             caller,
             vault_id
         );
@@ -4605,7 +5163,8 @@ pub async fn withdraw_collateral(vault_id: u64) -> Result<u64, ProtocolError> {
     if vault.borrowed_icusd_amount > ICUSD::new(0) {
         log!(
             INFO,
-            "[withdraw_collateral] Vault #{} has outstanding debt of {} icUSD",
+            "[withdraw_collateral] trace={} Vault #{} has outstanding debt of {} icUSD",
+            trace_tag(caller),
             vault_id,
             vault.borrowed_icusd_amount
         );
@@ -4619,7 +5178,8 @@ pub async fn withdraw_collateral(vault_id: u64) -> Result<u64, ProtocolError> {
     if vault.collateral_amount == 0 {
         log!(
             INFO,
-            "[withdraw_collateral] Vault #{} has no collateral to withdraw",
+            "[withdraw_collateral] trace={} Vault #{} has no collateral to withdraw",
+            trace_tag(caller),
             vault_id
         );
         return Err(ProtocolError::GenericError(
@@ -4644,7 +5204,8 @@ pub async fn withdraw_collateral(vault_id: u64) -> Result<u64, ProtocolError> {
     let amount_to_transfer = ICP::from(vault.collateral_amount);
     log!(
         INFO,
-        "[withdraw_collateral] Withdrawing {} from vault #{}",
+        "[withdraw_collateral] trace={} Withdrawing {} from vault #{}",
+        trace_tag(caller),
         amount_to_transfer,
         vault_id
     );
@@ -4677,7 +5238,8 @@ pub async fn withdraw_collateral(vault_id: u64) -> Result<u64, ProtocolError> {
         });
         log!(
             INFO,
-            "[withdraw_collateral] vault #{} native-XRP collateral -> XRP claim #{}",
+            "[withdraw_collateral] trace={} vault #{} native-XRP collateral -> XRP claim #{}",
+            trace_tag(caller),
             vault_id,
             claim_id
         );
@@ -4690,7 +5252,8 @@ pub async fn withdraw_collateral(vault_id: u64) -> Result<u64, ProtocolError> {
 
     log!(
         INFO,
-        "[withdraw_collateral] Transferring {} (after fee deduction) to {}",
+        "[withdraw_collateral] trace={} Transferring {} (after fee deduction) to {}",
+        trace_tag(caller),
         transfer_amount,
         caller
     );
@@ -4712,7 +5275,8 @@ pub async fn withdraw_collateral(vault_id: u64) -> Result<u64, ProtocolError> {
 
             log!(
                 INFO,
-                "[withdraw_collateral] Successfully withdrew {} from vault #{}, transfer block_index: {}",
+                "[withdraw_collateral] trace={} Successfully withdrew {} from vault #{}, transfer block_index: {}",
+                trace_tag(caller),
                 amount_to_transfer,
                 vault_id,
                 block_index
@@ -4732,7 +5296,8 @@ pub async fn withdraw_collateral(vault_id: u64) -> Result<u64, ProtocolError> {
 
             log!(
                 DEBUG,
-                "[withdraw_collateral] Failed to transfer {} to {}, error: {}",
+                "[withdraw_collateral] trace={} Failed to transfer {} to {}, error: {}",
+                trace_tag(caller),
                 transfer_amount,
                 caller,
                 error
@@ -4760,6 +5325,7 @@ pub async fn withdraw_partial_collateral(vault_id: u64, amount: u64) -> Result<u
     // the vault has no debt or when the elapsed window is zero.
     let now = ic_cdk::api::time();
     reject_active_xrp_sp_absorb_preflight(vault_id, now)?;
+    reject_if_vault_frozen(vault_id, now)?;
     mutate_state(|s| s.accrue_single_vault(vault_id, now));
 
     // Read vault, per-collateral price + config from state
@@ -4774,7 +5340,7 @@ pub async fn withdraw_partial_collateral(vault_id: u64, amount: u64) -> Result<u
     ) = match read_state(|s| match s.vault_id_to_vaults.get(&vault_id) {
         Some(vault) => {
             let price = s
-                .get_collateral_price_decimal(&vault.collateral_type)
+                .get_borrow_check_price_decimal(&vault.collateral_type)
                 .ok_or("No price available for collateral. Price feed may be down.")?;
             let config = s
                 .get_collateral_config(&vault.collateral_type)
@@ -4796,6 +5362,7 @@ pub async fn withdraw_partial_collateral(vault_id: u64, amount: u64) -> Result<u
     };
 
     require_vault_not_processing(&vault)?;
+    reject_if_status_disallows(vault.vault_id, VaultOperation::Withdraw)?;
 
     if min_deposit > 0 && withdraw_amount < ICP::new(min_deposit) {
         return Err(ProtocolError::AmountTooLow {
@@ -4805,7 +5372,8 @@ pub async fn withdraw_partial_collateral(vault_id: u64, amount: u64) -> Result<u
 
     log!(
         INFO,
-        "[withdraw_partial_collateral] Request to withdraw {} from vault #{} by principal {}",
+        "[withdraw_partial_collateral] trace={} Request to withdraw {} from vault #{} by principal {}",
+        trace_tag(caller),
         withdraw_amount,
         vault_id,
         caller
@@ -4839,7 +5407,8 @@ pub async fn withdraw_partial_collateral(vault_id: u64, amount: u64) -> Result<u
     if has_dust {
         log!(
             INFO,
-            "[withdraw_partial_collateral] Forgiving dust debt of {} on vault #{}",
+            "[withdraw_partial_collateral] trace={} Forgiving dust debt of {} on vault #{}",
+            trace_tag(caller),
             vault.borrowed_icusd_amount,
             vault_id
         );
@@ -4900,7 +5469,8 @@ pub async fn withdraw_partial_collateral(vault_id: u64, amount: u64) -> Result<u
 
     log!(
         INFO,
-        "[withdraw_partial_collateral] Max withdrawable: {}, requested: {} from vault #{}",
+        "[withdraw_partial_collateral] trace={} Max withdrawable: {}, requested: {} from vault #{}",
+        trace_tag(caller),
         max_withdrawable,
         withdraw_amount,
         vault_id
@@ -4927,7 +5497,8 @@ pub async fn withdraw_partial_collateral(vault_id: u64, amount: u64) -> Result<u
         });
         log!(
             INFO,
-            "[withdraw_partial_collateral] vault #{} native-XRP collateral -> XRP claim #{}",
+            "[withdraw_partial_collateral] trace={} vault #{} native-XRP collateral -> XRP claim #{}",
+            trace_tag(caller),
             vault_id,
             claim_id
         );
@@ -4939,7 +5510,8 @@ pub async fn withdraw_partial_collateral(vault_id: u64, amount: u64) -> Result<u
 
     log!(
         INFO,
-        "[withdraw_partial_collateral] Transferring {} (after fee) to {}",
+        "[withdraw_partial_collateral] trace={} Transferring {} (after fee) to {}",
+        trace_tag(caller),
         transfer_amount,
         caller
     );
@@ -4959,7 +5531,8 @@ pub async fn withdraw_partial_collateral(vault_id: u64, amount: u64) -> Result<u
 
             log!(
                 INFO,
-                "[withdraw_partial_collateral] Successfully withdrew {} from vault #{}, block_index: {}",
+                "[withdraw_partial_collateral] trace={} Successfully withdrew {} from vault #{}, block_index: {}",
+                trace_tag(caller),
                 withdraw_amount,
                 vault_id,
                 block_index
@@ -4973,7 +5546,8 @@ pub async fn withdraw_partial_collateral(vault_id: u64, amount: u64) -> Result<u
 
             log!(
                 DEBUG,
-                "[withdraw_partial_collateral] Failed to transfer {} to {}, error: {}",
+                "[withdraw_partial_collateral] trace={} Failed to transfer {} to {}, error: {}",
+                trace_tag(caller),
                 transfer_amount,
                 caller,
                 error
@@ -4994,7 +5568,7 @@ pub async fn withdraw_partial_collateral(vault_id: u64, amount: u64) -> Result<u
 /// vault's collateral, and transfers it out. ICRC collateral closes the vault
 /// after transfer; native-XRP collateral creates a claim and leaves the vault
 /// open because the XRPL account reserve stays locked.
-async fn withdraw_and_close_vault_internal(
+pub(crate) async fn withdraw_and_close_vault_internal(
     caller: Principal,
     vault_id: u64,
 ) -> Result<Option<u64>, ProtocolError> {
@@ -5005,6 +5579,7 @@ async fn withdraw_and_close_vault_internal(
         caller
     );
     reject_active_xrp_sp_absorb_preflight(vault_id, ic_cdk::api::time())?;
+    reject_if_vault_frozen(vault_id, ic_cdk::api::time())?;
 
     // Check if the vault exists first
     let vault = read_state(|s| {
@@ -5018,6 +5593,7 @@ async fn withdraw_and_close_vault_internal(
     })?;
 
     require_vault_not_processing(&vault)?;
+    reject_if_status_disallows(vault.vault_id, VaultOperation::Close)?;
 
     // Check collateral status allows withdraw + close
     let collateral_status = read_state(|s| s.get_collateral_status(&vault.collateral_type));
@@ -5103,6 +5679,14 @@ async fn withdraw_and_close_vault_internal(
             }
             // Wave-8b LIQ-002: collateral changed → re-key.
             state.reindex_vault_cr(vault_id);
+            // Collateral is on its way out: hold every other operation off
+            // until the payout settles one way or the other.
+            crate::vault_status::transition_or_log(
+                state,
+                vault_id,
+                VaultStatus::Settling,
+                ic_cdk::api::time(),
+            );
         });
 
         // P4: native-XRP collateral leaves into an XrpClaim, not an ICRC transfer.
@@ -5174,6 +5758,12 @@ async fn withdraw_and_close_vault_internal(
                         }
                         // Wave-8b LIQ-002: rollback restores collateral → re-key.
                         state.reindex_vault_cr(vault_id);
+                        crate::vault_status::transition_or_log(
+                            state,
+                            vault_id,
+                            VaultStatus::Active,
+                            ic_cdk::api::time(),
+                        );
                     });
 
                     log!(
@@ -5204,6 +5794,14 @@ async fn withdraw_and_close_vault_internal(
             "[withdraw_and_close] Keeping native-XRP vault #{} open because the XRPL reserve remains locked",
             vault_id
         );
+        mutate_state(|s| {
+            crate::vault_status::transition_or_log(
+                s,
+                vault_id,
+                VaultStatus::Active,
+                ic_cdk::api::time(),
+            )
+        });
         return Ok(block_index);
     }
 
@@ -5285,6 +5883,11 @@ pub async fn repay_and_close_vault(arg: VaultArg) -> Result<RepayAndCloseSuccess
             return Err(e);
         }
     };
+    // Checked up front so a frozen vault is not repaid and then left open.
+    if let Err(e) = reject_if_vault_frozen(vault_id, ic_cdk::api::time()) {
+        guard_principal.fail();
+        return Err(e);
+    }
 
     // Phase 1: repay. On failure the guard fails and we propagate the error —
     // no collateral movement attempted. `is_full_close=true` lets vaults stuck
@@ -5314,7 +5917,8 @@ pub async fn repay_and_close_vault(arg: VaultArg) -> Result<RepayAndCloseSuccess
             guard_principal.fail();
             log!(
                 INFO,
-                "[repay_and_close_vault] Repay succeeded (block {}) but withdraw/close failed for vault #{}: {:?}. Vault is recoverable via withdraw_and_close_vault.",
+                "[repay_and_close_vault] trace={} Repay succeeded (block {}) but withdraw/close failed for vault #{}: {:?}. Vault is recoverable via withdraw_and_close_vault.",
+                trace_tag(caller),
                 repay_block_index,
                 vault_id,
                 e
@@ -5324,13 +5928,423 @@ pub async fn repay_and_close_vault(arg: VaultArg) -> Result<RepayAndCloseSuccess
     }
 }
 
+/// What `repay_all_and_close_vault` settles, computed from the vault after
+/// interest accrual.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepayAllAndClosePlan {
+    /// icUSD debt to repay; zero when the debt is dust and forgiven instead.
+    pub repay: ICUSD,
+    /// Dust debt forgiven without a pull.
+    pub forgiven: ICUSD,
+    /// Stable token units (e6s, fee included) to pull instead of icUSD.
+    pub stable_pull_e6s: Option<u64>,
+    /// Fee surcharge within `stable_pull_e6s`, routed to treasury.
+    pub stable_fee_e6s: u64,
+    pub collateral: ICP,
+    pub collateral_type: Principal,
+}
+
+/// Validate a repay-all-and-close request and size the pull. Everything that
+/// can reject the call is checked here, before any funds move.
+///
+/// A stable-token pull rounds the debt up to whole e6s, so the caller may
+/// overpay by under one e6 unit; `State::repay_to_vault` clamps the credit
+/// to the debt.
+pub fn plan_repay_all_and_close(
+    state: &crate::state::State,
+    caller: Principal,
+    vault_id: u64,
+    token_type: Option<&StableTokenType>,
+) -> Result<RepayAllAndClosePlan, ProtocolError> {
+    let vault = state
+        .vault_id_to_vaults
+        .get(&vault_id)
+        .ok_or_else(|| ProtocolError::GenericError(format!("Vault #{} not found", vault_id)))?;
+    if caller != vault.owner {
+        return Err(ProtocolError::CallerNotOwner);
+    }
+    require_vault_not_processing(vault)?;
+
+    if let Some(status) = state.get_collateral_status(&vault.collateral_type) {
+        if !status.allows_repay() || !status.allows_withdraw() || !status.allows_close() {
+            return Err(ProtocolError::GenericError(
+                "Repay-and-close is not allowed for this collateral type.".to_string(),
+            ));
+        }
+    }
+    let config = state
+        .get_collateral_config(&vault.collateral_type)
+        .ok_or_else(|| ProtocolError::GenericError("Collateral type not configured".to_string()))?;
+    // Native-XRP vaults stay open after their collateral leaves (the XRPL
+    // reserve remains locked), so they keep using `repay_and_close_vault`.
+    if config.is_native_xrp() {
+        return Err(ProtocolError::GenericError(
+            "Native-XRP vaults cannot be closed in one call; use repay_and_close_vault."
+                .to_string(),
+        ));
+    }
+
+    if let Some(token_type) = token_type {
+        let enabled = match token_type {
+            StableTokenType::CKUSDT => state.ckusdt_enabled,
+            StableTokenType::CKUSDC => state.ckusdc_enabled,
+        };
+        if !enabled {
+            return Err(ProtocolError::GenericError(format!(
+                "{:?} repayments are currently disabled",
+                token_type
+            )));
+        }
+    }
+
+    let debt = vault.borrowed_icusd_amount;
+    let (repay, forgiven) = if debt.0 <= crate::state::DUST_DEBT_THRESHOLD {
+        (ICUSD::new(0), debt)
+    } else {
+        (debt, ICUSD::new(0))
+    };
+
+    let (stable_pull_e6s, stable_fee_e6s) = match token_type {
+        Some(_) if repay.0 > 0 => {
+            let (base_e6s, fee_e6s) = stable_pull(state, repay);
+            (Some(base_e6s + fee_e6s), fee_e6s)
+        }
+        _ => (None, 0),
+    };
+
+    Ok(RepayAllAndClosePlan {
+        repay,
+        forgiven,
+        stable_pull_e6s,
+        stable_fee_e6s,
+        collateral: ICP::from(vault.collateral_amount),
+        collateral_type: vault.collateral_type,
+    })
+}
+
+/// Result of `repay_all_and_close_vault`.
+#[derive(candid::CandidType, candid::Deserialize, Clone, Debug)]
+pub struct RepayAllAndCloseSuccess {
+    /// Block of the icUSD or stable-token pull; None when only dust was owed.
+    pub repay_block_index: Option<u64>,
+    /// Debt cleared, in icUSD e8s (repaid plus forgiven dust).
+    pub debt_cleared: u64,
+    /// Stable token units pulled (e6s, fee included), if repaid in a stable token.
+    pub stable_pulled_e6s: Option<u64>,
+    /// Collateral returned to the owner, before the ledger fee.
+    pub collateral_returned: u64,
+    /// Block of the collateral transfer, once it has landed.
+    pub collateral_return_block_index: Option<u64>,
+    /// The collateral transfer failed and is queued for retry.
+    pub collateral_return_pending: bool,
+}
+
+/// Repay a vault's whole outstanding debt and close it in one call.
+///
+/// Unlike `repay_and_close_vault`, the caller does not name an amount: the
+/// debt is accrued and pulled exactly, in icUSD or an enabled stable token.
+/// Every check runs before the pull, so a rejected or failed pull leaves the
+/// vault untouched. Once the pull lands, the repayment, the close and the
+/// collateral return are committed together; the return is queued as a
+/// pending margin transfer and sent straight away, and a failed send stays
+/// queued for the retry timer instead of reopening the vault.
+pub async fn repay_all_and_close_vault(
+    arg: crate::RepayAllAndCloseArg,
+) -> Result<RepayAllAndCloseSuccess, ProtocolError> {
+    let caller = ic_cdk::api::caller();
+    let vault_id = arg.vault_id;
+    // Same guard name as `repay_and_close_vault`: the two never overlap.
+    let guard_principal = GuardPrincipal::new(caller, &format!("repay_and_close_{}", vault_id))?;
+    let _vault_op_guard = match VaultLiquidationGuard::new(vault_id) {
+        Ok(g) => g,
+        Err(e) => {
+            guard_principal.fail();
+            return Err(e);
+        }
+    };
+
+    let now = ic_cdk::api::time();
+    let preflight = reject_if_vault_frozen(vault_id, now)
+        .and_then(|_| reject_active_xrp_sp_absorb_preflight(vault_id, now));
+    if let Err(e) = preflight {
+        guard_principal.fail();
+        return Err(e);
+    }
+    if let Some(token_type) = &arg.token_type {
+        if let Err(e) = crate::xrc::ensure_stable_not_depegged(token_type).await {
+            guard_principal.fail();
+            return Err(e);
+        }
+    }
+
+    let now = ic_cdk::api::time();
+    mutate_state(|s| s.accrue_single_vault(vault_id, now));
+    let plan = match read_state(|s| {
+        plan_repay_all_and_close(s, caller, vault_id, arg.token_type.as_ref())
+    }) {
+        Ok(plan) => plan,
+        Err(e) => {
+            guard_principal.fail();
+            return Err(e);
+        }
+    };
+
+    // Pull the debt. Nothing has changed yet, so a failure needs no rollback.
+    let repay_block_index = if plan.repay.0 == 0 {
+        None
+    } else {
+        let pulled = match (&arg.token_type, plan.stable_pull_e6s) {
+            (Some(token_type), Some(pull_e6s)) => {
+                transfer_stable_from(token_type.clone(), pull_e6s, caller)
+                    .await
+                    .map_err(|e| ProtocolError::TransferFromError(e, pull_e6s))
+            }
+            _ => transfer_icusd_from(plan.repay, caller)
+                .await
+                .map_err(|e| ProtocolError::TransferFromError(e, plan.repay.to_u64())),
+        };
+        match pulled {
+            Ok(block_index) => Some(block_index),
+            Err(e) => {
+                guard_principal.fail();
+                return Err(e);
+            }
+        }
+    };
+
+    // Commit repay + close + queued collateral return in one state mutation.
+    // What is left is the planned dust plus any interest the global timer
+    // accrued while the pull was in flight: forgiven up to the dust
+    // threshold, anything larger keeps the vault open.
+    let committed = mutate_state(|s| {
+        let interest_share = match repay_block_index {
+            Some(block_index) => {
+                let stable_fee = arg
+                    .token_type
+                    .as_ref()
+                    .and_then(|t| stable_repay_fee(s, t, plan.stable_fee_e6s));
+                record_repayed_to_vault(s, vault_id, plan.repay, block_index, stable_fee)
+            }
+            None => ICUSD::new(0),
+        };
+        let residual = s
+            .vault_id_to_vaults
+            .get(&vault_id)
+            .map(|v| v.borrowed_icusd_amount)
+            .unwrap_or(ICUSD::new(0));
+        if residual.0 > crate::state::DUST_DEBT_THRESHOLD {
+            return Err((interest_share, residual));
+        }
+        if residual.0 > 0 {
+            s.dust_forgiven_total += residual;
+            let _ = s.repay_to_vault(vault_id, residual);
+            crate::storage::record_event(&crate::event::Event::DustForgiven {
+                vault_id,
+                amount: residual,
+                timestamp: Some(now),
+            });
+        }
+
+        if let Some(vault) = s.vault_id_to_vaults.get_mut(&vault_id) {
+            vault.collateral_amount = 0;
+        }
+        crate::event::record_withdraw_and_close_vault(s, vault_id, plan.collateral, None);
+        let nonce = (plan.collateral > ICP::new(0)).then(|| {
+            let nonce = s.next_op_nonce();
+            queue_collateral_payout(
+                s,
+                vault_id,
+                caller,
+                caller,
+                plan.collateral,
+                plan.collateral_type,
+                nonce,
+                now,
+            );
+            nonce
+        });
+        Ok((interest_share, residual, nonce))
+    });
+
+    let interest_share = match &committed {
+        Ok((interest_share, _, _)) | Err((interest_share, _)) => *interest_share,
+    };
+    match &arg.token_type {
+        Some(token_type) => {
+            if interest_share.to_u64() > 0 {
+                crate::treasury::distribute_stablecoin_interest(
+                    interest_share.to_u64(),
+                    plan.collateral_type,
+                    token_type.clone(),
+                )
+                .await;
+            }
+            send_stable_repay_fee_to_treasury(token_type, plan.stable_fee_e6s, caller).await;
+        }
+        None => {
+            let unminted_interest =
+                crate::treasury::distribute_interest(interest_share, plan.collateral_type).await;
+            if unminted_interest.to_u64() > 0 {
+                mutate_state(|s| {
+                    s.restore_pending_interest_for_pool(
+                        plan.collateral_type,
+                        unminted_interest.to_u64(),
+                    )
+                });
+            }
+        }
+    }
+
+    let (residual, nonce) = match committed {
+        Ok((_, residual, nonce)) => (residual, nonce),
+        Err((_, residual)) => {
+            guard_principal.fail();
+            log!(
+                INFO,
+                "[repay_all_and_close_vault] trace={} Repaid vault #{} (block {:?}) but {} icUSD accrued during the call; vault left open",
+                trace_tag(caller),
+                vault_id,
+                repay_block_index,
+                residual
+            );
+            return Err(ProtocolError::GenericError(format!(
+                "Debt of vault #{} grew by {} icUSD during the call; the repayment is kept and the vault stays open. Repay the remainder and close again.",
+                vault_id, residual
+            )));
+        }
+    };
+
+    // Send the queued collateral now; a failure stays queued for retry.
+    let mut collateral_return_block_index = None;
+    if let Some(nonce) = nonce {
+        let (ledger_fee, ledger) = read_state(|s| {
+            s.get_collateral_config(&plan.collateral_type)
+                .map(|c| (ICP::from(c.ledger_fee), c.ledger_canister_id))
+                .unwrap_or((s.icp_ledger_fee, s.icp_ledger_principal))
+        });
+        if plan.collateral <= ledger_fee {
+            mutate_state(|s| s.pending_margin_transfers.remove(&(vault_id, caller)));
+        } else {
+            match management::transfer_collateral_with_nonce(
+                (plan.collateral - ledger_fee).to_u64(),
+                caller,
+                ledger,
+                nonce,
+            )
+            .await
+            {
+                Ok(block_index) => {
+                    mutate_state(|s| s.pending_margin_transfers.remove(&(vault_id, caller)));
+                    collateral_return_block_index = Some(block_index);
+                }
+                Err(e) => {
+                    log!(
+                        INFO,
+                        "[repay_all_and_close_vault] trace={} Collateral return for closed vault #{} failed: {}. Queued for retry",
+                        trace_tag(caller),
+                        vault_id,
+                        e
+                    );
+                    schedule_transfer_retry(vault_id, 0);
+                }
+            }
+        }
+    }
+    let collateral_return_pending =
+        read_state(|s| s.pending_margin_transfers.contains_key(&(vault_id, caller)));
+
+    log!(
+        INFO,
+        "[repay_all_and_close_vault] trace={} Closed vault #{}: repaid {}, forgave {}, returned {} collateral (pending: {})",
+        trace_tag(caller),
+        vault_id,
+        plan.repay,
+        residual,
+        plan.collateral,
+        collateral_return_pending
+    );
+    guard_principal.complete();
+    Ok(RepayAllAndCloseSuccess {
+        repay_block_index,
+        debt_cleared: (plan.repay + residual).to_u64(),
+        stable_pulled_e6s: plan.stable_pull_e6s,
+        collateral_returned: plan.collateral.to_u64(),
+        collateral_return_block_index,
+        collateral_return_pending,
+    })
+}
+
+/// Partially liquidate `vault_id` by exactly enough icUSD to lift it to
+/// `target_cr`, spending at most `max_icusd`. The repay amount is computed
+/// server-side (`State::quote_liquidation_to_target`) and executed through
+/// `liquidate_vault_partial`; the quote is returned alongside the result so
+/// liquidators can see which bound applied.
+pub async fn liquidate_to_target(
+    vault_id: u64,
+    target_cr: Ratio,
+    max_icusd: ICUSD,
+    min_collateral_out: Option<u64>,
+) -> Result<crate::LiquidateToTargetResult, ProtocolError> {
+    let quote = quote_to_target(vault_id, target_cr, max_icusd)?;
+    let liquidation =
+        liquidate_vault_partial(vault_id, quote.repay_e8s, min_collateral_out).await?;
+    Ok(crate::LiquidateToTargetResult { quote, liquidation })
+}
+
+/// `liquidate_to_target` paid in ckUSDT or ckUSDC. `max_amount` caps the
+/// debt repaid in icUSD e8s, before the stable surcharge.
+pub async fn liquidate_to_target_with_stable(
+    vault_id: u64,
+    target_cr: Ratio,
+    max_amount: ICUSD,
+    token_type: StableTokenType,
+    min_collateral_out: Option<u64>,
+) -> Result<crate::LiquidateToTargetResult, ProtocolError> {
+    let quote = quote_to_target(vault_id, target_cr, max_amount)?;
+    let liquidation = liquidate_vault_partial_with_stable(
+        vault_id,
+        quote.repay_e8s,
+        token_type,
+        min_collateral_out,
+    )
+    .await?;
+    Ok(crate::LiquidateToTargetResult { quote, liquidation })
+}
+
+fn quote_to_target(
+    vault_id: u64,
+    target_cr: Ratio,
+    max_icusd: ICUSD,
+) -> Result<crate::LiquidationTargetQuote, ProtocolError> {
+    let quote = read_state(|s| {
+        let vault = s
+            .vault_id_to_vaults
+            .get(&vault_id)
+            .ok_or_else(|| format!("Vault #{} not found", vault_id))?;
+        s.quote_liquidation_to_target(vault, target_cr, max_icusd)
+    })
+    .map_err(ProtocolError::GenericError)?;
+
+    log!(
+        INFO,
+        "[liquidate_to_target] Vault #{}: target CR {}, repaying {} icUSD ({:?})",
+        vault_id,
+        quote.target_cr,
+        quote.repay_e8s,
+        quote.limited_by
+    );
+    Ok(quote)
+}
+
 pub async fn liquidate_vault_partial(
     vault_id: u64,
     icusd_amount: u64,
+    min_collateral_out: Option<u64>,
 ) -> Result<SuccessWithFee, ProtocolError> {
     let caller = ic_cdk::api::caller();
     let guard_principal =
         GuardPrincipal::new(caller, &format!("liquidate_vault_partial_{}", vault_id))?;
+    reject_if_reserved_for_pool(vault_id, caller)?;
     reject_if_bot_processing(vault_id)?; // LIQ-101: don't double-seize a bot-claimed vault
                                          // BK-001/002: per-vault lock so two different callers can't race this vault
                                          // and both be paid the full pre-state collateral from the shared pool.
@@ -5368,6 +6382,7 @@ pub async fn liquidate_vault_partial(
         collateral_to_liquidator,
         total_to_seize,
         protocol_cut,
+        cap_bound,
     ) = match read_state(|s| {
         match s.vault_id_to_vaults.get(&vault_id) {
             Some(vault) => {
@@ -5416,33 +6431,34 @@ pub async fn liquidate_vault_partial(
                         .get_collateral_config(&vault.collateral_type)
                         .map(|c| c.min_vault_debt)
                         .unwrap_or(ICUSD::new(0));
-                    let actual_liquidation_amount =
+                    let rounded_amount =
                         round_up_partial_liq_dust(vault, capped_amount, min_vault_debt);
 
-                    if actual_liquidation_amount == ICUSD::new(0) {
+                    if rounded_amount == ICUSD::new(0) {
                         return Err("Cannot liquidate zero amount".to_string());
                     }
 
-                    // Calculate collateral to transfer (debt + liquidation bonus)
-                    let liq_bonus = s.get_liquidation_bonus_for(&vault.collateral_type);
-                    let protocol_share = s.get_liquidation_protocol_share();
-                    let collateral_raw = crate::numeric::icusd_to_collateral_amount(
-                        actual_liquidation_amount,
+                    // Clip to what the collateral's liquidation cap lets it
+                    // give up now (see `liquidation_caps`).
+                    let (actual_liquidation_amount, seizable) = crate::liquidation_caps::cap_repay(
+                        s,
+                        vault,
+                        rounded_amount,
                         price,
                         decimals,
-                    );
-                    let collateral_with_bonus = ICP::from(collateral_raw) * liq_bonus;
-                    let total_to_seize =
-                        collateral_with_bonus.min(ICP::from(vault.collateral_amount));
-
-                    // Split: protocol gets a share of the bonus portion (liquidator's profit)
-                    let bonus_portion = total_to_seize.to_u64().saturating_sub(collateral_raw);
-                    let protocol_cut = (rust_decimal::Decimal::from(bonus_portion)
-                        * protocol_share.0)
-                        .to_u64()
-                        .unwrap_or(0);
-                    let collateral_to_liquidator =
-                        ICP::from(total_to_seize.to_u64() - protocol_cut);
+                        ic_cdk::api::time(),
+                    )?;
+
+                    // Calculate collateral to transfer (debt + liquidation bonus);
+                    // the protocol gets a share of the bonus portion. Shared with
+                    // `preview_liquidation`.
+                    let (total_to_seize, protocol_cut, collateral_to_liquidator) = s
+                        .partial_liquidation_split(
+                            vault,
+                            actual_liquidation_amount,
+                            price,
+                            decimals,
+                        );
 
                     Ok((
                         vault.clone(),
@@ -5454,6 +6470,7 @@ pub async fn liquidate_vault_partial(
                         collateral_to_liquidator,
                         total_to_seize,
                         protocol_cut,
+                        seizable.map(|seizable| (rounded_amount, seizable)),
                     ))
                 }
             }
@@ -5468,7 +6485,8 @@ pub async fn liquidate_vault_partial(
     };
 
     log!(INFO,
-        "[liquidate_vault_partial] Vault #{}: liquidating {} icUSD (max: {}), getting {} ICP collateral (protocol fee: {} ICP)",
+        "[liquidate_vault_partial] trace={} Vault #{}: liquidating {} icUSD (max: {}), getting {} ICP collateral (protocol fee: {} ICP)",
+        trace_tag(caller),
         vault_id,
         max_liquidatable_debt.to_u64(),
         vault.borrowed_icusd_amount.to_u64(),
@@ -5476,12 +6494,19 @@ pub async fn liquidate_vault_partial(
         protocol_cut
     );
 
+    if let Err(e) = check_min_collateral_out(collateral_to_liquidator, min_collateral_out) {
+        guard_principal.fail();
+        return Err(e);
+    }
+
     // Step 2: Take icUSD from liquidator
+    let observed_seq = crate::storage::observe_events();
     let icusd_block_index = match transfer_icusd_from(max_liquidatable_debt, caller).await {
         Ok(block_index) => {
             log!(
                 INFO,
-                "[liquidate_vault_partial] Received {} icUSD from liquidator",
+                "[liquidate_vault_partial] trace={} Received {} icUSD from liquidator",
+                trace_tag(caller),
                 max_liquidatable_debt.to_u64()
             );
             block_index
@@ -5497,6 +6522,7 @@ pub async fn liquidate_vault_partial(
 
     // Step 3: Update protocol state (partial liquidation)
     let (interest_share, xrp_claim_id) = mutate_state(|s| {
+        let vault_before = s.vault_id_to_vaults.get(&vault_id).cloned();
         // Compute proportional interest share before reducing debt
         let interest_share = if let Some(vault) = s.vault_id_to_vaults.get(&vault_id) {
             if vault.accrued_interest.0 > 0 && vault.borrowed_icusd_amount.0 > 0 {
@@ -5566,10 +6592,12 @@ pub async fn liquidate_vault_partial(
             );
             if s.check_deficit_readonly_latch() {
                 log!(INFO,
-                    "[LIQ-005] deficit threshold {} crossed by partial vault #{} shortfall {}; auto-latched ReadOnly",
+                    "[LIQ-005] trace={} deficit threshold {} crossed by partial vault #{} shortfall {}; auto-latched ReadOnly",
+                    trace_tag(caller),
                     s.deficit_readonly_threshold_e8s, vault_id, shortfall.to_u64()
                 );
             }
+            crate::event::record_mode_transitions(s);
         }
 
         // Record the partial liquidation event (applied payout, so replay's
@@ -5588,7 +6616,17 @@ pub async fn liquidate_vault_partial(
             timestamp: Some(ic_cdk::api::time()),
             three_usd_reserves_e8s: None,
         };
-        crate::storage::record_event(&event);
+        crate::storage::record_event_after(&event, observed_seq);
+        crate::liquidation_caps::note_liquidation(s, &event);
+        if let Some((requested, seizable)) = cap_bound {
+            crate::event::record_liquidation_cap_bound(
+                vault_id,
+                vault.collateral_type,
+                requested,
+                max_liquidatable_debt,
+                seizable,
+            );
+        }
 
         // Liquidator-reward payout: PendingMarginTransfer for ICRC, XrpClaim for
         // native-XRP. Capture vault.owner (custody key) BEFORE cleanup_if_drained.
@@ -5609,14 +6647,31 @@ pub async fn liquidate_vault_partial(
         if s.cleanup_if_drained(vault_id) {
             log!(
                 INFO,
-                "[liquidate_vault_partial] Vault #{} fully liquidated — removed",
+                "[liquidate_vault_partial] trace={} Vault #{} fully liquidated — removed",
+                trace_tag(caller),
                 vault_id
             );
         }
 
+        // The owner's post-mortem; see `liquidation_receipts`.
+        if let Some(before) = &vault_before {
+            crate::liquidation_receipts::issue(
+                s,
+                before,
+                crate::liquidation_receipts::Settlement {
+                    liquidator: Some(caller),
+                    price: collateral_price,
+                    protocol_fee: protocol_cut.min(collateral_applied),
+                    ..Default::default()
+                },
+                ic_cdk::api::time(),
+            );
+        }
+
         log!(
             INFO,
-            "[liquidate_vault_partial] Partial liquidation completed, {} pending transfers created",
+            "[liquidate_vault_partial] trace={} Partial liquidation completed, {} pending transfers created",
+            trace_tag(caller),
             1
         );
         (interest_share, xrp_claim_id)
@@ -5653,13 +6708,8 @@ pub async fn liquidate_vault_partial(
                 );
             });
         } else {
-            let asset_type = crate::treasury::collateral_to_asset_type(&vault.collateral_type);
-            crate::treasury::send_liquidation_fee_to_treasury(
-                protocol_cut,
-                vault.collateral_type,
-                asset_type,
-            )
-            .await;
+            crate::treasury::send_liquidation_fee_to_treasury(protocol_cut, vault.collateral_type)
+                .await;
         }
     }
 
@@ -5668,12 +6718,13 @@ pub async fn liquidate_vault_partial(
         Ok(processed_count) => {
             log!(
                 INFO,
-                "[liquidate_vault_partial] Successfully processed {} transfers immediately",
+                "[liquidate_vault_partial] trace={} Successfully processed {} transfers immediately",
+                trace_tag(caller),
                 processed_count
             );
         }
         Err(e) => {
-            log!(INFO, "[liquidate_vault_partial] Immediate processing failed: {}. Transfers will be retried via timer", e);
+            log!(INFO, "[liquidate_vault_partial] trace={} Immediate processing failed: {}. Transfers will be retried via timer", trace_tag(caller), e);
             schedule_transfer_retry(vault_id, 0);
         }
     }
@@ -5682,7 +6733,8 @@ pub async fn liquidate_vault_partial(
         ic_cdk::spawn(async move {
             log!(
                 INFO,
-                "[liquidate_vault_partial] Backup timer processing transfers for vault #{}",
+                "[liquidate_vault_partial] trace={} Backup timer processing transfers for vault #{}",
+                trace_tag(caller),
                 vault_id
             );
             let _ = crate::process_pending_transfer().await;
@@ -5703,7 +6755,8 @@ pub async fn liquidate_vault_partial(
         ICUSD::new(0)
     };
 
-    log!(INFO, "[liquidate_vault_partial] Partial liquidation completed. Block index: {}, Fee: {}, Collateral: {}",
+    log!(INFO, "[liquidate_vault_partial] trace={} Partial liquidation completed. Block index: {}, Fee: {}, Collateral: {}",
+         trace_tag(caller),
          icusd_block_index, fee_amount.to_u64(), collateral_to_liquidator.to_u64());
 
     Ok(SuccessWithFee {
@@ -5716,15 +6769,48 @@ pub async fn liquidate_vault_partial(
     })
 }
 
+/// Refuse a liquidation paid in `token_type` while that stable is disabled
+/// or off its peg.
+async fn check_stable_liquidation_payment(
+    token_type: &StableTokenType,
+) -> Result<(), ProtocolError> {
+    let is_enabled = read_state(|s| match token_type {
+        StableTokenType::CKUSDT => s.ckusdt_enabled,
+        StableTokenType::CKUSDC => s.ckusdc_enabled,
+    });
+    if !is_enabled {
+        return Err(ProtocolError::GenericError(format!(
+            "{:?} liquidations are currently disabled",
+            token_type
+        )));
+    }
+    // Depeg protection: fetch fresh stablecoin price and reject if outside $0.95–$1.05
+    crate::xrc::ensure_stable_not_depegged(token_type).await
+}
+
+/// What paying `debt` in a stable token costs, as (base, surcharge) in
+/// e6s. The stable is taken 1:1 with icUSD, rounded up to the next e6 so it
+/// covers every e8 of debt cleared, plus the `ckstable_repay_fee` surcharge
+/// that goes to the treasury.
+pub fn stable_pull(state: &crate::state::State, debt: ICUSD) -> (u64, u64) {
+    let base_e6s = debt.to_u64().div_ceil(100);
+    let fee_e6s = (Decimal::from(base_e6s) * state.ckstable_repay_fee.0)
+        .to_u64()
+        .unwrap_or(0);
+    (base_e6s, fee_e6s)
+}
+
 /// Liquidate a vault using ckUSDT or ckUSDC (1:1 with icUSD, plus configurable fee)
 pub async fn liquidate_vault_partial_with_stable(
     vault_id: u64,
     stable_amount: u64,
     token_type: StableTokenType,
+    min_collateral_out: Option<u64>,
 ) -> Result<SuccessWithFee, ProtocolError> {
     let caller = ic_cdk::api::caller();
     let guard_principal =
         GuardPrincipal::new(caller, &format!("liquidate_vault_stable_{}", vault_id))?;
+    reject_if_reserved_for_pool(vault_id, caller)?;
     reject_if_bot_processing(vault_id)?; // LIQ-101: don't double-seize a bot-claimed vault
     let _vault_liq_guard = VaultLiquidationGuard::new(vault_id)?; // BK-001/002 per-vault lock
     if let Err(e) = reject_active_xrp_sp_absorb_preflight(vault_id, ic_cdk::api::time()) {
@@ -5735,21 +6821,7 @@ pub async fn liquidate_vault_partial_with_stable(
     // Wave-8b LIQ-002 band gate deactivated 2026-05-18 (see
     // `liquidate_vault_partial` above for rationale).
 
-    // Check if the selected stable token is enabled
-    let is_enabled = read_state(|s| match token_type {
-        StableTokenType::CKUSDT => s.ckusdt_enabled,
-        StableTokenType::CKUSDC => s.ckusdc_enabled,
-    });
-    if !is_enabled {
-        guard_principal.fail();
-        return Err(ProtocolError::GenericError(format!(
-            "{:?} liquidations are currently disabled",
-            token_type
-        )));
-    }
-
-    // Depeg protection: fetch fresh stablecoin price and reject if outside $0.95–$1.05
-    if let Err(e) = crate::xrc::ensure_stable_not_depegged(&token_type).await {
+    if let Err(e) = check_stable_liquidation_payment(&token_type).await {
         guard_principal.fail();
         return Err(e);
     }
@@ -5776,6 +6848,7 @@ pub async fn liquidate_vault_partial_with_stable(
         collateral_to_liquidator,
         total_to_seize,
         protocol_cut,
+        cap_bound,
     ) = match read_state(|s| {
         match s.vault_id_to_vaults.get(&vault_id) {
             Some(vault) => {
@@ -5823,13 +6896,24 @@ pub async fn liquidate_vault_partial_with_stable(
                         .get_collateral_config(&vault.collateral_type)
                         .map(|c| c.min_vault_debt)
                         .unwrap_or(ICUSD::new(0));
-                    let actual_liquidation_amount =
+                    let rounded_amount =
                         round_up_partial_liq_dust(vault, capped_amount, min_vault_debt);
 
-                    if actual_liquidation_amount == ICUSD::new(0) {
+                    if rounded_amount == ICUSD::new(0) {
                         return Err("Cannot liquidate zero amount".to_string());
                     }
 
+                    // Clip to what the collateral's liquidation cap lets it
+                    // give up now (see `liquidation_caps`).
+                    let (actual_liquidation_amount, seizable) = crate::liquidation_caps::cap_repay(
+                        s,
+                        vault,
+                        rounded_amount,
+                        price,
+                        decimals,
+                        ic_cdk::api::time(),
+                    )?;
+
                     let liq_bonus = s.get_liquidation_bonus_for(&vault.collateral_type);
                     let protocol_share = s.get_liquidation_protocol_share();
                     let collateral_raw = crate::numeric::icusd_to_collateral_amount(
@@ -5860,6 +6944,7 @@ pub async fn liquidate_vault_partial_with_stable(
                         collateral_to_liquidator,
                         total_to_seize,
                         protocol_cut,
+                        seizable.map(|seizable| (rounded_amount, seizable)),
                     ))
                 }
             }
@@ -5874,7 +6959,8 @@ pub async fn liquidate_vault_partial_with_stable(
     };
 
     log!(INFO,
-        "[liquidate_vault_stable] Vault #{}: liquidating {} {:?} (max: {}), getting {} ICP collateral (protocol fee: {} ICP)",
+        "[liquidate_vault_stable] trace={} Vault #{}: liquidating {} {:?} (max: {}), getting {} ICP collateral (protocol fee: {} ICP)",
+        trace_tag(caller),
         vault_id,
         max_liquidatable_debt.to_u64(),
         token_type,
@@ -5883,21 +6969,23 @@ pub async fn liquidate_vault_partial_with_stable(
         protocol_cut
     );
 
+    if let Err(e) = check_min_collateral_out(collateral_to_liquidator, min_collateral_out) {
+        guard_principal.fail();
+        return Err(e);
+    }
+
     // Step 2: Convert e8s to e6s and add fee surcharge, then take stable token from liquidator
-    let debt_e8s = max_liquidatable_debt.to_u64();
-    let base_stable_e6s = debt_e8s / 100;
-    let fee_rate = read_state(|s| s.ckstable_repay_fee);
-    let fee_e6s = (rust_decimal::Decimal::from(base_stable_e6s) * fee_rate.0)
-        .to_u64()
-        .unwrap_or(0);
+    let (base_stable_e6s, fee_e6s) = read_state(|s| stable_pull(s, max_liquidatable_debt));
     let total_pull_e6s = base_stable_e6s + fee_e6s;
 
+    let observed_seq = crate::storage::observe_events();
     let stable_block_index =
         match transfer_stable_from(token_type.clone(), total_pull_e6s, caller).await {
             Ok(block_index) => {
                 log!(
                     INFO,
-                    "[liquidate_vault_stable] Received {} e6s {:?} from liquidator (fee: {} e6s)",
+                    "[liquidate_vault_stable] trace={} Received {} e6s {:?} (fee: {} e6s)",
+                    trace_tag(caller),
                     total_pull_e6s,
                     token_type,
                     fee_e6s
@@ -5915,6 +7003,7 @@ pub async fn liquidate_vault_partial_with_stable(
 
     // Step 3: Update protocol state (partial liquidation)
     let (interest_share, xrp_claim_id) = mutate_state(|s| {
+        let vault_before = s.vault_id_to_vaults.get(&vault_id).cloned();
         // Compute proportional interest share before reducing debt
         let interest_share = if let Some(vault) = s.vault_id_to_vaults.get(&vault_id) {
             if vault.accrued_interest.0 > 0 && vault.borrowed_icusd_amount.0 > 0 {
@@ -5979,10 +7068,12 @@ pub async fn liquidate_vault_partial_with_stable(
             );
             if s.check_deficit_readonly_latch() {
                 log!(INFO,
-                    "[LIQ-005] deficit threshold {} crossed by stable-partial vault #{} shortfall {}; auto-latched ReadOnly",
+                    "[LIQ-005] trace={} deficit threshold {} crossed by stable-partial vault #{} shortfall {}; auto-latched ReadOnly",
+                    trace_tag(caller),
                     s.deficit_readonly_threshold_e8s, vault_id, shortfall.to_u64()
                 );
             }
+            crate::event::record_mode_transitions(s);
         }
 
         // Record the partial liquidation event (applied payout, replay-exact)
@@ -6000,7 +7091,17 @@ pub async fn liquidate_vault_partial_with_stable(
             timestamp: Some(ic_cdk::api::time()),
             three_usd_reserves_e8s: None,
         };
-        crate::storage::record_event(&event);
+        crate::storage::record_event_after(&event, observed_seq);
+        crate::liquidation_caps::note_liquidation(s, &event);
+        if let Some((requested, seizable)) = cap_bound {
+            crate::event::record_liquidation_cap_bound(
+                vault_id,
+                vault.collateral_type,
+                requested,
+                max_liquidatable_debt,
+                seizable,
+            );
+        }
 
         // Create pending transfer for liquidator reward
         let nonce = s.next_op_nonce();
@@ -6020,14 +7121,31 @@ pub async fn liquidate_vault_partial_with_stable(
         if s.cleanup_if_drained(vault_id) {
             log!(
                 INFO,
-                "[liquidate_vault_stable] Vault #{} fully liquidated — removed",
+                "[liquidate_vault_stable] trace={} Vault #{} fully liquidated — removed",
+                trace_tag(caller),
                 vault_id
             );
         }
 
+        // The owner's post-mortem; see `liquidation_receipts`.
+        if let Some(before) = &vault_before {
+            crate::liquidation_receipts::issue(
+                s,
+                before,
+                crate::liquidation_receipts::Settlement {
+                    liquidator: Some(caller),
+                    price: collateral_price,
+                    protocol_fee: protocol_cut.min(collateral_applied),
+                    ..Default::default()
+                },
+                ic_cdk::api::time(),
+            );
+        }
+
         log!(
             INFO,
-            "[liquidate_vault_stable] Partial liquidation completed, pending transfer created"
+            "[liquidate_vault_stable] trace={} Partial liquidation completed, pending transfer created",
+            trace_tag(caller)
         );
         (interest_share, xrp_claim_id)
     });
@@ -6043,32 +7161,7 @@ pub async fn liquidate_vault_partial_with_stable(
     }
 
     // Route fee surcharge to treasury as stablecoins (mirrors repay_to_vault_with_stable)
-    if fee_e6s > 0 {
-        let (treasury, stable_ledger) = read_state(|s| {
-            let ledger = match token_type {
-                StableTokenType::CKUSDT => s.ckusdt_ledger_principal,
-                StableTokenType::CKUSDC => s.ckusdc_ledger_principal,
-            };
-            (s.treasury_principal, ledger)
-        });
-        if let (Some(treasury_principal), Some(stable_ledger)) = (treasury, stable_ledger) {
-            match management::transfer_collateral(fee_e6s, treasury_principal, stable_ledger).await
-            {
-                Ok(block) => {
-                    log!(INFO,
-                        "[liquidate_vault_stable] Transferred {} e6s fee surcharge to treasury (block {})",
-                        fee_e6s, block
-                    );
-                }
-                Err(e) => {
-                    log!(INFO,
-                        "[liquidate_vault_stable] Fee surcharge transfer to treasury failed: {:?}. Fee remains in reserves.",
-                        e
-                    );
-                }
-            }
-        }
-    }
+    send_stable_repay_fee_to_treasury(&token_type, fee_e6s, caller).await;
 
     // Send protocol's liquidation fee cut to treasury (fire-and-forget)
     if protocol_cut > 0 {
@@ -6090,13 +7183,8 @@ pub async fn liquidate_vault_partial_with_stable(
                 );
             });
         } else {
-            let asset_type = crate::treasury::collateral_to_asset_type(&vault.collateral_type);
-            crate::treasury::send_liquidation_fee_to_treasury(
-                protocol_cut,
-                vault.collateral_type,
-                asset_type,
-            )
-            .await;
+            crate::treasury::send_liquidation_fee_to_treasury(protocol_cut, vault.collateral_type)
+                .await;
         }
     }
 
@@ -6105,12 +7193,13 @@ pub async fn liquidate_vault_partial_with_stable(
         Ok(processed_count) => {
             log!(
                 INFO,
-                "[liquidate_vault_stable] Successfully processed {} transfers immediately",
+                "[liquidate_vault_stable] trace={} Successfully processed {} transfers immediately",
+                trace_tag(caller),
                 processed_count
             );
         }
         Err(e) => {
-            log!(INFO, "[liquidate_vault_stable] Immediate processing failed: {}. Transfers will be retried via timer", e);
+            log!(INFO, "[liquidate_vault_stable] trace={} Immediate processing failed: {}. Transfers will be retried via timer", trace_tag(caller), e);
             schedule_transfer_retry(vault_id, 0);
         }
     }
@@ -6119,7 +7208,8 @@ pub async fn liquidate_vault_partial_with_stable(
         ic_cdk::spawn(async move {
             log!(
                 INFO,
-                "[liquidate_vault_stable] Backup timer processing transfers for vault #{}",
+                "[liquidate_vault_stable] trace={} Backup timer processing transfers for vault #{}",
+                trace_tag(caller),
                 vault_id
             );
             let _ = crate::process_pending_transfer().await;
@@ -6142,7 +7232,8 @@ pub async fn liquidate_vault_partial_with_stable(
 
     log!(
         INFO,
-        "[liquidate_vault_stable] Liquidation completed. Block index: {}, Fee: {}, Collateral: {}",
+        "[liquidate_vault_stable] trace={} Liquidation completed. Block index: {}, Fee: {}, Collateral: {}",
+        trace_tag(caller),
         stable_block_index,
         fee_amount.to_u64(),
         collateral_to_liquidator.to_u64()
@@ -6293,8 +7384,9 @@ pub async fn liquidate_vault_debt_already_burned(
         guard_principal.fail();
         log!(
             INFO,
-            "[liquidate_vault_debt_burned] [LIQ-004] proof verification FAILED for vault #{} \
+            "[liquidate_vault_debt_burned] trace={} [LIQ-004] proof verification FAILED for vault #{} \
              ({:?} block {}): {}",
+            trace_tag(caller),
             vault_id,
             proof.ledger_kind,
             proof.block_index,
@@ -6404,7 +7496,8 @@ pub async fn liquidate_vault_debt_already_burned(
     };
 
     log!(INFO,
-        "[liquidate_vault_debt_burned] Vault #{}: writing down {} icUSD (burned via 3pool), releasing {} collateral (protocol fee: {})",
+        "[liquidate_vault_debt_burned] trace={} Vault #{}: writing down {} icUSD (burned via 3pool), releasing {} collateral (protocol fee: {})",
+        trace_tag(caller),
         vault_id, max_liquidatable_debt.to_u64(), collateral_to_liquidator.to_u64(), protocol_cut
     );
 
@@ -6419,8 +7512,9 @@ pub async fn liquidate_vault_debt_already_burned(
     let min_liq = read_state(|s| s.get_min_liquidation_ratio_for(&vault.collateral_type));
     if pre_call_cr >= min_liq {
         log!(INFO,
-            "[liquidate_vault_debt_burned] [LIQ-004] WARN: SP writedown applied to vault #{} \
+            "[liquidate_vault_debt_burned] trace={} [LIQ-004] WARN: SP writedown applied to vault #{} \
              whose pre-call CR ({}) is above min_liq_ratio ({}). Caller={} proof={:?}. Investigate.",
+            trace_tag(caller),
             vault_id, pre_call_cr.to_f64(), min_liq.to_f64(), caller, proof
         );
     }
@@ -6430,6 +7524,7 @@ pub async fn liquidate_vault_debt_already_burned(
 
     // Step 3: Update protocol state (partial liquidation)
     let interest_share = mutate_state(|s| {
+        let vault_before = s.vault_id_to_vaults.get(&vault_id).cloned();
         // Wave-8c LIQ-004: record the proof as consumed atomically with the
         // writedown so a partial failure cannot leave the proof unconsumed
         // (replay risk) or consumed without an effect (orphan risk).
@@ -6503,10 +7598,12 @@ pub async fn liquidate_vault_debt_already_burned(
             );
             if s.check_deficit_readonly_latch() {
                 log!(INFO,
-                    "[LIQ-005] deficit threshold {} crossed by SP writedown vault #{} shortfall {}; auto-latched ReadOnly",
+                    "[LIQ-005] trace={} deficit threshold {} crossed by SP writedown vault #{} shortfall {}; auto-latched ReadOnly",
+                    trace_tag(caller),
                     s.deficit_readonly_threshold_e8s, vault_id, shortfall.to_u64()
                 );
             }
+            crate::event::record_mode_transitions(s);
         }
 
         // AR-B-001/BK-001 (audit 2026-06-09): applied payout, replay-exact.
@@ -6525,6 +7622,7 @@ pub async fn liquidate_vault_debt_already_burned(
             three_usd_reserves_e8s: three_usd_received_e8s,
         };
         crate::storage::record_event(&event);
+        crate::liquidation_caps::note_liquidation(s, &event);
 
         // Track 3USD reserves at runtime (also persisted via event replay)
         if let Some(three_usd_e8s) = three_usd_received_e8s {
@@ -6551,11 +7649,27 @@ pub async fn liquidate_vault_debt_already_burned(
         if s.cleanup_if_drained(vault_id) {
             log!(
                 INFO,
-                "[liquidate_vault_debt_burned] Vault #{} fully liquidated — removed",
+                "[liquidate_vault_debt_burned] trace={} Vault #{} fully liquidated — removed",
+                trace_tag(caller),
                 vault_id
             );
         }
 
+        // The owner's post-mortem; see `liquidation_receipts`.
+        if let Some(before) = &vault_before {
+            crate::liquidation_receipts::issue(
+                s,
+                before,
+                crate::liquidation_receipts::Settlement {
+                    liquidator: Some(caller),
+                    price: collateral_price,
+                    protocol_fee: protocol_cut.min(collateral_applied),
+                    ..Default::default()
+                },
+                ic_cdk::api::time(),
+            );
+        }
+
         interest_share
     });
 
@@ -6590,13 +7704,8 @@ pub async fn liquidate_vault_debt_already_burned(
                 );
             });
         } else {
-            let asset_type = crate::treasury::collateral_to_asset_type(&vault.collateral_type);
-            crate::treasury::send_liquidation_fee_to_treasury(
-                protocol_cut,
-                vault.collateral_type,
-                asset_type,
-            )
-            .await;
+            crate::treasury::send_liquidation_fee_to_treasury(protocol_cut, vault.collateral_type)
+                .await;
         }
     }
 
@@ -6605,14 +7714,16 @@ pub async fn liquidate_vault_debt_already_burned(
         Ok(processed_count) => {
             log!(
                 INFO,
-                "[liquidate_vault_debt_burned] Processed {} transfers immediately",
+                "[liquidate_vault_debt_burned] trace={} Processed {} transfers immediately",
+                trace_tag(caller),
                 processed_count
             );
         }
         Err(e) => {
             log!(
                 INFO,
-                "[liquidate_vault_debt_burned] Immediate processing failed: {}. Retrying via timer",
+                "[liquidate_vault_debt_burned] trace={} Immediate processing failed: {}. Retrying via timer",
+                trace_tag(caller),
                 e
             );
             schedule_transfer_retry(vault_id, 0);
@@ -6623,7 +7734,8 @@ pub async fn liquidate_vault_debt_already_burned(
         ic_cdk::spawn(async move {
             log!(
                 INFO,
-                "[liquidate_vault_debt_burned] Backup timer for vault #{}",
+                "[liquidate_vault_debt_burned] trace={} Backup timer for vault #{}",
+                trace_tag(caller),
                 vault_id
             );
             let _ = crate::process_pending_transfer().await;
@@ -6645,7 +7757,8 @@ pub async fn liquidate_vault_debt_already_burned(
 
     log!(
         INFO,
-        "[liquidate_vault_debt_burned] Completed. Fee: {}, Collateral: {}",
+        "[liquidate_vault_debt_burned] trace={} Completed. Fee: {}, Collateral: {}",
+        trace_tag(caller),
         fee_amount.to_u64(),
         collateral_to_liquidator.to_u64()
     );
@@ -6662,15 +7775,172 @@ pub async fn liquidate_vault_debt_already_burned(
     })
 }
 
-pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolError> {
+/// How `liquidate_vault` splits a vault at `collateral_price`, before the
+/// commit-time re-cap to the vault's live collateral.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiquidationSplit {
+    pub debt_amount: ICUSD,
+    /// Bonus collateral plus the tip.
+    pub collateral_to_liquidator: ICP,
+    pub total_to_seize: ICP,
+    pub protocol_cut: u64,
+    pub excess_collateral: ICP,
+    /// Collateral paid as the liquidation tip, included in
+    /// `collateral_to_liquidator`.
+    pub tip_collateral: u64,
+    pub is_recovery_partial: bool,
+}
+
+/// The split of a `liquidate_vault` call on `vault`. In Recovery mode only
+/// enough debt to restore the target CR is repaid (vault CR between 133%
+/// and 150%); otherwise the whole debt is, and the collateral left after
+/// the bonus goes back to the owner. The protocol takes its share of the
+/// bonus.
+///
+/// The liquidation tip (`liquidation_tip_e8s`, set in icUSD) is paid in
+/// collateral at `collateral_price` and funded from the penalty: first from
+/// the protocol's cut, then from the collateral that would go back to the
+/// owner. It never adds to what is seized for the debt, and it is paid out
+/// in collateral so icUSD supply stays backed by vault debt.
+pub fn plan_liquidation(
+    s: &crate::state::State,
+    vault: &Vault,
+    collateral_price: Decimal,
+    config_decimals: u8,
+    collateral_price_usd: UsdIcp,
+) -> LiquidationSplit {
+    let vault_collateral = ICP::from(vault.collateral_amount);
+    let liq_bonus = s.get_liquidation_bonus_for(&vault.collateral_type);
+    let protocol_share = s.get_liquidation_protocol_share();
+    let (debt_amount, is_recovery_partial) =
+        match s.compute_recovery_repay_cap(vault, collateral_price_usd) {
+            Some(repay_cap) => (repay_cap, true),
+            None => (vault.borrowed_icusd_amount, false),
+        };
+    let collateral_raw =
+        crate::numeric::icusd_to_collateral_amount(debt_amount, collateral_price, config_decimals);
+    let total_to_seize = (ICP::from(collateral_raw) * liq_bonus).min(vault_collateral);
+    // Split: protocol gets a share of the bonus portion (liquidator's profit)
+    let bonus_portion = total_to_seize.to_u64().saturating_sub(collateral_raw);
+    let bonus_cut = (Decimal::from(bonus_portion) * protocol_share.0)
+        .to_u64()
+        .unwrap_or(0);
+    let excess = if is_recovery_partial {
+        0
+    } else {
+        vault_collateral.saturating_sub(total_to_seize).to_u64()
+    };
+
+    let tip = crate::numeric::icusd_to_collateral_amount(
+        ICUSD::new(s.liquidation_tip_e8s),
+        collateral_price,
+        config_decimals,
+    );
+    let tip_from_cut = tip.min(bonus_cut);
+    let tip_from_excess = (tip - tip_from_cut).min(excess);
+    let tip_collateral = tip_from_cut + tip_from_excess;
+    LiquidationSplit {
+        debt_amount,
+        collateral_to_liquidator: ICP::from(total_to_seize.to_u64() - bonus_cut + tip_collateral),
+        total_to_seize,
+        protocol_cut: bonus_cut - tip_from_cut,
+        excess_collateral: ICP::from(excess - tip_from_excess),
+        tip_collateral,
+        is_recovery_partial,
+    }
+}
+
+/// What a `liquidate_vault` call on a vault would pay its caller now.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct LiquidationRewardPreview {
+    pub vault_id: u64,
+    /// icUSD the caller pays.
+    pub debt_to_repay_e8s: u64,
+    /// Collateral the caller receives, tip included.
+    pub collateral_to_liquidator: u64,
+    pub tip_collateral: u64,
+    /// The two amounts above at the current price, in icUSD e8s.
+    pub collateral_value_e8s: u64,
+    pub tip_value_e8s: u64,
+    pub recovery_partial: bool,
+    /// Collateral the collateral's liquidation cap lets one call seize now;
+    /// `None` when it is uncapped.
+    pub seizure_cap: Option<u64>,
+    /// The call would seize more than `seizure_cap` and be refused; a
+    /// partial liquidation gets what fits.
+    pub exceeds_seizure_cap: bool,
+}
+
+pub fn liquidation_reward_preview(
+    state: &crate::state::State,
+    vault_id: u64,
+    now: u64,
+) -> Result<LiquidationRewardPreview, ProtocolError> {
+    let price =
+        crate::auction::liquidatable_price(state, vault_id).map_err(ProtocolError::GenericError)?;
+    let vault = &state.vault_id_to_vaults[&vault_id];
+    let decimals = state
+        .get_collateral_config(&vault.collateral_type)
+        .map(|c| c.decimals)
+        .unwrap_or(8);
+    let split = plan_liquidation(state, vault, price, decimals, UsdIcp::from(price));
+    let seizure_cap = crate::liquidation_caps::seizable(state, &vault.collateral_type, now);
+    let value =
+        |amount: u64| crate::numeric::collateral_usd_value(amount, price, decimals).to_u64();
+    Ok(LiquidationRewardPreview {
+        vault_id,
+        debt_to_repay_e8s: split.debt_amount.to_u64(),
+        collateral_to_liquidator: split.collateral_to_liquidator.to_u64(),
+        tip_collateral: split.tip_collateral,
+        collateral_value_e8s: value(split.collateral_to_liquidator.to_u64()),
+        tip_value_e8s: value(split.tip_collateral),
+        recovery_partial: split.is_recovery_partial,
+        seizure_cap,
+        exceeds_seizure_cap: seizure_cap.is_some_and(|cap| split.total_to_seize.to_u64() > cap),
+    })
+}
+
+pub async fn liquidate_vault(
+    vault_id: u64,
+    min_collateral_out: Option<u64>,
+) -> Result<SuccessWithFee, ProtocolError> {
+    liquidate_vault_paid_with(vault_id, None, min_collateral_out).await
+}
+
+/// `liquidate_vault` paid in ckUSDT or ckUSDC instead of icUSD: 1:1 plus the
+/// `ckstable_repay_fee` surcharge (see `stable_pull`).
+pub async fn liquidate_vault_with_stable(
+    vault_id: u64,
+    token_type: StableTokenType,
+    min_collateral_out: Option<u64>,
+) -> Result<SuccessWithFee, ProtocolError> {
+    liquidate_vault_paid_with(vault_id, Some(token_type), min_collateral_out).await
+}
+
+/// A full liquidation paid in icUSD (`None`), which is burned, or in a
+/// stable token, which joins the reserves. Either way the vault's debt,
+/// the breaker and deficit accounting and the liquidator's stats are in
+/// icUSD.
+async fn liquidate_vault_paid_with(
+    vault_id: u64,
+    token_type: Option<StableTokenType>,
+    min_collateral_out: Option<u64>,
+) -> Result<SuccessWithFee, ProtocolError> {
     let caller = ic_cdk::api::caller();
     let guard_principal = GuardPrincipal::new(caller, &format!("liquidate_vault_{}", vault_id))?;
+    reject_if_reserved_for_pool(vault_id, caller)?;
     reject_if_bot_processing(vault_id)?; // LIQ-101: don't double-seize a bot-claimed vault
     let _vault_liq_guard = VaultLiquidationGuard::new(vault_id)?; // BK-001/002 per-vault lock
     if let Err(e) = reject_active_xrp_sp_absorb_preflight(vault_id, ic_cdk::api::time()) {
         guard_principal.fail();
         return Err(e);
     }
+    if let Some(token_type) = &token_type {
+        if let Err(e) = check_stable_liquidation_payment(token_type).await {
+            guard_principal.fail();
+            return Err(e);
+        }
+    }
 
     // Wave-8b LIQ-002 band gate deactivated 2026-05-18 (see
     // `liquidate_vault_partial` above for rationale).
@@ -6724,90 +7994,94 @@ pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolEr
         };
 
     // Step 2: Calculate liquidation amounts
-    // Check if this is a recovery-mode targeted liquidation (vault CR between 133-150%)
-    let vault_collateral = ICP::from(vault.collateral_amount);
-    let (
+    let LiquidationSplit {
         debt_amount,
         collateral_to_liquidator,
         total_to_seize,
         protocol_cut,
         excess_collateral,
+        tip_collateral,
         is_recovery_partial,
-    ) = read_state(|s| {
-        let liq_bonus = s.get_liquidation_bonus_for(&vault.collateral_type);
-        let protocol_share = s.get_liquidation_protocol_share();
-        if let Some(repay_cap) = s.compute_recovery_repay_cap(&vault, collateral_price_usd) {
-            // Recovery mode: only liquidate enough to restore CR to target
-            let collateral_raw = crate::numeric::icusd_to_collateral_amount(
-                repay_cap,
-                collateral_price,
-                config_decimals,
-            );
-            let total_to_seize = (ICP::from(collateral_raw) * liq_bonus).min(vault_collateral);
-            // Split: protocol gets a share of the bonus portion (liquidator's profit)
-            let bonus_portion = total_to_seize.to_u64().saturating_sub(collateral_raw);
-            let protocol_cut = (rust_decimal::Decimal::from(bonus_portion) * protocol_share.0)
-                .to_u64()
-                .unwrap_or(0);
-            let collateral_to_liquidator = ICP::from(total_to_seize.to_u64() - protocol_cut);
-            (
-                repay_cap,
-                collateral_to_liquidator,
-                total_to_seize,
-                protocol_cut,
-                ICP::new(0),
-                true,
-            )
-        } else {
-            // Normal full liquidation
-            let debt = vault.borrowed_icusd_amount;
-            let collateral_raw =
-                crate::numeric::icusd_to_collateral_amount(debt, collateral_price, config_decimals);
-            let icp_with_bonus = ICP::from(collateral_raw) * liq_bonus;
-            let total_to_seize = icp_with_bonus.min(vault_collateral);
-            // Split: protocol gets a share of the bonus portion (liquidator's profit)
-            let bonus_portion = total_to_seize.to_u64().saturating_sub(collateral_raw);
-            let protocol_cut = (rust_decimal::Decimal::from(bonus_portion) * protocol_share.0)
-                .to_u64()
-                .unwrap_or(0);
-            let collateral_to_liquidator = ICP::from(total_to_seize.to_u64() - protocol_cut);
-            let excess = vault_collateral.saturating_sub(total_to_seize);
-            (
-                debt,
-                collateral_to_liquidator,
-                total_to_seize,
-                protocol_cut,
-                excess,
-                false,
-            )
+    } = match read_state(|s| {
+        let split = plan_liquidation(
+            s,
+            &vault,
+            collateral_price,
+            config_decimals,
+            collateral_price_usd,
+        );
+        // A seizure past the collateral's liquidation cap has to go through
+        // `liquidate_vault_partial` instead.
+        crate::liquidation_caps::check_seizure(
+            s,
+            vault_id,
+            &vault.collateral_type,
+            split.total_to_seize.to_u64(),
+            ic_cdk::api::time(),
+        )
+        .map(|()| split)
+    }) {
+        Ok(split) => split,
+        Err(msg) => {
+            guard_principal.fail();
+            return Err(ProtocolError::GenericError(msg));
         }
-    });
+    };
 
     log!(INFO,
-        "[liquidate_vault] Vault #{}: debt_to_repay={} icUSD, liquidator gets {} ICP (protocol fee: {} ICP), excess={} ICP, recovery_partial={}",
+        "[liquidate_vault] trace={} Vault #{}: debt_to_repay={} icUSD, liquidator gets {} ICP (tip: {} ICP, protocol fee: {} ICP), excess={} ICP, recovery_partial={}",
+        trace_tag(caller),
         vault_id,
         debt_amount.to_u64(),
         collateral_to_liquidator.to_u64(),
+        tip_collateral,
         protocol_cut,
         excess_collateral.to_u64(),
         is_recovery_partial
     );
 
-    // Step 3: Take icUSD from liquidator (this must succeed for liquidation to proceed)
-    let icusd_block_index = match transfer_icusd_from(debt_amount, caller).await {
+    if let Err(e) = check_min_collateral_out(collateral_to_liquidator, min_collateral_out) {
+        guard_principal.fail();
+        return Err(e);
+    }
+
+    // Step 3: Take icUSD (or the stable token plus its surcharge) from the
+    // liquidator (this must succeed for liquidation to proceed)
+    let (stable_base_e6s, stable_fee_e6s) = match &token_type {
+        Some(_) => read_state(|s| stable_pull(s, debt_amount)),
+        None => (0, 0),
+    };
+    let stable_pull_e6s = stable_base_e6s + stable_fee_e6s;
+    let observed_seq = crate::storage::observe_events();
+    let pulled = match &token_type {
+        Some(token_type) => transfer_stable_from(token_type.clone(), stable_pull_e6s, caller)
+            .await
+            .map_err(|e| (e, stable_pull_e6s)),
+        None => transfer_icusd_from(debt_amount, caller)
+            .await
+            .map_err(|e| (e, debt_amount.to_u64())),
+    };
+    let icusd_block_index = match pulled {
         Ok(block_index) => {
             log!(
                 INFO,
-                "[liquidate_vault] Received {} icUSD from liquidator",
-                debt_amount.to_u64()
+                "[liquidate_vault] trace={} Received {} from liquidator",
+                trace_tag(caller),
+                match &token_type {
+                    Some(token_type) => format!(
+                        "{} e6s {:?} (fee: {} e6s)",
+                        stable_pull_e6s, token_type, stable_fee_e6s
+                    ),
+                    None => format!("{} icUSD", debt_amount.to_u64()),
+                }
             );
             block_index
         }
-        Err(transfer_from_error) => {
+        Err((transfer_from_error, amount)) => {
             guard_principal.fail();
             return Err(ProtocolError::TransferFromError(
                 transfer_from_error,
-                debt_amount.to_u64(),
+                amount,
             ));
         }
     };
@@ -6821,6 +8095,7 @@ pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolEr
         if !s.vault_id_to_vaults.contains_key(&vault_id) {
             return None;
         }
+        let vault_before = s.vault_id_to_vaults.get(&vault_id).cloned();
         // AR-B-001/BK-001 (audit 2026-06-09): re-cap every collateral payout
         // to the vault's LIVE collateral at commit time. The split below was
         // computed from the pre-await snapshot; the per-vault op lock makes a
@@ -6838,7 +8113,7 @@ pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolEr
                 .to_u64()
                 .min(live_collateral.saturating_sub(cut_applied)),
         );
-        let excess_pay = ICP::from(
+        let mut excess_pay = ICP::from(
             excess_collateral.to_u64().min(
                 live_collateral
                     .saturating_sub(cut_applied)
@@ -6880,10 +8155,12 @@ pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolEr
             );
             if s.check_deficit_readonly_latch() {
                 log!(INFO,
-                    "[LIQ-005] deficit threshold {} crossed by vault #{} shortfall {}; auto-latched ReadOnly",
+                    "[LIQ-005] trace={} deficit threshold {} crossed by vault #{} shortfall {}; auto-latched ReadOnly",
+                    trace_tag(caller),
                     s.deficit_readonly_threshold_e8s, vault_id, shortfall.to_u64()
                 );
             }
+            crate::event::record_mode_transitions(s);
         }
 
         // Record the liquidation event
@@ -6893,8 +8170,17 @@ pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolEr
             icp_rate: collateral_price_usd,
             liquidator: Some(caller),
             timestamp: Some(ic_cdk::api::time()),
+            collateral_seized: Some(total_to_seize.to_u64().min(live_collateral)),
         };
-        crate::storage::record_event(&event);
+        crate::storage::record_event_after(&event, observed_seq);
+        // `liquidate_vault` above may have removed the vault, so count by
+        // its collateral directly.
+        crate::liquidation_caps::note_seized(
+            s,
+            &vault.collateral_type,
+            total_to_seize.to_u64().min(live_collateral),
+            ic_cdk::api::time(),
+        );
 
         // Create pending transfer for liquidator reward (minus protocol cut)
         let liquidator_nonce = s.next_op_nonce();
@@ -6909,12 +8195,47 @@ pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolEr
             ic_cdk::api::time(),
         );
 
+        // Owner rebate as a top-up: the excess stays in custody and is
+        // credited to the owner's riskiest other vault of this collateral,
+        // so no debt is cleared without icUSD being burned.
+        let mut rebate_credited = 0;
+        let rebate_mode = s
+            .get_collateral_config(&vault.collateral_type)
+            .map(|c| c.liquidation_rebate_mode)
+            .unwrap_or_default();
+        if !is_recovery_partial
+            && excess_pay > ICP::new(0)
+            && rebate_mode == crate::state::LiquidationRebateMode::CollateralTopUp
+        {
+            if let Some(target_vault_id) =
+                s.liquidation_rebate_target(vault.owner, vault.collateral_type, vault_id)
+            {
+                rebate_credited = excess_pay.to_u64();
+                crate::event::record_liquidation_rebate_applied(
+                    s,
+                    vault_id,
+                    target_vault_id,
+                    vault.owner,
+                    rebate_credited,
+                );
+                excess_pay = ICP::new(0);
+                log!(
+                    INFO,
+                    "[liquidate_vault] trace={} Credited {} of the owner's excess to vault #{}",
+                    trace_tag(caller),
+                    rebate_credited,
+                    target_vault_id
+                );
+            }
+        }
+
         // Create pending transfer for excess collateral to vault owner (if any)
         // (only for full liquidations, not recovery partial)
         if !is_recovery_partial && excess_pay > ICP::new(0) {
             log!(
                 INFO,
-                "[liquidate_vault] Scheduling excess collateral return to vault owner"
+                "[liquidate_vault] trace={} Scheduling excess collateral return to vault owner",
+                trace_tag(caller)
             );
             // Native-XRP excess returns to the owner as an XrpClaim; ICRC excess
             // goes through the pending-excess transfer machinery.
@@ -6940,14 +8261,32 @@ pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolEr
                         collateral_type: vault.collateral_type,
                         retry_count: 0,
                         op_nonce: excess_nonce,
+                        trace_id: crate::guard::current_trace(caller),
                     },
                 );
             }
         }
 
+        // The owner's post-mortem; see `liquidation_receipts`.
+        if let Some(before) = &vault_before {
+            crate::liquidation_receipts::issue(
+                s,
+                before,
+                crate::liquidation_receipts::Settlement {
+                    liquidator: Some(caller),
+                    price: collateral_price,
+                    protocol_fee: cut_applied,
+                    residual_returned: excess_pay.to_u64(),
+                    residual_rebated: rebate_credited,
+                },
+                ic_cdk::api::time(),
+            );
+        }
+
         log!(
             INFO,
-            "[liquidate_vault] Protocol state updated, {} pending transfers created",
+            "[liquidate_vault] trace={} Protocol state updated, {} pending transfers created",
+            trace_tag(caller),
             if !is_recovery_partial && excess_pay > ICP::new(0) {
                 2
             } else {
@@ -6958,20 +8297,29 @@ pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolEr
     }) {
         Some(result) => result,
         None => {
+            guard_principal.fail();
+            if let Some(token_type) = &token_type {
+                refund_stable_liquidation(vault_id, token_type, stable_pull_e6s, caller).await;
+                return Err(ProtocolError::GenericError(format!(
+                    "Vault #{} was already liquidated by a concurrent operation; your {} e6s {:?} has been refunded",
+                    vault_id, stable_pull_e6s, token_type
+                )));
+            }
             // ASYNC-002: the vault was liquidated by a concurrent op while our
             // icUSD pull was in flight. Refund the liquidator (mirrors the
             // redeem_reserves durable-refund saga) and return a clean error
             // instead of trapping with the liquidator's icUSD stuck.
-            guard_principal.fail();
             log!(INFO,
-                "[liquidate_vault] Vault #{} already liquidated by a concurrent op; refunding {} icUSD to {}",
+                "[liquidate_vault] trace={} Vault #{} already liquidated by a concurrent op; refunding {} icUSD to {}",
+                trace_tag(caller),
                 vault_id, debt_amount.to_u64(), caller);
             let refund_nonce = mutate_state(|s| s.next_op_nonce());
             match management::transfer_icusd_with_nonce(debt_amount, caller, refund_nonce).await {
                 Ok(refund_block) => {
                     log!(
                         INFO,
-                        "[liquidate_vault] Refunded {} icUSD to {} (block {})",
+                        "[liquidate_vault] trace={} Refunded {} icUSD to {} (block {})",
+                        trace_tag(caller),
                         debt_amount.to_u64(),
                         caller,
                         refund_block
@@ -6979,8 +8327,9 @@ pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolEr
                 }
                 Err(refund_err) => {
                     log!(INFO,
-                        "[liquidate_vault] Vault gone AND inline icUSD refund failed for {}: {:?}. \
+                        "[liquidate_vault] trace={} Vault gone AND inline icUSD refund failed for {}: {:?}. \
                          Enqueueing durable refund (block {}).",
+                        trace_tag(caller),
                         caller, refund_err, icusd_block_index);
                     mutate_state(|s| {
                         s.pending_refunds.insert(
@@ -6993,9 +8342,7 @@ pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolEr
                             },
                         );
                     });
-                    ic_cdk_timers::set_timer(std::time::Duration::from_secs(2), || {
-                        ic_cdk::spawn(crate::process_pending_transfer())
-                    });
+                    crate::scheduler::kick_pending_transfers(2);
                 }
             }
             return Err(ProtocolError::GenericError(format!(
@@ -7005,15 +8352,35 @@ pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolEr
         }
     };
 
-    // Route interest share via N-way split
-    // IC-B-002 (audit 2026-06-09): re-queue any unminted interest share so the
-    // next flush retries it instead of silently dropping treasury revenue.
-    let unminted_interest =
-        crate::treasury::distribute_interest(interest_share, vault.collateral_type).await;
-    if unminted_interest.to_u64() > 0 {
-        mutate_state(|s| {
-            s.restore_pending_interest_for_pool(vault.collateral_type, unminted_interest.to_u64())
-        });
+    match &token_type {
+        // Route interest via N-way split (stablecoin-denominated), and the
+        // surcharge to treasury (mirrors liquidate_vault_partial_with_stable)
+        Some(token_type) => {
+            if interest_share.to_u64() > 0 {
+                crate::treasury::distribute_stablecoin_interest(
+                    interest_share.to_u64(),
+                    vault.collateral_type,
+                    token_type.clone(),
+                )
+                .await;
+            }
+            send_stable_repay_fee_to_treasury(token_type, stable_fee_e6s, caller).await;
+        }
+        // Route interest share via N-way split
+        // IC-B-002 (audit 2026-06-09): re-queue any unminted interest share so the
+        // next flush retries it instead of silently dropping treasury revenue.
+        None => {
+            let unminted_interest =
+                crate::treasury::distribute_interest(interest_share, vault.collateral_type).await;
+            if unminted_interest.to_u64() > 0 {
+                mutate_state(|s| {
+                    s.restore_pending_interest_for_pool(
+                        vault.collateral_type,
+                        unminted_interest.to_u64(),
+                    )
+                });
+            }
+        }
     }
 
     // Send protocol's liquidation fee cut to treasury (fire-and-forget)
@@ -7036,20 +8403,16 @@ pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolEr
                 );
             });
         } else {
-            let asset_type = crate::treasury::collateral_to_asset_type(&vault.collateral_type);
-            crate::treasury::send_liquidation_fee_to_treasury(
-                protocol_cut,
-                vault.collateral_type,
-                asset_type,
-            )
-            .await;
+            crate::treasury::send_liquidation_fee_to_treasury(protocol_cut, vault.collateral_type)
+                .await;
         }
     }
 
     // Step 5: Attempt immediate transfer processing (best effort)
     log!(
         INFO,
-        "[liquidate_vault] Attempting immediate transfer processing..."
+        "[liquidate_vault] trace={} Attempting immediate transfer processing...",
+        trace_tag(caller)
     );
 
     // Try to process transfers immediately
@@ -7057,12 +8420,13 @@ pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolEr
         Ok(processed_count) => {
             log!(
                 INFO,
-                "[liquidate_vault] Successfully processed {} transfers immediately",
+                "[liquidate_vault] trace={} Successfully processed {} transfers immediately",
+                trace_tag(caller),
                 processed_count
             );
         }
         Err(e) => {
-            log!(INFO, "[liquidate_vault] Immediate processing failed: {}. Transfers will be retried via timer", e);
+            log!(INFO, "[liquidate_vault] trace={} Immediate processing failed: {}. Transfers will be retried via timer", trace_tag(caller), e);
 
             // Schedule retry with exponential backoff
             schedule_transfer_retry(vault_id, 0);
@@ -7074,7 +8438,8 @@ pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolEr
         ic_cdk::spawn(async move {
             log!(
                 INFO,
-                "[liquidate_vault] Backup timer processing transfers for vault #{}",
+                "[liquidate_vault] trace={} Backup timer processing transfers for vault #{}",
+                trace_tag(caller),
                 vault_id
             );
             let _ = crate::process_pending_transfer().await;
@@ -7096,21 +8461,65 @@ pub async fn liquidate_vault(vault_id: u64) -> Result<SuccessWithFee, ProtocolEr
         ICUSD::new(0)
     };
 
-    log!(INFO, "[liquidate_vault] Liquidation completed successfully. Block index: {}, Fee: {}, Collateral: {}",
+    log!(INFO, "[liquidate_vault] trace={} Liquidation completed successfully. Block index: {}, Fee: {}, Collateral: {}",
+         trace_tag(caller),
          icusd_block_index, fee_amount.to_u64(), collateral_to_liquidator.to_u64());
 
     Ok(SuccessWithFee {
         block_index: icusd_block_index,
         fee_amount_paid: fee_amount.to_u64(),
         collateral_amount_received: Some(collateral_to_liquidator.to_u64()),
-        debt_liquidated_e8s: None, // SP-101
-        stable_pulled_e6s: None,   // SP-110
+        debt_liquidated_e8s: token_type.as_ref().map(|_| debt_amount.to_u64()), // SP-101
+        stable_pulled_e6s: token_type.as_ref().map(|_| stable_pull_e6s),        // SP-110
         xrp_claim_id,
     })
 }
 
+/// Hand a stable-token liquidation payment back when the vault was gone by
+/// the time it landed. There is no durable stable refund queue, so a failed
+/// refund is logged for manual recovery and stays in reserves.
+async fn refund_stable_liquidation(
+    vault_id: u64,
+    token_type: &StableTokenType,
+    amount_e6s: u64,
+    caller: Principal,
+) {
+    let ledger = read_state(|s| match token_type {
+        StableTokenType::CKUSDT => s.ckusdt_ledger_principal,
+        StableTokenType::CKUSDC => s.ckusdc_ledger_principal,
+    });
+    let result = match ledger {
+        Some(ledger) => management::transfer_collateral(amount_e6s, caller, ledger)
+            .await
+            .map_err(|e| format!("{:?}", e)),
+        None => Err(format!("{:?} ledger not configured", token_type)),
+    };
+    match result {
+        Ok(block) => log!(
+            INFO,
+            "[liquidate_vault] trace={} Vault #{} already liquidated; refunded {} e6s {:?} to {} (block {})",
+            trace_tag(caller),
+            vault_id,
+            amount_e6s,
+            token_type,
+            caller,
+            block
+        ),
+        Err(e) => log!(
+            CRITICAL,
+            "[liquidate_vault] trace={} Vault #{} already liquidated AND the refund of {} e6s {:?} to {} failed: {}. Needs manual recovery.",
+            trace_tag(caller),
+            vault_id,
+            amount_e6s,
+            token_type,
+            caller,
+            e
+        ),
+    }
+}
+
 // Helper function to attempt immediate transfer processing
-async fn try_process_pending_transfers_immediate(vault_id: u64) -> Result<u32, String> {
+pub(crate) async fn try_process_pending_transfers_immediate(vault_id: u64) -> Result<u32, String> {
     let mut processed_count = 0;
 
     // Wave-4 LIQ-001: collect every pending margin/excess entry whose key matches
@@ -7150,7 +8559,8 @@ async fn try_process_pending_transfers_immediate(vault_id: u64) -> Result<u32, S
             );
 
         if transfer.margin <= ledger_fee {
-            log!(INFO, "[immediate_transfer] Skipping {} transfer {} owner {} - margin {} <= fee {}, removing",
+            log!(INFO, "[immediate_transfer] trace={} Skipping {} transfer {} owner {} - margin {} <= fee {}, removing",
+                TraceTag(transfer.trace_id),
                 transfer_type, transfer_vault_id, transfer_owner, transfer.margin.to_u64(), ledger_fee.to_u64());
             mutate_state(|s| {
                 let key = (transfer_vault_id, transfer_owner);
@@ -7171,7 +8581,8 @@ async fn try_process_pending_transfers_immediate(vault_id: u64) -> Result<u32, S
 
         log!(
             INFO,
-            "[immediate_transfer] Processing {} transfer {} of {} collateral to {}",
+            "[immediate_transfer] trace={} Processing {} transfer {} of {} collateral to {}",
+            TraceTag(transfer.trace_id),
             transfer_type,
             transfer_vault_id,
             transfer_amount.to_u64(),
@@ -7189,7 +8600,8 @@ async fn try_process_pending_transfers_immediate(vault_id: u64) -> Result<u32, S
             Ok(block_index) => {
                 log!(
                     INFO,
-                    "[immediate_transfer] Transfer {} owner {} successful, block: {}",
+                    "[immediate_transfer] trace={} Transfer {} owner {} successful, block: {}",
+                    TraceTag(transfer.trace_id),
                     transfer_vault_id,
                     transfer_owner,
                     block_index
@@ -7214,7 +8626,8 @@ async fn try_process_pending_transfers_immediate(vault_id: u64) -> Result<u32, S
             Err(error) => {
                 log!(
                     INFO,
-                    "[immediate_transfer] Transfer {} owner {} failed: {}. Will retry later",
+                    "[immediate_transfer] trace={} Transfer {} owner {} failed: {}. Will retry later",
+                    TraceTag(transfer.trace_id),
                     transfer_vault_id,
                     transfer_owner,
                     error
@@ -7229,7 +8642,7 @@ async fn try_process_pending_transfers_immediate(vault_id: u64) -> Result<u32, S
 }
 
 // Helper function to schedule transfer retries with exponential backoff
-fn schedule_transfer_retry(vault_id: u64, retry_count: u32) {
+pub(crate) fn schedule_transfer_retry(vault_id: u64, retry_count: u32) {
     let max_retries = 5;
     if retry_count >= max_retries {
         log!(
@@ -7329,10 +8742,15 @@ pub async fn partial_repay_to_vault(arg: VaultArg) -> Result<u64, ProtocolError>
         }
     }
 
-    if caller != vault.owner {
-        guard_principal.fail();
-        return Err(ProtocolError::CallerNotOwner);
-    }
+    let session = match read_state(|s| {
+        crate::session_keys::authorize_vault_op(s, caller, &vault, SessionScope::Repay, now)
+    }) {
+        Ok(session) => session,
+        Err(e) => {
+            guard_principal.fail();
+            return Err(e);
+        }
+    };
 
     if amount < read_state(|s| s.min_icusd_amount) {
         guard_principal.fail();
@@ -7361,10 +8779,29 @@ pub async fn partial_repay_to_vault(arg: VaultArg) -> Result<u64, ProtocolError>
         return Err(e);
     }
 
+    if let Some(session_principal) = session {
+        if let Err(e) = mutate_state(|s| {
+            crate::session_keys::reserve_repay_allowance(s, session_principal, amount.to_u64())
+        }) {
+            guard_principal.fail();
+            return Err(e);
+        }
+    }
+
     match transfer_icusd_from(amount, caller).await {
         Ok(block_index) => {
-            let interest_share =
-                mutate_state(|s| record_repayed_to_vault(s, arg.vault_id, amount, block_index));
+            let interest_share = mutate_state(|s| {
+                if let Some(session_principal) = session {
+                    record_session_key_used(
+                        s,
+                        session_principal,
+                        arg.vault_id,
+                        SessionScope::Repay,
+                        amount.to_u64(),
+                    );
+                }
+                record_repayed_to_vault(s, arg.vault_id, amount, block_index, None)
+            });
             // IC-B-002 (audit 2026-06-09): re-queue any unminted interest share so the
             // next flush retries it instead of silently dropping treasury revenue.
             let unminted_interest =
@@ -7381,6 +8818,15 @@ pub async fn partial_repay_to_vault(arg: VaultArg) -> Result<u64, ProtocolError>
             Ok(block_index)
         }
         Err(transfer_from_error) => {
+            if let Some(session_principal) = session {
+                mutate_state(|s| {
+                    crate::session_keys::release_repay_allowance(
+                        s,
+                        session_principal,
+                        amount.to_u64(),
+                    )
+                });
+            }
             guard_principal.fail(); // Mark as failed
             Err(ProtocolError::TransferFromError(
                 transfer_from_error,
@@ -7390,10 +8836,14 @@ pub async fn partial_repay_to_vault(arg: VaultArg) -> Result<u64, ProtocolError>
     }
 }
 
-pub async fn partial_liquidate_vault(arg: VaultArg) -> Result<SuccessWithFee, ProtocolError> {
+pub async fn partial_liquidate_vault(
+    arg: VaultArg,
+    min_collateral_out: Option<u64>,
+) -> Result<SuccessWithFee, ProtocolError> {
     let caller = ic_cdk::api::caller();
     let guard_principal =
         GuardPrincipal::new(caller, &format!("partial_liquidate_vault_{}", arg.vault_id))?;
+    reject_if_reserved_for_pool(arg.vault_id, caller)?;
     reject_if_bot_processing(arg.vault_id)?; // LIQ-101: don't double-seize a bot-claimed vault
     let _vault_liq_guard = VaultLiquidationGuard::new(arg.vault_id)?; // BK-001/002 per-vault lock
     if let Err(e) = reject_active_xrp_sp_absorb_preflight(arg.vault_id, ic_cdk::api::time()) {
@@ -7468,16 +8918,32 @@ pub async fn partial_liquidate_vault(arg: VaultArg) -> Result<SuccessWithFee, Pr
 
     // Cap payment to recovery_target_cr, then round residual up to full debt
     // if it would land in (0, min_vault_debt) (LIQ-003: mirrors the repay-side
-    // invariant in `check_min_vault_debt_after_repay`).
-    let liquidator_payment = read_state(|s| {
+    // invariant in `check_min_vault_debt_after_repay`), then clip it to the
+    // collateral's liquidation cap.
+    let (liquidator_payment, cap_bound) = match read_state(|s| {
         let cap = s.compute_partial_liquidation_cap(&vault, collateral_price_usd);
         let capped = liquidator_payment.min(cap);
         let min_vault_debt = s
             .get_collateral_config(&vault.collateral_type)
             .map(|c| c.min_vault_debt)
             .unwrap_or(ICUSD::new(0));
-        round_up_partial_liq_dust(&vault, capped, min_vault_debt)
-    });
+        let rounded = round_up_partial_liq_dust(&vault, capped, min_vault_debt);
+        let (payment, seizable) = crate::liquidation_caps::cap_repay(
+            s,
+            &vault,
+            rounded,
+            collateral_price,
+            config_decimals,
+            ic_cdk::api::time(),
+        )?;
+        Ok::<_, String>((payment, seizable.map(|seizable| (rounded, seizable))))
+    }) {
+        Ok(result) => result,
+        Err(msg) => {
+            guard_principal.fail();
+            return Err(ProtocolError::GenericError(msg));
+        }
+    };
 
     if liquidator_payment > vault.borrowed_icusd_amount {
         guard_principal.fail();
@@ -7510,7 +8976,8 @@ pub async fn partial_liquidate_vault(arg: VaultArg) -> Result<SuccessWithFee, Pr
     let collateral_to_liquidator = ICP::from(total_to_seize.to_u64() - protocol_cut);
 
     log!(INFO,
-        "[partial_liquidate_vault] Vault #{}: liquidator pays {} icUSD, gets {} ICP (protocol fee: {} ICP, bonus: {})",
+        "[partial_liquidate_vault] trace={} Vault #{}: liquidator pays {} icUSD, gets {} ICP (protocol fee: {} ICP, bonus: {})",
+        trace_tag(caller),
         arg.vault_id,
         liquidator_payment.to_u64(),
         collateral_to_liquidator.to_u64(),
@@ -7518,12 +8985,19 @@ pub async fn partial_liquidate_vault(arg: VaultArg) -> Result<SuccessWithFee, Pr
         liq_bonus.to_f64()
     );
 
+    if let Err(e) = check_min_collateral_out(collateral_to_liquidator, min_collateral_out) {
+        guard_principal.fail();
+        return Err(e);
+    }
+
     // Step 4: Take icUSD from liquidator
+    let observed_seq = crate::storage::observe_events();
     let icusd_block_index = match transfer_icusd_from(liquidator_payment, caller).await {
         Ok(block_index) => {
             log!(
                 INFO,
-                "[partial_liquidate_vault] Received {} icUSD from liquidator",
+                "[partial_liquidate_vault] trace={} Received {} icUSD from liquidator",
+                trace_tag(caller),
                 liquidator_payment.to_u64()
             );
             block_index
@@ -7539,6 +9013,7 @@ pub async fn partial_liquidate_vault(arg: VaultArg) -> Result<SuccessWithFee, Pr
 
     // Step 5: Update protocol state ATOMICALLY
     let (interest_share, xrp_claim_id) = mutate_state(|s| {
+        let vault_before = s.vault_id_to_vaults.get(&arg.vault_id).cloned();
         // Compute proportional interest share before reducing debt
         let interest_share = if let Some(vault) = s.vault_id_to_vaults.get(&arg.vault_id) {
             if vault.accrued_interest.0 > 0 && vault.borrowed_icusd_amount.0 > 0 {
@@ -7600,10 +9075,12 @@ pub async fn partial_liquidate_vault(arg: VaultArg) -> Result<SuccessWithFee, Pr
             );
             if s.check_deficit_readonly_latch() {
                 log!(INFO,
-                    "[LIQ-005] deficit threshold {} crossed by partial_liquidate_vault #{} shortfall {}; auto-latched ReadOnly",
+                    "[LIQ-005] trace={} deficit threshold {} crossed by partial_liquidate_vault #{} shortfall {}; auto-latched ReadOnly",
+                    trace_tag(caller),
                     s.deficit_readonly_threshold_e8s, arg.vault_id, shortfall.to_u64()
                 );
             }
+            crate::event::record_mode_transitions(s);
         }
 
         // Record the partial liquidation event (applied payout, replay-exact)
@@ -7621,7 +9098,17 @@ pub async fn partial_liquidate_vault(arg: VaultArg) -> Result<SuccessWithFee, Pr
             timestamp: Some(ic_cdk::api::time()),
             three_usd_reserves_e8s: None,
         };
-        crate::storage::record_event(&event);
+        crate::storage::record_event_after(&event, observed_seq);
+        crate::liquidation_caps::note_liquidation(s, &event);
+        if let Some((requested, seizable)) = cap_bound {
+            crate::event::record_liquidation_cap_bound(
+                arg.vault_id,
+                vault.collateral_type,
+                requested,
+                liquidator_payment,
+                seizable,
+            );
+        }
 
         // Create pending transfer for liquidator reward (minus protocol cut)
         let nonce = s.next_op_nonce();
@@ -7641,14 +9128,31 @@ pub async fn partial_liquidate_vault(arg: VaultArg) -> Result<SuccessWithFee, Pr
         if s.cleanup_if_drained(arg.vault_id) {
             log!(
                 INFO,
-                "[partial_liquidate_vault] Vault #{} fully liquidated — removed",
+                "[partial_liquidate_vault] trace={} Vault #{} fully liquidated — removed",
+                trace_tag(caller),
                 arg.vault_id
             );
         }
 
+        // The owner's post-mortem; see `liquidation_receipts`.
+        if let Some(before) = &vault_before {
+            crate::liquidation_receipts::issue(
+                s,
+                before,
+                crate::liquidation_receipts::Settlement {
+                    liquidator: Some(caller),
+                    price: collateral_price,
+                    protocol_fee: protocol_cut.min(collateral_applied),
+                    ..Default::default()
+                },
+                ic_cdk::api::time(),
+            );
+        }
+
         log!(
             INFO,
-            "[partial_liquidate_vault] Protocol state updated, pending transfer created"
+            "[partial_liquidate_vault] trace={} Protocol state updated, pending transfer created",
+            trace_tag(caller)
         );
         (interest_share, xrp_claim_id)
     });
@@ -7684,32 +9188,29 @@ pub async fn partial_liquidate_vault(arg: VaultArg) -> Result<SuccessWithFee, Pr
                 );
             });
         } else {
-            let asset_type = crate::treasury::collateral_to_asset_type(&vault.collateral_type);
-            crate::treasury::send_liquidation_fee_to_treasury(
-                protocol_cut,
-                vault.collateral_type,
-                asset_type,
-            )
-            .await;
+            crate::treasury::send_liquidation_fee_to_treasury(protocol_cut, vault.collateral_type)
+                .await;
         }
     }
 
     // Step 6: Attempt immediate transfer processing
     log!(
         INFO,
-        "[partial_liquidate_vault] Attempting immediate transfer processing..."
+        "[partial_liquidate_vault] trace={} Attempting immediate transfer processing...",
+        trace_tag(caller)
     );
 
     match try_process_pending_transfers_immediate(arg.vault_id).await {
         Ok(processed_count) => {
             log!(
                 INFO,
-                "[partial_liquidate_vault] Successfully processed {} transfers immediately",
+                "[partial_liquidate_vault] trace={} Successfully processed {} transfers immediately",
+                trace_tag(caller),
                 processed_count
             );
         }
         Err(e) => {
-            log!(INFO, "[partial_liquidate_vault] Immediate processing failed: {}. Transfers will be retried via timer", e);
+            log!(INFO, "[partial_liquidate_vault] trace={} Immediate processing failed: {}. Transfers will be retried via timer", trace_tag(caller), e);
             schedule_transfer_retry(arg.vault_id, 0);
         }
     }
@@ -7719,7 +9220,8 @@ pub async fn partial_liquidate_vault(arg: VaultArg) -> Result<SuccessWithFee, Pr
         ic_cdk::spawn(async move {
             log!(
                 INFO,
-                "[partial_liquidate_vault] Backup timer processing transfers for vault #{}",
+                "[partial_liquidate_vault] trace={} Backup timer processing transfers for vault #{}",
+                trace_tag(caller),
                 arg.vault_id
             );
             let _ = crate::process_pending_transfer().await;
@@ -7741,7 +9243,8 @@ pub async fn partial_liquidate_vault(arg: VaultArg) -> Result<SuccessWithFee, Pr
         ICUSD::new(0)
     };
 
-    log!(INFO, "[partial_liquidate_vault] Partial liquidation completed successfully. Block index: {}, Fee: {}, Collateral: {}",
+    log!(INFO, "[partial_liquidate_vault] trace={} Partial liquidation completed successfully. Block index: {}, Fee: {}, Collateral: {}",
+         trace_tag(caller),
          icusd_block_index, fee_amount.to_u64(), collateral_to_liquidator.to_u64());
 
     Ok(SuccessWithFee {
@@ -0,0 +1,184 @@
+//! Guardian freeze of a single vault.
+//!
+//! Incident response for one vault (a confirmed compromised owner key, say)
+//! without pausing a whole collateral class. A guardian, or the developer,
+//! freezes the vault for a bounded time: while the freeze is live the vault
+//! cannot withdraw collateral, borrow or close, so nothing can leave it.
+//! Repayments, margin top-ups, liquidation and redemption are unaffected, so
+//! the vault can only get safer and the protocol keeps its usual tools.
+//!
+//! Every freeze carries an expiry of at most `MAX_VAULT_FREEZE_DURATION_NS`;
+//! there is no indefinite freeze. Expiry is checked lazily against the
+//! current time, so a lapsed freeze needs no event to end. Freezing an
+//! already frozen vault replaces the freeze (extending or shortening it).
+//!
+//! Freezes and unfreezes are logged as `VaultFrozen` / `VaultUnfrozen` and
+//! rebuilt by replay. The owner is sent a `VaultNotification` for each on
+//! the live path only, like every other notification.
+
+use crate::compute_collateral_ratio;
+use crate::notifications::{
+    classify_vault, push_notification, VaultNotification, VaultNotificationKind, VaultRiskLevel,
+};
+use crate::numeric::UsdIcp;
+use crate::state::State;
+use crate::ProtocolError;
+use candid::{CandidType, Deserialize, Principal};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// Longest a single freeze may last (7 days).
+pub const MAX_VAULT_FREEZE_DURATION_NS: u64 = 7 * 24 * 3600 * 1_000_000_000;
+
+/// Longest accepted freeze reason, in bytes.
+pub const MAX_FREEZE_REASON_LEN: usize = 256;
+
+/// `VaultNotification::parameter` of freeze and unfreeze entries.
+pub const FREEZE_NOTIFICATION_PARAMETER: &str = "vault_freeze";
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultFreeze {
+    pub vault_id: u64,
+    pub frozen_by: Principal,
+    pub reason: String,
+    pub frozen_at_ns: u64,
+    pub expires_at_ns: u64,
+}
+
+impl VaultFreeze {
+    pub fn is_expired_at(&self, now_ns: u64) -> bool {
+        now_ns >= self.expires_at_ns
+    }
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FreezeVaultArg {
+    pub vault_id: u64,
+    /// Freeze length in seconds; 1..=7 days.
+    pub duration_secs: u64,
+    pub reason: String,
+}
+
+/// Guardians and the developer may freeze and unfreeze vaults.
+pub fn is_freeze_authority(state: &State, caller: Principal) -> bool {
+    caller == state.developer_principal || state.guardian_principals.contains(&caller)
+}
+
+/// Check a freeze request and return its expiry.
+pub fn validate_freeze_arg(
+    state: &State,
+    arg: &FreezeVaultArg,
+    now_ns: u64,
+) -> Result<u64, String> {
+    if !state.vault_id_to_vaults.contains_key(&arg.vault_id) {
+        return Err(format!("Vault #{} not found", arg.vault_id));
+    }
+    if arg.duration_secs == 0 {
+        return Err("A freeze needs a non-zero duration".to_string());
+    }
+    let duration_ns = arg.duration_secs.saturating_mul(1_000_000_000);
+    if duration_ns > MAX_VAULT_FREEZE_DURATION_NS {
+        return Err(format!(
+            "A freeze can last at most {} days",
+            MAX_VAULT_FREEZE_DURATION_NS / (24 * 3600 * 1_000_000_000)
+        ));
+    }
+    if arg.reason.trim().is_empty() {
+        return Err("A freeze needs a reason".to_string());
+    }
+    if arg.reason.len() > MAX_FREEZE_REASON_LEN {
+        return Err(format!(
+            "Freeze reason can be at most {} bytes",
+            MAX_FREEZE_REASON_LEN
+        ));
+    }
+    Ok(now_ns.saturating_add(duration_ns))
+}
+
+/// The freeze on `vault_id`, if one is live at `now_ns`.
+pub fn active_freeze(state: &State, vault_id: u64, now_ns: u64) -> Option<&VaultFreeze> {
+    state
+        .vault_freezes
+        .get(&vault_id)
+        .filter(|f| !f.is_expired_at(now_ns))
+}
+
+/// Returns an error if the vault is frozen at `now_ns`.
+pub fn require_vault_not_frozen(
+    state: &State,
+    vault_id: u64,
+    now_ns: u64,
+) -> Result<(), ProtocolError> {
+    match active_freeze(state, vault_id, now_ns) {
+        Some(freeze) => Err(ProtocolError::GenericError(format!(
+            "Vault #{} is frozen until {} ({}). Repayments and margin top-ups are still allowed.",
+            vault_id, freeze.expires_at_ns, freeze.reason
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Record a freeze and prune lapsed ones. Pruning uses `now_ns`, the time
+/// the freeze was recorded, never the current time.
+pub fn apply_freeze(
+    state: &mut State,
+    vault_id: u64,
+    frozen_by: Principal,
+    reason: String,
+    expires_at_ns: u64,
+    now_ns: u64,
+) {
+    state.vault_freezes.retain(|_, f| !f.is_expired_at(now_ns));
+    state.vault_freezes.insert(
+        vault_id,
+        VaultFreeze {
+            vault_id,
+            frozen_by,
+            reason,
+            frozen_at_ns: now_ns,
+            expires_at_ns,
+        },
+    );
+}
+
+pub fn apply_unfreeze(state: &mut State, vault_id: u64, now_ns: u64) -> Result<(), String> {
+    let live = active_freeze(state, vault_id, now_ns).is_some();
+    state.vault_freezes.remove(&vault_id);
+    if live {
+        Ok(())
+    } else {
+        Err(format!("Vault #{} is not frozen", vault_id))
+    }
+}
+
+/// Tell the vault's owner it was frozen or unfrozen, unless they opted out
+/// of freeze notifications. Both levels carry the vault's current
+/// classification (`Healthy` while its collateral is unpriced), since a
+/// freeze does not move it.
+pub fn notify_owner(state: &mut State, vault_id: u64, kind: VaultNotificationKind, now_ns: u64) {
+    let Some(vault) = state.vault_id_to_vaults.get(&vault_id).cloned() else {
+        return;
+    };
+    let (level, collateral_ratio) = match classify_vault(state, &vault) {
+        Some(level) => (
+            level,
+            compute_collateral_ratio(&vault, UsdIcp::from(Decimal::ZERO), state).to_f64(),
+        ),
+        None => (VaultRiskLevel::Healthy, 0.0),
+    };
+    push_notification(
+        state,
+        vault.owner,
+        VaultNotification {
+            id: 0,
+            vault_id,
+            collateral_type: vault.collateral_type,
+            parameter: FREEZE_NOTIFICATION_PARAMETER.to_string(),
+            previous_level: level,
+            new_level: level,
+            collateral_ratio,
+            created_at_ns: now_ns,
+            kind,
+        },
+    );
+}
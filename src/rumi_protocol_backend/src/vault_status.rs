@@ -0,0 +1,235 @@
+//! Explicit vault lifecycle.
+//!
+//! A vault's state used to be implied by other bookkeeping: presence in
+//! `vault_id_to_vaults`, the `bot_processing` flag, a zeroed collateral
+//! amount while a close payout is in flight. `VaultStatus` names it, every
+//! change is checked against the transition graph below, and vault
+//! operations are gated on it:
+//!
+//! ```text
+//!   Active      -> AtRisk | Liquidating | Settling
+//!   AtRisk      -> Active | Liquidating
+//!   Liquidating -> Active | AtRisk
+//!   Settling    -> Active
+//!   any live    -> Closed (by removal)
+//! ```
+//!
+//! Only non-`Active` statuses of live vaults are stored. `Closed` is entered
+//! by the events that remove a vault (close, withdraw-and-close, full
+//! liquidation) and is derived: a vault id that was issued and is no longer
+//! live is closed.
+//!
+//! Changes made by an operation are recorded as `VaultStatusChanged`. The
+//! `Active`/`AtRisk` flips made by the vault check are not: they follow the
+//! price, are recomputed on every check and would otherwise add an event
+//! per flip for as long as the canister runs. After an upgrade the first
+//! check rebuilds them. A vault leaves `AtRisk` only once its CR clears its
+//! liquidation ratio by `AT_RISK_RECOVERY_BAND_BPS`, so one hovering at the
+//! line does not flip back and forth.
+
+use crate::event::record_vault_status_changed;
+use crate::state::State;
+use crate::vault::{CandidVault, Vault};
+use crate::ProtocolError;
+use std::collections::BTreeSet;
+
+pub use rumi_common::types::VaultStatus;
+
+/// How far above its liquidation ratio an `AtRisk` vault's CR must be
+/// before the vault check returns it to `Active` (2 percentage points).
+pub const AT_RISK_RECOVERY_BAND_BPS: u64 = 200;
+
+/// Operations gated on the vault status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VaultOperation {
+    Borrow,
+    AddMargin,
+    Repay,
+    Withdraw,
+    Close,
+    Liquidate,
+}
+
+/// The transition graph. Same-status "transitions" are no-ops and are not
+/// listed.
+pub fn can_transition(from: VaultStatus, to: VaultStatus) -> bool {
+    use VaultStatus::*;
+    matches!(
+        (from, to),
+        (Active, AtRisk)
+            | (Active, Liquidating)
+            | (Active, Settling)
+            | (AtRisk, Active)
+            | (AtRisk, Liquidating)
+            | (Liquidating, Active)
+            | (Liquidating, AtRisk)
+            | (Settling, Active)
+    )
+}
+
+/// Whether `op` may run on a vault in `status`. Every operation is gated the
+/// same way today; the operation is taken so callers state what they are
+/// about to do and the error can name it.
+pub fn allows(status: VaultStatus, _op: VaultOperation) -> bool {
+    match status {
+        // `AtRisk` restricts nothing: borrows and withdrawals are held to the
+        // ratio checks already, and the status can lag a price recovery by a
+        // check interval.
+        VaultStatus::Active | VaultStatus::AtRisk => true,
+        // The bot holds the seized collateral until it confirms; any change
+        // to the vault would race the write-down.
+        VaultStatus::Liquidating => false,
+        // Collateral is on its way out; new margin or debt would be closed
+        // out with the vault.
+        VaultStatus::Settling => false,
+        VaultStatus::Closed => false,
+    }
+}
+
+pub fn status_of(state: &State, vault_id: u64) -> VaultStatus {
+    if state.vault_id_to_vaults.contains_key(&vault_id) {
+        state
+            .vault_statuses
+            .get(&vault_id)
+            .copied()
+            .unwrap_or_default()
+    } else if vault_id < state.next_available_vault_id {
+        VaultStatus::Closed
+    } else {
+        VaultStatus::Active
+    }
+}
+
+/// Reject `op` on `vault_id` if its status does not allow it. Unknown vaults
+/// pass; the operation itself reports them.
+pub fn require_allows(
+    state: &State,
+    vault_id: u64,
+    op: VaultOperation,
+) -> Result<(), ProtocolError> {
+    if !state.vault_id_to_vaults.contains_key(&vault_id) {
+        return Ok(());
+    }
+    let status = status_of(state, vault_id);
+    if allows(status, op) {
+        Ok(())
+    } else {
+        Err(ProtocolError::GenericError(format!(
+            "Vault #{} is {:?}; {:?} is not allowed until it settles",
+            vault_id, status, op
+        )))
+    }
+}
+
+/// Set the stored status. Active and Closed are not stored: a vault with no
+/// entry is Active.
+pub fn apply_transition(state: &mut State, vault_id: u64, to: VaultStatus) {
+    match to {
+        VaultStatus::Active | VaultStatus::Closed => {
+            state.vault_statuses.remove(&vault_id);
+        }
+        _ => {
+            state.vault_statuses.insert(vault_id, to);
+        }
+    }
+}
+
+/// Validate a move of a live vault to `to`. `Ok(None)` when the vault is
+/// already there, `Ok(Some(from))` for a move the graph allows.
+pub fn check_transition(
+    state: &State,
+    vault_id: u64,
+    to: VaultStatus,
+) -> Result<Option<VaultStatus>, String> {
+    if !state.vault_id_to_vaults.contains_key(&vault_id) {
+        return Err(format!("Vault #{} is not live", vault_id));
+    }
+    let from = status_of(state, vault_id);
+    if from == to {
+        return Ok(None);
+    }
+    if !can_transition(from, to) {
+        return Err(format!(
+            "Vault #{} cannot move from {:?} to {:?}",
+            vault_id, from, to
+        ));
+    }
+    Ok(Some(from))
+}
+
+/// Move a live vault to `to`, recording the change. A no-op when the vault
+/// is already there; an error when the graph does not allow it.
+pub fn transition(
+    state: &mut State,
+    vault_id: u64,
+    to: VaultStatus,
+    now_ns: u64,
+) -> Result<(), String> {
+    if let Some(from) = check_transition(state, vault_id, to)? {
+        record_vault_status_changed(state, vault_id, from, to, now_ns);
+    }
+    Ok(())
+}
+
+/// `transition` for call sites where the move is implied by an operation that
+/// already succeeded (a bot claim, a settled payout): an invalid move is
+/// logged, never propagated.
+pub fn transition_or_log(state: &mut State, vault_id: u64, to: VaultStatus, now_ns: u64) {
+    if let Err(reason) = transition(state, vault_id, to, now_ns) {
+        ic_canister_log::log!(crate::logs::INFO, "[vault_status] {}", reason);
+    }
+}
+
+/// Whether an `AtRisk` vault's CR is back above its liquidation ratio by the
+/// recovery band. False without a price.
+fn clears_recovery_band(state: &State, vault_id: u64) -> bool {
+    let Some(vault) = state.vault_id_to_vaults.get(&vault_id) else {
+        return false;
+    };
+    let Some(cr_bps) = crate::auto_deleverage::vault_cr_bps(state, vault_id) else {
+        return false;
+    };
+    let min_bps = crate::auto_deleverage::ratio_to_bps(
+        state.get_min_liquidation_ratio_for(&vault.collateral_type),
+    );
+    cr_bps >= min_bps.saturating_add(AT_RISK_RECOVERY_BAND_BPS)
+}
+
+/// The moves that bring `AtRisk` in line with a vault check: the unhealthy
+/// vaults that are `Active` become `AtRisk`, and `AtRisk` vaults no longer
+/// unhealthy and clear of the recovery band go back to `Active`. Vaults in
+/// any other status are left alone.
+pub fn at_risk_changes(state: &State, unhealthy: &BTreeSet<u64>) -> Vec<(u64, VaultStatus)> {
+    let recovered = state
+        .vault_statuses
+        .iter()
+        .filter(|(id, status)| {
+            **status == VaultStatus::AtRisk
+                && !unhealthy.contains(id)
+                && clears_recovery_band(state, **id)
+        })
+        .map(|(id, _)| (*id, VaultStatus::Active));
+    let at_risk = unhealthy
+        .iter()
+        .filter(|id| {
+            state.vault_id_to_vaults.contains_key(id)
+                && status_of(state, **id) == VaultStatus::Active
+        })
+        .map(|id| (*id, VaultStatus::AtRisk));
+    recovered.chain(at_risk).collect()
+}
+
+/// Apply `at_risk_changes`. The flips are derived from the price and are
+/// not recorded; see the module docs.
+pub fn sync_at_risk(state: &mut State, unhealthy: &BTreeSet<u64>) {
+    for (vault_id, to) in at_risk_changes(state, unhealthy) {
+        apply_transition(state, vault_id, to);
+    }
+}
+
+/// `CandidVault` with the vault's current status filled in.
+pub fn candid_vault(state: &State, vault: &Vault) -> CandidVault {
+    let mut candid = CandidVault::from(vault.clone());
+    candid.status = status_of(state, vault.vault_id);
+    candid
+}
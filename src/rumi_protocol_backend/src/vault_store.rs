@@ -0,0 +1,398 @@
+//! Stable-memory checkpoint of the vault maps and pending transfer queues.
+//!
+//! `vault_id_to_vaults` and `principal_to_vault_ids` are the largest part of
+//! `State`, and the upgrade snapshot serialized them whole into one heap
+//! buffer on every checkpoint. `sync` mirrors them into two
+//! `StableBTreeMap`s, the vaults by id and an `(owner, vault id)` index,
+//! writing only the vaults that changed since the last checkpoint, and the
+//! snapshot leaves the heap maps out. `post_upgrade` refills them with
+//! `restore_into`. The pending margin, excess and redemption transfer
+//! queues go the same way through `StablePendingTransfers`.
+//!
+//! Scope: this is a checkpoint mirror, not a move of the working set to
+//! stable memory. The heap maps stay complete and every read and write
+//! between checkpoints goes to them; the stable maps are only as fresh as
+//! the last checkpoint. What it buys is a snapshot buffer without the vaults
+//! and queues, and a checkpoint whose cost follows what changed. It does not
+//! reduce the heap they occupy while the canister runs: serving them from
+//! the stable maps behind a bounded cache would, but every path that borrows
+//! a `&mut Vault` or a queue entry from `State` (some 350 of them) would
+//! first have to move to accessors, and that is left to a change of its
+//! own.
+//!
+//! Both sides are read through `VaultStore`. Writes to the stable side go
+//! through `StableVaultStore::put_vault` and `remove_vault`, which keep the
+//! owner index exactly in step with the vaults: one entry per vault, under
+//! its current owner. `check_invariants` verifies that before a restore.
+//!
+//! A snapshot written this way cannot be read by an older wasm, which would
+//! find no vaults and no pending transfers in it: do not downgrade past this
+//! change (see "State Serialization" in `storage`).
+
+use crate::state::{PendingMarginTransfer, State};
+use crate::vault::Vault;
+use candid::{CandidType, Deserialize, Principal};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{Memory, StableBTreeMap, Storable};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Read access to a set of vaults and their owner index.
+pub trait VaultStore {
+    fn vault(&self, vault_id: u64) -> Option<Vault>;
+    /// `owner`'s vault ids, ascending.
+    fn vault_ids_of(&self, owner: &Principal) -> Vec<u64>;
+    fn vault_count(&self) -> u64;
+    /// Every vault id, ascending.
+    fn vault_ids(&self) -> Vec<u64>;
+}
+
+impl VaultStore for State {
+    fn vault(&self, vault_id: u64) -> Option<Vault> {
+        self.vault_id_to_vaults.get(&vault_id).cloned()
+    }
+
+    fn vault_ids_of(&self, owner: &Principal) -> Vec<u64> {
+        self.principal_to_vault_ids
+            .get(owner)
+            .map(|ids| ids.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    fn vault_count(&self) -> u64 {
+        self.vault_id_to_vaults.len() as u64
+    }
+
+    fn vault_ids(&self) -> Vec<u64> {
+        self.vault_id_to_vaults.keys().copied().collect()
+    }
+}
+
+/// A vault as stored: CBOR, like the snapshot it used to live in.
+pub struct StoredVault(pub Vault);
+
+impl Storable for StoredVault {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&self.0, &mut buf).expect("failed to serialize Vault");
+        Cow::Owned(buf)
+    }
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        Self(ciborium::de::from_reader(bytes.as_ref()).expect("failed to deserialize Vault"))
+    }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// Owner index key: an owner's entries sort together, by vault id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct OwnerKey(pub Principal, pub u64);
+
+impl Storable for OwnerKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let owner = self.0.as_slice();
+        let mut buf = Vec::with_capacity(1 + owner.len() + 8);
+        buf.push(owner.len() as u8);
+        buf.extend_from_slice(owner);
+        buf.extend_from_slice(&self.1.to_be_bytes());
+        Cow::Owned(buf)
+    }
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        let len = bytes[0] as usize;
+        let owner = Principal::from_slice(&bytes[1..1 + len]);
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&bytes[1 + len..1 + len + 8]);
+        Self(owner, u64::from_be_bytes(id))
+    }
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1 + 29 + 8,
+        is_fixed_size: false,
+    };
+}
+
+pub struct StableVaultStore<M: Memory> {
+    vaults: StableBTreeMap<u64, StoredVault, M>,
+    owners: StableBTreeMap<OwnerKey, (), M>,
+}
+
+impl<M: Memory> StableVaultStore<M> {
+    pub fn init(vaults: M, owners: M) -> Self {
+        Self {
+            vaults: StableBTreeMap::init(vaults),
+            owners: StableBTreeMap::init(owners),
+        }
+    }
+
+    /// Store `vault`, moving its owner entry if the owner changed. Returns
+    /// whether anything was written: an unchanged vault is left alone.
+    pub fn put_vault(&mut self, vault: &Vault) -> bool {
+        match self.vaults.get(&vault.vault_id) {
+            Some(StoredVault(stored)) if stored == *vault => return false,
+            Some(StoredVault(stored)) if stored.owner != vault.owner => {
+                self.owners.remove(&OwnerKey(stored.owner, stored.vault_id));
+            }
+            _ => {}
+        }
+        self.vaults
+            .insert(vault.vault_id, StoredVault(vault.clone()));
+        self.owners
+            .insert(OwnerKey(vault.owner, vault.vault_id), ());
+        true
+    }
+
+    pub fn remove_vault(&mut self, vault_id: u64) -> Option<Vault> {
+        let StoredVault(vault) = self.vaults.remove(&vault_id)?;
+        self.owners.remove(&OwnerKey(vault.owner, vault_id));
+        Some(vault)
+    }
+
+    pub fn owner_entry_count(&self) -> u64 {
+        self.owners.len()
+    }
+}
+
+impl<M: Memory> VaultStore for StableVaultStore<M> {
+    fn vault(&self, vault_id: u64) -> Option<Vault> {
+        self.vaults.get(&vault_id).map(|StoredVault(v)| v)
+    }
+
+    fn vault_ids_of(&self, owner: &Principal) -> Vec<u64> {
+        self.owners
+            .range(OwnerKey(*owner, 0)..=OwnerKey(*owner, u64::MAX))
+            .map(|(OwnerKey(_, id), ())| id)
+            .collect()
+    }
+
+    fn vault_count(&self) -> u64 {
+        self.vaults.len()
+    }
+
+    fn vault_ids(&self) -> Vec<u64> {
+        // The owner index holds one key per vault and no values to decode.
+        let mut ids: Vec<u64> = self.owners.iter().map(|(OwnerKey(_, id), ())| id).collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+/// Check that the owner index has exactly one entry per stored vault, under
+/// that vault's owner.
+pub fn check_invariants<M: Memory>(store: &StableVaultStore<M>) -> Result<(), String> {
+    for (OwnerKey(owner, vault_id), ()) in store.owners.iter() {
+        match store.vault(vault_id) {
+            None => {
+                return Err(format!(
+                    "owner index lists vault {} of {}, which is not stored",
+                    vault_id, owner
+                ))
+            }
+            Some(vault) if vault.owner != owner => {
+                return Err(format!(
+                    "owner index lists vault {} under {}, but it belongs to {}",
+                    vault_id, owner, vault.owner
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+    // Every entry names a distinct stored vault, so equal counts leave no
+    // vault out.
+    if store.owners.len() != store.vaults.len() {
+        return Err(format!(
+            "owner index has {} entries for {} vaults",
+            store.owners.len(),
+            store.vaults.len()
+        ));
+    }
+    Ok(())
+}
+
+/// A pending transfer as stored, in CBOR like the vaults.
+pub struct StoredTransfer(pub PendingMarginTransfer);
+
+impl Storable for StoredTransfer {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&self.0, &mut buf)
+            .expect("failed to serialize PendingMarginTransfer");
+        Cow::Owned(buf)
+    }
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        Self(
+            ciborium::de::from_reader(bytes.as_ref())
+                .expect("failed to deserialize PendingMarginTransfer"),
+        )
+    }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// The pending transfer queues of `State`: margin and excess transfers by
+/// `(owner, vault id)`, redemption transfers by id.
+pub struct StablePendingTransfers<M: Memory> {
+    margin: StableBTreeMap<OwnerKey, StoredTransfer, M>,
+    excess: StableBTreeMap<OwnerKey, StoredTransfer, M>,
+    redemption: StableBTreeMap<u64, StoredTransfer, M>,
+}
+
+impl<M: Memory> StablePendingTransfers<M> {
+    pub fn init(margin: M, excess: M, redemption: M) -> Self {
+        Self {
+            margin: StableBTreeMap::init(margin),
+            excess: StableBTreeMap::init(excess),
+            redemption: StableBTreeMap::init(redemption),
+        }
+    }
+
+    /// Transfers in all three queues.
+    pub fn len(&self) -> u64 {
+        self.margin.len() + self.excess.len() + self.redemption.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Pending transfers in the heap queues of `state`.
+pub fn pending_transfer_count(state: &State) -> u64 {
+    (state.pending_margin_transfers.len()
+        + state.pending_excess_transfers.len()
+        + state.pending_redemption_transfer.len()) as u64
+}
+
+/// What a `sync` wrote.
+#[derive(CandidType, Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct VaultStoreSync {
+    /// Vaults in the store afterwards.
+    pub vaults: u64,
+    pub written: u64,
+    pub removed: u64,
+    pub unchanged: u64,
+    /// Pending transfers in the store afterwards.
+    pub pending_transfers: u64,
+    pub pending_written: u64,
+    pub pending_removed: u64,
+    /// Instructions the sync took; 0 outside a canister.
+    pub instructions: u64,
+    pub taken_at: u64,
+}
+
+/// Make `store` hold exactly the heap vaults of `state`.
+pub fn sync<M: Memory>(store: &mut StableVaultStore<M>, state: &State) -> VaultStoreSync {
+    let mut result = VaultStoreSync::default();
+    for vault_id in store.vault_ids() {
+        if !state.vault_id_to_vaults.contains_key(&vault_id) {
+            store.remove_vault(vault_id);
+            result.removed += 1;
+        }
+    }
+    for vault in state.vault_id_to_vaults.values() {
+        if store.put_vault(vault) {
+            result.written += 1;
+        } else {
+            result.unchanged += 1;
+        }
+    }
+    result.vaults = store.vault_count();
+    result
+}
+
+/// Make `store` hold exactly the pending transfer queues of `state`, adding
+/// what it wrote to `result`.
+pub fn sync_pending<M: Memory>(
+    store: &mut StablePendingTransfers<M>,
+    state: &State,
+    result: &mut VaultStoreSync,
+) {
+    let by_owner = |queue: &BTreeMap<(u64, Principal), PendingMarginTransfer>| {
+        queue
+            .iter()
+            .map(|(&(vault_id, owner), &transfer)| (OwnerKey(owner, vault_id), transfer))
+            .collect::<BTreeMap<_, _>>()
+    };
+    sync_queue(
+        &mut store.margin,
+        by_owner(&state.pending_margin_transfers),
+        result,
+    );
+    sync_queue(
+        &mut store.excess,
+        by_owner(&state.pending_excess_transfers),
+        result,
+    );
+    sync_queue(
+        &mut store.redemption,
+        state.pending_redemption_transfer.clone(),
+        result,
+    );
+    result.pending_transfers = store.len();
+}
+
+fn sync_queue<K: Storable + Ord + Clone, M: Memory>(
+    stable: &mut StableBTreeMap<K, StoredTransfer, M>,
+    heap: BTreeMap<K, PendingMarginTransfer>,
+    result: &mut VaultStoreSync,
+) {
+    let gone: Vec<K> = stable
+        .iter()
+        .map(|(key, _)| key)
+        .filter(|key| !heap.contains_key(key))
+        .collect();
+    for key in gone {
+        stable.remove(&key);
+        result.pending_removed += 1;
+    }
+    for (key, transfer) in heap {
+        if stable
+            .get(&key)
+            .is_some_and(|StoredTransfer(stored)| stored == transfer)
+        {
+            continue;
+        }
+        stable.insert(key, StoredTransfer(transfer));
+        result.pending_written += 1;
+    }
+}
+
+/// Refill the heap vault maps of `state` from `store`.
+pub fn restore_into<M: Memory>(state: &mut State, store: &StableVaultStore<M>) {
+    state.vault_id_to_vaults.clear();
+    state.principal_to_vault_ids.clear();
+    for (vault_id, StoredVault(vault)) in store.vaults.iter() {
+        state
+            .principal_to_vault_ids
+            .entry(vault.owner)
+            .or_insert_with(BTreeSet::new)
+            .insert(vault_id);
+        state.vault_id_to_vaults.insert(vault_id, vault);
+    }
+}
+
+/// Refill the heap pending transfer queues of `state` from `store`.
+pub fn restore_pending_into<M: Memory>(state: &mut State, store: &StablePendingTransfers<M>) {
+    state.pending_margin_transfers = store
+        .margin
+        .iter()
+        .map(|(OwnerKey(owner, vault_id), StoredTransfer(t))| ((vault_id, owner), t))
+        .collect();
+    state.pending_excess_transfers = store
+        .excess
+        .iter()
+        .map(|(OwnerKey(owner, vault_id), StoredTransfer(t))| ((vault_id, owner), t))
+        .collect();
+    state.pending_redemption_transfer = store
+        .redemption
+        .iter()
+        .map(|(id, StoredTransfer(t))| (id, t))
+        .collect();
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct VaultStoreStatus {
+    pub heap_vaults: u64,
+    pub stable_vaults: u64,
+    pub stable_owner_entries: u64,
+    pub heap_pending_transfers: u64,
+    pub stable_pending_transfers: u64,
+    /// The last checkpoint's sync since this wasm started.
+    pub last_sync: Option<VaultStoreSync>,
+}
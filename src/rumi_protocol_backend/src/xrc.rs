@@ -1,14 +1,15 @@
 use crate::event::Event;
 use crate::logs::{INFO, TRACE_XRC};
 use crate::numeric::UsdIcp;
-use crate::state::{mutate_state, read_state, CollateralStatus, State};
+use crate::state::{mutate_state, read_state, CollateralStatus, ModeTransitionReason, State};
 use crate::Decimal;
 use crate::Mode;
-use candid::Principal;
+use candid::{CandidType, Deserialize, Principal};
 use ic_canister_log::log;
 use ic_xrc_types::GetExchangeRateResult;
 use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
 use rust_decimal_macros::dec;
+use serde::Serialize;
 use std::time::Duration;
 
 /// Wave-14a CDP-14: minimum number of CEX sources that must contribute to
@@ -30,6 +31,13 @@ pub fn xrc_metadata_meets_source_floor(num_sources_used: u32, min_required: u32)
     num_sources_used >= min_required
 }
 
+/// XRC's `metadata.standard_deviation` (scaled by `metadata.decimals`, like
+/// the rate) in USD per whole token. Stored next to each applied price for
+/// `CollateralConfig::borrow_check_price`.
+pub fn xrc_std_dev(standard_deviation: u64, decimals: u32) -> f64 {
+    standard_deviation as f64 / 10f64.powi(decimals as i32)
+}
+
 /// Wave-14a CDP-01: maximum number of consecutive XRC fetch failures the
 /// protocol will tolerate before falling back to ReadOnly. The 300-second
 /// poll cadence and the 10-minute hard staleness gate cover the slow-
@@ -63,7 +71,10 @@ pub fn note_xrc_failure_at(state: &mut State, now_ns: u64) -> Option<Event> {
         return None;
     }
 
-    state.mode = Mode::ReadOnly;
+    if !state.enter_read_only(ModeTransitionReason::OracleCircuitBreaker) {
+        // Shadowed (see `shadow`): the trip is only noted.
+        return None;
+    }
     state.mode_triggered_by_oracle = true;
     Some(Event::OracleCircuitBreaker {
         consecutive_failures: state.consecutive_xrc_failures,
@@ -79,11 +90,437 @@ pub fn note_xrc_success(state: &mut State) {
     state.consecutive_xrc_failures = 0;
 
     if state.mode == Mode::ReadOnly && state.mode_triggered_by_oracle {
-        state.mode = Mode::GeneralAvailability;
+        // If a readiness check still fails (e.g. TCR < 100%), stay ReadOnly
+        // but hand the latch back to the TCR path: the oracle is no longer
+        // the reason the protocol is halted.
+        let _ = state.transition_mode(
+            Mode::ReadOnly,
+            Mode::GeneralAvailability,
+            ModeTransitionReason::OracleRecovered,
+        );
         state.mode_triggered_by_oracle = false;
     }
 }
 
+/// Median of the quotes a `PriceSource::Median` collected, or `None` when
+/// fewer than `min_feeds` usable (finite, positive) quotes came back. With an
+/// even count the two middle quotes are averaged.
+pub fn median_price(quotes: &[f64], min_feeds: u32) -> Option<f64> {
+    let mut usable: Vec<f64> = quotes
+        .iter()
+        .copied()
+        .filter(|q| q.is_finite() && *q > 0.0)
+        .collect();
+    if usable.is_empty() || usable.len() < min_feeds as usize {
+        return None;
+    }
+    usable.sort_by(|a, b| a.total_cmp(b));
+    let mid = usable.len() / 2;
+    if usable.len() % 2 == 0 {
+        Some((usable[mid - 1] + usable[mid]) / 2.0)
+    } else {
+        Some(usable[mid])
+    }
+}
+
+/// Divergence of `xrc_price` from `secondary_price`, in basis points of the
+/// secondary price (rounded to the nearest bp, saturating).
+pub fn price_divergence_bps(xrc_price: f64, secondary_price: f64) -> u64 {
+    ((xrc_price - secondary_price).abs() / secondary_price * 10_000.0).round() as u64
+}
+
+/// Cross-check an XRC sample against the collateral's secondary source
+/// before it is applied. Returns whether the sample may proceed to the
+/// sanity band, and the dispute event (if any) for the caller to persist.
+///
+/// - No secondary source configured: always proceeds.
+/// - Divergence beyond `max_divergence_bps`: the sample is dropped and the
+///   collateral marked `price_disputed`; `PriceDisputed` is emitted only on
+///   entering the dispute, not on every tick that keeps it open.
+/// - Within the band: proceeds, and an open dispute clears itself.
+/// - Secondary unavailable (`None`): proceeds unless a dispute is open. An
+///   outage of the secondary alone must not stall pricing, but it cannot
+///   end a dispute either.
+pub fn cross_check_price_at(
+    state: &mut State,
+    collateral_type: &Principal,
+    xrc_price: f64,
+    secondary_price: Option<f64>,
+    now_ns: u64,
+) -> (bool, Option<Event>) {
+    let Some(config) = state.collateral_configs.get_mut(collateral_type) else {
+        return (true, None);
+    };
+    let Some(source) = &config.secondary_price_source else {
+        return (true, None);
+    };
+    let Some(secondary_price) = secondary_price.filter(|p| p.is_finite() && *p > 0.0) else {
+        return (!config.price_disputed, None);
+    };
+
+    let divergence_bps = price_divergence_bps(xrc_price, secondary_price);
+    if divergence_bps > source.max_divergence_bps {
+        if config.price_disputed {
+            return (false, None);
+        }
+        config.price_disputed = true;
+        return (
+            false,
+            Some(Event::PriceDisputed {
+                collateral_type: *collateral_type,
+                xrc_price: xrc_price.to_string(),
+                secondary_price: secondary_price.to_string(),
+                divergence_bps,
+                timestamp: now_ns,
+            }),
+        );
+    }
+
+    if config.price_disputed {
+        config.price_disputed = false;
+        return (
+            true,
+            Some(Event::PriceDisputeCleared {
+                collateral_type: *collateral_type,
+                resolved_by: None,
+                timestamp: now_ns,
+            }),
+        );
+    }
+    (true, None)
+}
+
+/// Fetch the secondary price (when a source is configured), run
+/// `cross_check_price_at`, and persist any resulting event. Returns false
+/// when the XRC sample must not be applied.
+pub async fn cross_check_xrc_price(collateral_type: Principal, xrc_price: f64) -> bool {
+    let source = read_state(|s| {
+        s.get_collateral_config(&collateral_type)
+            .and_then(|c| c.secondary_price_source.clone())
+    });
+    let Some(source) = source else {
+        return true;
+    };
+    let secondary_price = crate::management::fetch_secondary_price(collateral_type, &source).await;
+    let (accepted, event) = mutate_state(|s| {
+        cross_check_price_at(
+            s,
+            &collateral_type,
+            xrc_price,
+            secondary_price,
+            ic_cdk::api::time(),
+        )
+    });
+    if let Some(event) = event {
+        crate::storage::record_event(&event);
+    }
+    if !accepted {
+        log!(
+            TRACE_XRC,
+            "[cross_check_xrc_price] holding {} price: XRC {} vs secondary {:?} (disputed)",
+            collateral_type,
+            xrc_price,
+            secondary_price
+        );
+    }
+    accepted
+}
+
+/// Longest window the price-deviation breaker may look back over (1 day).
+pub const MAX_PRICE_DEVIATION_WINDOW_NS: u64 = 24 * 3600 * 1_000_000_000;
+
+/// Price-deviation breaker. A fresh sample that moves more than
+/// `max_deviation_bps` away from the collateral's last accepted price, when
+/// that price is at most `window_ns` older than the sample, is rejected and
+/// the old price kept. A real move therefore waits out the window; a single
+/// bad response never reaches collateral ratios. With `enter_recovery`, a
+/// rejection also moves the protocol to Recovery and holds it there until
+/// the collateral's next accepted price.
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceDeviationBreaker {
+    pub max_deviation_bps: u64,
+    pub window_ns: u64,
+    pub enter_recovery: bool,
+}
+
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct PriceDeviationBreakerStatus {
+    pub config: Option<PriceDeviationBreaker>,
+    /// Collaterals whose rejected price is holding the protocol in Recovery.
+    pub holding_recovery: Vec<Principal>,
+}
+
+pub fn validate_price_deviation_breaker(config: &PriceDeviationBreaker) -> Result<(), String> {
+    if config.max_deviation_bps == 0 || config.max_deviation_bps > 10_000 {
+        return Err("max_deviation_bps must be between 1 and 10000".to_string());
+    }
+    if config.window_ns == 0 || config.window_ns > MAX_PRICE_DEVIATION_WINDOW_NS {
+        return Err(format!(
+            "window_ns must be between 1 and {}",
+            MAX_PRICE_DEVIATION_WINDOW_NS
+        ));
+    }
+    Ok(())
+}
+
+/// Install or remove the breaker. Recovery holds are dropped once the
+/// breaker no longer asks for them.
+pub fn apply_price_deviation_breaker(state: &mut State, config: Option<PriceDeviationBreaker>) {
+    state.price_deviation_breaker = config;
+    if !config.map_or(false, |c| c.enter_recovery) {
+        state.price_deviation_holds.clear();
+    }
+}
+
+/// Run the price-deviation breaker on a sample taken at `sample_ts_ns`.
+/// Returns whether the sample may proceed to the sanity band, and the
+/// `PriceRejected` event for the caller to count (`note_price_rejection`)
+/// when it may not. A rejection under `enter_recovery` also buffers the
+/// Recovery transition.
+///
+/// - No breaker, no previous price, or a previous price older than the
+///   window: proceeds.
+/// - Within `max_deviation_bps` of the previous price: proceeds.
+/// - Circuit breakers in shadow mode: proceeds, and the rejection is
+///   noted (see `shadow`).
+pub fn check_price_deviation_at(
+    state: &mut State,
+    collateral_type: &Principal,
+    price: f64,
+    sample_ts_ns: u64,
+    now_ns: u64,
+) -> (bool, Option<Event>) {
+    let Some(breaker) = state.price_deviation_breaker else {
+        return (true, None);
+    };
+    let Some((last_price, last_ts)) = state
+        .get_collateral_config(collateral_type)
+        .and_then(|c| c.last_price.zip(c.last_price_timestamp))
+        .filter(|(p, _)| p.is_finite() && *p > 0.0)
+    else {
+        return (true, None);
+    };
+    if sample_ts_ns.saturating_sub(last_ts) > breaker.window_ns {
+        return (true, None);
+    }
+    let deviation_bps = price_divergence_bps(price, last_price);
+    if deviation_bps <= breaker.max_deviation_bps {
+        return (true, None);
+    }
+
+    if crate::shadow::is_shadowed(state, crate::shadow::ShadowComponent::CircuitBreakers) {
+        crate::shadow::note(
+            state,
+            crate::shadow::ShadowAction::RejectPrice {
+                collateral_type: *collateral_type,
+                price: price.to_string(),
+                last_price: last_price.to_string(),
+                deviation_bps,
+            },
+        );
+        crate::shadow::flush(state, now_ns);
+        return (true, None);
+    }
+    if breaker.enter_recovery {
+        state.price_deviation_holds.insert(*collateral_type);
+        if state.mode == Mode::GeneralAvailability {
+            let _ = state.transition_mode(
+                Mode::GeneralAvailability,
+                Mode::Recovery,
+                ModeTransitionReason::PriceDeviation,
+            );
+        }
+    }
+    (
+        false,
+        Some(Event::PriceRejected {
+            collateral_type: *collateral_type,
+            rejected_price: price.to_string(),
+            last_price: last_price.to_string(),
+            deviation_bps,
+            recovery: breaker.enter_recovery,
+            timestamp: now_ns,
+        }),
+    )
+}
+
+/// Samples of one collateral rejected since its last accepted price. Only
+/// the first rejection of each kind is recorded as an event; the rest are
+/// counted here, and the counts are recorded as `PriceRejectionsCleared`
+/// when the collateral's next price is accepted.
+#[derive(CandidType, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceRejections {
+    /// Samples outside the collateral's price bounds.
+    pub out_of_bounds: u64,
+    /// Samples the price-deviation breaker rejected.
+    pub deviation: u64,
+    /// Time of the first rejection since the last accepted price.
+    pub since_ns: u64,
+}
+
+/// Count a rejection event against its collateral. Returns the event when
+/// it is the first of its kind since the collateral's last accepted price,
+/// and so is to be recorded; `None` for a repeat. Other events pass
+/// through untouched.
+pub fn note_price_rejection(state: &mut State, event: Event) -> Option<Event> {
+    let (collateral_type, timestamp) = match &event {
+        Event::PriceOutOfBounds {
+            collateral_type,
+            timestamp,
+            ..
+        }
+        | Event::PriceRejected {
+            collateral_type,
+            timestamp,
+            ..
+        } => (*collateral_type, *timestamp),
+        _ => return Some(event),
+    };
+    let rejections = state
+        .price_rejections
+        .entry(collateral_type)
+        .or_insert(PriceRejections {
+            since_ns: timestamp,
+            ..Default::default()
+        });
+    let count = match event {
+        Event::PriceOutOfBounds { .. } => &mut rejections.out_of_bounds,
+        _ => &mut rejections.deviation,
+    };
+    *count += 1;
+    (*count == 1).then_some(event)
+}
+
+/// Run `check_price_deviation_at` on a fresh sample and persist any mode
+/// change and, for the first rejection since the last accepted price, its
+/// event. Returns false when the sample must not be applied.
+pub fn check_price_deviation(collateral_type: Principal, price: f64, sample_ts_ns: u64) -> bool {
+    let accepted = mutate_state(|s| {
+        let (accepted, event) = check_price_deviation_at(
+            s,
+            &collateral_type,
+            price,
+            sample_ts_ns,
+            ic_cdk::api::time(),
+        );
+        if let Some(event) = event {
+            if let Some(event) = note_price_rejection(s, event) {
+                crate::storage::record_event(&event);
+            }
+            crate::event::record_mode_transitions(s);
+        }
+        accepted
+    });
+    if !accepted {
+        log!(
+            TRACE_XRC,
+            "[check_price_deviation] rejecting {} price {}: too far from the last accepted price",
+            collateral_type,
+            price
+        );
+    }
+    accepted
+}
+
+/// One US dollar in the units `PriceBounds` uses.
+const USD_E8S: u64 = 100_000_000;
+
+/// Bounds for a collateral without its own: $0.00000001 to $10,000,000.
+/// Wide enough for any listed asset; they only catch garbage.
+pub const DEFAULT_PRICE_BOUNDS: PriceBounds = PriceBounds {
+    min_price_e8s: 1,
+    max_price_e8s: 10_000_000 * USD_E8S,
+};
+
+/// Absolute bounds on a collateral's USD price, in USD e8s per whole token
+/// ($0.10 is `10_000_000`). A sample outside `[min, max]` is rejected
+/// before the deviation breaker and the sanity band see it, the old price
+/// is kept, and the rejection is counted (see `PriceRejections`).
+#[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceBounds {
+    pub min_price_e8s: u64,
+    pub max_price_e8s: u64,
+}
+
+pub fn validate_price_bounds(bounds: &PriceBounds) -> Result<(), String> {
+    if bounds.min_price_e8s == 0 {
+        return Err("min_price_e8s must be positive".to_string());
+    }
+    if bounds.min_price_e8s >= bounds.max_price_e8s {
+        return Err("min_price_e8s must be below max_price_e8s".to_string());
+    }
+    Ok(())
+}
+
+/// Set (`Some`) or reset to the defaults (`None`) `collateral_type`'s
+/// bounds. Prices already accepted are not rechecked.
+pub fn apply_price_bounds(
+    state: &mut State,
+    collateral_type: Principal,
+    bounds: Option<PriceBounds>,
+) {
+    match bounds {
+        Some(bounds) => {
+            state.price_bounds.insert(collateral_type, bounds);
+        }
+        None => {
+            state.price_bounds.remove(&collateral_type);
+        }
+    }
+}
+
+/// Bounds in force for `collateral_type`.
+pub fn price_bounds(state: &State, collateral_type: &Principal) -> PriceBounds {
+    state
+        .price_bounds
+        .get(collateral_type)
+        .copied()
+        .unwrap_or(DEFAULT_PRICE_BOUNDS)
+}
+
+/// The `PriceOutOfBounds` event for a sample outside `collateral_type`'s
+/// bounds, or `None` when the sample may proceed.
+pub fn check_price_bounds_at(
+    state: &State,
+    collateral_type: &Principal,
+    price: f64,
+    now_ns: u64,
+) -> Option<Event> {
+    let bounds = price_bounds(state, collateral_type);
+    let price_e8s = price * USD_E8S as f64;
+    if price_e8s >= bounds.min_price_e8s as f64 && price_e8s <= bounds.max_price_e8s as f64 {
+        return None;
+    }
+    Some(Event::PriceOutOfBounds {
+        collateral_type: *collateral_type,
+        rejected_price: price.to_string(),
+        min_price_e8s: bounds.min_price_e8s,
+        max_price_e8s: bounds.max_price_e8s,
+        timestamp: now_ns,
+    })
+}
+
+/// Run `check_price_bounds_at` on a fresh sample and persist its event if
+/// it is the first rejection since the last accepted price. Returns false
+/// when the sample must not be applied.
+pub fn check_price_bounds(collateral_type: Principal, price: f64) -> bool {
+    let event =
+        read_state(|s| check_price_bounds_at(s, &collateral_type, price, ic_cdk::api::time()));
+    let Some(event) = event else {
+        return true;
+    };
+    if let Some(event) = mutate_state(|s| note_price_rejection(s, event)) {
+        crate::storage::record_event(&event);
+    }
+    log!(
+        INFO,
+        "[check_price_bounds] rejecting {} price {}: outside its price bounds",
+        collateral_type,
+        price
+    );
+    false
+}
+
 /// Wave-9d DOS-011: classifies whether a collateral type's periodic
 /// background XRC price refresh is still useful given its lifecycle
 /// status. Returns true for `Active`, `Paused`, and `Sunset`: all three can
@@ -132,19 +569,6 @@ pub fn spawn_collateral_price_fetch_if_needed(ledger_id: Principal) {
     }
 }
 
-thread_local! {
-    /// 2026-07-03 cycle-burn optimization: the `TimerId` of the background
-    /// price-refresh timer for each collateral, so
-    /// `set_collateral_price_fetch_interval_secs` can clear + re-register a
-    /// single collateral's timer in place (mirroring the ICP / settlement /
-    /// observer timers in `main`). NOT persisted: timers never survive an
-    /// upgrade, and `setup_timers` re-registers each collateral's timer from
-    /// `State::collateral_price_fetch_interval_secs` on every start.
-    static COLLATERAL_PRICE_TIMER_IDS: std::cell::RefCell<
-        std::collections::BTreeMap<Principal, ic_cdk_timers::TimerId>,
-    > = std::cell::RefCell::new(std::collections::BTreeMap::new());
-}
-
 /// 2026-07-03: fallback background price-fetch cadence (seconds) for a
 /// collateral with no entry in `State::collateral_price_fetch_interval_secs`.
 /// Equal to the historical hardcoded 300s, so an unconfigured collateral
@@ -166,6 +590,13 @@ pub fn collateral_price_fetch_secs(state: &State, ledger_id: &Principal) -> u64
     }
 }
 
+/// Whether `ledger_id` has a background price timer registered.
+pub fn collateral_price_timer_registered(ledger_id: &Principal) -> bool {
+    crate::scheduler::is_registered(crate::scheduler::ScheduledTask::CollateralPriceFetch(
+        *ledger_id,
+    ))
+}
+
 /// Wave-9d DOS-011: registers the recurring per-collateral XRC price
 /// timer with the status-check gate baked in. Used by both
 /// `setup_timers()` (re-registering after upgrade) and
@@ -173,30 +604,25 @@ pub fn collateral_price_fetch_secs(state: &State, ledger_id: &Principal) -> u64
 ///
 /// 2026-07-03: the interval is now per-collateral (resolved via
 /// `collateral_price_fetch_secs`, default 300s) rather than a fixed
-/// `FETCHING_ICP_RATE_INTERVAL`, and the timer id is tracked in
-/// `COLLATERAL_PRICE_TIMER_IDS` so a re-register (setter, or repeated
-/// `setup_timers`) clears the prior timer first and never leaks a duplicate
-/// interval timer for the same collateral.
+/// `FETCHING_ICP_RATE_INTERVAL`. The timer is registered with the
+/// `scheduler` as the collateral's `CollateralPriceFetch` task, so a
+/// re-register (setter, or repeated `setup_timers`) replaces the prior timer
+/// and never leaks a duplicate interval timer for the same collateral.
 ///
 /// The timer keeps firing on its cadence regardless of status — that's free.
-/// The gate runs synchronously INSIDE the closure (before any `ic_cdk::spawn`):
-/// if the collateral is wound down, we early-return before allocating an async
-/// task and before the (~1B cycle) XRC call.
+/// The gate runs synchronously INSIDE the job (before its own
+/// `ic_cdk::spawn`): if the collateral is wound down, we early-return before
+/// the (~1B cycle) XRC call.
 pub fn register_collateral_price_timer(ledger_id: Principal) {
-    // Clear any prior timer for this collateral so a re-register never leaks a
-    // second interval timer (which would double this collateral's fetch burn).
-    COLLATERAL_PRICE_TIMER_IDS.with(|cell| {
-        if let Some(old) = cell.borrow_mut().remove(&ledger_id) {
-            ic_cdk_timers::clear_timer(old);
-        }
-    });
     let secs = read_state(|s| collateral_price_fetch_secs(s, &ledger_id));
-    let new_id = ic_cdk_timers::set_timer_interval(Duration::from_secs(secs), move || {
-        spawn_collateral_price_fetch_if_needed(ledger_id);
-    });
-    COLLATERAL_PRICE_TIMER_IDS.with(|cell| {
-        cell.borrow_mut().insert(ledger_id, new_id);
-    });
+    crate::scheduler::set_interval(
+        crate::scheduler::ScheduledTask::CollateralPriceFetch(ledger_id),
+        secs,
+        move || async move {
+            spawn_collateral_price_fetch_if_needed(ledger_id);
+            Ok(())
+        },
+    );
 }
 
 /// How often to passively fetch ICP price from XRC (background polling).
@@ -217,6 +643,18 @@ pub const FETCHING_ICP_RATE_INTERVAL: Duration = Duration::from_secs(300);
 /// and lets bursts of activity within the same fetch window hit the cache.
 pub const PRICE_FRESHNESS_THRESHOLD_NANOS: u64 = 60 * 1_000_000_000;
 
+/// `fetch_icp_rate` as a scheduled job: an error when the fetch added to
+/// the consecutive-failure counter.
+pub async fn scheduled_icp_rate_fetch() -> Result<(), String> {
+    let failures_before = read_state(|s| s.consecutive_xrc_failures);
+    fetch_icp_rate().await;
+    let failures = read_state(|s| s.consecutive_xrc_failures);
+    if failures > failures_before {
+        return Err(format!("ICP rate fetch failed ({} in a row)", failures));
+    }
+    Ok(())
+}
+
 pub async fn fetch_icp_rate() {
     let _guard = match crate::guard::FetchXrcGuard::new() {
         Some(guard) => guard,
@@ -295,8 +733,12 @@ pub async fn fetch_icp_rate() {
                     } else {
                         let icp_ct = read_state(|s| s.icp_collateral_type());
                         let rate_f64 = rate.to_f64().unwrap_or(0.0);
-                        let accepted =
-                            mutate_state(|s| s.check_price_sanity_band(&icp_ct, rate_f64));
+                        // A disputed sample is dropped before the sanity band
+                        // and, like a band rejection, counts toward CDP-01.
+                        let accepted = cross_check_xrc_price(icp_ct, rate_f64).await
+                            && check_price_bounds(icp_ct, rate_f64)
+                            && check_price_deviation(icp_ct, rate_f64, ts_nanos)
+                            && mutate_state(|s| s.check_price_sanity_band(&icp_ct, rate_f64));
                         if !accepted {
                             log!(
                             TRACE_XRC,
@@ -310,8 +752,9 @@ pub async fn fetch_icp_rate() {
                                 exchange_rate_result.timestamp
                             );
                                 mutate_state(|s| {
-                                    s.mode = Mode::ReadOnly;
+                                    s.enter_read_only(ModeTransitionReason::PriceFloor);
                                     s.mode_triggered_by_oracle = false;
+                                    crate::event::record_mode_transitions(s);
                                 });
                             }
                             log!(
@@ -319,10 +762,15 @@ pub async fn fetch_icp_rate() {
                                 "[FetchPrice] fetched new ICP rate: {rate} with timestamp: {}",
                                 exchange_rate_result.timestamp
                             );
+                            let std_dev = xrc_std_dev(
+                                exchange_rate_result.metadata.standard_deviation,
+                                exchange_rate_result.metadata.decimals,
+                            );
                             mutate_state(|s| {
                                 s.set_icp_rate(UsdIcp::from(rate), Some(ts_nanos));
                                 let icp_ct = s.icp_collateral_type();
-                                crate::event::record_price_update(icp_ct, rate, ts_nanos);
+                                s.set_price_std_dev(&icp_ct, Some(std_dev));
+                                crate::event::record_price_update(s, icp_ct, rate, ts_nanos);
                             });
                             xrc_call_succeeded = true;
                         }
@@ -359,8 +807,10 @@ pub async fn fetch_icp_rate() {
         crate::storage::record_event(&ev);
     }
     if let Some(last_icp_rate) = read_state(|s| s.last_icp_rate) {
-        mutate_state(|s| s.update_total_collateral_ratio_and_mode(last_icp_rate));
+        let now = ic_cdk::api::time();
+        mutate_state(|s| s.update_total_collateral_ratio_and_mode(last_icp_rate, now));
     }
+    mutate_state(crate::event::record_mode_transitions);
     // Wave-14b CDP-12: the post-fetch interest / treasury / vault-check work
     // moved out of this function and into separate, independently scheduled
     // timers. See `interest_and_treasury_tick` (Timer B) and
@@ -394,6 +844,7 @@ pub async fn interest_and_treasury_tick() {
     // Flush accumulated interest to pools/treasury when threshold is reached.
     crate::treasury::flush_pending_interest().await;
     crate::treasury::flush_pending_stability_pool_interest_notifications().await;
+    crate::treasury::flush_pending_treasury_deposits().await;
     crate::treasury::flush_pending_amm1_donations().await;
 
     // Phase 1b foreign-chain-only supply-invariant self-check. Runs on every
@@ -428,8 +879,9 @@ pub async fn interest_and_treasury_tick() {
         mutate_state(|s| {
             s.multi_chain.invariant_halted = true;
             if matches!(s.mode, Mode::GeneralAvailability) {
-                s.mode = Mode::ReadOnly;
+                s.enter_read_only(ModeTransitionReason::SupplyInvariantHalt);
                 s.mode_triggered_by_oracle = false;
+                crate::event::record_mode_transitions(s);
             }
         });
         crate::storage::record_event(&Event::SupplyInvariantSelfCheckFailed {
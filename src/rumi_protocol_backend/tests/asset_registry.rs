@@ -0,0 +1,148 @@
+//! The supported asset registry: every ledger the protocol works with is
+//! listed once with its roles, native XRP collateral is left out, and
+//! `icrc1_metadata` responses are parsed into the cached metadata.
+//!
+//! The registry under test lists icUSD, the ICP collateral, ckUSDT, ckUSDC
+//! and the 3pool.
+
+mod common;
+
+use candid::{Nat, Principal};
+use icrc_ledger_types::icrc::generic_metadata_value::MetadataValue;
+
+use rumi_protocol_backend::asset_registry::{
+    parse_metadata, supported_assets, supported_ledgers, AssetMetadata, AssetRole,
+};
+use rumi_protocol_backend::state::{CustodyKind, State};
+
+use common::{icp, init_arg_with};
+
+fn icusd() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn ckusdt() -> Principal {
+    Principal::from_slice(&[20])
+}
+
+fn ckusdc() -> Principal {
+    Principal::from_slice(&[21])
+}
+
+fn three_pool() -> Principal {
+    Principal::from_slice(&[30])
+}
+
+fn fixture() -> State {
+    let mut state = init_arg_with()
+        .icusd_ledger(icusd())
+        .ckusdt_ledger(ckusdt())
+        .ckusdc_ledger(ckusdc())
+        .state();
+    state.three_pool_canister = Some(three_pool());
+    state
+}
+
+fn metadata_entries(logo: bool) -> Vec<(String, MetadataValue)> {
+    let mut entries = vec![
+        (
+            "icrc1:name".to_string(),
+            MetadataValue::Text("Internet Computer".to_string()),
+        ),
+        (
+            "icrc1:symbol".to_string(),
+            MetadataValue::Text("ICP".to_string()),
+        ),
+        (
+            "icrc1:decimals".to_string(),
+            MetadataValue::Nat(Nat::from(8u64)),
+        ),
+        (
+            "icrc1:fee".to_string(),
+            MetadataValue::Nat(Nat::from(10_000u64)),
+        ),
+        (
+            "icrc1:max_memo_length".to_string(),
+            MetadataValue::Nat(Nat::from(32u64)),
+        ),
+    ];
+    if logo {
+        entries.push((
+            "icrc1:logo".to_string(),
+            MetadataValue::Text("data:image/svg+xml;base64,AA==".to_string()),
+        ));
+    }
+    entries
+}
+
+#[test]
+fn every_ledger_is_listed_once_with_its_roles() {
+    let ledgers = supported_ledgers(&fixture());
+    assert_eq!(ledgers.len(), 5);
+    assert_eq!(ledgers[&icusd()], vec![AssetRole::Stablecoin]);
+    assert_eq!(ledgers[&icp()], vec![AssetRole::Collateral]);
+    assert_eq!(ledgers[&ckusdt()], vec![AssetRole::RepaymentStable]);
+    assert_eq!(ledgers[&ckusdc()], vec![AssetRole::RepaymentStable]);
+    assert_eq!(ledgers[&three_pool()], vec![AssetRole::ThreeUsd]);
+
+    // A stable that is also collateral is listed once with both roles.
+    let mut state = fixture();
+    let mut config = state.collateral_configs[&icp()].clone();
+    config.ledger_canister_id = ckusdt();
+    state.collateral_configs.insert(ckusdt(), config);
+    assert_eq!(
+        supported_ledgers(&state)[&ckusdt()],
+        vec![AssetRole::Collateral, AssetRole::RepaymentStable]
+    );
+}
+
+#[test]
+fn native_xrp_collateral_is_left_out() {
+    let mut state = fixture();
+    let xrp = Principal::from_slice(&[40]);
+    let mut config = state.collateral_configs[&icp()].clone();
+    config.ledger_canister_id = xrp;
+    config.custody_kind = Some(CustodyKind::NativeXrp);
+    state.collateral_configs.insert(xrp, config);
+    assert!(!supported_ledgers(&state).contains_key(&xrp));
+}
+
+#[test]
+fn metadata_is_parsed_from_the_standard_keys() {
+    assert_eq!(
+        parse_metadata(metadata_entries(false), 5),
+        Some(AssetMetadata {
+            name: "Internet Computer".to_string(),
+            symbol: "ICP".to_string(),
+            decimals: 8,
+            fee: 10_000,
+            logo: None,
+            fetched_at_ns: 5,
+        })
+    );
+    assert_eq!(
+        parse_metadata(metadata_entries(true), 5)
+            .unwrap()
+            .logo
+            .as_deref(),
+        Some("data:image/svg+xml;base64,AA==")
+    );
+
+    let mut incomplete = metadata_entries(true);
+    incomplete.retain(|(key, _)| key != "icrc1:fee");
+    assert_eq!(parse_metadata(incomplete, 5), None);
+}
+
+#[test]
+fn assets_carry_the_cached_metadata() {
+    let mut state = fixture();
+    let metadata = parse_metadata(metadata_entries(false), 5).unwrap();
+    state.asset_metadata.insert(icp(), metadata.clone());
+
+    let assets = supported_assets(&state);
+    assert_eq!(assets.len(), 5);
+    let icp_asset = assets.iter().find(|a| a.ledger == icp()).unwrap();
+    assert_eq!(icp_asset.metadata, Some(metadata));
+    let icusd_asset = assets.iter().find(|a| a.ledger == icusd()).unwrap();
+    assert_eq!(icusd_asset.metadata, None);
+}
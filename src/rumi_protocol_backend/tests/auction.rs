@@ -0,0 +1,325 @@
+//! Liquidation auctions: the config is bounded, the price decays per step
+//! down to its floor, only vaults the stability pool passed on start once
+//! the grace period is over, a bid is capped at the debt the auction still
+//! covers, a bid that empties the vault writes off the debt left, auctions
+//! end when their vault recovers, leaves or expires, and replay rebuilds
+//! the auction and the vault.
+//!
+//! ICP is at $6 and one 10 ICP vault owes 50 icUSD (CR 120%, under the
+//! liquidation ratio); the stability pool has already attempted it. The
+//! auction starts 10% above the oracle price and drops 1% of the start
+//! price every minute, down to 80% of the oracle price.
+
+mod common;
+
+use std::collections::BTreeSet;
+
+use candid::Principal;
+
+use rumi_protocol_backend::auction::{
+    apply_bid, apply_end, apply_start, due_ends, end_after_bid, plan_bid, plan_start, price_at,
+    update_candidates, validate_config, AuctionConfig, AuctionEnd, AuctionEndReason,
+};
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::{UsdIcp, ICUSD};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::Vault;
+use rust_decimal::Decimal;
+
+use common::{fresh_state, init_arg, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const SEC: u64 = 1_000_000_000;
+const NOW: u64 = 1_000_000 * SEC;
+
+fn owner() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn bidder() -> Principal {
+    Principal::from_slice(&[2])
+}
+
+fn vault() -> Vault {
+    VaultBuilder::new(1)
+        .owner(owner())
+        .collateral(10 * E8S)
+        .debt(50 * E8S)
+        .build()
+}
+
+fn config() -> AuctionConfig {
+    AuctionConfig {
+        enabled: true,
+        grace_period_secs: 600,
+        start_premium_bps: 1_000,
+        decay_bps_per_step: 100,
+        step_secs: 60,
+        floor_bps: 8_000,
+        max_duration_secs: 3_600,
+    }
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 6.0, Some(NOW));
+    state.open_vault(vault());
+    state.sp_attempted_vaults.insert(1);
+    state.auction_config = Some(config());
+    state
+}
+
+fn unhealthy() -> BTreeSet<u64> {
+    BTreeSet::from([1])
+}
+
+fn started() -> State {
+    let mut state = fixture();
+    let auction = plan_start(&state, 1, &config(), NOW).unwrap();
+    apply_start(&mut state, auction);
+    state
+}
+
+#[test]
+fn config_is_bounded() {
+    assert!(validate_config(&config()).is_ok());
+
+    let with = |f: fn(&mut AuctionConfig)| {
+        let mut c = config();
+        f(&mut c);
+        validate_config(&c)
+    };
+    assert!(with(|c| c.step_secs = 0).is_err());
+    assert!(with(|c| c.max_duration_secs = 0).is_err());
+    assert!(with(|c| c.decay_bps_per_step = 0).is_err());
+    assert!(with(|c| c.decay_bps_per_step = 10_001).is_err());
+    assert!(with(|c| c.floor_bps = 0).is_err());
+    // The floor cannot sit above the start price.
+    assert!(with(|c| c.floor_bps = 11_001).is_err());
+}
+
+#[test]
+fn the_price_decays_per_step_down_to_the_floor() {
+    let state = started();
+    let auction = &state.auctions[&0];
+    assert_eq!(auction.start_price_e8s, 660_000_000);
+    assert_eq!(auction.floor_price_e8s, 480_000_000);
+    assert_eq!(auction.expires_at, NOW + 3_600 * SEC);
+
+    assert_eq!(price_at(auction, NOW), 660_000_000);
+    // Only whole steps count.
+    assert_eq!(price_at(auction, NOW + 59 * SEC), 660_000_000);
+    assert_eq!(price_at(auction, NOW + 60 * SEC), 653_400_000);
+    assert_eq!(price_at(auction, NOW + 10 * 60 * SEC), 594_000_000);
+    assert_eq!(price_at(auction, NOW + 40 * 60 * SEC), 480_000_000);
+}
+
+#[test]
+fn only_vaults_the_pool_passed_on_start_after_the_grace_period() {
+    let mut state = fixture();
+    let mut other = vault();
+    other.vault_id = 2;
+    state.open_vault(other);
+
+    assert!(update_candidates(&mut state, &BTreeSet::from([1, 2]), NOW).is_empty());
+    // Vault #2 was never offered to the pool.
+    assert_eq!(
+        state.auction_candidates.keys().collect::<Vec<_>>(),
+        vec![&1]
+    );
+    assert!(update_candidates(&mut state, &unhealthy(), NOW + 599 * SEC).is_empty());
+    assert_eq!(
+        update_candidates(&mut state, &unhealthy(), NOW + 600 * SEC),
+        vec![1]
+    );
+
+    // Recovering drops the candidate; the clock restarts next time.
+    assert!(update_candidates(&mut state, &BTreeSet::new(), NOW + 700 * SEC).is_empty());
+    assert!(state.auction_candidates.is_empty());
+    assert!(update_candidates(&mut state, &unhealthy(), NOW + 800 * SEC).is_empty());
+
+    // Disabled auctions keep no candidates.
+    state.auction_config.as_mut().unwrap().enabled = false;
+    assert!(update_candidates(&mut state, &unhealthy(), NOW + 2_000 * SEC).is_empty());
+    assert!(state.auction_candidates.is_empty());
+}
+
+#[test]
+fn an_auction_covers_the_partial_liquidation_cap() {
+    let state = started();
+    let auction = &state.auctions[&0];
+    let cap = state.compute_partial_liquidation_cap(
+        &state.vault_id_to_vaults[&1],
+        UsdIcp::from(Decimal::from(6)),
+    );
+    assert_eq!(auction.debt_target_e8s, cap.to_u64());
+    assert_eq!(state.next_auction_id, 1);
+    assert!(state.auction_candidates.is_empty());
+
+    // A running auction keeps the vault off the candidate list.
+    let mut state = state;
+    assert!(update_candidates(&mut state, &unhealthy(), NOW + 10_000 * SEC).is_empty());
+
+    // A healthy vault is not auctioned.
+    let mut healthy = fixture();
+    set_icp_price(&mut healthy, 10.0, Some(NOW));
+    assert!(plan_start(&healthy, 1, &config(), NOW).is_err());
+}
+
+#[test]
+fn a_bid_is_capped_at_the_debt_left_and_priced_now() {
+    let mut state = started();
+    let target = state.auctions[&0].debt_target_e8s;
+    let at = NOW + 10 * 60 * SEC;
+
+    let plan = plan_bid(&state, 0, 10 * E8S, at).unwrap();
+    assert_eq!(plan.price_e8s, 594_000_000);
+    assert_eq!(plan.icusd, ICUSD::new(10 * E8S));
+    // 10 icUSD at $5.94.
+    assert_eq!(plan.collateral, 168_350_168);
+    assert_eq!(plan.debt_written_off, ICUSD::new(0));
+
+    apply_bid(
+        &mut state,
+        0,
+        1,
+        plan.icusd,
+        plan.collateral,
+        plan.debt_written_off,
+    );
+    let vault = &state.vault_id_to_vaults[&1];
+    assert_eq!(vault.borrowed_icusd_amount, ICUSD::new(40 * E8S));
+    assert_eq!(vault.collateral_amount, 10 * E8S - 168_350_168);
+    assert_eq!(state.auctions[&0].debt_covered_e8s, 10 * E8S);
+    assert_eq!(end_after_bid(&state, 0), None);
+
+    // An oversized bid buys what is left of the target.
+    let rest = plan_bid(&state, 0, 1_000 * E8S, at).unwrap();
+    assert_eq!(rest.icusd, ICUSD::new(target - 10 * E8S));
+    apply_bid(
+        &mut state,
+        0,
+        1,
+        rest.icusd,
+        rest.collateral,
+        rest.debt_written_off,
+    );
+    assert_eq!(end_after_bid(&state, 0), Some(AuctionEndReason::Filled));
+}
+
+#[test]
+fn a_bid_that_empties_the_vault_writes_off_the_debt_left() {
+    let mut state = started();
+    // The price crashed: the vault is underwater and the target is the
+    // whole debt.
+    set_icp_price(&mut state, 3.0, Some(NOW));
+    state.auctions.get_mut(&0).unwrap().debt_target_e8s = 50 * E8S;
+    state.auctions.get_mut(&0).unwrap().floor_price_e8s = 1;
+    let at = NOW + 30 * 60 * SEC;
+
+    let plan = plan_bid(&state, 0, 50 * E8S, at).unwrap();
+    assert_eq!(plan.price_e8s, 462_000_000);
+    assert_eq!(plan.collateral, 10 * E8S);
+    assert_eq!(plan.icusd, ICUSD::new(4_620_000_000));
+    assert_eq!(plan.debt_written_off, ICUSD::new(380_000_000));
+
+    apply_bid(
+        &mut state,
+        0,
+        1,
+        plan.icusd,
+        plan.collateral,
+        plan.debt_written_off,
+    );
+    assert!(!state.vault_id_to_vaults.contains_key(&1));
+    assert_eq!(
+        end_after_bid(&state, 0),
+        Some(AuctionEndReason::CollateralExhausted)
+    );
+}
+
+#[test]
+fn bids_are_refused_on_ended_or_recovered_auctions() {
+    let mut state = started();
+    assert!(plan_bid(&state, 7, E8S, NOW).is_err());
+    // Dust bids are refused unless they finish the auction.
+    assert!(plan_bid(&state, 0, 1_000, NOW).is_err());
+    assert!(plan_bid(&state, 0, E8S, NOW + 3_600 * SEC).is_err());
+
+    set_icp_price(&mut state, 10.0, Some(NOW));
+    assert!(plan_bid(&state, 0, E8S, NOW).is_err());
+}
+
+#[test]
+fn auctions_end_when_the_vault_recovers_leaves_or_expires() {
+    let state = started();
+    assert!(due_ends(&state, &unhealthy(), NOW).is_empty());
+    assert_eq!(
+        due_ends(&state, &BTreeSet::new(), NOW),
+        vec![(0, AuctionEndReason::VaultRecovered)]
+    );
+    assert_eq!(
+        due_ends(&state, &unhealthy(), NOW + 3_600 * SEC),
+        vec![(0, AuctionEndReason::Expired)]
+    );
+
+    let mut state = state;
+    state.vault_id_to_vaults.remove(&1);
+    assert_eq!(
+        due_ends(&state, &unhealthy(), NOW),
+        vec![(0, AuctionEndReason::VaultClosed)]
+    );
+
+    apply_end(&mut state, 0, AuctionEndReason::VaultClosed, NOW);
+    assert!(due_ends(&state, &unhealthy(), NOW).is_empty());
+}
+
+#[test]
+fn replay_rebuilds_the_auction_and_the_vault() {
+    let auction = plan_start(&fixture(), 1, &config(), NOW).unwrap();
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::OpenVault {
+            vault: vault(),
+            block_index: 0,
+            timestamp: None,
+        },
+        Event::SetAuctionConfig { config: config() },
+        Event::AuctionStarted {
+            auction: auction.clone(),
+        },
+        Event::AuctionBid {
+            auction_id: 0,
+            vault_id: 1,
+            bidder: bidder(),
+            icusd_amount: ICUSD::new(10 * E8S),
+            collateral_amount: 168_350_168,
+            price_e8s: 594_000_000,
+            debt_written_off: ICUSD::new(0),
+            icusd_block_index: 3,
+            timestamp: NOW + 600 * SEC,
+        },
+        Event::AuctionEnded {
+            auction_id: 0,
+            vault_id: 1,
+            reason: AuctionEndReason::Expired,
+            timestamp: NOW + 3_600 * SEC,
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(state.auction_config, Some(config()));
+    assert_eq!(state.next_auction_id, 1);
+    let replayed = &state.auctions[&0];
+    assert_eq!(replayed.debt_covered_e8s, 10 * E8S);
+    assert_eq!((replayed.collateral_sold, replayed.bids), (168_350_168, 1));
+    assert_eq!(
+        replayed.ended,
+        Some(AuctionEnd {
+            reason: AuctionEndReason::Expired,
+            at: NOW + 3_600 * SEC,
+        })
+    );
+    let vault = &state.vault_id_to_vaults[&1];
+    assert_eq!(vault.borrowed_icusd_amount, ICUSD::new(40 * E8S));
+    assert_eq!(vault.collateral_amount, 10 * E8S - 168_350_168);
+}
@@ -211,7 +211,7 @@ fn cdp_01_circuit_breaker_survives_tcr_recalculation() {
     // breaker in fetch_icp_rate. A healthy cached price would normally
     // flip mode back to GA, but the circuit breaker must take precedence.
     let healthy_price = UsdIcp::from(dec!(10.0));
-    state.update_total_collateral_ratio_and_mode(healthy_price);
+    state.update_total_collateral_ratio_and_mode(healthy_price, 0);
 
     assert_eq!(
         state.mode,
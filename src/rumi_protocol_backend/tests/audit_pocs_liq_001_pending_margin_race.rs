@@ -80,6 +80,7 @@ fn pending(owner: Principal, margin_e8s: u64, op_nonce: u128) -> PendingMarginTr
         collateral_type: Principal::anonymous(),
         retry_count: 0,
         op_nonce,
+        trace_id: None,
     }
 }
 
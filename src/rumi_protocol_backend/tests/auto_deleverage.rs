@@ -0,0 +1,217 @@
+//! Auto-deleveraging: opt-ins are validated against the vault's collateral,
+//! a sale is only planned once the vault has been idle long enough and sits
+//! between its liquidation ratio and the owner's trigger, its size is
+//! bounded by the weekly allowance, owner activity restarts the clock, and
+//! replay rebuilds the opt-in, the window and the vault.
+//!
+//! The vault holds 10 ICP at $10 and owes 50 icUSD (CR 200%), with a 0.0001
+//! ICP ledger fee. It opted in at NOW with a 7-day inactivity period, a
+//! 220% trigger, a 300% target and a 20% weekly limit.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::auto_deleverage::{
+    apply_deleverage, apply_set, due_vaults, note_activity, plan_deleverage, validate_config,
+    vault_cr_bps, AutoDeleverageConfig, AutoDeleverageRoute, AUTO_DELEVERAGE_RETRY_BACKOFF_NS,
+    AUTO_DELEVERAGE_WEEK_NS,
+};
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::Vault;
+
+use common::{fresh_state, icp, init_arg, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const FEE: u64 = 10_000;
+const DAY: u64 = 24 * 3600 * 1_000_000_000;
+const NOW: u64 = 1_000 * DAY;
+
+fn owner() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn dex() -> Principal {
+    Principal::from_slice(&[40])
+}
+
+fn vault() -> Vault {
+    VaultBuilder::new(1)
+        .owner(owner())
+        .collateral(10 * E8S)
+        .debt(50 * E8S)
+        .build()
+}
+
+fn config() -> AutoDeleverageConfig {
+    AutoDeleverageConfig {
+        inactivity_days: 7,
+        trigger_cr_bps: 22_000,
+        target_cr_bps: 30_000,
+        max_weekly_sell_bps: 2_000,
+    }
+}
+
+fn route() -> AutoDeleverageRoute {
+    AutoDeleverageRoute {
+        collateral_type: icp(),
+        dex: dex(),
+        pool_id: "icp_icusd".to_string(),
+    }
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    state.collateral_configs.get_mut(&icp()).unwrap().ledger_fee = FEE;
+    set_icp_price(&mut state, 10.0, Some(NOW));
+    state.open_vault(vault());
+    state.auto_deleverage_routes.insert(icp(), route());
+    apply_set(&mut state, 1, Some(config()), NOW);
+    state
+}
+
+#[test]
+fn config_is_bounded() {
+    let state = fixture();
+    assert!(validate_config(&state, 1, &config()).is_ok());
+
+    let with = |f: fn(&mut AutoDeleverageConfig)| {
+        let mut c = config();
+        f(&mut c);
+        validate_config(&state, 1, &c)
+    };
+    assert!(with(|c| c.inactivity_days = 0).is_err());
+    assert!(with(|c| c.inactivity_days = 366).is_err());
+    // At or under the liquidation ratio the buffer would never fire first.
+    assert!(with(|c| c.trigger_cr_bps = 10_000).is_err());
+    assert!(with(|c| c.target_cr_bps = c.trigger_cr_bps).is_err());
+    assert!(with(|c| c.max_weekly_sell_bps = 0).is_err());
+    assert!(with(|c| c.max_weekly_sell_bps = 5_000).is_err());
+    assert!(validate_config(&state, 2, &config()).is_err());
+}
+
+#[test]
+fn sale_waits_for_inactivity_and_is_capped_weekly() {
+    let state = fixture();
+    assert_eq!(vault_cr_bps(&state, 1), Some(20_000));
+    assert!(plan_deleverage(&state, 1, NOW + 7 * DAY - 1).is_err());
+    assert!(due_vaults(&state, NOW + 7 * DAY - 1, 3).is_empty());
+
+    // Reaching 300% needs 25 icUSD of sales; the week allows 2 ICP.
+    let plan = plan_deleverage(&state, 1, NOW + 7 * DAY).expect("plan");
+    assert_eq!(plan.amount_in, 2 * E8S - 2 * FEE);
+    assert_eq!(plan.allowance, 2 * E8S - FEE);
+    assert_eq!(plan.collateral_debit, 2 * E8S);
+    // Oracle value of the sale less 2%.
+    assert_eq!(plan.min_icusd_out, 1_959_804_000);
+    assert_eq!(plan.cr_before_bps, 20_000);
+    assert_eq!(plan.route, route());
+    assert_eq!(due_vaults(&state, NOW + 7 * DAY, 3), vec![1]);
+}
+
+#[test]
+fn only_vaults_between_liquidation_and_trigger_are_sold() {
+    let mut state = fixture();
+    set_icp_price(&mut state, 12.0, Some(NOW));
+    assert!(plan_deleverage(&state, 1, NOW + 7 * DAY).is_err());
+    set_icp_price(&mut state, 6.0, Some(NOW));
+    assert!(plan_deleverage(&state, 1, NOW + 7 * DAY).is_err());
+
+    // No route, or a cancelled opt-in: nothing to do.
+    let mut state = fixture();
+    state.auto_deleverage_routes.clear();
+    assert!(plan_deleverage(&state, 1, NOW + 7 * DAY).is_err());
+    let mut state = fixture();
+    apply_set(&mut state, 1, None, NOW);
+    assert!(state.auto_deleverage.is_empty());
+    assert!(plan_deleverage(&state, 1, NOW + 7 * DAY).is_err());
+}
+
+#[test]
+fn activity_restarts_the_clock() {
+    let mut state = fixture();
+    note_activity(&mut state, 1, NOW + 5 * DAY);
+    assert!(plan_deleverage(&state, 1, NOW + 7 * DAY).is_err());
+    assert!(plan_deleverage(&state, 1, NOW + 12 * DAY).is_ok());
+}
+
+#[test]
+fn a_sale_is_charged_to_the_week() {
+    let mut state = fixture();
+    let at = NOW + 7 * DAY;
+    apply_deleverage(&mut state, 1, 2 * E8S, ICUSD::new(19 * E8S), at);
+    let vault = &state.vault_id_to_vaults[&1];
+    assert_eq!(vault.collateral_amount, 8 * E8S);
+    assert_eq!(vault.borrowed_icusd_amount, ICUSD::new(31 * E8S));
+    let entry = &state.auto_deleverage[&1];
+    assert_eq!(entry.window_start_ns, at);
+    assert_eq!(entry.window_budget, 2 * E8S);
+    assert_eq!(entry.window_sold, 2 * E8S);
+
+    // Still under the trigger, but the week's allowance is spent.
+    set_icp_price(&mut state, 8.0, Some(NOW));
+    assert!(plan_deleverage(&state, 1, at + DAY).is_err());
+    // A new week allows 20% of the 8 ICP left.
+    let plan = plan_deleverage(&state, 1, at + AUTO_DELEVERAGE_WEEK_NS).expect("plan");
+    assert_eq!(plan.collateral_debit, 8 * E8S / 5);
+}
+
+#[test]
+fn replay_rebuilds_the_opt_in_and_the_vault() {
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::OpenVault {
+            vault: vault(),
+            block_index: 0,
+            timestamp: None,
+        },
+        Event::SetAutoDeleverage {
+            vault_id: 1,
+            owner: owner(),
+            config: Some(config()),
+            timestamp: NOW,
+        },
+        Event::SetAutoDeleverageRoute {
+            collateral_type: icp(),
+            route: Some(route()),
+        },
+        Event::RepayToVault {
+            vault_id: 1,
+            repayed_amount: ICUSD::new(E8S),
+            block_index: 1,
+            caller: Some(owner()),
+            timestamp: Some(NOW + DAY),
+            stable_fee: None,
+        },
+        Event::VaultAutoDeleveraged {
+            vault_id: 1,
+            collateral_type: icp(),
+            collateral_sold: 2 * E8S,
+            icusd_repaid: ICUSD::new(19 * E8S),
+            cr_before_bps: 20_000,
+            dex: dex(),
+            timestamp: NOW + 8 * DAY,
+        },
+        Event::AutoDeleverageFailed {
+            vault_id: 1,
+            reason: "pool unavailable".to_string(),
+            timestamp: NOW + 9 * DAY,
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(state.auto_deleverage_routes.get(&icp()), Some(&route()));
+    let entry = &state.auto_deleverage[&1];
+    assert_eq!(entry.config, config());
+    assert_eq!(entry.last_activity_ns, NOW + DAY);
+    assert_eq!(entry.window_start_ns, NOW + 8 * DAY);
+    assert_eq!(entry.window_sold, 2 * E8S);
+    assert_eq!(
+        entry.retry_after_ns,
+        NOW + 9 * DAY + AUTO_DELEVERAGE_RETRY_BACKOFF_NS
+    );
+    let vault = &state.vault_id_to_vaults[&1];
+    assert_eq!(vault.collateral_amount, 8 * E8S);
+    assert_eq!(vault.borrowed_icusd_amount, ICUSD::new(30 * E8S));
+}
@@ -0,0 +1,169 @@
+//! Admin batch jobs: a job stops when its budget is spent and resumes from
+//! its cursor, every call makes progress, the snapshot matches the vaults,
+//! the sweep drops stale borrower entries, one job of a kind runs at a
+//! time, cancelled jobs stay cancelled and finished jobs are capped.
+//!
+//! Five borrowers each own one vault of 10 ICP at $10, owing 10 to 50
+//! icUSD.
+
+mod common;
+
+use std::collections::BTreeSet;
+
+use candid::Principal;
+
+use rumi_protocol_backend::batch_jobs::{
+    cancel, jobs, start, step, BatchCursor, BatchJobKind, BatchJobStatus, MAX_FINISHED_BATCH_JOBS,
+};
+use rumi_protocol_backend::state::{Mode, State};
+use rumi_protocol_backend::vault::Vault;
+
+use common::{fresh_state, icp, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const T0: u64 = 1_700_000_000 * 1_000_000_000;
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    state.mode = Mode::GeneralAvailability;
+    set_icp_price(&mut state, 10.0, Some(0));
+    for id in 1..=5u64 {
+        state.open_vault(
+            VaultBuilder::new(id)
+                .owner(Principal::from_slice(&[id as u8]))
+                .collateral(10 * E8S)
+                .debt(id * 10 * E8S)
+                .build(),
+        );
+    }
+    state
+}
+
+/// A budget that runs out after `items` items.
+fn items(items: u64) -> impl FnMut() -> bool {
+    let mut left = items;
+    move || {
+        left -= 1;
+        left == 0
+    }
+}
+
+#[test]
+fn a_job_resumes_from_its_cursor() {
+    let mut state = fixture();
+    let indexed = state.liquidation_index.clone();
+    state.liquidation_index.clear();
+    state.liquidation_index_keys.clear();
+
+    let id = start(&mut state, BatchJobKind::RecomputeVaultIndexes, T0).unwrap();
+    let job = step(&mut state, id, T0 + 1, items(2)).unwrap();
+    assert_eq!(job.status, BatchJobStatus::Running);
+    assert_eq!((job.processed, job.total), (2, 5));
+    assert_eq!(job.cursor, Some(BatchCursor::Vault(3)));
+    assert_eq!(state.liquidation_index_keys.len(), 2);
+
+    let job = step(&mut state, id, T0 + 2, items(10)).unwrap();
+    assert_eq!(job.status, BatchJobStatus::Completed);
+    assert_eq!((job.processed, job.steps), (5, 2));
+    assert_eq!(job.cursor, None);
+    assert_eq!(job.updated_at, T0 + 2);
+    assert_eq!(state.liquidation_index, indexed);
+
+    // A finished job cannot be continued.
+    assert!(step(&mut state, id, T0 + 3, items(1)).is_err());
+}
+
+#[test]
+fn every_call_makes_progress() {
+    let mut state = fixture();
+    let id = start(&mut state, BatchJobKind::RecomputeVaultIndexes, T0).unwrap();
+    let mut calls = 0;
+    loop {
+        calls += 1;
+        let job = step(&mut state, id, T0, || true).unwrap();
+        if job.status == BatchJobStatus::Completed {
+            assert_eq!(job.processed, 5);
+            break;
+        }
+        assert_eq!(job.processed, calls);
+    }
+    // One call per vault, and one that finds none left.
+    assert_eq!(calls, 6);
+}
+
+#[test]
+fn the_snapshot_matches_the_vaults() {
+    let mut state = fixture();
+    let id = start(&mut state, BatchJobKind::ProtocolSnapshot, T0).unwrap();
+    step(&mut state, id, T0, items(3)).unwrap();
+    let job = step(&mut state, id, T0 + 1, items(10)).unwrap();
+
+    let snapshot = job.snapshot.expect("completed");
+    assert_eq!(snapshot.timestamp, T0 + 1);
+    assert_eq!(snapshot.total_vault_count, 5);
+    assert_eq!(snapshot.total_debt, 150 * E8S);
+    assert_eq!(snapshot.total_collateral_value_usd, 500 * E8S);
+    let icp_snapshot = snapshot
+        .collateral_snapshots
+        .iter()
+        .find(|c| c.collateral_type == icp())
+        .unwrap();
+    assert_eq!(icp_snapshot.total_collateral, 50 * E8S);
+    assert_eq!(icp_snapshot.vault_count, 5);
+    assert_eq!(icp_snapshot.price, 10.0);
+}
+
+#[test]
+fn the_sweep_drops_stale_borrower_entries() {
+    let mut state = fixture();
+    let owner = Principal::from_slice(&[1]);
+    // A closed vault still listed, and a borrower with nothing left.
+    state
+        .principal_to_vault_ids
+        .get_mut(&owner)
+        .unwrap()
+        .insert(99);
+    state
+        .principal_to_vault_ids
+        .insert(Principal::from_slice(&[42]), BTreeSet::new());
+
+    let id = start(&mut state, BatchJobKind::SweepPrincipalIndex, T0).unwrap();
+    step(&mut state, id, T0, items(1)).unwrap();
+    let job = step(&mut state, id, T0, items(10)).unwrap();
+    assert_eq!(job.status, BatchJobStatus::Completed);
+    assert_eq!((job.processed, job.total, job.removed), (6, 6, 2));
+    assert_eq!(state.principal_to_vault_ids.len(), 5);
+    assert_eq!(state.principal_to_vault_ids[&owner], BTreeSet::from([1]));
+}
+
+#[test]
+fn one_job_of_a_kind_runs_at_a_time() {
+    let mut state = fixture();
+    let id = start(&mut state, BatchJobKind::ProtocolSnapshot, T0).unwrap();
+    assert!(start(&mut state, BatchJobKind::ProtocolSnapshot, T0).is_err());
+    assert!(start(&mut state, BatchJobKind::SweepPrincipalIndex, T0).is_ok());
+
+    step(&mut state, id, T0, items(2)).unwrap();
+    let job = cancel(&mut state, id, T0 + 1).unwrap();
+    assert_eq!(job.status, BatchJobStatus::Cancelled);
+    assert_eq!(job.processed, 2);
+    assert!(step(&mut state, id, T0 + 2, items(1)).is_err());
+    assert!(cancel(&mut state, id, T0 + 2).is_err());
+    assert!(state.batch_jobs[&id].snapshot.is_none());
+
+    assert!(start(&mut state, BatchJobKind::ProtocolSnapshot, T0).is_ok());
+}
+
+#[test]
+fn finished_jobs_are_capped() {
+    let mut state = fixture();
+    let running = start(&mut state, BatchJobKind::SweepPrincipalIndex, T0).unwrap();
+    for _ in 0..MAX_FINISHED_BATCH_JOBS + 3 {
+        let id = start(&mut state, BatchJobKind::RecomputeVaultIndexes, T0).unwrap();
+        step(&mut state, id, T0, || false).unwrap();
+    }
+    let listed = jobs(&state);
+    assert_eq!(listed.len(), MAX_FINISHED_BATCH_JOBS + 1);
+    assert!(listed.windows(2).all(|w| w[0].id > w[1].id), "newest first");
+    assert!(listed.iter().any(|job| job.id == running));
+}
@@ -0,0 +1,146 @@
+//! Resumable upgrade replay: a replay cut into chunks arrives at the same
+//! state as one pass, the protocol stays in Bootstrapping with the event log
+//! sealed until the last event, and the mode the events left it in is
+//! restored afterwards.
+//!
+//! The replayed log holds Init, three ICP vaults and, after the first
+//! vault, an upgrade into Recovery.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::bootstrap::{begin, begin_from_init, replay_chunk};
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::state::{is_legal_mode_transition, Mode, ModeTransitionReason, State};
+use rumi_protocol_backend::storage::is_event_log_sealed;
+use rumi_protocol_backend::{ProtocolError, UpgradeArg};
+
+use common::{init_arg, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+
+fn open_vault(vault_id: u64) -> Event {
+    Event::OpenVault {
+        vault: VaultBuilder::new(vault_id)
+            .owner(Principal::from_slice(&[1]))
+            .collateral(20 * E8S)
+            .debt(100 * E8S)
+            .build(),
+        block_index: vault_id,
+        timestamp: None,
+    }
+}
+
+fn upgrade(mode: Mode) -> UpgradeArg {
+    UpgradeArg {
+        mode: Some(mode),
+        description: None,
+    }
+}
+
+fn events() -> Vec<Event> {
+    vec![
+        Event::Init(init_arg()),
+        open_vault(1),
+        Event::Upgrade(upgrade(Mode::Recovery)),
+        open_vault(2),
+        open_vault(3),
+    ]
+}
+
+/// Run one chunk of a single event, the smallest budget.
+fn step(state: State) -> State {
+    let next = state.replay_cursor.as_ref().unwrap().next_event as usize;
+    replay_chunk(state, events().into_iter().skip(next), || true)
+}
+
+#[test]
+fn a_chunked_replay_matches_a_single_pass() {
+    let expected = replay(events().into_iter()).expect("replay");
+
+    let mut state = begin_from_init(events().into_iter(), events().len() as u64).expect("begin");
+    let mut chunks = 0;
+    while state.replay_cursor.is_some() {
+        state = step(state);
+        chunks += 1;
+    }
+    assert_eq!(chunks, 4);
+    assert_eq!(
+        state.vault_id_to_vaults.keys().collect::<Vec<_>>(),
+        expected.vault_id_to_vaults.keys().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        state.next_available_vault_id,
+        expected.next_available_vault_id
+    );
+    assert_eq!(state.mode, expected.mode);
+    assert_eq!(state.mode, Mode::Recovery);
+}
+
+#[test]
+fn the_protocol_bootstraps_until_the_last_event() {
+    let mut state = begin_from_init(events().into_iter(), events().len() as u64).expect("begin");
+    for _ in 0..3 {
+        assert_eq!(state.mode, Mode::Bootstrapping);
+        assert!(matches!(
+            state.mode.check_available(),
+            Err(ProtocolError::TemporarilyUnavailable(_))
+        ));
+        assert!(is_event_log_sealed());
+        state = step(state);
+    }
+    // The upgrade into Recovery has been applied and is held for later.
+    let cursor = state.replay_cursor.clone().unwrap();
+    assert_eq!((cursor.next_event, cursor.end_event), (4, 5));
+    assert_eq!(cursor.resume_mode, Mode::Recovery);
+    assert_eq!(state.mode, Mode::Bootstrapping);
+
+    let state = step(state);
+    assert!(state.replay_cursor.is_none());
+    assert_eq!(state.mode, Mode::Recovery);
+    assert!(state.mode.check_available().is_ok());
+    assert!(!is_event_log_sealed());
+}
+
+#[test]
+fn a_checkpoint_tail_applies_the_upgrade_args_on_completion() {
+    let checkpoint = replay(events().into_iter().take(2)).expect("replay");
+    let state = begin(checkpoint, 2, 5, Some(upgrade(Mode::ReadOnly)));
+    let state = replay_chunk(state, events().into_iter().skip(2), || false);
+    assert!(state.replay_cursor.is_none());
+    assert_eq!(state.vault_id_to_vaults.len(), 3);
+    assert_eq!(state.mode, Mode::ReadOnly);
+
+    // Nothing left to replay: done at once.
+    let checkpoint = replay(events().into_iter()).expect("replay");
+    let state = begin(checkpoint, 5, 5, None);
+    let state = replay_chunk(state, std::iter::empty(), || true);
+    assert!(state.replay_cursor.is_none());
+    assert_eq!(state.mode, Mode::Recovery);
+    assert!(!is_event_log_sealed());
+}
+
+#[test]
+fn bootstrapping_is_never_a_transition() {
+    for reason in [
+        ModeTransitionReason::Upgrade,
+        ModeTransitionReason::AdminOverride,
+    ] {
+        assert!(!is_legal_mode_transition(
+            Mode::GeneralAvailability,
+            Mode::Bootstrapping,
+            reason
+        ));
+        assert!(!is_legal_mode_transition(
+            Mode::Bootstrapping,
+            Mode::GeneralAvailability,
+            reason
+        ));
+    }
+
+    // An upgrade asking for Bootstrapping is ignored.
+    let mut state = replay(events().into_iter()).expect("replay");
+    state.upgrade(upgrade(Mode::Bootstrapping));
+    assert_eq!(state.mode, Mode::Recovery);
+}
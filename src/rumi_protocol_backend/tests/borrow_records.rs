@@ -0,0 +1,159 @@
+//! Borrow origination records: replayed `BorrowFromVault` events become one
+//! record per borrow with the effective fee rate and origination price, a
+//! fee rebate lands on the borrow it was paid with, `borrow_records_page`
+//! pages through one vault, and each vault keeps only its latest records.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::borrow_records::{
+    borrow_records_page, fee_rate, push_borrow_record, MAX_BORROW_RECORDS_PAGE,
+    MAX_BORROW_RECORDS_PER_VAULT,
+};
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::vault::Vault;
+
+use common::{fresh_state, icp, init_arg, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+
+fn vault(vault_id: u64) -> Vault {
+    VaultBuilder::new(vault_id)
+        .owner(Principal::from_slice(&[1]))
+        .collateral(100 * E8S)
+        .build()
+}
+
+fn borrow(vault_id: u64, amount: u64, fee: u64, price: Option<&str>, block: u64) -> Event {
+    Event::BorrowFromVault {
+        vault_id,
+        borrowed_amount: ICUSD::new(amount),
+        fee_amount: ICUSD::new(fee),
+        block_index: block,
+        caller: None,
+        timestamp: Some(block * 1_000),
+        collateral_price: price.map(str::to_string),
+    }
+}
+
+#[test]
+fn fee_rate_is_the_effective_rate() {
+    assert_eq!(
+        fee_rate(ICUSD::new(100 * E8S), ICUSD::new(E8S / 2)),
+        "0.005"
+    );
+    assert_eq!(fee_rate(ICUSD::new(0), ICUSD::new(0)), "0");
+}
+
+#[test]
+fn replay_records_each_borrow_and_its_rebate() {
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::OpenVault {
+            vault: vault(1),
+            block_index: 0,
+            timestamp: None,
+        },
+        borrow(1, 100 * E8S, E8S / 2, Some("10.25"), 7),
+        Event::BorrowFeeRebated {
+            campaign_id: 0,
+            vault_id: 1,
+            rebate_e8s: E8S / 4,
+            timestamp: 7_000,
+        },
+        // Logged before the price field existed.
+        borrow(1, 50 * E8S, E8S / 2, None, 9),
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+
+    let page = borrow_records_page(&state, 1, None, 10);
+    assert_eq!(page.next_cursor, None);
+    assert_eq!(page.records.len(), 2);
+    let first = &page.records[0];
+    assert_eq!(first.collateral_type, icp());
+    assert_eq!(
+        (first.borrowed_amount, first.fee_amount),
+        (100 * E8S, E8S / 2)
+    );
+    assert_eq!(first.fee_rebated, E8S / 4);
+    assert_eq!(first.fee_rate, "0.005");
+    assert_eq!(first.collateral_price.as_deref(), Some("10.25"));
+    assert_eq!((first.block_index, first.timestamp), (7, 7_000));
+
+    let second = &page.records[1];
+    assert_eq!(second.fee_rebated, 0);
+    assert_eq!(second.fee_rate, "0.01");
+    assert_eq!(second.collateral_price, None);
+
+    assert_eq!(
+        state.vault_id_to_vaults[&1].borrowed_icusd_amount,
+        ICUSD::new(150 * E8S)
+    );
+}
+
+#[test]
+fn records_page_with_cursor() {
+    let mut state = fresh_state();
+    state.open_vault(vault(1));
+    state.open_vault(vault(2));
+    let total = MAX_BORROW_RECORDS_PAGE as u64 + 3;
+    for block in 0..total {
+        push_borrow_record(
+            &mut state,
+            1,
+            ICUSD::new(E8S),
+            ICUSD::new(0),
+            None,
+            block,
+            0,
+        );
+    }
+    push_borrow_record(&mut state, 2, ICUSD::new(E8S), ICUSD::new(0), None, 0, 0);
+    // Unknown vaults get no record.
+    push_borrow_record(&mut state, 9, ICUSD::new(E8S), ICUSD::new(0), None, 0, 0);
+
+    let first = borrow_records_page(&state, 1, None, usize::MAX);
+    assert_eq!(first.records.len(), MAX_BORROW_RECORDS_PAGE);
+    let second = borrow_records_page(&state, 1, first.next_cursor, usize::MAX);
+    assert_eq!(second.records.len(), 3);
+    assert_eq!(second.records[2].block_index, total - 1);
+    assert_eq!(second.next_cursor, None);
+
+    assert_eq!(borrow_records_page(&state, 2, None, 10).records.len(), 1);
+    assert!(borrow_records_page(&state, 9, None, 10).records.is_empty());
+    assert!(borrow_records_page(&state, 1, Some(total + 5), 10)
+        .records
+        .is_empty());
+}
+
+#[test]
+fn a_vault_keeps_its_latest_records() {
+    let mut state = fresh_state();
+    state.open_vault(vault(1));
+    let total = MAX_BORROW_RECORDS_PER_VAULT as u64 + 5;
+    for block in 0..total {
+        push_borrow_record(
+            &mut state,
+            1,
+            ICUSD::new(E8S),
+            ICUSD::new(0),
+            None,
+            block,
+            0,
+        );
+    }
+    assert_eq!(state.borrow_records[&1].len(), MAX_BORROW_RECORDS_PER_VAULT);
+
+    // Reading from the start begins at the oldest record kept.
+    let page = borrow_records_page(&state, 1, None, 1);
+    assert_eq!(page.records[0].block_index, 5);
+    assert_eq!(page.next_cursor, Some(6));
+
+    // Positions keep counting: a cursor still points at the same borrow.
+    let page = borrow_records_page(&state, 1, Some(total - 1), 10);
+    assert_eq!(page.records.len(), 1);
+    assert_eq!(page.records[0].block_index, total - 1);
+    assert_eq!(page.next_cursor, None);
+}
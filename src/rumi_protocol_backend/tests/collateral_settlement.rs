@@ -0,0 +1,234 @@
+//! Collateral settlement: each vault's debt is cleared against its own
+//! collateral at the fixed price, under-water vaults leave a shortfall, the
+//! ICP and busy collaterals are refused, holders redeem pro-rata against the
+//! pool, owners claim what is left, and everything replays.
+//!
+//! ckETH is priced at $2000 next to ICP. ckETH vault #1 owes 500 icUSD on 1
+//! ETH, #2 owes 1500 icUSD on 0.5 ETH (under water) and #3 owes nothing on
+//! 1 ETH; #4 is an ICP vault.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal::Decimal;
+
+use rumi_protocol_backend::collateral_settlement::{
+    apply_claim, apply_settle, check_settleable, quote_redemption, restore_redemption,
+    settlement_price, status, take_redemption,
+};
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::UsdIcp;
+use rumi_protocol_backend::state::{CollateralStatus, State};
+use rumi_protocol_backend::vault::Vault;
+use rumi_protocol_backend::vault_store::VaultStore;
+use rumi_protocol_backend::ProtocolError;
+
+use common::{icp, init_arg_with, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+
+fn cketh() -> Principal {
+    Principal::from_slice(&[11])
+}
+
+fn alice() -> Principal {
+    Principal::from_slice(&[20])
+}
+
+fn bob() -> Principal {
+    Principal::from_slice(&[21])
+}
+
+fn vault(
+    vault_id: u64,
+    owner: Principal,
+    collateral_type: Principal,
+    collateral: u64,
+    debt: u64,
+) -> Vault {
+    VaultBuilder::new(vault_id)
+        .owner(owner)
+        .collateral(collateral)
+        .collateral_type(collateral_type)
+        .debt(debt)
+        .build()
+}
+
+fn price(usd: u64) -> UsdIcp {
+    UsdIcp::from(Decimal::from(usd))
+}
+
+fn fixture() -> State {
+    let mut state = init_arg_with()
+        .icusd_ledger(Principal::from_slice(&[1]))
+        .state();
+    set_icp_price(&mut state, 10.0, None);
+    let mut config = state.collateral_configs[&icp()].clone();
+    config.ledger_canister_id = cketh();
+    config.last_price = Some(2000.0);
+    state.collateral_configs.insert(cketh(), config);
+    state.open_vault(vault(1, alice(), cketh(), E8S, 500 * E8S));
+    state.open_vault(vault(2, bob(), cketh(), E8S / 2, 1500 * E8S));
+    state.open_vault(vault(3, alice(), cketh(), E8S, 0));
+    state.open_vault(vault(4, bob(), icp(), 100 * E8S, 50 * E8S));
+    state
+}
+
+fn settled() -> State {
+    let mut state = fixture();
+    let price = settlement_price(&state, &cketh()).unwrap();
+    apply_settle(&mut state, cketh(), price, 7);
+    state
+}
+
+#[test]
+fn settlement_clears_debt_against_collateral() {
+    let state = settled();
+    let collateral_and_debt = |vault_id: u64| {
+        let vault = &state.vault_id_to_vaults[&vault_id];
+        (
+            vault.collateral_amount,
+            vault.borrowed_icusd_amount.to_u64(),
+        )
+    };
+    assert_eq!(collateral_and_debt(1), (3 * E8S / 4, 0));
+    assert_eq!(collateral_and_debt(2), (0, 0));
+    assert_eq!(collateral_and_debt(3), (E8S, 0));
+    assert_eq!(collateral_and_debt(4), (100 * E8S, 50 * E8S));
+    assert_eq!(
+        state.collateral_configs[&cketh()].status,
+        CollateralStatus::Frozen
+    );
+
+    let status = status(&state).pop().unwrap();
+    assert_eq!(status.collateral_type, cketh());
+    assert_eq!(status.price, 2000.0);
+    assert_eq!(status.settled_at, 7);
+    assert_eq!(status.vaults_settled, 3);
+    assert_eq!(status.debt_settled, 2000 * E8S);
+    assert_eq!(status.collateral_pooled, 3 * E8S / 4);
+    assert_eq!(status.shortfall, 500 * E8S);
+    assert_eq!(status.debt_outstanding, 2000 * E8S);
+    assert_eq!(status.vaults_open, 3);
+}
+
+#[test]
+fn the_icp_busy_and_settled_collaterals_are_refused() {
+    let mut state = fixture();
+    assert!(check_settleable(&state, &icp()).is_err());
+    assert!(check_settleable(&state, &Principal::from_slice(&[99])).is_err());
+
+    state.vault_id_to_vaults.get_mut(&2).unwrap().bot_processing = true;
+    assert!(matches!(
+        check_settleable(&state, &cketh()),
+        Err(ProtocolError::TemporarilyUnavailable(_))
+    ));
+    state.vault_id_to_vaults.get_mut(&2).unwrap().bot_processing = false;
+    assert!(check_settleable(&state, &cketh()).is_ok());
+
+    apply_settle(&mut state, cketh(), price(2000), 7);
+    assert!(check_settleable(&state, &cketh()).is_err());
+}
+
+#[test]
+fn holders_redeem_pro_rata() {
+    let mut state = settled();
+    // 0.75 ETH backs 2000 icUSD: 0.0375 ETH per 100 icUSD.
+    let quote = quote_redemption(&state, &cketh(), 200 * E8S).unwrap();
+    assert_eq!(quote, 3 * E8S / 40);
+    take_redemption(&mut state, &cketh(), 200 * E8S, quote);
+    assert_eq!(
+        quote_redemption(&state, &cketh(), 200 * E8S).unwrap(),
+        3 * E8S / 40
+    );
+    assert!(quote_redemption(&state, &cketh(), 1800 * E8S + 1).is_err());
+    assert!(quote_redemption(&state, &icp(), E8S).is_err());
+
+    restore_redemption(&mut state, &cketh(), 200 * E8S, quote);
+    let status = status(&state).pop().unwrap();
+    assert_eq!((status.icusd_redeemed, status.collateral_redeemed), (0, 0));
+
+    // The last icUSD out takes whatever the pool still holds.
+    take_redemption(&mut state, &cketh(), 1999 * E8S, 7);
+    assert_eq!(
+        quote_redemption(&state, &cketh(), E8S).unwrap(),
+        3 * E8S / 4 - 7
+    );
+}
+
+#[test]
+fn an_owner_claims_what_is_left() {
+    let mut state = settled();
+    assert_eq!(apply_claim(&mut state, 1), 3 * E8S / 4);
+    assert!(!state.vault_id_to_vaults.contains_key(&1));
+    assert_eq!(state.vault_ids_of(&alice()), vec![3]);
+    assert_eq!(apply_claim(&mut state, 2), 0);
+
+    let status = status(&state).pop().unwrap();
+    assert_eq!(status.vaults_claimed, 2);
+    assert_eq!(status.collateral_claimed, 3 * E8S / 4);
+    assert_eq!(status.vaults_open, 1);
+    // Claims leave the pool alone.
+    assert_eq!(status.pool_remaining, 3 * E8S / 4);
+}
+
+#[test]
+fn settlement_replays() {
+    // Replay applies what the log holds; refusing the ICP collateral is the
+    // live path's check.
+    let state = replay(
+        vec![
+            Event::Init(
+                init_arg_with()
+                    .icusd_ledger(Principal::from_slice(&[1]))
+                    .build(),
+            ),
+            Event::OpenVault {
+                vault: vault(1, alice(), icp(), 100 * E8S, 500 * E8S),
+                block_index: 0,
+                timestamp: None,
+            },
+            Event::OpenVault {
+                vault: vault(2, bob(), icp(), 10 * E8S, 200 * E8S),
+                block_index: 1,
+                timestamp: None,
+            },
+            Event::CollateralSettled {
+                collateral_type: icp(),
+                price: price(10),
+                settled_by: Principal::anonymous(),
+                timestamp: 7,
+            },
+            Event::SettledVaultClaimed {
+                vault_id: 1,
+                owner: alice(),
+                collateral_type: icp(),
+                collateral_amount: 50 * E8S,
+                timestamp: 8,
+            },
+            Event::SettledCollateralRedeemed {
+                redeemer: bob(),
+                collateral_type: icp(),
+                icusd_amount: 70 * E8S,
+                collateral_amount: 6 * E8S,
+                icusd_block_index: 2,
+                timestamp: 9,
+            },
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    let status = status(&state).pop().unwrap();
+    assert_eq!(status.debt_settled, 700 * E8S);
+    assert_eq!(status.collateral_pooled, 60 * E8S);
+    assert_eq!(status.shortfall, 100 * E8S);
+    assert_eq!(status.vaults_claimed, 1);
+    assert_eq!(status.collateral_claimed, 50 * E8S);
+    assert_eq!(status.debt_outstanding, 630 * E8S);
+    assert_eq!(status.pool_remaining, 54 * E8S);
+    assert_eq!(
+        state.collateral_configs[&icp()].status,
+        CollateralStatus::Frozen
+    );
+    assert_eq!(state.vault_ids(), vec![2]);
+}
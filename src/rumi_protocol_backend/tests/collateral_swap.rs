@@ -0,0 +1,252 @@
+//! In-place collateral swap: routes are validated and replaced per pair, a
+//! swap plan is refused for anything but the owner of a vault with a
+//! whitelisted route and room under the new collateral's debt ceiling, the
+//! DEX minimum is raised to what keeps the vault healthy, applying (or
+//! replaying) a swap re-keys the vault without touching its debt, and a
+//! failed swap takes its allowance fees off the vault.
+//!
+//! Besides ICP at $10 there is a second collateral, "ckBTC", at $50,000;
+//! both have 8 decimals and a 150% borrow threshold. One 10 ICP vault owes
+//! 50 icUSD.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::collateral_swap::{
+    apply_collateral_swap, apply_collateral_swap_failed, apply_set_route, find_route,
+    plan_collateral_swap, validate_route, CollateralSwapRoute, SwapVaultCollateralArg,
+};
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::state::{CollateralStatus, Mode, State};
+use rumi_protocol_backend::vault::Vault;
+use rumi_protocol_backend::ProtocolError;
+
+use common::{fresh_state, icp, init_arg, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+
+fn ckbtc() -> Principal {
+    Principal::from_slice(&[20])
+}
+
+fn owner() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn dex() -> Principal {
+    Principal::from_slice(&[40])
+}
+
+fn route() -> CollateralSwapRoute {
+    CollateralSwapRoute {
+        from: icp(),
+        to: ckbtc(),
+        dex: dex(),
+        pool_id: "icp_ckbtc".to_string(),
+    }
+}
+
+fn vault() -> Vault {
+    VaultBuilder::new(1)
+        .owner(owner())
+        .collateral(10 * E8S)
+        .debt(50 * E8S)
+        .build()
+}
+
+fn arg(min_amount_out: u64) -> SwapVaultCollateralArg {
+    SwapVaultCollateralArg {
+        vault_id: 1,
+        new_collateral_type: ckbtc(),
+        min_amount_out,
+    }
+}
+
+fn add_ckbtc(state: &mut State) {
+    let mut cfg = state.collateral_configs.get(&icp()).cloned().unwrap();
+    cfg.ledger_canister_id = ckbtc();
+    cfg.ledger_fee = 10;
+    cfg.status = CollateralStatus::Active;
+    cfg.last_price = Some(50_000.0);
+    cfg.last_price_timestamp = Some(1);
+    state.collateral_configs.insert(ckbtc(), cfg);
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 10.0, None);
+    add_ckbtc(&mut state);
+    state.open_vault(vault());
+    apply_set_route(&mut state, icp(), ckbtc(), Some(route()));
+    state
+}
+
+#[test]
+fn routes_are_validated() {
+    let state = fixture();
+    assert!(validate_route(&state, &route()).is_ok());
+
+    let same = CollateralSwapRoute {
+        to: icp(),
+        ..route()
+    };
+    assert!(validate_route(&state, &same).is_err());
+    let unknown = CollateralSwapRoute {
+        to: Principal::from_slice(&[99]),
+        ..route()
+    };
+    assert!(validate_route(&state, &unknown).is_err());
+    let anonymous = CollateralSwapRoute {
+        dex: Principal::anonymous(),
+        ..route()
+    };
+    assert!(validate_route(&state, &anonymous).is_err());
+    let no_pool = CollateralSwapRoute {
+        pool_id: String::new(),
+        ..route()
+    };
+    assert!(validate_route(&state, &no_pool).is_err());
+}
+
+#[test]
+fn setting_a_route_replaces_or_removes_it() {
+    let mut state = fixture();
+    let moved = CollateralSwapRoute {
+        dex: Principal::from_slice(&[41]),
+        ..route()
+    };
+    apply_set_route(&mut state, icp(), ckbtc(), Some(moved.clone()));
+    assert_eq!(state.collateral_swap_routes, vec![moved]);
+    assert!(find_route(&state, &ckbtc(), &icp()).is_none());
+
+    apply_set_route(&mut state, icp(), ckbtc(), None);
+    assert!(state.collateral_swap_routes.is_empty());
+}
+
+#[test]
+fn plan_raises_the_minimum_to_the_required_ratio() {
+    let state = fixture();
+    let plan = plan_collateral_swap(&state, owner(), &arg(0), 1).expect("plan");
+    assert_eq!(plan.from, icp());
+    assert_eq!(plan.to, ckbtc());
+    assert_eq!(plan.debt, 50 * E8S);
+    // Approve and transfer-from fees on the ICP ledger.
+    assert_eq!(plan.amount_in, 10 * E8S - 20_000);
+    assert_eq!(plan.allowance, 10 * E8S - 10_000);
+    // 150% of 50 icUSD is $75, i.e. 0.0015 ckBTC, plus one unit of rounding.
+    assert_eq!(plan.min_amount_out, 150_001);
+    assert_eq!(plan.from_ledger_fee, 10_000);
+    assert_eq!(plan.to_ledger_fee, 10);
+
+    // A stricter owner floor wins.
+    let plan = plan_collateral_swap(&state, owner(), &arg(1_000_000), 1).expect("plan");
+    assert_eq!(plan.min_amount_out, 1_000_000);
+}
+
+#[test]
+fn plan_is_refused_without_owner_route_or_headroom() {
+    let state = fixture();
+    assert!(matches!(
+        plan_collateral_swap(&state, Principal::from_slice(&[2]), &arg(0), 1),
+        Err(ProtocolError::CallerNotOwner)
+    ));
+
+    let mut no_route = fixture();
+    apply_set_route(&mut no_route, icp(), ckbtc(), None);
+    assert!(plan_collateral_swap(&no_route, owner(), &arg(0), 1).is_err());
+
+    let mut read_only = fixture();
+    read_only.mode = Mode::ReadOnly;
+    assert!(plan_collateral_swap(&read_only, owner(), &arg(0), 1).is_err());
+
+    let mut capped = fixture();
+    capped
+        .collateral_configs
+        .get_mut(&ckbtc())
+        .unwrap()
+        .debt_ceiling = 10 * E8S;
+    assert!(plan_collateral_swap(&capped, owner(), &arg(0), 1).is_err());
+
+    let mut unpriced = fixture();
+    unpriced
+        .collateral_configs
+        .get_mut(&ckbtc())
+        .unwrap()
+        .last_price = None;
+    assert!(plan_collateral_swap(&unpriced, owner(), &arg(0), 1).is_err());
+}
+
+#[test]
+fn applying_a_swap_rekeys_the_vault() {
+    let mut state = fixture();
+    apply_collateral_swap(&mut state, 1, ckbtc(), 200_000);
+
+    let vault = &state.vault_id_to_vaults[&1];
+    assert_eq!(vault.collateral_type, ckbtc());
+    assert_eq!(vault.collateral_amount, 200_000);
+    assert_eq!(vault.borrowed_icusd_amount, ICUSD::new(50 * E8S));
+    assert!(state.collateral_to_vault_ids[&ckbtc()].contains(&1));
+    assert!(!state
+        .collateral_to_vault_ids
+        .get(&icp())
+        .is_some_and(|ids| ids.contains(&1)));
+    assert_eq!(state.total_debt_for_collateral(&icp()), ICUSD::new(0));
+    assert_eq!(
+        state.total_debt_for_collateral(&ckbtc()),
+        ICUSD::new(50 * E8S)
+    );
+
+    // Unknown vault: nothing happens.
+    apply_collateral_swap(&mut state, 7, icp(), 1);
+    assert!(!state.vault_id_to_vaults.contains_key(&7));
+}
+
+#[test]
+fn a_failed_swap_takes_its_fees_off_the_vault() {
+    let mut state = fixture();
+    // Grant and revoke, at the ICP ledger fee each.
+    apply_collateral_swap_failed(&mut state, 1, 20_000);
+
+    let vault = &state.vault_id_to_vaults[&1];
+    assert_eq!(vault.collateral_type, icp());
+    assert_eq!(vault.collateral_amount, 10 * E8S - 20_000);
+    assert_eq!(vault.borrowed_icusd_amount, ICUSD::new(50 * E8S));
+
+    // Unknown vault: nothing happens.
+    apply_collateral_swap_failed(&mut state, 7, 20_000);
+    assert!(!state.vault_id_to_vaults.contains_key(&7));
+}
+
+#[test]
+fn replay_rebuilds_routes_and_swapped_vaults() {
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::SetCollateralSwapRoute {
+            from_collateral_type: icp(),
+            to_collateral_type: ckbtc(),
+            route: Some(route()),
+        },
+        Event::OpenVault {
+            vault: vault(),
+            block_index: 0,
+            timestamp: None,
+        },
+        Event::VaultCollateralSwapped {
+            vault_id: 1,
+            from_collateral_type: icp(),
+            to_collateral_type: ckbtc(),
+            amount_in: 10 * E8S - 20_000,
+            amount_out: 200_000,
+            dex: dex(),
+            timestamp: 5,
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(state.collateral_swap_routes, vec![route()]);
+    let vault = &state.vault_id_to_vaults[&1];
+    assert_eq!(vault.collateral_type, ckbtc());
+    assert_eq!(vault.collateral_amount, 200_000);
+    assert!(state.collateral_to_vault_ids[&ckbtc()].contains(&1));
+}
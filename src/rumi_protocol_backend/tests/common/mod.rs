@@ -0,0 +1,157 @@
+//! Fixtures shared by the integration tests: a protocol whose only
+//! collateral is ICP, as `InitArg` configures it, with no fee, treasury,
+//! stability pool or reserve stables.
+//!
+//! `init_arg_with` sets the ledgers and principals a test needs on top of
+//! that, `VaultBuilder` makes vaults, and `set_icp_price` prices ICP.
+#![allow(dead_code)]
+
+use candid::Principal;
+
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::Vault;
+use rumi_protocol_backend::InitArg;
+
+/// The ICP ledger, and so the ICP collateral type.
+pub fn icp() -> Principal {
+    Principal::from_slice(&[10])
+}
+
+pub fn init_arg() -> InitArg {
+    InitArg {
+        xrc_principal: Principal::anonymous(),
+        icusd_ledger_principal: Principal::anonymous(),
+        icp_ledger_principal: icp(),
+        fee_e8s: 0,
+        developer_principal: Principal::anonymous(),
+        treasury_principal: None,
+        stability_pool_principal: None,
+        ckusdt_ledger_principal: None,
+        ckusdc_ledger_principal: None,
+    }
+}
+
+pub fn fresh_state() -> State {
+    State::from(init_arg())
+}
+
+/// `init_arg` with the principals a test sets itself, e.g.
+/// `init_arg_with().icusd_ledger(icusd()).build()`.
+pub fn init_arg_with() -> InitArgBuilder {
+    InitArgBuilder(init_arg())
+}
+
+#[derive(Clone, Debug)]
+pub struct InitArgBuilder(InitArg);
+
+impl InitArgBuilder {
+    pub fn icusd_ledger(mut self, ledger: Principal) -> Self {
+        self.0.icusd_ledger_principal = ledger;
+        self
+    }
+
+    /// Also the ICP collateral type.
+    pub fn icp_ledger(mut self, ledger: Principal) -> Self {
+        self.0.icp_ledger_principal = ledger;
+        self
+    }
+
+    pub fn developer(mut self, developer: Principal) -> Self {
+        self.0.developer_principal = developer;
+        self
+    }
+
+    pub fn treasury(mut self, treasury: Principal) -> Self {
+        self.0.treasury_principal = Some(treasury);
+        self
+    }
+
+    pub fn stability_pool(mut self, pool: Principal) -> Self {
+        self.0.stability_pool_principal = Some(pool);
+        self
+    }
+
+    pub fn ckusdt_ledger(mut self, ledger: Principal) -> Self {
+        self.0.ckusdt_ledger_principal = Some(ledger);
+        self
+    }
+
+    pub fn ckusdc_ledger(mut self, ledger: Principal) -> Self {
+        self.0.ckusdc_ledger_principal = Some(ledger);
+        self
+    }
+
+    pub fn build(self) -> InitArg {
+        self.0
+    }
+
+    /// `State::from` the built `InitArg`.
+    pub fn state(self) -> State {
+        State::from(self.0)
+    }
+}
+
+/// A vault of ICP collateral owned by the anonymous principal, with no
+/// collateral, debt or accrued interest until set, e.g.
+/// `VaultBuilder::new(1).owner(alice()).collateral(E8S).debt(5 * E8S).build()`.
+#[derive(Clone, Debug)]
+pub struct VaultBuilder(Vault);
+
+impl VaultBuilder {
+    pub fn new(vault_id: u64) -> Self {
+        Self(Vault {
+            owner: Principal::anonymous(),
+            vault_id,
+            collateral_amount: 0,
+            borrowed_icusd_amount: ICUSD::new(0),
+            collateral_type: icp(),
+            last_accrual_time: 0,
+            accrued_interest: ICUSD::new(0),
+            bot_processing: false,
+        })
+    }
+
+    pub fn owner(mut self, owner: Principal) -> Self {
+        self.0.owner = owner;
+        self
+    }
+
+    /// In the collateral's native units.
+    pub fn collateral(mut self, amount: u64) -> Self {
+        self.0.collateral_amount = amount;
+        self
+    }
+
+    pub fn collateral_type(mut self, collateral_type: Principal) -> Self {
+        self.0.collateral_type = collateral_type;
+        self
+    }
+
+    /// Borrowed icUSD, in e8s.
+    pub fn debt(mut self, debt_e8s: u64) -> Self {
+        self.0.borrowed_icusd_amount = ICUSD::new(debt_e8s);
+        self
+    }
+
+    pub fn accrued_interest(mut self, interest_e8s: u64) -> Self {
+        self.0.accrued_interest = ICUSD::new(interest_e8s);
+        self
+    }
+
+    pub fn last_accrual_time(mut self, at: u64) -> Self {
+        self.0.last_accrual_time = at;
+        self
+    }
+
+    pub fn build(self) -> Vault {
+        self.0
+    }
+}
+
+/// Price ICP at `price`, fetched at `at`.
+pub fn set_icp_price(state: &mut State, price: f64, at: Option<u64>) {
+    let config = state.collateral_configs.get_mut(&icp()).unwrap();
+    config.last_price = Some(price);
+    config.last_price_timestamp = at;
+}
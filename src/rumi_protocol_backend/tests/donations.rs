@@ -0,0 +1,238 @@
+//! Donations to the surplus buffer: icUSD repays the deficit before it
+//! reaches the buffer, the icUSD buffer absorbs later deficit accruals, the
+//! buffer and per-donor totals replay from `Donation` events, and donations
+//! under the minimum or into an unknown ledger are refused.
+//!
+//! Donations go to a distinct icUSD ledger and come from two donors.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::donations::{
+    check, deficit_share, donation_totals, minimum_donation, surplus_buffer, DonateArg,
+    DonationTotal, SurplusBufferEntry, MAX_DONATION_MEMO_LEN, MIN_DONATION_FEE_MULTIPLE,
+};
+use rumi_protocol_backend::event::{
+    record_deficit_accrued, replay, DeficitSource, Event, FeeSource,
+};
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::ProtocolError;
+
+use common::{icp, init_arg_with};
+
+const E8S: u64 = 100_000_000;
+
+fn icusd() -> Principal {
+    Principal::from_slice(&[20])
+}
+
+fn alice() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn bob() -> Principal {
+    Principal::from_slice(&[2])
+}
+
+fn donation(donor: Principal, ledger: Principal, amount: u64, deficit_repaid: u64) -> Event {
+    Event::Donation {
+        donor,
+        ledger,
+        amount,
+        deficit_repaid,
+        block_index: 1,
+        memo: None,
+        timestamp: 0,
+    }
+}
+
+fn arg(ledger: Principal, amount: u64) -> DonateArg {
+    DonateArg {
+        ledger,
+        amount,
+        memo: None,
+    }
+}
+
+fn buffer(state: &State, ledger: Principal) -> u64 {
+    state
+        .surplus_buffer
+        .get(&ledger)
+        .copied()
+        .unwrap_or_default()
+}
+
+#[test]
+fn icusd_donations_repay_the_deficit_first() {
+    let mut state = init_arg_with().icusd_ledger(icusd()).state();
+    state.protocol_deficit_icusd = ICUSD::new(30 * E8S);
+    assert_eq!(deficit_share(&state, &icusd(), 50 * E8S), 30 * E8S);
+    assert_eq!(deficit_share(&state, &icusd(), 10 * E8S), 10 * E8S);
+    // Collateral never goes to the deficit.
+    assert_eq!(deficit_share(&state, &icp(), 50 * E8S), 0);
+
+    let state = replay(
+        vec![
+            Event::Init(init_arg_with().icusd_ledger(icusd()).build()),
+            Event::DeficitAccrued {
+                vault_id: 1,
+                amount: ICUSD::new(30 * E8S),
+                new_deficit: ICUSD::new(30 * E8S),
+                timestamp: 0,
+                source: None,
+            },
+            Event::DeficitRepaid {
+                amount: ICUSD::new(30 * E8S),
+                source: FeeSource::Donation,
+                remaining_deficit: ICUSD::new(0),
+                anchor_block_index: Some(1),
+                timestamp: 0,
+            },
+            donation(alice(), icusd(), 50 * E8S, 30 * E8S),
+            donation(bob(), icp(), 2 * E8S, 0),
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    assert_eq!(state.protocol_deficit_icusd, ICUSD::new(0));
+    assert_eq!(buffer(&state, icusd()), 20 * E8S);
+    assert_eq!(buffer(&state, icp()), 2 * E8S);
+    assert_eq!(
+        surplus_buffer(&state),
+        vec![
+            SurplusBufferEntry {
+                ledger: icp(),
+                balance: 2 * E8S,
+                total_donated: 2 * E8S,
+            },
+            SurplusBufferEntry {
+                ledger: icusd(),
+                balance: 20 * E8S,
+                total_donated: 50 * E8S,
+            },
+        ]
+    );
+}
+
+#[test]
+fn the_icusd_buffer_absorbs_later_deficits() {
+    let mut state = replay(
+        vec![
+            Event::Init(init_arg_with().icusd_ledger(icusd()).build()),
+            donation(alice(), icusd(), 20 * E8S, 0),
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+
+    record_deficit_accrued(
+        &mut state,
+        DeficitSource::Liquidation { vault_id: 1 },
+        ICUSD::new(15 * E8S),
+        0,
+    );
+    assert_eq!(state.protocol_deficit_icusd, ICUSD::new(0));
+    assert_eq!(buffer(&state, icusd()), 5 * E8S);
+
+    record_deficit_accrued(
+        &mut state,
+        DeficitSource::Liquidation { vault_id: 2 },
+        ICUSD::new(15 * E8S),
+        0,
+    );
+    assert_eq!(state.protocol_deficit_icusd, ICUSD::new(10 * E8S));
+    assert_eq!(buffer(&state, icusd()), 0);
+
+    // Replay takes the absorption back out of the buffer.
+    let state = replay(
+        vec![
+            Event::Init(init_arg_with().icusd_ledger(icusd()).build()),
+            donation(alice(), icusd(), 20 * E8S, 0),
+            Event::DeficitAccrued {
+                vault_id: 1,
+                amount: ICUSD::new(15 * E8S),
+                new_deficit: ICUSD::new(15 * E8S),
+                timestamp: 0,
+                source: None,
+            },
+            Event::DeficitRepaid {
+                amount: ICUSD::new(15 * E8S),
+                source: FeeSource::SurplusBuffer,
+                remaining_deficit: ICUSD::new(0),
+                anchor_block_index: None,
+                timestamp: 0,
+            },
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    assert_eq!(state.protocol_deficit_icusd, ICUSD::new(0));
+    assert_eq!(buffer(&state, icusd()), 5 * E8S);
+}
+
+#[test]
+fn donations_below_the_minimum_or_into_unknown_ledgers_are_refused() {
+    let state = init_arg_with().icusd_ledger(icusd()).state();
+    let icp_minimum = minimum_donation(&state, &icp()).unwrap();
+    assert_eq!(
+        icp_minimum,
+        state.icp_ledger_fee.to_u64() * MIN_DONATION_FEE_MULTIPLE
+    );
+    assert_eq!(
+        minimum_donation(&state, &icusd()).unwrap(),
+        state.min_icusd_amount.to_u64()
+    );
+
+    assert!(check(&state, &arg(icp(), icp_minimum)).is_ok());
+    assert!(matches!(
+        check(&state, &arg(icp(), icp_minimum - 1)),
+        Err(ProtocolError::AmountTooLow { minimum_amount }) if minimum_amount == icp_minimum
+    ));
+    assert!(matches!(
+        check(&state, &arg(Principal::from_slice(&[99]), E8S)),
+        Err(ProtocolError::GenericError(_))
+    ));
+    let long_memo = DonateArg {
+        memo: Some("x".repeat(MAX_DONATION_MEMO_LEN + 1)),
+        ..arg(icp(), icp_minimum)
+    };
+    assert!(matches!(
+        check(&state, &long_memo),
+        Err(ProtocolError::GenericError(_))
+    ));
+}
+
+#[test]
+fn totals_are_kept_per_donor_and_ledger() {
+    let state = replay(
+        vec![
+            Event::Init(init_arg_with().icusd_ledger(icusd()).build()),
+            donation(alice(), icp(), E8S, 0),
+            donation(alice(), icp(), 2 * E8S, 0),
+            donation(alice(), icusd(), 5 * E8S, 0),
+            donation(bob(), icp(), 4 * E8S, 0),
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+
+    assert_eq!(
+        donation_totals(&state, Some(alice())),
+        vec![
+            DonationTotal {
+                donor: alice(),
+                ledger: icp(),
+                amount: 3 * E8S,
+            },
+            DonationTotal {
+                donor: alice(),
+                ledger: icusd(),
+                amount: 5 * E8S,
+            },
+        ]
+    );
+    assert_eq!(donation_totals(&state, None).len(), 3);
+    assert_eq!(buffer(&state, icp()), 7 * E8S);
+}
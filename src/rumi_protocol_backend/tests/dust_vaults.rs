@@ -0,0 +1,125 @@
+//! Minimum debt and dust vaults: debt strictly between zero and the
+//! collateral's `min_vault_debt` is refused, only owed dust up to
+//! `DUST_THRESHOLD` is swept, busy or unavailable vaults are left alone, and
+//! a closure replays with its write-off.
+//!
+//! ICP's `min_vault_debt` is 0.1 icUSD, and five vaults of 1 ICP owe 50 e8s
+//! (dust), 100 e8s (dust), 101 e8s, 10 icUSD and nothing.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::dust_vaults::{apply_close, dust_vaults, is_dust};
+use rumi_protocol_backend::event::{replay, DeficitSource, Event};
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::state::{Mode, State};
+use rumi_protocol_backend::vault::{below_min_vault_debt, Vault};
+use rumi_protocol_backend::DUST_THRESHOLD;
+
+use common::{fresh_state, icp, init_arg, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+
+fn owner(vault_id: u64) -> Principal {
+    Principal::from_slice(&[vault_id as u8])
+}
+
+fn vault(vault_id: u64, debt_e8s: u64) -> Vault {
+    VaultBuilder::new(vault_id)
+        .owner(owner(vault_id))
+        .collateral(E8S)
+        .debt(debt_e8s)
+        .build()
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    state.mode = Mode::GeneralAvailability;
+    for (id, debt) in [(1, 50), (2, 100), (3, 101), (4, 10 * E8S), (5, 0)] {
+        state.open_vault(vault(id, debt));
+    }
+    state
+}
+
+#[test]
+fn debt_between_zero_and_the_minimum_is_refused() {
+    let state = fixture();
+    let vault = &state.vault_id_to_vaults[&4];
+    let min = ICUSD::new(E8S / 10);
+    assert_eq!(below_min_vault_debt(&state, vault, ICUSD::new(0)), None);
+    assert_eq!(
+        below_min_vault_debt(&state, vault, ICUSD::new(1)),
+        Some(min)
+    );
+    assert_eq!(
+        below_min_vault_debt(&state, vault, ICUSD::new(E8S / 10 - 1)),
+        Some(min)
+    );
+    assert_eq!(below_min_vault_debt(&state, vault, min), None);
+}
+
+#[test]
+fn only_owed_dust_is_swept() {
+    assert!(!is_dust(ICUSD::new(0)));
+    assert!(is_dust(ICUSD::new(1)));
+    assert!(is_dust(DUST_THRESHOLD));
+    assert!(!is_dust(DUST_THRESHOLD + ICUSD::new(1)));
+
+    assert_eq!(dust_vaults(&fixture(), 0), vec![1, 2]);
+}
+
+#[test]
+fn busy_vaults_and_an_unavailable_protocol_are_left_alone() {
+    let mut state = fixture();
+    state.principal_guards.insert(owner(1));
+    state.vault_id_to_vaults.get_mut(&2).unwrap().bot_processing = true;
+    assert!(dust_vaults(&state, 0).is_empty());
+
+    let mut state = fixture();
+    state.mode = Mode::ReadOnly;
+    assert!(dust_vaults(&state, 0).is_empty());
+}
+
+#[test]
+fn a_closure_clears_the_vault() {
+    let mut state = fixture();
+    apply_close(&mut state, 1);
+    assert!(!state.vault_id_to_vaults.contains_key(&1));
+    assert!(!state.principal_to_vault_ids.contains_key(&owner(1)));
+    assert!(!state.liquidation_index_keys.contains_key(&1));
+    assert_eq!(dust_vaults(&state, 0), vec![2]);
+}
+
+#[test]
+fn a_closure_replays_with_its_write_off() {
+    let state = replay(
+        vec![
+            Event::Init(init_arg()),
+            Event::OpenVault {
+                vault: vault(1, 50),
+                block_index: 0,
+                timestamp: None,
+            },
+            Event::DeficitAccrued {
+                vault_id: 1,
+                amount: ICUSD::new(50),
+                new_deficit: ICUSD::new(50),
+                timestamp: 0,
+                source: Some(DeficitSource::DustWriteOff { vault_id: 1 }),
+            },
+            Event::DustVaultClosed {
+                vault_id: 1,
+                owner: owner(1),
+                collateral_type: icp(),
+                collateral_amount: E8S,
+                debt: ICUSD::new(50),
+                timestamp: 0,
+            },
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    assert!(state.vault_id_to_vaults.is_empty());
+    assert_eq!(state.protocol_deficit_icusd, ICUSD::new(50));
+}
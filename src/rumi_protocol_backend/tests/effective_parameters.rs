@@ -0,0 +1,120 @@
+//! Effective parameters: each value is what the protocol's own getters
+//! resolve for the collateral, and its source follows the cascade — config
+//! values by default, Recovery-mode overrides only while in Recovery, admin
+//! overrides over protocol-wide defaults, and active campaigns as promos.
+//!
+//! Unless a test says otherwise, the ICP collateral keeps its defaults and
+//! the protocol is in General Availability.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::campaigns::{apply_create, CreateRebateCampaignArg};
+use rumi_protocol_backend::effective_parameters::{resolve, ParameterSource};
+use rumi_protocol_backend::numeric::Ratio;
+use rumi_protocol_backend::state::Mode;
+
+use common::{fresh_state, icp};
+
+const NOW: u64 = 1_000 * 1_000_000_000;
+
+#[test]
+fn config_values_by_default() {
+    let state = fresh_state();
+    let params = resolve(&state, &icp(), NOW).expect("icp");
+    let config = state.get_collateral_config(&icp()).unwrap();
+
+    assert_eq!(params.mode, Mode::GeneralAvailability);
+    assert_eq!(params.borrowing_fee.value, config.borrowing_fee.to_f64());
+    assert_eq!(params.borrowing_fee.source, ParameterSource::Config);
+    assert_eq!(params.borrowing_fee_rebate.value, 0.0);
+    assert_eq!(params.borrowing_fee_rebate.source, ParameterSource::Default);
+    assert_eq!(
+        params.liquidation_threshold.value,
+        config.liquidation_ratio.to_f64()
+    );
+    assert_eq!(
+        params.healthy_cr.value,
+        state.get_healthy_cr_for(&icp()).to_f64()
+    );
+    assert_eq!(params.healthy_cr.source, ParameterSource::Default);
+    assert_eq!(params.interest_rate_curve.source, ParameterSource::Default);
+    assert_eq!(
+        params.interest_rate_curve.markers.len(),
+        state.resolve_layer1_markers(&icp()).len()
+    );
+    assert_eq!(
+        params.min_xrc_sources.value,
+        state.min_xrc_sources_used as u64
+    );
+    assert_eq!(params.min_xrc_sources.source, ParameterSource::Default);
+    assert_eq!(params.computed_at, NOW);
+
+    assert!(resolve(&state, &Principal::from_slice(&[99]), NOW).is_none());
+}
+
+#[test]
+fn recovery_overrides_apply_only_in_recovery() {
+    let mut state = fresh_state();
+    let config = state.collateral_configs.get_mut(&icp()).unwrap();
+    config.recovery_borrowing_fee = Some(Ratio::new(dec!(0.02)));
+    config.recovery_interest_rate_apr = Some(Ratio::new(dec!(0.1)));
+
+    let params = resolve(&state, &icp(), NOW).unwrap();
+    assert_eq!(params.borrowing_fee.source, ParameterSource::Config);
+    assert_eq!(params.interest_rate_apr.source, ParameterSource::Config);
+
+    state.mode = Mode::Recovery;
+    let params = resolve(&state, &icp(), NOW).unwrap();
+    assert_eq!(params.borrowing_fee.value, 0.02);
+    assert_eq!(params.borrowing_fee.source, ParameterSource::Override);
+    assert_eq!(
+        params.borrowing_fee.value,
+        state.get_borrowing_fee_for(&icp()).to_f64()
+    );
+    assert_eq!(params.interest_rate_apr.value, 0.1);
+    assert_eq!(params.interest_rate_apr.source, ParameterSource::Override);
+    // Recovery liquidates below the borrow threshold.
+    assert_eq!(
+        params.liquidation_threshold.value,
+        state.get_min_liquidation_ratio_for(&icp()).to_f64()
+    );
+}
+
+#[test]
+fn admin_overrides_replace_defaults() {
+    let mut state = fresh_state();
+    let config = state.collateral_configs.get_mut(&icp()).unwrap();
+    config.healthy_cr = Some(Ratio::new(dec!(2.5)));
+    config.min_xrc_sources = Some(2);
+
+    let params = resolve(&state, &icp(), NOW).unwrap();
+    assert_eq!(params.healthy_cr.value, 2.5);
+    assert_eq!(params.healthy_cr.source, ParameterSource::Override);
+    assert_eq!(params.min_xrc_sources.value, 2);
+    assert_eq!(params.min_xrc_sources.source, ParameterSource::Override);
+}
+
+#[test]
+fn active_campaign_is_a_promo() {
+    let mut state = fresh_state();
+    apply_create(
+        &mut state,
+        1,
+        CreateRebateCampaignArg {
+            name: "launch".to_string(),
+            rebate_bps: 5_000,
+            start_ns: NOW,
+            end_ns: NOW + 10,
+            pool_e8s: 1_000,
+        },
+    );
+    let params = resolve(&state, &icp(), NOW).unwrap();
+    assert_eq!(params.borrowing_fee_rebate.value, 0.5);
+    assert_eq!(params.borrowing_fee_rebate.source, ParameterSource::Promo);
+
+    let params = resolve(&state, &icp(), NOW + 10).unwrap();
+    assert_eq!(params.borrowing_fee_rebate.source, ParameterSource::Default);
+}
@@ -0,0 +1,186 @@
+//! Emergency shutdown: it fixes a price for every collateral with vaults and
+//! freezes them all, a missing price refuses it, each collateral then
+//! settles at its fixed price (ICP included) until none is pending, and
+//! everything replays.
+//!
+//! ICP is at $10 and ckETH at $2000. Vault #1 owes 500 icUSD on 100 ICP and
+//! vault #2 owes 500 icUSD on 1 ETH; an unpriced collateral has no vaults.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal::Decimal;
+
+use rumi_protocol_backend::collateral_settlement::{apply_settle, check_settleable};
+use rumi_protocol_backend::emergency_shutdown::{
+    apply_shutdown, check_not_shut_down, fix_prices, pending, status,
+};
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::{UsdIcp, ICUSD};
+use rumi_protocol_backend::state::{CollateralStatus, State};
+use rumi_protocol_backend::vault::Vault;
+
+use common::{icp, init_arg_with, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+
+fn cketh() -> Principal {
+    Principal::from_slice(&[11])
+}
+
+fn unpriced() -> Principal {
+    Principal::from_slice(&[12])
+}
+
+fn alice() -> Principal {
+    Principal::from_slice(&[20])
+}
+
+fn bob() -> Principal {
+    Principal::from_slice(&[21])
+}
+
+fn vault(
+    vault_id: u64,
+    owner: Principal,
+    collateral_type: Principal,
+    collateral: u64,
+    debt: u64,
+) -> Vault {
+    VaultBuilder::new(vault_id)
+        .owner(owner)
+        .collateral(collateral)
+        .collateral_type(collateral_type)
+        .debt(debt)
+        .build()
+}
+
+fn price(usd: u64) -> UsdIcp {
+    UsdIcp::from(Decimal::from(usd))
+}
+
+fn fixture() -> State {
+    let mut state = init_arg_with()
+        .icusd_ledger(Principal::from_slice(&[1]))
+        .state();
+    set_icp_price(&mut state, 10.0, None);
+    let mut config = state.collateral_configs[&icp()].clone();
+    config.ledger_canister_id = cketh();
+    config.last_price = Some(2000.0);
+    state.collateral_configs.insert(cketh(), config.clone());
+    config.ledger_canister_id = unpriced();
+    config.last_price = None;
+    state.collateral_configs.insert(unpriced(), config);
+    state.open_vault(vault(1, alice(), icp(), 100 * E8S, 500 * E8S));
+    state.open_vault(vault(2, bob(), cketh(), E8S, 500 * E8S));
+    state
+}
+
+fn shut_down() -> State {
+    let mut state = fixture();
+    let prices = fix_prices(&state).unwrap();
+    apply_shutdown(&mut state, alice(), &prices, 7);
+    state
+}
+
+#[test]
+fn a_shutdown_fixes_prices_and_freezes_everything() {
+    let state = fixture();
+    assert!(check_not_shut_down(&state).is_ok());
+    assert_eq!(
+        fix_prices(&state).unwrap(),
+        vec![(icp(), price(10)), (cketh(), price(2000))]
+    );
+
+    let state = shut_down();
+    assert!(check_not_shut_down(&state).is_err());
+    for collateral_type in [icp(), cketh(), unpriced()] {
+        assert_eq!(
+            state.collateral_configs[&collateral_type].status,
+            CollateralStatus::Frozen
+        );
+    }
+    let status = status(&state).unwrap();
+    assert_eq!(status.triggered_at, 7);
+    assert_eq!(status.prices, vec![(icp(), 10.0), (cketh(), 2000.0)]);
+    assert_eq!(status.pending, vec![icp(), cketh()]);
+    assert!(!status.settled);
+}
+
+#[test]
+fn a_collateral_with_vaults_and_no_price_refuses_it() {
+    let mut state = fixture();
+    state
+        .collateral_configs
+        .get_mut(&cketh())
+        .unwrap()
+        .last_price = None;
+    assert!(fix_prices(&state).is_err());
+    assert_eq!(status(&state), None);
+}
+
+#[test]
+fn each_collateral_settles_at_its_fixed_price() {
+    let mut state = fixture();
+    assert!(check_settleable(&state, &icp()).is_err());
+    let prices = fix_prices(&state).unwrap();
+    apply_shutdown(&mut state, alice(), &prices, 7);
+    assert!(check_settleable(&state, &icp()).is_ok());
+
+    // A later price does not move the settlement.
+    state
+        .collateral_configs
+        .get_mut(&cketh())
+        .unwrap()
+        .last_price = Some(1000.0);
+    let fixed = state.emergency_shutdown.as_ref().unwrap().prices[&cketh()];
+    apply_settle(&mut state, cketh(), fixed, 8);
+    assert_eq!(pending(&state), vec![icp()]);
+    assert_eq!(state.vault_id_to_vaults[&2].collateral_amount, 3 * E8S / 4);
+
+    let fixed = state.emergency_shutdown.as_ref().unwrap().prices[&icp()];
+    apply_settle(&mut state, icp(), fixed, 9);
+    assert_eq!(state.vault_id_to_vaults[&1].collateral_amount, 50 * E8S);
+    assert!(status(&state).unwrap().settled);
+}
+
+#[test]
+fn a_shutdown_replays() {
+    let state = replay(
+        vec![
+            Event::Init(
+                init_arg_with()
+                    .icusd_ledger(Principal::from_slice(&[1]))
+                    .build(),
+            ),
+            Event::OpenVault {
+                vault: vault(1, alice(), icp(), 100 * E8S, 500 * E8S),
+                block_index: 0,
+                timestamp: None,
+            },
+            Event::EmergencyShutdown {
+                triggered_by: alice(),
+                prices: vec![(icp(), price(10))],
+                timestamp: 7,
+            },
+            Event::CollateralSettled {
+                collateral_type: icp(),
+                price: price(10),
+                settled_by: bob(),
+                timestamp: 8,
+            },
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    let status = status(&state).unwrap();
+    assert_eq!(status.triggered_by, alice());
+    assert!(status.settled);
+    assert_eq!(
+        state.collateral_configs[&icp()].status,
+        CollateralStatus::Frozen
+    );
+    let vault = &state.vault_id_to_vaults[&1];
+    assert_eq!(vault.borrowed_icusd_amount, ICUSD::new(0));
+    assert_eq!(vault.collateral_amount, 50 * E8S);
+}
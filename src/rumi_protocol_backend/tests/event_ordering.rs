@@ -0,0 +1,171 @@
+//! Event ordering: causality tokens must follow the log position one by
+//! one, and replaying the known interleavings of liquidations and
+//! pending-transfer payouts in log order reproduces the live vault.
+//!
+//! Events 0 and 1 give one owner a 10 ICP vault at $10 with 80 icUSD of
+//! debt. Liquidators A and B both read that vault at position 2, before
+//! awaiting the liquidator's payment.
+
+mod common;
+
+use candid::Principal;
+use ic_stable_structures::Storable;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::event::{replay, Event, ReplayLogError};
+use rumi_protocol_backend::event_ordering::{check_order, CausalityToken, OrderSummary};
+use rumi_protocol_backend::numeric::{UsdIcp, ICP, ICUSD};
+
+use common::{init_arg, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+
+fn owner() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn liquidator_a() -> Principal {
+    Principal::from_slice(&[2])
+}
+
+fn liquidator_b() -> Principal {
+    Principal::from_slice(&[3])
+}
+
+/// Events 0 and 1.
+fn fixture() -> Vec<Event> {
+    vec![
+        Event::Init(init_arg()),
+        Event::OpenVault {
+            vault: VaultBuilder::new(1)
+                .owner(owner())
+                .collateral(10 * E8S)
+                .debt(80 * E8S)
+                .build(),
+            block_index: 1,
+            timestamp: None,
+        },
+    ]
+}
+
+/// A partial liquidation of vault 1 carrying the amounts it applied.
+fn partial_liquidation(liquidator: Principal, debt_e8s: u64, collateral_e8s: u64) -> Event {
+    Event::PartialLiquidateVault {
+        vault_id: 1,
+        liquidator_payment: ICUSD::new(debt_e8s),
+        icp_to_liquidator: ICP::new(collateral_e8s),
+        liquidator: Some(liquidator),
+        icp_rate: Some(UsdIcp::from(dec!(10))),
+        protocol_fee_collateral: None,
+        timestamp: None,
+        three_usd_reserves_e8s: None,
+    }
+}
+
+#[test]
+fn synchronous_events_have_no_lag() {
+    let tokens: Vec<CausalityToken> = (5..10).map(|seq| CausalityToken::new(seq, seq)).collect();
+    assert_eq!(
+        check_order(5, tokens.clone()).unwrap(),
+        OrderSummary {
+            checked: 5,
+            interleaved: 0,
+            max_lag: 0,
+        }
+    );
+    assert!(tokens.iter().all(|t| t.interleaved().is_empty()));
+
+    // An observation past the event is clamped, and tokens survive storage.
+    let token = CausalityToken::new(7, 9);
+    assert_eq!(token.observed_seq, 7);
+    let token = CausalityToken::new(7, 4);
+    assert_eq!(CausalityToken::from_bytes(token.to_bytes()), token);
+}
+
+#[test]
+fn out_of_order_tokens_fail_the_replay_check() {
+    let token = |seq, observed_seq| CausalityToken { seq, observed_seq };
+    for (name, tokens) in [
+        ("gap", vec![token(3, 3), token(5, 5)]),
+        ("repeat", vec![token(3, 3), token(3, 3)]),
+        ("swapped", vec![token(4, 3), token(3, 3)]),
+        ("misaligned", vec![token(2, 2), token(3, 3)]),
+        ("observed ahead", vec![token(3, 3), token(4, 5)]),
+    ] {
+        assert!(
+            matches!(
+                check_order(3, tokens),
+                Err(ReplayLogError::InconsistentLog(_))
+            ),
+            "{} accepted",
+            name
+        );
+    }
+}
+
+#[test]
+fn concurrent_partial_liquidations_replay_in_log_order() {
+    // A and B both saw 80 icUSD / 10 ICP and both asked for 50 icUSD /
+    // 5.5 ICP. A's payment cleared first; B's record capped its deduction to
+    // what A left, so the vault drains instead of underflowing.
+    let mut events = fixture();
+    events.push(partial_liquidation(liquidator_a(), 50 * E8S, 55 * E8S / 10));
+    events.push(partial_liquidation(liquidator_b(), 30 * E8S, 45 * E8S / 10));
+    let tokens = [CausalityToken::new(2, 2), CausalityToken::new(3, 2)];
+
+    let summary = check_order(2, tokens).unwrap();
+    assert_eq!((summary.interleaved, summary.max_lag), (1, 1));
+    // B's observation predates exactly A's liquidation.
+    assert_eq!(tokens[1].interleaved(), 2..3);
+    assert!(matches!(
+        &events[2],
+        Event::PartialLiquidateVault {
+            liquidator: Some(l),
+            ..
+        } if *l == liquidator_a()
+    ));
+
+    let state = replay(events.into_iter()).expect("replay must succeed");
+    assert!(!state.vault_id_to_vaults.contains_key(&1));
+}
+
+#[test]
+fn margin_payout_after_a_concurrent_liquidation_replays_in_log_order() {
+    // The payout loop read A's pending margin at position 3. B's
+    // liquidation, which read the vault at 2, landed at 3 while the payout
+    // was awaited, and the payout was recorded at 4.
+    let mut events = fixture();
+    events.push(partial_liquidation(liquidator_a(), 50 * E8S, 55 * E8S / 10));
+    events.push(partial_liquidation(liquidator_b(), 20 * E8S, 22 * E8S / 10));
+    events.push(Event::MarginTransfer {
+        vault_id: 1,
+        block_index: 7,
+        timestamp: None,
+    });
+    let tokens = [
+        CausalityToken::new(2, 2),
+        CausalityToken::new(3, 2),
+        CausalityToken::new(4, 3),
+    ];
+
+    let summary = check_order(2, tokens).unwrap();
+    assert_eq!((summary.checked, summary.interleaved), (3, 2));
+    let interleaved: Vec<&Event> = tokens[2]
+        .interleaved()
+        .map(|i| &events[i as usize])
+        .collect();
+    assert!(matches!(
+        interleaved[..],
+        [Event::PartialLiquidateVault {
+            vault_id: 1,
+            liquidator: Some(l),
+            ..
+        }] if *l == liquidator_b()
+    ));
+
+    // The payout does not touch the vault; both deductions do, in order.
+    let state = replay(events.into_iter()).expect("replay must succeed");
+    let vault = &state.vault_id_to_vaults[&1];
+    assert_eq!(vault.borrowed_icusd_amount, ICUSD::new(10 * E8S));
+    assert_eq!(vault.collateral_amount, 23 * E8S / 10);
+}
@@ -0,0 +1,263 @@
+//! Fee invoices: borrowing, redemption and stable-repay fees of the
+//! principal's events land as lines in the period, other principals' and
+//! fee-free events do not, liquidation penalties come from the owner's
+//! receipts, totals are per token and cover lines dropped past the cap.
+//!
+//! The event log holds a borrow, a reserve redemption and a ckUSDT
+//! repayment by Alice, and a borrow by Bob.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event, StableRepayFee};
+use rumi_protocol_backend::fee_invoice::{
+    fee_invoice, FeeInvoiceTotal, FeeKind, FeeLineSource, MAX_FEE_INVOICE_LINES,
+};
+use rumi_protocol_backend::liquidation_receipts::LiquidationReceipt;
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::EventTimeRange;
+
+use common::{icp, init_arg_with};
+
+const E8S: u64 = 100_000_000;
+const DAY: u64 = 24 * 3600 * 1_000_000_000;
+
+fn icusd() -> Principal {
+    Principal::from_slice(&[9])
+}
+
+fn ckusdt() -> Principal {
+    Principal::from_slice(&[11])
+}
+
+fn alice() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn bob() -> Principal {
+    Principal::from_slice(&[2])
+}
+
+fn fresh() -> State {
+    replay(
+        vec![Event::Init(
+            init_arg_with()
+                .icusd_ledger(icusd())
+                .ckusdt_ledger(ckusdt())
+                .build(),
+        )]
+        .into_iter(),
+    )
+    .expect("replay")
+}
+
+fn borrow(caller: Principal, fee_e8s: u64, ts: u64) -> Event {
+    Event::BorrowFromVault {
+        vault_id: 1,
+        borrowed_amount: ICUSD::new(100 * E8S),
+        fee_amount: ICUSD::new(fee_e8s),
+        block_index: 0,
+        caller: Some(caller),
+        timestamp: Some(ts),
+        collateral_price: None,
+    }
+}
+
+fn log() -> Vec<Event> {
+    vec![
+        Event::Init(
+            init_arg_with()
+                .icusd_ledger(icusd())
+                .ckusdt_ledger(ckusdt())
+                .build(),
+        ),
+        borrow(alice(), E8S / 2, DAY),
+        borrow(bob(), E8S, DAY),
+        // Fee-free borrows have no line.
+        borrow(alice(), 0, DAY),
+        Event::ReserveRedemption {
+            owner: alice(),
+            icusd_amount: ICUSD::new(50 * E8S),
+            fee_amount: ICUSD::new(E8S / 4),
+            stable_token_ledger: ckusdt(),
+            stable_amount_sent: 49_750_000,
+            fee_stable_amount: 250_000,
+            icusd_block_index: 3,
+            timestamp: Some(2 * DAY),
+        },
+        Event::RepayToVault {
+            vault_id: 1,
+            repayed_amount: ICUSD::new(10 * E8S),
+            block_index: 4,
+            caller: Some(alice()),
+            timestamp: Some(3 * DAY),
+            stable_fee: Some(StableRepayFee {
+                ledger: ckusdt(),
+                fee_e6s: 50_000,
+            }),
+        },
+        // An icUSD repayment carries no surcharge.
+        Event::RepayToVault {
+            vault_id: 1,
+            repayed_amount: ICUSD::new(10 * E8S),
+            block_index: 5,
+            caller: Some(alice()),
+            timestamp: Some(3 * DAY),
+            stable_fee: None,
+        },
+    ]
+}
+
+fn indexed(events: Vec<Event>) -> impl Iterator<Item = (u64, Event)> {
+    events
+        .into_iter()
+        .enumerate()
+        .map(|(idx, event)| (idx as u64, event))
+}
+
+fn receipt(id: u64, bonus_paid: u64, created_at_ns: u64) -> LiquidationReceipt {
+    LiquidationReceipt {
+        id,
+        vault_id: 2,
+        collateral_type: icp(),
+        liquidator: Some(bob()),
+        price_usd: 10.0,
+        collateral_ratio_at_trigger: 1.3,
+        liquidation_ratio: 1.33,
+        debt_repaid_e8s: 50 * E8S,
+        collateral_seized: 5 * E8S + bonus_paid,
+        bonus_paid,
+        protocol_fee: bonus_paid / 2,
+        residual_returned: 0,
+        residual_rebated: 0,
+        debt_remaining_e8s: 0,
+        collateral_remaining: 0,
+        created_at_ns,
+    }
+}
+
+fn period(start_ns: u64, end_ns: u64) -> EventTimeRange {
+    EventTimeRange { start_ns, end_ns }
+}
+
+#[test]
+fn the_principals_fees_are_itemized_per_event() {
+    let state = fresh();
+    let invoice = fee_invoice(&state, alice(), period(0, 10 * DAY), indexed(log()));
+
+    let lines: Vec<_> = invoice
+        .lines
+        .iter()
+        .map(|l| (l.kind, l.source, l.vault_id, l.token, l.amount))
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            (
+                FeeKind::Borrowing,
+                FeeLineSource::Event(1),
+                Some(1),
+                icusd(),
+                E8S / 2
+            ),
+            (
+                FeeKind::Redemption,
+                FeeLineSource::Event(4),
+                None,
+                icusd(),
+                E8S / 4
+            ),
+            (
+                FeeKind::StableRepay,
+                FeeLineSource::Event(5),
+                Some(1),
+                ckusdt(),
+                50_000
+            ),
+        ]
+    );
+    assert_eq!(
+        invoice.totals,
+        vec![
+            FeeInvoiceTotal {
+                token: icusd(),
+                amount: 3 * E8S / 4,
+                lines: 2,
+            },
+            FeeInvoiceTotal {
+                token: ckusdt(),
+                amount: 50_000,
+                lines: 1,
+            },
+        ]
+    );
+    assert!(!invoice.lines_truncated);
+
+    let bob_invoice = fee_invoice(&state, bob(), period(0, 10 * DAY), indexed(log()));
+    assert_eq!(bob_invoice.lines.len(), 1);
+    assert_eq!(bob_invoice.totals[0].amount, E8S);
+}
+
+#[test]
+fn only_fees_inside_the_period_are_invoiced() {
+    let state = fresh();
+    let invoice = fee_invoice(&state, alice(), period(2 * DAY, 2 * DAY), indexed(log()));
+    assert_eq!(invoice.lines.len(), 1);
+    assert_eq!(invoice.lines[0].kind, FeeKind::Redemption);
+    assert_eq!(invoice.period, period(2 * DAY, 2 * DAY));
+}
+
+#[test]
+fn liquidation_penalties_come_from_the_owners_receipts() {
+    let mut state = fresh();
+    state.liquidation_receipts.insert(
+        alice(),
+        vec![
+            receipt(7, E8S / 10, 4 * DAY),
+            // An underwater vault paid no bonus.
+            receipt(8, 0, 4 * DAY),
+            receipt(9, E8S / 10, 20 * DAY),
+        ],
+    );
+
+    let invoice = fee_invoice(&state, alice(), period(0, 10 * DAY), indexed(log()));
+    let penalty = invoice.lines.last().unwrap();
+    assert_eq!(penalty.kind, FeeKind::LiquidationPenalty);
+    assert_eq!(penalty.source, FeeLineSource::LiquidationReceipt(7));
+    assert_eq!((penalty.token, penalty.amount), (icp(), E8S / 10));
+    assert_eq!(invoice.lines.len(), 4);
+
+    // Bob liquidated the vault; the penalty is not his.
+    let bob_invoice = fee_invoice(&state, bob(), period(0, 10 * DAY), indexed(log()));
+    assert!(bob_invoice
+        .lines
+        .iter()
+        .all(|l| l.kind != FeeKind::LiquidationPenalty));
+}
+
+#[test]
+fn totals_cover_lines_past_the_cap() {
+    let state = fresh();
+    let count = MAX_FEE_INVOICE_LINES as u64 + 5;
+    let events: Vec<Event> = std::iter::once(Event::Init(
+        init_arg_with()
+            .icusd_ledger(icusd())
+            .ckusdt_ledger(ckusdt())
+            .build(),
+    ))
+    .chain((1..=count).map(|i| borrow(alice(), 1, i)))
+    .collect();
+
+    let invoice = fee_invoice(&state, alice(), period(0, u64::MAX), indexed(events));
+    assert!(invoice.lines_truncated);
+    assert_eq!(invoice.lines.len(), MAX_FEE_INVOICE_LINES);
+    // The newest lines are kept.
+    assert_eq!(invoice.lines.last().unwrap().timestamp, count);
+    assert_eq!(invoice.lines[0].timestamp, 6);
+    assert_eq!(
+        (invoice.totals[0].amount, invoice.totals[0].lines),
+        (count, count)
+    );
+}
@@ -0,0 +1,188 @@
+//! Fee sponsorship: only small operations by verified owners are sponsored,
+//! each user's daily count resets with the UTC day, the pool never pays past
+//! its balance, returns to the treasury take the fee too, and config,
+//! verification, funding and sponsored fees replay from events.
+//!
+//! ICP is at $10 with a ledger fee of 10_000, and two users fund and draw
+//! on sponsorship.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::fee_sponsorship::{
+    allowance, apply_funding, apply_sponsored, minimum_funding, restore_return, sponsored_fee,
+    take_for_return, FeeSponsorshipConfig, MIN_FUNDING_FEE_MULTIPLE,
+};
+use rumi_protocol_backend::state::State;
+
+use common::{icp, init_arg};
+
+const E8S: u64 = 100_000_000;
+const FEE: u64 = 10_000;
+const DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+fn alice() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn bob() -> Principal {
+    Principal::from_slice(&[2])
+}
+
+fn config(daily_operations_per_user: u32) -> FeeSponsorshipConfig {
+    FeeSponsorshipConfig {
+        enabled: true,
+        // $5
+        max_operation_value_e8s: 5 * E8S,
+        daily_operations_per_user,
+    }
+}
+
+fn fixture(daily_operations_per_user: u32, funded: u64) -> State {
+    let mut state = replay(
+        vec![
+            Event::Init(init_arg()),
+            Event::SetFeeSponsorshipConfig {
+                config: config(daily_operations_per_user),
+            },
+            Event::SetFeeSponsorshipVerified {
+                user: alice(),
+                verified: true,
+            },
+            Event::FundFeeSponsorship {
+                funder: bob(),
+                ledger: icp(),
+                amount: funded,
+                block_index: 1,
+                timestamp: 0,
+            },
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    let config = state.collateral_configs.get_mut(&icp()).unwrap();
+    config.ledger_fee = FEE;
+    config.last_price = Some(10.0);
+    state
+}
+
+/// `user` topping up their own vault with `amount` at `now`.
+fn fee_for(state: &State, user: Principal, amount: u64, now: u64) -> Option<u64> {
+    sponsored_fee(state, user, user, &icp(), &icp(), amount, now)
+}
+
+#[test]
+fn only_small_operations_by_verified_owners_are_sponsored() {
+    let mut state = fixture(3, 100 * FEE);
+
+    // 0.5 ICP is $5, at the limit; 0.6 ICP is over it.
+    assert_eq!(fee_for(&state, alice(), E8S / 2, 0), Some(FEE));
+    assert_eq!(fee_for(&state, alice(), 6 * E8S / 10, 0), None);
+    // Bob is not verified, and Alice does not get sponsored on Bob's vault.
+    assert_eq!(fee_for(&state, bob(), E8S / 10, 0), None);
+    assert_eq!(
+        sponsored_fee(&state, alice(), bob(), &icp(), &icp(), E8S / 10, 0),
+        None
+    );
+
+    state.fee_sponsorship_config.as_mut().unwrap().enabled = false;
+    assert_eq!(fee_for(&state, alice(), E8S / 10, 0), None);
+    assert_eq!(allowance(&state, &alice(), 0).remaining_today, 0);
+}
+
+#[test]
+fn the_daily_cap_resets_with_the_day_and_the_pool_runs_dry() {
+    let mut state = fixture(2, 3 * FEE);
+    for _ in 0..2 {
+        assert_eq!(fee_for(&state, alice(), E8S / 10, DAY + 1), Some(FEE));
+        apply_sponsored(&mut state, alice(), icp(), FEE, DAY + 1);
+    }
+    assert_eq!(fee_for(&state, alice(), E8S / 10, DAY + 2), None);
+    let today = allowance(&state, &alice(), DAY + 2);
+    assert!(today.verified);
+    assert_eq!((today.used_today, today.remaining_today), (2, 0));
+
+    // A new day, one fee left in the pool.
+    assert_eq!(allowance(&state, &alice(), 2 * DAY).used_today, 0);
+    apply_sponsored(&mut state, alice(), icp(), FEE, 2 * DAY);
+    assert_eq!(fee_for(&state, alice(), E8S / 10, 2 * DAY), None);
+
+    let pool = &state.fee_sponsorship_pools[&icp()];
+    assert_eq!(pool.balance, 0);
+    assert_eq!(pool.total_sponsored, 3 * FEE);
+    assert_eq!(pool.operations, 3);
+}
+
+#[test]
+fn returns_take_the_fee_and_fundings_have_a_minimum() {
+    let mut state = fixture(1, 10 * FEE);
+    assert!(take_for_return(&mut state, icp(), 10 * FEE, FEE).is_err());
+    take_for_return(&mut state, icp(), 9 * FEE, FEE).unwrap();
+    assert_eq!(state.fee_sponsorship_pools[&icp()].balance, 0);
+    restore_return(&mut state, icp(), 9 * FEE, FEE);
+    let pool = &state.fee_sponsorship_pools[&icp()];
+    assert_eq!((pool.balance, pool.total_returned), (10 * FEE, 0));
+
+    apply_funding(&mut state, icp(), 5 * FEE);
+    assert_eq!(state.fee_sponsorship_pools[&icp()].total_funded, 15 * FEE);
+    assert_eq!(
+        minimum_funding(&state, &icp()).unwrap(),
+        FEE * MIN_FUNDING_FEE_MULTIPLE
+    );
+    assert!(minimum_funding(&state, &Principal::from_slice(&[99])).is_err());
+}
+
+#[test]
+fn sponsorship_replays_from_events() {
+    let state = replay(
+        vec![
+            Event::Init(init_arg()),
+            Event::SetFeeSponsorshipConfig { config: config(5) },
+            Event::SetFeeSponsorshipVerified {
+                user: alice(),
+                verified: true,
+            },
+            Event::SetFeeSponsorshipVerified {
+                user: bob(),
+                verified: true,
+            },
+            Event::SetFeeSponsorshipVerified {
+                user: bob(),
+                verified: false,
+            },
+            Event::FundFeeSponsorship {
+                funder: bob(),
+                ledger: icp(),
+                amount: 100 * FEE,
+                block_index: 1,
+                timestamp: 0,
+            },
+            Event::FeeSponsored {
+                user: alice(),
+                vault_id: 1,
+                ledger: icp(),
+                fee: FEE,
+                timestamp: DAY,
+            },
+            Event::ReturnFeeSponsorship {
+                ledger: icp(),
+                amount: 50 * FEE,
+                fee: FEE,
+                block_index: 2,
+                timestamp: DAY,
+            },
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+
+    assert_eq!(state.fee_sponsorship_config, Some(config(5)));
+    assert!(state.fee_sponsorship_verified.contains(&alice()));
+    assert!(!state.fee_sponsorship_verified.contains(&bob()));
+    let pool = &state.fee_sponsorship_pools[&icp()];
+    assert_eq!(pool.balance, 48 * FEE);
+    assert_eq!(pool.total_returned, 51 * FEE);
+    assert_eq!(allowance(&state, &alice(), DAY).used_today, 1);
+}
@@ -0,0 +1,160 @@
+//! Flash mints: the config is validated, the fee rounds up, only
+//! allowlisted callbacks may borrow and only up to the cap, and replay drops
+//! a callback that defaulted from the allowlist while its deficit comes back
+//! from the `DeficitAccrued` that followed.
+//!
+//! Flash mints cost 9 bps, are capped at 1,000 icUSD and may call back into
+//! two allowlisted canisters.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, DeficitSource, Event};
+use rumi_protocol_backend::flash_mint::{
+    apply_default, apply_set_config, check, fee_e8s, validate_config, FlashMintConfig,
+};
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::ProtocolError;
+
+use common::{fresh_state, init_arg};
+
+const E8S: u64 = 100_000_000;
+const NOW: u64 = 1_700_000_000 * 1_000_000_000;
+
+fn arbitrage_bot() -> Principal {
+    Principal::from_slice(&[21])
+}
+
+fn liquidator() -> Principal {
+    Principal::from_slice(&[22])
+}
+
+fn config() -> FlashMintConfig {
+    FlashMintConfig {
+        fee_bps: 9,
+        max_amount_e8s: 1_000 * E8S,
+        allowed_callbacks: vec![arbitrage_bot(), liquidator()],
+    }
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    apply_set_config(&mut state, Some(config()));
+    state
+}
+
+#[test]
+fn configs_are_validated() {
+    assert!(validate_config(&config()).is_ok());
+    for bad in [
+        FlashMintConfig {
+            fee_bps: 1_001,
+            ..config()
+        },
+        FlashMintConfig {
+            max_amount_e8s: 0,
+            ..config()
+        },
+    ] {
+        assert!(validate_config(&bad).is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn the_fee_rounds_up() {
+    assert_eq!(fee_e8s(&config(), 1_000 * E8S), 90_000_000);
+    assert_eq!(fee_e8s(&config(), 1), 1);
+    let free = FlashMintConfig {
+        fee_bps: 0,
+        ..config()
+    };
+    assert_eq!(fee_e8s(&free, 1_000 * E8S), 0);
+}
+
+#[test]
+fn only_allowlisted_callbacks_may_borrow_up_to_the_cap() {
+    let state = fixture();
+    assert!(matches!(
+        check(&state, &arbitrage_bot(), 100 * E8S),
+        Ok(9_000_000)
+    ));
+    assert!(matches!(
+        check(&state, &liquidator(), 1_000 * E8S),
+        Ok(90_000_000)
+    ));
+
+    let stranger = Principal::from_slice(&[23]);
+    assert!(matches!(
+        check(&state, &stranger, E8S),
+        Err(ProtocolError::GenericError(_))
+    ));
+    assert!(matches!(
+        check(&state, &arbitrage_bot(), 1_000 * E8S + 1),
+        Err(ProtocolError::GenericError(_))
+    ));
+    assert!(matches!(
+        check(&state, &arbitrage_bot(), 0),
+        Err(ProtocolError::AmountTooLow { minimum_amount: 1 })
+    ));
+
+    let disabled = fresh_state();
+    assert!(matches!(
+        check(&disabled, &arbitrage_bot(), E8S),
+        Err(ProtocolError::TemporarilyUnavailable(_))
+    ));
+}
+
+#[test]
+fn a_default_drops_the_callback() {
+    let mut state = fixture();
+    apply_default(&mut state, &arbitrage_bot());
+    assert_eq!(
+        state.flash_mint.as_ref().unwrap().allowed_callbacks,
+        vec![liquidator()]
+    );
+    assert!(check(&state, &arbitrage_bot(), E8S).is_err());
+    assert!(check(&state, &liquidator(), E8S).is_ok());
+}
+
+#[test]
+fn replay_rebuilds_the_config_and_defaults() {
+    let flash = |callback, repay_block_index| Event::FlashMint {
+        initiator: Principal::from_slice(&[1]),
+        callback,
+        amount_e8s: 500 * E8S,
+        fee_e8s: 45_000_000,
+        mint_block_index: 7,
+        repay_block_index,
+        timestamp: NOW,
+    };
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::SetFlashMintConfig {
+            config: Some(config()),
+        },
+        flash(liquidator(), Some(8)),
+        flash(arbitrage_bot(), None),
+        Event::DeficitAccrued {
+            vault_id: 0,
+            amount: ICUSD::new(500 * E8S),
+            new_deficit: ICUSD::new(500 * E8S),
+            timestamp: NOW,
+            source: Some(DeficitSource::FlashMint {
+                callback: arbitrage_bot(),
+            }),
+        },
+    ];
+    let state = replay(events.clone().into_iter()).expect("replay");
+    assert_eq!(
+        state.flash_mint.as_ref().unwrap().allowed_callbacks,
+        vec![liquidator()]
+    );
+    assert_eq!(state.protocol_deficit_icusd, ICUSD::new(500 * E8S));
+
+    let mut events = events;
+    events.push(Event::SetFlashMintConfig { config: None });
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(state.flash_mint, None);
+}
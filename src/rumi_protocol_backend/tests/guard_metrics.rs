@@ -0,0 +1,186 @@
+//! Guard metrics: released guards land in the right age bucket and outcome
+//! count, principals past the cap share the anonymous entry, only guards
+//! past the hard timeout are stuck, a guard replaced while its transfers
+//! were checked is left alone, and an open transfer is only found on the
+//! ledger by its memo and amount.
+//!
+//! Two users take and release guards on an otherwise untouched protocol.
+
+mod common;
+
+use common::init_arg_with;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::guard::{GuardOutcome, OpenLedgerCall, OperationState};
+use rumi_protocol_backend::guard_metrics::{
+    age_bucket, clear_stuck_guard, find_transfer, record_refusal, record_release, stuck_guards,
+    totals, GuardClearReason, GUARD_AGE_BUCKET_BOUNDS_SECS, GUARD_HARD_TIMEOUT_NANOS,
+    MAX_GUARD_METRICS_PRINCIPALS,
+};
+use rumi_protocol_backend::icrc3_proof::DecodedBlock;
+use rumi_protocol_backend::state::State;
+
+const SEC: u64 = 1_000_000_000;
+
+fn alice() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn bob() -> Principal {
+    Principal::from_slice(&[2])
+}
+
+fn ledger() -> Principal {
+    Principal::from_slice(&[10])
+}
+
+fn fresh() -> State {
+    replay(vec![Event::Init(init_arg_with().icp_ledger(ledger()).build())].into_iter())
+        .expect("replay")
+}
+
+fn hold(state: &mut State, principal: Principal, operation: &str, started_at: u64) {
+    state.principal_guards.insert(principal);
+    state
+        .principal_guard_timestamps
+        .insert(principal, started_at);
+    state
+        .operation_states
+        .insert(principal, OperationState::InProgress);
+    state
+        .operation_names
+        .insert(principal, operation.to_string());
+}
+
+fn block(amount: u128, memo: &[u8]) -> DecodedBlock {
+    DecodedBlock {
+        op: "xfer".to_string(),
+        from: None,
+        to: None,
+        spender: None,
+        amount,
+        memo: Some(memo.to_vec()),
+    }
+}
+
+#[test]
+fn released_guards_are_bucketed_by_age_and_outcome() {
+    let mut state = fresh();
+    record_release(&mut state, alice(), GuardOutcome::Completed, SEC / 2);
+    record_release(&mut state, alice(), GuardOutcome::Failed, 10 * SEC);
+    record_release(&mut state, alice(), GuardOutcome::Released, 3_600 * SEC);
+    record_refusal(&mut state, alice());
+    record_release(&mut state, bob(), GuardOutcome::AutoCleared, 3_600 * SEC);
+
+    let metrics = &state.guard_metrics[&alice()];
+    assert_eq!(metrics.age_histogram, vec![1, 0, 1, 0, 0, 0, 1]);
+    assert_eq!(
+        (
+            metrics.completed,
+            metrics.failed,
+            metrics.released,
+            metrics.refused
+        ),
+        (1, 1, 1, 1)
+    );
+    assert_eq!(metrics.max_age_ns, 3_600 * SEC);
+
+    let total = totals(&state);
+    assert_eq!(total.age_histogram, vec![1, 0, 1, 0, 0, 0, 2]);
+    assert_eq!(total.auto_cleared, 1);
+    // A bound is exclusive.
+    assert_eq!(age_bucket(GUARD_AGE_BUCKET_BOUNDS_SECS[0] * SEC), 1);
+}
+
+#[test]
+fn principals_past_the_cap_share_the_anonymous_entry() {
+    let mut state = fresh();
+    for i in 0..MAX_GUARD_METRICS_PRINCIPALS as u32 {
+        record_refusal(&mut state, Principal::from_slice(&i.to_be_bytes()));
+    }
+    record_refusal(&mut state, alice());
+    record_refusal(&mut state, bob());
+
+    assert_eq!(state.guard_metrics.len(), MAX_GUARD_METRICS_PRINCIPALS + 1);
+    assert!(!state.guard_metrics.contains_key(&alice()));
+    assert_eq!(state.guard_metrics[&Principal::anonymous()].refused, 2);
+}
+
+#[test]
+fn only_guards_past_the_hard_timeout_are_cleared() {
+    let mut state = fresh();
+    let now = 2 * GUARD_HARD_TIMEOUT_NANOS;
+    hold(&mut state, alice(), "borrow_from_vault", 0);
+    hold(&mut state, bob(), "repay_to_vault", now - SEC);
+
+    let stuck = stuck_guards(&state, now);
+    assert_eq!(stuck.len(), 1);
+    assert_eq!(stuck[0].principal, alice());
+    assert_eq!(stuck[0].operation_name, "borrow_from_vault");
+
+    assert!(clear_stuck_guard(&mut state, &stuck[0], now));
+    assert!(!state.principal_guards.contains(&alice()));
+    assert!(state.principal_guards.contains(&bob()));
+    let metrics = &state.guard_metrics[&alice()];
+    assert_eq!((metrics.auto_cleared, metrics.max_age_ns), (1, now));
+}
+
+#[test]
+fn a_guard_replaced_during_the_check_is_left_alone() {
+    let mut state = fresh();
+    let now = 2 * GUARD_HARD_TIMEOUT_NANOS;
+    hold(&mut state, alice(), "borrow_from_vault", 0);
+    let stuck = stuck_guards(&state, now);
+
+    // The old operation returned and a new one started meanwhile.
+    hold(&mut state, alice(), "repay_to_vault", now - SEC);
+    assert!(!clear_stuck_guard(&mut state, &stuck[0], now));
+    assert_eq!(state.operation_names[&alice()], "repay_to_vault");
+    assert!(state.guard_metrics.get(&alice()).is_none());
+}
+
+#[test]
+fn open_transfers_are_found_by_memo_and_amount() {
+    let call = OpenLedgerCall {
+        ledger: ledger(),
+        memo: vec![7; 16],
+        amount: 500,
+        started_at: 0,
+    };
+    let blocks = vec![
+        (40, block(500, &[8; 16])),
+        (41, block(400, &[7; 16])),
+        (42, block(500, &[7; 16])),
+    ];
+    assert_eq!(find_transfer(&blocks, &call), Some(42));
+    assert_eq!(find_transfer(&blocks[..2], &call), None);
+}
+
+#[test]
+fn auto_clears_replay_as_no_ops() {
+    let event = Event::GuardAutoCleared {
+        principal: alice(),
+        operation_name: "borrow_from_vault".to_string(),
+        age_ns: GUARD_HARD_TIMEOUT_NANOS + SEC,
+        reason: GuardClearReason::TransferOnLedger {
+            ledger: ledger(),
+            block_index: 42,
+        },
+        timestamp: GUARD_HARD_TIMEOUT_NANOS + SEC,
+    };
+    assert!(event.involves_principal(&alice()));
+    assert!(!event.involves_principal(&bob()));
+
+    let state = replay(
+        vec![
+            Event::Init(init_arg_with().icp_ledger(ledger()).build()),
+            event,
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    assert!(state.guard_metrics.is_empty());
+    assert!(state.principal_guards.is_empty());
+}
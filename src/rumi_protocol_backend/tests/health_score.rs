@@ -0,0 +1,142 @@
+//! Protocol health score: each signal is scored on its own scale (stability
+//! coverage from the cached stability pool depth), the composite is their
+//! weighted average, and the badge follows the composite.
+//!
+//! One 10 ICP vault owes 50 icUSD with ICP freshly priced at $10, a TCR of
+//! 200% against the 150% recovery threshold.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::health_score::{
+    badge_for, compute_health_score, HealthBadge, PENDING_QUEUE_ALARM,
+};
+use rumi_protocol_backend::numeric::{UsdIcp, ICP, ICUSD};
+use rumi_protocol_backend::pool_priority::{PoolDepthSnapshot, MAX_POOL_DEPTH_AGE_NS};
+use rumi_protocol_backend::state::{PendingMarginTransfer, State};
+use rumi_protocol_backend::xrc::PRICE_FRESHNESS_THRESHOLD_NANOS;
+use rust_decimal_macros::dec;
+
+use common::{fresh_state, icp, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const NOW: u64 = 1_000 * 1_000_000_000;
+
+fn refresh_tcr(state: &mut State) {
+    state.total_collateral_ratio = state.compute_total_collateral_ratio(UsdIcp::from(dec!(10)));
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 10.0, Some(NOW));
+    state.open_vault(
+        VaultBuilder::new(1)
+            .owner(Principal::from_slice(&[1]))
+            .collateral(10 * E8S)
+            .debt(50 * E8S)
+            .build(),
+    );
+    refresh_tcr(&mut state);
+    state
+}
+
+#[test]
+fn healthy_protocol_scores_high() {
+    let health = compute_health_score(&fixture(), NOW);
+    // 200% is a third of the way from the threshold to twice it.
+    assert_eq!(health.total_collateral_ratio.score, 67);
+    assert_eq!(health.price_freshness.score, 100);
+    assert_eq!(health.stability_coverage.score, 100);
+    assert_eq!(health.reserve_coverage.score, 100);
+    assert_eq!(health.pending_queue.score, 100);
+    // 67 * 35% + 100 * 65%.
+    assert_eq!(health.score, 88);
+    assert_eq!(health.badge, HealthBadge::Healthy);
+    assert_eq!(health.computed_at, NOW);
+}
+
+#[test]
+fn each_signal_scores_on_its_own_scale() {
+    let mut state = fixture();
+
+    // 125%: halfway between insolvency and the recovery threshold.
+    state
+        .vault_id_to_vaults
+        .get_mut(&1)
+        .unwrap()
+        .borrowed_icusd_amount = ICUSD::new(80 * E8S);
+    refresh_tcr(&mut state);
+    let health = compute_health_score(&state, NOW);
+    assert_eq!(health.total_collateral_ratio.score, 25);
+
+    // Halfway between the freshness threshold and ten times it.
+    let health = compute_health_score(&state, NOW + PRICE_FRESHNESS_THRESHOLD_NANOS * 11 / 2);
+    assert_eq!(health.price_freshness.score, 50);
+    assert_eq!(health.price_freshness.value, 330.0);
+
+    // Half of the liquidatable debt is covered by the stability pool's
+    // depth for ICP; icUSD in the liquidity pool does not count.
+    state.liquidatable_vault_ids.insert(1);
+    state.provide_liquidity(ICUSD::new(80 * E8S), Principal::from_slice(&[2]));
+    assert_eq!(
+        compute_health_score(&state, NOW).stability_coverage.score,
+        0
+    );
+    state.pool_depth = Some(PoolDepthSnapshot {
+        eligible_icusd: vec![(icp(), 40 * E8S)],
+        updated_at_ns: NOW,
+    });
+    let health = compute_health_score(&state, NOW);
+    assert_eq!(health.stability_coverage.score, 50);
+    // A stale snapshot covers nothing.
+    let stale = NOW + MAX_POOL_DEPTH_AGE_NS + 1;
+    assert_eq!(
+        compute_health_score(&state, stale).stability_coverage.score,
+        0
+    );
+
+    // A quarter of the deficit is backed by reserves.
+    state.protocol_deficit_icusd = ICUSD::new(100 * E8S);
+    state.protocol_3usd_reserves = 25 * E8S;
+    let health = compute_health_score(&state, NOW);
+    assert_eq!(health.reserve_coverage.score, 25);
+
+    // A full retry queue.
+    for n in 0..PENDING_QUEUE_ALARM {
+        state.pending_margin_transfers.insert(
+            (n, Principal::from_slice(&[3])),
+            PendingMarginTransfer {
+                owner: Principal::from_slice(&[3]),
+                margin: ICP::new(E8S),
+                collateral_type: icp(),
+                retry_count: 0,
+                op_nonce: 0,
+                trace_id: None,
+            },
+        );
+    }
+    let health = compute_health_score(&state, NOW);
+    assert_eq!(health.pending_queue.score, 0);
+    assert_eq!(health.badge, HealthBadge::Critical);
+}
+
+#[test]
+fn unpriced_collateral_with_debt_scores_zero_freshness() {
+    let mut state = fixture();
+    state
+        .collateral_configs
+        .get_mut(&icp())
+        .unwrap()
+        .last_price_timestamp = None;
+    assert_eq!(compute_health_score(&state, NOW).price_freshness.score, 0);
+}
+
+#[test]
+fn badge_follows_the_composite() {
+    assert_eq!(badge_for(100), HealthBadge::Healthy);
+    assert_eq!(badge_for(80), HealthBadge::Healthy);
+    assert_eq!(badge_for(79), HealthBadge::Caution);
+    assert_eq!(badge_for(50), HealthBadge::Caution);
+    assert_eq!(badge_for(49), HealthBadge::Critical);
+}
@@ -0,0 +1,169 @@
+//! The ICRC-3 log of protocol-initiated icUSD operations: transfers are
+//! typed as the ledger sees them, blocks chain by `phash`, a ledger block is
+//! logged once, and `icrc3_get_blocks` pages within its response cap.
+//!
+//! `protocol()` is the protocol canister, whose main account is the icUSD
+//! minting account; one user is on the other side of every transfer.
+
+use candid::{Nat, Principal};
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+
+use rumi_protocol_backend::icrc3_log::{
+    append, classify, encode_block, get_blocks, hash_value, tip, LedgerTransfer, ProtocolBlockKind,
+    MAX_GET_BLOCKS_RESPONSE,
+};
+use rumi_protocol_backend::storage::protocol_block;
+
+fn protocol() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn account(owner: Principal, subaccount: Option<[u8; 32]>) -> Account {
+    Account { owner, subaccount }
+}
+
+fn user() -> Account {
+    account(Principal::from_slice(&[2]), None)
+}
+
+fn minting_account() -> Account {
+    account(protocol(), None)
+}
+
+fn mint(amount: u128) -> LedgerTransfer {
+    LedgerTransfer {
+        from: minting_account(),
+        to: user(),
+        spender: None,
+        amount,
+        memo: Some(vec![7]),
+        created_at_time: Some(5),
+    }
+}
+
+fn burn(amount: u128) -> LedgerTransfer {
+    LedgerTransfer {
+        from: user(),
+        to: minting_account(),
+        spender: Some(minting_account()),
+        amount,
+        memo: None,
+        created_at_time: None,
+    }
+}
+
+fn field<'a>(value: &'a ICRC3Value, key: &str) -> Option<&'a ICRC3Value> {
+    match value {
+        ICRC3Value::Map(map) => map.get(key),
+        _ => None,
+    }
+}
+
+#[test]
+fn transfers_are_typed_as_the_ledger_sees_them() {
+    assert_eq!(classify(protocol(), &mint(1)), ProtocolBlockKind::Mint);
+    assert_eq!(classify(protocol(), &burn(1)), ProtocolBlockKind::Burn);
+
+    let from_subaccount = LedgerTransfer {
+        from: account(protocol(), Some([3; 32])),
+        ..mint(1)
+    };
+    assert_eq!(
+        classify(protocol(), &from_subaccount),
+        ProtocolBlockKind::Transfer
+    );
+    // Swept back into the main account: a burn.
+    let swept = LedgerTransfer {
+        to: minting_account(),
+        ..from_subaccount
+    };
+    assert_eq!(classify(protocol(), &swept), ProtocolBlockKind::Burn);
+
+    let pulled = LedgerTransfer {
+        to: account(Principal::from_slice(&[4]), None),
+        ..burn(1)
+    };
+    assert_eq!(
+        classify(protocol(), &pulled),
+        ProtocolBlockKind::TransferFrom
+    );
+}
+
+#[test]
+fn blocks_chain_by_parent_hash() {
+    assert_eq!(append(protocol(), &mint(100), 10, 1_000), Some(0));
+    assert_eq!(append(protocol(), &burn(40), 11, 2_000), Some(1));
+
+    let first = protocol_block(0).unwrap();
+    let second = protocol_block(1).unwrap();
+    assert_eq!(first.phash, None);
+    assert_eq!(first.hash, hash_value(&encode_block(&first)));
+    assert_eq!(second.phash, Some(first.hash));
+    assert_eq!(second.hash, hash_value(&encode_block(&second)));
+    assert_eq!(tip(), Some((1, second.hash)));
+
+    let encoded = encode_block(&second);
+    assert_eq!(
+        field(&encoded, "btype"),
+        Some(&ICRC3Value::Text("1burn".to_string()))
+    );
+    assert_eq!(
+        field(&encoded, "ledger_block"),
+        Some(&ICRC3Value::Nat(Nat::from(11u64)))
+    );
+    let tx = field(&encoded, "tx").unwrap();
+    assert!(field(tx, "from").is_some());
+    assert!(field(tx, "to").is_none());
+    assert_eq!(field(tx, "amt"), Some(&ICRC3Value::Nat(Nat::from(40u64))));
+
+    let tx = field(&encode_block(&first), "tx").unwrap().clone();
+    assert!(field(&tx, "from").is_none());
+    assert_eq!(field(&tx, "ts"), Some(&ICRC3Value::Nat(Nat::from(5u64))));
+}
+
+#[test]
+fn a_ledger_block_is_logged_once() {
+    assert_eq!(append(protocol(), &mint(100), 10, 1_000), Some(0));
+    // A retry the ledger deduplicated comes back with the same block.
+    assert_eq!(append(protocol(), &mint(100), 10, 2_000), None);
+    assert_eq!(tip().map(|(index, _)| index), Some(0));
+}
+
+#[test]
+fn get_blocks_pages_within_the_cap() {
+    for ledger_block in 0..5 {
+        append(protocol(), &mint(1), ledger_block, ledger_block);
+    }
+    let result = get_blocks(vec![
+        GetBlocksRequest {
+            start: Nat::from(1u64),
+            length: Nat::from(2u64),
+        },
+        GetBlocksRequest {
+            start: Nat::from(4u64),
+            length: Nat::from(10u64),
+        },
+    ]);
+    assert_eq!(result.log_length, Nat::from(5u64));
+    let ids: Vec<Nat> = result.blocks.iter().map(|b| b.id.clone()).collect();
+    assert_eq!(ids, vec![Nat::from(1u64), Nat::from(2u64), Nat::from(4u64)]);
+    assert!(result.archived_blocks.is_empty());
+
+    let blocks = MAX_GET_BLOCKS_RESPONSE + 5;
+    for ledger_block in 5..blocks {
+        append(protocol(), &mint(1), ledger_block, ledger_block);
+    }
+    let result = get_blocks(vec![
+        GetBlocksRequest {
+            start: Nat::from(0u64),
+            length: Nat::from(blocks),
+        },
+        GetBlocksRequest {
+            start: Nat::from(0u64),
+            length: Nat::from(1u64),
+        },
+    ]);
+    assert_eq!(result.blocks.len() as u64, MAX_GET_BLOCKS_RESPONSE);
+}
@@ -0,0 +1,105 @@
+//! Liquidatable-set refresh: vaults are sorted into liquidatable and near
+//! liquidation by their collateral ratio, leave the set when the price
+//! recovers or the vault closes, and a refresh that runs out of budget
+//! resumes from its cursor.
+//!
+//! With ICP at $10 (liquidation ratio 133%, 10% alert band), three 10 ICP
+//! vaults owe 50 (200%), 70 (~143%, near) and 80 (125%) icUSD.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::liquidatable_set::{liquidatable_set_view, refresh_liquidatable_set};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::Vault;
+
+use common::{fresh_state, icp, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const BUDGET: usize = 100;
+
+fn vault(vault_id: u64, debt_icusd: u64) -> Vault {
+    VaultBuilder::new(vault_id)
+        .owner(Principal::from_slice(&[1]))
+        .collateral(10 * E8S)
+        .debt(debt_icusd * E8S)
+        .build()
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 10.0, None);
+    state.open_vault(vault(1, 50));
+    state.open_vault(vault(2, 70));
+    state.open_vault(vault(3, 80));
+    state
+}
+
+#[test]
+fn vaults_are_classified_by_ratio() {
+    let mut state = fixture();
+    let delta = refresh_liquidatable_set(&mut state, &icp(), BUDGET, 1);
+    assert_eq!(delta.added, vec![3]);
+    assert!(delta.removed.is_empty());
+    assert_eq!(delta.vaults_visited, 3);
+    assert!(!delta.truncated);
+
+    let view = liquidatable_set_view(&state);
+    assert_eq!(view.liquidatable_vault_ids, vec![3]);
+    // 142.86% against 133%.
+    assert_eq!(view.near_liquidation, vec![(2, 985)]);
+    assert_eq!(view.stats.refreshes, 1);
+    assert_eq!(view.stats.total_added, 1);
+
+    // Nothing changed: an empty delta.
+    assert!(refresh_liquidatable_set(&mut state, &icp(), BUDGET, 2).is_empty());
+}
+
+#[test]
+fn recovery_and_closure_leave_the_set() {
+    let mut state = fixture();
+    refresh_liquidatable_set(&mut state, &icp(), BUDGET, 1);
+
+    set_icp_price(&mut state, 20.0, None);
+    let delta = refresh_liquidatable_set(&mut state, &icp(), BUDGET, 2);
+    assert_eq!(delta.removed, vec![3]);
+    assert!(state.liquidatable_vault_ids.is_empty());
+    assert!(state.near_liquidation_vaults.is_empty());
+
+    set_icp_price(&mut state, 10.0, None);
+    refresh_liquidatable_set(&mut state, &icp(), BUDGET, 3);
+    state.close_vault(3);
+    state.close_vault(2);
+    let delta = refresh_liquidatable_set(&mut state, &icp(), BUDGET, 4);
+    assert_eq!(delta.removed, vec![3]);
+    assert!(state.near_liquidation_vaults.is_empty());
+}
+
+#[test]
+fn unpriced_vaults_are_never_liquidatable() {
+    let mut state = fixture();
+    state.collateral_configs.get_mut(&icp()).unwrap().last_price = None;
+    let delta = refresh_liquidatable_set(&mut state, &icp(), BUDGET, 1);
+    assert!(delta.is_empty());
+    assert_eq!(delta.vaults_visited, 3);
+    assert!(state.near_liquidation_vaults.is_empty());
+}
+
+#[test]
+fn truncated_refresh_resumes_from_cursor() {
+    let mut state = fixture();
+    let delta = refresh_liquidatable_set(&mut state, &icp(), 2, 1);
+    assert!(delta.truncated);
+    assert_eq!(delta.vaults_visited, 2);
+    assert!(delta.added.is_empty());
+    assert_eq!(state.liquidatable_refresh_cursors.get(&icp()), Some(&3));
+
+    let delta = refresh_liquidatable_set(&mut state, &icp(), 2, 2);
+    assert!(!delta.truncated);
+    assert_eq!(delta.vaults_visited, 1);
+    assert_eq!(delta.added, vec![3]);
+    assert!(state.liquidatable_refresh_cursors.is_empty());
+    assert_eq!(state.liquidatable_refresh_stats.truncated_refreshes, 1);
+    assert_eq!(state.liquidatable_refresh_stats.refreshes, 2);
+}
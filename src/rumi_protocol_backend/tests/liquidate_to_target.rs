@@ -0,0 +1,123 @@
+//! `liquidate_to_target` sizing: the server-side quote must hit the target
+//! CR exactly when no bound applies, and otherwise report which bound
+//! (max partial ratio, protocol cap, liquidator budget, dust round-up) set
+//! the repay amount.
+//!
+//! The default ICP config applies (liquidation 133%, borrow threshold 150%,
+//! bonus 115%, `max_partial_liquidation_ratio` 50%), with a 10 ICP vault
+//! owing 100 icUSD.
+
+mod common;
+
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::numeric::{Ratio, ICUSD};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::Vault;
+use rumi_protocol_backend::LiquidationTargetLimit;
+
+use common::{fresh_state, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+
+fn state_at_price(price: f64) -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, price, None);
+    state
+}
+
+fn vault() -> Vault {
+    VaultBuilder::new(1)
+        .collateral(10 * E8S)
+        .debt(100 * E8S)
+        .build()
+}
+
+#[test]
+fn reaches_target_exactly_when_unbounded() {
+    // CR 125%. R = (130 - 125) / (1.30 - 1.15) = 33.33.. icUSD, rounded up.
+    let state = state_at_price(12.5);
+    let quote = state
+        .quote_liquidation_to_target(&vault(), Ratio::from(dec!(1.3)), ICUSD::new(1_000 * E8S))
+        .unwrap();
+
+    assert_eq!(quote.cr_before, 1.25);
+    assert_eq!(quote.required_repay_e8s, 3_333_333_334);
+    assert_eq!(quote.repay_e8s, quote.required_repay_e8s);
+    assert_eq!(quote.limited_by, LiquidationTargetLimit::Target);
+    assert!(quote.target_reached);
+    assert!(quote.projected_cr_after >= 1.3);
+}
+
+#[test]
+fn capped_by_max_partial_ratio() {
+    // Target 140% needs 60 icUSD; 50% of 100 icUSD debt caps it at 50.
+    let state = state_at_price(12.5);
+    let quote = state
+        .quote_liquidation_to_target(&vault(), Ratio::from(dec!(1.4)), ICUSD::new(1_000 * E8S))
+        .unwrap();
+
+    assert_eq!(quote.required_repay_e8s, 60 * E8S);
+    assert_eq!(quote.max_partial_repay_e8s, 50 * E8S);
+    assert_eq!(quote.repay_e8s, 50 * E8S);
+    assert_eq!(quote.limited_by, LiquidationTargetLimit::MaxPartialRatio);
+    assert!(!quote.target_reached);
+    // (125 - 50 * 1.15) / 50 = 1.35
+    assert!((quote.projected_cr_after - 1.35).abs() < 1e-9);
+}
+
+#[test]
+fn capped_by_liquidator_budget() {
+    let state = state_at_price(12.5);
+    let quote = state
+        .quote_liquidation_to_target(&vault(), Ratio::from(dec!(1.3)), ICUSD::new(20 * E8S))
+        .unwrap();
+
+    assert_eq!(quote.repay_e8s, 20 * E8S);
+    assert_eq!(quote.limited_by, LiquidationTargetLimit::MaxIcusd);
+    assert_eq!(quote.projected_debt_after_e8s, 80 * E8S);
+    assert!(!quote.target_reached);
+}
+
+#[test]
+fn dust_residual_rounds_up_only_within_budget() {
+    // CR 100%: the target needs more than the whole debt, so with the partial
+    // ratio lifted every bound but the budget is the full 100 icUSD.
+    let mut state = state_at_price(10.0);
+    state.max_partial_liquidation_ratio = Ratio::from(dec!(1.0));
+
+    let just_short = ICUSD::new(100 * E8S - 5_000_000);
+    let err = state
+        .quote_liquidation_to_target(&vault(), Ratio::from(dec!(1.2)), just_short)
+        .unwrap_err();
+    assert!(
+        err.contains("minimum vault debt"),
+        "unexpected error: {}",
+        err
+    );
+
+    let quote = state
+        .quote_liquidation_to_target(&vault(), Ratio::from(dec!(1.2)), ICUSD::new(100 * E8S))
+        .unwrap();
+    assert_eq!(quote.repay_e8s, 100 * E8S);
+    assert_eq!(quote.projected_debt_after_e8s, 0);
+    assert!(quote.target_reached);
+}
+
+#[test]
+fn rejects_target_below_current_cr() {
+    let state = state_at_price(12.5);
+    assert!(state
+        .quote_liquidation_to_target(&vault(), Ratio::from(dec!(1.2)), ICUSD::new(100 * E8S))
+        .is_err());
+}
+
+#[test]
+fn rejects_target_at_or_below_bonus() {
+    // CR 100%, target 110% < 115% bonus: every repay lowers the CR further.
+    let state = state_at_price(10.0);
+    let err = state
+        .quote_liquidation_to_target(&vault(), Ratio::from(dec!(1.1)), ICUSD::new(100 * E8S))
+        .unwrap_err();
+    assert!(err.contains("unreachable"), "unexpected error: {}", err);
+}
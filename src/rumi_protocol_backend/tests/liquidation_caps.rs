@@ -0,0 +1,196 @@
+//! Liquidation caps: a partial liquidation is clipped to the tighter of the
+//! per-call cap and what the window has left, never into dust debt, a full
+//! one past the cap is refused, usage resets with the next window, the
+//! previews show the cap, and replay rebuilds both the cap and the window's
+//! seizures.
+//!
+//! ICP is at $10 with the default 115% bonus, and liquidations are capped
+//! at 23 ICP per call and 30 ICP per minute. Vault #1 holds 100 ICP owing
+//! 800 icUSD, vault #2 owes 200.05 icUSD.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::liquidation_caps::{
+    apply_set_config, cap_repay, capacity, check_seizure, note_seized, seizable, validate_config,
+    LiquidationCapConfig, MIN_LIQUIDATION_WINDOW_NS,
+};
+use rumi_protocol_backend::numeric::{UsdIcp, ICP, ICUSD};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::{liquidation_reward_preview, Vault};
+
+use common::{fresh_state, icp, init_arg, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const SEC: u64 = 1_000_000_000;
+const WINDOW: u64 = 60 * SEC;
+const NOW: u64 = 100 * WINDOW + 5 * SEC;
+
+fn vault(vault_id: u64, debt: u64) -> Vault {
+    VaultBuilder::new(vault_id)
+        .owner(Principal::from_slice(&[1]))
+        .collateral(100 * E8S)
+        .debt(debt)
+        .build()
+}
+
+fn config() -> LiquidationCapConfig {
+    LiquidationCapConfig {
+        window_ns: WINDOW,
+        max_seized_per_call: 23 * E8S,
+        max_seized_per_window: 30 * E8S,
+    }
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 10.0, None);
+    state.open_vault(vault(1, 800 * E8S));
+    state.open_vault(vault(2, 20_005_000_000));
+    apply_set_config(&mut state, icp(), Some(config()));
+    state
+}
+
+#[test]
+fn configs_are_validated() {
+    assert!(validate_config(&config()).is_ok());
+    let bad = [
+        LiquidationCapConfig {
+            window_ns: MIN_LIQUIDATION_WINDOW_NS - 1,
+            ..config()
+        },
+        LiquidationCapConfig {
+            window_ns: 2 * 86_400 * SEC,
+            ..config()
+        },
+        LiquidationCapConfig {
+            max_seized_per_call: 0,
+            max_seized_per_window: 0,
+            ..config()
+        },
+    ];
+    for config in bad {
+        assert!(validate_config(&config).is_err(), "{:?}", config);
+    }
+}
+
+#[test]
+fn a_partial_liquidation_is_clipped_to_the_cap() {
+    let state = fixture();
+    let vault = &state.vault_id_to_vaults[&1];
+
+    // 100 icUSD seizes 11.5 ICP: within the cap.
+    assert_eq!(
+        cap_repay(&state, vault, ICUSD::new(100 * E8S), dec!(10), 8, NOW),
+        Ok((ICUSD::new(100 * E8S), None))
+    );
+    // 400 icUSD would seize 46 ICP: clipped to the 200 icUSD that seize 23.
+    assert_eq!(
+        cap_repay(&state, vault, ICUSD::new(400 * E8S), dec!(10), 8, NOW),
+        Ok((ICUSD::new(200 * E8S), Some(23 * E8S)))
+    );
+
+    // Clipping to 200 icUSD would leave 0.05 icUSD of #2's debt; it stops
+    // at the 0.1 icUSD floor instead.
+    let vault = &state.vault_id_to_vaults[&2];
+    assert_eq!(
+        cap_repay(&state, vault, ICUSD::new(20_005_000_000), dec!(10), 8, NOW),
+        Ok((ICUSD::new(19_995_000_000), Some(23 * E8S)))
+    );
+
+    // Uncapped collaterals are never clipped.
+    let mut state = fixture();
+    apply_set_config(&mut state, icp(), None);
+    let vault = &state.vault_id_to_vaults[&1];
+    assert_eq!(seizable(&state, &icp(), NOW), None);
+    assert_eq!(
+        cap_repay(&state, vault, ICUSD::new(400 * E8S), dec!(10), 8, NOW),
+        Ok((ICUSD::new(400 * E8S), None))
+    );
+}
+
+#[test]
+fn the_window_fills_and_resets() {
+    let mut state = fixture();
+    assert!(check_seizure(&state, 1, &icp(), 23 * E8S, NOW).is_ok());
+    assert!(check_seizure(&state, 1, &icp(), 23 * E8S + 1, NOW).is_err());
+
+    note_seized(&mut state, &icp(), 23 * E8S, NOW);
+    let cap = capacity(&state, &icp(), NOW + SEC);
+    assert_eq!(cap.window_start_ns, 100 * WINDOW);
+    assert_eq!(cap.window_end_ns, 101 * WINDOW);
+    assert_eq!(cap.seized, 23 * E8S);
+    assert_eq!(cap.seizable, Some(7 * E8S));
+
+    // The window is spent: nothing more until the next one.
+    note_seized(&mut state, &icp(), 7 * E8S, NOW);
+    let vault = &state.vault_id_to_vaults[&1];
+    match cap_repay(&state, vault, ICUSD::new(100 * E8S), dec!(10), 8, NOW) {
+        Err(msg) => assert!(msg.contains("Liquidation cap reached"), "{}", msg),
+        other => panic!("expected an error, got {:?}", other),
+    }
+    assert_eq!(seizable(&state, &icp(), 101 * WINDOW), Some(23 * E8S));
+}
+
+#[test]
+fn the_previews_show_the_cap() {
+    let state = fixture();
+    let preview = state
+        .preview_liquidation(1, Some(ICUSD::new(400 * E8S)), NOW)
+        .unwrap();
+    assert_eq!(preview.seizure_cap, Some(23 * E8S));
+    assert_eq!(preview.uncapped_repay_e8s, Some(400 * E8S));
+    assert_eq!(preview.repay_e8s, 200 * E8S);
+    assert_eq!(preview.collateral_seized, 23 * E8S);
+
+    // A full liquidation of #1 would seize 92 ICP.
+    let reward = liquidation_reward_preview(&state, 1, NOW).unwrap();
+    assert_eq!(reward.seizure_cap, Some(23 * E8S));
+    assert!(reward.exceeds_seizure_cap);
+}
+
+#[test]
+fn caps_and_seizures_replay() {
+    let state = replay(
+        vec![
+            Event::Init(init_arg()),
+            Event::OpenVault {
+                vault: vault(1, 800 * E8S),
+                block_index: 0,
+                timestamp: None,
+            },
+            Event::SetCollateralLiquidationCap {
+                collateral_type: icp(),
+                config: Some(config()),
+            },
+            Event::LiquidationCapBound {
+                vault_id: 1,
+                collateral_type: icp(),
+                requested: ICUSD::new(400 * E8S),
+                repay: ICUSD::new(200 * E8S),
+                seizable: 23 * E8S,
+                timestamp: NOW,
+            },
+            Event::PartialLiquidateVault {
+                vault_id: 1,
+                liquidator_payment: ICUSD::new(200 * E8S),
+                icp_to_liquidator: ICP::new(22 * E8S),
+                liquidator: None,
+                icp_rate: Some(UsdIcp::from(dec!(10))),
+                protocol_fee_collateral: Some(E8S),
+                timestamp: Some(NOW),
+                three_usd_reserves_e8s: None,
+            },
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    assert_eq!(state.liquidation_caps.get(&icp()), Some(&config()));
+    let cap = capacity(&state, &icp(), NOW);
+    assert_eq!(cap.seized, 23 * E8S);
+    assert_eq!(cap.seizable, Some(7 * E8S));
+    assert_eq!(state.vault_id_to_vaults[&1].collateral_amount, 77 * E8S);
+}
@@ -0,0 +1,140 @@
+//! Liquidation index: liquidatable vaults are read worst first, a price or
+//! mode change moves the threshold without re-keying, vault changes and
+//! accrual re-key, unpriced collateral yields nothing, and the result
+//! always matches a full scan.
+//!
+//! ICP is at $6 (liquidation ratio 133%, 150% in Recovery). Vaults of 10
+//! ICP owe 50 (CR 120%), 42 (CR 142.9%), 20 (CR 300%) and 0 icUSD.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::compute_collateral_ratio;
+use rumi_protocol_backend::liquidation_index::{key, liquidatable, liquidatable_for};
+use rumi_protocol_backend::numeric::{UsdIcp, ICUSD};
+use rumi_protocol_backend::state::{Mode, State};
+use rumi_protocol_backend::vault::Vault;
+use rust_decimal::Decimal;
+
+use common::{fresh_state, icp, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+
+fn vault(vault_id: u64, collateral_e8s: u64, debt_e8s: u64) -> Vault {
+    VaultBuilder::new(vault_id)
+        .owner(Principal::from_slice(&[vault_id as u8]))
+        .collateral(collateral_e8s)
+        .debt(debt_e8s)
+        .build()
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    state.mode = Mode::GeneralAvailability;
+    set_icp_price(&mut state, 6.0, Some(0));
+    for (id, debt) in [(1, 50), (2, 42), (3, 20), (4, 0)] {
+        state.open_vault(vault(id, 10 * E8S, debt * E8S));
+    }
+    state
+}
+
+fn ids(vaults: Vec<&Vault>) -> Vec<u64> {
+    vaults.into_iter().map(|v| v.vault_id).collect()
+}
+
+/// The liquidatable vaults by checking every one, lowest CR first.
+fn full_scan(state: &State) -> Vec<u64> {
+    let rate = UsdIcp::from(Decimal::ZERO);
+    let mut vaults: Vec<(Decimal, u64)> = state
+        .vault_id_to_vaults
+        .values()
+        .map(|v| (compute_collateral_ratio(v, rate, state).0, v))
+        .filter(|(cr, v)| *cr < state.get_min_liquidation_ratio_for(&v.collateral_type).0)
+        .map(|(cr, v)| (cr, v.vault_id))
+        .collect();
+    vaults.sort();
+    vaults.into_iter().map(|(_, id)| id).collect()
+}
+
+#[test]
+fn keys_order_vaults_by_debt_per_collateral() {
+    assert_eq!(key(0, 10 * E8S), 0);
+    assert_eq!(key(E8S, 0), u128::MAX);
+    assert!(key(50 * E8S, 10 * E8S) > key(42 * E8S, 10 * E8S));
+    assert_eq!(key(20 * E8S, 10 * E8S), key(40 * E8S, 20 * E8S));
+
+    let state = fixture();
+    assert_eq!(state.liquidation_index_keys.len(), 4);
+    assert_eq!(
+        state.liquidation_index_keys[&1],
+        (icp(), key(50 * E8S, 10 * E8S))
+    );
+}
+
+#[test]
+fn price_and_mode_changes_move_the_threshold_only() {
+    let mut state = fixture();
+    assert_eq!(ids(liquidatable_for(&state, &icp())), vec![1]);
+
+    let before = state.liquidation_index.clone();
+    set_icp_price(&mut state, 5.0, Some(0));
+    assert_eq!(state.liquidation_index, before);
+    assert_eq!(ids(liquidatable_for(&state, &icp())), vec![1, 2]);
+
+    set_icp_price(&mut state, 6.0, Some(0));
+    state.mode = Mode::Recovery;
+    assert_eq!(ids(liquidatable(&state)), vec![1, 2]);
+}
+
+#[test]
+fn vault_changes_and_accrual_rekey() {
+    let mut state = fixture();
+    state.borrow_from_vault(3, ICUSD::new(31 * E8S));
+    assert_eq!(ids(liquidatable(&state)), vec![3, 1]);
+
+    state.remove_vault_and_unindex(3);
+    assert!(!state.liquidation_index_keys.contains_key(&3));
+    assert_eq!(ids(liquidatable(&state)), vec![1]);
+
+    // Debt grown by accrual without a re-key through the CR index.
+    state
+        .vault_id_to_vaults
+        .get_mut(&2)
+        .unwrap()
+        .borrowed_icusd_amount = ICUSD::new(46 * E8S);
+    assert_eq!(ids(liquidatable(&state)), vec![1]);
+    state.accrue_single_vault(2, 1);
+    assert_eq!(ids(liquidatable(&state)), vec![1, 2]);
+}
+
+#[test]
+fn unpriced_collateral_has_no_liquidatable_vaults() {
+    let mut state = fixture();
+    state.collateral_configs.get_mut(&icp()).unwrap().last_price = None;
+    assert!(liquidatable(&state).is_empty());
+}
+
+#[test]
+fn the_index_matches_a_full_scan() {
+    let mut state = fresh_state();
+    state.mode = Mode::GeneralAvailability;
+    for id in 1..=60u64 {
+        let collateral = (id % 7 + 1) * E8S + id * 1_234_567;
+        let debt = (id * 37 % 50) * E8S / 3 + id;
+        state.open_vault(vault(id, collateral, debt));
+    }
+    for price in [1.0, 2.5, 3.33, 4.0, 7.77, 12.0] {
+        set_icp_price(&mut state, price, Some(0));
+        for mode in [Mode::GeneralAvailability, Mode::Recovery] {
+            state.mode = mode;
+            assert_eq!(
+                ids(liquidatable(&state)),
+                full_scan(&state),
+                "price {} in {:?}",
+                price,
+                mode
+            );
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! `preview_liquidation`: the dry-run must size the repay and split the
+//! seized collateral exactly as `liquidate_vault_partial` would, and report
+//! a healthy vault as not liquidatable without amounts.
+//!
+//! Previews run against the default ICP config (liquidation 133%, borrow
+//! threshold 150%, bonus 115%) and a 10 ICP vault owing 100 icUSD.
+
+mod common;
+
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::state::State;
+
+use common::{fresh_state, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+
+fn state_at_price(price: f64) -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, price, None);
+    state.vault_id_to_vaults.insert(
+        1,
+        VaultBuilder::new(1)
+            .collateral(10 * E8S)
+            .debt(100 * E8S)
+            .build(),
+    );
+    state
+}
+
+#[test]
+fn sizes_and_splits_like_a_partial_liquidation() {
+    // CR 125%: 20 icUSD at 12.5 $/ICP is 1.6 ICP, 1.84 ICP with the bonus.
+    let state = state_at_price(12.5);
+    let preview = state
+        .preview_liquidation(1, Some(ICUSD::new(20 * E8S)), 0)
+        .unwrap();
+
+    assert!(preview.liquidatable);
+    assert_eq!(preview.collateral_ratio, 1.25);
+    assert_eq!(preview.repay_e8s, 20 * E8S);
+    assert_eq!(preview.collateral_seized, 184_000_000);
+    let share = state.get_liquidation_protocol_share().0;
+    let expected_cut = (Decimal::from(24_000_000u64) * share).to_u64().unwrap();
+    assert_eq!(preview.protocol_cut, expected_cut);
+    assert_eq!(
+        preview.collateral_to_liquidator + preview.protocol_cut,
+        preview.collateral_seized
+    );
+}
+
+#[test]
+fn full_debt_request_is_capped_at_the_protocol_cap() {
+    let state = state_at_price(12.5);
+    let vault = state.vault_id_to_vaults[&1].clone();
+    let cap = state.compute_partial_liquidation_cap(&vault, Default::default());
+
+    let preview = state.preview_liquidation(1, None, 0).unwrap();
+    assert_eq!(preview.max_liquidatable_debt_e8s, cap.to_u64());
+    assert_eq!(preview.repay_e8s, cap.to_u64());
+    assert!(preview.repay_e8s < preview.debt_e8s);
+}
+
+#[test]
+fn healthy_vault_previews_without_amounts() {
+    let state = state_at_price(20.0);
+    let preview = state.preview_liquidation(1, None, 0).unwrap();
+
+    assert!(!preview.liquidatable);
+    assert_eq!(preview.repay_e8s, 0);
+    assert_eq!(preview.collateral_seized, 0);
+    assert_eq!(preview.collateral_to_liquidator, 0);
+
+    assert!(state.preview_liquidation(2, None, 0).is_err());
+}
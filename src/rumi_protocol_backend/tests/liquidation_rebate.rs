@@ -0,0 +1,96 @@
+//! Owner rebates credited as collateral: `liquidation_rebate_target` picks
+//! the owner's riskiest other indebted vault, and replay of
+//! `LiquidationRebateApplied` tops it up without touching any debt.
+//!
+//! ICP is at $10 with a minimum vault debt of 0.1 icUSD. One owner has two
+//! 10 ICP vaults owing 70 and 50 icUSD; a stranger owns a third.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::state::{LiquidationRebateMode, State};
+use rumi_protocol_backend::vault::Vault;
+
+use common::{fresh_state, icp, init_arg, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+
+fn owner() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn vault(vault_id: u64, owner: Principal, debt_e8s: u64) -> Vault {
+    VaultBuilder::new(vault_id)
+        .owner(owner)
+        .collateral(10 * E8S)
+        .debt(debt_e8s)
+        .build()
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 10.0, None);
+    state.open_vault(vault(1, owner(), 70 * E8S));
+    state.open_vault(vault(2, owner(), 50 * E8S));
+    state.open_vault(vault(3, Principal::from_slice(&[2]), 90 * E8S));
+    state
+}
+
+#[test]
+fn legacy_configs_default_to_collateral_payout() {
+    let state = fixture();
+    assert_eq!(
+        state.collateral_configs[&icp()].liquidation_rebate_mode,
+        LiquidationRebateMode::CollateralPayout
+    );
+}
+
+#[test]
+fn rebate_goes_to_the_lowest_cr_other_vault() {
+    let mut state = fixture();
+    // Vault 1 (CR 142.9%) is riskier than vault 2 (200%).
+    assert_eq!(state.liquidation_rebate_target(owner(), icp(), 9), Some(1));
+    // Never the vault being liquidated itself.
+    assert_eq!(state.liquidation_rebate_target(owner(), icp(), 1), Some(2));
+
+    // Debt-free vaults need no top-up; with none left the rebate is paid out
+    // and the stranger's vault is never picked.
+    state.repay_to_vault(1, ICUSD::new(70 * E8S));
+    assert_eq!(state.liquidation_rebate_target(owner(), icp(), 9), Some(2));
+    state.repay_to_vault(2, ICUSD::new(50 * E8S));
+    assert_eq!(state.liquidation_rebate_target(owner(), icp(), 9), None);
+}
+
+#[test]
+fn replay_credits_rebate_as_collateral() {
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::OpenVault {
+            vault: vault(1, owner(), 70 * E8S),
+            block_index: 0,
+            timestamp: None,
+        },
+        Event::SetCollateralLiquidationRebateMode {
+            collateral_type: icp(),
+            mode: LiquidationRebateMode::CollateralTopUp,
+        },
+        Event::LiquidationRebateApplied {
+            source_vault_id: 9,
+            target_vault_id: 1,
+            owner: owner(),
+            collateral_amount: 2 * E8S,
+            timestamp: 0,
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(
+        state.collateral_configs[&icp()].liquidation_rebate_mode,
+        LiquidationRebateMode::CollateralTopUp
+    );
+    let vault = &state.vault_id_to_vaults[&1];
+    assert_eq!(vault.collateral_amount, 12 * E8S);
+    assert_eq!(vault.borrowed_icusd_amount, ICUSD::new(70 * E8S));
+}
@@ -0,0 +1,158 @@
+//! Liquidation receipts: each liquidation leaves the owner a receipt with
+//! the price, the CR at trigger and the repaid, seized, bonus and residual
+//! amounts read off the vault before and after, and a `Liquidated`
+//! notification pointing at it.
+//!
+//! One owner's 10 ICP vault owes 80 icUSD with ICP at $10, a CR of 125%
+//! against the 133% liquidation ratio.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::liquidation_receipts::{
+    issue, Settlement, LIQUIDATION_NOTIFICATION_PARAMETER, MAX_RECEIPTS_PER_OWNER,
+};
+use rumi_protocol_backend::notifications::{VaultNotificationKind, VaultRiskLevel};
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::state::{Mode, State};
+use rumi_protocol_backend::vault::Vault;
+
+use common::{fresh_state, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const NOW_NS: u64 = 1_000;
+
+fn owner() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn liquidator() -> Principal {
+    Principal::from_slice(&[2])
+}
+
+fn vault(vault_id: u64, debt_e8s: u64) -> Vault {
+    VaultBuilder::new(vault_id)
+        .owner(owner())
+        .collateral(10 * E8S)
+        .debt(debt_e8s)
+        .build()
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 10.0, None);
+    state.open_vault(vault(1, 80 * E8S));
+    state
+}
+
+fn settlement() -> Settlement {
+    Settlement {
+        liquidator: Some(liquidator()),
+        price: dec!(10),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn a_partial_liquidation_leaves_a_receipt_and_a_notification() {
+    let mut state = fixture();
+    let before = state.vault_id_to_vaults[&1].clone();
+    // Repay 40 icUSD for 4 ICP plus a 10% bonus, 0.1 ICP of it to the
+    // protocol.
+    let v = state.vault_id_to_vaults.get_mut(&1).unwrap();
+    v.borrowed_icusd_amount = ICUSD::new(40 * E8S);
+    v.collateral_amount -= 44 * E8S / 10;
+    let id = issue(
+        &mut state,
+        &before,
+        Settlement {
+            protocol_fee: E8S / 10,
+            ..settlement()
+        },
+        NOW_NS,
+    );
+
+    let receipt = &state.liquidation_receipts[&owner()][0];
+    assert_eq!(receipt.id, id);
+    assert_eq!(receipt.liquidator, Some(liquidator()));
+    assert_eq!(receipt.price_usd, 10.0);
+    assert_eq!(receipt.collateral_ratio_at_trigger, 1.25);
+    assert_eq!(receipt.liquidation_ratio, 1.33);
+    assert_eq!(receipt.debt_repaid_e8s, 40 * E8S);
+    assert_eq!(receipt.collateral_seized, 44 * E8S / 10);
+    assert_eq!(receipt.bonus_paid, 4 * E8S / 10);
+    assert_eq!(receipt.protocol_fee, E8S / 10);
+    assert_eq!(receipt.residual_returned, 0);
+    assert_eq!(receipt.debt_remaining_e8s, 40 * E8S);
+    assert_eq!(receipt.collateral_remaining, 56 * E8S / 10);
+    assert_eq!(receipt.created_at_ns, NOW_NS);
+
+    let notification = &state.vault_notifications[&owner()][0];
+    assert_eq!(notification.parameter, LIQUIDATION_NOTIFICATION_PARAMETER);
+    assert_eq!(
+        notification.kind,
+        VaultNotificationKind::Liquidated { receipt_id: id }
+    );
+    assert_eq!(notification.previous_level, VaultRiskLevel::Liquidatable);
+    // 56 ICP-dollars against 40 icUSD: 140%, below the borrow threshold.
+    assert_eq!(notification.new_level, VaultRiskLevel::AtRisk);
+}
+
+#[test]
+fn a_full_liquidation_reports_the_residual() {
+    let mut state = fixture();
+    let before = state.vault_id_to_vaults[&1].clone();
+    let _ = state.liquidate_vault(1, Mode::GeneralAvailability, dec!(10).into());
+    assert!(!state.vault_id_to_vaults.contains_key(&1));
+    // 80 icUSD buys 8 ICP plus a 10% bonus; 1.2 ICP goes back to the owner.
+    issue(
+        &mut state,
+        &before,
+        Settlement {
+            residual_returned: 12 * E8S / 10,
+            ..settlement()
+        },
+        NOW_NS,
+    );
+
+    let receipt = &state.liquidation_receipts[&owner()][0];
+    assert_eq!(receipt.debt_repaid_e8s, 80 * E8S);
+    assert_eq!(receipt.collateral_seized, 88 * E8S / 10);
+    assert_eq!(receipt.bonus_paid, 8 * E8S / 10);
+    assert_eq!(receipt.residual_returned, 12 * E8S / 10);
+    assert_eq!(
+        (receipt.debt_remaining_e8s, receipt.collateral_remaining),
+        (0, 0)
+    );
+}
+
+#[test]
+fn an_underwater_liquidation_pays_no_bonus() {
+    let mut state = fixture();
+    state
+        .vault_id_to_vaults
+        .get_mut(&1)
+        .unwrap()
+        .borrowed_icusd_amount = ICUSD::new(110 * E8S);
+    let before = state.vault_id_to_vaults[&1].clone();
+    let _ = state.liquidate_vault(1, Mode::GeneralAvailability, dec!(10).into());
+    issue(&mut state, &before, settlement(), NOW_NS);
+
+    let receipt = &state.liquidation_receipts[&owner()][0];
+    assert_eq!(receipt.collateral_seized, 10 * E8S);
+    assert_eq!(receipt.bonus_paid, 0);
+}
+
+#[test]
+fn receipts_are_capped_per_owner() {
+    let mut state = fixture();
+    let before = state.vault_id_to_vaults[&1].clone();
+    for _ in 0..=MAX_RECEIPTS_PER_OWNER {
+        issue(&mut state, &before, settlement(), NOW_NS);
+    }
+    let receipts = &state.liquidation_receipts[&owner()];
+    assert_eq!(receipts.len(), MAX_RECEIPTS_PER_OWNER);
+    assert_eq!(receipts[0].id, 1);
+}
@@ -0,0 +1,129 @@
+//! Liquidation tip: with no tip the split is unchanged, the tip is funded
+//! from the protocol's cut first and then from the owner's leftover
+//! collateral, never beyond both, the preview shows what the caller gets
+//! and refuses healthy vaults, and the setting replays.
+//!
+//! A vault with 10 ICP at $10 owes 80 icUSD (CR 125%). Liquidating it
+//! seizes 9.2 ICP with the 15% bonus, 0.036 ICP of which is the protocol's
+//! 3% cut, and leaves 0.8 ICP for the owner.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::{UsdIcp, ICUSD};
+use rumi_protocol_backend::state::{Mode, State};
+use rumi_protocol_backend::vault::{
+    liquidation_reward_preview, plan_liquidation, LiquidationSplit, Vault,
+};
+
+use common::{fresh_state, init_arg, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+
+fn vault(vault_id: u64, debt_icusd: u64) -> Vault {
+    VaultBuilder::new(vault_id)
+        .owner(Principal::from_slice(&[1]))
+        .collateral(10 * E8S)
+        .debt(debt_icusd * E8S)
+        .build()
+}
+
+fn fixture(tip_e8s: u64) -> State {
+    let mut state = fresh_state();
+    state.mode = Mode::GeneralAvailability;
+    set_icp_price(&mut state, 10.0, Some(0));
+    state.liquidation_tip_e8s = tip_e8s;
+    state.open_vault(vault(1, 80));
+    state.open_vault(vault(2, 20));
+    state
+}
+
+fn split(tip_e8s: u64) -> LiquidationSplit {
+    let state = fixture(tip_e8s);
+    let split = plan_liquidation(
+        &state,
+        &state.vault_id_to_vaults[&1],
+        dec!(10),
+        8,
+        UsdIcp::from(dec!(10)),
+    );
+    // Every split hands out exactly the vault's collateral.
+    assert_eq!(
+        split.collateral_to_liquidator.to_u64()
+            + split.protocol_cut
+            + split.excess_collateral.to_u64(),
+        10 * E8S
+    );
+    split
+}
+
+/// (to the liquidator, of which tip, protocol cut, back to the owner)
+fn amounts(split: LiquidationSplit) -> (u64, u64, u64, u64) {
+    (
+        split.collateral_to_liquidator.to_u64(),
+        split.tip_collateral,
+        split.protocol_cut,
+        split.excess_collateral.to_u64(),
+    )
+}
+
+#[test]
+fn without_a_tip_the_split_is_unchanged() {
+    let split = split(0);
+    assert_eq!(split.debt_amount, ICUSD::new(80 * E8S));
+    assert_eq!(split.total_to_seize.to_u64(), 920_000_000);
+    assert!(!split.is_recovery_partial);
+    assert_eq!(amounts(split), (916_400_000, 0, 3_600_000, 80_000_000));
+}
+
+#[test]
+fn the_tip_comes_from_the_protocol_cut_first() {
+    // 0.2 icUSD = 0.02 ICP, within the cut.
+    assert_eq!(
+        amounts(split(E8S / 5)),
+        (918_400_000, 2_000_000, 1_600_000, 80_000_000)
+    );
+    // 1 icUSD = 0.1 ICP: the whole cut, then 0.064 ICP of the owner's.
+    assert_eq!(
+        amounts(split(E8S)),
+        (926_400_000, 10_000_000, 0, 73_600_000)
+    );
+}
+
+#[test]
+fn the_tip_never_exceeds_the_penalty() {
+    // 100 icUSD = 10 ICP, more than the cut and the leftover together.
+    assert_eq!(amounts(split(100 * E8S)), (10 * E8S, 83_600_000, 0, 0));
+}
+
+#[test]
+fn the_preview_shows_what_the_caller_gets() {
+    let state = fixture(E8S);
+    let preview = liquidation_reward_preview(&state, 1, 0).unwrap();
+    assert_eq!(preview.debt_to_repay_e8s, 80 * E8S);
+    assert_eq!(preview.collateral_to_liquidator, 926_400_000);
+    assert_eq!(preview.tip_collateral, 10_000_000);
+    assert_eq!(preview.collateral_value_e8s, 9_264_000_000);
+    assert_eq!(preview.tip_value_e8s, E8S);
+    assert!(!preview.recovery_partial);
+
+    // Vault 2 is healthy (CR 500%), vault 3 does not exist.
+    assert!(liquidation_reward_preview(&state, 2, 0).is_err());
+    assert!(liquidation_reward_preview(&state, 3, 0).is_err());
+}
+
+#[test]
+fn the_tip_replays() {
+    let state = replay(
+        vec![
+            Event::Init(init_arg()),
+            Event::SetLiquidationTip { tip_e8s: E8S },
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    assert_eq!(state.liquidation_tip_e8s, E8S);
+}
@@ -0,0 +1,141 @@
+//! Liquidator registry: registrations, renames, removals and the
+//! self-registration switch replay from events, only registered liquidators
+//! collect stats, the leaderboard ranks by volume, and anonymous callers and
+//! bad names are refused.
+//!
+//! Three liquidators act on an otherwise untouched protocol.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::liquidators::{
+    check_register, entry, leaderboard, record_outcome, validate_name, LiquidatorStats,
+    MAX_LEADERBOARD_LEN, MAX_LIQUIDATOR_NAME_LEN,
+};
+use rumi_protocol_backend::parameter_journal::parameter_change;
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::ProtocolError;
+
+use common::init_arg;
+
+const E8S: u64 = 100_000_000;
+
+fn alice() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn bob() -> Principal {
+    Principal::from_slice(&[2])
+}
+
+fn carol() -> Principal {
+    Principal::from_slice(&[3])
+}
+
+fn register(liquidator: Principal, name: &str, timestamp: u64) -> Event {
+    Event::RegisterLiquidator {
+        liquidator,
+        name: Some(name.to_string()),
+        self_registered: false,
+        timestamp,
+    }
+}
+
+fn registered(liquidators: &[Principal]) -> State {
+    let events = std::iter::once(Event::Init(init_arg()))
+        .chain(liquidators.iter().map(|l| register(*l, "bot", 0)))
+        .collect::<Vec<_>>();
+    replay(events.into_iter()).expect("replay")
+}
+
+#[test]
+fn the_registry_replays_from_events() {
+    let state = replay(
+        vec![
+            Event::Init(init_arg()),
+            Event::SetLiquidatorSelfRegistration { enabled: true },
+            register(alice(), "alpha", 10),
+            Event::RegisterLiquidator {
+                liquidator: bob(),
+                name: None,
+                self_registered: true,
+                timestamp: 20,
+            },
+            // A re-registration renames and keeps the original time.
+            register(alice(), "alpha v2", 30),
+            Event::RemoveLiquidator {
+                liquidator: bob(),
+                timestamp: 40,
+            },
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+
+    assert!(state.liquidator_self_registration);
+    let alice_entry = entry(&state, &alice()).unwrap();
+    assert_eq!(alice_entry.registration.name.as_deref(), Some("alpha v2"));
+    assert_eq!(alice_entry.registration.registered_at, 10);
+    assert!(!alice_entry.registration.self_registered);
+    assert!(entry(&state, &bob()).is_none());
+
+    let (parameter, _, new_value) =
+        parameter_change(&Event::SetLiquidatorSelfRegistration { enabled: true }).unwrap();
+    assert_eq!(parameter, "liquidator_self_registration");
+    assert_eq!(new_value, "true");
+}
+
+#[test]
+fn only_registered_liquidators_collect_stats() {
+    let mut state = registered(&[alice()]);
+    record_outcome(&mut state, alice(), Some(5 * E8S), 100);
+    record_outcome(&mut state, alice(), Some(3 * E8S), 200);
+    record_outcome(&mut state, alice(), None, 300);
+    record_outcome(&mut state, bob(), Some(E8S), 400);
+
+    assert_eq!(
+        entry(&state, &alice()).unwrap().stats,
+        LiquidatorStats {
+            liquidations: 2,
+            volume_e8s: 8 * E8S,
+            failures: 1,
+            last_liquidation_at: Some(200),
+        }
+    );
+    assert!(state.liquidator_stats.get(&bob()).is_none());
+}
+
+#[test]
+fn the_leaderboard_ranks_by_volume_then_count() {
+    let mut state = registered(&[alice(), bob(), carol()]);
+    record_outcome(&mut state, alice(), Some(2 * E8S), 0);
+    record_outcome(&mut state, bob(), Some(5 * E8S), 0);
+    record_outcome(&mut state, carol(), Some(E8S), 0);
+    record_outcome(&mut state, carol(), Some(E8S), 0);
+
+    let ranked: Vec<Principal> = leaderboard(&state, MAX_LEADERBOARD_LEN)
+        .into_iter()
+        .map(|e| e.liquidator)
+        .collect();
+    // Alice and Carol tie on volume; Carol liquidated more often.
+    assert_eq!(ranked, vec![bob(), carol(), alice()]);
+    assert_eq!(leaderboard(&state, 1).len(), 1);
+    assert_eq!(leaderboard(&state, usize::MAX).len(), 3);
+}
+
+#[test]
+fn anonymous_callers_and_bad_names_are_refused() {
+    let state = registered(&[]);
+    assert!(matches!(
+        check_register(&state, &Principal::anonymous()),
+        Err(ProtocolError::AnonymousCallerNotAllowed)
+    ));
+    assert!(check_register(&state, &alice()).is_ok());
+
+    assert!(validate_name(&None).is_ok());
+    assert!(validate_name(&Some("keeper".to_string())).is_ok());
+    assert!(validate_name(&Some(String::new())).is_err());
+    assert!(validate_name(&Some("x".repeat(MAX_LIQUIDATOR_NAME_LEN + 1))).is_err());
+}
@@ -0,0 +1,200 @@
+//! Liquidity pool anti-dust policy: every deposit (top-ups included) meets
+//! the minimum and the provider book is capped, a withdrawal that would
+//! leave less than the minimum behind takes the remainder with it, dust
+//! returns are set aside for the treasury (never credited as icUSD), and the
+//! residual sweep sees sub-minimum positions smallest first.
+//!
+//! The minimum is 10 icUSD and ICP is at $10.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::liquidity_pool::{
+    apply_dust_sweep, check_provide, dust_sweep, plan_withdrawal, residual_positions,
+    MAX_LIQUIDITY_PROVIDERS,
+};
+use rumi_protocol_backend::numeric::{UsdIcp, ICP, ICUSD};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::ProtocolError;
+
+use common::{fresh_state, init_arg};
+
+const E8S: u64 = 100_000_000;
+
+fn user(n: u8) -> Principal {
+    Principal::from_slice(&[n])
+}
+
+fn icusd(units: u64) -> ICUSD {
+    ICUSD::new(units * E8S)
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    state.last_icp_rate = Some(UsdIcp::from(dec!(10)));
+    state
+}
+
+#[test]
+fn every_deposit_meets_the_minimum() {
+    let mut state = fixture();
+    assert!(check_provide(&state, user(1), icusd(10)).is_ok());
+    state.provide_liquidity(icusd(10), user(1));
+
+    // A top-up is held to the same minimum as the first deposit.
+    assert!(matches!(
+        check_provide(&state, user(1), icusd(9)),
+        Err(ProtocolError::AmountTooLow { minimum_amount }) if minimum_amount == 10 * E8S
+    ));
+}
+
+#[test]
+fn provider_book_is_capped_for_newcomers_only() {
+    let mut state = fixture();
+    for n in 0..MAX_LIQUIDITY_PROVIDERS as u32 {
+        state.provide_liquidity(icusd(10), Principal::from_slice(&n.to_be_bytes()));
+    }
+    let existing = Principal::from_slice(&0u32.to_be_bytes());
+    assert!(check_provide(&state, existing, icusd(10)).is_ok());
+    assert!(matches!(
+        check_provide(&state, user(200), icusd(10)),
+        Err(ProtocolError::GenericError(_))
+    ));
+}
+
+#[test]
+fn withdrawal_takes_a_sub_minimum_remainder_along() {
+    let mut state = fixture();
+    state.provide_liquidity(icusd(15), user(1));
+    assert_eq!(
+        plan_withdrawal(&state, user(1), icusd(10)).unwrap(),
+        (icusd(15), icusd(5))
+    );
+    assert_eq!(
+        plan_withdrawal(&state, user(1), icusd(15)).unwrap(),
+        (icusd(15), icusd(0))
+    );
+    assert!(matches!(
+        plan_withdrawal(&state, user(1), icusd(3)),
+        Err(ProtocolError::AmountTooLow { .. })
+    ));
+    assert!(plan_withdrawal(&state, user(1), icusd(16)).is_err());
+    assert!(plan_withdrawal(&state, user(2), icusd(10)).is_err());
+
+    state.provide_liquidity(icusd(10), user(1));
+    assert_eq!(
+        plan_withdrawal(&state, user(1), icusd(10)).unwrap(),
+        (icusd(10), icusd(0))
+    );
+
+    // A legacy position under the minimum can still be withdrawn whole.
+    state.provide_liquidity(icusd(4), user(2));
+    assert_eq!(
+        plan_withdrawal(&state, user(2), icusd(4)).unwrap(),
+        (icusd(4), icusd(0))
+    );
+}
+
+#[test]
+fn dust_returns_are_swept_not_credited_as_icusd() {
+    let mut state = fixture();
+    state.provide_liquidity(icusd(10), user(1));
+    state.liquidity_returns.insert(user(1), ICP::new(50_000));
+
+    let returns = dust_sweep(&state, user(1)).expect("dust");
+    assert_eq!(returns, ICP::new(50_000));
+    apply_dust_sweep(&mut state, user(1), returns);
+    assert!(!state.liquidity_returns.contains_key(&user(1)));
+    assert_eq!(state.get_provided_liquidity(user(1)), icusd(10));
+    assert_eq!(state.liquidity_dust_swept, returns);
+
+    // Claimable returns or returns without a position: untouched.
+    state.liquidity_returns.insert(user(1), ICP::new(E8S));
+    assert!(dust_sweep(&state, user(1)).is_none());
+    state.liquidity_returns.insert(user(2), ICP::new(50_000));
+    assert!(dust_sweep(&state, user(2)).is_none());
+}
+
+#[test]
+fn replay_tracks_swept_dust_until_sent() {
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::ProvideLiquidity {
+            amount: icusd(10),
+            block_index: 1,
+            caller: user(1),
+            timestamp: None,
+        },
+        Event::LiquidityDustSwept {
+            caller: user(1),
+            returns: ICP::new(50_000),
+            timestamp: 2,
+        },
+        Event::LiquidityDustSwept {
+            caller: user(1),
+            returns: ICP::new(30_000),
+            timestamp: 3,
+        },
+    ];
+    let state = replay(events.clone().into_iter()).expect("replay");
+    assert_eq!(state.liquidity_dust_swept, ICP::new(80_000));
+    assert_eq!(state.get_provided_liquidity(user(1)), icusd(10));
+
+    let mut events = events;
+    events.push(Event::LiquidityDustSentToTreasury {
+        amount: ICP::new(80_000),
+        block_index: 4,
+        timestamp: 4,
+    });
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(state.liquidity_dust_swept, ICP::new(0));
+}
+
+#[test]
+fn residual_positions_are_listed_smallest_first() {
+    let mut state = fixture();
+    state.provide_liquidity(icusd(7), user(1));
+    state.provide_liquidity(icusd(20), user(2));
+    state.provide_liquidity(icusd(3), user(3));
+    state.provide_liquidity(icusd(5), user(4));
+
+    assert_eq!(
+        residual_positions(&state, 10),
+        vec![
+            (user(3), icusd(3)),
+            (user(4), icusd(5)),
+            (user(1), icusd(7))
+        ]
+    );
+    assert_eq!(residual_positions(&state, 1), vec![(user(3), icusd(3))]);
+}
+
+#[test]
+fn replay_removes_returned_residuals() {
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::ProvideLiquidity {
+            amount: icusd(15),
+            block_index: 1,
+            caller: user(1),
+            timestamp: None,
+        },
+        Event::WithdrawLiquidity {
+            amount: icusd(10),
+            block_index: 2,
+            caller: user(1),
+            timestamp: None,
+        },
+        Event::LiquidityResidualReturned {
+            caller: user(1),
+            amount: icusd(5),
+            block_index: 2,
+            timestamp: 5,
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert!(state.liquidity_pool.is_empty());
+}
@@ -0,0 +1,106 @@
+//! Log retention: critical entries persisted to stable memory are kept in a
+//! bounded ring (oldest dropped first), `/logs?persisted=true` returns the
+//! ones recorded before the running wasm started, buffer sizes are bounded,
+//! and the configured sizes are rebuilt by replay.
+//!
+//! Each test runs on its own thread, so each sees a fresh stable memory.
+
+mod common;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::logs::{
+    validate_retention, Log, LogEntry, LogRetentionConfig, Priority,
+    DEFAULT_PERSISTED_CRITICAL_CAPACITY, MAX_LOG_BUFFER_CAPACITY, MIN_LOG_BUFFER_CAPACITY,
+};
+use rumi_protocol_backend::storage::{
+    count_critical_logs, critical_logs, record_critical_log, trim_critical_logs,
+};
+
+use common::{fresh_state, init_arg};
+
+fn entry(timestamp: u64) -> LogEntry {
+    LogEntry {
+        timestamp,
+        priority: Priority::Critical,
+        file: "src/lib.rs".to_string(),
+        line: 1,
+        message: format!("CRITICAL: entry {}", timestamp),
+        counter: timestamp,
+    }
+}
+
+#[test]
+fn persisted_ring_drops_the_oldest_entries() {
+    for ts in 1..=5 {
+        record_critical_log(&entry(ts), 3);
+    }
+    assert_eq!(count_critical_logs(), 3);
+    let kept: Vec<u64> = critical_logs().iter().map(|e| e.timestamp).collect();
+    assert_eq!(kept, vec![3, 4, 5]);
+
+    // Lowering the capacity trims immediately.
+    trim_critical_logs(1);
+    let kept: Vec<u64> = critical_logs().iter().map(|e| e.timestamp).collect();
+    assert_eq!(kept, vec![5]);
+}
+
+#[test]
+fn persisted_view_returns_entries_from_before_the_upgrade() {
+    for ts in [10, 20, 30, 40] {
+        record_critical_log(&entry(ts), DEFAULT_PERSISTED_CRITICAL_CAPACITY);
+    }
+    let mut log = Log::default();
+    log.push_persisted_critical(30);
+    let seen: Vec<u64> = log.entries.iter().map(|e| e.timestamp).collect();
+    assert_eq!(seen, vec![10, 20]);
+    assert!(log.entries[0].message.contains("entry 10"));
+}
+
+#[test]
+fn capacities_are_bounded() {
+    assert!(validate_retention(&LogRetentionConfig::default()).is_ok());
+    assert!(validate_retention(&LogRetentionConfig {
+        info_capacity: MAX_LOG_BUFFER_CAPACITY,
+        persisted_critical_capacity: MIN_LOG_BUFFER_CAPACITY,
+        ..Default::default()
+    })
+    .is_ok());
+
+    let err = validate_retention(&LogRetentionConfig {
+        debug_capacity: MIN_LOG_BUFFER_CAPACITY - 1,
+        ..Default::default()
+    })
+    .unwrap_err();
+    assert!(err.contains("debug_capacity"), "{}", err);
+    assert!(validate_retention(&LogRetentionConfig {
+        persisted_critical_capacity: MAX_LOG_BUFFER_CAPACITY + 1,
+        ..Default::default()
+    })
+    .is_err());
+}
+
+#[test]
+fn replay_rebuilds_retention() {
+    assert_eq!(fresh_state().log_retention, LogRetentionConfig::default());
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::SetLogRetention {
+            critical_capacity: 500,
+            info_capacity: 5_000,
+            debug_capacity: 100,
+            trace_xrc_capacity: 100,
+            persisted_critical_capacity: 10_000,
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(
+        state.log_retention,
+        LogRetentionConfig {
+            critical_capacity: 500,
+            info_capacity: 5_000,
+            debug_capacity: 100,
+            trace_xrc_capacity: 100,
+            persisted_critical_capacity: 10_000,
+        }
+    );
+}
@@ -0,0 +1,122 @@
+//! Loyalty metrics: a borrower is tracked from their first update, amounts
+//! are weighted by how long they were held, the checkpoint picks up
+//! changes the borrower did not make and keeps borrowers who left, and
+//! pages cover every tracked principal once.
+//!
+//! Alice's vault holds 10 ICP at $6 and owes 20 icUSD.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::loyalty::{checkpoint, page, touch, view};
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::Vault;
+
+use common::{fresh_state, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const DAY: u64 = 24 * 3600 * 1_000_000_000;
+const T0: u64 = 100 * DAY;
+
+fn alice() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn vault(vault_id: u64, owner: Principal) -> Vault {
+    VaultBuilder::new(vault_id)
+        .owner(owner)
+        .collateral(10 * E8S)
+        .debt(20 * E8S)
+        .build()
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 6.0, Some(T0));
+    state.open_vault(vault(1, alice()));
+    state
+}
+
+#[test]
+fn a_borrower_is_tracked_from_their_first_update() {
+    let mut state = fixture();
+    assert_eq!(view(&state, &alice(), T0), None);
+
+    touch(&mut state, alice(), T0);
+    let metrics = view(&state, &alice(), T0).unwrap();
+    assert_eq!(metrics.first_seen_ns, T0);
+    assert_eq!(
+        (metrics.debt_e8s, metrics.collateral_usd_e8s),
+        (20 * E8S, 60 * E8S)
+    );
+    assert_eq!(metrics.avg_debt_e8s, 20 * E8S);
+
+    // Someone who never had a vault is not tracked.
+    let bob = Principal::from_slice(&[2]);
+    touch(&mut state, bob, T0);
+    assert_eq!(view(&state, &bob, T0), None);
+}
+
+#[test]
+fn amounts_are_weighted_by_how_long_they_were_held() {
+    let mut state = fixture();
+    touch(&mut state, alice(), T0);
+    state.borrow_from_vault(1, ICUSD::new(20 * E8S));
+    touch(&mut state, alice(), T0 + DAY);
+
+    // 20 icUSD for a day, then 40 for two days, the last one projected.
+    let metrics = view(&state, &alice(), T0 + 3 * DAY).unwrap();
+    assert_eq!(metrics.debt_time_e8s_ns, 100 * E8S as u128 * DAY as u128);
+    assert_eq!(metrics.avg_debt_e8s, 100 * E8S / 3);
+    assert_eq!(metrics.as_of_ns, T0 + 3 * DAY);
+    // Projection does not write anything.
+    assert_eq!(state.loyalty_metrics[&alice()].last_update_ns, T0 + DAY);
+}
+
+#[test]
+fn the_checkpoint_picks_up_changes_the_borrower_did_not_make() {
+    let mut state = fixture();
+    touch(&mut state, alice(), T0);
+    set_icp_price(&mut state, 3.0, Some(T0));
+    checkpoint(&mut state, T0 + DAY);
+
+    let metrics = view(&state, &alice(), T0 + 2 * DAY).unwrap();
+    assert_eq!(metrics.collateral_usd_e8s, 30 * E8S);
+    assert_eq!(metrics.avg_collateral_usd_e8s, 45 * E8S);
+
+    // A borrower who left keeps their history at zero amounts.
+    state.remove_vault_and_unindex(1);
+    checkpoint(&mut state, T0 + 2 * DAY);
+    let metrics = view(&state, &alice(), T0 + 4 * DAY).unwrap();
+    assert_eq!((metrics.debt_e8s, metrics.collateral_usd_e8s), (0, 0));
+    assert_eq!(metrics.avg_debt_e8s, 10 * E8S);
+}
+
+#[test]
+fn pages_cover_every_tracked_principal_once() {
+    let mut state = fixture();
+    for id in 2..=3u8 {
+        state.open_vault(vault(id as u64, Principal::from_slice(&[id])));
+    }
+    checkpoint(&mut state, T0);
+
+    let first = page(&state, None, 2, T0);
+    assert_eq!(first.total, 3);
+    assert_eq!(first.entries.len(), 2);
+    assert_eq!(first.next, Some(first.entries[1].principal));
+
+    let second = page(&state, first.next, 2, T0);
+    assert_eq!(second.entries.len(), 1);
+    assert_eq!(second.next, None);
+
+    let mut seen: Vec<Principal> = first
+        .entries
+        .iter()
+        .chain(&second.entries)
+        .map(|e| e.principal)
+        .collect();
+    seen.dedup();
+    assert_eq!(seen.len(), 3);
+}
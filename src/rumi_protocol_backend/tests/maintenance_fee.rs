@@ -0,0 +1,176 @@
+//! Maintenance fee: a collateral's annual fee on collateral value is added
+//! to its vaults' debt by interest accrual, borrowing or not, only from the
+//! moment it is set, and shows up in the effective parameters and on replay,
+//! where restored prices make replayed debt match the live run.
+//!
+//! One owner has a 10 ICP vault with ICP at $10, so $100 of collateral.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::effective_parameters::{resolve, ParameterSource};
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::{Ratio, ICUSD, NANOS_PER_YEAR};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::Vault;
+
+use common::{fresh_state, icp, init_arg, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const NOW: u64 = 1_000 * 1_000_000_000;
+
+fn vault(debt_e8s: u64) -> Vault {
+    VaultBuilder::new(1)
+        .owner(Principal::from_slice(&[1]))
+        .collateral(10 * E8S)
+        .debt(debt_e8s)
+        .build()
+}
+
+fn fixture(debt_e8s: u64) -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 10.0, None);
+    state.open_vault(vault(debt_e8s));
+    state
+}
+
+fn one_percent() -> Option<Ratio> {
+    Some(Ratio::from(dec!(0.01)))
+}
+
+#[test]
+fn an_idle_vault_pays_the_fee_as_debt() {
+    let mut state = fixture(0);
+    state.set_maintenance_fee_apr(&icp(), one_percent(), NOW);
+    assert_eq!(
+        state.pending_interest(1, NOW + NANOS_PER_YEAR),
+        ICUSD::new(E8S)
+    );
+
+    state.accrue_all_vault_interest(NOW + NANOS_PER_YEAR);
+    let vault = &state.vault_id_to_vaults[&1];
+    // 1% of $100 for a year.
+    assert_eq!(vault.borrowed_icusd_amount, ICUSD::new(E8S));
+    assert_eq!(vault.accrued_interest, ICUSD::new(E8S));
+    assert_eq!(vault.last_accrual_time, NOW + NANOS_PER_YEAR);
+}
+
+#[test]
+fn the_fee_is_not_charged_for_time_before_it_was_set() {
+    let mut state = fixture(0);
+    // The idle vault's accrual clock has stood at 0 since it opened.
+    state.set_maintenance_fee_apr(&icp(), one_percent(), NOW);
+    state.accrue_single_vault(1, NOW);
+    assert_eq!(
+        state.vault_id_to_vaults[&1].borrowed_icusd_amount,
+        ICUSD::new(0)
+    );
+
+    state.set_maintenance_fee_apr(&icp(), None, NOW);
+    state.accrue_all_vault_interest(NOW + NANOS_PER_YEAR);
+    assert_eq!(
+        state.vault_id_to_vaults[&1].borrowed_icusd_amount,
+        ICUSD::new(0)
+    );
+}
+
+#[test]
+fn a_borrowing_vault_pays_the_fee_on_top_of_interest() {
+    let mut without_fee = fixture(50 * E8S);
+    let mut with_fee = fixture(50 * E8S);
+    without_fee.set_maintenance_fee_apr(&icp(), None, NOW);
+    with_fee.set_maintenance_fee_apr(&icp(), one_percent(), NOW);
+
+    without_fee.accrue_single_vault(1, NOW + NANOS_PER_YEAR);
+    with_fee.accrue_single_vault(1, NOW + NANOS_PER_YEAR);
+    let debt = |s: &State| s.vault_id_to_vaults[&1].borrowed_icusd_amount.to_u64();
+    assert_eq!(debt(&with_fee), debt(&without_fee) + E8S);
+}
+
+#[test]
+fn effective_parameters_disclose_the_fee() {
+    let mut state = fixture(0);
+    let params = resolve(&state, &icp(), NOW).expect("icp");
+    assert_eq!(params.maintenance_fee_apr.value, 0.0);
+    assert_eq!(params.maintenance_fee_apr.source, ParameterSource::Default);
+
+    state.set_maintenance_fee_apr(&icp(), one_percent(), NOW);
+    let params = resolve(&state, &icp(), NOW).expect("icp");
+    assert_eq!(params.maintenance_fee_apr.value, 0.01);
+    assert_eq!(params.maintenance_fee_apr.source, ParameterSource::Override);
+}
+
+#[test]
+fn replay_restores_the_fee() {
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::SetCollateralMaintenanceFee {
+            collateral_type: icp(),
+            maintenance_fee_apr: Some("0.01".to_string()),
+            timestamp: NOW,
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(
+        state.get_maintenance_fee_apr_for(&icp()),
+        one_percent().unwrap()
+    );
+
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::SetCollateralMaintenanceFee {
+            collateral_type: icp(),
+            maintenance_fee_apr: Some("0.01".to_string()),
+            timestamp: NOW,
+        },
+        Event::SetCollateralMaintenanceFee {
+            collateral_type: icp(),
+            maintenance_fee_apr: None,
+            timestamp: NOW,
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(state.collateral_configs[&icp()].maintenance_fee_apr, None);
+}
+
+#[test]
+fn replay_books_the_same_fee_as_the_live_path() {
+    let later = NOW + NANOS_PER_YEAR;
+
+    let mut live = fresh_state();
+    live.open_vault(vault(0));
+    live.set_maintenance_fee_apr(&icp(), one_percent(), NOW);
+    live.apply_price_update(icp(), dec!(10), NOW);
+    live.accrue_all_vault_interest(later);
+
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::OpenVault {
+            vault: vault(0),
+            block_index: 0,
+            timestamp: None,
+        },
+        Event::SetCollateralMaintenanceFee {
+            collateral_type: icp(),
+            maintenance_fee_apr: Some("0.01".to_string()),
+            timestamp: NOW,
+        },
+        Event::PriceUpdate {
+            collateral_type: icp(),
+            price: "10".to_string(),
+            timestamp: NOW,
+        },
+        Event::AccrueInterest { timestamp: later },
+    ];
+    let replayed = replay(events.into_iter()).expect("replay");
+
+    let debt = |s: &State| s.vault_id_to_vaults[&1].borrowed_icusd_amount;
+    assert_eq!(debt(&live), ICUSD::new(E8S));
+    assert_eq!(debt(&replayed), debt(&live));
+    assert_eq!(
+        replayed.collateral_configs[&icp()].last_price,
+        live.collateral_configs[&icp()].last_price
+    );
+}
@@ -0,0 +1,107 @@
+//! Math test vectors: the artifact is deterministic, serializes every number
+//! as a string, and its outputs agree with hand-checked values for the cases
+//! simple enough to work out by hand.
+
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::math_vectors::{
+    math_test_vectors, math_test_vectors_json, MATH_TEST_VECTORS_VERSION,
+};
+
+const E8S: u64 = 100_000_000;
+
+fn decimal(s: &str) -> Decimal {
+    s.parse().expect("decimal string")
+}
+
+fn int(s: &str) -> u64 {
+    s.parse().expect("integer string")
+}
+
+#[test]
+fn vectors_are_deterministic_and_cover_every_section() {
+    let vectors = math_test_vectors();
+    assert_eq!(vectors, math_test_vectors());
+    assert_eq!(vectors.version, MATH_TEST_VECTORS_VERSION);
+    assert!(!vectors.collateral_ratio.is_empty());
+    assert!(!vectors.borrowing_fee.is_empty());
+    assert!(!vectors.redemption_fee.is_empty());
+    assert!(!vectors.liquidation.is_empty());
+    assert!(!vectors.redemption_split.is_empty());
+}
+
+#[test]
+fn json_numbers_are_strings() {
+    let json: serde_json::Value =
+        serde_json::from_str(&math_test_vectors_json()).expect("valid JSON");
+    assert_eq!(json["version"], MATH_TEST_VECTORS_VERSION);
+    let first = &json["collateral_ratio"][0];
+    assert_eq!(first["collateral_amount"], "1000000000");
+    assert!(first["collateral_ratio"].is_string());
+    // 5 tokens at 18 decimals do not fit a JS number exactly.
+    assert_eq!(
+        json["collateral_ratio"][3]["collateral_amount"],
+        "5000000000000000000"
+    );
+}
+
+#[test]
+fn hand_checked_outputs() {
+    let vectors = math_test_vectors();
+
+    // 10 ICP at $10 against 50 icUSD.
+    assert_eq!(
+        decimal(&vectors.collateral_ratio[0].collateral_ratio),
+        dec!(2)
+    );
+
+    // 0.5% of 100 icUSD; a 100% fee still leaves 1 e8s to mint.
+    assert_eq!(int(&vectors.borrowing_fee[0].fee_e8s), E8S / 2);
+    assert_eq!(int(&vectors.borrowing_fee[4].fee_e8s), 9);
+
+    // 20% of the debt redeemed blows through the 5% ceiling; no debt, no fee.
+    assert_eq!(decimal(&vectors.redemption_fee[3].fee_rate), dec!(0.05));
+    assert_eq!(decimal(&vectors.redemption_fee[5].fee_rate), dec!(0));
+
+    // $50 of debt at $4.50 is worth more than the 10 ICP in the vault: the
+    // whole vault is seized and there is no bonus for the protocol to share.
+    let capped = &vectors.liquidation[2];
+    assert_eq!(int(&capped.collateral_seized), 10 * E8S);
+    assert_eq!(int(&capped.protocol_cut), 0);
+    assert_eq!(int(&capped.collateral_to_liquidator), 10 * E8S);
+    for v in &vectors.liquidation {
+        assert_eq!(
+            int(&v.collateral_seized),
+            int(&v.protocol_cut) + int(&v.collateral_to_liquidator)
+        );
+        assert!(int(&v.collateral_seized) <= int(&v.collateral_amount));
+    }
+}
+
+#[test]
+fn redemption_splits_start_at_the_lowest_ratio() {
+    let vectors = math_test_vectors();
+
+    // 150 icUSD over three 100 icUSD vaults: the 150% vault goes first and
+    // the whole claim is filled.
+    let split = &vectors.redemption_split[1];
+    assert_eq!(split.redemptions[0].vault_id, split.vaults[0].vault_id);
+    let redeemed: u64 = split
+        .redemptions
+        .iter()
+        .map(|r| int(&r.icusd_redeemed_e8s))
+        .sum();
+    assert_eq!(redeemed, 150 * E8S);
+
+    // A claim larger than the debt takes no more than the debt.
+    for split in &vectors.redemption_split {
+        let debt: u64 = split.vaults.iter().map(|v| int(&v.debt_e8s)).sum();
+        let redeemed: u64 = split
+            .redemptions
+            .iter()
+            .map(|r| int(&r.icusd_redeemed_e8s))
+            .sum();
+        assert!(redeemed <= debt.min(int(&split.redeemed_e8s)));
+    }
+}
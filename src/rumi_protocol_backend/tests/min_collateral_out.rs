@@ -0,0 +1,60 @@
+//! `min_collateral_out` on the liquidation endpoints: the payout bound is
+//! checked before the liquidator's icUSD / stable is pulled, so a payout that
+//! shrank between quote and execution rejects without moving any funds.
+
+use rumi_protocol_backend::numeric::ICP;
+use rumi_protocol_backend::vault::check_min_collateral_out;
+use rumi_protocol_backend::ProtocolError;
+
+#[test]
+fn no_bound_always_passes() {
+    assert!(check_min_collateral_out(ICP::new(0), None).is_ok());
+    assert!(check_min_collateral_out(ICP::new(1_000), None).is_ok());
+}
+
+#[test]
+fn payout_at_or_above_bound_passes() {
+    assert!(check_min_collateral_out(ICP::new(1_000), Some(1_000)).is_ok());
+    assert!(check_min_collateral_out(ICP::new(1_001), Some(1_000)).is_ok());
+}
+
+#[test]
+fn payout_below_bound_reports_both_amounts() {
+    assert!(matches!(
+        check_min_collateral_out(ICP::new(999), Some(1_000)),
+        Err(ProtocolError::MinCollateralOutNotMet {
+            min_collateral_out: 1_000,
+            collateral_out: 999,
+        })
+    ));
+}
+
+#[test]
+fn bound_is_checked_before_funds_are_pulled() {
+    let src = std::fs::read_to_string(
+        std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/vault.rs"),
+    )
+    .expect("read vault.rs");
+    for (entry, transfer) in [
+        (
+            "pub async fn liquidate_vault_partial(",
+            "transfer_icusd_from(",
+        ),
+        (
+            "pub async fn liquidate_vault_partial_with_stable(",
+            "transfer_stable_from(",
+        ),
+        (
+            "pub async fn partial_liquidate_vault(",
+            "transfer_icusd_from(",
+        ),
+        ("pub async fn liquidate_vault(", "transfer_icusd_from("),
+    ] {
+        let body = &src[src.find(entry).expect(entry)..];
+        let check = body
+            .find("check_min_collateral_out(")
+            .unwrap_or_else(|| panic!("{} does not check min_collateral_out", entry));
+        let pull = body.find(transfer).expect(transfer);
+        assert!(check < pull, "{} pulls funds before the bound check", entry);
+    }
+}
@@ -0,0 +1,164 @@
+//! Recovery hysteresis and the freshness gate on relaxing transitions.
+//!
+//! The protocol enters Recovery below the recovery threshold `X` but leaves
+//! it only at `X + band`, and an automatic move toward a less restrictive
+//! mode waits for a fresh price on every collateral backing debt.
+//!
+//! ICP is the only collateral (X = 150%, default band 5 points) and one
+//! vault of 10 ICP owes 50 icUSD, so the TCR is the ICP price × 20%.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::mode::{
+    collateral_ratio_target, is_relaxation, DEFAULT_RECOVERY_EXIT_BAND,
+    MODE_RELAXATION_MAX_PRICE_AGE_NANOS,
+};
+use rumi_protocol_backend::numeric::{Ratio, UsdIcp};
+use rumi_protocol_backend::state::{Mode, ModeTransition, ModeTransitionReason, State};
+
+use common::{fresh_state, init_arg, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const T0: u64 = 1_000_000_000_000;
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    state.open_vault(
+        VaultBuilder::new(1)
+            .owner(Principal::from_slice(&[1]))
+            .collateral(10 * E8S)
+            .debt(50 * E8S)
+            .build(),
+    );
+    state
+}
+
+/// Price ICP at `price` as of `priced_at`, then re-evaluate the mode at `now`.
+fn tick(state: &mut State, price: UsdIcp, priced_at: u64, now: u64) {
+    state.set_icp_rate(price, Some(priced_at));
+    state.update_total_collateral_ratio_and_mode(price, now);
+}
+
+#[test]
+fn recovery_is_left_only_above_the_exit_band() {
+    let mut state = fixture();
+    // 140%: below 150%.
+    tick(&mut state, UsdIcp::from(dec!(7)), T0, T0);
+    assert_eq!(state.recovery_mode_threshold, Ratio::from(dec!(1.5)));
+    assert_eq!(state.mode, Mode::Recovery);
+
+    // 152%: above the threshold but inside the band.
+    tick(&mut state, UsdIcp::from(dec!(7.6)), T0 + 1, T0 + 1);
+    assert_eq!(state.mode, Mode::Recovery);
+
+    // 156%: clear of the band.
+    tick(&mut state, UsdIcp::from(dec!(7.8)), T0 + 2, T0 + 2);
+    assert_eq!(state.mode, Mode::GeneralAvailability);
+
+    // 152% again: GA only re-enters Recovery below 150%.
+    tick(&mut state, UsdIcp::from(dec!(7.6)), T0 + 3, T0 + 3);
+    assert_eq!(state.mode, Mode::GeneralAvailability);
+
+    assert_eq!(
+        state.pending_mode_transitions,
+        vec![
+            ModeTransition {
+                from: Mode::GeneralAvailability,
+                to: Mode::Recovery,
+                reason: ModeTransitionReason::CollateralRatio,
+            },
+            ModeTransition {
+                from: Mode::Recovery,
+                to: Mode::GeneralAvailability,
+                reason: ModeTransitionReason::CollateralRatio,
+            },
+        ]
+    );
+}
+
+#[test]
+fn a_zero_band_exits_at_the_threshold() {
+    let mut state = fixture();
+    state.recovery_exit_band = Some(Ratio::from(dec!(0)));
+    tick(&mut state, UsdIcp::from(dec!(7)), T0, T0);
+    assert_eq!(state.mode, Mode::Recovery);
+    tick(&mut state, UsdIcp::from(dec!(7.6)), T0 + 1, T0 + 1);
+    assert_eq!(state.mode, Mode::GeneralAvailability);
+}
+
+#[test]
+fn a_stale_price_holds_relaxation_but_not_entry() {
+    let mut state = fixture();
+    let stale = MODE_RELAXATION_MAX_PRICE_AGE_NANOS + 1;
+    // Entering Recovery never waits for a price.
+    tick(&mut state, UsdIcp::from(dec!(7)), T0, T0 + stale);
+    assert_eq!(state.mode, Mode::Recovery);
+
+    // 160%, but priced too long ago to leave Recovery on.
+    tick(&mut state, UsdIcp::from(dec!(8)), T0, T0 + stale);
+    assert_eq!(state.mode, Mode::Recovery);
+
+    tick(&mut state, UsdIcp::from(dec!(8)), T0 + stale, T0 + stale);
+    assert_eq!(state.mode, Mode::GeneralAvailability);
+}
+
+#[test]
+fn the_target_depends_on_the_current_mode() {
+    let x = Ratio::from(dec!(1.5));
+    let band = DEFAULT_RECOVERY_EXIT_BAND;
+    let tcr = Ratio::from(dec!(1.52));
+    assert_eq!(
+        collateral_ratio_target(Mode::GeneralAvailability, tcr, x, band, false),
+        Mode::GeneralAvailability
+    );
+    assert_eq!(
+        collateral_ratio_target(Mode::Recovery, tcr, x, band, false),
+        Mode::Recovery
+    );
+    assert_eq!(
+        collateral_ratio_target(Mode::ReadOnly, tcr, x, band, false),
+        Mode::Recovery
+    );
+    // A deviation hold keeps Recovery at any ratio.
+    assert_eq!(
+        collateral_ratio_target(
+            Mode::GeneralAvailability,
+            Ratio::from(dec!(3)),
+            x,
+            band,
+            true
+        ),
+        Mode::Recovery
+    );
+
+    assert!(is_relaxation(Mode::Recovery, Mode::GeneralAvailability));
+    assert!(is_relaxation(Mode::ReadOnly, Mode::Recovery));
+    assert!(!is_relaxation(Mode::GeneralAvailability, Mode::Recovery));
+    assert!(!is_relaxation(Mode::Recovery, Mode::ReadOnly));
+}
+
+#[test]
+fn replay_restores_the_exit_band() {
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::SetRecoveryExitBand {
+            band: Some("0.1".to_string()),
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(state.get_recovery_exit_band(), Ratio::from(dec!(0.1)));
+
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::SetRecoveryExitBand {
+            band: Some("0.1".to_string()),
+        },
+        Event::SetRecoveryExitBand { band: None },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(state.get_recovery_exit_band(), DEFAULT_RECOVERY_EXIT_BAND);
+}
@@ -0,0 +1,99 @@
+//! Mode propagation bookkeeping: companions that have not acknowledged the
+//! current mode are the ones to notify, an acknowledgement only counts for a
+//! registered companion, re-registration drops stale acknowledgements, and
+//! replay rebuilds the companion set (but never acknowledgements).
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::mode_propagation::{
+    apply_set_companions, companions_to_notify, mode_propagation_status, record_ack,
+    validate_companions, MAX_MODE_COMPANIONS,
+};
+use rumi_protocol_backend::state::{Mode, State};
+
+use common::{fresh_state, init_arg};
+
+fn pool() -> Principal {
+    Principal::from_slice(&[30])
+}
+
+fn treasury() -> Principal {
+    Principal::from_slice(&[31])
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    apply_set_companions(&mut state, vec![pool(), treasury()]);
+    state
+}
+
+#[test]
+fn companions_are_validated() {
+    assert!(validate_companions(&[pool(), treasury()]).is_ok());
+    assert!(validate_companions(&[]).is_ok());
+    assert!(validate_companions(&[pool(), Principal::anonymous()]).is_err());
+    let too_many: Vec<Principal> = (0..=MAX_MODE_COMPANIONS as u8)
+        .map(|i| Principal::from_slice(&[100, i]))
+        .collect();
+    assert!(validate_companions(&too_many).is_err());
+}
+
+#[test]
+fn unacknowledged_companions_are_notified() {
+    let mut state = fixture();
+    assert_eq!(companions_to_notify(&state), vec![pool(), treasury()]);
+
+    let mode = state.mode;
+    record_ack(&mut state, pool(), mode);
+    assert_eq!(companions_to_notify(&state), vec![treasury()]);
+    record_ack(&mut state, treasury(), mode);
+    assert!(companions_to_notify(&state).is_empty());
+    assert!(mode_propagation_status(&state).iter().all(|c| c.in_sync));
+
+    // A mode change puts every companion out of sync again.
+    state.mode = Mode::ReadOnly;
+    assert_eq!(companions_to_notify(&state), vec![pool(), treasury()]);
+    let status = mode_propagation_status(&state);
+    assert_eq!(status[0].acknowledged_mode, Some(mode));
+    assert!(!status[0].in_sync);
+}
+
+#[test]
+fn acknowledgements_follow_registration() {
+    let mut state = fixture();
+    let mode = state.mode;
+    // Not registered: ignored.
+    record_ack(&mut state, Principal::from_slice(&[99]), mode);
+    assert!(state.mode_companion_acks.is_empty());
+
+    record_ack(&mut state, pool(), mode);
+    record_ack(&mut state, treasury(), mode);
+    apply_set_companions(&mut state, vec![treasury()]);
+    assert!(!state.mode_companion_acks.contains_key(&pool()));
+    assert!(companions_to_notify(&state).is_empty());
+
+    // Re-adding a companion requires a fresh acknowledgement.
+    apply_set_companions(&mut state, vec![treasury(), pool()]);
+    assert_eq!(companions_to_notify(&state), vec![pool()]);
+}
+
+#[test]
+fn replay_rebuilds_companions_without_acks() {
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::SetModeCompanionCanisters {
+            canisters: vec![pool(), treasury()],
+        },
+        Event::SetModeCompanionCanisters {
+            canisters: vec![treasury()],
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(state.mode_companion_canisters.len(), 1);
+    assert!(state.mode_companion_canisters.contains(&treasury()));
+    assert!(state.mode_companion_acks.is_empty());
+    assert_eq!(companions_to_notify(&state), vec![treasury()]);
+}
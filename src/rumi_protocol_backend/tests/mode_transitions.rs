@@ -0,0 +1,214 @@
+//! Mode transitions go through `State::transition_mode`, which validates
+//! the `from -> to` edge against the trigger that caused it and buffers
+//! each applied transition for `event::record_mode_transitions`.
+//!
+//! Layered fences:
+//!  1. Entering ReadOnly is legal from every mode for every safety trigger.
+//!  2. Leaving ReadOnly requires a recovery-style reason, and the readiness
+//!     checks (TCR >= 100%, no supply-invariant halt, deficit below the
+//!     latch threshold) must pass. Upgrades skip the checks; admin
+//!     overrides skip only the deficit latch.
+//!  3. A caller holding a stale `from` is rejected without mutating state.
+//!  4. Applied transitions land in `pending_mode_transitions`; no-ops and
+//!     rejections do not.
+
+mod common;
+
+use rumi_protocol_backend::numeric::{Ratio, ICUSD};
+use rumi_protocol_backend::state::{
+    is_legal_mode_transition, Mode, ModeTransition, ModeTransitionError, ModeTransitionReason,
+    State,
+};
+use rust_decimal_macros::dec;
+
+use common::fresh_state;
+
+fn read_only_state() -> State {
+    let mut state = fresh_state();
+    assert!(state.enter_read_only(ModeTransitionReason::Insolvency));
+    state.pending_mode_transitions.clear();
+    state
+}
+
+#[test]
+fn safety_triggers_enter_read_only_from_any_mode() {
+    for from in [Mode::GeneralAvailability, Mode::Recovery] {
+        for reason in [
+            ModeTransitionReason::Insolvency,
+            ModeTransitionReason::OracleCircuitBreaker,
+            ModeTransitionReason::PriceFloor,
+            ModeTransitionReason::DeficitThreshold,
+            ModeTransitionReason::SupplyInvariantHalt,
+            ModeTransitionReason::AdminOverride,
+        ] {
+            assert!(
+                is_legal_mode_transition(from, Mode::ReadOnly, reason),
+                "{} -> ReadOnly should be legal for {:?}",
+                from,
+                reason
+            );
+        }
+    }
+}
+
+#[test]
+fn safety_triggers_cannot_leave_read_only() {
+    for reason in [
+        ModeTransitionReason::Insolvency,
+        ModeTransitionReason::OracleCircuitBreaker,
+        ModeTransitionReason::PriceFloor,
+        ModeTransitionReason::DeficitThreshold,
+        ModeTransitionReason::SupplyInvariantHalt,
+    ] {
+        let mut state = read_only_state();
+        let err = state
+            .transition_mode(Mode::ReadOnly, Mode::GeneralAvailability, reason)
+            .unwrap_err();
+        assert!(matches!(err, ModeTransitionError::IllegalTransition { .. }));
+        assert_eq!(state.mode, Mode::ReadOnly);
+    }
+}
+
+#[test]
+fn oracle_recovery_cannot_move_between_ga_and_recovery() {
+    let mut state = fresh_state();
+    let err = state
+        .transition_mode(
+            Mode::GeneralAvailability,
+            Mode::Recovery,
+            ModeTransitionReason::OracleRecovered,
+        )
+        .unwrap_err();
+    assert!(matches!(err, ModeTransitionError::IllegalTransition { .. }));
+    assert_eq!(state.mode, Mode::GeneralAvailability);
+}
+
+#[test]
+fn stale_from_is_rejected() {
+    let mut state = fresh_state();
+    let err = state
+        .transition_mode(
+            Mode::Recovery,
+            Mode::GeneralAvailability,
+            ModeTransitionReason::CollateralRatio,
+        )
+        .unwrap_err();
+    assert_eq!(
+        err,
+        ModeTransitionError::UnexpectedCurrentMode {
+            expected: Mode::Recovery,
+            actual: Mode::GeneralAvailability,
+        }
+    );
+    assert!(state.pending_mode_transitions.is_empty());
+}
+
+#[test]
+fn leaving_read_only_requires_solvency() {
+    let mut state = read_only_state();
+    state.total_collateral_ratio = Ratio::from(dec!(0.95));
+
+    let err = state
+        .transition_mode(
+            Mode::ReadOnly,
+            Mode::GeneralAvailability,
+            ModeTransitionReason::AdminOverride,
+        )
+        .unwrap_err();
+    assert!(matches!(err, ModeTransitionError::ExitChecksFailed(_)));
+    assert_eq!(state.mode, Mode::ReadOnly);
+
+    state.total_collateral_ratio = Ratio::from(dec!(1.5));
+    assert_eq!(
+        state.transition_mode(
+            Mode::ReadOnly,
+            Mode::GeneralAvailability,
+            ModeTransitionReason::AdminOverride,
+        ),
+        Ok(true)
+    );
+    assert_eq!(state.mode, Mode::GeneralAvailability);
+}
+
+#[test]
+fn leaving_read_only_blocked_by_supply_invariant_halt() {
+    let mut state = read_only_state();
+    state.multi_chain.invariant_halted = true;
+
+    let err = state
+        .transition_mode(
+            Mode::ReadOnly,
+            Mode::GeneralAvailability,
+            ModeTransitionReason::OracleRecovered,
+        )
+        .unwrap_err();
+    assert!(matches!(err, ModeTransitionError::ExitChecksFailed(_)));
+    assert_eq!(state.mode, Mode::ReadOnly);
+}
+
+#[test]
+fn deficit_latch_blocks_automatic_exit_but_not_admin_override() {
+    let mut state = read_only_state();
+    state.deficit_readonly_threshold_e8s = 1_000;
+    state.protocol_deficit_icusd = ICUSD::new(1_000);
+
+    let err = state
+        .transition_mode(
+            Mode::ReadOnly,
+            Mode::GeneralAvailability,
+            ModeTransitionReason::CollateralRatio,
+        )
+        .unwrap_err();
+    assert!(matches!(err, ModeTransitionError::ExitChecksFailed(_)));
+
+    assert_eq!(
+        state.transition_mode(
+            Mode::ReadOnly,
+            Mode::GeneralAvailability,
+            ModeTransitionReason::AdminOverride,
+        ),
+        Ok(true)
+    );
+}
+
+#[test]
+fn upgrade_skips_read_only_exit_checks() {
+    let mut state = read_only_state();
+    state.total_collateral_ratio = Ratio::from(dec!(0.5));
+
+    assert_eq!(
+        state.transition_mode(
+            Mode::ReadOnly,
+            Mode::GeneralAvailability,
+            ModeTransitionReason::Upgrade,
+        ),
+        Ok(true)
+    );
+}
+
+#[test]
+fn applied_transitions_are_buffered_for_the_event_log() {
+    let mut state = fresh_state();
+
+    assert_eq!(
+        state.transition_mode(
+            Mode::GeneralAvailability,
+            Mode::GeneralAvailability,
+            ModeTransitionReason::CollateralRatio,
+        ),
+        Ok(false)
+    );
+    assert!(state.pending_mode_transitions.is_empty());
+
+    assert!(state.enter_read_only(ModeTransitionReason::PriceFloor));
+    assert!(!state.enter_read_only(ModeTransitionReason::Insolvency));
+
+    assert_eq!(
+        state.pending_mode_transitions,
+        vec![ModeTransition {
+            from: Mode::GeneralAvailability,
+            to: Mode::ReadOnly,
+            reason: ModeTransitionReason::PriceFloor,
+        }]
+    );
+}
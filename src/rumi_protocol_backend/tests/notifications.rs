@@ -0,0 +1,133 @@
+//! Risk-change notifications: a parameter change queues one entry per vault
+//! whose risk level moved, for that vault's owner, and leaves vaults that
+//! stayed in their band alone.
+//!
+//! ICP is at $10 (liquidation 133%, borrow threshold 150%, healthy 225%)
+//! and three owners hold 10 ICP each.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::notifications::{
+    classify_vault, dismiss_notifications, with_risk_notifications, VaultRiskLevel,
+    MAX_NOTIFICATIONS_PER_OWNER,
+};
+use rumi_protocol_backend::numeric::Ratio;
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::Vault;
+
+use common::{fresh_state, icp, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const NOW_NS: u64 = 1_000;
+
+fn owner(n: u8) -> Principal {
+    Principal::from_slice(&[n])
+}
+
+fn vault(vault_id: u64, owner: Principal, debt_e8s: u64) -> Vault {
+    VaultBuilder::new(vault_id)
+        .owner(owner)
+        .collateral(10 * E8S)
+        .debt(debt_e8s)
+        .build()
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 10.0, None);
+    state.open_vault(vault(1, owner(1), 50 * E8S)); // CR 200%
+    state.open_vault(vault(2, owner(2), 70 * E8S)); // CR ~142.9%
+    state.open_vault(vault(3, owner(3), 74 * E8S)); // CR ~135.1%
+    state
+}
+
+fn level(state: &State, vault_id: u64) -> VaultRiskLevel {
+    classify_vault(state, &state.vault_id_to_vaults[&vault_id]).unwrap()
+}
+
+#[test]
+fn classification_bands() {
+    let state = fixture();
+    assert_eq!(level(&state, 1), VaultRiskLevel::Caution);
+    assert_eq!(level(&state, 2), VaultRiskLevel::AtRisk);
+    assert_eq!(level(&state, 3), VaultRiskLevel::AtRisk);
+}
+
+#[test]
+fn raising_liquidation_ratio_notifies_only_vaults_that_crossed() {
+    let mut state = fixture();
+    let ((), queued) =
+        with_risk_notifications(&mut state, icp(), "liquidation_ratio", NOW_NS, |s| {
+            s.collateral_configs
+                .get_mut(&icp())
+                .unwrap()
+                .liquidation_ratio = Ratio::from(dec!(1.4));
+        });
+
+    assert_eq!(queued, 1);
+    assert!(!state.vault_notifications.contains_key(&owner(2)));
+    let entries = &state.vault_notifications[&owner(3)];
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].vault_id, 3);
+    assert_eq!(entries[0].parameter, "liquidation_ratio");
+    assert_eq!(entries[0].previous_level, VaultRiskLevel::AtRisk);
+    assert_eq!(entries[0].new_level, VaultRiskLevel::Liquidatable);
+    assert_eq!(entries[0].created_at_ns, NOW_NS);
+}
+
+#[test]
+fn improvements_are_reported_too() {
+    let mut state = fixture();
+    let ((), queued) = with_risk_notifications(&mut state, icp(), "healthy_cr", NOW_NS, |s| {
+        s.collateral_configs.get_mut(&icp()).unwrap().healthy_cr = Some(Ratio::from(dec!(1.9)));
+    });
+
+    assert_eq!(queued, 1);
+    let entry = &state.vault_notifications[&owner(1)][0];
+    assert_eq!(
+        (entry.previous_level, entry.new_level),
+        (VaultRiskLevel::Caution, VaultRiskLevel::Healthy)
+    );
+}
+
+#[test]
+fn unpriced_collateral_is_not_classified() {
+    let mut state = fixture();
+    state.collateral_configs.get_mut(&icp()).unwrap().last_price = None;
+    let ((), queued) =
+        with_risk_notifications(&mut state, icp(), "liquidation_ratio", NOW_NS, |s| {
+            s.collateral_configs
+                .get_mut(&icp())
+                .unwrap()
+                .liquidation_ratio = Ratio::from(dec!(1.45));
+        });
+    assert_eq!(queued, 0);
+    assert!(state.vault_notifications.is_empty());
+}
+
+#[test]
+fn queue_is_bounded_and_dismissable() {
+    let mut state = fixture();
+    for i in 0..(MAX_NOTIFICATIONS_PER_OWNER + 5) {
+        let healthy = if i % 2 == 0 { dec!(1.9) } else { dec!(2.25) };
+        with_risk_notifications(&mut state, icp(), "healthy_cr", NOW_NS, |s| {
+            s.collateral_configs.get_mut(&icp()).unwrap().healthy_cr = Some(Ratio::from(healthy));
+        });
+    }
+
+    let entries = &state.vault_notifications[&owner(1)];
+    assert_eq!(entries.len(), MAX_NOTIFICATIONS_PER_OWNER);
+    // The oldest five were dropped.
+    assert_eq!(entries[0].id, 5);
+
+    let last_id = entries.last().unwrap().id;
+    assert_eq!(dismiss_notifications(&mut state, owner(1), 9), 5);
+    assert_eq!(
+        dismiss_notifications(&mut state, owner(1), last_id),
+        MAX_NOTIFICATIONS_PER_OWNER - 5
+    );
+    assert!(!state.vault_notifications.contains_key(&owner(1)));
+}
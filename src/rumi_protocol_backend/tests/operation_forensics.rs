@@ -0,0 +1,252 @@
+//! Operation forensics: the bundle holds the user's in-flight guard, guard
+//! history and operation names, the log lines naming them or one of their
+//! traces, their events within the range, and every pending payout they are
+//! still owed; the guard history itself is bounded and filtered per user.
+//!
+//! User `[1]` holds a `borrow_from_vault` guard and is owed both an excess
+//! transfer on vault 7 and a reserve-redemption refund.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::Event;
+use rumi_protocol_backend::forensics::{
+    operation_forensics, pending_entries, validate_time_range, PendingQueue, MAX_FORENSIC_EVENTS,
+};
+use rumi_protocol_backend::guard::{
+    guard_history, record_guard, GuardOutcome, GuardRecord, OperationState, TraceId,
+    GUARD_HISTORY_CAPACITY,
+};
+use rumi_protocol_backend::logs::{LogEntry, Priority};
+use rumi_protocol_backend::numeric::ICP;
+use rumi_protocol_backend::state::{PendingMarginTransfer, PendingRefund, State};
+use rumi_protocol_backend::EventTimeRange;
+
+use common::{fresh_state, icp, VaultBuilder};
+
+const SECOND: u64 = 1_000_000_000;
+const T0: u64 = 1_700_000_000 * SECOND;
+
+fn user() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn other() -> Principal {
+    Principal::from_slice(&[2])
+}
+
+fn range() -> EventTimeRange {
+    EventTimeRange {
+        start_ns: T0,
+        end_ns: T0 + 60 * SECOND,
+    }
+}
+
+fn guard(principal: Principal, operation_name: &str, trace: u64, at: u64) -> GuardRecord {
+    GuardRecord {
+        principal,
+        operation_name: operation_name.to_string(),
+        trace: TraceId(trace).to_string(),
+        started_at: at,
+        finished_at: at + SECOND,
+        outcome: GuardOutcome::Failed,
+    }
+}
+
+fn log_line(timestamp: u64, message: String) -> LogEntry {
+    LogEntry {
+        timestamp,
+        priority: Priority::Info,
+        file: "src/vault.rs".to_string(),
+        line: 1,
+        message,
+        counter: timestamp,
+    }
+}
+
+fn open_vault(owner: Principal, vault_id: u64, timestamp: u64) -> Event {
+    Event::OpenVault {
+        vault: VaultBuilder::new(vault_id)
+            .owner(owner)
+            .collateral(100)
+            .build(),
+        block_index: vault_id,
+        timestamp: Some(timestamp),
+    }
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    state.principal_guards.insert(user());
+    state
+        .principal_guard_timestamps
+        .insert(user(), T0 + 50 * SECOND);
+    state
+        .operation_states
+        .insert(user(), OperationState::InProgress);
+    state
+        .operation_names
+        .insert(user(), "borrow_from_vault".to_string());
+    state.pending_excess_transfers.insert(
+        (7, user()),
+        PendingMarginTransfer {
+            owner: user(),
+            margin: ICP::new(5_000),
+            // Queued before multi-collateral: resolved to the ICP ledger.
+            collateral_type: Principal::anonymous(),
+            retry_count: 2,
+            op_nonce: 0,
+            trace_id: Some(TraceId(0xbeef)),
+        },
+    );
+    state.pending_margin_transfers.insert(
+        (8, other()),
+        PendingMarginTransfer {
+            owner: other(),
+            margin: ICP::new(1),
+            collateral_type: icp(),
+            retry_count: 0,
+            op_nonce: 0,
+            trace_id: None,
+        },
+    );
+    state.pending_refunds.insert(
+        42,
+        PendingRefund {
+            user: user(),
+            amount_e8s: 700,
+            retry_count: 1,
+            op_nonce: 1,
+        },
+    );
+    state
+}
+
+#[test]
+fn time_ranges_are_validated() {
+    assert!(validate_time_range(&range()).is_ok());
+    assert!(validate_time_range(&EventTimeRange {
+        start_ns: T0,
+        end_ns: T0,
+    })
+    .is_ok());
+    assert!(validate_time_range(&EventTimeRange {
+        start_ns: T0 + 1,
+        end_ns: T0,
+    })
+    .is_err());
+}
+
+#[test]
+fn guard_history_is_bounded_and_per_principal() {
+    record_guard(guard(user(), "open_vault", 1, T0));
+    record_guard(guard(other(), "open_vault", 2, T0));
+    record_guard(guard(user(), "borrow_from_vault", 3, T0 + 120 * SECOND));
+
+    let history = guard_history(user(), T0, T0 + 60 * SECOND);
+    assert_eq!(history, vec![guard(user(), "open_vault", 1, T0)]);
+
+    for i in 0..GUARD_HISTORY_CAPACITY as u64 {
+        record_guard(guard(other(), "repay_to_vault", i, T0 + i));
+    }
+    assert!(guard_history(user(), 0, u64::MAX).is_empty());
+    assert_eq!(
+        guard_history(other(), 0, u64::MAX).len(),
+        GUARD_HISTORY_CAPACITY
+    );
+}
+
+#[test]
+fn pending_entries_cover_every_queue_the_user_is_owed_from() {
+    let state = fixture();
+    let entries = pending_entries(&state, user());
+    assert_eq!(entries.len(), 2);
+
+    let excess = &entries[0];
+    assert_eq!(excess.queue, PendingQueue::Excess);
+    assert_eq!(excess.key, 7);
+    assert_eq!(excess.ledger, icp());
+    assert_eq!(excess.amount_e8s, 5_000);
+    assert_eq!(excess.retry_count, 2);
+    assert_eq!(excess.trace.as_deref(), Some("000000000000beef"));
+
+    let refund = &entries[1];
+    assert_eq!(refund.queue, PendingQueue::Refund);
+    assert_eq!(refund.key, 42);
+    assert_eq!(refund.ledger, state.icusd_ledger_principal);
+    assert_eq!(refund.trace, None);
+}
+
+#[test]
+fn the_bundle_collects_what_names_the_user() {
+    let state = fixture();
+    let history = vec![guard(user(), "open_vault", 0xabc, T0 + SECOND)];
+    let events = vec![
+        open_vault(user(), 1, T0 + SECOND),
+        open_vault(other(), 2, T0 + 2 * SECOND),
+        // Outside the range.
+        open_vault(user(), 3, T0 + 120 * SECOND),
+    ];
+    let logs = vec![
+        log_line(
+            T0 + 3 * SECOND,
+            format!("[guard] trace={} Created new guard", TraceId(0xabc)),
+        ),
+        log_line(T0 + 2 * SECOND, format!("[open_vault] owner {}", user())),
+        log_line(
+            T0 + 4 * SECOND,
+            format!("[transfering_excess] trace={} retry", TraceId(0xbeef)),
+        ),
+        log_line(T0 + 5 * SECOND, format!("[open_vault] owner {}", other())),
+        log_line(T0 + 120 * SECOND, format!("[open_vault] owner {}", user())),
+    ];
+
+    let bundle = operation_forensics(
+        &state,
+        user(),
+        range(),
+        history.clone(),
+        events.into_iter().enumerate().map(|(i, e)| (i as u64, e)),
+        logs,
+    );
+
+    let in_flight = bundle.in_flight.expect("guard held");
+    assert_eq!(in_flight.operation_name, "borrow_from_vault");
+    assert_eq!(in_flight.state, OperationState::InProgress);
+    assert_eq!(bundle.guard_history, history);
+    assert_eq!(
+        bundle.operation_names,
+        vec!["borrow_from_vault".to_string(), "open_vault".to_string()]
+    );
+
+    // By principal and by either trace, oldest first.
+    let messages: Vec<_> = bundle
+        .log_lines
+        .iter()
+        .map(|l| l.message.as_str())
+        .collect();
+    assert_eq!(messages.len(), 3);
+    assert!(messages[0].starts_with("[open_vault]"));
+    assert!(messages[1].contains("trace=0000000000000abc"));
+    assert!(messages[2].contains("trace=000000000000beef"));
+    assert_eq!(bundle.log_lines[0].priority, "Info");
+    assert!(!bundle.log_lines_truncated);
+
+    assert_eq!(bundle.events.len(), 1);
+    assert_eq!(bundle.events[0].0, 0);
+    assert_eq!(bundle.pending.len(), 2);
+}
+
+#[test]
+fn the_newest_events_are_kept() {
+    let state = fresh_state();
+    let total = MAX_FORENSIC_EVENTS as u64 + 5;
+    let events = (0..total).map(|i| (i, open_vault(user(), i, T0 + i)));
+    let bundle = operation_forensics(&state, user(), range(), vec![], events, vec![]);
+    assert!(bundle.in_flight.is_none());
+    assert!(bundle.events_truncated);
+    assert_eq!(bundle.events.len(), MAX_FORENSIC_EVENTS);
+    assert_eq!(bundle.events[0].0, 5);
+    assert_eq!(bundle.events.last().unwrap().0, total - 1);
+}
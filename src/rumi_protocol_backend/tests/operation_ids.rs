@@ -0,0 +1,167 @@
+//! Operation ids: a retry of a succeeded operation gets the original result
+//! back, a retry of a running one is refused, a failed one can be retried,
+//! and ids are scoped to their principal, bound to their arguments and
+//! forgotten after their TTL.
+//!
+//! The owner borrows 10 icUSD from vault 1 under id `[7; 32]`.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::guard_metrics::GUARD_HARD_TIMEOUT_NANOS;
+use rumi_protocol_backend::operation_ids::{
+    claim, complete, parse_operation_id, prune, release, OperationId, OperationOutcome,
+    OperationRequest, RecordedOutcome, OPERATION_ID_TTL_NANOS,
+};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::OpenVaultSuccess;
+use rumi_protocol_backend::{ProtocolError, SuccessWithFee};
+
+use common::fresh_state;
+
+const E8S: u64 = 100_000_000;
+const NOW_NS: u64 = 1_000_000_000_000;
+const ID: OperationId = [7; 32];
+
+fn owner() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn fixture() -> State {
+    fresh_state()
+}
+
+fn borrow() -> OperationRequest {
+    OperationRequest::Borrow {
+        vault_id: 1,
+        amount: 10 * E8S,
+    }
+}
+
+fn borrowed() -> OperationOutcome {
+    OperationOutcome::Borrow {
+        block_index: 42,
+        fee_amount_paid: 5_000_000,
+    }
+}
+
+#[test]
+fn a_succeeded_operation_is_not_run_again() {
+    let mut state = fixture();
+    assert!(matches!(
+        claim(&mut state, owner(), ID, borrow(), NOW_NS),
+        Ok(None)
+    ));
+    // The first call is still awaiting its mint.
+    assert!(matches!(
+        claim(&mut state, owner(), ID, borrow(), NOW_NS + 1),
+        Err(ProtocolError::TemporarilyUnavailable(_))
+    ));
+
+    complete(&mut state, owner(), ID, borrowed());
+    let replayed = claim(&mut state, owner(), ID, borrow(), NOW_NS + 2).unwrap();
+    assert_eq!(replayed, Some(borrowed()));
+    let success = SuccessWithFee::from_outcome(&borrowed()).unwrap();
+    assert_eq!(
+        (success.block_index, success.fee_amount_paid),
+        (42, 5_000_000)
+    );
+    assert_eq!(u64::from_outcome(&borrowed()), None);
+
+    // The same id with other arguments is refused, and does not clobber it.
+    let more = OperationRequest::Borrow {
+        vault_id: 1,
+        amount: 20 * E8S,
+    };
+    assert!(matches!(
+        claim(&mut state, owner(), ID, more, NOW_NS + 3),
+        Err(ProtocolError::GenericError(_))
+    ));
+    let replayed = claim(&mut state, owner(), ID, borrow(), NOW_NS + 4).unwrap();
+    assert_eq!(replayed, Some(borrowed()));
+
+    // Another principal's id space is its own.
+    let other = Principal::from_slice(&[2]);
+    assert!(matches!(
+        claim(&mut state, other, ID, borrow(), NOW_NS + 5),
+        Ok(None)
+    ));
+    assert_eq!(state.operation_ids.len(), 2);
+}
+
+#[test]
+fn a_failed_or_stuck_operation_can_be_retried() {
+    let mut state = fixture();
+    claim(&mut state, owner(), ID, borrow(), NOW_NS).unwrap();
+    release(&mut state, owner(), &ID);
+    assert!(state.operation_ids.is_empty());
+    assert!(matches!(
+        claim(&mut state, owner(), ID, borrow(), NOW_NS + 1),
+        Ok(None)
+    ));
+
+    // A call that trapped mid-flight never completes its claim; it is taken
+    // over once the operation's guard would have been cleared.
+    let stuck_until = NOW_NS + 1 + GUARD_HARD_TIMEOUT_NANOS;
+    assert!(claim(&mut state, owner(), ID, borrow(), stuck_until - 1).is_err());
+    assert!(matches!(
+        claim(&mut state, owner(), ID, borrow(), stuck_until),
+        Ok(None)
+    ));
+    assert_eq!(
+        state.operation_ids.get(owner(), &ID).unwrap().claimed_at_ns,
+        stuck_until
+    );
+    assert_eq!(state.operation_ids.len(), 1);
+}
+
+#[test]
+fn ids_are_forgotten_after_their_ttl() {
+    let mut state = fixture();
+    let open = OperationRequest::OpenVault {
+        collateral_amount: 10 * E8S,
+        collateral_type: None,
+    };
+    let opened = OpenVaultSuccess {
+        vault_id: 1,
+        block_index: 41,
+    };
+    claim(&mut state, owner(), [1; 32], open.clone(), NOW_NS).unwrap();
+    complete(&mut state, owner(), [1; 32], opened.outcome());
+    claim(&mut state, owner(), ID, borrow(), NOW_NS + 60).unwrap();
+    complete(&mut state, owner(), ID, borrowed());
+
+    prune(&mut state, NOW_NS + OPERATION_ID_TTL_NANOS - 1);
+    assert_eq!(state.operation_ids.len(), 2);
+    let replayed = claim(&mut state, owner(), [1; 32], open.clone(), NOW_NS + 1)
+        .unwrap()
+        .unwrap();
+    assert_eq!(OpenVaultSuccess::from_outcome(&replayed), Some(opened));
+
+    prune(&mut state, NOW_NS + OPERATION_ID_TTL_NANOS);
+    assert!(state.operation_ids.get(owner(), &[1; 32]).is_none());
+    assert!(state.operation_ids.get(owner(), &ID).is_some());
+    assert!(matches!(
+        claim(
+            &mut state,
+            owner(),
+            [1; 32],
+            open,
+            NOW_NS + OPERATION_ID_TTL_NANOS
+        ),
+        Ok(None)
+    ));
+}
+
+#[test]
+fn operation_ids_must_be_32_bytes() {
+    assert_eq!(parse_operation_id(&[7; 32]).ok(), Some(ID));
+    for len in [0, 16, 33] {
+        assert!(parse_operation_id(&vec![7; len]).is_err(), "{} bytes", len);
+    }
+    assert_eq!(
+        Option::<u64>::from_outcome(&OperationOutcome::Close { block_index: None }),
+        Some(None)
+    );
+}
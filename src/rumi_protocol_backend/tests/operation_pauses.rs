@@ -0,0 +1,158 @@
+//! Operation pauses: only emergency pausers and the developer hold the
+//! switch, a pause stops its own operation and nothing else, every gated
+//! entry point checks its pause, and replay rebuilds the pausers, the
+//! pauses and their journal entries.
+//!
+//! Alice is the developer and Bob an emergency pauser.
+
+mod common;
+
+use common::init_arg_with;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::operation_pauses::{
+    apply_set_paused, check_not_paused, is_pause_authority, PausableOperation,
+};
+use rumi_protocol_backend::state::State;
+
+use PausableOperation::*;
+
+fn alice() -> Principal {
+    Principal::from_slice(&[20])
+}
+
+fn bob() -> Principal {
+    Principal::from_slice(&[21])
+}
+
+fn carol() -> Principal {
+    Principal::from_slice(&[22])
+}
+
+fn fixture() -> State {
+    let mut state = init_arg_with()
+        .icusd_ledger(Principal::from_slice(&[1]))
+        .developer(alice())
+        .state();
+    state.emergency_pausers.insert(bob());
+    state
+}
+
+/// The body of `fn name` in `source`, up to its closing brace.
+fn fn_body<'a>(source: &'a str, name: &str) -> &'a str {
+    let start = source
+        .find(&format!("fn {}(", name))
+        .unwrap_or_else(|| panic!("fn {} not found", name));
+    let len = source[start..].find("\n}\n").expect("closing brace");
+    &source[start..start + len]
+}
+
+#[test]
+fn only_pausers_and_the_developer_pause() {
+    let state = fixture();
+    assert!(is_pause_authority(&state, alice()));
+    assert!(is_pause_authority(&state, bob()));
+    assert!(!is_pause_authority(&state, carol()));
+}
+
+#[test]
+fn a_pause_stops_only_its_operation() {
+    let mut state = fixture();
+    for operation in [Borrow, Redemption, Liquidation, ReserveRedemption] {
+        assert!(check_not_paused(&state, operation).is_ok());
+    }
+
+    apply_set_paused(&mut state, Borrow, true);
+    assert!(check_not_paused(&state, Borrow).is_err());
+    for operation in [Redemption, Liquidation, ReserveRedemption] {
+        assert!(check_not_paused(&state, operation).is_ok());
+    }
+
+    apply_set_paused(&mut state, Borrow, false);
+    assert!(check_not_paused(&state, Borrow).is_ok());
+    assert!(state.paused_operations.is_empty());
+}
+
+#[test]
+fn every_gated_entry_point_checks_its_pause() {
+    let main_source = include_str!("../src/main.rs");
+    let gated = [
+        ("open_vault_and_borrow", Borrow),
+        ("open_vault_with_deposit", Borrow),
+        ("borrow_from_vault", Borrow),
+        ("borrow_from_vault_as_stable", Borrow),
+        ("borrow_from_vault_as_stable", ReserveRedemption),
+        ("redeem_icp", Redemption),
+        ("redeem_collateral", Redemption),
+        ("redeem_reserves", ReserveRedemption),
+        ("validate_liquidation_not_frozen", Liquidation),
+    ];
+    for (name, operation) in gated {
+        let check = format!(
+            "validate_operation_not_paused(PausableOperation::{:?})",
+            operation
+        );
+        assert!(
+            fn_body(main_source, name).contains(&check),
+            "{} does not check the {:?} pause",
+            name,
+            operation
+        );
+    }
+
+    // Repayment paths stay open whatever is paused.
+    for name in ["repay_to_vault", "add_margin_to_vault", "close_vault"] {
+        assert!(
+            !fn_body(main_source, name).contains("validate_operation_not_paused"),
+            "{} checks a pause",
+            name
+        );
+    }
+}
+
+#[test]
+fn pauses_replay() {
+    let paused = |operation, paused| Event::SetOperationPaused {
+        operation,
+        paused,
+        changed_by: bob(),
+        timestamp: 1_000,
+    };
+    let state = replay(
+        vec![
+            Event::Init(
+                init_arg_with()
+                    .icusd_ledger(Principal::from_slice(&[1]))
+                    .developer(alice())
+                    .build(),
+            ),
+            Event::SetEmergencyPausers {
+                principals: vec![bob(), carol()],
+            },
+            paused(Borrow, true),
+            paused(Liquidation, true),
+            paused(Borrow, false),
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    assert_eq!(
+        state.emergency_pausers.iter().collect::<Vec<_>>(),
+        vec![&bob(), &carol()]
+    );
+    assert_eq!(
+        state.paused_operations.iter().collect::<Vec<_>>(),
+        vec![&Liquidation]
+    );
+
+    // The journal tracks each operation under its own scope.
+    let borrow: Vec<_> = state
+        .parameter_journal
+        .iter()
+        .filter(|c| c.parameter == "operation_paused" && c.scope.as_deref() == Some("Borrow"))
+        .collect();
+    assert_eq!(borrow.len(), 2);
+    assert_eq!(borrow[1].old_value, Some(borrow[0].new_value.clone()));
+}
@@ -0,0 +1,204 @@
+//! Operator panel: the token gate is off until a hash is set and then tells
+//! a missing token from a wrong one, actions parse from their path and
+//! value, the pause and requeue actions check the caller as their endpoints
+//! do, and the status reports switches, price ages and stuck guards.
+//!
+//! ICP was last priced 90 seconds ago, and one guard is held past the hard
+//! timeout next to a fresh one. Carol is the developer and Bob an emergency
+//! pauser.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::guard_metrics::GUARD_HARD_TIMEOUT_NANOS;
+use rumi_protocol_backend::operation_pauses::PausableOperation;
+use rumi_protocol_backend::ops_panel::{
+    check_caller, check_token, status, token_hash, OpsAction, TokenCheck, TOKEN_HEADER,
+};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::transfer_retry::PendingTransferKey;
+
+use common::{icp, init_arg_with, set_icp_price};
+
+const SEC: u64 = 1_000_000_000;
+const NOW: u64 = 10 * GUARD_HARD_TIMEOUT_NANOS;
+
+fn alice() -> Principal {
+    Principal::from_slice(&[20])
+}
+
+fn bob() -> Principal {
+    Principal::from_slice(&[21])
+}
+
+fn carol() -> Principal {
+    Principal::from_slice(&[22])
+}
+
+fn fixture() -> State {
+    let mut state = init_arg_with()
+        .icusd_ledger(Principal::from_slice(&[1]))
+        .developer(carol())
+        .state();
+    set_icp_price(&mut state, 10.0, Some(NOW - 90 * SEC));
+    for (principal, started_at) in [
+        (alice(), NOW - GUARD_HARD_TIMEOUT_NANOS - 60 * SEC),
+        (bob(), NOW - SEC),
+    ] {
+        state.principal_guards.insert(principal);
+        state
+            .principal_guard_timestamps
+            .insert(principal, started_at);
+        state
+            .operation_names
+            .insert(principal, "withdraw_collateral".to_string());
+    }
+    state.emergency_pausers.insert(bob());
+    state
+}
+
+fn headers(name: &str, value: &str) -> Vec<(String, String)> {
+    vec![(name.to_string(), value.to_string())]
+}
+
+#[test]
+fn the_token_gate() {
+    let mut state = fixture();
+    assert_eq!(
+        check_token(&state, &headers(TOKEN_HEADER, "hunter2")),
+        TokenCheck::Disabled
+    );
+
+    state.ops_panel_token_hash = Some(token_hash("hunter2"));
+    assert_eq!(check_token(&state, &[]), TokenCheck::Missing);
+    assert_eq!(
+        check_token(&state, &headers(TOKEN_HEADER, "")),
+        TokenCheck::Missing
+    );
+    assert_eq!(
+        check_token(&state, &headers(TOKEN_HEADER, "hunter3")),
+        TokenCheck::Wrong
+    );
+    assert_eq!(
+        check_token(&state, &headers("X-Rumi-Ops-Token", " hunter2 ")),
+        TokenCheck::Valid
+    );
+}
+
+#[test]
+fn actions_parse_from_path_and_value() {
+    assert_eq!(
+        OpsAction::parse("refresh_prices", None),
+        Some(OpsAction::RefreshPrices)
+    );
+    assert_eq!(
+        OpsAction::parse("resolve_stuck_guards", Some("true")),
+        Some(OpsAction::ResolveStuckGuards)
+    );
+    assert_eq!(
+        OpsAction::parse("set_frozen", Some("true")),
+        Some(OpsAction::SetFrozen(true))
+    );
+    assert_eq!(
+        OpsAction::parse("set_liquidation_frozen", Some("false")),
+        Some(OpsAction::SetLiquidationFrozen(false))
+    );
+    // A switch needs an explicit value.
+    assert_eq!(OpsAction::parse("set_frozen", None), None);
+    assert_eq!(OpsAction::parse("set_frozen", Some("yes")), None);
+    assert_eq!(OpsAction::parse("drop_all_vaults", None), None);
+}
+
+#[test]
+fn pause_operation_is_for_pausers_and_the_developer() {
+    let state = fixture();
+    let action = OpsAction::parse("pause_operation", Some("Borrow")).unwrap();
+    assert_eq!(action, OpsAction::PauseOperation(PausableOperation::Borrow));
+    assert_eq!(OpsAction::parse("pause_operation", Some("Repayment")), None);
+    assert_eq!(OpsAction::parse("pause_operation", None), None);
+
+    assert!(check_caller(&state, Principal::anonymous(), action).is_err());
+    assert!(check_caller(&state, alice(), action).is_err());
+    assert!(check_caller(&state, bob(), action).is_ok());
+    assert!(check_caller(&state, carol(), action).is_ok());
+}
+
+#[test]
+fn unpause_operation_is_for_pausers_and_the_developer() {
+    let state = fixture();
+    let action = OpsAction::parse("unpause_operation", Some("ReserveRedemption")).unwrap();
+    assert_eq!(
+        action,
+        OpsAction::UnpauseOperation(PausableOperation::ReserveRedemption)
+    );
+
+    assert!(check_caller(&state, Principal::anonymous(), action).is_err());
+    assert!(check_caller(&state, bob(), action).is_ok());
+    assert!(check_caller(&state, carol(), action).is_ok());
+}
+
+#[test]
+fn requeue_failed_transfer_is_for_the_developer() {
+    let state = fixture();
+    let margin = format!("margin:7:{}", alice().to_text());
+    let action = OpsAction::parse("requeue_failed_transfer", Some(&margin)).unwrap();
+    assert_eq!(
+        action,
+        OpsAction::RequeueFailedTransfer(PendingTransferKey::Margin {
+            vault_id: 7,
+            owner: alice(),
+        })
+    );
+    assert_eq!(
+        OpsAction::parse("requeue_failed_transfer", Some("redemption:42")),
+        Some(OpsAction::RequeueFailedTransfer(
+            PendingTransferKey::Redemption {
+                icusd_block_index: 42
+            }
+        ))
+    );
+    assert_eq!(
+        OpsAction::parse("requeue_failed_transfer", Some("three_usd_refund:9")),
+        Some(OpsAction::RequeueFailedTransfer(
+            PendingTransferKey::ThreeUsdRefund { op_nonce: 9 }
+        ))
+    );
+    // A vault key needs its owner, a block key must not have one.
+    assert_eq!(
+        OpsAction::parse("requeue_failed_transfer", Some("excess:7")),
+        None
+    );
+    assert_eq!(
+        OpsAction::parse("requeue_failed_transfer", Some("refund:42:aaaaa-aa")),
+        None
+    );
+
+    // Pausers cannot resolve failed transfers through the panel either.
+    assert!(check_caller(&state, Principal::anonymous(), action).is_err());
+    assert!(check_caller(&state, bob(), action).is_err());
+    assert!(check_caller(&state, carol(), action).is_ok());
+}
+
+#[test]
+fn the_status_reports_switches_prices_and_stuck_guards() {
+    let mut state = fixture();
+    state.liquidation_frozen = true;
+    let status = status(&state, NOW);
+    assert!(!status.frozen);
+    assert!(status.liquidation_frozen);
+
+    let icp_price = status
+        .prices
+        .iter()
+        .find(|price| price.collateral_type == icp().to_text())
+        .unwrap();
+    assert_eq!(icp_price.price, Some(10.0));
+    assert_eq!(icp_price.age_secs, Some(90));
+
+    assert_eq!(status.stuck_guards.len(), 1);
+    let guard = &status.stuck_guards[0];
+    assert_eq!(guard.principal, alice().to_text());
+    assert_eq!(guard.operation_name, "withdraw_collateral");
+    assert_eq!(guard.held_secs, GUARD_HARD_TIMEOUT_NANOS / SEC + 60);
+}
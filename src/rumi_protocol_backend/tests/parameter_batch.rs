@@ -0,0 +1,158 @@
+//! Parameter batches: every change is bounds-checked, the batch is checked
+//! against cross-parameter constraints as it would leave them, one bad
+//! change rejects the whole batch, and a recorded batch replays and journals
+//! one entry per change.
+//!
+//! Batches change the ICP collateral as `InitArg` configures it:
+//! liquidation ratio 1.33, borrow threshold 1.5, redemption fee ceiling 5%.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::Ratio;
+use rumi_protocol_backend::parameter_batch::{
+    apply, validate, BatchedParameter, ParameterUpdate, MAX_PARAMETER_BATCH,
+};
+
+use common::{fresh_state, icp, init_arg};
+
+fn liquidation_ratio(value: f64) -> ParameterUpdate {
+    ParameterUpdate::CollateralLiquidationRatio {
+        collateral_type: icp(),
+        value,
+    }
+}
+
+fn borrow_threshold(value: f64) -> ParameterUpdate {
+    ParameterUpdate::CollateralBorrowThreshold {
+        collateral_type: icp(),
+        value,
+    }
+}
+
+#[test]
+fn a_batch_is_checked_as_a_whole_not_change_by_change() {
+    let mut state = fresh_state();
+    // On its own, the first change would put the liquidation ratio above
+    // the current borrow threshold of 1.5.
+    let updates = vec![liquidation_ratio(1.6), borrow_threshold(1.8)];
+
+    let batch = validate(&state, &updates).unwrap();
+    assert_eq!(
+        batch,
+        vec![
+            BatchedParameter::CollateralLiquidationRatio {
+                collateral_type: icp(),
+                value: "1.6".to_string(),
+            },
+            BatchedParameter::CollateralBorrowThreshold {
+                collateral_type: icp(),
+                value: "1.8".to_string(),
+            },
+        ]
+    );
+    apply(&mut state, &batch);
+
+    let config = &state.collateral_configs[&icp()];
+    assert_eq!(config.liquidation_ratio, Ratio::from(dec!(1.6)));
+    assert_eq!(config.borrow_threshold_ratio, Ratio::from(dec!(1.8)));
+    assert_eq!(
+        config.recovery_target_cr,
+        Ratio::from(dec!(1.8)) * state.recovery_cr_multiplier
+    );
+}
+
+#[test]
+fn cross_parameter_constraints_use_the_values_the_batch_leaves() {
+    let state = fresh_state();
+
+    // Liquidation ratio at or above the (unchanged) borrow threshold.
+    assert!(validate(&state, &[liquidation_ratio(1.5)]).is_err());
+    // Borrow threshold at or above a healthy CR set in the same batch.
+    let healthy = ParameterUpdate::HealthyCr {
+        collateral_type: icp(),
+        value: Some(1.7),
+    };
+    assert!(validate(&state, &[borrow_threshold(1.7), healthy.clone()]).is_err());
+    assert!(validate(&state, &[borrow_threshold(1.6), healthy]).is_ok());
+
+    // Floor above the current ceiling, unless the ceiling moves with it.
+    let floor = ParameterUpdate::RedemptionFeeFloor { value: 0.08 };
+    assert!(validate(&state, &[floor.clone()]).is_err());
+    assert!(validate(
+        &state,
+        &[floor, ParameterUpdate::RedemptionFeeCeiling { value: 0.20 }]
+    )
+    .is_ok());
+}
+
+#[test]
+fn one_bad_change_rejects_the_whole_batch() {
+    let state = fresh_state();
+
+    // Out of the single setter's bounds.
+    let out_of_bounds = ParameterUpdate::CollateralLiquidationBonus {
+        collateral_type: icp(),
+        value: 2.0,
+    };
+    assert!(validate(&state, &[borrow_threshold(1.6), out_of_bounds]).is_err());
+    // Unknown collateral.
+    let unknown = ParameterUpdate::CollateralBorrowingFee {
+        collateral_type: Principal::from_slice(&[99]),
+        value: 0.01,
+    };
+    assert!(validate(&state, &[borrow_threshold(1.6), unknown]).is_err());
+    // The same parameter twice.
+    assert!(validate(&state, &[borrow_threshold(1.6), borrow_threshold(1.7)]).is_err());
+    // Empty and oversized batches.
+    assert!(validate(&state, &[]).is_err());
+    let oversized = vec![borrow_threshold(1.6); MAX_PARAMETER_BATCH + 1];
+    assert!(validate(&state, &oversized).is_err());
+}
+
+#[test]
+fn replay_applies_a_batch_and_journals_each_change() {
+    let batch = validate(
+        &fresh_state(),
+        &[liquidation_ratio(1.4), borrow_threshold(1.6)],
+    )
+    .unwrap();
+    let state = replay(
+        vec![
+            Event::Init(init_arg()),
+            Event::ApplyParameterBatch {
+                changes: batch,
+                timestamp: 7,
+            },
+        ]
+        .into_iter(),
+    )
+    .unwrap();
+
+    let config = &state.collateral_configs[&icp()];
+    assert_eq!(config.liquidation_ratio, Ratio::from(dec!(1.4)));
+    assert_eq!(config.borrow_threshold_ratio, Ratio::from(dec!(1.6)));
+
+    let journal: Vec<_> = state
+        .parameter_journal
+        .iter()
+        .map(|c| {
+            (
+                c.parameter.as_str(),
+                c.new_value.as_str(),
+                c.event_index,
+                c.timestamp,
+            )
+        })
+        .collect();
+    assert_eq!(
+        journal,
+        vec![
+            ("collateral_liquidation_ratio", "1.4", 1, 7),
+            ("collateral_borrow_threshold", "1.6", 1, 7),
+        ]
+    );
+}
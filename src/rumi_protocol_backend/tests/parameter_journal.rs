@@ -0,0 +1,191 @@
+//! Parameter-change journal: setter events are split into parameter, scope
+//! and value, old values are read from state (or chain per (parameter,
+//! scope) where state has none), replay rebuilds the journal, the journal is
+//! capped, and `parameter_history` pages through one parameter.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::Ratio;
+use rumi_protocol_backend::parameter_journal::{
+    journal_event, parameter_change, parameter_history, MAX_PARAMETER_HISTORY_PAGE,
+    MAX_PARAMETER_JOURNAL,
+};
+
+use common::{fresh_state, init_arg};
+
+fn admin() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn liquidation_ratio(collateral_type: Principal, ratio: &str) -> Event {
+    Event::SetCollateralLiquidationRatio {
+        collateral_type,
+        liquidation_ratio: ratio.to_string(),
+    }
+}
+
+fn borrowing_fee(rate: &str) -> Event {
+    Event::SetBorrowingFee {
+        rate: rate.to_string(),
+    }
+}
+
+#[test]
+fn setter_events_split_into_parameter_scope_and_value() {
+    let icp = Principal::from_slice(&[10]);
+    assert_eq!(
+        parameter_change(&liquidation_ratio(icp, "1.4")),
+        Some((
+            "collateral_liquidation_ratio".to_string(),
+            Some(icp.to_text()),
+            "1.4".to_string()
+        ))
+    );
+    assert_eq!(
+        parameter_change(&Event::SetBreakerWindowNs {
+            window_ns: 60,
+            timestamp: 5,
+        }),
+        Some(("breaker_window_ns".to_string(), None, "60".to_string()))
+    );
+    assert_eq!(
+        parameter_change(&Event::SetHealthyCr {
+            collateral_type: icp.to_text(),
+            healthy_cr: None,
+        }),
+        Some((
+            "healthy_cr".to_string(),
+            Some(icp.to_text()),
+            "null".to_string()
+        ))
+    );
+    assert_eq!(
+        parameter_change(&Event::AccrueInterest { timestamp: 1 }),
+        None
+    );
+}
+
+#[test]
+fn old_values_are_read_from_state_per_parameter_and_scope() {
+    let icp = Principal::from_slice(&[10]);
+    // Not a collateral, so state has no value for it.
+    let other = Principal::from_slice(&[11]);
+    let mut state = fresh_state();
+    let initial = state.collateral_configs[&icp]
+        .liquidation_ratio
+        .0
+        .to_string();
+    journal_event(
+        &mut state,
+        &liquidation_ratio(icp, "1.35"),
+        1,
+        Some(admin()),
+        100,
+    );
+    // What the setter applies after journaling.
+    state
+        .collateral_configs
+        .get_mut(&icp)
+        .unwrap()
+        .liquidation_ratio = Ratio::from(dec!(1.35));
+    journal_event(
+        &mut state,
+        &liquidation_ratio(other, "1.5"),
+        2,
+        Some(admin()),
+        200,
+    );
+    journal_event(&mut state, &borrowing_fee("0.005"), 3, Some(admin()), 250);
+    journal_event(
+        &mut state,
+        &liquidation_ratio(icp, "1.4"),
+        4,
+        Some(admin()),
+        300,
+    );
+
+    let page = parameter_history(&state, "collateral_liquidation_ratio", None, 10);
+    assert_eq!(page.next_cursor, None);
+    let values: Vec<_> = page
+        .entries
+        .iter()
+        .map(|c| (c.old_value.as_deref(), c.new_value.as_str()))
+        .collect();
+    assert_eq!(
+        values,
+        vec![
+            (Some(initial.as_str()), "1.35"),
+            (None, "1.5"),
+            (Some("1.35"), "1.4")
+        ]
+    );
+    let last = page.entries.last().unwrap();
+    assert_eq!(last.actor, Some(admin()));
+    assert_eq!((last.timestamp, last.event_index), (300, 4));
+}
+
+#[test]
+fn history_pages_with_cursor() {
+    let total = MAX_PARAMETER_HISTORY_PAGE + 3;
+    let mut events = vec![Event::Init(init_arg())];
+    for i in 0..total {
+        events.push(borrowing_fee(&i.to_string()));
+        events.push(Event::SetMinIcusdAmount {
+            amount: i.to_string(),
+        });
+    }
+    let state = replay(events.into_iter()).expect("replay");
+
+    let first = parameter_history(&state, "borrowing_fee", None, usize::MAX);
+    assert_eq!(first.entries.len(), MAX_PARAMETER_HISTORY_PAGE);
+    let second = parameter_history(&state, "borrowing_fee", first.next_cursor, usize::MAX);
+    assert_eq!(second.entries.len(), 3);
+    assert_eq!(second.next_cursor, None);
+    assert_eq!(second.entries[0].old_value.as_deref(), Some("99"));
+    assert_eq!(second.entries[2].new_value, (total - 1).to_string());
+}
+
+#[test]
+fn replay_rebuilds_journal_without_actor() {
+    let icp = Principal::from_slice(&[10]);
+    let events = vec![
+        Event::Init(init_arg()),
+        borrowing_fee("0.005"),
+        Event::AccrueInterest { timestamp: 7 },
+        borrowing_fee("0.01"),
+        liquidation_ratio(icp, "1.4"),
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+
+    assert_eq!(state.parameter_journal.len(), 3);
+    let fee = parameter_history(&state, "borrowing_fee", None, 10).entries;
+    assert_eq!(fee[1].old_value.as_deref(), Some("0.005"));
+    assert_eq!(fee[1].new_value, "0.01");
+    assert_eq!(fee[1].event_index, 3);
+    assert_eq!(fee[1].actor, None);
+}
+
+#[test]
+fn the_journal_keeps_the_latest_entries_and_their_ids() {
+    let mut state = fresh_state();
+    let total = MAX_PARAMETER_JOURNAL + 5;
+    for i in 0..total {
+        journal_event(
+            &mut state,
+            &Event::SetLiquidationTip { tip_e8s: i as u64 },
+            i as u64,
+            None,
+            0,
+        );
+    }
+
+    assert_eq!(state.parameter_journal.len(), MAX_PARAMETER_JOURNAL);
+    assert_eq!(state.parameter_journal.front().unwrap().id, 5);
+    let page = parameter_history(&state, "liquidation_tip", Some(total as u64 - 2), 10);
+    let ids: Vec<_> = page.entries.iter().map(|c| c.id).collect();
+    assert_eq!(ids, vec![total as u64 - 2, total as u64 - 1]);
+}
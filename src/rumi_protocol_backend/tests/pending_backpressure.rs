@@ -0,0 +1,149 @@
+//! Pending-queue backpressure: once the collateral (margin + excess) or
+//! redemption payout queue reaches its threshold, operations that would add
+//! to that queue are rejected with `TemporarilyUnavailable` and counted,
+//! the other queue's operations are unaffected, a threshold of 0 turns the
+//! check off, and the thresholds are rebuilt by replay.
+//!
+//! Backpressure starts at 3 queued collateral payouts and 2 queued
+//! redemption payouts.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::ICP;
+use rumi_protocol_backend::pending_backpressure::{
+    check_room, status, validate_config, PayoutQueue, PendingBackpressureConfig,
+    DEFAULT_MAX_PENDING_COLLATERAL_TRANSFERS, DEFAULT_MAX_PENDING_REDEMPTION_TRANSFERS,
+    MAX_PENDING_TRANSFER_THRESHOLD,
+};
+use rumi_protocol_backend::state::{PendingMarginTransfer, State};
+use rumi_protocol_backend::ProtocolError;
+
+use common::{fresh_state, icp, init_arg};
+
+fn transfer(owner: u8) -> PendingMarginTransfer {
+    PendingMarginTransfer {
+        owner: Principal::from_slice(&[owner]),
+        margin: ICP::from(100_000_000),
+        collateral_type: icp(),
+        retry_count: 0,
+        op_nonce: 0,
+        trace_id: None,
+    }
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    state.pending_backpressure = PendingBackpressureConfig {
+        max_pending_collateral_transfers: 3,
+        max_pending_redemption_transfers: 2,
+    };
+    state
+}
+
+#[test]
+fn collateral_queue_at_threshold_rejects_closes_and_withdrawals() {
+    let mut state = fixture();
+    for vault_id in 1..=2u64 {
+        state
+            .pending_margin_transfers
+            .insert((vault_id, Principal::from_slice(&[1])), transfer(1));
+    }
+    assert!(check_room(&mut state, PayoutQueue::Collateral).is_ok());
+
+    // Excess transfers count towards the same queue.
+    state
+        .pending_excess_transfers
+        .insert((3, Principal::from_slice(&[1])), transfer(1));
+    match check_room(&mut state, PayoutQueue::Collateral) {
+        Err(ProtocolError::TemporarilyUnavailable(msg)) => {
+            assert!(msg.contains("3 collateral payouts"), "{}", msg);
+            assert!(msg.contains("Repayments and margin additions"), "{}", msg);
+        }
+        other => panic!("expected TemporarilyUnavailable, got {:?}", other),
+    }
+    // Redemptions pay out through their own queue and still go through.
+    assert!(check_room(&mut state, PayoutQueue::Redemption).is_ok());
+
+    let view = status(&state);
+    assert!(view.collateral_blocked);
+    assert!(!view.redemption_blocked);
+    assert_eq!(view.pending_collateral_transfers, 3);
+    assert_eq!(view.stats.collateral_rejections, 1);
+    assert_eq!(view.stats.redemption_rejections, 0);
+
+    // Draining one entry lifts the backpressure.
+    state.pending_excess_transfers.clear();
+    assert!(check_room(&mut state, PayoutQueue::Collateral).is_ok());
+    assert!(!status(&state).collateral_blocked);
+}
+
+#[test]
+fn redemption_queue_at_threshold_rejects_redemptions_only() {
+    let mut state = fixture();
+    state.pending_redemption_transfer.insert(1, transfer(1));
+    state.pending_redemption_transfer.insert(2, transfer(2));
+
+    assert!(matches!(
+        check_room(&mut state, PayoutQueue::Redemption),
+        Err(ProtocolError::TemporarilyUnavailable(_))
+    ));
+    assert!(check_room(&mut state, PayoutQueue::Collateral).is_ok());
+    assert_eq!(status(&state).stats.redemption_rejections, 1);
+}
+
+#[test]
+fn zero_threshold_disables_the_check() {
+    let mut state = fixture();
+    state.pending_backpressure.max_pending_redemption_transfers = 0;
+    for block in 0..10u64 {
+        state.pending_redemption_transfer.insert(block, transfer(1));
+    }
+    assert!(check_room(&mut state, PayoutQueue::Redemption).is_ok());
+    assert!(!status(&state).redemption_blocked);
+}
+
+#[test]
+fn defaults_and_bounds() {
+    let state = fresh_state();
+    assert_eq!(
+        state.pending_backpressure.max_pending_collateral_transfers,
+        DEFAULT_MAX_PENDING_COLLATERAL_TRANSFERS
+    );
+    assert_eq!(
+        state.pending_backpressure.max_pending_redemption_transfers,
+        DEFAULT_MAX_PENDING_REDEMPTION_TRANSFERS
+    );
+
+    assert!(validate_config(&PendingBackpressureConfig {
+        max_pending_collateral_transfers: MAX_PENDING_TRANSFER_THRESHOLD,
+        max_pending_redemption_transfers: 0,
+    })
+    .is_ok());
+    assert!(validate_config(&PendingBackpressureConfig {
+        max_pending_collateral_transfers: 10,
+        max_pending_redemption_transfers: MAX_PENDING_TRANSFER_THRESHOLD + 1,
+    })
+    .is_err());
+}
+
+#[test]
+fn replay_rebuilds_thresholds() {
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::SetPendingBackpressure {
+            max_pending_collateral_transfers: 40,
+            max_pending_redemption_transfers: 0,
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(
+        state.pending_backpressure,
+        PendingBackpressureConfig {
+            max_pending_collateral_transfers: 40,
+            max_pending_redemption_transfers: 0,
+        }
+    );
+}
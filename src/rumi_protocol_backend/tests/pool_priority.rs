@@ -0,0 +1,162 @@
+//! Recovery-mode stability-pool priority: vaults the pool's reported depth
+//! covers are reserved for it (worst CR first, per collateral), the rest
+//! overflow to the usual cascade, a live reservation turns away everyone but
+//! the pool until the pool has had its attempt, and nothing binds outside
+//! Recovery or without a fresh depth snapshot.
+//!
+//! The protocol is in Recovery and the pool reports 100 icUSD of ICP depth.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::pool_priority::{
+    live_reservation, plan_routing, require_not_reserved, validate_config, PoolDepthSnapshot,
+    PoolPriorityConfig, MAX_POOL_DEPTH_AGE_NS,
+};
+use rumi_protocol_backend::state::{Mode, State};
+use rumi_protocol_backend::{LiquidatableVaultInfo, ProtocolError};
+
+use common::{icp, init_arg_with};
+
+const E8S: u64 = 100_000_000;
+const NOW: u64 = 1_000 * 1_000_000_000;
+const WINDOW: u64 = 300 * 1_000_000_000;
+
+fn pool() -> Principal {
+    Principal::from_slice(&[20])
+}
+
+fn liquidator() -> Principal {
+    Principal::from_slice(&[30])
+}
+
+fn fixture() -> State {
+    let mut state = init_arg_with().stability_pool(pool()).state();
+    state.mode = Mode::Recovery;
+    state.pool_priority = PoolPriorityConfig {
+        enabled: true,
+        window_ns: WINDOW,
+    };
+    state.pool_depth = Some(PoolDepthSnapshot {
+        eligible_icusd: vec![(icp(), 100 * E8S)],
+        updated_at_ns: NOW,
+    });
+    state
+}
+
+fn candidate(vault_id: u64, collateral_type: Principal, repay: u64) -> LiquidatableVaultInfo {
+    LiquidatableVaultInfo {
+        vault_id,
+        collateral_type,
+        debt_amount: 2 * repay * E8S,
+        collateral_amount: 0,
+        recommended_liquidation_amount: repay * E8S,
+        collateral_price_e8s: 0,
+    }
+}
+
+#[test]
+fn depth_is_spent_in_order_and_the_rest_overflows() {
+    let state = fixture();
+    let other = Principal::from_slice(&[11]);
+    let candidates = [
+        candidate(1, icp(), 60),
+        candidate(2, icp(), 50),
+        candidate(3, icp(), 40),
+        candidate(4, other, 1),
+    ];
+    let routing = plan_routing(&state, &candidates, NOW).expect("routing");
+    assert_eq!(routing.to_pool, vec![1, 3]);
+    assert_eq!(routing.overflow, vec![2, 4]);
+    assert_eq!(routing.pool_depth, vec![(icp(), 100 * E8S)]);
+    assert_eq!(routing.reserved_until_ns, NOW + WINDOW);
+}
+
+#[test]
+fn no_routing_outside_recovery_or_on_stale_depth() {
+    let candidates = [candidate(1, icp(), 10)];
+
+    let mut state = fixture();
+    state.mode = Mode::GeneralAvailability;
+    assert!(plan_routing(&state, &candidates, NOW).is_none());
+
+    let mut state = fixture();
+    state.pool_priority.enabled = false;
+    assert!(plan_routing(&state, &candidates, NOW).is_none());
+
+    let state = fixture();
+    assert!(plan_routing(&state, &candidates, NOW + MAX_POOL_DEPTH_AGE_NS + 1).is_none());
+
+    // Vaults the pool already tried are not routed again.
+    let mut state = fixture();
+    state.sp_attempted_vaults.insert(1);
+    assert!(plan_routing(&state, &candidates, NOW).is_none());
+}
+
+#[test]
+fn reservation_turns_away_everyone_but_the_pool() {
+    let mut state = fixture();
+    state.pool_reservations.insert(1, NOW + WINDOW);
+
+    assert!(matches!(
+        require_not_reserved(&state, 1, liquidator(), NOW),
+        Err(ProtocolError::GenericError(_))
+    ));
+    assert!(require_not_reserved(&state, 1, pool(), NOW).is_ok());
+    assert!(require_not_reserved(&state, 2, liquidator(), NOW).is_ok());
+
+    // Expired, or the pool has had its attempt: open to all.
+    assert!(require_not_reserved(&state, 1, liquidator(), NOW + WINDOW).is_ok());
+    state.sp_attempted_vaults.insert(1);
+    assert_eq!(live_reservation(&state, 1, NOW), None);
+    assert!(require_not_reserved(&state, 1, liquidator(), NOW).is_ok());
+
+    // Reservations only bind in Recovery.
+    state.sp_attempted_vaults.clear();
+    state.mode = Mode::GeneralAvailability;
+    assert!(require_not_reserved(&state, 1, liquidator(), NOW).is_ok());
+}
+
+#[test]
+fn window_must_be_bounded() {
+    let config = |window_ns| PoolPriorityConfig {
+        enabled: true,
+        window_ns,
+    };
+    assert!(validate_config(&config(WINDOW)).is_ok());
+    assert!(validate_config(&config(0)).is_err());
+    assert!(validate_config(&config(2 * 3600 * 1_000_000_000)).is_err());
+}
+
+#[test]
+fn replay_rebuilds_reservations() {
+    let events = vec![
+        Event::Init(init_arg_with().stability_pool(pool()).build()),
+        Event::SetRecoveryPoolPriority {
+            enabled: true,
+            window_ns: WINDOW,
+        },
+        Event::RecoveryPoolRouting {
+            to_pool: vec![1],
+            overflow: vec![2],
+            pool_depth: vec![(icp(), 100 * E8S)],
+            reserved_until_ns: NOW + WINDOW,
+            timestamp: NOW,
+        },
+        // A later decision keeps the first expiry and drops lapsed entries.
+        Event::RecoveryPoolRouting {
+            to_pool: vec![1, 3],
+            overflow: vec![],
+            pool_depth: vec![(icp(), 100 * E8S)],
+            reserved_until_ns: NOW + 2 * WINDOW,
+            timestamp: NOW + WINDOW / 2,
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert!(state.pool_priority.enabled);
+    assert_eq!(state.pool_reservations.get(&1), Some(&(NOW + WINDOW)));
+    assert_eq!(state.pool_reservations.get(&3), Some(&(NOW + 2 * WINDOW)));
+    assert!(!state.pool_reservations.contains_key(&2));
+}
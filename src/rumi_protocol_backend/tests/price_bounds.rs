@@ -0,0 +1,185 @@
+//! Price bounds: a sample outside the collateral's bounds is rejected with a
+//! `PriceOutOfBounds` event, only the first of a run of rejections is
+//! recorded and the rest are counted, collaterals without bounds of their
+//! own get the wide defaults, bounds are validated and show up in the
+//! effective parameters, and replay rebuilds them.
+//!
+//! ICP is bounded to $0.10..$10,000.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::effective_parameters::{resolve, ParameterSource};
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::xrc::{
+    apply_price_bounds, check_price_bounds_at, note_price_rejection, price_bounds,
+    validate_price_bounds, PriceBounds, PriceRejections, DEFAULT_PRICE_BOUNDS,
+};
+
+use common::{fresh_state, icp, init_arg};
+
+const NOW: u64 = 1_700_000_000 * 1_000_000_000;
+
+fn bounds() -> PriceBounds {
+    PriceBounds {
+        min_price_e8s: 10_000_000,
+        max_price_e8s: 1_000_000_000_000,
+    }
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    apply_price_bounds(&mut state, icp(), Some(bounds()));
+    state
+}
+
+#[test]
+fn bounds_are_validated() {
+    assert!(validate_price_bounds(&bounds()).is_ok());
+    assert!(validate_price_bounds(&DEFAULT_PRICE_BOUNDS).is_ok());
+    for bad in [
+        PriceBounds {
+            min_price_e8s: 0,
+            ..bounds()
+        },
+        PriceBounds {
+            min_price_e8s: 5,
+            max_price_e8s: 5,
+        },
+        PriceBounds {
+            min_price_e8s: 6,
+            max_price_e8s: 5,
+        },
+    ] {
+        assert!(validate_price_bounds(&bad).is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn prices_inside_the_bounds_proceed() {
+    let state = fixture();
+    for price in [0.1, 7.25, 10_000.0] {
+        assert_eq!(check_price_bounds_at(&state, &icp(), price, NOW), None);
+    }
+}
+
+#[test]
+fn prices_outside_the_bounds_are_rejected() {
+    let state = fixture();
+    assert_eq!(
+        check_price_bounds_at(&state, &icp(), 0.09, NOW),
+        Some(Event::PriceOutOfBounds {
+            collateral_type: icp(),
+            rejected_price: "0.09".to_string(),
+            min_price_e8s: 10_000_000,
+            max_price_e8s: 1_000_000_000_000,
+            timestamp: NOW,
+        })
+    );
+    for price in [10_000.01, 0.0, f64::NAN, f64::INFINITY] {
+        assert!(
+            check_price_bounds_at(&state, &icp(), price, NOW).is_some(),
+            "{}",
+            price
+        );
+    }
+}
+
+#[test]
+fn only_the_first_rejection_of_a_run_is_recorded() {
+    let mut state = fixture();
+    let first = check_price_bounds_at(&state, &icp(), 0.09, NOW).unwrap();
+    assert_eq!(note_price_rejection(&mut state, first.clone()), Some(first));
+    for price in [0.08, 20_000.0] {
+        let repeat = check_price_bounds_at(&state, &icp(), price, NOW + 1).unwrap();
+        assert_eq!(note_price_rejection(&mut state, repeat), None);
+    }
+    assert_eq!(
+        state.price_rejections[&icp()],
+        PriceRejections {
+            out_of_bounds: 3,
+            deviation: 0,
+            since_ns: NOW,
+        }
+    );
+
+    // An accepted price ends the run.
+    let state = replay(
+        vec![
+            Event::Init(init_arg()),
+            Event::PriceOutOfBounds {
+                collateral_type: icp(),
+                rejected_price: "0.01".to_string(),
+                min_price_e8s: 10_000_000,
+                max_price_e8s: 1_000_000_000_000,
+                timestamp: NOW,
+            },
+            Event::PriceUpdate {
+                collateral_type: icp(),
+                price: "7.25".to_string(),
+                timestamp: NOW + 1,
+            },
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    assert!(state.price_rejections.is_empty());
+}
+
+#[test]
+fn collaterals_without_bounds_use_the_defaults() {
+    let mut state = fixture();
+    let other = Principal::from_slice(&[11]);
+    assert_eq!(price_bounds(&state, &other), DEFAULT_PRICE_BOUNDS);
+    assert_eq!(check_price_bounds_at(&state, &other, 95_000.0, NOW), None);
+    assert!(check_price_bounds_at(&state, &other, 20_000_000.0, NOW).is_some());
+
+    apply_price_bounds(&mut state, icp(), None);
+    assert_eq!(price_bounds(&state, &icp()), DEFAULT_PRICE_BOUNDS);
+    assert_eq!(check_price_bounds_at(&state, &icp(), 20_000.0, NOW), None);
+}
+
+#[test]
+fn bounds_are_effective_parameters() {
+    let state = fresh_state();
+    let params = resolve(&state, &icp(), NOW).unwrap();
+    assert_eq!(
+        params.min_price_e8s.value,
+        DEFAULT_PRICE_BOUNDS.min_price_e8s
+    );
+    assert_eq!(params.min_price_e8s.source, ParameterSource::Default);
+
+    let params = resolve(&fixture(), &icp(), NOW).unwrap();
+    assert_eq!(params.min_price_e8s.value, 10_000_000);
+    assert_eq!(params.max_price_e8s.value, 1_000_000_000_000);
+    assert_eq!(params.max_price_e8s.source, ParameterSource::Override);
+}
+
+#[test]
+fn replay_rebuilds_the_bounds() {
+    let mut events = vec![
+        Event::Init(init_arg()),
+        Event::SetCollateralPriceBounds {
+            collateral_type: icp(),
+            bounds: Some(bounds()),
+        },
+        Event::PriceOutOfBounds {
+            collateral_type: icp(),
+            rejected_price: "0.01".to_string(),
+            min_price_e8s: 10_000_000,
+            max_price_e8s: 1_000_000_000_000,
+            timestamp: NOW,
+        },
+    ];
+    let state = replay(events.clone().into_iter()).expect("replay");
+    assert_eq!(price_bounds(&state, &icp()), bounds());
+
+    events.push(Event::SetCollateralPriceBounds {
+        collateral_type: icp(),
+        bounds: None,
+    });
+    let state = replay(events.into_iter()).expect("replay");
+    assert!(state.price_bounds.is_empty());
+}
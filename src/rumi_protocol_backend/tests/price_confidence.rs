@@ -0,0 +1,107 @@
+//! Price confidence: XRC's spread is stored next to each price, borrow and
+//! withdrawal checks read `price - k·σ` when a collateral has `k` set, the
+//! mid-price is untouched for everything else, and `k` survives replay.
+
+mod common;
+
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::Ratio;
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::xrc::xrc_std_dev;
+
+use common::{fresh_state, init_arg};
+
+/// ICP priced at $10.00 with a $0.20 spread.
+fn priced_state() -> State {
+    let mut state = fresh_state();
+    let icp = state.icp_collateral_type();
+    let config = state.collateral_configs.get_mut(&icp).unwrap();
+    config.last_price = Some(10.0);
+    config.last_price_std_dev = Some(0.2);
+    state
+}
+
+#[test]
+fn std_dev_is_scaled_like_the_rate() {
+    assert_eq!(xrc_std_dev(20_000_000, 8), 0.2);
+    assert_eq!(xrc_std_dev(0, 8), 0.0);
+    assert_eq!(xrc_std_dev(15, 0), 15.0);
+}
+
+#[test]
+fn borrow_check_price_takes_k_sigma_off_the_mid() {
+    let mut state = priced_state();
+    let icp = state.icp_collateral_type();
+
+    // No multiple configured: borrows see the mid-price.
+    let config = &state.collateral_configs[&icp];
+    assert_eq!(config.borrow_check_price(), Some(10.0));
+
+    state.set_price_confidence_k(&icp, Some(Ratio::from(dec!(2))));
+    let config = &state.collateral_configs[&icp];
+    assert!((config.borrow_check_price().unwrap() - 9.6).abs() < 1e-9);
+
+    // A spread wider than the price floors at zero rather than going negative.
+    state.set_price_std_dev(&icp, Some(8.0));
+    assert_eq!(
+        state.collateral_configs[&icp].borrow_check_price(),
+        Some(0.0)
+    );
+
+    // No spread reported: fall back to the mid-price.
+    state.set_price_std_dev(&icp, None);
+    assert_eq!(
+        state.collateral_configs[&icp].borrow_check_price(),
+        Some(10.0)
+    );
+
+    state.collateral_configs.get_mut(&icp).unwrap().last_price = None;
+    assert_eq!(state.collateral_configs[&icp].borrow_check_price(), None);
+}
+
+#[test]
+fn only_borrow_checks_see_the_conservative_price() {
+    let mut state = priced_state();
+    let icp = state.icp_collateral_type();
+    state.set_price_confidence_k(&icp, Some(Ratio::from(dec!(1))));
+
+    let mid = state.get_collateral_price_decimal(&icp).unwrap();
+    let bound = state.get_borrow_check_price_decimal(&icp).unwrap();
+    assert_eq!(mid, Decimal::from(10));
+    assert!(bound < mid);
+    assert!((bound - dec!(9.8)).abs() < dec!(0.000001));
+}
+
+#[test]
+fn replay_restores_the_confidence_multiple() {
+    let icp = init_arg().icp_ledger_principal;
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::SetCollateralPriceConfidence {
+            collateral_type: icp,
+            k: Some("1.5".to_string()),
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(
+        state.collateral_configs[&icp].price_confidence_k,
+        Some(Ratio::from(dec!(1.5)))
+    );
+
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::SetCollateralPriceConfidence {
+            collateral_type: icp,
+            k: Some("1.5".to_string()),
+        },
+        Event::SetCollateralPriceConfidence {
+            collateral_type: icp,
+            k: None,
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(state.collateral_configs[&icp].price_confidence_k, None);
+}
@@ -0,0 +1,162 @@
+//! Secondary-source price cross-check: an XRC sample that diverges from the
+//! collateral's secondary oracle beyond its band is held back and the
+//! collateral marked disputed, until the sources agree again or the dispute
+//! is cleared manually. Replay reproduces the flag.
+//!
+//! ICP is at $10, with a secondary source and a 200 bps band.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::state::{SecondaryPriceSource, State};
+use rumi_protocol_backend::xrc::{cross_check_price_at, price_divergence_bps};
+
+use common::{fresh_state, icp, init_arg, set_icp_price};
+
+const NOW_NS: u64 = 1_000;
+
+fn source() -> SecondaryPriceSource {
+    SecondaryPriceSource {
+        canister_id: Principal::from_slice(&[20]),
+        method: "get_twap".to_string(),
+        max_divergence_bps: 200,
+    }
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    state.set_secondary_price_source(&icp(), Some(source()));
+    set_icp_price(&mut state, 10.0, None);
+    state
+}
+
+fn disputed(state: &State) -> bool {
+    state.collateral_configs[&icp()].price_disputed
+}
+
+#[test]
+fn divergence_is_measured_against_the_secondary_price() {
+    assert_eq!(price_divergence_bps(10.2, 10.0), 200);
+    assert_eq!(price_divergence_bps(9.0, 10.0), 1_000);
+    assert_eq!(price_divergence_bps(10.0, 10.0), 0);
+}
+
+#[test]
+fn unconfigured_collateral_is_not_checked() {
+    let mut state = fresh_state();
+    assert_eq!(
+        cross_check_price_at(&mut state, &icp(), 50.0, Some(10.0), NOW_NS),
+        (true, None)
+    );
+    assert!(!disputed(&state));
+}
+
+#[test]
+fn agreeing_sources_pass() {
+    let mut state = fixture();
+    assert_eq!(
+        cross_check_price_at(&mut state, &icp(), 10.15, Some(10.0), NOW_NS),
+        (true, None)
+    );
+    assert!(!disputed(&state));
+}
+
+#[test]
+fn divergence_opens_a_dispute_once() {
+    let mut state = fixture();
+    let (accepted, event) = cross_check_price_at(&mut state, &icp(), 11.0, Some(10.0), NOW_NS);
+    assert!(!accepted);
+    assert!(matches!(
+        event,
+        Some(Event::PriceDisputed {
+            divergence_bps: 1_000,
+            timestamp: NOW_NS,
+            ..
+        })
+    ));
+    assert!(disputed(&state));
+
+    // Still diverging: held back, no second incident.
+    assert_eq!(
+        cross_check_price_at(&mut state, &icp(), 11.5, Some(10.0), NOW_NS),
+        (false, None)
+    );
+    // Secondary unavailable: cannot end the dispute.
+    assert_eq!(
+        cross_check_price_at(&mut state, &icp(), 10.0, None, NOW_NS),
+        (false, None)
+    );
+    assert_eq!(state.collateral_configs[&icp()].last_price, Some(10.0));
+}
+
+#[test]
+fn convergence_clears_the_dispute() {
+    let mut state = fixture();
+    cross_check_price_at(&mut state, &icp(), 11.0, Some(10.0), NOW_NS);
+    let (accepted, event) = cross_check_price_at(&mut state, &icp(), 10.1, Some(10.0), NOW_NS);
+    assert!(accepted);
+    assert!(matches!(
+        event,
+        Some(Event::PriceDisputeCleared {
+            resolved_by: None,
+            ..
+        })
+    ));
+    assert!(!disputed(&state));
+}
+
+#[test]
+fn secondary_outage_does_not_stall_undisputed_pricing() {
+    let mut state = fixture();
+    assert_eq!(
+        cross_check_price_at(&mut state, &icp(), 10.5, None, NOW_NS),
+        (true, None)
+    );
+}
+
+#[test]
+fn removing_the_source_lifts_the_dispute() {
+    let mut state = fixture();
+    cross_check_price_at(&mut state, &icp(), 11.0, Some(10.0), NOW_NS);
+    state.set_secondary_price_source(&icp(), None);
+    assert!(!disputed(&state));
+    assert_eq!(
+        state.collateral_configs[&icp()].secondary_price_source,
+        None
+    );
+}
+
+#[test]
+fn replay_tracks_source_and_dispute() {
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::SetCollateralSecondaryPriceSource {
+            collateral_type: icp(),
+            source: Some(source()),
+        },
+        Event::PriceDisputed {
+            collateral_type: icp(),
+            xrc_price: "11".to_string(),
+            secondary_price: "10".to_string(),
+            divergence_bps: 1_000,
+            timestamp: NOW_NS,
+        },
+    ];
+    let state = replay(events.clone().into_iter()).expect("replay");
+    assert_eq!(
+        state.collateral_configs[&icp()].secondary_price_source,
+        Some(source())
+    );
+    assert!(disputed(&state));
+
+    let mut events = events;
+    events.push(Event::PriceDisputeCleared {
+        collateral_type: icp(),
+        resolved_by: Some(Principal::anonymous()),
+        timestamp: NOW_NS + 1,
+    });
+    let state = replay(events.into_iter()).expect("replay");
+    assert!(!disputed(&state));
+}
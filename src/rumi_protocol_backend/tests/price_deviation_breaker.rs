@@ -0,0 +1,211 @@
+//! Price-deviation breaker: a sample too far from a recent accepted price is
+//! rejected with a `PriceRejected` event and the old price kept, an older
+//! price lets any sample through to the sanity band, and under
+//! `enter_recovery` a rejection moves the protocol to Recovery and holds it
+//! there until the collateral's next accepted price, on replay as well.
+//!
+//! ICP was last accepted at $10.00 at `T0`, and the breaker trips on a 10%
+//! move within 10 minutes.
+
+mod common;
+
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::UsdIcp;
+use rumi_protocol_backend::state::{
+    is_legal_mode_transition, Mode, ModeTransition, ModeTransitionReason, State,
+};
+use rumi_protocol_backend::xrc::{
+    apply_price_deviation_breaker, check_price_deviation_at, validate_price_deviation_breaker,
+    PriceDeviationBreaker, MAX_PRICE_DEVIATION_WINDOW_NS,
+};
+
+use common::{fresh_state, icp, init_arg, set_icp_price};
+
+const MINUTE: u64 = 60 * 1_000_000_000;
+const T0: u64 = 1_700_000_000 * 1_000_000_000;
+
+fn breaker(enter_recovery: bool) -> PriceDeviationBreaker {
+    PriceDeviationBreaker {
+        max_deviation_bps: 1_000,
+        window_ns: 10 * MINUTE,
+        enter_recovery,
+    }
+}
+
+fn fixture(enter_recovery: bool) -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 10.0, Some(T0));
+    apply_price_deviation_breaker(&mut state, Some(breaker(enter_recovery)));
+    state
+}
+
+#[test]
+fn configs_are_validated() {
+    assert!(validate_price_deviation_breaker(&breaker(true)).is_ok());
+    for bad in [
+        PriceDeviationBreaker {
+            max_deviation_bps: 0,
+            ..breaker(false)
+        },
+        PriceDeviationBreaker {
+            max_deviation_bps: 10_001,
+            ..breaker(false)
+        },
+        PriceDeviationBreaker {
+            window_ns: 0,
+            ..breaker(false)
+        },
+        PriceDeviationBreaker {
+            window_ns: MAX_PRICE_DEVIATION_WINDOW_NS + 1,
+            ..breaker(false)
+        },
+    ] {
+        assert!(validate_price_deviation_breaker(&bad).is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn a_jump_within_the_window_is_rejected() {
+    let mut state = fixture(false);
+    let sample_ts = T0 + 5 * MINUTE;
+
+    assert_eq!(
+        check_price_deviation_at(&mut state, &icp(), 10.9, sample_ts, sample_ts),
+        (true, None)
+    );
+
+    let (accepted, event) = check_price_deviation_at(&mut state, &icp(), 7.5, sample_ts, sample_ts);
+    assert!(!accepted);
+    assert_eq!(
+        event,
+        Some(Event::PriceRejected {
+            collateral_type: icp(),
+            rejected_price: "7.5".to_string(),
+            last_price: "10".to_string(),
+            deviation_bps: 2_500,
+            recovery: false,
+            timestamp: sample_ts,
+        })
+    );
+    assert_eq!(state.collateral_configs[&icp()].last_price, Some(10.0));
+    assert_eq!(state.mode, Mode::GeneralAvailability);
+    assert!(state.price_deviation_holds.is_empty());
+}
+
+#[test]
+fn an_old_price_or_no_breaker_lets_samples_through() {
+    let mut state = fixture(false);
+    let late = T0 + 11 * MINUTE;
+    assert_eq!(
+        check_price_deviation_at(&mut state, &icp(), 7.5, late, late),
+        (true, None)
+    );
+
+    apply_price_deviation_breaker(&mut state, None);
+    assert_eq!(
+        check_price_deviation_at(&mut state, &icp(), 1.0, T0 + 1, T0 + 1),
+        (true, None)
+    );
+
+    // No accepted price yet: nothing to compare against.
+    let mut fresh = fixture(false);
+    fresh.collateral_configs.get_mut(&icp()).unwrap().last_price = None;
+    assert_eq!(
+        check_price_deviation_at(&mut fresh, &icp(), 1.0, T0 + 1, T0 + 1),
+        (true, None)
+    );
+}
+
+#[test]
+fn a_rejection_can_hold_the_protocol_in_recovery() {
+    let mut state = fixture(true);
+    let (accepted, event) = check_price_deviation_at(&mut state, &icp(), 13.0, T0 + 1, T0 + 1);
+    assert!(!accepted);
+    assert!(matches!(
+        event,
+        Some(Event::PriceRejected { recovery: true, .. })
+    ));
+    assert_eq!(state.mode, Mode::Recovery);
+    assert!(state.price_deviation_holds.contains(&icp()));
+    assert_eq!(
+        state.pending_mode_transitions,
+        vec![ModeTransition {
+            from: Mode::GeneralAvailability,
+            to: Mode::Recovery,
+            reason: ModeTransitionReason::PriceDeviation,
+        }]
+    );
+
+    // No debt, so the ratio alone would return to GA; the hold wins.
+    state.update_total_collateral_ratio_and_mode(UsdIcp::from(dec!(10)), T0 + 1);
+    assert_eq!(state.mode, Mode::Recovery);
+
+    // Turning the breaker off releases the hold.
+    apply_price_deviation_breaker(&mut state, None);
+    assert!(state.price_deviation_holds.is_empty());
+    state.update_total_collateral_ratio_and_mode(UsdIcp::from(dec!(10)), T0 + 1);
+    assert_eq!(state.mode, Mode::GeneralAvailability);
+}
+
+#[test]
+fn the_breaker_only_enters_recovery() {
+    let reason = ModeTransitionReason::PriceDeviation;
+    assert!(is_legal_mode_transition(
+        Mode::GeneralAvailability,
+        Mode::Recovery,
+        reason
+    ));
+    assert!(!is_legal_mode_transition(
+        Mode::Recovery,
+        Mode::GeneralAvailability,
+        reason
+    ));
+    assert!(!is_legal_mode_transition(
+        Mode::GeneralAvailability,
+        Mode::ReadOnly,
+        reason
+    ));
+    assert!(!is_legal_mode_transition(
+        Mode::ReadOnly,
+        Mode::Recovery,
+        reason
+    ));
+}
+
+#[test]
+fn replay_rebuilds_the_hold_until_the_next_accepted_price() {
+    let mut events = vec![
+        Event::Init(init_arg()),
+        Event::SetPriceDeviationBreaker {
+            config: Some(breaker(true)),
+        },
+        Event::PriceRejected {
+            collateral_type: icp(),
+            rejected_price: "13".to_string(),
+            last_price: "10".to_string(),
+            deviation_bps: 3_000,
+            recovery: true,
+            timestamp: T0,
+        },
+        Event::ModeTransition {
+            from: Mode::GeneralAvailability,
+            to: Mode::Recovery,
+            reason: ModeTransitionReason::PriceDeviation,
+            timestamp: T0,
+        },
+    ];
+    let state = replay(events.clone().into_iter()).expect("replay");
+    assert_eq!(state.price_deviation_breaker, Some(breaker(true)));
+    assert!(state.price_deviation_holds.contains(&icp()));
+    assert_eq!(state.mode, Mode::Recovery);
+
+    events.push(Event::PriceUpdate {
+        collateral_type: icp(),
+        price: "10.2".to_string(),
+        timestamp: T0 + 11 * MINUTE,
+    });
+    let state = replay(events.into_iter()).expect("replay");
+    assert!(state.price_deviation_holds.is_empty());
+}
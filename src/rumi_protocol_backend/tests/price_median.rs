@@ -0,0 +1,90 @@
+//! Median price source: the middle quote is published (the two middle ones
+//! averaged for an even count), unusable quotes are dropped before counting
+//! against `min_feeds`, and malformed feed lists are rejected up front.
+
+use candid::Principal;
+
+use rumi_protocol_backend::state::{
+    validate_price_source, PriceFeed, PriceSource, XrcAssetClass, MAX_MEDIAN_PRICE_FEEDS,
+};
+use rumi_protocol_backend::xrc::median_price;
+
+fn xrc_feed(base_asset: &str) -> PriceFeed {
+    PriceFeed::Xrc {
+        base_asset: base_asset.to_string(),
+        base_asset_class: XrcAssetClass::Cryptocurrency,
+        quote_asset: "USD".to_string(),
+        quote_asset_class: XrcAssetClass::FiatCurrency,
+    }
+}
+
+fn twap_feed() -> PriceFeed {
+    PriceFeed::Canister {
+        canister_id: Principal::from_slice(&[42]),
+        method: "get_twap".to_string(),
+    }
+}
+
+fn median(feeds: Vec<PriceFeed>, min_feeds: u32) -> PriceSource {
+    PriceSource::Median { feeds, min_feeds }
+}
+
+#[test]
+fn the_middle_quote_wins() {
+    assert_eq!(median_price(&[10.0, 50.0, 11.0], 1), Some(11.0));
+    assert_eq!(median_price(&[10.0, 12.0, 11.0, 100.0], 1), Some(11.5));
+    assert_eq!(median_price(&[7.0], 1), Some(7.0));
+}
+
+#[test]
+fn too_few_usable_quotes_publish_nothing() {
+    assert_eq!(median_price(&[], 1), None);
+    assert_eq!(median_price(&[10.0, 11.0], 3), None);
+    // Zero, negative and non-finite quotes do not count towards the minimum.
+    assert_eq!(median_price(&[10.0, 0.0, -1.0, f64::NAN], 2), None);
+    assert_eq!(median_price(&[10.0, f64::INFINITY, 12.0], 2), Some(11.0));
+}
+
+#[test]
+fn median_sources_are_validated() {
+    let coingecko = PriceFeed::CoinGecko {
+        coin_id: "bob-3".to_string(),
+        vs_currency: "usd".to_string(),
+    };
+    assert!(
+        validate_price_source(&median(vec![xrc_feed("BOB"), twap_feed(), coingecko], 2)).is_ok()
+    );
+
+    let bad = [
+        median(vec![], 1),
+        median(vec![xrc_feed("BOB"); MAX_MEDIAN_PRICE_FEEDS + 1], 1),
+        median(vec![xrc_feed("BOB"), twap_feed()], 0),
+        median(vec![xrc_feed("BOB"), twap_feed()], 3),
+        median(vec![xrc_feed(""), twap_feed()], 1),
+        median(
+            vec![PriceFeed::Canister {
+                canister_id: Principal::from_slice(&[42]),
+                method: String::new(),
+            }],
+            1,
+        ),
+    ];
+    for source in bad {
+        assert!(validate_price_source(&source).is_err(), "{:?}", source);
+    }
+
+    // Single-source variants have nothing to validate.
+    assert!(validate_price_source(&PriceSource::CoinGecko {
+        coin_id: "bob-3".to_string(),
+        vs_currency: "usd".to_string(),
+    })
+    .is_ok());
+}
+
+#[test]
+fn median_sources_compare_by_feeds_and_minimum() {
+    let a = median(vec![xrc_feed("BOB"), twap_feed()], 2);
+    assert_eq!(a, median(vec![xrc_feed("BOB"), twap_feed()], 2));
+    assert_ne!(a, median(vec![xrc_feed("BOB"), twap_feed()], 1));
+    assert_ne!(a, median(vec![twap_feed(), xrc_feed("BOB")], 2));
+}
@@ -0,0 +1,138 @@
+//! Public statistics: vaults are bucketed by collateral value, debt
+//! percentiles appear only once enough vaults borrow, liquidations are
+//! counted over trailing windows, and the snapshot is refreshed with the
+//! other cached aggregates.
+//!
+//! ICP is at $10.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::liquidation_receipts::{issue, Settlement};
+use rumi_protocol_backend::public_stats::{
+    compute, DebtPercentiles, LiquidationFrequency, MIN_VAULTS_FOR_PERCENTILES,
+};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::Vault;
+
+use common::{fresh_state, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const DAY_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+const NOW: u64 = 100 * DAY_NS;
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 10.0, None);
+    state
+}
+
+fn vault(vault_id: u64, collateral_icp: u64, debt_icusd: u64) -> Vault {
+    VaultBuilder::new(vault_id)
+        .owner(Principal::from_slice(&[vault_id as u8]))
+        .collateral(collateral_icp * E8S)
+        .debt(debt_icusd * E8S)
+        .build()
+}
+
+#[test]
+fn vaults_are_bucketed_by_collateral_value() {
+    let mut state = fixture();
+    state.open_vault(vault(1, 5, 0)); // $50
+    state.open_vault(vault(2, 50, 0)); // $500
+    state.open_vault(vault(3, 500, 100)); // $5,000
+    state.open_vault(vault(4, 510, 0)); // $5,100
+    state.open_vault(vault(5, 20_000, 0)); // $200,000
+
+    let stats = compute(&state, NOW);
+    let counts: Vec<(u64, Option<u64>, u64)> = stats
+        .vault_size_buckets
+        .iter()
+        .map(|b| (b.min_usd, b.max_usd, b.vault_count))
+        .collect();
+    assert_eq!(
+        counts,
+        vec![
+            (0, Some(100), 1),
+            (100, Some(1_000), 1),
+            (1_000, Some(10_000), 2),
+            (10_000, Some(100_000), 0),
+            (100_000, None, 1),
+        ]
+    );
+    assert_eq!(stats.vault_count, 5);
+    assert_eq!(stats.borrowing_vault_count, 1);
+    assert_eq!(stats.total_debt_e8s, 100 * E8S);
+    assert_eq!(stats.total_collateral_value_e8s, 210_650 * E8S);
+}
+
+#[test]
+fn debt_percentiles_need_enough_borrowers() {
+    let mut state = fixture();
+    for id in 1..MIN_VAULTS_FOR_PERCENTILES as u64 {
+        state.open_vault(vault(id, 100, id));
+    }
+    assert_eq!(compute(&state, NOW).debt_percentiles, None);
+
+    state.open_vault(vault(MIN_VAULTS_FOR_PERCENTILES as u64, 100, 10));
+    assert_eq!(
+        compute(&state, NOW).debt_percentiles,
+        Some(DebtPercentiles {
+            p10: E8S,
+            p25: 3 * E8S,
+            p50: 5 * E8S,
+            p75: 8 * E8S,
+            p90: 9 * E8S,
+        })
+    );
+}
+
+#[test]
+fn average_ratios_cover_borrowing_vaults() {
+    let mut state = fixture();
+    state.open_vault(vault(1, 10, 50)); // 200%
+    state.open_vault(vault(2, 10, 100)); // 100%
+    state.open_vault(vault(3, 10, 0));
+
+    let stats = compute(&state, NOW);
+    assert_eq!(stats.average_collateral_ratio, Some(1.5));
+    // $200 against 150 icUSD.
+    let weighted = stats.debt_weighted_collateral_ratio.unwrap();
+    assert!((weighted - 4.0 / 3.0).abs() < 1e-9);
+
+    assert_eq!(compute(&fixture(), NOW).average_collateral_ratio, None);
+}
+
+#[test]
+fn liquidations_are_counted_over_trailing_windows() {
+    let mut state = fixture();
+    let before = vault(1, 10, 80);
+    let settlement = || Settlement {
+        price: dec!(10),
+        ..Default::default()
+    };
+    for age in [DAY_NS / 2, 3 * DAY_NS, 20 * DAY_NS, 40 * DAY_NS] {
+        issue(&mut state, &before, settlement(), NOW - age);
+    }
+    assert_eq!(
+        compute(&state, NOW).liquidations,
+        LiquidationFrequency {
+            last_24h: 1,
+            last_7d: 2,
+            last_30d: 3,
+        }
+    );
+}
+
+#[test]
+fn the_snapshot_is_refreshed_with_the_other_aggregates() {
+    let mut state = fixture();
+    assert!(state.public_stats_snapshot.is_none());
+    state.open_vault(vault(1, 10, 50));
+    state.refresh_aggregate_snapshots(NOW);
+    let snapshot = state.public_stats_snapshot.clone().expect("snapshot");
+    assert_eq!(snapshot, compute(&state, NOW));
+    assert_eq!(snapshot.computed_at_ns, NOW);
+}
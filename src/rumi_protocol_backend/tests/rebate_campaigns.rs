@@ -0,0 +1,166 @@
+//! Borrowing-fee rebate campaigns: pool reservation, window/exhaustion
+//! gating, failed-borrow release, close semantics, and replay of the
+//! campaign event stream.
+
+mod common;
+
+use rumi_protocol_backend::campaigns::{
+    apply_close, apply_create, apply_fund, apply_rebate_paid, release_rebate, reserve_rebate,
+    validate_create_arg, CreateRebateCampaignArg,
+};
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::state::State;
+
+use common::{fresh_state, init_arg};
+
+const START_NS: u64 = 1_000;
+const END_NS: u64 = 2_000;
+const DURING_NS: u64 = 1_500;
+
+fn campaign_arg(rebate_bps: u64, pool_e8s: u64) -> CreateRebateCampaignArg {
+    CreateRebateCampaignArg {
+        name: "launch".to_string(),
+        rebate_bps,
+        start_ns: START_NS,
+        end_ns: END_NS,
+        pool_e8s,
+    }
+}
+
+fn state_with_campaign(rebate_bps: u64, pool_e8s: u64) -> State {
+    let mut state = fresh_state();
+    apply_create(&mut state, 0, campaign_arg(rebate_bps, pool_e8s));
+    state
+}
+
+#[test]
+fn create_arg_validation() {
+    assert!(validate_create_arg(&campaign_arg(5_000, 100), 0).is_ok());
+    assert!(validate_create_arg(&campaign_arg(0, 100), 0).is_err());
+    assert!(validate_create_arg(&campaign_arg(10_001, 100), 0).is_err());
+    assert!(validate_create_arg(&campaign_arg(5_000, 0), 0).is_err());
+    assert!(
+        validate_create_arg(&campaign_arg(5_000, 100), END_NS).is_err(),
+        "a campaign whose window already ended is rejected"
+    );
+    let mut inverted = campaign_arg(5_000, 100);
+    inverted.end_ns = inverted.start_ns;
+    assert!(validate_create_arg(&inverted, 0).is_err());
+}
+
+#[test]
+fn rebate_is_fee_share_reserved_from_pool() {
+    let mut state = state_with_campaign(2_500, 1_000_000);
+
+    let (id, rebate) = reserve_rebate(&mut state, ICUSD::new(400_000), DURING_NS).unwrap();
+    assert_eq!(id, 0);
+    assert_eq!(rebate, ICUSD::new(100_000));
+    assert_eq!(state.rebate_campaigns[&0].remaining_e8s, 900_000);
+}
+
+#[test]
+fn no_rebate_outside_window() {
+    let mut state = state_with_campaign(2_500, 1_000_000);
+    assert!(reserve_rebate(&mut state, ICUSD::new(400_000), START_NS - 1).is_none());
+    assert!(reserve_rebate(&mut state, ICUSD::new(400_000), END_NS).is_none());
+    assert_eq!(state.rebate_campaigns[&0].remaining_e8s, 1_000_000);
+}
+
+#[test]
+fn rebate_capped_by_pool_then_exhausted() {
+    let mut state = state_with_campaign(10_000, 150);
+
+    let (_, first) = reserve_rebate(&mut state, ICUSD::new(100), DURING_NS).unwrap();
+    let (_, second) = reserve_rebate(&mut state, ICUSD::new(100), DURING_NS).unwrap();
+    assert_eq!(first, ICUSD::new(100));
+    assert_eq!(
+        second,
+        ICUSD::new(50),
+        "partial rebate up to pool exhaustion"
+    );
+    assert!(reserve_rebate(&mut state, ICUSD::new(100), DURING_NS).is_none());
+}
+
+#[test]
+fn failed_borrow_releases_reservation() {
+    let mut state = state_with_campaign(5_000, 1_000);
+
+    let (id, rebate) = reserve_rebate(&mut state, ICUSD::new(1_000), DURING_NS).unwrap();
+    assert_eq!(state.rebate_campaigns[&0].remaining_e8s, 500);
+    release_rebate(&mut state, id, rebate);
+    assert_eq!(state.rebate_campaigns[&0].remaining_e8s, 1_000);
+    assert_eq!(state.rebate_campaigns[&0].rebated_e8s, 0);
+}
+
+#[test]
+fn closed_campaign_stops_rebates_and_keeps_released_budget() {
+    let mut state = state_with_campaign(5_000, 1_000);
+    let (id, rebate) = reserve_rebate(&mut state, ICUSD::new(1_000), DURING_NS).unwrap();
+
+    assert_eq!(apply_close(&mut state, id, DURING_NS), Ok(500));
+    assert!(apply_close(&mut state, id, DURING_NS).is_err());
+    assert!(apply_fund(&mut state, id, 100).is_err());
+
+    // The in-flight borrow fails after the close: the budget already went
+    // back to the treasury, so the pool is not re-credited.
+    release_rebate(&mut state, id, rebate);
+    assert_eq!(state.rebate_campaigns[&0].remaining_e8s, 0);
+    assert!(reserve_rebate(&mut state, ICUSD::new(1_000), DURING_NS).is_none());
+}
+
+#[test]
+fn oldest_active_campaign_applies_first() {
+    let mut state = state_with_campaign(1_000, 10);
+    apply_create(&mut state, 1, campaign_arg(9_000, 1_000_000));
+    assert_eq!(state.next_rebate_campaign_id, 2);
+
+    let (id, _) = reserve_rebate(&mut state, ICUSD::new(1_000), DURING_NS).unwrap();
+    assert_eq!(id, 0);
+    // Campaign 0 is now exhausted, so the next borrow falls through to 1.
+    let (id, rebate) = reserve_rebate(&mut state, ICUSD::new(1_000), DURING_NS).unwrap();
+    assert_eq!((id, rebate), (1, ICUSD::new(900)));
+}
+
+#[test]
+fn replay_reconstructs_campaign_accounting() {
+    let mut live = state_with_campaign(5_000, 1_000);
+    apply_fund(&mut live, 0, 500).unwrap();
+    let (id, rebate) = reserve_rebate(&mut live, ICUSD::new(400), DURING_NS).unwrap();
+    apply_rebate_paid(&mut live, id, rebate, true);
+    let unspent = apply_close(&mut live, id, DURING_NS).unwrap();
+
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::RebateCampaignCreated {
+            campaign_id: 0,
+            arg: campaign_arg(5_000, 1_000),
+            timestamp: START_NS,
+        },
+        Event::RebateCampaignFunded {
+            campaign_id: 0,
+            amount_e8s: 500,
+            timestamp: START_NS,
+        },
+        Event::BorrowFeeRebated {
+            campaign_id: 0,
+            vault_id: 7,
+            rebate_e8s: rebate.to_u64(),
+            timestamp: DURING_NS,
+        },
+        Event::RebateCampaignClosed {
+            campaign_id: 0,
+            unspent_e8s: unspent,
+            timestamp: DURING_NS,
+        },
+    ];
+    let replayed = replay(events.into_iter()).expect("replay");
+
+    assert_eq!(replayed.rebate_campaigns, live.rebate_campaigns);
+    assert_eq!(replayed.next_rebate_campaign_id, 1);
+    let campaign = &replayed.rebate_campaigns[&0];
+    assert_eq!(campaign.funded_e8s, 1_500);
+    assert_eq!(campaign.rebated_e8s, 200);
+    assert_eq!(campaign.borrows_rebated, 1);
+    assert_eq!(campaign.remaining_e8s, 0);
+}
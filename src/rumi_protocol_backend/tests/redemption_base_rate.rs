@@ -0,0 +1,160 @@
+//! Redemption base rate: it halves every 12 hours without redemptions,
+//! decaying minute by minute, a redemption raises it by half the redeemed
+//! fraction of the debt, the stored rate prices the next redemption, and
+//! replay rebuilds it from the recorded updates.
+//!
+//! A redemption at `NOW` left ICP's base rate at 4%.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::{Ratio, ICUSD};
+use rumi_protocol_backend::record_per_collateral_redemption_fee;
+use rumi_protocol_backend::state::{
+    base_rate_after_redemption, compute_redemption_fee, decay_base_rate, State,
+    DEFAULT_REDEMPTION_FEE_CEILING, DEFAULT_REDEMPTION_FEE_FLOOR,
+    REDEMPTION_BASE_RATE_HALF_LIFE_NS,
+};
+
+use common::{fresh_state, icp, init_arg};
+
+const NOW: u64 = 1_700_000_000 * 1_000_000_000;
+const MINUTE_NS: u64 = 60 * 1_000_000_000;
+const E8S: u64 = 100_000_000;
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    record_per_collateral_redemption_fee(&mut state, &icp(), Ratio::from(dec!(0.04)), NOW);
+    state
+}
+
+fn assert_close(actual: Ratio, expected: Decimal) {
+    assert!(
+        (actual.0 - expected).abs() < dec!(0.000000001),
+        "{} != {}",
+        actual.0,
+        expected
+    );
+}
+
+#[test]
+fn the_base_rate_halves_every_half_life() {
+    let base = Ratio::from(dec!(0.04));
+    assert_eq!(decay_base_rate(base, 0), base);
+    assert_close(
+        decay_base_rate(base, REDEMPTION_BASE_RATE_HALF_LIFE_NS),
+        dec!(0.02),
+    );
+    assert_close(
+        decay_base_rate(base, 2 * REDEMPTION_BASE_RATE_HALF_LIFE_NS),
+        dec!(0.01),
+    );
+    // Six hours is half a half-life: 0.04 / sqrt(2).
+    assert_close(
+        decay_base_rate(base, REDEMPTION_BASE_RATE_HALF_LIFE_NS / 2),
+        dec!(0.028284271247),
+    );
+    assert_eq!(
+        decay_base_rate(base, 64 * REDEMPTION_BASE_RATE_HALF_LIFE_NS).0,
+        Decimal::ZERO
+    );
+    assert_eq!(decay_base_rate(base, u64::MAX).0, Decimal::ZERO);
+}
+
+#[test]
+fn the_base_rate_decays_once_per_minute() {
+    let base = Ratio::from(dec!(0.04));
+    assert_eq!(decay_base_rate(base, MINUTE_NS - 1), base);
+    let one_minute = decay_base_rate(base, MINUTE_NS);
+    assert!(one_minute < base);
+    assert_eq!(decay_base_rate(base, 2 * MINUTE_NS - 1), one_minute);
+}
+
+#[test]
+fn redemptions_raise_the_base_rate_by_half_the_redeemed_fraction() {
+    let debt = ICUSD::new(1_000 * E8S);
+    let decayed = Ratio::from(dec!(0.01));
+    assert_eq!(
+        base_rate_after_redemption(decayed, ICUSD::new(20 * E8S), debt).0,
+        dec!(0.02)
+    );
+    // Never above 100%, and unchanged with no debt to redeem.
+    let saturated = Ratio::from(dec!(0.9));
+    assert_eq!(
+        base_rate_after_redemption(saturated, debt, debt).0,
+        Decimal::ONE
+    );
+    assert_eq!(
+        base_rate_after_redemption(decayed, ICUSD::new(20 * E8S), ICUSD::new(0)),
+        decayed
+    );
+}
+
+#[test]
+fn the_fee_clamps_the_post_redemption_base_rate() {
+    let debt = ICUSD::new(1_000 * E8S);
+    let fee = |elapsed_ns, base: Decimal| {
+        compute_redemption_fee(
+            elapsed_ns,
+            ICUSD::new(E8S),
+            debt,
+            Ratio::from(base),
+            DEFAULT_REDEMPTION_FEE_FLOOR,
+            DEFAULT_REDEMPTION_FEE_CEILING,
+        )
+    };
+    assert_eq!(fee(0, dec!(0.02)).0, dec!(0.0205));
+    assert_close(
+        fee(REDEMPTION_BASE_RATE_HALF_LIFE_NS, dec!(0.02)),
+        dec!(0.0105),
+    );
+    assert_eq!(fee(0, dec!(0.5)), DEFAULT_REDEMPTION_FEE_CEILING);
+    assert_eq!(fee(0, dec!(0)), DEFAULT_REDEMPTION_FEE_FLOOR);
+}
+
+#[test]
+fn the_stored_base_rate_is_read_per_collateral() {
+    let state = fixture();
+    assert_eq!(state.get_base_rate_at(&icp(), NOW).0, dec!(0.04));
+    assert_close(
+        state.get_base_rate_at(&icp(), NOW + REDEMPTION_BASE_RATE_HALF_LIFE_NS),
+        dec!(0.02),
+    );
+    // Unknown collaterals read the legacy global rate.
+    let other = Principal::from_slice(&[11]);
+    assert_eq!(state.get_base_rate_at(&other, NOW).0, Decimal::ZERO);
+    // Without debt to redeem the fee is zero, but the rate still decays.
+    let (fee, base_rate) = state.redemption_fee_at(&icp(), ICUSD::new(E8S), NOW);
+    assert_eq!(fee.0, Decimal::ZERO);
+    assert_eq!(base_rate.0, dec!(0.04));
+}
+
+#[test]
+fn replay_rebuilds_the_base_rate() {
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::RedemptionBaseRateUpdated {
+            collateral_type: icp(),
+            base_rate: "0.04".to_string(),
+            timestamp: NOW,
+        },
+        Event::RedemptionBaseRateUpdated {
+            collateral_type: icp(),
+            base_rate: "0.025".to_string(),
+            timestamp: NOW + REDEMPTION_BASE_RATE_HALF_LIFE_NS,
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    let config = &state.collateral_configs[&icp()];
+    assert_eq!(config.current_base_rate.0, dec!(0.025));
+    assert_eq!(
+        config.last_redemption_time,
+        NOW + REDEMPTION_BASE_RATE_HALF_LIFE_NS
+    );
+    // The legacy global rate is never touched.
+    assert_eq!(state.current_base_rate.0, Decimal::ZERO);
+}
@@ -0,0 +1,212 @@
+//! Cancelling a failing redemption payout: the redeemer may back out once
+//! the payout has failed enough times and within the window, the vaults get
+//! back what the redemption took, and replay reverses it the same way.
+//!
+//! Two ICP vaults hold 20 ICP and owe 100 icUSD each. A redemption at $10
+//! took 4 ICP for 40 icUSD from vault 1 and 2 ICP for 20 icUSD from vault
+//! 2.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::event::{replay, Event, VaultRedemption};
+use rumi_protocol_backend::numeric::{UsdIcp, ICUSD};
+use rumi_protocol_backend::redemption_cancel::{
+    check_cancel_pending_redemption, MIN_FAILED_PAYOUTS_BEFORE_CANCEL,
+    REDEMPTION_CANCEL_WINDOW_NANOS,
+};
+use rumi_protocol_backend::state::{Mode, PendingRefund, State};
+use rumi_protocol_backend::ProtocolError;
+
+use common::{icp, init_arg, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const NOW: u64 = 1_000_000_000_000_000_000;
+const BLOCK: u64 = 7;
+
+fn redeemer() -> Principal {
+    Principal::from_slice(&[2])
+}
+
+fn vault_redemptions() -> Vec<VaultRedemption> {
+    vec![
+        VaultRedemption {
+            vault_id: 1,
+            icusd_redeemed_e8s: 40 * E8S,
+            collateral_seized: 4 * E8S,
+        },
+        VaultRedemption {
+            vault_id: 2,
+            icusd_redeemed_e8s: 20 * E8S,
+            collateral_seized: 2 * E8S,
+        },
+    ]
+}
+
+fn events() -> Vec<Event> {
+    let mut events = vec![Event::Init(init_arg())];
+    for vault_id in [1, 2] {
+        events.push(Event::OpenVault {
+            vault: VaultBuilder::new(vault_id)
+                .owner(Principal::from_slice(&[1]))
+                .collateral(20 * E8S)
+                .debt(100 * E8S)
+                .build(),
+            block_index: vault_id,
+            timestamp: None,
+        });
+    }
+    events.push(Event::RedemptionOnVaults {
+        owner: redeemer(),
+        current_icp_rate: UsdIcp::from(dec!(10)),
+        icusd_amount: ICUSD::new(60 * E8S),
+        fee_amount: ICUSD::new(0),
+        icusd_block_index: BLOCK,
+        collateral_type: Some(icp()),
+        timestamp: Some(NOW),
+        vault_redemptions: Some(vault_redemptions()),
+    });
+    events
+}
+
+/// The redeemed state with a payout that has failed enough to cancel.
+fn fixture() -> State {
+    let mut state = replay(events().into_iter()).expect("replay");
+    state
+        .pending_redemption_transfer
+        .get_mut(&BLOCK)
+        .unwrap()
+        .retry_count = MIN_FAILED_PAYOUTS_BEFORE_CANCEL;
+    state
+}
+
+fn position(state: &State, vault_id: u64) -> (u64, u64) {
+    let vault = &state.vault_id_to_vaults[&vault_id];
+    (
+        vault.collateral_amount,
+        vault.borrowed_icusd_amount.to_u64(),
+    )
+}
+
+#[test]
+fn a_redemption_keeps_its_breakdown_while_the_payout_is_pending() {
+    let state = fixture();
+    assert_eq!(position(&state, 1), (16 * E8S, 60 * E8S));
+    assert_eq!(
+        state.pending_redemption_transfer[&BLOCK].margin.to_u64(),
+        6 * E8S
+    );
+    let record = &state.pending_redemption_records[&BLOCK];
+    assert_eq!(record.vault_redemptions, vault_redemptions());
+    assert_eq!(record.shortfall_e8s, 0);
+    assert_eq!(record.redeemed_at_ns, NOW);
+
+    let mut events = events();
+    events.push(Event::RedemptionTransfered {
+        icusd_block_index: BLOCK,
+        icp_block_index: 3,
+        timestamp: Some(NOW + 1),
+    });
+    let state = replay(events.into_iter()).expect("replay");
+    assert!(state.pending_redemption_transfer.is_empty());
+    assert!(state.pending_redemption_records.is_empty());
+}
+
+#[test]
+fn the_redeemer_may_cancel_after_failed_payouts() {
+    let state = fixture();
+    assert_eq!(
+        check_cancel_pending_redemption(&state, redeemer(), BLOCK, NOW + 1).expect("cancel"),
+        vault_redemptions()
+    );
+
+    let mut fresh = fixture();
+    fresh
+        .pending_redemption_transfer
+        .get_mut(&BLOCK)
+        .unwrap()
+        .retry_count = MIN_FAILED_PAYOUTS_BEFORE_CANCEL - 1;
+    assert!(check_cancel_pending_redemption(&fresh, redeemer(), BLOCK, NOW + 1).is_err());
+
+    let late = NOW + REDEMPTION_CANCEL_WINDOW_NANOS + 1;
+    assert!(check_cancel_pending_redemption(&state, redeemer(), BLOCK, late).is_err());
+}
+
+#[test]
+fn requests_are_checked() {
+    let state = fixture();
+    assert!(matches!(
+        check_cancel_pending_redemption(&state, Principal::from_slice(&[1]), BLOCK, NOW),
+        Err(ProtocolError::CallerNotOwner)
+    ));
+    assert!(check_cancel_pending_redemption(&state, redeemer(), BLOCK + 1, NOW).is_err());
+
+    let mut untracked = fixture();
+    untracked.pending_redemption_records.clear();
+    assert!(check_cancel_pending_redemption(&untracked, redeemer(), BLOCK, NOW).is_err());
+
+    let mut closed = fixture();
+    closed.vault_id_to_vaults.remove(&2);
+    assert!(check_cancel_pending_redemption(&closed, redeemer(), BLOCK, NOW).is_err());
+
+    let mut claimed = fixture();
+    claimed
+        .vault_id_to_vaults
+        .get_mut(&1)
+        .unwrap()
+        .bot_processing = true;
+    assert!(check_cancel_pending_redemption(&claimed, redeemer(), BLOCK, NOW).is_err());
+
+    let mut shortfall = fixture();
+    shortfall
+        .pending_redemption_records
+        .get_mut(&BLOCK)
+        .unwrap()
+        .shortfall_e8s = 1;
+    assert!(check_cancel_pending_redemption(&shortfall, redeemer(), BLOCK, NOW).is_err());
+
+    let mut refund_queued = fixture();
+    refund_queued.pending_refunds.insert(
+        BLOCK,
+        PendingRefund {
+            user: redeemer(),
+            amount_e8s: E8S,
+            retry_count: 0,
+            op_nonce: 1,
+        },
+    );
+    assert!(matches!(
+        check_cancel_pending_redemption(&refund_queued, redeemer(), BLOCK, NOW),
+        Err(ProtocolError::TemporarilyUnavailable(_))
+    ));
+
+    let mut processing = fixture();
+    processing.is_timer_running = true;
+    assert!(matches!(
+        check_cancel_pending_redemption(&processing, redeemer(), BLOCK, NOW),
+        Err(ProtocolError::TemporarilyUnavailable(_))
+    ));
+
+    let mut read_only = fixture();
+    read_only.mode = Mode::ReadOnly;
+    assert!(check_cancel_pending_redemption(&read_only, redeemer(), BLOCK, NOW).is_err());
+}
+
+#[test]
+fn replay_returns_the_collateral_and_debt_to_the_vaults() {
+    let mut events = events();
+    events.push(Event::RedemptionCancelled {
+        owner: redeemer(),
+        icusd_block_index: BLOCK,
+        icusd_refunded: ICUSD::new(60 * E8S),
+        vault_redemptions: vault_redemptions(),
+        timestamp: NOW + 1,
+    });
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(position(&state, 1), (20 * E8S, 100 * E8S));
+    assert_eq!(position(&state, 2), (20 * E8S, 100 * E8S));
+    assert!(state.pending_redemption_transfer.is_empty());
+    assert!(state.pending_redemption_records.is_empty());
+}
@@ -0,0 +1,209 @@
+//! Redemption caps: the tighter of the absolute and debt-share limits
+//! applies, a claim the epoch cannot fit is rejected, usage resets when the
+//! next epoch starts (against the debt left then), uncapped collaterals are
+//! never counted, and replay rebuilds both the cap and the epoch's usage.
+//!
+//! A single ICP vault owes 1,000 icUSD.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::event::{replay, Event, VaultRedemption};
+use rumi_protocol_backend::numeric::{UsdIcp, ICUSD};
+use rumi_protocol_backend::redemption_caps::{
+    apply_set_config, capacity, check_capacity, note_redeemed, remaining, validate_config,
+    RedemptionCapConfig, MIN_REDEMPTION_EPOCH_NS,
+};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::Vault;
+use rumi_protocol_backend::ProtocolError;
+
+use common::{fresh_state, icp, init_arg, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const DAY: u64 = 86_400 * 1_000_000_000;
+const NOW: u64 = 100 * DAY + 5;
+
+fn vault() -> Vault {
+    VaultBuilder::new(1)
+        .owner(Principal::from_slice(&[1]))
+        .collateral(200 * E8S)
+        .debt(1_000 * E8S)
+        .build()
+}
+
+/// At most 200 icUSD and at most 10% of the debt per day.
+fn config() -> RedemptionCapConfig {
+    RedemptionCapConfig {
+        epoch_ns: DAY,
+        max_redeemed_e8s: 200 * E8S,
+        max_debt_bps: 1_000,
+    }
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    state.open_vault(vault());
+    apply_set_config(&mut state, icp(), Some(config()));
+    state
+}
+
+#[test]
+fn configs_are_validated() {
+    assert!(validate_config(&config()).is_ok());
+    let bad = [
+        RedemptionCapConfig {
+            epoch_ns: MIN_REDEMPTION_EPOCH_NS - 1,
+            ..config()
+        },
+        RedemptionCapConfig {
+            epoch_ns: 31 * DAY,
+            ..config()
+        },
+        RedemptionCapConfig {
+            max_debt_bps: 10_001,
+            ..config()
+        },
+        RedemptionCapConfig {
+            max_redeemed_e8s: 0,
+            max_debt_bps: 0,
+            ..config()
+        },
+    ];
+    for config in bad {
+        assert!(validate_config(&config).is_err(), "{:?}", config);
+    }
+}
+
+#[test]
+fn the_tighter_limit_applies() {
+    let mut state = fixture();
+    let cap = capacity(&state, &icp(), NOW);
+    assert_eq!(cap.epoch_start_ns, 100 * DAY);
+    assert_eq!(cap.epoch_end_ns, 101 * DAY);
+    assert_eq!(cap.limit_e8s, Some(100 * E8S));
+    assert_eq!(cap.remaining_e8s, Some(100 * E8S));
+
+    // Half the debt allowed: the 200 icUSD absolute cap is tighter.
+    apply_set_config(
+        &mut state,
+        icp(),
+        Some(RedemptionCapConfig {
+            max_debt_bps: 5_000,
+            ..config()
+        }),
+    );
+    assert_eq!(remaining(&state, &icp(), NOW), 200 * E8S);
+
+    // No relative cap: only the absolute one counts.
+    apply_set_config(
+        &mut state,
+        icp(),
+        Some(RedemptionCapConfig {
+            max_debt_bps: 0,
+            ..config()
+        }),
+    );
+    assert_eq!(remaining(&state, &icp(), NOW), 200 * E8S);
+}
+
+#[test]
+fn claims_past_the_cap_are_rejected() {
+    let mut state = fixture();
+    assert!(check_capacity(&state, &icp(), 100 * E8S, NOW).is_ok());
+    match check_capacity(&state, &icp(), 100 * E8S + 1, NOW) {
+        Err(ProtocolError::GenericError(msg)) => {
+            assert!(msg.contains("left this epoch"), "{}", msg)
+        }
+        other => panic!("expected GenericError, got {:?}", other),
+    }
+
+    // 60 icUSD redeemed: the debt dropped to 940, but the epoch's limit keeps
+    // the base it started from.
+    state
+        .vault_id_to_vaults
+        .get_mut(&1)
+        .unwrap()
+        .borrowed_icusd_amount = ICUSD::new(940 * E8S);
+    note_redeemed(&mut state, &icp(), 60 * E8S, NOW);
+    let cap = capacity(&state, &icp(), NOW + 1);
+    assert_eq!(cap.redeemed_e8s, 60 * E8S);
+    assert_eq!(cap.limit_e8s, Some(100 * E8S));
+    assert_eq!(cap.remaining_e8s, Some(40 * E8S));
+    assert!(check_capacity(&state, &icp(), 41 * E8S, NOW + 1).is_err());
+}
+
+#[test]
+fn usage_resets_each_epoch() {
+    let mut state = fixture();
+    state
+        .vault_id_to_vaults
+        .get_mut(&1)
+        .unwrap()
+        .borrowed_icusd_amount = ICUSD::new(900 * E8S);
+    note_redeemed(&mut state, &icp(), 100 * E8S, NOW);
+    assert_eq!(remaining(&state, &icp(), NOW), 0);
+
+    // The next day starts from the 900 icUSD still owed.
+    let tomorrow = NOW + DAY;
+    let cap = capacity(&state, &icp(), tomorrow);
+    assert_eq!(cap.epoch_start_ns, 101 * DAY);
+    assert_eq!(cap.redeemed_e8s, 0);
+    assert_eq!(cap.remaining_e8s, Some(90 * E8S));
+
+    // Changing the cap starts the count over.
+    apply_set_config(&mut state, icp(), Some(config()));
+    assert_eq!(remaining(&state, &icp(), NOW), 90 * E8S);
+}
+
+#[test]
+fn uncapped_collaterals_are_not_counted() {
+    let mut state = fixture();
+    apply_set_config(&mut state, icp(), None);
+    note_redeemed(&mut state, &icp(), 500 * E8S, NOW);
+    assert!(state.redemption_epoch_usage.is_empty());
+    assert_eq!(remaining(&state, &icp(), NOW), u64::MAX);
+    assert!(check_capacity(&state, &icp(), u64::MAX, NOW).is_ok());
+    let cap = capacity(&state, &icp(), NOW);
+    assert_eq!(cap.config, None);
+    assert_eq!(cap.remaining_e8s, None);
+}
+
+#[test]
+fn replay_rebuilds_the_cap_and_usage() {
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::OpenVault {
+            vault: vault(),
+            block_index: 0,
+            timestamp: None,
+        },
+        Event::SetCollateralRedemptionCap {
+            collateral_type: icp(),
+            config: Some(config()),
+        },
+        Event::RedemptionOnVaults {
+            owner: Principal::from_slice(&[2]),
+            current_icp_rate: UsdIcp::from(dec!(10)),
+            icusd_amount: ICUSD::new(70 * E8S),
+            fee_amount: ICUSD::new(0),
+            icusd_block_index: 1,
+            collateral_type: Some(icp()),
+            timestamp: Some(NOW),
+            vault_redemptions: Some(vec![VaultRedemption {
+                vault_id: 1,
+                icusd_redeemed_e8s: 60 * E8S,
+                collateral_seized: 6 * E8S,
+            }]),
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(state.redemption_caps[&icp()], config());
+    let usage = state.redemption_epoch_usage[&icp()];
+    assert_eq!(usage.epoch_start_ns, 100 * DAY);
+    assert_eq!(usage.debt_at_start_e8s, 1_000 * E8S);
+    assert_eq!(usage.redeemed_e8s, 60 * E8S);
+    assert_eq!(remaining(&state, &icp(), NOW), 40 * E8S);
+}
@@ -0,0 +1,656 @@
+//! Redemptions end to end, across vaults and collaterals: the collateral
+//! with the least healthy vault is redeemed, its vaults lowest-CR-first with
+//! a larger redemption levelling the lowest band up to the next vault, the
+//! fee is clamped to that collateral's floor and ceiling, every redemption
+//! records the raised base rate, each vault gives up the collateral its
+//! redeemed debt is worth, and the redeemer's payout settles.
+//!
+//! ICP is at $10 and a second collateral, ETH, is at $2000 with a 1%
+//! redemption fee floor. One borrower holds three 10-ICP vaults owing 50,
+//! 40 and 25 icUSD (CR 200%, 250% and 400%) and a 1-ETH vault owing 400
+//! icUSD (CR 500%); the redeemer holds 100 icUSD. ICP's fee floor is 0.5%
+//! and its ceiling 5%. Fees, interest and the redemption margin are neutral
+//! (RMR 1.0), so a redemption retires exactly its icUSD less the fee.
+
+use candid::{decode_one, encode_args, encode_one, CandidType, Deserialize, Nat, Principal};
+use pocket_ic::{PocketIc, PocketIcBuilder, WasmResult};
+use std::time::{Duration, SystemTime};
+
+use rumi_protocol_backend::numeric::{icusd_to_collateral_amount, ICUSD};
+use rumi_protocol_backend::pending_backpressure::PendingBackpressureStatus;
+use rumi_protocol_backend::state::{CollateralConfig, PriceSource, XrcAssetClass};
+use rumi_protocol_backend::vault::{CandidVault, OpenVaultSuccess, VaultArg};
+use rumi_protocol_backend::{AddCollateralArg, ProtocolError, SuccessWithFee};
+use rust_decimal::Decimal;
+
+const E8S: u64 = 100_000_000;
+const ICP_FEE: u64 = 10_000;
+const ETH_FEE: u64 = 10;
+
+// ─── ICRC-1 candid mirrors ───
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+struct Account {
+    owner: Principal,
+    subaccount: Option<[u8; 32]>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct FeatureFlags {
+    icrc2: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct ArchiveOptions {
+    num_blocks_to_archive: u64,
+    trigger_threshold: u64,
+    controller_id: Principal,
+    max_transactions_per_response: Option<u64>,
+    max_message_size_bytes: Option<u64>,
+    cycles_for_archive_creation: Option<u64>,
+    node_max_memory_size_bytes: Option<u64>,
+    more_controller_ids: Option<Vec<Principal>>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct MetadataValue {
+    #[serde(rename = "Text")]
+    text: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct InitArgs {
+    minting_account: Account,
+    fee_collector_account: Option<Account>,
+    transfer_fee: Nat,
+    decimals: Option<u8>,
+    max_memo_length: Option<u16>,
+    token_name: String,
+    token_symbol: String,
+    metadata: Vec<(String, MetadataValue)>,
+    initial_balances: Vec<(Account, Nat)>,
+    feature_flags: Option<FeatureFlags>,
+    maximum_number_of_accounts: Option<u64>,
+    accounts_overflow_trim_quantity: Option<u64>,
+    archive_options: ArchiveOptions,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+enum LedgerArg {
+    #[serde(rename = "Init")]
+    Init(InitArgs),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct ApproveArgs {
+    from_subaccount: Option<[u8; 32]>,
+    spender: Account,
+    amount: Nat,
+    expected_allowance: Option<Nat>,
+    expires_at: Option<u64>,
+    fee: Option<Nat>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct TransferArg {
+    from_subaccount: Option<[u8; 32]>,
+    to: Account,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+// ─── Backend init ───
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct ProtocolInitArg {
+    xrc_principal: Principal,
+    icusd_ledger_principal: Principal,
+    icp_ledger_principal: Principal,
+    fee_e8s: u64,
+    developer_principal: Principal,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+enum ProtocolArgVariant {
+    Init(ProtocolInitArg),
+}
+
+// ─── Wasm fixtures ───
+
+fn icrc1_ledger_wasm() -> Vec<u8> {
+    include_bytes!("../../ledger/ic-icrc1-ledger.wasm").to_vec()
+}
+
+fn protocol_wasm() -> Vec<u8> {
+    include_bytes!("../../../target/wasm32-unknown-unknown/release/rumi_protocol_backend.wasm")
+        .to_vec()
+}
+
+fn xrc_wasm() -> Vec<u8> {
+    include_bytes!("../../xrc_demo/xrc/xrc.wasm").to_vec()
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+struct MockXRC {
+    rates: Vec<(String, u64)>,
+}
+
+fn prepare_mock_xrc() -> Vec<u8> {
+    let mock = MockXRC {
+        rates: vec![
+            ("ICP/USD".to_string(), 10 * E8S),
+            ("ETH/USD".to_string(), 2_000 * E8S),
+        ],
+    };
+    encode_one(mock).expect("encode mock XRC init")
+}
+
+// ─── Helpers ───
+
+fn account(owner: Principal) -> Account {
+    Account {
+        owner,
+        subaccount: None,
+    }
+}
+
+fn deploy_icrc1_ledger(
+    pic: &PocketIc,
+    minting_account: Account,
+    transfer_fee: u64,
+    initial_balances: Vec<(Account, Nat)>,
+    symbol: &str,
+    controller: Principal,
+) -> Principal {
+    let ledger_id = pic.create_canister();
+    pic.add_cycles(ledger_id, 2_000_000_000_000);
+    let init = InitArgs {
+        minting_account,
+        fee_collector_account: None,
+        transfer_fee: Nat::from(transfer_fee),
+        decimals: Some(8),
+        max_memo_length: Some(64),
+        token_name: symbol.into(),
+        token_symbol: symbol.into(),
+        metadata: vec![],
+        initial_balances,
+        feature_flags: Some(FeatureFlags { icrc2: true }),
+        maximum_number_of_accounts: None,
+        accounts_overflow_trim_quantity: None,
+        archive_options: ArchiveOptions {
+            num_blocks_to_archive: 2000,
+            trigger_threshold: 1000,
+            controller_id: controller,
+            max_transactions_per_response: None,
+            max_message_size_bytes: None,
+            cycles_for_archive_creation: None,
+            node_max_memory_size_bytes: None,
+            more_controller_ids: None,
+        },
+    };
+    pic.install_canister(
+        ledger_id,
+        icrc1_ledger_wasm(),
+        encode_args((LedgerArg::Init(init),)).expect("encode ledger init"),
+        None,
+    );
+    ledger_id
+}
+
+fn update<T>(
+    pic: &PocketIc,
+    canister: Principal,
+    sender: Principal,
+    method: &str,
+    arg: Vec<u8>,
+) -> T
+where
+    T: CandidType + for<'a> Deserialize<'a>,
+{
+    match pic
+        .update_call(canister, sender, method, arg)
+        .unwrap_or_else(|e| panic!("{} call failed: {}", method, e))
+    {
+        WasmResult::Reply(b) => {
+            decode_one(&b).unwrap_or_else(|e| panic!("decode {}: {}", method, e))
+        }
+        WasmResult::Reject(m) => panic!("{} rejected: {}", method, m),
+    }
+}
+
+fn query<T>(pic: &PocketIc, canister: Principal, method: &str, arg: Vec<u8>) -> T
+where
+    T: CandidType + for<'a> Deserialize<'a>,
+{
+    match pic
+        .query_call(canister, Principal::anonymous(), method, arg)
+        .unwrap_or_else(|e| panic!("{} query failed: {}", method, e))
+    {
+        WasmResult::Reply(b) => {
+            decode_one(&b).unwrap_or_else(|e| panic!("decode {}: {}", method, e))
+        }
+        WasmResult::Reject(m) => panic!("{} rejected: {}", method, m),
+    }
+}
+
+fn approve(pic: &PocketIc, ledger: Principal, sender: Principal, spender: Principal, amount: u64) {
+    let args = ApproveArgs {
+        from_subaccount: None,
+        spender: account(spender),
+        amount: Nat::from(amount),
+        expected_allowance: None,
+        expires_at: None,
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    };
+    let result: Result<Nat, candid::Reserved> = update(
+        pic,
+        ledger,
+        sender,
+        "icrc2_approve",
+        encode_one(args).unwrap(),
+    );
+    result.expect("approve returned error");
+}
+
+fn transfer(pic: &PocketIc, ledger: Principal, sender: Principal, to: Principal, amount: u64) {
+    let args = TransferArg {
+        from_subaccount: None,
+        to: account(to),
+        amount: Nat::from(amount),
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    };
+    let result: Result<Nat, candid::Reserved> = update(
+        pic,
+        ledger,
+        sender,
+        "icrc1_transfer",
+        encode_one(args).unwrap(),
+    );
+    result.expect("transfer returned error");
+}
+
+fn balance_of(pic: &PocketIc, ledger: Principal, owner: Principal) -> u64 {
+    let balance: Nat = query(
+        pic,
+        ledger,
+        "icrc1_balance_of",
+        encode_one(account(owner)).unwrap(),
+    );
+    balance.0.try_into().expect("balance fits u64")
+}
+
+fn admin(f: &Fixture, method: &str, arg: Vec<u8>) {
+    let result: Result<(), ProtocolError> = update(&f.pic, f.protocol_id, f.developer, method, arg);
+    result.unwrap_or_else(|e| panic!("{} returned error: {:?}", method, e));
+}
+
+// ─── Fixture ───
+
+struct Fixture {
+    pic: PocketIc,
+    protocol_id: Principal,
+    icp_ledger: Principal,
+    eth_ledger: Principal,
+    developer: Principal,
+    borrower: Principal,
+    redeemer: Principal,
+    /// ICP vaults at CR 200%, 250% and 400%.
+    icp_vaults: [u64; 3],
+    eth_vault: u64,
+}
+
+fn open_vault(f: &Fixture, collateral: Principal, amount: u64, debt: u64) -> u64 {
+    let opened: Result<OpenVaultSuccess, ProtocolError> = update(
+        &f.pic,
+        f.protocol_id,
+        f.borrower,
+        "open_vault",
+        encode_args((amount, Some(collateral))).unwrap(),
+    );
+    let vault_id = opened.expect("open_vault returned error").vault_id;
+    let borrowed: Result<SuccessWithFee, ProtocolError> = update(
+        &f.pic,
+        f.protocol_id,
+        f.borrower,
+        "borrow_from_vault",
+        encode_args((VaultArg {
+            vault_id,
+            amount: debt,
+        },))
+        .unwrap(),
+    );
+    borrowed.expect("borrow_from_vault returned error");
+    vault_id
+}
+
+fn setup_fixture() -> Fixture {
+    let pic = PocketIcBuilder::new().with_nns_subnet().build();
+
+    let borrower = Principal::self_authenticating(b"redemption_e2e_borrower");
+    let redeemer = Principal::self_authenticating(b"redemption_e2e_redeemer");
+    let developer = Principal::self_authenticating(b"redemption_e2e_developer");
+
+    let protocol_id = pic.create_canister();
+    pic.add_cycles(protocol_id, 2_000_000_000_000);
+    pic.set_controllers(protocol_id, None, vec![Principal::anonymous(), developer])
+        .expect("set_controllers failed");
+
+    let icp_ledger = deploy_icrc1_ledger(
+        &pic,
+        account(protocol_id),
+        ICP_FEE,
+        vec![(account(borrower), Nat::from(100 * E8S))],
+        "ICP",
+        developer,
+    );
+    let icusd_ledger =
+        deploy_icrc1_ledger(&pic, account(protocol_id), 0, vec![], "icUSD", developer);
+    let eth_ledger = deploy_icrc1_ledger(
+        &pic,
+        account(Principal::management_canister()),
+        ETH_FEE,
+        vec![(account(borrower), Nat::from(10 * E8S))],
+        "ETH",
+        developer,
+    );
+
+    let xrc_id = pic.create_canister();
+    pic.add_cycles(xrc_id, 1_000_000_000_000);
+    pic.install_canister(xrc_id, xrc_wasm(), prepare_mock_xrc(), None);
+
+    pic.set_time(SystemTime::UNIX_EPOCH + Duration::from_secs(1_711_324_800));
+
+    let init = ProtocolArgVariant::Init(ProtocolInitArg {
+        fee_e8s: ICP_FEE,
+        icp_ledger_principal: icp_ledger,
+        xrc_principal: xrc_id,
+        icusd_ledger_principal: icusd_ledger,
+        developer_principal: developer,
+    });
+    pic.install_canister(
+        protocol_id,
+        protocol_wasm(),
+        encode_args((init,)).expect("encode protocol init"),
+        None,
+    );
+    pic.advance_time(Duration::from_secs(1));
+    for _ in 0..10 {
+        pic.tick();
+    }
+
+    let mut f = Fixture {
+        pic,
+        protocol_id,
+        icp_ledger,
+        eth_ledger,
+        developer,
+        borrower,
+        redeemer,
+        icp_vaults: [0; 3],
+        eth_vault: 0,
+    };
+
+    admin(
+        &f,
+        "add_collateral_token",
+        encode_args((AddCollateralArg {
+            ledger_canister_id: eth_ledger,
+            price_source: PriceSource::Xrc {
+                base_asset: "ETH".to_string(),
+                base_asset_class: XrcAssetClass::Cryptocurrency,
+                quote_asset: "USD".to_string(),
+                quote_asset_class: XrcAssetClass::FiatCurrency,
+            },
+            liquidation_ratio: 1.5,
+            borrow_threshold_ratio: 1.6,
+            liquidation_bonus: 1.1,
+            borrowing_fee: 0.0,
+            debt_ceiling: u64::MAX,
+            min_vault_debt: E8S,
+            interest_rate_apr: 0.0,
+            min_collateral_deposit: 0,
+            display_color: None,
+            redemption_fee_floor: Some(0.01),
+            redemption_fee_ceiling: Some(0.05),
+            redemption_tier: Some(1),
+        },))
+        .unwrap(),
+    );
+    admin(
+        &f,
+        "set_borrowing_fee_curve",
+        encode_args((None::<String>,)).unwrap(),
+    );
+    admin(&f, "set_borrowing_fee", encode_args((0.0f64,)).unwrap());
+    admin(
+        &f,
+        "set_rate_curve_markers",
+        encode_args((None::<Principal>, vec![(1.5f64, 1.0f64), (3.0f64, 1.0f64)])).unwrap(),
+    );
+    admin(
+        &f,
+        "set_interest_rate",
+        encode_args((icp_ledger, 0.0f64)).unwrap(),
+    );
+    admin(&f, "set_rmr_floor", encode_args((1.0f64,)).unwrap());
+    admin(
+        &f,
+        "set_collateral_redemption_fee_floor",
+        encode_args((icp_ledger, 0.005f64)).unwrap(),
+    );
+    admin(
+        &f,
+        "set_collateral_redemption_fee_ceiling",
+        encode_args((icp_ledger, 0.05f64)).unwrap(),
+    );
+
+    approve(&f.pic, icp_ledger, borrower, protocol_id, 100 * E8S);
+    approve(&f.pic, eth_ledger, borrower, protocol_id, 10 * E8S);
+    f.icp_vaults = [50, 40, 25].map(|debt| open_vault(&f, icp_ledger, 10 * E8S, debt * E8S));
+    f.eth_vault = open_vault(&f, eth_ledger, E8S, 400 * E8S);
+
+    transfer(&f.pic, icusd_ledger, borrower, redeemer, 100 * E8S);
+    approve(&f.pic, icusd_ledger, redeemer, protocol_id, 100 * E8S);
+    f
+}
+
+// ─── Queries and actions ───
+
+fn vault(f: &Fixture, vault_id: u64) -> CandidVault {
+    let vaults: Vec<CandidVault> = query(
+        &f.pic,
+        f.protocol_id,
+        "get_vaults",
+        encode_args((Some(f.borrower),)).unwrap(),
+    );
+    vaults
+        .into_iter()
+        .find(|v| v.vault_id == vault_id)
+        .unwrap_or_else(|| panic!("vault {} not found", vault_id))
+}
+
+fn collateral_config(f: &Fixture, ledger: Principal) -> CollateralConfig {
+    let config: Option<CollateralConfig> = query(
+        &f.pic,
+        f.protocol_id,
+        "get_collateral_config",
+        encode_args((ledger,)).unwrap(),
+    );
+    config.expect("collateral config")
+}
+
+/// Collateral ratio of `v` at `price` USD, both sides in e8s.
+fn cr(v: &CandidVault, price: u64) -> f64 {
+    (v.collateral_amount as f64 * price as f64) / v.borrowed_icusd_amount as f64
+}
+
+fn redeem(f: &Fixture, collateral: Principal, amount: u64) -> SuccessWithFee {
+    let result: Result<SuccessWithFee, ProtocolError> = update(
+        &f.pic,
+        f.protocol_id,
+        f.redeemer,
+        "redeem_collateral",
+        encode_args((collateral, amount)).unwrap(),
+    );
+    result.expect("redeem_collateral returned error")
+}
+
+/// Tick until the redemption payout queue is empty.
+fn settle_payouts(f: &Fixture) {
+    for _ in 0..20 {
+        let status: PendingBackpressureStatus = query(
+            &f.pic,
+            f.protocol_id,
+            "get_pending_backpressure",
+            encode_args(()).unwrap(),
+        );
+        if status.pending_redemption_transfers == 0 {
+            return;
+        }
+        f.pic.advance_time(Duration::from_secs(5));
+        f.pic.tick();
+    }
+    panic!("redemption payouts did not settle");
+}
+
+/// What a redemption took from each of `vault_ids`: (debt, collateral).
+fn deductions(before: &[CandidVault], after: &[CandidVault]) -> Vec<(u64, u64)> {
+    before
+        .iter()
+        .zip(after)
+        .map(|(b, a)| {
+            (
+                b.borrowed_icusd_amount - a.borrowed_icusd_amount,
+                b.collateral_amount - a.collateral_amount,
+            )
+        })
+        .collect()
+}
+
+/// Redeem `amount` icUSD against `collateral` and check what every vault in
+/// `vault_ids` gave up and what the redeemer received. Returns the receipt
+/// and the per-vault deductions.
+fn redeem_and_check(
+    f: &Fixture,
+    collateral: Principal,
+    price_usd: u64,
+    ledger_fee: u64,
+    vault_ids: &[u64],
+    amount: u64,
+) -> (SuccessWithFee, Vec<(u64, u64)>) {
+    let before: Vec<CandidVault> = vault_ids.iter().map(|id| vault(f, *id)).collect();
+    let balance_before = balance_of(&f.pic, collateral, f.redeemer);
+
+    let receipt = redeem(f, collateral, amount);
+    settle_payouts(f);
+
+    let after: Vec<CandidVault> = vault_ids.iter().map(|id| vault(f, *id)).collect();
+    let taken = deductions(&before, &after);
+
+    // RMR 1.0: the redemption retires exactly its icUSD less the fee.
+    let retired: u64 = taken.iter().map(|(debt, _)| debt).sum();
+    assert_eq!(retired, amount - receipt.fee_amount_paid);
+
+    // Each vault gives up its redeemed debt's worth of collateral, rounded
+    // down at each of the (at most two) water-fill steps that reached it.
+    for (debt, seized) in &taken {
+        let worth = icusd_to_collateral_amount(ICUSD::new(*debt), Decimal::from(price_usd), 8);
+        assert!(
+            *seized <= worth && worth - seized <= 1,
+            "{} seized for {} icUSD e8s worth {}",
+            seized,
+            debt,
+            worth
+        );
+    }
+
+    // The payout covers the seized collateral, up to one unit of rounding
+    // per vault, and reaches the redeemer less one ledger fee.
+    let seized: u64 = taken.iter().map(|(_, seized)| seized).sum();
+    let paid = receipt
+        .collateral_amount_received
+        .expect("collateral_amount_received");
+    assert!(
+        paid >= seized && paid - seized <= vault_ids.len() as u64,
+        "payout {} for {} seized",
+        paid,
+        seized
+    );
+    assert_eq!(
+        balance_of(&f.pic, collateral, f.redeemer) - balance_before,
+        paid - ledger_fee
+    );
+    (receipt, taken)
+}
+
+// ─── Tests ───
+
+#[test]
+fn redemptions_split_lowest_cr_first_within_fee_bounds_and_settle() {
+    let f = setup_fixture();
+    let [a, b, c] = f.icp_vaults;
+    let eth_base_rate = collateral_config(&f, f.eth_ledger).current_base_rate;
+
+    // 1 icUSD raises ICP's base rate by 0.5 × 1/115, under the 0.5% floor:
+    // the floor is charged and only the 200% vault is touched.
+    let (receipt, taken) = redeem_and_check(&f, f.icp_ledger, 10, ICP_FEE, &[a, b, c], E8S);
+    assert_eq!(receipt.fee_amount_paid, E8S / 200);
+    assert!(taken[0].0 > 0);
+    assert_eq!((taken[1], taken[2]), ((0, 0), (0, 0)));
+    let first_rate = collateral_config(&f, f.icp_ledger).current_base_rate;
+    assert!(first_rate.to_f64() > 0.004 && first_rate.to_f64() < 0.005);
+
+    // 30 icUSD lifts the base rate past the 5% ceiling, which is charged.
+    // The 200% vault is levelled up to the 250% one and the rest is split
+    // between the two; the 400% vault is untouched.
+    let (receipt, taken) = redeem_and_check(&f, f.icp_ledger, 10, ICP_FEE, &[a, b, c], 30 * E8S);
+    assert_eq!(receipt.fee_amount_paid, 30 * E8S / 20);
+    assert!(taken[0].0 > taken[1].0 && taken[1].0 > 0);
+    assert_eq!(taken[2], (0, 0));
+    let (va, vb, vc) = (vault(&f, a), vault(&f, b), vault(&f, c));
+    assert!((cr(&va, 10) - cr(&vb, 10)).abs() < 1e-4);
+    assert!(cr(&vb, 10) > 2.5 && cr(&vb, 10) < cr(&vc, 10));
+    let second_rate = collateral_config(&f, f.icp_ledger).current_base_rate;
+    assert!(second_rate > first_rate && second_rate.to_f64() > 0.05);
+
+    // Neither ICP redemption touched ETH.
+    assert_eq!(vault(&f, f.eth_vault).borrowed_icusd_amount, 400 * E8S);
+    assert_eq!(
+        collateral_config(&f, f.eth_ledger).current_base_rate,
+        eth_base_rate
+    );
+
+    // A 174% ETH vault is now the least healthy of all: the next
+    // redemption is taken from ETH, its lowest-CR vault first, priced at
+    // ETH's own 1% floor and base rate.
+    let weak_eth = open_vault(&f, f.eth_ledger, E8S, 1_150 * E8S);
+    let icp_before: Vec<CandidVault> = f.icp_vaults.iter().map(|id| vault(&f, *id)).collect();
+    let (receipt, taken) = redeem_and_check(
+        &f,
+        f.eth_ledger,
+        2_000,
+        ETH_FEE,
+        &[weak_eth, f.eth_vault],
+        2 * E8S,
+    );
+    assert_eq!(receipt.fee_amount_paid, 2 * E8S / 100);
+    assert!(taken[0].0 > 0);
+    assert_eq!(taken[1], (0, 0));
+    let icp_after: Vec<CandidVault> = f.icp_vaults.iter().map(|id| vault(&f, *id)).collect();
+    assert!(deductions(&icp_before, &icp_after)
+        .iter()
+        .all(|d| *d == (0, 0)));
+    assert!(collateral_config(&f, f.eth_ledger).current_base_rate > eth_base_rate);
+    assert_eq!(
+        collateral_config(&f, f.icp_ledger).current_base_rate,
+        second_rate
+    );
+}
@@ -0,0 +1,172 @@
+//! Vault redistribution: an unabsorbed vault's debt and collateral are
+//! split across the other vaults of its type by collateral, shares are
+//! booked once on settlement or accrual, vaults opened later take no part
+//! in earlier splits, a removed vault's unbooked share goes to the rest,
+//! only liquidatable vaults with someone to take them are redistributed,
+//! and replay rebuilds the split.
+//!
+//! ICP is at $6. Vault 1 holds 10 ICP owing 50 icUSD (CR 120%, under the
+//! liquidation ratio), vault 2 holds 30 ICP owing 60 icUSD and vault 3
+//! holds 10 ICP owing 20 icUSD.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::redistribution::{
+    apply_redistribute, check, pending, settle, PendingRedistribution,
+};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::Vault;
+
+use common::{fresh_state, icp, init_arg, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+
+fn vault(vault_id: u64, collateral_icp: u64, debt_icusd: u64) -> Vault {
+    VaultBuilder::new(vault_id)
+        .owner(Principal::from_slice(&[vault_id as u8]))
+        .collateral(collateral_icp * E8S)
+        .debt(debt_icusd * E8S)
+        .build()
+}
+
+fn vaults() -> Vec<Vault> {
+    vec![vault(1, 10, 50), vault(2, 30, 60), vault(3, 10, 20)]
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 6.0, Some(0));
+    for vault in vaults() {
+        state.open_vault(vault);
+    }
+    state
+}
+
+fn redistributed() -> State {
+    let mut state = fixture();
+    apply_redistribute(&mut state, 1).unwrap();
+    state
+}
+
+fn amounts(state: &State, vault_id: u64) -> (u64, u64) {
+    let vault = &state.vault_id_to_vaults[&vault_id];
+    (
+        vault.collateral_amount,
+        vault.borrowed_icusd_amount.to_u64(),
+    )
+}
+
+fn share(collateral: u64, debt: u64) -> PendingRedistribution {
+    PendingRedistribution { collateral, debt }
+}
+
+#[test]
+fn the_vault_is_split_by_collateral() {
+    let mut state = fixture();
+    assert_eq!(
+        apply_redistribute(&mut state, 1).unwrap(),
+        share(10 * E8S, 50 * E8S)
+    );
+    assert!(!state.vault_id_to_vaults.contains_key(&1));
+
+    assert_eq!(pending(&state, 2), share(75 * E8S / 10, 375 * E8S / 10));
+    assert_eq!(pending(&state, 3), share(25 * E8S / 10, 125 * E8S / 10));
+    // Nothing is booked yet, but the totals count it.
+    assert_eq!(amounts(&state, 2), (30 * E8S, 60 * E8S));
+    assert_eq!(state.total_collateral_for(&icp()), 50 * E8S);
+    assert_eq!(state.total_debt_for_collateral(&icp()).to_u64(), 130 * E8S);
+    assert_eq!(state.total_borrowed_icusd_amount().to_u64(), 130 * E8S);
+}
+
+#[test]
+fn a_share_is_booked_once() {
+    let mut state = redistributed();
+    assert_eq!(settle(&mut state, 2), share(75 * E8S / 10, 375 * E8S / 10));
+    assert_eq!(amounts(&state, 2), (375 * E8S / 10, 975 * E8S / 10));
+    assert_eq!(pending(&state, 2), share(0, 0));
+    assert_eq!(settle(&mut state, 2), share(0, 0));
+    assert_eq!(state.total_debt_for_collateral(&icp()).to_u64(), 130 * E8S);
+
+    // The accrual tick books every vault.
+    state.accrue_all_vault_interest(0);
+    assert_eq!(amounts(&state, 3), (125 * E8S / 10, 325 * E8S / 10));
+    let index = &state.redistribution_indexes[&icp()];
+    assert_eq!((index.pending_collateral, index.pending_debt), (0, 0));
+}
+
+#[test]
+fn later_vaults_take_no_part_in_earlier_splits() {
+    let mut state = redistributed();
+    state.open_vault(vault(4, 10, 0));
+    assert_eq!(pending(&state, 4), share(0, 0));
+
+    // Vault 3 (12.5 ICP, 32.5 icUSD once booked) goes to vault 2's stake
+    // of 30 ICP and vault 4's of 10.
+    apply_redistribute(&mut state, 3).unwrap();
+    assert_eq!(
+        pending(&state, 4),
+        share(3_125 * E8S / 1_000, 8_125 * E8S / 1_000)
+    );
+    assert_eq!(
+        pending(&state, 2),
+        share(16_875 * E8S / 1_000, 61_875 * E8S / 1_000)
+    );
+}
+
+#[test]
+fn a_removed_vaults_unbooked_share_goes_to_the_rest() {
+    let mut state = redistributed();
+    // Vault 2 leaves with its 7.5 ICP and 37.5 icUSD unbooked.
+    state.remove_vault_and_unindex(2);
+    assert_eq!(pending(&state, 3), share(10 * E8S, 50 * E8S));
+    assert_eq!(state.total_collateral_for(&icp()), 20 * E8S);
+    assert_eq!(state.total_debt_for_collateral(&icp()).to_u64(), 70 * E8S);
+}
+
+#[test]
+fn only_liquidatable_vaults_with_someone_to_take_them_are_redistributed() {
+    let state = fixture();
+    assert!(check(&state, 1).is_ok());
+    assert!(check(&state, 2).is_err());
+    assert!(check(&state, 9).is_err());
+
+    let mut alone = fresh_state();
+    set_icp_price(&mut alone, 6.0, Some(0));
+    alone.open_vault(vault(1, 10, 50));
+    assert!(check(&alone, 1).is_err());
+    assert!(apply_redistribute(&mut alone, 1).is_err());
+    assert!(alone.vault_id_to_vaults.contains_key(&1));
+}
+
+#[test]
+fn replay_rebuilds_the_split() {
+    let mut events = vec![Event::Init(init_arg())];
+    events.extend(vaults().into_iter().map(|vault| Event::OpenVault {
+        vault,
+        block_index: 0,
+        timestamp: None,
+    }));
+    events.push(Event::SetRedistributionEnabled { enabled: true });
+    events.push(Event::VaultRedistributed {
+        vault_id: 1,
+        owner: Principal::from_slice(&[1]),
+        collateral_type: icp(),
+        collateral_amount: 10 * E8S,
+        debt: ICUSD::new(50 * E8S),
+        timestamp: 0,
+    });
+    let state = replay(events.into_iter()).expect("replay");
+
+    let live = redistributed();
+    assert!(state.redistribution_enabled);
+    assert_eq!(state.redistribution_indexes, live.redistribution_indexes);
+    assert_eq!(
+        state.redistribution_snapshots,
+        live.redistribution_snapshots
+    );
+    assert_eq!(pending(&state, 2), pending(&live, 2));
+}
@@ -0,0 +1,99 @@
+//! Repay-all-and-close planning: the pull is the vault's whole debt (dust
+//! is forgiven instead), a stable-token pull rounds up to whole e6s and adds
+//! the repay fee, and every rejection happens before any funds move.
+//!
+//! Principal 1 owns one 10 ICP vault owing 50.00000050 icUSD.
+
+mod common;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::numeric::{Ratio, ICP, ICUSD};
+use rumi_protocol_backend::state::{CollateralStatus, State, DUST_DEBT_THRESHOLD};
+use rumi_protocol_backend::vault::plan_repay_all_and_close;
+use rumi_protocol_backend::{ProtocolError, StableTokenType};
+
+use common::{fresh_state, icp, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const DEBT: u64 = 50 * E8S + 50;
+
+fn owner() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    state.open_vault(
+        VaultBuilder::new(1)
+            .owner(owner())
+            .collateral(10 * E8S)
+            .debt(DEBT)
+            .build(),
+    );
+    state
+}
+
+fn set_debt(state: &mut State, debt: u64) {
+    state
+        .vault_id_to_vaults
+        .get_mut(&1)
+        .unwrap()
+        .borrowed_icusd_amount = ICUSD::new(debt);
+}
+
+#[test]
+fn icusd_pull_is_the_whole_debt() {
+    let plan = plan_repay_all_and_close(&fixture(), owner(), 1, None).unwrap();
+    assert_eq!(plan.repay, ICUSD::new(DEBT));
+    assert_eq!(plan.forgiven, ICUSD::new(0));
+    assert_eq!(plan.stable_pull_e6s, None);
+    assert_eq!(plan.collateral, ICP::new(10 * E8S));
+    assert_eq!(plan.collateral_type, icp());
+}
+
+#[test]
+fn dust_debt_is_forgiven_without_a_pull() {
+    let mut state = fixture();
+    set_debt(&mut state, DUST_DEBT_THRESHOLD);
+    let plan =
+        plan_repay_all_and_close(&state, owner(), 1, Some(&StableTokenType::CKUSDT)).unwrap();
+    assert_eq!(plan.repay, ICUSD::new(0));
+    assert_eq!(plan.forgiven, ICUSD::new(DUST_DEBT_THRESHOLD));
+    assert_eq!(plan.stable_pull_e6s, None);
+}
+
+#[test]
+fn stable_pull_rounds_up_and_adds_the_fee() {
+    let mut state = fixture();
+    state.ckusdc_enabled = true;
+    state.ckstable_repay_fee = Ratio::from(dec!(0.01));
+    let plan =
+        plan_repay_all_and_close(&state, owner(), 1, Some(&StableTokenType::CKUSDC)).unwrap();
+    // 50.00000050 icUSD needs 50.000001 ckUSDC, plus 1%.
+    assert_eq!(plan.repay, ICUSD::new(DEBT));
+    assert_eq!(plan.stable_fee_e6s, 500_000);
+    assert_eq!(plan.stable_pull_e6s, Some(50_000_001 + 500_000));
+}
+
+#[test]
+fn rejections_happen_before_the_pull() {
+    let mut state = fixture();
+    assert!(matches!(
+        plan_repay_all_and_close(&state, Principal::from_slice(&[2]), 1, None),
+        Err(ProtocolError::CallerNotOwner)
+    ));
+    assert!(plan_repay_all_and_close(&state, owner(), 2, None).is_err());
+
+    state.ckusdt_enabled = false;
+    assert!(plan_repay_all_and_close(&state, owner(), 1, Some(&StableTokenType::CKUSDT)).is_err());
+
+    // Paused collateral can be repaid but not withdrawn.
+    state.collateral_configs.get_mut(&icp()).unwrap().status = CollateralStatus::Paused;
+    assert!(plan_repay_all_and_close(&state, owner(), 1, None).is_err());
+    state.collateral_configs.get_mut(&icp()).unwrap().status = CollateralStatus::Active;
+
+    state.vault_id_to_vaults.get_mut(&1).unwrap().bot_processing = true;
+    assert!(plan_repay_all_and_close(&state, owner(), 1, None).is_err());
+}
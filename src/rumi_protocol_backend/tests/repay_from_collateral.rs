@@ -0,0 +1,181 @@
+//! Repay from collateral: a sale is sized against the vault, its DEX minimum
+//! never lets the vault's collateral ratio fall, it is capped at the debt's
+//! worth, only the owner may ask for one, and replay rebuilds the vault and
+//! counts the sale as owner activity.
+//!
+//! One 10 ICP vault at $10 owes 50 icUSD (CR 200%), the ICP ledger fee is
+//! 0.0001 ICP and an ICP/icUSD route is whitelisted.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::auto_deleverage::{AutoDeleverageConfig, AutoDeleverageRoute};
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::repay_from_collateral::{
+    apply_repay_from_collateral, plan_repay_from_collateral,
+};
+use rumi_protocol_backend::state::{Mode, State};
+use rumi_protocol_backend::vault::Vault;
+use rumi_protocol_backend::ProtocolError;
+
+use common::{fresh_state, icp, init_arg, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const FEE: u64 = 10_000;
+const NOW: u64 = 1_000_000_000_000_000_000;
+
+fn owner() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn dex() -> Principal {
+    Principal::from_slice(&[40])
+}
+
+fn vault() -> Vault {
+    VaultBuilder::new(1)
+        .owner(owner())
+        .collateral(10 * E8S)
+        .debt(50 * E8S)
+        .build()
+}
+
+fn route() -> AutoDeleverageRoute {
+    AutoDeleverageRoute {
+        collateral_type: icp(),
+        dex: dex(),
+        pool_id: "icp_icusd".to_string(),
+    }
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    state.collateral_configs.get_mut(&icp()).unwrap().ledger_fee = FEE;
+    set_icp_price(&mut state, 10.0, Some(NOW));
+    state.open_vault(vault());
+    state.auto_deleverage_routes.insert(icp(), route());
+    state
+}
+
+#[test]
+fn sale_is_sized_and_never_lowers_the_ratio() {
+    let state = fixture();
+    let plan = plan_repay_from_collateral(&state, owner(), 1, 2 * E8S, 5 * E8S, NOW).expect("plan");
+    assert_eq!(plan.amount_in, 2 * E8S);
+    assert_eq!(plan.allowance, 2 * E8S + FEE);
+    assert_eq!(plan.collateral_debit, 2 * E8S + 2 * FEE);
+    assert_eq!(plan.debt, 50 * E8S);
+    assert_eq!(plan.route, route());
+    // The owner's 5 icUSD floor is raised to the debt share of the 2.0002 ICP
+    // taken off: 50 × 2.0002 / 10.
+    assert_eq!(plan.min_icusd_out, 1_000_100_000);
+
+    // A floor above the proportional share is kept as is.
+    let plan =
+        plan_repay_from_collateral(&state, owner(), 1, 2 * E8S, 19 * E8S, NOW).expect("plan");
+    assert_eq!(plan.min_icusd_out, 19 * E8S);
+}
+
+#[test]
+fn requests_are_bounded() {
+    let state = fixture();
+    let plan = |state: &State, caller, amount, min| {
+        plan_repay_from_collateral(state, caller, 1, amount, min, NOW)
+    };
+    assert!(matches!(
+        plan(&state, Principal::from_slice(&[2]), E8S, 0),
+        Err(ProtocolError::CallerNotOwner)
+    ));
+    assert!(plan(&state, owner(), 0, 0).is_err());
+    // $60 of ICP against $50 of debt.
+    assert!(plan(&state, owner(), 6 * E8S, 0).is_err());
+    assert!(plan(&state, owner(), 5 * E8S, 0).is_ok());
+    assert!(plan(&state, owner(), E8S, 51 * E8S).is_err());
+    assert!(plan_repay_from_collateral(&state, owner(), 2, E8S, 0, NOW).is_err());
+
+    // The ledger fees come out of the vault too: at $1 the debt cap allows
+    // the whole vault, but only what is left after the fees can be sold.
+    let mut cheap = fixture();
+    cheap.collateral_configs.get_mut(&icp()).unwrap().last_price = Some(1.0);
+    assert!(plan(&cheap, owner(), 10 * E8S, 0).is_err());
+    assert!(plan(&cheap, owner(), 10 * E8S - 2 * FEE, 0).is_ok());
+
+    let mut no_route = fixture();
+    no_route.auto_deleverage_routes.clear();
+    assert!(plan(&no_route, owner(), E8S, 0).is_err());
+
+    let mut claimed = fixture();
+    claimed
+        .vault_id_to_vaults
+        .get_mut(&1)
+        .unwrap()
+        .bot_processing = true;
+    assert!(plan(&claimed, owner(), E8S, 0).is_err());
+
+    let mut read_only = fixture();
+    read_only.mode = Mode::ReadOnly;
+    assert!(plan(&read_only, owner(), E8S, 0).is_err());
+}
+
+#[test]
+fn a_sale_repays_at_most_the_debt() {
+    let mut state = fixture();
+    apply_repay_from_collateral(&mut state, 1, 2 * E8S, ICUSD::new(19 * E8S));
+    let vault = &state.vault_id_to_vaults[&1];
+    assert_eq!(vault.collateral_amount, 8 * E8S);
+    assert_eq!(vault.borrowed_icusd_amount, ICUSD::new(31 * E8S));
+
+    apply_repay_from_collateral(&mut state, 1, E8S, ICUSD::new(40 * E8S));
+    let vault = &state.vault_id_to_vaults[&1];
+    assert_eq!(vault.collateral_amount, 7 * E8S);
+    assert_eq!(vault.borrowed_icusd_amount, ICUSD::new(0));
+
+    // Unknown vault: nothing to apply.
+    assert_eq!(
+        apply_repay_from_collateral(&mut state, 9, E8S, ICUSD::new(E8S)),
+        ICUSD::new(0)
+    );
+}
+
+#[test]
+fn replay_rebuilds_the_vault_and_counts_as_activity() {
+    let config = AutoDeleverageConfig {
+        inactivity_days: 7,
+        trigger_cr_bps: 22_000,
+        target_cr_bps: 30_000,
+        max_weekly_sell_bps: 2_000,
+    };
+    let sale = Event::VaultRepaidFromCollateral {
+        vault_id: 1,
+        owner: owner(),
+        collateral_type: icp(),
+        collateral_sold: 2 * E8S,
+        icusd_repaid: ICUSD::new(19 * E8S),
+        dex: dex(),
+        timestamp: NOW + 5,
+    };
+    assert!(sale.involves_principal(&owner()));
+
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::OpenVault {
+            vault: vault(),
+            block_index: 0,
+            timestamp: None,
+        },
+        Event::SetAutoDeleverage {
+            vault_id: 1,
+            owner: owner(),
+            config: Some(config),
+            timestamp: NOW,
+        },
+        sale,
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    let vault = &state.vault_id_to_vaults[&1];
+    assert_eq!(vault.collateral_amount, 8 * E8S);
+    assert_eq!(vault.borrowed_icusd_amount, ICUSD::new(31 * E8S));
+    assert_eq!(state.auto_deleverage[&1].last_activity_ns, NOW + 5);
+}
@@ -0,0 +1,225 @@
+//! Reserve redemption stables: the list defaults to ckUSDT then ckUSDC,
+//! the first edit keeps them, entries sort by priority and only enabled
+//! ones are used, entries are validated, a redemption pays from the first
+//! token covering it or else the best-stocked one, a borrow paid from a
+//! reserve gets 1:1 less the token's fee, and replay rebuilds the list.
+//!
+//! ckUSDT and ckUSDC are configured as reserve stables, next to a third
+//! stable with 8 decimals.
+
+mod common;
+
+use common::init_arg_with;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::reserve_stables::{
+    apply_remove, apply_set, borrow_payout, configured, e8s_to_native, enabled, native_to_e8s,
+    pick, validate, ReservePayout, ReserveStable, MAX_RESERVE_STABLES,
+};
+use rumi_protocol_backend::state::State;
+
+const E8S: u64 = 100_000_000;
+
+fn icusd() -> Principal {
+    Principal::from_slice(&[9])
+}
+
+fn ckusdt() -> Principal {
+    Principal::from_slice(&[11])
+}
+
+fn ckusdc() -> Principal {
+    Principal::from_slice(&[12])
+}
+
+fn usdx() -> Principal {
+    Principal::from_slice(&[13])
+}
+
+fn fresh() -> State {
+    replay(
+        vec![Event::Init(
+            init_arg_with()
+                .icusd_ledger(icusd())
+                .ckusdt_ledger(ckusdt())
+                .ckusdc_ledger(ckusdc())
+                .build(),
+        )]
+        .into_iter(),
+    )
+    .expect("replay")
+}
+
+fn usdx_stable(priority: u32) -> ReserveStable {
+    ReserveStable {
+        ledger: usdx(),
+        symbol: "USDX".to_string(),
+        decimals: 8,
+        enabled: true,
+        priority,
+        fee_bps: Some(50),
+    }
+}
+
+fn ledgers(stables: &[ReserveStable]) -> Vec<Principal> {
+    stables.iter().map(|s| s.ledger).collect()
+}
+
+#[test]
+fn the_list_defaults_to_ckusdt_then_ckusdc() {
+    let state = fresh();
+    let stables = configured(&state);
+    assert_eq!(ledgers(&stables), vec![ckusdt(), ckusdc()]);
+    assert_eq!(stables[0].symbol, "ckUSDT");
+    assert_eq!((stables[0].decimals, stables[0].fee_bps), (6, None));
+    assert!(state.reserve_stables.is_empty());
+}
+
+#[test]
+fn the_first_edit_keeps_the_default_tokens() {
+    let mut state = fresh();
+    apply_set(&mut state, usdx_stable(0));
+    // Ties go by ledger principal.
+    assert_eq!(
+        ledgers(&configured(&state)),
+        vec![ckusdt(), usdx(), ckusdc()]
+    );
+
+    let mut ckusdc_first = configured(&state)[2].clone();
+    ckusdc_first.priority = 0;
+    apply_set(&mut state, ckusdc_first);
+    let mut ckusdt_off = configured(&state)[0].clone();
+    ckusdt_off.enabled = false;
+    apply_set(&mut state, ckusdt_off);
+    assert_eq!(ledgers(&enabled(&state)), vec![ckusdc(), usdx()]);
+    assert_eq!(configured(&state).len(), 3);
+
+    assert!(apply_remove(&mut state, ckusdc()));
+    assert!(!apply_remove(&mut state, ckusdc()));
+    assert_eq!(ledgers(&enabled(&state)), vec![usdx()]);
+
+    // Removing a default token from the unedited list keeps the other.
+    let mut removed = fresh();
+    assert!(apply_remove(&mut removed, ckusdt()));
+    assert_eq!(ledgers(&configured(&removed)), vec![ckusdc()]);
+}
+
+#[test]
+fn entries_are_validated() {
+    let state = fresh();
+    assert!(validate(&state, &usdx_stable(0)).is_ok());
+
+    let with = |f: fn(&mut ReserveStable)| {
+        let mut s = usdx_stable(0);
+        f(&mut s);
+        validate(&state, &s)
+    };
+    assert!(with(|s| s.ledger = Principal::anonymous()).is_err());
+    assert!(with(|s| s.ledger = icusd()).is_err());
+    assert!(with(|s| s.symbol = String::new()).is_err());
+    assert!(with(|s| s.decimals = 18).is_err());
+    assert!(with(|s| s.fee_bps = Some(1_001)).is_err());
+
+    let mut full = fresh();
+    for i in 0..MAX_RESERVE_STABLES as u8 - 2 {
+        let mut stable = usdx_stable(5);
+        stable.ledger = Principal::from_slice(&[100 + i]);
+        apply_set(&mut full, stable);
+    }
+    assert!(validate(&full, &usdx_stable(0)).is_err());
+    // Editing a listed token is still allowed.
+    assert!(validate(&full, &configured(&full)[0]).is_ok());
+}
+
+#[test]
+fn a_redemption_pays_from_the_first_token_covering_it() {
+    let mut state = fresh();
+    apply_set(&mut state, usdx_stable(2));
+    let candidates = enabled(&state);
+
+    // 100 icUSD: ckUSDT is short, ckUSDC covers it.
+    let balances = [50_000_000, 200_000_000, 500 * E8S];
+    assert_eq!(
+        pick(&candidates, &balances, 100 * E8S).map(|s| s.ledger),
+        Some(ckusdc())
+    );
+
+    // Nobody covers 1000 icUSD: the best-stocked one pays what it can.
+    assert_eq!(
+        pick(&candidates, &balances, 1_000 * E8S).map(|s| s.ledger),
+        Some(usdx())
+    );
+    // Equal balances go to the earlier token.
+    assert_eq!(
+        pick(&candidates, &[0, 0, 0], E8S).map(|s| s.ledger),
+        Some(ckusdt())
+    );
+    assert_eq!(pick(&[], &[], E8S), None);
+}
+
+#[test]
+fn amounts_convert_between_e8s_and_token_units() {
+    assert_eq!(e8s_to_native(123_456_789, 6), 1_234_567);
+    assert_eq!(e8s_to_native(123_456_789, 8), 123_456_789);
+    assert_eq!(native_to_e8s(1_234_567, 6), 123_456_700);
+}
+
+#[test]
+fn a_borrow_is_paid_one_to_one_less_the_reserve_fee() {
+    let state = fresh();
+    let ckusdt_stable = configured(&state)[0].clone();
+    let proceeds = ICUSD::new(100 * E8S);
+
+    // ckUSDT takes the global 0.3%, in e6s.
+    let payout = borrow_payout(proceeds, &ckusdt_stable, state.reserve_redemption_fee);
+    assert_eq!(
+        payout,
+        ReservePayout {
+            fee_icusd: ICUSD::new(30_000_000),
+            amount_native: 99_700_000,
+            fee_native: 300_000,
+        }
+    );
+    // Both transfers pay a ledger fee.
+    assert_eq!(payout.needed(10_000), 100_020_000);
+
+    // USDX has its own 0.5% and 8 decimals.
+    let payout = borrow_payout(proceeds, &usdx_stable(0), state.reserve_redemption_fee);
+    assert_eq!(payout.fee_icusd, ICUSD::new(50_000_000));
+    assert_eq!(
+        (payout.amount_native, payout.fee_native),
+        (9_950_000_000, 50_000_000)
+    );
+
+    // Without a fee there is a single transfer.
+    let free = ReserveStable {
+        fee_bps: Some(0),
+        ..ckusdt_stable
+    };
+    let payout = borrow_payout(proceeds, &free, state.reserve_redemption_fee);
+    assert_eq!((payout.amount_native, payout.fee_native), (100_000_000, 0));
+    assert_eq!(payout.needed(10_000), 100_010_000);
+}
+
+#[test]
+fn replay_rebuilds_the_list() {
+    let events = vec![
+        Event::Init(
+            init_arg_with()
+                .icusd_ledger(icusd())
+                .ckusdt_ledger(ckusdt())
+                .ckusdc_ledger(ckusdc())
+                .build(),
+        ),
+        Event::SetReserveStable {
+            stable: usdx_stable(0),
+        },
+        Event::RemoveReserveStable { ledger: ckusdt() },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(ledgers(&configured(&state)), vec![usdx(), ckusdc()]);
+    assert_eq!(state.reserve_stables[&usdx()], usdx_stable(0));
+}
@@ -0,0 +1,158 @@
+//! Scheduler bookkeeping: runs and failures are counted per task, a run
+//! dropped before it finishes counts as a trap, the next run is the earliest
+//! of the interval tick and any one-shot run, and every background timer
+//! `setup_timers` starts goes through the scheduler.
+//!
+//! A ckETH collateral is configured next to ICP.
+
+mod common;
+
+use candid::Principal;
+use ic_cdk_timers::TimerId;
+
+use rumi_protocol_backend::scheduler::{
+    begin_run, interval_tasks, is_registered, note_registered, note_scheduled, task_status,
+    ScheduledTask, FIXED_INTERVAL_TASKS, TRAPPED,
+};
+use rumi_protocol_backend::state::State;
+
+use ScheduledTask::*;
+
+use common::{icp, init_arg_with};
+
+const SEC: u64 = 1_000_000_000;
+
+fn cketh() -> Principal {
+    Principal::from_slice(&[11])
+}
+
+fn fixture() -> State {
+    let mut state = init_arg_with()
+        .icusd_ledger(Principal::from_slice(&[1]))
+        .state();
+    let mut config = state.collateral_configs[&icp()].clone();
+    config.ledger_canister_id = cketh();
+    state.collateral_configs.insert(cketh(), config);
+    state
+}
+
+/// The body of `fn name` in `source`, up to its closing brace.
+fn fn_body<'a>(source: &'a str, name: &str) -> &'a str {
+    let start = source
+        .find(&format!("fn {}(", name))
+        .unwrap_or_else(|| panic!("fn {} not found", name));
+    let len = source[start..].find("\n}\n").expect("closing brace");
+    &source[start..start + len]
+}
+
+#[test]
+fn runs_and_failures_are_counted() {
+    begin_run(VaultCheck, 10 * SEC, None).finish(11 * SEC, Ok(()));
+    begin_run(VaultCheck, 20 * SEC, None).finish(22 * SEC, Err("boom".to_string()));
+    begin_run(VaultCheck, 30 * SEC, None).finish(31 * SEC, Ok(()));
+
+    let status = task_status(VaultCheck).expect("status");
+    assert_eq!(status.runs, 3);
+    assert_eq!(status.errors, 1);
+    assert_eq!(status.last_error.as_deref(), Some("boom"));
+    assert_eq!(status.last_error_ns, Some(20 * SEC));
+    assert_eq!(status.last_run_ns, Some(30 * SEC));
+    assert_eq!(status.last_finished_ns, Some(31 * SEC));
+    assert_eq!(status.in_flight, 0);
+}
+
+#[test]
+fn a_dropped_run_counts_as_trapped() {
+    let run = begin_run(InterestTreasury, 10 * SEC, None);
+    assert_eq!(task_status(InterestTreasury).unwrap().in_flight, 1);
+    drop(run);
+
+    let status = task_status(InterestTreasury).unwrap();
+    assert_eq!(status.in_flight, 0);
+    assert_eq!((status.runs, status.errors), (1, 1));
+    assert_eq!(status.last_error.as_deref(), Some(TRAPPED));
+    assert_eq!(status.last_finished_ns, None);
+}
+
+#[test]
+fn the_next_run_is_the_earliest_scheduled() {
+    assert!(!is_registered(ProtocolSnapshot));
+    assert_eq!(
+        note_registered(ProtocolSnapshot, TimerId::default(), 3600, 0),
+        None
+    );
+    assert!(is_registered(ProtocolSnapshot));
+    note_scheduled(ProtocolSnapshot, 5 * SEC);
+    let status = task_status(ProtocolSnapshot).unwrap();
+    assert_eq!(status.interval_secs, Some(3600));
+    assert_eq!(status.next_run_ns, Some(5 * SEC));
+
+    // The one-shot run is no longer pending once it starts.
+    begin_run(ProtocolSnapshot, 5 * SEC, Some(5 * SEC)).finish(5 * SEC, Ok(()));
+    assert_eq!(
+        task_status(ProtocolSnapshot).unwrap().next_run_ns,
+        Some(3600 * SEC)
+    );
+    begin_run(ProtocolSnapshot, 3600 * SEC, None).finish(3600 * SEC, Ok(()));
+    assert_eq!(
+        task_status(ProtocolSnapshot).unwrap().next_run_ns,
+        Some(7200 * SEC)
+    );
+
+    // Re-registering hands back the timer to clear and keeps the counters.
+    assert!(note_registered(ProtocolSnapshot, TimerId::default(), 60, 7200 * SEC).is_some());
+    let status = task_status(ProtocolSnapshot).unwrap();
+    assert_eq!(status.interval_secs, Some(60));
+    assert_eq!(status.next_run_ns, Some(7260 * SEC));
+    assert_eq!(status.runs, 2);
+
+    // A task only scheduled one-shot has no interval.
+    note_scheduled(PendingTransfers, 2 * SEC);
+    let status = task_status(PendingTransfers).unwrap();
+    assert_eq!(status.interval_secs, None);
+    assert!(!is_registered(PendingTransfers));
+}
+
+#[test]
+fn every_background_timer_goes_through_the_scheduler() {
+    let tasks = interval_tasks(&fixture());
+    assert_eq!(tasks.len(), FIXED_INTERVAL_TASKS.len() + 1);
+    assert!(tasks.contains(&CollateralPriceFetch(cketh())));
+    assert!(!tasks.contains(&CollateralPriceFetch(icp())));
+
+    let main_source = include_str!("../src/main.rs");
+    for name in [
+        "setup_timers",
+        "register_xrc_fetch_timer",
+        "register_interest_treasury_timer",
+        "register_vault_check_timer",
+        "register_settlement_timer",
+        "register_observer_timer",
+        "register_chain_interest_timer",
+        "register_chain_vault_gc_timer",
+    ] {
+        assert!(
+            !fn_body(main_source, name).contains("ic_cdk_timers::"),
+            "{} sets a timer outside the scheduler",
+            name
+        );
+    }
+    for (source, name) in [
+        (
+            include_str!("../src/xrc.rs"),
+            "register_collateral_price_timer",
+        ),
+        (
+            include_str!("../src/guard_metrics.rs"),
+            "setup_resolve_timer",
+        ),
+        (include_str!("../src/loyalty.rs"), "setup_checkpoint_timer"),
+        (include_str!("../src/dust_vaults.rs"), "setup_sweep_timer"),
+    ] {
+        assert!(
+            !fn_body(source, name).contains("ic_cdk_timers::"),
+            "{} sets a timer outside the scheduler",
+            name
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Self-liquidation: an owner may close a vault below its minimum collateral
+//! ratio by selling the debt's worth of collateral plus slippage, the DEX
+//! must return the whole debt, and vaults that are healthy or underwater are
+//! turned away.
+//!
+//! One 10 ICP vault at $10 owes 70 icUSD (CR ~143%, under the 150% minimum
+//! ratio). The ICP ledger fee is 0.0001 ICP and an ICP/icUSD route is
+//! whitelisted.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::auto_deleverage::AutoDeleverageRoute;
+use rumi_protocol_backend::self_liquidation::plan_self_liquidation;
+use rumi_protocol_backend::state::{Mode, State};
+use rumi_protocol_backend::ProtocolError;
+
+use common::{fresh_state, icp, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const FEE: u64 = 10_000;
+const NOW: u64 = 1_000_000_000_000_000_000;
+
+fn owner() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn route() -> AutoDeleverageRoute {
+    AutoDeleverageRoute {
+        collateral_type: icp(),
+        dex: Principal::from_slice(&[40]),
+        pool_id: "icp_icusd".to_string(),
+    }
+}
+
+fn fixture(debt_e8s: u64) -> State {
+    let mut state = fresh_state();
+    state.collateral_configs.get_mut(&icp()).unwrap().ledger_fee = FEE;
+    set_icp_price(&mut state, 10.0, Some(NOW));
+    state.open_vault(
+        VaultBuilder::new(1)
+            .owner(owner())
+            .collateral(10 * E8S)
+            .debt(debt_e8s)
+            .build(),
+    );
+    state.auto_deleverage_routes.insert(icp(), route());
+    state
+}
+
+#[test]
+fn the_sale_covers_the_debt_with_slippage() {
+    let state = fixture(70 * E8S);
+    let plan = plan_self_liquidation(&state, owner(), 1, NOW).expect("plan");
+    // 70.7 icUSD (debt + 1%) of ICP, plus one unit against rounding.
+    assert_eq!(plan.amount_in, 707_000_001);
+    assert_eq!(plan.allowance, 707_000_001 + FEE);
+    assert_eq!(plan.collateral_debit, 707_000_001 + 2 * FEE);
+    assert_eq!(plan.debt, 70 * E8S);
+    assert_eq!(plan.min_icusd_out, 70 * E8S);
+    assert_eq!(plan.route, route());
+}
+
+#[test]
+fn only_vaults_below_the_minimum_ratio_qualify() {
+    // CR 200%: the owner can repay and close as usual.
+    assert!(plan_self_liquidation(&fixture(50 * E8S), owner(), 1, NOW).is_err());
+    assert!(plan_self_liquidation(&fixture(0), owner(), 1, NOW).is_err());
+    // The least debt that puts $100 of ICP below 150%.
+    let at_minimum = 100 * E8S * 2 / 3 + 1;
+    assert!(plan_self_liquidation(&fixture(at_minimum - 1), owner(), 1, NOW).is_err());
+    assert!(plan_self_liquidation(&fixture(at_minimum), owner(), 1, NOW).is_ok());
+}
+
+#[test]
+fn an_underwater_vault_is_left_to_liquidation() {
+    // $100 of ICP cannot repay 110 icUSD.
+    assert!(plan_self_liquidation(&fixture(110 * E8S), owner(), 1, NOW).is_err());
+    // Nor 99.999 once the ledger fees are set aside.
+    assert!(plan_self_liquidation(&fixture(99_999 * E8S / 1_000), owner(), 1, NOW).is_err());
+
+    // Close to water, the slippage is cut to what the vault can sell.
+    let plan = plan_self_liquidation(&fixture(9_995 * E8S / 100), owner(), 1, NOW).expect("plan");
+    assert_eq!(plan.amount_in, 10 * E8S - 2 * FEE);
+    assert_eq!(plan.collateral_debit, 10 * E8S);
+    assert_eq!(plan.min_icusd_out, 9_995 * E8S / 100);
+}
+
+#[test]
+fn requests_are_checked() {
+    let state = fixture(70 * E8S);
+    assert!(matches!(
+        plan_self_liquidation(&state, Principal::from_slice(&[2]), 1, NOW),
+        Err(ProtocolError::CallerNotOwner)
+    ));
+    assert!(plan_self_liquidation(&state, owner(), 2, NOW).is_err());
+
+    let mut no_route = fixture(70 * E8S);
+    no_route.auto_deleverage_routes.clear();
+    assert!(plan_self_liquidation(&no_route, owner(), 1, NOW).is_err());
+
+    let mut no_price = fixture(70 * E8S);
+    no_price
+        .collateral_configs
+        .get_mut(&icp())
+        .unwrap()
+        .last_price = None;
+    assert!(plan_self_liquidation(&no_price, owner(), 1, NOW).is_err());
+
+    let mut claimed = fixture(70 * E8S);
+    claimed
+        .vault_id_to_vaults
+        .get_mut(&1)
+        .unwrap()
+        .bot_processing = true;
+    assert!(plan_self_liquidation(&claimed, owner(), 1, NOW).is_err());
+
+    let mut read_only = fixture(70 * E8S);
+    read_only.mode = Mode::ReadOnly;
+    assert!(plan_self_liquidation(&read_only, owner(), 1, NOW).is_err());
+}
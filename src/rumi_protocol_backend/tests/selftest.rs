@@ -0,0 +1,103 @@
+//! Startup self-test: every ledger is queried once, only collaterals still
+//! priced get a price check, the invariant checks report a broken index
+//! instead of trapping, and one failed check fails the report.
+//!
+//! Besides ICP, ckETH is configured as a deprecated collateral, and ckUSDT
+//! as both a reserve stable and a collateral.
+
+mod common;
+
+use std::collections::BTreeSet;
+
+use candid::Principal;
+
+use rumi_protocol_backend::selftest::{
+    build_report, collaterals_to_price, invariant_checks, ledgers_to_check, SelftestCheck,
+    SelftestCheckKind,
+};
+use rumi_protocol_backend::state::{CollateralStatus, State};
+
+use common::{icp, init_arg_with};
+
+fn icusd() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn cketh() -> Principal {
+    Principal::from_slice(&[11])
+}
+
+fn ckusdt() -> Principal {
+    Principal::from_slice(&[12])
+}
+
+fn fixture() -> State {
+    let mut state = init_arg_with()
+        .icusd_ledger(icusd())
+        .ckusdt_ledger(ckusdt())
+        .state();
+    for (ledger, status) in [
+        (cketh(), CollateralStatus::Deprecated),
+        (ckusdt(), CollateralStatus::Active),
+    ] {
+        let mut config = state.collateral_configs[&icp()].clone();
+        config.ledger_canister_id = ledger;
+        config.status = status;
+        state.collateral_configs.insert(ledger, config);
+    }
+    state
+}
+
+fn check(error: Option<&str>) -> SelftestCheck {
+    SelftestCheck {
+        kind: SelftestCheckKind::Timer,
+        name: "vault_check timer".to_string(),
+        target: None,
+        error: error.map(str::to_string),
+    }
+}
+
+#[test]
+fn every_ledger_is_queried_once() {
+    let ledgers = ledgers_to_check(&fixture());
+    assert_eq!(ledgers[0], ("icUSD ledger".to_string(), icusd()));
+    let unique: BTreeSet<Principal> = ledgers.iter().map(|(_, ledger)| *ledger).collect();
+    assert_eq!(unique.len(), ledgers.len());
+    assert_eq!(unique, BTreeSet::from([icusd(), icp(), cketh(), ckusdt()]));
+}
+
+#[test]
+fn only_priced_collaterals_get_a_price_check() {
+    let priced = collaterals_to_price(&fixture());
+    assert!(priced.contains(&icp()));
+    assert!(priced.contains(&ckusdt()));
+    assert!(!priced.contains(&cketh()));
+}
+
+#[test]
+fn a_broken_index_fails_the_invariant_check() {
+    let mut state = fixture();
+    assert!(invariant_checks(&state).iter().all(SelftestCheck::passed));
+
+    // A vault id owned by someone but missing from the vault map.
+    state
+        .principal_to_vault_ids
+        .entry(Principal::from_slice(&[30]))
+        .or_default()
+        .insert(99);
+    let checks = invariant_checks(&state);
+    assert_eq!(checks[0].kind, SelftestCheckKind::Invariants);
+    assert!(!checks[0].passed());
+    assert!(checks[1].passed());
+}
+
+#[test]
+fn one_failure_fails_the_report() {
+    let report = build_report(10, 20, vec![check(None), check(None)]);
+    assert!(report.passed);
+
+    let report = build_report(10, 20, vec![check(None), check(Some("not registered"))]);
+    assert!(!report.passed);
+    assert_eq!(report.checks.len(), 2);
+    assert_eq!((report.started_at_ns, report.finished_at_ns), (10, 20));
+}
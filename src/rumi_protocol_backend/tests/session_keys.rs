@@ -0,0 +1,209 @@
+//! Scoped session keys: registration rules, scope and expiry enforcement in
+//! `authorize_vault_op`, the lifetime repay limit (reserve / release), and
+//! replay of the session-key event stream.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::session_keys::{
+    apply_register, apply_revoke, apply_session_use, authorize_vault_op, release_repay_allowance,
+    reserve_repay_allowance, validate_register_arg, RegisterSessionKeyArg, SessionScope,
+    MAX_SESSION_KEY_DURATION_NS,
+};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::Vault;
+use rumi_protocol_backend::ProtocolError;
+
+use common::{fresh_state, init_arg, VaultBuilder};
+
+const NOW_NS: u64 = 1_000;
+const EXPIRES_NS: u64 = 2_000;
+
+fn owner() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn bot() -> Principal {
+    Principal::from_slice(&[2])
+}
+
+fn vault_of(owner: Principal) -> Vault {
+    VaultBuilder::new(1)
+        .owner(owner)
+        .collateral(100_000_000)
+        .debt(100_000_000)
+        .build()
+}
+
+fn repay_only(repay_limit_e8s: Option<u64>) -> RegisterSessionKeyArg {
+    RegisterSessionKeyArg {
+        session_principal: bot(),
+        scopes: vec![SessionScope::Repay],
+        expires_at_ns: EXPIRES_NS,
+        repay_limit_e8s,
+    }
+}
+
+fn state_with_key(arg: RegisterSessionKeyArg) -> State {
+    let mut state = fresh_state();
+    apply_register(&mut state, owner(), arg, NOW_NS);
+    state
+}
+
+#[test]
+fn register_arg_validation() {
+    let state = fresh_state();
+    assert!(validate_register_arg(&state, owner(), &repay_only(None), NOW_NS).is_ok());
+
+    let mut own = repay_only(None);
+    own.session_principal = owner();
+    assert!(validate_register_arg(&state, owner(), &own, NOW_NS).is_err());
+
+    let mut no_scopes = repay_only(None);
+    no_scopes.scopes.clear();
+    assert!(validate_register_arg(&state, owner(), &no_scopes, NOW_NS).is_err());
+
+    let mut too_long = repay_only(None);
+    too_long.expires_at_ns = NOW_NS + MAX_SESSION_KEY_DURATION_NS + 1;
+    assert!(validate_register_arg(&state, owner(), &too_long, NOW_NS).is_err());
+
+    assert!(validate_register_arg(&state, owner(), &repay_only(None), EXPIRES_NS).is_err());
+    assert!(validate_register_arg(&state, owner(), &repay_only(Some(0)), NOW_NS).is_err());
+}
+
+#[test]
+fn live_key_cannot_be_claimed_by_another_owner() {
+    let state = state_with_key(repay_only(None));
+    let other_owner = Principal::from_slice(&[3]);
+    assert!(validate_register_arg(&state, other_owner, &repay_only(None), NOW_NS).is_err());
+    // Once expired the principal is free again.
+    let mut later = repay_only(None);
+    later.expires_at_ns = EXPIRES_NS + 1_000;
+    assert!(validate_register_arg(&state, other_owner, &later, EXPIRES_NS).is_ok());
+}
+
+#[test]
+fn owner_always_authorized() {
+    let state = fresh_state();
+    let vault = vault_of(owner());
+    for scope in [SessionScope::Repay, SessionScope::AddMargin] {
+        assert!(matches!(
+            authorize_vault_op(&state, owner(), &vault, scope, NOW_NS),
+            Ok(None)
+        ));
+    }
+}
+
+#[test]
+fn session_key_limited_to_its_scopes_and_owner() {
+    let state = state_with_key(repay_only(None));
+
+    let session = authorize_vault_op(
+        &state,
+        bot(),
+        &vault_of(owner()),
+        SessionScope::Repay,
+        NOW_NS,
+    );
+    assert_eq!(session.ok(), Some(Some(bot())));
+    assert!(matches!(
+        authorize_vault_op(
+            &state,
+            bot(),
+            &vault_of(owner()),
+            SessionScope::AddMargin,
+            NOW_NS
+        ),
+        Err(ProtocolError::GenericError(_))
+    ));
+    let stranger_vault = vault_of(Principal::from_slice(&[3]));
+    assert!(matches!(
+        authorize_vault_op(&state, bot(), &stranger_vault, SessionScope::Repay, NOW_NS),
+        Err(ProtocolError::CallerNotOwner)
+    ));
+}
+
+#[test]
+fn expired_and_revoked_keys_are_rejected() {
+    let mut state = state_with_key(repay_only(None));
+    let vault = vault_of(owner());
+
+    assert!(authorize_vault_op(&state, bot(), &vault, SessionScope::Repay, EXPIRES_NS).is_err());
+
+    assert!(apply_revoke(&mut state, Principal::from_slice(&[3]), bot()).is_err());
+    apply_revoke(&mut state, owner(), bot()).unwrap();
+    assert!(matches!(
+        authorize_vault_op(&state, bot(), &vault, SessionScope::Repay, NOW_NS),
+        Err(ProtocolError::CallerNotOwner)
+    ));
+}
+
+#[test]
+fn repay_limit_is_reserved_and_released() {
+    let mut state = state_with_key(repay_only(Some(100)));
+
+    reserve_repay_allowance(&mut state, bot(), 60).unwrap();
+    assert!(
+        reserve_repay_allowance(&mut state, bot(), 50).is_err(),
+        "in-flight reservation counts against the limit"
+    );
+    release_repay_allowance(&mut state, bot(), 60);
+    reserve_repay_allowance(&mut state, bot(), 100).unwrap();
+    assert_eq!(state.session_keys[&bot()].remaining_repay_e8s(), Some(0));
+}
+
+#[test]
+fn register_prunes_owner_expired_keys() {
+    let mut state = state_with_key(repay_only(None));
+    let mut second = repay_only(None);
+    second.session_principal = Principal::from_slice(&[4]);
+    second.expires_at_ns = EXPIRES_NS + 1_000;
+    apply_register(&mut state, owner(), second, EXPIRES_NS);
+
+    assert!(!state.session_keys.contains_key(&bot()));
+    assert_eq!(state.session_keys.len(), 1);
+}
+
+#[test]
+fn replay_reconstructs_session_keys() {
+    let mut live = state_with_key(repay_only(Some(1_000)));
+    reserve_repay_allowance(&mut live, bot(), 400).unwrap();
+    apply_session_use(&mut live, bot(), SessionScope::Repay, 400, true);
+
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::SessionKeyRegistered {
+            owner: owner(),
+            arg: repay_only(Some(1_000)),
+            timestamp: NOW_NS,
+        },
+        Event::SessionKeyUsed {
+            session_principal: bot(),
+            vault_id: 1,
+            scope: SessionScope::Repay,
+            amount_e8s: 400,
+            timestamp: NOW_NS,
+        },
+        Event::SessionKeyUsed {
+            session_principal: bot(),
+            vault_id: 1,
+            scope: SessionScope::AddMargin,
+            amount_e8s: 5_000,
+            timestamp: NOW_NS,
+        },
+    ];
+    let replayed = replay(events.clone().into_iter()).expect("replay");
+    assert_eq!(replayed.session_keys, live.session_keys);
+    assert_eq!(replayed.session_keys[&bot()].repaid_e8s, 400);
+
+    let mut with_revoke = events;
+    with_revoke.push(Event::SessionKeyRevoked {
+        owner: owner(),
+        session_principal: bot(),
+        timestamp: NOW_NS,
+    });
+    let replayed = replay(with_revoke.into_iter()).expect("replay");
+    assert!(replayed.session_keys.is_empty());
+}
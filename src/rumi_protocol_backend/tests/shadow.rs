@@ -0,0 +1,185 @@
+//! Shadow mode: a shadowed component decides without acting and its
+//! decisions are noted once, stamped on flush and kept newest first; admin
+//! overrides are never shadowed, the liquidation and price-deviation
+//! breakers only note their trips, and the config replays.
+//!
+//! ICP was last accepted at $10.00 at `T0`, with the protocol in
+//! GeneralAvailability.
+
+mod common;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::shadow::{
+    decisions, flush, note, ShadowAction, ShadowComponent, ShadowConfig, MAX_SHADOW_DECISIONS,
+};
+use rumi_protocol_backend::state::{record_recent_liquidation, Mode, ModeTransitionReason, State};
+use rumi_protocol_backend::xrc::{
+    apply_price_deviation_breaker, check_price_deviation_at, PriceDeviationBreaker,
+};
+
+use common::{fresh_state, icp, init_arg, set_icp_price};
+
+const MINUTE: u64 = 60 * 1_000_000_000;
+const T0: u64 = 1_700_000_000 * 1_000_000_000;
+
+fn fixture(shadow_config: ShadowConfig) -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 10.0, Some(T0));
+    state.mode = Mode::GeneralAvailability;
+    state.shadow_config = shadow_config;
+    state
+}
+
+fn all_shadowed() -> ShadowConfig {
+    ShadowConfig {
+        mode_machine: true,
+        circuit_breakers: true,
+        recovery_routing: true,
+    }
+}
+
+#[test]
+fn a_shadowed_transition_is_noted_once_and_not_applied() {
+    let mut state = fixture(all_shadowed());
+    for _ in 0..2 {
+        let changed = state
+            .transition_mode(
+                Mode::GeneralAvailability,
+                Mode::Recovery,
+                ModeTransitionReason::CollateralRatio,
+            )
+            .unwrap();
+        assert!(!changed);
+    }
+    assert_eq!(state.mode, Mode::GeneralAvailability);
+    assert!(state.pending_mode_transitions.is_empty());
+    assert_eq!(state.pending_shadow_actions.len(), 1);
+
+    flush(&mut state, T0);
+    assert!(state.pending_shadow_actions.is_empty());
+    let logged = decisions(&state, None, 10);
+    assert_eq!(logged.len(), 1);
+    assert_eq!(logged[0].component, ShadowComponent::ModeMachine);
+    assert_eq!(logged[0].timestamp, T0);
+    assert_eq!(
+        logged[0].action,
+        ShadowAction::ModeTransition {
+            from: Mode::GeneralAvailability,
+            to: Mode::Recovery,
+            reason: ModeTransitionReason::CollateralRatio,
+        }
+    );
+
+    // Repeats are also recognized against flushed decisions.
+    state.enter_read_only(ModeTransitionReason::Insolvency);
+    state.enter_read_only(ModeTransitionReason::Insolvency);
+    assert_eq!(state.pending_shadow_actions.len(), 1);
+}
+
+#[test]
+fn admin_overrides_are_never_shadowed() {
+    let mut state = fixture(all_shadowed());
+    assert!(state.enter_read_only(ModeTransitionReason::AdminOverride));
+    assert_eq!(state.mode, Mode::ReadOnly);
+    assert!(state.pending_shadow_actions.is_empty());
+}
+
+#[test]
+fn the_liquidation_breaker_only_notes_its_trip() {
+    let mut state = fixture(all_shadowed());
+    state.breaker_window_ns = 10 * MINUTE;
+    state.breaker_window_debt_ceiling_e8s = 1_000;
+
+    assert!(!record_recent_liquidation(&mut state, 600, T0));
+    assert!(!record_recent_liquidation(&mut state, 600, T0 + 1));
+    assert!(!record_recent_liquidation(&mut state, 600, T0 + 2));
+    assert!(!state.liquidation_breaker_tripped);
+    let logged = decisions(&state, Some(ShadowComponent::CircuitBreakers), 10);
+    assert_eq!(
+        logged,
+        decisions(&state, None, 10),
+        "only the breaker decided"
+    );
+    assert_eq!(logged.len(), 1);
+    assert_eq!(
+        logged[0].action,
+        ShadowAction::TripLiquidationBreaker {
+            windowed_total_e8s: 1_200,
+            ceiling_e8s: 1_000,
+        }
+    );
+
+    // Enforced again, the breaker latches.
+    state.shadow_config.circuit_breakers = false;
+    assert!(record_recent_liquidation(&mut state, 600, T0 + 3));
+    assert!(state.liquidation_breaker_tripped);
+}
+
+#[test]
+fn a_shadowed_price_deviation_lets_the_sample_through() {
+    let mut state = fixture(all_shadowed());
+    apply_price_deviation_breaker(
+        &mut state,
+        Some(PriceDeviationBreaker {
+            max_deviation_bps: 1_000,
+            window_ns: 10 * MINUTE,
+            enter_recovery: true,
+        }),
+    );
+
+    let (accepted, event) =
+        check_price_deviation_at(&mut state, &icp(), 7.0, T0 + MINUTE, T0 + MINUTE);
+    assert!(accepted);
+    assert_eq!(event, None);
+    assert_eq!(state.mode, Mode::GeneralAvailability);
+    assert!(state.price_deviation_holds.is_empty());
+
+    let logged = decisions(&state, Some(ShadowComponent::CircuitBreakers), 10);
+    assert_eq!(logged.len(), 1);
+    assert_eq!(logged[0].timestamp, T0 + MINUTE);
+    assert!(matches!(
+        logged[0].action,
+        ShadowAction::RejectPrice {
+            deviation_bps: 3_000,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn decisions_are_capped_and_filtered() {
+    let mut state = fixture(all_shadowed());
+    for i in 0..=MAX_SHADOW_DECISIONS as u64 {
+        note(
+            &mut state,
+            ShadowAction::ReserveForPool {
+                to_pool: vec![i],
+                overflow: vec![],
+            },
+        );
+        flush(&mut state, T0 + i);
+    }
+    assert_eq!(state.shadow_decisions.len(), MAX_SHADOW_DECISIONS);
+
+    let newest = decisions(&state, Some(ShadowComponent::RecoveryRouting), 2);
+    assert_eq!(newest.len(), 2);
+    assert_eq!(newest[0].timestamp, T0 + MAX_SHADOW_DECISIONS as u64);
+    assert_eq!(newest[1].timestamp, T0 + MAX_SHADOW_DECISIONS as u64 - 1);
+    assert!(decisions(&state, Some(ShadowComponent::ModeMachine), 10).is_empty());
+}
+
+#[test]
+fn the_config_replays() {
+    let state = replay(
+        vec![
+            Event::Init(init_arg()),
+            Event::SetShadowConfig {
+                config: all_shadowed(),
+            },
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    assert_eq!(state.shadow_config, all_shadowed());
+    assert!(state.shadow_decisions.is_empty());
+}
@@ -0,0 +1,156 @@
+//! Per-endpoint SLOs: calls are counted and measured, over-budget calls and
+//! error-rate windows are breaches reported once per window, thresholds
+//! fall back from the endpoint to the default, and only failures the
+//! protocol caused count against the error rate.
+//!
+//! Every call measured is a `borrow_from_vault`.
+
+mod common;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::parameter_journal::parameter_change;
+use rumi_protocol_backend::slo::{
+    endpoint_stats, is_service_error, record_call, thresholds_for, CallOutcome, SloBreachKind,
+    SloThresholds, DEFAULT_SLO_THRESHOLDS, SLO_WINDOW_NANOS,
+};
+use rumi_protocol_backend::ProtocolError;
+
+use common::init_arg;
+
+const ENDPOINT: &str = "borrow_from_vault";
+const MS: u64 = 1_000_000;
+
+fn thresholds() -> SloThresholds {
+    SloThresholds {
+        max_latency_ms: 1_000,
+        max_instructions: 1_000_000,
+        max_error_rate_bps: 2_500,
+        min_calls: 4,
+    }
+}
+
+fn call(latency_ms: u64, error: Option<bool>) -> CallOutcome {
+    CallOutcome {
+        latency_ns: latency_ms * MS,
+        instructions: 1_000,
+        error,
+    }
+}
+
+#[test]
+fn calls_are_counted_and_measured() {
+    assert!(record_call(&thresholds(), ENDPOINT, call(200, None), 10).is_empty());
+    assert!(record_call(&thresholds(), ENDPOINT, call(400, Some(false)), 20).is_empty());
+
+    let stats = endpoint_stats()[ENDPOINT].clone();
+    assert_eq!((stats.calls, stats.errors, stats.service_errors), (2, 1, 0));
+    assert_eq!(stats.total_latency_ns, 600 * MS);
+    assert_eq!(stats.max_latency_ns, 400 * MS);
+    assert_eq!(stats.total_instructions, 2_000);
+    assert_eq!(stats.breaches, 0);
+}
+
+#[test]
+fn a_breach_is_reported_once_per_window() {
+    let slow = record_call(&thresholds(), ENDPOINT, call(1_500, None), 10);
+    assert_eq!(slow.len(), 1);
+    assert_eq!(slow[0].kind, SloBreachKind::Latency);
+    assert_eq!((slow[0].observed, slow[0].threshold), (1_500, 1_000));
+
+    // Still counted, no longer reported.
+    assert!(record_call(&thresholds(), ENDPOINT, call(2_000, None), 20).is_empty());
+    assert_eq!(endpoint_stats()[ENDPOINT].breaches, 2);
+
+    let next_window = record_call(
+        &thresholds(),
+        ENDPOINT,
+        call(2_000, None),
+        SLO_WINDOW_NANOS + 20,
+    );
+    assert_eq!(next_window.len(), 1);
+
+    // A disabled check never breaches.
+    let off = SloThresholds {
+        max_latency_ms: 0,
+        ..thresholds()
+    };
+    assert!(record_call(&off, "repay_to_vault", call(60_000, None), 10).is_empty());
+}
+
+#[test]
+fn the_error_rate_is_judged_once_the_window_has_enough_calls() {
+    // 2 service errors in 3 calls is over 25%, but the window is too small.
+    assert!(record_call(&thresholds(), ENDPOINT, call(10, Some(true)), 10).is_empty());
+    assert!(record_call(&thresholds(), ENDPOINT, call(10, Some(true)), 20).is_empty());
+    assert!(record_call(&thresholds(), ENDPOINT, call(10, None), 30).is_empty());
+
+    let breaches = record_call(&thresholds(), ENDPOINT, call(10, None), 40);
+    assert_eq!(breaches.len(), 1);
+    assert_eq!(breaches[0].kind, SloBreachKind::ErrorRate);
+    assert_eq!(breaches[0].observed, 5_000);
+
+    // Rejected requests do not count against the rate.
+    assert!(record_call(&thresholds(), "repay_to_vault", call(10, Some(false)), 10).is_empty());
+    for at in 20..25 {
+        assert!(record_call(&thresholds(), "repay_to_vault", call(10, Some(false)), at).is_empty());
+    }
+}
+
+#[test]
+fn thresholds_fall_back_to_the_default_and_replay() {
+    let state = replay(vec![Event::Init(init_arg())].into_iter()).expect("replay");
+    assert_eq!(thresholds_for(&state, ENDPOINT), DEFAULT_SLO_THRESHOLDS);
+
+    let default = SloThresholds {
+        max_latency_ms: 5_000,
+        ..DEFAULT_SLO_THRESHOLDS
+    };
+    let state = replay(
+        vec![
+            Event::Init(init_arg()),
+            Event::SetSloThresholds {
+                endpoint: None,
+                thresholds: Some(default),
+            },
+            Event::SetSloThresholds {
+                endpoint: Some(ENDPOINT.to_string()),
+                thresholds: Some(thresholds()),
+            },
+            Event::SetSloThresholds {
+                endpoint: Some("repay_to_vault".to_string()),
+                thresholds: Some(thresholds()),
+            },
+            Event::SetSloThresholds {
+                endpoint: Some("repay_to_vault".to_string()),
+                thresholds: None,
+            },
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    assert_eq!(thresholds_for(&state, ENDPOINT), thresholds());
+    assert_eq!(thresholds_for(&state, "repay_to_vault"), default);
+
+    let (parameter, scope, _) = parameter_change(&Event::SetSloThresholds {
+        endpoint: Some(ENDPOINT.to_string()),
+        thresholds: Some(thresholds()),
+    })
+    .unwrap();
+    assert_eq!(parameter, "slo_thresholds");
+    assert_eq!(scope.as_deref(), Some(ENDPOINT));
+}
+
+#[test]
+fn only_failures_the_protocol_caused_are_service_errors() {
+    assert!(is_service_error(&ProtocolError::TemporarilyUnavailable(
+        "price too old".to_string()
+    )));
+    assert!(is_service_error(&ProtocolError::SupplyInvariantHalted));
+    assert!(!is_service_error(&ProtocolError::CallerNotOwner));
+    assert!(!is_service_error(&ProtocolError::AmountTooLow {
+        minimum_amount: 1
+    }));
+    assert!(!is_service_error(&ProtocolError::GenericError(
+        "vault not found".to_string()
+    )));
+}
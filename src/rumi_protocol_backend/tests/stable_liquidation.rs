@@ -0,0 +1,53 @@
+//! Stable-token liquidation pulls: the debt cleared is charged 1:1 in e6s,
+//! rounded up so the stable covers every e8 of it, with the repay fee on
+//! top of the rounded amount.
+//!
+//! The ckstable repay fee is set to 0.5%.
+
+mod common;
+
+use common::init_arg_with;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::numeric::{Ratio, ICUSD};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::stable_pull;
+
+const E8S: u64 = 100_000_000;
+
+fn fixture() -> State {
+    let mut state = init_arg_with()
+        .icusd_ledger(Principal::from_slice(&[1]))
+        .state();
+    state.ckstable_repay_fee = Ratio::from(dec!(0.005));
+    state
+}
+
+#[test]
+fn the_pull_covers_the_debt_and_adds_the_fee() {
+    let state = fixture();
+    // 200 icUSD is 200 ckUSDT, plus 1 ckUSDT of fee.
+    assert_eq!(
+        stable_pull(&state, ICUSD::new(200 * E8S)),
+        (200_000_000, 1_000_000)
+    );
+    // A debt off the e6 grid rounds up rather than leaving up to 99 e8s
+    // unpaid.
+    assert_eq!(
+        stable_pull(&state, ICUSD::new(200 * E8S + 1)),
+        (200_000_001, 1_000_000)
+    );
+    assert_eq!(stable_pull(&state, ICUSD::new(0)), (0, 0));
+}
+
+#[test]
+fn no_fee_without_a_fee_rate() {
+    let mut state = fixture();
+    state.ckstable_repay_fee = Ratio::from(dec!(0));
+    assert_eq!(
+        stable_pull(&state, ICUSD::new(12_345_678_999)),
+        (123_456_790, 0)
+    );
+}
@@ -0,0 +1,120 @@
+//! State checkpoints: an upgrade replays only the events logged after the
+//! checkpoint its snapshot was taken at (nothing without one), replaying that
+//! tail onto the checkpointed state gives the same state as replaying from
+//! genesis, and the chain events replay cannot apply are recognised.
+//!
+//! The log opens vaults 1 and 2 after Init, sets ICP price bounds, closes
+//! vault 1 and opens vault 3; the checkpoint is taken after the first three
+//! events.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::chains::config::ChainId;
+use rumi_protocol_backend::event::{replay, replay_onto, Event};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::storage::{replay_range, StateCheckpoint};
+use rumi_protocol_backend::xrc::PriceBounds;
+
+use common::{icp, init_arg, VaultBuilder};
+
+const CHECKPOINT_AT: usize = 3;
+
+fn checkpoint(events_covered: u64) -> StateCheckpoint {
+    StateCheckpoint {
+        events_covered,
+        taken_at: 0,
+        size_bytes: 1_024,
+    }
+}
+
+fn open_vault(vault_id: u64) -> Event {
+    Event::OpenVault {
+        vault: VaultBuilder::new(vault_id)
+            .owner(Principal::from_slice(&[1]))
+            .collateral(100 * vault_id)
+            .build(),
+        block_index: vault_id,
+        timestamp: Some(vault_id),
+    }
+}
+
+fn events() -> Vec<Event> {
+    vec![
+        Event::Init(init_arg()),
+        open_vault(1),
+        open_vault(2),
+        Event::SetCollateralPriceBounds {
+            collateral_type: icp(),
+            bounds: Some(PriceBounds {
+                min_price_e8s: 10_000_000,
+                max_price_e8s: 1_000_000_000_000,
+            }),
+        },
+        Event::CloseVault {
+            vault_id: 1,
+            block_index: None,
+            timestamp: None,
+        },
+        open_vault(3),
+    ]
+}
+
+fn summary(state: &State) -> (Vec<(u64, u64)>, usize) {
+    (
+        state
+            .vault_id_to_vaults
+            .values()
+            .map(|v| (v.vault_id, v.collateral_amount))
+            .collect(),
+        state.price_bounds.len(),
+    )
+}
+
+#[test]
+fn only_events_after_the_checkpoint_are_replayed() {
+    assert_eq!(replay_range(Some(&checkpoint(3)), 10), 3..10);
+    assert!(replay_range(Some(&checkpoint(10)), 10).is_empty());
+    // Without a checkpoint the snapshot covers the whole log.
+    assert!(replay_range(None, 10).is_empty());
+    // A checkpoint past the end of the log never replays anything.
+    assert!(replay_range(Some(&checkpoint(12)), 10).is_empty());
+}
+
+#[test]
+fn replaying_the_tail_matches_replaying_from_genesis() {
+    let events = events();
+    let genesis = replay(events.clone().into_iter()).expect("replay");
+
+    let checkpointed = replay(events[..CHECKPOINT_AT].iter().cloned()).expect("replay");
+    assert_eq!(checkpointed.vault_id_to_vaults.len(), 2);
+    let tail = replay_range(Some(&checkpoint(CHECKPOINT_AT as u64)), events.len() as u64);
+    let restored = replay_onto(
+        checkpointed,
+        tail.start,
+        events[tail.start as usize..].iter().cloned(),
+    );
+
+    assert_eq!(summary(&restored), summary(&genesis));
+    assert_eq!(summary(&restored), (vec![(2, 200), (3, 300)], 1));
+    // The tail moves the id counter past every vault it opens, as opening
+    // them live did.
+    assert_eq!(restored.next_available_vault_id, 4);
+}
+
+#[test]
+fn chain_events_are_recognised() {
+    let deposit = Event::DepositObserved {
+        chain_id: ChainId(1),
+        vault_id: 1,
+        custody_address: "0x00".to_string(),
+        amount_e18: 1,
+        tx_hash: "0x01".to_string(),
+        block_number: 1,
+        timestamp: 1,
+    };
+    assert!(deposit.is_chain_observation());
+    assert!(!open_vault(1).is_chain_observation());
+    assert!(!Event::Init(init_arg()).is_chain_observation());
+}
@@ -0,0 +1,170 @@
+//! Surplus buffer: the retained share of a fee is floored and bounded, the
+//! icUSD buffer absorbs a vault's debt with a proportional share of its
+//! collateral (all of it when the debt is covered), sweeps debit the buffer
+//! and are undone on a failed mint, and everything replays.
+//!
+//! The fee share is 25%, the icUSD buffer holds 4 icUSD and one vault of 1
+//! ICP owes 10 icUSD.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event, FeeSource};
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::state::{Mode, State};
+use rumi_protocol_backend::surplus::{
+    apply_absorption, apply_fee_retained, fee_share_from, plan_absorption, restore_sweep,
+    retained_share, status, take_for_sweep,
+};
+use rumi_protocol_backend::vault::Vault;
+
+use common::{icp, init_arg_with, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+
+fn icusd() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn vault(debt_e8s: u64) -> Vault {
+    VaultBuilder::new(1)
+        .owner(Principal::from_slice(&[20]))
+        .collateral(E8S)
+        .debt(debt_e8s)
+        .build()
+}
+
+fn fixture() -> State {
+    let mut state = init_arg_with().icusd_ledger(icusd()).state();
+    state.mode = Mode::GeneralAvailability;
+    state.surplus_fee_share = fee_share_from(0.25).unwrap();
+    apply_fee_retained(&mut state, icusd(), 4 * E8S);
+    state.open_vault(vault(10 * E8S));
+    state
+}
+
+#[test]
+fn the_retained_share_is_floored_and_bounded() {
+    let state = fixture();
+    assert_eq!(retained_share(&state, 0), 0);
+    assert_eq!(retained_share(&state, 3), 0);
+    assert_eq!(retained_share(&state, 7), 1);
+    assert_eq!(retained_share(&state, 4 * E8S), E8S);
+
+    let mut state = fixture();
+    state.surplus_fee_share = fee_share_from(0.0).unwrap();
+    assert_eq!(retained_share(&state, 4 * E8S), 0);
+    state.surplus_fee_share = fee_share_from(1.0).unwrap();
+    assert_eq!(retained_share(&state, 4 * E8S), 4 * E8S);
+
+    for share in [-0.1, 1.1, f64::NAN, f64::INFINITY] {
+        assert!(fee_share_from(share).is_err());
+    }
+}
+
+#[test]
+fn the_buffer_absorbs_part_of_a_vault() {
+    let mut state = fixture();
+    let (debt, collateral) = plan_absorption(&state, 1).unwrap();
+    assert_eq!(debt, ICUSD::new(4 * E8S));
+    assert_eq!(collateral, 4 * E8S / 10);
+
+    assert!(!apply_absorption(&mut state, 1, debt, collateral));
+    let vault = &state.vault_id_to_vaults[&1];
+    assert_eq!(vault.borrowed_icusd_amount, ICUSD::new(6 * E8S));
+    assert_eq!(vault.collateral_amount, 6 * E8S / 10);
+
+    let status = status(&state);
+    assert_eq!(status.icusd_balance, 0);
+    assert_eq!(status.totals.debt_absorbed, 4 * E8S);
+    assert_eq!(
+        status.totals.collateral_absorbed,
+        vec![(icp(), 4 * E8S / 10)]
+    );
+    assert_eq!(state.surplus_buffer[&icp()], 4 * E8S / 10);
+    assert_eq!(plan_absorption(&state, 1), None);
+}
+
+#[test]
+fn a_covered_vault_is_removed_with_all_its_collateral() {
+    let mut state = fixture();
+    apply_fee_retained(&mut state, icusd(), 8 * E8S);
+    let (debt, collateral) = plan_absorption(&state, 1).unwrap();
+    assert_eq!((debt, collateral), (ICUSD::new(10 * E8S), E8S));
+
+    assert!(apply_absorption(&mut state, 1, debt, collateral));
+    assert!(state.vault_id_to_vaults.is_empty());
+    assert_eq!(state.surplus_buffer[&icusd()], 2 * E8S);
+    assert_eq!(state.surplus_buffer[&icp()], E8S);
+}
+
+#[test]
+fn a_failed_sweep_is_restored() {
+    let mut state = fixture();
+    assert!(take_for_sweep(&mut state, 5 * E8S).is_err());
+    assert!(take_for_sweep(&mut state, 3 * E8S).is_ok());
+    assert_eq!(status(&state).icusd_balance, E8S);
+    assert_eq!(state.surplus_totals.swept, 3 * E8S);
+
+    restore_sweep(&mut state, 3 * E8S);
+    assert_eq!(status(&state).icusd_balance, 4 * E8S);
+    assert_eq!(state.surplus_totals.swept, 0);
+}
+
+#[test]
+fn the_buffer_replays() {
+    let state = replay(
+        vec![
+            Event::Init(init_arg_with().icusd_ledger(icusd()).build()),
+            Event::SetSurplusFeeShare {
+                share: fee_share_from(0.25).unwrap(),
+            },
+            Event::OpenVault {
+                vault: vault(10 * E8S),
+                block_index: 0,
+                timestamp: None,
+            },
+            Event::SurplusFeeRetained {
+                ledger: icusd(),
+                amount: 4 * E8S,
+                source: FeeSource::BorrowingFee,
+                timestamp: 0,
+            },
+            Event::SurplusFeeRetained {
+                ledger: icp(),
+                amount: 1_000,
+                source: FeeSource::LiquidationFee,
+                timestamp: 0,
+            },
+            Event::SurplusAbsorbedVault {
+                vault_id: 1,
+                collateral_type: icp(),
+                collateral_amount: E8S / 10,
+                debt: ICUSD::new(E8S),
+                timestamp: 0,
+            },
+            Event::SurplusSwept {
+                amount: 2 * E8S,
+                block_index: 3,
+                timestamp: 0,
+            },
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    let status = status(&state);
+    assert_eq!(status.fee_share, 0.25);
+    assert_eq!(status.icusd_balance, E8S);
+    assert_eq!(
+        status.totals.fees_retained,
+        vec![(icusd(), 4 * E8S), (icp(), 1_000)]
+    );
+    assert_eq!(status.totals.debt_absorbed, E8S);
+    assert_eq!(status.totals.swept, 2 * E8S);
+    assert_eq!(state.surplus_buffer[&icp()], 1_000 + E8S / 10);
+    assert_eq!(
+        state.vault_id_to_vaults[&1].borrowed_icusd_amount,
+        ICUSD::new(9 * E8S)
+    );
+}
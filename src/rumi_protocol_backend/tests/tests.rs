@@ -368,7 +368,7 @@ mod protocol_safety_tests {
         state.total_collateral_ratio = after_drop_ratio;
         
         // Update mode based on new ratio
-        state.update_total_collateral_ratio_and_mode(new_rate);
+        state.update_total_collateral_ratio_and_mode(new_rate, 0);
         
         // Check if mode changed appropriately
         // With only ICP configured, the dynamic threshold equals RECOVERY_COLLATERAL_RATIO
@@ -1253,6 +1253,9 @@ mod multi_collateral_tests {
         // Create a ckETH vault (vault 2)
         let eth_vault = create_cketh_vault(user_b, 2, 1_000_000_000_000_000_000, 1000 * 100_000_000);
         state.vault_id_to_vaults.insert(2, eth_vault);
+        // Redemptions walk the per-collateral CR index.
+        state.reindex_vault_cr(1);
+        state.reindex_vault_cr(2);
         state.principal_to_vault_ids
             .entry(user_b)
             .or_default()
@@ -1302,6 +1305,9 @@ mod multi_collateral_tests {
         // ckETH vault
         let eth_vault = create_cketh_vault(user_b, 2, 1_000_000_000_000_000_000, 1000 * 100_000_000);
         state.vault_id_to_vaults.insert(2, eth_vault);
+        // Redemptions walk the per-collateral CR index.
+        state.reindex_vault_cr(1);
+        state.reindex_vault_cr(2);
 
         let icp_before = state.vault_id_to_vaults.get(&1).unwrap().collateral_amount;
 
@@ -1839,7 +1845,7 @@ mod multi_collateral_tests {
 
         // Weighted threshold = 1.45 (50/50 at 1.50 and 1.40)
         // System CR at $10 ICP, $2000 ckETH: ($100+$100)/$100 = 200% → GA
-        state.update_total_collateral_ratio_and_mode(UsdIcp::from(dec!(10.0)));
+        state.update_total_collateral_ratio_and_mode(UsdIcp::from(dec!(10.0)), 2_000_000_000);
         assert_eq!(state.mode, Mode::GeneralAvailability);
         assert_eq!(state.recovery_mode_threshold.0, dec!(1.45));
 
@@ -1850,7 +1856,7 @@ mod multi_collateral_tests {
         // But 135% > the old static 133% liquidation ratio, so this would have been missed
         // with a static threshold that was too low
         state.set_icp_rate(UsdIcp::from(dec!(3.5)), Some(2_000_000_000));
-        state.update_total_collateral_ratio_and_mode(UsdIcp::from(dec!(3.5)));
+        state.update_total_collateral_ratio_and_mode(UsdIcp::from(dec!(3.5)), 2_000_000_000);
 
         assert_eq!(state.mode, Mode::Recovery);
         // Threshold should still be 1.45
@@ -1862,7 +1868,7 @@ mod multi_collateral_tests {
 // Dynamic Redemption Fee Tests
 // ============================================================================
 //
-// The fee formula: fee = base_rate × 0.94^elapsed_hours + (redeemed / total_borrowed) × 0.5
+// The fee formula: fee = base_rate × 0.5^(elapsed / 12h) + (redeemed / total_borrowed) × 0.5
 // Clamped between floor (0.3%) and ceiling (5%).
 //
 // Tests call compute_redemption_fee directly (pure function) to avoid
@@ -1878,11 +1884,13 @@ mod redemption_fee_tests {
         DEFAULT_REDEMPTION_FEE_CEILING,
     };
 
+    const HOUR_NS: u64 = 3_600 * 1_000_000_000;
+
     #[test]
     fn test_fee_zero_total_borrowed_returns_zero() {
         // Edge case: no debt in protocol → fee should be zero (avoid division by zero)
         let fee = compute_redemption_fee(
-            0,                              // elapsed_hours
+            0,                              // elapsed_ns
             ICUSD::from(100_000_000),       // redeemed: 1 icUSD
             ICUSD::from(0),                 // total_borrowed: 0
             Ratio::from(dec!(0)),           // base_rate
@@ -1895,7 +1903,7 @@ mod redemption_fee_tests {
     #[test]
     fn test_fee_fresh_redemption_small_amount() {
         // No prior redemptions (base_rate=0), redeem 1 of 1000 icUSD
-        // fee = 0 * 0.94^0 + (1/1000) * 0.5 = 0.0005
+        // fee = 0 * 0.5^0 + (1/1000) * 0.5 = 0.0005
         // But floor is 0.003 → clamp up
         let fee = compute_redemption_fee(
             0,
@@ -1943,10 +1951,10 @@ mod redemption_fee_tests {
     #[test]
     fn test_fee_decay_with_base_rate() {
         // base_rate = 4%, 11 hours elapsed
-        // 0.94^11 ≈ 0.506 → decayed = 0.04 * 0.506 ≈ 0.02024
-        // + (1/1000)*0.5 = 0.0005 → total ≈ 0.02074
+        // 0.5^(11/12) ≈ 0.529 → decayed = 0.04 * 0.529 ≈ 0.02115
+        // + (1/1000)*0.5 = 0.0005 → total ≈ 0.02165
         let fee = compute_redemption_fee(
-            11,                                 // 11 hours elapsed
+            11 * HOUR_NS,                       // 11 hours elapsed
             ICUSD::from(1 * 100_000_000),       // tiny redemption
             ICUSD::from(1000 * 100_000_000),
             Ratio::from(dec!(0.04)),            // 4% base rate
@@ -1960,7 +1968,7 @@ mod redemption_fee_tests {
     #[test]
     fn test_fee_no_decay_at_zero_hours() {
         // base_rate = 4%, 0 hours elapsed → no decay
-        // fee = 0.04 * 0.94^0 + (1/1000)*0.5 = 0.04 + 0.0005 = 0.0405
+        // fee = 0.04 * 0.5^0 + (1/1000)*0.5 = 0.04 + 0.0005 = 0.0405
         let fee = compute_redemption_fee(
             0,
             ICUSD::from(1 * 100_000_000),
@@ -1989,10 +1997,10 @@ mod redemption_fee_tests {
 
     #[test]
     fn test_fee_full_decay_returns_to_floor() {
-        // After 1000 hours, 0.94^1000 ≈ 0 → base effectively gone
+        // After 1000 hours, 0.5^(1000/12) ≈ 0 → base effectively gone
         // + tiny proportion → below floor → clamp to floor
         let fee = compute_redemption_fee(
-            1000,
+            1000 * HOUR_NS,
             ICUSD::from(1 * 100_000_000),
             ICUSD::from(1000 * 100_000_000),
             Ratio::from(dec!(0.04)),
@@ -2067,7 +2075,7 @@ mod redemption_fee_tests {
         assert_eq!(fee1.0, dec!(0.01));
 
         // Second redemption immediately (0 elapsed): base=0.01, redeem 20
-        // fee = 0.01 * 0.94^0 + (20/1000)*0.5 = 0.01 + 0.01 = 0.02
+        // fee = 0.01 * 0.5^0 + (20/1000)*0.5 = 0.01 + 0.01 = 0.02
         let fee2 = compute_redemption_fee(
             0,
             ICUSD::from(20 * 100_000_000),
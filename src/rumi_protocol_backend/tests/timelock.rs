@@ -0,0 +1,165 @@
+//! Parameter timelock: proposals need the timelock on and a timelocked
+//! setter, activate one delay later, only the developer or a guardian may
+//! cancel them, every timelocked setter checks the timelock, and the queue
+//! replays.
+//!
+//! Changes wait 24 hours; Alice is the developer and Bob a guardian.
+
+mod common;
+
+use common::init_arg_with;
+
+use std::collections::BTreeSet;
+
+use candid::Principal;
+use serde_bytes::ByteBuf;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::timelock::{
+    apply_proposed, apply_removed, check_cancellable, check_direct_call, check_executable, propose,
+    validate_delay, PendingParameterChange, MAX_PARAMETER_TIMELOCK_NS, TIMELOCKED_METHODS,
+};
+use rumi_protocol_backend::ProtocolError;
+
+const DAY: u64 = 86_400 * 1_000_000_000;
+const NOW: u64 = 1_000 * DAY;
+
+fn alice() -> Principal {
+    Principal::from_slice(&[20])
+}
+
+fn bob() -> Principal {
+    Principal::from_slice(&[21])
+}
+
+fn carol() -> Principal {
+    Principal::from_slice(&[22])
+}
+
+fn arg() -> ByteBuf {
+    ByteBuf::from(candid::encode_one(500_000_u64).unwrap())
+}
+
+/// Alice proposes `method` now.
+fn proposal(state: &State, method: &str) -> Result<PendingParameterChange, ProtocolError> {
+    propose(state, method.to_string(), arg(), alice(), NOW)
+}
+
+fn fixture() -> State {
+    let mut state = init_arg_with()
+        .icusd_ledger(Principal::from_slice(&[1]))
+        .developer(alice())
+        .state();
+    state.parameter_timelock_ns = DAY;
+    state.guardian_principals.insert(bob());
+    state
+}
+
+#[test]
+fn proposals_need_the_timelock_and_a_timelocked_setter() {
+    let mut state = init_arg_with()
+        .icusd_ledger(Principal::from_slice(&[1]))
+        .developer(alice())
+        .state();
+    assert!(check_direct_call(&state, "set_borrowing_fee").is_ok());
+    assert!(proposal(&state, "set_borrowing_fee").is_err());
+
+    state.parameter_timelock_ns = DAY;
+    assert!(check_direct_call(&state, "set_borrowing_fee").is_err());
+    // Kill switches stay instant.
+    assert!(check_direct_call(&state, "set_collateral_status").is_ok());
+    assert!(proposal(&state, "set_collateral_status").is_err());
+
+    assert!(validate_delay(MAX_PARAMETER_TIMELOCK_NS).is_ok());
+    assert!(validate_delay(MAX_PARAMETER_TIMELOCK_NS + 1).is_err());
+}
+
+#[test]
+fn a_change_activates_one_delay_later() {
+    let mut state = fixture();
+    let change = proposal(&state, "set_borrowing_fee").unwrap();
+    assert_eq!(change.id, 0);
+    assert_eq!(change.activates_at, NOW + DAY);
+    apply_proposed(&mut state, change.clone());
+
+    assert!(check_executable(&state, 0, NOW + DAY - 1).is_err());
+    assert_eq!(check_executable(&state, 0, NOW + DAY).unwrap(), change);
+
+    let next = proposal(&state, "set_interest_rate").unwrap();
+    assert_eq!(next.id, 1);
+
+    apply_removed(&mut state, 0);
+    assert!(check_executable(&state, 0, NOW + DAY).is_err());
+}
+
+#[test]
+fn only_the_developer_or_a_guardian_cancels() {
+    let mut state = fixture();
+    let change = proposal(&state, "set_borrowing_fee").unwrap();
+    apply_proposed(&mut state, change);
+
+    assert!(check_cancellable(&state, 0, alice()).is_ok());
+    assert!(check_cancellable(&state, 0, bob()).is_ok());
+    assert!(check_cancellable(&state, 0, carol()).is_err());
+    assert!(check_cancellable(&state, 1, alice()).is_err());
+}
+
+#[test]
+fn every_timelocked_setter_checks_the_timelock() {
+    let main_source = include_str!("../src/main.rs");
+    let mut seen = BTreeSet::new();
+    for method in TIMELOCKED_METHODS {
+        assert!(seen.insert(method), "{} is listed twice", method);
+        assert!(
+            main_source.contains(&format!("timelock_caller(\"{}\")", method)),
+            "{} does not call timelock_caller",
+            method
+        );
+    }
+}
+
+#[test]
+fn the_queue_replays() {
+    let proposed = |id, method: &str| Event::ParameterChangeProposed {
+        id,
+        method: method.to_string(),
+        arg: arg(),
+        proposed_by: alice(),
+        activates_at: NOW + DAY,
+        timestamp: NOW,
+    };
+    let state = replay(
+        vec![
+            Event::Init(
+                init_arg_with()
+                    .icusd_ledger(Principal::from_slice(&[1]))
+                    .developer(alice())
+                    .build(),
+            ),
+            Event::SetParameterTimelock { delay_ns: DAY },
+            proposed(0, "set_borrowing_fee"),
+            proposed(1, "set_interest_rate"),
+            proposed(2, "set_liquidation_tip"),
+            Event::ParameterChangeCancelled {
+                id: 0,
+                cancelled_by: bob(),
+                timestamp: NOW + 1,
+            },
+            Event::ParameterChangeExecuted {
+                id: 2,
+                executed_by: carol(),
+                timestamp: NOW + DAY,
+            },
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    assert_eq!(state.parameter_timelock_ns, DAY);
+    assert_eq!(
+        state.pending_parameter_changes.keys().collect::<Vec<_>>(),
+        vec![&1]
+    );
+    assert_eq!(state.pending_parameter_changes[&1].proposed_at, NOW);
+    assert_eq!(state.next_parameter_change_id, 3);
+}
@@ -0,0 +1,123 @@
+//! Operation trace ids: ids round-trip through their log form and sort by
+//! start time, a scope registers its id for the principal only while no
+//! other operation of that principal holds one, string errors are tagged
+//! (except the read-only rejection), and the log filter keeps only the
+//! lines of one trace.
+
+use candid::Principal;
+
+use rumi_protocol_backend::guard::{current_trace, trace_tag, TraceId, TraceScope};
+use rumi_protocol_backend::logs::{Log, LogEntry, Priority};
+use rumi_protocol_backend::ProtocolError;
+
+const MS: u64 = 1_000_000;
+
+fn user(n: u8) -> Principal {
+    Principal::from_slice(&[n])
+}
+
+fn entry(message: &str) -> LogEntry {
+    LogEntry {
+        timestamp: 0,
+        priority: Priority::Info,
+        file: "vault.rs".to_string(),
+        line: 1,
+        message: message.to_string(),
+        counter: 0,
+    }
+}
+
+#[test]
+fn trace_ids_round_trip_and_sort_by_time() {
+    let first = TraceId::new(1_000 * MS);
+    let second = TraceId::new(1_000 * MS);
+    let later = TraceId::new(1_001 * MS);
+    assert_ne!(first, second);
+    assert!(first < later && second < later);
+
+    let shown = first.to_string();
+    assert_eq!(shown.len(), 16);
+    assert_eq!(shown.parse::<TraceId>(), Ok(first));
+    assert_eq!(format!("trace={}", shown).parse::<TraceId>(), Ok(first));
+    assert!("".parse::<TraceId>().is_err());
+    assert!("xyz".parse::<TraceId>().is_err());
+    assert!("12345678901234567".parse::<TraceId>().is_err());
+}
+
+#[test]
+fn scope_registers_one_trace_per_principal() {
+    let alice = user(1);
+    assert_eq!(trace_tag(alice).to_string(), "trace=-");
+
+    let outer = TraceScope::begin(alice, MS);
+    assert_eq!(current_trace(alice), Some(outer.trace_id()));
+    assert_eq!(
+        trace_tag(alice).to_string(),
+        format!("trace={}", outer.trace_id())
+    );
+
+    // A nested guard joins the running trace; a concurrent call gets its own
+    // id but does not displace the running one.
+    let joined = TraceScope::join(alice, 2 * MS);
+    assert_eq!(joined.trace_id(), outer.trace_id());
+    let concurrent = TraceScope::begin(alice, 3 * MS);
+    assert_ne!(concurrent.trace_id(), outer.trace_id());
+    drop(joined);
+    drop(concurrent);
+    assert_eq!(current_trace(alice), Some(outer.trace_id()));
+
+    // Other principals are independent.
+    assert_eq!(current_trace(user(2)), None);
+
+    drop(outer);
+    assert_eq!(current_trace(alice), None);
+}
+
+#[test]
+fn string_errors_carry_the_trace() {
+    let trace = TraceId(0xabc);
+    let scope_tag = "[trace=0000000000000abc]";
+
+    match ProtocolError::GenericError("Vault #1 not found".to_string()).with_trace(trace) {
+        ProtocolError::GenericError(msg) => {
+            assert_eq!(msg, format!("Vault #1 not found {}", scope_tag))
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    match ProtocolError::TemporarilyUnavailable("busy".to_string()).with_trace(trace) {
+        ProtocolError::TemporarilyUnavailable(msg) => assert!(msg.ends_with(scope_tag)),
+        other => panic!("unexpected {:?}", other),
+    }
+    // The read-only rejection stays byte-identical.
+    let read_only = match ProtocolError::read_only_mode() {
+        ProtocolError::TemporarilyUnavailable(msg) => msg,
+        other => panic!("unexpected {:?}", other),
+    };
+    match ProtocolError::read_only_mode().with_trace(trace) {
+        ProtocolError::TemporarilyUnavailable(msg) => assert_eq!(msg, read_only),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(matches!(
+        ProtocolError::CallerNotOwner.with_trace(trace),
+        ProtocolError::CallerNotOwner
+    ));
+}
+
+#[test]
+fn log_filter_keeps_one_trace() {
+    let trace = TraceId(0xabc);
+    let mut log = Log {
+        entries: vec![
+            entry("[borrow_from_vault] trace=0000000000000abc minted 5"),
+            entry("[borrow_from_vault] trace=0000000000000abd minted 7"),
+            entry("[transfering_margins] trace=- successfully transferred"),
+            entry("[transfer_idempotent] trace=0000000000000abc ledger reported Duplicate"),
+        ],
+    };
+    log.retain_trace(trace);
+    assert_eq!(log.entries.len(), 2);
+    assert!(log
+        .entries
+        .iter()
+        .all(|e| e.message.contains("trace=0000000000000abc")));
+}
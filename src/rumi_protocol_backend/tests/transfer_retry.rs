@@ -0,0 +1,162 @@
+//! Pending-transfer retries: failed attempts back off exponentially within
+//! the ledgers' deduplication window, entries are dead-lettered after
+//! `MAX_PENDING_RETRIES` failures, and the developer can requeue them.
+//!
+//! The owner of vault 1 is owed one 1 ICP margin payout, queued at
+//! `QUEUED_NS` with op nonce `(QUEUED_NS << 64) | 1`.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::numeric::ICP;
+use rumi_protocol_backend::state::{PendingMarginTransfer, State};
+use rumi_protocol_backend::transfer_retry::{
+    backoff_secs, check_refund, failed_transfers, is_due, metrics, next_run_delay_secs,
+    prune_backoff, record_failure, requeue, PendingTransferKey, RetryOutcome, BASE_BACKOFF_SECS,
+    MAX_BACKOFF_SECS, MAX_PENDING_RETRIES,
+};
+
+use common::{fresh_state, icp};
+
+const E8S: u64 = 100_000_000;
+const SEC: u64 = 1_000_000_000;
+const QUEUED_NS: u64 = 1_000 * SEC;
+
+fn owner() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn margin_key() -> PendingTransferKey {
+    PendingTransferKey::Margin {
+        vault_id: 1,
+        owner: owner(),
+    }
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    state.pending_margin_transfers.insert(
+        (1, owner()),
+        PendingMarginTransfer {
+            owner: owner(),
+            margin: ICP::new(E8S),
+            collateral_type: icp(),
+            retry_count: 0,
+            op_nonce: ((QUEUED_NS as u128) << 64) | 1,
+            trace_id: None,
+        },
+    );
+    state
+}
+
+/// Fail the margin payout every time it is due, starting at `now`, until it
+/// is dead-lettered. Returns when that happened.
+fn fail_until_dead_lettered(state: &mut State, mut now: u64) -> u64 {
+    loop {
+        assert!(is_due(state, &margin_key(), now));
+        match record_failure(state, margin_key(), "TemporarilyUnavailable".into(), now) {
+            RetryOutcome::Retry {
+                next_attempt_ns, ..
+            } => now = next_attempt_ns,
+            RetryOutcome::DeadLettered { attempts } => {
+                assert_eq!(attempts, MAX_PENDING_RETRIES);
+                return now;
+            }
+            RetryOutcome::Gone => panic!("the payout left its queue"),
+        }
+    }
+}
+
+#[test]
+fn backoff_doubles_up_to_the_cap_within_the_dedup_window() {
+    assert_eq!(backoff_secs(0), 0);
+    assert_eq!(backoff_secs(1), BASE_BACKOFF_SECS);
+    assert_eq!(backoff_secs(2), 2 * BASE_BACKOFF_SECS);
+    assert_eq!(backoff_secs(3), 4 * BASE_BACKOFF_SECS);
+    assert_eq!(backoff_secs(MAX_PENDING_RETRIES), MAX_BACKOFF_SECS);
+    assert_eq!(backoff_secs(u8::MAX), MAX_BACKOFF_SECS);
+
+    let schedule_secs: u64 = (1..MAX_PENDING_RETRIES).map(backoff_secs).sum();
+    assert!(schedule_secs < 24 * 3_600, "{}s", schedule_secs);
+}
+
+#[test]
+fn failures_back_off_then_dead_letter() {
+    let mut state = fixture();
+    let outcome = record_failure(&mut state, margin_key(), "first".into(), QUEUED_NS);
+    assert_eq!(
+        outcome,
+        RetryOutcome::Retry {
+            attempts: 1,
+            next_attempt_ns: QUEUED_NS + BASE_BACKOFF_SECS * SEC,
+        }
+    );
+    assert!(!is_due(&state, &margin_key(), QUEUED_NS + SEC));
+    assert_eq!(
+        next_run_delay_secs(&state, QUEUED_NS + SEC),
+        Some(BASE_BACKOFF_SECS)
+    );
+
+    let failed_at = fail_until_dead_lettered(&mut state, QUEUED_NS + BASE_BACKOFF_SECS * SEC);
+    assert!(state.pending_margin_transfers.is_empty());
+    assert!(state.pending_transfer_backoff.is_empty());
+    assert_eq!(next_run_delay_secs(&state, failed_at), None);
+
+    let failed = failed_transfers(&state);
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].key, margin_key());
+    assert_eq!((failed[0].recipient, failed[0].ledger), (owner(), icp()));
+    assert_eq!(failed[0].amount, E8S);
+    assert_eq!(failed[0].failed_at_ns, failed_at);
+    assert_eq!(failed[0].queued_at_ns, Some(QUEUED_NS));
+
+    // A payout that settled meanwhile is left alone.
+    let mut state = fixture();
+    state.pending_margin_transfers.clear();
+    assert_eq!(
+        record_failure(&mut state, margin_key(), "late".into(), QUEUED_NS),
+        RetryOutcome::Gone
+    );
+}
+
+#[test]
+fn requeue_restores_a_fresh_attempt_budget() {
+    let mut state = fixture();
+    assert!(requeue(&mut state, margin_key()).is_err());
+
+    let failed_at = fail_until_dead_lettered(&mut state, QUEUED_NS);
+    requeue(&mut state, margin_key()).unwrap();
+    assert!(state.failed_transfers.is_empty());
+    let transfer = state.pending_margin_transfers[&(1, owner())];
+    assert_eq!(transfer.retry_count, 0);
+    assert_eq!(transfer.op_nonce, ((QUEUED_NS as u128) << 64) | 1);
+    assert!(is_due(&state, &margin_key(), failed_at));
+    assert!(requeue(&mut state, margin_key()).is_err());
+
+    // Only redemption payouts can be reversed.
+    fail_until_dead_lettered(&mut state, failed_at);
+    assert!(check_refund(&state, &margin_key(), failed_at).is_err());
+}
+
+#[test]
+fn metrics_report_depth_backoff_and_oldest_age() {
+    let mut state = fixture();
+    let now = QUEUED_NS + 60 * SEC;
+    let m = metrics(&state, now);
+    assert_eq!((m.queued, m.backing_off, m.failed), (1, 0, 0));
+    assert_eq!(m.oldest_age_ns, Some(60 * SEC));
+
+    record_failure(&mut state, margin_key(), "down".into(), now);
+    assert_eq!(metrics(&state, now).backing_off, 1);
+    assert_eq!(
+        metrics(&state, now + BASE_BACKOFF_SECS * SEC).backing_off,
+        0
+    );
+
+    // A backoff outliving its entry is pruned.
+    state.pending_margin_transfers.clear();
+    prune_backoff(&mut state);
+    assert!(state.pending_transfer_backoff.is_empty());
+    assert_eq!(metrics(&state, now).oldest_age_ns, None);
+}
@@ -0,0 +1,172 @@
+//! Treasury co-approvals: only a matching live approval can be consumed,
+//! retries of a consumed withdrawal still pass until it expires, a used
+//! approval cannot be revoked, lifetimes are bounded and approvals replay.
+//!
+//! A guardian granted request 7 at T0: an approval of 500 icUSD from the
+//! treasury to a grants wallet, for an hour.
+
+mod common;
+
+use common::init_arg_with;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::treasury_approvals::{
+    apply_approve, apply_consume, apply_revoke, approvals, check_consume, live_approval,
+    validate_approval, TreasuryWithdrawalRequest, MAX_TREASURY_APPROVAL_TTL_NS,
+};
+
+const E8S: u64 = 100_000_000;
+const T0: u64 = 1_700_000_000 * 1_000_000_000;
+const HOUR_NS: u64 = 3_600 * 1_000_000_000;
+
+fn guardian() -> Principal {
+    Principal::from_slice(&[20])
+}
+
+fn request() -> TreasuryWithdrawalRequest {
+    TreasuryWithdrawalRequest {
+        request_id: 7,
+        ledger: Principal::from_slice(&[1]),
+        to: Principal::from_slice(&[30]),
+        amount: 500 * E8S,
+    }
+}
+
+fn fixture() -> State {
+    let mut state = init_arg_with()
+        .icusd_ledger(Principal::from_slice(&[1]))
+        .state();
+    let expires_at = validate_approval(&state, &request(), 3_600, T0).unwrap();
+    assert_eq!(expires_at, T0 + HOUR_NS);
+    apply_approve(&mut state, request(), guardian(), expires_at, T0);
+    state
+}
+
+#[test]
+fn only_a_matching_live_approval_is_consumed() {
+    let state = fixture();
+    for request in [
+        TreasuryWithdrawalRequest {
+            amount: 501 * E8S,
+            ..request()
+        },
+        TreasuryWithdrawalRequest {
+            to: Principal::from_slice(&[31]),
+            ..request()
+        },
+        TreasuryWithdrawalRequest {
+            ledger: Principal::from_slice(&[10]),
+            ..request()
+        },
+        TreasuryWithdrawalRequest {
+            request_id: 8,
+            ..request()
+        },
+    ] {
+        assert!(check_consume(&state, &request, T0 + 1).is_err());
+    }
+    assert_eq!(check_consume(&state, &request(), T0 + 1), Ok(true));
+    assert!(check_consume(&state, &request(), T0 + HOUR_NS).is_err());
+}
+
+#[test]
+fn retries_pass_until_the_approval_expires() {
+    let mut state = fixture();
+    apply_consume(&mut state, 7, T0 + 1);
+    assert_eq!(check_consume(&state, &request(), T0 + 2), Ok(false));
+    // A second consumption keeps the first time.
+    apply_consume(&mut state, 7, T0 + 2);
+    assert_eq!(
+        live_approval(&state, 7, T0 + 2).unwrap().consumed_at_ns,
+        Some(T0 + 1)
+    );
+    assert!(check_consume(&state, &request(), T0 + HOUR_NS).is_err());
+    assert!(approvals(&state, T0 + HOUR_NS).is_empty());
+}
+
+#[test]
+fn only_unused_approvals_can_be_revoked() {
+    let mut state = fixture();
+    apply_consume(&mut state, 7, T0 + 1);
+    assert!(apply_revoke(&mut state, 7, T0 + 2).is_err());
+
+    let mut state = fixture();
+    assert!(apply_revoke(&mut state, 7, T0 + 1).is_ok());
+    assert!(check_consume(&state, &request(), T0 + 2).is_err());
+    assert!(apply_revoke(&mut state, 7, T0 + 2).is_err());
+}
+
+#[test]
+fn lifetimes_are_bounded_and_live_approvals_are_not_replaced() {
+    let state = fixture();
+    let max_secs = MAX_TREASURY_APPROVAL_TTL_NS / 1_000_000_000;
+    let other = TreasuryWithdrawalRequest {
+        request_id: 8,
+        ..request()
+    };
+    assert!(validate_approval(&state, &other, 0, T0).is_err());
+    assert!(validate_approval(&state, &other, max_secs + 1, T0).is_err());
+    assert_eq!(
+        validate_approval(&state, &other, max_secs, T0),
+        Ok(T0 + MAX_TREASURY_APPROVAL_TTL_NS)
+    );
+    assert!(validate_approval(
+        &state,
+        &TreasuryWithdrawalRequest { amount: 0, ..other },
+        60,
+        T0
+    )
+    .is_err());
+
+    // Request 7 is approved until T0 + 1h, then free again.
+    assert!(validate_approval(&state, &request(), 60, T0 + 1).is_err());
+    assert!(validate_approval(&state, &request(), 60, T0 + HOUR_NS).is_ok());
+}
+
+#[test]
+fn approvals_replay() {
+    let other = TreasuryWithdrawalRequest {
+        request_id: 8,
+        ..request()
+    };
+    let state = replay(
+        vec![
+            Event::Init(
+                init_arg_with()
+                    .icusd_ledger(Principal::from_slice(&[1]))
+                    .build(),
+            ),
+            Event::TreasuryWithdrawalApproved {
+                request: request(),
+                approved_by: guardian(),
+                expires_at_ns: T0 + HOUR_NS,
+                timestamp: T0,
+            },
+            Event::TreasuryWithdrawalApproved {
+                request: other,
+                approved_by: guardian(),
+                expires_at_ns: T0 + HOUR_NS,
+                timestamp: T0,
+            },
+            Event::TreasuryWithdrawalApprovalConsumed {
+                request_id: 7,
+                timestamp: T0 + 1,
+            },
+            Event::TreasuryWithdrawalApprovalRevoked {
+                request_id: 8,
+                revoked_by: guardian(),
+                timestamp: T0 + 2,
+            },
+        ]
+        .into_iter(),
+    )
+    .expect("replay");
+    let live = approvals(&state, T0 + 3);
+    assert_eq!(live.len(), 1);
+    assert_eq!(live[0].request, request());
+    assert_eq!(live[0].approved_by, guardian());
+    assert_eq!(live[0].consumed_at_ns, Some(T0 + 1));
+}
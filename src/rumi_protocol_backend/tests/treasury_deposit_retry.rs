@@ -0,0 +1,99 @@
+//! Treasury deposit retries: a deposit whose `deposit()` notification
+//! failed is queued once per funding block, leaves the queue when a retry
+//! is delivered, is given up after `MAX_TREASURY_DEPOSIT_ATTEMPTS`, and the
+//! queue is capped at `MAX_PENDING_TREASURY_DEPOSITS`.
+//!
+//! Each deposit is icUSD interest minted to the treasury at some block.
+
+mod common;
+
+use common::init_arg_with;
+
+use candid::Principal;
+
+use rumi_protocol_backend::state::{
+    PendingTreasuryDeposit, MAX_PENDING_TREASURY_DEPOSITS, MAX_TREASURY_DEPOSIT_ATTEMPTS,
+};
+use rumi_protocol_backend::treasury::DepositType;
+
+fn icusd_ledger() -> Principal {
+    Principal::from_slice(&[11])
+}
+
+fn deposit(block_index: u64) -> PendingTreasuryDeposit {
+    PendingTreasuryDeposit {
+        treasury: Principal::from_slice(&[20]),
+        deposit_type: DepositType::InterestRevenue,
+        ledger: icusd_ledger(),
+        amount: 5_000,
+        block_index,
+        attempts: 0,
+    }
+}
+
+#[test]
+fn a_failed_notification_is_retried_until_delivered() {
+    let mut state = init_arg_with()
+        .icusd_ledger(icusd_ledger())
+        .treasury(Principal::from_slice(&[20]))
+        .state();
+
+    assert!(state.queue_treasury_deposit(deposit(42)));
+    // A second failure for the same block does not queue it twice.
+    assert!(state.queue_treasury_deposit(deposit(42)));
+    assert_eq!(state.pending_treasury_deposits.len(), 1);
+
+    assert_eq!(
+        state.settle_treasury_deposit_retry(icusd_ledger(), 42, false),
+        None
+    );
+    assert_eq!(
+        state.pending_treasury_deposits[&(icusd_ledger(), 42)].attempts,
+        1
+    );
+    assert_eq!(
+        state.settle_treasury_deposit_retry(icusd_ledger(), 42, true),
+        None
+    );
+    assert!(state.pending_treasury_deposits.is_empty());
+}
+
+#[test]
+fn a_deposit_is_given_up_after_the_attempt_limit() {
+    let mut state = init_arg_with()
+        .icusd_ledger(icusd_ledger())
+        .treasury(Principal::from_slice(&[20]))
+        .state();
+    state.queue_treasury_deposit(deposit(42));
+
+    for _ in 1..MAX_TREASURY_DEPOSIT_ATTEMPTS {
+        assert_eq!(
+            state.settle_treasury_deposit_retry(icusd_ledger(), 42, false),
+            None
+        );
+    }
+    let given_up = state
+        .settle_treasury_deposit_retry(icusd_ledger(), 42, false)
+        .unwrap();
+    assert_eq!(given_up.attempts, MAX_TREASURY_DEPOSIT_ATTEMPTS);
+    assert!(state.pending_treasury_deposits.is_empty());
+}
+
+#[test]
+fn the_queue_is_capped() {
+    let mut state = init_arg_with()
+        .icusd_ledger(icusd_ledger())
+        .treasury(Principal::from_slice(&[20]))
+        .state();
+    for block_index in 0..MAX_PENDING_TREASURY_DEPOSITS as u64 {
+        assert!(state.queue_treasury_deposit(deposit(block_index)));
+    }
+
+    assert!(!state.queue_treasury_deposit(deposit(MAX_PENDING_TREASURY_DEPOSITS as u64)));
+    // A deposit already queued can still be re-queued.
+    assert!(state.queue_treasury_deposit(deposit(0)));
+    assert_eq!(
+        state.pending_treasury_deposits.len(),
+        MAX_PENDING_TREASURY_DEPOSITS
+    );
+}
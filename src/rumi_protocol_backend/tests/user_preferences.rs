@@ -0,0 +1,149 @@
+//! User preferences: the defaults store nothing and change nothing, bad
+//! language tags are refused, notifications and receipt notifications
+//! follow the owner's opt-ins and alert threshold, and consent messages fall
+//! back to the stored language when the wallet sends none.
+//!
+//! ICP is at $10 (liquidation 133%, borrow threshold 150%, healthy 225%)
+//! and one owner's 10 ICP vault owes 80 icUSD (CR 125%).
+
+mod common;
+
+use candid::Principal;
+use rust_decimal_macros::dec;
+
+use rumi_protocol_backend::icrc21::Locale;
+use rumi_protocol_backend::liquidation_receipts::{issue, Settlement};
+use rumi_protocol_backend::notifications::{
+    push_notification, VaultNotification, VaultNotificationKind, VaultRiskLevel,
+};
+use rumi_protocol_backend::numeric::ICUSD;
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::user_preferences::{
+    consent_locale, preferences, set_preferences, UserPreferences, MAX_LANGUAGE_TAG_LEN,
+};
+
+use VaultRiskLevel::*;
+
+use common::{fresh_state, icp, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const NOW_NS: u64 = 1_000;
+
+fn owner() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 10.0, None);
+    state.open_vault(
+        VaultBuilder::new(1)
+            .owner(owner())
+            .collateral(10 * E8S)
+            .debt(80 * E8S)
+            .build(),
+    );
+    state
+}
+
+fn risk_change(previous_level: VaultRiskLevel, new_level: VaultRiskLevel) -> VaultNotification {
+    VaultNotification {
+        id: 0,
+        vault_id: 1,
+        collateral_type: icp(),
+        parameter: "healthy_cr".to_string(),
+        previous_level,
+        new_level,
+        collateral_ratio: 1.25,
+        created_at_ns: NOW_NS,
+        kind: VaultNotificationKind::RiskChange,
+    }
+}
+
+#[test]
+fn defaults_are_not_stored() {
+    let mut state = fixture();
+    assert_eq!(preferences(&state, owner()), UserPreferences::default());
+
+    let spanish = UserPreferences {
+        language: Some("es-MX".to_string()),
+        ..Default::default()
+    };
+    set_preferences(&mut state, owner(), spanish.clone()).unwrap();
+    assert_eq!(preferences(&state, owner()), spanish);
+
+    set_preferences(&mut state, owner(), UserPreferences::default()).unwrap();
+    assert!(state.user_preferences.is_empty());
+}
+
+#[test]
+fn bad_language_tags_are_refused() {
+    let mut state = fixture();
+    for tag in [
+        String::new(),
+        "en US".to_string(),
+        "x".repeat(MAX_LANGUAGE_TAG_LEN + 1),
+    ] {
+        let prefs = UserPreferences {
+            language: Some(tag.clone()),
+            ..Default::default()
+        };
+        assert!(
+            set_preferences(&mut state, owner(), prefs).is_err(),
+            "{:?} accepted",
+            tag
+        );
+    }
+    assert!(state.user_preferences.is_empty());
+}
+
+#[test]
+fn notifications_follow_the_owner_preferences() {
+    let mut state = fixture();
+    // Defaults keep every notification.
+    assert!(push_notification(&mut state, owner(), risk_change(Healthy, Caution)).is_some());
+
+    let prefs = UserPreferences {
+        notify_liquidations: false,
+        alert_threshold: AtRisk,
+        ..Default::default()
+    };
+    set_preferences(&mut state, owner(), prefs).unwrap();
+    assert!(push_notification(&mut state, owner(), risk_change(Healthy, Caution)).is_none());
+    assert!(push_notification(&mut state, owner(), risk_change(Caution, AtRisk)).is_some());
+    // Recovering out of the threshold band is still news.
+    assert!(push_notification(&mut state, owner(), risk_change(AtRisk, Caution)).is_some());
+    assert_eq!(state.vault_notifications[&owner()].len(), 3);
+
+    // The receipt is kept, its notification is not.
+    let before = state.vault_id_to_vaults[&1].clone();
+    let v = state.vault_id_to_vaults.get_mut(&1).unwrap();
+    v.borrowed_icusd_amount = ICUSD::new(40 * E8S);
+    v.collateral_amount -= 44 * E8S / 10;
+    let settlement = Settlement {
+        price: dec!(10),
+        ..Default::default()
+    };
+    issue(&mut state, &before, settlement, NOW_NS);
+    assert_eq!(state.liquidation_receipts[&owner()].len(), 1);
+    assert_eq!(state.vault_notifications[&owner()].len(), 3);
+}
+
+#[test]
+fn consent_messages_fall_back_to_the_stored_language() {
+    let mut state = fixture();
+    assert_eq!(consent_locale(&state, owner(), ""), Locale::En);
+
+    let prefs = UserPreferences {
+        language: Some("es".to_string()),
+        ..Default::default()
+    };
+    set_preferences(&mut state, owner(), prefs).unwrap();
+    assert_eq!(consent_locale(&state, owner(), ""), Locale::Es);
+    // The wallet's language wins when it sends one.
+    assert_eq!(consent_locale(&state, owner(), "en-GB"), Locale::En);
+    assert_eq!(
+        consent_locale(&state, Principal::anonymous(), ""),
+        Locale::En
+    );
+}
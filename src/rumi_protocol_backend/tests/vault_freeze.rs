@@ -0,0 +1,205 @@
+//! Guardian vault freezes: a freeze request is bounded and needs a reason,
+//! a live freeze blocks withdrawals/borrows/closes until it lapses or is
+//! lifted, the owner is notified, and replay rebuilds guardians and freezes.
+//!
+//! One 10 ICP vault at $10 owes 50 icUSD.
+
+mod common;
+
+use candid::Principal;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::notifications::{VaultNotificationKind, VaultRiskLevel};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault::Vault;
+use rumi_protocol_backend::vault_freeze::{
+    active_freeze, apply_freeze, apply_unfreeze, is_freeze_authority, notify_owner,
+    require_vault_not_frozen, validate_freeze_arg, FreezeVaultArg, FREEZE_NOTIFICATION_PARAMETER,
+    MAX_VAULT_FREEZE_DURATION_NS,
+};
+
+use common::{fresh_state, init_arg, set_icp_price, VaultBuilder};
+
+const E8S: u64 = 100_000_000;
+const NOW_NS: u64 = 1_000;
+const HOUR_NS: u64 = 3_600 * 1_000_000_000;
+
+fn owner() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn guardian() -> Principal {
+    Principal::from_slice(&[7])
+}
+
+fn vault() -> Vault {
+    VaultBuilder::new(1)
+        .owner(owner())
+        .collateral(10 * E8S)
+        .debt(50 * E8S)
+        .build()
+}
+
+fn fixture() -> State {
+    let mut state = fresh_state();
+    set_icp_price(&mut state, 10.0, None);
+    state.open_vault(vault());
+    state
+}
+
+fn arg(duration_secs: u64, reason: &str) -> FreezeVaultArg {
+    FreezeVaultArg {
+        vault_id: 1,
+        duration_secs,
+        reason: reason.to_string(),
+    }
+}
+
+#[test]
+fn only_guardians_and_the_developer_may_freeze() {
+    let mut state = fixture();
+    assert!(is_freeze_authority(&state, Principal::anonymous()));
+    assert!(!is_freeze_authority(&state, guardian()));
+    state.guardian_principals.insert(guardian());
+    assert!(is_freeze_authority(&state, guardian()));
+    assert!(!is_freeze_authority(&state, owner()));
+}
+
+#[test]
+fn freeze_requests_are_bounded() {
+    let state = fixture();
+    assert_eq!(
+        validate_freeze_arg(&state, &arg(3_600, "key leak"), NOW_NS),
+        Ok(NOW_NS + HOUR_NS)
+    );
+    let max_secs = MAX_VAULT_FREEZE_DURATION_NS / 1_000_000_000;
+    assert!(validate_freeze_arg(&state, &arg(max_secs, "key leak"), NOW_NS).is_ok());
+    assert!(validate_freeze_arg(&state, &arg(max_secs + 1, "key leak"), NOW_NS).is_err());
+    assert!(validate_freeze_arg(&state, &arg(0, "key leak"), NOW_NS).is_err());
+    assert!(validate_freeze_arg(&state, &arg(3_600, "  "), NOW_NS).is_err());
+    let unknown = FreezeVaultArg {
+        vault_id: 9,
+        ..arg(3_600, "key leak")
+    };
+    assert!(validate_freeze_arg(&state, &unknown, NOW_NS).is_err());
+}
+
+#[test]
+fn freeze_blocks_until_expiry() {
+    let mut state = fixture();
+    apply_freeze(
+        &mut state,
+        1,
+        guardian(),
+        "key leak".to_string(),
+        NOW_NS + HOUR_NS,
+        NOW_NS,
+    );
+    assert!(require_vault_not_frozen(&state, 1, NOW_NS).is_err());
+    assert!(require_vault_not_frozen(&state, 1, NOW_NS + HOUR_NS - 1).is_err());
+    assert!(require_vault_not_frozen(&state, 2, NOW_NS).is_ok());
+    // Lapses on its own, with no unfreeze.
+    assert!(require_vault_not_frozen(&state, 1, NOW_NS + HOUR_NS).is_ok());
+    assert!(active_freeze(&state, 1, NOW_NS + HOUR_NS).is_none());
+    // Nothing live left to lift.
+    assert!(apply_unfreeze(&mut state, 1, NOW_NS + HOUR_NS).is_err());
+}
+
+#[test]
+fn refreeze_replaces_expiry_and_unfreeze_lifts() {
+    let mut state = fixture();
+    apply_freeze(
+        &mut state,
+        1,
+        guardian(),
+        "a".to_string(),
+        NOW_NS + HOUR_NS,
+        NOW_NS,
+    );
+    apply_freeze(
+        &mut state,
+        1,
+        guardian(),
+        "b".to_string(),
+        NOW_NS + 3 * HOUR_NS,
+        NOW_NS + 1,
+    );
+    let freeze = active_freeze(&state, 1, NOW_NS + 2 * HOUR_NS).expect("still frozen");
+    assert_eq!(freeze.reason, "b");
+    assert_eq!(freeze.frozen_at_ns, NOW_NS + 1);
+
+    assert_eq!(apply_unfreeze(&mut state, 1, NOW_NS + 2), Ok(()));
+    assert!(require_vault_not_frozen(&state, 1, NOW_NS + 2).is_ok());
+}
+
+#[test]
+fn owner_is_notified() {
+    let mut state = fixture();
+    notify_owner(
+        &mut state,
+        1,
+        VaultNotificationKind::Frozen {
+            reason: "key leak".to_string(),
+            expires_at_ns: NOW_NS + HOUR_NS,
+        },
+        NOW_NS,
+    );
+    notify_owner(&mut state, 1, VaultNotificationKind::Unfrozen, NOW_NS + 1);
+    // Unknown vaults notify nobody.
+    notify_owner(&mut state, 9, VaultNotificationKind::Unfrozen, NOW_NS);
+
+    let queue = &state.vault_notifications[&owner()];
+    assert_eq!(queue.len(), 2);
+    assert_eq!(queue[0].parameter, FREEZE_NOTIFICATION_PARAMETER);
+    assert_eq!(queue[0].previous_level, queue[0].new_level);
+    assert_eq!(queue[0].new_level, VaultRiskLevel::Caution);
+    assert!(matches!(
+        queue[0].kind,
+        VaultNotificationKind::Frozen {
+            expires_at_ns,
+            ..
+        } if expires_at_ns == NOW_NS + HOUR_NS
+    ));
+    assert_eq!(queue[1].kind, VaultNotificationKind::Unfrozen);
+    assert!(queue[0].id < queue[1].id);
+}
+
+#[test]
+fn replay_rebuilds_guardians_and_freezes() {
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::OpenVault {
+            vault: vault(),
+            block_index: 0,
+            timestamp: None,
+        },
+        Event::SetGuardianPrincipals {
+            principals: vec![guardian()],
+        },
+        Event::VaultFrozen {
+            vault_id: 1,
+            frozen_by: guardian(),
+            reason: "key leak".to_string(),
+            expires_at_ns: NOW_NS + HOUR_NS,
+            timestamp: NOW_NS,
+        },
+    ];
+    let state = replay(events.clone().into_iter()).expect("replay");
+    assert!(state.guardian_principals.contains(&guardian()));
+    assert_eq!(
+        active_freeze(&state, 1, NOW_NS).map(|f| f.frozen_by),
+        Some(guardian())
+    );
+    // Notifications are not replayed.
+    assert!(state.vault_notifications.is_empty());
+
+    let mut events = events;
+    events.push(Event::VaultUnfrozen {
+        vault_id: 1,
+        unfrozen_by: guardian(),
+        timestamp: NOW_NS + 1,
+    });
+    let state = replay(events.into_iter()).expect("replay");
+    assert!(active_freeze(&state, 1, NOW_NS + 1).is_none());
+    assert!(state.vault_freezes.is_empty());
+}
@@ -0,0 +1,155 @@
+//! Vault lifecycle: moves follow the transition graph, operations are gated
+//! on the status, a vault check moves unhealthy vaults to `AtRisk` and back
+//! once they clear the recovery band, a removed vault reads as `Closed`, and
+//! the stored statuses are rebuilt by replaying `VaultStatusChanged`.
+
+mod common;
+
+use std::collections::BTreeSet;
+
+use rumi_protocol_backend::event::{replay, Event};
+use rumi_protocol_backend::state::State;
+use rumi_protocol_backend::vault_status::{
+    apply_transition, at_risk_changes, can_transition, candid_vault, check_transition,
+    require_allows, status_of, VaultOperation, VaultStatus,
+};
+use rumi_protocol_backend::ProtocolError;
+
+use common::{fresh_state, init_arg, VaultBuilder};
+
+/// Two live vaults, ids 1 and 2, each holding 10 ICP and owing 100 icUSD.
+fn fixture() -> State {
+    let mut state = fresh_state();
+    for vault_id in 1..=2u64 {
+        state.vault_id_to_vaults.insert(
+            vault_id,
+            VaultBuilder::new(vault_id)
+                .collateral(1_000_000_000)
+                .debt(10_000_000_000)
+                .build(),
+        );
+    }
+    state.next_available_vault_id = 3;
+    state
+}
+
+#[test]
+fn transition_graph() {
+    use VaultStatus::*;
+    assert!(can_transition(Active, Settling));
+    assert!(can_transition(AtRisk, Liquidating));
+    assert!(can_transition(Liquidating, AtRisk));
+    assert!(!can_transition(AtRisk, Settling));
+    assert!(!can_transition(Settling, Liquidating));
+    assert!(!can_transition(Closed, Active));
+    assert!(!can_transition(Active, Closed));
+
+    let mut state = fixture();
+    assert_eq!(check_transition(&state, 1, Liquidating), Ok(Some(Active)));
+    apply_transition(&mut state, 1, Liquidating);
+    assert_eq!(check_transition(&state, 1, Liquidating), Ok(None));
+    let err = check_transition(&state, 1, Settling).unwrap_err();
+    assert!(
+        err.contains("cannot move from Liquidating to Settling"),
+        "{}",
+        err
+    );
+    assert!(check_transition(&state, 7, AtRisk).is_err());
+}
+
+#[test]
+fn operations_are_gated_on_status() {
+    let mut state = fixture();
+    apply_transition(&mut state, 1, VaultStatus::Settling);
+    apply_transition(&mut state, 2, VaultStatus::AtRisk);
+
+    match require_allows(&state, 1, VaultOperation::AddMargin) {
+        Err(ProtocolError::GenericError(msg)) => {
+            assert!(msg.contains("Settling"), "{}", msg)
+        }
+        other => panic!("expected GenericError, got {:?}", other),
+    }
+    assert!(require_allows(&state, 1, VaultOperation::Liquidate).is_err());
+    for op in [
+        VaultOperation::Borrow,
+        VaultOperation::Repay,
+        VaultOperation::Withdraw,
+        VaultOperation::Liquidate,
+    ] {
+        assert!(require_allows(&state, 2, op).is_ok());
+    }
+
+    // The payout settled: the vault is usable again and reports it.
+    apply_transition(&mut state, 1, VaultStatus::Active);
+    assert!(require_allows(&state, 1, VaultOperation::Borrow).is_ok());
+    let vault = state.vault_id_to_vaults[&2].clone();
+    assert_eq!(candid_vault(&state, &vault).status, VaultStatus::AtRisk);
+}
+
+#[test]
+fn vault_check_moves_vaults_in_and_out_of_at_risk() {
+    let mut state = fixture();
+    apply_transition(&mut state, 2, VaultStatus::Liquidating);
+
+    // A claimed vault stays with the bot.
+    let changes = at_risk_changes(&state, &BTreeSet::from([1, 2]));
+    assert_eq!(changes, vec![(1, VaultStatus::AtRisk)]);
+    for (vault_id, to) in changes {
+        apply_transition(&mut state, vault_id, to);
+    }
+    assert!(at_risk_changes(&state, &BTreeSet::from([1, 2])).is_empty());
+
+    // Just above the 133% liquidation ratio is still inside the band.
+    let icp = state.icp_collateral_type();
+    state.collateral_configs.get_mut(&icp).unwrap().last_price = Some(13.4);
+    assert!(at_risk_changes(&state, &BTreeSet::new()).is_empty());
+
+    state.collateral_configs.get_mut(&icp).unwrap().last_price = Some(13.5);
+    assert_eq!(
+        at_risk_changes(&state, &BTreeSet::new()),
+        vec![(1, VaultStatus::Active)]
+    );
+}
+
+#[test]
+fn removed_vault_is_closed() {
+    let mut state = fixture();
+    apply_transition(&mut state, 1, VaultStatus::Settling);
+    state.remove_vault_and_unindex(1);
+
+    assert_eq!(status_of(&state, 1), VaultStatus::Closed);
+    assert!(state.vault_statuses.is_empty());
+    assert_eq!(status_of(&state, 3), VaultStatus::Active);
+    // Gating leaves unknown vaults to the operation's own not-found error.
+    assert!(require_allows(&state, 1, VaultOperation::Close).is_ok());
+}
+
+#[test]
+fn replay_rebuilds_statuses() {
+    let events = vec![
+        Event::Init(init_arg()),
+        Event::VaultStatusChanged {
+            vault_id: 4,
+            from: VaultStatus::Active,
+            to: VaultStatus::Liquidating,
+            timestamp: 1,
+        },
+        Event::VaultStatusChanged {
+            vault_id: 5,
+            from: VaultStatus::Active,
+            to: VaultStatus::AtRisk,
+            timestamp: 2,
+        },
+        Event::VaultStatusChanged {
+            vault_id: 5,
+            from: VaultStatus::AtRisk,
+            to: VaultStatus::Active,
+            timestamp: 3,
+        },
+    ];
+    let state = replay(events.into_iter()).expect("replay");
+    assert_eq!(
+        state.vault_statuses.into_iter().collect::<Vec<_>>(),
+        vec![(4, VaultStatus::Liquidating)]
+    );
+}
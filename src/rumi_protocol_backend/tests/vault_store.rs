@@ -0,0 +1,199 @@
+//! Stable vault store: a sync writes only changed vaults and removes closed
+//! ones, an owner change moves the index entry, a restore rebuilds both heap
+//! maps, a broken owner index is caught, and the pending transfer queues
+//! round-trip the same way.
+//!
+//! Alice owns two of three vaults and Bob the third, synced once.
+
+mod common;
+
+use common::{init_arg_with, VaultBuilder};
+
+use candid::Principal;
+use ic_stable_structures::VectorMemory;
+
+use rumi_protocol_backend::numeric::ICP;
+use rumi_protocol_backend::state::{PendingMarginTransfer, State};
+use rumi_protocol_backend::vault::Vault;
+use rumi_protocol_backend::vault_store::{
+    check_invariants, restore_into, restore_pending_into, sync, sync_pending,
+    StablePendingTransfers, StableVaultStore, VaultStore, VaultStoreSync,
+};
+
+const E8S: u64 = 100_000_000;
+
+type Store = StableVaultStore<VectorMemory>;
+
+fn alice() -> Principal {
+    Principal::from_slice(&[20])
+}
+
+fn bob() -> Principal {
+    Principal::from_slice(&[21])
+}
+
+fn vault(vault_id: u64, owner: Principal) -> Vault {
+    VaultBuilder::new(vault_id)
+        .owner(owner)
+        .collateral(E8S)
+        .debt(vault_id * E8S)
+        .build()
+}
+
+fn fixture() -> (State, Store) {
+    let mut state = init_arg_with()
+        .icusd_ledger(Principal::from_slice(&[1]))
+        .state();
+    state.open_vault(vault(1, alice()));
+    state.open_vault(vault(2, bob()));
+    state.open_vault(vault(3, alice()));
+    let mut store = Store::init(VectorMemory::default(), VectorMemory::default());
+    let first = sync(&mut store, &state);
+    assert_eq!((first.vaults, first.written, first.unchanged), (3, 3, 0));
+    (state, store)
+}
+
+#[test]
+fn a_sync_writes_only_what_changed() {
+    let (mut state, mut store) = fixture();
+    let again = sync(&mut store, &state);
+    assert_eq!((again.written, again.removed, again.unchanged), (0, 0, 3));
+
+    state
+        .vault_id_to_vaults
+        .get_mut(&2)
+        .unwrap()
+        .collateral_amount += 1;
+    state.close_vault(3);
+    let next = sync(&mut store, &state);
+    assert_eq!(
+        (next.vaults, next.written, next.removed, next.unchanged),
+        (2, 1, 1, 1)
+    );
+    assert_eq!(store.vault(2).unwrap().collateral_amount, E8S + 1);
+    assert_eq!(store.vault(3), None);
+    assert_eq!(store.vault_ids_of(&alice()), vec![1]);
+    assert_eq!(check_invariants(&store), Ok(()));
+}
+
+#[test]
+fn an_owner_change_moves_the_index_entry() {
+    let (_, mut store) = fixture();
+    assert!(store.put_vault(&vault(1, bob())));
+    assert_eq!(store.vault_ids_of(&alice()), vec![3]);
+    assert_eq!(store.vault_ids_of(&bob()), vec![1, 2]);
+    assert_eq!(store.owner_entry_count(), 3);
+    assert_eq!(check_invariants(&store), Ok(()));
+}
+
+#[test]
+fn a_restore_rebuilds_both_heap_maps() {
+    let (state, store) = fixture();
+    let mut restored = init_arg_with()
+        .icusd_ledger(Principal::from_slice(&[1]))
+        .state();
+    restore_into(&mut restored, &store);
+    assert_eq!(restored.vault_id_to_vaults, state.vault_id_to_vaults);
+    assert_eq!(
+        restored.principal_to_vault_ids,
+        state.principal_to_vault_ids
+    );
+    assert_eq!(restored.vault_ids(), store.vault_ids());
+    assert_eq!(restored.vault_ids_of(&alice()), vec![1, 3]);
+}
+
+#[test]
+fn a_stray_owner_entry_breaks_the_invariants() {
+    let (_, mut store) = fixture();
+    assert_eq!(store.remove_vault(2).map(|v| v.owner), Some(bob()));
+    assert_eq!(check_invariants(&store), Ok(()));
+
+    // Drop vault 2 behind the owner index's back: the vaults memory is
+    // shared, the index it is removed from is not.
+    let vaults = VectorMemory::default();
+    let owners = VectorMemory::default();
+    let mut full = Store::init(vaults.clone(), owners.clone());
+    full.put_vault(&vault(1, alice()));
+    full.put_vault(&vault(2, bob()));
+    Store::init(vaults.clone(), VectorMemory::default()).remove_vault(2);
+    let broken = Store::init(vaults, owners);
+    assert!(check_invariants(&broken).is_err());
+}
+
+fn transfer(owner: Principal, margin: u64) -> PendingMarginTransfer {
+    PendingMarginTransfer {
+        owner,
+        margin: ICP::new(margin),
+        collateral_type: Principal::from_slice(&[10]),
+        retry_count: 0,
+        op_nonce: 0,
+        trace_id: None,
+    }
+}
+
+#[test]
+fn the_pending_queues_round_trip_through_the_store() {
+    let (mut state, _) = fixture();
+    state
+        .pending_margin_transfers
+        .insert((1, alice()), transfer(alice(), E8S));
+    state
+        .pending_margin_transfers
+        .insert((1, bob()), transfer(bob(), 2 * E8S));
+    state
+        .pending_excess_transfers
+        .insert((2, bob()), transfer(bob(), 3 * E8S));
+    state
+        .pending_redemption_transfer
+        .insert(7, transfer(alice(), 4 * E8S));
+
+    let mut store = StablePendingTransfers::init(
+        VectorMemory::default(),
+        VectorMemory::default(),
+        VectorMemory::default(),
+    );
+    let mut first = VaultStoreSync::default();
+    sync_pending(&mut store, &state, &mut first);
+    assert_eq!(
+        (
+            first.pending_transfers,
+            first.pending_written,
+            first.pending_removed
+        ),
+        (4, 4, 0)
+    );
+
+    state.pending_margin_transfers.remove(&(1, bob()));
+    state
+        .pending_redemption_transfer
+        .get_mut(&7)
+        .unwrap()
+        .retry_count += 1;
+    let mut next = VaultStoreSync::default();
+    sync_pending(&mut store, &state, &mut next);
+    assert_eq!(
+        (
+            next.pending_transfers,
+            next.pending_written,
+            next.pending_removed
+        ),
+        (3, 1, 1)
+    );
+
+    let mut restored = init_arg_with()
+        .icusd_ledger(Principal::from_slice(&[1]))
+        .state();
+    restore_pending_into(&mut restored, &store);
+    assert_eq!(
+        restored.pending_margin_transfers,
+        state.pending_margin_transfers
+    );
+    assert_eq!(
+        restored.pending_excess_transfers,
+        state.pending_excess_transfers
+    );
+    assert_eq!(
+        restored.pending_redemption_transfer,
+        state.pending_redemption_transfer
+    );
+}
@@ -0,0 +1,515 @@
+//! Stable vault store, end to end: the vault maps reach stable memory at
+//! each checkpoint, a later checkpoint rewrites only the vaults that
+//! changed, and an upgrade restores them from there rather than from the
+//! snapshot.
+//!
+//! The instruction counts of the syncs are printed
+//! (`cargo test --test vault_store_pic -- --nocapture`) as the benchmark of
+//! the stable writes: the first checkpoint writes every vault, the next one
+//! only reads and compares them, and a single change writes one. Reads and
+//! writes on the hot paths cost what they did before, since they only touch
+//! the heap cache.
+//!
+//! The protocol runs against a mock XRC at $10/ICP, and one user owns
+//! `VAULTS` collateral-only vaults of 1 ICP.
+
+use candid::{decode_one, encode_args, encode_one, CandidType, Deserialize, Nat, Principal};
+use pocket_ic::{PocketIc, PocketIcBuilder, WasmResult};
+use std::time::{Duration, SystemTime};
+
+use rumi_protocol_backend::vault_store::VaultStoreStatus;
+use rumi_protocol_backend::ProtocolError;
+
+const E8S: u64 = 100_000_000;
+const VAULTS: u64 = 200;
+
+// ─── ICRC-1 candid mirrors ───
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+struct Account {
+    owner: Principal,
+    subaccount: Option<[u8; 32]>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct FeatureFlags {
+    icrc2: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct ArchiveOptions {
+    num_blocks_to_archive: u64,
+    trigger_threshold: u64,
+    controller_id: Principal,
+    max_transactions_per_response: Option<u64>,
+    max_message_size_bytes: Option<u64>,
+    cycles_for_archive_creation: Option<u64>,
+    node_max_memory_size_bytes: Option<u64>,
+    more_controller_ids: Option<Vec<Principal>>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct MetadataValue {
+    #[serde(rename = "Text")]
+    text: Option<String>,
+    #[serde(rename = "Nat")]
+    nat: Option<Nat>,
+    #[serde(rename = "Int")]
+    int: Option<i64>,
+    #[serde(rename = "Blob")]
+    blob: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct InitArgs {
+    minting_account: Account,
+    fee_collector_account: Option<Account>,
+    transfer_fee: Nat,
+    decimals: Option<u8>,
+    max_memo_length: Option<u16>,
+    token_name: String,
+    token_symbol: String,
+    metadata: Vec<(String, MetadataValue)>,
+    initial_balances: Vec<(Account, Nat)>,
+    feature_flags: Option<FeatureFlags>,
+    maximum_number_of_accounts: Option<u64>,
+    accounts_overflow_trim_quantity: Option<u64>,
+    archive_options: ArchiveOptions,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+enum LedgerArg {
+    #[serde(rename = "Init")]
+    Init(InitArgs),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct ApproveArgs {
+    from_subaccount: Option<[u8; 32]>,
+    spender: Account,
+    amount: Nat,
+    expected_allowance: Option<Nat>,
+    expires_at: Option<u64>,
+    fee: Option<Nat>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+enum ApproveError {
+    BadFee { expected_fee: Nat },
+    InsufficientFunds { balance: Nat },
+    AllowanceChanged { current_allowance: Nat },
+    Expired { ledger_time: u64 },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+// ─── Backend init / vault types ───
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct ProtocolInitArg {
+    xrc_principal: Principal,
+    icusd_ledger_principal: Principal,
+    icp_ledger_principal: Principal,
+    fee_e8s: u64,
+    developer_principal: Principal,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct UpgradeArg {
+    mode: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+enum ProtocolArgVariant {
+    Init(ProtocolInitArg),
+    Upgrade(UpgradeArg),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct VaultArg {
+    vault_id: u64,
+    amount: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct OpenVaultSuccess {
+    vault_id: u64,
+    block_index: u64,
+}
+
+// ─── Wasm fixtures ───
+
+fn icrc1_ledger_wasm() -> Vec<u8> {
+    include_bytes!("../../ledger/ic-icrc1-ledger.wasm").to_vec()
+}
+
+fn protocol_wasm() -> Vec<u8> {
+    include_bytes!("../../../target/wasm32-unknown-unknown/release/rumi_protocol_backend.wasm")
+        .to_vec()
+}
+
+fn xrc_wasm() -> Vec<u8> {
+    include_bytes!("../../xrc_demo/xrc/xrc.wasm").to_vec()
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+struct MockXRC {
+    rates: Vec<(String, u64)>,
+}
+
+fn prepare_mock_xrc() -> Vec<u8> {
+    let mock = MockXRC {
+        rates: vec![("ICP/USD".to_string(), 1_000_000_000)], // $10.00 e8s
+    };
+    encode_one(mock).expect("encode mock XRC init")
+}
+
+// ─── Helpers ───
+
+fn account(owner: Principal) -> Account {
+    Account {
+        owner,
+        subaccount: None,
+    }
+}
+
+fn deploy_icrc1_ledger(
+    pic: &PocketIc,
+    minting_account: Account,
+    transfer_fee: u64,
+    initial_balances: Vec<(Account, Nat)>,
+    name: &str,
+    symbol: &str,
+    controller: Principal,
+) -> Principal {
+    let ledger_id = pic.create_canister();
+    pic.add_cycles(ledger_id, 2_000_000_000_000);
+    let init = InitArgs {
+        minting_account,
+        fee_collector_account: None,
+        transfer_fee: Nat::from(transfer_fee),
+        decimals: Some(8),
+        max_memo_length: Some(64),
+        token_name: name.into(),
+        token_symbol: symbol.into(),
+        metadata: vec![],
+        initial_balances,
+        feature_flags: Some(FeatureFlags { icrc2: true }),
+        maximum_number_of_accounts: None,
+        accounts_overflow_trim_quantity: None,
+        archive_options: ArchiveOptions {
+            num_blocks_to_archive: 2000,
+            trigger_threshold: 1000,
+            controller_id: controller,
+            max_transactions_per_response: None,
+            max_message_size_bytes: None,
+            cycles_for_archive_creation: None,
+            node_max_memory_size_bytes: None,
+            more_controller_ids: None,
+        },
+    };
+    pic.install_canister(
+        ledger_id,
+        icrc1_ledger_wasm(),
+        encode_args((LedgerArg::Init(init),)).expect("encode ledger init"),
+        None,
+    );
+    ledger_id
+}
+
+fn icrc2_approve_call(
+    pic: &PocketIc,
+    ledger: Principal,
+    sender: Principal,
+    spender: Principal,
+    amount: u128,
+) {
+    let args = ApproveArgs {
+        from_subaccount: None,
+        spender: account(spender),
+        amount: Nat::from(amount),
+        expected_allowance: None,
+        expires_at: None,
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    };
+    let result = pic
+        .update_call(ledger, sender, "icrc2_approve", encode_one(args).unwrap())
+        .expect("icrc2_approve call failed");
+    let parsed: Result<Nat, ApproveError> = match result {
+        WasmResult::Reply(b) => decode_one(&b).expect("decode icrc2_approve"),
+        WasmResult::Reject(m) => panic!("icrc2_approve rejected: {}", m),
+    };
+    parsed.expect("approve returned error");
+}
+
+// ─── Fixture ───
+
+struct Fixture {
+    pic: PocketIc,
+    protocol_id: Principal,
+    developer: Principal,
+    test_user: Principal,
+}
+
+/// Stand up the protocol with a mock XRC at $10/ICP, mint a fat ICP
+/// balance to `test_user`, and pre-approve the protocol for that
+/// allowance. Borrowing fee + interest curves are zeroed so opens are
+/// exact and don't drift the test math.
+fn setup_fixture(initial_icp_e8s: u128) -> Fixture {
+    let pic = PocketIcBuilder::new().with_nns_subnet().build();
+
+    let test_user = Principal::self_authenticating(b"vault_store_test_user");
+    let developer = Principal::self_authenticating(b"vault_store_developer");
+
+    let protocol_id = pic.create_canister();
+    pic.add_cycles(protocol_id, 2_000_000_000_000);
+    pic.set_controllers(protocol_id, None, vec![Principal::anonymous(), developer])
+        .expect("set_controllers failed");
+
+    let icp_ledger = deploy_icrc1_ledger(
+        &pic,
+        account(protocol_id),
+        10_000,
+        vec![(account(test_user), Nat::from(initial_icp_e8s))],
+        "Internet Computer Protocol",
+        "ICP",
+        developer,
+    );
+
+    let icusd_ledger = deploy_icrc1_ledger(
+        &pic,
+        account(protocol_id),
+        0,
+        vec![],
+        "icUSD",
+        "icUSD",
+        developer,
+    );
+
+    let xrc_id = pic.create_canister();
+    pic.add_cycles(xrc_id, 1_000_000_000_000);
+    pic.install_canister(xrc_id, xrc_wasm(), prepare_mock_xrc(), None);
+
+    pic.set_time(SystemTime::UNIX_EPOCH + Duration::from_secs(1_711_324_800));
+
+    let init = ProtocolArgVariant::Init(ProtocolInitArg {
+        fee_e8s: 10_000,
+        icp_ledger_principal: icp_ledger,
+        xrc_principal: xrc_id,
+        icusd_ledger_principal: icusd_ledger,
+        developer_principal: developer,
+    });
+    pic.install_canister(
+        protocol_id,
+        protocol_wasm(),
+        encode_args((init,)).expect("encode protocol init"),
+        None,
+    );
+
+    pic.advance_time(Duration::from_secs(1));
+    for _ in 0..10 {
+        pic.tick();
+    }
+
+    // Zero out fee/interest curves so the test math stays exact.
+    let _ = pic
+        .update_call(
+            protocol_id,
+            developer,
+            "set_borrowing_fee_curve",
+            encode_args((None::<String>,)).unwrap(),
+        )
+        .expect("set_borrowing_fee_curve");
+    let _ = pic
+        .update_call(
+            protocol_id,
+            developer,
+            "set_rate_curve_markers",
+            encode_args((None::<Principal>, vec![(1.5f64, 1.0f64), (3.0f64, 1.0f64)])).unwrap(),
+        )
+        .expect("set_rate_curve_markers");
+    let _ = pic
+        .update_call(
+            protocol_id,
+            developer,
+            "set_borrowing_fee",
+            encode_args((0.0f64,)).unwrap(),
+        )
+        .expect("set_borrowing_fee");
+
+    // Approve once with the full balance so subsequent open_vaults can
+    // pull collateral without a fresh approve per call.
+    icrc2_approve_call(&pic, icp_ledger, test_user, protocol_id, initial_icp_e8s);
+
+    Fixture {
+        pic,
+        protocol_id,
+        developer,
+        test_user,
+    }
+}
+
+fn open_collateral_only_vault(f: &Fixture, collateral_e8s: u64) -> u64 {
+    let result = f
+        .pic
+        .update_call(
+            f.protocol_id,
+            f.test_user,
+            "open_vault",
+            encode_args((collateral_e8s, None::<Principal>)).unwrap(),
+        )
+        .expect("open_vault failed");
+    match result {
+        WasmResult::Reply(bytes) => {
+            let r: Result<OpenVaultSuccess, ProtocolError> =
+                decode_one(&bytes).expect("decode open_vault");
+            r.expect("open_vault returned error").vault_id
+        }
+        WasmResult::Reject(msg) => panic!("open_vault rejected: {}", msg),
+    }
+}
+
+fn add_margin(f: &Fixture, vault_id: u64, amount_e8s: u64) {
+    let result = f
+        .pic
+        .update_call(
+            f.protocol_id,
+            f.test_user,
+            "add_margin_to_vault",
+            encode_args((VaultArg {
+                vault_id,
+                amount: amount_e8s,
+            },))
+            .unwrap(),
+        )
+        .expect("add_margin call failed");
+    if let WasmResult::Reject(m) = result {
+        panic!("add_margin rejected: {}", m);
+    }
+}
+
+fn take_checkpoint(f: &Fixture) {
+    let result = f
+        .pic
+        .update_call(
+            f.protocol_id,
+            f.developer,
+            "take_state_checkpoint",
+            encode_args(()).unwrap(),
+        )
+        .expect("take_state_checkpoint call failed");
+    match result {
+        WasmResult::Reply(b) => {
+            let r: Result<candid::Reserved, ProtocolError> =
+                decode_one(&b).expect("decode take_state_checkpoint");
+            r.expect("take_state_checkpoint returned error");
+        }
+        WasmResult::Reject(m) => panic!("take_state_checkpoint rejected: {}", m),
+    }
+}
+
+fn vault_store_status(f: &Fixture) -> VaultStoreStatus {
+    let result = f
+        .pic
+        .query_call(
+            f.protocol_id,
+            Principal::anonymous(),
+            "get_vault_store_status",
+            encode_args(()).unwrap(),
+        )
+        .expect("get_vault_store_status query failed");
+    match result {
+        WasmResult::Reply(b) => decode_one(&b).expect("decode get_vault_store_status"),
+        WasmResult::Reject(m) => panic!("get_vault_store_status rejected: {}", m),
+    }
+}
+
+fn query_get_vault_count(f: &Fixture) -> u64 {
+    let result = f
+        .pic
+        .query_call(
+            f.protocol_id,
+            Principal::anonymous(),
+            "get_vault_count",
+            encode_args(()).unwrap(),
+        )
+        .expect("get_vault_count query failed");
+    match result {
+        WasmResult::Reply(b) => decode_one(&b).expect("decode get_vault_count"),
+        WasmResult::Reject(m) => panic!("get_vault_count rejected: {}", m),
+    }
+}
+
+#[test]
+fn checkpoints_write_only_changed_vaults_and_upgrades_restore_them() {
+    let f = setup_fixture(((VAULTS + 10) * E8S) as u128);
+    let ids: Vec<u64> = (0..VAULTS)
+        .map(|_| open_collateral_only_vault(&f, E8S))
+        .collect();
+
+    let before = vault_store_status(&f);
+    assert_eq!(before.heap_vaults, VAULTS);
+    assert_eq!(before.stable_vaults, 0);
+
+    take_checkpoint(&f);
+    let first = vault_store_status(&f).last_sync.expect("first sync");
+    assert_eq!(
+        (first.vaults, first.written, first.unchanged),
+        (VAULTS, VAULTS, 0)
+    );
+
+    take_checkpoint(&f);
+    let second = vault_store_status(&f).last_sync.expect("second sync");
+    assert_eq!((second.written, second.unchanged), (0, VAULTS));
+    assert!(
+        second.instructions < first.instructions,
+        "an unchanged sync ({}) must be cheaper than the first ({})",
+        second.instructions,
+        first.instructions
+    );
+
+    add_margin(&f, ids[0], E8S / 10);
+    take_checkpoint(&f);
+    let third = vault_store_status(&f).last_sync.expect("third sync");
+    assert_eq!((third.written, third.removed), (1, 0));
+
+    println!(
+        "vault store sync over {} vaults: first {} instructions ({} written), \
+         unchanged {} instructions, one change {} instructions",
+        VAULTS, first.instructions, first.written, second.instructions, third.instructions
+    );
+
+    let upgrade_arg = ProtocolArgVariant::Upgrade(UpgradeArg {
+        mode: None,
+        description: Some("vault store PIC upgrade".to_string()),
+    });
+    f.pic
+        .upgrade_canister(
+            f.protocol_id,
+            protocol_wasm(),
+            encode_args((upgrade_arg,)).expect("encode upgrade"),
+            None,
+        )
+        .expect("upgrade_canister failed");
+    f.pic.tick();
+
+    assert_eq!(query_get_vault_count(&f), VAULTS);
+    let after = vault_store_status(&f);
+    assert_eq!(after.heap_vaults, VAULTS);
+    assert_eq!(after.stable_vaults, VAULTS);
+    assert_eq!(after.stable_owner_entries, VAULTS);
+
+    // The restored vaults are live: a change after the upgrade is synced.
+    add_margin(&f, ids[1], E8S / 10);
+    take_checkpoint(&f);
+    let fourth = vault_store_status(&f).last_sync.expect("post-upgrade sync");
+    assert_eq!((fourth.vaults, fourth.written), (VAULTS, 1));
+}
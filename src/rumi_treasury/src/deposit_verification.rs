@@ -0,0 +1,357 @@
+//! Verification of deposits against the ledger block that funded them.
+//!
+//! A deposit names the block of its funding transfer. Before anything is
+//! credited the block is fetched with `icrc3_get_blocks`, following the
+//! ledger's archives for old blocks, and must be a transfer or mint into the
+//! treasury's main account of exactly the deposited amount. A block funds a
+//! single deposit: `TreasuryState::claimed_deposit_blocks` keeps the deposit
+//! each (ledger, block) was credited to and a second claim is rejected.
+//!
+//! A ledger that does not serve `icrc3_get_blocks` (the ICP ledger) is read
+//! with `query_blocks` instead, where recipients are account identifiers;
+//! the treasury's own identifier comes from the ledger's
+//! `account_identifier`. A deposit neither source can verify is rejected and
+//! left for the depositor to retry.
+
+use candid::{CandidType, Deserialize, Nat, Principal};
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::icrc3::blocks::{BlockWithId, GetBlocksRequest, GetBlocksResult};
+
+/// The fields of a ledger block a deposit is checked against.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LedgerTransfer {
+    /// Operation with the schema version stripped (`"1xfer"` → `"xfer"`).
+    pub op: String,
+    pub to: Option<Account>,
+    pub amount: u128,
+}
+
+/// Read the operation, destination and amount out of an ICRC-3 block.
+/// Accepts both the standard ledger layout (top-level `btype`) and the one
+/// that only carries `tx.op`.
+pub fn decode_transfer(block: &ICRC3Value) -> Result<LedgerTransfer, String> {
+    let ICRC3Value::Map(block) = block else {
+        return Err("block is not a Map".to_string());
+    };
+    let Some(ICRC3Value::Map(tx)) = block.get("tx") else {
+        return Err("block has no 'tx' Map".to_string());
+    };
+    let op = match (block.get("btype"), tx.get("op")) {
+        (Some(ICRC3Value::Text(op)), _) | (None, Some(ICRC3Value::Text(op))) => op
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .to_ascii_lowercase(),
+        _ => return Err("block has neither 'btype' nor 'tx.op'".to_string()),
+    };
+    let to = tx.get("to").map(account_from_value).transpose()?;
+    let amount = match tx.get("amt") {
+        Some(ICRC3Value::Nat(amount)) => u128::try_from(amount.0.clone())
+            .map_err(|_| format!("amount {} does not fit in u128", amount))?,
+        _ => return Err("tx has no 'amt' Nat".to_string()),
+    };
+    Ok(LedgerTransfer { op, to, amount })
+}
+
+/// Check that `transfer` paid `amount` into `treasury`'s main account.
+pub fn validate_deposit(
+    transfer: &LedgerTransfer,
+    treasury: Principal,
+    amount: u64,
+) -> Result<(), String> {
+    check_operation(&transfer.op)?;
+    let expected_to = Account {
+        owner: treasury,
+        subaccount: None,
+    };
+    match transfer.to {
+        Some(to) if to == expected_to => {}
+        Some(to) => return Err(format!("block pays {}, not the treasury", to)),
+        None => return Err("block has no recipient".to_string()),
+    }
+    check_amount(transfer.amount, amount)
+}
+
+/// The fields of an ICP ledger block a deposit is checked against. The
+/// recipient is an account identifier rather than an account.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IcpLedgerTransfer {
+    pub op: String,
+    pub to: Option<Vec<u8>>,
+    pub amount: u128,
+}
+
+/// Read the operation, destination and amount out of an ICP ledger block.
+pub fn decode_icp_transfer(block: &IcpBlock) -> Result<IcpLedgerTransfer, String> {
+    match &block.transaction.operation {
+        Some(IcpOperation::Transfer { to, amount, .. }) => Ok(IcpLedgerTransfer {
+            op: "transfer".to_string(),
+            to: Some(to.clone()),
+            amount: amount.e8s as u128,
+        }),
+        Some(IcpOperation::Mint { to, amount }) => Ok(IcpLedgerTransfer {
+            op: "mint".to_string(),
+            to: Some(to.clone()),
+            amount: amount.e8s as u128,
+        }),
+        Some(IcpOperation::Burn(_)) => Ok(IcpLedgerTransfer {
+            op: "burn".to_string(),
+            to: None,
+            amount: 0,
+        }),
+        Some(IcpOperation::Approve(_)) => Ok(IcpLedgerTransfer {
+            op: "approve".to_string(),
+            to: None,
+            amount: 0,
+        }),
+        None => Err("block has no operation".to_string()),
+    }
+}
+
+/// Check that `transfer` paid `amount` into the account identified by
+/// `treasury_account_id`.
+pub fn validate_icp_deposit(
+    transfer: &IcpLedgerTransfer,
+    treasury_account_id: &[u8],
+    amount: u64,
+) -> Result<(), String> {
+    check_operation(&transfer.op)?;
+    match &transfer.to {
+        Some(to) if to.as_slice() == treasury_account_id => {}
+        Some(to) => return Err(format!("block pays {}, not the treasury", hex(to))),
+        None => return Err("block has no recipient".to_string()),
+    }
+    check_amount(transfer.amount, amount)
+}
+
+fn check_operation(op: &str) -> Result<(), String> {
+    if matches!(op, "xfer" | "transfer" | "mint") {
+        Ok(())
+    } else {
+        Err(format!("block is a {}, not a transfer", op))
+    }
+}
+
+fn check_amount(in_block: u128, amount: u64) -> Result<(), String> {
+    if in_block != amount as u128 {
+        return Err(format!(
+            "block amount {} does not match the deposit amount {}",
+            in_block, amount
+        ));
+    }
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Fetch block `block_index` from `ledger` and check that it funds a deposit
+/// of `amount` to this canister.
+pub async fn verify_deposit_block(
+    ledger: Principal,
+    block_index: u64,
+    amount: u64,
+) -> Result<(), String> {
+    let checked = match fetch_block(ledger, block_index).await {
+        Ok(block) => decode_transfer(&block)
+            .and_then(|transfer| validate_deposit(&transfer, ic_cdk::api::id(), amount)),
+        Err(icrc3_error) => verify_icp_block(ledger, block_index, amount)
+            .await
+            .map_err(|e| format!("{}; query_blocks: {}", icrc3_error, e)),
+    };
+    checked.map_err(|e| format!("block {} on {}: {}", block_index, ledger, e))
+}
+
+async fn verify_icp_block(ledger: Principal, block_index: u64, amount: u64) -> Result<(), String> {
+    let block = fetch_icp_block(ledger, block_index).await?;
+    let transfer = decode_icp_transfer(&block)?;
+    let treasury = Account {
+        owner: ic_cdk::api::id(),
+        subaccount: None,
+    };
+    let (treasury_account_id,): (Vec<u8>,) =
+        ic_cdk::call(ledger, "account_identifier", (treasury,))
+            .await
+            .map_err(|(code, msg)| {
+                format!(
+                    "account_identifier failed on {}: {:?} {}",
+                    ledger, code, msg
+                )
+            })?;
+    validate_icp_deposit(&transfer, &treasury_account_id, amount)
+}
+
+async fn fetch_block(ledger: Principal, block_index: u64) -> Result<ICRC3Value, String> {
+    let request = vec![GetBlocksRequest {
+        start: Nat::from(block_index),
+        length: Nat::from(1u64),
+    }];
+    let (result,): (GetBlocksResult,) = ic_cdk::call(ledger, "icrc3_get_blocks", (request,))
+        .await
+        .map_err(|(code, msg)| {
+            format!("icrc3_get_blocks failed on {}: {:?} {}", ledger, code, msg)
+        })?;
+    if let Some(block) = find_block(result.blocks, block_index) {
+        return Ok(block);
+    }
+    // Blocks moved to an archive are served by the archive canister.
+    for archived in result.archived_blocks {
+        let (archive_result,): (GetBlocksResult,) = ic_cdk::call(
+            archived.callback.canister_id,
+            &archived.callback.method,
+            (archived.args,),
+        )
+        .await
+        .map_err(|(code, msg)| {
+            format!(
+                "{} failed on archive {}: {:?} {}",
+                archived.callback.method, archived.callback.canister_id, code, msg
+            )
+        })?;
+        if let Some(block) = find_block(archive_result.blocks, block_index) {
+            return Ok(block);
+        }
+    }
+    Err(format!("{} has no block {}", ledger, block_index))
+}
+
+async fn fetch_icp_block(ledger: Principal, block_index: u64) -> Result<IcpBlock, String> {
+    let args = IcpGetBlocksArgs {
+        start: block_index,
+        length: 1,
+    };
+    let (response,): (IcpQueryBlocksResponse,) =
+        ic_cdk::call(ledger, "query_blocks", (args.clone(),))
+            .await
+            .map_err(|(code, msg)| {
+                format!("query_blocks failed on {}: {:?} {}", ledger, code, msg)
+            })?;
+    if block_index >= response.first_block_index {
+        let offset = (block_index - response.first_block_index) as usize;
+        return response
+            .blocks
+            .into_iter()
+            .nth(offset)
+            .ok_or_else(|| format!("{} has no block {}", ledger, block_index));
+    }
+    // Blocks moved to an archive are served by the archive canister.
+    for archived in response.archived_blocks {
+        if !(archived.start..archived.start + archived.length).contains(&block_index) {
+            continue;
+        }
+        let (result,): (IcpArchiveResult,) = ic_cdk::call(
+            archived.callback.0.principal,
+            &archived.callback.0.method,
+            (args.clone(),),
+        )
+        .await
+        .map_err(|(code, msg)| {
+            format!(
+                "{} failed on archive {}: {:?} {}",
+                archived.callback.0.method, archived.callback.0.principal, code, msg
+            )
+        })?;
+        return match result {
+            IcpArchiveResult::Ok(range) => range
+                .blocks
+                .into_iter()
+                .next()
+                .ok_or_else(|| format!("archive has no block {}", block_index)),
+            IcpArchiveResult::Err(error) => Err(format!(
+                "archive rejected block {}: {:?}",
+                block_index, error
+            )),
+        };
+    }
+    Err(format!("{} has no block {}", ledger, block_index))
+}
+
+fn find_block(blocks: Vec<BlockWithId>, block_index: u64) -> Option<ICRC3Value> {
+    blocks
+        .into_iter()
+        .find(|b| b.id == Nat::from(block_index))
+        .map(|b| b.block)
+}
+
+fn account_from_value(value: &ICRC3Value) -> Result<Account, String> {
+    let ICRC3Value::Array(parts) = value else {
+        return Err("account is not an Array".to_string());
+    };
+    let owner = match parts.first() {
+        Some(ICRC3Value::Blob(owner)) => Principal::try_from_slice(&owner[..])
+            .map_err(|e| format!("invalid account owner: {}", e))?,
+        _ => return Err("account has no owner Blob".to_string()),
+    };
+    let subaccount = match parts.get(1) {
+        None => None,
+        Some(ICRC3Value::Blob(subaccount)) => Some(
+            <[u8; 32]>::try_from(&subaccount[..])
+                .map_err(|_| "subaccount is not 32 bytes".to_string())?,
+        ),
+        Some(_) => return Err("account subaccount is not a Blob".to_string()),
+    };
+    if parts.len() > 2 {
+        return Err(format!("account has {} parts", parts.len()));
+    }
+    Ok(Account { owner, subaccount })
+}
+
+// ─── ICP ledger `query_blocks` types (the fields deposits need) ───
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct IcpGetBlocksArgs {
+    start: u64,
+    length: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct IcpTokens {
+    pub e8s: u64,
+}
+
+/// An ICP ledger operation. Burns and approvals never fund a deposit, so
+/// their payloads are not decoded.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum IcpOperation {
+    Transfer { to: Vec<u8>, amount: IcpTokens },
+    Mint { to: Vec<u8>, amount: IcpTokens },
+    Burn(candid::Reserved),
+    Approve(candid::Reserved),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct IcpTransaction {
+    pub operation: Option<IcpOperation>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct IcpBlock {
+    pub transaction: IcpTransaction,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct IcpBlockRange {
+    blocks: Vec<IcpBlock>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+enum IcpArchiveResult {
+    Ok(IcpBlockRange),
+    Err(candid::Reserved),
+}
+
+candid::define_function!(IcpArchiveFn : (IcpGetBlocksArgs) -> (IcpArchiveResult) query);
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct IcpArchivedBlocksRange {
+    start: u64,
+    length: u64,
+    callback: IcpArchiveFn,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct IcpQueryBlocksResponse {
+    first_block_index: u64,
+    blocks: Vec<IcpBlock>,
+    archived_blocks: Vec<IcpArchivedBlocksRange>,
+}
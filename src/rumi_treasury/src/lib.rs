@@ -1,3 +1,4 @@
+mod deposit_verification;
 mod state;
 mod types;
 
@@ -10,12 +11,17 @@ use ic_cdk::api::caller;
 use ic_cdk::{init, post_upgrade, pre_upgrade, query, update};
 use icrc_ledger_types::icrc1::account::Account;
 use icrc_ledger_types::icrc1::transfer::{TransferArg, TransferError};
+use rumi_common::error::ProtocolError;
 use state::{init_state, restore_state, with_state, with_state_mut};
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::time::Duration;
 use types::{
-    AssetType, DepositArgs, DepositRecord, TreasuryAction, TreasuryEvent, TreasuryInitArgs,
-    TreasuryStatus, WithdrawArgs, WithdrawResult,
+    AssetKind, CreatePaymentStreamArgs, DepositArgs, DepositRecord, ModeInheritancePolicy,
+    ModeInheritanceStatus, PaymentStream, Proposal, ProposalAction, ProposalStatus, ProtocolMode,
+    ReconciliationReport, SignerConfig, StreamStatus, TreasuryAction, TreasuryAsset, TreasuryEvent,
+    TreasuryInitArgs, TreasuryStatus, TreasuryWithdrawalRequest, WithdrawArgs, WithdrawResult,
+    WithdrawalDestination,
 };
 
 // Declare log buffer for debugging
@@ -26,11 +32,61 @@ declare_log_buffer!(name = LOG, capacity = 1000);
 /// treasury solvent (we send slightly less) rather than risking an over-send.
 const DEFAULT_LEDGER_FEE_E8S: u64 = 10_000;
 
+/// Delay before a newly added withdrawal destination can receive funds. A
+/// compromised controller key cannot withdraw to an address of its own
+/// before this passes, which leaves time to spot the
+/// `WithdrawalDestinationScheduled` event and rotate controllers.
+const WITHDRAWAL_DESTINATION_TIMELOCK_NANOS: u64 = 48 * 60 * 60 * 1_000_000_000;
+
+/// Longest label accepted for a withdrawal destination.
+const MAX_DESTINATION_LABEL_LEN: usize = 64;
+
+/// Longest symbol accepted for a registered asset.
+const MAX_ASSET_SYMBOL_LEN: usize = 32;
+
+/// How often tracked balances are reconciled against the ledgers (6 hours).
+const RECONCILIATION_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// How often due payment-stream payments are made (1 hour).
+const STREAM_PAYOUT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Start of the error a withdrawal returns when the ledger call failed in
+/// transport and the transfer may have landed.
+const TRANSPORT_ERROR_PREFIX: &str = "Transport error";
+
 thread_local! {
     /// Per-ledger transfer-fee cache, populated lazily from `icrc1_fee` on the
     /// first withdrawal against a ledger. Heap-only (not persisted), so it is
     /// simply re-warmed after an upgrade.
     static LEDGER_FEES: RefCell<HashMap<Principal, u64>> = RefCell::new(HashMap::new());
+
+    /// Set while a reconciliation is awaiting ledger responses, so the
+    /// timer and `reconcile_balances` never run two at once.
+    static RECONCILING: RefCell<bool> = const { RefCell::new(false) };
+
+    /// Set while due stream payments are being made, so two timer runs
+    /// never pay the same period.
+    static PAYING_STREAMS: RefCell<bool> = const { RefCell::new(false) };
+}
+
+/// Holds `PAYING_STREAMS` for one run of `pay_due_streams`. Released on
+/// drop, so a trap or early return after an await cannot leave it set.
+#[must_use]
+struct PayingStreamsGuard(());
+
+impl PayingStreamsGuard {
+    fn new() -> Option<Self> {
+        if PAYING_STREAMS.with(|p| p.replace(true)) {
+            return None;
+        }
+        Some(PayingStreamsGuard(()))
+    }
+}
+
+impl Drop for PayingStreamsGuard {
+    fn drop(&mut self) {
+        PAYING_STREAMS.with(|p| *p.borrow_mut() = false);
+    }
 }
 
 /// Fetch a ledger's transfer fee, caching the result per ledger. On query
@@ -64,6 +120,209 @@ fn withdrawal_send_amount(amount: u64, fee: u64) -> Result<u64, String> {
     Ok(amount - fee)
 }
 
+/// Register an asset first seen in a deposit from its ledger's ICRC-1
+/// metadata, as a fungible token.
+async fn register_asset_from_ledger(ledger: Principal) -> Result<TreasuryAsset, String> {
+    let (symbol,): (String,) = ic_cdk::call(ledger, "icrc1_symbol", ())
+        .await
+        .map_err(|(code, msg)| format!("icrc1_symbol failed on {}: {:?} {}", ledger, code, msg))?;
+    let (decimals,): (u8,) =
+        ic_cdk::call(ledger, "icrc1_decimals", ())
+            .await
+            .map_err(|(code, msg)| {
+                format!("icrc1_decimals failed on {}: {:?} {}", ledger, code, msg)
+            })?;
+    let asset = TreasuryAsset {
+        ledger,
+        symbol: symbol.chars().take(MAX_ASSET_SYMBOL_LEN).collect(),
+        decimals,
+        kind: AssetKind::Fungible,
+    };
+    // Another deposit may have registered it while the calls were in flight.
+    if let Some(existing) = with_state(|s| s.asset(&ledger)) {
+        return Ok(existing);
+    }
+    with_state_mut(|s| s.register_asset(asset.clone()))?;
+    log!(
+        LOG,
+        "Registered asset {} ({}) on first deposit",
+        asset.symbol,
+        ledger
+    );
+    with_state_mut(|s| {
+        s.push_event(
+            ic_cdk::api::id(),
+            TreasuryAction::AssetRegistered {
+                asset: asset.clone(),
+            },
+        )
+    });
+    Ok(asset)
+}
+
+/// Activate the withdrawal destinations whose timelock has passed and log
+/// an event for each.
+fn activate_due_withdrawal_destinations() {
+    let now = ic_cdk::api::time();
+    let activated = with_state_mut(|s| s.activate_due_withdrawal_destinations(now));
+    for to in activated {
+        log!(LOG, "Withdrawal destination {} is now active", to);
+        with_state_mut(|s| {
+            s.push_event(
+                ic_cdk::api::id(),
+                TreasuryAction::WithdrawalDestinationActivated { to },
+            )
+        });
+    }
+}
+
+/// Timelock scheduler: run `activate_due_withdrawal_destinations` once
+/// `active_at` has passed. Timers do not survive an upgrade, so
+/// `post_upgrade` re-arms one per pending destination; `withdraw` also
+/// activates due destinations itself, so a lost timer only delays the event.
+fn schedule_destination_activation(active_at: u64) {
+    let delay = active_at.saturating_sub(ic_cdk::api::time());
+    ic_cdk_timers::set_timer(
+        Duration::from_nanos(delay),
+        activate_due_withdrawal_destinations,
+    );
+}
+
+/// Query every reconcilable asset's ledger for the treasury account's
+/// balance, store the comparison with the tracked totals as the latest
+/// report and log a `BalanceDiscrepancy` event for each new delta.
+///
+/// Tracked totals are read after the ledgers answer, so a deposit or
+/// withdrawal settling in between shows up as a transient delta that the
+/// next run clears. A positive delta is usually a transfer to the treasury
+/// that was never recorded with `deposit`; a negative one means the books
+/// promise more than the treasury holds.
+async fn reconcile() -> Result<ReconciliationReport, String> {
+    if RECONCILING.with(|r| r.replace(true)) {
+        return Err("A reconciliation is already running".to_string());
+    }
+    let treasury = Account {
+        owner: ic_cdk::api::id(),
+        subaccount: None,
+    };
+    let mut results = vec![];
+    for asset in with_state(|s| s.reconcilable_assets()) {
+        let result: Result<(candid::Nat,), _> =
+            ic_cdk::call(asset.ledger, "icrc1_balance_of", (treasury,)).await;
+        let balance = match result {
+            Ok((balance,)) => u64::try_from(balance.0)
+                .map_err(|_| format!("Balance on {} does not fit in u64", asset.ledger)),
+            Err((code, msg)) => Err(format!(
+                "icrc1_balance_of failed on {}: {:?} {}",
+                asset.ledger, code, msg
+            )),
+        };
+        results.push((asset.ledger, balance));
+    }
+    let now = ic_cdk::api::time();
+    let discrepancies = with_state_mut(|s| s.record_reconciliation(results, now));
+    RECONCILING.with(|r| *r.borrow_mut() = false);
+    for entry in discrepancies {
+        log!(
+            LOG,
+            "RECONCILIATION: {} ({}) tracked {} but the ledger holds {} (delta {})",
+            entry.symbol,
+            entry.ledger,
+            entry.tracked,
+            entry.actual.unwrap_or_default(),
+            entry.delta
+        );
+        with_state_mut(|s| {
+            s.push_event(
+                ic_cdk::api::id(),
+                TreasuryAction::BalanceDiscrepancy {
+                    ledger: entry.ledger,
+                    tracked: entry.tracked,
+                    actual: entry.actual.unwrap_or_default(),
+                    delta: entry.delta,
+                },
+            )
+        });
+    }
+    Ok(with_state(|s| s.reconciliation_report()))
+}
+
+/// Run `reconcile` every `RECONCILIATION_INTERVAL`. Timers do not survive
+/// an upgrade, so both `init` and `post_upgrade` arm it.
+fn schedule_reconciliation() {
+    ic_cdk_timers::set_timer_interval(RECONCILIATION_INTERVAL, || {
+        ic_cdk::spawn(async {
+            if let Err(e) = reconcile().await {
+                log!(LOG, "Scheduled reconciliation skipped: {}", e);
+            }
+        })
+    });
+}
+
+/// Make the next payment of every stream that is due, one period per
+/// stream per run, so a stream behind by several periods catches up over as
+/// many runs. A failed payment is retried on the next run, except after a
+/// transport error: the transfer may have landed and a retry would debit
+/// the balance again, so the stream halts.
+async fn pay_due_streams() {
+    let Some(_guard) = PayingStreamsGuard::new() else {
+        return;
+    };
+    let treasury = ic_cdk::api::id();
+    for (id, args) in with_state(|s| s.due_stream_payments(ic_cdk::api::time())) {
+        // Skip a stream cancelled while an earlier payment was in flight.
+        let active = with_state(|s| s.get_stream(id))
+            .is_some_and(|stream| stream.status == StreamStatus::Active);
+        if !active {
+            continue;
+        }
+        let amount = args.amount;
+        match execute_withdrawal(treasury, args).await {
+            Ok(result) => {
+                log!(
+                    LOG,
+                    "Stream {} paid {} (block {})",
+                    id,
+                    amount,
+                    result.block_index
+                );
+                with_state_mut(|s| {
+                    let completed = s.record_stream_payment(id);
+                    s.push_event(
+                        treasury,
+                        TreasuryAction::StreamPayment {
+                            id,
+                            amount,
+                            block_index: result.block_index,
+                        },
+                    );
+                    if completed {
+                        s.push_event(treasury, TreasuryAction::StreamCompleted { id });
+                    }
+                });
+            }
+            Err(error) => {
+                let halt = error.starts_with(TRANSPORT_ERROR_PREFIX);
+                log!(LOG, "Stream {} payment failed: {}", id, error);
+                with_state_mut(|s| {
+                    let new_error = s.record_stream_failure(id, error.clone(), halt);
+                    if halt {
+                        s.push_event(treasury, TreasuryAction::StreamHalted { id, error });
+                    } else if new_error {
+                        s.push_event(treasury, TreasuryAction::StreamPaymentFailed { id, error });
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Run `pay_due_streams` every `STREAM_PAYOUT_INTERVAL`. Timers do not
+/// survive an upgrade, so both `init` and `post_upgrade` arm it.
+fn schedule_stream_payouts() {
+    ic_cdk_timers::set_timer_interval(STREAM_PAYOUT_INTERVAL, || ic_cdk::spawn(pay_due_streams()));
+}
+
 /// Initialize the treasury canister
 #[init]
 #[candid_method(init)]
@@ -82,6 +341,8 @@ fn init(args: TreasuryInitArgs) {
         args.controller
     );
     init_state(args);
+    schedule_reconciliation();
+    schedule_stream_payouts();
 }
 
 /// Pre-upgrade hook to save state
@@ -94,6 +355,13 @@ fn pre_upgrade() {
 #[post_upgrade]
 fn post_upgrade() {
     restore_state();
+    for destination in with_state(|s| s.withdrawal_destinations()) {
+        if !destination.active {
+            schedule_destination_activation(destination.active_at);
+        }
+    }
+    schedule_reconciliation();
+    schedule_stream_payouts();
     log!(
         LOG,
         "Treasury upgrade completed — state restored from stable memory"
@@ -113,7 +381,9 @@ fn ensure_controller() -> Result<(), String> {
     Ok(())
 }
 
-/// Deposit funds to treasury (controllers only)
+/// Deposit funds to treasury (controllers only). The funding block must be
+/// a transfer of `amount` to the treasury that no other deposit has claimed.
+/// Repeating a recorded deposit returns its id, so senders can retry.
 #[update]
 #[candid_method(update)]
 async fn deposit(args: DepositArgs) -> Result<u64, String> {
@@ -125,37 +395,63 @@ async fn deposit(args: DepositArgs) -> Result<u64, String> {
         return Err("Treasury is paused and not accepting deposits".to_string());
     }
 
+    let ledger = with_state(|s| {
+        s.get_config()
+            .resolve_ledger(args.asset_type.as_ref(), args.ledger)
+    })?;
+
     log!(
         LOG,
-        "Processing deposit: {:?} {} {:?}",
+        "Processing deposit: {:?} {} {:?} (ledger {})",
         args.deposit_type,
         args.amount,
-        args.asset_type
+        args.asset_type,
+        ledger
     );
 
+    if with_state(|s| s.asset(&ledger)).is_none() {
+        register_asset_from_ledger(ledger).await?;
+    }
+
+    if let Some(existing) = with_state(|s| {
+        s.repeated_deposit(ledger, args.block_index, args.amount, &args.deposit_type)
+    }) {
+        log!(LOG, "Deposit {} repeated; already recorded", existing);
+        return Ok(existing);
+    }
+    if let Some(existing) = with_state(|s| s.deposit_block_claim(ledger, args.block_index)) {
+        return Err(format!(
+            "Block {} on {} already funded deposit {}",
+            args.block_index, ledger, existing
+        ));
+    }
+    deposit_verification::verify_deposit_block(ledger, args.block_index, args.amount).await?;
+
     let dep_type = args.deposit_type.clone();
-    let asset = args.asset_type.clone();
+    let asset_type = with_state(|s| s.get_config().legacy_asset_type(&ledger));
     let amount = args.amount;
     let deposit_caller = caller();
 
     let record = DepositRecord {
         id: 0, // Will be set by add_deposit
         deposit_type: args.deposit_type,
-        asset_type: args.asset_type,
+        asset_type: asset_type.clone(),
+        ledger: Some(ledger),
         amount: args.amount,
         block_index: args.block_index,
         timestamp: ic_cdk::api::time(),
         memo: args.memo,
     };
 
-    let deposit_id = with_state_mut(|s| s.add_deposit(record));
+    let deposit_id = with_state_mut(|s| s.add_verified_deposit(record))?;
 
     with_state_mut(|s| {
         s.push_event(
             deposit_caller,
             TreasuryAction::Deposit {
                 deposit_type: dep_type,
-                asset_type: asset,
+                asset_type,
+                ledger: Some(ledger),
                 amount,
             },
         )
@@ -175,6 +471,157 @@ fn set_stability_pool_reporter(reporter: Option<Principal>) -> Result<(), String
     with_state_mut(|s| s.set_stability_pool_reporter(reporter))
 }
 
+/// Configure the protocol backend, the only canister that may push its mode
+/// via `set_protocol_mode`.
+#[update]
+#[candid_method(update)]
+fn set_protocol_backend(backend: Option<Principal>) -> Result<(), String> {
+    ensure_controller()?;
+    with_state_mut(|s| s.set_protocol_backend(backend))
+}
+
+/// Backend push of its protocol mode on every mode change. Withdrawals are
+/// refused while the mode is one the inheritance policy blocks; repeating
+/// the current mode is a no-op.
+#[update]
+#[candid_method(update)]
+fn set_protocol_mode(mode: ProtocolMode) -> Result<(), String> {
+    let backend = caller();
+    if with_state(|s| s.get_config().protocol_backend) != Some(backend) {
+        return Err("Access denied: caller is not the configured protocol backend".to_string());
+    }
+    if with_state_mut(|s| s.set_protocol_mode(mode))? {
+        log!(LOG, "Inherited backend protocol mode {:?}", mode);
+        with_state_mut(|s| s.push_event(backend, TreasuryAction::ProtocolModeInherited { mode }));
+    }
+    Ok(())
+}
+
+/// Choose which backend modes block withdrawals (controllers only).
+#[update]
+#[candid_method(update)]
+fn set_mode_inheritance_policy(policy: ModeInheritancePolicy) -> Result<(), String> {
+    ensure_controller()?;
+    let c = caller();
+    log!(LOG, "Setting mode inheritance policy to {:?}", policy);
+    with_state_mut(|s| s.set_mode_inheritance_policy(policy.clone()))?;
+    with_state_mut(|s| s.push_event(c, TreasuryAction::SetModeInheritancePolicy { policy }));
+    Ok(())
+}
+
+#[query]
+#[candid_method(query)]
+fn get_mode_inheritance() -> ModeInheritanceStatus {
+    with_state(|s| s.mode_inheritance_status())
+}
+
+/// Register a withdrawal destination (controllers only). It can receive
+/// funds once the timelock has passed; returns that time.
+#[update]
+#[candid_method(update)]
+fn add_withdrawal_destination(to: Principal, label: String) -> Result<u64, String> {
+    ensure_controller()?;
+    if to == Principal::anonymous() {
+        return Err("The anonymous principal cannot be a withdrawal destination".to_string());
+    }
+    if label.len() > MAX_DESTINATION_LABEL_LEN {
+        return Err(format!(
+            "Label longer than {} bytes",
+            MAX_DESTINATION_LABEL_LEN
+        ));
+    }
+    let c = caller();
+    let active_at = with_state_mut(|s| {
+        s.schedule_withdrawal_destination(
+            to,
+            label,
+            ic_cdk::api::time(),
+            WITHDRAWAL_DESTINATION_TIMELOCK_NANOS,
+        )
+    })?;
+    log!(
+        LOG,
+        "Scheduled withdrawal destination {} (active at {})",
+        to,
+        active_at
+    );
+    with_state_mut(|s| {
+        s.push_event(
+            c,
+            TreasuryAction::WithdrawalDestinationScheduled { to, active_at },
+        )
+    });
+    schedule_destination_activation(active_at);
+    Ok(active_at)
+}
+
+/// Remove a withdrawal destination, active or pending (controllers only).
+/// Removal is immediate: it can only narrow where funds may go.
+#[update]
+#[candid_method(update)]
+fn remove_withdrawal_destination(to: Principal) -> Result<(), String> {
+    ensure_controller()?;
+    let c = caller();
+    with_state_mut(|s| s.remove_withdrawal_destination(to))?;
+    log!(LOG, "Removed withdrawal destination {}", to);
+    with_state_mut(|s| s.push_event(c, TreasuryAction::WithdrawalDestinationRemoved { to }));
+    Ok(())
+}
+
+#[query]
+#[candid_method(query)]
+fn get_withdrawal_destinations() -> Vec<WithdrawalDestination> {
+    with_state(|s| s.withdrawal_destinations())
+}
+
+/// Register an asset the treasury can hold, or correct the metadata of a
+/// registered one (controllers only). Needed for LP share tokens and
+/// receipt NFT collections; an ICRC-1 token is also registered by its first
+/// deposit.
+#[update]
+#[candid_method(update)]
+fn register_asset(asset: TreasuryAsset) -> Result<(), String> {
+    ensure_controller()?;
+    if asset.ledger == Principal::anonymous() {
+        return Err("The anonymous principal cannot be an asset ledger".to_string());
+    }
+    if asset.symbol.is_empty() || asset.symbol.len() > MAX_ASSET_SYMBOL_LEN {
+        return Err(format!(
+            "Symbol must be between 1 and {} bytes",
+            MAX_ASSET_SYMBOL_LEN
+        ));
+    }
+    let c = caller();
+    log!(LOG, "Registering asset {:?}", asset);
+    with_state_mut(|s| s.register_asset(asset.clone()))?;
+    with_state_mut(|s| s.push_event(c, TreasuryAction::AssetRegistered { asset }));
+    Ok(())
+}
+
+/// Every registered asset.
+#[query]
+#[candid_method(query)]
+fn get_assets() -> Vec<TreasuryAsset> {
+    with_state(|s| s.assets())
+}
+
+/// Reconcile tracked balances against the ledgers now instead of waiting
+/// for the timer (controllers only).
+#[update]
+#[candid_method(update)]
+async fn reconcile_balances() -> Result<ReconciliationReport, String> {
+    ensure_controller()?;
+    reconcile().await
+}
+
+/// The last reconciliation: per asset, the tracked and ledger balances,
+/// their delta and when it was checked.
+#[query]
+#[candid_method(query)]
+fn get_reconciliation_report() -> ReconciliationReport {
+    with_state(|s| s.reconciliation_report())
+}
+
 /// Record an icUSD transfer already made by the configured Stability Pool when
 /// no opted-in icUSD depositor existed. The backend mint receipts make the
 /// record exactly-once across SP retries and lost callback responses.
@@ -204,7 +651,8 @@ fn record_stability_pool_unallocated_interest(
                 reporter,
                 TreasuryAction::Deposit {
                     deposit_type: types::DepositType::InterestRevenue,
-                    asset_type: types::AssetType::ICUSD,
+                    asset_type: Some(types::AssetType::ICUSD),
+                    ledger: Some(config.icusd_ledger),
                     amount,
                 },
             )
@@ -213,7 +661,9 @@ fn record_stability_pool_unallocated_interest(
     Ok(deposit_id)
 }
 
-/// Withdraw funds from treasury (controllers only).
+/// Withdraw funds from treasury. Controllers only until a signer set is
+/// configured; after that signers only, up to the asset's single-signer
+/// limit (larger withdrawals go through `propose`).
 ///
 /// Audit Wave-3 (ICRC-002/ICRC-003) hardening:
 /// - The recipient bears the ledger fee: bookkeeping is debited `amount` and
@@ -227,11 +677,39 @@ fn record_stability_pool_unallocated_interest(
 /// - The balance is restored ONLY for clear ledger errors; on a
 ///   transport-layer error it stays deducted while a reconciliation hint is
 ///   logged for the controller.
+///
+/// `to` must be an active withdrawal destination (see
+/// `add_withdrawal_destination`).
 #[update]
 #[candid_method(update)]
 async fn withdraw(args: WithdrawArgs) -> Result<WithdrawResult, String> {
-    ensure_controller()?;
     let caller_principal = caller();
+    if with_state(|s| s.signer_config()).is_none() {
+        ensure_controller()?;
+    } else {
+        let ledger = with_state(|s| {
+            s.get_config()
+                .resolve_ledger(args.asset_type.as_ref(), args.ledger)
+        })?;
+        with_state(|s| s.check_direct_withdrawal(caller_principal, ledger, args.amount))?;
+    }
+    execute_withdrawal(caller_principal, args).await
+}
+
+/// Carry out a withdrawal authorized by `caller_principal`, directly or as
+/// an approved proposal.
+async fn execute_withdrawal(
+    caller_principal: Principal,
+    args: WithdrawArgs,
+) -> Result<WithdrawResult, String> {
+    if let Some(mode) = with_state(|s| s.withdrawals_restricted_by_mode()) {
+        return Err(format!(
+            "Withdrawals are disabled while the protocol is in {:?} mode",
+            mode
+        ));
+    }
+    activate_due_withdrawal_destinations();
+    with_state(|s| s.check_withdrawal_destination(args.to))?;
 
     log!(
         LOG,
@@ -244,24 +722,37 @@ async fn withdraw(args: WithdrawArgs) -> Result<WithdrawResult, String> {
     // Resolve the ledger and fee BEFORE debiting, so an unconfigured ledger
     // or a dust amount can't leave the bookkeeping debited with no transfer.
     let ledger_principal = with_state(|s| {
-        let config = s.get_config();
-        match args.asset_type {
-            AssetType::ICUSD => Some(config.icusd_ledger),
-            AssetType::ICP => Some(config.icp_ledger),
-            AssetType::CKBTC => config.ckbtc_ledger,
-            AssetType::CKUSDT => config.ckusdt_ledger,
-            AssetType::CKUSDC => config.ckusdc_ledger,
+        s.get_config()
+            .resolve_ledger(args.asset_type.as_ref(), args.ledger)
+    })?;
+    match with_state(|s| s.asset(&ledger_principal)) {
+        None => return Err(format!("{} is not a registered asset", ledger_principal)),
+        Some(asset) if asset.kind == AssetKind::ReceiptNft => {
+            return Err(format!(
+                "{} is a receipt NFT collection and cannot be withdrawn with icrc1_transfer",
+                asset.symbol
+            ))
         }
-    })
-    .ok_or("Ledger not configured for this asset type")?;
+        Some(_) => {}
+    }
+
+    if let Some(request) = with_state(|s| s.co_approval_request(ledger_principal, &args))? {
+        consume_protocol_co_approval(request.clone()).await?;
+        with_state_mut(|s| {
+            s.push_event(
+                caller_principal,
+                TreasuryAction::WithdrawalCoApproved { request },
+            )
+        });
+    }
 
     let fee = ledger_fee(ledger_principal).await;
     let send_amount = withdrawal_send_amount(args.amount, fee)?;
 
-    with_state_mut(|s| s.withdraw(args.asset_type.clone(), args.amount))?;
+    with_state_mut(|s| s.withdraw(ledger_principal, args.amount))?;
 
     let request_id = args.request_id.unwrap_or_else(|| {
-        derive_request_id(&caller_principal, &args.asset_type, args.amount, &args.to)
+        derive_request_id(&caller_principal, &ledger_principal, args.amount, &args.to)
     });
     let created_at_time =
         with_state_mut(|s| s.created_at_time_for_request(request_id, ic_cdk::api::time()));
@@ -302,29 +793,31 @@ async fn withdraw(args: WithdrawArgs) -> Result<WithdrawResult, String> {
                 ) {
                     s.clear_request_created_at(request_id);
                 }
-                s.restore_balance(&args.asset_type, args.amount)
+                s.restore_balance(&ledger_principal, args.amount)
             });
             return Err(format!("Transfer failed: {:?}", e));
         }
         Err(LedgerError::Transport(msg)) => {
             log!(LOG,
-                "RECONCILIATION REQUIRED: transport error during withdrawal of {} {:?} to {} (request_id {}). \
+                "RECONCILIATION REQUIRED: transport error during withdrawal of {} (ledger {}) to {} (request_id {}). \
                  Balance NOT restored — the transfer may have committed. Verify on-chain via ledger \
                  icrc3_get_blocks before retrying or reconciling. Error: {}",
-                args.amount, args.asset_type, args.to, request_id, msg
+                args.amount, ledger_principal, args.to, request_id, msg
             );
             return Err(format!(
-                "Transport error: {} (reconciliation required, request_id={})",
-                msg, request_id
+                "{}: {} (reconciliation required, request_id={})",
+                TRANSPORT_ERROR_PREFIX, msg, request_id
             ));
         }
     };
 
     with_state_mut(|s| {
+        let asset_type = s.get_config().legacy_asset_type(&ledger_principal);
         s.push_event(
             caller_principal,
             TreasuryAction::Withdraw {
-                asset_type: args.asset_type.clone(),
+                asset_type,
+                ledger: Some(ledger_principal),
                 amount: args.amount,
                 to: args.to,
             },
@@ -340,13 +833,260 @@ async fn withdraw(args: WithdrawArgs) -> Result<WithdrawResult, String> {
     })
 }
 
+/// Have the protocol backend confirm, and mark used, its co-approval of
+/// `request`. Retries of the same withdrawal are confirmed again until the
+/// approval expires.
+async fn consume_protocol_co_approval(request: TreasuryWithdrawalRequest) -> Result<(), String> {
+    let backend = with_state(|s| s.get_config().protocol_backend)
+        .ok_or_else(|| "No protocol backend is configured".to_string())?;
+    let result: Result<(Result<(), ProtocolError>,), _> =
+        ic_cdk::call(backend, "consume_treasury_withdrawal_approval", (request,)).await;
+    match result {
+        Ok((Ok(()),)) => Ok(()),
+        Ok((Err(e),)) => Err(format!("Protocol co-approval refused: {:?}", e)),
+        // Nothing was transferred, so unlike a ledger transport error this
+        // is safe to retry.
+        Err((code, msg)) => Err(format!(
+            "Protocol co-approval check failed: {:?} {}",
+            code, msg
+        )),
+    }
+}
+
+/// Require a co-approval on the protocol backend for withdrawals of more
+/// than `threshold` from `ledger`, or lift the requirement with `None`
+/// (controllers only). Applies to every withdrawal: direct, by proposal and
+/// stream payments.
+#[update]
+#[candid_method(update)]
+fn set_co_approval_threshold(ledger: Principal, threshold: Option<u64>) -> Result<(), String> {
+    ensure_controller()?;
+    let c = caller();
+    log!(
+        LOG,
+        "Setting co-approval threshold on {} to {:?}",
+        ledger,
+        threshold
+    );
+    with_state_mut(|s| s.set_co_approval_threshold(ledger, threshold))?;
+    with_state_mut(|s| {
+        s.push_event(
+            c,
+            TreasuryAction::CoApprovalThresholdSet { ledger, threshold },
+        )
+    });
+    Ok(())
+}
+
+#[query]
+#[candid_method(query)]
+fn get_co_approval_thresholds() -> Vec<(Principal, u64)> {
+    with_state(|s| s.co_approval_thresholds())
+}
+
+/// Set the first signer set (controllers only). From then on withdrawals
+/// need signers and the set itself changes only by an approved proposal.
+#[update]
+#[candid_method(update)]
+fn set_signer_config(config: SignerConfig) -> Result<(), String> {
+    ensure_controller()?;
+    if with_state(|s| s.signer_config()).is_some() {
+        return Err(
+            "A signer set is configured; change it with a SetSignerConfig proposal".to_string(),
+        );
+    }
+    let c = caller();
+    log!(LOG, "Setting signer config to {:?}", config);
+    with_state_mut(|s| s.set_signer_config(config.clone()))?;
+    with_state_mut(|s| s.push_event(c, TreasuryAction::SignerConfigSet { config }));
+    Ok(())
+}
+
+#[query]
+#[candid_method(query)]
+fn get_signer_config() -> Option<SignerConfig> {
+    with_state(|s| s.signer_config())
+}
+
+/// Mark the proposals whose expiry has passed as expired and log an event
+/// for each.
+fn expire_proposals() {
+    let expired = with_state_mut(|s| s.expire_proposals(ic_cdk::api::time()));
+    for id in expired {
+        log!(LOG, "Proposal {} expired", id);
+        with_state_mut(|s| s.push_event(ic_cdk::api::id(), TreasuryAction::ProposalExpired { id }));
+    }
+}
+
+/// Execute proposal `id` if it has the required approvals. The proposal is
+/// marked `Executing` before the first await, so a concurrent approval
+/// cannot run it twice. A failed action fails the proposal; signers submit
+/// a new one to retry (a withdrawal keeps its `request_id`, so the ledger
+/// deduplicates a transfer that did land).
+async fn execute_proposal_if_approved(id: u64) -> Result<Proposal, String> {
+    let action = with_state_mut(|s| s.start_proposal_execution(id, ic_cdk::api::time()))?;
+    if let Some(action) = action {
+        let c = caller();
+        log!(LOG, "Executing proposal {}", id);
+        let result = match action {
+            ProposalAction::Withdraw(args) => execute_withdrawal(c, args)
+                .await
+                .map(|result| Some(result.block_index)),
+            ProposalAction::SetSignerConfig(config) => {
+                with_state_mut(|s| s.set_signer_config(config.clone())).map(|()| {
+                    with_state_mut(|s| s.push_event(c, TreasuryAction::SignerConfigSet { config }));
+                    None
+                })
+            }
+            ProposalAction::CreatePaymentStream(args) => open_payment_stream(c, args).map(|_| None),
+        };
+        let (status, action) = match result {
+            Ok(block_index) => (
+                ProposalStatus::Executed { block_index },
+                TreasuryAction::ProposalExecuted { id },
+            ),
+            Err(error) => {
+                log!(LOG, "Proposal {} failed: {}", id, error);
+                (
+                    ProposalStatus::Failed {
+                        error: error.clone(),
+                    },
+                    TreasuryAction::ProposalFailed { id, error },
+                )
+            }
+        };
+        with_state_mut(|s| {
+            s.set_proposal_status(id, status);
+            s.push_event(c, action);
+        });
+    }
+    with_state(|s| s.get_proposal(id)).ok_or_else(|| format!("No proposal {}", id))
+}
+
+/// Submit a proposal (signers only), approved by the proposer. It executes
+/// as soon as it has `required_approvals`, which may be at once.
+#[update]
+#[candid_method(update)]
+async fn propose(action: ProposalAction) -> Result<Proposal, String> {
+    let c = caller();
+    expire_proposals();
+    let id = with_state_mut(|s| s.submit_proposal(c, action.clone(), ic_cdk::api::time()))?;
+    log!(LOG, "Proposal {} submitted by {}: {:?}", id, c, action);
+    with_state_mut(|s| s.push_event(c, TreasuryAction::ProposalSubmitted { id, action }));
+    execute_proposal_if_approved(id).await
+}
+
+/// Approve a pending proposal (signers only), executing it if this is the
+/// last approval it needs.
+#[update]
+#[candid_method(update)]
+async fn approve_proposal(id: u64) -> Result<Proposal, String> {
+    let c = caller();
+    expire_proposals();
+    let approvals = with_state_mut(|s| s.approve_proposal(id, c, ic_cdk::api::time()))?;
+    log!(
+        LOG,
+        "Proposal {} approved by {} ({} approvals)",
+        id,
+        c,
+        approvals
+    );
+    with_state_mut(|s| s.push_event(c, TreasuryAction::ProposalApproved { id, approvals }));
+    execute_proposal_if_approved(id).await
+}
+
+/// Withdraw a pending proposal (its proposer only).
+#[update]
+#[candid_method(update)]
+fn cancel_proposal(id: u64) -> Result<(), String> {
+    let c = caller();
+    expire_proposals();
+    with_state_mut(|s| s.cancel_proposal(id, c, ic_cdk::api::time()))?;
+    log!(LOG, "Proposal {} cancelled", id);
+    with_state_mut(|s| s.push_event(c, TreasuryAction::ProposalCancelled { id }));
+    Ok(())
+}
+
+/// Proposals still collecting approvals, oldest first.
+#[query]
+#[candid_method(query)]
+fn get_pending_proposals() -> Vec<Proposal> {
+    with_state(|s| s.pending_proposals(ic_cdk::api::time()))
+}
+
+#[query]
+#[candid_method(query)]
+fn get_proposal(id: u64) -> Option<Proposal> {
+    with_state(|s| s.get_proposal(id))
+}
+
+/// Open a payment stream authorized by `created_by`, directly or as an
+/// approved proposal.
+fn open_payment_stream(
+    created_by: Principal,
+    args: CreatePaymentStreamArgs,
+) -> Result<u64, String> {
+    activate_due_withdrawal_destinations();
+    let id = with_state_mut(|s| s.create_stream(created_by, args.clone(), ic_cdk::api::time()))?;
+    log!(
+        LOG,
+        "Payment stream {} opened: {} of {} to {} every {}ns until {}",
+        id,
+        args.amount_per_period,
+        args.ledger,
+        args.recipient,
+        args.period_nanos,
+        args.end_time
+    );
+    with_state_mut(|s| s.push_event(created_by, TreasuryAction::StreamCreated { id, args }));
+    Ok(id)
+}
+
+/// Open a payment stream (controllers only). Once a signer set is
+/// configured, streams are opened by a `CreatePaymentStream` proposal.
+/// Payments are withdrawals: the recipient must be an active withdrawal
+/// destination, and mode restrictions delay them.
+#[update]
+#[candid_method(update)]
+fn create_payment_stream(args: CreatePaymentStreamArgs) -> Result<u64, String> {
+    ensure_controller()?;
+    if with_state(|s| s.signer_config()).is_some() {
+        return Err(
+            "A signer set is configured; open the stream with a CreatePaymentStream proposal"
+                .to_string(),
+        );
+    }
+    open_payment_stream(caller(), args)
+}
+
+/// Stop a payment stream (controllers and signers).
+#[update]
+#[candid_method(update)]
+fn cancel_stream(id: u64) -> Result<(), String> {
+    let c = caller();
+    if !ic_cdk::api::is_controller(&c) {
+        with_state(|s| s.check_signer(c))?;
+    }
+    with_state_mut(|s| s.cancel_stream(id))?;
+    log!(LOG, "Payment stream {} cancelled by {}", id, c);
+    with_state_mut(|s| s.push_event(c, TreasuryAction::StreamCancelled { id }));
+    Ok(())
+}
+
+/// Every payment stream, oldest first.
+#[query]
+#[candid_method(query)]
+fn get_streams() -> Vec<PaymentStream> {
+    with_state(|s| s.streams())
+}
+
 /// Derive a stable request_id from withdrawal args when the caller doesn't
 /// supply one. Bucketing the timestamp at one-minute resolution so a
 /// same-minute retry produces the same id (and therefore the same
 /// `created_at_time` at the ledger, enabling dedup).
 fn derive_request_id(
     caller_principal: &Principal,
-    asset: &AssetType,
+    ledger: &Principal,
     amount: u64,
     to: &Principal,
 ) -> u64 {
@@ -354,7 +1094,7 @@ fn derive_request_id(
     use std::hash::{Hash, Hasher};
     let mut h = DefaultHasher::new();
     caller_principal.as_slice().hash(&mut h);
-    format!("{:?}", asset).hash(&mut h);
+    ledger.as_slice().hash(&mut h);
     amount.hash(&mut h);
     to.as_slice().hash(&mut h);
     let bucket = ic_cdk::api::time() / 60_000_000_000;
@@ -376,15 +1116,10 @@ enum LedgerError {
 fn get_status() -> TreasuryStatus {
     with_state(|s| {
         let config = s.get_config();
-        let balances = s
-            .balances
-            .iter()
-            .map(|(asset_type, balance)| (asset_type.clone(), balance.clone()))
-            .collect();
-
         TreasuryStatus {
             total_deposits: s.get_deposits_count(),
-            balances,
+            balances: s.legacy_balances(),
+            assets: s.holdings(),
             controller: ic_cdk::api::id(), // show canister's own principal
             is_paused: config.is_paused,
         }
@@ -421,9 +1156,9 @@ fn cycle_manager_metrics() -> Vec<rumi_cycle_manager::CycleManagerMetric> {
             ),
             rumi_cycle_manager::metric(
                 "ledger:asset:count",
-                s.balances.len() as u64,
-                s.balances.len() as u64,
-                Some("tracked treasury assets"),
+                s.assets().len() as u64,
+                s.assets().len() as u64,
+                Some("registered treasury assets"),
             ),
         ]
     })
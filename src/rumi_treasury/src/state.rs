@@ -1,6 +1,9 @@
 use crate::types::{
-    AssetBalance, AssetType, BalancesSnapshot, DepositRecord, TreasuryAction, TreasuryEvent,
-    TreasuryInitArgs,
+    AssetBalance, AssetHolding, AssetKind, AssetType, BalancesSnapshot, CreatePaymentStreamArgs,
+    DepositRecord, DepositType, ModeInheritancePolicy, ModeInheritanceStatus, PaymentStream,
+    Proposal, ProposalAction, ProposalStatus, ProtocolMode, ReconciliationEntry,
+    ReconciliationReport, SignerConfig, StreamStatus, TreasuryAction, TreasuryAsset, TreasuryEvent,
+    TreasuryInitArgs, TreasuryWithdrawalRequest, WithdrawArgs, WithdrawalDestination,
 };
 use candid::Principal;
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
@@ -26,6 +29,23 @@ const MEM_EVENTS: u8 = 3; // StableBTreeMap<u64, TreasuryEvent>  (event log)
 const MEM_WITHDRAWAL_CREATED_AT: u8 = 4; // StableBTreeMap<u64, u64> (request_id → first-attempt created_at_time)
 const MEM_SP_UNALLOCATED_INTEREST_BLOCKS: u8 = 5; // StableBTreeMap<u64, u64> (backend mint block → deposit id)
 const MEM_SP_UNALLOCATED_INTEREST_TRANSFER_BLOCKS: u8 = 6; // StableBTreeMap<u64, u64> (icUSD transfer block → deposit id)
+const MEM_RECONCILIATION: u8 = 7; // StableCell<ReconciliationReport>    (last balance reconciliation)
+const MEM_CLAIMED_DEPOSIT_BLOCKS: u8 = 8; // StableBTreeMap<(Principal, u64), u64> ((ledger, block) → deposit id)
+const MEM_PROPOSALS: u8 = 9; // StableBTreeMap<u64, Proposal>       (signer proposals)
+const MEM_STREAMS: u8 = 10; // StableBTreeMap<u64, PaymentStream>  (payment streams)
+
+/// Most signers a `SignerConfig` may list.
+pub const MAX_SIGNERS: usize = 20;
+
+/// Most proposals that may be pending at once, so a single signer cannot
+/// flood the queue.
+pub const MAX_PENDING_PROPOSALS: usize = 32;
+
+/// Shortest period a payment stream may pay on (1 day).
+pub const MIN_STREAM_PERIOD_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Most payment streams that may be active at once.
+pub const MAX_ACTIVE_STREAMS: usize = 64;
 
 /// Every stable memory slot this canister owns, paired with a human label.
 /// Single source of truth for the layout; iterated by the uniqueness test.
@@ -43,14 +63,18 @@ const MEMORY_LAYOUT: &[(u8, &str)] = &[
         MEM_SP_UNALLOCATED_INTEREST_TRANSFER_BLOCKS,
         "sp_unallocated_interest_transfer_blocks",
     ),
+    (MEM_RECONCILIATION, "reconciliation"),
+    (MEM_CLAIMED_DEPOSIT_BLOCKS, "claimed_deposit_blocks"),
+    (MEM_PROPOSALS, "proposals"),
+    (MEM_STREAMS, "streams"),
 ];
 
 /// Treasury state that persists across upgrades
 pub struct TreasuryState {
     /// All deposit records, indexed by deposit ID
     pub deposits: StableBTreeMap<u64, DepositRecord, Memory>,
-    /// Current balances by asset type (in-memory mirror of balances_cell)
-    pub balances: HashMap<AssetType, AssetBalance>,
+    /// Current balances by asset ledger (in-memory mirror of balances_cell)
+    pub balances: HashMap<Principal, AssetBalance>,
     /// Balances persisted to stable memory — written on every mutation
     pub balances_cell: StableCell<BalancesSnapshot, Memory>,
     /// Configuration data
@@ -71,6 +95,19 @@ pub struct TreasuryState {
     /// Physical icUSD transfer block → deposit ID. Source receipts and
     /// transfer receipts must agree before any balance is credited.
     pub sp_unallocated_interest_transfer_blocks: StableBTreeMap<u64, u64, Memory>,
+    /// Last balance reconciliation against the ledgers.
+    pub reconciliation: StableCell<ReconciliationReport, Memory>,
+    /// (ledger, block) → deposit ID of every funding block credited, so a
+    /// block cannot fund two deposits.
+    pub claimed_deposit_blocks: StableBTreeMap<(Principal, u64), u64, Memory>,
+    /// Signer proposals by ID, pending and settled.
+    pub proposals: StableBTreeMap<u64, Proposal, Memory>,
+    /// Next available proposal ID
+    pub next_proposal_id: u64,
+    /// Payment streams by ID, active and ended.
+    pub streams: StableBTreeMap<u64, PaymentStream, Memory>,
+    /// Next available stream ID
+    pub next_stream_id: u64,
 }
 
 /// Treasury configuration stored in stable memory
@@ -92,6 +129,97 @@ pub struct TreasuryConfig {
     /// receives no controller or withdrawal authority.
     #[serde(default)]
     pub stability_pool_reporter: Option<Principal>,
+    /// The protocol backend, the only caller of `set_protocol_mode`.
+    #[serde(default)]
+    pub protocol_backend: Option<Principal>,
+    /// Last mode pushed by `protocol_backend`.
+    #[serde(default)]
+    pub protocol_mode: Option<ProtocolMode>,
+    /// `None` = `ModeInheritancePolicy::default()`.
+    #[serde(default)]
+    pub mode_inheritance_policy: Option<ModeInheritancePolicy>,
+    /// Registered withdrawal recipients; `None` = none registered.
+    #[serde(default)]
+    pub withdrawal_destinations: Option<Vec<WithdrawalDestination>>,
+    /// Asset registry, one entry per ledger. `None` only on a canister
+    /// installed before the registry, until its next upgrade migrates it.
+    #[serde(default)]
+    pub assets: Option<Vec<TreasuryAsset>>,
+    /// M-of-N signers guarding withdrawals; `None` = controllers withdraw.
+    #[serde(default)]
+    pub signer_config: Option<SignerConfig>,
+    /// Per-ledger amount above which a withdrawal also needs a co-approval
+    /// on `protocol_backend`; `None` = no thresholds set.
+    #[serde(default)]
+    pub co_approval_thresholds: Option<Vec<(Principal, u64)>>,
+}
+
+impl TreasuryConfig {
+    /// Ledger behind a legacy asset type, if configured.
+    pub fn legacy_ledger(&self, asset_type: &AssetType) -> Option<Principal> {
+        match asset_type {
+            AssetType::ICUSD => Some(self.icusd_ledger),
+            AssetType::ICP => Some(self.icp_ledger),
+            AssetType::CKBTC => self.ckbtc_ledger,
+            AssetType::CKUSDT => self.ckusdt_ledger,
+            AssetType::CKUSDC => self.ckusdc_ledger,
+        }
+    }
+
+    /// Legacy asset type of `ledger`, for callers that still report by it.
+    pub fn legacy_asset_type(&self, ledger: &Principal) -> Option<AssetType> {
+        [
+            AssetType::ICUSD,
+            AssetType::ICP,
+            AssetType::CKBTC,
+            AssetType::CKUSDT,
+            AssetType::CKUSDC,
+        ]
+        .into_iter()
+        .find(|asset_type| self.legacy_ledger(asset_type) == Some(*ledger))
+    }
+
+    /// Resolve the asset a request names: `ledger` when set, otherwise the
+    /// ledger of the legacy `asset_type`.
+    pub fn resolve_ledger(
+        &self,
+        asset_type: Option<&AssetType>,
+        ledger: Option<Principal>,
+    ) -> Result<Principal, String> {
+        match (ledger, asset_type) {
+            (Some(ledger), _) => Ok(ledger),
+            (None, Some(asset_type)) => self
+                .legacy_ledger(asset_type)
+                .ok_or_else(|| format!("Ledger not configured for {:?}", asset_type)),
+            (None, None) => Err("Specify the asset's ledger or asset type".to_string()),
+        }
+    }
+}
+
+/// Registry entries for the ledgers the treasury was installed with. Seeds
+/// the registry on install and when migrating a pre-registry canister.
+fn legacy_assets(config: &TreasuryConfig) -> Vec<TreasuryAsset> {
+    let candidates = [
+        (Some(config.icusd_ledger), "icUSD", 8),
+        (Some(config.icp_ledger), "ICP", 8),
+        (config.ckbtc_ledger, "ckBTC", 8),
+        (config.ckusdt_ledger, "ckUSDT", 6),
+        (config.ckusdc_ledger, "ckUSDC", 6),
+    ];
+    let mut assets: Vec<TreasuryAsset> = vec![];
+    for (ledger, symbol, decimals) in candidates {
+        let Some(ledger) = ledger else { continue };
+        if assets.iter().any(|a| a.ledger == ledger) {
+            continue;
+        }
+        assets.push(TreasuryAsset {
+            ledger,
+            symbol: symbol.to_string(),
+            decimals,
+            kind: AssetKind::Fungible,
+        });
+    }
+    assets
 }
 
 // Storable implementation for TreasuryConfig
@@ -150,6 +278,48 @@ impl ic_stable_structures::Storable for BalancesSnapshot {
         ic_stable_structures::storable::Bound::Unbounded;
 }
 
+// Storable implementation for Proposal
+impl ic_stable_structures::Storable for Proposal {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+// Storable implementation for PaymentStream
+impl ic_stable_structures::Storable for PaymentStream {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+// Storable implementation for ReconciliationReport
+impl ic_stable_structures::Storable for ReconciliationReport {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
         RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
@@ -157,15 +327,12 @@ thread_local! {
     static STATE: RefCell<Option<TreasuryState>> = RefCell::new(None);
 }
 
-/// Build the default empty balances HashMap.
-fn empty_balances() -> HashMap<AssetType, AssetBalance> {
-    let mut m = HashMap::new();
-    m.insert(AssetType::ICUSD, AssetBalance::default());
-    m.insert(AssetType::ICP, AssetBalance::default());
-    m.insert(AssetType::CKBTC, AssetBalance::default());
-    m.insert(AssetType::CKUSDT, AssetBalance::default());
-    m.insert(AssetType::CKUSDC, AssetBalance::default());
-    m
+/// Build an empty balance for every registered asset.
+fn empty_balances(assets: &[TreasuryAsset]) -> HashMap<Principal, AssetBalance> {
+    assets
+        .iter()
+        .map(|asset| (asset.ledger, AssetBalance::default()))
+        .collect()
 }
 
 impl TreasuryState {
@@ -174,7 +341,7 @@ impl TreasuryState {
         MEMORY_MANAGER.with(|mm| {
             let memory_manager = mm.borrow();
 
-            let config = TreasuryConfig {
+            let mut config = TreasuryConfig {
                 icusd_ledger: args.icusd_ledger,
                 icp_ledger: args.icp_ledger,
                 ckbtc_ledger: args.ckbtc_ledger,
@@ -182,9 +349,28 @@ impl TreasuryState {
                 ckusdc_ledger: args.ckusdc_ledger,
                 is_paused: false,
                 stability_pool_reporter: None,
+                protocol_backend: None,
+                protocol_mode: None,
+                mode_inheritance_policy: None,
+                withdrawal_destinations: args.withdrawal_destinations.map(|destinations| {
+                    destinations
+                        .into_iter()
+                        .map(|to| WithdrawalDestination {
+                            to,
+                            label: "install".to_string(),
+                            added_at: 0,
+                            active_at: 0,
+                            active: true,
+                        })
+                        .collect()
+                }),
+                assets: None,
+                signer_config: None,
+                co_approval_thresholds: None,
             };
-
-            let balances = empty_balances();
+            let assets = legacy_assets(&config);
+            let balances = empty_balances(&assets);
+            config.assets = Some(assets);
 
             Self {
                 deposits: StableBTreeMap::init(memory_manager.get(MemoryId::new(MEM_DEPOSITS))),
@@ -208,6 +394,18 @@ impl TreasuryState {
                 sp_unallocated_interest_transfer_blocks: StableBTreeMap::init(
                     memory_manager.get(MemoryId::new(MEM_SP_UNALLOCATED_INTEREST_TRANSFER_BLOCKS)),
                 ),
+                reconciliation: StableCell::init(
+                    memory_manager.get(MemoryId::new(MEM_RECONCILIATION)),
+                    ReconciliationReport::default(),
+                )
+                .unwrap(),
+                claimed_deposit_blocks: StableBTreeMap::init(
+                    memory_manager.get(MemoryId::new(MEM_CLAIMED_DEPOSIT_BLOCKS)),
+                ),
+                proposals: StableBTreeMap::init(memory_manager.get(MemoryId::new(MEM_PROPOSALS))),
+                next_proposal_id: 1,
+                streams: StableBTreeMap::init(memory_manager.get(MemoryId::new(MEM_STREAMS))),
+                next_stream_id: 1,
             }
         })
     }
@@ -220,17 +418,114 @@ impl TreasuryState {
     /// Must be called after every mutation to `self.balances`.
     fn persist_balances(&mut self) {
         let snapshot = BalancesSnapshot {
-            entries: self
-                .balances
-                .iter()
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect(),
+            entries: vec![],
+            by_ledger: self.balances.iter().map(|(k, v)| (*k, v.clone())).collect(),
         };
-        // Ignore the error — StableCell::set only fails if the value is too
-        // large for the memory region, which won't happen for 5 balance entries.
+        // Ignore the error — the cell is unbounded, so StableCell::set cannot
+        // run out of room for a handful of balance entries.
         let _ = self.balances_cell.set(snapshot);
     }
 
+    // ------------------------------------------------------------------
+    // Asset registry
+    // ------------------------------------------------------------------
+
+    pub fn assets(&self) -> Vec<TreasuryAsset> {
+        self.config.get().assets.clone().unwrap_or_default()
+    }
+
+    pub fn asset(&self, ledger: &Principal) -> Option<TreasuryAsset> {
+        self.assets().into_iter().find(|a| a.ledger == *ledger)
+    }
+
+    /// Add `asset` to the registry, or replace the metadata of its ledger's
+    /// entry. Its balance is kept.
+    pub fn register_asset(&mut self, asset: TreasuryAsset) -> Result<(), String> {
+        let mut config = self.config.get().clone();
+        let mut assets = config.assets.take().unwrap_or_default();
+        match assets.iter_mut().find(|a| a.ledger == asset.ledger) {
+            Some(existing) => *existing = asset.clone(),
+            None => assets.push(asset.clone()),
+        }
+        config.assets = Some(assets);
+        self.config
+            .set(config)
+            .map_err(|e| format!("Failed to update asset registry: {:?}", e))?;
+        self.balances.entry(asset.ledger).or_default();
+        self.persist_balances();
+        Ok(())
+    }
+
+    /// Every registered asset with its balance, in registration order.
+    pub fn holdings(&self) -> Vec<AssetHolding> {
+        self.assets()
+            .into_iter()
+            .map(|asset| AssetHolding {
+                balance: self
+                    .balances
+                    .get(&asset.ledger)
+                    .cloned()
+                    .unwrap_or_default(),
+                asset,
+            })
+            .collect()
+    }
+
+    /// Balances of the legacy asset types, as `get_status` has always
+    /// reported them.
+    pub fn legacy_balances(&self) -> Vec<(AssetType, AssetBalance)> {
+        let config = self.config.get();
+        self.balances
+            .iter()
+            .filter_map(|(ledger, balance)| {
+                config
+                    .legacy_asset_type(ledger)
+                    .map(|asset_type| (asset_type, balance.clone()))
+            })
+            .collect()
+    }
+
+    /// One-time migration of a canister installed before the asset registry:
+    /// seed the registry from the configured ledgers, give every deposit
+    /// record its ledger, and persist balances keyed by ledger. Event
+    /// history is left as recorded.
+    fn migrate_to_asset_registry(&mut self) {
+        let mut config = self.config.get().clone();
+        let mut assets = legacy_assets(&config);
+        for ledger in self.balances.keys() {
+            if !assets.iter().any(|a| a.ledger == *ledger) {
+                assets.push(TreasuryAsset {
+                    ledger: *ledger,
+                    symbol: ledger.to_text(),
+                    decimals: 8,
+                    kind: AssetKind::Fungible,
+                });
+            }
+        }
+        for asset in &assets {
+            self.balances.entry(asset.ledger).or_default();
+        }
+        config.assets = Some(assets);
+
+        let unmigrated: Vec<DepositRecord> = self
+            .deposits
+            .iter()
+            .filter(|(_, record)| record.ledger.is_none())
+            .map(|(_, record)| record)
+            .collect();
+        for mut record in unmigrated {
+            record.ledger = record
+                .asset_type
+                .as_ref()
+                .and_then(|asset_type| config.legacy_ledger(asset_type));
+            self.deposits.insert(record.id, record);
+        }
+
+        // Ignore the error — as for balances, the config cell is unbounded.
+        let _ = self.config.set(config);
+        self.persist_balances();
+    }
+
     // ------------------------------------------------------------------
     // Event logging
     // ------------------------------------------------------------------
@@ -274,14 +569,23 @@ impl TreasuryState {
         let deposit_id = self.next_deposit_id;
         self.next_deposit_id += 1;
 
-        // Update balance for this asset type
-        if let Some(balance) = self.balances.get_mut(&record.asset_type) {
-            balance.total += record.amount;
-            balance.available += record.amount;
+        let mut final_record = record;
+        if final_record.ledger.is_none() {
+            let config = self.config.get();
+            final_record.ledger = final_record
+                .asset_type
+                .as_ref()
+                .and_then(|asset_type| config.legacy_ledger(asset_type));
+        }
+
+        // Update balance for this asset
+        if let Some(ledger) = final_record.ledger {
+            let balance = self.balances.entry(ledger).or_default();
+            balance.total += final_record.amount;
+            balance.available += final_record.amount;
         }
 
         // Store the deposit record
-        let mut final_record = record;
         final_record.id = deposit_id;
         self.deposits.insert(deposit_id, final_record);
 
@@ -289,6 +593,61 @@ impl TreasuryState {
         deposit_id
     }
 
+    /// Deposit already credited from `block_index` on `ledger`, if any.
+    pub fn deposit_block_claim(&self, ledger: Principal, block_index: u64) -> Option<u64> {
+        self.claimed_deposit_blocks.get(&(ledger, block_index))
+    }
+
+    /// The deposit `block_index` on `ledger` already funded, if it was booked
+    /// with the same amount and type. A sender retrying a deposit whose reply
+    /// it never saw gets that deposit back instead of an error.
+    pub fn repeated_deposit(
+        &self,
+        ledger: Principal,
+        block_index: u64,
+        amount: u64,
+        deposit_type: &DepositType,
+    ) -> Option<u64> {
+        let id = self.deposit_block_claim(ledger, block_index)?;
+        let record = self.deposits.get(&id)?;
+        (record.amount == amount && &record.deposit_type == deposit_type).then_some(id)
+    }
+
+    /// Credit a deposit whose funding block has been verified, claiming the
+    /// block. Fails if the block already funded a deposit, which a deposit
+    /// verified concurrently may have done while the ledger was queried.
+    pub fn add_verified_deposit(&mut self, record: DepositRecord) -> Result<u64, String> {
+        let ledger = record
+            .ledger
+            .ok_or_else(|| "deposit has no ledger".to_string())?;
+        if let Some(existing) = self.deposit_block_claim(ledger, record.block_index) {
+            return Err(format!(
+                "block {} on {} already funded deposit {}",
+                record.block_index, ledger, existing
+            ));
+        }
+        let block_index = record.block_index;
+        let deposit_id = self.add_deposit(record);
+        self.claimed_deposit_blocks
+            .insert((ledger, block_index), deposit_id);
+        Ok(deposit_id)
+    }
+
+    /// Claim the funding blocks of the deposits recorded before blocks were
+    /// claimed. Where two deposits name the same block the first keeps it.
+    fn claim_recorded_deposit_blocks(&mut self) {
+        let claims: Vec<((Principal, u64), u64)> = self
+            .deposits
+            .iter()
+            .filter_map(|(id, record)| Some(((record.ledger?, record.block_index), id)))
+            .collect();
+        for (key, id) in claims {
+            if !self.claimed_deposit_blocks.contains_key(&key) {
+                self.claimed_deposit_blocks.insert(key, id);
+            }
+        }
+    }
+
     /// Record a Stability Pool treasury forward exactly once per backend mint
     /// receipt. This method has no await, so lookup, deposit creation, and
     /// receipt indexing are one canister-state transition.
@@ -337,11 +696,19 @@ impl TreasuryState {
                 transfer_block_index, existing_transfer
             ));
         }
+        let icusd_ledger = self.config.get().icusd_ledger;
+        if let Some(existing) = self.deposit_block_claim(icusd_ledger, transfer_block_index) {
+            return Err(format!(
+                "transfer block {} already funded deposit {}",
+                transfer_block_index, existing
+            ));
+        }
 
         let deposit_id = self.add_deposit(DepositRecord {
             id: 0,
             deposit_type: crate::types::DepositType::InterestRevenue,
-            asset_type: crate::types::AssetType::ICUSD,
+            asset_type: Some(AssetType::ICUSD),
+            ledger: Some(icusd_ledger),
             amount,
             block_index: transfer_block_index,
             timestamp: now,
@@ -353,15 +720,17 @@ impl TreasuryState {
         }
         self.sp_unallocated_interest_transfer_blocks
             .insert(transfer_block_index, deposit_id);
+        self.claimed_deposit_blocks
+            .insert((icusd_ledger, transfer_block_index), deposit_id);
         Ok((deposit_id, true))
     }
 
     /// Reserve `amount` from bookkeeping before attempting a withdrawal transfer.
-    pub fn withdraw(&mut self, asset_type: AssetType, amount: u64) -> Result<(), String> {
+    pub fn withdraw(&mut self, ledger: Principal, amount: u64) -> Result<(), String> {
         let balance = self
             .balances
-            .get_mut(&asset_type)
-            .ok_or_else(|| format!("Unknown asset type: {:?}", asset_type))?;
+            .get_mut(&ledger)
+            .ok_or_else(|| format!("Unknown asset ledger: {}", ledger))?;
 
         if balance.available < amount {
             return Err(format!(
@@ -377,8 +746,8 @@ impl TreasuryState {
     }
 
     /// Restore balance after a failed withdrawal transfer.
-    pub fn restore_balance(&mut self, asset_type: &AssetType, amount: u64) {
-        if let Some(balance) = self.balances.get_mut(asset_type) {
+    pub fn restore_balance(&mut self, ledger: &Principal, amount: u64) {
+        if let Some(balance) = self.balances.get_mut(ledger) {
             balance.total += amount;
             balance.available += amount;
         }
@@ -450,6 +819,635 @@ impl TreasuryState {
         Ok(())
     }
 
+    pub fn set_protocol_backend(&mut self, backend: Option<Principal>) -> Result<(), String> {
+        let mut config = self.config.get().clone();
+        config.protocol_backend = backend;
+        self.config
+            .set(config)
+            .map_err(|e| format!("Failed to update protocol backend: {:?}", e))?;
+        Ok(())
+    }
+
+    /// Store the backend's mode. Returns `false` if it was already current.
+    pub fn set_protocol_mode(&mut self, mode: ProtocolMode) -> Result<bool, String> {
+        let mut config = self.config.get().clone();
+        if config.protocol_mode == Some(mode) {
+            return Ok(false);
+        }
+        config.protocol_mode = Some(mode);
+        self.config
+            .set(config)
+            .map_err(|e| format!("Failed to update protocol mode: {:?}", e))?;
+        Ok(true)
+    }
+
+    pub fn set_mode_inheritance_policy(
+        &mut self,
+        policy: ModeInheritancePolicy,
+    ) -> Result<(), String> {
+        let mut config = self.config.get().clone();
+        config.mode_inheritance_policy = Some(policy);
+        self.config
+            .set(config)
+            .map_err(|e| format!("Failed to update mode inheritance policy: {:?}", e))?;
+        Ok(())
+    }
+
+    /// The inherited mode, if the policy blocks withdrawals in it.
+    pub fn withdrawals_restricted_by_mode(&self) -> Option<ProtocolMode> {
+        let config = self.config.get();
+        let policy = config.mode_inheritance_policy.clone().unwrap_or_default();
+        config
+            .protocol_mode
+            .filter(|mode| policy.block_withdrawals_in.contains(&mode.restricts_as()))
+    }
+
+    pub fn mode_inheritance_status(&self) -> ModeInheritanceStatus {
+        let config = self.config.get();
+        ModeInheritanceStatus {
+            protocol_backend: config.protocol_backend,
+            protocol_mode: config.protocol_mode,
+            policy: config.mode_inheritance_policy.clone().unwrap_or_default(),
+            withdrawals_blocked: self.withdrawals_restricted_by_mode().is_some(),
+        }
+    }
+
+    // ------------------------------------------------------------------
+    // Withdrawal destinations
+    // ------------------------------------------------------------------
+
+    pub fn withdrawal_destinations(&self) -> Vec<WithdrawalDestination> {
+        self.config
+            .get()
+            .withdrawal_destinations
+            .clone()
+            .unwrap_or_default()
+    }
+
+    fn set_withdrawal_destinations(
+        &mut self,
+        destinations: Vec<WithdrawalDestination>,
+    ) -> Result<(), String> {
+        let mut config = self.config.get().clone();
+        config.withdrawal_destinations = Some(destinations);
+        self.config
+            .set(config)
+            .map_err(|e| format!("Failed to update withdrawal destinations: {:?}", e))?;
+        Ok(())
+    }
+
+    /// Register `to` as a withdrawal destination that activates `delay`
+    /// nanoseconds from `now`. Returns the activation time.
+    pub fn schedule_withdrawal_destination(
+        &mut self,
+        to: Principal,
+        label: String,
+        now: u64,
+        delay: u64,
+    ) -> Result<u64, String> {
+        let mut destinations = self.withdrawal_destinations();
+        if destinations.iter().any(|d| d.to == to) {
+            return Err(format!("{} is already a withdrawal destination", to));
+        }
+        let active_at = now.saturating_add(delay);
+        destinations.push(WithdrawalDestination {
+            to,
+            label,
+            added_at: now,
+            active_at,
+            active: false,
+        });
+        self.set_withdrawal_destinations(destinations)?;
+        Ok(active_at)
+    }
+
+    /// Remove `to`, active or still pending. Takes effect immediately.
+    pub fn remove_withdrawal_destination(&mut self, to: Principal) -> Result<(), String> {
+        let mut destinations = self.withdrawal_destinations();
+        let before = destinations.len();
+        destinations.retain(|d| d.to != to);
+        if destinations.len() == before {
+            return Err(format!("{} is not a withdrawal destination", to));
+        }
+        self.set_withdrawal_destinations(destinations)
+    }
+
+    /// Activate every pending destination whose timelock has passed at
+    /// `now`, returning the newly activated ones.
+    pub fn activate_due_withdrawal_destinations(&mut self, now: u64) -> Vec<Principal> {
+        let mut destinations = self.withdrawal_destinations();
+        let mut activated = vec![];
+        for destination in destinations.iter_mut() {
+            if !destination.active && destination.active_at <= now {
+                destination.active = true;
+                activated.push(destination.to);
+            }
+        }
+        if !activated.is_empty() {
+            // Ignore the error — as for balances, the config cell is unbounded.
+            let _ = self.set_withdrawal_destinations(destinations);
+        }
+        activated
+    }
+
+    /// Refuse a withdrawal to anything but an active destination.
+    pub fn check_withdrawal_destination(&self, to: Principal) -> Result<(), String> {
+        match self.withdrawal_destinations().iter().find(|d| d.to == to) {
+            Some(d) if d.active => Ok(()),
+            Some(d) => Err(format!(
+                "Withdrawal destination {} is timelocked until {}",
+                to, d.active_at
+            )),
+            None => Err(format!("{} is not a registered withdrawal destination", to)),
+        }
+    }
+
+    // ------------------------------------------------------------------
+    // Signer approval
+    // ------------------------------------------------------------------
+
+    pub fn signer_config(&self) -> Option<SignerConfig> {
+        self.config.get().signer_config.clone()
+    }
+
+    pub fn set_signer_config(&mut self, signer_config: SignerConfig) -> Result<(), String> {
+        validate_signer_config(&signer_config)?;
+        let mut config = self.config.get().clone();
+        config.signer_config = Some(signer_config);
+        self.config
+            .set(config)
+            .map_err(|e| format!("Failed to update signer config: {:?}", e))?;
+        Ok(())
+    }
+
+    pub fn check_signer(&self, caller: Principal) -> Result<SignerConfig, String> {
+        let config = self
+            .signer_config()
+            .ok_or_else(|| "No signer set is configured".to_string())?;
+        if !config.signers.contains(&caller) {
+            return Err(format!("{} is not a signer", caller));
+        }
+        Ok(config)
+    }
+
+    /// Refuse a withdrawal by `caller` without a proposal unless it is a
+    /// signer and `amount` is within `ledger`'s single-signer limit.
+    pub fn check_direct_withdrawal(
+        &self,
+        caller: Principal,
+        ledger: Principal,
+        amount: u64,
+    ) -> Result<(), String> {
+        let config = self.check_signer(caller)?;
+        let limit = config
+            .withdrawal_limits
+            .iter()
+            .find(|(l, _)| *l == ledger)
+            .map_or(0, |(_, limit)| *limit);
+        if amount > limit {
+            return Err(format!(
+                "Withdrawal of {} exceeds the single-signer limit of {} on {}; submit it as a proposal",
+                amount, limit, ledger
+            ));
+        }
+        Ok(())
+    }
+
+    // ------------------------------------------------------------------
+    // Protocol co-approval
+    // ------------------------------------------------------------------
+
+    pub fn co_approval_thresholds(&self) -> Vec<(Principal, u64)> {
+        self.config
+            .get()
+            .co_approval_thresholds
+            .clone()
+            .unwrap_or_default()
+    }
+
+    /// Set (`Some`) or clear (`None`) `ledger`'s co-approval threshold.
+    pub fn set_co_approval_threshold(
+        &mut self,
+        ledger: Principal,
+        threshold: Option<u64>,
+    ) -> Result<(), String> {
+        if self.asset(&ledger).is_none() {
+            return Err(format!("{} is not a registered asset", ledger));
+        }
+        let mut thresholds = self.co_approval_thresholds();
+        thresholds.retain(|(l, _)| *l != ledger);
+        if let Some(threshold) = threshold {
+            thresholds.push((ledger, threshold));
+        }
+        let mut config = self.config.get().clone();
+        config.co_approval_thresholds = Some(thresholds);
+        self.config
+            .set(config)
+            .map_err(|e| format!("Failed to update co-approval thresholds: {:?}", e))?;
+        Ok(())
+    }
+
+    /// The co-approval a withdrawal of `amount` from `ledger` needs from the
+    /// protocol backend, `None` if it is within the ledger's threshold. Such
+    /// a withdrawal must carry its own `request_id`, which the approval names.
+    pub fn co_approval_request(
+        &self,
+        ledger: Principal,
+        args: &WithdrawArgs,
+    ) -> Result<Option<TreasuryWithdrawalRequest>, String> {
+        let Some(threshold) = self
+            .co_approval_thresholds()
+            .iter()
+            .find(|(l, _)| *l == ledger)
+            .map(|(_, threshold)| *threshold)
+        else {
+            return Ok(None);
+        };
+        if args.amount <= threshold {
+            return Ok(None);
+        }
+        if self.config.get().protocol_backend.is_none() {
+            return Err(format!(
+                "Withdrawal of {} exceeds the co-approval threshold of {} on {} and no protocol backend is configured",
+                args.amount, threshold, ledger
+            ));
+        }
+        let request_id = args.request_id.ok_or_else(|| {
+            format!(
+                "Withdrawal of {} exceeds the co-approval threshold of {} on {}; it needs a request_id for the protocol to approve",
+                args.amount, threshold, ledger
+            )
+        })?;
+        Ok(Some(TreasuryWithdrawalRequest {
+            request_id,
+            ledger,
+            to: args.to,
+            amount: args.amount,
+        }))
+    }
+
+    /// Queue `action`, approved by its proposer. Returns the proposal ID.
+    pub fn submit_proposal(
+        &mut self,
+        proposer: Principal,
+        action: ProposalAction,
+        now: u64,
+    ) -> Result<u64, String> {
+        let config = self.check_signer(proposer)?;
+        if let ProposalAction::SetSignerConfig(signer_config) = &action {
+            validate_signer_config(signer_config)?;
+        }
+        if self.pending_proposals(now).len() >= MAX_PENDING_PROPOSALS {
+            return Err(format!(
+                "{} proposals are already pending",
+                MAX_PENDING_PROPOSALS
+            ));
+        }
+        let id = self.next_proposal_id;
+        self.next_proposal_id += 1;
+        self.proposals.insert(
+            id,
+            Proposal {
+                id,
+                action,
+                proposer,
+                approvals: vec![proposer],
+                created_at: now,
+                expires_at: now.saturating_add(config.proposal_expiry_nanos),
+                status: ProposalStatus::Pending,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Add `signer`'s approval to pending proposal `id`. Returns how many
+    /// current signers have approved it.
+    pub fn approve_proposal(
+        &mut self,
+        id: u64,
+        signer: Principal,
+        now: u64,
+    ) -> Result<u32, String> {
+        self.check_signer(signer)?;
+        let mut proposal = self.pending_proposal(id, now)?;
+        if proposal.approvals.contains(&signer) {
+            return Err(format!("{} already approved proposal {}", signer, id));
+        }
+        proposal.approvals.push(signer);
+        let approvals = self.current_approvals(&proposal);
+        self.proposals.insert(id, proposal);
+        Ok(approvals)
+    }
+
+    /// Approvals of `proposal` from signers still in the set, so removing a
+    /// signer withdraws their approvals.
+    fn current_approvals(&self, proposal: &Proposal) -> u32 {
+        let signers = self.signer_config().map(|c| c.signers).unwrap_or_default();
+        proposal
+            .approvals
+            .iter()
+            .filter(|a| signers.contains(a))
+            .count() as u32
+    }
+
+    fn pending_proposal(&self, id: u64, now: u64) -> Result<Proposal, String> {
+        let proposal = self
+            .proposals
+            .get(&id)
+            .ok_or_else(|| format!("No proposal {}", id))?;
+        if proposal.status != ProposalStatus::Pending {
+            return Err(format!("Proposal {} is {:?}", id, proposal.status));
+        }
+        if proposal.expires_at <= now {
+            return Err(format!("Proposal {} has expired", id));
+        }
+        Ok(proposal)
+    }
+
+    /// Move pending proposal `id` to `Executing` and return its action if
+    /// it has the required approvals; `None` if it is still short.
+    pub fn start_proposal_execution(
+        &mut self,
+        id: u64,
+        now: u64,
+    ) -> Result<Option<ProposalAction>, String> {
+        let mut proposal = self.pending_proposal(id, now)?;
+        let required = self
+            .signer_config()
+            .map_or(u32::MAX, |c| c.required_approvals);
+        if self.current_approvals(&proposal) < required {
+            return Ok(None);
+        }
+        proposal.status = ProposalStatus::Executing;
+        let action = proposal.action.clone();
+        self.proposals.insert(id, proposal);
+        Ok(Some(action))
+    }
+
+    pub fn set_proposal_status(&mut self, id: u64, status: ProposalStatus) {
+        if let Some(mut proposal) = self.proposals.get(&id) {
+            proposal.status = status;
+            self.proposals.insert(id, proposal);
+        }
+    }
+
+    /// Withdraw pending proposal `id`. Only its proposer may.
+    pub fn cancel_proposal(&mut self, id: u64, caller: Principal, now: u64) -> Result<(), String> {
+        let proposal = self.pending_proposal(id, now)?;
+        if proposal.proposer != caller {
+            return Err(format!(
+                "Only {} can cancel proposal {}",
+                proposal.proposer, id
+            ));
+        }
+        self.set_proposal_status(id, ProposalStatus::Cancelled);
+        Ok(())
+    }
+
+    /// Mark every pending proposal whose expiry has passed at `now` as
+    /// `Expired`, returning their IDs.
+    pub fn expire_proposals(&mut self, now: u64) -> Vec<u64> {
+        // Proposals are signer-only and rare, so a full scan is cheap.
+        let expired: Vec<u64> = self
+            .proposals
+            .iter()
+            .filter(|(_, p)| p.status == ProposalStatus::Pending && p.expires_at <= now)
+            .map(|(id, _)| id)
+            .collect();
+        for id in &expired {
+            self.set_proposal_status(*id, ProposalStatus::Expired);
+        }
+        expired
+    }
+
+    /// Proposals still collecting approvals at `now`, oldest first.
+    pub fn pending_proposals(&self, now: u64) -> Vec<Proposal> {
+        self.proposals
+            .iter()
+            .map(|(_, p)| p)
+            .filter(|p| p.status == ProposalStatus::Pending && p.expires_at > now)
+            .collect()
+    }
+
+    pub fn get_proposal(&self, id: u64) -> Option<Proposal> {
+        self.proposals.get(&id)
+    }
+
+    // ------------------------------------------------------------------
+    // Payment streams
+    // ------------------------------------------------------------------
+
+    /// Open a payment stream whose first payment falls due one period after
+    /// `now`. Returns the stream ID.
+    pub fn create_stream(
+        &mut self,
+        created_by: Principal,
+        args: CreatePaymentStreamArgs,
+        now: u64,
+    ) -> Result<u64, String> {
+        if args.amount_per_period == 0 {
+            return Err("amount_per_period must be positive".to_string());
+        }
+        if args.period_nanos < MIN_STREAM_PERIOD_NANOS {
+            return Err(format!(
+                "period_nanos must be at least {}",
+                MIN_STREAM_PERIOD_NANOS
+            ));
+        }
+        let first_payment_at = now.saturating_add(args.period_nanos);
+        if args.end_time < first_payment_at {
+            return Err(format!(
+                "end_time {} is before the first payment at {}",
+                args.end_time, first_payment_at
+            ));
+        }
+        match self.asset(&args.ledger) {
+            None => return Err(format!("{} is not a registered asset", args.ledger)),
+            Some(asset) if asset.kind == AssetKind::ReceiptNft => {
+                return Err(format!(
+                    "{} is a receipt NFT collection and cannot be streamed",
+                    asset.symbol
+                ))
+            }
+            Some(_) => {}
+        }
+        self.check_withdrawal_destination(args.recipient)?;
+        let active = self
+            .streams
+            .iter()
+            .filter(|(_, s)| s.status == StreamStatus::Active)
+            .count();
+        if active >= MAX_ACTIVE_STREAMS {
+            return Err(format!(
+                "{} payment streams are already active",
+                MAX_ACTIVE_STREAMS
+            ));
+        }
+        let id = self.next_stream_id;
+        self.next_stream_id += 1;
+        self.streams.insert(
+            id,
+            PaymentStream {
+                id,
+                recipient: args.recipient,
+                ledger: args.ledger,
+                amount_per_period: args.amount_per_period,
+                period_nanos: args.period_nanos,
+                end_time: args.end_time,
+                created_by,
+                created_at: now,
+                next_payment_at: first_payment_at,
+                payments_made: 0,
+                total_paid: 0,
+                last_error: None,
+                status: StreamStatus::Active,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Stop stream `id`; an active or halted stream makes no further
+    /// payments.
+    pub fn cancel_stream(&mut self, id: u64) -> Result<(), String> {
+        let mut stream = self
+            .streams
+            .get(&id)
+            .ok_or_else(|| format!("No stream {}", id))?;
+        match stream.status {
+            StreamStatus::Active | StreamStatus::Halted { .. } => {}
+            status => return Err(format!("Stream {} is {:?}", id, status)),
+        }
+        stream.status = StreamStatus::Cancelled;
+        self.streams.insert(id, stream);
+        Ok(())
+    }
+
+    /// The withdrawal of the next payment of every active stream due at
+    /// `now`, by stream ID.
+    pub fn due_stream_payments(&self, now: u64) -> Vec<(u64, WithdrawArgs)> {
+        self.streams
+            .iter()
+            .filter(|(_, s)| s.status == StreamStatus::Active && s.next_payment_at <= now)
+            .map(|(id, s)| (id, stream_payment_args(&s)))
+            .collect()
+    }
+
+    /// Count a payment of stream `id` and move it on a period. Returns
+    /// whether that was its last payment.
+    pub fn record_stream_payment(&mut self, id: u64) -> bool {
+        let Some(mut stream) = self.streams.get(&id) else {
+            return false;
+        };
+        stream.payments_made += 1;
+        stream.total_paid = stream.total_paid.saturating_add(stream.amount_per_period);
+        stream.next_payment_at = stream.next_payment_at.saturating_add(stream.period_nanos);
+        stream.last_error = None;
+        let completed =
+            stream.status == StreamStatus::Active && stream.next_payment_at > stream.end_time;
+        if completed {
+            stream.status = StreamStatus::Completed;
+        }
+        self.streams.insert(id, stream);
+        completed
+    }
+
+    /// Note a failed payment of stream `id`, halting the stream when the
+    /// payment may have landed. Returns whether the error differs from the
+    /// last one, so a payment retried every run is reported once.
+    pub fn record_stream_failure(&mut self, id: u64, error: String, halt: bool) -> bool {
+        let Some(mut stream) = self.streams.get(&id) else {
+            return false;
+        };
+        let new_error = stream.last_error.as_ref() != Some(&error);
+        if halt && stream.status == StreamStatus::Active {
+            stream.status = StreamStatus::Halted {
+                error: error.clone(),
+            };
+        }
+        stream.last_error = Some(error);
+        self.streams.insert(id, stream);
+        new_error
+    }
+
+    pub fn get_stream(&self, id: u64) -> Option<PaymentStream> {
+        self.streams.get(&id)
+    }
+
+    /// Every stream, oldest first.
+    pub fn streams(&self) -> Vec<PaymentStream> {
+        self.streams.iter().map(|(_, s)| s).collect()
+    }
+
+    // ------------------------------------------------------------------
+    // Balance reconciliation
+    // ------------------------------------------------------------------
+
+    /// Assets whose ledger answers `icrc1_balance_of` in token units.
+    /// Receipt NFT collections count tokens on an ICRC-7 ledger and are
+    /// left out.
+    pub fn reconcilable_assets(&self) -> Vec<TreasuryAsset> {
+        self.assets()
+            .into_iter()
+            .filter(|a| a.kind != AssetKind::ReceiptNft)
+            .collect()
+    }
+
+    /// Compare each ledger's reported balance of the treasury account with
+    /// the tracked total and store the result as the latest report.
+    /// `results` holds each ledger's `icrc1_balance_of`, or the error from
+    /// querying it. Returns the entries whose delta is nonzero and differs
+    /// from the previous run's, so a standing discrepancy is reported once.
+    pub fn record_reconciliation(
+        &mut self,
+        results: Vec<(Principal, Result<u64, String>)>,
+        now: u64,
+    ) -> Vec<ReconciliationEntry> {
+        let previous = self.reconciliation.get().clone();
+        let mut entries = vec![];
+        let mut new_discrepancies = vec![];
+        for (ledger, result) in results {
+            let symbol = self.asset(&ledger).map(|a| a.symbol).unwrap_or_default();
+            let tracked = self.balances.get(&ledger).map_or(0, |b| b.total);
+            let (actual, error) = match result {
+                Ok(balance) => (Some(balance), None),
+                Err(e) => (None, Some(e)),
+            };
+            let delta = actual.map_or(0, |actual| {
+                (actual as i128 - tracked as i128).clamp(i64::MIN as i128, i64::MAX as i128) as i64
+            });
+            let entry = ReconciliationEntry {
+                ledger,
+                symbol,
+                tracked,
+                actual,
+                delta,
+                checked_at: now,
+                error,
+            };
+            let previous_delta = previous
+                .entries
+                .iter()
+                .find(|e| e.ledger == ledger && e.actual.is_some())
+                .map_or(0, |e| e.delta);
+            if entry.actual.is_some() && delta != 0 && delta != previous_delta {
+                new_discrepancies.push(entry.clone());
+            }
+            entries.push(entry);
+        }
+        let report = ReconciliationReport {
+            last_run_at: Some(now),
+            discrepancies: entries.iter().filter(|e| e.delta != 0).count() as u64,
+            entries,
+        };
+        // Ignore the error — as for balances, the cell is unbounded.
+        let _ = self.reconciliation.set(report);
+        new_discrepancies
+    }
+
+    pub fn reconciliation_report(&self) -> ReconciliationReport {
+        self.reconciliation.get().clone()
+    }
+
     // ------------------------------------------------------------------
     // Queries
     // ------------------------------------------------------------------
@@ -470,6 +1468,59 @@ impl TreasuryState {
     }
 }
 
+/// Check a signer set: distinct, non-anonymous signers, an approval count
+/// they can reach, a nonzero expiry and one limit per ledger.
+pub fn validate_signer_config(config: &SignerConfig) -> Result<(), String> {
+    if config.signers.is_empty() || config.signers.len() > MAX_SIGNERS {
+        return Err(format!("A signer set needs 1 to {} signers", MAX_SIGNERS));
+    }
+    for (i, signer) in config.signers.iter().enumerate() {
+        if *signer == Principal::anonymous() {
+            return Err("The anonymous principal cannot be a signer".to_string());
+        }
+        if config.signers[..i].contains(signer) {
+            return Err(format!("{} is listed twice", signer));
+        }
+    }
+    if config.required_approvals == 0 || config.required_approvals as usize > config.signers.len() {
+        return Err(format!(
+            "required_approvals must be between 1 and {}",
+            config.signers.len()
+        ));
+    }
+    if config.proposal_expiry_nanos == 0 {
+        return Err("proposal_expiry_nanos must be positive".to_string());
+    }
+    for (i, (ledger, _)) in config.withdrawal_limits.iter().enumerate() {
+        if config.withdrawal_limits[..i]
+            .iter()
+            .any(|(l, _)| l == ledger)
+        {
+            return Err(format!("{} has two withdrawal limits", ledger));
+        }
+    }
+    Ok(())
+}
+
+/// The withdrawal paying `stream`'s next period. Its `request_id` is fixed
+/// per stream and payment, so retrying a payment deduplicates at the ledger.
+pub fn stream_payment_args(stream: &PaymentStream) -> WithdrawArgs {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    let mut h = DefaultHasher::new();
+    "stream".hash(&mut h);
+    stream.id.hash(&mut h);
+    stream.payments_made.hash(&mut h);
+    WithdrawArgs {
+        asset_type: None,
+        ledger: Some(stream.ledger),
+        amount: stream.amount_per_period,
+        to: stream.recipient,
+        memo: None,
+        request_id: Some(h.finish()),
+    }
+}
+
 // ======================================================================
 // Module-level state helpers
 // ======================================================================
@@ -504,8 +1555,15 @@ pub fn restore_state() {
                 ckusdc_ledger: None,
                 is_paused: true,
                 stability_pool_reporter: None,
+                protocol_backend: None,
+                protocol_mode: None,
+                mode_inheritance_policy: None,
+                withdrawal_destinations: None,
+                assets: None,
+                signer_config: None,
+                co_approval_thresholds: None,
             };
-            let config =
+            let config: StableCell<TreasuryConfig, Memory> =
                 StableCell::init(memory_manager.get(MemoryId::new(MEM_CONFIG)), dummy_config)
                     .unwrap();
 
@@ -519,26 +1577,38 @@ pub fn restore_state() {
             .unwrap();
 
             let snapshot = balances_cell.get().clone();
-            let balances = if snapshot.entries.is_empty() {
+            let treasury_config = config.get().clone();
+            let mut balances = empty_balances(&treasury_config.assets.clone().unwrap_or_default());
+            if !snapshot.by_ledger.is_empty() {
+                // Normal path: restore from persisted snapshot.
+                balances.extend(snapshot.by_ledger);
+            } else if !snapshot.entries.is_empty() {
+                // First upgrade to the asset registry: re-key the snapshot
+                // by ledger. A legacy asset without a configured ledger
+                // could never be withdrawn and is dropped.
+                for (asset_type, balance) in snapshot.entries {
+                    if let Some(ledger) = treasury_config.legacy_ledger(&asset_type) {
+                        balances.insert(ledger, balance);
+                    }
+                }
+            } else {
                 // First upgrade from pre-BalancesSnapshot code: reconstruct
                 // from deposit records (withdrawals still lost — acceptable
                 // since treasury has had no withdrawals yet).
-                let mut b = empty_balances();
                 for (_id, record) in deposits.iter() {
-                    if let Some(balance) = b.get_mut(&record.asset_type) {
+                    let ledger = record.ledger.or_else(|| {
+                        record
+                            .asset_type
+                            .as_ref()
+                            .and_then(|asset_type| treasury_config.legacy_ledger(asset_type))
+                    });
+                    if let Some(ledger) = ledger {
+                        let balance = balances.entry(ledger).or_default();
                         balance.total += record.amount;
                         balance.available += record.amount;
                     }
                 }
-                b
-            } else {
-                // Normal path: restore from persisted snapshot.
-                let mut b = empty_balances();
-                for (asset, bal) in snapshot.entries {
-                    b.insert(asset, bal);
-                }
-                b
-            };
+            }
 
             // Compute next_deposit_id from max key in the deposit map.
             let max_id = deposits.iter().map(|(id, _)| id).last().unwrap_or(0);
@@ -565,8 +1635,21 @@ pub fn restore_state() {
                 StableBTreeMap::init(
                     memory_manager.get(MemoryId::new(MEM_SP_UNALLOCATED_INTEREST_TRANSFER_BLOCKS)),
                 );
-
-            *s.borrow_mut() = Some(TreasuryState {
+            let reconciliation: StableCell<ReconciliationReport, Memory> = StableCell::init(
+                memory_manager.get(MemoryId::new(MEM_RECONCILIATION)),
+                ReconciliationReport::default(),
+            )
+            .unwrap();
+            let claimed_deposit_blocks: StableBTreeMap<(Principal, u64), u64, Memory> =
+                StableBTreeMap::init(memory_manager.get(MemoryId::new(MEM_CLAIMED_DEPOSIT_BLOCKS)));
+            let proposals: StableBTreeMap<u64, Proposal, Memory> =
+                StableBTreeMap::init(memory_manager.get(MemoryId::new(MEM_PROPOSALS)));
+            let next_proposal_id = proposals.iter().map(|(id, _)| id).last().unwrap_or(0) + 1;
+            let streams: StableBTreeMap<u64, PaymentStream, Memory> =
+                StableBTreeMap::init(memory_manager.get(MemoryId::new(MEM_STREAMS)));
+            let next_stream_id = streams.iter().map(|(id, _)| id).last().unwrap_or(0) + 1;
+
+            let mut state = TreasuryState {
                 deposits,
                 balances,
                 balances_cell,
@@ -577,7 +1660,20 @@ pub fn restore_state() {
                 withdrawal_created_at,
                 sp_unallocated_interest_blocks,
                 sp_unallocated_interest_transfer_blocks,
-            });
+                reconciliation,
+                claimed_deposit_blocks,
+                proposals,
+                next_proposal_id,
+                streams,
+                next_stream_id,
+            };
+            if treasury_config.assets.is_none() {
+                state.migrate_to_asset_registry();
+            }
+            if state.claimed_deposit_blocks.is_empty() {
+                state.claim_recorded_deposit_blocks();
+            }
+            *s.borrow_mut() = Some(state);
         });
     });
 }
@@ -1,7 +1,10 @@
 #[cfg(test)]
 mod tests {
     use crate::types::*;
-    use candid::Principal;
+    use candid::{Nat, Principal};
+    use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+    use serde_bytes::ByteBuf;
+    use std::collections::BTreeMap;
 
     fn mock_principal() -> Principal {
         Principal::anonymous()
@@ -10,30 +13,31 @@ mod tests {
     fn init_test_treasury() {
         let args = TreasuryInitArgs {
             controller: mock_principal(),
-            icusd_ledger: mock_principal(),
-            icp_ledger: mock_principal(),
-            ckbtc_ledger: Some(mock_principal()),
-            ckusdt_ledger: Some(mock_principal()),
-            ckusdc_ledger: Some(mock_principal()),
+            icusd_ledger: Principal::from_slice(&[1]),
+            icp_ledger: Principal::from_slice(&[2]),
+            ckbtc_ledger: Some(Principal::from_slice(&[3])),
+            ckusdt_ledger: Some(Principal::from_slice(&[4])),
+            ckusdc_ledger: Some(Principal::from_slice(&[5])),
+            withdrawal_destinations: None,
         };
         crate::state::init_state(args);
     }
 
+    /// Ledger of a legacy asset type in the test treasury.
+    fn ledger(asset_type: AssetType) -> Principal {
+        crate::state::with_state(|s| s.get_config().legacy_ledger(&asset_type).unwrap())
+    }
+
     #[test]
     fn test_treasury_initialization() {
         init_test_treasury();
 
         let status = crate::state::with_state(|s| {
             let config = s.get_config();
-            let balances = s
-                .balances
-                .iter()
-                .map(|(asset_type, balance)| (asset_type.clone(), balance.clone()))
-                .collect();
-
             TreasuryStatus {
                 total_deposits: s.get_deposits_count(),
-                balances,
+                balances: s.legacy_balances(),
+                assets: s.holdings(),
                 controller: config.icusd_ledger, // just use any principal for display
                 is_paused: config.is_paused,
             }
@@ -42,6 +46,7 @@ mod tests {
         assert!(!status.is_paused);
         assert_eq!(status.total_deposits, 0);
         assert_eq!(status.balances.len(), 5); // ICUSD, ICP, CKBTC, CKUSDT, CKUSDC
+        assert_eq!(status.assets.len(), 5);
     }
 
     #[test]
@@ -51,7 +56,8 @@ mod tests {
         let deposit_record = DepositRecord {
             id: 0, // Will be set by add_deposit
             deposit_type: DepositType::BorrowingFee,
-            asset_type: AssetType::ICUSD,
+            asset_type: Some(AssetType::ICUSD),
+            ledger: None,
             amount: 1_000_000, // 0.01 icUSD in e8s
             block_index: 12345,
             timestamp: 1234567890,
@@ -63,8 +69,9 @@ mod tests {
         assert_eq!(deposit_id, 1);
 
         // Check balance was updated
-        let balance =
-            crate::state::with_state(|s| s.balances.get(&AssetType::ICUSD).unwrap().clone());
+        let balance = crate::state::with_state(|s| {
+            s.balances.get(&ledger(AssetType::ICUSD)).unwrap().clone()
+        });
 
         assert_eq!(balance.total, 1_000_000);
         assert_eq!(balance.available, 1_000_000);
@@ -85,7 +92,7 @@ mod tests {
         });
         assert_eq!(duplicate_id, deposit_id);
         assert!(!duplicate_new);
-        let balance = crate::state::with_state(|s| s.balances[&AssetType::ICUSD].clone());
+        let balance = crate::state::with_state(|s| s.balances[&ledger(AssetType::ICUSD)].clone());
         assert_eq!(balance.total, 900);
         assert_eq!(
             crate::state::with_state(|s| s.get_deposits_count()),
@@ -118,7 +125,8 @@ mod tests {
         let deposit_record = DepositRecord {
             id: 0,
             deposit_type: DepositType::LiquidationFee,
-            asset_type: AssetType::ICP,
+            asset_type: Some(AssetType::ICP),
+            ledger: None,
             amount: 5_000_000, // 0.05 ICP in e8s
             block_index: 54321,
             timestamp: 1234567890,
@@ -128,13 +136,14 @@ mod tests {
         crate::state::with_state_mut(|s| s.add_deposit(deposit_record));
 
         // Now try to withdraw less than available
-        let result = crate::state::with_state_mut(|s| s.withdraw(AssetType::ICP, 2_000_000));
+        let icp_ledger = ledger(AssetType::ICP);
+        let result = crate::state::with_state_mut(|s| s.withdraw(icp_ledger, 2_000_000));
 
         assert!(result.is_ok());
 
         // Check remaining balance
         let balance =
-            crate::state::with_state(|s| s.balances.get(&AssetType::ICP).unwrap().clone());
+            crate::state::with_state(|s| s.balances.get(&ledger(AssetType::ICP)).unwrap().clone());
 
         assert_eq!(balance.total, 3_000_000);
         assert_eq!(balance.available, 3_000_000);
@@ -145,7 +154,8 @@ mod tests {
         init_test_treasury();
 
         // Try to withdraw from empty treasury
-        let result = crate::state::with_state_mut(|s| s.withdraw(AssetType::CKBTC, 1_000_000));
+        let ckbtc_ledger = ledger(AssetType::CKBTC);
+        let result = crate::state::with_state_mut(|s| s.withdraw(ckbtc_ledger, 1_000_000));
 
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Insufficient balance"));
@@ -159,7 +169,8 @@ mod tests {
         let deposit_record = DepositRecord {
             id: 0,
             deposit_type: DepositType::InterestRevenue,
-            asset_type: AssetType::ICUSD,
+            asset_type: Some(AssetType::ICUSD),
+            ledger: None,
             amount: 10_000_000,
             block_index: 1,
             timestamp: 1000,
@@ -168,17 +179,18 @@ mod tests {
         crate::state::with_state_mut(|s| s.add_deposit(deposit_record));
 
         // Withdraw (simulating pre-transfer deduction)
-        crate::state::with_state_mut(|s| s.withdraw(AssetType::ICUSD, 3_000_000)).unwrap();
+        let icusd_ledger = ledger(AssetType::ICUSD);
+        crate::state::with_state_mut(|s| s.withdraw(icusd_ledger, 3_000_000)).unwrap();
 
         let balance_after_withdraw =
-            crate::state::with_state(|s| s.balances.get(&AssetType::ICUSD).unwrap().clone());
+            crate::state::with_state(|s| s.balances.get(&icusd_ledger).unwrap().clone());
         assert_eq!(balance_after_withdraw.available, 7_000_000);
 
         // Simulate transfer failure → restore
-        crate::state::with_state_mut(|s| s.restore_balance(&AssetType::ICUSD, 3_000_000));
+        crate::state::with_state_mut(|s| s.restore_balance(&icusd_ledger, 3_000_000));
 
         let balance_after_restore =
-            crate::state::with_state(|s| s.balances.get(&AssetType::ICUSD).unwrap().clone());
+            crate::state::with_state(|s| s.balances.get(&icusd_ledger).unwrap().clone());
         assert_eq!(balance_after_restore.available, 10_000_000);
         assert_eq!(balance_after_restore.total, 10_000_000);
     }
@@ -202,6 +214,99 @@ mod tests {
         assert!(!config.is_paused);
     }
 
+    #[test]
+    fn test_withdrawals_follow_inherited_protocol_mode() {
+        init_test_treasury();
+        let restricted = || crate::state::with_state(|s| s.withdrawals_restricted_by_mode());
+        assert_eq!(restricted(), None);
+
+        // Recovery is not blocked by the default policy; ReadOnly is.
+        let changed = crate::state::with_state_mut(|s| s.set_protocol_mode(ProtocolMode::Recovery));
+        assert_eq!(changed, Ok(true));
+        assert_eq!(restricted(), None);
+        crate::state::with_state_mut(|s| s.set_protocol_mode(ProtocolMode::ReadOnly)).unwrap();
+        assert_eq!(restricted(), Some(ProtocolMode::ReadOnly));
+        // Repeating the current mode changes nothing.
+        let changed = crate::state::with_state_mut(|s| s.set_protocol_mode(ProtocolMode::ReadOnly));
+        assert_eq!(changed, Ok(false));
+        // A bootstrapping backend is held to the ReadOnly entry.
+        crate::state::with_state_mut(|s| s.set_protocol_mode(ProtocolMode::Bootstrapping)).unwrap();
+        assert_eq!(restricted(), Some(ProtocolMode::Bootstrapping));
+
+        crate::state::with_state_mut(|s| {
+            s.set_mode_inheritance_policy(ModeInheritancePolicy {
+                block_withdrawals_in: vec![ProtocolMode::Recovery],
+            })
+        })
+        .unwrap();
+        assert_eq!(restricted(), None);
+        crate::state::with_state_mut(|s| s.set_protocol_mode(ProtocolMode::Recovery)).unwrap();
+        let status = crate::state::with_state(|s| s.mode_inheritance_status());
+        assert!(status.withdrawals_blocked);
+        assert_eq!(status.protocol_mode, Some(ProtocolMode::Recovery));
+        assert_eq!(status.protocol_backend, None);
+    }
+
+    #[test]
+    fn test_withdrawal_destination_timelock() {
+        init_test_treasury();
+        let to = Principal::from_slice(&[7]);
+        let hour = 60 * 60 * 1_000_000_000u64;
+        let check = || crate::state::with_state(|s| s.check_withdrawal_destination(to));
+
+        // Nothing is registered yet, so every destination is refused.
+        assert!(check().unwrap_err().contains("not a registered"));
+
+        let active_at = crate::state::with_state_mut(|s| {
+            s.schedule_withdrawal_destination(to, "ops".to_string(), 10 * hour, 48 * hour)
+        });
+        assert_eq!(active_at, Ok(58 * hour));
+        assert!(check().unwrap_err().contains("timelocked"));
+        // Adding it again does not reset the clock.
+        assert!(crate::state::with_state_mut(|s| {
+            s.schedule_withdrawal_destination(to, "ops".to_string(), 11 * hour, 48 * hour)
+        })
+        .is_err());
+
+        let early =
+            crate::state::with_state_mut(|s| s.activate_due_withdrawal_destinations(57 * hour));
+        assert!(early.is_empty());
+        assert!(check().is_err());
+        let due =
+            crate::state::with_state_mut(|s| s.activate_due_withdrawal_destinations(58 * hour));
+        assert_eq!(due, vec![to]);
+        assert_eq!(check(), Ok(()));
+        // Activation happens once.
+        let again =
+            crate::state::with_state_mut(|s| s.activate_due_withdrawal_destinations(59 * hour));
+        assert!(again.is_empty());
+
+        crate::state::with_state_mut(|s| s.remove_withdrawal_destination(to)).unwrap();
+        assert!(check().is_err());
+        assert!(crate::state::with_state_mut(|s| s.remove_withdrawal_destination(to)).is_err());
+    }
+
+    #[test]
+    fn test_install_withdrawal_destinations_are_active() {
+        let to = Principal::from_slice(&[7]);
+        crate::state::init_state(TreasuryInitArgs {
+            controller: mock_principal(),
+            icusd_ledger: mock_principal(),
+            icp_ledger: mock_principal(),
+            ckbtc_ledger: None,
+            ckusdt_ledger: None,
+            ckusdc_ledger: None,
+            withdrawal_destinations: Some(vec![to]),
+        });
+        let destinations = crate::state::with_state(|s| s.withdrawal_destinations());
+        assert_eq!(destinations.len(), 1);
+        assert!(destinations[0].active);
+        assert_eq!(
+            crate::state::with_state(|s| s.check_withdrawal_destination(to)),
+            Ok(())
+        );
+    }
+
     #[test]
     fn test_deposit_history() {
         init_test_treasury();
@@ -211,7 +316,8 @@ mod tests {
             DepositRecord {
                 id: 0,
                 deposit_type: DepositType::BorrowingFee,
-                asset_type: AssetType::ICUSD,
+                asset_type: Some(AssetType::ICUSD),
+                ledger: None,
                 amount: 1_000_000,
                 block_index: 1,
                 timestamp: 1000,
@@ -220,7 +326,8 @@ mod tests {
             DepositRecord {
                 id: 0,
                 deposit_type: DepositType::RedemptionFee,
-                asset_type: AssetType::ICP,
+                asset_type: Some(AssetType::ICP),
+                ledger: None,
                 amount: 2_000_000,
                 block_index: 2,
                 timestamp: 2000,
@@ -249,7 +356,8 @@ mod tests {
         let deposit = DepositRecord {
             id: 0,
             deposit_type: DepositType::LiquidationFee,
-            asset_type: AssetType::ICP,
+            asset_type: Some(AssetType::ICP),
+            ledger: None,
             amount: 10_000_000,
             block_index: 1,
             timestamp: 1000,
@@ -261,11 +369,12 @@ mod tests {
         // `amount - fee`, and the ledger debits `sent + fee` from the account.
         let amount = 2_000_000u64;
         let fee = 10_000u64;
-        crate::state::with_state_mut(|s| s.withdraw(AssetType::ICP, amount)).unwrap();
+        let icp_ledger = ledger(AssetType::ICP);
+        crate::state::with_state_mut(|s| s.withdraw(icp_ledger, amount)).unwrap();
         let sent = crate::withdrawal_send_amount(amount, fee).unwrap();
 
         let balance =
-            crate::state::with_state(|s| s.balances.get(&AssetType::ICP).unwrap().clone());
+            crate::state::with_state(|s| s.balances.get(&ledger(AssetType::ICP)).unwrap().clone());
         let tracked_drop = 10_000_000 - balance.total;
         let onchain_drop = sent + fee;
 
@@ -341,7 +450,8 @@ mod tests {
         let deposit = DepositRecord {
             id: 0,
             deposit_type: DepositType::BorrowingFee,
-            asset_type: AssetType::ICP,
+            asset_type: Some(AssetType::ICP),
+            ledger: None,
             amount: 5_000_000,
             block_index: 1,
             timestamp: 1000,
@@ -351,7 +461,7 @@ mod tests {
 
         // Verify the StableCell snapshot matches in-memory balances
         let (in_memory, snapshot) = crate::state::with_state(|s| {
-            let mem = s.balances.get(&AssetType::ICP).unwrap().clone();
+            let mem = s.balances.get(&ledger(AssetType::ICP)).unwrap().clone();
             let snap = s.balances_cell.get().clone();
             (mem, snap)
         });
@@ -359,12 +469,663 @@ mod tests {
         assert_eq!(in_memory.total, 5_000_000);
         // Find ICP in snapshot
         let icp_snap = snapshot
-            .entries
+            .by_ledger
             .iter()
-            .find(|(a, _)| *a == AssetType::ICP)
+            .find(|(a, _)| *a == ledger(AssetType::ICP))
             .map(|(_, b)| b.clone())
             .unwrap();
         assert_eq!(icp_snap.total, 5_000_000);
         assert_eq!(icp_snap.available, 5_000_000);
     }
+
+    #[test]
+    fn test_asset_registry_seeded_from_install_ledgers() {
+        init_test_treasury();
+
+        let assets = crate::state::with_state(|s| s.assets());
+        let symbols: Vec<(&str, u8)> = assets
+            .iter()
+            .map(|a| (a.symbol.as_str(), a.decimals))
+            .collect();
+        assert_eq!(
+            symbols,
+            vec![
+                ("icUSD", 8),
+                ("ICP", 8),
+                ("ckBTC", 8),
+                ("ckUSDT", 6),
+                ("ckUSDC", 6)
+            ]
+        );
+        assert!(assets.iter().all(|a| a.kind == AssetKind::Fungible));
+        assert_eq!(assets[1].ledger, ledger(AssetType::ICP));
+    }
+
+    #[test]
+    fn test_registered_asset_is_held_and_reported() {
+        init_test_treasury();
+
+        let lp_ledger = Principal::from_slice(&[9]);
+        let lp = TreasuryAsset {
+            ledger: lp_ledger,
+            symbol: "3USD".to_string(),
+            decimals: 8,
+            kind: AssetKind::LpShare,
+        };
+        crate::state::with_state_mut(|s| s.register_asset(lp.clone())).unwrap();
+        crate::state::with_state_mut(|s| {
+            s.add_deposit(DepositRecord {
+                id: 0,
+                deposit_type: DepositType::InterestRevenue,
+                asset_type: None,
+                ledger: Some(lp_ledger),
+                amount: 4_000,
+                block_index: 1,
+                timestamp: 1000,
+                memo: None,
+            })
+        });
+
+        let holding = crate::state::with_state(|s| s.holdings())
+            .into_iter()
+            .find(|h| h.asset.ledger == lp_ledger)
+            .unwrap();
+        assert_eq!(holding.asset, lp);
+        assert_eq!(holding.balance.total, 4_000);
+        // The legacy report only covers the legacy asset types.
+        assert_eq!(crate::state::with_state(|s| s.legacy_balances()).len(), 5);
+
+        // Re-registering corrects the metadata and keeps the balance.
+        let renamed = TreasuryAsset {
+            symbol: "3USD-LP".to_string(),
+            ..lp
+        };
+        crate::state::with_state_mut(|s| s.register_asset(renamed.clone())).unwrap();
+        assert_eq!(crate::state::with_state(|s| s.assets()).len(), 6);
+        assert_eq!(
+            crate::state::with_state(|s| s.asset(&lp_ledger)),
+            Some(renamed)
+        );
+        assert_eq!(
+            crate::state::with_state(|s| s.balances[&lp_ledger].total),
+            4_000
+        );
+    }
+
+    #[test]
+    fn test_upgrade_migrates_records_keyed_by_asset_type() {
+        init_test_treasury();
+        let icp_ledger = ledger(AssetType::ICP);
+
+        // Roll the stable state back to its pre-registry shape.
+        crate::state::with_state_mut(|s| {
+            let mut config = s.get_config();
+            config.assets = None;
+            s.config.set(config).unwrap();
+            s.balances_cell
+                .set(BalancesSnapshot {
+                    entries: vec![(
+                        AssetType::ICP,
+                        AssetBalance {
+                            total: 7_000,
+                            reserved: 0,
+                            available: 7_000,
+                        },
+                    )],
+                    by_ledger: vec![],
+                })
+                .unwrap();
+            s.deposits.insert(
+                1,
+                DepositRecord {
+                    id: 1,
+                    deposit_type: DepositType::LiquidationFee,
+                    asset_type: Some(AssetType::ICP),
+                    ledger: None,
+                    amount: 7_000,
+                    block_index: 1,
+                    timestamp: 1000,
+                    memo: None,
+                },
+            );
+        });
+
+        crate::state::restore_state();
+
+        crate::state::with_state(|s| {
+            assert_eq!(s.assets().len(), 5);
+            assert_eq!(s.balances[&icp_ledger].total, 7_000);
+            assert_eq!(s.deposits.get(&1).unwrap().ledger, Some(icp_ledger));
+            let snapshot = s.balances_cell.get().clone();
+            assert!(snapshot.entries.is_empty());
+            assert!(snapshot
+                .by_ledger
+                .iter()
+                .any(|(l, b)| *l == icp_ledger && b.total == 7_000));
+        });
+    }
+
+    #[test]
+    fn test_reconciliation_reports_deltas_against_ledger_balances() {
+        init_test_treasury();
+        let icp_ledger = ledger(AssetType::ICP);
+        let icusd_ledger = ledger(AssetType::ICUSD);
+        crate::state::with_state_mut(|s| {
+            s.add_deposit(DepositRecord {
+                id: 0,
+                deposit_type: DepositType::LiquidationFee,
+                asset_type: None,
+                ledger: Some(icp_ledger),
+                amount: 5_000,
+                block_index: 1,
+                timestamp: 1000,
+                memo: None,
+            })
+        });
+
+        // The ledger holds 300 more ICP than recorded; icUSD is unreachable.
+        let discrepancies = crate::state::with_state_mut(|s| {
+            s.record_reconciliation(
+                vec![
+                    (icp_ledger, Ok(5_300)),
+                    (icusd_ledger, Err("unreachable".to_string())),
+                ],
+                2000,
+            )
+        });
+        assert_eq!(discrepancies.len(), 1);
+        assert_eq!(discrepancies[0].ledger, icp_ledger);
+        assert_eq!(discrepancies[0].delta, 300);
+
+        let report = crate::state::with_state(|s| s.reconciliation_report());
+        assert_eq!(report.last_run_at, Some(2000));
+        assert_eq!(report.discrepancies, 1);
+        let icp = report
+            .entries
+            .iter()
+            .find(|e| e.ledger == icp_ledger)
+            .unwrap();
+        assert_eq!(
+            (icp.symbol.as_str(), icp.tracked, icp.actual),
+            ("ICP", 5_000, Some(5_300))
+        );
+        assert_eq!(icp.checked_at, 2000);
+        let icusd = report
+            .entries
+            .iter()
+            .find(|e| e.ledger == icusd_ledger)
+            .unwrap();
+        assert_eq!((icusd.actual, icusd.delta), (None, 0));
+        assert!(icusd.error.is_some());
+
+        // A standing discrepancy is reported once; a changed one again.
+        let repeat = crate::state::with_state_mut(|s| {
+            s.record_reconciliation(vec![(icp_ledger, Ok(5_300))], 3000)
+        });
+        assert!(repeat.is_empty());
+        let short = crate::state::with_state_mut(|s| {
+            s.record_reconciliation(vec![(icp_ledger, Ok(4_000))], 4000)
+        });
+        assert_eq!(short[0].delta, -1_000);
+    }
+
+    #[test]
+    fn test_reconciliation_skips_receipt_nfts_and_survives_upgrade() {
+        init_test_treasury();
+        let nft_ledger = Principal::from_slice(&[9]);
+        crate::state::with_state_mut(|s| {
+            s.register_asset(TreasuryAsset {
+                ledger: nft_ledger,
+                symbol: "RCPT".to_string(),
+                decimals: 0,
+                kind: AssetKind::ReceiptNft,
+            })
+        })
+        .unwrap();
+        let reconcilable = crate::state::with_state(|s| s.reconcilable_assets());
+        assert_eq!(reconcilable.len(), 5);
+        assert!(reconcilable.iter().all(|a| a.ledger != nft_ledger));
+
+        let icp_ledger = ledger(AssetType::ICP);
+        crate::state::with_state_mut(|s| s.record_reconciliation(vec![(icp_ledger, Ok(0))], 2000));
+        crate::state::restore_state();
+        let report = crate::state::with_state(|s| s.reconciliation_report());
+        assert_eq!(report.last_run_at, Some(2000));
+        assert_eq!(report.discrepancies, 0);
+        assert_eq!(report.entries.len(), 1);
+    }
+
+    fn transfer_block(btype: &str, to: Principal, amount: u64) -> ICRC3Value {
+        let account = ICRC3Value::Array(vec![ICRC3Value::Blob(ByteBuf::from(
+            to.as_slice().to_vec(),
+        ))]);
+        let tx = BTreeMap::from([
+            ("to".to_string(), account),
+            ("amt".to_string(), ICRC3Value::Nat(Nat::from(amount))),
+        ]);
+        ICRC3Value::Map(BTreeMap::from([
+            ("btype".to_string(), ICRC3Value::Text(btype.to_string())),
+            ("tx".to_string(), ICRC3Value::Map(tx)),
+        ]))
+    }
+
+    #[test]
+    fn test_deposit_block_must_pay_the_amount_to_the_treasury() {
+        use crate::deposit_verification::{decode_transfer, validate_deposit};
+        let treasury = Principal::from_slice(&[7]);
+
+        for btype in ["1xfer", "2xfer", "1mint"] {
+            let transfer = decode_transfer(&transfer_block(btype, treasury, 500)).unwrap();
+            assert_eq!(transfer.amount, 500);
+            assert!(validate_deposit(&transfer, treasury, 500).is_ok());
+        }
+        let transfer = decode_transfer(&transfer_block("1xfer", treasury, 500)).unwrap();
+        assert!(validate_deposit(&transfer, treasury, 501).is_err());
+
+        let elsewhere = decode_transfer(&transfer_block("1xfer", mock_principal(), 500)).unwrap();
+        assert!(validate_deposit(&elsewhere, treasury, 500).is_err());
+        let approve = decode_transfer(&transfer_block("2approve", treasury, 500)).unwrap();
+        assert_eq!(approve.op, "approve");
+        assert!(validate_deposit(&approve, treasury, 500).is_err());
+        assert!(decode_transfer(&ICRC3Value::Text("1xfer".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_icp_deposit_block_must_pay_the_treasury_account_identifier() {
+        use crate::deposit_verification::{
+            decode_icp_transfer, validate_icp_deposit, IcpBlock, IcpOperation, IcpTokens,
+            IcpTransaction,
+        };
+        let treasury_account_id = vec![7u8; 32];
+        let block = |operation| IcpBlock {
+            transaction: IcpTransaction {
+                operation: Some(operation),
+            },
+        };
+        let transfer_to = |to: Vec<u8>| {
+            block(IcpOperation::Transfer {
+                to,
+                amount: IcpTokens { e8s: 500 },
+            })
+        };
+
+        let transfer = decode_icp_transfer(&transfer_to(treasury_account_id.clone())).unwrap();
+        assert!(validate_icp_deposit(&transfer, &treasury_account_id, 500).is_ok());
+        assert!(validate_icp_deposit(&transfer, &treasury_account_id, 501).is_err());
+        let mint = decode_icp_transfer(&block(IcpOperation::Mint {
+            to: treasury_account_id.clone(),
+            amount: IcpTokens { e8s: 500 },
+        }))
+        .unwrap();
+        assert!(validate_icp_deposit(&mint, &treasury_account_id, 500).is_ok());
+
+        let elsewhere = decode_icp_transfer(&transfer_to(vec![8u8; 32])).unwrap();
+        assert!(validate_icp_deposit(&elsewhere, &treasury_account_id, 500).is_err());
+        let burn = decode_icp_transfer(&block(IcpOperation::Burn(candid::Reserved))).unwrap();
+        assert!(validate_icp_deposit(&burn, &treasury_account_id, 0).is_err());
+        let empty = IcpBlock {
+            transaction: IcpTransaction { operation: None },
+        };
+        assert!(decode_icp_transfer(&empty).is_err());
+    }
+
+    #[test]
+    fn test_a_funding_block_is_claimed_once() {
+        init_test_treasury();
+        let icp_ledger = ledger(AssetType::ICP);
+        let record = |block_index| DepositRecord {
+            id: 0,
+            deposit_type: DepositType::LiquidationFee,
+            asset_type: None,
+            ledger: Some(icp_ledger),
+            amount: 5_000,
+            block_index,
+            timestamp: 1000,
+            memo: None,
+        };
+
+        let first = crate::state::with_state_mut(|s| s.add_verified_deposit(record(42))).unwrap();
+        assert_eq!(
+            crate::state::with_state(|s| s.deposit_block_claim(icp_ledger, 42)),
+            Some(first)
+        );
+        assert!(crate::state::with_state_mut(|s| s.add_verified_deposit(record(42))).is_err());
+        // A repeat of the recorded deposit is recognised; a different claim
+        // on the same block is not.
+        assert_eq!(
+            crate::state::with_state(|s| {
+                s.repeated_deposit(icp_ledger, 42, 5_000, &DepositType::LiquidationFee)
+            }),
+            Some(first)
+        );
+        assert!(crate::state::with_state(|s| {
+            s.repeated_deposit(icp_ledger, 42, 4_999, &DepositType::LiquidationFee)
+        })
+        .is_none());
+        assert!(crate::state::with_state(|s| {
+            s.repeated_deposit(icp_ledger, 42, 5_000, &DepositType::BorrowingFee)
+        })
+        .is_none());
+        // The same index on another ledger is another block.
+        assert!(crate::state::with_state_mut(|s| {
+            s.add_verified_deposit(DepositRecord {
+                ledger: Some(ledger(AssetType::ICUSD)),
+                ..record(42)
+            })
+        })
+        .is_ok());
+        let balance = crate::state::with_state(|s| s.balances[&icp_ledger].clone());
+        assert_eq!(balance.total, 5_000);
+
+        // Deposits recorded before claims existed are claimed on upgrade.
+        crate::state::with_state_mut(|s| {
+            s.add_deposit(record(43));
+            s.claimed_deposit_blocks.clear_new();
+        });
+        crate::state::restore_state();
+        assert!(crate::state::with_state(|s| s.deposit_block_claim(icp_ledger, 43)).is_some());
+        assert_eq!(
+            crate::state::with_state(|s| s.deposit_block_claim(icp_ledger, 42)),
+            Some(first)
+        );
+    }
+
+    fn signer_config(signers: &[Principal], required_approvals: u32) -> SignerConfig {
+        SignerConfig {
+            signers: signers.to_vec(),
+            required_approvals,
+            withdrawal_limits: vec![(ledger(AssetType::ICUSD), 1_000)],
+            proposal_expiry_nanos: 100,
+        }
+    }
+
+    fn withdrawal(amount: u64) -> ProposalAction {
+        ProposalAction::Withdraw(WithdrawArgs {
+            asset_type: Some(AssetType::ICUSD),
+            ledger: None,
+            amount,
+            to: Principal::from_slice(&[7]),
+            memo: None,
+            request_id: None,
+        })
+    }
+
+    #[test]
+    fn test_signer_config_and_direct_withdrawal_limits() {
+        init_test_treasury();
+        let (alice, bob) = (Principal::from_slice(&[11]), Principal::from_slice(&[12]));
+        let icusd = ledger(AssetType::ICUSD);
+
+        for bad in [
+            signer_config(&[], 1),
+            signer_config(&[alice, alice], 1),
+            signer_config(&[alice, bob], 3),
+            signer_config(&[alice, Principal::anonymous()], 1),
+        ] {
+            assert!(crate::state::with_state_mut(|s| s.set_signer_config(bad)).is_err());
+        }
+        let config = signer_config(&[alice, bob], 2);
+        crate::state::with_state_mut(|s| s.set_signer_config(config.clone())).unwrap();
+        assert_eq!(
+            crate::state::with_state(|s| s.signer_config()),
+            Some(config)
+        );
+
+        let direct = |caller, ledger, amount| {
+            crate::state::with_state(|s| s.check_direct_withdrawal(caller, ledger, amount))
+        };
+        assert_eq!(direct(alice, icusd, 1_000), Ok(()));
+        assert!(direct(alice, icusd, 1_001)
+            .unwrap_err()
+            .contains("proposal"));
+        // A ledger without a limit needs a proposal for any amount.
+        assert!(direct(alice, ledger(AssetType::ICP), 1).is_err());
+        assert!(direct(Principal::from_slice(&[13]), icusd, 1)
+            .unwrap_err()
+            .contains("not a signer"));
+    }
+
+    #[test]
+    fn test_proposals_need_approvals_and_expire() {
+        init_test_treasury();
+        let (alice, bob, carol) = (
+            Principal::from_slice(&[11]),
+            Principal::from_slice(&[12]),
+            Principal::from_slice(&[13]),
+        );
+        crate::state::with_state_mut(|s| {
+            s.set_signer_config(signer_config(&[alice, bob, carol], 2))
+        })
+        .unwrap();
+
+        let id = crate::state::with_state_mut(|s| s.submit_proposal(alice, withdrawal(5_000), 10))
+            .unwrap();
+        // The proposer's approval alone is short of two.
+        assert!(
+            crate::state::with_state_mut(|s| s.start_proposal_execution(id, 20))
+                .unwrap()
+                .is_none()
+        );
+        assert!(crate::state::with_state_mut(|s| s.approve_proposal(id, alice, 20)).is_err());
+        assert_eq!(
+            crate::state::with_state_mut(|s| s.approve_proposal(id, bob, 20)),
+            Ok(2)
+        );
+        let action = crate::state::with_state_mut(|s| s.start_proposal_execution(id, 20)).unwrap();
+        assert!(matches!(action, Some(ProposalAction::Withdraw(_))));
+        // Executing proposals take no more approvals and do not run twice.
+        assert!(crate::state::with_state_mut(|s| s.approve_proposal(id, carol, 20)).is_err());
+        assert!(crate::state::with_state_mut(|s| s.start_proposal_execution(id, 20)).is_err());
+        assert!(crate::state::with_state(|s| s.pending_proposals(20)).is_empty());
+
+        // Removing a signer withdraws their approval.
+        let id = crate::state::with_state_mut(|s| s.submit_proposal(bob, withdrawal(5_000), 30))
+            .unwrap();
+        crate::state::with_state_mut(|s| s.approve_proposal(id, carol, 30)).unwrap();
+        crate::state::with_state_mut(|s| s.set_signer_config(signer_config(&[alice, bob], 2)))
+            .unwrap();
+        assert!(
+            crate::state::with_state_mut(|s| s.start_proposal_execution(id, 40))
+                .unwrap()
+                .is_none()
+        );
+        assert!(crate::state::with_state_mut(|s| s.cancel_proposal(id, alice, 40)).is_err());
+
+        // Past its expiry a proposal can no longer be approved.
+        assert_eq!(
+            crate::state::with_state(|s| s.pending_proposals(129)).len(),
+            1
+        );
+        assert!(crate::state::with_state(|s| s.pending_proposals(130)).is_empty());
+        assert!(crate::state::with_state_mut(|s| s.approve_proposal(id, alice, 130)).is_err());
+        assert_eq!(
+            crate::state::with_state_mut(|s| s.expire_proposals(130)),
+            vec![id]
+        );
+        assert_eq!(
+            crate::state::with_state(|s| s.get_proposal(id))
+                .unwrap()
+                .status,
+            ProposalStatus::Expired
+        );
+
+        // Proposals survive an upgrade and IDs keep counting.
+        crate::state::restore_state();
+        let next = crate::state::with_state_mut(|s| {
+            s.submit_proposal(
+                alice,
+                ProposalAction::SetSignerConfig(signer_config(&[alice], 1)),
+                200,
+            )
+        })
+        .unwrap();
+        assert_eq!(next, 3);
+        crate::state::with_state_mut(|s| s.cancel_proposal(next, alice, 200)).unwrap();
+        assert_eq!(
+            crate::state::with_state(|s| s.get_proposal(next))
+                .unwrap()
+                .status,
+            ProposalStatus::Cancelled
+        );
+    }
+
+    fn stream_args(recipient: Principal, end_time: u64) -> CreatePaymentStreamArgs {
+        CreatePaymentStreamArgs {
+            recipient,
+            ledger: ledger(AssetType::ICUSD),
+            amount_per_period: 1_000,
+            period_nanos: crate::state::MIN_STREAM_PERIOD_NANOS,
+            end_time,
+        }
+    }
+
+    #[test]
+    fn test_payment_streams_pay_each_period_until_the_end() {
+        init_test_treasury();
+        let day = crate::state::MIN_STREAM_PERIOD_NANOS;
+        let (admin, to) = (Principal::from_slice(&[11]), Principal::from_slice(&[7]));
+        let create = |args| crate::state::with_state_mut(|s| s.create_stream(admin, args, 0));
+
+        // The recipient must be an active withdrawal destination.
+        assert!(create(stream_args(to, 2 * day))
+            .unwrap_err()
+            .contains("not a registered"));
+        crate::state::with_state_mut(|s| {
+            s.schedule_withdrawal_destination(to, "grants".to_string(), 0, 0)
+        })
+        .unwrap();
+        crate::state::with_state_mut(|s| s.activate_due_withdrawal_destinations(0));
+
+        for bad in [
+            CreatePaymentStreamArgs {
+                amount_per_period: 0,
+                ..stream_args(to, 2 * day)
+            },
+            CreatePaymentStreamArgs {
+                period_nanos: day - 1,
+                ..stream_args(to, 2 * day)
+            },
+            CreatePaymentStreamArgs {
+                ledger: Principal::from_slice(&[99]),
+                ..stream_args(to, 2 * day)
+            },
+            // Ends before its first payment.
+            stream_args(to, day - 1),
+        ] {
+            assert!(create(bad).is_err());
+        }
+
+        let id = create(stream_args(to, 2 * day)).unwrap();
+        let due = |now| crate::state::with_state(|s| s.due_stream_payments(now));
+        assert!(due(day - 1).is_empty());
+        let first = due(day);
+        assert_eq!(first.len(), 1);
+        let (due_id, args) = &first[0];
+        assert_eq!((*due_id, args.amount, args.to), (id, 1_000, to));
+        // Retrying the same payment reuses its request_id.
+        assert_eq!(due(day + 1)[0].1.request_id, args.request_id);
+
+        // A failed payment is reported once and stays due.
+        let fail = |error: &str, halt| {
+            crate::state::with_state_mut(|s| s.record_stream_failure(id, error.to_string(), halt))
+        };
+        assert!(fail("Insufficient balance", false));
+        assert!(!fail("Insufficient balance", false));
+        assert_eq!(due(day).len(), 1);
+
+        assert!(!crate::state::with_state_mut(
+            |s| s.record_stream_payment(id)
+        ));
+        assert!(due(day).is_empty());
+        let second = due(2 * day);
+        assert_ne!(second[0].1.request_id, args.request_id);
+        assert!(crate::state::with_state_mut(|s| s.record_stream_payment(id)));
+        let stream = crate::state::with_state(|s| s.get_stream(id)).unwrap();
+        assert_eq!(stream.status, StreamStatus::Completed);
+        assert_eq!((stream.payments_made, stream.total_paid), (2, 2_000));
+        assert_eq!(stream.last_error, None);
+        assert!(due(10 * day).is_empty());
+        assert!(crate::state::with_state_mut(|s| s.cancel_stream(id)).is_err());
+
+        // A transport error halts the stream until it is cancelled.
+        let halted = create(stream_args(to, 5 * day)).unwrap();
+        crate::state::with_state_mut(|s| {
+            s.record_stream_failure(halted, "Transport error: timeout".to_string(), true)
+        });
+        assert!(matches!(
+            crate::state::with_state(|s| s.get_stream(halted))
+                .unwrap()
+                .status,
+            StreamStatus::Halted { .. }
+        ));
+        assert!(due(day).is_empty());
+        crate::state::with_state_mut(|s| s.cancel_stream(halted)).unwrap();
+
+        // Streams survive an upgrade and IDs keep counting.
+        crate::state::restore_state();
+        assert_eq!(crate::state::with_state(|s| s.streams()).len(), 2);
+        assert_eq!(create(stream_args(to, 2 * day)), Ok(3));
+    }
+
+    #[test]
+    fn test_withdrawals_above_the_threshold_need_a_protocol_co_approval() {
+        init_test_treasury();
+        let (icusd, icp) = (ledger(AssetType::ICUSD), ledger(AssetType::ICP));
+        let args = |amount, request_id| WithdrawArgs {
+            asset_type: None,
+            ledger: Some(icusd),
+            amount,
+            to: Principal::from_slice(&[7]),
+            memo: None,
+            request_id,
+        };
+        let request =
+            |args: WithdrawArgs| crate::state::with_state(|s| s.co_approval_request(icusd, &args));
+
+        // No threshold: nothing needs a co-approval.
+        assert_eq!(request(args(u64::MAX, None)), Ok(None));
+
+        crate::state::with_state_mut(|s| s.set_co_approval_threshold(icusd, Some(1_000))).unwrap();
+        assert!(crate::state::with_state_mut(|s| {
+            s.set_co_approval_threshold(Principal::from_slice(&[99]), Some(1))
+        })
+        .is_err());
+        assert_eq!(request(args(1_000, None)), Ok(None));
+        // Above it the protocol backend must be configured...
+        assert!(request(args(1_001, Some(5)))
+            .unwrap_err()
+            .contains("no protocol backend"));
+        crate::state::with_state_mut(|s| {
+            s.set_protocol_backend(Some(Principal::from_slice(&[40])))
+        })
+        .unwrap();
+        // ...and the withdrawal must name its request for the approval.
+        assert!(request(args(1_001, None))
+            .unwrap_err()
+            .contains("request_id"));
+        assert_eq!(
+            request(args(1_001, Some(5))),
+            Ok(Some(TreasuryWithdrawalRequest {
+                request_id: 5,
+                ledger: icusd,
+                to: Principal::from_slice(&[7]),
+                amount: 1_001,
+            }))
+        );
+        // Thresholds are per ledger.
+        assert_eq!(
+            crate::state::with_state(|s| s.co_approval_request(icp, &args(1_001, None))),
+            Ok(None)
+        );
+
+        // Setting replaces, `None` clears, and both survive an upgrade.
+        crate::state::with_state_mut(|s| s.set_co_approval_threshold(icusd, Some(5_000))).unwrap();
+        crate::state::with_state_mut(|s| s.set_co_approval_threshold(icp, Some(1))).unwrap();
+        crate::state::with_state_mut(|s| s.set_co_approval_threshold(icp, None)).unwrap();
+        crate::state::restore_state();
+        assert_eq!(
+            crate::state::with_state(|s| s.co_approval_thresholds()),
+            vec![(icusd, 5_000)]
+        );
+    }
 }
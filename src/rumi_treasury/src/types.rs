@@ -15,9 +15,15 @@ pub enum DepositType {
     /// Interest revenue accrued on vault debt
     #[serde(alias = "StabilityFee")]
     InterestRevenue,
+    /// Fee on a repaid icUSD flash mint
+    FlashMintFee,
+    /// icUSD swept from the backend's surplus buffer
+    SurplusSweep,
 }
 
-/// Asset types that can be held in treasury
+/// Asset identifiers from before the asset registry. Still accepted by
+/// `deposit` and `withdraw`, which map them to their ledgers through the
+/// config; any other token is identified by its ledger.
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum AssetType {
     /// icUSD stablecoin
@@ -32,6 +38,34 @@ pub enum AssetType {
     CKUSDC,
 }
 
+/// What a registered asset is. Only used for reporting, except that receipt
+/// NFTs cannot be withdrawn through `icrc1_transfer`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetKind {
+    /// ICRC-1 token (collaterals, stablecoins)
+    Fungible,
+    /// ICRC-1 liquidity-pool share token
+    LpShare,
+    /// Receipt NFT collection; balances count tokens
+    ReceiptNft,
+}
+
+/// An asset the treasury can hold, keyed by its ledger.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TreasuryAsset {
+    pub ledger: Principal,
+    pub symbol: String,
+    pub decimals: u8,
+    pub kind: AssetKind,
+}
+
+/// A registered asset and its balance.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct AssetHolding {
+    pub asset: TreasuryAsset,
+    pub balance: AssetBalance,
+}
+
 /// A record of a deposit to the treasury
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct DepositRecord {
@@ -39,8 +73,12 @@ pub struct DepositRecord {
     pub id: u64,
     /// Type of deposit (minting fee, liquidation surplus, etc.)
     pub deposit_type: DepositType,
-    /// Asset type (icUSD, ICP, ckBTC)
-    pub asset_type: AssetType,
+    /// Legacy asset type; `None` for assets known only by ledger
+    pub asset_type: Option<AssetType>,
+    /// Ledger of the deposited asset. Records written before the asset
+    /// registry are migrated on upgrade, so this is always set.
+    #[serde(default)]
+    pub ledger: Option<Principal>,
     /// Amount deposited (in e8s)
     pub amount: u64,
     /// Block index of the transfer that funded this deposit
@@ -67,8 +105,11 @@ pub struct AssetBalance {
 pub struct TreasuryStatus {
     /// Total number of deposits
     pub total_deposits: u64,
-    /// Balances by asset type
+    /// Balances of the legacy asset types
     pub balances: Vec<(AssetType, AssetBalance)>,
+    /// Every registered asset with its balance
+    #[serde(default)]
+    pub assets: Vec<AssetHolding>,
     /// Controller principal (pre-SNS) or governance canister (post-SNS)
     pub controller: Principal,
     /// Whether treasury is paused
@@ -90,6 +131,9 @@ pub struct TreasuryInitArgs {
     pub ckusdt_ledger: Option<Principal>,
     /// ckUSDC ledger principal (for vault repayment)
     pub ckusdc_ledger: Option<Principal>,
+    /// Withdrawal destinations usable from install, without the timelock.
+    #[serde(default)]
+    pub withdrawal_destinations: Option<Vec<Principal>>,
 }
 
 /// Arguments for making a deposit
@@ -97,11 +141,16 @@ pub struct TreasuryInitArgs {
 pub struct DepositArgs {
     /// Type of deposit
     pub deposit_type: DepositType,
-    /// Asset being deposited
-    pub asset_type: AssetType,
+    /// Asset being deposited, for the legacy asset types. Ignored when
+    /// `ledger` is set.
+    pub asset_type: Option<AssetType>,
+    /// Ledger of the asset being deposited. An unregistered ledger is
+    /// registered from its ICRC-1 metadata.
+    #[serde(default)]
+    pub ledger: Option<Principal>,
     /// Amount to deposit (in e8s)
     pub amount: u64,
-    /// Block index of the funding transfer
+    /// Block index of the funding transfer, checked against the ledger
     pub block_index: u64,
     /// Optional memo
     pub memo: Option<String>,
@@ -110,8 +159,12 @@ pub struct DepositArgs {
 /// Arguments for withdrawing from treasury
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct WithdrawArgs {
-    /// Asset to withdraw
-    pub asset_type: AssetType,
+    /// Asset to withdraw, for the legacy asset types. Ignored when `ledger`
+    /// is set.
+    pub asset_type: Option<AssetType>,
+    /// Ledger of the asset to withdraw; must be registered.
+    #[serde(default)]
+    pub ledger: Option<Principal>,
     /// Amount to withdraw (in e8s)
     pub amount: u64,
     /// Destination principal
@@ -122,7 +175,7 @@ pub struct WithdrawArgs {
     /// attempt's `created_at_time` per `request_id` and reuses it on retries,
     /// so re-submitting with the same `request_id` lets the ledger
     /// deduplicate (audit ICRC-003). If omitted, treasury derives one from
-    /// `(caller, asset_type, amount, to, floor(now / 60s))` so a same-minute
+    /// `(caller, ledger, amount, to, floor(now / 60s))` so a same-minute
     /// repeat still dedups.
     #[serde(default)]
     pub request_id: Option<u64>,
@@ -143,7 +196,195 @@ pub struct WithdrawResult {
 /// Survives canister upgrades (unlike the in-memory `HashMap`).
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
 pub struct BalancesSnapshot {
+    /// Pre-registry balances, keyed by legacy asset type. Migrated to
+    /// `by_ledger` on the first upgrade and left empty afterwards.
     pub entries: Vec<(AssetType, AssetBalance)>,
+    #[serde(default)]
+    pub by_ledger: Vec<(Principal, AssetBalance)>,
+}
+
+// ─── Backend mode inheritance ───
+
+/// The protocol backend's operating mode, as pushed by the backend on every
+/// mode change. Shared with the stability pool.
+pub use rumi_common::types::ProtocolMode;
+
+/// Backend modes in which the treasury refuses withdrawals. Deposits are
+/// never restricted, so fees keep landing while the backend recovers.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ModeInheritancePolicy {
+    pub block_withdrawals_in: Vec<ProtocolMode>,
+}
+
+impl Default for ModeInheritancePolicy {
+    fn default() -> Self {
+        Self {
+            block_withdrawals_in: vec![ProtocolMode::ReadOnly],
+        }
+    }
+}
+
+/// Reply of `get_mode_inheritance`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ModeInheritanceStatus {
+    /// The only canister allowed to push a mode.
+    pub protocol_backend: Option<Principal>,
+    /// Last mode pushed, `None` until the backend first reports.
+    pub protocol_mode: Option<ProtocolMode>,
+    pub policy: ModeInheritancePolicy,
+    pub withdrawals_blocked: bool,
+}
+
+// ─── Withdrawal destinations ───
+
+/// A pre-registered withdrawal recipient. Withdrawals may only go to an
+/// `active` destination; a newly added one activates once `active_at` passes.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct WithdrawalDestination {
+    pub to: Principal,
+    pub label: String,
+    pub added_at: u64,
+    pub active_at: u64,
+    pub active: bool,
+}
+
+// ─── Balance reconciliation ───
+
+/// One asset's tracked balance against what its ledger reports.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ReconciliationEntry {
+    pub ledger: Principal,
+    pub symbol: String,
+    /// `AssetBalance::total` when the check ran.
+    pub tracked: u64,
+    /// `icrc1_balance_of` of the treasury's main account; `None` if the
+    /// ledger could not be queried.
+    pub actual: Option<u64>,
+    /// `actual - tracked`: positive when the ledger holds more than the
+    /// books, negative when less. Zero when `actual` is unknown.
+    pub delta: i64,
+    pub checked_at: u64,
+    pub error: Option<String>,
+}
+
+/// Result of the last reconciliation run.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReconciliationReport {
+    /// `None` until the first run.
+    pub last_run_at: Option<u64>,
+    pub entries: Vec<ReconciliationEntry>,
+    /// Entries with a nonzero delta.
+    pub discrepancies: u64,
+}
+
+// ─── Signer approval ───
+
+/// M-of-N signer set guarding withdrawals. Once set, only signers may
+/// withdraw: directly up to the asset's limit, through an approved
+/// `Proposal` above it. Changing the set takes a proposal too.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SignerConfig {
+    pub signers: Vec<Principal>,
+    /// Approvals a proposal needs, the proposer's included.
+    pub required_approvals: u32,
+    /// Largest withdrawal a single signer may make, per asset ledger. A
+    /// ledger without a limit needs a proposal for any amount.
+    pub withdrawal_limits: Vec<(Principal, u64)>,
+    /// How long a proposal collects approvals before it expires.
+    pub proposal_expiry_nanos: u64,
+}
+
+/// What an approved proposal does.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum ProposalAction {
+    Withdraw(WithdrawArgs),
+    SetSignerConfig(SignerConfig),
+    CreatePaymentStream(CreatePaymentStreamArgs),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ProposalStatus {
+    /// Collecting approvals.
+    Pending,
+    /// Approved; the action is in flight.
+    Executing,
+    /// `block_index` of the withdrawal transfer, `None` for a signer change.
+    Executed {
+        block_index: Option<u64>,
+    },
+    Failed {
+        error: String,
+    },
+    Cancelled,
+    Expired,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Proposal {
+    pub id: u64,
+    pub action: ProposalAction,
+    pub proposer: Principal,
+    /// Signers who approved, the proposer first.
+    pub approvals: Vec<Principal>,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub status: ProposalStatus,
+}
+
+// ─── Protocol co-approval ───
+
+/// A withdrawal above its ledger's co-approval threshold, as sent to the
+/// protocol backend's `consume_treasury_withdrawal_approval`. Shared with the
+/// backend.
+pub use rumi_common::types::TreasuryWithdrawalRequest;
+
+// ─── Payment streams ───
+
+/// Arguments of `create_payment_stream`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CreatePaymentStreamArgs {
+    /// Must be an active withdrawal destination.
+    pub recipient: Principal,
+    /// Ledger of the asset paid out; must be registered.
+    pub ledger: Principal,
+    /// Withdrawn each period; the recipient receives it less the ledger fee.
+    pub amount_per_period: u64,
+    pub period_nanos: u64,
+    /// No payment falls due after this time.
+    pub end_time: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum StreamStatus {
+    Active,
+    /// Every payment up to `end_time` was made.
+    Completed,
+    Cancelled,
+    /// A payment hit a transport error and may have landed; the stream
+    /// stops until the ledger has been checked.
+    Halted {
+        error: String,
+    },
+}
+
+/// A recurring payout, paid by timer at the end of each period.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PaymentStream {
+    pub id: u64,
+    pub recipient: Principal,
+    pub ledger: Principal,
+    pub amount_per_period: u64,
+    pub period_nanos: u64,
+    pub end_time: u64,
+    pub created_by: Principal,
+    pub created_at: u64,
+    /// When the next payment falls due.
+    pub next_payment_at: u64,
+    pub payments_made: u64,
+    pub total_paid: u64,
+    /// Why the last attempt failed; cleared by the next payment.
+    pub last_error: Option<String>,
+    pub status: StreamStatus,
 }
 
 // ─── Treasury Events (audit trail) ───
@@ -152,17 +393,104 @@ pub struct BalancesSnapshot {
 pub enum TreasuryAction {
     Deposit {
         deposit_type: DepositType,
-        asset_type: AssetType,
+        asset_type: Option<AssetType>,
+        #[serde(default)]
+        ledger: Option<Principal>,
         amount: u64,
     },
     Withdraw {
-        asset_type: AssetType,
+        asset_type: Option<AssetType>,
+        #[serde(default)]
+        ledger: Option<Principal>,
         amount: u64,
         to: Principal,
     },
+    AssetRegistered {
+        asset: TreasuryAsset,
+    },
     SetPaused {
         paused: bool,
     },
+    ProtocolModeInherited {
+        mode: ProtocolMode,
+    },
+    SetModeInheritancePolicy {
+        policy: ModeInheritancePolicy,
+    },
+    WithdrawalDestinationScheduled {
+        to: Principal,
+        active_at: u64,
+    },
+    WithdrawalDestinationActivated {
+        to: Principal,
+    },
+    WithdrawalDestinationRemoved {
+        to: Principal,
+    },
+    /// A reconciliation found the ledger balance off the tracked balance,
+    /// or off by a different amount than the previous run.
+    BalanceDiscrepancy {
+        ledger: Principal,
+        tracked: u64,
+        actual: u64,
+        delta: i64,
+    },
+    SignerConfigSet {
+        config: SignerConfig,
+    },
+    ProposalSubmitted {
+        id: u64,
+        action: ProposalAction,
+    },
+    ProposalApproved {
+        id: u64,
+        approvals: u32,
+    },
+    ProposalExecuted {
+        id: u64,
+    },
+    ProposalFailed {
+        id: u64,
+        error: String,
+    },
+    ProposalCancelled {
+        id: u64,
+    },
+    ProposalExpired {
+        id: u64,
+    },
+    StreamCreated {
+        id: u64,
+        args: CreatePaymentStreamArgs,
+    },
+    StreamPayment {
+        id: u64,
+        amount: u64,
+        block_index: u64,
+    },
+    StreamPaymentFailed {
+        id: u64,
+        error: String,
+    },
+    StreamCompleted {
+        id: u64,
+    },
+    StreamCancelled {
+        id: u64,
+    },
+    StreamHalted {
+        id: u64,
+        error: String,
+    },
+    /// `None` clears the ledger's threshold.
+    CoApprovalThresholdSet {
+        ledger: Principal,
+        threshold: Option<u64>,
+    },
+    /// The protocol backend confirmed its co-approval of a withdrawal.
+    WithdrawalCoApproved {
+        request: TreasuryWithdrawalRequest,
+    },
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
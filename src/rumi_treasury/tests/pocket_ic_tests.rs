@@ -0,0 +1,778 @@
+//! PocketIC integration suite for the treasury: deploys the treasury next to
+//! a real ICRC-1 ledger and drives the controller-facing surface end to end.
+//!
+//! Covers the bookkeeping/ledger round trip that the unit tests in
+//! `src/tests.rs` cannot reach:
+//!  1. Deposits are bookkeeping-only and emit a `Deposit` event.
+//!  2. A successful withdrawal debits exactly `amount` from both the tracked
+//!     balance and the canister's ledger account (recipient bears the fee).
+//!  3. A withdrawal the ledger rejects (insufficient ledger funds) restores
+//!     the tracked balance and emits no `Withdraw` event.
+//!  4. Fee-dominated and over-balance withdrawals are refused before any
+//!     debit.
+//!  5. Authority follows the IC controller list, so rotating controllers
+//!     moves deposit/withdraw/pause rights with it.
+//!  6. Pausing blocks deposits until unpaused.
+//!  7. Withdrawals only reach registered destinations, and a newly added
+//!     destination is usable only after its timelock.
+//!  8. A due payment stream is paid by the payout timer: the recipient is
+//!     credited, the balance debited and the payment recorded.
+//!
+//! Requires `target/wasm32-unknown-unknown/release/rumi_treasury.wasm`
+//! (`cargo build --release --target wasm32-unknown-unknown -p rumi_treasury`).
+
+use candid::{decode_one, encode_args, encode_one, CandidType, Deserialize, Nat, Principal};
+use icrc_ledger_types::icrc1::account::Account;
+use pocket_ic::{PocketIc, PocketIcBuilder, WasmResult};
+
+const E8S: u64 = 100_000_000;
+const LEDGER_FEE: u64 = 10_000;
+
+// ─── Treasury candid mirrors (the crate is cdylib-only) ───
+
+#[allow(dead_code)]
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+enum DepositType {
+    BorrowingFee,
+    RedemptionFee,
+    LiquidationFee,
+    InterestRevenue,
+}
+
+#[allow(dead_code)]
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+enum AssetType {
+    ICUSD,
+    ICP,
+    CKBTC,
+    CKUSDT,
+    CKUSDC,
+}
+
+#[allow(dead_code)]
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+struct AssetBalance {
+    total: u64,
+    reserved: u64,
+    available: u64,
+}
+
+#[allow(dead_code)]
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct TreasuryStatus {
+    total_deposits: u64,
+    balances: Vec<(AssetType, AssetBalance)>,
+    controller: Principal,
+    is_paused: bool,
+}
+
+#[derive(CandidType, Deserialize)]
+struct TreasuryInitArgs {
+    controller: Principal,
+    icusd_ledger: Principal,
+    icp_ledger: Principal,
+    ckbtc_ledger: Option<Principal>,
+    ckusdt_ledger: Option<Principal>,
+    ckusdc_ledger: Option<Principal>,
+    withdrawal_destinations: Option<Vec<Principal>>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct DepositArgs {
+    deposit_type: DepositType,
+    asset_type: AssetType,
+    amount: u64,
+    block_index: u64,
+    memo: Option<String>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct WithdrawArgs {
+    asset_type: AssetType,
+    amount: u64,
+    to: Principal,
+    memo: Option<String>,
+    request_id: Option<u64>,
+}
+
+#[allow(dead_code)]
+#[derive(CandidType, Deserialize, Debug)]
+struct WithdrawResult {
+    block_index: u64,
+    amount_transferred: u64,
+    fee: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct CreatePaymentStreamArgs {
+    recipient: Principal,
+    ledger: Principal,
+    amount_per_period: u64,
+    period_nanos: u64,
+    end_time: u64,
+}
+
+#[allow(dead_code)]
+#[derive(CandidType, Deserialize, Clone, Debug)]
+enum TreasuryAction {
+    Deposit {
+        deposit_type: DepositType,
+        asset_type: Option<AssetType>,
+        ledger: Option<Principal>,
+        amount: u64,
+    },
+    Withdraw {
+        asset_type: Option<AssetType>,
+        ledger: Option<Principal>,
+        amount: u64,
+        to: Principal,
+    },
+    SetPaused {
+        paused: bool,
+    },
+    WithdrawalDestinationScheduled {
+        to: Principal,
+        active_at: u64,
+    },
+    WithdrawalDestinationActivated {
+        to: Principal,
+    },
+    BalanceDiscrepancy {
+        ledger: Principal,
+        tracked: u64,
+        actual: u64,
+        delta: i64,
+    },
+    StreamCreated {
+        id: u64,
+        args: CreatePaymentStreamArgs,
+    },
+    StreamPayment {
+        id: u64,
+        amount: u64,
+        block_index: u64,
+    },
+    StreamPaymentFailed {
+        id: u64,
+        error: String,
+    },
+    StreamCompleted {
+        id: u64,
+    },
+    StreamHalted {
+        id: u64,
+        error: String,
+    },
+}
+
+#[allow(dead_code)]
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct TreasuryEvent {
+    id: u64,
+    timestamp: u64,
+    caller: Principal,
+    action: TreasuryAction,
+}
+
+// ─── Candid types for ICRC-1 ledger initialization ───
+
+#[derive(CandidType, Deserialize)]
+struct FeatureFlags {
+    icrc2: bool,
+}
+
+#[derive(CandidType, Deserialize)]
+struct ArchiveOptions {
+    num_blocks_to_archive: u64,
+    trigger_threshold: u64,
+    controller_id: Principal,
+    max_transactions_per_response: Option<u64>,
+    max_message_size_bytes: Option<u64>,
+    cycles_for_archive_creation: Option<u64>,
+    node_max_memory_size_bytes: Option<u64>,
+    more_controller_ids: Option<Vec<Principal>>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct LedgerInitArgs {
+    minting_account: Account,
+    fee_collector_account: Option<Account>,
+    transfer_fee: Nat,
+    decimals: Option<u8>,
+    max_memo_length: Option<u16>,
+    token_name: String,
+    token_symbol: String,
+    metadata: Vec<(String, MetadataValue)>,
+    initial_balances: Vec<(Account, Nat)>,
+    feature_flags: Option<FeatureFlags>,
+    maximum_number_of_accounts: Option<u64>,
+    accounts_overflow_trim_quantity: Option<u64>,
+    archive_options: ArchiveOptions,
+}
+
+#[derive(CandidType, Deserialize)]
+enum MetadataValue {
+    Nat(Nat),
+    Int(candid::Int),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+#[derive(CandidType, Deserialize)]
+enum LedgerArg {
+    Init(LedgerInitArgs),
+}
+
+// ─── WASM loaders ───
+
+fn icrc1_ledger_wasm() -> Vec<u8> {
+    include_bytes!("../../ledger/ic-icrc1-ledger.wasm").to_vec()
+}
+
+fn treasury_wasm() -> Vec<u8> {
+    include_bytes!("../../../target/wasm32-unknown-unknown/release/rumi_treasury.wasm").to_vec()
+}
+
+// ─── Test Harness ───
+
+struct TestEnv {
+    pic: PocketIc,
+    treasury_id: Principal,
+    ledger_id: Principal,
+    admin: Principal,
+    user: Principal,
+}
+
+/// Deploys the treasury (controlled by `admin`, with `user` as its install
+/// withdrawal destination) and an icUSD ledger whose only funded account is
+/// the treasury's, holding `treasury_ledger_funds`.
+fn setup(treasury_ledger_funds: u64) -> TestEnv {
+    let pic = PocketIcBuilder::new().with_application_subnet().build();
+
+    let minting_account = Principal::self_authenticating(&[100, 100, 100]);
+    let admin = Principal::self_authenticating(&[5, 6, 7, 8]);
+    let user = Principal::self_authenticating(&[1, 2, 3, 4]);
+
+    let treasury_id = pic.create_canister_with_settings(Some(admin), None);
+    pic.add_cycles(treasury_id, 2_000_000_000_000);
+
+    let ledger_id = deploy_ledger(
+        &pic,
+        minting_account,
+        admin,
+        treasury_id,
+        treasury_ledger_funds,
+    );
+
+    let init = TreasuryInitArgs {
+        controller: admin,
+        icusd_ledger: ledger_id,
+        icp_ledger: ledger_id,
+        ckbtc_ledger: None,
+        ckusdt_ledger: None,
+        ckusdc_ledger: None,
+        withdrawal_destinations: Some(vec![user]),
+    };
+    pic.install_canister(
+        treasury_id,
+        treasury_wasm(),
+        encode_one(init).unwrap(),
+        Some(admin),
+    );
+
+    TestEnv {
+        pic,
+        treasury_id,
+        ledger_id,
+        admin,
+        user,
+    }
+}
+
+fn deploy_ledger(
+    pic: &PocketIc,
+    minting_account: Principal,
+    admin: Principal,
+    funded: Principal,
+    initial_balance: u64,
+) -> Principal {
+    let ledger_id = pic.create_canister();
+    pic.add_cycles(ledger_id, 2_000_000_000_000);
+
+    let init_args = LedgerInitArgs {
+        minting_account: Account {
+            owner: minting_account,
+            subaccount: None,
+        },
+        fee_collector_account: None,
+        transfer_fee: Nat::from(LEDGER_FEE),
+        decimals: Some(8),
+        max_memo_length: Some(32),
+        token_name: "icUSD".to_string(),
+        token_symbol: "icUSD".to_string(),
+        metadata: vec![],
+        initial_balances: vec![(
+            Account {
+                owner: funded,
+                subaccount: None,
+            },
+            Nat::from(initial_balance),
+        )],
+        feature_flags: Some(FeatureFlags { icrc2: true }),
+        maximum_number_of_accounts: None,
+        accounts_overflow_trim_quantity: None,
+        archive_options: ArchiveOptions {
+            num_blocks_to_archive: 2000,
+            trigger_threshold: 1000,
+            controller_id: admin,
+            max_transactions_per_response: None,
+            max_message_size_bytes: None,
+            cycles_for_archive_creation: None,
+            node_max_memory_size_bytes: None,
+            more_controller_ids: None,
+        },
+    };
+
+    let encoded = encode_args((LedgerArg::Init(init_args),)).unwrap();
+    pic.install_canister(ledger_id, icrc1_ledger_wasm(), encoded, None);
+    ledger_id
+}
+
+// ─── Call helpers ───
+
+fn reply(result: WasmResult, method: &str) -> Vec<u8> {
+    match result {
+        WasmResult::Reply(bytes) => bytes,
+        WasmResult::Reject(msg) => panic!("{} rejected: {}", method, msg),
+    }
+}
+
+fn deposit(env: &TestEnv, sender: Principal, amount: u64) -> Result<u64, String> {
+    let args = DepositArgs {
+        deposit_type: DepositType::BorrowingFee,
+        asset_type: AssetType::ICUSD,
+        amount,
+        block_index: 0,
+        memo: None,
+    };
+    let result = env
+        .pic
+        .update_call(
+            env.treasury_id,
+            sender,
+            "deposit",
+            encode_one(args).unwrap(),
+        )
+        .expect("deposit call failed");
+    decode_one(&reply(result, "deposit")).unwrap()
+}
+
+fn withdraw(
+    env: &TestEnv,
+    sender: Principal,
+    amount: u64,
+    request_id: u64,
+) -> Result<WithdrawResult, String> {
+    withdraw_to(env, sender, env.user, amount, request_id)
+}
+
+fn withdraw_to(
+    env: &TestEnv,
+    sender: Principal,
+    to: Principal,
+    amount: u64,
+    request_id: u64,
+) -> Result<WithdrawResult, String> {
+    let args = WithdrawArgs {
+        asset_type: AssetType::ICUSD,
+        amount,
+        to,
+        memo: None,
+        request_id: Some(request_id),
+    };
+    let result = env
+        .pic
+        .update_call(
+            env.treasury_id,
+            sender,
+            "withdraw",
+            encode_one(args).unwrap(),
+        )
+        .expect("withdraw call failed");
+    decode_one(&reply(result, "withdraw")).unwrap()
+}
+
+fn add_withdrawal_destination(
+    env: &TestEnv,
+    sender: Principal,
+    to: Principal,
+) -> Result<u64, String> {
+    let result = env
+        .pic
+        .update_call(
+            env.treasury_id,
+            sender,
+            "add_withdrawal_destination",
+            encode_args((to, "test".to_string())).unwrap(),
+        )
+        .expect("add_withdrawal_destination call failed");
+    decode_one(&reply(result, "add_withdrawal_destination")).unwrap()
+}
+
+fn set_paused(env: &TestEnv, sender: Principal, paused: bool) -> Result<(), String> {
+    let result = env
+        .pic
+        .update_call(
+            env.treasury_id,
+            sender,
+            "set_paused",
+            encode_one(paused).unwrap(),
+        )
+        .expect("set_paused call failed");
+    decode_one(&reply(result, "set_paused")).unwrap()
+}
+
+fn create_payment_stream(
+    env: &TestEnv,
+    sender: Principal,
+    args: CreatePaymentStreamArgs,
+) -> Result<u64, String> {
+    let result = env
+        .pic
+        .update_call(
+            env.treasury_id,
+            sender,
+            "create_payment_stream",
+            encode_one(args).unwrap(),
+        )
+        .expect("create_payment_stream call failed");
+    decode_one(&reply(result, "create_payment_stream")).unwrap()
+}
+
+fn status(env: &TestEnv) -> TreasuryStatus {
+    let result = env
+        .pic
+        .query_call(
+            env.treasury_id,
+            Principal::anonymous(),
+            "get_status",
+            encode_args(()).unwrap(),
+        )
+        .expect("get_status call failed");
+    decode_one(&reply(result, "get_status")).unwrap()
+}
+
+fn icusd_balance(env: &TestEnv) -> AssetBalance {
+    status(env)
+        .balances
+        .into_iter()
+        .find(|(asset, _)| *asset == AssetType::ICUSD)
+        .map(|(_, balance)| balance)
+        .expect("ICUSD balance missing from status")
+}
+
+fn events(env: &TestEnv) -> Vec<TreasuryEvent> {
+    let result = env
+        .pic
+        .query_call(
+            env.treasury_id,
+            Principal::anonymous(),
+            "get_events",
+            encode_args((None::<u64>, None::<u64>)).unwrap(),
+        )
+        .expect("get_events call failed");
+    decode_one(&reply(result, "get_events")).unwrap()
+}
+
+fn ledger_balance(env: &TestEnv, owner: Principal) -> u64 {
+    let account = Account {
+        owner,
+        subaccount: None,
+    };
+    let result = env
+        .pic
+        .query_call(
+            env.ledger_id,
+            Principal::anonymous(),
+            "icrc1_balance_of",
+            encode_one(account).unwrap(),
+        )
+        .expect("icrc1_balance_of call failed");
+    let n: Nat = decode_one(&reply(result, "icrc1_balance_of")).unwrap();
+    n.0.try_into().unwrap()
+}
+
+// ════════════════════════════════════════════════════════════════════════
+// Tests
+// ════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn deposit_updates_bookkeeping_and_emits_event() {
+    let env = setup(10 * E8S);
+
+    let id = deposit(&env, env.admin, 10 * E8S).expect("deposit should succeed");
+    assert_eq!(id, 0);
+
+    let balance = icusd_balance(&env);
+    assert_eq!(balance.total, 10 * E8S);
+    assert_eq!(balance.available, 10 * E8S);
+    assert_eq!(status(&env).total_deposits, 1);
+
+    let events = events(&env);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].caller, env.admin);
+    assert!(matches!(
+        events[0].action,
+        TreasuryAction::Deposit { amount, .. } if amount == 10 * E8S
+    ));
+}
+
+#[test]
+fn repeated_deposit_returns_the_recorded_id() {
+    let env = setup(10 * E8S);
+
+    let id = deposit(&env, env.admin, 10 * E8S).expect("deposit should succeed");
+    // A sender whose first call's reply was lost retries the same block.
+    assert_eq!(deposit(&env, env.admin, 10 * E8S), Ok(id));
+    assert_eq!(icusd_balance(&env).total, 10 * E8S);
+    assert_eq!(status(&env).total_deposits, 1);
+    assert_eq!(events(&env).len(), 1);
+
+    // Another amount on the same block is a different claim.
+    assert!(deposit(&env, env.admin, E8S)
+        .unwrap_err()
+        .contains("already funded"));
+}
+
+#[test]
+fn successful_withdraw_debits_exactly_amount() {
+    let env = setup(10 * E8S);
+    deposit(&env, env.admin, 10 * E8S).unwrap();
+
+    let result = withdraw(&env, env.admin, 2 * E8S, 1).expect("withdraw should succeed");
+    assert_eq!(result.fee, LEDGER_FEE);
+    assert_eq!(result.amount_transferred, 2 * E8S - LEDGER_FEE);
+
+    // Recipient bears the fee; the treasury account drops by exactly `amount`,
+    // so tracked and real balances stay in step.
+    assert_eq!(ledger_balance(&env, env.user), 2 * E8S - LEDGER_FEE);
+    assert_eq!(ledger_balance(&env, env.treasury_id), 8 * E8S);
+    assert_eq!(icusd_balance(&env).available, 8 * E8S);
+
+    let events = events(&env);
+    assert_eq!(events.len(), 2);
+    assert!(matches!(
+        events[1].action,
+        TreasuryAction::Withdraw { amount, to, .. } if amount == 2 * E8S && to == env.user
+    ));
+}
+
+#[test]
+fn ledger_rejected_withdraw_restores_balance() {
+    // Bookkeeping says 10 icUSD, but the ledger account only holds 1.
+    let env = setup(E8S);
+    deposit(&env, env.admin, 10 * E8S).unwrap();
+
+    let err = withdraw(&env, env.admin, 5 * E8S, 1).expect_err("ledger should reject");
+    assert!(
+        err.contains("InsufficientFunds"),
+        "unexpected error: {}",
+        err
+    );
+
+    let balance = icusd_balance(&env);
+    assert_eq!(balance.total, 10 * E8S);
+    assert_eq!(balance.available, 10 * E8S);
+    assert_eq!(ledger_balance(&env, env.user), 0);
+    assert_eq!(
+        events(&env).len(),
+        1,
+        "failed withdraw must not emit an event"
+    );
+
+    // The restored balance is immediately usable for a withdrawal the ledger
+    // can actually cover.
+    withdraw(&env, env.admin, E8S / 2, 2).expect("covered withdraw should succeed");
+    assert_eq!(icusd_balance(&env).available, 10 * E8S - E8S / 2);
+}
+
+#[test]
+fn fee_dominated_withdraw_rejected_before_debit() {
+    let env = setup(10 * E8S);
+    deposit(&env, env.admin, 10 * E8S).unwrap();
+
+    let err = withdraw(&env, env.admin, LEDGER_FEE, 1).expect_err("dust should be refused");
+    assert!(
+        err.contains("does not exceed the ledger fee"),
+        "unexpected error: {}",
+        err
+    );
+
+    assert_eq!(icusd_balance(&env).available, 10 * E8S);
+    assert_eq!(ledger_balance(&env, env.treasury_id), 10 * E8S);
+}
+
+#[test]
+fn withdraw_above_tracked_balance_rejected() {
+    let env = setup(10 * E8S);
+    deposit(&env, env.admin, E8S).unwrap();
+
+    let err = withdraw(&env, env.admin, 2 * E8S, 1).expect_err("over-balance should be refused");
+    assert!(
+        err.contains("Insufficient balance"),
+        "unexpected error: {}",
+        err
+    );
+
+    assert_eq!(icusd_balance(&env).available, E8S);
+    assert_eq!(ledger_balance(&env, env.treasury_id), 10 * E8S);
+}
+
+#[test]
+fn non_controller_cannot_move_funds() {
+    let env = setup(10 * E8S);
+    deposit(&env, env.admin, 10 * E8S).unwrap();
+
+    assert!(deposit(&env, env.user, E8S)
+        .unwrap_err()
+        .contains("Access denied"));
+    assert!(withdraw(&env, env.user, E8S, 1)
+        .unwrap_err()
+        .contains("Access denied"));
+    assert!(set_paused(&env, env.user, true)
+        .unwrap_err()
+        .contains("Access denied"));
+
+    assert_eq!(icusd_balance(&env).available, 10 * E8S);
+    assert_eq!(ledger_balance(&env, env.user), 0);
+}
+
+#[test]
+fn controller_rotation_moves_authority() {
+    let env = setup(10 * E8S);
+    deposit(&env, env.admin, 10 * E8S).unwrap();
+
+    let new_admin = Principal::self_authenticating(&[9, 9, 9, 9]);
+    env.pic
+        .set_controllers(env.treasury_id, Some(env.admin), vec![new_admin])
+        .expect("set_controllers failed");
+
+    assert!(deposit(&env, env.admin, E8S)
+        .unwrap_err()
+        .contains("Access denied"));
+    assert!(withdraw(&env, env.admin, E8S, 1)
+        .unwrap_err()
+        .contains("Access denied"));
+
+    withdraw(&env, new_admin, E8S, 2).expect("new controller should withdraw");
+    assert_eq!(icusd_balance(&env).available, 9 * E8S);
+    assert_eq!(events(&env).last().unwrap().caller, new_admin);
+}
+
+#[test]
+fn pause_blocks_deposits_until_unpaused() {
+    let env = setup(10 * E8S);
+
+    set_paused(&env, env.admin, true).unwrap();
+    assert!(status(&env).is_paused);
+    let err = deposit(&env, env.admin, E8S).expect_err("paused treasury should refuse deposits");
+    assert!(err.contains("paused"), "unexpected error: {}", err);
+    assert_eq!(status(&env).total_deposits, 0);
+
+    set_paused(&env, env.admin, false).unwrap();
+    assert!(!status(&env).is_paused);
+    deposit(&env, env.admin, E8S).expect("unpaused treasury should accept deposits");
+
+    let actions: Vec<_> = events(&env).into_iter().map(|e| e.action).collect();
+    assert!(matches!(
+        actions[0],
+        TreasuryAction::SetPaused { paused: true }
+    ));
+    assert!(matches!(
+        actions[1],
+        TreasuryAction::SetPaused { paused: false }
+    ));
+    assert!(matches!(actions[2], TreasuryAction::Deposit { .. }));
+}
+
+#[test]
+fn withdrawals_wait_for_the_destination_timelock() {
+    let env = setup(10 * E8S);
+    deposit(&env, env.admin, 10 * E8S).unwrap();
+    let attacker = Principal::self_authenticating(&[6, 6, 6, 6]);
+
+    let err = withdraw_to(&env, env.admin, attacker, E8S, 1).expect_err("unregistered");
+    assert!(
+        err.contains("not a registered"),
+        "unexpected error: {}",
+        err
+    );
+    assert!(add_withdrawal_destination(&env, env.user, attacker)
+        .unwrap_err()
+        .contains("Access denied"));
+
+    add_withdrawal_destination(&env, env.admin, attacker).expect("controller adds");
+    let err = withdraw_to(&env, env.admin, attacker, E8S, 2).expect_err("timelocked");
+    assert!(err.contains("timelocked"), "unexpected error: {}", err);
+    assert_eq!(icusd_balance(&env).available, 10 * E8S);
+
+    // The activation timer fires once the 48h timelock has passed.
+    env.pic
+        .advance_time(std::time::Duration::from_secs(48 * 60 * 60 + 1));
+    env.pic.tick();
+    assert!(matches!(
+        events(&env).last().unwrap().action,
+        TreasuryAction::WithdrawalDestinationActivated { to } if to == attacker
+    ));
+    withdraw_to(&env, env.admin, attacker, E8S, 3).expect("active destination");
+    assert_eq!(ledger_balance(&env, attacker), E8S - LEDGER_FEE);
+}
+
+#[test]
+fn due_stream_is_paid_by_the_payout_timer() {
+    let env = setup(10 * E8S);
+    deposit(&env, env.admin, 10 * E8S).unwrap();
+
+    const DAY_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+    let now = env
+        .pic
+        .get_time()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64;
+    let args = CreatePaymentStreamArgs {
+        recipient: env.user,
+        ledger: env.ledger_id,
+        amount_per_period: E8S,
+        period_nanos: DAY_NANOS,
+        // Room for exactly one payment.
+        end_time: now + DAY_NANOS + DAY_NANOS / 2,
+    };
+    let id = create_payment_stream(&env, env.admin, args).expect("controller opens stream");
+
+    // The first payment falls due a period in; the hourly payout timer
+    // picks it up on its next run.
+    env.pic
+        .advance_time(std::time::Duration::from_secs(25 * 60 * 60));
+    for _ in 0..10 {
+        env.pic.tick();
+    }
+
+    assert_eq!(ledger_balance(&env, env.user), E8S - LEDGER_FEE);
+    assert_eq!(ledger_balance(&env, env.treasury_id), 9 * E8S);
+    assert_eq!(icusd_balance(&env).available, 9 * E8S);
+
+    let actions: Vec<_> = events(&env).into_iter().map(|e| e.action).collect();
+    let paid = actions
+        .iter()
+        .position(|a| matches!(a, TreasuryAction::StreamPayment { id: i, amount, .. } if *i == id && *amount == E8S))
+        .expect("stream payment recorded");
+    assert!(matches!(
+        actions[paid - 1],
+        TreasuryAction::Withdraw { amount, to, .. } if amount == E8S && to == env.user
+    ));
+    assert!(matches!(
+        actions[paid + 1],
+        TreasuryAction::StreamCompleted { id: i } if i == id
+    ));
+}
@@ -0,0 +1,362 @@
+//! Opt-in compounding of collateral gains back into icUSD deposits.
+//!
+//! A depositor calls `enable_auto_compound` and from then on an hourly timer
+//! sells their claimable collateral gains for icUSD and adds the proceeds to
+//! their deposit. An admin sets one `AutoCompoundRoute` per collateral: the
+//! Rumi AMM canister and pool to sell through and the slippage it tolerates.
+//! Collateral without a route, native collateral that needs a payout address,
+//! and gains held by safe mode are left alone.
+//!
+//! Each tick takes the gains of all opted-in depositors for one collateral,
+//! as `claim_collateral` does, and sells them in a single swap. The pool pays
+//! the approve fee and the DEX's `icrc2_transfer_from` fee out of the batch.
+//! The swap must return at least the better of the DEX quote and the value
+//! at the last liquidation price, less the route's slippage, so a drained or
+//! manipulated AMM pool fails the swap instead of selling cheap. The icUSD
+//! received, less its ledger fee, is split pro rata to the gains sold. A
+//! failed sale puts the gains back, less any fee already spent. Rounding
+//! dust of either split stays with the pool.
+//!
+//! Proceeds that return while a liquidation is apportioning are queued and
+//! credited on the next tick.
+
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_log::log;
+use ic_cdk::call;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::icrc2::approve::{ApproveArgs, ApproveError};
+use std::time::Duration;
+
+use crate::logs::INFO;
+use crate::pool_guard::AutoCompoundTickGuard;
+use crate::state::{mutate_state, read_state};
+use crate::types::{AutoCompoundRoute, PoolEventType, StabilityPoolError};
+
+/// How often opted-in gains are compounded.
+pub const AUTO_COMPOUND_INTERVAL_SECONDS: u64 = 3_600;
+
+/// Highest slippage a route may allow (10%).
+pub const MAX_AUTO_COMPOUND_SLIPPAGE_BPS: u16 = 1_000;
+
+/// Reply of the Rumi AMM `swap`; its `fee` field is not needed.
+#[derive(CandidType, Deserialize, Debug)]
+struct DexSwapResult {
+    amount_out: u128,
+}
+
+pub fn setup_compound_timer() {
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(AUTO_COMPOUND_INTERVAL_SECONDS), || {
+        ic_cdk::spawn(run_auto_compound())
+    });
+}
+
+/// Opt the caller in or out.
+pub fn set_enabled(enabled: bool) -> Result<(), StabilityPoolError> {
+    let caller = ic_cdk::api::caller();
+    mutate_state(|s| {
+        if s.set_auto_compound(caller, enabled) {
+            let event = if enabled {
+                PoolEventType::AutoCompoundEnabled
+            } else {
+                PoolEventType::AutoCompoundDisabled
+            };
+            s.push_event(caller, event);
+        }
+    });
+    Ok(())
+}
+
+/// Split `amount` between `weights` pro rata, rounding down. Entries whose
+/// share rounds to zero are dropped.
+pub fn split_pro_rata(amount: u64, weights: &[(Principal, u64)]) -> Vec<(Principal, u64)> {
+    let total: u128 = weights.iter().map(|(_, w)| *w as u128).sum();
+    if total == 0 {
+        return Vec::new();
+    }
+    weights
+        .iter()
+        .map(|(user, w)| (*user, (amount as u128 * *w as u128 / total) as u64))
+        .filter(|(_, share)| *share > 0)
+        .collect()
+}
+
+/// Least icUSD (e8s) to accept for `amount_in`: the better of `quote` and
+/// the value at `reference_price_e8s`, less `max_slippage_bps`.
+pub fn min_amount_out(
+    amount_in: u64,
+    decimals: u8,
+    quote: u128,
+    reference_price_e8s: Option<u64>,
+    max_slippage_bps: u16,
+) -> u128 {
+    let reference = reference_price_e8s.map_or(0, |price| {
+        amount_in as u128 * price as u128 / 10u128.pow(decimals as u32)
+    });
+    quote.max(reference) * (10_000 - max_slippage_bps.min(10_000) as u128) / 10_000
+}
+
+/// Collateral to sell out of `gains` once the approve and DEX transfer fees
+/// are set aside, `None` while the gains are not worth three fees.
+pub fn sale_amount(gains: u64, fee: u64) -> Option<u64> {
+    (gains > 3 * fee).then(|| gains - 2 * fee)
+}
+
+/// A completed sale: icUSD the DEX sent, before the icUSD ledger fee, and
+/// the floor it was held to.
+pub(crate) struct Sale {
+    pub amount_out: u128,
+    pub min_out: u128,
+}
+
+/// Why a sale failed, and whether its approve fee was already spent.
+pub(crate) struct SaleFailure {
+    pub reason: String,
+    pub approve_fee_spent: bool,
+}
+
+/// DEX quote, in icUSD e8s, for selling `amount_in` of `collateral_ledger`.
+pub(crate) async fn quote(
+    collateral_ledger: Principal,
+    route: &AutoCompoundRoute,
+    amount_in: u64,
+) -> Result<u128, String> {
+    let quote_result: Result<(Result<u128, candid::Reserved>,), _> = call(
+        route.dex,
+        "get_quote",
+        (route.pool_id.clone(), collateral_ledger, amount_in as u128),
+    )
+    .await;
+    match quote_result {
+        Ok((Ok(quote),)) => Ok(quote),
+        Ok((Err(_),)) => Err("get_quote rejected".to_string()),
+        Err((code, msg)) => Err(format!("get_quote call: {:?} {}", code, msg)),
+    }
+}
+
+/// `min_amount_out` for `amount_in` of `collateral_ledger`, priced against
+/// its last liquidation.
+pub(crate) fn sale_floor(
+    collateral_ledger: Principal,
+    amount_in: u64,
+    quote: u128,
+    max_slippage_bps: u16,
+) -> u128 {
+    let (reference_price, decimals) = read_state(|s| {
+        (
+            s.last_liquidation_price_e8s(&collateral_ledger),
+            s.collateral_registry
+                .get(&collateral_ledger)
+                .map_or(8, |c| c.decimals),
+        )
+    });
+    min_amount_out(
+        amount_in,
+        decimals,
+        quote,
+        reference_price,
+        max_slippage_bps,
+    )
+}
+
+/// Sell `amount_in` of `collateral_ledger` for icUSD through `route`,
+/// approving the DEX for it plus its transfer fee `fee`.
+pub(crate) async fn sell_for_icusd(
+    collateral_ledger: Principal,
+    route: &AutoCompoundRoute,
+    amount_in: u64,
+    fee: u64,
+    max_slippage_bps: u16,
+) -> Result<Sale, SaleFailure> {
+    let approve_args = ApproveArgs {
+        from_subaccount: None,
+        spender: Account {
+            owner: route.dex,
+            subaccount: None,
+        },
+        amount: candid::Nat::from(amount_in as u128 + fee as u128),
+        expected_allowance: None,
+        expires_at: Some(ic_cdk::api::time() + 300_000_000_000), // 5 min
+        fee: None,
+        memo: None,
+        created_at_time: Some(ic_cdk::api::time()),
+    };
+    let approve_result: Result<(Result<candid::Nat, ApproveError>,), _> =
+        call(collateral_ledger, "icrc2_approve", (approve_args,)).await;
+    let unapproved = |reason: String| SaleFailure {
+        reason,
+        approve_fee_spent: false,
+    };
+    match approve_result {
+        Ok((Ok(_),)) => {}
+        Ok((Err(e),)) => return Err(unapproved(format!("approve: {:?}", e))),
+        Err((code, msg)) => return Err(unapproved(format!("approve call: {:?} {}", code, msg))),
+    }
+    // The approve fee is spent from here on.
+    let failed = |reason: String| SaleFailure {
+        reason,
+        approve_fee_spent: true,
+    };
+
+    let quote = quote(collateral_ledger, route, amount_in)
+        .await
+        .map_err(failed)?;
+    let min_out = sale_floor(collateral_ledger, amount_in, quote, max_slippage_bps);
+
+    let swap_result: Result<(Result<DexSwapResult, candid::Reserved>,), _> = call(
+        route.dex,
+        "swap",
+        (
+            route.pool_id.clone(),
+            collateral_ledger,
+            amount_in as u128,
+            min_out,
+        ),
+    )
+    .await;
+    match swap_result {
+        Ok((Ok(result),)) => Ok(Sale {
+            amount_out: result.amount_out,
+            min_out,
+        }),
+        // The allowance is left to expire.
+        Ok((Err(_),)) => Err(failed(format!("swap rejected (min out {})", min_out))),
+        // See withdraw() for the ICRC-002 caveat about
+        // transport-error-then-no-retry.
+        Err((code, msg)) => Err(failed(format!("swap call: {:?} {}", code, msg))),
+    }
+}
+
+async fn run_auto_compound() {
+    // Timer ticks overlap when a swap is slow; the second one skips.
+    let Ok(_guard) = AutoCompoundTickGuard::new() else {
+        return;
+    };
+    if read_state(|s| s.configuration.emergency_pause) || crate::pool_balance_mutation_blocked() {
+        return;
+    }
+    let Some(icusd_ledger) = read_state(|s| s.icusd_ledger()) else {
+        return;
+    };
+
+    let pending = mutate_state(|s| s.take_pending_auto_compound_credits());
+    credit(icusd_ledger, &pending);
+
+    let routes: Vec<(Principal, AutoCompoundRoute)> = read_state(|s| {
+        s.auto_compound_routes
+            .as_ref()
+            .map(|routes| routes.iter().map(|(c, r)| (*c, r.clone())).collect())
+            .unwrap_or_default()
+    });
+    for (collateral_ledger, route) in routes {
+        if crate::pool_balance_mutation_blocked() {
+            break;
+        }
+        compound_collateral(icusd_ledger, collateral_ledger, route).await;
+    }
+}
+
+/// Add bought icUSD to deposits, or queue it while a liquidation is
+/// apportioning.
+pub(crate) fn credit(icusd_ledger: Principal, credits: &[(Principal, u64)]) {
+    if credits.is_empty() {
+        return;
+    }
+    if crate::pool_balance_mutation_blocked() {
+        mutate_state(|s| s.defer_auto_compound_credits(credits));
+        return;
+    }
+    mutate_state(|s| {
+        s.accrue_rewards(ic_cdk::api::time());
+        for (user, amount) in credits {
+            s.add_deposit(*user, icusd_ledger, *amount);
+        }
+    });
+}
+
+fn fail(collateral_ledger: Principal, restore: &[(Principal, u64)], reason: String) {
+    log!(
+        INFO,
+        "Auto-compound of {} failed, restoring gains: {}",
+        collateral_ledger,
+        reason
+    );
+    mutate_state(|s| {
+        s.restore_auto_compound_gains(&collateral_ledger, restore, ic_cdk::api::time());
+        s.push_event(
+            ic_cdk::id(),
+            PoolEventType::AutoCompoundFailed {
+                collateral_ledger,
+                reason,
+            },
+        );
+    });
+}
+
+async fn compound_collateral(
+    icusd_ledger: Principal,
+    collateral_ledger: Principal,
+    route: AutoCompoundRoute,
+) {
+    let batch = mutate_state(|s| s.take_auto_compound_gains(&collateral_ledger));
+    if batch.is_empty() {
+        return;
+    }
+    let total: u64 = batch.iter().map(|(_, gains)| *gains).sum();
+
+    let fee = crate::deposits::ledger_transfer_fee(collateral_ledger).await;
+    let Some(amount_in) = sale_amount(total, fee) else {
+        // Not worth two fees yet; try again once more gains accrue.
+        mutate_state(|s| {
+            s.restore_auto_compound_gains(&collateral_ledger, &batch, ic_cdk::api::time())
+        });
+        return;
+    };
+    let amount_out = match sell_for_icusd(
+        collateral_ledger,
+        &route,
+        amount_in,
+        fee,
+        route.max_slippage_bps,
+    )
+    .await
+    {
+        Ok(sale) => sale.amount_out,
+        Err(failure) if failure.approve_fee_spent => {
+            let restore = split_pro_rata(total - fee, &batch);
+            return fail(collateral_ledger, &restore, failure.reason);
+        }
+        Err(failure) => return fail(collateral_ledger, &batch, failure.reason),
+    };
+
+    // The DEX pays out `amount_out` less the icUSD ledger fee.
+    let icusd_fee = crate::deposits::ledger_transfer_fee(icusd_ledger).await;
+    let received = u64::try_from(amount_out)
+        .unwrap_or(u64::MAX)
+        .saturating_sub(icusd_fee);
+    let credits = split_pro_rata(received, &batch);
+    log!(
+        INFO,
+        "Auto-compound: sold {} of {} for {} icUSD across {} depositors",
+        amount_in,
+        collateral_ledger,
+        received,
+        credits.len()
+    );
+    credit(icusd_ledger, &credits);
+    mutate_state(|s| {
+        for (user, collateral_amount) in &batch {
+            let icusd_amount = credits
+                .iter()
+                .find(|(u, _)| u == user)
+                .map_or(0, |(_, amount)| *amount);
+            s.push_event(
+                *user,
+                PoolEventType::AutoCompounded {
+                    collateral_ledger,
+                    collateral_amount: *collateral_amount,
+                    icusd_amount,
+                },
+            );
+        }
+    });
+}
@@ -0,0 +1,298 @@
+//! Choosing the payout token of a collateral claim.
+//!
+//! `claim_collateral_as` lets a depositor take one collateral's gains either
+//! as the seized collateral itself, exactly as `claim_collateral` does, or as
+//! icUSD, chosen per claim. An icUSD payout sells the gains at claim time
+//! through the collateral's auto-compound route with the protections of
+//! `auto_compound`: the swap must return at least the better of the DEX
+//! quote and the value at the last liquidation price, less the route's
+//! slippage or the caller's tighter bound.
+//!
+//! The sale spends two collateral ledger fees (approve and the DEX's
+//! `icrc2_transfer_from`) and the proceeds two icUSD fees (the DEX payout
+//! and the transfer to the caller). `quote_claim_payout` discloses them,
+//! with the current quote and the swap's floor, before the caller commits,
+//! and the receipt reports what was actually spent.
+//!
+//! Gains too small to be worth the fees and a failed approve, quote or swap
+//! fall back to the collateral: the gains go back to the caller, less an
+//! approve fee already spent, and are claimed as `claim_collateral` would.
+//! icUSD that cannot be transferred is added to the caller's deposit.
+
+use candid::Principal;
+use ic_canister_log::log;
+use ic_cdk::call;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::icrc1::transfer::{TransferArg, TransferError};
+
+use crate::auto_compound::{sale_amount, sale_floor, sell_for_icusd};
+use crate::deposits::ledger_transfer_fee;
+use crate::logs::INFO;
+use crate::state::{mutate_state, read_state, StabilityPoolState};
+use crate::types::{
+    AutoCompoundRoute, ClaimPayout, ClaimPayoutQuote, ClaimPayoutReceipt, PoolEventType,
+    StabilityPoolError,
+};
+
+/// Slippage an icUSD payout is held to: the route's, or `requested` when it
+/// is tighter.
+pub fn payout_slippage_bps(route: &AutoCompoundRoute, requested: Option<u16>) -> u16 {
+    requested.map_or(route.max_slippage_bps, |bps| {
+        bps.min(route.max_slippage_bps)
+    })
+}
+
+fn claimable_gains(
+    state: &StabilityPoolState,
+    user: &Principal,
+    collateral_ledger: &Principal,
+) -> u64 {
+    state
+        .get_collateral_gains(user)
+        .get(collateral_ledger)
+        .copied()
+        .unwrap_or(0)
+}
+
+fn too_small(gains: u64, fee: u64) -> String {
+    format!(
+        "gains of {} do not cover three ledger fees of {}",
+        gains, fee
+    )
+}
+
+fn to_u64(amount: u128) -> u64 {
+    u64::try_from(amount).unwrap_or(u64::MAX)
+}
+
+/// Fees, quote and floor of claiming the caller's `collateral_ledger` gains
+/// as icUSD now.
+pub async fn quote_claim_payout(
+    collateral_ledger: Principal,
+    max_slippage_bps: Option<u16>,
+) -> Result<ClaimPayoutQuote, StabilityPoolError> {
+    let caller = ic_cdk::api::caller();
+    let (icusd_ledger, route) = read_state(|s| s.icusd_payout_route(&collateral_ledger))?;
+    let max_slippage_bps = payout_slippage_bps(&route, max_slippage_bps);
+    let gains = read_state(|s| claimable_gains(s, &caller, &collateral_ledger));
+    let unavailable = |reason: String| StabilityPoolError::PayoutSwapUnavailable {
+        collateral: collateral_ledger,
+        reason,
+    };
+
+    let fee = ledger_transfer_fee(collateral_ledger).await;
+    let amount_in = sale_amount(gains, fee).ok_or_else(|| unavailable(too_small(gains, fee)))?;
+    let dex_quote = crate::auto_compound::quote(collateral_ledger, &route, amount_in)
+        .await
+        .map_err(unavailable)?;
+    let min_out = sale_floor(collateral_ledger, amount_in, dex_quote, max_slippage_bps);
+    let icusd_fees = 2 * ledger_transfer_fee(icusd_ledger).await;
+    Ok(ClaimPayoutQuote {
+        collateral_ledger,
+        gains,
+        collateral_payout: gains.saturating_sub(fee),
+        amount_in,
+        collateral_fees: 2 * fee,
+        max_slippage_bps,
+        dex_quote: to_u64(dex_quote),
+        min_icusd_out: to_u64(min_out),
+        icusd_fees,
+        estimated_icusd: to_u64(dex_quote).saturating_sub(icusd_fees),
+    })
+}
+
+/// Claim the caller's `collateral_ledger` gains, paid out as `payout`.
+pub async fn claim_collateral_as(
+    collateral_ledger: Principal,
+    payout: ClaimPayout,
+) -> Result<ClaimPayoutReceipt, StabilityPoolError> {
+    match payout {
+        ClaimPayout::Collateral => pay_collateral(collateral_ledger).await,
+        ClaimPayout::IcUsd { max_slippage_bps } => {
+            claim_as_icusd(collateral_ledger, max_slippage_bps).await
+        }
+    }
+}
+
+fn receipt(collateral_ledger: Principal, gains: u64, paid_ledger: Principal) -> ClaimPayoutReceipt {
+    ClaimPayoutReceipt {
+        collateral_ledger,
+        gains,
+        paid_ledger,
+        paid_amount: 0,
+        collateral_fees: 0,
+        icusd_fees: 0,
+        min_icusd_out: None,
+        fallback_reason: None,
+        credited_to_deposit: false,
+    }
+}
+
+async fn pay_collateral(
+    collateral_ledger: Principal,
+) -> Result<ClaimPayoutReceipt, StabilityPoolError> {
+    let caller = ic_cdk::api::caller();
+    let gains = read_state(|s| claimable_gains(s, &caller, &collateral_ledger));
+    let paid = crate::deposits::claim_collateral(collateral_ledger).await?;
+    if paid == 0 {
+        // Nothing to claim, or too little to cover the fee: the gains stay.
+        return Ok(receipt(collateral_ledger, 0, collateral_ledger));
+    }
+    Ok(ClaimPayoutReceipt {
+        paid_amount: paid,
+        collateral_fees: gains - paid,
+        ..receipt(collateral_ledger, gains, collateral_ledger)
+    })
+}
+
+/// Pay the collateral after an icUSD payout failed, `fees_spent` of the
+/// gains being gone already.
+async fn fall_back(
+    collateral_ledger: Principal,
+    fees_spent: u64,
+    reason: String,
+) -> Result<ClaimPayoutReceipt, StabilityPoolError> {
+    let caller = ic_cdk::api::caller();
+    log!(
+        INFO,
+        "icUSD payout of {} for {} failed, paying collateral: {}",
+        collateral_ledger,
+        caller,
+        reason
+    );
+    mutate_state(|s| {
+        s.push_event(
+            caller,
+            PoolEventType::ClaimPayoutFellBack {
+                collateral_ledger,
+                reason: reason.clone(),
+            },
+        )
+    });
+    let receipt = pay_collateral(collateral_ledger).await?;
+    Ok(ClaimPayoutReceipt {
+        gains: receipt.gains + fees_spent,
+        collateral_fees: receipt.collateral_fees + fees_spent,
+        fallback_reason: Some(reason),
+        ..receipt
+    })
+}
+
+async fn claim_as_icusd(
+    collateral_ledger: Principal,
+    max_slippage_bps: Option<u16>,
+) -> Result<ClaimPayoutReceipt, StabilityPoolError> {
+    // SP-102: refuse balance-mutating ops while a liquidation is apportioning.
+    if crate::pool_balance_mutation_blocked() {
+        return Err(StabilityPoolError::SystemBusy);
+    }
+    let caller = ic_cdk::api::caller();
+    if read_state(|s| s.configuration.emergency_pause) {
+        return Err(StabilityPoolError::EmergencyPaused);
+    }
+    read_state(|s| s.ensure_icrc_claimable_collateral(&collateral_ledger))?;
+    read_state(|s| s.ensure_gains_not_held(&caller, &collateral_ledger))?;
+    let (icusd_ledger, route) = read_state(|s| s.icusd_payout_route(&collateral_ledger))?;
+    let max_slippage_bps = payout_slippage_bps(&route, max_slippage_bps);
+
+    // Deduct before the first await, as `claim_collateral` does.
+    let gains = mutate_state(|s| {
+        let amount = claimable_gains(s, &caller, &collateral_ledger);
+        if amount > 0 {
+            s.mark_gains_claimed(&caller, &collateral_ledger, amount);
+        }
+        amount
+    });
+    if gains == 0 {
+        return Ok(receipt(collateral_ledger, 0, icusd_ledger));
+    }
+    let restore = |amount: u64| {
+        mutate_state(|s| {
+            s.restore_auto_compound_gains(
+                &collateral_ledger,
+                &[(caller, amount)],
+                ic_cdk::api::time(),
+            )
+        })
+    };
+
+    let fee = ledger_transfer_fee(collateral_ledger).await;
+    let Some(amount_in) = sale_amount(gains, fee) else {
+        restore(gains);
+        return fall_back(collateral_ledger, 0, too_small(gains, fee)).await;
+    };
+    let sale =
+        match sell_for_icusd(collateral_ledger, &route, amount_in, fee, max_slippage_bps).await {
+            Ok(sale) => sale,
+            Err(failure) => {
+                let spent = if failure.approve_fee_spent { fee } else { 0 };
+                restore(gains - spent);
+                return fall_back(collateral_ledger, spent, failure.reason).await;
+            }
+        };
+
+    // The DEX pays out `amount_out` less the icUSD ledger fee.
+    let icusd_fee = ledger_transfer_fee(icusd_ledger).await;
+    let received = to_u64(sale.amount_out).saturating_sub(icusd_fee);
+    let mut receipt = ClaimPayoutReceipt {
+        collateral_fees: 2 * fee,
+        icusd_fees: icusd_fee,
+        min_icusd_out: Some(to_u64(sale.min_out)),
+        ..receipt(collateral_ledger, gains, icusd_ledger)
+    };
+    if received > icusd_fee {
+        let transfer_args = TransferArg {
+            to: Account {
+                owner: caller,
+                subaccount: None,
+            },
+            amount: (received - icusd_fee).into(),
+            fee: None,
+            memo: None,
+            created_at_time: Some(ic_cdk::api::time()),
+            from_subaccount: None,
+        };
+        let result: Result<(Result<candid::Nat, TransferError>,), _> =
+            call(icusd_ledger, "icrc1_transfer", (transfer_args,)).await;
+        match result {
+            Ok((Ok(_),)) | Ok((Err(TransferError::Duplicate { .. }),)) => {
+                receipt.paid_amount = received - icusd_fee;
+                receipt.icusd_fees += icusd_fee;
+            }
+            // See withdraw() for the ICRC-002 caveat about
+            // transport-error-then-no-retry.
+            Ok((Err(e),)) => log!(INFO, "icUSD payout to {} failed: {:?}", caller, e),
+            Err((code, msg)) => log!(
+                INFO,
+                "icUSD payout to {} failed: {:?} {}",
+                caller,
+                code,
+                msg
+            ),
+        }
+    }
+    if receipt.paid_amount == 0 && received > 0 {
+        crate::auto_compound::credit(icusd_ledger, &[(caller, received)]);
+        receipt.paid_amount = received;
+        receipt.credited_to_deposit = true;
+    }
+    log!(
+        INFO,
+        "Claim: {} of {} sold for {} icUSD by {}",
+        amount_in,
+        collateral_ledger,
+        receipt.paid_amount,
+        caller
+    );
+    mutate_state(|s| {
+        s.push_event(
+            caller,
+            PoolEventType::CollateralClaimedAsIcusd {
+                collateral_ledger,
+                collateral_amount: gains,
+                icusd_amount: receipt.paid_amount,
+            },
+        )
+    });
+    Ok(receipt)
+}
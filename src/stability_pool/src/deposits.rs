@@ -8,7 +8,7 @@ use icrc_ledger_types::icrc1::account::Account;
 use icrc_ledger_types::icrc1::transfer::{TransferArg, TransferError};
 use icrc_ledger_types::icrc2::approve::{ApproveArgs, ApproveError};
 use icrc_ledger_types::icrc2::transfer_from::{TransferFromArgs, TransferFromError};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::{BTreeMap, HashMap};
 
 /// Conservative fallback for a stablecoin ledger's transfer fee (native units),
@@ -17,11 +17,25 @@ use std::collections::{BTreeMap, HashMap};
 /// keeps the pool solvent rather than risking an over-send.
 const DEFAULT_LEDGER_FEE: u64 = 10_000;
 
+/// Most withdrawals the queue holds; past it `withdraw` answers `SystemBusy`
+/// while a liquidation is pending, as it did before the queue.
+pub const MAX_QUEUED_WITHDRAWALS: usize = 1_000;
+
+/// A queued withdrawal is paid after this long even if no liquidation
+/// settled meanwhile (the pending one failed or was skipped).
+pub const WITHDRAWAL_QUEUE_MAX_DELAY_SECONDS: u64 = 600;
+
+/// How often a non-empty withdrawal queue is retried.
+pub const WITHDRAWAL_QUEUE_RETRY_SECONDS: u64 = 30;
+
 thread_local! {
     /// Per-ledger transfer-fee cache for transfer/refund math, populated lazily from
     /// `icrc1_fee`. Heap-only (not persisted), so it is simply re-warmed after
     /// an upgrade. Mirrors rumi_3pool::transfers::LEDGER_FEES.
     static LEDGER_FEES: RefCell<HashMap<Principal, u64>> = RefCell::new(HashMap::new());
+
+    /// Whether a `process_withdrawal_queue` timer is already set.
+    static WITHDRAWAL_QUEUE_SCHEDULED: Cell<bool> = const { Cell::new(false) };
 }
 
 fn record_deposit_credit_after_async(
@@ -31,6 +45,7 @@ fn record_deposit_credit_after_async(
 ) -> Result<(), StabilityPoolError> {
     crate::ensure_pool_balance_mutation_allowed()?;
     mutate_state(|s| {
+        s.accrue_rewards(ic_cdk::api::time());
         s.add_deposit(caller, token_ledger, amount);
         s.push_event(
             caller,
@@ -52,6 +67,7 @@ fn record_deposit_as_3usd_credit_after_async(
 ) -> Result<(), StabilityPoolError> {
     crate::ensure_pool_balance_mutation_allowed()?;
     mutate_state(|s| {
+        s.accrue_rewards(ic_cdk::api::time());
         s.add_deposit(caller, three_usd_ledger, lp_amount);
         s.push_event(
             caller,
@@ -206,55 +222,61 @@ fn prepare_withdrawal_after_ledger_check(
     ledger_balance: Option<u64>,
     ledger_fee: u64,
 ) -> Result<(u64, Option<String>), StabilityPoolError> {
+    // Settled, so that the sole-holder check compares the stored balance
+    // with the aggregate to the unit. Rewards accrue first, while the
+    // caller's deposit still earns them.
     mutate_state(|s| {
-        let mut withdrawal_amount = requested_amount;
-        let mut correction_msg = None;
-
-        if let Some(live_balance) = ledger_balance {
-            let user_balance = s
-                .deposits
-                .get(&caller)
-                .and_then(|pos| pos.stablecoin_balances.get(&token_ledger).copied())
-                .unwrap_or(0);
-            let aggregate_balance = s
-                .total_stablecoin_balances
-                .get(&token_ledger)
-                .copied()
-                .unwrap_or(0);
-
-            if live_balance < aggregate_balance {
-                if live_balance <= ledger_fee {
-                    return Err(StabilityPoolError::AmountTooLow {
-                        minimum_e8s: ledger_fee + 1,
-                    });
-                }
-
-                let sole_holder = user_balance > 0 && user_balance == aggregate_balance;
-                let drains_recorded_position = requested_amount == user_balance;
-                let drains_live_balance = requested_amount == live_balance;
+        s.accrue_rewards(ic_cdk::api::time());
+        s.with_settled(&caller, |s| {
+            let mut withdrawal_amount = requested_amount;
+            let mut correction_msg = None;
+
+            if let Some(live_balance) = ledger_balance {
+                let user_balance = s
+                    .deposits
+                    .get(&caller)
+                    .and_then(|pos| pos.stablecoin_balances.get(&token_ledger).copied())
+                    .unwrap_or(0);
+                let aggregate_balance = s
+                    .total_stablecoin_balances
+                    .get(&token_ledger)
+                    .copied()
+                    .unwrap_or(0);
+
+                if live_balance < aggregate_balance {
+                    if live_balance <= ledger_fee {
+                        return Err(StabilityPoolError::AmountTooLow {
+                            minimum_e8s: ledger_fee + 1,
+                        });
+                    }
 
-                if sole_holder && (drains_recorded_position || drains_live_balance) {
-                    let msg = s.correct_balance(caller, token_ledger, live_balance);
-                    s.push_event(
-                        caller,
-                        PoolEventType::BalanceCorrected {
-                            user: caller,
-                            token_ledger,
-                            new_amount: live_balance,
-                        },
-                    );
-                    correction_msg = Some(msg);
-                    withdrawal_amount = live_balance;
-                } else {
+                    let sole_holder = user_balance > 0 && user_balance == aggregate_balance;
+                    let drains_recorded_position = requested_amount == user_balance;
+                    let drains_live_balance = requested_amount == live_balance;
+
+                    if sole_holder && (drains_recorded_position || drains_live_balance) {
+                        let msg = s.correct_balance(caller, token_ledger, live_balance);
+                        s.push_event(
+                            caller,
+                            PoolEventType::BalanceCorrected {
+                                user: caller,
+                                token_ledger,
+                                new_amount: live_balance,
+                            },
+                        );
+                        correction_msg = Some(msg);
+                        withdrawal_amount = live_balance;
+                    } else {
+                        return Err(StabilityPoolError::InsufficientPoolBalance);
+                    }
+                } else if live_balance < requested_amount {
                     return Err(StabilityPoolError::InsufficientPoolBalance);
                 }
-            } else if live_balance < requested_amount {
-                return Err(StabilityPoolError::InsufficientPoolBalance);
             }
-        }
 
-        s.process_withdrawal(caller, token_ledger, withdrawal_amount)?;
-        Ok((withdrawal_amount, correction_msg))
+            s.process_withdrawal(caller, token_ledger, withdrawal_amount)?;
+            Ok((withdrawal_amount, correction_msg))
+        })
     })
 }
 
@@ -291,6 +313,9 @@ pub async fn deposit(token_ledger: Principal, amount: u64) -> Result<(), Stabili
     if read_state(|s| s.configuration.emergency_pause) {
         return Err(StabilityPoolError::EmergencyPaused);
     }
+    if let Some(mode) = read_state(|s| s.deposits_restricted_by_mode()) {
+        return Err(StabilityPoolError::ProtocolModeRestricted { mode });
+    }
 
     log!(
         INFO,
@@ -380,17 +405,112 @@ pub async fn deposit(token_ledger: Principal, amount: u64) -> Result<(), Stabili
 /// 1. Deduct balance from state (prevents concurrent withdrawals from passing balance check)
 /// 2. Transfer tokens to user
 /// 3. If transfer fails, rollback the deduction
+/// Withdraw `amount` of `token_ledger` to the caller.
+///
+/// While a liquidation is pending the withdrawal is queued instead and the
+/// reply is `WithdrawalQueued`: it is paid by `process_withdrawal_queue` once
+/// the liquidation has settled, from the balance left after its loss.
 pub async fn withdraw(token_ledger: Principal, amount: u64) -> Result<(), StabilityPoolError> {
+    let caller = ic_cdk::api::caller();
+    if read_state(|s| s.configuration.emergency_pause) {
+        return Err(StabilityPoolError::EmergencyPaused);
+    }
     // SP-102: refuse balance-mutating ops while a liquidation is apportioning,
     // so a withdraw cannot land between a liquidation's snapshot and its burn
     // apportionment and escape the depositor's share of the loss.
     if crate::pool_balance_mutation_blocked() {
-        return Err(StabilityPoolError::SystemBusy);
+        let position = mutate_state(|s| {
+            let position = s.queue_withdrawal(caller, token_ledger, amount, ic_cdk::api::time())?;
+            let epoch = s.total_liquidations_executed;
+            s.push_event(
+                caller,
+                PoolEventType::WithdrawalQueued {
+                    token_ledger,
+                    amount,
+                    epoch,
+                },
+            );
+            Ok::<_, StabilityPoolError>(position)
+        })?;
+        schedule_withdrawal_queue();
+        return Err(StabilityPoolError::WithdrawalQueued { position });
     }
-    let caller = ic_cdk::api::caller();
+    withdraw_for(caller, token_ledger, amount).await
+}
 
-    if read_state(|s| s.configuration.emergency_pause) {
-        return Err(StabilityPoolError::EmergencyPaused);
+/// Set a timer for `process_withdrawal_queue` if the queue is not empty and
+/// none is set.
+pub fn schedule_withdrawal_queue() {
+    if read_state(|s| s.withdrawal_queue_len()) == 0
+        || WITHDRAWAL_QUEUE_SCHEDULED.with(|f| f.replace(true))
+    {
+        return;
+    }
+    ic_cdk_timers::set_timer(
+        std::time::Duration::from_secs(WITHDRAWAL_QUEUE_RETRY_SECONDS),
+        || ic_cdk::spawn(process_withdrawal_queue()),
+    );
+}
+
+/// Pay the queued withdrawals whose liquidation has settled, oldest first.
+/// Each pays at most the balance the depositor has left; one that cannot be
+/// paid is dropped with a `QueuedWithdrawalFailed` event.
+pub async fn process_withdrawal_queue() {
+    WITHDRAWAL_QUEUE_SCHEDULED.with(|f| f.set(false));
+    if read_state(|s| s.configuration.emergency_pause) || crate::pool_balance_mutation_blocked() {
+        schedule_withdrawal_queue();
+        return;
+    }
+    let ready = mutate_state(|s| s.take_ready_withdrawals(ic_cdk::api::time()));
+    for (i, queued) in ready.iter().enumerate() {
+        if crate::pool_balance_mutation_blocked() {
+            mutate_state(|s| s.requeue_withdrawals(&ready[i..]));
+            break;
+        }
+        let balance = read_state(|s| {
+            s.position(&queued.user)
+                .and_then(|pos| pos.stablecoin_balances.get(&queued.token_ledger).copied())
+                .unwrap_or(0)
+        });
+        let amount = queued.amount.min(balance);
+        match withdraw_for(queued.user, queued.token_ledger, amount).await {
+            Ok(()) => {}
+            Err(StabilityPoolError::SystemBusy) => {
+                mutate_state(|s| s.requeue_withdrawals(&ready[i..]));
+                break;
+            }
+            Err(e) => {
+                log!(
+                    INFO,
+                    "Queued withdrawal of {} {} for {} failed: {:?}",
+                    amount,
+                    queued.token_ledger,
+                    queued.user,
+                    e
+                );
+                mutate_state(|s| {
+                    s.push_event(
+                        queued.user,
+                        PoolEventType::QueuedWithdrawalFailed {
+                            token_ledger: queued.token_ledger,
+                            amount,
+                            reason: format!("{:?}", e),
+                        },
+                    )
+                });
+            }
+        }
+    }
+    schedule_withdrawal_queue();
+}
+
+async fn withdraw_for(
+    caller: Principal,
+    token_ledger: Principal,
+    amount: u64,
+) -> Result<(), StabilityPoolError> {
+    if crate::pool_balance_mutation_blocked() {
+        return Err(StabilityPoolError::SystemBusy);
     }
 
     // The ledger debits `transfer_amount + fee` from the pool. Query the live
@@ -537,14 +657,15 @@ pub async fn claim_collateral(collateral_ledger: Principal) -> Result<u64, Stabi
     }
 
     read_state(|s| s.ensure_icrc_claimable_collateral(&collateral_ledger))?;
+    read_state(|s| s.ensure_gains_not_held(&caller, &collateral_ledger))?;
 
     // Read and deduct gains atomically BEFORE transfer.
     // mark_gains_claimed uses saturating_sub and cleans up zero entries.
     let gains = mutate_state(|s| {
         let amount = s
-            .deposits
-            .get(&caller)
-            .and_then(|pos| pos.collateral_gains.get(&collateral_ledger).copied())
+            .get_collateral_gains(&caller)
+            .get(&collateral_ledger)
+            .copied()
             .unwrap_or(0);
         if amount > 0 {
             s.mark_gains_claimed(&caller, &collateral_ledger, amount);
@@ -768,6 +889,9 @@ pub async fn deposit_as_3usd(
     if read_state(|s| s.configuration.emergency_pause) {
         return Err(StabilityPoolError::EmergencyPaused);
     }
+    if let Some(mode) = read_state(|s| s.deposits_restricted_by_mode()) {
+        return Err(StabilityPoolError::ProtocolModeRestricted { mode });
+    }
 
     let amount_e8s = normalize_to_e8s(amount, config.decimals);
     let min_deposit = read_state(|s| s.configuration.min_deposit_e8s);
@@ -1153,7 +1277,7 @@ pub async fn claim_pending_refund(refund_id: u64) -> Result<u64, StabilityPoolEr
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rumi_protocol_backend::chains::config::ChainId;
+    use rumi_common::chains::ChainId;
 
     fn principal(byte: u8) -> Principal {
         Principal::from_slice(&[byte])
@@ -1175,9 +1299,9 @@ mod tests {
             stables_consumed,
             burn_created_at_time_ns: 123,
             status: ChainSpAbsorbIntentStatus::Burned,
-            burn_proof: Some(rumi_protocol_backend::icrc3_proof::SpWritedownProof {
+            burn_proof: Some(rumi_common::icrc3_proof::SpWritedownProof {
                 block_index: 44,
-                ledger_kind: rumi_protocol_backend::icrc3_proof::SpProofLedger::IcusdBurn,
+                ledger_kind: rumi_common::icrc3_proof::SpProofLedger::IcusdBurn,
                 vault_id_memo: 77,
             }),
             backend_result: None,
@@ -4,10 +4,15 @@ use ic_cdk::{init, post_upgrade, pre_upgrade, query, update};
 use std::collections::BTreeMap;
 use std::time::Duration;
 
+pub mod auto_compound;
+pub mod claim_payout;
 pub mod deposits;
 pub mod liquidation;
 pub mod logs;
 pub mod pool_guard;
+pub mod product_sum;
+pub mod rewards;
+pub mod safe_mode;
 pub mod state;
 pub mod types;
 
@@ -64,6 +69,9 @@ fn init(args: StabilityPoolInitArgs) {
         setup_chain_absorb_auto_timer();
         setup_unallocated_interest_forward_retry_timer();
         setup_ledger_reconciliation_timer();
+        crate::safe_mode::setup_probe_timer();
+        crate::rewards::setup_accrual_timer();
+        crate::auto_compound::setup_compound_timer();
     });
 }
 
@@ -86,6 +94,15 @@ fn post_upgrade(_args: StabilityPoolInitArgs) {
         read_state(|s| s.total_liquidations_executed)
     );
 
+    // Positions stored before loss cohorts existed join theirs now; a no-op
+    // on later upgrades, when every position with deposits is attached.
+    mutate_state(|s| s.attach_all_positions());
+    log!(
+        INFO,
+        "Migration: {} loss cohorts",
+        read_state(|s| s.loss_cohorts.as_ref().map_or(0, |c| c.len()))
+    );
+
     if let Err(error) = read_state(|s| s.validate_state()) {
         ic_cdk::trap(&format!("State validation failed after upgrade: {}", error));
     }
@@ -103,6 +120,11 @@ fn post_upgrade(_args: StabilityPoolInitArgs) {
         setup_chain_absorb_auto_timer();
         setup_unallocated_interest_forward_retry_timer();
         setup_ledger_reconciliation_timer();
+        crate::safe_mode::setup_probe_timer();
+        crate::rewards::setup_accrual_timer();
+        crate::auto_compound::setup_compound_timer();
+        // Timers do not survive upgrades: resume paying queued withdrawals.
+        crate::deposits::schedule_withdrawal_queue();
     });
 }
 
@@ -213,6 +235,44 @@ pub async fn claim_all_collateral() -> Result<BTreeMap<Principal, u64>, Stabilit
     crate::deposits::claim_all_collateral().await
 }
 
+/// Claim one collateral's gains as the collateral itself or as icUSD. See
+/// `claim_payout`.
+#[update]
+pub async fn claim_collateral_as(
+    collateral_ledger: Principal,
+    payout: ClaimPayout,
+) -> Result<ClaimPayoutReceipt, StabilityPoolError> {
+    crate::claim_payout::claim_collateral_as(collateral_ledger, payout).await
+}
+
+/// The fees, DEX quote and minimum output of claiming the caller's gains
+/// of `collateral_ledger` as icUSD now.
+#[update]
+pub async fn quote_claim_payout(
+    collateral_ledger: Principal,
+    max_slippage_bps: Option<u16>,
+) -> Result<ClaimPayoutQuote, StabilityPoolError> {
+    crate::claim_payout::quote_claim_payout(collateral_ledger, max_slippage_bps).await
+}
+
+/// Claim accrued reward tokens. See `rewards`.
+#[update]
+pub async fn claim_rewards() -> Result<u64, StabilityPoolError> {
+    crate::rewards::claim_rewards().await
+}
+
+/// Have collateral gains sold for icUSD and added to the caller's deposit
+/// on a timer. See `auto_compound`.
+#[update]
+pub fn enable_auto_compound() -> Result<(), StabilityPoolError> {
+    crate::auto_compound::set_enabled(true)
+}
+
+#[update]
+pub fn disable_auto_compound() -> Result<(), StabilityPoolError> {
+    crate::auto_compound::set_enabled(false)
+}
+
 /// Convenience: deposit a stablecoin (icUSD, ckUSDT, ckUSDC) and have the pool
 /// mint 3USD on the user's behalf by depositing into the 3pool.
 #[update]
@@ -424,8 +484,8 @@ async fn ensure_backend_xrp_claim_absent(
     claimant: Principal,
 ) -> Result<(), StabilityPoolError> {
     let method = "stability_pool_xrp_claim_outstanding";
-    let response: Result<(Result<bool, rumi_protocol_backend::ProtocolError>,), _> =
-        ic_cdk::call(protocol_canister_id, method, (claim_id, claimant)).await;
+    let response: Result<(Result<bool, rumi_common::ProtocolError>,), _> =
+        crate::safe_mode::call_backend(protocol_canister_id, method, (claim_id, claimant)).await;
 
     match response {
         Ok((Ok(false),)) => Ok(()),
@@ -480,6 +540,15 @@ pub async fn execute_liquidation(vault_id: u64) -> Result<LiquidationResult, Sta
     crate::liquidation::execute_liquidation(vault_id).await
 }
 
+/// Dry run of `execute_liquidation` against live backend data: expected
+/// depositor gains and the pool depth impact, without executing anything.
+#[update]
+pub async fn simulate_liquidation(
+    vault_id: u64,
+) -> Result<LiquidationSimulation, StabilityPoolError> {
+    crate::liquidation::simulate_liquidation(vault_id).await
+}
+
 #[update]
 pub async fn sp_absorb_chain_vault(
     vault_id: u64,
@@ -735,6 +804,25 @@ pub fn get_user_position(user: Option<Principal>) -> Option<UserStabilityPositio
     read_state(|s| s.get_user_position(&target))
 }
 
+/// `user`'s stablecoin balances after every liquidation loss so far.
+#[query]
+pub fn get_compounded_deposit(user: Principal) -> CompoundedDeposit {
+    read_state(|s| s.get_compounded_deposit(&user))
+}
+
+/// `user`'s unclaimed collateral gains, keyed by collateral ledger.
+#[query]
+pub fn get_depositor_collateral_gain(user: Principal) -> BTreeMap<Principal, u64> {
+    read_state(|s| s.get_collateral_gains(&user))
+}
+
+/// Realized APY, utilization and liquidation losses over the trailing
+/// 7/30/90 days. See `StabilityPoolState::liquidity_pool_stats`.
+#[query]
+pub fn get_liquidity_pool_stats() -> LiquidityPoolStats {
+    read_state(|s| s.liquidity_pool_stats(ic_cdk::api::time()))
+}
+
 #[query]
 pub fn get_liquidation_history(limit: Option<u64>) -> Vec<PoolLiquidationRecord> {
     let limit = limit.unwrap_or(50).min(100) as usize;
@@ -887,8 +975,8 @@ async fn compute_ledger_reconciliation() -> Vec<LedgerReconciliationEntry> {
 /// before it blocks withdrawals. Admin-gated because it triggers one
 /// inter-canister balance query per token.
 #[update]
-pub async fn get_ledger_reconciliation() -> Result<Vec<LedgerReconciliationEntry>, StabilityPoolError>
-{
+pub async fn get_ledger_reconciliation(
+) -> Result<Vec<LedgerReconciliationEntry>, StabilityPoolError> {
     let caller = ic_cdk::api::caller();
     if !read_state(|s| s.is_admin(&caller)) {
         return Err(StabilityPoolError::Unauthorized);
@@ -1072,11 +1160,54 @@ pub fn icrc21_canister_call_consent_message(
                 Err(_) => "Claim collateral rewards from the Rumi Protocol Stability Pool.".to_string(),
             }
         }
+        "claim_collateral_as" => {
+            match candid::decode_args::<(Principal, ClaimPayout)>(&request.arg) {
+                Ok((collateral_ledger, payout)) => {
+                    let symbol = read_state(|s| {
+                        s.collateral_registry
+                            .get(&collateral_ledger)
+                            .map(|c| c.symbol.clone())
+                            .unwrap_or_else(|| format!("collateral {}", collateral_ledger))
+                    });
+                    match payout {
+                        ClaimPayout::Collateral => format!(
+                            "## Claim Collateral Rewards\n\n\
+                             You are claiming your **{}** collateral rewards from the Rumi Protocol Stability Pool.",
+                            symbol
+                        ),
+                        ClaimPayout::IcUsd { .. } => format!(
+                            "## Claim Collateral Rewards as icUSD\n\n\
+                             Your **{}** collateral rewards will be swapped into icUSD on a DEX and sent to you. \
+                             The swap costs two {} and two icUSD ledger fees. If it fails or the price is \
+                             worse than the slippage bound, you receive the {} instead.",
+                            symbol, symbol, symbol
+                        ),
+                    }
+                }
+                Err(_) => "Claim collateral rewards from the Rumi Protocol Stability Pool.".to_string(),
+            }
+        }
         "claim_all_collateral" => {
             "## Claim All Collateral Rewards\n\n\
              You are claiming **all** of your collateral rewards from the Rumi Protocol Stability Pool."
                 .to_string()
         }
+        "claim_rewards" => {
+            "## Claim Depositor Rewards\n\n\
+             You are claiming the reward tokens your Rumi Protocol Stability Pool deposits have accrued."
+                .to_string()
+        }
+        "enable_auto_compound" => {
+            "## Enable Auto-Compounding\n\n\
+             Your future collateral rewards from the Rumi Protocol Stability Pool will be \
+             swapped into icUSD and added to your deposit automatically."
+                .to_string()
+        }
+        "disable_auto_compound" => {
+            "## Disable Auto-Compounding\n\n\
+             Your collateral rewards will accumulate for you to claim instead of being swapped into icUSD."
+                .to_string()
+        }
         "claim_cfx" => {
             "## Claim CFX Rewards\n\n\
              You are claiming CFX rewards from chain-vault liquidations."
@@ -1255,6 +1386,141 @@ pub fn resume_operations() -> Result<(), StabilityPoolError> {
     Ok(())
 }
 
+/// Backend push of its protocol mode on every mode change (protocol canister
+/// only). The pool restricts new deposits and liquidations per its
+/// `ModeInheritancePolicy`; repeating the current mode is a no-op.
+#[update]
+pub fn set_protocol_mode(mode: ProtocolMode) -> Result<(), StabilityPoolError> {
+    let caller = ic_cdk::api::caller();
+    let expected = read_state(|s| s.protocol_canister_id);
+    if caller != expected {
+        return Err(StabilityPoolError::Unauthorized);
+    }
+    if read_state(|s| s.protocol_mode == Some(mode)) {
+        return Ok(());
+    }
+    mutate_state(|s| {
+        s.protocol_mode = Some(mode);
+        s.push_event(caller, PoolEventType::ProtocolModeInherited { mode });
+    });
+    log!(INFO, "Inherited backend protocol mode {:?}", mode);
+    Ok(())
+}
+
+/// Choose which backend modes block new deposits and liquidations (admin
+/// only). Takes effect for the mode already inherited.
+#[update]
+pub fn set_mode_inheritance_policy(
+    policy: ModeInheritancePolicy,
+) -> Result<(), StabilityPoolError> {
+    let caller = ic_cdk::api::caller();
+    if !read_state(|s| s.is_admin(&caller)) {
+        return Err(StabilityPoolError::Unauthorized);
+    }
+    log!(
+        INFO,
+        "Mode inheritance policy set to {:?} by {}",
+        policy,
+        caller
+    );
+    mutate_state(|s| {
+        s.mode_inheritance_policy = Some(policy);
+        s.push_event(caller, PoolEventType::ModeInheritancePolicyUpdated);
+    });
+    Ok(())
+}
+
+#[query]
+pub fn get_mode_inheritance() -> ModeInheritanceStatus {
+    read_state(|s| s.mode_inheritance_status())
+}
+
+/// Whether the pool is in safe mode because the backend stopped replying.
+/// See `safe_mode`.
+#[query]
+pub fn get_safe_mode_status() -> SafeModeStatus {
+    read_state(|s| s.safe_mode_status())
+}
+
+/// Configure the reward token emission to depositors (admin only). Rewards
+/// owed under the previous config accrue first. See `rewards`.
+#[update]
+pub fn set_reward_config(config: RewardConfig) -> Result<(), StabilityPoolError> {
+    let caller = ic_cdk::api::caller();
+    if !read_state(|s| s.is_admin(&caller)) {
+        return Err(StabilityPoolError::Unauthorized);
+    }
+    mutate_state(|s| {
+        s.set_reward_config(config.clone(), ic_cdk::api::time())?;
+        s.push_event(
+            caller,
+            PoolEventType::RewardConfigUpdated {
+                config: config.clone(),
+            },
+        );
+        Ok::<_, StabilityPoolError>(())
+    })?;
+    log!(INFO, "Reward config set to {:?} by {}", config, caller);
+    Ok(())
+}
+
+#[query]
+pub fn get_rewards_status() -> RewardsStatus {
+    read_state(|s| s.rewards_status())
+}
+
+/// Rewards `user` (default: the caller) could claim now.
+#[query]
+pub fn get_pending_rewards(user: Option<Principal>) -> u64 {
+    let user = user.unwrap_or_else(ic_cdk::api::caller);
+    read_state(|s| s.pending_rewards(&user, ic_cdk::api::time()))
+}
+
+/// Set or clear the DEX route auto-compounding sells `collateral_ledger`
+/// through (admin only).
+#[update]
+pub fn set_auto_compound_route(
+    collateral_ledger: Principal,
+    route: Option<AutoCompoundRoute>,
+) -> Result<(), StabilityPoolError> {
+    let caller = ic_cdk::api::caller();
+    if !read_state(|s| s.is_admin(&caller)) {
+        return Err(StabilityPoolError::Unauthorized);
+    }
+    mutate_state(|s| {
+        s.set_auto_compound_route(collateral_ledger, route.clone())?;
+        s.push_event(
+            caller,
+            PoolEventType::AutoCompoundRouteUpdated {
+                collateral_ledger,
+                route: route.clone(),
+            },
+        );
+        Ok::<_, StabilityPoolError>(())
+    })?;
+    log!(
+        INFO,
+        "Auto-compound route for {} set to {:?} by {}",
+        collateral_ledger,
+        route,
+        caller
+    );
+    Ok(())
+}
+
+/// Auto-compound routes and whether `user` (default: the caller) is opted in.
+#[query]
+pub fn get_auto_compound_status(user: Option<Principal>) -> AutoCompoundStatus {
+    let user = user.unwrap_or_else(ic_cdk::api::caller);
+    read_state(|s| s.auto_compound_status(&user))
+}
+
+/// Where `user`'s queued withdrawals stand, one entry per stablecoin.
+#[query]
+pub fn get_withdrawal_queue_position(user: Principal) -> Vec<WithdrawalQueuePosition> {
+    read_state(|s| s.withdrawal_queue_positions(&user))
+}
+
 /// Set the sole treasury destination for interest which cannot be credited to
 /// an opted-in icUSD depositor. Destination changes are rejected while any
 /// route is unsettled, so a persisted receipt can never be retargeted.
@@ -1364,7 +1630,7 @@ pub fn admin_correct_collateral_gain(
 mod tests {
     use super::*;
     use icrc_ledger_types::icrc1::account::Account;
-    use rumi_protocol_backend::chains::config::ChainId;
+    use rumi_common::chains::ChainId;
 
     fn principal(byte: u8) -> Principal {
         Principal::from_slice(&[byte])
@@ -1386,9 +1652,9 @@ mod tests {
             stables_consumed,
             burn_created_at_time_ns: 123,
             status: ChainSpAbsorbIntentStatus::Burned,
-            burn_proof: Some(rumi_protocol_backend::icrc3_proof::SpWritedownProof {
+            burn_proof: Some(rumi_common::icrc3_proof::SpWritedownProof {
                 block_index: 44,
-                ledger_kind: rumi_protocol_backend::icrc3_proof::SpProofLedger::IcusdBurn,
+                ledger_kind: rumi_common::icrc3_proof::SpProofLedger::IcusdBurn,
                 vault_id_memo: 77,
             }),
             backend_result: None,
@@ -5,10 +5,11 @@ use icrc_ledger_types::icrc1::account::Account;
 use icrc_ledger_types::icrc1::transfer::{Memo, TransferArg, TransferError};
 use icrc_ledger_types::icrc2::approve::{ApproveArgs, ApproveError};
 use num_traits::ToPrimitive;
-use rumi_protocol_backend::chains::config::ChainId;
+use rumi_common::chains::ChainId;
 use std::collections::BTreeMap;
 
 use crate::logs::INFO;
+use crate::safe_mode::call_backend;
 use crate::state::{mutate_state, read_state, StabilityPoolState};
 use crate::types::*;
 
@@ -48,10 +49,10 @@ pub fn build_icusd_burn_transfer_arg(
 pub fn build_icusd_burn_proof(
     block_index: u64,
     vault_id: u64,
-) -> rumi_protocol_backend::icrc3_proof::SpWritedownProof {
-    rumi_protocol_backend::icrc3_proof::SpWritedownProof {
+) -> rumi_common::icrc3_proof::SpWritedownProof {
+    rumi_common::icrc3_proof::SpWritedownProof {
         block_index,
-        ledger_kind: rumi_protocol_backend::icrc3_proof::SpProofLedger::IcusdBurn,
+        ledger_kind: rumi_common::icrc3_proof::SpProofLedger::IcusdBurn,
         vault_id_memo: vault_id,
     }
 }
@@ -77,7 +78,7 @@ pub async fn burn_icusd_for_chain_writedown_with_account(
     amount_e8s: u64,
     vault_id: u64,
     created_at_time: u64,
-) -> Result<rumi_protocol_backend::icrc3_proof::SpWritedownProof, StabilityPoolError> {
+) -> Result<rumi_common::icrc3_proof::SpWritedownProof, StabilityPoolError> {
     if amount_e8s == 0 {
         return Err(StabilityPoolError::AmountTooLow { minimum_e8s: 1 });
     }
@@ -112,7 +113,7 @@ pub async fn burn_icusd_for_chain_writedown(
     icusd_ledger: Principal,
     amount_e8s: u64,
     vault_id: u64,
-) -> Result<rumi_protocol_backend::icrc3_proof::SpWritedownProof, StabilityPoolError> {
+) -> Result<rumi_common::icrc3_proof::SpWritedownProof, StabilityPoolError> {
     let minting_account = fetch_icusd_minting_account(icusd_ledger).await?;
     burn_icusd_for_chain_writedown_with_account(
         icusd_ledger,
@@ -213,10 +214,7 @@ fn chain_absorb_plan_from_intent(intent: &ChainSpAbsorbIntent) -> ChainAbsorbPla
 
 fn burned_chain_absorb_replay_plan(
     intent: &ChainSpAbsorbIntent,
-) -> Option<(
-    ChainAbsorbPlan,
-    rumi_protocol_backend::icrc3_proof::SpWritedownProof,
-)> {
+) -> Option<(ChainAbsorbPlan, rumi_common::icrc3_proof::SpWritedownProof)> {
     intent
         .burn_proof
         .clone()
@@ -289,7 +287,7 @@ fn native_xrp_intent_matches_plan(
 
 fn native_xrp_request_from_intent(
     intent: &NativeXrpAbsorbIntent,
-    proof: rumi_protocol_backend::icrc3_proof::SpWritedownProof,
+    proof: rumi_common::icrc3_proof::SpWritedownProof,
 ) -> XrpSpAbsorbRequest {
     XrpSpAbsorbRequest {
         vault_id: intent.vault_id,
@@ -339,7 +337,7 @@ pub(crate) fn prepare_or_reuse_native_xrp_absorb_intent_in_state(
 pub(crate) fn mark_native_xrp_absorb_burned_in_state(
     state: &mut StabilityPoolState,
     vault_id: u64,
-    proof: rumi_protocol_backend::icrc3_proof::SpWritedownProof,
+    proof: rumi_common::icrc3_proof::SpWritedownProof,
     now_ns: u64,
 ) -> Result<NativeXrpAbsorbIntent, StabilityPoolError> {
     let mut intent = state
@@ -489,7 +487,7 @@ pub(crate) trait NativeXrpAbsorbIo {
         amount_e8s: u64,
         vault_id: u64,
         created_at_time: u64,
-    ) -> Result<rumi_protocol_backend::icrc3_proof::SpWritedownProof, StabilityPoolError>;
+    ) -> Result<rumi_common::icrc3_proof::SpWritedownProof, StabilityPoolError>;
 
     async fn submit_xrp_absorb(
         &mut self,
@@ -520,9 +518,9 @@ impl NativeXrpAbsorbIo for CdkNativeXrpAbsorbIo {
         expected_icusd_burn_e8s: u64,
     ) -> Result<XrpSpAbsorbPreflight, StabilityPoolError> {
         let preflight_result: Result<
-            (Result<XrpSpAbsorbPreflight, rumi_protocol_backend::ProtocolError>,),
+            (Result<XrpSpAbsorbPreflight, rumi_common::ProtocolError>,),
             _,
-        > = call(
+        > = call_backend(
             protocol_id,
             "stability_pool_preflight_xrp_absorb",
             (vault_id, expected_icusd_burn_e8s),
@@ -549,7 +547,7 @@ impl NativeXrpAbsorbIo for CdkNativeXrpAbsorbIo {
         amount_e8s: u64,
         vault_id: u64,
         created_at_time: u64,
-    ) -> Result<rumi_protocol_backend::icrc3_proof::SpWritedownProof, StabilityPoolError> {
+    ) -> Result<rumi_common::icrc3_proof::SpWritedownProof, StabilityPoolError> {
         burn_icusd_for_chain_writedown_with_account(
             icusd_ledger,
             minting_account,
@@ -566,15 +564,13 @@ impl NativeXrpAbsorbIo for CdkNativeXrpAbsorbIo {
         request: XrpSpAbsorbRequest,
     ) -> Result<XrpSpAbsorbResult, StabilityPoolError> {
         let vault_id = request.vault_id;
-        let backend_result: Result<
-            (Result<XrpSpAbsorbResult, rumi_protocol_backend::ProtocolError>,),
-            _,
-        > = call(
-            protocol_id,
-            "stability_pool_liquidate_xrp_vault",
-            (request,),
-        )
-        .await;
+        let backend_result: Result<(Result<XrpSpAbsorbResult, rumi_common::ProtocolError>,), _> =
+            call_backend(
+                protocol_id,
+                "stability_pool_liquidate_xrp_vault",
+                (request,),
+            )
+            .await;
 
         match backend_result {
             Ok((Ok(result),)) => Ok(result),
@@ -991,7 +987,7 @@ pub(crate) fn prepare_or_reuse_chain_absorb_intent_in_state(
 pub(crate) fn mark_chain_absorb_burned_in_state(
     state: &mut StabilityPoolState,
     vault_id: u64,
-    proof: rumi_protocol_backend::icrc3_proof::SpWritedownProof,
+    proof: rumi_common::icrc3_proof::SpWritedownProof,
     now_ns: u64,
 ) -> Result<ChainSpAbsorbIntent, StabilityPoolError> {
     let mut intent = state.get_pending_chain_absorb(vault_id).ok_or_else(|| {
@@ -1381,10 +1377,10 @@ pub(crate) fn recredit_failed_cfx_claim_payout_in_state_at(
     Ok(true)
 }
 
-pub(crate) fn is_duplicate_chain_claim_error(error: &rumi_protocol_backend::ProtocolError) -> bool {
+pub(crate) fn is_duplicate_chain_claim_error(error: &rumi_common::ProtocolError) -> bool {
     match error {
-        rumi_protocol_backend::ProtocolError::ChainAdmin(msg)
-        | rumi_protocol_backend::ProtocolError::GenericError(msg) => {
+        rumi_common::ProtocolError::ChainAdmin(msg)
+        | rumi_common::ProtocolError::GenericError(msg) => {
             msg.contains("Duplicate chain collateral claim payout idempotency key")
         }
         _ => false,
@@ -1404,6 +1400,23 @@ pub async fn notify_liquidatable_vaults(
         );
         return vec![];
     }
+    if let Some(mode) = read_state(|s| s.liquidations_restricted_by_mode()) {
+        log!(
+            INFO,
+            "Backend is in {:?} mode — ignoring {} liquidatable vaults",
+            mode,
+            vaults.len()
+        );
+        return vec![];
+    }
+    if read_state(|s| s.safe_mode.is_some()) {
+        log!(
+            INFO,
+            "Pool is in safe mode — ignoring {} liquidatable vaults",
+            vaults.len()
+        );
+        return vec![];
+    }
 
     // SP-102: hold the per-pool liquidation lock across the whole batch so
     // deposit/withdraw/claim cannot land between a vault's snapshot and its
@@ -1502,6 +1515,10 @@ pub async fn execute_liquidation(vault_id: u64) -> Result<LiquidationResult, Sta
     if read_state(|s| s.configuration.emergency_pause) {
         return Err(StabilityPoolError::EmergencyPaused);
     }
+    if let Some(mode) = read_state(|s| s.liquidations_restricted_by_mode()) {
+        return Err(StabilityPoolError::ProtocolModeRestricted { mode });
+    }
+    read_state(|s| s.ensure_not_in_safe_mode())?;
 
     if read_state(|s| s.in_flight_liquidations.contains(&vault_id)) {
         return Err(StabilityPoolError::SystemBusy);
@@ -1514,8 +1531,8 @@ pub async fn execute_liquidation(vault_id: u64) -> Result<LiquidationResult, Sta
     // Fetch vault info from backend
     let protocol_id = read_state(|s| s.protocol_canister_id);
 
-    let (vaults,): (Vec<rumi_protocol_backend::vault::CandidVault>,) =
-        call(protocol_id, "get_liquidatable_vaults", ())
+    let (vaults,): (Vec<rumi_common::types::CandidVault>,) =
+        call_backend(protocol_id, "get_liquidatable_vaults", ())
             .await
             .map_err(|_e| StabilityPoolError::InterCanisterCallFailed {
                 target: "Protocol".to_string(),
@@ -1560,6 +1577,133 @@ pub async fn execute_liquidation(vault_id: u64) -> Result<LiquidationResult, Sta
     Ok(result)
 }
 
+/// Smallest per-token draw the backend accepts for a partial liquidation
+/// (0.1 icUSD); smaller draws are skipped.
+const MIN_BACKEND_LIQUIDATION_E8S: u64 = 10_000_000;
+
+/// Dry run of `execute_liquidation(vault_id)`: fetch the backend's preview
+/// of the vault and price the pool's side of it. Nothing is executed.
+pub async fn simulate_liquidation(
+    vault_id: u64,
+) -> Result<LiquidationSimulation, StabilityPoolError> {
+    if ic_cdk::api::caller() == Principal::anonymous() {
+        return Err(StabilityPoolError::Unauthorized);
+    }
+    let protocol_id = read_state(|s| s.protocol_canister_id);
+    let (preview,): (Result<rumi_common::types::LiquidationPreview, rumi_common::ProtocolError>,) =
+        call_backend(protocol_id, "preview_liquidation", (vault_id, None::<u64>))
+            .await
+            .map_err(|_e| StabilityPoolError::InterCanisterCallFailed {
+                target: "Protocol".to_string(),
+                method: "preview_liquidation".to_string(),
+            })?;
+    let preview = preview.map_err(|e| StabilityPoolError::LiquidationFailed {
+        vault_id,
+        reason: format!("{:?}", e),
+    })?;
+    Ok(read_state(|s| simulate_with_preview(s, preview)))
+}
+
+/// Price a pool liquidation of `preview`'s vault against the pool's books.
+/// Mirrors `execute_single_liquidation`: the draw covers the backend's
+/// capped repay, draws under the backend minimum are skipped, ckUSDT/ckUSDC
+/// pay the repay-fee surcharge, and every non-LP ledger charges its fee on
+/// the approve and again on the transfer_from. A draw short of the repay
+/// earns a proportional share of the collateral.
+pub fn simulate_with_preview(
+    state: &StabilityPoolState,
+    preview: rumi_common::types::LiquidationPreview,
+) -> LiquidationSimulation {
+    let collateral_type = preview.collateral_type;
+    let mut blockers = Vec::new();
+    if state.configuration.emergency_pause {
+        blockers.push("The pool is paused".to_string());
+    }
+    if let Some(mode) = state.liquidations_restricted_by_mode() {
+        blockers.push(format!("Liquidations are restricted in {:?} mode", mode));
+    }
+    if state.safe_mode.is_some() {
+        blockers.push("The pool is in safe mode: the backend is unreachable".to_string());
+    }
+    if !preview.liquidatable {
+        blockers.push("The vault is not liquidatable".to_string());
+    }
+    let depth_before = state.effective_pool_for_collateral(&collateral_type);
+    if depth_before < preview.debt_e8s {
+        blockers.push("The opted-in pool balance does not cover the vault's debt".to_string());
+    }
+
+    let vps = state.virtual_prices();
+    let icusd_ledger = state.icusd_ledger();
+    let mut token_draw = BTreeMap::new();
+    let (mut draw_value, mut repay_fee, mut ledger_fees) = (0u64, 0u64, 0u64);
+    if preview.repay_e8s > 0 {
+        for (ledger, amount) in state.compute_token_draw(preview.repay_e8s, &collateral_type) {
+            let Some(config) = state.stablecoin_registry.get(&ledger) else {
+                continue;
+            };
+            if config.is_lp_token.unwrap_or(false) {
+                draw_value += vps
+                    .get(&ledger)
+                    .map(|&vp| lp_to_usd_e8s(amount, vp))
+                    .unwrap_or(0);
+                token_draw.insert(ledger, amount);
+                continue;
+            }
+            let value = normalize_to_e8s(amount, config.decimals);
+            if value < MIN_BACKEND_LIQUIDATION_E8S {
+                continue;
+            }
+            draw_value += value;
+            if Some(ledger) != icusd_ledger {
+                repay_fee += (value as f64 * preview.ckstable_repay_fee) as u64;
+            }
+            ledger_fees += normalize_to_e8s(
+                config.transfer_fee.unwrap_or(0).saturating_mul(2),
+                config.decimals,
+            );
+            token_draw.insert(ledger, amount);
+        }
+    }
+    if preview.liquidatable && token_draw.is_empty() {
+        blockers.push("No stablecoins available for liquidation".to_string());
+    }
+
+    let collateral_gained = if draw_value >= preview.repay_e8s {
+        preview.collateral_to_liquidator
+    } else {
+        (preview.collateral_to_liquidator as u128 * draw_value as u128 / preview.repay_e8s as u128)
+            as u64
+    };
+    let collateral_value = (collateral_gained as f64
+        / 10f64.powi(preview.collateral_decimals as i32)
+        * preview.collateral_price_usd
+        * 1e8) as u64;
+    let spent = draw_value + repay_fee + ledger_fees;
+    let net_gain = collateral_value as i64 - spent as i64;
+    let depositor_return_bps = if depth_before > 0 {
+        (net_gain as i128 * 10_000 / depth_before as i128) as i64
+    } else {
+        0
+    };
+
+    LiquidationSimulation {
+        token_draw,
+        draw_value_e8s: draw_value,
+        repay_fee_e8s: repay_fee,
+        ledger_fees_e8s: ledger_fees,
+        expected_collateral_gained: collateral_gained,
+        expected_collateral_value_e8s: collateral_value,
+        expected_net_gain_e8s: net_gain,
+        depositor_return_bps,
+        opted_in_depositors: state.opted_in_depositor_count(&collateral_type),
+        pool_depth_before_e8s: depth_before,
+        pool_depth_after_e8s: depth_before.saturating_sub(spent),
+        blockers,
+        preview,
+    }
+}
+
 pub async fn scan_chain_absorb_candidates(
     max_per_chain: Option<u64>,
 ) -> Result<Vec<ChainSpAbsorbCandidate>, StabilityPoolError> {
@@ -1587,7 +1731,7 @@ async fn discover_chain_absorb_candidates(
     let mut candidates = Vec::new();
     for chain in chains {
         let call_result: Result<(Vec<ChainLiquidatableVaultInfo>,), _> =
-            call(protocol_id, "get_chain_liquidatable_vaults", (chain,)).await;
+            call_backend(protocol_id, "get_chain_liquidatable_vaults", (chain,)).await;
         let (vaults,) = call_result.map_err(|_| StabilityPoolError::InterCanisterCallFailed {
             target: format!("{}", protocol_id),
             method: "get_chain_liquidatable_vaults".to_string(),
@@ -1629,12 +1773,12 @@ async fn submit_chain_absorb_to_backend(
     protocol_id: Principal,
     vault_id: u64,
     plan: &ChainAbsorbPlan,
-    proof: rumi_protocol_backend::icrc3_proof::SpWritedownProof,
+    proof: rumi_common::icrc3_proof::SpWritedownProof,
 ) -> Result<ChainStabilityPoolLiquidationResult, StabilityPoolError> {
     let backend_result: Result<
-        (Result<ChainStabilityPoolLiquidationResult, rumi_protocol_backend::ProtocolError>,),
+        (Result<ChainStabilityPoolLiquidationResult, rumi_common::ProtocolError>,),
         _,
-    > = call(
+    > = call_backend(
         protocol_id,
         "stability_pool_liquidate_chain_vault",
         (vault_id, plan.icusd_to_burn_e8s, proof),
@@ -1689,7 +1833,7 @@ async fn preflight_chain_absorb_with_backend(
     vault_id: u64,
     icusd_to_burn_e8s: u64,
 ) -> Result<(), StabilityPoolError> {
-    let preflight_result: Result<(Result<(), rumi_protocol_backend::ProtocolError>,), _> = call(
+    let preflight_result: Result<(Result<(), rumi_common::ProtocolError>,), _> = call_backend(
         protocol_id,
         "stability_pool_preflight_chain_absorb",
         (vault_id, icusd_to_burn_e8s),
@@ -1795,6 +1939,7 @@ async fn sp_absorb_chain_vault_core(
     if read_state(|s| s.configuration.emergency_pause) {
         return Err(StabilityPoolError::EmergencyPaused);
     }
+    read_state(|s| s.ensure_not_in_safe_mode())?;
 
     let (protocol_id, chains) = read_state(|s| {
         (
@@ -1812,7 +1957,7 @@ async fn sp_absorb_chain_vault_core(
     let mut candidate: Option<ChainLiquidatableVaultInfo> = None;
     for chain in chains {
         let call_result: Result<(Vec<ChainLiquidatableVaultInfo>,), _> =
-            call(protocol_id, "get_chain_liquidatable_vaults", (chain,)).await;
+            call_backend(protocol_id, "get_chain_liquidatable_vaults", (chain,)).await;
         let (vaults,) = call_result.map_err(|_| StabilityPoolError::InterCanisterCallFailed {
             target: format!("{}", protocol_id),
             method: "get_chain_liquidatable_vaults".to_string(),
@@ -1918,6 +2063,20 @@ pub async fn run_chain_absorb_auto_tick(
         mutate_state(|s| s.record_chain_absorb_auto_tick(tick.clone()));
         return Ok(Some(tick));
     }
+    // Safe mode still lets an in-flight absorb finish.
+    if read_state(|s| s.safe_mode.is_some() && s.pending_chain_absorbs().is_empty()) {
+        let tick = ChainAbsorbAutoTickRecord {
+            started_at_ns,
+            completed_at_ns: ic_cdk::api::time(),
+            attempted_vault_id: None,
+            candidates_scanned: 0,
+            absorbed: None,
+            error: None,
+            skipped_reason: Some("backend unreachable".to_string()),
+        };
+        mutate_state(|s| s.record_chain_absorb_auto_tick(tick.clone()));
+        return Ok(Some(tick));
+    }
 
     let pending_vault = read_state(|s| {
         s.pending_chain_absorbs()
@@ -2010,7 +2169,7 @@ pub async fn claim_cfx(
             caller,
             chain_sentinel,
             dest_evm,
-            rumi_protocol_backend::chains::evm::tecdsa::is_valid_evm_address,
+            rumi_common::chains::is_valid_evm_address,
         )
     })?;
     let Some(plan) = plan else {
@@ -2018,7 +2177,7 @@ pub async fn claim_cfx(
     };
 
     let protocol_id = read_state(|s| s.protocol_canister_id);
-    let backend_result: Result<(Result<u64, rumi_protocol_backend::ProtocolError>,), _> = call(
+    let backend_result: Result<(Result<u64, rumi_common::ProtocolError>,), _> = call_backend(
         protocol_id,
         "claim_chain_collateral",
         (
@@ -2127,7 +2286,7 @@ async fn execute_single_liquidation(vault_info: &LiquidatableVaultInfo) -> Liqui
         } else {
             crate::types::normalize_to_e8s(*amount, token_decimals)
         };
-        if amount_e8s_check < 10_000_000 {
+        if amount_e8s_check < MIN_BACKEND_LIQUIDATION_E8S {
             log!(
                 INFO,
                 "Skipping token {}: amount {} e8s below backend minimum (0.1)",
@@ -2193,17 +2352,12 @@ async fn execute_single_liquidation(vault_info: &LiquidatableVaultInfo) -> Liqui
         // Call the appropriate backend endpoint
         let liq_result = if is_icusd {
             let call_result: Result<
-                (
-                    Result<
-                        rumi_protocol_backend::SuccessWithFee,
-                        rumi_protocol_backend::ProtocolError,
-                    >,
-                ),
+                (Result<rumi_common::types::SuccessWithFee, rumi_common::ProtocolError>,),
                 _,
-            > = call(
+            > = call_backend(
                 protocol_id,
                 "liquidate_vault_partial",
-                (rumi_protocol_backend::vault::VaultArg {
+                (rumi_common::types::VaultArg {
                     vault_id: vault_info.vault_id,
                     amount: *amount,
                 },),
@@ -2216,17 +2370,12 @@ async fn execute_single_liquidation(vault_info: &LiquidatableVaultInfo) -> Liqui
                 Some(tt) => {
                     let amount_e8s = crate::types::normalize_to_e8s(*amount, token_decimals);
                     let call_result: Result<
-                        (
-                            Result<
-                                rumi_protocol_backend::SuccessWithFee,
-                                rumi_protocol_backend::ProtocolError,
-                            >,
-                        ),
+                        (Result<rumi_common::types::SuccessWithFee, rumi_common::ProtocolError>,),
                         _,
-                    > = call(
+                    > = call_backend(
                         protocol_id,
                         "liquidate_vault_partial_with_stable",
-                        (rumi_protocol_backend::VaultArgWithToken {
+                        (rumi_common::types::VaultArgWithToken {
                             vault_id: vault_info.vault_id,
                             amount: amount_e8s,
                             token_type: tt,
@@ -2402,9 +2551,9 @@ async fn execute_single_liquidation(vault_info: &LiquidatableVaultInfo) -> Liqui
         // audit 2026-04-22-28e9896).
 
         let liq_result: Result<
-            (Result<StabilityPoolLiquidationResult, rumi_protocol_backend::ProtocolError>,),
+            (Result<StabilityPoolLiquidationResult, rumi_common::ProtocolError>,),
             _,
-        > = call(
+        > = call_backend(
             protocol_id,
             "stability_pool_liquidate_with_reserves",
             (vault_info.vault_id, icusd_equiv_e8s, *amount, *token_ledger),
@@ -2539,11 +2688,11 @@ async fn execute_single_liquidation(vault_info: &LiquidatableVaultInfo) -> Liqui
 fn determine_stable_token_type(
     ledger: Principal,
     configs: &BTreeMap<Principal, StablecoinConfig>,
-) -> Option<rumi_protocol_backend::StableTokenType> {
+) -> Option<rumi_common::types::StableTokenType> {
     let config = configs.get(&ledger)?;
     match config.symbol.as_str() {
-        "ckUSDT" => Some(rumi_protocol_backend::StableTokenType::CKUSDT),
-        "ckUSDC" => Some(rumi_protocol_backend::StableTokenType::CKUSDC),
+        "ckUSDT" => Some(rumi_common::types::StableTokenType::CKUSDT),
+        "ckUSDC" => Some(rumi_common::types::StableTokenType::CKUSDC),
         _ => None,
     }
 }
@@ -2581,7 +2730,7 @@ mod tests {
     }
 
     fn xrp_ledger() -> Principal {
-        rumi_protocol_backend::state::xrp_collateral_principal()
+        rumi_common::chains::xrp_collateral_principal()
     }
 
     fn valid_xrp_address() -> String {
@@ -2633,12 +2782,14 @@ mod tests {
         token: Principal,
         amount: u64,
     ) {
+        state.settle_position(&user);
         let position = state
             .deposits
             .entry(user)
             .or_insert_with(|| DepositPosition::new(0));
         *position.stablecoin_balances.entry(token).or_insert(0) += amount;
         *state.total_stablecoin_balances.entry(token).or_insert(0) += amount;
+        state.attach_position(&user);
     }
 
     fn chain_vault(debt_e8s: u128, sp_attempted: bool) -> ChainLiquidatableVaultInfo {
@@ -2652,7 +2803,7 @@ mod tests {
     ) -> ChainLiquidatableVaultInfo {
         ChainLiquidatableVaultInfo {
             vault_id,
-            chain_id: rumi_protocol_backend::chains::config::ChainId(1030),
+            chain_id: rumi_common::chains::ChainId(1030),
             chain_collateral_sentinel: chain_collateral_sentinel(1030),
             sp_attempted,
             debt_e8s,
@@ -2675,7 +2826,7 @@ mod tests {
         ChainStabilityPoolLiquidationResult {
             success: true,
             vault_id: 77,
-            chain_id: rumi_protocol_backend::chains::config::ChainId(1030),
+            chain_id: rumi_common::chains::ChainId(1030),
             liquidated_debt_e8s: 100_00000000,
             collateral_received_native: 10_000_000_000_000_000_000u128,
             claim_id: 77,
@@ -2690,7 +2841,7 @@ mod tests {
         preflight: Option<XrpSpAbsorbPreflight>,
         submit_result: Option<XrpSpAbsorbResult>,
         minting_account: Option<Account>,
-        burn_proof: Option<rumi_protocol_backend::icrc3_proof::SpWritedownProof>,
+        burn_proof: Option<rumi_common::icrc3_proof::SpWritedownProof>,
         events: Vec<String>,
         submitted_requests: Vec<XrpSpAbsorbRequest>,
     }
@@ -2732,8 +2883,7 @@ mod tests {
             amount_e8s: u64,
             vault_id: u64,
             created_at_time: u64,
-        ) -> Result<rumi_protocol_backend::icrc3_proof::SpWritedownProof, StabilityPoolError>
-        {
+        ) -> Result<rumi_common::icrc3_proof::SpWritedownProof, StabilityPoolError> {
             self.events
                 .push(format!("burn:{vault_id}:{amount_e8s}:{created_at_time}"));
             Ok(self
@@ -2896,24 +3046,21 @@ mod tests {
         );
         assert_eq!(
             read_state(|s| s
-                .deposits
-                .get(&user_a())
+                .position(&user_a())
                 .and_then(|pos| pos.stablecoin_balances.get(&icusd_ledger()).copied())),
             Some(40_00000000),
             "opted-in depositor burns their pro-rata icUSD",
         );
         assert_eq!(
             read_state(|s| s
-                .deposits
-                .get(&user_b())
+                .position(&user_b())
                 .and_then(|pos| pos.stablecoin_balances.get(&icusd_ledger()).copied())),
             Some(50_00000000),
             "non-opted-in depositor must not burn for native XRP",
         );
         assert!(
             read_state(|s| s
-                .deposits
-                .get(&user_a())
+                .position(&user_a())
                 .and_then(|pos| pos.collateral_gains.get(&xrp_ledger()).copied())
                 .unwrap_or(0))
                 == 0,
@@ -2975,8 +3122,7 @@ mod tests {
         assert!(read_state(|s| s.native_xrp_pending_payouts_for(&user_a())).is_empty());
         assert_eq!(
             read_state(|s| s
-                .deposits
-                .get(&user_a())
+                .position(&user_a())
                 .and_then(|pos| pos.stablecoin_balances.get(&icusd_ledger()).copied())),
             Some(100_00000000),
             "local balances are not deducted until backend result is accepted",
@@ -3010,8 +3156,7 @@ mod tests {
         assert_eq!(payouts[0].drops, 12_000_000);
         assert_eq!(
             read_state(|s| s
-                .deposits
-                .get(&user_a())
+                .position(&user_a())
                 .and_then(|pos| pos.stablecoin_balances.get(&icusd_ledger()).copied())),
             Some(40_00000000),
         );
@@ -3273,8 +3418,7 @@ mod tests {
         assert_eq!(io.events, vec!["preflight:145:1000000000"]);
         assert_eq!(
             read_state(|s| s
-                .deposits
-                .get(&user_a())
+                .position(&user_a())
                 .and_then(|pos| pos.stablecoin_balances.get(&icusd_ledger()).copied())),
             Some(10_00000000),
             "empty allocation rejection must not burn or mutate pool balances",
@@ -3324,7 +3468,7 @@ mod tests {
         assert_eq!(&memo[..13], b"RUMI-LIQ-004:");
         assert_eq!(&memo[13..], &vault_id.to_be_bytes());
         assert_eq!(
-            rumi_protocol_backend::icrc3_proof::decode_writedown_memo(&memo),
+            rumi_common::icrc3_proof::decode_writedown_memo(&memo),
             Ok(vault_id),
             "SP burn memo must be accepted by backend proof verifier",
         );
@@ -3361,7 +3505,7 @@ mod tests {
         assert_eq!(proof.block_index, block_index);
         assert_eq!(
             proof.ledger_kind,
-            rumi_protocol_backend::icrc3_proof::SpProofLedger::IcusdBurn
+            rumi_common::icrc3_proof::SpProofLedger::IcusdBurn
         );
         assert_eq!(proof.vault_id_memo, vault_id);
     }
@@ -3375,7 +3519,7 @@ mod tests {
 
         assert_eq!(
             registered_chain_ids_from_sentinels(&state),
-            vec![rumi_protocol_backend::chains::config::ChainId(1030)],
+            vec![rumi_common::chains::ChainId(1030)],
         );
     }
 
@@ -3888,7 +4032,7 @@ mod tests {
             user_a(),
             sentinel,
             "0x000000000000000000000000000000000000c0de".to_string(),
-            rumi_protocol_backend::chains::evm::tecdsa::is_valid_evm_address,
+            rumi_common::chains::is_valid_evm_address,
         )
         .expect("claim plan")
         .expect("nonzero claim");
@@ -3939,7 +4083,7 @@ mod tests {
             user_a(),
             sentinel,
             "0x000000000000000000000000000000000000c0de".to_string(),
-            rumi_protocol_backend::chains::evm::tecdsa::is_valid_evm_address,
+            rumi_common::chains::is_valid_evm_address,
         )
         .expect("claim plan")
         .expect("nonzero claim");
@@ -4014,7 +4158,7 @@ mod tests {
             user_a(),
             sentinel,
             "0x000000000000000000000000000000000000c0de".to_string(),
-            rumi_protocol_backend::chains::evm::tecdsa::is_valid_evm_address,
+            rumi_common::chains::is_valid_evm_address,
         )
         .expect("claim plan")
         .expect("nonzero claim");
@@ -4122,7 +4266,7 @@ mod tests {
             user_a(),
             sentinel,
             "0x000000000000000000000000000000000000c0de".to_string(),
-            rumi_protocol_backend::chains::evm::tecdsa::is_valid_evm_address,
+            rumi_common::chains::is_valid_evm_address,
         )
         .expect("claim plan")
         .expect("nonzero claim");
@@ -4186,7 +4330,7 @@ mod tests {
             user_a(),
             sentinel,
             "0x000000000000000000000000000000000000c0de".to_string(),
-            rumi_protocol_backend::chains::evm::tecdsa::is_valid_evm_address,
+            rumi_common::chains::is_valid_evm_address,
         )
         .expect("first claim plan")
         .expect("nonzero first claim");
@@ -4209,7 +4353,7 @@ mod tests {
             user_a(),
             sentinel,
             "0x000000000000000000000000000000000000c0de".to_string(),
-            rumi_protocol_backend::chains::evm::tecdsa::is_valid_evm_address,
+            rumi_common::chains::is_valid_evm_address,
         )
         .expect("second claim plan")
         .expect("nonzero second claim");
@@ -4304,15 +4448,103 @@ mod tests {
 
     #[test]
     fn duplicate_chain_claim_error_is_not_rolled_back() {
-        let duplicate = rumi_protocol_backend::ProtocolError::ChainAdmin(
+        let duplicate = rumi_common::ProtocolError::ChainAdmin(
             "Duplicate chain collateral claim payout idempotency key chain-collateral-claim-77"
                 .to_string(),
         );
-        let ordinary = rumi_protocol_backend::ProtocolError::ChainAdmin(
+        let ordinary = rumi_common::ProtocolError::ChainAdmin(
             "chain collateral claim: unknown claim 77".to_string(),
         );
 
         assert!(is_duplicate_chain_claim_error(&duplicate));
         assert!(!is_duplicate_chain_claim_error(&ordinary));
     }
+
+    fn icp_preview(liquidatable: bool) -> rumi_common::types::LiquidationPreview {
+        // 100 icUSD repay at $10/ICP with a 10% bonus: 11 ICP, 0.1 ICP to the protocol.
+        rumi_common::types::LiquidationPreview {
+            vault_id: 5,
+            collateral_type: principal(20),
+            debt_e8s: 150 * 100_000_000,
+            collateral_amount: 20 * 100_000_000,
+            collateral_decimals: 8,
+            collateral_price_usd: 10.0,
+            collateral_ratio: 1.3,
+            min_liquidation_ratio: 1.33,
+            liquidatable,
+            max_liquidatable_debt_e8s: 100 * 100_000_000,
+            repay_e8s: if liquidatable { 100 * 100_000_000 } else { 0 },
+            liquidation_bonus: 1.1,
+            collateral_seized: if liquidatable { 1_100_000_000 } else { 0 },
+            protocol_cut: if liquidatable { 10_000_000 } else { 0 },
+            collateral_to_liquidator: if liquidatable { 1_090_000_000 } else { 0 },
+            min_liquidation_amount_e8s: 10_000_000,
+            ckstable_repay_fee: 0.005,
+            seizure_cap: None,
+            uncapped_repay_e8s: None,
+        }
+    }
+
+    #[test]
+    fn simulation_prices_an_icusd_draw() {
+        let mut state = test_state();
+        add_deposit_direct(&mut state, user_a(), icusd_ledger(), 200 * 100_000_000);
+
+        let sim = simulate_with_preview(&state, icp_preview(true));
+        assert!(sim.blockers.is_empty(), "{:?}", sim.blockers);
+        assert_eq!(sim.token_draw[&icusd_ledger()], 100 * 100_000_000);
+        assert_eq!(sim.draw_value_e8s, 100 * 100_000_000);
+        assert_eq!(sim.repay_fee_e8s, 0);
+        assert_eq!(sim.ledger_fees_e8s, 200_000);
+        assert_eq!(sim.expected_collateral_gained, 1_090_000_000);
+        assert_eq!(sim.expected_collateral_value_e8s, 109 * 100_000_000);
+        assert_eq!(sim.expected_net_gain_e8s, 899_800_000);
+        assert_eq!(sim.depositor_return_bps, 449);
+        assert_eq!(sim.opted_in_depositors, 1);
+        assert_eq!(sim.pool_depth_before_e8s, 200 * 100_000_000);
+        assert_eq!(sim.pool_depth_after_e8s, 9_999_800_000);
+    }
+
+    #[test]
+    fn simulation_charges_the_ckstable_surcharge_and_scales_a_short_draw() {
+        let mut state = test_state();
+        // 160 ckUSDC covers the 150 icUSD debt check; the 100 icUSD repay is
+        // drawn from it in full.
+        add_deposit_direct(&mut state, user_a(), ckusdc_ledger(), 160_000_000);
+        let sim = simulate_with_preview(&state, icp_preview(true));
+        assert!(sim.blockers.is_empty(), "{:?}", sim.blockers);
+        assert_eq!(sim.token_draw[&ckusdc_ledger()], 100_000_000);
+        assert_eq!(sim.repay_fee_e8s, 50_000_000);
+        assert_eq!(sim.ledger_fees_e8s, 2_000);
+        assert_eq!(sim.expected_collateral_gained, 1_090_000_000);
+
+        // A shallow pool is blocked by the debt check and earns pro rata.
+        let mut state = test_state();
+        add_deposit_direct(&mut state, user_a(), ckusdc_ledger(), 50_000_000);
+        let sim = simulate_with_preview(&state, icp_preview(true));
+        assert_eq!(sim.draw_value_e8s, 50 * 100_000_000);
+        assert_eq!(sim.expected_collateral_gained, 545_000_000);
+        assert!(sim.blockers.iter().any(|b| b.contains("does not cover")));
+        assert_eq!(sim.pool_depth_after_e8s, 0);
+    }
+
+    #[test]
+    fn simulation_reports_blockers() {
+        let mut state = test_state();
+        add_deposit_direct(&mut state, user_a(), icusd_ledger(), 200 * 100_000_000);
+
+        let healthy = simulate_with_preview(&state, icp_preview(false));
+        assert!(healthy.token_draw.is_empty());
+        assert_eq!(healthy.expected_net_gain_e8s, 0);
+        assert_eq!(
+            healthy.blockers,
+            vec!["The vault is not liquidatable".to_string()]
+        );
+
+        state.configuration.emergency_pause = true;
+        let paused = simulate_with_preview(&state, icp_preview(true));
+        assert_eq!(paused.blockers, vec!["The pool is paused".to_string()]);
+        // Paused or not, the books are priced the same way.
+        assert_eq!(paused.expected_net_gain_e8s, 899_800_000);
+    }
 }
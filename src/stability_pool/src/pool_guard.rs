@@ -27,6 +27,7 @@ thread_local! {
     static BALANCE_ASYNC_IN_FLIGHT: RefCell<u32> = const { RefCell::new(0) };
     static CHAIN_ABSORB_AUTO_TICK_ACTIVE: RefCell<bool> = const { RefCell::new(false) };
     static UNALLOCATED_INTEREST_FORWARD_ACTIVE: RefCell<bool> = const { RefCell::new(false) };
+    static AUTO_COMPOUND_TICK_ACTIVE: RefCell<bool> = const { RefCell::new(false) };
 }
 
 #[must_use]
@@ -147,6 +148,29 @@ impl Drop for UnallocatedInterestForwardGuard {
     }
 }
 
+/// Serialize auto-compound ticks, which hold taken gains across DEX awaits.
+#[must_use]
+pub struct AutoCompoundTickGuard;
+
+impl AutoCompoundTickGuard {
+    pub fn new() -> Result<Self, StabilityPoolError> {
+        AUTO_COMPOUND_TICK_ACTIVE.with(|f| {
+            let mut held = f.borrow_mut();
+            if *held {
+                return Err(StabilityPoolError::SystemBusy);
+            }
+            *held = true;
+            Ok(Self)
+        })
+    }
+}
+
+impl Drop for AutoCompoundTickGuard {
+    fn drop(&mut self) {
+        AUTO_COMPOUND_TICK_ACTIVE.with(|f| *f.borrow_mut() = false);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,467 @@
+//! Product-sum accounting of liquidation losses and gains, after Liquity's
+//! stability pool.
+//!
+//! Depositors with the same opt-in choices form a [`LossCohort`]: every
+//! liquidation they absorb takes the same fraction of each member's deposit
+//! of the drawn token and pays them gains in proportion. Per stablecoin, the
+//! cohort keeps a running product `P` of those fractions and, per gain
+//! ledger, a running sum `S` of the gain paid per unit of deposit. A
+//! member's stored balance and the `P` and `S` it joined at are enough to
+//! compound the deposit and its gains on demand, so a liquidation touches
+//! each cohort once rather than every depositor.
+//!
+//! `P` only shrinks. When a loss would take it below `SCALE_FACTOR` it is
+//! multiplied back up and `scale` advances; when a loss empties the token,
+//! `epoch` advances and `P` restarts. Deposits are floored at every step, so
+//! members together hold slightly less than the cohort total; a cohort's
+//! sole remaining member is owed what is left.
+
+use candid::{CandidType, Principal};
+use ethnum::U256;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::types::DepositPosition;
+
+/// `P` at the start of every epoch.
+pub const PRODUCT_PRECISION: u128 = 1_000_000_000_000_000_000;
+/// `P` is multiplied by this, and `scale` advances, when it falls below it.
+pub const SCALE_FACTOR: u128 = 1_000_000_000;
+
+/// `a * b / c`, rounded down, without overflowing the product.
+pub fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    match a.checked_mul(b) {
+        Some(product) => product / c,
+        None => (U256::from(a) * U256::from(b) / U256::from(c)).as_u128(),
+    }
+}
+
+/// One stablecoin's deposits within a cohort.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProductSum {
+    pub product: u128,
+    pub scale: u64,
+    pub epoch: u64,
+    /// The members' deposits of the token, compounded, plus rounding dust.
+    pub total: u64,
+    /// Per gain ledger, the running sum for each `(epoch, scale)`.
+    pub sums: BTreeMap<Principal, BTreeMap<(u64, u64), u128>>,
+    /// Gains credited here and not yet settled into a position.
+    pub unsettled_gains: BTreeMap<Principal, u64>,
+}
+
+impl Default for ProductSum {
+    fn default() -> Self {
+        Self {
+            product: PRODUCT_PRECISION,
+            scale: 0,
+            epoch: 0,
+            total: 0,
+            sums: BTreeMap::new(),
+            unsettled_gains: BTreeMap::new(),
+        }
+    }
+}
+
+/// `P` and `S` as a member joined a [`ProductSum`].
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenSnapshot {
+    pub product: u128,
+    pub scale: u64,
+    pub epoch: u64,
+    pub sums: BTreeMap<Principal, u128>,
+}
+
+impl ProductSum {
+    /// Pay `gains` to the current deposits, then take `loss` out of them.
+    /// A loss beyond the total is capped at it.
+    pub fn absorb(&mut self, loss: u64, gains: &[(Principal, u64)]) {
+        if self.total == 0 {
+            return;
+        }
+        for &(ledger, amount) in gains {
+            if amount == 0 {
+                continue;
+            }
+            let sum = self
+                .sums
+                .entry(ledger)
+                .or_default()
+                .entry((self.epoch, self.scale))
+                .or_insert(0);
+            *sum = sum.saturating_add(mul_div(amount as u128, self.product, self.total as u128));
+            let unsettled = self.unsettled_gains.entry(ledger).or_insert(0);
+            *unsettled = unsettled.saturating_add(amount);
+        }
+
+        let remaining = self.total - loss.min(self.total);
+        if remaining == self.total {
+            return;
+        }
+        if remaining == 0 {
+            self.epoch += 1;
+            self.scale = 0;
+            self.product = PRODUCT_PRECISION;
+        } else {
+            let total = U256::from(self.total);
+            let mut scaled = U256::from(self.product) * U256::from(remaining);
+            while scaled / total < U256::from(SCALE_FACTOR) {
+                scaled *= U256::from(SCALE_FACTOR);
+                self.scale += 1;
+            }
+            self.product = (scaled / total).as_u128();
+        }
+        self.total = remaining;
+    }
+
+    fn snapshot(&self) -> TokenSnapshot {
+        TokenSnapshot {
+            product: self.product,
+            scale: self.scale,
+            epoch: self.epoch,
+            sums: self
+                .sums
+                .iter()
+                .filter_map(|(ledger, sums)| {
+                    sums.get(&(self.epoch, self.scale))
+                        .map(|sum| (*ledger, *sum))
+                })
+                .collect(),
+        }
+    }
+
+    /// A deposit of `deposit` made at `snapshot`, after the losses since.
+    pub fn compounded(&self, deposit: u64, snapshot: &TokenSnapshot) -> u64 {
+        if snapshot.epoch < self.epoch {
+            return 0;
+        }
+        let compounded = match self.scale.saturating_sub(snapshot.scale) {
+            0 => mul_div(deposit as u128, self.product, snapshot.product),
+            1 => mul_div(
+                deposit as u128,
+                self.product,
+                snapshot.product * SCALE_FACTOR,
+            ),
+            _ => 0,
+        };
+        compounded.min(deposit as u128) as u64
+    }
+
+    /// The `ledger` gains earned since `snapshot` by a deposit of `deposit`.
+    pub fn gain(&self, deposit: u64, snapshot: &TokenSnapshot, ledger: &Principal) -> u64 {
+        let Some(sums) = self.sums.get(ledger) else {
+            return 0;
+        };
+        let at_snapshot = sums
+            .get(&(snapshot.epoch, snapshot.scale))
+            .copied()
+            .unwrap_or(0)
+            .saturating_sub(snapshot.sums.get(ledger).copied().unwrap_or(0));
+        // Gains after the next rescale were summed against a `P` one
+        // `SCALE_FACTOR` larger. Later ones are negligible.
+        let next_scale = sums
+            .get(&(snapshot.epoch, snapshot.scale + 1))
+            .copied()
+            .unwrap_or(0);
+        let per_unit = U256::from(at_snapshot) * U256::from(SCALE_FACTOR) + U256::from(next_scale);
+        let gain = U256::from(deposit) * per_unit
+            / (U256::from(snapshot.product) * U256::from(SCALE_FACTOR));
+        gain.min(U256::from(u64::MAX)).as_u64()
+    }
+}
+
+/// Where a member stands in its cohort.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositSnapshot {
+    pub cohort: u64,
+    pub tokens: BTreeMap<Principal, TokenSnapshot>,
+}
+
+/// Depositors with the same opt-in choices, who absorb the same liquidations.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LossCohort {
+    pub opted_out_collateral: BTreeSet<Principal>,
+    pub opted_in_chain_collateral: BTreeSet<Principal>,
+    /// Native collateral the members have a payout address for.
+    pub native_payout_collateral: BTreeSet<Principal>,
+    pub members: u64,
+    pub tokens: BTreeMap<Principal, ProductSum>,
+    /// When gains were last paid to the cohort, per collateral ledger.
+    pub gains_credited_at: BTreeMap<Principal, u64>,
+}
+
+/// A member's deposits and unsettled gains, compounded through its cohort.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Compounded {
+    pub balances: BTreeMap<Principal, u64>,
+    pub gains: BTreeMap<Principal, u64>,
+}
+
+impl LossCohort {
+    pub fn for_position(position: &DepositPosition) -> Self {
+        Self {
+            opted_out_collateral: position.opted_out_collateral.clone(),
+            opted_in_chain_collateral: opted_in_chain_collateral(position),
+            native_payout_collateral: native_payout_collateral(position),
+            members: 0,
+            tokens: BTreeMap::new(),
+            gains_credited_at: BTreeMap::new(),
+        }
+    }
+
+    pub fn matches(&self, position: &DepositPosition) -> bool {
+        self.opted_out_collateral == position.opted_out_collateral
+            && self.opted_in_chain_collateral == opted_in_chain_collateral(position)
+            && self.native_payout_collateral == native_payout_collateral(position)
+    }
+
+    /// The cohort's deposits of `token_ledger`.
+    pub fn total(&self, token_ledger: &Principal) -> u64 {
+        self.tokens
+            .get(token_ledger)
+            .map_or(0, |tokens| tokens.total)
+    }
+
+    /// Add `balances` to the cohort as cohort `id`'s newest member.
+    pub fn join(&mut self, id: u64, balances: &BTreeMap<Principal, u64>) -> DepositSnapshot {
+        self.members += 1;
+        let tokens = balances
+            .iter()
+            .filter(|(_, &amount)| amount > 0)
+            .map(|(ledger, &amount)| {
+                let tokens = self.tokens.entry(*ledger).or_default();
+                tokens.total += amount;
+                (*ledger, tokens.snapshot())
+            })
+            .collect();
+        DepositSnapshot { cohort: id, tokens }
+    }
+
+    /// `balances`, stored at `snapshot`, compounded to now. A sole member
+    /// is owed everything left in the cohort, rounding dust included.
+    pub fn compound(
+        &self,
+        balances: &BTreeMap<Principal, u64>,
+        snapshot: &DepositSnapshot,
+    ) -> Compounded {
+        if self.members == 1 {
+            return self.remainder();
+        }
+        let mut compounded = Compounded::default();
+        for (ledger, token_snapshot) in &snapshot.tokens {
+            let Some(tokens) = self.tokens.get(ledger) else {
+                continue;
+            };
+            let deposit = balances.get(ledger).copied().unwrap_or(0);
+            let balance = tokens.compounded(deposit, token_snapshot);
+            if balance > 0 {
+                compounded.balances.insert(*ledger, balance);
+            }
+            for gain_ledger in tokens.sums.keys() {
+                let gain = tokens.gain(deposit, token_snapshot, gain_ledger).min(
+                    tokens
+                        .unsettled_gains
+                        .get(gain_ledger)
+                        .copied()
+                        .unwrap_or(0),
+                );
+                if gain > 0 {
+                    *compounded.gains.entry(*gain_ledger).or_insert(0) += gain;
+                }
+            }
+        }
+        compounded
+    }
+
+    /// Take a member out of the cohort, returning its compounded balances and
+    /// gains. The last member also takes the rounding dust.
+    pub fn leave(
+        &mut self,
+        balances: &BTreeMap<Principal, u64>,
+        snapshot: &DepositSnapshot,
+    ) -> Compounded {
+        let compounded = self.compound(balances, snapshot);
+        self.members = self.members.saturating_sub(1);
+        if self.members == 0 {
+            self.tokens.clear();
+            return compounded;
+        }
+
+        for (ledger, token_snapshot) in &snapshot.tokens {
+            let Some(tokens) = self.tokens.get_mut(ledger) else {
+                continue;
+            };
+            let deposit = balances.get(ledger).copied().unwrap_or(0);
+            tokens.total = tokens
+                .total
+                .saturating_sub(tokens.compounded(deposit, token_snapshot));
+            let gains: Vec<(Principal, u64)> = tokens
+                .sums
+                .keys()
+                .map(|gain_ledger| {
+                    (
+                        *gain_ledger,
+                        tokens.gain(deposit, token_snapshot, gain_ledger),
+                    )
+                })
+                .collect();
+            for (gain_ledger, gain) in gains {
+                if let Some(unsettled) = tokens.unsettled_gains.get_mut(&gain_ledger) {
+                    *unsettled = unsettled.saturating_sub(gain);
+                }
+            }
+        }
+        compounded
+    }
+
+    /// Everything still in the cohort's deposits and unsettled gains.
+    fn remainder(&self) -> Compounded {
+        let mut rest = Compounded::default();
+        for (ledger, tokens) in &self.tokens {
+            if tokens.total > 0 {
+                *rest.balances.entry(*ledger).or_insert(0) += tokens.total;
+            }
+            for (gain_ledger, &gain) in &tokens.unsettled_gains {
+                if gain > 0 {
+                    *rest.gains.entry(*gain_ledger).or_insert(0) += gain;
+                }
+            }
+        }
+        rest
+    }
+}
+
+fn opted_in_chain_collateral(position: &DepositPosition) -> BTreeSet<Principal> {
+    position
+        .opted_in_chain_collateral
+        .clone()
+        .unwrap_or_default()
+}
+
+fn native_payout_collateral(position: &DepositPosition) -> BTreeSet<Principal> {
+    position
+        .native_payout_addresses
+        .as_ref()
+        .map(|addresses| addresses.keys().copied().collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn icusd() -> Principal {
+        Principal::from_slice(&[1])
+    }
+
+    fn icp() -> Principal {
+        Principal::from_slice(&[2])
+    }
+
+    fn balances(amount: u64) -> BTreeMap<Principal, u64> {
+        BTreeMap::from([(icusd(), amount)])
+    }
+
+    fn cohort() -> LossCohort {
+        LossCohort::for_position(&DepositPosition::new(0))
+    }
+
+    #[test]
+    fn losses_and_gains_split_pro_rata() {
+        let mut cohort = cohort();
+        let a = cohort.join(0, &balances(60_00000000));
+        let b = cohort.join(0, &balances(40_00000000));
+
+        let tokens = cohort.tokens.get_mut(&icusd()).unwrap();
+        tokens.absorb(10_00000000, &[(icp(), 2_00000000)]);
+        tokens.absorb(45_00000000, &[(icp(), 9_00000000)]);
+
+        let a_now = cohort.compound(&balances(60_00000000), &a);
+        let b_now = cohort.compound(&balances(40_00000000), &b);
+        assert_eq!(a_now.balances[&icusd()], 27_00000000);
+        assert_eq!(b_now.balances[&icusd()], 18_00000000);
+        assert_eq!(a_now.gains[&icp()], 6_60000000);
+        assert_eq!(b_now.gains[&icp()], 4_40000000);
+    }
+
+    #[test]
+    fn a_later_member_does_not_share_earlier_losses() {
+        let mut cohort = cohort();
+        let a = cohort.join(0, &balances(100));
+        cohort
+            .tokens
+            .get_mut(&icusd())
+            .unwrap()
+            .absorb(50, &[(icp(), 10)]);
+        let b = cohort.join(0, &balances(50));
+        cohort
+            .tokens
+            .get_mut(&icusd())
+            .unwrap()
+            .absorb(50, &[(icp(), 10)]);
+
+        let a_now = cohort.compound(&balances(100), &a);
+        let b_now = cohort.compound(&balances(50), &b);
+        assert_eq!(a_now.balances[&icusd()], 25);
+        assert_eq!(b_now.balances[&icusd()], 25);
+        assert_eq!(a_now.gains[&icp()], 15);
+        assert_eq!(b_now.gains[&icp()], 5);
+    }
+
+    #[test]
+    fn a_full_wipe_starts_a_new_epoch() {
+        let mut cohort = cohort();
+        let a = cohort.join(0, &balances(100));
+        cohort
+            .tokens
+            .get_mut(&icusd())
+            .unwrap()
+            .absorb(100, &[(icp(), 7)]);
+        let b = cohort.join(0, &balances(30));
+
+        let a_now = cohort.compound(&balances(100), &a);
+        assert!(a_now.balances.is_empty());
+        assert_eq!(a_now.gains[&icp()], 7);
+        assert_eq!(cohort.compound(&balances(30), &b).balances[&icusd()], 30);
+        assert_eq!(cohort.tokens[&icusd()].epoch, 1);
+    }
+
+    #[test]
+    fn deep_losses_rescale_the_product() {
+        let mut cohort = cohort();
+        let a = cohort.join(0, &balances(u64::MAX / 2));
+        let tokens = cohort.tokens.get_mut(&icusd()).unwrap();
+        // Leave a billionth, then a billionth of that.
+        tokens.absorb(u64::MAX / 2 - u64::MAX / 2_000_000_000, &[]);
+        assert_eq!(tokens.scale, 1);
+        let left = tokens.total;
+        tokens.absorb(0, &[(icp(), 1_000)]);
+
+        let a_now = cohort.compound(&balances(u64::MAX / 2), &a);
+        assert!(a_now.balances[&icusd()].abs_diff(left) <= 1);
+        assert!(a_now.gains[&icp()].abs_diff(1_000) <= 1);
+    }
+
+    #[test]
+    fn the_last_member_to_leave_takes_the_rounding_dust() {
+        let mut cohort = cohort();
+        let snapshots: Vec<DepositSnapshot> =
+            (0..3).map(|_| cohort.join(0, &balances(1))).collect();
+        cohort
+            .tokens
+            .get_mut(&icusd())
+            .unwrap()
+            .absorb(1, &[(icp(), 2)]);
+
+        let left: Vec<Compounded> = snapshots
+            .iter()
+            .map(|snapshot| cohort.leave(&balances(1), snapshot))
+            .collect();
+        let balance: u64 = left.iter().filter_map(|c| c.balances.get(&icusd())).sum();
+        let gains: u64 = left.iter().filter_map(|c| c.gains.get(&icp())).sum();
+        assert_eq!(balance, 2);
+        assert_eq!(gains, 2);
+        assert_eq!(cohort.members, 0);
+        assert!(cohort.tokens.is_empty());
+    }
+}
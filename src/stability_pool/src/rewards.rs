@@ -0,0 +1,138 @@
+//! Reward token emissions to depositors.
+//!
+//! An admin configures a `RewardConfig`: an ICRC-1 reward ledger (RUMI or a
+//! partner token), a per-second emission and an optional end time. The pool's
+//! own account on the reward ledger funds the emission; it is topped up by
+//! plain transfers and nothing here mints.
+//!
+//! Emission accrues in snapshots. Each accrual splits what was emitted since
+//! the previous one between the depositors pro rata to the USD value of their
+//! deposits at that moment, and credits it to their reward balance. Accrual
+//! runs before every deposit, withdrawal and liquidation changes balances, so
+//! each span is split by the balances that held through it, and on an hourly
+//! timer to bound the drift from interest credits and corrections. Emission
+//! while the pool is empty, and per-depositor rounding dust, is forfeited.
+//!
+//! `claim_rewards` pays out the caller's balance, accrued up to the call,
+//! less the reward ledger's fee. A balance survives a full withdrawal.
+
+use candid::Principal;
+use ic_canister_log::log;
+use ic_cdk::call;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::icrc1::transfer::{TransferArg, TransferError};
+use std::time::Duration;
+
+use crate::logs::INFO;
+use crate::state::{mutate_state, read_state};
+use crate::types::{PoolEventType, StabilityPoolError};
+
+/// How often rewards accrue when no balance change triggers it.
+pub const REWARD_ACCRUAL_INTERVAL_SECONDS: u64 = 3_600;
+
+pub fn setup_accrual_timer() {
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(REWARD_ACCRUAL_INTERVAL_SECONDS), || {
+        mutate_state(|s| s.accrue_rewards(ic_cdk::api::time()))
+    });
+}
+
+/// Pay out the caller's accrued rewards. Returns the amount received, 0 when
+/// the balance does not cover the ledger fee.
+///
+/// The balance is taken before the transfer, as in `claim_collateral`, and
+/// put back if the transfer fails.
+pub async fn claim_rewards() -> Result<u64, StabilityPoolError> {
+    let caller = ic_cdk::api::caller();
+
+    if read_state(|s| s.configuration.emergency_pause) {
+        return Err(StabilityPoolError::EmergencyPaused);
+    }
+
+    let (reward_ledger, rewards) = mutate_state(|s| {
+        let reward_ledger = s
+            .reward_config
+            .as_ref()
+            .map(|c| c.reward_ledger)
+            .ok_or(StabilityPoolError::RewardsNotConfigured)?;
+        s.accrue_rewards(ic_cdk::api::time());
+        Ok::<_, StabilityPoolError>((reward_ledger, s.take_reward_balance(&caller)))
+    })?;
+
+    if rewards == 0 {
+        return Ok(0);
+    }
+
+    let ledger_fee = crate::deposits::ledger_transfer_fee(reward_ledger).await;
+    if rewards <= ledger_fee {
+        mutate_state(|s| s.restore_reward_balance(caller, rewards));
+        return Ok(0);
+    }
+
+    let transfer_amount = rewards - ledger_fee;
+    log!(
+        INFO,
+        "Reward claim: {} of {} (transfer {} - fee {}) by {}",
+        rewards,
+        reward_ledger,
+        transfer_amount,
+        ledger_fee,
+        caller
+    );
+
+    let transfer_args = TransferArg {
+        to: Account {
+            owner: caller,
+            subaccount: None,
+        },
+        amount: transfer_amount.into(),
+        fee: None,
+        memo: None,
+        created_at_time: Some(ic_cdk::api::time()),
+        from_subaccount: None,
+    };
+
+    let result: Result<(Result<candid::Nat, TransferError>,), _> =
+        call(reward_ledger, "icrc1_transfer", (transfer_args,)).await;
+
+    match result {
+        Ok((Ok(_),)) | Ok((Err(TransferError::Duplicate { .. }),)) => {
+            // Duplicate means an earlier attempt already paid the caller.
+            mutate_state(|s| {
+                s.record_rewards_claimed(rewards);
+                s.push_event(
+                    caller,
+                    PoolEventType::RewardsClaimed {
+                        reward_ledger,
+                        amount: transfer_amount,
+                    },
+                )
+            });
+            Ok(transfer_amount)
+        }
+        Ok((Err(transfer_error),)) => {
+            log!(
+                INFO,
+                "Reward claim failed, rolling back: {:?}",
+                transfer_error
+            );
+            mutate_state(|s| s.restore_reward_balance(caller, rewards));
+            Err(StabilityPoolError::LedgerTransferFailed {
+                reason: format!("{:?}", transfer_error),
+            })
+        }
+        Err(call_error) => {
+            log!(
+                INFO,
+                "Inter-canister call failed, rolling back: {:?}",
+                call_error
+            );
+            // See withdraw() for the ICRC-002 caveat about
+            // transport-error-then-no-retry.
+            mutate_state(|s| s.restore_reward_balance(caller, rewards));
+            Err(StabilityPoolError::InterCanisterCallFailed {
+                target: format!("{}", reward_ledger),
+                method: "icrc1_transfer".to_string(),
+            })
+        }
+    }
+}
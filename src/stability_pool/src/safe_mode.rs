@@ -0,0 +1,112 @@
+//! Safe mode: the pool stops acting on backend data while the protocol
+//! canister is unreachable.
+//!
+//! Every call the pool makes to the protocol canister goes through
+//! `call_backend`, which counts consecutive rejected calls. Any reply counts
+//! as reachable, including the backend's own `Err`. After
+//! `SAFE_MODE_FAILURE_THRESHOLD` rejections in a row the pool enters safe
+//! mode:
+//!
+//! - it starts no new liquidations: backend pushes are ignored, and
+//!   `execute_liquidation`, new chain absorbs and the chain-absorb timer are
+//!   refused;
+//! - claims of collateral gains credited during safe mode, or within
+//!   `RECENT_GAIN_WINDOW_NS` before it began, are held, since those
+//!   liquidations settled against vault data the pool cannot re-check.
+//!
+//! Deposits, stablecoin withdrawals and older gains are unaffected, and
+//! absorbs already in flight still finish, since abandoning them would
+//! strand burned icUSD.
+//!
+//! While safe mode is on, a timer probes the backend every
+//! `SAFE_MODE_PROBE_INTERVAL_SECONDS`. The first call that gets a reply,
+//! probe or not, ends safe mode.
+
+use candid::utils::{ArgumentDecoder, ArgumentEncoder};
+use candid::Principal;
+use ic_canister_log::log;
+use ic_cdk::api::call::CallResult;
+use std::time::Duration;
+
+use crate::logs::INFO;
+use crate::state::{mutate_state, read_state};
+use crate::types::PoolEventType;
+
+/// Consecutive rejected backend calls that put the pool in safe mode.
+pub const SAFE_MODE_FAILURE_THRESHOLD: u32 = 3;
+
+pub const SAFE_MODE_PROBE_INTERVAL_SECONDS: u64 = 60;
+
+/// Gains credited this long before safe mode began are held with those
+/// credited during it (1 hour).
+pub const RECENT_GAIN_WINDOW_NS: u64 = 3_600 * 1_000_000_000;
+
+/// `ic_cdk::call` to the protocol canister, recording whether it replied.
+pub async fn call_backend<T: ArgumentEncoder, R: for<'a> ArgumentDecoder<'a>>(
+    protocol_id: Principal,
+    method: &str,
+    args: T,
+) -> CallResult<R> {
+    let result = ic_cdk::call(protocol_id, method, args).await;
+    let now = ic_cdk::api::time();
+    match &result {
+        Ok(_) => {
+            if let Some(ended) = mutate_state(|s| s.record_backend_reply(now)) {
+                mutate_state(|s| {
+                    s.push_event(
+                        ic_cdk::id(),
+                        PoolEventType::SafeModeExited {
+                            entered_at_ns: ended.entered_at_ns,
+                        },
+                    )
+                });
+                log!(
+                    INFO,
+                    "Backend reachable again ({}); safe mode ended after {} probes",
+                    method,
+                    ended.probes
+                );
+            }
+        }
+        Err((code, message)) => {
+            let error = format!("{}: {:?} {}", method, code, message);
+            let entered = mutate_state(|s| s.record_backend_failure(error.clone(), now));
+            if entered {
+                mutate_state(|s| {
+                    s.push_event(
+                        ic_cdk::id(),
+                        PoolEventType::SafeModeEntered {
+                            consecutive_failures: SAFE_MODE_FAILURE_THRESHOLD,
+                        },
+                    )
+                });
+                log!(INFO, "Backend unreachable ({}); entering safe mode", error);
+            }
+        }
+    }
+    result
+}
+
+pub fn setup_probe_timer() {
+    ic_cdk_timers::set_timer_interval(
+        Duration::from_secs(SAFE_MODE_PROBE_INTERVAL_SECONDS),
+        || {
+            if read_state(|s| s.safe_mode.is_some()) {
+                ic_cdk::spawn(probe());
+            }
+        },
+    );
+}
+
+/// Query the backend's protocol status; only whether a reply arrives
+/// matters.
+async fn probe() {
+    let protocol_id = mutate_state(|s| {
+        if let Some(safe_mode) = s.safe_mode.as_mut() {
+            safe_mode.probes += 1;
+        }
+        s.protocol_canister_id
+    });
+    let _: CallResult<(candid::Reserved,)> =
+        call_backend(protocol_id, "get_protocol_status", ()).await;
+}
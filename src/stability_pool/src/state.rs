@@ -4,7 +4,11 @@ use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::collections::{BTreeMap, BTreeSet};
 
+use crate::auto_compound::MAX_AUTO_COMPOUND_SLIPPAGE_BPS;
+use crate::deposits::{MAX_QUEUED_WITHDRAWALS, WITHDRAWAL_QUEUE_MAX_DELAY_SECONDS};
 use crate::logs::INFO;
+use crate::product_sum::{Compounded, DepositSnapshot, LossCohort};
+use crate::safe_mode::{RECENT_GAIN_WINDOW_NS, SAFE_MODE_FAILURE_THRESHOLD};
 use crate::types::*;
 
 pub const ICUSD_TRANSFER_FEE_E8S: u64 = 100_000;
@@ -146,6 +150,63 @@ pub struct StabilityPoolState {
     pub pending_refunds: Option<BTreeMap<u64, PendingRefund>>,
     #[serde(default)]
     pub next_pending_refund_id: Option<u64>,
+    /// Last mode pushed by the backend via `set_protocol_mode`.
+    /// `Option` is required for Candid backward-compatible stable memory upgrades.
+    #[serde(default)]
+    pub protocol_mode: Option<ProtocolMode>,
+    /// `None` = `ModeInheritancePolicy::default()`.
+    #[serde(default)]
+    pub mode_inheritance_policy: Option<ModeInheritancePolicy>,
+    /// Set while the protocol canister is treated as unreachable. See
+    /// `safe_mode`.
+    #[serde(default)]
+    pub safe_mode: Option<SafeMode>,
+    /// Rejected backend calls since the last reply.
+    #[serde(default)]
+    pub backend_consecutive_failures: Option<u32>,
+    #[serde(default)]
+    pub last_backend_reply_ns: Option<u64>,
+    #[serde(default)]
+    pub last_backend_error: Option<String>,
+    /// Reward token emission to depositors. See `rewards`.
+    #[serde(default)]
+    pub reward_config: Option<RewardConfig>,
+    /// Emission is distributed up to this time.
+    #[serde(default)]
+    pub rewards_accrued_until_ns: Option<u64>,
+    /// Accrued, unclaimed rewards per depositor (reward ledger units).
+    #[serde(default)]
+    pub reward_balances: Option<BTreeMap<Principal, u64>>,
+    #[serde(default)]
+    pub total_rewards_emitted: Option<u64>,
+    #[serde(default)]
+    pub total_rewards_claimed: Option<u64>,
+    /// Depositors whose collateral gains are swapped into icUSD and
+    /// redeposited. See `auto_compound`.
+    #[serde(default)]
+    pub auto_compound_users: Option<BTreeSet<Principal>>,
+    /// Swap route per collateral ledger; collateral without one is left to
+    /// accumulate as ordinary gains.
+    #[serde(default)]
+    pub auto_compound_routes: Option<BTreeMap<Principal, AutoCompoundRoute>>,
+    /// icUSD already bought for a depositor but not yet credited because a
+    /// liquidation was apportioning when the swap returned.
+    #[serde(default)]
+    pub pending_auto_compound_credits: Option<BTreeMap<Principal, u64>>,
+    /// Withdrawals requested while a liquidation was pending, oldest first.
+    /// See `deposits::process_withdrawal_queue`.
+    #[serde(default)]
+    pub withdrawal_queue: Option<Vec<QueuedWithdrawal>>,
+    /// Depositors grouped by opt-in choices, with the products and sums
+    /// their deposits compound through. See `product_sum`.
+    #[serde(default)]
+    pub loss_cohorts: Option<BTreeMap<u64, LossCohort>>,
+    #[serde(default)]
+    pub next_loss_cohort_id: Option<u64>,
+    /// The cohort each attached position is in and the accumulators it
+    /// joined at. An attached position's stored balances are as of then.
+    #[serde(default)]
+    pub deposit_snapshots: Option<BTreeMap<Principal, DepositSnapshot>>,
 }
 
 impl Default for StabilityPoolState {
@@ -187,6 +248,24 @@ impl Default for StabilityPoolState {
             next_event_id: Some(0),
             pending_refunds: Some(BTreeMap::new()),
             next_pending_refund_id: Some(0),
+            protocol_mode: None,
+            mode_inheritance_policy: None,
+            safe_mode: None,
+            backend_consecutive_failures: None,
+            last_backend_reply_ns: None,
+            last_backend_error: None,
+            reward_config: None,
+            rewards_accrued_until_ns: None,
+            reward_balances: None,
+            total_rewards_emitted: None,
+            total_rewards_claimed: None,
+            auto_compound_users: None,
+            auto_compound_routes: None,
+            pending_auto_compound_credits: None,
+            withdrawal_queue: None,
+            loss_cohorts: None,
+            next_loss_cohort_id: None,
+            deposit_snapshots: None,
         }
     }
 }
@@ -456,6 +535,571 @@ impl StabilityPoolState {
         self.configuration.authorized_admins.contains(caller)
     }
 
+    // ─── Backend mode inheritance ───
+
+    pub fn mode_inheritance_policy(&self) -> ModeInheritancePolicy {
+        self.mode_inheritance_policy.clone().unwrap_or_default()
+    }
+
+    /// The inherited mode, if the policy blocks new deposits in it.
+    pub fn deposits_restricted_by_mode(&self) -> Option<ProtocolMode> {
+        self.protocol_mode.filter(|mode| {
+            self.mode_inheritance_policy()
+                .block_deposits_in
+                .contains(&mode.restricts_as())
+        })
+    }
+
+    /// The inherited mode, if the policy blocks new liquidations in it.
+    pub fn liquidations_restricted_by_mode(&self) -> Option<ProtocolMode> {
+        self.protocol_mode.filter(|mode| {
+            self.mode_inheritance_policy()
+                .block_liquidations_in
+                .contains(&mode.restricts_as())
+        })
+    }
+
+    pub fn mode_inheritance_status(&self) -> ModeInheritanceStatus {
+        ModeInheritanceStatus {
+            protocol_mode: self.protocol_mode,
+            policy: self.mode_inheritance_policy(),
+            deposits_blocked: self.deposits_restricted_by_mode().is_some(),
+            liquidations_blocked: self.liquidations_restricted_by_mode().is_some(),
+        }
+    }
+
+    // ─── Backend safe mode ───
+
+    /// Note a reply from the protocol canister. Returns the safe mode this
+    /// ended, if any.
+    pub fn record_backend_reply(&mut self, now_ns: u64) -> Option<SafeMode> {
+        self.backend_consecutive_failures = Some(0);
+        self.last_backend_reply_ns = Some(now_ns);
+        self.safe_mode.take()
+    }
+
+    /// Note a rejected call to the protocol canister. Returns whether this
+    /// failure put the pool in safe mode.
+    pub fn record_backend_failure(&mut self, error: String, now_ns: u64) -> bool {
+        let failures = self
+            .backend_consecutive_failures
+            .unwrap_or(0)
+            .saturating_add(1);
+        self.backend_consecutive_failures = Some(failures);
+        self.last_backend_error = Some(error);
+        if self.safe_mode.is_some() || failures < SAFE_MODE_FAILURE_THRESHOLD {
+            return false;
+        }
+        self.safe_mode = Some(SafeMode {
+            entered_at_ns: now_ns,
+            probes: 0,
+        });
+        true
+    }
+
+    /// Refuse new liquidations while in safe mode.
+    pub fn ensure_not_in_safe_mode(&self) -> Result<(), StabilityPoolError> {
+        match &self.safe_mode {
+            Some(safe_mode) => Err(StabilityPoolError::BackendUnreachable {
+                since_ns: safe_mode.entered_at_ns,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Gains credited at or after this time are held while in safe mode.
+    pub fn gains_held_since_ns(&self) -> Option<u64> {
+        self.safe_mode
+            .as_ref()
+            .map(|m| m.entered_at_ns.saturating_sub(RECENT_GAIN_WINDOW_NS))
+    }
+
+    /// Refuse to pay out `user`'s `collateral_ledger` gains if safe mode
+    /// holds them. Gains are one balance per collateral, so a recent credit
+    /// holds the whole balance.
+    pub fn ensure_gains_not_held(
+        &self,
+        user: &Principal,
+        collateral_ledger: &Principal,
+    ) -> Result<(), StabilityPoolError> {
+        let Some(held_since) = self.gains_held_since_ns() else {
+            return Ok(());
+        };
+        let credited_at = self
+            .position(user)
+            .and_then(|p| p.gains_credited_at)
+            .and_then(|at| at.get(collateral_ledger).copied());
+        match credited_at {
+            Some(at) if at >= held_since => self.ensure_not_in_safe_mode(),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn safe_mode_status(&self) -> SafeModeStatus {
+        SafeModeStatus {
+            active: self.safe_mode.is_some(),
+            entered_at_ns: self.safe_mode.as_ref().map(|m| m.entered_at_ns),
+            probes: self.safe_mode.as_ref().map_or(0, |m| m.probes),
+            consecutive_failures: self.backend_consecutive_failures.unwrap_or(0),
+            failure_threshold: SAFE_MODE_FAILURE_THRESHOLD,
+            last_backend_reply_ns: self.last_backend_reply_ns,
+            last_backend_error: self.last_backend_error.clone(),
+            gains_held_since_ns: self.gains_held_since_ns(),
+        }
+    }
+
+    // ─── Depositor rewards ───
+
+    /// Replace the reward emission, after accruing what the old one owes up
+    /// to `now_ns`. Emission under the new config starts at `now_ns`.
+    pub fn set_reward_config(
+        &mut self,
+        config: RewardConfig,
+        now_ns: u64,
+    ) -> Result<(), StabilityPoolError> {
+        if self.stablecoin_registry.contains_key(&config.reward_ledger)
+            || self.collateral_registry.contains_key(&config.reward_ledger)
+        {
+            // Claims would pay out of depositors' funds.
+            return Err(StabilityPoolError::InvalidRewardConfig {
+                reason: format!(
+                    "{} is a pool stablecoin or collateral",
+                    config.reward_ledger
+                ),
+            });
+        }
+        self.accrue_rewards(now_ns);
+        let unclaimed = self.total_unclaimed_rewards();
+        if let Some(current) = &self.reward_config {
+            if current.reward_ledger != config.reward_ledger && unclaimed > 0 {
+                return Err(StabilityPoolError::InvalidRewardConfig {
+                    reason: format!(
+                        "{} of {} is still unclaimed",
+                        unclaimed, current.reward_ledger
+                    ),
+                });
+            }
+        }
+        self.reward_config = Some(config);
+        self.rewards_accrued_until_ns = Some(now_ns);
+        Ok(())
+    }
+
+    /// Reward emitted since the last accrual, up to `now_ns` or the end of
+    /// the emission, and the time accrual may advance to.
+    fn reward_emission_since_accrual(&self, now_ns: u64) -> (u128, u64) {
+        let Some(config) = &self.reward_config else {
+            return (0, now_ns);
+        };
+        let from = self.rewards_accrued_until_ns.unwrap_or(now_ns).min(now_ns);
+        let until = config.end_time_ns.map_or(now_ns, |end| end.min(now_ns));
+        if until <= from {
+            return (0, now_ns);
+        }
+        let emission = config.emission_per_second as u128 * (until - from) as u128 / 1_000_000_000;
+        (emission, now_ns)
+    }
+
+    /// The reward weight of each cohort's deposits of each token, their USD
+    /// value in e8s, and the total.
+    fn reward_weights(&self) -> (Vec<(u64, Principal, u64)>, u128) {
+        let weights: Vec<(u64, Principal, u64)> = self
+            .loss_cohorts
+            .iter()
+            .flatten()
+            .flat_map(|(id, cohort)| {
+                cohort.tokens.iter().map(move |(ledger, tokens)| {
+                    (*id, *ledger, self.stable_usd_e8s(ledger, tokens.total))
+                })
+            })
+            .filter(|(_, _, weight)| *weight > 0)
+            .collect();
+        let total = weights.iter().map(|(_, _, w)| *w as u128).sum();
+        (weights, total)
+    }
+
+    /// Credit the emission since the last accrual to depositors, pro rata
+    /// to the USD value of their deposits now. Each cohort's share of each
+    /// token is paid into its sums, so accrual does not visit depositors.
+    /// Emission while the pool is empty, and rounding dust, is not
+    /// distributed.
+    pub fn accrue_rewards(&mut self, now_ns: u64) {
+        let Some(reward_ledger) = self.reward_ledger() else {
+            return;
+        };
+        let (emission, accrued_until) = self.reward_emission_since_accrual(now_ns);
+        self.rewards_accrued_until_ns = Some(accrued_until);
+        if emission == 0 {
+            return;
+        }
+        let (weights, total_weight) = self.reward_weights();
+        if total_weight == 0 {
+            return;
+        }
+        let Some(cohorts) = self.loss_cohorts.as_mut() else {
+            return;
+        };
+        let mut distributed: u64 = 0;
+        for (id, ledger, weight) in weights {
+            let share = (emission * weight as u128 / total_weight) as u64;
+            let Some(tokens) = cohorts
+                .get_mut(&id)
+                .and_then(|cohort| cohort.tokens.get_mut(&ledger))
+            else {
+                continue;
+            };
+            if share > 0 {
+                tokens.absorb(0, &[(reward_ledger, share)]);
+                distributed += share;
+            }
+        }
+        self.total_rewards_emitted = Some(
+            self.total_rewards_emitted
+                .unwrap_or(0)
+                .saturating_add(distributed),
+        );
+    }
+
+    /// `user`'s rewards accrued so far, settled or still in their cohort.
+    pub fn accrued_rewards(&self, user: &Principal) -> u64 {
+        let settled = self
+            .reward_balances
+            .as_ref()
+            .and_then(|b| b.get(user).copied())
+            .unwrap_or(0);
+        let unsettled = self
+            .reward_ledger()
+            .zip(self.compounded(user))
+            .and_then(|(ledger, (compounded, _))| compounded.gains.get(&ledger).copied())
+            .unwrap_or(0);
+        settled + unsettled
+    }
+
+    /// `user`'s claimable rewards as of `now_ns`, accrual included.
+    pub fn pending_rewards(&self, user: &Principal, now_ns: u64) -> u64 {
+        let accrued = self.accrued_rewards(user);
+        let (emission, _) = self.reward_emission_since_accrual(now_ns);
+        if emission == 0 {
+            return accrued;
+        }
+        let (_, total_weight) = self.reward_weights();
+        if total_weight == 0 {
+            return accrued;
+        }
+        let weight = self.position(user).map_or(0, |pos| {
+            pos.total_usd_value(&self.stablecoin_registry, self.virtual_prices())
+        });
+        accrued + (emission * weight as u128 / total_weight) as u64
+    }
+
+    /// Remove and return `user`'s accrued rewards, ahead of paying them out.
+    pub fn take_reward_balance(&mut self, user: &Principal) -> u64 {
+        self.with_settled(user, |s| {
+            s.reward_balances
+                .as_mut()
+                .and_then(|b| b.remove(user))
+                .unwrap_or(0)
+        })
+    }
+
+    /// Put back rewards taken for a payout that did not happen.
+    pub fn restore_reward_balance(&mut self, user: Principal, amount: u64) {
+        *self
+            .reward_balances
+            .get_or_insert_with(BTreeMap::new)
+            .entry(user)
+            .or_insert(0) += amount;
+    }
+
+    pub fn record_rewards_claimed(&mut self, amount: u64) {
+        self.total_rewards_claimed = Some(
+            self.total_rewards_claimed
+                .unwrap_or(0)
+                .saturating_add(amount),
+        );
+    }
+
+    pub fn total_unclaimed_rewards(&self) -> u64 {
+        let settled: u64 = self
+            .reward_balances
+            .as_ref()
+            .map_or(0, |b| b.values().sum());
+        let unsettled: u64 = self.reward_ledger().map_or(0, |ledger| {
+            self.loss_cohorts()
+                .flat_map(|cohort| cohort.tokens.values())
+                .filter_map(|tokens| tokens.unsettled_gains.get(&ledger))
+                .sum()
+        });
+        settled + unsettled
+    }
+
+    pub fn rewards_status(&self) -> RewardsStatus {
+        RewardsStatus {
+            config: self.reward_config.clone(),
+            accrued_until_ns: self.rewards_accrued_until_ns,
+            total_emitted: self.total_rewards_emitted.unwrap_or(0),
+            total_claimed: self.total_rewards_claimed.unwrap_or(0),
+            total_unclaimed: self.total_unclaimed_rewards(),
+        }
+    }
+
+    // ─── Auto-compounding ───
+
+    pub fn auto_compound_enabled(&self, user: &Principal) -> bool {
+        self.auto_compound_users
+            .as_ref()
+            .is_some_and(|users| users.contains(user))
+    }
+
+    /// Opt `user` in or out. Returns whether anything changed.
+    pub fn set_auto_compound(&mut self, user: Principal, enabled: bool) -> bool {
+        let users = self.auto_compound_users.get_or_insert_with(BTreeSet::new);
+        if enabled {
+            users.insert(user)
+        } else {
+            users.remove(&user)
+        }
+    }
+
+    /// Set or clear the swap route for `collateral_ledger`.
+    pub fn set_auto_compound_route(
+        &mut self,
+        collateral_ledger: Principal,
+        route: Option<AutoCompoundRoute>,
+    ) -> Result<(), StabilityPoolError> {
+        let Some(route) = route else {
+            if let Some(routes) = self.auto_compound_routes.as_mut() {
+                routes.remove(&collateral_ledger);
+            }
+            return Ok(());
+        };
+        if !self.collateral_registry.contains_key(&collateral_ledger) {
+            return Err(StabilityPoolError::CollateralNotFound {
+                ledger: collateral_ledger,
+            });
+        }
+        self.ensure_icrc_claimable_collateral(&collateral_ledger)?;
+        let invalid = |reason: String| StabilityPoolError::InvalidAutoCompoundRoute { reason };
+        if route.pool_id.is_empty() {
+            return Err(invalid("pool_id is empty".to_string()));
+        }
+        if route.max_slippage_bps > MAX_AUTO_COMPOUND_SLIPPAGE_BPS {
+            return Err(invalid(format!(
+                "max_slippage_bps {} exceeds {}",
+                route.max_slippage_bps, MAX_AUTO_COMPOUND_SLIPPAGE_BPS
+            )));
+        }
+        self.auto_compound_routes
+            .get_or_insert_with(BTreeMap::new)
+            .insert(collateral_ledger, route);
+        Ok(())
+    }
+
+    /// The icUSD ledger and route a claim of `collateral_ledger` gains as
+    /// icUSD goes through: the collateral's auto-compound route.
+    pub fn icusd_payout_route(
+        &self,
+        collateral_ledger: &Principal,
+    ) -> Result<(Principal, AutoCompoundRoute), StabilityPoolError> {
+        let unavailable = |reason: &str| StabilityPoolError::PayoutSwapUnavailable {
+            collateral: *collateral_ledger,
+            reason: reason.to_string(),
+        };
+        let icusd_ledger = self
+            .icusd_ledger()
+            .ok_or_else(|| unavailable("icUSD is not registered"))?;
+        let route = self
+            .auto_compound_routes
+            .as_ref()
+            .and_then(|routes| routes.get(collateral_ledger))
+            .ok_or_else(|| unavailable("no DEX route for this collateral"))?;
+        Ok((icusd_ledger, route.clone()))
+    }
+
+    /// Take the `collateral_ledger` gains of every opted-in depositor, ahead
+    /// of selling them. Gains held by safe mode stay where they are.
+    pub fn take_auto_compound_gains(
+        &mut self,
+        collateral_ledger: &Principal,
+    ) -> Vec<(Principal, u64)> {
+        let users: Vec<Principal> = self
+            .auto_compound_users
+            .as_ref()
+            .map(|users| users.iter().copied().collect())
+            .unwrap_or_default();
+        let mut batch = Vec::new();
+        for user in users {
+            let gains = self
+                .get_collateral_gains(&user)
+                .get(collateral_ledger)
+                .copied()
+                .unwrap_or(0);
+            if gains == 0
+                || self
+                    .ensure_gains_not_held(&user, collateral_ledger)
+                    .is_err()
+            {
+                continue;
+            }
+            self.mark_gains_claimed(&user, collateral_ledger, gains);
+            batch.push((user, gains));
+        }
+        batch
+    }
+
+    /// Put back gains taken for a sale that did not happen, as in the
+    /// `claim_collateral` rollback.
+    pub fn restore_auto_compound_gains(
+        &mut self,
+        collateral_ledger: &Principal,
+        amounts: &[(Principal, u64)],
+        now_ns: u64,
+    ) {
+        for (user, amount) in amounts {
+            let position = self
+                .deposits
+                .entry(*user)
+                .or_insert_with(|| DepositPosition::new(now_ns));
+            *position
+                .collateral_gains
+                .entry(*collateral_ledger)
+                .or_insert(0) += amount;
+            if let Some(claimed) = position.total_claimed_gains.get_mut(collateral_ledger) {
+                *claimed = claimed.saturating_sub(*amount);
+            }
+        }
+    }
+
+    /// Queue icUSD bought for depositors while their deposits cannot change.
+    pub fn defer_auto_compound_credits(&mut self, credits: &[(Principal, u64)]) {
+        let pending = self
+            .pending_auto_compound_credits
+            .get_or_insert_with(BTreeMap::new);
+        for (user, amount) in credits {
+            *pending.entry(*user).or_insert(0) += amount;
+        }
+    }
+
+    pub fn take_pending_auto_compound_credits(&mut self) -> Vec<(Principal, u64)> {
+        self.pending_auto_compound_credits
+            .take()
+            .map(|pending| pending.into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Latest liquidation price of `collateral_ledger` (USD e8s per whole
+    /// token), the floor auto-compounding sells against.
+    pub fn last_liquidation_price_e8s(&self, collateral_ledger: &Principal) -> Option<u64> {
+        self.liquidation_history
+            .iter()
+            .rev()
+            .filter(|record| record.collateral_type == *collateral_ledger)
+            .find_map(|record| record.collateral_price_e8s)
+    }
+
+    pub fn auto_compound_status(&self, user: &Principal) -> AutoCompoundStatus {
+        AutoCompoundStatus {
+            enabled: self.auto_compound_enabled(user),
+            routes: self
+                .auto_compound_routes
+                .as_ref()
+                .map(|routes| routes.iter().map(|(c, r)| (*c, r.clone())).collect())
+                .unwrap_or_default(),
+            opted_in_users: self.auto_compound_users.as_ref().map_or(0, |u| u.len()) as u64,
+            pending_credit: self
+                .pending_auto_compound_credits
+                .as_ref()
+                .and_then(|pending| pending.get(user).copied())
+                .unwrap_or(0),
+        }
+    }
+
+    // ─── Withdrawal queue ───
+
+    /// Queue a withdrawal of `amount` of `token_ledger` by `user`, or update
+    /// the amount of the one already queued, keeping its place. Returns the
+    /// 1-based position.
+    pub fn queue_withdrawal(
+        &mut self,
+        user: Principal,
+        token_ledger: Principal,
+        amount: u64,
+        now_ns: u64,
+    ) -> Result<u64, StabilityPoolError> {
+        let available = self
+            .position(&user)
+            .and_then(|pos| pos.stablecoin_balances.get(&token_ledger).copied())
+            .unwrap_or(0);
+        if amount > available {
+            return Err(StabilityPoolError::InsufficientBalance {
+                token: token_ledger,
+                required: amount,
+                available,
+            });
+        }
+        let epoch = self.total_liquidations_executed;
+        let queue = self.withdrawal_queue.get_or_insert_with(Vec::new);
+        if let Some(i) = queue
+            .iter()
+            .position(|w| w.user == user && w.token_ledger == token_ledger)
+        {
+            queue[i].amount = amount;
+            return Ok(i as u64 + 1);
+        }
+        if queue.len() >= MAX_QUEUED_WITHDRAWALS {
+            return Err(StabilityPoolError::SystemBusy);
+        }
+        queue.push(QueuedWithdrawal {
+            user,
+            token_ledger,
+            amount,
+            requested_at_ns: now_ns,
+            epoch,
+        });
+        Ok(queue.len() as u64)
+    }
+
+    /// Take the queued withdrawals that may be paid: those requested before
+    /// a liquidation that has since settled, or waiting longer than
+    /// `WITHDRAWAL_QUEUE_MAX_DELAY_SECONDS`. The rest keep their places.
+    pub fn take_ready_withdrawals(&mut self, now_ns: u64) -> Vec<QueuedWithdrawal> {
+        let epoch = self.total_liquidations_executed;
+        let Some(queue) = self.withdrawal_queue.as_mut() else {
+            return Vec::new();
+        };
+        let (ready, waiting) = std::mem::take(queue).into_iter().partition(|w| {
+            w.epoch < epoch
+                || now_ns.saturating_sub(w.requested_at_ns)
+                    >= WITHDRAWAL_QUEUE_MAX_DELAY_SECONDS * 1_000_000_000
+        });
+        *queue = waiting;
+        ready
+    }
+
+    /// Put back withdrawals taken but not paid, ahead of the rest.
+    pub fn requeue_withdrawals(&mut self, withdrawals: &[QueuedWithdrawal]) {
+        let queue = self.withdrawal_queue.get_or_insert_with(Vec::new);
+        queue.splice(0..0, withdrawals.iter().cloned());
+    }
+
+    pub fn withdrawal_queue_len(&self) -> usize {
+        self.withdrawal_queue.as_ref().map_or(0, |q| q.len())
+    }
+
+    pub fn withdrawal_queue_positions(&self, user: &Principal) -> Vec<WithdrawalQueuePosition> {
+        let queue = self.withdrawal_queue.as_deref().unwrap_or_default();
+        queue
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| w.user == *user)
+            .map(|(i, w)| WithdrawalQueuePosition {
+                position: i as u64 + 1,
+                queue_length: queue.len() as u64,
+                withdrawal: w.clone(),
+            })
+            .collect()
+    }
+
     // ─── Stablecoin Registry ───
 
     pub fn register_stablecoin(&mut self, config: StablecoinConfig) {
@@ -542,7 +1186,7 @@ impl StabilityPoolState {
         // (e.g. bridged XRP on an EVM sidechain): it would route a CFX-style
         // sentinel opt-in through the payout-address branch and silently break
         // that depositor's absorption. Identity-only avoids that hazard.
-        *collateral_type == rumi_protocol_backend::state::xrp_collateral_principal()
+        *collateral_type == rumi_common::chains::xrp_collateral_principal()
     }
 
     pub fn native_payout_address(
@@ -609,31 +1253,222 @@ impl StabilityPoolState {
         pos.is_opted_in(collateral_type)
     }
 
-    // ─── Deposits ───
+    // ─── Loss cohorts ───
 
-    pub fn add_deposit(&mut self, user: Principal, token_ledger: Principal, amount: u64) {
-        let position = self
-            .deposits
-            .entry(user)
-            .or_insert_with(|| DepositPosition::new(ic_cdk::api::time()));
-        *position
-            .stablecoin_balances
-            .entry(token_ledger)
-            .or_insert(0) += amount;
-        *self
-            .total_stablecoin_balances
-            .entry(token_ledger)
-            .or_insert(0) += amount;
+    /// `position_opted_in_for`, for every member of `cohort`.
+    fn cohort_opted_in_for(&self, cohort: &LossCohort, collateral_type: &Principal) -> bool {
+        if self.collateral_requires_payout_address(collateral_type) {
+            return cohort.native_payout_collateral.contains(collateral_type);
+        }
+        if self.is_chain_collateral_sentinel(collateral_type) {
+            return cohort.opted_in_chain_collateral.contains(collateral_type);
+        }
+        !cohort.opted_out_collateral.contains(collateral_type)
     }
 
-    /// Distribute icUSD interest revenue to eligible icUSD-holding depositors.
-    /// Called by the backend after minting interest to the pool canister.
-    ///
-    /// Interest is distributed pro-rata based on each depositor's **icUSD balance
-    /// only** (computed via `DepositPosition::icusd_value`). Depositors who hold
-    /// only 3USD, ckUSDC, or ckUSDT do not earn from this stream (they still
-    /// absorb liquidations pro-rata via the separate `compute_token_draw` path,
-    /// but the protocol's borrowing-interest yield goes to icUSD holders only).
+    fn loss_cohorts(&self) -> impl Iterator<Item = &LossCohort> {
+        self.loss_cohorts
+            .iter()
+            .flat_map(|cohorts| cohorts.values())
+    }
+
+    /// Deposits of `token_ledger` that would absorb a liquidation of
+    /// `collateral_type`.
+    fn opted_in_token_total(&self, collateral_type: &Principal, token_ledger: &Principal) -> u64 {
+        self.loss_cohorts()
+            .filter(|cohort| self.cohort_opted_in_for(cohort, collateral_type))
+            .map(|cohort| cohort.total(token_ledger))
+            .sum()
+    }
+
+    fn reward_ledger(&self) -> Option<Principal> {
+        self.reward_config
+            .as_ref()
+            .map(|config| config.reward_ledger)
+    }
+
+    /// `user`'s attached position compounded through its cohort, and when
+    /// the cohort was last paid each collateral.
+    fn compounded(&self, user: &Principal) -> Option<(Compounded, &BTreeMap<Principal, u64>)> {
+        let snapshot = self.deposit_snapshots.as_ref()?.get(user)?;
+        let cohort = self.loss_cohorts.as_ref()?.get(&snapshot.cohort)?;
+        let position = self.deposits.get(user)?;
+        Some((
+            cohort.compound(&position.stablecoin_balances, snapshot),
+            &cohort.gains_credited_at,
+        ))
+    }
+
+    /// `user`'s position with its deposits compounded and its unsettled
+    /// collateral gains added, or `None` once nothing is left in it.
+    pub fn position(&self, user: &Principal) -> Option<DepositPosition> {
+        let mut position = self.deposits.get(user)?.clone();
+        if let Some((compounded, credited_at)) = self.compounded(user) {
+            apply_compounded(&mut position, compounded, credited_at, self.reward_ledger());
+        }
+        (!position.is_empty()).then_some(position)
+    }
+
+    /// Every position, as `position` returns it.
+    pub fn positions(&self) -> impl Iterator<Item = (Principal, DepositPosition)> + '_ {
+        self.deposits
+            .keys()
+            .filter_map(|user| Some((*user, self.position(user)?)))
+    }
+
+    /// Take `user`'s position out of its cohort, storing its compounded
+    /// balances and gains. Reward gains go to `reward_balances`.
+    pub fn settle_position(&mut self, user: &Principal) {
+        let Some(snapshot) = self
+            .deposit_snapshots
+            .as_mut()
+            .and_then(|snapshots| snapshots.remove(user))
+        else {
+            return;
+        };
+        let Some(cohort) = self
+            .loss_cohorts
+            .as_mut()
+            .and_then(|cohorts| cohorts.get_mut(&snapshot.cohort))
+        else {
+            return;
+        };
+        let reward_ledger = self.reward_config.as_ref().map(|c| c.reward_ledger);
+        let position = self
+            .deposits
+            .entry(*user)
+            .or_insert_with(|| DepositPosition::new(0));
+        let compounded = cohort.leave(&position.stablecoin_balances, &snapshot);
+        let rewards = apply_compounded(
+            position,
+            compounded,
+            &cohort.gains_credited_at,
+            reward_ledger,
+        );
+        if cohort.members == 0 {
+            if let Some(cohorts) = self.loss_cohorts.as_mut() {
+                cohorts.remove(&snapshot.cohort);
+            }
+        }
+        if position.is_empty() {
+            self.deposits.remove(user);
+        }
+        if rewards > 0 {
+            *self
+                .reward_balances
+                .get_or_insert_with(BTreeMap::new)
+                .entry(*user)
+                .or_insert(0) += rewards;
+        }
+    }
+
+    /// Put `user`'s position in the cohort of depositors with its opt-in
+    /// choices, from where liquidations compound onto it. A position without
+    /// deposits is left out.
+    pub fn attach_position(&mut self, user: &Principal) {
+        if self
+            .deposit_snapshots
+            .as_ref()
+            .is_some_and(|snapshots| snapshots.contains_key(user))
+        {
+            return;
+        }
+        let Some(position) = self.deposits.get(user) else {
+            return;
+        };
+        if position
+            .stablecoin_balances
+            .values()
+            .all(|&amount| amount == 0)
+        {
+            return;
+        }
+        let cohorts = self.loss_cohorts.get_or_insert_with(BTreeMap::new);
+        let existing = cohorts
+            .iter()
+            .find(|(_, cohort)| cohort.matches(position))
+            .map(|(id, _)| *id);
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let id = self.next_loss_cohort_id.unwrap_or(0);
+                self.next_loss_cohort_id = Some(id + 1);
+                id
+            }
+        };
+        let snapshot = cohorts
+            .entry(id)
+            .or_insert_with(|| LossCohort::for_position(position))
+            .join(id, &position.stablecoin_balances);
+        self.deposit_snapshots
+            .get_or_insert_with(BTreeMap::new)
+            .insert(*user, snapshot);
+    }
+
+    /// Settle every attached position, leaving each cohort's rounding dust
+    /// with its last member.
+    pub fn settle_all_positions(&mut self) {
+        let users: Vec<Principal> = self
+            .deposit_snapshots
+            .iter()
+            .flat_map(|snapshots| snapshots.keys().copied())
+            .collect();
+        for user in users {
+            self.settle_position(&user);
+        }
+    }
+
+    /// Attach every position that is not in a cohort. Run after an upgrade
+    /// from before cohorts, when every position is unattached.
+    pub fn attach_all_positions(&mut self) {
+        let users: Vec<Principal> = self.deposits.keys().copied().collect();
+        for user in users {
+            self.attach_position(&user);
+        }
+    }
+
+    /// Run `f` with `user`'s position settled, so that it sees and may
+    /// change the stored balances, then attach it again.
+    pub fn with_settled<R>(&mut self, user: &Principal, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.settle_position(user);
+        let result = f(self);
+        self.attach_position(user);
+        result
+    }
+
+    /// `with_settled` for every position, for the paths that still credit
+    /// depositors one by one.
+    fn with_all_settled<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.settle_all_positions();
+        let result = f(self);
+        self.attach_all_positions();
+        result
+    }
+
+    // ─── Deposits ───
+
+    pub fn add_deposit(&mut self, user: Principal, token_ledger: Principal, amount: u64) {
+        self.with_settled(&user, |s| {
+            let position = s
+                .deposits
+                .entry(user)
+                .or_insert_with(|| DepositPosition::new(ic_cdk::api::time()));
+            *position
+                .stablecoin_balances
+                .entry(token_ledger)
+                .or_insert(0) += amount;
+            *s.total_stablecoin_balances.entry(token_ledger).or_insert(0) += amount;
+        })
+    }
+
+    /// Distribute icUSD interest revenue to eligible icUSD-holding depositors.
+    /// Called by the backend after minting interest to the pool canister.
+    ///
+    /// Interest is distributed pro-rata based on each depositor's **icUSD balance
+    /// only** (computed via `DepositPosition::icusd_value`). Depositors who hold
+    /// only 3USD, ckUSDC, or ckUSDT do not earn from this stream (they still
+    /// absorb liquidations pro-rata via the separate `compute_token_draw` path,
+    /// but the protocol's borrowing-interest yield goes to icUSD holders only).
     ///
     /// When `collateral_type` is provided, depositors who have opted out of that
     /// collateral are excluded from the distribution (they should not earn
@@ -649,84 +1484,86 @@ impl StabilityPoolState {
         amount: u64,
         collateral_type: Option<Principal>,
     ) {
-        if amount == 0 {
-            return;
-        }
-
-        let decimals = self
-            .stablecoin_registry
-            .get(&token_ledger)
-            .map(|c| c.decimals)
-            .unwrap_or(8);
+        self.with_all_settled(|s| {
+            if amount == 0 {
+                return;
+            }
 
-        // Only icUSD-denominated balances earn the interest stream.
-        // 3USD, ckUSDC, ckUSDT depositors still participate in liquidations
-        // pro-rata but no longer earn the interest distribution.
-        let holders: Vec<(Principal, u64)> = self
-            .deposits
-            .iter()
-            .filter_map(|(p, pos)| {
-                let icusd_value = pos.icusd_value(&self.stablecoin_registry);
-                if icusd_value == 0 {
-                    return None;
-                }
-                // If we know the collateral source, skip opted-out depositors
-                if let Some(ct) = &collateral_type {
-                    if !self.position_opted_in_for(pos, ct) {
+            let decimals = s
+                .stablecoin_registry
+                .get(&token_ledger)
+                .map(|c| c.decimals)
+                .unwrap_or(8);
+
+            // Only icUSD-denominated balances earn the interest stream.
+            // 3USD, ckUSDC, ckUSDT depositors still participate in liquidations
+            // pro-rata but no longer earn the interest distribution.
+            let holders: Vec<(Principal, u64)> = s
+                .deposits
+                .iter()
+                .filter_map(|(p, pos)| {
+                    let icusd_value = pos.icusd_value(&s.stablecoin_registry);
+                    if icusd_value == 0 {
                         return None;
                     }
-                }
-                Some((*p, icusd_value))
-            })
-            .collect();
-
-        let eligible_total: u64 = holders.iter().map(|(_, b)| *b).sum();
-        if eligible_total == 0 {
-            log!(
-                INFO,
-                "WARN distribute_interest_revenue: {} of token {} received but no eligible icUSD depositor exists; explicit reconciliation required",
-                amount,
-                token_ledger,
-            );
-            return;
-        }
+                    // If we know the collateral source, skip opted-out depositors
+                    if let Some(ct) = &collateral_type {
+                        if !s.position_opted_in_for(pos, ct) {
+                            return None;
+                        }
+                    }
+                    Some((*p, icusd_value))
+                })
+                .collect();
+
+            let eligible_total: u64 = holders.iter().map(|(_, b)| *b).sum();
+            if eligible_total == 0 {
+                log!(
+                    INFO,
+                    "WARN distribute_interest_revenue: {} of token {} received but no eligible icUSD depositor exists; explicit reconciliation required",
+                    amount,
+                    token_ledger,
+                );
+                return;
+            }
 
-        let mut distributed: u64 = 0;
-        let mut first_eligible: Option<Principal> = None;
+            let mut distributed: u64 = 0;
+            let mut first_eligible: Option<Principal> = None;
 
-        for (principal, balance) in &holders {
-            if first_eligible.is_none() {
-                first_eligible = Some(*principal);
-            }
-            let credit = (amount as u128 * *balance as u128 / eligible_total as u128) as u64;
-            if credit > 0 {
-                if let Some(pos) = self.deposits.get_mut(principal) {
-                    *pos.stablecoin_balances.entry(token_ledger).or_insert(0) += credit;
-                    *pos.total_interest_earned_e8s.get_or_insert(0) +=
-                        normalize_to_e8s(credit, decimals);
+            for (principal, balance) in &holders {
+                if first_eligible.is_none() {
+                    first_eligible = Some(*principal);
+                }
+                let credit = (amount as u128 * *balance as u128 / eligible_total as u128) as u64;
+                if credit > 0 {
+                    if let Some(pos) = s.deposits.get_mut(principal) {
+                        *pos.stablecoin_balances.entry(token_ledger).or_insert(0) += credit;
+                        *pos.total_interest_earned_e8s.get_or_insert(0) +=
+                            normalize_to_e8s(credit, decimals);
+                    }
+                    distributed += credit;
                 }
-                distributed += credit;
             }
-        }
 
-        // Assign rounding dust to first eligible depositor
-        let dust = amount.saturating_sub(distributed);
-        if dust > 0 {
-            if let Some(first) = first_eligible {
-                if let Some(pos) = self.deposits.get_mut(&first) {
-                    *pos.stablecoin_balances.entry(token_ledger).or_insert(0) += dust;
-                    *pos.total_interest_earned_e8s.get_or_insert(0) +=
-                        normalize_to_e8s(dust, decimals);
+            // Assign rounding dust to first eligible depositor
+            let dust = amount.saturating_sub(distributed);
+            if dust > 0 {
+                if let Some(first) = first_eligible {
+                    if let Some(pos) = s.deposits.get_mut(&first) {
+                        *pos.stablecoin_balances.entry(token_ledger).or_insert(0) += dust;
+                        *pos.total_interest_earned_e8s.get_or_insert(0) +=
+                            normalize_to_e8s(dust, decimals);
+                    }
                 }
             }
-        }
 
-        // Update aggregate totals
-        *self
-            .total_stablecoin_balances
-            .entry(token_ledger)
-            .or_insert(0) += amount;
-        *self.total_interest_received_e8s.get_or_insert(0) += normalize_to_e8s(amount, decimals);
+            // Update aggregate totals
+            *s
+                .total_stablecoin_balances
+                .entry(token_ledger)
+                .or_insert(0) += amount;
+            *s.total_interest_received_e8s.get_or_insert(0) += normalize_to_e8s(amount, decimals);
+        })
     }
 
     /// True when at least one icUSD depositor is eligible for interest from the
@@ -734,10 +1571,13 @@ impl StabilityPoolState {
     /// `distribute_interest_revenue`, so an unallocated payment is routed to
     /// treasury only when no payout recipient exists at receipt time.
     pub fn has_eligible_interest_recipient(&self, collateral_type: Option<&Principal>) -> bool {
-        self.deposits.values().any(|pos| {
-            pos.icusd_value(&self.stablecoin_registry) > 0
+        let Some(icusd_ledger) = self.icusd_ledger() else {
+            return false;
+        };
+        self.loss_cohorts().any(|cohort| {
+            cohort.total(&icusd_ledger) > 0
                 && collateral_type
-                    .map(|ct| self.position_opted_in_for(pos, ct))
+                    .map(|ct| self.cohort_opted_in_for(cohort, ct))
                     .unwrap_or(true)
         })
     }
@@ -748,39 +1588,41 @@ impl StabilityPoolState {
         token_ledger: Principal,
         amount: u64,
     ) -> Result<(), StabilityPoolError> {
-        let position = self
-            .deposits
-            .get_mut(&user)
-            .ok_or(StabilityPoolError::NoPositionFound)?;
+        self.with_settled(&user, |s| {
+            let position = s
+                .deposits
+                .get_mut(&user)
+                .ok_or(StabilityPoolError::NoPositionFound)?;
 
-        let balance = position
-            .stablecoin_balances
-            .get(&token_ledger)
-            .copied()
-            .unwrap_or(0);
-        if balance < amount {
-            return Err(StabilityPoolError::InsufficientBalance {
-                token: token_ledger,
-                required: amount,
-                available: balance,
-            });
-        }
+            let balance = position
+                .stablecoin_balances
+                .get(&token_ledger)
+                .copied()
+                .unwrap_or(0);
+            if balance < amount {
+                return Err(StabilityPoolError::InsufficientBalance {
+                    token: token_ledger,
+                    required: amount,
+                    available: balance,
+                });
+            }
 
-        // Safe subtraction: unwrap is justified for per-user balance (we just checked it exists
-        // with balance >= amount above), but use saturating_sub for aggregate to be defensive.
-        *position.stablecoin_balances.get_mut(&token_ledger).unwrap() -= amount;
-        if let Some(total) = self.total_stablecoin_balances.get_mut(&token_ledger) {
-            *total = total.saturating_sub(amount);
-        }
+            // Safe subtraction: unwrap is justified for per-user balance (we just checked it exists
+            // with balance >= amount above), but use saturating_sub for aggregate to be defensive.
+            *position.stablecoin_balances.get_mut(&token_ledger).unwrap() -= amount;
+            if let Some(total) = s.total_stablecoin_balances.get_mut(&token_ledger) {
+                *total = total.saturating_sub(amount);
+            }
 
-        // Clean up zero balances
-        if position.stablecoin_balances.get(&token_ledger) == Some(&0) {
-            position.stablecoin_balances.remove(&token_ledger);
-        }
-        if position.is_empty() {
-            self.deposits.remove(&user);
-        }
-        Ok(())
+            // Clean up zero balances
+            if position.stablecoin_balances.get(&token_ledger) == Some(&0) {
+                position.stablecoin_balances.remove(&token_ledger);
+            }
+            if position.is_empty() {
+                s.deposits.remove(&user);
+            }
+            Ok(())
+        })
     }
 
     /// Proportionally debit a token balance across all depositors who hold it.
@@ -794,66 +1636,68 @@ impl StabilityPoolState {
     /// migration quirk or an external reconciliation). `correct_balance` is the
     /// per-depositor-targeted analogue for surgical corrections.
     pub fn deduct_burned_lp_from_balances(&mut self, token_ledger: Principal, burned_amount: u64) {
-        let total = self
-            .total_stablecoin_balances
-            .get(&token_ledger)
-            .copied()
-            .unwrap_or(0);
-        if total == 0 || burned_amount == 0 {
-            return;
-        }
-        let actual_deduct = burned_amount.min(total);
+        self.with_all_settled(|s| {
+            let total = s
+                .total_stablecoin_balances
+                .get(&token_ledger)
+                .copied()
+                .unwrap_or(0);
+            if total == 0 || burned_amount == 0 {
+                return;
+            }
+            let actual_deduct = burned_amount.min(total);
 
-        // Distribute proportionally across depositors
-        let depositors: Vec<(Principal, u64)> = self
-            .deposits
-            .iter()
-            .filter_map(|(p, pos)| {
-                let bal = pos
-                    .stablecoin_balances
-                    .get(&token_ledger)
-                    .copied()
-                    .unwrap_or(0);
-                if bal > 0 {
-                    Some((*p, bal))
-                } else {
-                    None
-                }
-            })
-            .collect();
+            // Distribute proportionally across depositors
+            let depositors: Vec<(Principal, u64)> = s
+                .deposits
+                .iter()
+                .filter_map(|(p, pos)| {
+                    let bal = pos
+                        .stablecoin_balances
+                        .get(&token_ledger)
+                        .copied()
+                        .unwrap_or(0);
+                    if bal > 0 {
+                        Some((*p, bal))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
 
-        let mut total_deducted = 0u64;
-        for (principal, user_bal) in &depositors {
-            let user_share = (*user_bal as u128 * actual_deduct as u128 / total as u128) as u64;
-            let user_share = user_share.min(*user_bal);
-            if let Some(pos) = self.deposits.get_mut(principal) {
-                if let Some(bal) = pos.stablecoin_balances.get_mut(&token_ledger) {
-                    *bal = bal.saturating_sub(user_share);
+            let mut total_deducted = 0u64;
+            for (principal, user_bal) in &depositors {
+                let user_share = (*user_bal as u128 * actual_deduct as u128 / total as u128) as u64;
+                let user_share = user_share.min(*user_bal);
+                if let Some(pos) = s.deposits.get_mut(principal) {
+                    if let Some(bal) = pos.stablecoin_balances.get_mut(&token_ledger) {
+                        *bal = bal.saturating_sub(user_share);
+                    }
                 }
+                total_deducted += user_share;
             }
-            total_deducted += user_share;
-        }
 
-        // Assign rounding dust to largest holder to prevent aggregate/individual drift
-        let dust = actual_deduct.saturating_sub(total_deducted);
-        if dust > 0 {
-            if let Some(largest_p) = depositors
-                .iter()
-                .max_by_key(|(_, bal)| *bal)
-                .map(|(p, _)| *p)
-            {
-                if let Some(pos) = self.deposits.get_mut(&largest_p) {
-                    if let Some(bal) = pos.stablecoin_balances.get_mut(&token_ledger) {
-                        *bal = bal.saturating_sub(dust);
+            // Assign rounding dust to largest holder to prevent aggregate/individual drift
+            let dust = actual_deduct.saturating_sub(total_deducted);
+            if dust > 0 {
+                if let Some(largest_p) = depositors
+                    .iter()
+                    .max_by_key(|(_, bal)| *bal)
+                    .map(|(p, _)| *p)
+                {
+                    if let Some(pos) = s.deposits.get_mut(&largest_p) {
+                        if let Some(bal) = pos.stablecoin_balances.get_mut(&token_ledger) {
+                            *bal = bal.saturating_sub(dust);
+                        }
                     }
+                    total_deducted += dust;
                 }
-                total_deducted += dust;
             }
-        }
 
-        if let Some(agg) = self.total_stablecoin_balances.get_mut(&token_ledger) {
-            *agg = agg.saturating_sub(total_deducted);
-        }
+            if let Some(agg) = s.total_stablecoin_balances.get_mut(&token_ledger) {
+                *agg = agg.saturating_sub(total_deducted);
+            }
+        })
     }
 
     /// Inverse of `deduct_burned_lp_from_balances`: proportionally credit a token
@@ -863,71 +1707,69 @@ impl StabilityPoolState {
     /// pre-deduct pattern, there are no rollback sites that need this function.
     /// Retained as the symmetric operator tool alongside `deduct_burned_lp_from_balances`.
     pub fn credit_tokens_to_pool(&mut self, token_ledger: Principal, amount: u64) {
-        if amount == 0 {
-            return;
-        }
-        // Add back to aggregate
-        *self
-            .total_stablecoin_balances
-            .entry(token_ledger)
-            .or_insert(0) += amount;
-
-        // Distribute proportionally across depositors who hold this token
-        let holders: Vec<(Principal, u64)> = self
-            .deposits
-            .iter()
-            .filter_map(|(p, pos)| {
-                let bal = pos
-                    .stablecoin_balances
-                    .get(&token_ledger)
-                    .copied()
-                    .unwrap_or(0);
-                if bal > 0 {
-                    Some((*p, bal))
-                } else {
-                    None
-                }
-            })
-            .collect();
+        self.with_all_settled(|s| {
+            if amount == 0 {
+                return;
+            }
+            // Add back to aggregate
+            *s.total_stablecoin_balances.entry(token_ledger).or_insert(0) += amount;
 
-        if holders.is_empty() {
-            // Edge case: no holders, credit to first depositor
-            if let Some((first_p, _)) = self.deposits.iter().next() {
-                let first_p = *first_p;
-                if let Some(pos) = self.deposits.get_mut(&first_p) {
-                    *pos.stablecoin_balances.entry(token_ledger).or_insert(0) += amount;
+            // Distribute proportionally across depositors who hold this token
+            let holders: Vec<(Principal, u64)> = s
+                .deposits
+                .iter()
+                .filter_map(|(p, pos)| {
+                    let bal = pos
+                        .stablecoin_balances
+                        .get(&token_ledger)
+                        .copied()
+                        .unwrap_or(0);
+                    if bal > 0 {
+                        Some((*p, bal))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            if holders.is_empty() {
+                // Edge case: no holders, credit to first depositor
+                if let Some((first_p, _)) = s.deposits.iter().next() {
+                    let first_p = *first_p;
+                    if let Some(pos) = s.deposits.get_mut(&first_p) {
+                        *pos.stablecoin_balances.entry(token_ledger).or_insert(0) += amount;
+                    }
                 }
+                return;
             }
-            return;
-        }
 
-        let holder_total: u64 = holders.iter().map(|(_, b)| *b).sum();
-        let mut credited = 0u64;
-        for (principal, bal) in &holders {
-            let share = (amount as u128 * *bal as u128 / holder_total as u128) as u64;
-            if let Some(pos) = self.deposits.get_mut(principal) {
-                *pos.stablecoin_balances.entry(token_ledger).or_insert(0) += share;
+            let holder_total: u64 = holders.iter().map(|(_, b)| *b).sum();
+            let mut credited = 0u64;
+            for (principal, bal) in &holders {
+                let share = (amount as u128 * *bal as u128 / holder_total as u128) as u64;
+                if let Some(pos) = s.deposits.get_mut(principal) {
+                    *pos.stablecoin_balances.entry(token_ledger).or_insert(0) += share;
+                }
+                credited += share;
             }
-            credited += share;
-        }
 
-        // Assign dust to first holder
-        let dust = amount.saturating_sub(credited);
-        if dust > 0 {
-            if let Some((first_p, _)) = holders.first() {
-                if let Some(pos) = self.deposits.get_mut(first_p) {
-                    *pos.stablecoin_balances.entry(token_ledger).or_insert(0) += dust;
+            // Assign dust to first holder
+            let dust = amount.saturating_sub(credited);
+            if dust > 0 {
+                if let Some((first_p, _)) = holders.first() {
+                    if let Some(pos) = s.deposits.get_mut(first_p) {
+                        *pos.stablecoin_balances.entry(token_ledger).or_insert(0) += dust;
+                    }
                 }
             }
-        }
+        })
     }
 
     // ─── Collateral Gains ───
 
     pub fn get_collateral_gains(&self, user: &Principal) -> BTreeMap<Principal, u64> {
-        self.deposits
-            .get(user)
-            .map(|p| p.collateral_gains.clone())
+        self.position(user)
+            .map(|p| p.collateral_gains)
             .unwrap_or_default()
     }
 
@@ -937,18 +1779,20 @@ impl StabilityPoolState {
         collateral_ledger: &Principal,
         amount: u64,
     ) {
-        if let Some(position) = self.deposits.get_mut(user) {
-            if let Some(gains) = position.collateral_gains.get_mut(collateral_ledger) {
-                *gains = gains.saturating_sub(amount);
-                if *gains == 0 {
-                    position.collateral_gains.remove(collateral_ledger);
+        self.with_settled(user, |s| {
+            if let Some(position) = s.deposits.get_mut(user) {
+                if let Some(gains) = position.collateral_gains.get_mut(collateral_ledger) {
+                    *gains = gains.saturating_sub(amount);
+                    if *gains == 0 {
+                        position.collateral_gains.remove(collateral_ledger);
+                    }
                 }
+                *position
+                    .total_claimed_gains
+                    .entry(*collateral_ledger)
+                    .or_insert(0) += amount;
             }
-            *position
-                .total_claimed_gains
-                .entry(*collateral_ledger)
-                .or_insert(0) += amount;
-        }
+        })
     }
 
     pub fn mark_cfx_claimed(&mut self, user: &Principal, chain_sentinel: &Principal, amount: u128) {
@@ -1219,39 +2063,41 @@ impl StabilityPoolState {
         user: &Principal,
         collateral_type: Principal,
     ) -> Result<(), StabilityPoolError> {
-        if self.collateral_requires_payout_address(&collateral_type) {
-            let position = self
+        self.with_settled(user, |s| {
+            if s.collateral_requires_payout_address(&collateral_type) {
+                let position = s
+                    .deposits
+                    .get_mut(user)
+                    .ok_or(StabilityPoolError::NoPositionFound)?;
+                let removed = position
+                    .native_payout_addresses
+                    .get_or_insert_with(BTreeMap::new)
+                    .remove(&collateral_type);
+                position
+                    .native_payout_destination_tags
+                    .get_or_insert_with(BTreeMap::new)
+                    .remove(&collateral_type);
+                if removed.is_none() {
+                    return Err(StabilityPoolError::AlreadyOptedOut {
+                        collateral: collateral_type,
+                    });
+                }
+                return Ok(());
+            }
+            if s.is_chain_collateral_sentinel(&collateral_type) {
+                return s.opt_out_cfx(user, collateral_type);
+            }
+            let position = s
                 .deposits
                 .get_mut(user)
                 .ok_or(StabilityPoolError::NoPositionFound)?;
-            let removed = position
-                .native_payout_addresses
-                .get_or_insert_with(BTreeMap::new)
-                .remove(&collateral_type);
-            position
-                .native_payout_destination_tags
-                .get_or_insert_with(BTreeMap::new)
-                .remove(&collateral_type);
-            if removed.is_none() {
+            if !position.opted_out_collateral.insert(collateral_type) {
                 return Err(StabilityPoolError::AlreadyOptedOut {
                     collateral: collateral_type,
                 });
             }
-            return Ok(());
-        }
-        if self.is_chain_collateral_sentinel(&collateral_type) {
-            return self.opt_out_cfx(user, collateral_type);
-        }
-        let position = self
-            .deposits
-            .get_mut(user)
-            .ok_or(StabilityPoolError::NoPositionFound)?;
-        if !position.opted_out_collateral.insert(collateral_type) {
-            return Err(StabilityPoolError::AlreadyOptedOut {
-                collateral: collateral_type,
-            });
-        }
-        Ok(())
+            Ok(())
+        })
     }
 
     pub fn opt_in_collateral(
@@ -1259,32 +2105,34 @@ impl StabilityPoolState {
         user: &Principal,
         collateral_type: Principal,
     ) -> Result<(), StabilityPoolError> {
-        // BOB is in wind-down. Existing receiving positions may leave through
-        // `opt_out_collateral`, but neither a new nor former participant may
-        // create fresh BOB liquidation exposure.
-        if collateral_type == bob_collateral() {
-            return Err(StabilityPoolError::TokenNotActive {
-                ledger: collateral_type,
-            });
-        }
-        if self.collateral_requires_payout_address(&collateral_type) {
-            return Err(StabilityPoolError::PayoutAddressRequired {
-                collateral: collateral_type,
-            });
-        }
-        if self.is_chain_collateral_sentinel(&collateral_type) {
-            return self.opt_in_cfx(user, collateral_type);
-        }
-        let position = self
-            .deposits
-            .get_mut(user)
-            .ok_or(StabilityPoolError::NoPositionFound)?;
-        if !position.opted_out_collateral.remove(&collateral_type) {
-            return Err(StabilityPoolError::AlreadyOptedIn {
-                collateral: collateral_type,
-            });
-        }
-        Ok(())
+        self.with_settled(user, |s| {
+            // BOB is in wind-down. Existing receiving positions may leave through
+            // `opt_out_collateral`, but neither a new nor former participant may
+            // create fresh BOB liquidation exposure.
+            if collateral_type == bob_collateral() {
+                return Err(StabilityPoolError::TokenNotActive {
+                    ledger: collateral_type,
+                });
+            }
+            if s.collateral_requires_payout_address(&collateral_type) {
+                return Err(StabilityPoolError::PayoutAddressRequired {
+                    collateral: collateral_type,
+                });
+            }
+            if s.is_chain_collateral_sentinel(&collateral_type) {
+                return s.opt_in_cfx(user, collateral_type);
+            }
+            let position = s
+                .deposits
+                .get_mut(user)
+                .ok_or(StabilityPoolError::NoPositionFound)?;
+            if !position.opted_out_collateral.remove(&collateral_type) {
+                return Err(StabilityPoolError::AlreadyOptedIn {
+                    collateral: collateral_type,
+                });
+            }
+            Ok(())
+        })
     }
 
     pub fn opt_in_cfx(
@@ -1292,22 +2140,24 @@ impl StabilityPoolState {
         user: &Principal,
         sentinel: Principal,
     ) -> Result<(), StabilityPoolError> {
-        if !self.is_chain_collateral_sentinel(&sentinel) {
-            return Err(StabilityPoolError::CollateralNotFound { ledger: sentinel });
-        }
-        let position = self
-            .deposits
-            .get_mut(user)
-            .ok_or(StabilityPoolError::NoPositionFound)?;
-        let opted_in = position
-            .opted_in_chain_collateral
-            .get_or_insert_with(BTreeSet::new);
-        if !opted_in.insert(sentinel) {
-            return Err(StabilityPoolError::AlreadyOptedIn {
-                collateral: sentinel,
-            });
-        }
-        Ok(())
+        self.with_settled(user, |s| {
+            if !s.is_chain_collateral_sentinel(&sentinel) {
+                return Err(StabilityPoolError::CollateralNotFound { ledger: sentinel });
+            }
+            let position = s
+                .deposits
+                .get_mut(user)
+                .ok_or(StabilityPoolError::NoPositionFound)?;
+            let opted_in = position
+                .opted_in_chain_collateral
+                .get_or_insert_with(BTreeSet::new);
+            if !opted_in.insert(sentinel) {
+                return Err(StabilityPoolError::AlreadyOptedIn {
+                    collateral: sentinel,
+                });
+            }
+            Ok(())
+        })
     }
 
     pub fn opt_out_cfx(
@@ -1315,22 +2165,24 @@ impl StabilityPoolState {
         user: &Principal,
         sentinel: Principal,
     ) -> Result<(), StabilityPoolError> {
-        if !self.is_chain_collateral_sentinel(&sentinel) {
-            return Err(StabilityPoolError::CollateralNotFound { ledger: sentinel });
-        }
-        let position = self
-            .deposits
-            .get_mut(user)
-            .ok_or(StabilityPoolError::NoPositionFound)?;
-        let opted_in = position
-            .opted_in_chain_collateral
-            .get_or_insert_with(BTreeSet::new);
-        if !opted_in.remove(&sentinel) {
-            return Err(StabilityPoolError::AlreadyOptedOut {
-                collateral: sentinel,
-            });
-        }
-        Ok(())
+        self.with_settled(user, |s| {
+            if !s.is_chain_collateral_sentinel(&sentinel) {
+                return Err(StabilityPoolError::CollateralNotFound { ledger: sentinel });
+            }
+            let position = s
+                .deposits
+                .get_mut(user)
+                .ok_or(StabilityPoolError::NoPositionFound)?;
+            let opted_in = position
+                .opted_in_chain_collateral
+                .get_or_insert_with(BTreeSet::new);
+            if !opted_in.remove(&sentinel) {
+                return Err(StabilityPoolError::AlreadyOptedOut {
+                    collateral: sentinel,
+                });
+            }
+            Ok(())
+        })
     }
 
     pub fn opt_in_native_collateral(
@@ -1349,35 +2201,37 @@ impl StabilityPoolState {
         payout_address: String,
         destination_tag: Option<u32>,
     ) -> Result<(), StabilityPoolError> {
-        if !self.collateral_requires_payout_address(&collateral_type) {
-            return self.opt_in_collateral(user, collateral_type);
-        }
-
-        let address = payout_address.trim().to_string();
-        rumi_protocol_backend::chains::xrp::address::account_id_from_classic_address(&address)
-            .map_err(|reason| StabilityPoolError::InvalidPayoutAddress { reason })?;
-
-        let position = self
-            .deposits
-            .get_mut(user)
-            .ok_or(StabilityPoolError::NoPositionFound)?;
-        position.opted_out_collateral.remove(&collateral_type);
-        position
-            .native_payout_addresses
-            .get_or_insert_with(BTreeMap::new)
-            .insert(collateral_type, address);
-        let tags = position
-            .native_payout_destination_tags
-            .get_or_insert_with(BTreeMap::new);
-        match destination_tag {
-            Some(tag) => {
-                tags.insert(collateral_type, tag);
+        self.with_settled(user, |s| {
+            if !s.collateral_requires_payout_address(&collateral_type) {
+                return s.opt_in_collateral(user, collateral_type);
             }
-            None => {
-                tags.remove(&collateral_type);
+
+            let address = payout_address.trim().to_string();
+            rumi_common::chains::account_id_from_classic_address(&address)
+                .map_err(|reason| StabilityPoolError::InvalidPayoutAddress { reason })?;
+
+            let position = s
+                .deposits
+                .get_mut(user)
+                .ok_or(StabilityPoolError::NoPositionFound)?;
+            position.opted_out_collateral.remove(&collateral_type);
+            position
+                .native_payout_addresses
+                .get_or_insert_with(BTreeMap::new)
+                .insert(collateral_type, address);
+            let tags = position
+                .native_payout_destination_tags
+                .get_or_insert_with(BTreeMap::new);
+            match destination_tag {
+                Some(tag) => {
+                    tags.insert(collateral_type, tag);
+                }
+                None => {
+                    tags.remove(&collateral_type);
+                }
             }
-        }
-        Ok(())
+            Ok(())
+        })
     }
 
     pub fn build_native_xrp_payout_allocations(
@@ -1398,8 +2252,8 @@ impl StabilityPoolState {
             });
         }
 
-        let mut eligible_principals: Vec<Principal> = self
-            .deposits
+        let deposits: BTreeMap<Principal, DepositPosition> = self.positions().collect();
+        let mut eligible_principals: Vec<Principal> = deposits
             .iter()
             .filter(|(_, pos)| self.position_opted_in_for(pos, &collateral_type))
             .filter(|(_, pos)| {
@@ -1418,7 +2272,7 @@ impl StabilityPoolState {
         for token_ledger in stables_consumed.keys() {
             let total: u64 = eligible_principals
                 .iter()
-                .filter_map(|principal| self.deposits.get(principal))
+                .filter_map(|principal| deposits.get(principal))
                 .map(|pos| {
                     pos.stablecoin_balances
                         .get(token_ledger)
@@ -1463,7 +2317,7 @@ impl StabilityPoolState {
 
         let mut candidates: Vec<Candidate> = Vec::new();
         for principal in eligible_principals {
-            let Some(position) = self.deposits.get(&principal) else {
+            let Some(position) = deposits.get(&principal) else {
                 continue;
             };
             let payout_address = position
@@ -1692,11 +2546,25 @@ impl StabilityPoolState {
 
     /// Compute total opted-in stablecoin value (e8s) for a given collateral type.
     pub fn effective_pool_for_collateral(&self, collateral_type: &Principal) -> u64 {
-        let vps = self.virtual_prices();
-        self.deposits
-            .values()
-            .filter(|pos| self.position_opted_in_for(pos, collateral_type))
-            .map(|pos| pos.total_usd_value(&self.stablecoin_registry, vps))
+        self.stablecoin_registry
+            .keys()
+            .map(|ledger| {
+                self.stable_usd_e8s(ledger, self.opted_in_token_total(collateral_type, ledger))
+            })
+            .sum()
+    }
+
+    /// Depositors whose positions would absorb a liquidation of `collateral_type`.
+    pub fn opted_in_depositor_count(&self, collateral_type: &Principal) -> u64 {
+        self.loss_cohorts()
+            .filter(|cohort| self.cohort_opted_in_for(cohort, collateral_type))
+            .filter(|cohort| {
+                cohort
+                    .tokens
+                    .iter()
+                    .any(|(ledger, tokens)| self.stable_usd_e8s(ledger, tokens.total) > 0)
+            })
+            .map(|cohort| cohort.members)
             .sum()
     }
 
@@ -1710,11 +2578,9 @@ impl StabilityPoolState {
     /// Compute opted-in icUSD coverage only. Chain-native liquidations use
     /// this instead of the mixed-token draw so Inc 4 burns only IC-native icUSD.
     pub fn effective_icusd_pool_for_collateral(&self, collateral_type: &Principal) -> u64 {
-        self.deposits
-            .values()
-            .filter(|pos| self.position_opted_in_for(pos, collateral_type))
-            .map(|pos| pos.icusd_value(&self.stablecoin_registry))
-            .sum()
+        self.icusd_ledger().map_or(0, |icusd_ledger| {
+            self.opted_in_token_total(collateral_type, &icusd_ledger)
+        })
     }
 
     // ─── Liquidation Processing ───
@@ -1737,12 +2603,7 @@ impl StabilityPoolState {
         let mut all_tokens: Vec<(Principal, u64, u8, bool, u64)> = Vec::new();
 
         for (ledger, config) in &self.stablecoin_registry {
-            let available_native: u64 = self
-                .deposits
-                .values()
-                .filter(|pos| self.position_opted_in_for(pos, collateral_type))
-                .map(|pos| pos.stablecoin_balances.get(ledger).copied().unwrap_or(0))
-                .sum();
+            let available_native = self.opted_in_token_total(collateral_type, ledger);
             if available_native > 0 {
                 let is_lp = config.is_lp_token.unwrap_or(false);
                 let available_e8s = if is_lp {
@@ -1801,12 +2662,7 @@ impl StabilityPoolState {
         // Group by priority
         let mut priority_buckets: BTreeMap<u8, Vec<(Principal, u64, u8, bool)>> = BTreeMap::new();
         for (ledger, config) in &self.stablecoin_registry {
-            let available_native: u64 = self
-                .deposits
-                .values()
-                .filter(|pos| self.position_opted_in_for(pos, collateral_type))
-                .map(|pos| pos.stablecoin_balances.get(ledger).copied().unwrap_or(0))
-                .sum();
+            let available_native = self.opted_in_token_total(collateral_type, ledger);
             if available_native > 0 {
                 let is_lp = config.is_lp_token.unwrap_or(false);
                 priority_buckets.entry(config.priority).or_default().push((
@@ -1948,228 +2804,231 @@ impl StabilityPoolState {
         payout_claims: &[XrpSpPayoutClaim],
         timestamp: u64,
     ) -> Result<(), StabilityPoolError> {
-        if !self.collateral_requires_payout_address(&collateral_type) {
-            return Err(StabilityPoolError::PayoutAddressRequired {
-                collateral: collateral_type,
-            });
-        }
-        if collateral_received_drops == 0 || payout_claims.is_empty() {
-            return Err(StabilityPoolError::LiquidationFailed {
-                vault_id,
-                reason: "native XRP absorb has no payout allocations".to_string(),
-            });
-        }
-        if payout_claims.len() > MAX_XRP_SP_PAYOUT_ALLOCATIONS {
-            return Err(StabilityPoolError::LiquidationFailed {
-                vault_id,
-                reason: format!(
-                    "native XRP payout fanout {} exceeds max {}",
-                    payout_claims.len(),
-                    MAX_XRP_SP_PAYOUT_ALLOCATIONS
-                ),
-            });
-        }
-
-        let mut payout_sum = 0u64;
-        let mut seen_claim_ids = BTreeSet::new();
-        let mut seen_claimants = BTreeSet::new();
-        for claim in payout_claims {
-            if claim.drops == 0 || claim.payout_address.trim().is_empty() {
+        self.with_all_settled(|s| {
+            if !s.collateral_requires_payout_address(&collateral_type) {
+                return Err(StabilityPoolError::PayoutAddressRequired {
+                    collateral: collateral_type,
+                });
+            }
+            if collateral_received_drops == 0 || payout_claims.is_empty() {
                 return Err(StabilityPoolError::LiquidationFailed {
                     vault_id,
-                    reason: "native XRP payout claim has invalid amount or address".to_string(),
+                    reason: "native XRP absorb has no payout allocations".to_string(),
                 });
             }
-            if !seen_claim_ids.insert(claim.claim_id) || !seen_claimants.insert(claim.claimant) {
+            if payout_claims.len() > MAX_XRP_SP_PAYOUT_ALLOCATIONS {
                 return Err(StabilityPoolError::LiquidationFailed {
                     vault_id,
-                    reason: "native XRP payout claims contain duplicate ids or claimants"
-                        .to_string(),
+                    reason: format!(
+                        "native XRP payout fanout {} exceeds max {}",
+                        payout_claims.len(),
+                        MAX_XRP_SP_PAYOUT_ALLOCATIONS
+                    ),
                 });
             }
-            let opted_in = self
-                .deposits
-                .get(&claim.claimant)
-                .map(|pos| self.position_opted_in_for(pos, &collateral_type))
-                .unwrap_or(false);
-            if !opted_in {
+
+            let mut payout_sum = 0u64;
+            let mut seen_claim_ids = BTreeSet::new();
+            let mut seen_claimants = BTreeSet::new();
+            for claim in payout_claims {
+                if claim.drops == 0 || claim.payout_address.trim().is_empty() {
+                    return Err(StabilityPoolError::LiquidationFailed {
+                        vault_id,
+                        reason: "native XRP payout claim has invalid amount or address".to_string(),
+                    });
+                }
+                if !seen_claim_ids.insert(claim.claim_id) || !seen_claimants.insert(claim.claimant)
+                {
+                    return Err(StabilityPoolError::LiquidationFailed {
+                        vault_id,
+                        reason: "native XRP payout claims contain duplicate ids or claimants"
+                            .to_string(),
+                    });
+                }
+                let opted_in = s
+                    .deposits
+                    .get(&claim.claimant)
+                    .map(|pos| s.position_opted_in_for(pos, &collateral_type))
+                    .unwrap_or(false);
+                if !opted_in {
+                    return Err(StabilityPoolError::LiquidationFailed {
+                        vault_id,
+                        reason: "backend returned native XRP payout for non-opted-in depositor"
+                            .to_string(),
+                    });
+                }
+                payout_sum = payout_sum.saturating_add(claim.drops);
+            }
+            if payout_sum != collateral_received_drops {
                 return Err(StabilityPoolError::LiquidationFailed {
                     vault_id,
-                    reason: "backend returned native XRP payout for non-opted-in depositor"
+                    reason: "backend native XRP payout sum does not match preflight collateral"
                         .to_string(),
                 });
             }
-            payout_sum = payout_sum.saturating_add(claim.drops);
-        }
-        if payout_sum != collateral_received_drops {
-            return Err(StabilityPoolError::LiquidationFailed {
-                vault_id,
-                reason: "backend native XRP payout sum does not match preflight collateral"
-                    .to_string(),
-            });
-        }
 
-        let mut opted_in_principals: Vec<Principal> = self
-            .deposits
-            .iter()
-            .filter(|(_, pos)| self.position_opted_in_for(pos, &collateral_type))
-            .filter(|(_, pos)| {
-                stables_consumed
-                    .keys()
-                    .any(|token| pos.stablecoin_balances.get(token).copied().unwrap_or(0) > 0)
-            })
-            .map(|(principal, _)| *principal)
-            .collect();
-        opted_in_principals.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
-        if opted_in_principals.is_empty() {
-            return Err(StabilityPoolError::InsufficientPoolBalance);
-        }
+            let mut opted_in_principals: Vec<Principal> = s
+                .deposits
+                .iter()
+                .filter(|(_, pos)| s.position_opted_in_for(pos, &collateral_type))
+                .filter(|(_, pos)| {
+                    stables_consumed
+                        .keys()
+                        .any(|token| pos.stablecoin_balances.get(token).copied().unwrap_or(0) > 0)
+                })
+                .map(|(principal, _)| *principal)
+                .collect();
+            opted_in_principals.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+            if opted_in_principals.is_empty() {
+                return Err(StabilityPoolError::InsufficientPoolBalance);
+            }
 
-        let mut per_token_opted_in_totals: BTreeMap<Principal, u64> = BTreeMap::new();
-        for token_ledger in stables_consumed.keys() {
-            let total: u64 = opted_in_principals
+            let mut per_token_opted_in_totals: BTreeMap<Principal, u64> = BTreeMap::new();
+            for token_ledger in stables_consumed.keys() {
+                let total: u64 = opted_in_principals
+                    .iter()
+                    .filter_map(|principal| s.deposits.get(principal))
+                    .map(|pos| {
+                        pos.stablecoin_balances
+                            .get(token_ledger)
+                            .copied()
+                            .unwrap_or(0)
+                    })
+                    .sum();
+                per_token_opted_in_totals.insert(*token_ledger, total);
+            }
+
+            let vps = s.virtual_prices().clone();
+            let registry_snapshot: BTreeMap<Principal, (u8, bool)> = stables_consumed
+                .keys()
+                .filter_map(|ledger| {
+                    s.stablecoin_registry
+                        .get(ledger)
+                        .map(|c| (*ledger, (c.decimals, c.is_lp_token.unwrap_or(false))))
+                })
+                .collect();
+            let total_consumed_e8s: u64 = stables_consumed
                 .iter()
-                .filter_map(|principal| self.deposits.get(principal))
-                .map(|pos| {
-                    pos.stablecoin_balances
-                        .get(token_ledger)
-                        .copied()
-                        .unwrap_or(0)
+                .map(|(ledger, &amount)| {
+                    let (decimals, is_lp) =
+                        registry_snapshot.get(ledger).copied().unwrap_or((8, false));
+                    if is_lp {
+                        vps.get(ledger)
+                            .map(|&vp| lp_to_usd_e8s(amount, vp))
+                            .unwrap_or(0)
+                    } else {
+                        normalize_to_e8s(amount, decimals)
+                    }
                 })
                 .sum();
-            per_token_opted_in_totals.insert(*token_ledger, total);
-        }
+            if total_consumed_e8s == 0 {
+                return Err(StabilityPoolError::InsufficientPoolBalance);
+            }
 
-        let vps = self.virtual_prices().clone();
-        let registry_snapshot: BTreeMap<Principal, (u8, bool)> = stables_consumed
-            .keys()
-            .filter_map(|ledger| {
-                self.stablecoin_registry
-                    .get(ledger)
-                    .map(|c| (*ledger, (c.decimals, c.is_lp_token.unwrap_or(false))))
-            })
-            .collect();
-        let total_consumed_e8s: u64 = stables_consumed
-            .iter()
-            .map(|(ledger, &amount)| {
-                let (decimals, is_lp) =
-                    registry_snapshot.get(ledger).copied().unwrap_or((8, false));
-                if is_lp {
-                    vps.get(ledger)
-                        .map(|&vp| lp_to_usd_e8s(amount, vp))
-                        .unwrap_or(0)
-                } else {
-                    normalize_to_e8s(amount, decimals)
+            let mut actual_deductions_per_token: BTreeMap<Principal, u64> = BTreeMap::new();
+            for principal in &opted_in_principals {
+                if let Some(position) = s.deposits.get_mut(principal) {
+                    for (token_ledger, &total_consumed) in stables_consumed {
+                        let total_opted_in = per_token_opted_in_totals
+                            .get(token_ledger)
+                            .copied()
+                            .unwrap_or(0);
+                        if total_opted_in == 0 {
+                            continue;
+                        }
+                        let user_balance = position
+                            .stablecoin_balances
+                            .get(token_ledger)
+                            .copied()
+                            .unwrap_or(0);
+                        if user_balance == 0 {
+                            continue;
+                        }
+
+                        let user_share_native = (total_consumed as u128 * user_balance as u128
+                            / total_opted_in as u128)
+                            as u64;
+                        let user_share_native = user_share_native.min(user_balance);
+                        if let Some(balance) = position.stablecoin_balances.get_mut(token_ledger) {
+                            *balance = balance.saturating_sub(user_share_native);
+                        }
+                        *actual_deductions_per_token
+                            .entry(*token_ledger)
+                            .or_insert(0) += user_share_native;
+                    }
                 }
-            })
-            .sum();
-        if total_consumed_e8s == 0 {
-            return Err(StabilityPoolError::InsufficientPoolBalance);
-        }
+            }
 
-        let mut actual_deductions_per_token: BTreeMap<Principal, u64> = BTreeMap::new();
-        for principal in &opted_in_principals {
-            if let Some(position) = self.deposits.get_mut(principal) {
-                for (token_ledger, &total_consumed) in stables_consumed {
-                    let total_opted_in = per_token_opted_in_totals
-                        .get(token_ledger)
-                        .copied()
-                        .unwrap_or(0);
-                    if total_opted_in == 0 {
-                        continue;
+            for (token_ledger, &total_consumed) in stables_consumed {
+                let actual_deducted = actual_deductions_per_token
+                    .get(token_ledger)
+                    .copied()
+                    .unwrap_or(0);
+                let mut remaining = total_consumed.saturating_sub(actual_deducted);
+                if remaining == 0 {
+                    continue;
+                }
+
+                for principal in &opted_in_principals {
+                    if remaining == 0 {
+                        break;
                     }
-                    let user_balance = position
-                        .stablecoin_balances
-                        .get(token_ledger)
-                        .copied()
-                        .unwrap_or(0);
-                    if user_balance == 0 {
+                    let Some(position) = s.deposits.get_mut(principal) else {
+                        continue;
+                    };
+                    let Some(balance) = position.stablecoin_balances.get_mut(token_ledger) else {
+                        continue;
+                    };
+                    if *balance == 0 {
                         continue;
                     }
-
-                    let user_share_native = (total_consumed as u128 * user_balance as u128
-                        / total_opted_in as u128)
-                        as u64;
-                    let user_share_native = user_share_native.min(user_balance);
-                    if let Some(balance) = position.stablecoin_balances.get_mut(token_ledger) {
-                        *balance = balance.saturating_sub(user_share_native);
-                    }
+                    let extra = remaining.min(*balance);
+                    *balance = balance.saturating_sub(extra);
                     *actual_deductions_per_token
                         .entry(*token_ledger)
-                        .or_insert(0) += user_share_native;
+                        .or_insert(0) += extra;
+                    remaining -= extra;
                 }
-            }
-        }
-
-        for (token_ledger, &total_consumed) in stables_consumed {
-            let actual_deducted = actual_deductions_per_token
-                .get(token_ledger)
-                .copied()
-                .unwrap_or(0);
-            let mut remaining = total_consumed.saturating_sub(actual_deducted);
-            if remaining == 0 {
-                continue;
-            }
 
-            for principal in &opted_in_principals {
-                if remaining == 0 {
-                    break;
-                }
-                let Some(position) = self.deposits.get_mut(principal) else {
-                    continue;
-                };
-                let Some(balance) = position.stablecoin_balances.get_mut(token_ledger) else {
-                    continue;
-                };
-                if *balance == 0 {
-                    continue;
+                if remaining > 0 {
+                    return Err(StabilityPoolError::LiquidationFailed {
+                        vault_id,
+                        reason: "native XRP absorb could not deduct full burned stablecoin amount"
+                            .to_string(),
+                    });
                 }
-                let extra = remaining.min(*balance);
-                *balance = balance.saturating_sub(extra);
-                *actual_deductions_per_token
-                    .entry(*token_ledger)
-                    .or_insert(0) += extra;
-                remaining -= extra;
             }
 
-            if remaining > 0 {
-                return Err(StabilityPoolError::LiquidationFailed {
-                    vault_id,
-                    reason: "native XRP absorb could not deduct full burned stablecoin amount"
-                        .to_string(),
-                });
+            for (token_ledger, actual_deducted) in &actual_deductions_per_token {
+                if let Some(total) = s.total_stablecoin_balances.get_mut(token_ledger) {
+                    *total = total.saturating_sub(*actual_deducted);
+                }
             }
-        }
 
-        for (token_ledger, actual_deducted) in &actual_deductions_per_token {
-            if let Some(total) = self.total_stablecoin_balances.get_mut(token_ledger) {
-                *total = total.saturating_sub(*actual_deducted);
+            for claim in payout_claims {
+                s.record_native_xrp_pending_payout(
+                    claim.claimant,
+                    NativeXrpPendingPayout {
+                        claim_id: claim.claim_id,
+                        collateral_type,
+                        vault_id,
+                        drops: claim.drops,
+                        payout_address: claim.payout_address.clone(),
+                        destination_tag: claim.destination_tag,
+                        created_at_ns: timestamp,
+                    },
+                )?;
             }
-        }
-
-        for claim in payout_claims {
-            self.record_native_xrp_pending_payout(
-                claim.claimant,
-                NativeXrpPendingPayout {
-                    claim_id: claim.claim_id,
-                    collateral_type,
-                    vault_id,
-                    drops: claim.drops,
-                    payout_address: claim.payout_address.clone(),
-                    destination_tag: claim.destination_tag,
-                    created_at_ns: timestamp,
-                },
-            )?;
-        }
 
-        self.total_liquidations_executed += 1;
-        self.deposits.retain(|_, pos| !pos.is_empty());
-        debug_assert!(
-            self.validate_state().is_ok(),
-            "stability pool aggregate/per-depositor invariant violated after \
-             process_native_xrp_absorb_success_at"
-        );
-        Ok(())
+            s.total_liquidations_executed += 1;
+            s.deposits.retain(|_, pos| !pos.is_empty());
+            debug_assert!(
+                s.validate_state().is_ok(),
+                "stability pool aggregate/per-depositor invariant violated after \
+                 process_native_xrp_absorb_success_at"
+            );
+            Ok(())
+        })
     }
 
     /// Core liquidation gain processing logic with explicit timestamp (testable without IC runtime).
@@ -2193,28 +3052,27 @@ impl StabilityPoolState {
             return;
         }
 
-        // Phase 1: Compute each opted-in depositor's share of the consumed stables (in e8s)
-        let opted_in_principals: Vec<Principal> = self
-            .deposits
+        // Rewards up to now are owed to the balances before they shrink.
+        self.accrue_rewards(timestamp);
+
+        // Phase 1: Find the cohorts that absorb this collateral. Each cohort
+        // is charged as a whole; its members' balances follow from its
+        // running product when they are next settled.
+        let eligible_cohorts: Vec<u64> = self
+            .loss_cohorts
             .iter()
-            .filter(|(_, pos)| self.position_opted_in_for(pos, &collateral_type))
-            .map(|(p, _)| *p)
+            .flat_map(|cohorts| cohorts.iter())
+            .filter(|(_, cohort)| self.cohort_opted_in_for(cohort, &collateral_type))
+            .map(|(id, _)| *id)
             .collect();
 
         // For each consumed token, compute total opted-in balance for that token
         let mut per_token_opted_in_totals: BTreeMap<Principal, u64> = BTreeMap::new();
         for token_ledger in stables_consumed.keys() {
-            let total: u64 = opted_in_principals
-                .iter()
-                .filter_map(|p| self.deposits.get(p))
-                .map(|pos| {
-                    pos.stablecoin_balances
-                        .get(token_ledger)
-                        .copied()
-                        .unwrap_or(0)
-                })
-                .sum();
-            per_token_opted_in_totals.insert(*token_ledger, total);
+            per_token_opted_in_totals.insert(
+                *token_ledger,
+                self.opted_in_token_total(&collateral_type, token_ledger),
+            );
         }
 
         // Phase 2: Compute total e8s consumed to determine collateral distribution shares.
@@ -2229,104 +3087,78 @@ impl StabilityPoolState {
                     .map(|c| (*ledger, (c.decimals, c.is_lp_token.unwrap_or(false))))
             })
             .collect();
+        let consumed_e8s = |ledger: &Principal, amount: u64| -> u64 {
+            let (decimals, is_lp) = registry_snapshot.get(ledger).copied().unwrap_or((8, false));
+            if is_lp {
+                vps.get(ledger)
+                    .map(|&vp| lp_to_usd_e8s(amount, vp))
+                    .unwrap_or(0)
+            } else {
+                normalize_to_e8s(amount, decimals)
+            }
+        };
         let total_consumed_e8s: u64 = stables_consumed
             .iter()
-            .map(|(ledger, &amount)| {
-                let (decimals, is_lp) =
-                    registry_snapshot.get(ledger).copied().unwrap_or((8, false));
-                if is_lp {
-                    vps.get(ledger)
-                        .map(|&vp| lp_to_usd_e8s(amount, vp))
-                        .unwrap_or(0)
-                } else {
-                    normalize_to_e8s(amount, decimals)
-                }
-            })
+            .map(|(ledger, &amount)| consumed_e8s(ledger, amount))
             .sum();
 
         if total_consumed_e8s == 0 {
             return;
         }
 
-        // Phase 3: For each opted-in depositor, reduce their token balances and add collateral gains.
-        // Track actual deductions per token to avoid rounding drift between aggregate and individual totals.
+        // Phase 3: Split each token's consumption across the cohorts holding it
+        // and pay collateral in proportion to the e8s each cohort gave up.
+        // Track actual deductions per token to avoid rounding drift between
+        // aggregate and cohort totals.
+        let mut parts: Vec<(u64, Principal, u64, u64)> = Vec::new();
         let mut actual_deductions_per_token: BTreeMap<Principal, u64> = BTreeMap::new();
         let mut total_collateral_distributed: u64 = 0;
+        let mut depositors_count: u64 = 0;
 
-        for principal in &opted_in_principals {
-            let mut user_consumed_e8s: u64 = 0;
-
-            if let Some(position) = self.deposits.get_mut(principal) {
-                for (token_ledger, &total_consumed) in stables_consumed {
-                    let total_opted_in = per_token_opted_in_totals
-                        .get(token_ledger)
-                        .copied()
-                        .unwrap_or(0);
-                    if total_opted_in == 0 {
-                        continue;
-                    }
-                    let user_balance = position
-                        .stablecoin_balances
-                        .get(token_ledger)
-                        .copied()
-                        .unwrap_or(0);
-                    if user_balance == 0 {
-                        continue;
-                    }
-
-                    // User's share of this token's consumption
-                    let user_share_native = (total_consumed as u128 * user_balance as u128
-                        / total_opted_in as u128)
-                        as u64;
-                    let user_share_native = user_share_native.min(user_balance);
-
-                    // Reduce balance
-                    if let Some(bal) = position.stablecoin_balances.get_mut(token_ledger) {
-                        *bal = bal.saturating_sub(user_share_native);
-                    }
-
-                    // Track actual deduction for aggregate update
-                    *actual_deductions_per_token
-                        .entry(*token_ledger)
-                        .or_insert(0) += user_share_native;
-
-                    // Track consumed value in e8s for collateral distribution.
-                    // LP tokens valued at virtual price, not face value.
-                    let (decimals, is_lp) = registry_snapshot
-                        .get(token_ledger)
-                        .copied()
-                        .unwrap_or((8, false));
-                    let share_e8s = if is_lp {
-                        vps.get(token_ledger)
-                            .map(|&vp| lp_to_usd_e8s(user_share_native, vp))
-                            .unwrap_or(0)
-                    } else {
-                        normalize_to_e8s(user_share_native, decimals)
-                    };
-                    user_consumed_e8s += share_e8s;
+        for id in &eligible_cohorts {
+            let cohort = &self.loss_cohorts.as_ref().expect("eligible cohort")[id];
+            depositors_count += cohort.members;
+            for (token_ledger, &total_consumed) in stables_consumed {
+                let total_opted_in = per_token_opted_in_totals
+                    .get(token_ledger)
+                    .copied()
+                    .unwrap_or(0);
+                let holding = cohort.total(token_ledger);
+                if total_opted_in == 0 || holding == 0 {
+                    continue;
                 }
 
-                // Distribute collateral proportional to e8s consumed
-                if user_consumed_e8s > 0 {
-                    let user_collateral = (collateral_gained as u128 * user_consumed_e8s as u128
-                        / total_consumed_e8s as u128)
-                        as u64;
-                    *position
-                        .collateral_gains
-                        .entry(collateral_type)
-                        .or_insert(0) += user_collateral;
-                    total_collateral_distributed += user_collateral;
-                }
+                // Cohort's share of this token's consumption
+                let loss =
+                    (total_consumed as u128 * holding as u128 / total_opted_in as u128) as u64;
+                let loss = loss.min(holding);
+                *actual_deductions_per_token
+                    .entry(*token_ledger)
+                    .or_insert(0) += loss;
+
+                let gain = (collateral_gained as u128 * consumed_e8s(token_ledger, loss) as u128
+                    / total_consumed_e8s as u128) as u64;
+                total_collateral_distributed += gain;
+                parts.push((*id, *token_ledger, loss, gain));
             }
         }
 
-        // Phase 3b: Assign collateral rounding dust to first opted-in depositor
+        // Phase 3b: Assign collateral rounding dust to the first cohort that lost anything
         let collateral_dust = collateral_gained.saturating_sub(total_collateral_distributed);
-        if collateral_dust > 0 {
-            if let Some(first) = opted_in_principals.first() {
-                if let Some(pos) = self.deposits.get_mut(first) {
-                    *pos.collateral_gains.entry(collateral_type).or_insert(0) += collateral_dust;
-                }
+        if let Some(first) = parts.iter_mut().find(|(_, _, loss, _)| *loss > 0) {
+            first.3 += collateral_dust;
+        }
+
+        let cohorts = self.loss_cohorts.get_or_insert_with(BTreeMap::new);
+        for (id, token_ledger, loss, gain) in parts {
+            let Some(cohort) = cohorts.get_mut(&id) else {
+                continue;
+            };
+            if let Some(tokens) = cohort.tokens.get_mut(&token_ledger) {
+                tokens.absorb(loss, &[(collateral_type, gain)]);
+            }
+            if gain > 0 {
+                cohort.gains_credited_at.insert(collateral_type, timestamp);
             }
         }
 
@@ -2345,7 +3177,7 @@ impl StabilityPoolState {
             stables_consumed: stables_consumed.clone(),
             collateral_gained,
             collateral_type,
-            depositors_count: opted_in_principals.len() as u64,
+            depositors_count,
             collateral_price_e8s: Some(collateral_price_e8s),
         };
         self.liquidation_history.push(record);
@@ -2357,9 +3189,6 @@ impl StabilityPoolState {
             self.liquidation_history.drain(..excess);
         }
 
-        // Phase 6: Clean up empty positions
-        self.deposits.retain(|_, pos| !pos.is_empty());
-
         // SP-001 regression fence: per-depositor balances must sum to the
         // aggregate total after the full gains pass. Violations indicate a
         // double-deduction or divergent-update bug (debug builds only — the
@@ -2383,169 +3212,176 @@ impl StabilityPoolState {
         _collateral_price_e8s: u64,
         _timestamp: u64,
     ) {
-        if !self.is_chain_collateral_sentinel(&chain_sentinel) || cfx_gained_native == 0 {
-            return;
-        }
+        self.with_all_settled(|s| {
+            if !s.is_chain_collateral_sentinel(&chain_sentinel) || cfx_gained_native == 0 {
+                return;
+            }
 
-        let opted_in_principals: Vec<Principal> = self
-            .deposits
-            .iter()
-            .filter(|(_, pos)| pos.is_opted_in_for_chain(&chain_sentinel))
-            .map(|(p, _)| *p)
-            .collect();
-        if opted_in_principals.is_empty() {
-            return;
-        }
+            let opted_in_principals: Vec<Principal> = s
+                .deposits
+                .iter()
+                .filter(|(_, pos)| pos.is_opted_in_for_chain(&chain_sentinel))
+                .map(|(p, _)| *p)
+                .collect();
+            if opted_in_principals.is_empty() {
+                return;
+            }
 
-        let mut per_token_opted_in_totals: BTreeMap<Principal, u64> = BTreeMap::new();
-        for token_ledger in stables_consumed.keys() {
-            let total: u64 = opted_in_principals
+            let mut per_token_opted_in_totals: BTreeMap<Principal, u64> = BTreeMap::new();
+            for token_ledger in stables_consumed.keys() {
+                let total: u64 = opted_in_principals
+                    .iter()
+                    .filter_map(|p| s.deposits.get(p))
+                    .map(|pos| {
+                        pos.stablecoin_balances
+                            .get(token_ledger)
+                            .copied()
+                            .unwrap_or(0)
+                    })
+                    .sum();
+                per_token_opted_in_totals.insert(*token_ledger, total);
+            }
+
+            let vps = s.virtual_prices().clone();
+            let registry_snapshot: BTreeMap<Principal, (u8, bool)> = stables_consumed
+                .keys()
+                .filter_map(|ledger| {
+                    s.stablecoin_registry
+                        .get(ledger)
+                        .map(|c| (*ledger, (c.decimals, c.is_lp_token.unwrap_or(false))))
+                })
+                .collect();
+            let total_consumed_e8s: u64 = stables_consumed
                 .iter()
-                .filter_map(|p| self.deposits.get(p))
-                .map(|pos| {
-                    pos.stablecoin_balances
-                        .get(token_ledger)
-                        .copied()
-                        .unwrap_or(0)
+                .map(|(ledger, &amount)| {
+                    let (decimals, is_lp) =
+                        registry_snapshot.get(ledger).copied().unwrap_or((8, false));
+                    if is_lp {
+                        vps.get(ledger)
+                            .map(|&vp| lp_to_usd_e8s(amount, vp))
+                            .unwrap_or(0)
+                    } else {
+                        normalize_to_e8s(amount, decimals)
+                    }
                 })
                 .sum();
-            per_token_opted_in_totals.insert(*token_ledger, total);
-        }
-
-        let vps = self.virtual_prices().clone();
-        let registry_snapshot: BTreeMap<Principal, (u8, bool)> = stables_consumed
-            .keys()
-            .filter_map(|ledger| {
-                self.stablecoin_registry
-                    .get(ledger)
-                    .map(|c| (*ledger, (c.decimals, c.is_lp_token.unwrap_or(false))))
-            })
-            .collect();
-        let total_consumed_e8s: u64 = stables_consumed
-            .iter()
-            .map(|(ledger, &amount)| {
-                let (decimals, is_lp) =
-                    registry_snapshot.get(ledger).copied().unwrap_or((8, false));
-                if is_lp {
-                    vps.get(ledger)
-                        .map(|&vp| lp_to_usd_e8s(amount, vp))
-                        .unwrap_or(0)
-                } else {
-                    normalize_to_e8s(amount, decimals)
-                }
-            })
-            .sum();
-        if total_consumed_e8s == 0 {
-            return;
-        }
+            if total_consumed_e8s == 0 {
+                return;
+            }
 
-        let mut actual_deductions_per_token: BTreeMap<Principal, u64> = BTreeMap::new();
-        let mut total_cfx_distributed: u128 = 0;
+            let mut actual_deductions_per_token: BTreeMap<Principal, u64> = BTreeMap::new();
+            let mut total_cfx_distributed: u128 = 0;
 
-        for principal in &opted_in_principals {
-            let mut user_consumed_e8s: u64 = 0;
+            for principal in &opted_in_principals {
+                let mut user_consumed_e8s: u64 = 0;
+
+                if let Some(position) = s.deposits.get_mut(principal) {
+                    for (token_ledger, &total_consumed) in stables_consumed {
+                        let total_opted_in = per_token_opted_in_totals
+                            .get(token_ledger)
+                            .copied()
+                            .unwrap_or(0);
+                        if total_opted_in == 0 {
+                            continue;
+                        }
+                        let user_balance = position
+                            .stablecoin_balances
+                            .get(token_ledger)
+                            .copied()
+                            .unwrap_or(0);
+                        if user_balance == 0 {
+                            continue;
+                        }
 
-            if let Some(position) = self.deposits.get_mut(principal) {
-                for (token_ledger, &total_consumed) in stables_consumed {
-                    let total_opted_in = per_token_opted_in_totals
-                        .get(token_ledger)
-                        .copied()
-                        .unwrap_or(0);
-                    if total_opted_in == 0 {
-                        continue;
-                    }
-                    let user_balance = position
-                        .stablecoin_balances
-                        .get(token_ledger)
-                        .copied()
-                        .unwrap_or(0);
-                    if user_balance == 0 {
-                        continue;
+                        let user_share_native = (total_consumed as u128 * user_balance as u128
+                            / total_opted_in as u128)
+                            as u64;
+                        let user_share_native = user_share_native.min(user_balance);
+                        if let Some(bal) = position.stablecoin_balances.get_mut(token_ledger) {
+                            *bal = bal.saturating_sub(user_share_native);
+                        }
+                        *actual_deductions_per_token
+                            .entry(*token_ledger)
+                            .or_insert(0) += user_share_native;
+
+                        let (decimals, is_lp) = registry_snapshot
+                            .get(token_ledger)
+                            .copied()
+                            .unwrap_or((8, false));
+                        let share_e8s = if is_lp {
+                            vps.get(token_ledger)
+                                .map(|&vp| lp_to_usd_e8s(user_share_native, vp))
+                                .unwrap_or(0)
+                        } else {
+                            normalize_to_e8s(user_share_native, decimals)
+                        };
+                        user_consumed_e8s = user_consumed_e8s.saturating_add(share_e8s);
                     }
 
-                    let user_share_native = (total_consumed as u128 * user_balance as u128
-                        / total_opted_in as u128)
-                        as u64;
-                    let user_share_native = user_share_native.min(user_balance);
-                    if let Some(bal) = position.stablecoin_balances.get_mut(token_ledger) {
-                        *bal = bal.saturating_sub(user_share_native);
+                    if user_consumed_e8s > 0 {
+                        let user_cfx = cfx_gained_native.saturating_mul(user_consumed_e8s as u128)
+                            / total_consumed_e8s as u128;
+                        let claims = position.cfx_claims.get_or_insert_with(BTreeMap::new);
+                        let entry = claims.entry(chain_sentinel).or_insert(0);
+                        *entry = entry.saturating_add(user_cfx);
+                        total_cfx_distributed = total_cfx_distributed.saturating_add(user_cfx);
                     }
-                    *actual_deductions_per_token
-                        .entry(*token_ledger)
-                        .or_insert(0) += user_share_native;
-
-                    let (decimals, is_lp) = registry_snapshot
-                        .get(token_ledger)
-                        .copied()
-                        .unwrap_or((8, false));
-                    let share_e8s = if is_lp {
-                        vps.get(token_ledger)
-                            .map(|&vp| lp_to_usd_e8s(user_share_native, vp))
-                            .unwrap_or(0)
-                    } else {
-                        normalize_to_e8s(user_share_native, decimals)
-                    };
-                    user_consumed_e8s = user_consumed_e8s.saturating_add(share_e8s);
-                }
-
-                if user_consumed_e8s > 0 {
-                    let user_cfx = cfx_gained_native.saturating_mul(user_consumed_e8s as u128)
-                        / total_consumed_e8s as u128;
-                    let claims = position.cfx_claims.get_or_insert_with(BTreeMap::new);
-                    let entry = claims.entry(chain_sentinel).or_insert(0);
-                    *entry = entry.saturating_add(user_cfx);
-                    total_cfx_distributed = total_cfx_distributed.saturating_add(user_cfx);
                 }
             }
-        }
 
-        let cfx_dust = cfx_gained_native.saturating_sub(total_cfx_distributed);
-        if cfx_dust > 0 {
-            if let Some(first) = opted_in_principals.first() {
-                if let Some(pos) = self.deposits.get_mut(first) {
-                    let claims = pos.cfx_claims.get_or_insert_with(BTreeMap::new);
-                    let entry = claims.entry(chain_sentinel).or_insert(0);
-                    *entry = entry.saturating_add(cfx_dust);
+            let cfx_dust = cfx_gained_native.saturating_sub(total_cfx_distributed);
+            if cfx_dust > 0 {
+                if let Some(first) = opted_in_principals.first() {
+                    if let Some(pos) = s.deposits.get_mut(first) {
+                        let claims = pos.cfx_claims.get_or_insert_with(BTreeMap::new);
+                        let entry = claims.entry(chain_sentinel).or_insert(0);
+                        *entry = entry.saturating_add(cfx_dust);
+                    }
                 }
             }
-        }
 
-        for (token_ledger, &actual_deducted) in &actual_deductions_per_token {
-            if let Some(total) = self.total_stablecoin_balances.get_mut(token_ledger) {
-                *total = total.saturating_sub(actual_deducted);
+            for (token_ledger, &actual_deducted) in &actual_deductions_per_token {
+                if let Some(total) = s.total_stablecoin_balances.get_mut(token_ledger) {
+                    *total = total.saturating_sub(actual_deducted);
+                }
             }
-        }
 
-        self.total_liquidations_executed += 1;
-        self.deposits.retain(|_, pos| !pos.is_empty());
-        debug_assert!(
-            self.validate_state().is_ok(),
-            "stability pool aggregate/per-depositor invariant violated after \
-             process_chain_liquidation_gains_at"
-        );
+            s.total_liquidations_executed += 1;
+            s.deposits.retain(|_, pos| !pos.is_empty());
+            debug_assert!(
+                s.validate_state().is_ok(),
+                "stability pool aggregate/per-depositor invariant violated after \
+                 process_chain_liquidation_gains_at"
+            );
+        })
     }
 
     // ─── Query Helpers ───
 
+    /// USD value (e8s) of `amount` native units of a registered stablecoin.
+    /// LP tokens are valued at the cached virtual price (0 before one is
+    /// cached); unknown ledgers are assumed to have 8 decimals.
+    fn stable_usd_e8s(&self, ledger: &Principal, amount: u64) -> u64 {
+        let config = self.stablecoin_registry.get(ledger);
+        if config
+            .map(|c| c.is_lp_token.unwrap_or(false))
+            .unwrap_or(false)
+        {
+            self.virtual_prices()
+                .get(ledger)
+                .map(|&vp| lp_to_usd_e8s(amount, vp))
+                .unwrap_or(0)
+        } else {
+            let decimals = config.map(|c| c.decimals).unwrap_or(8);
+            normalize_to_e8s(amount, decimals)
+        }
+    }
+
     pub fn get_pool_status(&self) -> StabilityPoolStatus {
-        let vps = self.virtual_prices();
         let total_e8s: u64 = self
             .total_stablecoin_balances
             .iter()
-            .map(|(ledger, &amount)| {
-                let config = self.stablecoin_registry.get(ledger);
-                if config
-                    .map(|c| c.is_lp_token.unwrap_or(false))
-                    .unwrap_or(false)
-                {
-                    vps.get(ledger)
-                        .map(|&vp| lp_to_usd_e8s(amount, vp))
-                        .unwrap_or(0)
-                } else {
-                    let decimals = config.map(|c| c.decimals).unwrap_or(8);
-                    normalize_to_e8s(amount, decimals)
-                }
-            })
+            .map(|(ledger, &amount)| self.stable_usd_e8s(ledger, amount))
             .sum();
 
         let total_collateral_gains: BTreeMap<Principal, u64> = {
@@ -2571,6 +3407,104 @@ impl StabilityPoolState {
         }
     }
 
+    /// Realized pool performance over each trailing window in
+    /// `LIQUIDITY_POOL_STATS_WINDOWS_DAYS`, ending at `now`. Interest comes
+    /// from `InterestReceived` pool events, liquidation results from
+    /// `liquidation_history`; both are bounded logs, so a window that
+    /// reaches past the oldest retained entry is reported as incomplete.
+    pub fn liquidity_pool_stats(&self, now: u64) -> LiquidityPoolStats {
+        const DAY_NANOS: u64 = 86_400 * 1_000_000_000;
+        let total_deposits_e8s: u64 = self
+            .total_stablecoin_balances
+            .iter()
+            .map(|(ledger, &amount)| self.stable_usd_e8s(ledger, amount))
+            .sum();
+        let events = self.pool_events();
+        let windows = LIQUIDITY_POOL_STATS_WINDOWS_DAYS
+            .iter()
+            .map(|&window_days| {
+                let start = now.saturating_sub(window_days * DAY_NANOS);
+                let interest_distributed_e8s: u64 = events
+                    .iter()
+                    .filter(|e| e.timestamp >= start)
+                    .filter_map(|e| match &e.event_type {
+                        PoolEventType::InterestReceived {
+                            token_ledger,
+                            amount,
+                        } => Some(self.stable_usd_e8s(token_ledger, *amount)),
+                        _ => None,
+                    })
+                    .sum();
+
+                let mut stats = LiquidityPoolWindowStats {
+                    window_days,
+                    interest_distributed_e8s,
+                    liquidations: 0,
+                    stables_consumed_e8s: 0,
+                    collateral_gained_usd_e8s: 0,
+                    redistribution_losses_e8s: 0,
+                    unpriced_liquidations: 0,
+                    utilization_bps: 0,
+                    realized_apy: None,
+                    complete: false,
+                };
+                for record in self
+                    .liquidation_history
+                    .iter()
+                    .filter(|r| r.timestamp >= start)
+                {
+                    stats.liquidations += 1;
+                    let Some(price_e8s) = record.collateral_price_e8s else {
+                        stats.unpriced_liquidations += 1;
+                        continue;
+                    };
+                    let decimals = self
+                        .collateral_registry
+                        .get(&record.collateral_type)
+                        .map(|c| c.decimals)
+                        .unwrap_or(8);
+                    let gained_usd = (record.collateral_gained as u128 * price_e8s as u128
+                        / 10u128.pow(decimals as u32)) as u64;
+                    let consumed: u64 = record
+                        .stables_consumed
+                        .iter()
+                        .map(|(ledger, &amount)| self.stable_usd_e8s(ledger, amount))
+                        .sum();
+                    stats.collateral_gained_usd_e8s += gained_usd;
+                    stats.stables_consumed_e8s += consumed;
+                    stats.redistribution_losses_e8s += consumed.saturating_sub(gained_usd);
+                }
+
+                if total_deposits_e8s > 0 {
+                    stats.utilization_bps = (stats.stables_consumed_e8s as u128 * 10_000
+                        / total_deposits_e8s as u128)
+                        as u64;
+                    let net = stats.interest_distributed_e8s as f64
+                        + stats.collateral_gained_usd_e8s as f64
+                        - stats.stables_consumed_e8s as f64;
+                    let period_return = (net / total_deposits_e8s as f64).max(-1.0);
+                    stats.realized_apy =
+                        Some((1.0 + period_return).powf(365.0 / window_days as f64) - 1.0);
+                }
+                stats.complete = (events.len() < MAX_POOL_EVENTS
+                    || events.first().is_some_and(|e| e.timestamp <= start))
+                    && (self.liquidation_history.len() < MAX_LIQUIDATION_HISTORY
+                        || self
+                            .liquidation_history
+                            .first()
+                            .is_some_and(|r| r.timestamp <= start));
+                stats
+            })
+            .collect();
+
+        LiquidityPoolStats {
+            total_deposits_e8s,
+            total_depositors: self.deposits.len() as u64,
+            windows,
+            computed_at: now,
+        }
+    }
+
     /// For each collateral type, compute the total icUSD balance (e8s) held by
     /// depositors who are opted in to that collateral type.
     ///
@@ -2580,38 +3514,36 @@ impl StabilityPoolState {
     fn eligible_icusd_per_collateral(&self) -> Vec<(Principal, u64)> {
         self.collateral_registry
             .keys()
-            .map(|ct| {
-                let eligible: u64 = self
-                    .deposits
-                    .values()
-                    .filter(|pos| self.position_opted_in_for(pos, ct))
-                    .map(|pos| pos.icusd_value(&self.stablecoin_registry))
-                    .sum();
-                (*ct, eligible)
-            })
+            .map(|ct| (*ct, self.effective_icusd_pool_for_collateral(ct)))
             .collect()
     }
 
     /// Per-collateral liquidation capacity across every accepted stablecoin.
     /// This must remain distinct from the icUSD-only interest denominator.
     fn eligible_usd_per_collateral(&self) -> Vec<(Principal, u64)> {
-        let virtual_prices = self.virtual_prices();
         self.collateral_registry
             .keys()
-            .map(|ct| {
-                let eligible: u64 = self
-                    .deposits
-                    .values()
-                    .filter(|pos| self.position_opted_in_for(pos, ct))
-                    .map(|pos| pos.total_usd_value(&self.stablecoin_registry, &virtual_prices))
-                    .sum();
-                (*ct, eligible)
-            })
+            .map(|ct| (*ct, self.effective_pool_for_collateral(ct)))
             .collect()
     }
 
+    /// `user`'s deposit as it stands after every liquidation and interest
+    /// credit so far. Liquidations only move their loss cohort's running
+    /// product (`process_liquidation_gains_at`); the stored balances lag
+    /// until the position is next settled, so this reads the compounded
+    /// view rather than `deposits`.
+    pub fn get_compounded_deposit(&self, user: &Principal) -> CompoundedDeposit {
+        self.position(user)
+            .map(|pos| CompoundedDeposit {
+                stablecoin_balances: pos.stablecoin_balances.clone(),
+                total_usd_value_e8s: pos
+                    .total_usd_value(&self.stablecoin_registry, self.virtual_prices()),
+            })
+            .unwrap_or_default()
+    }
+
     pub fn get_user_position(&self, user: &Principal) -> Option<UserStabilityPosition> {
-        self.deposits.get(user).map(|pos| UserStabilityPosition {
+        self.position(user).map(|pos| UserStabilityPosition {
             stablecoin_balances: pos.stablecoin_balances.clone(),
             collateral_gains: pos.collateral_gains.clone(),
             cfx_claims: pos.cfx_claims.clone(),
@@ -2619,7 +3551,7 @@ impl StabilityPoolState {
             eligible_interest_collateral: Some(
                 self.collateral_registry
                     .keys()
-                    .filter(|collateral| self.position_opted_in_for(pos, collateral))
+                    .filter(|collateral| self.position_opted_in_for(&pos, collateral))
                     .copied()
                     .collect(),
             ),
@@ -2639,8 +3571,9 @@ impl StabilityPoolState {
 
     // ─── Fee Accounting ───
 
-    /// Deduct a ledger fee (e.g. approve fee) proportionally from all depositors
-    /// who hold `token_ledger`, then adjust the aggregate total to match.
+    /// Deduct a ledger fee (e.g. approve fee) proportionally from every cohort
+    /// holding `token_ledger`, as a loss its members share, then adjust the
+    /// aggregate total to match.
     pub fn deduct_fee_from_pool(&mut self, token_ledger: Principal, fee: u64) {
         let total = match self.total_stablecoin_balances.get(&token_ledger).copied() {
             Some(t) if t > 0 => t,
@@ -2648,26 +3581,17 @@ impl StabilityPoolState {
         };
 
         let mut deducted: u64 = 0;
-        let depositor_keys: Vec<Principal> = self.deposits.keys().copied().collect();
-
-        for key in &depositor_keys {
-            if let Some(pos) = self.deposits.get_mut(key) {
-                if let Some(bal) = pos.stablecoin_balances.get_mut(&token_ledger) {
-                    if *bal > 0 {
-                        // Proportional share: fee * bal / total (rounded down)
-                        let share = (fee as u128 * *bal as u128 / total as u128) as u64;
-                        let actual = share.min(*bal);
-                        *bal = bal.saturating_sub(actual);
-                        deducted += actual;
-                        if *bal == 0 {
-                            pos.stablecoin_balances.remove(&token_ledger);
-                        }
-                    }
-                }
+        for cohort in self.loss_cohorts.iter_mut().flat_map(|c| c.values_mut()) {
+            if let Some(tokens) = cohort.tokens.get_mut(&token_ledger) {
+                // Proportional share: fee * holding / total (rounded down)
+                let share = (fee as u128 * tokens.total as u128 / total as u128) as u64;
+                let actual = share.min(tokens.total);
+                tokens.absorb(actual, &[]);
+                deducted += actual;
             }
         }
 
-        // Apply any rounding remainder (at most depositor_count - 1 units) to the aggregate
+        // Apply any rounding remainder (at most cohort_count - 1 units) to the aggregate
         if let Some(agg) = self.total_stablecoin_balances.get_mut(&token_ledger) {
             *agg = agg.saturating_sub(deducted);
         }
@@ -2684,45 +3608,47 @@ impl StabilityPoolState {
         token_ledger: Principal,
         correct_amount: u64,
     ) -> String {
-        let old_amount = self
-            .deposits
-            .get(&user)
-            .and_then(|pos| pos.stablecoin_balances.get(&token_ledger).copied())
-            .unwrap_or(0);
+        self.with_settled(&user, |s| {
+            let old_amount = s
+                .deposits
+                .get(&user)
+                .and_then(|pos| pos.stablecoin_balances.get(&token_ledger).copied())
+                .unwrap_or(0);
 
-        if old_amount == correct_amount {
-            return format!(
-                "No change needed: user {} balance for {} is already {}",
-                user, token_ledger, correct_amount
-            );
-        }
+            if old_amount == correct_amount {
+                return format!(
+                    "No change needed: user {} balance for {} is already {}",
+                    user, token_ledger, correct_amount
+                );
+            }
 
-        let diff = old_amount as i128 - correct_amount as i128;
+            let diff = old_amount as i128 - correct_amount as i128;
 
-        if let Some(pos) = self.deposits.get_mut(&user) {
-            if correct_amount == 0 {
-                pos.stablecoin_balances.remove(&token_ledger);
-            } else {
-                pos.stablecoin_balances.insert(token_ledger, correct_amount);
-            }
-            if pos.is_empty() {
-                self.deposits.remove(&user);
+            if let Some(pos) = s.deposits.get_mut(&user) {
+                if correct_amount == 0 {
+                    pos.stablecoin_balances.remove(&token_ledger);
+                } else {
+                    pos.stablecoin_balances.insert(token_ledger, correct_amount);
+                }
+                if pos.is_empty() {
+                    s.deposits.remove(&user);
+                }
             }
-        }
 
-        // Adjust aggregate total
-        if let Some(total) = self.total_stablecoin_balances.get_mut(&token_ledger) {
-            if diff > 0 {
-                *total = total.saturating_sub(diff as u64);
-            } else {
-                *total = total.saturating_add((-diff) as u64);
+            // Adjust aggregate total
+            if let Some(total) = s.total_stablecoin_balances.get_mut(&token_ledger) {
+                if diff > 0 {
+                    *total = total.saturating_sub(diff as u64);
+                } else {
+                    *total = total.saturating_add((-diff) as u64);
+                }
             }
-        }
 
-        format!(
-            "Corrected {} balance for {}: {} -> {}",
-            token_ledger, user, old_amount, correct_amount
-        )
+            format!(
+                "Corrected {} balance for {}: {} -> {}",
+                token_ledger, user, old_amount, correct_amount
+            )
+        })
     }
 
     /// Set a depositor's collateral gain for a specific collateral type to `correct_amount`.
@@ -2733,43 +3659,55 @@ impl StabilityPoolState {
         collateral_ledger: Principal,
         correct_amount: u64,
     ) -> String {
-        let old_amount = self
-            .deposits
-            .get(&user)
-            .and_then(|pos| pos.collateral_gains.get(&collateral_ledger).copied())
-            .unwrap_or(0);
+        self.with_settled(&user, |s| {
+            let old_amount = s
+                .deposits
+                .get(&user)
+                .and_then(|pos| pos.collateral_gains.get(&collateral_ledger).copied())
+                .unwrap_or(0);
 
-        if old_amount == correct_amount {
-            return format!(
-                "No change needed: user {} gain for {} is already {}",
-                user, collateral_ledger, correct_amount
-            );
-        }
+            if old_amount == correct_amount {
+                return format!(
+                    "No change needed: user {} gain for {} is already {}",
+                    user, collateral_ledger, correct_amount
+                );
+            }
 
-        if let Some(pos) = self.deposits.get_mut(&user) {
-            if correct_amount == 0 {
-                pos.collateral_gains.remove(&collateral_ledger);
-            } else {
-                pos.collateral_gains
-                    .insert(collateral_ledger, correct_amount);
+            if let Some(pos) = s.deposits.get_mut(&user) {
+                if correct_amount == 0 {
+                    pos.collateral_gains.remove(&collateral_ledger);
+                } else {
+                    pos.collateral_gains
+                        .insert(collateral_ledger, correct_amount);
+                }
             }
-        }
 
-        format!(
-            "Corrected {} collateral gain for {}: {} -> {}",
-            collateral_ledger, user, old_amount, correct_amount
-        )
+            format!(
+                "Corrected {} collateral gain for {}: {} -> {}",
+                collateral_ledger, user, old_amount, correct_amount
+            )
+        })
     }
 
     // ─── State Validation ───
 
     pub fn validate_state(&self) -> Result<(), String> {
         for (ledger, &tracked_total) in &self.total_stablecoin_balances {
-            let computed_total: u64 = self
+            // Attached positions are counted through their cohort, whose
+            // totals already carry the liquidations their stored balances lag.
+            let unattached: u64 = self
                 .deposits
-                .values()
-                .map(|pos| pos.stablecoin_balances.get(ledger).copied().unwrap_or(0))
+                .iter()
+                .filter(|(user, _)| {
+                    !self
+                        .deposit_snapshots
+                        .as_ref()
+                        .is_some_and(|snapshots| snapshots.contains_key(user))
+                })
+                .map(|(_, pos)| pos.stablecoin_balances.get(ledger).copied().unwrap_or(0))
                 .sum();
+            let computed_total =
+                unattached + self.loss_cohorts().map(|c| c.total(ledger)).sum::<u64>();
             if computed_total != tracked_total {
                 return Err(format!(
                     "Stablecoin total mismatch for {}: tracked={}, computed={}",
@@ -2781,6 +3719,35 @@ impl StabilityPoolState {
     }
 }
 
+/// Store `compounded` in `position`, stamping collateral gains with when
+/// the cohort was paid them. Returns the reward gains, which are kept
+/// outside the position.
+fn apply_compounded(
+    position: &mut DepositPosition,
+    compounded: Compounded,
+    credited_at: &BTreeMap<Principal, u64>,
+    reward_ledger: Option<Principal>,
+) -> u64 {
+    position.stablecoin_balances = compounded.balances;
+    let mut rewards = 0;
+    for (ledger, gain) in compounded.gains {
+        if Some(ledger) == reward_ledger {
+            rewards += gain;
+            continue;
+        }
+        *position.collateral_gains.entry(ledger).or_insert(0) += gain;
+        if let Some(&at) = credited_at.get(&ledger) {
+            let stamped = position
+                .gains_credited_at
+                .get_or_insert_with(BTreeMap::new)
+                .entry(ledger)
+                .or_insert(0);
+            *stamped = (*stamped).max(at);
+        }
+    }
+    rewards
+}
+
 // ─── Thread-local state + accessors ───
 
 thread_local! {
@@ -2904,6 +3871,24 @@ impl From<StabilityPoolStateV1> for StabilityPoolState {
             next_event_id: v1.next_event_id,
             pending_refunds: Some(BTreeMap::new()),
             next_pending_refund_id: Some(0),
+            protocol_mode: None,
+            mode_inheritance_policy: None,
+            safe_mode: None,
+            backend_consecutive_failures: None,
+            last_backend_reply_ns: None,
+            last_backend_error: None,
+            reward_config: None,
+            rewards_accrued_until_ns: None,
+            reward_balances: None,
+            total_rewards_emitted: None,
+            total_rewards_claimed: None,
+            auto_compound_users: None,
+            auto_compound_routes: None,
+            pending_auto_compound_credits: None,
+            withdrawal_queue: None,
+            loss_cohorts: None,
+            next_loss_cohort_id: None,
+            deposit_snapshots: None,
         }
     }
 }
@@ -3019,7 +4004,7 @@ mod tests {
         Principal::from_slice(&[30])
     }
     fn xrp_ledger() -> Principal {
-        rumi_protocol_backend::state::xrp_collateral_principal()
+        rumi_common::chains::xrp_collateral_principal()
     }
     fn valid_xrp_address() -> String {
         "rUn84CUYbNjRoTQ6mSW7BVJPSVJNLb1QLo".to_string()
@@ -3152,12 +4137,14 @@ mod tests {
         token: Principal,
         amount: u64,
     ) {
+        state.settle_position(&user);
         let position = state
             .deposits
             .entry(user)
             .or_insert_with(|| DepositPosition::new(0));
         *position.stablecoin_balances.entry(token).or_insert(0) += amount;
         *state.total_stablecoin_balances.entry(token).or_insert(0) += amount;
+        state.attach_position(&user);
     }
 
     // ─── Test: Deposit and Withdrawal ───
@@ -3171,7 +4158,7 @@ mod tests {
         add_deposit_direct(&mut state, user_a(), ckusdt_ledger(), 2_000_000); // 2 ckUSDT
 
         // Verify balances
-        let pos = state.deposits.get(&user_a()).unwrap();
+        let pos = state.position(&user_a()).unwrap();
         assert_eq!(
             pos.stablecoin_balances.get(&icusd_ledger()),
             Some(&100_000_000)
@@ -3195,7 +4182,7 @@ mod tests {
         state
             .process_withdrawal(user_a(), icusd_ledger(), 30_000_000)
             .unwrap();
-        let pos = state.deposits.get(&user_a()).unwrap();
+        let pos = state.position(&user_a()).unwrap();
         assert_eq!(
             pos.stablecoin_balances.get(&icusd_ledger()),
             Some(&70_000_000)
@@ -3209,7 +4196,7 @@ mod tests {
         state
             .process_withdrawal(user_a(), ckusdt_ledger(), 2_000_000)
             .unwrap();
-        let pos = state.deposits.get(&user_a()).unwrap();
+        let pos = state.position(&user_a()).unwrap();
         assert_eq!(pos.stablecoin_balances.get(&ckusdt_ledger()), None);
 
         // Full withdrawal of remaining icUSD -- empty position should be removed
@@ -3217,7 +4204,7 @@ mod tests {
             .process_withdrawal(user_a(), icusd_ledger(), 70_000_000)
             .unwrap();
         assert!(
-            state.deposits.get(&user_a()).is_none(),
+            state.position(&user_a()).is_none(),
             "Empty position should be removed"
         );
 
@@ -3354,9 +4341,9 @@ mod tests {
         // user_a consumed: 10 * (50/100) = 5 icUSD -> remaining: 45
         // user_b consumed: 10 * (30/100) = 3 icUSD -> remaining: 27
         // user_c consumed: 10 * (20/100) = 2 icUSD -> remaining: 18
-        let pos_a = state.deposits.get(&user_a()).unwrap();
-        let pos_b = state.deposits.get(&user_b()).unwrap();
-        let pos_c = state.deposits.get(&user_c()).unwrap();
+        let pos_a = state.position(&user_a()).unwrap();
+        let pos_b = state.position(&user_b()).unwrap();
+        let pos_c = state.position(&user_c()).unwrap();
 
         assert_eq!(
             pos_a
@@ -3429,6 +4416,71 @@ mod tests {
         assert_eq!(state.total_liquidations_executed, 1);
     }
 
+    #[test]
+    fn compounded_deposits_carry_successive_liquidations() {
+        let mut state = test_state();
+        add_deposit_direct(&mut state, user_a(), icusd_ledger(), 60_00000000);
+        add_deposit_direct(&mut state, user_b(), icusd_ledger(), 40_00000000);
+
+        // Two liquidations of 10 and 45 icUSD: each depositor keeps
+        // 90% then 50% of what they had.
+        for (vault_id, consumed, gained) in
+            [(1, 10_00000000, 2_00000000), (2, 45_00000000, 9_00000000)]
+        {
+            let stables_consumed = BTreeMap::from([(icusd_ledger(), consumed)]);
+            state.process_liquidation_gains_at(
+                vault_id,
+                icp_ledger(),
+                &stables_consumed,
+                gained,
+                7_50000000,
+                1_000_000_000,
+            );
+        }
+
+        let a = state.get_compounded_deposit(&user_a());
+        assert_eq!(
+            a.stablecoin_balances.get(&icusd_ledger()).copied(),
+            Some(27_00000000)
+        );
+        assert_eq!(a.total_usd_value_e8s, 27_00000000);
+        assert_eq!(
+            state.get_compounded_deposit(&user_b()).total_usd_value_e8s,
+            18_00000000
+        );
+        // Gains follow each depositor's share at the time of each liquidation.
+        assert_eq!(
+            state
+                .get_collateral_gains(&user_a())
+                .get(&icp_ledger())
+                .copied(),
+            Some(6_60000000)
+        );
+        assert_eq!(
+            state
+                .get_collateral_gains(&user_b())
+                .get(&icp_ledger())
+                .copied(),
+            Some(4_40000000)
+        );
+        // Liquidations touch only the cohort: stored balances catch up
+        // when the position is next settled.
+        assert_eq!(
+            state.deposits[&user_a()].stablecoin_balances[&icusd_ledger()],
+            60_00000000
+        );
+        state.settle_position(&user_a());
+        assert_eq!(
+            state.deposits[&user_a()].stablecoin_balances[&icusd_ledger()],
+            27_00000000
+        );
+        assert!(state.validate_state().is_ok());
+        assert_eq!(
+            state.get_compounded_deposit(&user_c()),
+            CompoundedDeposit::default()
+        );
+    }
+
     // ─── Test: Opt-out Filtering ───
 
     #[test]
@@ -3474,7 +4526,7 @@ mod tests {
         );
 
         // user_a should lose all 20 icUSD (only opted-in depositor)
-        let pos_a = state.deposits.get(&user_a()).unwrap();
+        let pos_a = state.position(&user_a()).unwrap();
         assert_eq!(
             pos_a
                 .stablecoin_balances
@@ -3493,7 +4545,7 @@ mod tests {
         );
 
         // user_b should be completely untouched
-        let pos_b = state.deposits.get(&user_b()).unwrap();
+        let pos_b = state.position(&user_b()).unwrap();
         assert_eq!(
             pos_b
                 .stablecoin_balances
@@ -3542,12 +4594,14 @@ mod tests {
         add_deposit_direct(&mut state, user_a(), icusd_ledger(), 10_00000000);
         let bob = crate::types::bob_collateral();
 
+        state.settle_position(&user_a());
         state
             .deposits
             .get_mut(&user_a())
             .unwrap()
             .opted_out_collateral
             .remove(&bob);
+        state.attach_position(&user_a());
         assert_eq!(state.effective_pool_for_collateral(&bob), 10_00000000);
 
         state.opt_out_collateral(&user_a(), bob).unwrap();
@@ -3565,7 +4619,10 @@ mod tests {
         state.opt_out_collateral(&user_a(), icp_ledger()).unwrap();
         state.opt_in_collateral(&user_a(), icp_ledger()).unwrap();
 
-        assert!(state.deposits[&user_a()].is_opted_in(&icp_ledger()));
+        assert!(state
+            .position(&user_a())
+            .unwrap()
+            .is_opted_in(&icp_ledger()));
     }
 
     #[test]
@@ -3731,7 +4788,7 @@ mod tests {
             3_000_000_000,
         );
 
-        let pos_a = state.deposits.get(&user_a()).unwrap();
+        let pos_a = state.position(&user_a()).unwrap();
         assert_eq!(
             pos_a
                 .stablecoin_balances
@@ -3762,8 +4819,7 @@ mod tests {
         state.distribute_interest_revenue(icusd_ledger(), 9_00000000, Some(xrp_ledger()));
         assert_eq!(
             state
-                .deposits
-                .get(&user_a())
+                .position(&user_a())
                 .unwrap()
                 .stablecoin_balances
                 .get(&icusd_ledger())
@@ -3774,8 +4830,7 @@ mod tests {
         );
         assert_eq!(
             state
-                .deposits
-                .get(&user_b())
+                .position(&user_b())
                 .unwrap()
                 .stablecoin_balances
                 .get(&icusd_ledger())
@@ -3789,8 +4844,8 @@ mod tests {
             .unwrap();
         state.distribute_interest_revenue(icusd_ledger(), 9_00000000, Some(xrp_ledger()));
 
-        let pos_a = state.deposits.get(&user_a()).unwrap();
-        let pos_b = state.deposits.get(&user_b()).unwrap();
+        let pos_a = state.position(&user_a()).unwrap();
+        let pos_b = state.position(&user_b()).unwrap();
         assert_eq!(
             pos_a
                 .stablecoin_balances
@@ -3994,15 +5049,13 @@ mod tests {
 
         assert_eq!(
             state
-                .deposits
-                .get(&user_a())
+                .position(&user_a())
                 .and_then(|pos| pos.stablecoin_balances.get(&icusd_ledger()).copied()),
             Some(0),
         );
         assert_eq!(
             state
-                .deposits
-                .get(&user_b())
+                .position(&user_b())
                 .and_then(|pos| pos.stablecoin_balances.get(&icusd_ledger()).copied()),
             Some(1),
         );
@@ -4078,7 +5131,7 @@ mod tests {
 
         // Opts in via the chain (CFX) path; the payout-address endpoint is wrong here.
         state.opt_in_cfx(&user_a(), sentinel).unwrap();
-        let pos = state.deposits.get(&user_a()).unwrap();
+        let pos = state.position(&user_a()).unwrap();
         assert!(state.position_opted_in_for(pos, &sentinel));
         assert_eq!(state.native_payout_address(&user_a(), &sentinel), None);
     }
@@ -4404,7 +5457,7 @@ mod tests {
 
         // Partially claim
         state.mark_gains_claimed(&user_a(), &icp_ledger(), 2_00000000);
-        let pos = state.deposits.get(&user_a()).unwrap();
+        let pos = state.position(&user_a()).unwrap();
         assert_eq!(
             pos.collateral_gains
                 .get(&icp_ledger())
@@ -4422,7 +5475,7 @@ mod tests {
 
         // Claim the rest
         state.mark_gains_claimed(&user_a(), &icp_ledger(), 3_00000000);
-        let pos = state.deposits.get(&user_a()).unwrap();
+        let pos = state.position(&user_a()).unwrap();
         assert_eq!(
             pos.collateral_gains.get(&icp_ledger()),
             None,
@@ -4556,8 +5609,7 @@ mod tests {
         );
 
         let claim_a = state
-            .deposits
-            .get(&user_a())
+            .position(&user_a())
             .unwrap()
             .cfx_claims
             .as_ref()
@@ -4566,8 +5618,7 @@ mod tests {
             .copied()
             .unwrap_or(0);
         let claim_b = state
-            .deposits
-            .get(&user_b())
+            .position(&user_b())
             .unwrap()
             .cfx_claims
             .as_ref()
@@ -4599,8 +5650,7 @@ mod tests {
 
         state.mark_cfx_claimed(&user_a(), &cfx_sentinel(), 3 * E18);
         let after_partial = state
-            .deposits
-            .get(&user_a())
+            .position(&user_a())
             .unwrap()
             .cfx_claims
             .as_ref()
@@ -4611,8 +5661,7 @@ mod tests {
         assert_eq!(after_partial, 9_997 * E18 + 1);
         state.mark_cfx_claimed(&user_a(), &cfx_sentinel(), 9_997 * E18 + 1);
         assert!(state
-            .deposits
-            .get(&user_a())
+            .position(&user_a())
             .unwrap()
             .cfx_claims
             .as_ref()
@@ -4693,7 +5742,7 @@ mod tests {
         );
 
         // user_a has all the ckUSDT, so consumes all 20 ckUSDT
-        let pos_a = state.deposits.get(&user_a()).unwrap();
+        let pos_a = state.position(&user_a()).unwrap();
         assert_eq!(
             pos_a
                 .stablecoin_balances
@@ -4713,7 +5762,7 @@ mod tests {
         );
 
         // user_b has all the ckUSDC, so consumes all 20 ckUSDC
-        let pos_b = state.deposits.get(&user_b()).unwrap();
+        let pos_b = state.position(&user_b()).unwrap();
         assert_eq!(
             pos_b
                 .stablecoin_balances
@@ -4767,7 +5816,7 @@ mod tests {
 
         // user_a's stablecoin balance is zero, but they have collateral gains
         // so position should NOT be removed
-        let pos = state.deposits.get(&user_a());
+        let pos = state.position(&user_a());
         assert!(
             pos.is_some(),
             "Position with collateral gains should not be cleaned up"
@@ -4894,7 +5943,7 @@ mod tests {
         add_deposit_direct(&mut state, user_a(), icusd_ledger(), 30_00000000);
         add_deposit_direct(&mut state, user_a(), icusd_ledger(), 20_00000000);
 
-        let pos = state.deposits.get(&user_a()).unwrap();
+        let pos = state.position(&user_a()).unwrap();
         assert_eq!(
             pos.stablecoin_balances.get(&icusd_ledger()),
             Some(&100_00000000)
@@ -4914,7 +5963,7 @@ mod tests {
 
         state.distribute_interest_revenue(icusd_ledger(), 5_00000000, None);
 
-        let pos = state.deposits.get(&user_a()).unwrap();
+        let pos = state.position(&user_a()).unwrap();
         assert_eq!(pos.stablecoin_balances[&icusd_ledger()], 105_00000000);
         assert_eq!(pos.total_interest_earned_e8s, Some(5_00000000)); // icUSD is 8 decimals = e8s
         assert_eq!(state.total_interest_received_e8s, Some(5_00000000));
@@ -4933,8 +5982,8 @@ mod tests {
 
         state.distribute_interest_revenue(icusd_ledger(), 10_00000000, None);
 
-        let a = state.deposits.get(&user_a()).unwrap();
-        let b = state.deposits.get(&user_b()).unwrap();
+        let a = state.position(&user_a()).unwrap();
+        let b = state.position(&user_b()).unwrap();
         // A gets 7.5, B gets 2.5
         assert_eq!(a.stablecoin_balances[&icusd_ledger()], 82_50000000);
         assert_eq!(b.stablecoin_balances[&icusd_ledger()], 27_50000000);
@@ -4970,9 +6019,8 @@ mod tests {
 
         // Each gets floor(10 * 100/300) = 3. Dust = 10 - 9 = 1 goes to first depositor.
         let total: u64 = state
-            .deposits
-            .values()
-            .map(|p| {
+            .positions()
+            .map(|(_, p)| {
                 p.stablecoin_balances
                     .get(&icusd_ledger())
                     .copied()
@@ -4997,8 +6045,8 @@ mod tests {
         // Distribute 10 icUSD interest
         state.distribute_interest_revenue(icusd_ledger(), 10_00000000, None);
 
-        let a = state.deposits.get(&user_a()).unwrap();
-        let b = state.deposits.get(&user_b()).unwrap();
+        let a = state.position(&user_a()).unwrap();
+        let b = state.position(&user_b()).unwrap();
         let a_interest = a.stablecoin_balances[&icusd_ledger()] - 50_00000000;
         let b_interest = b
             .stablecoin_balances
@@ -5040,8 +6088,8 @@ mod tests {
         // Distribute 20 icUSD interest
         state.distribute_interest_revenue(icusd_ledger(), 20_00000000, None);
 
-        let a = state.deposits.get(&user_a()).unwrap();
-        let b = state.deposits.get(&user_b()).unwrap();
+        let a = state.position(&user_a()).unwrap();
+        let b = state.position(&user_b()).unwrap();
         let a_interest = a.stablecoin_balances[&icusd_ledger()] - 100_00000000;
         let b_interest = b
             .stablecoin_balances
@@ -5081,16 +6129,14 @@ mod tests {
         state.distribute_interest_revenue(icusd_ledger(), 10_00000000, Some(icp_ledger()));
 
         let alice_icusd = state
-            .deposits
-            .get(&user_a())
+            .position(&user_a())
             .unwrap()
             .stablecoin_balances
             .get(&icusd_ledger())
             .copied()
             .unwrap_or(0);
         let bob_icusd = state
-            .deposits
-            .get(&user_b())
+            .position(&user_b())
             .unwrap()
             .stablecoin_balances
             .get(&icusd_ledger())
@@ -5129,24 +6175,21 @@ mod tests {
         state.distribute_interest_revenue(icusd_ledger(), 20_00000000, None);
 
         let alice_icusd = state
-            .deposits
-            .get(&user_a())
+            .position(&user_a())
             .unwrap()
             .stablecoin_balances
             .get(&icusd_ledger())
             .copied()
             .unwrap_or(0);
         let bob_icusd = state
-            .deposits
-            .get(&user_b())
+            .position(&user_b())
             .unwrap()
             .stablecoin_balances
             .get(&icusd_ledger())
             .copied()
             .unwrap_or(0);
         let bob_three_usd = state
-            .deposits
-            .get(&user_b())
+            .position(&user_b())
             .unwrap()
             .stablecoin_balances
             .get(&three_usd_ledger())
@@ -5242,7 +6285,7 @@ mod tests {
 
         // Neither depositor's balances changed
         for user in [user_a(), user_b()] {
-            let pos = state.deposits.get(&user).unwrap();
+            let pos = state.position(&user).unwrap();
             assert_eq!(
                 pos.stablecoin_balances
                     .get(&icusd_ledger())
@@ -5272,12 +6315,12 @@ mod tests {
         state.distribute_interest_revenue(icusd_ledger(), 20_00000000, Some(icp_ledger()));
 
         assert_eq!(
-            state.deposits[&user_a()].stablecoin_balances[&icusd_ledger()],
+            state.position(&user_a()).unwrap().stablecoin_balances[&icusd_ledger()],
             100_00000000,
             "a depositor opted out of ICP receives none of the ICP-vault interest"
         );
         assert_eq!(
-            state.deposits[&user_b()].stablecoin_balances[&icusd_ledger()],
+            state.position(&user_b()).unwrap().stablecoin_balances[&icusd_ledger()],
             120_00000000,
             "the opted-in icUSD depositor receives the full ICP-vault interest"
         );
@@ -5351,11 +6394,12 @@ mod tests {
             "State must remain consistent after rounding"
         );
 
-        // Verify individual balances sum to aggregate
+        // Verify individual balances sum to aggregate. Compounded views round
+        // down; the cohort's dust goes to whichever member settles last.
+        state.settle_all_positions();
         let sum: u64 = state
-            .deposits
-            .values()
-            .map(|p| {
+            .positions()
+            .map(|(_, p)| {
                 p.stablecoin_balances
                     .get(&icusd_ledger())
                     .copied()
@@ -5840,7 +6884,7 @@ mod tests {
                 result: ChainSpAbsorbResult {
                     success: true,
                     vault_id,
-                    chain_id: rumi_protocol_backend::chains::config::ChainId(1030),
+                    chain_id: rumi_common::chains::ChainId(1030),
                     icusd_burned_e8s: 100_00000000,
                     liquidated_debt_e8s: 100_00000000,
                     collateral_received_native: 10_000_000_000_000_000_000u128,
@@ -5892,7 +6936,7 @@ mod tests {
         );
 
         // user_b escaped the burn entirely (balance untouched, no gains)...
-        let pos_b = state.deposits.get(&user_b()).unwrap();
+        let pos_b = state.position(&user_b()).unwrap();
         assert_eq!(
             pos_b.stablecoin_balances.get(&icusd_ledger()).copied(),
             Some(50_00000000),
@@ -5908,11 +6952,583 @@ mod tests {
         );
 
         // ...while user_a absorbed the FULL 40 instead of their fair 20.
-        let pos_a = state.deposits.get(&user_a()).unwrap();
+        let pos_a = state.position(&user_a()).unwrap();
         assert_eq!(
             pos_a.stablecoin_balances.get(&icusd_ledger()).copied(),
             Some(10_00000000),
             "remaining depositor over-absorbs the escaped share",
         );
     }
+
+    // ─── Test: Liquidity pool stats ───
+
+    const DAY_NS: u64 = 86_400 * 1_000_000_000;
+
+    fn interest_event(id: u64, timestamp: u64, amount: u64) -> PoolEvent {
+        PoolEvent {
+            id,
+            timestamp,
+            caller: Principal::anonymous(),
+            event_type: PoolEventType::InterestReceived {
+                token_ledger: icusd_ledger(),
+                amount,
+            },
+        }
+    }
+
+    fn liquidation_record(
+        timestamp: u64,
+        consumed_e8s: u64,
+        collateral_gained: u64,
+        collateral_price_e8s: Option<u64>,
+    ) -> PoolLiquidationRecord {
+        PoolLiquidationRecord {
+            vault_id: timestamp,
+            timestamp,
+            stables_consumed: BTreeMap::from([(icusd_ledger(), consumed_e8s)]),
+            collateral_gained,
+            collateral_type: icp_ledger(),
+            depositors_count: 1,
+            collateral_price_e8s,
+        }
+    }
+
+    #[test]
+    fn test_liquidity_pool_stats_windows() {
+        let mut state = test_state();
+        let now = 100 * DAY_NS;
+        // 1,000 icUSD plus 1,000 ckUSDT (6 decimals) in the pool.
+        add_deposit_direct(&mut state, user_a(), icusd_ledger(), 1_000_00000000);
+        add_deposit_direct(&mut state, user_b(), ckusdt_ledger(), 1_000_000000);
+
+        state.pool_events = Some(vec![
+            interest_event(0, now - 60 * DAY_NS, 20_00000000),
+            interest_event(1, now - 2 * DAY_NS, 2_00000000),
+        ]);
+        state.liquidation_history = vec![
+            // 10 icUSD for 1.2 ICP at $10: +2 icUSD.
+            liquidation_record(
+                now - 20 * DAY_NS,
+                10_00000000,
+                1_20000000,
+                Some(10_00000000),
+            ),
+            // 10 icUSD for 0.9 ICP at $10: -1 icUSD.
+            liquidation_record(now - DAY_NS, 10_00000000, 90000000, Some(10_00000000)),
+            liquidation_record(now - DAY_NS, 5_00000000, 1, None),
+        ];
+
+        let stats = state.liquidity_pool_stats(now);
+        assert_eq!(stats.total_deposits_e8s, 2_000_00000000);
+        assert_eq!(stats.total_depositors, 2);
+        assert_eq!(stats.computed_at, now);
+        let days: Vec<u64> = stats.windows.iter().map(|w| w.window_days).collect();
+        assert_eq!(days, vec![7, 30, 90]);
+
+        let week = &stats.windows[0];
+        assert_eq!(week.interest_distributed_e8s, 2_00000000);
+        assert_eq!(week.liquidations, 2);
+        assert_eq!(week.unpriced_liquidations, 1);
+        assert_eq!(week.stables_consumed_e8s, 10_00000000);
+        assert_eq!(week.collateral_gained_usd_e8s, 9_00000000);
+        assert_eq!(week.redistribution_losses_e8s, 1_00000000);
+        assert_eq!(week.utilization_bps, 50);
+        assert!(week.complete);
+        // Net +1 icUSD on 2,000 over 7 days.
+        let expected = (1.0f64 + 0.0005).powf(365.0 / 7.0) - 1.0;
+        assert!((week.realized_apy.unwrap() - expected).abs() < 1e-12);
+
+        let quarter = &stats.windows[2];
+        assert_eq!(quarter.interest_distributed_e8s, 22_00000000);
+        assert_eq!(quarter.collateral_gained_usd_e8s, 21_00000000);
+        assert_eq!(quarter.stables_consumed_e8s, 20_00000000);
+        assert_eq!(quarter.redistribution_losses_e8s, 1_00000000);
+        assert!(quarter.realized_apy.unwrap() > 0.0);
+    }
+
+    #[test]
+    fn test_liquidity_pool_stats_empty_pool_and_truncated_history() {
+        let mut state = test_state();
+        let now = 100 * DAY_NS;
+        let stats = state.liquidity_pool_stats(now);
+        assert!(stats.windows.iter().all(|w| w.realized_apy.is_none()));
+        assert!(stats.windows.iter().all(|w| w.complete));
+
+        // A full event log whose oldest entry is 10 days old cannot cover
+        // the 30- and 90-day windows.
+        state.pool_events = Some(
+            (0..MAX_POOL_EVENTS as u64)
+                .map(|id| interest_event(id, now - 10 * DAY_NS, 0))
+                .collect(),
+        );
+        let complete: Vec<bool> = state
+            .liquidity_pool_stats(now)
+            .windows
+            .iter()
+            .map(|w| w.complete)
+            .collect();
+        assert_eq!(complete, vec![true, false, false]);
+    }
+
+    #[test]
+    fn test_mode_inheritance_default_policy_blocks_read_only_only() {
+        let mut state = test_state();
+        assert_eq!(state.deposits_restricted_by_mode(), None);
+        assert_eq!(state.liquidations_restricted_by_mode(), None);
+
+        state.protocol_mode = Some(ProtocolMode::Recovery);
+        assert_eq!(state.deposits_restricted_by_mode(), None);
+        assert_eq!(state.liquidations_restricted_by_mode(), None);
+
+        state.protocol_mode = Some(ProtocolMode::ReadOnly);
+        assert_eq!(
+            state.deposits_restricted_by_mode(),
+            Some(ProtocolMode::ReadOnly)
+        );
+        assert_eq!(
+            state.liquidations_restricted_by_mode(),
+            Some(ProtocolMode::ReadOnly)
+        );
+
+        // A bootstrapping backend is held to the ReadOnly entries.
+        state.protocol_mode = Some(ProtocolMode::Bootstrapping);
+        assert_eq!(
+            state.deposits_restricted_by_mode(),
+            Some(ProtocolMode::Bootstrapping)
+        );
+        assert_eq!(
+            state.liquidations_restricted_by_mode(),
+            Some(ProtocolMode::Bootstrapping)
+        );
+    }
+
+    #[test]
+    fn test_mode_inheritance_custom_policy() {
+        let mut state = test_state();
+        state.protocol_mode = Some(ProtocolMode::Recovery);
+        state.mode_inheritance_policy = Some(ModeInheritancePolicy {
+            block_deposits_in: vec![ProtocolMode::ReadOnly, ProtocolMode::Recovery],
+            block_liquidations_in: vec![],
+        });
+        let status = state.mode_inheritance_status();
+        assert_eq!(status.protocol_mode, Some(ProtocolMode::Recovery));
+        assert!(status.deposits_blocked);
+        assert!(!status.liquidations_blocked);
+
+        state.protocol_mode = Some(ProtocolMode::ReadOnly);
+        assert!(!state.mode_inheritance_status().liquidations_blocked);
+    }
+
+    #[test]
+    fn test_safe_mode_entered_after_threshold_and_left_on_reply() {
+        let mut state = test_state();
+        for i in 1..SAFE_MODE_FAILURE_THRESHOLD {
+            assert!(!state.record_backend_failure(format!("reject {}", i), 100));
+        }
+        assert!(state.ensure_not_in_safe_mode().is_ok());
+        assert!(state.record_backend_failure("reject".to_string(), 100));
+        assert!(matches!(
+            state.ensure_not_in_safe_mode(),
+            Err(StabilityPoolError::BackendUnreachable { since_ns: 100 })
+        ));
+        // Further failures do not re-enter.
+        assert!(!state.record_backend_failure("reject".to_string(), 200));
+        assert_eq!(state.safe_mode_status().consecutive_failures, 4);
+
+        let ended = state.record_backend_reply(300).unwrap();
+        assert_eq!(ended.entered_at_ns, 100);
+        let status = state.safe_mode_status();
+        assert!(!status.active);
+        assert_eq!(status.consecutive_failures, 0);
+        assert_eq!(status.last_backend_reply_ns, Some(300));
+        assert!(state.record_backend_reply(400).is_none());
+    }
+
+    #[test]
+    fn test_safe_mode_failure_count_resets_on_reply() {
+        let mut state = test_state();
+        for _ in 1..SAFE_MODE_FAILURE_THRESHOLD {
+            state.record_backend_failure("reject".to_string(), 100);
+        }
+        state.record_backend_reply(200);
+        assert!(!state.record_backend_failure("reject".to_string(), 300));
+        assert!(state.safe_mode.is_none());
+    }
+
+    #[test]
+    fn test_safe_mode_holds_only_recent_gains() {
+        let mut state = test_state();
+        add_deposit_direct(&mut state, user_a(), icusd_ledger(), 50_00000000);
+        add_deposit_direct(&mut state, user_b(), icusd_ledger(), 50_00000000);
+        let entered_at = 10 * RECENT_GAIN_WINDOW_NS;
+        let mut stables_consumed = BTreeMap::new();
+        stables_consumed.insert(icusd_ledger(), 10_00000000);
+
+        // user_a's ICP gains predate the window; user_b's ckBTC gains fall in it.
+        state.process_liquidation_gains_at(
+            1,
+            icp_ledger(),
+            &stables_consumed,
+            1_00000000,
+            10_00000000,
+            entered_at - 2 * RECENT_GAIN_WINDOW_NS,
+        );
+        state.process_liquidation_gains_at(
+            2,
+            ckbtc_ledger(),
+            &stables_consumed,
+            100_000,
+            10_000_00000000,
+            entered_at - RECENT_GAIN_WINDOW_NS / 2,
+        );
+        assert!(state
+            .ensure_gains_not_held(&user_b(), &ckbtc_ledger())
+            .is_ok());
+
+        for _ in 0..SAFE_MODE_FAILURE_THRESHOLD {
+            state.record_backend_failure("reject".to_string(), entered_at);
+        }
+        assert!(state
+            .ensure_gains_not_held(&user_a(), &icp_ledger())
+            .is_ok());
+        assert!(matches!(
+            state.ensure_gains_not_held(&user_b(), &ckbtc_ledger()),
+            Err(StabilityPoolError::BackendUnreachable { .. })
+        ));
+        assert_eq!(
+            state.safe_mode_status().gains_held_since_ns,
+            Some(entered_at - RECENT_GAIN_WINDOW_NS)
+        );
+
+        state.record_backend_reply(entered_at + 1);
+        assert!(state
+            .ensure_gains_not_held(&user_b(), &ckbtc_ledger())
+            .is_ok());
+    }
+
+    // ─── Depositor rewards ───
+
+    const SECOND_NS: u64 = 1_000_000_000;
+
+    fn reward_ledger() -> Principal {
+        Principal::from_slice(&[40])
+    }
+
+    fn reward_config(emission_per_second: u64, end_time_ns: Option<u64>) -> RewardConfig {
+        RewardConfig {
+            reward_ledger: reward_ledger(),
+            emission_per_second,
+            end_time_ns,
+        }
+    }
+
+    fn reward_balance(state: &StabilityPoolState, user: Principal) -> u64 {
+        state.accrued_rewards(&user)
+    }
+
+    #[test]
+    fn test_rewards_accrue_pro_rata_until_the_end_time() {
+        let mut state = test_state();
+        add_deposit_direct(&mut state, user_a(), icusd_ledger(), 300_00000000); // $300
+        add_deposit_direct(&mut state, user_b(), ckusdt_ledger(), 100_000_000); // $100
+        state
+            .set_reward_config(reward_config(1_000, Some(100 * SECOND_NS)), 0)
+            .unwrap();
+
+        state.accrue_rewards(10 * SECOND_NS);
+        assert_eq!(reward_balance(&state, user_a()), 7_500);
+        assert_eq!(reward_balance(&state, user_b()), 2_500);
+        // The preview includes what has not been accrued yet.
+        assert_eq!(state.pending_rewards(&user_a(), 20 * SECOND_NS), 15_000);
+
+        // Nothing is emitted past the end time.
+        state.accrue_rewards(200 * SECOND_NS);
+        assert_eq!(reward_balance(&state, user_a()), 75_000);
+        assert_eq!(reward_balance(&state, user_b()), 25_000);
+        state.accrue_rewards(300 * SECOND_NS);
+        let status = state.rewards_status();
+        assert_eq!(status.total_emitted, 100_000);
+        assert_eq!(status.total_unclaimed, 100_000);
+        assert_eq!(status.accrued_until_ns, Some(300 * SECOND_NS));
+    }
+
+    #[test]
+    fn test_rewards_follow_balance_changes_and_skip_an_empty_pool() {
+        let mut state = test_state();
+        state
+            .set_reward_config(reward_config(1_000, None), 0)
+            .unwrap();
+
+        // Emission while the pool is empty is forfeited.
+        state.accrue_rewards(10 * SECOND_NS);
+        assert_eq!(state.rewards_status().total_emitted, 0);
+
+        add_deposit_direct(&mut state, user_a(), icusd_ledger(), 100_00000000);
+        state.accrue_rewards(20 * SECOND_NS);
+        assert_eq!(reward_balance(&state, user_a()), 10_000);
+
+        // A liquidation first accrues under the balances it is about to draw.
+        add_deposit_direct(&mut state, user_b(), icusd_ledger(), 100_00000000);
+        let mut stables_consumed = BTreeMap::new();
+        stables_consumed.insert(icusd_ledger(), 100_00000000);
+        state.process_liquidation_gains_at(
+            1,
+            icp_ledger(),
+            &stables_consumed,
+            10_00000000,
+            10_00000000,
+            30 * SECOND_NS,
+        );
+        assert_eq!(reward_balance(&state, user_a()), 15_000);
+        assert_eq!(reward_balance(&state, user_b()), 5_000);
+    }
+
+    #[test]
+    fn test_reward_config_changes_and_claims() {
+        let mut state = test_state();
+        add_deposit_direct(&mut state, user_a(), icusd_ledger(), 100_00000000);
+
+        // Pool tokens cannot be the reward: claims would pay from deposits.
+        let pool_token = RewardConfig {
+            reward_ledger: icusd_ledger(),
+            ..reward_config(1_000, None)
+        };
+        assert!(matches!(
+            state.set_reward_config(pool_token, 0),
+            Err(StabilityPoolError::InvalidRewardConfig { .. })
+        ));
+
+        state
+            .set_reward_config(reward_config(1_000, None), 0)
+            .unwrap();
+        // The old rate accrues up to the change.
+        state
+            .set_reward_config(reward_config(2_000, None), 10 * SECOND_NS)
+            .unwrap();
+        state.accrue_rewards(20 * SECOND_NS);
+        assert_eq!(reward_balance(&state, user_a()), 30_000);
+
+        let other_ledger = RewardConfig {
+            reward_ledger: Principal::from_slice(&[41]),
+            ..reward_config(1_000, None)
+        };
+        assert!(matches!(
+            state.set_reward_config(other_ledger.clone(), 20 * SECOND_NS),
+            Err(StabilityPoolError::InvalidRewardConfig { .. })
+        ));
+
+        // A failed payout puts the balance back; a paid one is recorded.
+        let taken = state.take_reward_balance(&user_a());
+        assert_eq!(taken, 30_000);
+        state.restore_reward_balance(user_a(), taken);
+        assert_eq!(reward_balance(&state, user_a()), 30_000);
+        let taken = state.take_reward_balance(&user_a());
+        state.record_rewards_claimed(taken);
+        assert_eq!(state.rewards_status().total_claimed, 30_000);
+        assert_eq!(state.total_unclaimed_rewards(), 0);
+
+        state
+            .set_reward_config(other_ledger, 20 * SECOND_NS)
+            .unwrap();
+    }
+
+    // ─── Auto-compounding ───
+
+    fn route(max_slippage_bps: u16) -> AutoCompoundRoute {
+        AutoCompoundRoute {
+            dex: Principal::from_slice(&[50]),
+            pool_id: "ICP_icUSD".to_string(),
+            max_slippage_bps,
+        }
+    }
+
+    fn gains(state: &StabilityPoolState, user: Principal, collateral: Principal) -> u64 {
+        state
+            .get_collateral_gains(&user)
+            .get(&collateral)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_auto_compound_takes_only_opted_in_gains_and_restores_them() {
+        let mut state = test_state();
+        add_deposit_direct(&mut state, user_a(), icusd_ledger(), 75_00000000);
+        add_deposit_direct(&mut state, user_b(), icusd_ledger(), 25_00000000);
+        let mut stables_consumed = BTreeMap::new();
+        stables_consumed.insert(icusd_ledger(), 10_00000000);
+        state.process_liquidation_gains_at(
+            1,
+            icp_ledger(),
+            &stables_consumed,
+            4_00000000,
+            10_00000000,
+            0,
+        );
+        assert_eq!(
+            state.last_liquidation_price_e8s(&icp_ledger()),
+            Some(10_00000000)
+        );
+
+        assert!(state.set_auto_compound(user_a(), true));
+        assert!(!state.set_auto_compound(user_a(), true));
+        let batch = state.take_auto_compound_gains(&icp_ledger());
+        assert_eq!(batch, vec![(user_a(), 3_00000000)]);
+        assert_eq!(gains(&state, user_a(), icp_ledger()), 0);
+        assert_eq!(gains(&state, user_b(), icp_ledger()), 1_00000000);
+
+        // A failed sale gives back what is left after the approve fee.
+        let restore = crate::auto_compound::split_pro_rata(3_00000000 - 10_000, &batch);
+        state.restore_auto_compound_gains(&icp_ledger(), &restore, 0);
+        assert_eq!(gains(&state, user_a(), icp_ledger()), 3_00000000 - 10_000);
+        // The spent fee stays counted as claimed.
+        assert_eq!(
+            state.position(&user_a()).unwrap().total_claimed_gains[&icp_ledger()],
+            10_000
+        );
+
+        assert!(state.set_auto_compound(user_a(), false));
+        assert!(state.take_auto_compound_gains(&icp_ledger()).is_empty());
+    }
+
+    #[test]
+    fn test_auto_compound_routes_and_pending_credits() {
+        let mut state = test_state();
+        assert!(state
+            .set_auto_compound_route(icp_ledger(), Some(route(100)))
+            .is_ok());
+        assert!(matches!(
+            state.set_auto_compound_route(icp_ledger(), Some(route(5_000))),
+            Err(StabilityPoolError::InvalidAutoCompoundRoute { .. })
+        ));
+        assert!(matches!(
+            state.set_auto_compound_route(Principal::from_slice(&[99]), Some(route(100))),
+            Err(StabilityPoolError::CollateralNotFound { .. })
+        ));
+        state.set_auto_compound(user_a(), true);
+        state.defer_auto_compound_credits(&[(user_a(), 500), (user_b(), 200)]);
+        state.defer_auto_compound_credits(&[(user_a(), 100)]);
+
+        let status = state.auto_compound_status(&user_a());
+        assert!(status.enabled);
+        assert_eq!(status.routes, vec![(icp_ledger(), route(100))]);
+        assert_eq!(status.opted_in_users, 1);
+        assert_eq!(status.pending_credit, 600);
+        assert_eq!(
+            state.take_pending_auto_compound_credits(),
+            vec![(user_a(), 600), (user_b(), 200)]
+        );
+
+        state.set_auto_compound_route(icp_ledger(), None).unwrap();
+        assert!(state.auto_compound_status(&user_a()).routes.is_empty());
+    }
+
+    #[test]
+    fn test_auto_compound_min_out_uses_the_better_price() {
+        use crate::auto_compound::min_amount_out;
+        // 2 ICP last liquidated at $10, 1% slippage: a better quote sets the floor.
+        assert_eq!(
+            min_amount_out(2_00000000, 8, 25_00000000, Some(10_00000000), 100),
+            2_475_000_000
+        );
+        // A drained pool quoting less is held to the liquidation price.
+        assert_eq!(
+            min_amount_out(2_00000000, 8, 1_00000000, Some(10_00000000), 100),
+            1_980_000_000
+        );
+        assert_eq!(
+            min_amount_out(2_00000000, 8, 30_00000000, None, 0),
+            30_00000000
+        );
+
+        let split = crate::auto_compound::split_pro_rata(
+            100,
+            &[(user_a(), 3), (user_b(), 1), (user_c(), 0)],
+        );
+        assert_eq!(split, vec![(user_a(), 75), (user_b(), 25)]);
+    }
+
+    #[test]
+    fn test_icusd_claim_payout_needs_a_route_and_only_tightens_slippage() {
+        use crate::claim_payout::payout_slippage_bps;
+        let mut state = test_state();
+        assert!(matches!(
+            state.icusd_payout_route(&icp_ledger()),
+            Err(StabilityPoolError::PayoutSwapUnavailable { .. })
+        ));
+        state
+            .set_auto_compound_route(icp_ledger(), Some(route(300)))
+            .unwrap();
+        assert_eq!(
+            state.icusd_payout_route(&icp_ledger()).unwrap(),
+            (icusd_ledger(), route(300))
+        );
+
+        assert_eq!(payout_slippage_bps(&route(300), None), 300);
+        assert_eq!(payout_slippage_bps(&route(300), Some(50)), 50);
+        assert_eq!(payout_slippage_bps(&route(300), Some(900)), 300);
+
+        // Three fees are the least worth selling; two are set aside.
+        use crate::auto_compound::sale_amount;
+        assert_eq!(sale_amount(30_000, 10_000), None);
+        assert_eq!(sale_amount(30_001, 10_000), Some(10_001));
+    }
+
+    #[test]
+    fn test_withdrawal_queue_keeps_places_and_releases_after_settlement() {
+        let mut state = test_state();
+        state.add_deposit(user_a(), icusd_ledger(), 1_000_000_000);
+        state.add_deposit(user_b(), icusd_ledger(), 500_000_000);
+
+        assert!(matches!(
+            state.queue_withdrawal(user_b(), icusd_ledger(), 600_000_000, 0),
+            Err(StabilityPoolError::InsufficientBalance { .. })
+        ));
+        assert_eq!(
+            state.queue_withdrawal(user_a(), icusd_ledger(), 400_000_000, 0),
+            Ok(1)
+        );
+        assert_eq!(
+            state.queue_withdrawal(user_b(), icusd_ledger(), 500_000_000, 10),
+            Ok(2)
+        );
+        // A new request from Alice updates her entry in place.
+        assert_eq!(
+            state.queue_withdrawal(user_a(), icusd_ledger(), 900_000_000, 20),
+            Ok(1)
+        );
+
+        let positions = state.withdrawal_queue_positions(&user_b());
+        assert_eq!(positions.len(), 1);
+        assert_eq!((positions[0].position, positions[0].queue_length), (2, 2));
+        assert!(state.withdrawal_queue_positions(&user_c()).is_empty());
+
+        // Nothing is released until the pending liquidation settles.
+        assert!(state.take_ready_withdrawals(1_000).is_empty());
+        state.total_liquidations_executed += 1;
+        let ready = state.take_ready_withdrawals(1_000);
+        assert_eq!(ready.len(), 2);
+        assert_eq!(ready[0].amount, 900_000_000);
+        assert_eq!(state.withdrawal_queue_len(), 0);
+
+        // Unpaid ones go back to the front.
+        state
+            .queue_withdrawal(user_c(), icusd_ledger(), 0, 2_000)
+            .unwrap();
+        state.requeue_withdrawals(&ready[1..]);
+        assert_eq!(state.withdrawal_queue_positions(&user_b())[0].position, 1);
+    }
+
+    #[test]
+    fn test_withdrawal_queue_releases_after_the_max_delay() {
+        let mut state = test_state();
+        state.add_deposit(user_a(), icusd_ledger(), 1_000_000_000);
+        state
+            .queue_withdrawal(user_a(), icusd_ledger(), 1_000_000_000, 0)
+            .unwrap();
+        let max_delay_ns = crate::deposits::WITHDRAWAL_QUEUE_MAX_DELAY_SECONDS * 1_000_000_000;
+
+        assert!(state.take_ready_withdrawals(max_delay_ns - 1).is_empty());
+        assert_eq!(state.take_ready_withdrawals(max_delay_ns).len(), 1);
+    }
 }
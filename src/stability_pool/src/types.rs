@@ -121,6 +121,10 @@ pub struct DepositPosition {
     /// authoritative.
     #[serde(default)]
     pub pending_native_xrp_payouts: Option<BTreeMap<u64, NativeXrpPendingPayout>>,
+    /// When liquidation gains were last credited, keyed by collateral ledger.
+    /// Read by safe mode to hold recent gains (see `safe_mode`).
+    #[serde(default)]
+    pub gains_credited_at: Option<BTreeMap<Principal, u64>>,
 }
 
 impl DepositPosition {
@@ -139,6 +143,7 @@ impl DepositPosition {
             native_payout_addresses: Some(BTreeMap::new()),
             native_payout_destination_tags: Some(BTreeMap::new()),
             pending_native_xrp_payouts: Some(BTreeMap::new()),
+            gains_credited_at: Some(BTreeMap::new()),
         }
     }
 
@@ -228,11 +233,11 @@ pub struct NativeXrpPayoutAllocation {
     pub drops: u64,
 }
 
-pub type XrpSpAbsorbPreflight = rumi_protocol_backend::XrpSpAbsorbPreflight;
-pub type XrpSpPayoutAllocation = rumi_protocol_backend::XrpSpPayoutAllocation;
-pub type XrpSpAbsorbRequest = rumi_protocol_backend::XrpSpAbsorbRequest;
-pub type XrpSpPayoutClaim = rumi_protocol_backend::XrpSpPayoutClaim;
-pub type XrpSpAbsorbResult = rumi_protocol_backend::XrpSpAbsorbResult;
+pub type XrpSpAbsorbPreflight = rumi_common::types::XrpSpAbsorbPreflight;
+pub type XrpSpPayoutAllocation = rumi_common::types::XrpSpPayoutAllocation;
+pub type XrpSpAbsorbRequest = rumi_common::types::XrpSpAbsorbRequest;
+pub type XrpSpPayoutClaim = rumi_common::types::XrpSpPayoutClaim;
+pub type XrpSpAbsorbResult = rumi_common::types::XrpSpAbsorbResult;
 
 #[derive(CandidType, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum NativeXrpAbsorbIntentStatus {
@@ -256,7 +261,7 @@ pub struct NativeXrpAbsorbIntent {
     pub allocations: Vec<XrpSpPayoutAllocation>,
     pub burn_created_at_time_ns: u64,
     pub status: NativeXrpAbsorbIntentStatus,
-    pub burn_proof: Option<rumi_protocol_backend::icrc3_proof::SpWritedownProof>,
+    pub burn_proof: Option<rumi_common::icrc3_proof::SpWritedownProof>,
     pub backend_result: Option<XrpSpAbsorbResult>,
     pub last_error: Option<String>,
     pub created_at_ns: u64,
@@ -351,12 +356,40 @@ pub struct LiquidationResult {
     pub error_message: Option<String>,
 }
 
+/// Dry run of a pool liquidation from `simulate_liquidation`: the backend's
+/// preview of the vault, the stablecoins the pool would draw for it, and
+/// what depositors would gain. Nothing is executed. USD values are e8s.
+#[derive(CandidType, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LiquidationSimulation {
+    pub preview: rumi_common::types::LiquidationPreview,
+    /// ledger -> amount the pool would draw (native decimals).
+    pub token_draw: BTreeMap<Principal, u64>,
+    pub draw_value_e8s: u64,
+    /// ckUSDT/ckUSDC repay-fee surcharge the backend pulls on top of the draw.
+    pub repay_fee_e8s: u64,
+    /// Approve + transfer_from fees on the drawn ledgers, at registry fees.
+    pub ledger_fees_e8s: u64,
+    /// Collateral depositors would receive (native decimals).
+    pub expected_collateral_gained: u64,
+    pub expected_collateral_value_e8s: u64,
+    /// Collateral value less the draw and all fees. Negative is a loss.
+    pub expected_net_gain_e8s: i64,
+    /// Net gain over the opted-in depth, in basis points.
+    pub depositor_return_bps: i64,
+    pub opted_in_depositors: u64,
+    /// Opted-in USD depth for the vault's collateral.
+    pub pool_depth_before_e8s: u64,
+    pub pool_depth_after_e8s: u64,
+    /// Why `execute_liquidation` would not go through right now, if anything.
+    pub blockers: Vec<String>,
+}
+
 /// Mirror of the backend's `ChainLiquidatableVault` Candid record. Kept local
 /// because the backend exports that type from its canister binary, not its lib.
 #[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ChainLiquidatableVaultInfo {
     pub vault_id: u64,
-    pub chain_id: rumi_protocol_backend::chains::config::ChainId,
+    pub chain_id: rumi_common::chains::ChainId,
     pub chain_collateral_sentinel: Principal,
     pub sp_attempted: bool,
     pub debt_e8s: u128,
@@ -372,7 +405,7 @@ pub struct ChainLiquidatableVaultInfo {
 pub struct ChainStabilityPoolLiquidationResult {
     pub success: bool,
     pub vault_id: u64,
-    pub chain_id: rumi_protocol_backend::chains::config::ChainId,
+    pub chain_id: rumi_common::chains::ChainId,
     pub liquidated_debt_e8s: u128,
     pub collateral_received_native: u128,
     pub claim_id: u64,
@@ -385,7 +418,7 @@ pub struct ChainStabilityPoolLiquidationResult {
 pub struct ChainSpAbsorbResult {
     pub success: bool,
     pub vault_id: u64,
-    pub chain_id: rumi_protocol_backend::chains::config::ChainId,
+    pub chain_id: rumi_common::chains::ChainId,
     pub icusd_burned_e8s: u64,
     pub liquidated_debt_e8s: u128,
     pub collateral_received_native: u128,
@@ -414,7 +447,7 @@ pub struct ChainSpAbsorbCandidate {
 #[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ChainSpAbsorbIntent {
     pub vault_id: u64,
-    pub chain_id: rumi_protocol_backend::chains::config::ChainId,
+    pub chain_id: rumi_common::chains::ChainId,
     pub chain_sentinel: Principal,
     pub icusd_ledger: Principal,
     pub icusd_minting_account: icrc_ledger_types::icrc1::account::Account,
@@ -422,7 +455,7 @@ pub struct ChainSpAbsorbIntent {
     pub stables_consumed: BTreeMap<Principal, u64>,
     pub burn_created_at_time_ns: u64,
     pub status: ChainSpAbsorbIntentStatus,
-    pub burn_proof: Option<rumi_protocol_backend::icrc3_proof::SpWritedownProof>,
+    pub burn_proof: Option<rumi_common::icrc3_proof::SpWritedownProof>,
     pub backend_result: Option<ChainStabilityPoolLiquidationResult>,
     pub last_error: Option<String>,
     pub created_at_ns: u64,
@@ -590,6 +623,184 @@ pub struct PoolConfiguration {
     pub authorized_admins: Vec<Principal>,
 }
 
+/// Protocol mode pushed by the backend. Shared with the treasury.
+pub use rumi_common::types::ProtocolMode;
+
+/// Which backend modes the pool inherits as restrictions. Admin-configurable;
+/// the default holds new deposits and liquidations while the backend is
+/// ReadOnly and leaves Recovery unrestricted, since that is when the pool is
+/// needed most.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModeInheritancePolicy {
+    /// Modes in which `deposit` and `deposit_as_3usd` are refused.
+    pub block_deposits_in: Vec<ProtocolMode>,
+    /// Modes in which `notify_liquidatable_vaults` and `execute_liquidation`
+    /// start no new liquidations.
+    pub block_liquidations_in: Vec<ProtocolMode>,
+}
+
+impl Default for ModeInheritancePolicy {
+    fn default() -> Self {
+        Self {
+            block_deposits_in: vec![ProtocolMode::ReadOnly],
+            block_liquidations_in: vec![ProtocolMode::ReadOnly],
+        }
+    }
+}
+
+/// Set while the protocol canister is treated as unreachable. See
+/// `safe_mode`.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SafeMode {
+    pub entered_at_ns: u64,
+    /// Recovery probes sent since entering.
+    pub probes: u64,
+}
+
+/// Reply of `get_safe_mode_status`.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SafeModeStatus {
+    pub active: bool,
+    pub entered_at_ns: Option<u64>,
+    pub probes: u64,
+    /// Rejected backend calls since the last reply.
+    pub consecutive_failures: u32,
+    pub failure_threshold: u32,
+    pub last_backend_reply_ns: Option<u64>,
+    pub last_backend_error: Option<String>,
+    /// While active, gains credited at or after this time cannot be claimed.
+    pub gains_held_since_ns: Option<u64>,
+}
+
+/// Emission of a reward token to depositors. See `rewards`.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RewardConfig {
+    /// ICRC-1 ledger of the reward token; the pool's own account funds it.
+    pub reward_ledger: Principal,
+    /// Reward ledger units emitted per second, shared by all depositors.
+    /// 0 pauses emission.
+    pub emission_per_second: u64,
+    /// Emission stops at this time; `None` runs until reconfigured.
+    pub end_time_ns: Option<u64>,
+}
+
+/// Reply of `get_rewards_status`.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RewardsStatus {
+    pub config: Option<RewardConfig>,
+    pub accrued_until_ns: Option<u64>,
+    pub total_emitted: u64,
+    pub total_claimed: u64,
+    /// Accrued to depositors and not yet claimed.
+    pub total_unclaimed: u64,
+}
+
+/// Where auto-compounding sells one collateral for icUSD. See `auto_compound`.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutoCompoundRoute {
+    /// Rumi AMM canister holding the collateral/icUSD pool.
+    pub dex: Principal,
+    pub pool_id: String,
+    /// Largest accepted shortfall below the better of the DEX quote and the
+    /// last liquidation price, in basis points.
+    pub max_slippage_bps: u16,
+}
+
+/// Reply of `get_auto_compound_status`.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutoCompoundStatus {
+    /// Whether the queried user has opted in.
+    pub enabled: bool,
+    pub routes: Vec<(Principal, AutoCompoundRoute)>,
+    pub opted_in_users: u64,
+    /// icUSD bought for the queried user but not yet credited to the deposit.
+    pub pending_credit: u64,
+}
+
+/// How `claim_collateral_as` pays out collateral gains. See `claim_payout`.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClaimPayout {
+    /// The seized collateral itself, as `claim_collateral` sends it.
+    Collateral,
+    /// icUSD bought with the gains through the collateral's auto-compound
+    /// route. `max_slippage_bps` can only tighten the route's own bound.
+    IcUsd { max_slippage_bps: Option<u16> },
+}
+
+/// Reply of `quote_claim_payout`: what claiming one collateral's gains as
+/// icUSD would cost and pay right now.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClaimPayoutQuote {
+    pub collateral_ledger: Principal,
+    pub gains: u64,
+    /// What `ClaimPayout::Collateral` sends, after one ledger fee.
+    pub collateral_payout: u64,
+    /// Gains sold, after the approve and DEX transfer fees.
+    pub amount_in: u64,
+    /// Collateral ledger fees the sale spends.
+    pub collateral_fees: u64,
+    pub max_slippage_bps: u16,
+    /// The DEX quote for `amount_in`, in icUSD e8s.
+    pub dex_quote: u64,
+    /// Least icUSD the swap accepts; a worse price pays out collateral.
+    pub min_icusd_out: u64,
+    /// icUSD ledger fees on the DEX payout and the transfer to the claimant.
+    pub icusd_fees: u64,
+    /// `dex_quote` less `icusd_fees`.
+    pub estimated_icusd: u64,
+}
+
+/// Reply of `claim_collateral_as`.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClaimPayoutReceipt {
+    pub collateral_ledger: Principal,
+    /// Gains taken for the claim.
+    pub gains: u64,
+    /// icUSD, or the collateral when it was asked for or the swap failed.
+    pub paid_ledger: Principal,
+    pub paid_amount: u64,
+    /// Collateral ledger fees spent, a failed swap's included.
+    pub collateral_fees: u64,
+    pub icusd_fees: u64,
+    /// Minimum the swap was held to, when one ran.
+    pub min_icusd_out: Option<u64>,
+    /// Why an icUSD payout fell back to the collateral.
+    pub fallback_reason: Option<String>,
+    /// The icUSD could not be sent and was added to the caller's deposit.
+    pub credited_to_deposit: bool,
+}
+
+/// A withdrawal requested while a liquidation was pending, paid out once
+/// it has settled. See `deposits::process_withdrawal_queue`.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedWithdrawal {
+    pub user: Principal,
+    pub token_ledger: Principal,
+    pub amount: u64,
+    pub requested_at_ns: u64,
+    /// `total_liquidations_executed` when the withdrawal was requested.
+    pub epoch: u64,
+}
+
+/// Reply of `get_withdrawal_queue_position`.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WithdrawalQueuePosition {
+    /// 1 for the next withdrawal to be paid.
+    pub position: u64,
+    pub queue_length: u64,
+    pub withdrawal: QueuedWithdrawal,
+}
+
+/// Reply of `get_mode_inheritance`.
+#[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModeInheritanceStatus {
+    /// Last mode pushed by the backend; `None` until the first push.
+    pub protocol_mode: Option<ProtocolMode>,
+    pub policy: ModeInheritancePolicy,
+    pub deposits_blocked: bool,
+    pub liquidations_blocked: bool,
+}
+
 #[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StabilityPoolStatus {
     pub total_deposits_e8s: u64,
@@ -613,6 +824,61 @@ pub struct StabilityPoolStatus {
     pub eligible_usd_per_collateral: Option<Vec<(Principal, u64)>>,
 }
 
+/// Trailing windows reported by `get_liquidity_pool_stats`, in days.
+pub const LIQUIDITY_POOL_STATS_WINDOWS_DAYS: [u64; 3] = [7, 30, 90];
+
+/// Pool performance over the trailing windows in
+/// `LIQUIDITY_POOL_STATS_WINDOWS_DAYS`, computed on-chain so every frontend
+/// quotes the same APY.
+#[derive(CandidType, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LiquidityPoolStats {
+    pub total_deposits_e8s: u64,
+    pub total_depositors: u64,
+    pub windows: Vec<LiquidityPoolWindowStats>,
+    pub computed_at: u64,
+}
+
+/// All USD amounts are e8s. Liquidation collateral is valued at the price
+/// recorded with each liquidation; LP-token stables at the current cached
+/// virtual price.
+#[derive(CandidType, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LiquidityPoolWindowStats {
+    pub window_days: u64,
+    /// Borrower interest distributed to depositors.
+    pub interest_distributed_e8s: u64,
+    pub liquidations: u64,
+    /// Stables consumed absorbing liquidations.
+    pub stables_consumed_e8s: u64,
+    /// Value of the collateral depositors received for them.
+    pub collateral_gained_usd_e8s: u64,
+    /// Sum of the shortfalls of liquidations whose collateral was worth less
+    /// than the stables they consumed.
+    pub redistribution_losses_e8s: u64,
+    /// Liquidations recorded without a price. Excluded from the three
+    /// liquidation amounts above and from the APY.
+    pub unpriced_liquidations: u64,
+    /// Stables consumed as a share of current deposits, in basis points.
+    pub utilization_bps: u64,
+    /// Net return (interest + collateral value - stables consumed) over the
+    /// window relative to current deposits, compounded to a year
+    /// (0.05 = 5%). `None` while the pool is empty.
+    pub realized_apy: Option<f64>,
+    /// False when the retained event or liquidation history does not reach
+    /// back to the window start, so the figures cover less than the window.
+    pub complete: bool,
+}
+
+/// A depositor's stablecoin balances with every liquidation loss and
+/// interest credit so far applied. See
+/// `StabilityPoolState::get_compounded_deposit`.
+#[derive(CandidType, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompoundedDeposit {
+    /// Balances keyed by stablecoin ledger, in native decimals.
+    pub stablecoin_balances: BTreeMap<Principal, u64>,
+    /// The balances valued in USD, LP tokens at their virtual price.
+    pub total_usd_value_e8s: u64,
+}
+
 #[derive(CandidType, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct UserStabilityPosition {
     pub stablecoin_balances: BTreeMap<Principal, u64>,
@@ -675,6 +941,14 @@ pub enum StabilityPoolError {
         reason: String,
     },
     EmergencyPaused,
+    /// Refused by the mode inheritance policy while the backend is in `mode`.
+    ProtocolModeRestricted {
+        mode: ProtocolMode,
+    },
+    /// Refused while the pool is in safe mode, entered at `since_ns`.
+    BackendUnreachable {
+        since_ns: u64,
+    },
     SystemBusy,
     AlreadyOptedOut {
         collateral: Principal,
@@ -695,6 +969,24 @@ pub enum StabilityPoolError {
         reason: String,
     },
     RefundClaimNotFound,
+    /// No reward token emission has been configured.
+    RewardsNotConfigured,
+    InvalidRewardConfig {
+        reason: String,
+    },
+    InvalidAutoCompoundRoute {
+        reason: String,
+    },
+    /// Gains of `collateral` cannot be paid out as icUSD.
+    PayoutSwapUnavailable {
+        collateral: Principal,
+        reason: String,
+    },
+    /// Not a failure: a liquidation was pending, so the withdrawal was
+    /// queued at `position` and is paid once it settles.
+    WithdrawalQueued {
+        position: u64,
+    },
 }
 
 // ──────────────────────────────────────────────────────────────
@@ -921,6 +1213,63 @@ pub enum PoolEventType {
     ConfigurationUpdated,
     EmergencyPauseActivated,
     OperationsResumed,
+    // ─── Backend mode inheritance ───
+    ProtocolModeInherited {
+        mode: ProtocolMode,
+    },
+    ModeInheritancePolicyUpdated,
+    // ─── Backend safe mode ───
+    SafeModeEntered {
+        consecutive_failures: u32,
+    },
+    SafeModeExited {
+        entered_at_ns: u64,
+    },
+    // ─── Depositor rewards ───
+    RewardConfigUpdated {
+        config: RewardConfig,
+    },
+    RewardsClaimed {
+        reward_ledger: Principal,
+        amount: u64,
+    },
+    // ─── Auto-compounding ───
+    AutoCompoundEnabled,
+    AutoCompoundDisabled,
+    AutoCompoundRouteUpdated {
+        collateral_ledger: Principal,
+        route: Option<AutoCompoundRoute>,
+    },
+    AutoCompounded {
+        collateral_ledger: Principal,
+        collateral_amount: u64,
+        icusd_amount: u64,
+    },
+    AutoCompoundFailed {
+        collateral_ledger: Principal,
+        reason: String,
+    },
+    // ─── Claim payouts ───
+    CollateralClaimedAsIcusd {
+        collateral_ledger: Principal,
+        collateral_amount: u64,
+        icusd_amount: u64,
+    },
+    ClaimPayoutFellBack {
+        collateral_ledger: Principal,
+        reason: String,
+    },
+    // ─── Withdrawal queue ───
+    WithdrawalQueued {
+        token_ledger: Principal,
+        amount: u64,
+        epoch: u64,
+    },
+    QueuedWithdrawalFailed {
+        token_ledger: Principal,
+        amount: u64,
+        reason: String,
+    },
     // ─── Admin: Balance Corrections ───
     BalanceCorrected {
         user: Principal,
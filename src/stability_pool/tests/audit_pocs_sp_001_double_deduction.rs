@@ -90,19 +90,29 @@ fn fresh_state() -> StabilityPoolState {
 
 /// Seed a deposit without touching `ic_cdk::api::time()` (tests run outside the IC runtime).
 fn seed_deposit(state: &mut StabilityPoolState, user: Principal, token: Principal, amount: u64) {
+    state.settle_position(&user);
     let position = state.deposits.entry(user).or_insert_with(|| DepositPosition::new(0));
     *position.stablecoin_balances.entry(token).or_insert(0) += amount;
     *state.total_stablecoin_balances.entry(token).or_insert(0) += amount;
+    state.attach_position(&user);
+}
+
+/// Every position settled, so that per-depositor balances carry the loss
+/// cohorts' rounding dust and sum to the aggregate.
+fn settled(state: &StabilityPoolState) -> StabilityPoolState {
+    let mut state = state.clone();
+    state.settle_all_positions();
+    state
 }
 
 fn sum_stables(state: &StabilityPoolState, token: Principal) -> u64 {
-    state.deposits.values()
+    settled(state).deposits.values()
         .map(|p| p.stablecoin_balances.get(&token).copied().unwrap_or(0))
         .sum()
 }
 
 fn sum_collateral(state: &StabilityPoolState, collateral: Principal) -> u64 {
-    state.deposits.values()
+    settled(state).deposits.values()
         .map(|p| p.collateral_gains.get(&collateral).copied().unwrap_or(0))
         .sum()
 }
@@ -243,8 +253,8 @@ fn sp_001_fixed_sequence_conserves_value_asymmetric_depositors() {
     // Proportional distribution:
     // user_a: 150 − 60 (75% of 80) = 90 stables + 60 collateral
     // user_b:  50 − 20 (25% of 80) = 30 stables + 20 collateral
-    let pos_a = state.deposits.get(&user_a()).expect("user_a position");
-    let pos_b = state.deposits.get(&user_b()).expect("user_b position");
+    let pos_a = state.position(&user_a()).expect("user_a position");
+    let pos_b = state.position(&user_b()).expect("user_b position");
 
     assert_eq!(pos_a.stablecoin_balances.get(&icusd_ledger()).copied().unwrap_or(0), 90_00000000);
     assert_eq!(pos_a.collateral_gains.get(&icp_ledger()).copied().unwrap_or(0),       60_00000000);
@@ -272,7 +282,7 @@ fn sp_005_no_phantom_loss_on_failed_call() {
     let mut state = fresh_state();
     seed_deposit(&mut state, user_a(), icusd_ledger(), 100_00000000);
 
-    let snapshot_a = state.deposits.get(&user_a()).unwrap().stablecoin_balances.clone();
+    let snapshot_a = state.position(&user_a()).unwrap().stablecoin_balances.clone();
     let snapshot_agg = state.total_stablecoin_balances.clone();
 
     // Simulate the failure path: execute_single_liquidation's match arm for
@@ -281,7 +291,7 @@ fn sp_005_no_phantom_loss_on_failed_call() {
     // With the pre-deduct removed, no state mutation occurs in this branch.
 
     assert_eq!(
-        state.deposits.get(&user_a()).unwrap().stablecoin_balances,
+        state.position(&user_a()).unwrap().stablecoin_balances,
         snapshot_a,
         "SP-005: a failed call must not mutate per-depositor balances",
     );